@@ -0,0 +1,115 @@
+// Payments sent straight to a connector with ILP-over-HTTP credentials, without a node.
+// The CLI acts as the STREAM sender: it learns its address and asset from the connector
+// with IL-DCP, then sends the packets of the payment to the connector's HTTP endpoint.
+
+use interledger_errors::ExchangeRateStoreError;
+use interledger_ildcp::get_ildcp_info;
+use interledger_packet::Address;
+use interledger_rates::ExchangeRateStore;
+use interledger_service::{Account, Username};
+use interledger_spsp::HttpTransport;
+use interledger_stream::StreamDelivery;
+use std::{collections::HashMap, str::FromStr};
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DirectPaymentError {
+    #[error("Error getting the account details from the connector with IL-DCP")]
+    Ildcp,
+    #[error("Error sending the payment: {0}")]
+    Payment(#[from] interledger_spsp::Error),
+}
+
+/// The sender's account on the connector, whose details are given by the connector
+#[derive(Clone, Debug)]
+struct SenderAccount {
+    id: Uuid,
+    username: Username,
+    ilp_address: Address,
+    asset_code: String,
+    asset_scale: u8,
+}
+
+impl Account for SenderAccount {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn username(&self) -> &Username {
+        &self.username
+    }
+
+    fn ilp_address(&self) -> &Address {
+        &self.ilp_address
+    }
+
+    fn asset_scale(&self) -> u8 {
+        self.asset_scale
+    }
+
+    fn asset_code(&self) -> &str {
+        &self.asset_code
+    }
+}
+
+/// Without exchange rates, the minimum amount the receiver must get is only enforced when
+/// it uses the same asset as the sender
+#[derive(Clone)]
+struct NoRates;
+
+impl ExchangeRateStore for NoRates {
+    fn set_exchange_rates(
+        &self,
+        _rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        Ok(())
+    }
+
+    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        Err(ExchangeRateStoreError::PairNotFound {
+            from: asset_codes.get(0).unwrap_or(&"").to_string(),
+            to: asset_codes.get(1).unwrap_or(&"").to_string(),
+        })
+    }
+
+    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        Ok(HashMap::new())
+    }
+}
+
+/// Sends a STREAM payment to the receiver through the connector's ILP-over-HTTP endpoint
+/// and returns its receipt
+pub async fn pay(
+    url: &str,
+    token: &str,
+    receiver: &str,
+    source_amount: u64,
+    slippage: f64,
+) -> Result<StreamDelivery, DirectPaymentError> {
+    let mut transport = HttpTransport::new(url, token);
+    let mut account = SenderAccount {
+        id: Uuid::new_v4(),
+        username: Username::from_str("ilp-cli").unwrap(), // infallible unwrap
+        // Replaced by the address the connector gives the account
+        ilp_address: Address::from_str("local.ilp-cli").unwrap(), // infallible unwrap
+        asset_code: String::new(),
+        asset_scale: 0,
+    };
+    let info = get_ildcp_info(&mut transport, account.clone())
+        .await
+        .map_err(|_| DirectPaymentError::Ildcp)?;
+    account.ilp_address = info.ilp_address();
+    account.asset_code = String::from_utf8_lossy(info.asset_code()).into_owned();
+    account.asset_scale = info.asset_scale();
+
+    let receipt = interledger_spsp::pay(
+        transport,
+        account,
+        NoRates,
+        receiver,
+        source_amount,
+        slippage,
+    )
+    .await?;
+    Ok(receipt)
+}
@@ -1,3 +1,4 @@
+use crate::direct::{self, DirectPaymentError};
 use clap::ArgMatches;
 use reqwest::{
     self,
@@ -28,6 +29,12 @@ pub enum Error {
     WebsocketErr(#[from] tokio_tungstenite::tungstenite::error::Error),
     #[error("HTTP error: {0}")]
     Http(#[from] http::Error),
+    #[error("Error reading file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    DirectPayment(#[from] DirectPaymentError),
+    #[error("Error serializing the receipt: {0}")]
+    Json(#[from] serde_json::Error),
 }
 
 pub fn run(matches: &ArgMatches) -> Result<Response, Error> {
@@ -40,13 +47,16 @@ pub fn run(matches: &ArgMatches) -> Result<Response, Error> {
     match matches.subcommand() {
         ("accounts", Some(accounts_matches)) => match accounts_matches.subcommand() {
             ("balance", Some(submatches)) => client.get_account_balance(submatches),
+            ("balance-changes", Some(submatches)) => client.get_account_balance_changes(submatches),
             ("create", Some(submatches)) => client.post_accounts(submatches),
             ("delete", Some(submatches)) => client.delete_account(submatches),
+            ("events", Some(submatches)) => client.ws_account_events(submatches),
             ("incoming-payments", Some(submatches)) => {
                 client.ws_account_payments_incoming(submatches)
             }
             ("info", Some(submatches)) => client.get_account(submatches),
             ("list", Some(submatches)) => client.get_accounts(submatches),
+            ("reconciliation", Some(submatches)) => client.get_account_reconciliation(submatches),
             ("update", Some(submatches)) => client.put_account(submatches),
             ("update-settings", Some(submatches)) => client.put_account_settings(submatches),
             _ => Err(Error::Usage("ilp-cli help accounts")),
@@ -67,16 +77,31 @@ pub fn run(matches: &ArgMatches) -> Result<Response, Error> {
             ("set-all", Some(submatches)) => client.put_settlement_engines(submatches),
             _ => Err(Error::Usage("ilp-cli help settlement-engines")),
         },
+        ("reconciliation", Some(reconciliation_matches)) => {
+            client.get_reconciliation(reconciliation_matches)
+        }
+        ("events", Some(events_matches)) => client.ws_events(events_matches),
         ("status", Some(status_matches)) => client.get_root(status_matches),
         ("logs", Some(log_level)) => client.put_tracing_level(log_level),
+        ("export", Some(export_matches)) => client.get_export(export_matches),
+        ("import", Some(import_matches)) => client.post_import(import_matches),
         ("testnet", Some(testnet_matches)) => match testnet_matches.subcommand() {
+            ("connect", Some(submatches)) => client.connect_to_testnet(submatches),
             ("setup", Some(submatches)) => client.xpring_account(submatches),
             _ => Err(Error::Usage("ilp-cli help testnet")),
         },
         ("payments", Some(payments_matches)) => match payments_matches.subcommand() {
             ("incoming", Some(submatches)) => client.ws_payments_incoming(submatches),
+            ("stream", Some(submatches)) => client.post_account_payments_streams(submatches),
+            ("stream-status", Some(submatches)) => client.get_account_payments_stream(submatches),
+            ("stream-stop", Some(submatches)) => client.delete_account_payments_stream(submatches),
             _ => Err(Error::Usage("ilp-cli help payments")),
         },
+        ("spsp", Some(spsp_matches)) => match spsp_matches.subcommand() {
+            ("query", Some(submatches)) => client.get_spsp(submatches),
+            ("pay", Some(submatches)) => pay_directly(submatches),
+            _ => Err(Error::Usage("ilp-cli help spsp")),
+        },
         _ => Err(Error::Usage("ilp-cli help")),
     }
 }
@@ -98,6 +123,33 @@ impl NodeClient<'_> {
             .map_err(Error::Send)
     }
 
+    // GET /accounts/:username/balance/changes
+    fn get_account_balance_changes(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, mut args) = extract_args(matches);
+        let user = args.remove("username").unwrap(); // infallible unwrap
+        self.client
+            .get(&format!("{}/accounts/{}/balance/changes", self.url, user))
+            .bearer_auth(auth)
+            .query(&args)
+            .send()
+            .map_err(Error::Send)
+    }
+
+    // GET /accounts/:username/balance/reconciliation
+    fn get_account_reconciliation(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, mut args) = extract_args(matches);
+        let user = args.remove("username").unwrap(); // infallible unwrap
+        self.client
+            .get(&format!(
+                "{}/accounts/{}/balance/reconciliation",
+                self.url, user
+            ))
+            .bearer_auth(auth)
+            .query(&args)
+            .send()
+            .map_err(Error::Send)
+    }
+
     // POST /accounts
     fn post_accounts(&self, matches: &ArgMatches) -> Result<Response, Error> {
         let (auth, args) = extract_args(matches);
@@ -133,38 +185,33 @@ impl NodeClient<'_> {
     // WebSocket /accounts/:username/payments/incoming
     fn ws_account_payments_incoming(&self, matches: &ArgMatches) -> Result<Response, Error> {
         let (auth, args) = extract_args(matches);
-        let mut url = Url::parse(&format!(
-            "{}/accounts/{}/payments/incoming",
-            self.url, args["username"]
-        ))?;
-
-        let scheme = match url.scheme() {
-            "http" => Ok("ws"),
-            "https" => Ok("wss"),
-            s => Err(Error::Protocol(format!(
-                "{} (only HTTP and HTTPS are supported)",
-                s
-            ))),
-        }?;
-
-        url.set_scheme(scheme).map_err(Error::Scheme)?;
-
-        let request: Request = Request::builder()
-            .uri(url.into_string())
-            .header("Authorization", format!("Bearer {}", auth))
-            .body(())?;
-
-        let (mut socket, _) = connect(request)?;
-        loop {
-            let msg = socket.read_message()?;
-            println!("{}", msg);
-        }
+        self.print_ws_messages(
+            &format!("accounts/{}/payments/incoming", args["username"]),
+            auth,
+        )
     }
 
     // WebSocket /payments/incoming
     fn ws_payments_incoming(&self, matches: &ArgMatches) -> Result<Response, Error> {
         let (auth, _args) = extract_args(matches);
-        let mut url = Url::parse(&format!("{}/payments/incoming", self.url))?;
+        self.print_ws_messages("payments/incoming", auth)
+    }
+
+    // WebSocket /accounts/:username/events
+    fn ws_account_events(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.print_ws_messages(&format!("accounts/{}/events", args["username"]), auth)
+    }
+
+    // WebSocket /events
+    fn ws_events(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _args) = extract_args(matches);
+        self.print_ws_messages("events", auth)
+    }
+
+    // Prints the messages of one of the node's WebSockets until it is closed
+    fn print_ws_messages(&self, path: &str, auth: &str) -> Result<Response, Error> {
+        let mut url = Url::parse(&format!("{}/{}", self.url, path))?;
 
         let scheme = match url.scheme() {
             "http" => Ok("ws"),
@@ -201,10 +248,11 @@ impl NodeClient<'_> {
 
     // GET /accounts
     fn get_accounts(&self, matches: &ArgMatches) -> Result<Response, Error> {
-        let (auth, _) = extract_args(matches);
+        let (auth, args) = extract_args(matches);
         self.client
             .get(&format!("{}/accounts", self.url))
             .bearer_auth(auth)
+            .query(&args)
             .send()
             .map_err(Error::Send)
     }
@@ -233,6 +281,54 @@ impl NodeClient<'_> {
             .map_err(Error::Send)
     }
 
+    // POST /accounts/:username/payments/streams
+    fn post_account_payments_streams(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, mut args) = extract_args(matches);
+        let user = args.remove("username").unwrap(); // infallible unwrap
+        self.client
+            .post(&format!("{}/accounts/{}/payments/streams", self.url, user))
+            .bearer_auth(auth)
+            .json(&args)
+            .send()
+            .map_err(Error::Send)
+    }
+
+    // GET /accounts/:username/payments/streams/:id
+    fn get_account_payments_stream(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .get(&format!(
+                "{}/accounts/{}/payments/streams/{}",
+                self.url, args["username"], args["id"]
+            ))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::Send)
+    }
+
+    // DELETE /accounts/:username/payments/streams/:id
+    fn delete_account_payments_stream(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .delete(&format!(
+                "{}/accounts/{}/payments/streams/{}",
+                self.url, args["username"], args["id"]
+            ))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::Send)
+    }
+
+    // GET the SPSP details of a Payment Pointer, which is not on the node
+    fn get_spsp(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let receiver = matches.value_of("receiver").unwrap(); // infallible unwrap
+        self.client
+            .get(&interledger_spsp::payment_pointer_to_url(receiver))
+            .header("Accept", "application/spsp4+json")
+            .send()
+            .map_err(Error::Send)
+    }
+
     // GET /rates
     fn get_rates(&self, _matches: &ArgMatches) -> Result<Response, Error> {
         self.client
@@ -304,6 +400,40 @@ impl NodeClient<'_> {
             .map_err(Error::Send)
     }
 
+    // GET /export
+    fn get_export(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/export", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::Send)
+    }
+
+    // POST /import
+    fn post_import(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        let export = std::fs::read(args["file"])?;
+        self.client
+            .post(&format!("{}/import", self.url))
+            .bearer_auth(auth)
+            .header("Content-Type", "application/json")
+            .body(export)
+            .send()
+            .map_err(Error::Send)
+    }
+
+    // GET /reconciliation
+    fn get_reconciliation(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .get(&format!("{}/reconciliation", self.url))
+            .bearer_auth(auth)
+            .query(&args)
+            .send()
+            .map_err(Error::Send)
+    }
+
     // GET /
     fn get_root(&self, _matches: &ArgMatches) -> Result<Response, Error> {
         self.client
@@ -312,6 +442,52 @@ impl NodeClient<'_> {
             .map_err(Error::Send)
     }
 
+    // POST /accounts, then GET /
+    // The node gets its ILP address from the parent account with IL-DCP, and routes
+    // everything through it by default. No settlement engine or threshold is set, so
+    // the testnet is used without any funds.
+    fn connect_to_testnet(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, cli_args) = extract_args(matches);
+        let mut url = Url::parse(cli_args["url"])?;
+        let token = match (url.password(), cli_args.get("token")) {
+            (Some(password), _) => password.to_string(),
+            (None, Some(token)) => token.to_string(),
+            (None, None) => return Err(Error::Usage("ilp-cli help testnet connect")),
+        };
+        // The credentials are sent in the auth packet or header, not in the URL
+        url.set_username("").map_err(Error::Scheme)?;
+        url.set_password(None).map_err(Error::Scheme)?;
+
+        let mut args = HashMap::new();
+        match url.scheme() {
+            "btp+ws" | "btp+wss" => {
+                args.insert("ilp_over_btp_url", url.to_string());
+                args.insert("ilp_over_btp_outgoing_token", token);
+            }
+            "http" | "https" => {
+                args.insert("ilp_over_http_url", url.to_string());
+                args.insert("ilp_over_http_outgoing_token", token);
+            }
+            scheme => return Err(Error::Protocol(scheme.to_string())),
+        }
+        args.insert("username", cli_args["username"].to_string());
+        args.insert("asset_code", cli_args["asset_code"].to_string());
+        args.insert("asset_scale", cli_args["asset_scale"].to_string());
+        args.insert("routing_relation", String::from("Parent"));
+
+        let response = self
+            .client
+            .post(&format!("{}/accounts/", self.url))
+            .bearer_auth(auth)
+            .json(&args)
+            .send()?;
+        if !response.status().is_success() {
+            return Ok(response);
+        }
+        // The status shows the address the node got from the connector
+        self.get_root(matches)
+    }
+
     /*
     {"http_endpoint": "https://rs3.xpring.dev/ilp", // ilp_over_http_url
     "passkey": "b0i3q9tbvfgek",  // ilp_over_http_outgoing_token = username:passkey
@@ -365,6 +541,33 @@ impl NodeClient<'_> {
     }
 }
 
+// Sends a STREAM payment without going through the node, and responds with its receipt
+fn pay_directly(matches: &ArgMatches) -> Result<Response, Error> {
+    let value = |name| matches.value_of(name).unwrap(); // infallible unwrap
+    let source_amount = value("source_amount")
+        .parse()
+        .map_err(|_| Error::Usage("ilp-cli help spsp pay"))?;
+    let slippage = value("slippage")
+        .parse()
+        .map_err(|_| Error::Usage("ilp-cli help spsp pay"))?;
+    // The blocking client of the other commands may not be used in the runtime
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let receipt = runtime.block_on(direct::pay(
+        value("http_url"),
+        value("http_token"),
+        value("receiver"),
+        source_amount,
+        slippage,
+    ))?;
+    Ok(Response::from(
+        http::Response::builder()
+            .body(serde_json::to_string(&receipt)?)
+            .unwrap(), // infallible unwrap
+    ))
+}
+
 // This function takes the map of arguments parsed by Clap
 // and extracts the values for each argument.
 fn extract_args<'a>(matches: &'a ArgMatches) -> (&'a str, HashMap<&'a str, &'a str>) {
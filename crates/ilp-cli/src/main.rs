@@ -1,3 +1,4 @@
+mod direct;
 mod interpreter;
 mod parser;
 use std::process::exit;
@@ -72,6 +73,14 @@ mod interface_tests {
         ]);
     }
 
+    #[test]
+    fn accounts_balance_changes() {
+        should_parse(&[
+            "ilp-cli accounts balance-changes alice --auth foo", // minimal
+            "ilp-cli accounts balance-changes alice --auth foo --from 100 --limit 10", // maximal
+        ]);
+    }
+
     #[test]
     fn accounts_create() {
         should_parse(&[
@@ -121,6 +130,22 @@ mod interface_tests {
     fn accounts_list() {
         should_parse(&[
             "ilp-cli accounts list --auth foo", // minimal
+            "ilp-cli accounts list --auth foo --after 2f3b5bbd-62e0-4d0c-b8b3-91ad1fc9ba24 --limit 10 --asset-code XYZ --routing-relation Peer --ilp-address-prefix example.", // maximal
+        ]);
+    }
+
+    #[test]
+    fn accounts_reconciliation() {
+        should_parse(&[
+            "ilp-cli accounts reconciliation alice --auth foo", // minimal
+            "ilp-cli accounts reconciliation alice --auth foo --tolerance 10", // maximal
+        ]);
+    }
+
+    #[test]
+    fn accounts_events() {
+        should_parse(&[
+            "ilp-cli accounts events alice --auth foo", // minimal
         ]);
     }
 
@@ -137,6 +162,44 @@ mod interface_tests {
     fn pay() {
         should_parse(&[
             "ilp-cli pay alice --auth foo --amount 500 --to bar", // minimal
+            "ilp-cli pay alice --auth foo --amount 500 --destination example.bar --shared-secret qux --slippage 0.01", // STREAM destination
+        ]);
+    }
+
+    #[test]
+    fn pay_without_receiver() {
+        let mut app = crate::parser::build();
+        for example in &[
+            "ilp-cli pay alice --auth foo --amount 500",
+            "ilp-cli pay alice --auth foo --amount 500 --destination example.bar",
+            "ilp-cli pay alice --auth foo --amount 500 --to bar --destination example.bar --shared-secret qux",
+        ] {
+            assert!(app.get_matches_from_safe_borrow(example.split(' ')).is_err());
+        }
+    }
+
+    #[test]
+    fn payments_streams() {
+        should_parse(&[
+            "ilp-cli payments stream alice --auth foo --rate 10 --to bar", // minimal
+            "ilp-cli payments stream alice --auth foo --rate 10 --max-amount 500 --destination example.bar --shared-secret qux --slippage 0.01", // maximal
+            "ilp-cli payments stream-status alice 9b5ac1a4-8b6f-4a7f-8bd1-ef20a4c7d2e3 --auth foo",
+            "ilp-cli payments stream-stop alice 9b5ac1a4-8b6f-4a7f-8bd1-ef20a4c7d2e3 --auth foo",
+        ]);
+    }
+
+    #[test]
+    fn reconciliation() {
+        should_parse(&[
+            "ilp-cli reconciliation --auth foo",                // minimal
+            "ilp-cli reconciliation --auth foo --tolerance 10", // maximal
+        ]);
+    }
+
+    #[test]
+    fn events() {
+        should_parse(&[
+            "ilp-cli events --auth foo", // minimal
         ]);
     }
 
@@ -191,6 +254,33 @@ mod interface_tests {
         ]);
     }
 
+    #[test]
+    fn export() {
+        should_parse(&[
+            "ilp-cli export --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn import() {
+        let example = format!(
+            "ilp-cli import {}/Cargo.toml --auth foo",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        should_parse(&[
+            example.as_str(), // minimal
+        ]);
+    }
+
+    #[test]
+    fn spsp() {
+        should_parse(&[
+            "ilp-cli spsp query $localhost:1/alice",
+            "ilp-cli spsp pay --http-url http://localhost:1/ilp --http-token foo --amount 500 --to http://localhost:1/alice", // minimal
+            "ilp-cli spsp pay --http-url http://localhost:1/ilp --http-token foo --amount 500 --to http://localhost:1/alice --slippage 0.01", // maximal
+        ]);
+    }
+
     #[test]
     fn status() {
         should_parse(&[
@@ -198,6 +288,14 @@ mod interface_tests {
         ]);
     }
 
+    #[test]
+    fn testnet_connect() {
+        should_parse(&[
+            "ilp-cli testnet connect btp+wss://:token@connector.example/ilp/btp --auth foo", // minimal
+            "ilp-cli testnet connect https://connector.example/accounts/alice/ilp --auth foo --token bar --username parent --asset-code EUR --asset-scale 2", // maximal
+        ]);
+    }
+
     #[test]
     fn testnet_setup() {
         should_parse(&[
@@ -219,7 +317,8 @@ mod interface_tests {
                     Ok(_)
                     | Err(Error::Send(_))
                     | Err(Error::WebsocketErr(_))
-                    | Err(Error::Testnet(_)) => (),
+                    | Err(Error::Testnet(_))
+                    | Err(Error::DirectPayment(_)) => (),
                     Err(e) => panic!("Unexpected interpreter failure: {}", e),
                 },
             }
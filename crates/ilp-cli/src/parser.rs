@@ -4,11 +4,14 @@ pub fn build<'a, 'b>() -> App<'a, 'b> {
     ilp_cli().subcommands(vec![
         accounts().subcommands(vec![
             accounts_balance(),
+            accounts_balance_changes(),
             accounts_create(),
             accounts_delete(),
+            accounts_events(),
             accounts_incoming_payments(),
             accounts_info(),
             accounts_list(),
+            accounts_reconciliation(),
             accounts_update(),
             accounts_update_settings(),
         ]),
@@ -16,10 +19,20 @@ pub fn build<'a, 'b>() -> App<'a, 'b> {
         rates().subcommands(vec![rates_list(), rates_set_all()]),
         routes().subcommands(vec![routes_list(), routes_set(), routes_set_all()]),
         settlement_engines().subcommands(vec![settlement_engines_set_all()]),
+        reconciliation(),
+        events(),
         status(),
         logs(),
-        testnet().subcommands(vec![testnet_setup()]),
-        payments().subcommands(vec![payments_incoming()]),
+        export(),
+        import(),
+        testnet().subcommands(vec![testnet_connect(), testnet_setup()]),
+        payments().subcommands(vec![
+            payments_incoming(),
+            payments_stream(),
+            payments_stream_status(),
+            payments_stream_stop(),
+        ]),
+        spsp().subcommands(vec![spsp_query(), spsp_pay()]),
     ])
 }
 
@@ -78,6 +91,26 @@ fn accounts_balance<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
+fn accounts_balance_changes<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("balance-changes")
+        .about("Returns the balance change log of an account, oldest first")
+        .args(&[
+            Arg::with_name("username")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The username of the account whose balance changes to return"),
+            Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .help("The position of the first returned entry in the log"),
+            Arg::with_name("limit")
+                .long("limit")
+                .takes_value(true)
+                .help("The maximum number of returned entries"),
+        ])
+}
+
 fn accounts_create<'a, 'b>() -> App<'a, 'b> {
     AuthorizedSubCommand::with_name("create")
         .about("Creates a new account on this node")
@@ -104,6 +137,9 @@ fn accounts_create<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("max_packet_amount")
                 .long("max-packet-amount")
                 .takes_value(true),
+            Arg::with_name("min_packet_amount")
+                .long("min-packet-amount")
+                .takes_value(true),
             Arg::with_name("min_balance")
                 .long("min-balance")
                 .takes_value(true),
@@ -131,9 +167,15 @@ fn accounts_create<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("settle_to")
                 .long("settle-to")
                 .takes_value(true),
+            Arg::with_name("settle_every")
+                .long("settle-every")
+                .takes_value(true),
             Arg::with_name("routing_relation")
                 .long("routing-relation")
                 .takes_value(true),
+            Arg::with_name("routing_preference")
+                .long("routing-preference")
+                .takes_value(true),
             Arg::with_name("round_trip_time")
                 .long("round-trip-time")
                 .takes_value(true),
@@ -143,6 +185,12 @@ fn accounts_create<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("packets_per_minute_limit")
                 .long("packets-per-minute-limit")
                 .takes_value(true),
+            Arg::with_name("max_payment_amount")
+                .long("max-payment-amount")
+                .takes_value(true),
+            Arg::with_name("amount_per_day_limit")
+                .long("amount-per-day-limit")
+                .takes_value(true),
             Arg::with_name("settlement_engine_url")
                 .long("settlement-engine-url")
                 .takes_value(true),
@@ -175,6 +223,9 @@ fn accounts_update<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("max_packet_amount")
                 .long("max-packet-amount")
                 .takes_value(true),
+            Arg::with_name("min_packet_amount")
+                .long("min-packet-amount")
+                .takes_value(true),
             Arg::with_name("min_balance")
                 .long("min-balance")
                 .takes_value(true),
@@ -202,9 +253,15 @@ fn accounts_update<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("settle_to")
                 .long("settle-to")
                 .takes_value(true),
+            Arg::with_name("settle_every")
+                .long("settle-every")
+                .takes_value(true),
             Arg::with_name("routing_relation")
                 .long("routing-relation")
                 .takes_value(true),
+            Arg::with_name("routing_preference")
+                .long("routing-preference")
+                .takes_value(true),
             Arg::with_name("round_trip_time")
                 .long("round-trip-time")
                 .takes_value(true),
@@ -214,6 +271,12 @@ fn accounts_update<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("packets_per_minute_limit")
                 .long("packets-per-minute-limit")
                 .takes_value(true),
+            Arg::with_name("max_payment_amount")
+                .long("max-payment-amount")
+                .takes_value(true),
+            Arg::with_name("amount_per_day_limit")
+                .long("amount-per-day-limit")
+                .takes_value(true),
             Arg::with_name("settlement_engine_url")
                 .long("settlement-engine-url")
                 .takes_value(true),
@@ -232,6 +295,18 @@ fn accounts_delete<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
+fn accounts_events<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("events")
+        .about("Open a persistent connection to a node for monitoring the packets, balance updates and peer connections of an account")
+        .arg(
+            Arg::with_name("username")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The username of the account to monitor"),
+        )
+}
+
 fn accounts_incoming_payments<'a, 'b>() -> App<'a, 'b> {
     AuthorizedSubCommand::with_name("incoming-payments")
         .about("Open a persistent connection to a node for monitoring incoming payments to an account [COMING SOON]")
@@ -260,7 +335,48 @@ fn accounts_info<'a, 'b>() -> App<'a, 'b> {
 }
 
 fn accounts_list<'a, 'b>() -> App<'a, 'b> {
-    AuthorizedSubCommand::with_name("list").about("List all accounts on this node")
+    AuthorizedSubCommand::with_name("list")
+        .about("List all accounts on this node, or one page of them if any option is given")
+        .args(&[
+            Arg::with_name("after")
+                .long("after")
+                .takes_value(true)
+                .help(
+                "Only list the accounts after this account ID (the last ID of the previous page)",
+            ),
+            Arg::with_name("limit")
+                .long("limit")
+                .takes_value(true)
+                .help("The maximum number of accounts to list"),
+            Arg::with_name("asset_code")
+                .long("asset-code")
+                .takes_value(true)
+                .help("Only list the accounts with this asset code"),
+            Arg::with_name("routing_relation")
+                .long("routing-relation")
+                .takes_value(true)
+                .help("Only list the accounts with this routing relation"),
+            Arg::with_name("ilp_address_prefix")
+                .long("ilp-address-prefix")
+                .takes_value(true)
+                .help("Only list the accounts whose ILP address starts with this prefix"),
+        ])
+}
+
+fn accounts_reconciliation<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("reconciliation")
+        .about("Compare the amounts cleared with an account to the amounts settled with it")
+        .args(&[
+            Arg::with_name("username")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The username of the account to reconcile"),
+            Arg::with_name("tolerance")
+                .long("tolerance")
+                .takes_value(true)
+                .help("The largest drift, in the account's asset scale, which is not flagged"),
+        ])
 }
 
 fn accounts_update_settings<'a, 'b>() -> App<'a, 'b> {
@@ -296,6 +412,9 @@ fn accounts_update_settings<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("settle_to")
                 .long("settle-to")
                 .takes_value(true),
+            Arg::with_name("settle_every")
+                .long("settle-every")
+                .takes_value(true),
         ])
 }
 
@@ -317,8 +436,23 @@ fn pay<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("receiver")
                 .long("to")
                 .takes_value(true)
-                .required(true)
+                .required_unless("destination_account")
+                .conflicts_with("destination_account")
                 .help("The Payment Pointer or SPSP address of the account receiving the payment"),
+            Arg::with_name("destination_account")
+                .long("destination")
+                .takes_value(true)
+                .requires("shared_secret")
+                .help("The ILP address of a STREAM receiver, which is used instead of a Payment Pointer"),
+            Arg::with_name("shared_secret")
+                .long("shared-secret")
+                .takes_value(true)
+                .requires("destination_account")
+                .help("The base64-encoded STREAM shared secret of the destination"),
+            Arg::with_name("slippage")
+                .long("slippage")
+                .takes_value(true)
+                .help("The maximum acceptable slippage below the exchange rate, as a fraction"),
         ])
 }
 
@@ -406,6 +540,40 @@ fn logs<'a, 'b>() -> App<'a, 'b> {
             .help("The desired log level (error, debug, trace)")])
 }
 
+fn export<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("export")
+        .about("Export all accounts (including their tokens and balances), static routes and settlement engines of this node as JSON")
+}
+
+fn import<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("import")
+        .about("Import the output of the export command into this node, which must not have any accounts yet")
+        .arg(
+            Arg::with_name("file")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The file containing the exported data"),
+        )
+}
+
+fn reconciliation<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("reconciliation")
+        .about("Compare the amounts cleared with each account to the amounts settled with it")
+        .arg(
+            Arg::with_name("tolerance")
+                .long("tolerance")
+                .takes_value(true)
+                .help("The largest drift, in each account's asset scale, which is not flagged"),
+        )
+}
+
+fn events<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("events").about(
+        "Open a persistent connection to a node for monitoring all packets, balance updates and peer connections",
+    )
+}
+
 fn status<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("status").about("Query the status of the server")
 }
@@ -414,6 +582,34 @@ fn testnet<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("testnet").about("Easily access the testnet")
 }
 
+fn testnet_connect<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("connect")
+        .about("Connect this node to a testnet or bootstrap connector as its parent, from which it gets its ILP address and default route")
+        .args(&[
+            Arg::with_name("url")
+                .index(1)
+                .required(true)
+                .takes_value(true)
+                .help("The BTP or ILP-over-HTTP URL of the connector, with the token as its password, e.g. btp+wss://:token@connector.example/ilp/btp"),
+            Arg::with_name("token")
+                .long("token")
+                .takes_value(true)
+                .help("The token with which the node authenticates to the connector, if the URL has none"),
+            Arg::with_name("username")
+                .long("username")
+                .default_value("testnet")
+                .help("The username of the connector's account on this node"),
+            Arg::with_name("asset_code")
+                .long("asset-code")
+                .default_value("XRP")
+                .help("The code of the asset of the connector's account"),
+            Arg::with_name("asset_scale")
+                .long("asset-scale")
+                .default_value("9")
+                .help("The scale of the asset of the connector's account"),
+        ])
+}
+
 fn testnet_setup<'a, 'b>() -> App<'a, 'b> {
     AuthorizedSubCommand::with_name("setup")
         .about("Create a local account peered with a remote node on the testnet")
@@ -432,10 +628,136 @@ fn testnet_setup<'a, 'b>() -> App<'a, 'b> {
 }
 
 fn payments<'a, 'b>() -> App<'a, 'b> {
-    SubCommand::with_name("payments").about("All incoming payments")
+    SubCommand::with_name("payments").about("Operations for interacting with payments")
 }
 
 fn payments_incoming<'a, 'b>() -> App<'a, 'b> {
     AuthorizedSubCommand::with_name("incoming")
         .about("Open a persistent connection to a node for monitoring all incoming payments")
 }
+
+fn payments_stream<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("stream")
+        .about("Start sending money continuously from an account on this node, until the payment is stopped")
+        .args(&[
+            Arg::with_name("username")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The username of the account on this node sending the payment"),
+            Arg::with_name("rate_per_second")
+                .long("rate")
+                .takes_value(true)
+                .required(true)
+                .help("The amount sent per second, denominated in units of the sender's assets"),
+            Arg::with_name("max_amount")
+                .long("max-amount")
+                .takes_value(true)
+                .help("The amount after which the payment stops on its own"),
+            Arg::with_name("receiver")
+                .long("to")
+                .takes_value(true)
+                .required_unless("destination_account")
+                .conflicts_with("destination_account")
+                .help("The Payment Pointer or SPSP address of the account receiving the payment"),
+            Arg::with_name("destination_account")
+                .long("destination")
+                .takes_value(true)
+                .requires("shared_secret")
+                .help("The ILP address of a STREAM receiver, which is used instead of a Payment Pointer"),
+            Arg::with_name("shared_secret")
+                .long("shared-secret")
+                .takes_value(true)
+                .requires("destination_account")
+                .help("The base64-encoded STREAM shared secret of the destination"),
+            Arg::with_name("slippage")
+                .long("slippage")
+                .takes_value(true)
+                .help("The maximum acceptable slippage below the exchange rate, as a fraction"),
+        ])
+}
+
+fn payments_stream_status<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("stream-status")
+        .about("Show the status and receipt of a streaming payment")
+        .args(&[
+            Arg::with_name("username")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The username of the account sending the payment"),
+            Arg::with_name("id")
+                .index(2)
+                .takes_value(true)
+                .required(true)
+                .help("The ID of the streaming payment"),
+        ])
+}
+
+fn payments_stream_stop<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("stream-stop")
+        .about("Stop a streaming payment")
+        .args(&[
+            Arg::with_name("username")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The username of the account sending the payment"),
+            Arg::with_name("id")
+                .index(2)
+                .takes_value(true)
+                .required(true)
+                .help("The ID of the streaming payment"),
+        ])
+}
+
+fn spsp<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("spsp")
+        .about("Operations for interacting with SPSP receivers directly, without a node")
+}
+
+fn spsp_query<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("query")
+        .about("Query the ILP address and shared secret of a Payment Pointer or SPSP URL")
+        .arg(
+            Arg::with_name("receiver")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The Payment Pointer or SPSP URL of the receiver"),
+        )
+}
+
+fn spsp_pay<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("pay")
+        .about("Send a payment straight to a connector with ILP-over-HTTP credentials, and print its receipt")
+        .args(&[
+            Arg::with_name("http_url")
+                .long("http-url")
+                .env("ILP_CLI_HTTP_URL")
+                .takes_value(true)
+                .required(true)
+                .help("The ILP-over-HTTP URL of the connector"),
+            Arg::with_name("http_token")
+                .long("http-token")
+                .env("ILP_CLI_HTTP_TOKEN")
+                .takes_value(true)
+                .required(true)
+                .help("The token with which the payment is authenticated to the connector"),
+            Arg::with_name("source_amount")
+                .long("amount")
+                .takes_value(true)
+                .required(true)
+                .help("The amount to send, denominated in units of the asset of the account on the connector"),
+            Arg::with_name("receiver")
+                .long("to")
+                .takes_value(true)
+                .required(true)
+                .help("The Payment Pointer or SPSP URL of the receiver"),
+            Arg::with_name("slippage")
+                .long("slippage")
+                .takes_value(true)
+                .default_value("0.015")
+                .help("The maximum acceptable slippage below the exchange rate, as a fraction. Only enforced when the receiver uses the same asset"),
+        ])
+}
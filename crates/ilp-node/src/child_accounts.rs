@@ -0,0 +1,120 @@
+use interledger::{
+    btp::ConnectionEvent,
+    ccp::{CcpRouteManager, CcpRoutingAccount, CcpRoutingStore, RoutingRelation},
+    ildcp::is_ildcp_request,
+    service::{
+        runtime, Account as AccountTrait, AccountStore, AddressStore, BoxedIlpFuture,
+        IncomingRequest, IncomingService, OutgoingService,
+    },
+    store::account::Account,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, warn};
+
+/// Incoming service which answers the IL-DCP requests of child accounts with their current
+/// account, so that the children connecting without a configured address get the one the
+/// node gave them, without any manual step.
+///
+/// The BTP connections keep the account they authenticated as, so a child which connected
+/// before the node got its own address from its parent would be told the address it had at
+/// the time. The account is reloaded from the store instead, since the store moves the
+/// addresses of the children under the node's whenever it changes.
+#[derive(Clone)]
+pub struct ChildAccountsService<S, I> {
+    store: S,
+    next: I,
+}
+
+impl<S, I> ChildAccountsService<S, I> {
+    pub fn new(store: S, next: I) -> Self {
+        ChildAccountsService { store, next }
+    }
+}
+
+impl<S, I> IncomingService<Account> for ChildAccountsService<S, I>
+where
+    S: AccountStore<Account = Account> + Clone + Send + Sync + 'static,
+    I: IncomingService<Account> + Send,
+{
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    fn handle_request(&mut self, mut request: IncomingRequest<Account>) -> Self::Future<'_> {
+        Box::pin(async move {
+            if request.from.routing_relation() != RoutingRelation::Child
+                || !is_ildcp_request(&request.prepare)
+            {
+                return self.next.handle_request(request).await;
+            }
+
+            let id = request.from.id();
+            match self.store.get_accounts(vec![id]).await {
+                Ok(mut accounts) if !accounts.is_empty() => request.from = accounts.remove(0),
+                Ok(_) => {
+                    warn!(account.id = %id, "Child account not found, answering its IL-DCP request with the connected one");
+                }
+                Err(err) => {
+                    warn!(account.id = %id, "Error reloading the child account, answering its IL-DCP request with the connected one: {}", err);
+                }
+            }
+
+            debug!(account.id = %id, "Serving IL-DCP to child account at {}", request.from.ilp_address());
+            self.next.handle_request(request).await
+        })
+    }
+}
+
+/// Installs the route to the address of the children as soon as they connect over BTP,
+/// so that they can be paid at the address they get with IL-DCP. The route is part of
+/// the routing table which the CCP route manager maintains, so it follows the address of
+/// the child when the node's address changes.
+pub fn spawn_child_routes<I, O, S>(
+    store: S,
+    ccp: CcpRouteManager<I, O, S, Account>,
+    connection_events: Vec<broadcast::Receiver<ConnectionEvent>>,
+) where
+    I: IncomingService<Account> + Clone + Send + Sync + 'static,
+    O: OutgoingService<Account> + Clone + Send + Sync + 'static,
+    S: AccountStore<Account = Account>
+        + AddressStore
+        + CcpRoutingStore<Account = Account>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    for mut connection_events in connection_events {
+        let store = store.clone();
+        let ccp = ccp.clone();
+        runtime::spawn(async move {
+            loop {
+                let id = match connection_events.recv().await {
+                    Ok(ConnectionEvent::Connected(id)) => id,
+                    Ok(ConnectionEvent::Disconnected(_)) => continue,
+                    Err(RecvError::Lagged(skipped)) => {
+                        debug!("Missed {} peer connection events", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                let account = match store.get_accounts(vec![id]).await {
+                    Ok(mut accounts) if !accounts.is_empty() => accounts.remove(0),
+                    Ok(_) => {
+                        warn!(account.id = %id, "Connected account not found");
+                        continue;
+                    }
+                    Err(err) => {
+                        warn!(account.id = %id, "Error loading the connected account: {}", err);
+                        continue;
+                    }
+                };
+                if account.routing_relation() != RoutingRelation::Child {
+                    continue;
+                }
+                debug!(account.id = %id, "Child account connected at {}", account.ilp_address());
+                if let Err(err) = ccp.update_local_routes(id).await {
+                    warn!(account.id = %id, "Error installing the route to the child account: {}", err);
+                }
+            }
+        });
+    }
+}
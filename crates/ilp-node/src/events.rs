@@ -0,0 +1,221 @@
+use interledger::{
+    api::{EventAccount, NodeEvent, NodeEvents},
+    btp::ConnectionEvent,
+    ccp::{CcpRouteManager, CcpRoutingAccount, CcpRoutingStore},
+    service::{
+        runtime, Account, AccountStore, AddressStore, BoxedIlpFuture, IncomingService,
+        OutgoingRequest, OutgoingService,
+    },
+    service_util::BalanceStore,
+    settlement::core::SettlementEvent,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, warn};
+
+// Number of events buffered for the WebSocket subscribers which are slow to receive them
+pub const NODE_EVENTS_CAPACITY: usize = 1024;
+
+/// Outgoing service that publishes the fulfilled and rejected packets, and the balances
+/// they changed, to the subscribers of the node's events.
+#[derive(Clone)]
+pub struct EventsService<S, O> {
+    store: S,
+    events: NodeEvents,
+    next: O,
+}
+
+impl<S, O> EventsService<S, O> {
+    pub fn new(store: S, events: NodeEvents, next: O) -> Self {
+        EventsService {
+            store,
+            events,
+            next,
+        }
+    }
+}
+
+impl<S, O, A> OutgoingService<A> for EventsService<S, O>
+where
+    S: BalanceStore + Clone + Send + Sync + 'static,
+    O: OutgoingService<A> + Send,
+    A: Account + 'static,
+{
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            // Skip building the events if nobody is listening
+            if self.events.receiver_count() == 0 {
+                return self.next.send_request(request).await;
+            }
+
+            let from = EventAccount::new(&request.from);
+            let to = EventAccount::new(&request.to);
+            let destination = request.prepare.destination().to_string();
+            let source_amount = request.original_amount;
+            let amount = request.prepare.amount();
+            let result = self.next.send_request(request).await;
+
+            match result {
+                Ok(_) => {
+                    let _ = self.events.send(NodeEvent::PacketFulfilled {
+                        from: from.clone(),
+                        to: to.clone(),
+                        destination,
+                        source_amount,
+                        amount,
+                    });
+                    if amount > 0 {
+                        runtime::spawn(publish_balances(
+                            self.store.clone(),
+                            self.events.clone(),
+                            vec![from, to],
+                        ));
+                    }
+                }
+                Err(ref reject) => {
+                    let _ = self.events.send(NodeEvent::PacketRejected {
+                        from,
+                        to,
+                        destination,
+                        source_amount,
+                        amount,
+                        code: reject.code().to_string(),
+                        message: String::from_utf8_lossy(reject.message()).to_string(),
+                    });
+                }
+            }
+            result
+        })
+    }
+}
+
+async fn publish_balances<S: BalanceStore>(
+    store: S,
+    events: NodeEvents,
+    accounts: Vec<EventAccount>,
+) {
+    for account in accounts {
+        match store.get_balance(account.id).await {
+            Ok(balance) => {
+                let _ = events.send(NodeEvent::BalanceUpdated { account, balance });
+            }
+            Err(err) => warn!(account.id = %account.id, "Error loading balance: {}", err),
+        }
+    }
+}
+
+/// Publishes the peers connecting to and disconnecting from the BTP services
+pub fn spawn_connection_events<S, A>(
+    store: S,
+    connection_events: Vec<broadcast::Receiver<ConnectionEvent>>,
+    events: NodeEvents,
+) where
+    S: AccountStore<Account = A> + Clone + Send + Sync + 'static,
+    A: Account + 'static,
+{
+    for mut connection_events in connection_events {
+        let store = store.clone();
+        let events = events.clone();
+        runtime::spawn(async move {
+            loop {
+                let (id, connected) = match connection_events.recv().await {
+                    Ok(ConnectionEvent::Connected(id)) => (id, true),
+                    Ok(ConnectionEvent::Disconnected(id)) => (id, false),
+                    Err(RecvError::Lagged(skipped)) => {
+                        debug!("Missed {} peer connection events", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                if events.receiver_count() == 0 {
+                    continue;
+                }
+                let account = match store.get_accounts(vec![id]).await {
+                    Ok(mut accounts) if !accounts.is_empty() => {
+                        EventAccount::new(&accounts.remove(0))
+                    }
+                    Ok(_) | Err(_) => {
+                        warn!(account.id = %id, "Error loading the connected peer account");
+                        continue;
+                    }
+                };
+                let _ = events.send(if connected {
+                    NodeEvent::PeerConnected { account }
+                } else {
+                    NodeEvent::PeerDisconnected { account }
+                });
+            }
+        });
+    }
+}
+
+/// Publishes the settlements sent to and received from the settlement engines
+pub fn spawn_settlement_events<S, A>(
+    store: S,
+    mut settlement_events: broadcast::Receiver<SettlementEvent>,
+    events: NodeEvents,
+) where
+    S: AccountStore<Account = A> + Clone + Send + Sync + 'static,
+    A: Account + 'static,
+{
+    runtime::spawn(async move {
+        loop {
+            let settlement = match settlement_events.recv().await {
+                Ok(settlement) => settlement,
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("Missed {} settlement events", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            if events.receiver_count() == 0 {
+                continue;
+            }
+            let id = settlement.account_id();
+            let account = match store.get_accounts(vec![id]).await {
+                Ok(mut accounts) if !accounts.is_empty() => EventAccount::new(&accounts.remove(0)),
+                Ok(_) | Err(_) => {
+                    warn!(account.id = %id, "Error loading the settled account");
+                    continue;
+                }
+            };
+            let _ = events.send(NodeEvent::Settlement {
+                account,
+                settlement,
+            });
+        }
+    });
+}
+
+/// Fails over to the next best routes as soon as a peer disconnects, instead of waiting
+/// for its routes to be withdrawn, and requests the routing table of the peers which connect
+pub fn spawn_route_failover<I, O, S, A>(
+    ccp: CcpRouteManager<I, O, S, A>,
+    connection_events: Vec<broadcast::Receiver<ConnectionEvent>>,
+) where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    S: AddressStore + CcpRoutingStore<Account = A> + Clone + Send + Sync + 'static,
+    A: CcpRoutingAccount + Send + Sync + 'static,
+{
+    for mut connection_events in connection_events {
+        let ccp = ccp.clone();
+        runtime::spawn(async move {
+            loop {
+                match connection_events.recv().await {
+                    Ok(ConnectionEvent::Connected(id)) => ccp.next_hop_connected(id).await,
+                    Ok(ConnectionEvent::Disconnected(id)) => {
+                        if let Err(err) = ccp.next_hop_disconnected(id).await {
+                            warn!(account.id = %id, "Error failing over the routes of the peer: {}", err);
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        debug!("Missed {} peer connection events", skipped);
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,212 @@
+use interledger::{
+    api::NodeStore,
+    packet::{ErrorCode, RejectBuilder},
+    service::{Account, BoxedIlpFuture, IncomingRequest, IncomingService},
+};
+use serde::Deserialize;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Configuration of the active/standby failover between two nodes sharing a store. Both
+/// nodes connect to their peers and keep their routing tables up to date from the store,
+/// but only the one holding the leadership in the store handles packets. The other one
+/// takes over once the lease of the active node expires without being renewed.
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct FailoverConfig {
+    /// Id of the node, which must differ between the two nodes. Defaults to a random one.
+    #[serde(default = "FailoverConfig::default_node_id")]
+    pub node_id: String,
+    /// Time, in milliseconds, for which the active node keeps the leadership after it last
+    /// renewed it. It is renewed three times per lease. Defaults to 5000.
+    #[serde(default = "FailoverConfig::default_lease")]
+    pub lease: u64,
+}
+
+impl FailoverConfig {
+    fn default_node_id() -> String {
+        Uuid::new_v4().to_string()
+    }
+
+    fn default_lease() -> u64 {
+        5000
+    }
+}
+
+/// Whether the node is the active one, which is shared by the services of the node
+#[derive(Clone)]
+pub struct Leadership {
+    active: Arc<AtomicBool>,
+}
+
+impl Leadership {
+    /// A node which is always active
+    pub fn active() -> Self {
+        Leadership {
+            active: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Claims the leadership in the store and renews it for as long as the node runs.
+    /// The node is on standby until it holds the leadership.
+    pub fn spawn<S: NodeStore>(store: S, config: FailoverConfig) -> Self {
+        let leadership = Leadership {
+            active: Arc::new(AtomicBool::new(false)),
+        };
+        let active = leadership.active.clone();
+        let lease = Duration::from_millis(config.lease.max(1));
+        info!(target: "interledger-node", "Starting on standby as node {}", config.node_id);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(lease / 3);
+            let mut renewed_at: Option<Instant> = None;
+            loop {
+                interval.tick().await;
+                let claimed = match store.claim_leadership(&config.node_id, lease).await {
+                    Ok(claimed) => claimed,
+                    Err(err) => {
+                        error!(target: "interledger-node", "Error claiming the leadership: {}", err);
+                        // The node stays active for as long as its last renewal lasts, after
+                        // which the other node may have taken over
+                        renewed_at.map_or(false, |renewed_at| renewed_at.elapsed() < lease)
+                    }
+                };
+                if claimed {
+                    renewed_at = Some(Instant::now());
+                }
+                let was_active = active.swap(claimed, Ordering::SeqCst);
+                match (was_active, claimed) {
+                    (false, true) => {
+                        info!(target: "interledger-node", "Node {} is now the active node", config.node_id)
+                    }
+                    (true, false) => {
+                        warn!(target: "interledger-node", "Node {} lost the leadership and is now on standby", config.node_id)
+                    }
+                    _ => {}
+                }
+            }
+        });
+        leadership
+    }
+
+    /// Whether the node currently holds the leadership
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// Wraps the incoming service so that it only handles packets while the node is active
+    pub fn service<I>(&self, next: I) -> StandbyService<I> {
+        StandbyService {
+            leadership: self.clone(),
+            next,
+        }
+    }
+}
+
+/// Incoming service which rejects the packets with T03 (Connector Busy) while the node is on
+/// standby, so that the senders retry them through the active node.
+#[derive(Clone)]
+pub struct StandbyService<I> {
+    leadership: Leadership,
+    next: I,
+}
+
+impl<I, A> IncomingService<A> for StandbyService<I>
+where
+    I: IncomingService<A> + Send,
+    A: Account + 'static,
+{
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            if !self.leadership.is_active() {
+                return Err(RejectBuilder {
+                    code: ErrorCode::T03_CONNECTOR_BUSY,
+                    message: b"Node is on standby",
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build());
+            }
+            self.next.handle_request(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger::{
+        packet::{Address, FulfillBuilder, PrepareBuilder},
+        service::{incoming_service_fn, Username},
+    };
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    #[derive(Clone, Debug)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    fn request() -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount,
+            prepare: PrepareBuilder {
+                destination: EXAMPLE_ADDRESS.clone(),
+                amount: 100,
+                execution_condition: &[0; 32],
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[tokio::test]
+    async fn only_handles_packets_while_active() {
+        let leadership = Leadership::active();
+        let mut service =
+            leadership.service(incoming_service_fn(|_: IncomingRequest<TestAccount>| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }));
+        assert!(service.handle_request(request()).await.is_ok());
+
+        leadership.active.store(false, Ordering::SeqCst);
+        let reject = service.handle_request(request()).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T03_CONNECTOR_BUSY);
+        assert_eq!(reject.message(), b"Node is on standby");
+    }
+}
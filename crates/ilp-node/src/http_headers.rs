@@ -0,0 +1,299 @@
+use serde::{de::Error as DeserializeError, Deserialize, Deserializer};
+use std::time::Duration;
+use url::Url;
+use warp::{
+    filters::{cors, BoxedFilter},
+    http::{
+        header::{
+            HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY,
+            STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+        },
+        HeaderMap, Method,
+    },
+    Filter, Rejection, Reply,
+};
+
+/// Headers which the node adds to the responses of the HTTP API, which includes the SPSP
+/// and ILP-over-HTTP endpoints
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct HttpHeadersConfig {
+    /// CORS policy for browser-based clients, such as web wallets, which are served from
+    /// other origins. If this is not set, no CORS headers are sent, so browsers only allow
+    /// requests from the node's own origin.
+    #[serde(default)]
+    pub cors: Option<CorsConfig>,
+    /// Whether to add the `X-Content-Type-Options`, `X-Frame-Options`, `Referrer-Policy`
+    /// and `Content-Security-Policy` headers. Defaults to true.
+    #[serde(default = "HttpHeadersConfig::default_security_headers")]
+    pub security_headers: bool,
+    /// `max-age`, in seconds, of the `Strict-Transport-Security` header, which is not sent
+    /// if this is not set. Should only be set if the node is served over HTTPS.
+    #[serde(default)]
+    pub hsts_max_age: Option<u64>,
+}
+
+impl HttpHeadersConfig {
+    fn default_security_headers() -> bool {
+        true
+    }
+}
+
+impl Default for HttpHeadersConfig {
+    fn default() -> Self {
+        HttpHeadersConfig {
+            cors: None,
+            security_headers: HttpHeadersConfig::default_security_headers(),
+            hsts_max_age: None,
+        }
+    }
+}
+
+/// The origins, methods and headers which browsers may use in cross-origin requests
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct CorsConfig {
+    /// Allowed origins, such as `https://wallet.example`, or `*` to allow any origin
+    #[serde(deserialize_with = "deserialize_origins")]
+    pub allowed_origins: Vec<String>,
+    /// Allowed methods. Defaults to `GET`, `POST`, `PUT` and `DELETE`.
+    #[serde(
+        default = "CorsConfig::default_allowed_methods",
+        deserialize_with = "deserialize_methods"
+    )]
+    pub allowed_methods: Vec<String>,
+    /// Allowed request headers. Defaults to `Authorization` and `Content-Type`.
+    #[serde(
+        default = "CorsConfig::default_allowed_headers",
+        deserialize_with = "deserialize_header_names"
+    )]
+    pub allowed_headers: Vec<String>,
+    /// Time, in seconds, for which browsers may cache the responses to preflight requests
+    #[serde(default)]
+    pub max_age: Option<u64>,
+}
+
+impl CorsConfig {
+    fn default_allowed_methods() -> Vec<String> {
+        vec![
+            "GET".to_string(),
+            "POST".to_string(),
+            "PUT".to_string(),
+            "DELETE".to_string(),
+        ]
+    }
+
+    fn default_allowed_headers() -> Vec<String> {
+        vec!["authorization".to_string(), "content-type".to_string()]
+    }
+
+    fn builder(&self) -> cors::Builder {
+        let mut builder = warp::cors()
+            .allow_methods(self.allowed_methods.iter().map(String::as_str))
+            .allow_headers(self.allowed_headers.iter().map(String::as_str));
+        builder = if self.allowed_origins.iter().any(|origin| origin == "*") {
+            builder.allow_any_origin()
+        } else {
+            builder.allow_origins(self.allowed_origins.iter().map(String::as_str))
+        };
+        if let Some(max_age) = self.max_age {
+            builder = builder.max_age(Duration::from_secs(max_age));
+        }
+        builder
+    }
+}
+
+fn deserialize_checked<'de, D>(
+    deserializer: D,
+    is_valid: fn(&str) -> bool,
+    kind: &str,
+) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let values = Vec::<String>::deserialize(deserializer)?;
+    match values.iter().find(|value| !is_valid(value)) {
+        Some(invalid) => Err(DeserializeError::custom(format!(
+            "invalid {}: {}",
+            kind, invalid
+        ))),
+        None => Ok(values),
+    }
+}
+
+/// Origins must be `*` or a scheme and a host with an optional port, without a path
+fn deserialize_origins<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_checked(
+        deserializer,
+        |origin| {
+            origin == "*"
+                || Url::parse(origin)
+                    .map(|url| url.origin().ascii_serialization() == origin)
+                    .unwrap_or(false)
+        },
+        "origin",
+    )
+}
+
+fn deserialize_methods<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_checked(
+        deserializer,
+        |method| Method::from_bytes(method.as_bytes()).is_ok(),
+        "method",
+    )
+}
+
+fn deserialize_header_names<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_checked(
+        deserializer,
+        |header| HeaderName::from_bytes(header.as_bytes()).is_ok(),
+        "header name",
+    )
+}
+
+/// Adds the configured security headers to the responses of the filter and applies the
+/// CORS policy, if any. Preflight requests and requests from origins which are not
+/// allowed are answered without calling the filter.
+pub(crate) fn with_http_headers<F, R>(
+    filter: F,
+    config: &HttpHeadersConfig,
+) -> BoxedFilter<(Box<dyn Reply>,)>
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply + 'static,
+{
+    let mut headers = HeaderMap::new();
+    if config.security_headers {
+        headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+        headers.insert(X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+        headers.insert(REFERRER_POLICY, HeaderValue::from_static("no-referrer"));
+        headers.insert(
+            CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static("default-src 'none'; frame-ancestors 'none'"),
+        );
+    }
+    if let Some(max_age) = config.hsts_max_age {
+        headers.insert(
+            STRICT_TRANSPORT_SECURITY,
+            HeaderValue::from_str(&format!("max-age={}", max_age)).unwrap(),
+        );
+    }
+
+    let filter = filter
+        .with(warp::reply::with::headers(headers))
+        .map(|reply| Box::new(reply) as Box<dyn Reply>);
+    match config.cors {
+        Some(ref cors) => filter
+            .with(cors.builder())
+            .map(|reply| Box::new(reply) as Box<dyn Reply>)
+            .boxed(),
+        None => filter.boxed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn api(config: HttpHeadersConfig) -> BoxedFilter<(Box<dyn Reply>,)> {
+        with_http_headers(
+            warp::path("accounts")
+                .map(|| "accounts")
+                .recover(|_: Rejection| async { Ok::<_, Rejection>("not found") }),
+            &config,
+        )
+    }
+
+    fn cors_config() -> HttpHeadersConfig {
+        serde_json::from_value(json!({
+            "cors": {
+                "allowed_origins": ["https://wallet.example"],
+                "max_age": 600,
+            },
+            "hsts_max_age": 31536000,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn adds_security_headers() {
+        let resp = warp::test::request()
+            .path("/accounts")
+            .reply(&api(HttpHeadersConfig::default()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers()["x-content-type-options"], "nosniff");
+        assert_eq!(resp.headers()["x-frame-options"], "DENY");
+        assert!(resp.headers().get("strict-transport-security").is_none());
+        assert!(resp.headers().get("access-control-allow-origin").is_none());
+
+        let resp = warp::test::request()
+            .path("/accounts")
+            .reply(&api(cors_config()))
+            .await;
+        assert_eq!(
+            resp.headers()["strict-transport-security"],
+            "max-age=31536000"
+        );
+    }
+
+    #[tokio::test]
+    async fn answers_preflight_requests_of_allowed_origins() {
+        let api = api(cors_config());
+        let resp = warp::test::request()
+            .method("OPTIONS")
+            .path("/accounts")
+            .header("origin", "https://wallet.example")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "authorization")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers()["access-control-allow-origin"],
+            "https://wallet.example"
+        );
+        assert_eq!(resp.headers()["access-control-max-age"], "600");
+
+        let resp = warp::test::request()
+            .method("OPTIONS")
+            .path("/accounts")
+            .header("origin", "https://evil.example")
+            .header("access-control-request-method", "POST")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), 403);
+
+        let resp = warp::test::request()
+            .path("/accounts")
+            .header("origin", "https://wallet.example")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers()["access-control-allow-origin"],
+            "https://wallet.example"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_cors_config() {
+        for cors in &[
+            json!({"allowed_origins": ["wallet.example"]}),
+            json!({"allowed_origins": ["https://wallet.example/path"]}),
+            json!({"allowed_origins": ["*"], "allowed_methods": ["GET POST"]}),
+            json!({"allowed_origins": ["*"], "allowed_headers": ["x header"]}),
+        ] {
+            assert!(serde_json::from_value::<CorsConfig>(cors.clone()).is_err());
+        }
+        assert!(serde_json::from_value::<CorsConfig>(json!({"allowed_origins": ["*"]})).is_ok());
+    }
+}
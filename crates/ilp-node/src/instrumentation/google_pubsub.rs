@@ -2,11 +2,11 @@ use chrono::Utc;
 use futures::{Future, TryFutureExt};
 use interledger::{
     packet::Address,
-    service::{Account, IlpResult, OutgoingRequest, OutgoingService, Username},
+    service::{Account, BoxedIlpFuture, OutgoingRequest, OutgoingService, Username},
 };
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, pin::Pin, sync::Arc};
 use tokio::{spawn, sync::Mutex};
 use tracing::{error, info};
 use yup_oauth2::{read_service_account_key, ServiceAccountAuthenticator};
@@ -53,66 +53,113 @@ struct PacketRecord {
     timestamp: String,
 }
 
-use std::pin::Pin;
-type BoxedIlpFuture = Box<dyn Future<Output = IlpResult> + Send + 'static>;
+type TokenFuture = Pin<Box<dyn Future<Output = Result<String, ()>> + Send>>;
+/// Fetches the OAuth token used to publish to PubSub. Its concrete type depends on the HTTP
+/// connector of the authenticator, so it is boxed; it is only called for fulfilled packets,
+/// in the task which publishes them
+type TokenFetcher = Arc<dyn Fn() -> TokenFuture + Send + Sync>;
+
+#[derive(Clone)]
+struct Publisher {
+    client: Client,
+    api_endpoint: Arc<String>,
+    fetch_token: TokenFetcher,
+}
 
-/// Create an Interledger service wrapper that publishes records
-/// of fulfilled packets to Google Cloud PubSub.
+/// Outgoing service that publishes records of fulfilled packets to Google Cloud PubSub.
 ///
 /// This is an experimental feature that may be removed in the future.
-pub async fn create_google_pubsub_wrapper<A: Account + 'static>(
-    config: Option<PubsubConfig>,
-) -> impl Fn(OutgoingRequest<A>, Box<dyn OutgoingService<A> + Send>) -> Pin<BoxedIlpFuture> + Clone
-{
-    // If Google credentials were passed in, create an HTTP client and
-    // OAuth2 client that will automatically fetch and cache access tokens
-    let utilities = if let Some(config) = config {
-        let key = read_service_account_key(config.service_account_credentials.as_str())
-            .await
-            .expect("Unable to load Google Cloud credentials from file");
-        let access = ServiceAccountAuthenticator::builder(key)
-            .build()
-            .await
-            .expect("Failed to build Google Cloud service account authenticator");
-        // This needs to be wrapped in a Mutex because the .token()
-        // method takes a mutable reference to self and we want to
-        // reuse the same fetcher so that it caches the tokens
-        let token_fetcher = Arc::new(Mutex::new(access));
-
-        // TODO make sure the client uses HTTP/2
-        let client = Client::new();
-        let api_endpoint = Arc::new(format!(
-            "https://pubsub.googleapis.com/v1/projects/{}/topics/{}:publish",
-            config.project_id, config.topic
-        ));
-        info!("Fulfilled packets will be submitted to Google Cloud Pubsub (project ID: {}, topic: {})", config.project_id, config.topic);
-        Some((client, api_endpoint, token_fetcher))
-    } else {
-        None
-    };
-
-    move |request: OutgoingRequest<A>,
-          mut next: Box<dyn OutgoingService<A> + Send>|
-          -> Pin<BoxedIlpFuture> {
-        let (client, api_endpoint, token_fetcher) = if let Some(ref utilities) = utilities {
-            utilities.clone()
+#[derive(Clone)]
+pub struct GooglePubsubService<O> {
+    publisher: Option<Publisher>,
+    next: O,
+}
+
+impl<O> GooglePubsubService<O> {
+    pub async fn new(config: Option<PubsubConfig>, next: O) -> Self {
+        // If Google credentials were passed in, create an HTTP client and
+        // OAuth2 client that will automatically fetch and cache access tokens
+        let publisher = if let Some(config) = config {
+            let key = read_service_account_key(config.service_account_credentials.as_str())
+                .await
+                .expect("Unable to load Google Cloud credentials from file");
+            let access = ServiceAccountAuthenticator::builder(key)
+                .build()
+                .await
+                .expect("Failed to build Google Cloud service account authenticator");
+            // This needs to be wrapped in a Mutex because the .token()
+            // method takes a mutable reference to self and we want to
+            // reuse the same fetcher so that it caches the tokens
+            let token_fetcher = Arc::new(Mutex::new(access));
+            let fetch_token: TokenFetcher = Arc::new(move || -> TokenFuture {
+                let token_fetcher = token_fetcher.clone();
+                Box::pin(async move {
+                    // additional block to highlight that the lock is only held for this request
+                    let token = {
+                        token_fetcher
+                            .lock()
+                            .await
+                            .token(TOKEN_SCOPES)
+                            .map_err(|err| {
+                                error!("Error fetching OAuth token for Google PubSub: {:?}", err)
+                            })
+                            .await?
+                    };
+                    Ok(token.as_str().to_string())
+                })
+            });
+
+            // TODO make sure the client uses HTTP/2
+            let client = Client::new();
+            let api_endpoint = Arc::new(format!(
+                "https://pubsub.googleapis.com/v1/projects/{}/topics/{}:publish",
+                config.project_id, config.topic
+            ));
+            info!("Fulfilled packets will be submitted to Google Cloud Pubsub (project ID: {}, topic: {})", config.project_id, config.topic);
+            Some(Publisher {
+                client,
+                api_endpoint,
+                fetch_token,
+            })
         } else {
-            return Box::pin(async move { next.send_request(request).await });
+            None
         };
 
-        // Just pass the request on if no Google Pubsub details were configured
-        let prev_hop_account = request.from.username().clone();
-        let prev_hop_asset_code = request.from.asset_code().to_string();
-        let prev_hop_asset_scale = request.from.asset_scale();
-        let prev_hop_amount = request.original_amount;
-        let next_hop_account = request.to.username().clone();
-        let next_hop_asset_code = request.to.asset_code().to_string();
-        let next_hop_asset_scale = request.to.asset_scale();
-        let next_hop_amount = request.prepare.amount();
-        let destination_ilp_address = request.prepare.destination();
+        GooglePubsubService { publisher, next }
+    }
+}
+
+impl<O, A> OutgoingService<A> for GooglePubsubService<O>
+where
+    O: OutgoingService<A> + Send,
+    A: Account + 'static,
+{
+    type Future<'a> = BoxedIlpFuture<'a>;
 
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
         Box::pin(async move {
-            let result = next.send_request(request).await;
+            // Just pass the request on if no Google Pubsub details were configured
+            let Publisher {
+                client,
+                api_endpoint,
+                fetch_token,
+            } = if let Some(ref publisher) = self.publisher {
+                publisher.clone()
+            } else {
+                return self.next.send_request(request).await;
+            };
+
+            let prev_hop_account = request.from.username().clone();
+            let prev_hop_asset_code = request.from.asset_code().to_string();
+            let prev_hop_asset_scale = request.from.asset_scale();
+            let prev_hop_amount = request.original_amount;
+            let next_hop_account = request.to.username().clone();
+            let next_hop_asset_code = request.to.asset_code().to_string();
+            let next_hop_asset_scale = request.to.asset_scale();
+            let next_hop_amount = request.prepare.amount();
+            let destination_ilp_address = request.prepare.destination();
+
+            let result = self.next.send_request(request).await;
 
             // Only fulfilled packets are published for now
             if let Ok(ref fulfill) = result {
@@ -123,17 +170,7 @@ pub async fn create_google_pubsub_wrapper<A: Account + 'static>(
                 // Note this means that if there is a problem submitting the
                 // packet record to PubSub, it will only log an error
                 spawn(async move {
-                    // additional block to highlight that the lock is only held for this request
-                    let token = {
-                        token_fetcher
-                            .lock()
-                            .await
-                            .token(TOKEN_SCOPES)
-                            .map_err(|err| {
-                                error!("Error fetching OAuth token for Google PubSub: {:?}", err)
-                            })
-                            .await?
-                    };
+                    let token = fetch_token().await?;
                     let record = PacketRecord {
                         prev_hop_account,
                         prev_hop_asset_code,
@@ -1,82 +1,179 @@
 use interledger::{
+    api::NodeStore,
     ccp::CcpRoutingAccount,
+    router::RouterStore,
     service::{
-        Account, IlpResult, IncomingRequest, IncomingService, OutgoingRequest, OutgoingService,
+        metrics::{increment_counter, record_histogram, update_gauge},
+        Account, BoxedIncomingService, BoxedOutgoingService, IlpResult, IncomingRequest,
+        IncomingService, OutgoingRequest, OutgoingService,
     },
+    service_util::BalanceStore,
 };
-use metrics::{self, labels, recorder, Key};
-use std::time::Instant;
+use once_cell::sync::Lazy;
+use std::{
+    fmt::Write,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+use uuid::Uuid;
+
+/// The `accounts_balance` gauges of the last sample, in the Prometheus text format.
+/// The recorder can't remove gauges, so the balances are served from this snapshot
+/// instead, which only has the accounts that existed when it was taken.
+static BALANCES: Lazy<RwLock<String>> = Lazy::new(Default::default);
 
 pub async fn incoming_metrics<A: Account + CcpRoutingAccount>(
     request: IncomingRequest<A>,
-    mut next: Box<dyn IncomingService<A> + Send>,
+    mut next: BoxedIncomingService<A>,
 ) -> IlpResult {
-    let labels = labels!(
-        "from_asset_code" => request.from.asset_code().to_string(),
-        "from_routing_relation" => request.from.routing_relation().to_string(),
-    );
-    recorder().increment_counter(
-        Key::from_name_and_labels("requests.incoming.prepare", labels.clone()),
-        1,
-    );
+    let from_asset_code = request.from.asset_code().to_string();
+    let from_routing_relation = request.from.routing_relation().to_string();
+    let labels = [
+        ("from_asset_code", from_asset_code.as_str()),
+        ("from_routing_relation", from_routing_relation.as_str()),
+    ];
+    increment_counter("requests.incoming.prepare", &labels, 1);
     let start_time = Instant::now();
 
     let result = next.handle_request(request).await;
     if result.is_ok() {
-        recorder().increment_counter(
-            Key::from_name_and_labels("requests.incoming.fulfill", labels.clone()),
-            1,
-        );
+        increment_counter("requests.incoming.fulfill", &labels, 1);
     } else {
-        recorder().increment_counter(
-            Key::from_name_and_labels("requests.incoming.reject", labels.clone()),
-            1,
-        );
+        increment_counter("requests.incoming.reject", &labels, 1);
     }
 
-    recorder().record_histogram(
-        Key::from_name_and_labels("requests.incoming.duration", labels),
-        (Instant::now() - start_time).as_nanos() as u64,
+    record_histogram(
+        "requests.incoming.duration",
+        &labels,
+        start_time.elapsed().as_nanos() as u64,
     );
     result
 }
 
 pub async fn outgoing_metrics<A: Account + CcpRoutingAccount>(
     request: OutgoingRequest<A>,
-    mut next: Box<dyn OutgoingService<A> + Send>,
+    mut next: BoxedOutgoingService<A>,
 ) -> IlpResult {
-    let labels = labels!(
-        "from_asset_code" => request.from.asset_code().to_string(),
-        "to_asset_code" => request.to.asset_code().to_string(),
-        "from_routing_relation" => request.from.routing_relation().to_string(),
-        "to_routing_relation" => request.to.routing_relation().to_string(),
-    );
-
-    // TODO replace these calls with the counter! macro if there's a way to easily pass in the already-created labels
-    // right now if you pass the labels into one of the other macros, it gets a recursion limit error while expanding the macro
-    recorder().increment_counter(
-        Key::from_name_and_labels("requests.outgoing.prepare", labels.clone()),
-        1,
-    );
+    let from_asset_code = request.from.asset_code().to_string();
+    let to_asset_code = request.to.asset_code().to_string();
+    let from_routing_relation = request.from.routing_relation().to_string();
+    let to_routing_relation = request.to.routing_relation().to_string();
+    let labels = [
+        ("from_asset_code", from_asset_code.as_str()),
+        ("to_asset_code", to_asset_code.as_str()),
+        ("from_routing_relation", from_routing_relation.as_str()),
+        ("to_routing_relation", to_routing_relation.as_str()),
+    ];
+    increment_counter("requests.outgoing.prepare", &labels, 1);
     let start_time = Instant::now();
 
     let result = next.send_request(request).await;
     if result.is_ok() {
-        recorder().increment_counter(
-            Key::from_name_and_labels("requests.outgoing.fulfill", labels.clone()),
-            1,
-        );
+        increment_counter("requests.outgoing.fulfill", &labels, 1);
     } else {
-        recorder().increment_counter(
-            Key::from_name_and_labels("requests.outgoing.reject", labels.clone()),
-            1,
-        );
+        increment_counter("requests.outgoing.reject", &labels, 1);
     }
 
-    recorder().record_histogram(
-        Key::from_name_and_labels("requests.outgoing.duration", labels),
-        (Instant::now() - start_time).as_nanos() as u64,
+    record_histogram(
+        "requests.outgoing.duration",
+        &labels,
+        start_time.elapsed().as_nanos() as u64,
     );
 
     result
 }
+
+/// Spawns a task which records the gauges that are sampled rather than updated per
+/// packet: the balance of every account, the size of the routing table and the
+/// number of open BTP connections (as returned by `connection_count`)
+pub fn spawn_gauge_sampler<S, F>(store: S, connection_count: F, interval: Duration)
+where
+    S: NodeStore + BalanceStore + RouterStore + Clone + Send + Sync + 'static,
+    F: Fn() -> usize + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            sample_gauges(&store, &connection_count).await;
+        }
+    });
+}
+
+async fn sample_gauges<S>(store: &S, connection_count: &(dyn Fn() -> usize + Send))
+where
+    S: NodeStore + BalanceStore + RouterStore,
+{
+    update_gauge("routes.count", &[], store.routing_table().len() as i64);
+    update_gauge("connections.btp", &[], connection_count() as i64);
+
+    let accounts = match store.get_all_accounts().await {
+        Ok(accounts) => accounts,
+        Err(err) => {
+            warn!(target: "interledger-node", "Error loading accounts for the balance metrics: {}", err);
+            return;
+        }
+    };
+    update_gauge("accounts.count", &[], accounts.len() as i64);
+    let mut balances = Vec::with_capacity(accounts.len());
+    for account in accounts {
+        match store.get_balance(account.id()).await {
+            Ok(balance) => balances.push((account.id(), account.asset_code().to_string(), balance)),
+            Err(err) => {
+                warn!(target: "interledger-node", "Error loading the balance of account {}: {}", account.id(), err)
+            }
+        }
+    }
+    *BALANCES.write().unwrap() = render_balances(&balances);
+}
+
+/// Returns the `accounts_balance` gauges of the last sample, to be appended to the
+/// metrics served by the Prometheus endpoint
+pub(crate) fn sampled_balances() -> String {
+    BALANCES.read().unwrap().clone()
+}
+
+/// Renders the balances, labelled with the account ID and asset code, in the
+/// Prometheus text format
+fn render_balances(balances: &[(Uuid, String, i64)]) -> String {
+    if balances.is_empty() {
+        return String::new();
+    }
+    let mut rendered = String::from("# TYPE accounts_balance gauge\n");
+    for (account_id, asset_code, balance) in balances {
+        let _ = writeln!(
+            rendered,
+            "accounts_balance{{account_id=\"{}\",asset_code=\"{}\"}} {}",
+            account_id,
+            escape_label_value(asset_code),
+            balance
+        );
+    }
+    rendered
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_the_balances_by_account_id() {
+        let id = Uuid::new_v4();
+        assert_eq!(render_balances(&[]), "");
+        assert_eq!(
+            render_balances(&[(id, "X\"Y\\Z".to_string(), -5)]),
+            format!(
+                "# TYPE accounts_balance gauge\naccounts_balance{{account_id=\"{}\",asset_code=\"X\\\"Y\\\\Z\"}} -5\n",
+                id
+            )
+        );
+    }
+}
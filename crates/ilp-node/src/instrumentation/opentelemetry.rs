@@ -0,0 +1,67 @@
+use opentelemetry_crate::{
+    global,
+    runtime::Tokio,
+    sdk::trace::{self, Sampler, Tracer},
+    trace::TraceError,
+};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Configuration for exporting the spans of the packets to [Jaeger](https://www.jaegertracing.io)
+/// or any other tracing backend which accepts spans from a Jaeger agent, such as Grafana Tempo.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct OpenTelemetryConfig {
+    /// Address of the Jaeger agent to which the spans are sent over UDP.
+    /// Defaults to 127.0.0.1:6831.
+    #[serde(default = "OpenTelemetryConfig::default_agent_endpoint")]
+    pub agent_endpoint: SocketAddr,
+    /// Name of the node in the traces. Defaults to `ilp-node`.
+    #[serde(default = "OpenTelemetryConfig::default_service_name")]
+    pub service_name: String,
+    /// Fraction of the packets whose spans are exported, between 0 and 1. Packets which
+    /// are part of a sampled trace are always exported. Defaults to 0.01 (1%).
+    #[serde(default = "OpenTelemetryConfig::default_sample_ratio")]
+    pub sample_ratio: f64,
+}
+
+impl OpenTelemetryConfig {
+    fn default_agent_endpoint() -> SocketAddr {
+        ([127, 0, 0, 1], 6831).into()
+    }
+
+    fn default_service_name() -> String {
+        "ilp-node".to_string()
+    }
+
+    fn default_sample_ratio() -> f64 {
+        0.01
+    }
+}
+
+/// Creates a tracing layer which turns the spans of the packets (`incoming`, `forwarding`,
+/// `outgoing` and `transport`) into OpenTelemetry spans, and exports the sampled ones in
+/// batches from a background task.
+///
+/// Must be called from within the Tokio runtime.
+pub fn opentelemetry_layer<S>(
+    config: &OpenTelemetryConfig,
+) -> Result<OpenTelemetryLayer<S, Tracer>, TraceError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio)));
+    let tracer = opentelemetry_jaeger::new_pipeline()
+        .with_agent_endpoint(config.agent_endpoint)
+        .with_service_name(config.service_name.clone())
+        .with_trace_config(trace::config().with_sampler(sampler))
+        .install_batch(Tokio)?;
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Exports the spans which were not exported yet
+pub fn shutdown_opentelemetry() {
+    global::shutdown_tracer_provider();
+}
@@ -1,5 +1,8 @@
+use super::metrics::sampled_balances;
 use crate::InterledgerNode;
+use interledger::api::{ApiAuth, ApiScope};
 use metrics_core::{Builder, Drain, Observe};
+use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tracing::{error, info};
@@ -22,6 +25,14 @@ pub struct PrometheusConfig {
     /// 1 second of histogram data points every second. Defaults to 10000ms (10 seconds).
     #[serde(default = "PrometheusConfig::default_histogram_granularity")]
     pub histogram_granularity: u64,
+    /// Interval, in milliseconds, on which the node samples the account balances, the size
+    /// of the routing table and the number of open connections. Defaults to 10000ms (10 seconds).
+    #[serde(default = "PrometheusConfig::default_gauge_interval")]
+    pub gauge_interval: u64,
+    /// Whether scraping the metrics requires the admin auth token or an API key with the
+    /// `metrics` scope (sent as a Bearer token). Defaults to false.
+    #[serde(default)]
+    pub require_auth: bool,
 }
 
 impl PrometheusConfig {
@@ -32,9 +43,15 @@ impl PrometheusConfig {
     fn default_histogram_granularity() -> u64 {
         10_000
     }
+
+    pub(crate) fn default_gauge_interval() -> u64 {
+        10_000
+    }
 }
 
 /// Starts a Prometheus metrics server that will listen on the configured address.
+/// The metrics are served at the root and at `/metrics`, and require the `metrics` scope
+/// if `require_auth` is set.
 ///
 /// # Errors
 /// This will fail if another Prometheus server is already running in this
@@ -63,15 +80,40 @@ pub async fn serve_prometheus(node: InterledgerNode) -> Result<(), ()> {
         Ok(_) => {
             let observer = Arc::new(metrics_runtime::observers::PrometheusBuilder::default());
 
-            let filter = warp::get().and(warp::path::end()).map(move || {
-                let mut observer = observer.build();
-                controller.observe(&mut observer);
-                let prometheus_response = observer.drain();
-                Response::builder()
-                    .status(StatusCode::OK)
-                    .header("Content-Type", "text/plain; version=0.0.4")
-                    .body(prometheus_response)
-            });
+            let api_auth = ApiAuth::new(node.admin_auth_token.clone(), node.api_keys.0.clone());
+            let require_auth = prometheus.require_auth;
+            let metrics_path = warp::path::end().or(warp::path("metrics").and(warp::path::end()));
+            let filter = warp::get()
+                .and(metrics_path)
+                .and(warp::header::optional::<SecretString>("authorization"))
+                .map(move |_, authorization: Option<SecretString>| {
+                    let authorized = !require_auth
+                        || authorization.map_or(false, |authorization| {
+                            api_auth.authorize(authorization.expose_secret(), ApiScope::Metrics)
+                        });
+                    if !authorized {
+                        return Response::builder()
+                            .status(StatusCode::UNAUTHORIZED)
+                            .body(String::new());
+                    }
+                    let mut observer = observer.build();
+                    controller.observe(&mut observer);
+                    let mut prometheus_response = observer.drain();
+                    // The balances are not in the registry so that the ones of the deleted
+                    // accounts go away, see spawn_gauge_sampler
+                    let balances = sampled_balances();
+                    if !balances.is_empty() {
+                        if !prometheus_response.ends_with('\n') {
+                            prometheus_response.push('\n');
+                        }
+                        prometheus_response.push('\n');
+                        prometheus_response.push_str(&balances);
+                    }
+                    Response::builder()
+                        .status(StatusCode::OK)
+                        .header("Content-Type", "text/plain; version=0.0.4")
+                        .body(prometheus_response)
+                });
 
             info!(target: "interledger-node",
                 "Prometheus metrics server listening on: {}",
@@ -2,39 +2,48 @@ use interledger::{
     ccp::{CcpRoutingAccount, RoutingRelation},
     packet::{hex::HexString, ErrorCode, Fulfill, Reject},
     service::{
-        Account, IlpResult, IncomingRequest, IncomingService, OutgoingRequest, OutgoingService,
+        telemetry::{sample_packet, Correlate, CorrelationId, Redacted},
+        Account, BoxedIncomingService, BoxedOutgoingService, IlpResult, IncomingRequest,
+        IncomingService, OutgoingRequest, OutgoingService,
     },
 };
 use std::str;
-use tracing::{debug_span, error_span, info, info_span};
+use tracing::{debug_span, error_span, info, info_span, Span};
 use tracing_futures::Instrument;
-use uuid::Uuid;
 
 /// Add tracing context for the incoming request.
 /// This adds minimal information for the ERROR log
-/// level and more information for the DEBUG level.
+/// level and more information for the DEBUG level,
+/// for the packets which are sampled.
+/// The request is handled with its correlation ID, which is the one the transport received
+/// from the peer, if any, or a new one.
 pub async fn trace_incoming<A: Account>(
     request: IncomingRequest<A>,
-    mut next: Box<dyn IncomingService<A> + Send>,
+    mut next: BoxedIncomingService<A>,
 ) -> IlpResult {
+    let correlation_id = CorrelationId::current().unwrap_or_else(CorrelationId::new);
     let request_span = error_span!(target: "interledger-node",
         "incoming",
-        request.id = %Uuid::new_v4(),
+        request.id = %correlation_id,
         prepare.destination = %request.prepare.destination(),
         prepare.amount = request.prepare.amount(),
         from.id = %request.from.id()
     );
     // These details can be looked up by the account ID
     // so don't bother printing them unless we're debugging
-    let details_span = debug_span!(parent: &request_span,
-        // This isn't named because its only purpose is to add
-        // more details to the request_span context
-        "",
-        from.username = %request.from.username(),
-        from.ilp_address = %request.from.ilp_address(),
-        from.asset_code = %request.from.asset_code(),
-        from.asset_scale = %request.from.asset_scale(),
-    );
+    let details_span = if sample_packet() {
+        debug_span!(parent: &request_span,
+            // This isn't named because its only purpose is to add
+            // more details to the request_span context
+            "",
+            from.username = %request.from.username(),
+            from.ilp_address = %request.from.ilp_address(),
+            from.asset_code = %request.from.asset_code(),
+            from.asset_scale = %request.from.asset_scale(),
+        )
+    } else {
+        Span::none()
+    };
 
     let span = if details_span.is_none() {
         request_span
@@ -42,7 +51,12 @@ pub async fn trace_incoming<A: Account>(
         details_span
     };
 
-    trace_response(next.handle_request(request).instrument(span).await)
+    trace_response(
+        next.handle_request(request)
+            .instrument(span)
+            .correlated(correlation_id)
+            .await,
+    )
 }
 
 /// Add tracing context when the incoming request is
@@ -51,7 +65,7 @@ pub async fn trace_incoming<A: Account>(
 /// level and more information for the DEBUG level.
 pub async fn trace_forwarding<A: Account>(
     request: OutgoingRequest<A>,
-    mut next: Box<dyn OutgoingService<A> + Send>,
+    mut next: BoxedOutgoingService<A>,
 ) -> IlpResult {
     // Here we only include the outgoing details because this will be
     // inside the "incoming" span that includes the other details
@@ -78,28 +92,36 @@ pub async fn trace_forwarding<A: Account>(
 
 /// Add tracing context for the outgoing request (created by this node).
 /// This adds minimal information for the ERROR log
-/// level and more information for the DEBUG level.
+/// level and more information for the DEBUG level,
+/// for the packets which are sampled.
+/// The request keeps the correlation ID of the incoming request it is sent on behalf of,
+/// if any.
 pub async fn trace_outgoing<A: Account + CcpRoutingAccount>(
     request: OutgoingRequest<A>,
-    mut next: Box<dyn OutgoingService<A> + Send>,
+    mut next: BoxedOutgoingService<A>,
 ) -> IlpResult {
+    let correlation_id = CorrelationId::current().unwrap_or_else(CorrelationId::new);
     let request_span = error_span!(target: "interledger-node",
         "outgoing",
-        request.id = %Uuid::new_v4(),
+        request.id = %correlation_id,
         prepare.destination = %request.prepare.destination(),
         from.id = %request.from.id(),
         to.id = %request.to.id(),
     );
-    let details_span = debug_span!(parent: &request_span,
-        "",
-        from.username = %request.from.username(),
-        from.ilp_address = %request.from.ilp_address(),
-        from.asset_code = %request.from.asset_code(),
-        from.asset_scale = %request.from.asset_scale(),
-        to.username = %request.from.username(),
-        to.asset_code = %request.from.asset_code(),
-        to.asset_scale = %request.from.asset_scale(),
-    );
+    let details_span = if sample_packet() {
+        debug_span!(parent: &request_span,
+            "",
+            from.username = %request.from.username(),
+            from.ilp_address = %request.from.ilp_address(),
+            from.asset_code = %request.from.asset_code(),
+            from.asset_scale = %request.from.asset_scale(),
+            to.username = %request.from.username(),
+            to.asset_code = %request.from.asset_code(),
+            to.asset_scale = %request.from.asset_scale(),
+        )
+    } else {
+        Span::none()
+    };
 
     // Don't log anything for failed route updates sent to child accounts
     // because there's a good chance they'll be offline
@@ -111,7 +133,11 @@ pub async fn trace_outgoing<A: Account + CcpRoutingAccount>(
     } else {
         details_span
     };
-    let result = next.send_request(request).instrument(span).await;
+    let result = next
+        .send_request(request)
+        .instrument(span)
+        .correlated(correlation_id)
+        .await;
     if let Err(ref err) = result {
         if err.code() == ErrorCode::F02_UNREACHABLE && ignore_rejects {
             return result;
@@ -120,11 +146,26 @@ pub async fn trace_outgoing<A: Account + CcpRoutingAccount>(
     trace_response(result)
 }
 
+/// Add tracing context for sending the request to the next hop over ILP-over-HTTP or BTP,
+/// so that the time spent waiting for the peer can be told apart from the time spent in
+/// the node's own services.
+pub async fn trace_transport<A: Account>(
+    request: OutgoingRequest<A>,
+    mut next: BoxedOutgoingService<A>,
+) -> IlpResult {
+    let span = info_span!(target: "interledger-node",
+        "transport",
+        request.id = %CorrelationId::current().unwrap_or_else(CorrelationId::new),
+        to.id = %request.to.id(),
+    );
+    next.send_request(request).instrument(span).await
+}
+
 /// Log whether the response was a Fulfill or Reject
 fn trace_response(result: Result<Fulfill, Reject>) -> Result<Fulfill, Reject> {
     match result {
         Ok(ref fulfill) => {
-            debug_span!(target: "interledger-node", "", fulfillment = ?HexString(fulfill.fulfillment())).in_scope(
+            debug_span!(target: "interledger-node", "", fulfillment = ?Redacted(HexString(fulfill.fulfillment()))).in_scope(
                 || {
                     info!(target: "interledger-node", result = "fulfill");
                 },
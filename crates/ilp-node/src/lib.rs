@@ -1,8 +1,41 @@
 #![type_length_limit = "10000000"]
+mod child_accounts;
+mod events;
+mod failover;
+mod http_headers;
 mod instrumentation;
+mod local_provider;
 mod node;
+mod packet_history;
+mod relay;
+mod reload;
+mod runtime;
+mod secret_rotation;
+mod service_chain;
+mod shutdown;
+#[cfg(feature = "tls")]
+mod tls;
+mod webhooks;
 
 #[cfg(feature = "redis")]
 mod redis_store;
+#[cfg(feature = "sled")]
+mod sled_store;
+#[cfg(feature = "sqlite")]
+mod sqlite_store;
 
+pub use failover::FailoverConfig;
+pub use http_headers::{CorsConfig, HttpHeadersConfig};
+pub use local_provider::LocalProviderConfig;
 pub use node::*;
+pub use packet_history::PacketHistoryConfig;
+pub use reload::ConfigUpdates;
+pub use runtime::{ConcurrencyConfig, RuntimeConfig};
+pub use service_chain::{
+    DuplicatePrepareSettings, IncomingMiddleware, OutgoingMiddleware, ServiceChainConfig,
+    ValidatorSettings,
+};
+pub use shutdown::ShutdownSignal;
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
+pub use webhooks::{WebhookEndpoint, WebhooksConfig};
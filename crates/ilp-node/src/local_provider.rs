@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use interledger::{
+    api::{AccountDetails, NodeStore},
+    btp::{BtpProvisioningStore, BtpStore},
+    errors::{AccountStoreError, BtpStoreError, HttpStoreError},
+    http::HttpStore,
+    service::{Account as AccountTrait, AccountStore, Username},
+    store::account::Account,
+};
+use ring::digest::{digest, SHA256};
+use secrecy::SecretString;
+use serde::Deserialize;
+use std::{net::SocketAddr, str::FromStr};
+use tracing::{debug, info};
+
+/// Configuration of the local provider, a listener on which the apps running on the same
+/// machine connect over BTP or ILP-over-HTTP without being configured in advance, like
+/// they would to moneyd. Each app gets a child account, and so an ILP address under the
+/// node's, the first time it connects.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct LocalProviderConfig {
+    /// Address on which the apps connect. It must be a loopback address, since anyone who
+    /// can reach it can create accounts. Defaults to 127.0.0.1:7768.
+    #[serde(default = "LocalProviderConfig::default_bind_address")]
+    pub bind_address: SocketAddr,
+    /// Asset code of the accounts of the apps
+    pub asset_code: String,
+    /// Asset scale of the accounts of the apps
+    pub asset_scale: u8,
+    /// Maximum amount of the packets the apps send. Defaults to no limit.
+    #[serde(default = "LocalProviderConfig::default_max_packet_amount")]
+    pub max_packet_amount: u64,
+    /// Balance below which the apps cannot send packets. Defaults to no limit, so the
+    /// apps can send as much as they want.
+    #[serde(default)]
+    pub min_balance: Option<i64>,
+}
+
+impl LocalProviderConfig {
+    fn default_bind_address() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 7768))
+    }
+
+    fn default_max_packet_amount() -> u64 {
+        u64::max_value()
+    }
+}
+
+/// Store wrapper which creates the account of an app the first time it connects.
+/// The token the app connects with becomes the account's incoming token, so that
+/// only the app which created an account can connect to it again.
+#[derive(Clone)]
+pub struct LocalProvider<S> {
+    store: S,
+    config: LocalProviderConfig,
+}
+
+impl<S> LocalProvider<S>
+where
+    S: NodeStore<Account = Account> + AccountStore<Account = Account> + Clone + Send + Sync,
+{
+    pub fn new(store: S, config: LocalProviderConfig) -> Self {
+        LocalProvider { store, config }
+    }
+
+    /// Creates the child account with the username, or with a username derived from
+    /// the token for apps which do not pick one, unless it already exists
+    async fn provision(
+        &self,
+        username: Option<&Username>,
+        token: &str,
+    ) -> Result<Username, AccountStoreError> {
+        let username = match username {
+            Some(username) => username.clone(),
+            None => username_from_token(token),
+        };
+        match self.store.get_account_id_from_username(&username).await {
+            Ok(_) => return Ok(username),
+            Err(AccountStoreError::AccountNotFound(_)) => {}
+            Err(err) => return Err(err),
+        }
+
+        let details = self.account_details(username.clone(), token);
+        match self.store.insert_account(details).await {
+            Ok(account) => {
+                info!(target: "interledger-node", "Created local account {} with ILP address {}", username, account.ilp_address());
+            }
+            // Another connection of the same app may have created the account first
+            Err(err) => {
+                debug!(target: "interledger-node", "Error creating local account {}: {}", username, err);
+            }
+        }
+        Ok(username)
+    }
+
+    fn account_details(&self, username: Username, token: &str) -> AccountDetails {
+        AccountDetails {
+            // The store gives child accounts an address under the node's
+            ilp_address: None,
+            username,
+            asset_code: self.config.asset_code.clone(),
+            asset_scale: self.config.asset_scale,
+            max_packet_amount: self.config.max_packet_amount,
+            min_packet_amount: None,
+            min_balance: self.config.min_balance,
+            ilp_over_http_url: None,
+            ilp_over_http_incoming_token: Some(SecretString::new(token.to_string())),
+            ilp_over_http_outgoing_token: None,
+            ilp_over_btp_url: None,
+            ilp_over_btp_outgoing_token: None,
+            ilp_over_btp_incoming_token: Some(SecretString::new(token.to_string())),
+            settle_threshold: None,
+            settle_to: None,
+            settle_every: None,
+            routing_relation: Some("Child".to_string()),
+            routing_preference: None,
+            round_trip_time: None,
+            amount_per_minute_limit: None,
+            max_payment_amount: None,
+            amount_per_day_limit: None,
+            packets_per_minute_limit: None,
+            settlement_engine_url: None,
+        }
+    }
+}
+
+/// Apps connecting without a username, such as the ones using moneyd's default
+/// `btp+ws://:token@localhost:7768` URL, get a username derived from their token
+fn username_from_token(token: &str) -> Username {
+    let hash = digest(&SHA256, token.as_bytes());
+    Username::from_str(&format!("local_{}", hex::encode(&hash.as_ref()[..8]))).unwrap()
+}
+
+#[async_trait]
+impl<S> BtpProvisioningStore for LocalProvider<S>
+where
+    S: NodeStore<Account = Account>
+        + AccountStore<Account = Account>
+        + BtpStore<Account = Account>
+        + Clone
+        + Send
+        + Sync,
+{
+    type Account = Account;
+
+    async fn get_or_create_btp_account(
+        &self,
+        username: Option<&Username>,
+        token: &str,
+    ) -> Result<Account, BtpStoreError> {
+        let username = self.provision(username, token).await?;
+        self.store.get_account_from_btp_auth(&username, token).await
+    }
+}
+
+#[async_trait]
+impl<S> HttpStore for LocalProvider<S>
+where
+    S: NodeStore<Account = Account>
+        + AccountStore<Account = Account>
+        + HttpStore<Account = Account>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    type Account = Account;
+
+    async fn get_account_from_http_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Account, HttpStoreError> {
+        self.provision(Some(username), token)
+            .await
+            .map_err(|err| HttpStoreError::Other(Box::new(err)))?;
+        self.store.get_account_from_http_auth(username, token).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_usernames_from_tokens() {
+        let username = username_from_token("secret");
+        assert_eq!(username.as_ref(), "local_2bb80d537b1da3e3");
+        assert_eq!(username, username_from_token("secret"));
+        assert_ne!(username, username_from_token("other secret"));
+    }
+}
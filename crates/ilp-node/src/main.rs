@@ -1,6 +1,20 @@
 #![type_length_limit = "10000000"]
+mod child_accounts;
+mod events;
+mod http_headers;
 mod instrumentation;
+mod local_provider;
 pub mod node;
+mod packet_history;
+mod relay;
+mod reload;
+mod runtime;
+mod secret_rotation;
+mod service_chain;
+mod shutdown;
+#[cfg(feature = "tls")]
+mod tls;
+mod webhooks;
 
 use cfg_if::cfg_if;
 
@@ -8,28 +22,38 @@ cfg_if! {
     if #[cfg(feature = "monitoring")] {
         use tracing_subscriber::{
             filter::EnvFilter,
-            fmt::{time::ChronoUtc, Subscriber},
+            fmt::{self, time::ChronoUtc},
+            layer::SubscriberExt,
+            reload,
+            util::SubscriberInitExt,
         };
-        use node::LogWriter;
+        use node::{LogFormat, LogWriter};
     }
 }
 
+#[cfg(feature = "opentelemetry")]
+use instrumentation::opentelemetry::{opentelemetry_layer, shutdown_opentelemetry};
+
 #[cfg(feature = "redis")]
 mod redis_store;
+#[cfg(feature = "sled")]
+mod sled_store;
+#[cfg(feature = "sqlite")]
+mod sqlite_store;
 
 use clap::{App, Arg, ArgMatches};
 use config::{Config, Source};
 use config::{ConfigError, FileFormat, Value};
 use libc::{c_int, isatty};
 use node::InterledgerNode;
+use reload::ConfigUpdates;
 use std::{
     ffi::{OsStr, OsString},
     io::Read,
     vec::Vec,
 };
 
-#[tokio::main]
-async fn main() {
+fn main() {
     let version = format!(
         "{}-{}",
         env!("CARGO_PKG_VERSION"),
@@ -48,7 +72,7 @@ async fn main() {
         None
     };
 
-    let node = match load_configuration(app, args, additional_config) {
+    let node = match load_configuration(app, args.clone(), additional_config) {
         Ok(node) => node,
         Err(BadConfig::HelpOrVersion(e)) | Err(BadConfig::BadArguments(e)) => {
             e.exit();
@@ -62,26 +86,52 @@ async fn main() {
             std::process::exit(0);
         }
         Err(BadConfig::ConversionFailed(e)) => {
-            // this used to be an expect
-            panic!("Could not parse provided configuration options into an Interledger Node config: {}", e);
+            eprintln!("Invalid configuration option `{}`: {}", e.path(), e.inner());
+            std::process::exit(1);
         }
     };
 
+    // The runtime is built by hand, because its number of threads is configurable
+    let runtime = node.runtime.build().unwrap_or_else(|err| {
+        eprintln!("Error starting the Tokio runtime: {}", err);
+        std::process::exit(1);
+    });
+    runtime.block_on(run(node, version, args));
+}
+
+/// Sets up the logs and runs the node until it receives SIGTERM or SIGINT
+async fn run(node: InterledgerNode, version: String, args: Vec<OsString>) {
     cfg_if! {
         if #[cfg(feature = "monitoring")] {
             let mut log_writer = LogWriter::default();
 
             let (nb_log_writer, _guard) = tracing_appender::non_blocking(log_writer.clone());
 
-            let tracing_builder = Subscriber::builder()
+            // The filter is reloaded independently of the format of the logs
+            let (filter, handle) = reload::Layer::new(initial_env_filter(&node));
+            log_writer.handle = Some(handle);
+
+            let registry = tracing_subscriber::registry().with(filter);
+            #[cfg(feature = "opentelemetry")]
+            let registry = registry.with(node.opentelemetry.as_ref().and_then(|config| {
+                opentelemetry_layer(config)
+                    .map_err(|err| eprintln!("Error setting up the OpenTelemetry exporter: {}", err))
+                    .ok()
+            }));
+            let fmt_layer = fmt::layer()
                 .with_timer(ChronoUtc::rfc3339())
-                .with_env_filter(EnvFilter::from_default_env())
-                .with_writer(nb_log_writer)
-                .with_filter_reloading();
-
-            log_writer.handle = Some(tracing_builder.reload_handle());
-
-            let _ = tracing_builder.try_init();
+                .with_writer(nb_log_writer);
+            let _ = match node.log_format {
+                LogFormat::Full => registry.with(fmt_layer).try_init(),
+                LogFormat::Json => registry
+                    .with(
+                        fmt_layer
+                            .json()
+                            .with_current_span(true)
+                            .with_span_list(true),
+                    )
+                    .try_init(),
+            };
 
             let log_writer = Some(log_writer);
         } else {
@@ -89,11 +139,87 @@ async fn main() {
         }
     }
 
-    node.serve(log_writer.clone()).await.unwrap();
+    let updates = reload_on_hangup(version, args);
+    let result = node
+        .serve_until(log_writer.clone(), updates, Box::pin(termination()))
+        .await;
+    #[cfg(feature = "opentelemetry")]
+    shutdown_opentelemetry();
+    result.unwrap();
+}
+
+/// Completes when the node receives SIGTERM or SIGINT
+#[cfg(unix)]
+async fn termination() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                _ = terminate.recv() => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+        }
+        Err(err) => {
+            tracing::error!(target: "interledger-node", "Error listening for SIGTERM, only SIGINT shuts down the node: {}", err);
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn termination() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Returns the log level configured with `log_level`, or the one given in the `RUST_LOG`
+/// environment variable
+#[cfg(feature = "monitoring")]
+fn initial_env_filter(node: &InterledgerNode) -> EnvFilter {
+    match node.log_level {
+        Some(ref log_level) => EnvFilter::try_new(log_level).unwrap_or_else(|err| {
+            eprintln!("Invalid configuration option `log_level`: {}", err);
+            std::process::exit(1);
+        }),
+        None => EnvFilter::from_default_env(),
+    }
+}
+
+/// Loads the configuration again from the environment, the config file and the command line
+/// arguments whenever the node receives SIGHUP. Standard input is only read on startup.
+#[cfg(unix)]
+fn reload_on_hangup(version: String, args: Vec<OsString>) -> Option<ConfigUpdates> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            tracing::error!(target: "interledger-node", "Error listening for SIGHUP, the configuration will not be reloaded: {}", err);
+            return None;
+        }
+    };
+    let (sender, updates) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            let app = cmdline_configuration(&version);
+            match load_configuration(app, args.clone(), Option::<std::io::Empty>::None) {
+                Ok(node) => {
+                    if sender.send(node).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    tracing::error!(target: "interledger-node", "Not reloading the invalid configuration: {:?}", err)
+                }
+            }
+        }
+    });
+    Some(updates)
+}
 
-    // Add a future which is always pending. This will ensure main does not exist
-    // TODO: Is there a better way of doing this?
-    futures::future::pending().await
+#[cfg(not(unix))]
+fn reload_on_hangup(_version: String, _args: Vec<OsString>) -> Option<ConfigUpdates> {
+    None
 }
 
 fn cmdline_configuration<'b>(version: &'b str) -> clap::App<'static, 'b> {
@@ -147,7 +273,47 @@ fn cmdline_configuration<'b>(version: &'b str) -> clap::App<'static, 'b> {
             .long("database_prefix")
             .takes_value(true)
             .default_value("")
-            .help("Unique prefix that can be used to identify part of the db that this node will use. This can be used to enable multiple nodes to share the same database instance"),
+            .help("Unique prefix that can be used to identify part of the db that this node will use. This can be used to enable multiple nodes to share the same database instance. May only contain ASCII letters, digits, '_' and '-'"),
+        Arg::with_name("account_cache_capacity")
+            .long("account_cache_capacity")
+            .takes_value(true)
+            .help("Maximum number of accounts kept in memory to avoid loading them from the database for every packet. Disabled if 0, which is the default. Changes made by other nodes sharing the database are only picked up once the cached accounts expire"),
+        Arg::with_name("account_cache_ttl")
+            .long("account_cache_ttl")
+            .takes_value(true)
+            .help("Time, defined in milliseconds, after which cached accounts are reloaded from the database. Defaults to 1000ms (1 second)."),
+        Arg::with_name("store_master_key")
+            .long("store_master_key")
+            .takes_value(true)
+            .help("Key with which the account tokens are encrypted in the database, as 32 hex-encoded bytes. Defaults to a key derived from the secret_seed. When it is set for the first time, the existing tokens are re-encrypted with it. You can generate a random key by running `openssl rand -hex 32`"),
+        Arg::with_name("store_master_key_file")
+            .long("store_master_key_file")
+            .takes_value(true)
+            .help("File from which the store_master_key is read, e.g. a secret mounted by a key management service. Ignored if store_master_key is set"),
+        Arg::with_name("previous_store_master_key")
+            .long("previous_store_master_key")
+            .takes_value(true)
+            .help("Key which was used before the current store_master_key. Tokens encrypted with it are re-encrypted with the current key when the node starts"),
+        Arg::with_name("store_slow_operation_threshold")
+            .long("store_slow_operation_threshold")
+            .takes_value(true)
+            .help("Time, defined in milliseconds, after which store operations are logged as slow. Slow operations are not logged by default. Thresholds for individual store methods can be set with store_method_slow_operation_thresholds in the config file"),
+        Arg::with_name("redis_pool_size")
+            .long("redis_pool_size")
+            .takes_value(true)
+            .help("Number of connections opened to Redis. Each connection is multiplexed, so this only needs to be increased if a single connection becomes the bottleneck. Defaults to 1"),
+        Arg::with_name("redis_connection_timeout")
+            .long("redis_connection_timeout")
+            .takes_value(true)
+            .help("Time, defined in milliseconds, after which connecting to Redis is abandoned. There is no timeout by default"),
+        Arg::with_name("redis_max_retries")
+            .long("redis_max_retries")
+            .takes_value(true)
+            .help("Number of times connecting to Redis and commands failing with a transient error (e.g. Redis is still loading its dataset) are retried. Commands are never retried if the connection dropped. Defaults to 0"),
+        Arg::with_name("redis_retry_backoff")
+            .long("redis_retry_backoff")
+            .takes_value(true)
+            .help("Time, defined in milliseconds, to wait before retrying a Redis command, which is doubled for every further retry. Defaults to 100ms"),
         Arg::with_name("http_bind_address")
             .long("http_bind_address")
             .takes_value(true)
@@ -164,11 +330,16 @@ fn cmdline_configuration<'b>(version: &'b str) -> clap::App<'static, 'b> {
             .long("route_broadcast_interval")
             .takes_value(true)
             .help("Interval, defined in milliseconds, on which the node will broadcast routing information to other nodes using CCP. Defaults to 30000ms (30 seconds)."),
+        Arg::with_name("clock_skew_tolerance")
+            .long("clock_skew_tolerance")
+            .takes_value(true)
+            .help("Time, defined in milliseconds, after their expiry during which packets and CCP messages are not yet treated as expired, \
+                so that peers whose clocks are slightly behind don't get R00 rejects. Defaults to 0."),
         Arg::with_name("exchange_rate.provider")
             .long("exchange_rate.provider")
             .takes_value(true)
             .help("Exchange rate API to poll for exchange rates. If this is not set, the node will not poll for rates and will instead use the rates set via the HTTP API. \
-                Note that CryptoCompare can also be used when the node is configured via a config file or stdin, because an API key must be provided to use that service."),
+                Note that the CryptoCompare, Fixed and Http providers can also be used when the node is configured via a config file or stdin, because they take an API key, the rates or a URL."),
         Arg::with_name("exchange_rate.poll_interval")
             .long("exchange_rate.poll_interval")
             .default_value("60000") // also change ExchangeRateConfig::default_poll_interval
@@ -182,6 +353,21 @@ fn cmdline_configuration<'b>(version: &'b str) -> clap::App<'static, 'b> {
                 For example, take an incoming packet with an amount of 100. If the \
                 exchange rate is 1:0.5 and the spread is 0.01, the amount on the \
                     outgoing packet would be 198 (instead of 200 without the spread)."),
+        Arg::with_name("exchange_rate.max_rate_age")
+            .long("exchange_rate.max_rate_age")
+            .takes_value(true)
+            .help("Age, defined in milliseconds, after which exchange rates are stale. If this is not set, rates never go stale."),
+        Arg::with_name("exchange_rate.stale_rate_policy")
+            .long("exchange_rate.stale_rate_policy")
+            .takes_value(true)
+            .help("What to do with packets whose exchange rates are stale: `reject` them with T00 (the default) or `alert` by logging an error and using the last known rates. \
+                The last known rates can also be used with an extra spread when the node is configured via a config file or stdin."),
+        Arg::with_name("duplicate_prepare_policy")
+            .long("duplicate_prepare_policy")
+            .takes_value(true)
+            .possible_values(&["attach", "reject"])
+            .help("What to do with a Prepare which an account sends again while the original is still pending: `attach` it \
+                to the result of the original (the default) or `reject` it with F00. Either way, the account is not debited twice."),
         Arg::with_name("prometheus.bind_address")
             .long("prometheus.bind_address")
             .takes_value(true)
@@ -198,12 +384,61 @@ fn cmdline_configuration<'b>(version: &'b str) -> clap::App<'static, 'b> {
                 old data. For example, a value of 1000ms (1 second) would mean that the \
                 node forgets the oldest 1 second of histogram data points every second. \
                 Defaults to 10000ms (10 seconds)."),
+        Arg::with_name("prometheus.gauge_interval")
+            .long("prometheus.gauge_interval")
+            .takes_value(true)
+            .help("Interval, in milliseconds, on which the node samples the account \
+                balances, the size of the routing table and the number of open BTP \
+                connections. Defaults to 10000ms (10 seconds)."),
+        Arg::with_name("opentelemetry.agent_endpoint")
+            .long("opentelemetry.agent_endpoint")
+            .takes_value(true)
+            .help("IP address and port of the Jaeger agent to which the spans of the packets are exported. \
+                Defaults to 127.0.0.1:6831. Only used if the node was built with the opentelemetry feature."),
+        Arg::with_name("opentelemetry.service_name")
+            .long("opentelemetry.service_name")
+            .takes_value(true)
+            .help("Name of the node in the exported traces. Defaults to `ilp-node`."),
+        Arg::with_name("opentelemetry.sample_ratio")
+            .long("opentelemetry.sample_ratio")
+            .takes_value(true)
+            .help("Fraction of the packets whose spans are exported, between 0 and 1. Defaults to 0.01 (1%)."),
+        Arg::with_name("telemetry.sample_ratio")
+            .long("telemetry.sample_ratio")
+            .takes_value(true)
+            .help("Fraction of the packets whose debug and trace events are logged, between 0 and 1. \
+                Errors, warnings and info events are always logged. Defaults to 1 (all packets). \
+                Changes are applied when the configuration is reloaded on SIGHUP."),
+        Arg::with_name("telemetry.redact_secrets")
+            .long("telemetry.redact_secrets")
+            .takes_value(true)
+            .help("Whether fulfillments, STREAM shared secrets and auth tokens are replaced with \
+                `[redacted]` in the logs and traces. Defaults to true."),
+        Arg::with_name("telemetry.propagate_correlation_ids")
+            .long("telemetry.propagate_correlation_ids")
+            .takes_value(true)
+            .help("Whether the correlation IDs of the packets, which are logged as their `request.id`, are sent to the peers \
+                over BTP and ILP-over-HTTP, so that a payment can be followed across nodes. Defaults to false."),
+        Arg::with_name("log_level")
+            .long("log_level")
+            .takes_value(true)
+            .help("Log level, in the format of the RUST_LOG environment variable (for example, \"interledger=debug,info\"). \
+                Defaults to the RUST_LOG environment variable. Changes are applied when the configuration is \
+                reloaded on SIGHUP. Only used if the node was built with the monitoring feature."),
+        Arg::with_name("log_format")
+            .long("log_format")
+            .takes_value(true)
+            .possible_values(&["full", "json"])
+            .help("Format of the logs. `json` writes one JSON object per line, including the timestamp, level, \
+                target and the fields of the enclosing spans, such as the request and account IDs of packets. \
+                Defaults to `full`. Only used if the node was built with the monitoring feature."),
         Arg::with_name("settle_every")
             .long("settle_every")
             .takes_value(true)
             .help("Settlement delay, in seconds; the peering accounts will be settled after \
                 this many seconds after the first fulfill packet unless the balance had \
-                exceeded the settlement threshold.\n\n\
+                exceeded the settlement threshold. Accounts configured with their own \
+                settle_every use that instead.\n\n\
                 \
                 Note: In a cluster configuration where multiple nodes share a \
                 single database and database accounts, using this can result in \
@@ -217,7 +452,9 @@ enum BadConfig {
     BadArguments(clap::Error),
     MergingStdinFailed(config::ConfigError),
     MergingConfigFileFailed(String, config::ConfigError),
-    ConversionFailed(config::ConfigError),
+    /// The configuration could not be converted into a node config. The error contains the
+    /// path of the offending option, e.g. `prometheus.bind_address`.
+    ConversionFailed(serde_path_to_error::Error<config::ConfigError>),
 }
 
 fn load_configuration<R: Read>(
@@ -246,7 +483,7 @@ fn load_configuration<R: Read>(
 
     merge_args(&mut config, &matches);
 
-    config.try_into().map_err(BadConfig::ConversionFailed)
+    serde_path_to_error::deserialize(config).map_err(BadConfig::ConversionFailed)
 }
 
 fn output_config_error(error: ConfigError, config_path: Option<&str>) {
@@ -311,6 +548,8 @@ fn merge_read_in<R: Read>(mut input: R, config: &mut Config) -> Result<(), Confi
         if let Ok(buf_str) = String::from_utf8(buf) {
             let config_hash = FileFormat::Json
                 .parse(None, &buf_str)
+                // TOML is tried before YAML, which accepts any input as a document
+                .or_else(|_| FileFormat::Toml.parse(None, &buf_str))
                 .or_else(|_| FileFormat::Yaml.parse(None, &buf_str));
             if let Ok(config_hash) = config_hash {
                 // if the key is not defined in the given config already, set it to the config
@@ -414,6 +653,8 @@ fn is_fd_tty(file_descriptor: c_int) -> bool {
 #[cfg(test)]
 mod tests {
     use super::{cmdline_configuration, load_configuration, BadConfig, InterledgerNode};
+    use interledger::api::ApiScope;
+    use secrecy::ExposeSecret;
     use std::ffi::OsString;
     use std::io::Write;
 
@@ -449,11 +690,13 @@ mod tests {
     static ADDITIONAL_SECRETS: &[(&str, &[u8])] = &[
         ("json", b"{ \"secret_seed\": \"8852500887504328225458511465394229327394647958135038836332350604\" }"),
         ("yaml", b"secret_seed: \"8852500887504328225458511465394229327394647958135038836332350604\"\n"),
+        ("toml", b"secret_seed = \"8852500887504328225458511465394229327394647958135038836332350604\"\n"),
     ];
 
     static ADDITIONAL_AUTH_TOKEN: &[(&str, &[u8])] = &[
         ("json", b"{ \"admin_auth_token\": \"foobar\" }"),
         ("yaml", b"admin_auth_token: \"foobar\"\n"),
+        ("toml", b"admin_auth_token = \"foobar\"\n"),
     ];
 
     #[test]
@@ -687,4 +930,223 @@ mod tests {
             bad
         );
     }
+
+    #[test]
+    fn conversion_errors_point_at_the_option() {
+        let args = ["ilp-node", "--admin_auth_token", "foobar"]
+            .iter()
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+        let app = cmdline_configuration("anything");
+        let additional = Some(std::io::Cursor::new(&b"secret_seed = \"not hex\"\n"[..]));
+
+        match load_configuration(app, args, additional).unwrap_err() {
+            BadConfig::ConversionFailed(e) => assert_eq!(e.path().to_string(), "secret_seed"),
+            bad => panic!("unexpected: {:?}", bad),
+        }
+    }
+
+    #[test]
+    fn loads_accounts_to_bootstrap_from_a_file() {
+        let mut named_temp = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        named_temp
+            .write_all(
+                &br#"
+secret_seed = "8852500887504328225458511465394229327394647958135038836332350604"
+
+[[accounts]]
+username = "alice"
+asset_code = "XYZ"
+asset_scale = 9
+ilp_over_http_incoming_token = "password"
+max_packet_amount = 1000
+"#[..],
+            )
+            .unwrap();
+        named_temp.flush().unwrap();
+
+        let args = vec![
+            OsString::from("ilp-node"),
+            OsString::from("--admin_auth_token"),
+            OsString::from("foobar"),
+            OsString::from(named_temp.path()),
+        ];
+        let app = cmdline_configuration("anything");
+        let additional = Option::<std::io::Empty>::None;
+        let node = load_configuration(app, args, additional).unwrap();
+
+        let accounts = &node.accounts.0;
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].username.as_ref(), "alice");
+        assert_eq!(accounts[0].asset_scale, 9);
+        assert_eq!(accounts[0].max_packet_amount, 1000);
+    }
+
+    #[test]
+    fn loads_api_keys_from_a_file() {
+        let mut named_temp = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        named_temp
+            .write_all(
+                &br#"
+secret_seed = "8852500887504328225458511465394229327394647958135038836332350604"
+
+[[api_keys]]
+name = "grafana"
+key = "metrics-key"
+scopes = ["metrics", "read_only"]
+"#[..],
+            )
+            .unwrap();
+        named_temp.flush().unwrap();
+
+        let args = vec![
+            OsString::from("ilp-node"),
+            OsString::from("--admin_auth_token"),
+            OsString::from("foobar"),
+            OsString::from(named_temp.path()),
+        ];
+        let app = cmdline_configuration("anything");
+        let additional = Option::<std::io::Empty>::None;
+        let node = load_configuration(app, args, additional).unwrap();
+
+        let api_keys = &node.api_keys.0;
+        assert_eq!(api_keys.len(), 1);
+        assert_eq!(api_keys[0].name, "grafana");
+        assert_eq!(api_keys[0].key.expose_secret(), "metrics-key");
+        assert_eq!(
+            api_keys[0].scopes,
+            vec![ApiScope::Metrics, ApiScope::ReadOnly]
+        );
+    }
+
+    #[test]
+    fn loads_static_routes_from_a_file() {
+        let mut named_temp = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        named_temp
+            .write_all(
+                &br#"
+secret_seed = "8852500887504328225458511465394229327394647958135038836332350604"
+
+[static_routes]
+"example.alice" = "alice"
+"#[..],
+            )
+            .unwrap();
+        named_temp.flush().unwrap();
+
+        let args = vec![
+            OsString::from("ilp-node"),
+            OsString::from("--admin_auth_token"),
+            OsString::from("foobar"),
+            OsString::from(named_temp.path()),
+        ];
+        let app = cmdline_configuration("anything");
+        let additional = Option::<std::io::Empty>::None;
+        let node = load_configuration(app, args, additional).unwrap();
+
+        let routes = node.static_routes.unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes["example.alice"].as_ref(), "alice");
+    }
+
+    #[test]
+    fn loads_runtime_and_concurrency_from_a_file() {
+        let mut named_temp = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        named_temp
+            .write_all(
+                &br#"
+secret_seed = "8852500887504328225458511465394229327394647958135038836332350604"
+
+[runtime]
+worker_threads = 2
+
+[concurrency]
+store = 64
+btp = 8
+per_peer = 32
+"#[..],
+            )
+            .unwrap();
+        named_temp.flush().unwrap();
+
+        let args = vec![
+            OsString::from("ilp-node"),
+            OsString::from("--admin_auth_token"),
+            OsString::from("foobar"),
+            OsString::from(named_temp.path()),
+        ];
+        let app = cmdline_configuration("anything");
+        let additional = Option::<std::io::Empty>::None;
+        let node = load_configuration(app, args, additional).unwrap();
+
+        assert_eq!(node.runtime.worker_threads, Some(2));
+        assert_eq!(node.runtime.max_blocking_threads, None);
+        assert_eq!(node.concurrency.store, Some(64));
+        assert_eq!(node.concurrency.http_client, None);
+        assert_eq!(node.concurrency.btp, Some(8));
+        assert_eq!(node.concurrency.per_peer, Some(32));
+        assert_eq!(node.concurrency.per_peer_wait, None);
+    }
+
+    #[test]
+    fn loads_telemetry_from_the_cmdline() {
+        let args = [
+            "ilp-node",
+            "--admin_auth_token",
+            "foobar",
+            "--secret_seed",
+            "8852500887504328225458511465394229327394647958135038836332350604",
+            "--telemetry.sample_ratio",
+            "0.01",
+            "--telemetry.redact_secrets",
+            "false",
+        ]
+        .iter()
+        .map(OsString::from)
+        .collect();
+        let app = cmdline_configuration("anything");
+        let additional = Option::<std::io::Empty>::None;
+        let node = load_configuration(app, args, additional).unwrap();
+
+        assert_eq!(node.telemetry.sample_ratio, 0.01);
+        assert!(!node.telemetry.redact_secrets);
+    }
+
+    #[test]
+    fn loads_fees_from_a_file() {
+        let mut named_temp = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        named_temp
+            .write_all(
+                &br#"
+secret_seed = "8852500887504328225458511465394229327394647958135038836332350604"
+
+[[fees]]
+flat = 1
+
+[[fees]]
+from = "alice"
+to = "bob"
+percentage = 0.001
+"#[..],
+            )
+            .unwrap();
+        named_temp.flush().unwrap();
+
+        let args = vec![
+            OsString::from("ilp-node"),
+            OsString::from("--admin_auth_token"),
+            OsString::from("foobar"),
+            OsString::from(named_temp.path()),
+        ];
+        let app = cmdline_configuration("anything");
+        let additional = Option::<std::io::Empty>::None;
+        let node = load_configuration(app, args, additional).unwrap();
+
+        assert_eq!(node.fees.len(), 2);
+        assert_eq!(node.fees[0].flat, 1);
+        assert!(node.fees[0].from.is_none());
+        assert_eq!(node.fees[1].from.as_ref().unwrap().as_ref(), "alice");
+        assert_eq!(node.fees[1].to.as_ref().unwrap().as_ref(), "bob");
+        assert_eq!(node.fees[1].percentage, 0.001);
+    }
 }
@@ -1,74 +1,109 @@
 use cfg_if::cfg_if;
 
 #[cfg(feature = "google-pubsub")]
-use crate::instrumentation::google_pubsub::{create_google_pubsub_wrapper, PubsubConfig};
+use crate::instrumentation::google_pubsub::{GooglePubsubService, PubsubConfig};
+#[cfg(feature = "opentelemetry")]
+pub use crate::instrumentation::opentelemetry::OpenTelemetryConfig;
 
 cfg_if! {
     if #[cfg(feature = "monitoring")] {
-        use interledger::errors::ApiError;
-        use secrecy::{ExposeSecret, SecretString};
+        use interledger::{api::{ApiAuth, ApiScope}, errors::ApiError};
+        use secrecy::SecretString;
         use tracing::debug_span;
-        use tracing_appender::non_blocking::NonBlocking;
         use tracing_futures::Instrument;
         use tracing_subscriber::{
             filter::EnvFilter,
-            fmt::{format, time::ChronoUtc, Formatter},
+            registry::Registry,
             reload::Handle,
         };
         use crate::instrumentation::{
-            metrics::{incoming_metrics, outgoing_metrics},
+            metrics::{incoming_metrics, outgoing_metrics, spawn_gauge_sampler},
             prometheus::{serve_prometheus, PrometheusConfig},
-            trace::{trace_forwarding, trace_incoming, trace_outgoing},
+            trace::{trace_forwarding, trace_incoming, trace_outgoing, trace_transport},
         };
-        use interledger::service::IncomingService;
-        use futures::FutureExt;
         use std::{io::{self, Stdout}, sync::Arc};
     }
 }
 
-#[cfg(any(feature = "monitoring", feature = "google-pubsub"))]
-use interledger::service::OutgoingService;
-
+use crate::child_accounts::{spawn_child_routes, ChildAccountsService};
+use crate::events::{
+    spawn_connection_events, spawn_route_failover, spawn_settlement_events, EventsService,
+    NODE_EVENTS_CAPACITY,
+};
+use crate::failover::{FailoverConfig, Leadership};
+use crate::http_headers::{with_http_headers, HttpHeadersConfig};
+use crate::local_provider::{LocalProvider, LocalProviderConfig};
+use crate::packet_history::{
+    spawn_packet_history_pruning, PacketHistoryConfig, PacketHistoryService,
+};
+use crate::relay::serve_relay;
+use crate::reload::{apply_static_routes, ConfigReloader, ConfigUpdates};
+use crate::runtime::{ConcurrencyConfig, RuntimeConfig};
+use crate::secret_rotation::spawn_secret_generation_reload;
+use crate::service_chain::{IncomingMiddleware, OutgoingMiddleware, ServiceChainConfig};
+use crate::shutdown::{PacketDrain, ShutdownSignal};
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
+use crate::webhooks::{spawn_webhooks, WebhooksConfig};
 use bytes::Bytes;
-use futures::TryFutureExt;
+use futures::{FutureExt, TryFutureExt};
 use hex::FromHex;
 use interledger::{
-    api::{NodeApi, NodeStore},
-    btp::{btp_service_as_filter, connect_client, BtpOutgoingService, BtpStore},
-    ccp::{CcpRouteManagerBuilder, CcpRoutingAccount, CcpRoutingStore, RoutingRelation},
+    api::{AccountDetails, ApiKey, NodeApi, NodeStore},
+    btp::{
+        btp_service_as_filter, btp_service_as_provisioning_filter, connect_client,
+        BtpOutgoingService, BtpStore,
+    },
+    ccp::{
+        CcpRouteManagerBuilder, CcpRoutingAccount, CcpRoutingStore, LatencyScores, LatencyService,
+        RoutingRelation,
+    },
     errors::*,
     http::{HttpClientService, HttpServer as IlpOverHttpServer, HttpStore},
     ildcp::IldcpService,
     packet::Address,
     packet::{ErrorCode, RejectBuilder},
-    rates::{ExchangeRateFetcher, ExchangeRateStore},
+    rates::ExchangeRateStore,
     router::{Router, RouterStore},
     service::{
-        outgoing_service_fn, Account as AccountTrait, AccountStore, AddressStore, OutgoingRequest,
-        Username,
+        clock::SharedClock,
+        outgoing_service_fn,
+        telemetry::{self, TelemetryConfig},
+        Account as AccountTrait, AccountStore, AddressStore, BoxedIncomingService,
+        BoxedOutgoingService, IncomingService, OutgoingRequest, OutgoingService, Username,
     },
     service_util::{
-        BalanceStore, EchoService, ExchangeRateService, ExpiryShortenerService,
-        MaxPacketAmountService, RateLimitService, RateLimitStore, ValidatorService,
+        AdmissionService, AdmissionStore, AssetGuardService, BalanceStore,
+        DestinationFilterService, DestinationRule, DestinationRules, DuplicatePreparePolicy,
+        DuplicatePrepareService, EchoService, ExchangeRateService, ExpiryShortenerService,
+        FeeSchedule, FeeService, Fees, MaxPacketAmountService, MinPacketAmountService,
+        PacketHistoryStore, PacketJournalStore, PeerConcurrencyService, RateLimitStore, Spread,
+        StaleRatePolicy, ValidatorService, VolumeLimitStore,
     },
     settlement::{
         api::{create_settlements_filter, SettlementMessageService},
         core::{
             idempotency::IdempotentStore,
-            types::{LeftoversStore, SettlementStore},
+            types::{LeftoversStore, OutgoingSettlementStore, SettlementStore},
         },
     },
     store::account::Account,
-    stream::{StreamNotificationsStore, StreamReceiverService},
+    stream::{
+        ConnectionGenerator, IncomingPaymentsStore, StreamNotificationsStore, StreamReceiverService,
+    },
 };
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
+use secrecy::ExposeSecret;
 use serde::{de::Error as DeserializeError, Deserialize, Deserializer};
 #[cfg(feature = "balance-tracking")]
 use std::num::NonZeroU32;
 use std::{
+    collections::HashMap,
     convert::TryFrom,
+    fs,
     net::SocketAddr,
+    path::PathBuf,
     str::{self, FromStr},
     time::Duration,
 };
@@ -80,13 +115,28 @@ use warp::{self, Filter};
 
 #[cfg(feature = "redis")]
 use crate::redis_store::*;
+#[cfg(feature = "sled")]
+use crate::sled_store::*;
+#[cfg(feature = "sqlite")]
+use crate::sqlite_store::*;
 #[cfg(feature = "balance-tracking")]
-use interledger::service_util::{start_delayed_settlement, BalanceService};
+use interledger::service_util::{
+    start_delayed_settlement, start_journal_reconciliation, start_settlement_retries,
+    BalanceService,
+};
 
 #[doc(hidden)]
-pub use interledger::rates::ExchangeRateProvider;
+pub use interledger::rates::ExchangeRateProviderConfig;
 
 static DEFAULT_ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("local.host").unwrap());
+/// How often outgoing settlements which the engine did not answer are checked for retries
+#[cfg(feature = "balance-tracking")]
+const SETTLEMENT_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+/// How often the journaled packets are checked for ones to reconcile, if they are journaled
+#[cfg(feature = "balance-tracking")]
+const JOURNAL_RECONCILIATION_INTERVAL: Duration = Duration::from_secs(60);
+/// How long the BTP connections are given to send their Close frames on shutdown
+const BTP_CLOSE_GRACE_PERIOD: Duration = Duration::from_millis(500);
 
 fn default_settlement_api_bind_address() -> SocketAddr {
     SocketAddr::from(([127, 0, 0, 1], 7771))
@@ -101,8 +151,21 @@ fn default_http_bind_address() -> SocketAddr {
 #[allow(unreachable_code)]
 fn default_database_url() -> String {
     #[cfg(feature = "redis")]
-    return default_redis_url();
-    panic!("no backing store configured")
+    {
+        default_redis_url()
+    }
+    #[cfg(all(feature = "sqlite", not(feature = "redis")))]
+    {
+        default_sqlite_url()
+    }
+    #[cfg(all(feature = "sled", not(any(feature = "redis", feature = "sqlite"))))]
+    {
+        default_sled_url()
+    }
+    #[cfg(not(any(feature = "redis", feature = "sqlite", feature = "sled")))]
+    {
+        panic!("no backing store configured")
+    }
 }
 
 fn deserialize_optional_address<'de, D>(deserializer: D) -> Result<Option<Address>, D::Error>
@@ -130,6 +193,22 @@ where
     })
 }
 
+fn deserialize_optional_32_bytes_hex<'de, D>(deserializer: D) -> Result<Option<[u8; 32]>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    if let Ok(hex) = String::deserialize(deserializer) {
+        <[u8; 32]>::from_hex(hex).map(Some).map_err(|err| {
+            DeserializeError::custom(format!(
+                "Invalid hex value (must be 32 hex-encoded bytes): {:?}",
+                err
+            ))
+        })
+    } else {
+        Ok(None)
+    }
+}
+
 fn deserialize_optional_username<'de, D>(deserializer: D) -> Result<Option<Username>, D::Error>
 where
     D: Deserializer<'de>,
@@ -154,13 +233,15 @@ pub struct ExchangeRateConfig {
     /// that the connector will tolerate before invalidating the exchange rate cache.
     #[serde(default = "ExchangeRateConfig::default_poll_failure_tolerance")]
     pub poll_failure_tolerance: u32,
-    /// API to poll for exchange rates. Currently the supported options are:
+    /// Provider to poll for exchange rates. Currently the supported options are:
     /// - [CoinCap](https://docs.coincap.io)
     /// - [CryptoCompare](https://cryptocompare.com) (note this requires an API key)
+    /// - Fixed rates, given as the price of each asset in USD
+    /// - An HTTP endpoint which returns the price of each asset in USD
     /// If this value is not set, the node will not poll for exchange rates and will
     /// instead use the rates configured via the HTTP API.
     #[serde(default)]
-    pub provider: Option<ExchangeRateProvider>,
+    pub provider: Option<ExchangeRateProviderConfig>,
     /// Spread, as a fraction, to add on top of the exchange rate.
     /// This amount is kept as the node operator's profit, or may cover
     /// fluctuations in exchange rates.
@@ -169,6 +250,15 @@ pub struct ExchangeRateConfig {
     /// outgoing packet would be 198 (instead of 200 without the spread).
     #[serde(default)]
     pub spread: f64,
+    /// Age, defined in milliseconds, after which the rates are stale and the
+    /// `stale_rate_policy` is applied to the packets which use them. If this value is not
+    /// set, rates never go stale.
+    #[serde(default)]
+    pub max_rate_age: Option<u64>,
+    /// What to do with packets whose rates are stale: reject them with T00, use the last
+    /// known rates with an extra spread, or use them and log an error
+    #[serde(default)]
+    pub stale_rate_policy: StaleRatePolicy,
 }
 
 impl Default for ExchangeRateConfig {
@@ -180,6 +270,8 @@ impl Default for ExchangeRateConfig {
             poll_failure_tolerance: Self::default_poll_failure_tolerance(),
             provider: Default::default(),
             spread: Self::default_spread(),
+            max_rate_age: None,
+            stale_rate_policy: StaleRatePolicy::default(),
         }
     }
 }
@@ -196,6 +288,54 @@ impl ExchangeRateConfig {
     }
 }
 
+/// Accounts which are created when the node starts
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct BootstrapAccounts(pub Vec<AccountDetails>);
+
+impl PartialEq for BootstrapAccounts {
+    // AccountDetails can't be compared directly because of its secret tokens
+    fn eq(&self, other: &Self) -> bool {
+        serde_json::to_value(&self.0).ok() == serde_json::to_value(&other.0).ok()
+    }
+}
+
+/// API keys which authorize a subset of the admin routes
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct ApiKeys(pub Vec<ApiKey>);
+
+impl PartialEq for ApiKeys {
+    // ApiKey can't be compared directly because of its secret key
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().zip(other.0.iter()).all(|(key, other)| {
+                key.name == other.name
+                    && key.scopes == other.scopes
+                    && key.key.expose_secret() == other.key.expose_secret()
+            })
+    }
+}
+
+/// Format in which the node writes its logs
+#[cfg(feature = "monitoring")]
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines with the fields of the enclosing spans
+    Full,
+    /// One JSON object per line, with the timestamp, level, target, fields and the
+    /// fields of the enclosing spans (such as the request and account IDs)
+    Json,
+}
+
+#[cfg(feature = "monitoring")]
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Full
+    }
+}
+
 /// An all-in-one Interledger node that includes sender and receiver functionality,
 /// a connector, and a management API.
 /// Will connect to the database at the given URL; see the crate features defined in
@@ -211,20 +351,73 @@ pub struct InterledgerNode {
     pub secret_seed: [u8; 32],
     /// HTTP Authorization token for the node admin (sent as a Bearer token)
     pub admin_auth_token: String,
-    /// Data store URI (for example, "redis://127.0.0.1:6379" or "redis+unix:/tmp/redis.sock")
+    /// API keys (sent as Bearer tokens) whose scopes (`read_only`, `accounts:write`,
+    /// `payments:send`, `metrics` or `admin`) determine which API routes they may use,
+    /// e.g. so that monitoring systems don't need the admin auth token
+    #[serde(default)]
+    pub api_keys: ApiKeys,
+    /// Data store URI (for example, "redis://127.0.0.1:6379", "redis+unix:/tmp/redis.sock", "sqlite:///var/lib/ilp/node.db" or "sled:///var/lib/ilp/node")
     #[serde(
         default = "default_database_url",
         // temporary alias for backwards compatibility
         alias = "redis_url"
     )]
     pub database_url: String,
-    /// Database prefix which can be used in case a db instance is shared by multiple nodes
+    /// Database prefix which can be used in case a db instance is shared by multiple nodes.
+    /// May only contain ASCII letters, digits, `_` and `-`
     #[serde(default)]
     pub database_prefix: String,
+    /// Maximum number of accounts kept in memory to avoid loading them from the
+    /// database for every packet. Disabled if 0, which is the default.
+    #[serde(default)]
+    pub account_cache_capacity: usize,
+    /// Time in milliseconds after which cached accounts are reloaded from the database.
+    /// Defaults to 1000ms (1 second).
+    pub account_cache_ttl: Option<u64>,
+    /// Key with which the store encrypts the account tokens, as 32 hex-encoded bytes.
+    /// Defaults to a key derived from the `secret_seed`. When it is set for the first time,
+    /// the tokens encrypted with the key derived from the `secret_seed` are re-encrypted.
+    #[serde(default, deserialize_with = "deserialize_optional_32_bytes_hex")]
+    pub store_master_key: Option<[u8; 32]>,
+    /// File from which the `store_master_key` is read, e.g. a secret mounted by a key
+    /// management service. Ignored if `store_master_key` is set.
+    #[serde(default)]
+    pub store_master_key_file: Option<PathBuf>,
+    /// Key which was used before the current `store_master_key`. The tokens encrypted with
+    /// it are re-encrypted with the current key when the node starts.
+    #[serde(default, deserialize_with = "deserialize_optional_32_bytes_hex")]
+    pub previous_store_master_key: Option<[u8; 32]>,
+    /// Time in milliseconds after which store operations are logged as slow.
+    /// Slow operations are not logged by default.
+    pub store_slow_operation_threshold: Option<u64>,
+    /// Map of store method name (e.g. `update_balances_for_prepare`) -> time in milliseconds
+    /// after which calls to that method are logged as slow, overriding the
+    /// `store_slow_operation_threshold`
+    #[serde(default)]
+    pub store_method_slow_operation_thresholds: HashMap<String, u64>,
+    /// Number of connections opened to Redis. Each connection is multiplexed.
+    /// Defaults to 1.
+    pub redis_pool_size: Option<usize>,
+    /// Time in milliseconds after which connecting to Redis is abandoned.
+    /// There is no timeout by default.
+    pub redis_connection_timeout: Option<u64>,
+    /// Number of times connecting to Redis and commands failing with a transient error
+    /// (e.g. Redis is still loading its dataset) are retried. Defaults to 0.
+    pub redis_max_retries: Option<u32>,
+    /// Time in milliseconds to wait before retrying a Redis command, which is doubled
+    /// for every further retry. Defaults to 100ms.
+    pub redis_retry_backoff: Option<u64>,
     /// IP address and port to listen for HTTP connections
     /// This is used for both the API and ILP over HTTP packets
     #[serde(default = "default_http_bind_address")]
     pub http_bind_address: SocketAddr,
+    /// Certificate and private key with which the HTTP listener terminates TLS, so that
+    /// the API, SPSP, ILP-over-HTTP and BTP endpoints are served over HTTPS (and `wss://`).
+    /// If this is not provided, the listener serves plain HTTP.
+    /// Needs the feature flag "tls" to be enabled
+    #[cfg(feature = "tls")]
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
     /// IP address and port to listen for the Settlement Engine API
     #[serde(default = "default_settlement_api_bind_address")]
     pub settlement_api_bind_address: SocketAddr,
@@ -236,9 +429,61 @@ pub struct InterledgerNode {
     /// Interval, defined in milliseconds, on which the node will broadcast routing
     /// information to other nodes using CCP. Defaults to 30000ms (30 seconds).
     pub route_broadcast_interval: Option<u64>,
+    /// Prefer the next hop with the lowest latency between routes which are otherwise equally
+    /// good. The latency of each next hop is the exponentially weighted moving average of the
+    /// time it takes to answer the packets sent to it. Defaults to false.
+    #[serde(default)]
+    pub latency_aware_routing: bool,
+    /// Time, defined in milliseconds, after their expiry during which the packets received
+    /// over BTP and ILP-over-HTTP (including the STREAM packets paid to the node's accounts)
+    /// and the CCP messages are not yet treated as expired, so that peers whose clocks are
+    /// slightly behind don't get R00 rejects. Defaults to 0.
+    #[serde(default)]
+    pub clock_skew_tolerance: u64,
     #[serde(default)]
     /// Configuration for calculating exchange rates between various pairs.
     pub exchange_rate: ExchangeRateConfig,
+    /// Fees deducted from the packets forwarded from one account to another. The most
+    /// specific schedule which matches a packet's accounts applies: one for the pair of
+    /// accounts, then one for the sending account, then one for the receiving account,
+    /// then one without accounts. Changed when the configuration is reloaded.
+    #[serde(default)]
+    pub fees: Vec<FeeSchedule>,
+    /// Prefixes of the destinations to which the packets of an account, or of all accounts,
+    /// may or may not be sent. A packet is only routed if every rule which applies to the
+    /// account which sent it permits its destination. Changed when the configuration is reloaded.
+    #[serde(default)]
+    pub destinations: Vec<DestinationRule>,
+    /// What to do with a Prepare which an account sends again, with the same execution
+    /// condition, destination, amount and expiry, while the original is still pending:
+    /// `attach` it to the result of the original (the default) or `reject` it with F00.
+    /// Either way, the account is not debited twice.
+    #[serde(default)]
+    pub duplicate_prepare_policy: DuplicatePreparePolicy,
+    /// Accounts which are created when the node starts, unless an account with the same
+    /// username already exists (existing accounts are left unchanged). They are connected
+    /// to their BTP servers, parents and settlement engines like accounts created with the API.
+    #[serde(default)]
+    pub accounts: BootstrapAccounts,
+    /// Map of ILP address prefix -> username of the account to which packets for that prefix
+    /// are routed. If set, it replaces the static routes in the store when the node starts
+    /// and when the configuration is reloaded.
+    #[serde(default)]
+    pub static_routes: Option<HashMap<String, Username>>,
+    /// Tracing filter directives, e.g. `info,interledger=debug`. Defaults to the
+    /// `RUST_LOG` environment variable. Changed when the configuration is reloaded.
+    #[cfg(feature = "monitoring")]
+    #[serde(default)]
+    pub log_level: Option<String>,
+    /// Format of the logs, either `full` (the default) or `json`
+    #[cfg(feature = "monitoring")]
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// Sampling of the packets whose debug and trace events are logged, and redaction of
+    /// the fulfillments, shared secrets and auth tokens in the logs and traces. Changed when
+    /// the configuration is reloaded.
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
     /// Configuration for [Prometheus](https://prometheus.io) metrics collection.
     /// If this configuration is not provided, the node will not collect metrics.
     /// Needs the feature flag "monitoring" to be enabled
@@ -247,12 +492,78 @@ pub struct InterledgerNode {
     pub prometheus: Option<PrometheusConfig>,
     #[cfg(feature = "google-pubsub")]
     pub google_pubsub: Option<PubsubConfig>,
+    /// Configuration for exporting the spans of the packets with
+    /// [OpenTelemetry](https://opentelemetry.io). If this configuration is not provided,
+    /// no spans are exported.
+    /// Needs the feature flag "opentelemetry" to be enabled
+    #[cfg(feature = "opentelemetry")]
+    #[serde(default)]
+    pub opentelemetry: Option<OpenTelemetryConfig>,
+    /// CORS policy and security headers of the responses of the HTTP API, including the
+    /// SPSP and ILP-over-HTTP endpoints
+    #[serde(default)]
+    pub http_headers: HttpHeadersConfig,
+    /// Configuration of the webhooks to which the events of the accounts, such as incoming
+    /// payments and settlements, are sent. If this is not provided, no events are sent.
+    #[serde(default)]
+    pub webhooks: Option<WebhooksConfig>,
+    /// Configuration for recording the packets sent by and to the accounts, which wallets
+    /// can show as their transaction history. If this is not provided, no packets are recorded.
+    #[serde(default)]
+    pub packet_history: Option<PacketHistoryConfig>,
+    /// Configuration of the local provider, on which the apps running on the same machine
+    /// get child accounts the first time they connect over BTP or ILP-over-HTTP, without
+    /// any configuration. If this is not provided, the local provider is not started.
+    #[serde(default)]
+    pub local_provider: Option<LocalProviderConfig>,
+    /// Journal the outgoing packets in the store while they are in flight, so that the
+    /// balance changes of their responses are applied even if the node crashes before it
+    /// applied them. The journaled packets are reconciled a minute after they expired.
+    /// Costs two extra writes to the store per packet. Defaults to false.
+    #[serde(default)]
+    pub packet_journal: bool,
+    /// Record the money received on each STREAM connection in the store before fulfilling
+    /// its packets, so that the receiving applications can list the payments they did not
+    /// acknowledge yet and recover the ones they missed after a crash. Costs an extra write
+    /// to the store per packet received. Defaults to false.
+    #[serde(default)]
+    pub payment_acknowledgements: bool,
+    /// The middleware which the incoming and outgoing packets pass through, in order, and
+    /// their settings. Defaults to the chains of the previous versions of the node.
+    #[serde(default)]
+    pub service_chain: ServiceChainConfig,
+    /// Run the node as a stateless relay, which only authenticates, routes and validates the
+    /// packets it receives over ILP-over-HTTP. The `accounts` and `static_routes` are kept in
+    /// memory and the `database_url` is not used: there are no balances, settlements, API or
+    /// BTP connections. The `ilp_address` must be configured. Defaults to false.
+    #[serde(default)]
+    pub relay: bool,
+    /// Configuration of the active/standby failover with another node sharing the same store.
+    /// The node only handles packets while it holds the leadership in the store, and takes
+    /// over when the other node stops renewing it. If this is not provided, the node is
+    /// always active.
+    #[serde(default)]
+    pub failover: Option<FailoverConfig>,
+    /// Number of threads of the Tokio runtime. Only used by the `ilp-node` binary,
+    /// which builds the runtime before starting the node.
+    #[serde(default)]
+    pub runtime: RuntimeConfig,
+    /// Limits on the work done at the same time by the store, the ILP over HTTP client
+    /// and the BTP connections
+    #[serde(default)]
+    pub concurrency: ConcurrencyConfig,
     /// The delay in seconds to settle peering account to `settle_to` level in addition to settling
-    /// the account when it exceeds the settlement threshold.
+    /// the account when it exceeds the settlement threshold. Accounts with their own
+    /// `settle_every` use that delay instead.
     ///
     /// See further notes at `--help` output.
     #[cfg(feature = "balance-tracking")]
     pub settle_every: Option<NonZeroU32>,
+    /// The clock which all services of the node check the expiry of the packets, the age
+    /// of the exchange rates and the due settlements against. It can't be configured, but
+    /// tests embedding the node can replace it to control the time.
+    #[serde(skip)]
+    pub clock: SharedClock,
 }
 
 impl InterledgerNode {
@@ -263,9 +574,43 @@ impl InterledgerNode {
     // TODO when a BTP connection is made, insert a outgoing HTTP entry into the Store to tell other
     // connector instances to forward packets for that account to us
     pub async fn serve(self, log_writer: Option<LogWriter>) -> Result<(), ()> {
+        self.serve_with_updates(log_writer, None).await
+    }
+
+    /// Like `serve`, but also applies the log level, static routes and exchange rate
+    /// settings of every reloaded configuration received from `updates`
+    pub async fn serve_with_updates(
+        self,
+        log_writer: Option<LogWriter>,
+        updates: Option<ConfigUpdates>,
+    ) -> Result<(), ()> {
+        self.run(log_writer, updates, None).await
+    }
+
+    /// Like `serve_with_updates`, but only returns once the node was shut down gracefully
+    /// after `shutdown` completed: the HTTP listeners are closed, the packets in flight are
+    /// fulfilled or rejected (or expired), the BTP connections are closed and the store is
+    /// flushed
+    pub async fn serve_until(
+        self,
+        log_writer: Option<LogWriter>,
+        updates: Option<ConfigUpdates>,
+        shutdown: ShutdownSignal,
+    ) -> Result<(), ()> {
+        self.run(log_writer, updates, Some(shutdown)).await
+    }
+
+    async fn run(
+        self,
+        log_writer: Option<LogWriter>,
+        updates: Option<ConfigUpdates>,
+        shutdown: Option<ShutdownSignal>,
+    ) -> Result<(), ()> {
+        // Report a missing executor before anything is started on it
+        interledger::service::runtime::ensure().map_err(|err| error!("{}", err))?;
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
-                let f = futures::future::join(serve_prometheus(self.clone()), self.serve_node(log_writer)).then(
+                let f = futures::future::join(serve_prometheus(self.clone()), self.serve_node(log_writer, updates, shutdown)).then(
                     |r| async move {
                         if r.0.is_ok() || r.1.is_ok() {
                             Ok(())
@@ -275,14 +620,24 @@ impl InterledgerNode {
                     },
                 );
             } else {
-                let f = self.serve_node(log_writer);
+                let f = self.serve_node(log_writer, updates, shutdown);
             }
         }
 
         f.await
     }
 
-    async fn serve_node(self, log_writer: Option<LogWriter>) -> Result<(), ()> {
+    async fn serve_node(
+        self,
+        log_writer: Option<LogWriter>,
+        updates: Option<ConfigUpdates>,
+        shutdown: Option<ShutdownSignal>,
+    ) -> Result<(), ()> {
+        telemetry::set_config(&self.telemetry);
+        if self.relay {
+            return serve_relay(self, shutdown).await;
+        }
+
         let ilp_address = if let Some(address) = &self.ilp_address {
             address.clone()
         } else {
@@ -303,7 +658,13 @@ impl InterledgerNode {
 
         match database_url.scheme() {
             #[cfg(feature = "redis")]
-            "redis" | "redis+unix" => serve_redis_node(self, ilp_address, log_writer).await,
+            "redis" | "redis+unix" => {
+                serve_redis_node(self, ilp_address, log_writer, updates, shutdown).await
+            }
+            #[cfg(feature = "sqlite")]
+            "sqlite" => serve_sqlite_node(self, ilp_address, log_writer, updates, shutdown).await,
+            #[cfg(feature = "sled")]
+            "sled" => serve_sled_node(self, ilp_address, log_writer, updates, shutdown).await,
             other => {
                 error!("unsupported data source scheme: {}", other);
                 Err(())
@@ -311,12 +672,43 @@ impl InterledgerNode {
         }
     }
 
+    /// Returns the secret with which the store encrypts the account tokens, and the previous
+    /// secret if there are tokens which may need to be re-encrypted. `derived_secret` is
+    /// the secret derived from the `secret_seed`, which is used if no master key is configured.
+    pub(crate) fn store_secrets(
+        &self,
+        derived_secret: [u8; 32],
+    ) -> Result<([u8; 32], Option<[u8; 32]>), ()> {
+        let master_key = match (&self.store_master_key, &self.store_master_key_file) {
+            (Some(key), _) => Some(*key),
+            (None, Some(path)) => {
+                let hex = fs::read_to_string(path).map_err(|err| {
+                    error!(target: "interledger-node", "Error reading store master key from {}: {}", path.display(), err)
+                })?;
+                let key = <[u8; 32]>::from_hex(hex.trim()).map_err(|err| {
+                    error!(target: "interledger-node", "Invalid store master key in {} (must be 32 hex-encoded bytes): {:?}", path.display(), err)
+                })?;
+                Some(key)
+            }
+            (None, None) => None,
+        };
+
+        match master_key {
+            // Tokens stored before the master key was configured were encrypted with
+            // the derived secret, so they are migrated unless another key is given
+            Some(key) => Ok((key, self.previous_store_master_key.or(Some(derived_secret)))),
+            None => Ok((derived_secret, self.previous_store_master_key)),
+        }
+    }
+
     #[allow(clippy::cognitive_complexity)]
     pub(crate) async fn chain_services<S>(
         self,
         store: S,
         ilp_address: Address,
         _log_writer: Option<LogWriter>,
+        updates: Option<ConfigUpdates>,
+        shutdown: Option<ShutdownSignal>,
     ) -> Result<(), ()>
     where
         S: NodeStore<Account = Account>
@@ -324,14 +716,20 @@ impl InterledgerNode {
             + BtpStore<Account = Account>
             + HttpStore<Account = Account>
             + StreamNotificationsStore<Account = Account>
+            + IncomingPaymentsStore
             + BalanceStore
+            + PacketHistoryStore
+            + PacketJournalStore
             + SettlementStore<Account = Account>
+            + OutgoingSettlementStore
             + ExchangeRateStore
             + BalanceStore
             + SettlementStore<Account = Account>
             + RouterStore<Account = Account>
             + CcpRoutingStore<Account = Account>
             + RateLimitStore<Account = Account>
+            + VolumeLimitStore<Account = Account>
+            + AdmissionStore
             + LeftoversStore<AccountId = Uuid, AssetType = BigUint>
             + IdempotentStore
             + AccountStore<Account = Account>
@@ -347,16 +745,46 @@ impl InterledgerNode {
 
         let secret_seed = Bytes::copy_from_slice(&self.secret_seed[..]);
         let http_bind_address = self.http_bind_address;
+        // The certificate is read before anything is started, so that the node does not
+        // run without its HTTP listener if the files are invalid
+        #[cfg(feature = "tls")]
+        let tls = match self.tls {
+            Some(ref tls) => Some(tls.load().map_err(
+                |err| error!(target: "interledger-node", "Error loading the TLS certificate: {}", err),
+            )?),
+            None => None,
+        };
         let settlement_api_bind_address = self.settlement_api_bind_address;
+        if let Some(ref local_provider) = self.local_provider {
+            if !local_provider.bind_address.ip().is_loopback() {
+                error!(target: "interledger-node", "The local provider must be bound to a loopback address, since anyone who can reach it can create accounts (got {})", local_provider.bind_address);
+                return Err(());
+            }
+        }
+        if let Err(err) = self.service_chain.validate() {
+            error!(target: "interledger-node", "Invalid service chain: {}", err);
+            return Err(());
+        }
         let admin_auth_token = self.admin_auth_token.clone();
         let default_spsp_account = self.default_spsp_account.clone();
         let route_broadcast_interval = self.route_broadcast_interval;
-        let exchange_rate_provider = self.exchange_rate.provider.clone();
-        let exchange_rate_poll_interval = self.exchange_rate.poll_interval;
-        let exchange_rate_poll_failure_tolerance = self.exchange_rate.poll_failure_tolerance;
-        let exchange_rate_spread = self.exchange_rate.spread;
+        let clock_skew_tolerance = Duration::from_millis(self.clock_skew_tolerance);
+        let validator_skew_tolerance = self
+            .service_chain
+            .validator
+            .clock_skew_tolerance
+            .map(Duration::from_millis)
+            .unwrap_or(clock_skew_tolerance);
+        let spread = Spread::new(self.exchange_rate.spread);
+        let fees = Fees::new(self.fees.clone());
+        let destinations = DestinationRules::new(self.destinations.clone());
         #[cfg(feature = "google-pubsub")]
         let google_pubsub = self.google_pubsub.clone();
+        #[cfg(feature = "monitoring")]
+        let gauge_interval = self
+            .prometheus
+            .as_ref()
+            .map(|prometheus| Duration::from_millis(prometheus.gauge_interval));
 
         let btp_accounts = store
             .get_btp_outgoing_accounts()
@@ -401,45 +829,182 @@ impl InterledgerNode {
         let btp_server_service_clone = btp_server_service.clone();
         let btp = btp_client_service.clone();
 
+        #[cfg(feature = "monitoring")]
+        {
+            if let Some(interval) = gauge_interval {
+                let btp_server = btp_server_service.clone();
+                let btp_client = btp_client_service.clone();
+                spawn_gauge_sampler(
+                    store.clone(),
+                    move || btp_server.connection_count() + btp_client.connection_count(),
+                    interval,
+                );
+            }
+        }
+
         // The BTP service is both an Incoming and Outgoing one so we pass it first as the Outgoing
         // service to others like the router and then call handle_incoming on it to set up the incoming handler
         let outgoing_service = btp_server_service.clone();
         let outgoing_service = HttpClientService::new(store.clone(), outgoing_service);
+        let outgoing_service = match self.concurrency.http_client {
+            Some(limit) => outgoing_service.max_in_flight(limit),
+            None => outgoing_service,
+        };
 
         #[cfg(feature = "monitoring")]
-        let outgoing_service = outgoing_service.wrap(outgoing_metrics);
+        let outgoing_service = outgoing_service
+            .wrap(trace_transport)
+            .wrap(outgoing_metrics);
 
-        // Note: the expiry shortener must come after the Validator so that the expiry duration
-        // is shortened before we check whether there is enough time left
-        let outgoing_service = ValidatorService::outgoing(store.clone(), outgoing_service);
-        let outgoing_service = ExpiryShortenerService::new(outgoing_service);
+        // Measures the time each peer takes to answer, which breaks the ties between routes
+        let latency_scores = LatencyScores::new();
         let outgoing_service =
-            StreamReceiverService::new(secret_seed.clone(), store.clone(), outgoing_service);
+            LatencyService::new(latency_scores.clone(), store.clone(), outgoing_service);
 
-        #[cfg(feature = "balance-tracking")]
-        let outgoing_service = match self.settle_every {
-            Some(seconds) => {
-                use futures::stream::StreamExt;
+        // Caps the requests in flight to each peer, over whichever transport they are sent
+        let outgoing_service = PeerConcurrencyService::new(store.clone(), outgoing_service).wait(
+            Duration::from_millis(self.concurrency.per_peer_wait.unwrap_or(0)),
+        );
+        let outgoing_service = match self.concurrency.per_peer {
+            Some(limit) => outgoing_service.max_in_flight(limit),
+            None => outgoing_service,
+        };
 
-                let delay = Duration::from_secs(seconds.get().into());
-                let (tx, rx) = tokio::sync::mpsc::channel(128);
+        // The STREAM receiver and the SPSP endpoints share the server secret, which may
+        // have been rotated
+        let secret_generation = store.get_secret_generation().await.map_err(|err| {
+            error!(target: "interledger-node", "Error loading the generation of the STREAM server secret: {}", err)
+        })?;
+        let connection_generator =
+            ConnectionGenerator::with_generation(&secret_seed[..], &secret_generation);
 
-                let rx = tokio_stream::wrappers::ReceiverStream::new(rx);
-                let rx = rx.fuse();
+        // Publish the settlements to the subscribers of `/events` and to the webhooks
+        let (settlement_events, _) = tokio::sync::broadcast::channel(NODE_EVENTS_CAPACITY);
 
-                start_delayed_settlement(delay, rx.fuse(), store.clone());
+        #[cfg(feature = "balance-tracking")]
+        start_settlement_retries(
+            SETTLEMENT_RETRY_INTERVAL,
+            store.clone(),
+            self.clock.clone(),
+            Some(settlement_events.clone()),
+        );
 
-                BalanceService::new(store.clone(), Some(tx), outgoing_service)
-            }
-            None => BalanceService::new(store.clone(), None, outgoing_service),
-        };
+        // The configured middleware are chained from the last one a packet passes through.
+        // Note: the expiry shortener should come before the Validator so that the expiry
+        // duration is shortened before we check whether there is enough time left
+        let mut outgoing_service = BoxedOutgoingService::new(outgoing_service);
+        for middleware in self.service_chain.outgoing.iter().rev() {
+            outgoing_service = match middleware {
+                OutgoingMiddleware::Validator => BoxedOutgoingService::new(
+                    ValidatorService::outgoing(store.clone(), outgoing_service)
+                        .clock(self.clock.clone())
+                        .skew_tolerance(validator_skew_tolerance),
+                ),
+                OutgoingMiddleware::ExpiryShortener => BoxedOutgoingService::new(
+                    ExpiryShortenerService::new(outgoing_service).clock(self.clock.clone()),
+                ),
+                OutgoingMiddleware::StreamReceiver => {
+                    let stream_receiver = StreamReceiverService::with_connection_generator(
+                        connection_generator.clone(),
+                        store.clone(),
+                        outgoing_service,
+                    );
+                    if self.payment_acknowledgements {
+                        BoxedOutgoingService::new(stream_receiver.record_payments(store.clone()))
+                    } else {
+                        BoxedOutgoingService::new(stream_receiver)
+                    }
+                }
+                // The delayed settlements run even without a node-wide delay, as accounts
+                // can configure their own
+                #[cfg(feature = "balance-tracking")]
+                OutgoingMiddleware::Balance => {
+                    use futures::stream::StreamExt;
+
+                    let delay = self
+                        .settle_every
+                        .map(|seconds| Duration::from_secs(seconds.get().into()));
+                    let (tx, rx) = tokio::sync::mpsc::channel(128);
+
+                    let rx = tokio_stream::wrappers::ReceiverStream::new(rx);
+                    let rx = rx.fuse();
+
+                    start_delayed_settlement(
+                        delay,
+                        rx.fuse(),
+                        store.clone(),
+                        self.clock.clone(),
+                        Some(settlement_events.clone()),
+                    );
+
+                    let balance_service =
+                        BalanceService::new(store.clone(), Some(tx), outgoing_service)
+                            .clock(self.clock.clone())
+                            .settlement_events(settlement_events.clone());
+                    // The AdmissionService already deducted the amounts of the Prepare packets
+                    let balance_service = if self
+                        .service_chain
+                        .has_incoming(IncomingMiddleware::RateLimit)
+                    {
+                        balance_service.admitted_prepares()
+                    } else {
+                        balance_service
+                    };
+                    if self.packet_journal {
+                        start_journal_reconciliation(
+                            JOURNAL_RECONCILIATION_INTERVAL,
+                            store.clone(),
+                        );
+                        BoxedOutgoingService::new(balance_service.journal(store.clone()))
+                    } else {
+                        BoxedOutgoingService::new(balance_service)
+                    }
+                }
+                #[cfg(not(feature = "balance-tracking"))]
+                OutgoingMiddleware::Balance => {
+                    unreachable!("the service chain was validated")
+                }
+                OutgoingMiddleware::ExchangeRate => {
+                    let mut exchange_rate_service = ExchangeRateService::with_spread(
+                        spread.clone(),
+                        store.clone(),
+                        outgoing_service,
+                    )
+                    .clock(self.clock.clone());
+                    if let Some(max_age) = self.exchange_rate.max_rate_age {
+                        exchange_rate_service = exchange_rate_service.stale_rates(
+                            Duration::from_millis(max_age),
+                            self.exchange_rate.stale_rate_policy,
+                        );
+                    }
+                    BoxedOutgoingService::new(exchange_rate_service)
+                }
+                // Packets between accounts whose assets would be converted at a nonsense rate
+                // are rejected before the exchange rate is applied
+                OutgoingMiddleware::AssetGuard => BoxedOutgoingService::new(
+                    AssetGuardService::new(store.clone(), outgoing_service),
+                ),
+                // Fees are deducted before the exchange rate is applied, in the asset of the
+                // sending account
+                OutgoingMiddleware::Fees => BoxedOutgoingService::new(FeeService::new(
+                    fees.clone(),
+                    store.clone(),
+                    outgoing_service,
+                )),
+            };
+        }
 
-        let outgoing_service =
-            ExchangeRateService::new(exchange_rate_spread, store.clone(), outgoing_service);
+        // Publish the packets and balance updates to the subscribers of the events API
+        let (events, _) = tokio::sync::broadcast::channel(NODE_EVENTS_CAPACITY);
+        let outgoing_service = EventsService::new(store.clone(), events.clone(), outgoing_service);
+        let outgoing_service = PacketHistoryService::new(
+            store.clone(),
+            self.packet_history.is_some(),
+            outgoing_service,
+        );
 
         #[cfg(feature = "google-pubsub")]
-        let outgoing_service =
-            outgoing_service.wrap(create_google_pubsub_wrapper(google_pubsub).await);
+        let outgoing_service = GooglePubsubService::new(google_pubsub, outgoing_service).await;
 
         // Add tracing to add the outgoing request details to the incoming span
         cfg_if! {
@@ -465,18 +1030,92 @@ impl InterledgerNode {
             outgoing_service.clone(),
             incoming_service,
         );
-        ccp_builder.ilp_address(ilp_address.clone());
+        // Publish the changes of the routing table to the subscribers of `/routes/watch`
+        let (route_changes, _) = tokio::sync::broadcast::channel(NODE_EVENTS_CAPACITY);
+        ccp_builder
+            .ilp_address(ilp_address.clone())
+            .clock(self.clock.clone())
+            .skew_tolerance(clock_skew_tolerance)
+            .route_changes(route_changes.clone());
         if let Some(ms) = route_broadcast_interval {
             ccp_builder.broadcast_interval(ms);
         }
+        if self.latency_aware_routing {
+            ccp_builder.latency_scores(latency_scores);
+        }
 
-        let incoming_service = ccp_builder.to_service();
+        let ccp = ccp_builder.to_service();
+        let incoming_service = ccp.clone();
         let incoming_service = EchoService::new(store.clone(), incoming_service);
         let incoming_service = SettlementMessageService::new(incoming_service);
-        let incoming_service = IldcpService::new(incoming_service);
-        let incoming_service = MaxPacketAmountService::new(store.clone(), incoming_service);
-        let incoming_service = ValidatorService::incoming(store.clone(), incoming_service);
-        let incoming_service = RateLimitService::new(store.clone(), incoming_service);
+        let incoming_service =
+            ChildAccountsService::new(store.clone(), IldcpService::new(incoming_service));
+        let mut incoming_service = BoxedIncomingService::new(incoming_service);
+        for middleware in self.service_chain.incoming.iter().rev() {
+            incoming_service = match middleware {
+                IncomingMiddleware::MaxPacketAmount => BoxedIncomingService::new(
+                    MaxPacketAmountService::new(store.clone(), incoming_service),
+                ),
+                IncomingMiddleware::MinPacketAmount => BoxedIncomingService::new(
+                    MinPacketAmountService::new(store.clone(), incoming_service),
+                ),
+                IncomingMiddleware::DestinationFilter => {
+                    BoxedIncomingService::new(DestinationFilterService::new(
+                        destinations.clone(),
+                        store.clone(),
+                        incoming_service,
+                    ))
+                }
+                IncomingMiddleware::Validator => BoxedIncomingService::new(
+                    ValidatorService::incoming(store.clone(), incoming_service)
+                        .clock(self.clock.clone())
+                        .skew_tolerance(validator_skew_tolerance),
+                ),
+                // The limits and the balance of the sender are applied in a single call to
+                // the store
+                IncomingMiddleware::RateLimit => {
+                    let admission_service = AdmissionService::new(store.clone(), incoming_service);
+                    #[cfg(feature = "balance-tracking")]
+                    let admission_service =
+                        if self.service_chain.has_outgoing(OutgoingMiddleware::Balance) {
+                            let admission_service = admission_service.track_balances();
+                            // The BalanceService refunds the journaled packets itself
+                            if self.packet_journal {
+                                admission_service.leave_balance_refunds()
+                            } else {
+                                admission_service
+                            }
+                        } else {
+                            admission_service
+                        };
+                    BoxedIncomingService::new(admission_service)
+                }
+                // Duplicates of pending Prepares are short-circuited before the sender is
+                // debited again
+                IncomingMiddleware::DuplicatePrepare => {
+                    BoxedIncomingService::new(DuplicatePrepareService::new(
+                        self.service_chain
+                            .duplicate_prepare
+                            .policy
+                            .unwrap_or(self.duplicate_prepare_policy),
+                        store.clone(),
+                        incoming_service,
+                    ))
+                }
+            };
+        }
+
+        // Count the packets in flight so that they can be drained on shutdown
+        let packet_drain = PacketDrain::new();
+        let incoming_service = packet_drain.service(incoming_service);
+
+        // On standby, the connections and the routing table are kept up to date but the
+        // packets are left to the active node
+        let leadership = match self.failover {
+            Some(ref failover) => Leadership::spawn(store.clone(), failover.clone()),
+            None => Leadership::active(),
+        };
+        let incoming_service = leadership.service(incoming_service);
 
         // Add tracing to track the incoming request details
         #[cfg(feature = "monitoring")]
@@ -500,12 +1139,13 @@ impl InterledgerNode {
             }
         }
 
+        let btp_concurrency = self.concurrency.btp.unwrap_or(1);
         btp_server_service
-            .handle_incoming(incoming_service_btp.clone())
+            .handle_incoming_concurrently(incoming_service_btp.clone(), btp_concurrency)
             .await;
 
         btp_client_service
-            .handle_incoming(incoming_service_btp)
+            .handle_incoming_concurrently(incoming_service_btp, btp_concurrency)
             .await;
 
         cfg_if! {
@@ -534,7 +1174,83 @@ impl InterledgerNode {
         if let Some(username) = default_spsp_account {
             api.default_spsp_account(username);
         }
+        api.api_keys(self.api_keys.0.clone());
+        api.connection_generator(connection_generator.clone());
         api.node_version(env!("CARGO_PKG_VERSION").to_string());
+        let created = api
+            .bootstrap_accounts(self.accounts.0.clone())
+            .await
+            .map_err(|err| {
+                error!(target: "interledger-node", "Error creating the configured accounts: {:?}", err)
+            })?;
+        if created > 0 {
+            info!(target: "interledger-node", "Created {} configured accounts", created);
+        }
+        if let Some(ref routes) = self.static_routes {
+            apply_static_routes(&store, routes).await?;
+        }
+        // Starts polling exchange rates and applies the settings of reloaded configurations
+        let reloader = ConfigReloader::new(
+            store.clone(),
+            spread,
+            fees,
+            destinations,
+            &self,
+            _log_writer.clone(),
+        );
+        if let Some(updates) = updates {
+            reloader.spawn(updates);
+        }
+        if let Some(ref webhooks) = self.webhooks {
+            spawn_webhooks(
+                store.clone(),
+                webhooks.clone(),
+                settlement_events.subscribe(),
+            );
+        }
+        if let Some(ref packet_history) = self.packet_history {
+            spawn_packet_history_pruning(store.clone(), packet_history.clone());
+        }
+        spawn_secret_generation_reload(
+            store.clone(),
+            secret_seed.clone(),
+            connection_generator,
+            secret_generation,
+        );
+        // Peers may be connected to the BTP server or client
+        {
+            let btp_server = btp_server_service.clone();
+            let btp_client = btp.clone();
+            api.connection_count(std::sync::Arc::new(move || {
+                btp_server.connection_count() + btp_client.connection_count()
+            }));
+        }
+        spawn_connection_events(
+            store.clone(),
+            vec![
+                btp_server_service.subscribe_connection_events(),
+                btp.subscribe_connection_events(),
+            ],
+            events.clone(),
+        );
+        spawn_settlement_events(store.clone(), settlement_events.subscribe(), events.clone());
+        spawn_child_routes(
+            store.clone(),
+            ccp.clone(),
+            vec![
+                btp_server_service.subscribe_connection_events(),
+                btp.subscribe_connection_events(),
+            ],
+        );
+        spawn_route_failover(
+            ccp,
+            vec![
+                btp_server_service.subscribe_connection_events(),
+                btp.subscribe_connection_events(),
+            ],
+        );
+        api.events(events);
+        api.route_changes(route_changes);
 
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
@@ -549,6 +1265,21 @@ impl InterledgerNode {
             }
         }
 
+        // The apps on the same machine connect to the local provider without authentication
+        // and get their own accounts
+        let local_provider = self.local_provider.clone().map(|config| {
+            let bind_address = config.bind_address;
+            let provider = LocalProvider::new(store.clone(), config);
+            let filter = btp_service_as_provisioning_filter(
+                btp_server_service_clone.clone(),
+                provider.clone(),
+            )
+            .or(IlpOverHttpServer::new(incoming_service_http.clone(), provider).as_filter())
+            .recover(default_rejection_handler)
+            .with(warp::log("interledger-local-provider"));
+            (bind_address, filter)
+        });
+
         // add an API of ILP over HTTP and add rejection handler
         let api = api
             .into_warp_filter()
@@ -563,11 +1294,12 @@ impl InterledgerNode {
         // changing the tracing level by administrators
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
+                let api_auth = ApiAuth::new(self.admin_auth_token.clone(), self.api_keys.0.clone());
                 let admin_only = warp::header::<SecretString>("authorization")
                     .and_then(move |authorization: SecretString| {
-                        let admin_auth_header = format!("Bearer {}", self.admin_auth_token.clone());
+                        let authorized = api_auth.authorize(authorization.expose_secret(), ApiScope::Admin);
                         async move {
-                            if authorization.expose_secret() == &admin_auth_header {
+                            if authorized {
                                 Ok::<(), warp::Rejection>(())
                             } else {
                                 Err(warp::Rejection::from(ApiError::unauthorized()))
@@ -620,30 +1352,78 @@ impl InterledgerNode {
             }
         }
 
-        let api = api
-            .recover(default_rejection_handler)
+        let api = with_http_headers(api.recover(default_rejection_handler), &self.http_headers)
             .with(warp::log("interledger-api"))
             .boxed();
 
-        info!(target: "interledger-node", "Interledger.rs node HTTP API listening on: {}", http_bind_address);
-        spawn(warp::serve(api).bind(http_bind_address));
+        // The listeners stop accepting connections once the node shuts down
+        let shutdown = shutdown.map(FutureExt::shared);
+        let listeners_closed = {
+            let shutdown = shutdown.clone();
+            move || {
+                let shutdown = shutdown.clone();
+                async move {
+                    match shutdown {
+                        Some(shutdown) => shutdown.await,
+                        None => futures::future::pending().await,
+                    }
+                }
+            }
+        };
+
+        #[cfg(feature = "tls")]
+        let api_server = match tls {
+            Some((cert, key)) => {
+                info!(target: "interledger-node", "Interledger.rs node HTTPS API listening on: {}", http_bind_address);
+                let (_, api_server) = warp::serve(api)
+                    .tls()
+                    .cert(cert)
+                    .key(key)
+                    .bind_with_graceful_shutdown(http_bind_address, listeners_closed());
+                spawn(api_server)
+            }
+            None => {
+                info!(target: "interledger-node", "Interledger.rs node HTTP API listening on: {}", http_bind_address);
+                let (_, api_server) = warp::serve(api)
+                    .bind_with_graceful_shutdown(http_bind_address, listeners_closed());
+                spawn(api_server)
+            }
+        };
+        #[cfg(not(feature = "tls"))]
+        let api_server = {
+            info!(target: "interledger-node", "Interledger.rs node HTTP API listening on: {}", http_bind_address);
+            let (_, api_server) =
+                warp::serve(api).bind_with_graceful_shutdown(http_bind_address, listeners_closed());
+            spawn(api_server)
+        };
 
         // Settlement API
-        let settlement_api = create_settlements_filter(store.clone(), outgoing_service.clone());
+        let settlement_api =
+            create_settlements_filter(store.clone(), outgoing_service.clone(), settlement_events);
         info!(target: "interledger-node", "Settlement API listening on: {}", settlement_api_bind_address);
-        spawn(warp::serve(settlement_api).bind(settlement_api_bind_address));
+        let (_, settlement_server) = warp::serve(settlement_api)
+            .bind_with_graceful_shutdown(settlement_api_bind_address, listeners_closed());
+        let settlement_server = spawn(settlement_server);
+
+        let local_provider_server = local_provider.map(|(bind_address, filter)| {
+            info!(target: "interledger-node", "Local provider listening on: {}", bind_address);
+            let (_, server) =
+                warp::serve(filter).bind_with_graceful_shutdown(bind_address, listeners_closed());
+            spawn(server)
+        });
 
-        // Exchange Rate Polling
-        if let Some(provider) = exchange_rate_provider {
-            let exchange_rate_fetcher = ExchangeRateFetcher::new(
-                provider,
-                exchange_rate_poll_failure_tolerance,
-                store.clone(),
-            );
-            exchange_rate_fetcher
-                .spawn_interval(Duration::from_millis(exchange_rate_poll_interval));
-        } else {
-            debug!(target: "interledger-node", "Not using exchange rate provider. Rates must be set via the HTTP API");
+        if let Some(shutdown) = shutdown {
+            shutdown.await;
+            info!(target: "interledger-node", "Shutting down the Interledger.rs node");
+            // The HTTP servers finish the requests which are in progress before they stop
+            let _ =
+                futures::future::join3(packet_drain.drain(), api_server, settlement_server).await;
+            if let Some(server) = local_provider_server {
+                let _ = server.await;
+            }
+            btp_server_service.close();
+            btp.close();
+            tokio::time::sleep(BTP_CLOSE_GRACE_PERIOD).await;
         }
 
         Ok(())
@@ -652,13 +1432,10 @@ impl InterledgerNode {
 
 cfg_if! {
     if #[cfg(feature = "monitoring")] {
-        type TracingSubscriber =
-            Formatter<format::DefaultFields, format::Format<format::Full, ChronoUtc>, NonBlocking>;
-
         #[derive(Clone)]
         pub struct LogWriter {
             stdout:     Arc<Stdout>,
-            pub handle: Option<Handle<EnvFilter, TracingSubscriber>>,
+            pub handle: Option<Handle<EnvFilter, Registry>>,
         }
 
         impl Default for LogWriter {
@@ -0,0 +1,138 @@
+use interledger::{
+    service::{Account, BoxedIlpFuture, OutgoingRequest, OutgoingService},
+    service_util::{PacketDirection, PacketHistoryStore, PacketOutcome, PacketRecord},
+};
+use serde::Deserialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error};
+
+/// Interval on which the records older than the retention period are deleted
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Configuration for recording the packets sent by and to the accounts, which are returned
+/// by `GET /accounts/:username/payments`
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct PacketHistoryConfig {
+    /// Time, in seconds, for which the records are kept. Defaults to 2592000 (30 days).
+    #[serde(default = "PacketHistoryConfig::default_retention")]
+    pub retention: u64,
+}
+
+impl PacketHistoryConfig {
+    fn default_retention() -> u64 {
+        30 * 24 * 3600
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Outgoing service that records the fulfilled and rejected packets in the packet history
+/// of the two accounts, if the history is enabled. Packets without an amount, such as the
+/// STREAM control packets, are not recorded.
+#[derive(Clone)]
+pub struct PacketHistoryService<S, O> {
+    store: S,
+    enabled: bool,
+    next: O,
+}
+
+impl<S, O> PacketHistoryService<S, O> {
+    pub fn new(store: S, enabled: bool, next: O) -> Self {
+        PacketHistoryService {
+            store,
+            enabled,
+            next,
+        }
+    }
+}
+
+impl<S, O, A> OutgoingService<A> for PacketHistoryService<S, O>
+where
+    S: PacketHistoryStore + Clone + Send + Sync + 'static,
+    O: OutgoingService<A> + Send,
+    A: Account + 'static,
+{
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            if !self.enabled || request.prepare.amount() == 0 {
+                return self.next.send_request(request).await;
+            }
+
+            // The sender's record has the amount it sent, and the receiver's the amount
+            // it received
+            let accounts = [
+                (
+                    request.from.id(),
+                    PacketDirection::Outgoing,
+                    request.original_amount,
+                ),
+                (
+                    request.to.id(),
+                    PacketDirection::Incoming,
+                    request.prepare.amount(),
+                ),
+            ];
+            let destination = request.prepare.destination().to_string();
+            let result = self.next.send_request(request).await;
+
+            let (outcome, reject_code) = match result {
+                Ok(_) => (PacketOutcome::Fulfilled, None),
+                Err(ref reject) => (PacketOutcome::Rejected, Some(reject.code().to_string())),
+            };
+            let timestamp = now();
+            let records = accounts
+                .iter()
+                .map(|&(account_id, direction, amount)| PacketRecord {
+                    id: 0,
+                    timestamp,
+                    account_id,
+                    direction,
+                    outcome,
+                    amount,
+                    destination: destination.clone(),
+                    reject_code: reject_code.clone(),
+                })
+                .collect();
+            let store = self.store.clone();
+            tokio::spawn(async move {
+                if let Err(err) = store.record_packets(records).await {
+                    error!(target: "interledger-node", "Error recording packet history: {}", err);
+                }
+            });
+            result
+        })
+    }
+}
+
+/// Deletes the records of the packet history which are older than the retention period,
+/// once per hour
+pub fn spawn_packet_history_pruning<S>(store: S, config: PacketHistoryConfig)
+where
+    S: PacketHistoryStore + Send + Sync + 'static,
+{
+    let retention = Duration::from_secs(config.retention).as_millis() as u64;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match store
+                .prune_packet_history(now().saturating_sub(retention))
+                .await
+            {
+                Ok(deleted) => {
+                    debug!(target: "interledger-node", "Deleted {} packet history records", deleted)
+                }
+                Err(err) => {
+                    error!(target: "interledger-node", "Error pruning packet history: {}", err)
+                }
+            }
+        }
+    });
+}
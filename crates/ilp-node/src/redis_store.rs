@@ -1,6 +1,8 @@
 #![cfg(feature = "redis")]
 
 use crate::node::{InterledgerNode, LogWriter};
+use crate::reload::ConfigUpdates;
+use crate::shutdown::ShutdownSignal;
 use futures::TryFutureExt;
 pub use interledger::{
     api::{AccountDetails, NodeStore},
@@ -25,17 +27,52 @@ pub async fn serve_redis_node(
     node: InterledgerNode,
     ilp_address: Address,
     log_writer: Option<LogWriter>,
+    updates: Option<ConfigUpdates>,
+    shutdown: Option<ShutdownSignal>,
 ) -> Result<(), ()> {
     let redis_connection_info = node.database_url.clone().into_connection_info().unwrap();
     let redis_addr = redis_connection_info.addr.clone();
-    let redis_secret = generate_redis_secret(&node.secret_seed);
-    let store = RedisStoreBuilder::new(redis_connection_info, redis_secret)
+    let (redis_secret, previous_secret) =
+        node.store_secrets(generate_redis_secret(&node.secret_seed))?;
+    let mut builder = RedisStoreBuilder::new(redis_connection_info, redis_secret);
+    if let Some(previous_secret) = previous_secret {
+        builder.previous_secret(previous_secret);
+    }
+    builder
         .with_db_prefix(node.database_prefix.as_str())
         .node_ilp_address(ilp_address.clone())
+        .account_cache_capacity(node.account_cache_capacity);
+    if let Some(ttl) = node.account_cache_ttl {
+        builder.account_cache_ttl(ttl);
+    }
+    if let Some(threshold) = node.store_slow_operation_threshold {
+        builder.slow_operation_threshold(threshold);
+    }
+    for (method, threshold) in node.store_method_slow_operation_thresholds.iter() {
+        builder.method_slow_operation_threshold(method, *threshold);
+    }
+    if let Some(size) = node.redis_pool_size {
+        builder.pool_size(size);
+    }
+    if let Some(timeout) = node.redis_connection_timeout {
+        builder.connection_timeout(timeout);
+    }
+    if let Some(max_retries) = node.redis_max_retries {
+        builder.max_retries(max_retries);
+    }
+    if let Some(backoff) = node.redis_retry_backoff {
+        builder.retry_backoff(backoff);
+    }
+    if let Some(limit) = node.concurrency.store {
+        builder.max_concurrent_commands(limit);
+    }
+    let store = builder
         .connect()
         .map_err(move |err| error!(target: "interledger-node", "Error connecting to Redis: {:?} {:?}", redis_addr, err))
         .await?;
-    node.chain_services(store, ilp_address, log_writer).await
+    // Every write goes to Redis directly, so there is nothing to flush on shutdown
+    node.chain_services(store, ilp_address, log_writer, updates, shutdown)
+        .await
 }
 
 pub fn generate_redis_secret(secret_seed: &[u8; 32]) -> [u8; 32] {
@@ -0,0 +1,96 @@
+use crate::node::InterledgerNode;
+use crate::shutdown::{PacketDrain, ShutdownSignal};
+use futures::FutureExt;
+use interledger::{
+    errors::default_rejection_handler,
+    http::{HttpClientService, HttpServer as IlpOverHttpServer},
+    packet::{Address, ErrorCode, RejectBuilder},
+    router::Router,
+    service::{outgoing_service_fn, Account as AccountTrait, OutgoingRequest},
+    service_util::{ExpiryShortenerService, ValidatorService},
+    store::{account::Account, memory::MemoryStore},
+};
+use tokio::spawn;
+use tracing::{error, info};
+use warp::Filter;
+
+/// Runs the node as a stateless relay, which only authenticates the packets it receives
+/// over ILP-over-HTTP, routes them, and checks their expiry and fulfillments. The accounts
+/// and static routes of the configuration are kept in memory instead of a store, and
+/// there are no balances, settlements, API or BTP connections.
+pub(crate) async fn serve_relay(
+    node: InterledgerNode,
+    shutdown: Option<ShutdownSignal>,
+) -> Result<(), ()> {
+    // There is no parent to get an address from
+    let ilp_address: Address = node.ilp_address.clone().ok_or_else(
+        || error!(target: "interledger-node", "The ILP address of a relay must be configured"),
+    )?;
+    let store = MemoryStore::new(
+        ilp_address.clone(),
+        node.accounts.0.clone(),
+        node.static_routes.clone().unwrap_or_default(),
+    )
+    .map_err(|err| error!(target: "interledger-node", "Error loading the accounts of the relay: {}", err))?;
+
+    let outgoing_service = outgoing_service_fn({
+        let ilp_address = ilp_address.clone();
+        move |request: OutgoingRequest<Account>| {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: format!(
+                    "No ILP-over-HTTP URL for account: {}",
+                    request.to.username()
+                )
+                .as_bytes(),
+                triggered_by: Some(&ilp_address),
+                data: &[],
+            }
+            .build())
+        }
+    });
+    let outgoing_service = HttpClientService::new(store.clone(), outgoing_service);
+    let outgoing_service = match node.concurrency.http_client {
+        Some(limit) => outgoing_service.max_in_flight(limit),
+        None => outgoing_service,
+    };
+    // The expiry is shortened before the Validator checks whether there is enough time left
+    let outgoing_service =
+        ValidatorService::outgoing(store.clone(), outgoing_service).clock(node.clock.clone());
+    let outgoing_service = ExpiryShortenerService::new(outgoing_service).clock(node.clock.clone());
+
+    let incoming_service = Router::new(store.clone(), outgoing_service);
+    let incoming_service =
+        ValidatorService::incoming(store.clone(), incoming_service).clock(node.clock.clone());
+    let packet_drain = PacketDrain::new();
+    let incoming_service = packet_drain.service(incoming_service);
+
+    let filter = IlpOverHttpServer::new(incoming_service, store)
+        .as_filter()
+        .recover(default_rejection_handler)
+        .with(warp::log("interledger-relay"));
+
+    let shutdown = shutdown.map(FutureExt::shared);
+    let listeners_closed = {
+        let shutdown = shutdown.clone();
+        async move {
+            match shutdown {
+                Some(shutdown) => shutdown.await,
+                None => futures::future::pending().await,
+            }
+        }
+    };
+    info!(target: "interledger-node", "Interledger.rs relay with ILP address {} listening on: {}", ilp_address, node.http_bind_address);
+    let (_, server) =
+        warp::serve(filter).bind_with_graceful_shutdown(node.http_bind_address, listeners_closed);
+    let server = spawn(server);
+
+    if let Some(shutdown) = shutdown {
+        shutdown.await;
+        info!(target: "interledger-node", "Shutting down the Interledger.rs relay");
+        let _ = futures::future::join(packet_drain.drain(), server).await;
+    } else {
+        let _ = server.await;
+    }
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use crate::node::{ExchangeRateConfig, InterledgerNode, LogWriter};
+use interledger::{
+    api::NodeStore,
+    rates::{ExchangeRateFetcher, ExchangeRateStore},
+    service::{
+        runtime::{self, TaskHandle},
+        telemetry::{self, TelemetryConfig},
+        AccountStore, Username,
+    },
+    service_util::{DestinationRule, DestinationRules, FeeSchedule, Fees, Spread},
+};
+use std::{collections::HashMap, time::Duration};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::{debug, error, info, warn};
+#[cfg(feature = "monitoring")]
+use tracing_subscriber::filter::EnvFilter;
+
+/// Receives the node's configuration whenever it was reloaded, e.g. on SIGHUP
+pub type ConfigUpdates = UnboundedReceiver<InterledgerNode>;
+
+/// Replaces the static routes in the store with the configured ones
+pub(crate) async fn apply_static_routes<S>(
+    store: &S,
+    routes: &HashMap<String, Username>,
+) -> Result<(), ()>
+where
+    S: NodeStore + AccountStore,
+{
+    let mut resolved = Vec::with_capacity(routes.len());
+    for (prefix, username) in routes {
+        let account_id = store
+            .get_account_id_from_username(username)
+            .await
+            .map_err(|err| {
+                error!(target: "interledger-node", "Error resolving the account of static route {}: {}", prefix, err)
+            })?;
+        resolved.push((prefix.clone(), account_id));
+    }
+    store.set_static_routes(resolved).await.map_err(
+        |err| error!(target: "interledger-node", "Error setting the static routes: {}", err),
+    )
+}
+
+/// Applies the settings of a reloaded configuration which can be changed while the node
+/// is running: the log level, the telemetry settings, the static routes, the fees, the
+/// destination rules and the exchange rate settings. Changes to other settings only take effect when the node is
+/// restarted.
+pub(crate) struct ConfigReloader<S> {
+    store: S,
+    spread: Spread,
+    fees: Fees,
+    fee_schedules: Vec<FeeSchedule>,
+    destinations: DestinationRules,
+    destination_rules: Vec<DestinationRule>,
+    exchange_rate: ExchangeRateConfig,
+    rate_poller: Option<TaskHandle>,
+    static_routes: Option<HashMap<String, Username>>,
+    #[cfg_attr(not(feature = "monitoring"), allow(dead_code))]
+    log_writer: Option<LogWriter>,
+    #[cfg(feature = "monitoring")]
+    log_level: Option<String>,
+    telemetry: TelemetryConfig,
+}
+
+impl<S> ConfigReloader<S>
+where
+    S: NodeStore + AccountStore + ExchangeRateStore + Clone + Send + Sync + 'static,
+{
+    /// Creates a reloader of the node's running settings and starts polling the
+    /// exchange rate provider, if one is configured
+    pub(crate) fn new(
+        store: S,
+        spread: Spread,
+        fees: Fees,
+        destinations: DestinationRules,
+        node: &InterledgerNode,
+        log_writer: Option<LogWriter>,
+    ) -> Self {
+        let mut reloader = ConfigReloader {
+            store,
+            spread,
+            fees,
+            fee_schedules: node.fees.clone(),
+            destinations,
+            destination_rules: node.destinations.clone(),
+            exchange_rate: node.exchange_rate.clone(),
+            rate_poller: None,
+            static_routes: node.static_routes.clone(),
+            log_writer,
+            #[cfg(feature = "monitoring")]
+            log_level: node.log_level.clone(),
+            telemetry: node.telemetry.clone(),
+        };
+        reloader.start_rate_poller();
+        reloader
+    }
+
+    fn start_rate_poller(&mut self) {
+        if let Some(poller) = self.rate_poller.take() {
+            poller.abort();
+        }
+        if let Some(ref provider) = self.exchange_rate.provider {
+            let fetcher = ExchangeRateFetcher::new(
+                provider.build(),
+                self.exchange_rate.poll_failure_tolerance,
+                self.store.clone(),
+            );
+            self.rate_poller = Some(
+                fetcher.spawn_interval(Duration::from_millis(self.exchange_rate.poll_interval)),
+            );
+        } else {
+            debug!(target: "interledger-node", "Not using exchange rate provider. Rates must be set via the HTTP API");
+        }
+    }
+
+    #[cfg(feature = "monitoring")]
+    fn reload_log_level(&mut self, log_level: Option<String>) {
+        if log_level == self.log_level {
+            return;
+        }
+        let handle = match self.log_writer.as_ref().and_then(|w| w.handle.as_ref()) {
+            Some(handle) => handle,
+            None => return,
+        };
+        let filter = match log_level {
+            Some(ref log_level) => match EnvFilter::try_new(log_level) {
+                Ok(filter) => filter,
+                Err(err) => {
+                    error!(target: "interledger-node", "Not changing the invalid log level {}: {}", log_level, err);
+                    return;
+                }
+            },
+            None => EnvFilter::from_default_env(),
+        };
+        match handle.reload(filter) {
+            Ok(()) => {
+                info!(target: "interledger-node", "Log level changed to {:?}", log_level);
+                self.log_level = log_level;
+            }
+            Err(err) => error!(target: "interledger-node", "Error changing the log level: {}", err),
+        }
+    }
+
+    /// Applies the changed settings of the reloaded configuration
+    pub(crate) async fn reload(&mut self, node: InterledgerNode) {
+        #[cfg(feature = "monitoring")]
+        self.reload_log_level(node.log_level);
+        if node.telemetry != self.telemetry {
+            info!(target: "interledger-node", "Telemetry settings changed to {:?}", node.telemetry);
+            telemetry::set_config(&node.telemetry);
+            self.telemetry = node.telemetry;
+        }
+
+        let exchange_rate = node.exchange_rate;
+        if exchange_rate.spread != self.exchange_rate.spread {
+            info!(target: "interledger-node", "Exchange rate spread changed to {}", exchange_rate.spread);
+            self.spread.set(exchange_rate.spread);
+        }
+        if node.fees != self.fee_schedules {
+            info!(target: "interledger-node", "Fees changed");
+            self.fees.set(node.fees.clone());
+            self.fee_schedules = node.fees;
+        }
+        if node.destinations != self.destination_rules {
+            info!(target: "interledger-node", "Destination rules changed");
+            self.destinations.set(node.destinations.clone());
+            self.destination_rules = node.destinations;
+        }
+        let restart_poller = exchange_rate.provider != self.exchange_rate.provider
+            || exchange_rate.poll_interval != self.exchange_rate.poll_interval
+            || exchange_rate.poll_failure_tolerance != self.exchange_rate.poll_failure_tolerance;
+        if exchange_rate.max_rate_age != self.exchange_rate.max_rate_age
+            || exchange_rate.stale_rate_policy != self.exchange_rate.stale_rate_policy
+        {
+            warn!(target: "interledger-node", "Changes to the stale exchange rate settings take effect when the node is restarted");
+        }
+        self.exchange_rate = exchange_rate;
+        if restart_poller {
+            info!(target: "interledger-node", "Exchange rate polling settings changed");
+            self.start_rate_poller();
+        }
+
+        // Routes which could not be applied are retried on the next reload
+        if node.static_routes != self.static_routes {
+            if let Some(ref routes) = node.static_routes {
+                if apply_static_routes(&self.store, routes).await.is_err() {
+                    return;
+                }
+                info!(target: "interledger-node", "Static routes changed");
+            }
+            self.static_routes = node.static_routes;
+        }
+    }
+
+    /// Applies every configuration received from `updates`
+    pub(crate) fn spawn(mut self, mut updates: ConfigUpdates) {
+        runtime::spawn(async move {
+            while let Some(node) = updates.recv().await {
+                debug!(target: "interledger-node", "Reloading configuration");
+                self.reload(node).await;
+            }
+        });
+    }
+}
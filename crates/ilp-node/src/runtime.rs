@@ -0,0 +1,70 @@
+use serde::Deserialize;
+use tokio::runtime::{Builder, Runtime};
+
+/// Settings of the Tokio runtime on which the node runs. They are only read when the node
+/// starts, so changing them requires a restart.
+#[derive(Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+pub struct RuntimeConfig {
+    /// Number of threads which run the node's tasks. Defaults to the number of CPU cores.
+    #[serde(default)]
+    pub worker_threads: Option<usize>,
+    /// Maximum number of threads which run blocking operations, e.g. reading files or
+    /// verifying auth tokens.
+    /// Defaults to 512.
+    #[serde(default)]
+    pub max_blocking_threads: Option<usize>,
+}
+
+impl RuntimeConfig {
+    /// Builds the multi-threaded runtime with these settings
+    pub fn build(&self) -> std::io::Result<Runtime> {
+        let mut builder = Builder::new_multi_thread();
+        builder.enable_all();
+        if let Some(worker_threads) = self.worker_threads {
+            builder.worker_threads(worker_threads.max(1));
+        }
+        if let Some(max_blocking_threads) = self.max_blocking_threads {
+            builder.max_blocking_threads(max_blocking_threads.max(1));
+        }
+        builder.build()
+    }
+}
+
+/// Limits on the work which the subsystems of the node do at the same time. Nothing is
+/// limited by default, except for the incoming BTP packets, which are handled one at a time.
+#[derive(Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+pub struct ConcurrencyConfig {
+    /// Maximum number of commands sent to Redis at the same time. The SQLite store
+    /// always runs one operation at a time.
+    #[serde(default)]
+    pub store: Option<usize>,
+    /// Maximum number of ILP over HTTP requests in flight at the same time
+    #[serde(default)]
+    pub http_client: Option<usize>,
+    /// Number of incoming BTP packets handled at the same time. Defaults to 1.
+    #[serde(default)]
+    pub btp: Option<usize>,
+    /// Maximum number of requests in flight to each peer at the same time, so that a slow
+    /// peer can't hold up all of the others
+    #[serde(default)]
+    pub per_peer: Option<usize>,
+    /// Milliseconds a request beyond `per_peer` waits for a slot before it is rejected with
+    /// T03 (Connector Busy). Defaults to 0, which rejects it right away.
+    #[serde(default)]
+    pub per_peer_wait: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_runtime_with_limits() {
+        let config = RuntimeConfig {
+            worker_threads: Some(2),
+            max_blocking_threads: Some(4),
+        };
+        let runtime = config.build().unwrap();
+        assert_eq!(runtime.block_on(async { 1 + 1 }), 2);
+    }
+}
@@ -0,0 +1,41 @@
+use bytes::Bytes;
+use interledger::{
+    api::NodeStore,
+    service::runtime,
+    stream::{ConnectionGenerator, SecretGeneration},
+};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Interval on which the generation of the STREAM server secret is reloaded from the store
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Reloads the generation of the STREAM server secret from the store once per minute, so
+/// that the nodes sharing a store pick up the rotations made with the API of any of them.
+/// `current` is the generation the connection generator was created with.
+pub fn spawn_secret_generation_reload<S>(
+    store: S,
+    secret_seed: Bytes,
+    connection_generator: ConnectionGenerator,
+    mut current: SecretGeneration,
+) where
+    S: NodeStore,
+{
+    runtime::spawn(async move {
+        let mut interval = runtime::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            match store.get_secret_generation().await {
+                Ok(generation) if generation != current => {
+                    info!(target: "interledger-node", "Switching to generation {} of the STREAM server secret", generation.generation);
+                    connection_generator.set_generation(&secret_seed, &generation);
+                    current = generation;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    error!(target: "interledger-node", "Error reloading the generation of the STREAM server secret: {}", err)
+                }
+            }
+        }
+    });
+}
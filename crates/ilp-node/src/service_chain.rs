@@ -0,0 +1,220 @@
+use interledger::service_util::DuplicatePreparePolicy;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// The middleware of the incoming chain, which handle the packets received from the
+/// accounts before they are routed
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum IncomingMiddleware {
+    /// Answers or rejects the duplicates of the Prepares which are still pending
+    DuplicatePrepare,
+    /// Applies the packet, throughput and volume limits of the sender. When the `balance`
+    /// is in the outgoing chain, the sender is debited in the same call to the store.
+    RateLimit,
+    /// Rejects the expired packets
+    Validator,
+    /// Applies the `destinations` rules
+    DestinationFilter,
+    /// Rejects the packets below the minimum amount of the sender
+    MinPacketAmount,
+    /// Rejects the packets above the maximum amount of the sender
+    MaxPacketAmount,
+}
+
+/// The middleware of the outgoing chain, which handle the packets after they were routed
+/// and before they are sent to the next hop
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OutgoingMiddleware {
+    /// Deducts the `fees`
+    Fees,
+    /// Rejects the packets between assets which would be converted at a nonsense rate
+    AssetGuard,
+    /// Converts the amount to the asset of the next hop
+    ExchangeRate,
+    /// Updates the balances and triggers the settlements (requires the `balance-tracking`
+    /// feature)
+    Balance,
+    /// Receives the STREAM payments sent to the node's accounts. It is required.
+    StreamReceiver,
+    /// Shortens the expiry of the packets sent to the next hop
+    ExpiryShortener,
+    /// Rejects the packets which would expire before the next hop could answer them,
+    /// and the Fulfills which don't match their condition
+    Validator,
+}
+
+/// Settings of the validators of both chains
+#[derive(Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+pub struct ValidatorSettings {
+    /// Overrides the node's `clock_skew_tolerance`, in milliseconds
+    #[serde(default)]
+    pub clock_skew_tolerance: Option<u64>,
+}
+
+/// Settings of the `duplicate_prepare` middleware
+#[derive(Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+pub struct DuplicatePrepareSettings {
+    /// Overrides the node's `duplicate_prepare_policy`
+    #[serde(default)]
+    pub policy: Option<DuplicatePreparePolicy>,
+}
+
+/// Which middleware the packets pass through, in which order, and their settings.
+/// The routing, the transports and the instrumentation are always part of the chain.
+/// The defaults are the chains of the previous versions of the node.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct ServiceChainConfig {
+    /// The incoming middleware, in the order in which the packets pass through them
+    #[serde(default = "ServiceChainConfig::default_incoming")]
+    pub incoming: Vec<IncomingMiddleware>,
+    /// The outgoing middleware, in the order in which the packets pass through them
+    #[serde(default = "ServiceChainConfig::default_outgoing")]
+    pub outgoing: Vec<OutgoingMiddleware>,
+    #[serde(default)]
+    pub validator: ValidatorSettings,
+    #[serde(default)]
+    pub duplicate_prepare: DuplicatePrepareSettings,
+}
+
+impl Default for ServiceChainConfig {
+    fn default() -> Self {
+        ServiceChainConfig {
+            incoming: Self::default_incoming(),
+            outgoing: Self::default_outgoing(),
+            validator: ValidatorSettings::default(),
+            duplicate_prepare: DuplicatePrepareSettings::default(),
+        }
+    }
+}
+
+impl ServiceChainConfig {
+    fn default_incoming() -> Vec<IncomingMiddleware> {
+        vec![
+            IncomingMiddleware::DuplicatePrepare,
+            IncomingMiddleware::RateLimit,
+            IncomingMiddleware::Validator,
+            IncomingMiddleware::DestinationFilter,
+            IncomingMiddleware::MinPacketAmount,
+            IncomingMiddleware::MaxPacketAmount,
+        ]
+    }
+
+    fn default_outgoing() -> Vec<OutgoingMiddleware> {
+        let mut outgoing = vec![
+            OutgoingMiddleware::Fees,
+            OutgoingMiddleware::AssetGuard,
+            OutgoingMiddleware::ExchangeRate,
+        ];
+        if cfg!(feature = "balance-tracking") {
+            outgoing.push(OutgoingMiddleware::Balance);
+        }
+        outgoing.extend_from_slice(&[
+            OutgoingMiddleware::StreamReceiver,
+            OutgoingMiddleware::ExpiryShortener,
+            OutgoingMiddleware::Validator,
+        ]);
+        outgoing
+    }
+
+    /// Whether the middleware is part of the incoming chain
+    pub fn has_incoming(&self, middleware: IncomingMiddleware) -> bool {
+        self.incoming.contains(&middleware)
+    }
+
+    /// Whether the middleware is part of the outgoing chain
+    pub fn has_outgoing(&self, middleware: OutgoingMiddleware) -> bool {
+        self.outgoing.contains(&middleware)
+    }
+
+    /// Checks that the chains can be built
+    pub fn validate(&self) -> Result<(), String> {
+        check_unique("incoming", &self.incoming)?;
+        check_unique("outgoing", &self.outgoing)?;
+        if !self.has_outgoing(OutgoingMiddleware::StreamReceiver) {
+            return Err("the outgoing chain must contain the stream_receiver".to_string());
+        }
+        if !cfg!(feature = "balance-tracking") && self.has_outgoing(OutgoingMiddleware::Balance) {
+            return Err(
+                "the balance middleware requires the node to be built with the balance-tracking feature"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+fn check_unique<T: Eq + Hash + Debug>(chain: &str, middleware: &[T]) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for entry in middleware {
+        if !seen.insert(entry) {
+            return Err(format!(
+                "{:?} appears more than once in the {} chain",
+                entry, chain
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn defaults_to_the_built_in_chains() {
+        let config: ServiceChainConfig = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(config, ServiceChainConfig::default());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn parses_chains_and_settings() {
+        let config: ServiceChainConfig = serde_json::from_value(json!({
+            "incoming": ["max_packet_amount", "validator"],
+            "outgoing": ["stream_receiver", "exchange_rate"],
+            "validator": { "clock_skew_tolerance": 500 },
+            "duplicate_prepare": { "policy": "reject" },
+        }))
+        .unwrap();
+        assert_eq!(
+            config.incoming,
+            vec![
+                IncomingMiddleware::MaxPacketAmount,
+                IncomingMiddleware::Validator
+            ]
+        );
+        assert!(!config.has_incoming(IncomingMiddleware::RateLimit));
+        assert!(config.has_outgoing(OutgoingMiddleware::ExchangeRate));
+        assert_eq!(config.validator.clock_skew_tolerance, Some(500));
+        assert_eq!(
+            config.duplicate_prepare.policy,
+            Some(DuplicatePreparePolicy::Reject)
+        );
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_chains() {
+        let config: ServiceChainConfig = serde_json::from_value(json!({
+            "outgoing": ["stream_receiver", "fees", "fees"],
+        }))
+        .unwrap();
+        assert!(config.validate().is_err());
+
+        let config: ServiceChainConfig = serde_json::from_value(json!({
+            "outgoing": ["fees"],
+        }))
+        .unwrap();
+        assert!(config.validate().is_err());
+
+        assert!(serde_json::from_value::<ServiceChainConfig>(json!({
+            "incoming": ["unknown"],
+        }))
+        .is_err());
+    }
+}
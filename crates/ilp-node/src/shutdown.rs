@@ -0,0 +1,255 @@
+use futures::Future;
+use interledger::{
+    packet::{ErrorCode, RejectBuilder},
+    service::{Account, BoxedIlpFuture, IncomingRequest, IncomingService},
+};
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+
+/// Completes when the node should shut down
+pub type ShutdownSignal = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+#[derive(Default)]
+struct DrainState {
+    /// Set once the node started shutting down
+    draining: bool,
+    /// Number of incoming packets which are being processed
+    in_flight: usize,
+    /// The latest expiry of the packets which were processed since the node started,
+    /// after which none of them can be in flight anymore
+    latest_expiry: Option<SystemTime>,
+}
+
+/// Tracks the incoming packets which are in flight, so that the node can wait for
+/// them to be fulfilled or rejected before it shuts down.
+#[derive(Clone, Default)]
+pub struct PacketDrain {
+    state: Arc<Mutex<DrainState>>,
+    drained: Arc<Notify>,
+}
+
+impl PacketDrain {
+    pub fn new() -> Self {
+        PacketDrain::default()
+    }
+
+    /// Wraps the incoming service so that it counts the packets in flight
+    pub fn service<I>(&self, next: I) -> PacketDrainService<I> {
+        PacketDrainService {
+            drain: self.clone(),
+            next,
+        }
+    }
+
+    /// Rejects the new incoming packets and waits until the packets in flight are fulfilled
+    /// or rejected, or until all of them expired
+    pub async fn drain(&self) {
+        let (in_flight, latest_expiry) = {
+            let mut state = self.state.lock().unwrap();
+            state.draining = true;
+            (state.in_flight, state.latest_expiry)
+        };
+        if in_flight == 0 {
+            return;
+        }
+        debug!(target: "interledger-node", "Waiting for {} packets in flight", in_flight);
+
+        let timeout = latest_expiry
+            .and_then(|expiry| expiry.duration_since(SystemTime::now()).ok())
+            .unwrap_or_else(|| Duration::from_secs(0));
+        if tokio::time::timeout(timeout, self.drained.notified())
+            .await
+            .is_err()
+        {
+            warn!(target: "interledger-node",
+                "{} packets were still in flight after they expired",
+                self.state.lock().unwrap().in_flight
+            );
+        }
+    }
+}
+
+/// Incoming service which counts the packets in flight. Once the node is draining, new
+/// packets are rejected with T03 (Connector Busy), so that the senders retry them through
+/// another node.
+#[derive(Clone)]
+pub struct PacketDrainService<I> {
+    drain: PacketDrain,
+    next: I,
+}
+
+impl<I, A> IncomingService<A> for PacketDrainService<I>
+where
+    I: IncomingService<A> + Send,
+    A: Account + 'static,
+{
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            {
+                let mut state = self.drain.state.lock().unwrap();
+                if state.draining {
+                    return Err(RejectBuilder {
+                        code: ErrorCode::T03_CONNECTOR_BUSY,
+                        message: b"Node is shutting down",
+                        triggered_by: None,
+                        data: &[],
+                    }
+                    .build());
+                }
+                state.in_flight += 1;
+                let expires_at = request.prepare.expires_at();
+                if state
+                    .latest_expiry
+                    .map_or(true, |latest| expires_at > latest)
+                {
+                    state.latest_expiry = Some(expires_at);
+                }
+            }
+
+            // The packet is no longer in flight when its future completes or is dropped
+            let _guard = InFlight(self.drain.clone());
+            self.next.handle_request(request).await
+        })
+    }
+}
+
+struct InFlight(PacketDrain);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.in_flight -= 1;
+        if state.draining && state.in_flight == 0 {
+            self.0.drained.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use interledger::{
+        packet::{Address, FulfillBuilder, PrepareBuilder},
+        service::Username,
+    };
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    #[derive(Clone, Debug)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    fn request(expires_in: Duration) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount,
+            prepare: PrepareBuilder {
+                destination: EXAMPLE_ADDRESS.clone(),
+                amount: 100,
+                execution_condition: &[0; 32],
+                expires_at: SystemTime::now() + expires_in,
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    /// Fulfills the packets after a delay, so that they stay in flight
+    #[derive(Clone)]
+    struct DelayedFulfill(Duration);
+
+    impl IncomingService<TestAccount> for DelayedFulfill {
+        type Future<'a> = BoxedIlpFuture<'a>;
+
+        fn handle_request(&mut self, _request: IncomingRequest<TestAccount>) -> Self::Future<'_> {
+            Box::pin(async move {
+                tokio::time::sleep(self.0).await;
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn waits_for_packets_in_flight() {
+        let drain = PacketDrain::new();
+        let service = drain.service(DelayedFulfill(Duration::from_millis(50)));
+        let in_flight = tokio::spawn({
+            let mut service = service.clone();
+            async move {
+                service
+                    .handle_request(request(Duration::from_secs(30)))
+                    .await
+            }
+        });
+        tokio::task::yield_now().await;
+        let mut draining = tokio::spawn({
+            let drain = drain.clone();
+            async move { drain.drain().await }
+        });
+        tokio::task::yield_now().await;
+
+        // New packets are rejected while the node is draining
+        let reject = service
+            .clone()
+            .handle_request(request(Duration::from_secs(30)))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T03_CONNECTOR_BUSY);
+        assert!((&mut draining).now_or_never().is_none());
+
+        assert!(in_flight.await.unwrap().is_ok());
+        draining.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stops_waiting_when_the_packets_expired() {
+        let drain = PacketDrain::new();
+        let mut service = drain.service(DelayedFulfill(Duration::from_secs(60)));
+        let _in_flight = tokio::spawn(async move {
+            service
+                .handle_request(request(Duration::from_millis(10)))
+                .await
+        });
+        tokio::task::yield_now().await;
+        tokio::time::timeout(Duration::from_secs(1), drain.drain())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+#![cfg(feature = "sled")]
+
+use crate::node::{InterledgerNode, LogWriter};
+use crate::reload::ConfigUpdates;
+use crate::shutdown::ShutdownSignal;
+use futures::TryFutureExt;
+use interledger::{packet::Address, store::sled::SledStoreBuilder};
+use ring::hmac;
+use tracing::{debug, error};
+use url::Url;
+
+static SLED_SECRET_GENERATION_STRING: &str = "ilp_sled_secret";
+
+pub fn default_sled_url() -> String {
+    String::from("sled:ilp-node.sled")
+}
+
+// As with the redis store, this lives in its own module in order to consolidate
+// conditionally-compiled code into as few discrete units as possible.
+pub async fn serve_sled_node(
+    node: InterledgerNode,
+    ilp_address: Address,
+    log_writer: Option<LogWriter>,
+    updates: Option<ConfigUpdates>,
+    shutdown: Option<ShutdownSignal>,
+) -> Result<(), ()> {
+    // The path of the database directory is everything after the scheme, so
+    // "sled:///var/lib/ilp/node" is an absolute path and "sled:node" is relative
+    // to the working directory. The query parameters `flush_interval` (in milliseconds),
+    // `sync_writes` and `cache_capacity` (in bytes) tune the durability and memory use,
+    // e.g. "sled:///var/lib/ilp/node?sync_writes=true"
+    let url = Url::parse(&node.database_url)
+        .map_err(|err| error!(target: "interledger-node", "Invalid sled URL: {:?}", err))?;
+    let path = url.path().to_string();
+    let (sled_secret, previous_secret) =
+        node.store_secrets(generate_sled_secret(&node.secret_seed))?;
+    let mut builder = SledStoreBuilder::new(path.clone(), sled_secret);
+    builder.node_ilp_address(ilp_address.clone());
+    if let Some(previous_secret) = previous_secret {
+        builder.previous_secret(previous_secret);
+    }
+    if let Some(threshold) = node.store_slow_operation_threshold {
+        builder.slow_operation_threshold(threshold);
+    }
+    for (method, threshold) in node.store_method_slow_operation_thresholds.iter() {
+        builder.method_slow_operation_threshold(method, *threshold);
+    }
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "flush_interval" => {
+                let interval = value.parse::<u64>().map_err(
+                    |err| error!(target: "interledger-node", "Invalid flush interval: {:?}", err),
+                )?;
+                builder.flush_interval(interval);
+            }
+            "sync_writes" => {
+                let sync_writes = value.parse::<bool>().map_err(|err| {
+                    error!(target: "interledger-node", "Invalid sync_writes value: {:?}", err)
+                })?;
+                builder.sync_writes(sync_writes);
+            }
+            "cache_capacity" => {
+                let capacity = value.parse::<u64>().map_err(
+                    |err| error!(target: "interledger-node", "Invalid cache capacity: {:?}", err),
+                )?;
+                builder.cache_capacity(capacity);
+            }
+            other => {
+                error!(target: "interledger-node", "Unknown sled URL parameter: {}", other);
+                return Err(());
+            }
+        }
+    }
+    let store = builder
+        .connect()
+        .map_err(move |err| error!(target: "interledger-node", "Error opening sled database: {:?} {:?}", path, err))
+        .await?;
+    let graceful = shutdown.is_some();
+    node.chain_services(store.clone(), ilp_address, log_writer, updates, shutdown)
+        .await?;
+    // Write the changes since the last periodic flush after the node was shut down
+    if graceful {
+        store.flush()?;
+        debug!(target: "interledger-node", "Flushed the sled database to disk");
+    }
+    Ok(())
+}
+
+fn generate_sled_secret(secret_seed: &[u8; 32]) -> [u8; 32] {
+    let mut sled_secret: [u8; 32] = [0; 32];
+    let sig = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, secret_seed),
+        SLED_SECRET_GENERATION_STRING.as_bytes(),
+    );
+    sled_secret.copy_from_slice(sig.as_ref());
+    sled_secret
+}
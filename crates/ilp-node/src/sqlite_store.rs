@@ -0,0 +1,90 @@
+#![cfg(feature = "sqlite")]
+
+use crate::node::{InterledgerNode, LogWriter};
+use crate::reload::ConfigUpdates;
+use crate::shutdown::ShutdownSignal;
+use futures::TryFutureExt;
+use interledger::{packet::Address, store::sqlite::SqliteStoreBuilder};
+use ring::hmac;
+use tracing::{debug, error};
+use url::Url;
+
+static SQLITE_SECRET_GENERATION_STRING: &str = "ilp_sqlite_secret";
+
+pub fn default_sqlite_url() -> String {
+    String::from("sqlite:ilp-node.db")
+}
+
+// As with the redis store, this lives in its own module in order to consolidate
+// conditionally-compiled code into as few discrete units as possible.
+pub async fn serve_sqlite_node(
+    node: InterledgerNode,
+    ilp_address: Address,
+    log_writer: Option<LogWriter>,
+    updates: Option<ConfigUpdates>,
+    shutdown: Option<ShutdownSignal>,
+) -> Result<(), ()> {
+    // The path is everything after the scheme, so "sqlite:///var/lib/ilp/node.db"
+    // is an absolute path, "sqlite:node.db" is relative to the working directory
+    // and "sqlite::memory:" is a database which only lives in memory. The query
+    // parameters `snapshot` and `snapshot_interval` (in milliseconds) enable
+    // periodic snapshots, e.g. "sqlite::memory:?snapshot=/var/lib/ilp/node.snapshot"
+    let url = Url::parse(&node.database_url)
+        .map_err(|err| error!(target: "interledger-node", "Invalid SQLite URL: {:?}", err))?;
+    let path = url.path().to_string();
+    let (sqlite_secret, previous_secret) =
+        node.store_secrets(generate_sqlite_secret(&node.secret_seed))?;
+    let mut builder = SqliteStoreBuilder::new(path.clone(), sqlite_secret);
+    let mut snapshot_path = None;
+    builder.node_ilp_address(ilp_address.clone());
+    if let Some(previous_secret) = previous_secret {
+        builder.previous_secret(previous_secret);
+    }
+    if let Some(threshold) = node.store_slow_operation_threshold {
+        builder.slow_operation_threshold(threshold);
+    }
+    for (method, threshold) in node.store_method_slow_operation_thresholds.iter() {
+        builder.method_slow_operation_threshold(method, *threshold);
+    }
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "snapshot" => {
+                builder.snapshot_path(&*value);
+                snapshot_path = Some(value.into_owned());
+            }
+            "snapshot_interval" => {
+                let interval = value.parse::<u64>().map_err(|err| {
+                    error!(target: "interledger-node", "Invalid snapshot interval: {:?}", err)
+                })?;
+                builder.snapshot_interval(interval);
+            }
+            other => {
+                error!(target: "interledger-node", "Unknown SQLite URL parameter: {}", other);
+                return Err(());
+            }
+        }
+    }
+    let store = builder
+        .connect()
+        .map_err(move |err| error!(target: "interledger-node", "Error opening SQLite database: {:?} {:?}", path, err))
+        .await?;
+    let graceful = shutdown.is_some();
+    node.chain_services(store.clone(), ilp_address, log_writer, updates, shutdown)
+        .await?;
+    // Write the last changes to the snapshot after the node was shut down
+    if let Some(snapshot_path) = snapshot_path.filter(|_| graceful) {
+        store.save_snapshot(&snapshot_path)?;
+        debug!(target: "interledger-node", "Saved the SQLite snapshot to {}", snapshot_path);
+    }
+    Ok(())
+}
+
+fn generate_sqlite_secret(secret_seed: &[u8; 32]) -> [u8; 32] {
+    let mut sqlite_secret: [u8; 32] = [0; 32];
+    let sig = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, secret_seed),
+        SQLITE_SECRET_GENERATION_STRING.as_bytes(),
+    );
+    sqlite_secret.copy_from_slice(sig.as_ref());
+    sqlite_secret
+}
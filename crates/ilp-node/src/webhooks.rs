@@ -0,0 +1,678 @@
+use interledger::{
+    api::NodeStore,
+    service::{Account, AccountStore, Username},
+    service_util::{BalanceChange, BalanceChangeReason, BalanceStore},
+    settlement::core::{types::SettlementAccount, SettlementEvent},
+    stream::{
+        IncomingPayment, IncomingPaymentsStore, PaymentNotification, StreamNotificationsStore,
+    },
+};
+use ring::hmac;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+use tracing::{debug, error, warn};
+use url::Url;
+use uuid::Uuid;
+
+/// Header of the webhook requests with the hex-encoded HMAC-SHA256 of their body
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+/// Number of balance changes loaded at once
+const BALANCE_CHANGES_PAGE: usize = 100;
+/// Delay before the first retry of a failed delivery, which is doubled for every further retry
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Timeout of the webhook requests
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Configuration for sending the events of the node's accounts to webhooks
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct WebhooksConfig {
+    /// The URLs to which the events are sent
+    pub endpoints: Vec<WebhookEndpoint>,
+    /// Interval, in milliseconds, on which the balance change logs of the accounts are
+    /// checked for settlements and threshold crossings. Defaults to 1000ms (1 second).
+    #[serde(default = "WebhooksConfig::default_poll_interval")]
+    pub poll_interval: u64,
+    /// The longest time, in milliseconds, between two attempts to deliver an event.
+    /// Defaults to 300000ms (5 minutes).
+    #[serde(default = "WebhooksConfig::default_max_retry_delay")]
+    pub max_retry_delay: u64,
+    /// Balance thresholds of the accounts which trigger a `balance.alert` event when crossed
+    #[serde(default)]
+    pub alerts: Vec<BalanceAlert>,
+}
+
+impl WebhooksConfig {
+    fn default_poll_interval() -> u64 {
+        1000
+    }
+
+    fn default_max_retry_delay() -> u64 {
+        300_000
+    }
+}
+
+/// Thresholds of an account's balance which warn before its packets get rejected
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct BalanceAlert {
+    /// Username of the account
+    pub account: Username,
+    /// Alerts when the balance (including the prepaid amount) rises above this amount,
+    /// i.e. when the account owes the node more than it should before it settles
+    #[serde(default)]
+    pub balance_above: Option<i64>,
+    /// Alerts when the credit left to the account, its balance minus its `min_balance`,
+    /// drops below this amount. Ignored for accounts without a `min_balance`.
+    #[serde(default)]
+    pub available_credit_below: Option<u64>,
+}
+
+/// A URL to which events are POSTed
+#[derive(Deserialize, Clone, Debug)]
+pub struct WebhookEndpoint {
+    pub url: Url,
+    /// Secret with which the body of every request is signed in the `x-webhook-signature` header
+    pub secret: SecretString,
+    /// Usernames of the accounts whose events are sent. The events of all accounts are
+    /// sent if this is empty.
+    #[serde(default)]
+    pub accounts: Vec<Username>,
+}
+
+impl PartialEq for WebhookEndpoint {
+    fn eq(&self, other: &Self) -> bool {
+        self.url == other.url
+            && self.secret.expose_secret() == other.secret.expose_secret()
+            && self.accounts == other.accounts
+    }
+}
+
+impl WebhookEndpoint {
+    fn wants(&self, username: &Username) -> bool {
+        self.accounts.is_empty() || self.accounts.contains(username)
+    }
+}
+
+/// An event sent to the webhooks. Events are delivered at least once, so receivers should
+/// ignore events whose `id` they already processed.
+#[derive(Serialize, Clone, Debug)]
+pub struct WebhookEvent {
+    pub id: Uuid,
+    /// One of `payment.incoming`, `payment.completed`, `settlement.outgoing`,
+    /// `settlement.refunded`, `settlement.incoming`, `settlement.initiated`,
+    /// `settlement.succeeded`, `settlement.failed`, `settlement.received`,
+    /// `balance.threshold_crossed` or `balance.alert`
+    #[serde(rename = "type")]
+    pub event_type: &'static str,
+    /// Time of the event, in milliseconds since the UNIX epoch
+    pub timestamp: u64,
+    /// Username of the account the event belongs to
+    pub account: Username,
+    pub data: serde_json::Value,
+}
+
+impl WebhookEvent {
+    fn new(
+        event_type: &'static str,
+        timestamp: u64,
+        account: Username,
+        data: serde_json::Value,
+    ) -> Self {
+        WebhookEvent {
+            id: Uuid::new_v4(),
+            event_type,
+            timestamp,
+            account,
+            data,
+        }
+    }
+
+    fn incoming_payment(payment: PaymentNotification) -> Self {
+        WebhookEvent::new(
+            "payment.incoming",
+            now(),
+            payment.to_username.clone(),
+            json!(payment),
+        )
+    }
+
+    fn completed_payment(username: Username, payment: IncomingPayment) -> Self {
+        WebhookEvent::new(
+            "payment.completed",
+            payment.updated_at,
+            username,
+            json!(payment),
+        )
+    }
+
+    fn settlement(username: Username, settlement: SettlementEvent) -> Self {
+        let event_type = match settlement {
+            SettlementEvent::Initiated { .. } => "settlement.initiated",
+            SettlementEvent::Succeeded { .. } => "settlement.succeeded",
+            SettlementEvent::Failed { .. } => "settlement.failed",
+            SettlementEvent::Received { .. } => "settlement.received",
+        };
+        WebhookEvent::new(event_type, now(), username, json!(settlement))
+    }
+}
+
+/// Returns the events caused by a change of an account's balance
+fn balance_events(
+    username: &Username,
+    settle_threshold: Option<i64>,
+    change: &BalanceChange,
+) -> Vec<WebhookEvent> {
+    let mut events = Vec::new();
+    let event_type = match change.reason {
+        BalanceChangeReason::Settlement => Some("settlement.outgoing"),
+        BalanceChangeReason::SettlementRefund => Some("settlement.refunded"),
+        BalanceChangeReason::IncomingSettlement => Some("settlement.incoming"),
+        _ => None,
+    };
+    if let Some(event_type) = event_type {
+        events.push(WebhookEvent::new(
+            event_type,
+            change.timestamp,
+            username.clone(),
+            json!(change),
+        ));
+    }
+    if let Some(threshold) = settle_threshold {
+        let previous = change.balance.saturating_sub(change.delta);
+        if previous <= threshold && change.balance > threshold {
+            events.push(WebhookEvent::new(
+                "balance.threshold_crossed",
+                change.timestamp,
+                username.clone(),
+                json!({
+                    "settle_threshold": threshold,
+                    "balance": change.balance,
+                    "change": change,
+                }),
+            ));
+        }
+    }
+    events
+}
+
+/// Returns the `balance.alert` events of the thresholds the change crossed. An alert fires
+/// again only once the balance went back across its threshold.
+fn alert_events(
+    username: &Username,
+    alert: &BalanceAlert,
+    min_balance: Option<i64>,
+    change: &BalanceChange,
+) -> Vec<WebhookEvent> {
+    let balance = i128::from(change.balance);
+    let previous = balance - i128::from(change.delta);
+    let mut events = Vec::new();
+    let mut push = |kind: &str, threshold: serde_json::Value| {
+        events.push(WebhookEvent::new(
+            "balance.alert",
+            change.timestamp,
+            username.clone(),
+            json!({
+                "alert": kind,
+                "threshold": threshold,
+                "balance": change.balance,
+                "min_balance": min_balance,
+                "change": change,
+            }),
+        ))
+    };
+    if let Some(threshold) = alert.balance_above {
+        let threshold_value = i128::from(threshold);
+        if previous <= threshold_value && balance > threshold_value {
+            push("balance_above", json!(threshold));
+        }
+    }
+    if let (Some(threshold), Some(min_balance)) = (alert.available_credit_below, min_balance) {
+        let threshold_value = i128::from(threshold);
+        let min_balance = i128::from(min_balance);
+        if previous - min_balance >= threshold_value && balance - min_balance < threshold_value {
+            push("available_credit_below", json!(threshold));
+        }
+    }
+    events
+}
+
+/// Returns the hex-encoded HMAC-SHA256 of the body
+fn sign(secret: &SecretString, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.expose_secret().as_bytes());
+    hex::encode(hmac::sign(&key, body).as_ref())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Sends the events of the node's accounts to the configured webhooks: incoming STREAM
+/// payments, completed payments which were not acknowledged, outgoing, refunded and
+/// incoming settlements, the steps of the settlements sent to and received from the
+/// settlement engines, balances which crossed the account's settlement threshold, and
+/// balances which crossed the thresholds of the account's `alerts`.
+///
+/// Every endpoint receives its events in order. A failed delivery is retried, with an
+/// increasing delay, until it succeeds. Pending events are lost when the node stops, except
+/// for the completed payments which are still not acknowledged: they are sent again when
+/// the node starts.
+pub fn spawn_webhooks<S, A>(
+    store: S,
+    config: WebhooksConfig,
+    mut settlement_events: broadcast::Receiver<SettlementEvent>,
+) where
+    S: NodeStore<Account = A>
+        + AccountStore<Account = A>
+        + BalanceStore
+        + StreamNotificationsStore<Account = A>
+        + IncomingPaymentsStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    A: Account + SettlementAccount + Send + Sync + 'static,
+{
+    let client = reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .expect("Error building the webhooks HTTP client");
+    let max_retry_delay = Duration::from_millis(config.max_retry_delay);
+    let alerts: HashMap<Username, BalanceAlert> = config
+        .alerts
+        .into_iter()
+        .map(|alert| (alert.account.clone(), alert))
+        .collect();
+    let endpoints: Vec<_> = config
+        .endpoints
+        .into_iter()
+        .map(|endpoint| {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            tokio::spawn(deliver(
+                client.clone(),
+                endpoint.clone(),
+                max_retry_delay,
+                receiver,
+            ));
+            (endpoint, sender)
+        })
+        .collect();
+    let dispatch = move |event: WebhookEvent| {
+        for (endpoint, sender) in endpoints.iter() {
+            if endpoint.wants(&event.account) {
+                let _ = sender.send(event.clone());
+            }
+        }
+    };
+
+    let mut payments = store.all_payment_subscription();
+    let dispatch_payment = dispatch.clone();
+    let payments_store = store.clone();
+    tokio::spawn(async move {
+        resend_completed_payments(&payments_store, &dispatch_payment).await;
+        loop {
+            match payments.recv().await {
+                Ok(payment) if payment.connection_closed => {
+                    if let Some(event) = completed_payment(&payments_store, payment).await {
+                        dispatch_payment(event);
+                    }
+                }
+                Ok(payment) => dispatch_payment(WebhookEvent::incoming_payment(payment)),
+                Err(RecvError::Lagged(skipped)) => {
+                    error!(target: "interledger-node", "{} incoming payments were not sent to the webhooks", skipped)
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    let dispatch_settlement = dispatch.clone();
+    let settlements_store = store.clone();
+    tokio::spawn(async move {
+        loop {
+            let settlement = match settlement_events.recv().await {
+                Ok(settlement) => settlement,
+                Err(RecvError::Lagged(skipped)) => {
+                    error!(target: "interledger-node", "{} settlement events were not sent to the webhooks", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let account_id = settlement.account_id();
+            match settlements_store.get_accounts(vec![account_id]).await {
+                Ok(mut accounts) if !accounts.is_empty() => dispatch_settlement(
+                    WebhookEvent::settlement(accounts.remove(0).username().clone(), settlement),
+                ),
+                Ok(_) | Err(_) => {
+                    warn!(target: "interledger-node", "Error loading account {} for the webhooks", account_id)
+                }
+            }
+        }
+    });
+
+    let poll_interval = Duration::from_millis(config.poll_interval);
+    tokio::spawn(async move {
+        let mut cursors = HashMap::new();
+        let mut interval = tokio::time::interval(poll_interval);
+        // The changes made before the node started are skipped
+        let mut from_start = false;
+        loop {
+            interval.tick().await;
+            if poll_balance_changes(&store, &mut cursors, from_start, &alerts, &dispatch)
+                .await
+                .is_ok()
+            {
+                from_start = true;
+            }
+        }
+    });
+}
+
+/// Returns the `payment.completed` event of the closed connection, if its payment was
+/// recorded and is not acknowledged yet
+async fn completed_payment<S>(store: &S, closed: PaymentNotification) -> Option<WebhookEvent>
+where
+    S: AccountStore + IncomingPaymentsStore,
+{
+    let account_id = store
+        .get_account_id_from_username(&closed.to_username)
+        .await
+        .ok()?;
+    let payments = store
+        .get_unacknowledged_payments(account_id)
+        .await
+        .map_err(|err| warn!(target: "interledger-node", "Error loading the unacknowledged payments of account {}: {}", account_id, err))
+        .ok()?;
+    payments
+        .into_iter()
+        .find(|payment| payment.destination == closed.destination)
+        .map(|payment| WebhookEvent::completed_payment(closed.to_username, payment))
+}
+
+/// Sends the `payment.completed` events of the closed connections whose payment is still not
+/// acknowledged, which may not have been delivered before the node stopped
+async fn resend_completed_payments<S, A>(store: &S, dispatch: &(dyn Fn(WebhookEvent) + Send + Sync))
+where
+    S: NodeStore<Account = A> + IncomingPaymentsStore,
+    A: Account,
+{
+    let accounts = match store.get_all_accounts().await {
+        Ok(accounts) => accounts,
+        Err(err) => {
+            warn!(target: "interledger-node", "Error loading accounts for the webhooks: {}", err);
+            return;
+        }
+    };
+    for account in accounts {
+        match store.get_unacknowledged_payments(account.id()).await {
+            Ok(payments) => payments
+                .into_iter()
+                .filter(|payment| payment.closed)
+                .for_each(|payment| {
+                    dispatch(WebhookEvent::completed_payment(
+                        account.username().clone(),
+                        payment,
+                    ))
+                }),
+            Err(err) => {
+                warn!(target: "interledger-node", "Error loading the unacknowledged payments of account {}: {}", account.id(), err)
+            }
+        }
+    }
+}
+
+/// Loads the balance changes since the previous poll and dispatches their events. The logs
+/// of accounts which are not in `cursors` are read from their start if `from_start` is set,
+/// or skipped to their end otherwise.
+async fn poll_balance_changes<S, A>(
+    store: &S,
+    cursors: &mut HashMap<Uuid, u64>,
+    from_start: bool,
+    alerts: &HashMap<Username, BalanceAlert>,
+    dispatch: &(dyn Fn(WebhookEvent) + Send + Sync),
+) -> Result<(), ()>
+where
+    S: NodeStore<Account = A> + BalanceStore,
+    A: Account + SettlementAccount,
+{
+    let accounts = store.get_all_accounts().await.map_err(
+        |err| warn!(target: "interledger-node", "Error loading accounts for the webhooks: {}", err),
+    )?;
+    for account in accounts {
+        let alert = alerts.get(account.username());
+        let cursor = cursors.entry(account.id()).or_insert(0);
+        let notify = from_start || *cursor > 0;
+        loop {
+            let changes = match store
+                .get_balance_changes(account.id(), *cursor, BALANCE_CHANGES_PAGE)
+                .await
+            {
+                Ok(changes) => changes,
+                Err(err) => {
+                    warn!(target: "interledger-node", "Error loading the balance changes of account {}: {}", account.id(), err);
+                    break;
+                }
+            };
+            let page_size = changes.len();
+            for change in changes {
+                *cursor = change.sequence + 1;
+                if notify {
+                    for event in
+                        balance_events(account.username(), account.settle_threshold(), &change)
+                    {
+                        dispatch(event);
+                    }
+                    if let Some(alert) = alert {
+                        for event in
+                            alert_events(account.username(), alert, account.min_balance(), &change)
+                        {
+                            dispatch(event);
+                        }
+                    }
+                }
+            }
+            if page_size < BALANCE_CHANGES_PAGE {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sends the events to the endpoint, one at a time
+async fn deliver(
+    client: reqwest::Client,
+    endpoint: WebhookEndpoint,
+    max_retry_delay: Duration,
+    mut events: mpsc::UnboundedReceiver<WebhookEvent>,
+) {
+    while let Some(event) = events.recv().await {
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => body,
+            Err(err) => {
+                error!(target: "interledger-node", "Error serializing webhook event {}: {}", event.id, err);
+                continue;
+            }
+        };
+        let signature = sign(&endpoint.secret, &body);
+        let mut delay = FIRST_RETRY_DELAY;
+        loop {
+            let result = client
+                .post(endpoint.url.clone())
+                .header("content-type", "application/json")
+                .header(SIGNATURE_HEADER, signature.as_str())
+                .body(body.clone())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            match result {
+                Ok(_) => {
+                    debug!(target: "interledger-node", "Sent webhook event {} to {}", event.id, endpoint.url);
+                    break;
+                }
+                Err(err) => {
+                    warn!(target: "interledger-node", "Error sending webhook event {} to {}, retrying in {:?}: {}", event.id, endpoint.url, delay, err);
+                    tokio::time::sleep(delay).await;
+                    delay = std::cmp::min(delay * 2, max_retry_delay);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn change(reason: BalanceChangeReason, delta: i64, balance: i64) -> BalanceChange {
+        BalanceChange {
+            sequence: 0,
+            timestamp: 1,
+            account_id: Uuid::nil(),
+            packet_id: None,
+            delta,
+            balance,
+            reason,
+        }
+    }
+
+    fn event_types(events: Vec<WebhookEvent>) -> Vec<&'static str> {
+        events.into_iter().map(|event| event.event_type).collect()
+    }
+
+    #[test]
+    fn settlements_are_events() {
+        let alice = Username::from_str("alice").unwrap();
+        assert_eq!(
+            event_types(balance_events(
+                &alice,
+                None,
+                &change(BalanceChangeReason::Settlement, -100, 0)
+            )),
+            vec!["settlement.outgoing"]
+        );
+        assert_eq!(
+            event_types(balance_events(
+                &alice,
+                None,
+                &change(BalanceChangeReason::IncomingSettlement, 100, 100)
+            )),
+            vec!["settlement.incoming"]
+        );
+        assert!(balance_events(
+            &alice,
+            None,
+            &change(BalanceChangeReason::Fulfill, 100, 100)
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn settlement_steps_are_events() {
+        let alice = Username::from_str("alice").unwrap();
+        let event = WebhookEvent::settlement(
+            alice.clone(),
+            SettlementEvent::Failed {
+                account_id: Uuid::nil(),
+                amount: 100,
+                asset_scale: 9,
+                idempotency_key: "key".to_string(),
+                will_retry: true,
+                error: "timed out".to_string(),
+            },
+        );
+        assert_eq!(event.event_type, "settlement.failed");
+        assert_eq!(event.account, alice);
+        assert_eq!(event.data["status"], "failed");
+        assert_eq!(event.data["idempotency_key"], "key");
+        assert_eq!(event.data["amount"], 100);
+    }
+
+    #[test]
+    fn detects_threshold_crossings() {
+        let alice = Username::from_str("alice").unwrap();
+        let fulfill = |delta, balance| change(BalanceChangeReason::Fulfill, delta, balance);
+        assert_eq!(
+            event_types(balance_events(&alice, Some(100), &fulfill(50, 120))),
+            vec!["balance.threshold_crossed"]
+        );
+        assert!(balance_events(&alice, Some(100), &fulfill(50, 100)).is_empty());
+        assert!(balance_events(&alice, Some(100), &fulfill(50, 200)).is_empty());
+        assert!(balance_events(&alice, None, &fulfill(50, 120)).is_empty());
+    }
+
+    #[test]
+    fn detects_alert_crossings() {
+        let alice = Username::from_str("alice").unwrap();
+        let alert = BalanceAlert {
+            account: alice.clone(),
+            balance_above: Some(500),
+            available_credit_below: Some(100),
+        };
+        let alerts = |delta, balance, min_balance| {
+            alert_events(
+                &alice,
+                &alert,
+                min_balance,
+                &change(BalanceChangeReason::Prepare, delta, balance),
+            )
+            .into_iter()
+            .map(|event| event.data["alert"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(alerts(100, 550, None), vec!["balance_above"]);
+        // the alert is not repeated while the balance stays above the threshold
+        assert!(alerts(10, 560, None).is_empty());
+        // the credit left is the balance minus the min balance
+        assert_eq!(
+            alerts(-50, -950, Some(-1000)),
+            vec!["available_credit_below"]
+        );
+        assert!(alerts(-10, -960, Some(-1000)).is_empty());
+        assert!(alerts(-50, -950, None).is_empty());
+
+        let event = &alert_events(
+            &alice,
+            &alert,
+            Some(-1000),
+            &change(BalanceChangeReason::Prepare, -50, -950),
+        )[0];
+        assert_eq!(event.event_type, "balance.alert");
+        assert_eq!(event.data["threshold"], 100);
+        assert_eq!(event.data["min_balance"], -1000);
+    }
+
+    #[test]
+    fn filters_accounts() {
+        let alice = Username::from_str("alice").unwrap();
+        let bob = Username::from_str("bob").unwrap();
+        let mut endpoint = WebhookEndpoint {
+            url: Url::parse("http://localhost/events").unwrap(),
+            secret: SecretString::new("secret".to_string()),
+            accounts: Vec::new(),
+        };
+        assert!(endpoint.wants(&alice));
+        endpoint.accounts.push(bob.clone());
+        assert!(!endpoint.wants(&alice));
+        assert!(endpoint.wants(&bob));
+    }
+
+    #[test]
+    fn signs_the_body() {
+        let secret = SecretString::new("secret".to_string());
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let signature = sign(&secret, b"{}");
+        assert!(hmac::verify(&key, b"{}", &hex::decode(signature).unwrap()).is_ok());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::redis_helpers::*;
+use crate::test_helpers::*;
+use ilp_node::InterledgerNode;
+use interledger::service::Account as AccountTrait;
+use serde_json::json;
+
+#[tokio::test]
+async fn child_route_follows_the_node_address() {
+    // Node A is a child of Node B, which later becomes a child of Node C
+    let context = TestContext::new();
+
+    // Each node will use its own DB within the redis instance
+    let mut connection_info_a = context.get_client_connection_info();
+    connection_info_a.redis.db = 1;
+    let mut connection_info_b = context.get_client_connection_info();
+    connection_info_b.redis.db = 2;
+    let mut connection_info_c = context.get_client_connection_info();
+    connection_info_c.redis.db = 3;
+
+    let node_a_http = get_open_port(None);
+    let node_a_settlement = get_open_port(None);
+    let node_b_http = get_open_port(None);
+    let node_b_settlement = get_open_port(None);
+    let node_c_http = get_open_port(None);
+    let node_c_settlement = get_open_port(None);
+
+    // The child has no configured address
+    let a_on_b = json!({
+        "username": "a_on_b",
+        "asset_code": "XYZ",
+        "asset_scale": 9,
+        "ilp_over_btp_incoming_token" : "token_b",
+        "routing_relation": "Child",
+    });
+    let b_on_a = json!({
+        "username": "b_on_a",
+        "asset_code": "XYZ",
+        "asset_scale": 9,
+        "ilp_over_btp_url": format!("btp+ws://localhost:{}/accounts/{}/ilp/btp", node_b_http, "a_on_b"),
+        "ilp_over_btp_outgoing_token" : "token_b",
+        "routing_relation": "Parent",
+    });
+    let b_on_c = json!({
+        "username": "b_on_c",
+        "asset_code": "XYZ",
+        "asset_scale": 9,
+        "ilp_over_btp_incoming_token" : "token_c",
+        "routing_relation": "Child",
+    });
+    let c_on_b = json!({
+        "username": "c_on_b",
+        "asset_code": "XYZ",
+        "asset_scale": 9,
+        "ilp_over_btp_url": format!("btp+ws://localhost:{}/accounts/{}/ilp/btp", node_c_http, "b_on_c"),
+        "ilp_over_btp_outgoing_token" : "token_c",
+        "routing_relation": "Parent",
+    });
+
+    let node_a: InterledgerNode = serde_json::from_value(json!({
+        "admin_auth_token": "admin",
+        "database_url": connection_info_to_string(connection_info_a),
+        "http_bind_address": format!("127.0.0.1:{}", node_a_http),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", node_a_settlement),
+        "secret_seed": random_secret(),
+        "route_broadcast_interval": 200,
+        "exchange_rate": {
+            "poll_interval": 60000
+        },
+    }))
+    .expect("Error creating node_a.");
+    let node_b: InterledgerNode = serde_json::from_value(json!({
+        "admin_auth_token": "admin",
+        "database_url": connection_info_to_string(connection_info_b),
+        "http_bind_address": format!("127.0.0.1:{}", node_b_http),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", node_b_settlement),
+        "secret_seed": random_secret(),
+        "route_broadcast_interval": 200,
+        "exchange_rate": {
+            "poll_interval": 60000
+        },
+    }))
+    .expect("Error creating node_b.");
+    let node_c: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.c",
+        "admin_auth_token": "admin",
+        "database_url": connection_info_to_string(connection_info_c),
+        "http_bind_address": format!("127.0.0.1:{}", node_c_http),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", node_c_settlement),
+        "secret_seed": random_secret(),
+        "route_broadcast_interval": 200,
+        "exchange_rate": {
+            "poll_interval": 60000
+        },
+    }))
+    .expect("Error creating node_c.");
+
+    node_b.serve(None).await.unwrap();
+    create_account_on_node(node_b_http, a_on_b, "admin")
+        .await
+        .unwrap();
+    node_a.serve(None).await.unwrap();
+    create_account_on_node(node_a_http, b_on_a, "admin")
+        .await
+        .unwrap();
+
+    // The child got an address under Node B's and the route to it
+    let accounts = get_all_accounts(node_b_http, "admin").await.unwrap();
+    assert_eq!(accounts[0].ilp_address().to_string(), "local.host.a_on_b");
+    let routes = get_routes(node_b_http).await.unwrap();
+    assert_eq!(routes["local.host.a_on_b"], "a_on_b");
+
+    node_c.serve(None).await.unwrap();
+    create_account_on_node(node_c_http, b_on_c, "admin")
+        .await
+        .unwrap();
+    create_account_on_node(node_b_http, c_on_b, "admin")
+        .await
+        .unwrap();
+
+    // The route to the child's previous address is replaced once Node B got its address
+    // from its parent and recalculated its routes
+    let mut replaced = false;
+    for _ in 0..50 {
+        let routes = get_routes(node_b_http).await.unwrap();
+        if !routes.contains_key("local.host.a_on_b")
+            && routes.get("example.c.b_on_c.a_on_b").map(String::as_str) == Some("a_on_b")
+        {
+            replaced = true;
+            break;
+        }
+        delay(100).await;
+    }
+    assert!(replaced, "The route to the child was not replaced");
+    let accounts = get_all_accounts(node_b_http, "admin").await.unwrap();
+    let child = accounts
+        .iter()
+        .find(|account| account.username().to_string() == "a_on_b")
+        .unwrap();
+    assert_eq!(child.ilp_address().to_string(), "example.c.b_on_c.a_on_b");
+}
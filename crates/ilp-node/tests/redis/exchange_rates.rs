@@ -57,6 +57,45 @@ async fn coincap() {
     assert!(count as f32 >= 0.7 * expected_rates.len() as f32)
 }
 
+#[tokio::test]
+async fn fixed_rates() {
+    let context = TestContext::new();
+
+    let http_port = get_open_port(None);
+
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.one",
+        "admin_auth_token": "admin",
+        "database_url": connection_info_to_string(context.get_client_connection_info()),
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", get_open_port(None)),
+        "secret_seed": random_secret(),
+        "exchange_rate": {
+            "poll_interval": 100,
+            "provider": {
+                "fixed": {
+                    "EUR": 1.5,
+                    "XRP": 0.25,
+                },
+            },
+        },
+    }))
+    .unwrap();
+    node.serve(None).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let obj: Value = Client::new()
+        .get(&format!("http://localhost:{}/rates", http_port))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(obj, json!({"USD": 1.0, "EUR": 1.5, "XRP": 0.25}));
+}
+
 // TODO can we disable this with conditional compilation?
 #[tokio::test]
 async fn cryptocompare() {
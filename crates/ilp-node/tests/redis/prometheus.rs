@@ -2,6 +2,7 @@ use crate::redis_helpers::*;
 use crate::test_helpers::*;
 use futures::TryFutureExt;
 use ilp_node::InterledgerNode;
+use interledger::service::Account as AccountTrait;
 use reqwest::Client;
 use serde_json::{self, json};
 
@@ -74,6 +75,7 @@ async fn prometheus() {
             "bind_address": format!("127.0.0.1:{}", prometheus_port),
             "histogram_window": 10000,
             "histogram_granularity": 1000,
+            "gauge_interval": 100,
         }
     }))
     .unwrap();
@@ -98,7 +100,7 @@ async fn prometheus() {
     create_account_on_node(node_b_http, bob_on_b, "admin")
         .await
         .unwrap();
-    create_account_on_node(node_a_http, alice_on_a, "admin")
+    let alice = create_account_on_node(node_a_http, alice_on_a, "admin")
         .await
         .unwrap();
     create_account_on_node(node_a_http, b_on_a, "admin")
@@ -157,4 +159,46 @@ async fn prometheus() {
     assert!(ret.contains("requests_outgoing_prepare"));
     assert!(ret.contains("requests_outgoing_reject"));
     assert!(ret.contains("requests_outgoing_duration"));
+
+    let get_metrics = move || async move {
+        Client::new()
+            .get(&format!("http://127.0.0.1:{}/metrics", prometheus_port))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    };
+    let alice_balance = format!(
+        "accounts_balance{{account_id=\"{}\",asset_code=\"XYZ\"}}",
+        alice.id()
+    );
+
+    // The gauges are sampled periodically and also served at /metrics
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    let ret = get_metrics().await;
+    assert!(ret.starts_with("# metrics snapshot"));
+    assert!(ret.contains(&alice_balance));
+    assert!(ret.contains("accounts_count"));
+    assert!(ret.contains("routes_count"));
+    assert!(ret.contains("connections_btp"));
+    assert!(ret.contains("store_operation_duration{backend=\"redis\",method=\"get_accounts\""));
+
+    // The balances of the deleted accounts are not served anymore
+    Client::new()
+        .delete(&format!(
+            "http://127.0.0.1:{}/accounts/alice_on_a",
+            node_a_http
+        ))
+        .header("Authorization", "Bearer admin")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    let ret = get_metrics().await;
+    assert!(ret.contains("accounts_balance"));
+    assert!(!ret.contains(&alice_balance));
 }
@@ -1,5 +1,6 @@
 #![type_length_limit = "10000000"]
 mod btp;
+mod child_accounts;
 mod exchange_rates;
 mod payments_incoming;
 mod three_nodes;
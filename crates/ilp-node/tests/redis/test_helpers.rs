@@ -142,3 +142,17 @@ pub async fn get_balance<T: Display>(
     let ret: BalanceData = serde_json::from_slice(&body).unwrap();
     Ok(ret)
 }
+
+#[allow(unused)]
+pub async fn get_routes(node_port: u16) -> Result<HashMap<String, String>, ()> {
+    let client = reqwest::Client::new();
+    let res = client
+        .get(&format!("http://localhost:{}/routes", node_port))
+        .send()
+        .map_err(|_| ())
+        .await?;
+
+    let res = res.error_for_status().map_err(|_| ())?;
+    let body: Bytes = res.bytes().map_err(|_| ()).await?;
+    Ok(serde_json::from_slice(&body).unwrap())
+}
@@ -0,0 +1,528 @@
+use super::{
+    rpc::{EthereumClient, IncomingPayment, PaymentDetails, RpcError},
+    store::EthereumStore,
+    tx::{
+        address_from_key, erc20_transfer_data, format_address, parse_address, Address, Transaction,
+    },
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use interledger_errors::ApiError;
+use interledger_settlement::core::{
+    scale_with_precision_loss,
+    types::{ApiResponse, ApiResult, LeftoversStore, Quantity, SettlementEngine},
+};
+use num_bigint::BigUint;
+use num_traits::Zero;
+use secp256k1::SecretKey;
+use serde::{Deserialize, Serialize};
+use std::{str::FromStr, sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+use tracing::{debug, error, trace, warn};
+use url::Url;
+
+/// Gas used by a transfer of ETH to an account without code
+const ETH_GAS_LIMIT: u64 = 21_000;
+/// Gas which is enough for transfers of common ERC-20 tokens
+const TOKEN_GAS_LIMIT: u64 = 100_000;
+/// Number of blocks after which a transaction is considered final
+const DEFAULT_CONFIRMATIONS: u64 = 6;
+/// Scale of ETH amounts (wei) and of most ERC-20 tokens
+const DEFAULT_ASSET_SCALE: u8 = 18;
+/// Upper bound of the blocks which are searched for payments in one poll
+const MAX_BLOCKS_PER_POLL: u64 = 100;
+
+/// Messages exchanged with the peer's engine
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Message {
+    /// Requests the address (and token) to which settlements are paid
+    PaymentDetails,
+}
+
+/// Builder of an [`EthereumSettlementEngine`]
+pub struct EthereumSettlementEngineBuilder<S> {
+    store: S,
+    client: EthereumClient,
+    connector_url: Url,
+    key: SecretKey,
+    chain_id: Option<u64>,
+    confirmations: u64,
+    gas_price: Option<BigUint>,
+    gas_limit: Option<u64>,
+    token_address: Option<Address>,
+    asset_scale: u8,
+}
+
+impl<S> EthereumSettlementEngineBuilder<S>
+where
+    S: EthereumStore
+        + LeftoversStore<AccountId = String, AssetType = BigUint>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    /// Creates a builder of an engine which pays from the address of the key
+    pub fn new(store: S, client: EthereumClient, connector_url: Url, key: SecretKey) -> Self {
+        EthereumSettlementEngineBuilder {
+            store,
+            client,
+            connector_url,
+            key,
+            chain_id: None,
+            confirmations: DEFAULT_CONFIRMATIONS,
+            gas_price: None,
+            gas_limit: None,
+            token_address: None,
+            asset_scale: DEFAULT_ASSET_SCALE,
+        }
+    }
+
+    /// Sets the chain for which transactions are signed. Defaults to the chain of the node.
+    pub fn chain_id(&mut self, chain_id: u64) -> &mut Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    /// Sets the number of blocks which must be mined on top of the block of an
+    /// incoming payment before it is credited
+    pub fn confirmations(&mut self, confirmations: u64) -> &mut Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Sets the gas price of settlements, in wei. Defaults to the node's estimate.
+    pub fn gas_price(&mut self, gas_price: BigUint) -> &mut Self {
+        self.gas_price = Some(gas_price);
+        self
+    }
+
+    /// Sets the gas limit of settlements
+    pub fn gas_limit(&mut self, gas_limit: u64) -> &mut Self {
+        self.gas_limit = Some(gas_limit);
+        self
+    }
+
+    /// Settles with the ERC-20 token at the address instead of ETH
+    pub fn token_address(&mut self, token_address: Address) -> &mut Self {
+        self.token_address = Some(token_address);
+        self
+    }
+
+    /// Sets the scale of the settled asset, i.e. the `decimals` of the token
+    pub fn asset_scale(&mut self, asset_scale: u8) -> &mut Self {
+        self.asset_scale = asset_scale;
+        self
+    }
+
+    pub async fn connect(&self) -> Result<EthereumSettlementEngine<S>, RpcError> {
+        let chain_id = match self.chain_id {
+            Some(chain_id) => chain_id,
+            None => self.client.chain_id().await?,
+        };
+        let address = address_from_key(&self.key);
+        debug!(
+            "Settling from {} on chain {}",
+            format_address(&address),
+            chain_id
+        );
+        Ok(EthereumSettlementEngine {
+            store: self.store.clone(),
+            client: self.client.clone(),
+            http_client: reqwest::Client::new(),
+            connector_url: self.connector_url.clone(),
+            key: self.key,
+            address,
+            chain_id,
+            confirmations: self.confirmations,
+            gas_price: self.gas_price.clone(),
+            gas_limit: self.gas_limit,
+            token_address: self.token_address,
+            asset_scale: self.asset_scale,
+            next_nonce: Arc::new(Mutex::new(None)),
+        })
+    }
+}
+
+/// Settlement engine which settles with transfers of ETH or of an ERC-20 token.
+/// Incoming payments are attributed to accounts by the address which sent them.
+#[derive(Clone)]
+pub struct EthereumSettlementEngine<S> {
+    store: S,
+    client: EthereumClient,
+    http_client: reqwest::Client,
+    connector_url: Url,
+    key: SecretKey,
+    address: Address,
+    chain_id: u64,
+    confirmations: u64,
+    gas_price: Option<BigUint>,
+    gas_limit: Option<u64>,
+    token_address: Option<Address>,
+    asset_scale: u8,
+    /// Nonce of the next transaction. The lock is held until the transaction was
+    /// submitted, so concurrent settlements never use the same nonce.
+    next_nonce: Arc<Mutex<Option<u64>>>,
+}
+
+impl<S> EthereumSettlementEngine<S>
+where
+    S: EthereumStore
+        + LeftoversStore<AccountId = String, AssetType = BigUint>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    fn own_payment_details(&self) -> PaymentDetails {
+        PaymentDetails {
+            ethereum_address: format_address(&self.address),
+            token_address: self.token_address.as_ref().map(format_address),
+        }
+    }
+
+    /// Returns the peer's payment details, asking the peer's engine for them
+    /// (via the connector) if we don't know them yet
+    async fn peer_payment_details(&self, account_id: &str) -> Result<PaymentDetails, ApiError> {
+        if let Some(details) = self
+            .store
+            .load_peer_details(account_id)
+            .await
+            .map_err(internal_error)?
+        {
+            return Ok(details);
+        }
+
+        let mut url = self.connector_url.clone();
+        url.path_segments_mut()
+            .expect("Invalid connector URL")
+            .push("accounts")
+            .push(account_id)
+            .push("messages");
+        let message = serde_json::to_vec(&Message::PaymentDetails).unwrap();
+        let response = self
+            .http_client
+            .post(url.as_ref())
+            .header("Content-Type", "application/octet-stream")
+            .body(message)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(internal_error)?;
+        let body = response.bytes().await.map_err(internal_error)?;
+        let details: PaymentDetails = serde_json::from_slice(&body).map_err(|err| {
+            ApiError::internal_server_error().detail(format!(
+                "Invalid payment details from the peer's engine: {}",
+                err
+            ))
+        })?;
+        if parse_address(&details.ethereum_address).is_none() {
+            return Err(ApiError::internal_server_error().detail(format!(
+                "Invalid address from the peer's engine: {}",
+                details.ethereum_address
+            )));
+        }
+        // Otherwise a peer could claim the payments of another peer
+        if !self
+            .store
+            .save_peer_details(account_id, &details)
+            .await
+            .map_err(internal_error)?
+        {
+            return Err(ApiError::conflict().detail(format!(
+                "Address {} is used by another account",
+                details.ethereum_address
+            )));
+        }
+        debug!(
+            "Account {} is settled to {}",
+            account_id, details.ethereum_address
+        );
+        Ok(details)
+    }
+
+    /// Signs and submits a transaction with the next nonce and returns its hash
+    async fn send_transaction(
+        &self,
+        to: Address,
+        value: BigUint,
+        data: Vec<u8>,
+        gas_limit: u64,
+    ) -> Result<String, RpcError> {
+        let gas_price = match self.gas_price {
+            Some(ref gas_price) => gas_price.clone(),
+            None => self.client.gas_price().await?,
+        };
+        let mut next_nonce = self.next_nonce.lock().await;
+        let nonce = match *next_nonce {
+            Some(nonce) => nonce,
+            None => self.client.pending_nonce(&self.address).await?,
+        };
+        let transaction = Transaction {
+            nonce,
+            gas_price,
+            gas_limit,
+            to,
+            value,
+            data,
+        };
+        match self
+            .client
+            .send_raw_transaction(&transaction.sign(&self.key, self.chain_id))
+            .await
+        {
+            Ok(hash) => {
+                *next_nonce = Some(nonce + 1);
+                Ok(hash)
+            }
+            Err(err) => {
+                // The transaction may have been sent by another client of the
+                // account, so the nonce is loaded from the node again
+                *next_nonce = None;
+                Err(err)
+            }
+        }
+    }
+
+    /// Notifies the connector of an incoming payment. The id of the payment is used
+    /// as the idempotency key, so a payment is credited at most once.
+    async fn notify_connector(
+        &self,
+        account_id: &str,
+        payment: &IncomingPayment,
+    ) -> Result<(), reqwest::Error> {
+        let mut url = self.connector_url.clone();
+        url.path_segments_mut()
+            .expect("Invalid connector URL")
+            .push("accounts")
+            .push(account_id)
+            .push("settlements");
+        trace!(
+            "Notifying connector of incoming payment {} of {} to account {}",
+            payment.id,
+            payment.amount,
+            account_id
+        );
+        self.http_client
+            .post(url.as_ref())
+            .header("Idempotency-Key", payment.id.as_str())
+            .json(&Quantity::new(&payment.amount, self.asset_scale))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Polls the node for incoming payments at the provided interval, forever
+    pub async fn watch_incoming_payments(self, poll_interval: Duration) {
+        let mut interval = tokio::time::interval(poll_interval);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.process_incoming_payments().await {
+                warn!("Error processing incoming Ethereum payments: {}", err);
+            }
+        }
+    }
+
+    /// Credits the payments of the blocks which have enough confirmations since the
+    /// last observed block. The last observed block is only advanced once all payments
+    /// were credited, so that failed notifications are retried on the next poll.
+    async fn process_incoming_payments(
+        &self,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let head = self.client.block_number().await?;
+        let confirmed = match head.checked_sub(self.confirmations) {
+            Some(confirmed) => confirmed,
+            None => return Ok(()),
+        };
+        let from = match self.store.load_last_observed_block().await? {
+            Some(last_observed) => last_observed + 1,
+            None => {
+                // Payments which were sent before the engine was started are not credited
+                self.store.save_last_observed_block(confirmed).await?;
+                return Ok(());
+            }
+        };
+        if from > confirmed {
+            return Ok(());
+        }
+        let to = std::cmp::min(confirmed, from + MAX_BLOCKS_PER_POLL - 1);
+
+        let payments = match self.token_address {
+            Some(ref token_address) => {
+                self.client
+                    .token_payments(token_address, &self.address, from, to)
+                    .await?
+            }
+            None => {
+                let mut payments = Vec::new();
+                for block_number in from..=to {
+                    payments.extend(
+                        self.client
+                            .eth_payments(&self.address, block_number)
+                            .await?,
+                    );
+                }
+                payments
+            }
+        };
+        for payment in payments.iter() {
+            match self
+                .store
+                .load_account_id_from_address(&payment.from)
+                .await?
+            {
+                Some(account_id) => self.notify_connector(&account_id, payment).await?,
+                None => warn!(
+                    "Ignoring payment {} from unknown address {}",
+                    payment.id,
+                    format_address(&payment.from)
+                ),
+            }
+        }
+        self.store.save_last_observed_block(to).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S> SettlementEngine for EthereumSettlementEngine<S>
+where
+    S: EthereumStore
+        + LeftoversStore<AccountId = String, AssetType = BigUint>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    async fn create_account(&self, account_id: String) -> ApiResult {
+        // The peer's engine may not be set up yet, in which case the details are
+        // requested when the peer asks for ours or before the first settlement
+        if let Err(err) = self.peer_payment_details(&account_id).await {
+            debug!(
+                "Could not get payment details of account {} yet: {:?}",
+                account_id, err
+            );
+        }
+        Ok(ApiResponse::Default)
+    }
+
+    async fn delete_account(&self, account_id: String) -> ApiResult {
+        self.store
+            .delete_account(&account_id)
+            .await
+            .map_err(internal_error)?;
+        Ok(ApiResponse::Default)
+    }
+
+    async fn send_money(&self, account_id: String, money: Quantity) -> ApiResult {
+        let amount = BigUint::from_str(&money.amount).map_err(|err| {
+            ApiError::bad_request().detail(format!("Invalid amount {}: {}", money.amount, err))
+        })?;
+        // Amounts which are too small to be paid in the asset are added to the next settlement
+        let (amount, precision_loss) =
+            scale_with_precision_loss(amount, self.asset_scale, money.scale);
+        let leftovers = self
+            .store
+            .load_uncredited_settlement_amount(account_id.clone(), self.asset_scale)
+            .await
+            .map_err(internal_error)?;
+        let amount = amount + leftovers;
+        if !precision_loss.is_zero() {
+            self.store
+                .save_uncredited_settlement_amount(
+                    account_id.clone(),
+                    (precision_loss, std::cmp::max(money.scale, self.asset_scale)),
+                )
+                .await
+                .map_err(internal_error)?;
+        }
+        if amount.is_zero() {
+            return Ok(ApiResponse::Default);
+        }
+
+        let peer = self.peer_payment_details(&account_id).await?;
+        let own = self.own_payment_details();
+        if peer.token_address.map(|token| token.to_lowercase()) != own.token_address {
+            return Err(ApiError::bad_request().detail(format!(
+                "Account {} is settled with a different asset",
+                account_id
+            )));
+        }
+        let peer_address = parse_address(&peer.ethereum_address).ok_or_else(|| {
+            ApiError::internal_server_error()
+                .detail(format!("Invalid peer address {}", peer.ethereum_address))
+        })?;
+
+        let (to, value, data, gas_limit) = match self.token_address {
+            Some(token_address) => (
+                token_address,
+                BigUint::zero(),
+                erc20_transfer_data(&peer_address, &amount),
+                TOKEN_GAS_LIMIT,
+            ),
+            None => (peer_address, amount.clone(), Vec::new(), ETH_GAS_LIMIT),
+        };
+        let hash = self
+            .send_transaction(to, value, data, self.gas_limit.unwrap_or(gas_limit))
+            .await
+            .map_err(|err| {
+                error!("Error settling {} to {}: {}", amount, account_id, err);
+                internal_error(err)
+            })?;
+        debug!(
+            "Settled {} to account {} in transaction {}",
+            amount, account_id, hash
+        );
+        Ok(ApiResponse::Default)
+    }
+
+    async fn receive_message(&self, account_id: String, message: Vec<u8>) -> ApiResult {
+        let message: Message = serde_json::from_slice(&message)
+            .map_err(|err| ApiError::bad_request().detail(format!("Invalid message: {}", err)))?;
+        match message {
+            Message::PaymentDetails => {
+                // The peer is set up now, so we can ask for its details if we couldn't
+                // when the account was created
+                let engine = self.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = engine.peer_payment_details(&account_id).await {
+                        debug!(
+                            "Could not get payment details of account {}: {:?}",
+                            account_id, err
+                        );
+                    }
+                });
+                Ok(ApiResponse::Data(Bytes::from(
+                    serde_json::to_vec(&self.own_payment_details()).unwrap(),
+                )))
+            }
+        }
+    }
+}
+
+fn internal_error<E: std::fmt::Display>(err: E) -> ApiError {
+    ApiError::internal_server_error().detail(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_payment_details() {
+        assert_eq!(
+            serde_json::to_string(&Message::PaymentDetails).unwrap(),
+            r#"{"type":"paymentDetails"}"#
+        );
+        let details = PaymentDetails {
+            ethereum_address: "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf".to_string(),
+            token_address: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&details).unwrap(),
+            r#"{"ethereumAddress":"0x7e5f4552091a69125d5dfcb7b8c2659029395bdf","tokenAddress":null}"#
+        );
+    }
+}
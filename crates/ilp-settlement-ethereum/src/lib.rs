@@ -0,0 +1,20 @@
+//! # ilp-settlement-ethereum
+//!
+//! Settlement engine which settles Interledger balances with transfers of ETH or of an
+//! ERC-20 token on Ethereum. It implements the
+//! [Settlement Engine API](https://interledger.org/rfcs/0038-settlement-engines/).
+//! Transactions are signed by the engine, and incoming payments are only credited
+//! once they have the configured number of confirmations.
+
+/// The settlement engine and the watcher of incoming payments
+pub mod engine;
+/// Client of the Ethereum JSON-RPC API
+pub mod rpc;
+/// Storage of the engine's accounts and observed blocks
+pub mod store;
+/// Encoding and signing of transactions
+pub mod tx;
+
+pub use engine::{EthereumSettlementEngine, EthereumSettlementEngineBuilder};
+pub use rpc::EthereumClient;
+pub use store::{EthereumRedisStore, EthereumStore};
@@ -0,0 +1,146 @@
+use clap::{value_t, App, Arg};
+use ilp_settlement_ethereum::{
+    tx::parse_address, EthereumClient, EthereumRedisStore, EthereumSettlementEngineBuilder,
+};
+use interledger_settlement::core::engines_api::create_settlement_engine_filter;
+use num_bigint::BigUint;
+use redis_crate::IntoConnectionInfo;
+use secp256k1::SecretKey;
+use std::{net::SocketAddr, str::FromStr, time::Duration};
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+use url::Url;
+
+fn exit_with_error(message: String) -> ! {
+    error!("{}", message);
+    std::process::exit(1);
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let matches = App::new("ilp-settlement-ethereum")
+        .about("Settlement engine which settles Interledger balances with ETH or ERC-20 tokens")
+        .version(env!("CARGO_PKG_VERSION"))
+        .args(&[
+            Arg::with_name("private_key")
+                .long("private_key")
+                .takes_value(true)
+                .required(true)
+                .help("Hex encoded private key of the Ethereum account from which settlements are paid"),
+            Arg::with_name("ethereum_url")
+                .long("ethereum_url")
+                .takes_value(true)
+                .default_value("http://127.0.0.1:8545")
+                .help("URL of the JSON-RPC API of an Ethereum node"),
+            Arg::with_name("chain_id")
+                .long("chain_id")
+                .takes_value(true)
+                .help("Chain for which transactions are signed. Defaults to the chain of the node"),
+            Arg::with_name("token_address")
+                .long("token_address")
+                .takes_value(true)
+                .help("Address of the ERC-20 token which is settled. ETH is settled if not set"),
+            Arg::with_name("asset_scale")
+                .long("asset_scale")
+                .takes_value(true)
+                .default_value("18")
+                .help("Scale of the settled asset (the decimals of the token)"),
+            Arg::with_name("confirmations")
+                .long("confirmations")
+                .takes_value(true)
+                .default_value("6")
+                .help("Number of blocks mined on top of an incoming payment before it is credited"),
+            Arg::with_name("gas_price")
+                .long("gas_price")
+                .takes_value(true)
+                .help("Gas price of settlements in wei. Defaults to the estimate of the node"),
+            Arg::with_name("gas_limit")
+                .long("gas_limit")
+                .takes_value(true)
+                .help("Gas limit of settlements. Defaults to 21000 for ETH and 100000 for tokens"),
+            Arg::with_name("connector_url")
+                .long("connector_url")
+                .takes_value(true)
+                .default_value("http://127.0.0.1:7771")
+                .help("URL of the settlement API of the connector"),
+            Arg::with_name("redis_url")
+                .long("redis_url")
+                .takes_value(true)
+                .default_value("redis://127.0.0.1:6379")
+                .help("Redis database in which the engine's data is stored"),
+            Arg::with_name("settlement_api_bind_address")
+                .long("settlement_api_bind_address")
+                .takes_value(true)
+                .default_value("127.0.0.1:3000")
+                .help("Address to which the settlement engine API is bound"),
+            Arg::with_name("poll_interval")
+                .long("poll_interval")
+                .takes_value(true)
+                .default_value("5000")
+                .help("Interval, in milliseconds, at which the node is checked for incoming payments"),
+        ])
+        .get_matches();
+
+    let private_key = matches.value_of("private_key").unwrap();
+    let key = hex::decode(private_key.strip_prefix("0x").unwrap_or(private_key))
+        .ok()
+        .and_then(|key| SecretKey::from_slice(&key).ok())
+        .unwrap_or_else(|| exit_with_error("Invalid private key".to_string()));
+    let ethereum_url = value_t!(matches, "ethereum_url", Url).unwrap_or_else(|e| e.exit());
+    let connector_url = value_t!(matches, "connector_url", Url).unwrap_or_else(|e| e.exit());
+    let bind_address =
+        value_t!(matches, "settlement_api_bind_address", SocketAddr).unwrap_or_else(|e| e.exit());
+    let poll_interval = value_t!(matches, "poll_interval", u64).unwrap_or_else(|e| e.exit());
+    let redis_info = matches
+        .value_of("redis_url")
+        .unwrap()
+        .into_connection_info()
+        .unwrap_or_else(|err| exit_with_error(format!("Invalid Redis URL: {}", err)));
+
+    let store = EthereumRedisStore::connect(redis_info)
+        .await
+        .unwrap_or_else(|_| exit_with_error("Unable to connect to Redis".to_string()));
+    let mut builder = EthereumSettlementEngineBuilder::new(
+        store.clone(),
+        EthereumClient::new(ethereum_url),
+        connector_url,
+        key,
+    );
+    builder
+        .asset_scale(value_t!(matches, "asset_scale", u8).unwrap_or_else(|e| e.exit()))
+        .confirmations(value_t!(matches, "confirmations", u64).unwrap_or_else(|e| e.exit()));
+    if matches.is_present("chain_id") {
+        builder.chain_id(value_t!(matches, "chain_id", u64).unwrap_or_else(|e| e.exit()));
+    }
+    if matches.is_present("gas_limit") {
+        builder.gas_limit(value_t!(matches, "gas_limit", u64).unwrap_or_else(|e| e.exit()));
+    }
+    if let Some(gas_price) = matches.value_of("gas_price") {
+        builder.gas_price(
+            BigUint::from_str(gas_price)
+                .unwrap_or_else(|_| exit_with_error(format!("Invalid gas price {}", gas_price))),
+        );
+    }
+    if let Some(token_address) = matches.value_of("token_address") {
+        builder.token_address(parse_address(token_address).unwrap_or_else(|| {
+            exit_with_error(format!("Invalid token address {}", token_address))
+        }));
+    }
+    let engine = builder.connect().await.unwrap_or_else(|err| {
+        exit_with_error(format!("Unable to connect to the Ethereum node: {}", err))
+    });
+
+    tokio::spawn(
+        engine
+            .clone()
+            .watch_incoming_payments(Duration::from_millis(poll_interval)),
+    );
+
+    info!("Ethereum settlement engine listening on {}", bind_address);
+    let api = create_settlement_engine_filter(engine, store);
+    warp::serve(api).bind(bind_address).await;
+}
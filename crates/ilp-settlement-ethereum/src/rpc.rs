@@ -0,0 +1,330 @@
+use super::tx::{format_address, keccak256, parse_address, Address};
+use num_bigint::BigUint;
+use num_traits::Zero;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use url::Url;
+
+/// Errors returned by the Ethereum JSON-RPC API
+#[derive(Debug, Error)]
+pub enum RpcError {
+    #[error("error sending request to the Ethereum node: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Ethereum node returned error {code}: {message}")]
+    Node { code: i64, message: String },
+    #[error("unexpected response from the Ethereum node: {0}")]
+    UnexpectedResponse(String),
+}
+
+/// A confirmed transfer of ETH or tokens to the engine's address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncomingPayment {
+    /// Unique id of the transfer, which is used as the idempotency key. It is the hash of
+    /// the transaction, followed by the index of the log for token transfers.
+    pub id: String,
+    /// The address which sent the transfer
+    pub from: Address,
+    /// The amount which was transferred, in the smallest unit of the asset
+    pub amount: BigUint,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    jsonrpc: &'static str,
+    id: u32,
+    method: &'a str,
+    params: Value,
+}
+
+/// Minimal client of the [Ethereum JSON-RPC API](https://eth.wiki/json-rpc/API)
+#[derive(Clone)]
+pub struct EthereumClient {
+    client: reqwest::Client,
+    url: Url,
+}
+
+impl EthereumClient {
+    pub fn new(url: Url) -> Self {
+        EthereumClient {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let mut response: Value = self
+            .client
+            .post(self.url.as_ref())
+            .json(&Request {
+                jsonrpc: "2.0",
+                id: 1,
+                method,
+                params,
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            return Err(RpcError::Node {
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+            });
+        }
+        match response.get_mut("result") {
+            Some(result) => Ok(result.take()),
+            None => Err(RpcError::UnexpectedResponse(response.to_string())),
+        }
+    }
+
+    pub async fn chain_id(&self) -> Result<u64, RpcError> {
+        let result = self.call("eth_chainId", json!([])).await?;
+        parse_u64(&result)
+    }
+
+    pub async fn block_number(&self) -> Result<u64, RpcError> {
+        let result = self.call("eth_blockNumber", json!([])).await?;
+        parse_u64(&result)
+    }
+
+    pub async fn gas_price(&self) -> Result<BigUint, RpcError> {
+        let result = self.call("eth_gasPrice", json!([])).await?;
+        parse_biguint(&result)
+    }
+
+    /// Returns the nonce of the next transaction of the address, including the
+    /// transactions which are still pending
+    pub async fn pending_nonce(&self, address: &Address) -> Result<u64, RpcError> {
+        let result = self
+            .call(
+                "eth_getTransactionCount",
+                json!([format_address(address), "pending"]),
+            )
+            .await?;
+        parse_u64(&result)
+    }
+
+    /// Submits a signed transaction and returns its hash
+    pub async fn send_raw_transaction(&self, transaction: &[u8]) -> Result<String, RpcError> {
+        let result = self
+            .call(
+                "eth_sendRawTransaction",
+                json!([format!("0x{}", hex::encode(transaction))]),
+            )
+            .await?;
+        result
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| RpcError::UnexpectedResponse(result.to_string()))
+    }
+
+    /// Loads the successful ETH transfers to the address in the block
+    pub async fn eth_payments(
+        &self,
+        address: &Address,
+        block_number: u64,
+    ) -> Result<Vec<IncomingPayment>, RpcError> {
+        let block = self
+            .call(
+                "eth_getBlockByNumber",
+                json!([format!("{:#x}", block_number), true]),
+            )
+            .await?;
+        let mut payments = Vec::new();
+        for payment in parse_eth_payments(address, &block)? {
+            // Failed transactions are included in blocks too
+            let receipt = self
+                .call("eth_getTransactionReceipt", json!([payment.id]))
+                .await?;
+            if receipt["status"].as_str() == Some("0x1") {
+                payments.push(payment);
+            }
+        }
+        Ok(payments)
+    }
+
+    /// Loads the transfers of the ERC-20 token to the address in the range of blocks
+    pub async fn token_payments(
+        &self,
+        token_address: &Address,
+        address: &Address,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<IncomingPayment>, RpcError> {
+        let mut recipient = [0; 32];
+        recipient[12..].copy_from_slice(address);
+        let logs = self
+            .call(
+                "eth_getLogs",
+                json!([{
+                    "fromBlock": format!("{:#x}", from_block),
+                    "toBlock": format!("{:#x}", to_block),
+                    "address": format_address(token_address),
+                    "topics": [
+                        format!("0x{}", hex::encode(transfer_topic())),
+                        Value::Null,
+                        format!("0x{}", hex::encode(recipient)),
+                    ],
+                }]),
+            )
+            .await?;
+        parse_token_payments(&logs)
+    }
+}
+
+/// Topic of the ERC-20 `Transfer(address,address,uint256)` event
+fn transfer_topic() -> [u8; 32] {
+    keccak256(b"Transfer(address,address,uint256)")
+}
+
+fn parse_biguint(value: &Value) -> Result<BigUint, RpcError> {
+    let unexpected = || RpcError::UnexpectedResponse(value.to_string());
+    let hex = value
+        .as_str()
+        .and_then(|value| value.strip_prefix("0x"))
+        .ok_or_else(unexpected)?;
+    // Data of logs is not a quantity, so it may be empty or have leading zeros
+    if hex.is_empty() {
+        return Ok(BigUint::zero());
+    }
+    BigUint::parse_bytes(hex.as_bytes(), 16).ok_or_else(unexpected)
+}
+
+fn parse_u64(value: &Value) -> Result<u64, RpcError> {
+    let hex = value
+        .as_str()
+        .and_then(|value| value.strip_prefix("0x"))
+        .ok_or_else(|| RpcError::UnexpectedResponse(value.to_string()))?;
+    u64::from_str_radix(hex, 16).map_err(|_| RpcError::UnexpectedResponse(value.to_string()))
+}
+
+fn parse_eth_payments(address: &Address, block: &Value) -> Result<Vec<IncomingPayment>, RpcError> {
+    let transactions = block["transactions"]
+        .as_array()
+        .ok_or_else(|| RpcError::UnexpectedResponse(block.to_string()))?;
+    let mut payments = Vec::new();
+    for tx in transactions {
+        // Contract creations have no recipient
+        if tx["to"].as_str().and_then(parse_address).as_ref() != Some(address) {
+            continue;
+        }
+        let amount = parse_biguint(&tx["value"])?;
+        if amount.is_zero() {
+            continue;
+        }
+        payments.push(IncomingPayment {
+            id: tx["hash"]
+                .as_str()
+                .ok_or_else(|| RpcError::UnexpectedResponse(tx.to_string()))?
+                .to_string(),
+            from: tx["from"]
+                .as_str()
+                .and_then(parse_address)
+                .ok_or_else(|| RpcError::UnexpectedResponse(tx.to_string()))?,
+            amount,
+        });
+    }
+    Ok(payments)
+}
+
+fn parse_token_payments(logs: &Value) -> Result<Vec<IncomingPayment>, RpcError> {
+    let logs = logs
+        .as_array()
+        .ok_or_else(|| RpcError::UnexpectedResponse(logs.to_string()))?;
+    let mut payments = Vec::new();
+    for log in logs {
+        // Logs of blocks which were removed by a reorganization
+        if log["removed"].as_bool() == Some(true) {
+            continue;
+        }
+        let unexpected = || RpcError::UnexpectedResponse(log.to_string());
+        let sender = log["topics"][1].as_str().ok_or_else(unexpected)?;
+        let from =
+            parse_address(&sender[sender.len().saturating_sub(40)..]).ok_or_else(unexpected)?;
+        let hash = log["transactionHash"].as_str().ok_or_else(unexpected)?;
+        let log_index = parse_u64(&log["logIndex"])?;
+        payments.push(IncomingPayment {
+            id: format!("{}:{}", hash, log_index),
+            from,
+            amount: parse_biguint(&log["data"])?,
+        });
+    }
+    Ok(payments)
+}
+
+/// Payment details exchanged by the engines of two peers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentDetails {
+    pub ethereum_address: String,
+    /// The ERC-20 token which is settled, if the engine does not settle in ETH
+    pub token_address: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf";
+    const SENDER: &str = "0x3535353535353535353535353535353535353535";
+
+    #[test]
+    fn parses_incoming_eth_payments() {
+        let block = json!({
+            "transactions": [
+                { "hash": "0x01", "from": SENDER, "to": ADDRESS, "value": "0x3e8" },
+                { "hash": "0x02", "from": SENDER, "to": SENDER, "value": "0x3e8" },
+                { "hash": "0x03", "from": SENDER, "to": ADDRESS, "value": "0x0" },
+                { "hash": "0x04", "from": SENDER, "to": null, "value": "0x3e8" },
+            ],
+        });
+        let payments = parse_eth_payments(&parse_address(ADDRESS).unwrap(), &block).unwrap();
+        assert_eq!(
+            payments,
+            vec![IncomingPayment {
+                id: "0x01".to_string(),
+                from: parse_address(SENDER).unwrap(),
+                amount: BigUint::from(1000u32),
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_incoming_token_payments() {
+        let logs = json!([
+            {
+                "transactionHash": "0x01",
+                "logIndex": "0x2",
+                "topics": [
+                    "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef",
+                    "0x0000000000000000000000003535353535353535353535353535353535353535",
+                    "0x0000000000000000000000007e5f4552091a69125d5dfcb7b8c2659029395bdf",
+                ],
+                "data": "0x00000000000000000000000000000000000000000000000000000000000003e8",
+                "removed": false,
+            },
+            {
+                "transactionHash": "0x02",
+                "logIndex": "0x0",
+                "topics": [],
+                "data": "0x",
+                "removed": true,
+            },
+        ]);
+        let payments = parse_token_payments(&logs).unwrap();
+        assert_eq!(
+            payments,
+            vec![IncomingPayment {
+                id: "0x01:2".to_string(),
+                from: parse_address(SENDER).unwrap(),
+                amount: BigUint::from(1000u32),
+            }]
+        );
+        assert_eq!(
+            hex::encode(transfer_topic()),
+            "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        );
+    }
+}
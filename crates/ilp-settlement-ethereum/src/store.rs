@@ -0,0 +1,230 @@
+use super::rpc::PaymentDetails;
+use super::tx::{format_address, Address};
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::StatusCode;
+use interledger_errors::{IdempotentStoreError, LeftoversStoreError};
+use interledger_settlement::core::{
+    backends_common::redis::{EngineRedisStore, EngineRedisStoreBuilder},
+    idempotency::{IdempotentData, IdempotentStore},
+    types::LeftoversStore,
+};
+use num_bigint::BigUint;
+use redis_crate::{AsyncCommands, ConnectionInfo, RedisError};
+
+/// Map of peer address -> account id
+static PEER_ADDRESSES_KEY: &str = "eth:peer_addresses";
+/// Block up to which incoming payments were processed
+static LAST_BLOCK_KEY: &str = "eth:last_observed_block";
+
+fn account_key(account_id: &str) -> String {
+    format!("eth:accounts:{}", account_id)
+}
+
+/// Store of the Ethereum specific data of the engine
+#[async_trait]
+pub trait EthereumStore {
+    /// Saves the address (and token) to which settlements to the peer are paid. Incoming
+    /// payments from the address are credited to the account. Returns false if the
+    /// address belongs to another account.
+    async fn save_peer_details(
+        &self,
+        account_id: &str,
+        details: &PaymentDetails,
+    ) -> Result<bool, RedisError>;
+
+    /// Loads the address (and token) to which settlements to the peer are paid
+    async fn load_peer_details(
+        &self,
+        account_id: &str,
+    ) -> Result<Option<PaymentDetails>, RedisError>;
+
+    /// Loads the account of the peer which uses the address
+    async fn load_account_id_from_address(
+        &self,
+        address: &Address,
+    ) -> Result<Option<String>, RedisError>;
+
+    /// Removes all data of the account
+    async fn delete_account(&self, account_id: &str) -> Result<(), RedisError>;
+
+    /// Loads the block up to which incoming payments were processed
+    async fn load_last_observed_block(&self) -> Result<Option<u64>, RedisError>;
+
+    /// Saves the block up to which incoming payments were processed
+    async fn save_last_observed_block(&self, block_number: u64) -> Result<(), RedisError>;
+}
+
+/// Store of the Ethereum engine, which keeps the idempotency and leftover data
+/// in the common engine store
+#[derive(Clone)]
+pub struct EthereumRedisStore {
+    redis_store: EngineRedisStore,
+}
+
+impl EthereumRedisStore {
+    /// Connects to the provided redis_url
+    pub async fn connect(redis_url: ConnectionInfo) -> Result<Self, ()> {
+        let redis_store = EngineRedisStoreBuilder::new(redis_url).connect().await?;
+        Ok(EthereumRedisStore { redis_store })
+    }
+}
+
+#[async_trait]
+impl EthereumStore for EthereumRedisStore {
+    async fn save_peer_details(
+        &self,
+        account_id: &str,
+        details: &PaymentDetails,
+    ) -> Result<bool, RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        // Addresses are compared in lowercase
+        let address = details.ethereum_address.to_lowercase();
+        let assigned: bool = connection
+            .hset_nx(PEER_ADDRESSES_KEY, &address, account_id)
+            .await?;
+        if !assigned {
+            let owner: Option<String> = connection.hget(PEER_ADDRESSES_KEY, &address).await?;
+            if owner.as_deref() != Some(account_id) {
+                return Ok(false);
+            }
+        }
+        let previous = self.load_peer_details(account_id).await?;
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        if let Some(previous) = previous {
+            if previous.ethereum_address != address {
+                pipe.hdel(PEER_ADDRESSES_KEY, previous.ethereum_address)
+                    .ignore();
+            }
+        }
+        pipe.del(account_key(account_id)).ignore();
+        let mut fields = vec![("peer_address", address)];
+        if let Some(ref token_address) = details.token_address {
+            fields.push(("peer_token_address", token_address.to_lowercase()));
+        }
+        pipe.hset_multiple(account_key(account_id), &fields)
+            .ignore();
+        let _: () = pipe.query_async(&mut connection).await?;
+        Ok(true)
+    }
+
+    async fn load_peer_details(
+        &self,
+        account_id: &str,
+    ) -> Result<Option<PaymentDetails>, RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        let (ethereum_address, token_address): (Option<String>, Option<String>) =
+            redis_crate::cmd("HMGET")
+                .arg(account_key(account_id))
+                .arg("peer_address")
+                .arg("peer_token_address")
+                .query_async(&mut connection)
+                .await?;
+        Ok(ethereum_address.map(|ethereum_address| PaymentDetails {
+            ethereum_address,
+            token_address,
+        }))
+    }
+
+    async fn load_account_id_from_address(
+        &self,
+        address: &Address,
+    ) -> Result<Option<String>, RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        connection
+            .hget(PEER_ADDRESSES_KEY, format_address(address))
+            .await
+    }
+
+    async fn delete_account(&self, account_id: &str) -> Result<(), RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        let address = self
+            .load_peer_details(account_id)
+            .await?
+            .map(|details| details.ethereum_address);
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        pipe.del(account_key(account_id)).ignore();
+        if let Some(address) = address {
+            pipe.hdel(PEER_ADDRESSES_KEY, address).ignore();
+        }
+        pipe.query_async(&mut connection).await
+    }
+
+    async fn load_last_observed_block(&self) -> Result<Option<u64>, RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        connection.get(LAST_BLOCK_KEY).await
+    }
+
+    async fn save_last_observed_block(&self, block_number: u64) -> Result<(), RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        connection.set(LAST_BLOCK_KEY, block_number).await
+    }
+}
+
+#[async_trait]
+impl IdempotentStore for EthereumRedisStore {
+    async fn load_idempotent_data(
+        &self,
+        idempotency_key: String,
+    ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        self.redis_store.load_idempotent_data(idempotency_key).await
+    }
+
+    async fn save_idempotent_data(
+        &self,
+        idempotency_key: String,
+        input_hash: [u8; 32],
+        status_code: StatusCode,
+        data: Bytes,
+    ) -> Result<(), IdempotentStoreError> {
+        self.redis_store
+            .save_idempotent_data(idempotency_key, input_hash, status_code, data)
+            .await
+    }
+}
+
+#[async_trait]
+impl LeftoversStore for EthereumRedisStore {
+    type AccountId = String;
+    type AssetType = BigUint;
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+        uncredited_settlement_amount: (Self::AssetType, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        self.redis_store
+            .save_uncredited_settlement_amount(account_id, uncredited_settlement_amount)
+            .await
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+        local_scale: u8,
+    ) -> Result<Self::AssetType, LeftoversStoreError> {
+        self.redis_store
+            .load_uncredited_settlement_amount(account_id, local_scale)
+            .await
+    }
+
+    async fn clear_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+    ) -> Result<(), LeftoversStoreError> {
+        self.redis_store
+            .clear_uncredited_settlement_amount(account_id)
+            .await
+    }
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        self.redis_store
+            .get_uncredited_settlement_amount(account_id)
+            .await
+    }
+}
@@ -0,0 +1,205 @@
+use num_bigint::BigUint;
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use tiny_keccak::{Hasher, Keccak};
+
+/// An Ethereum account address
+pub type Address = [u8; 20];
+
+/// Selector of the ERC-20 `transfer(address,uint256)` function
+const TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    let mut output = [0; 32];
+    hasher.finalize(&mut output);
+    output
+}
+
+/// Parses a hex encoded address, with or without the `0x` prefix
+pub fn parse_address(address: &str) -> Option<Address> {
+    let address = address.strip_prefix("0x").unwrap_or(address);
+    let mut parsed = [0; 20];
+    hex::decode_to_slice(address, &mut parsed).ok()?;
+    Some(parsed)
+}
+
+/// Formats the address as lowercase hex with the `0x` prefix
+pub fn format_address(address: &Address) -> String {
+    format!("0x{}", hex::encode(address))
+}
+
+/// Returns the address controlled by the private key
+pub fn address_from_key(key: &SecretKey) -> Address {
+    let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), key);
+    // The address is the hash of the uncompressed key without its 0x04 prefix
+    let hash = keccak256(&public_key.serialize_uncompressed()[1..]);
+    let mut address = [0; 20];
+    address.copy_from_slice(&hash[12..]);
+    address
+}
+
+/// Returns the call data of an ERC-20 transfer
+pub fn erc20_transfer_data(to: &Address, amount: &BigUint) -> Vec<u8> {
+    let mut data = Vec::with_capacity(4 + 32 + 32);
+    data.extend_from_slice(&TRANSFER_SELECTOR);
+    data.extend_from_slice(&[0; 12]);
+    data.extend_from_slice(to);
+    let amount = amount.to_bytes_be();
+    data.extend(std::iter::repeat(0).take(32 - amount.len()));
+    data.extend_from_slice(&amount);
+    data
+}
+
+/// A legacy (pre EIP-1559) Ethereum transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub nonce: u64,
+    pub gas_price: BigUint,
+    pub gas_limit: u64,
+    pub to: Address,
+    pub value: BigUint,
+    pub data: Vec<u8>,
+}
+
+impl Transaction {
+    /// Signs the transaction with replay protection for the chain (EIP-155) and
+    /// returns the raw transaction which is submitted with `eth_sendRawTransaction`
+    pub fn sign(&self, key: &SecretKey, chain_id: u64) -> Vec<u8> {
+        let mut unsigned = self.rlp_fields();
+        unsigned.push(rlp_uint(chain_id));
+        unsigned.push(rlp_bytes(&[]));
+        unsigned.push(rlp_bytes(&[]));
+        let hash = keccak256(&rlp_list(&unsigned));
+
+        let message = Message::from_slice(&hash).expect("Hash has the size of a message");
+        let signature = Secp256k1::signing_only().sign_recoverable(&message, key);
+        let (recovery_id, signature) = signature.serialize_compact();
+        let v = recovery_id.to_i32() as u64 + chain_id * 2 + 35;
+
+        let mut signed = self.rlp_fields();
+        signed.push(rlp_uint(v));
+        signed.push(rlp_bytes(strip_zeros(&signature[..32])));
+        signed.push(rlp_bytes(strip_zeros(&signature[32..])));
+        rlp_list(&signed)
+    }
+
+    fn rlp_fields(&self) -> Vec<Vec<u8>> {
+        vec![
+            rlp_uint(self.nonce),
+            rlp_bytes(strip_zeros(&self.gas_price.to_bytes_be())),
+            rlp_uint(self.gas_limit),
+            rlp_bytes(&self.to),
+            rlp_bytes(strip_zeros(&self.value.to_bytes_be())),
+            rlp_bytes(&self.data),
+        ]
+    }
+}
+
+/// Integers are encoded without leading zeros, so 0 is the empty string
+fn strip_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| *b != 0)
+        .unwrap_or_else(|| bytes.len());
+    &bytes[start..]
+}
+
+fn rlp_uint(value: u64) -> Vec<u8> {
+    rlp_bytes(strip_zeros(&value.to_be_bytes()))
+}
+
+fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut encoded = rlp_length(bytes.len(), 0x80);
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+    let mut encoded = rlp_length(payload.len(), 0xc0);
+    encoded.extend(payload);
+    encoded
+}
+
+fn rlp_length(length: usize, offset: u8) -> Vec<u8> {
+    if length <= 55 {
+        vec![offset + length as u8]
+    } else {
+        let length = (length as u64).to_be_bytes();
+        let length = strip_zeros(&length);
+        let mut encoded = vec![offset + 55 + length.len() as u8];
+        encoded.extend_from_slice(length);
+        encoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rlp_encodes_like_the_specification() {
+        assert_eq!(rlp_bytes(b"dog"), vec![0x83, b'd', b'o', b'g']);
+        assert_eq!(rlp_bytes(&[]), vec![0x80]);
+        assert_eq!(rlp_uint(0), vec![0x80]);
+        assert_eq!(rlp_uint(15), vec![0x0f]);
+        assert_eq!(rlp_uint(1024), vec![0x82, 0x04, 0x00]);
+        assert_eq!(
+            rlp_list(&[rlp_bytes(b"cat"), rlp_bytes(b"dog")]),
+            hex::decode("c88363617483646f67").unwrap()
+        );
+        let lorem = b"Lorem ipsum dolor sit amet, consectetur adipisicing elit";
+        let encoded = rlp_bytes(lorem);
+        assert_eq!(encoded[..2], [0xb8, 0x38]);
+        assert_eq!(&encoded[2..], &lorem[..]);
+    }
+
+    #[test]
+    fn derives_addresses() {
+        let mut key = [0; 32];
+        key[31] = 1;
+        let key = SecretKey::from_slice(&key).unwrap();
+        assert_eq!(
+            format_address(&address_from_key(&key)),
+            "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf"
+        );
+        assert_eq!(
+            parse_address("0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf"),
+            Some(address_from_key(&key))
+        );
+        assert_eq!(parse_address("0x7e5f"), None);
+    }
+
+    #[test]
+    fn signs_transactions_like_eip_155() {
+        // The example of https://eips.ethereum.org/EIPS/eip-155
+        let key = SecretKey::from_slice(&[0x46; 32]).unwrap();
+        let tx = Transaction {
+            nonce: 9,
+            gas_price: BigUint::from(20_000_000_000u64),
+            gas_limit: 21000,
+            to: [0x35; 20],
+            value: BigUint::from(1_000_000_000_000_000_000u64),
+            data: Vec::new(),
+        };
+        assert_eq!(
+            hex::encode(tx.sign(&key, 1)),
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000\
+             8025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f\
+             761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+    }
+
+    #[test]
+    fn encodes_erc20_transfers() {
+        let data = erc20_transfer_data(&[0x35; 20], &BigUint::from(256u32));
+        assert_eq!(data.len(), 68);
+        assert_eq!(data[..4], TRANSFER_SELECTOR);
+        assert_eq!(data[16..36], [0x35; 20]);
+        assert_eq!(data[66..], [1, 0]);
+    }
+}
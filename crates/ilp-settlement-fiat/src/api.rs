@@ -0,0 +1,342 @@
+use super::{
+    ledger::FiatLedger,
+    store::{FiatStore, IncomingPayment},
+};
+use bytes::Bytes;
+use interledger_errors::{default_rejection_handler, ApiError};
+use interledger_settlement_engine::{EngineStore, LedgerSettlementEngine};
+use num_bigint::BigUint;
+use ring::hmac;
+use serde::Deserialize;
+use std::{str::FromStr, sync::Arc};
+use tracing::{debug, info, warn};
+use warp::{reply::Json, Filter, Rejection};
+
+/// Header of the webhooks with the hex encoded HMAC-SHA256 of their body
+const SIGNATURE_HEADER: &str = "x-webhook-signature";
+/// Type of the events which report a payment to us
+const PAYMENT_SUCCEEDED: &str = "payment.succeeded";
+
+/// The engine which settles with a [`FiatLedger`]
+pub type FiatEngine = LedgerSettlementEngine<FiatLedger, EngineStore>;
+
+/// Event of the payment processor. Processors with other formats are connected
+/// with a small adapter which translates their events to this one.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct WebhookEvent {
+    /// Unique id of the event, which is used to credit the payment at most once
+    pub id: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// The reference attached to the payment, i.e. the account id
+    pub reference: String,
+    /// Amount in the engine's asset scale
+    pub amount: String,
+}
+
+/// Decides which incoming payments are credited without the operator's approval
+#[derive(Debug, Clone, Default)]
+pub struct ApprovalPolicy {
+    /// Payments up to this amount are credited automatically
+    pub auto_approve_limit: BigUint,
+}
+
+impl ApprovalPolicy {
+    pub fn requires_approval(&self, amount: &BigUint) -> bool {
+        amount > &self.auto_approve_limit
+    }
+}
+
+/// Returns true if the signature is the HMAC-SHA256 of the body with the secret
+fn verify_signature(secret: &[u8], body: &[u8], signature: &str) -> bool {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    match hex::decode(signature) {
+        Ok(signature) => hmac::verify(&key, body, &signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+async fn credit(engine: &FiatEngine, payment: &IncomingPayment) -> Result<(), ApiError> {
+    let amount = BigUint::from_str(&payment.amount)
+        .map_err(|err| ApiError::bad_request().detail(err.to_string()))?;
+    engine
+        .credit_incoming_payment(&payment.account_id, &payment.id, &amount)
+        .await
+        .map_err(|err| ApiError::internal_server_error().detail(err.to_string()))
+}
+
+async fn process_event(
+    engine: FiatEngine,
+    store: EngineStore,
+    policy: Arc<ApprovalPolicy>,
+    event: WebhookEvent,
+) -> Result<(), ApiError> {
+    let store_error =
+        |err: redis_crate::RedisError| ApiError::internal_server_error().detail(err.to_string());
+    if event.event_type != PAYMENT_SUCCEEDED {
+        debug!("Ignoring event {} of type {}", event.id, event.event_type);
+        return Ok(());
+    }
+    if store.is_event_seen(&event.id).await.map_err(store_error)? {
+        debug!("Ignoring event {} which was already processed", event.id);
+        return Ok(());
+    }
+    let amount = BigUint::from_str(&event.amount).map_err(|err| {
+        ApiError::bad_request().detail(format!("Invalid amount {}: {}", event.amount, err))
+    })?;
+    let payment = IncomingPayment {
+        id: event.id,
+        account_id: event.reference,
+        amount: event.amount,
+    };
+
+    let credited = if policy.requires_approval(&amount) {
+        false
+    } else {
+        match credit(&engine, &payment).await {
+            Ok(()) => true,
+            Err(err) => {
+                warn!(
+                    "Could not credit payment {}, it waits for approval: {:?}",
+                    payment.id, err
+                );
+                false
+            }
+        }
+    };
+    if credited {
+        debug!(
+            "Credited payment {} of {} to account {}",
+            payment.id, payment.amount, payment.account_id
+        );
+    } else {
+        info!(
+            "Payment {} of {} to account {} waits for approval",
+            payment.id, payment.amount, payment.account_id
+        );
+        store
+            .save_incoming_payment(&payment)
+            .await
+            .map_err(store_error)?;
+    }
+    store
+        .mark_event_seen(&payment.id)
+        .await
+        .map_err(store_error)?;
+    Ok(())
+}
+
+/// Returns the API which receives the payment processor's webhooks and with which the
+/// operator approves incoming payments and completes payouts. The webhooks must be
+/// signed with the secret, the operator's requests authorized with the admin token.
+pub fn fiat_api(
+    engine: FiatEngine,
+    store: EngineStore,
+    policy: ApprovalPolicy,
+    webhook_secret: Vec<u8>,
+    admin_api_token: String,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let with_engine = warp::any().map(move || engine.clone());
+    let with_store = warp::any().map(move || store.clone());
+    let policy = Arc::new(policy);
+    let with_policy = warp::any().map(move || policy.clone());
+    let webhook_secret = Arc::new(webhook_secret);
+
+    let admin_auth_header = format!("Bearer {}", admin_api_token);
+    let admin_only = warp::header::<String>("authorization")
+        .and_then(move |authorization: String| {
+            let authorized = authorization == admin_auth_header;
+            async move {
+                if authorized {
+                    Ok::<(), Rejection>(())
+                } else {
+                    Err(Rejection::from(ApiError::unauthorized()))
+                }
+            }
+        })
+        .untuple_one();
+
+    // POST /webhooks
+    let webhooks = warp::post()
+        .and(warp::path("webhooks"))
+        .and(warp::path::end())
+        .and(warp::header::<String>(SIGNATURE_HEADER))
+        .and(warp::body::bytes())
+        .and_then(move |signature: String, body: Bytes| {
+            let valid = verify_signature(&webhook_secret, &body, &signature);
+            async move {
+                if !valid {
+                    return Err(Rejection::from(
+                        ApiError::unauthorized().detail("Invalid webhook signature"),
+                    ));
+                }
+                serde_json::from_slice::<WebhookEvent>(&body).map_err(|err| {
+                    Rejection::from(
+                        ApiError::bad_request().detail(format!("Invalid event: {}", err)),
+                    )
+                })
+            }
+        })
+        .and(with_engine.clone())
+        .and(with_store.clone())
+        .and(with_policy)
+        .and_then(
+            |event: WebhookEvent,
+             engine: FiatEngine,
+             store: EngineStore,
+             policy: Arc<ApprovalPolicy>| async move {
+                process_event(engine, store, policy, event).await?;
+                Ok::<_, Rejection>(warp::reply())
+            },
+        );
+
+    // GET /incoming_payments
+    let get_incoming_payments = warp::get()
+        .and(warp::path("incoming_payments"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: EngineStore| async move {
+            let payments = store
+                .load_incoming_payments()
+                .await
+                .map_err(|err| ApiError::internal_server_error().detail(err.to_string()))?;
+            Ok::<Json, Rejection>(warp::reply::json(&payments))
+        });
+
+    // POST /incoming_payments/:id/approve
+    let approve_incoming_payment = warp::post()
+        .and(warp::path("incoming_payments"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("approve"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_engine)
+        .and(with_store.clone())
+        .and_then(
+            |id: String, engine: FiatEngine, store: EngineStore| async move {
+                let payment = store
+                    .load_incoming_payment(&id)
+                    .await
+                    .map_err(|err| ApiError::internal_server_error().detail(err.to_string()))?
+                    .ok_or_else(ApiError::not_found)?;
+                credit(&engine, &payment).await?;
+                store
+                    .remove_incoming_payment(&id)
+                    .await
+                    .map_err(|err| ApiError::internal_server_error().detail(err.to_string()))?;
+                info!(
+                    "Credited approved payment {} of {} to account {}",
+                    payment.id, payment.amount, payment.account_id
+                );
+                Ok::<_, Rejection>(warp::reply())
+            },
+        );
+
+    // POST /incoming_payments/:id/reject
+    let reject_incoming_payment = warp::post()
+        .and(warp::path("incoming_payments"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("reject"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|id: String, store: EngineStore| async move {
+            if !store
+                .remove_incoming_payment(&id)
+                .await
+                .map_err(|err| ApiError::internal_server_error().detail(err.to_string()))?
+            {
+                return Err(Rejection::from(ApiError::not_found()));
+            }
+            info!("Rejected incoming payment {}", id);
+            Ok(warp::reply())
+        });
+
+    // GET /payouts
+    let get_payouts = warp::get()
+        .and(warp::path("payouts"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: EngineStore| async move {
+            let payouts = store
+                .load_payouts()
+                .await
+                .map_err(|err| ApiError::internal_server_error().detail(err.to_string()))?;
+            Ok::<Json, Rejection>(warp::reply::json(&payouts))
+        });
+
+    // POST /payouts/:id/complete
+    let complete_payout = warp::post()
+        .and(warp::path("payouts"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("complete"))
+        .and(warp::path::end())
+        .and(admin_only)
+        .and(with_store)
+        .and_then(|id: String, store: EngineStore| async move {
+            if !store
+                .remove_payout(&id)
+                .await
+                .map_err(|err| ApiError::internal_server_error().detail(err.to_string()))?
+            {
+                return Err(Rejection::from(ApiError::not_found()));
+            }
+            info!("Payout {} was completed", id);
+            Ok(warp::reply())
+        });
+
+    webhooks
+        .or(get_incoming_payments)
+        .or(approve_incoming_payment)
+        .or(reject_incoming_payment)
+        .or(get_payouts)
+        .or(complete_payout)
+        .recover(default_rejection_handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_webhook_signatures() {
+        let body =
+            br#"{"id":"evt_1","type":"payment.succeeded","reference":"alice","amount":"100"}"#;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let signature = hex::encode(hmac::sign(&key, body).as_ref());
+
+        assert!(verify_signature(b"secret", body, &signature));
+        assert!(!verify_signature(b"other secret", body, &signature));
+        assert!(!verify_signature(b"secret", b"{}", &signature));
+        assert!(!verify_signature(b"secret", body, "not hex"));
+    }
+
+    #[test]
+    fn parses_webhook_events() {
+        let event: WebhookEvent = serde_json::from_str(
+            r#"{"id":"evt_1","type":"payment.succeeded","reference":"alice","amount":"100"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            event,
+            WebhookEvent {
+                id: "evt_1".to_string(),
+                event_type: PAYMENT_SUCCEEDED.to_string(),
+                reference: "alice".to_string(),
+                amount: "100".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn requires_approval_over_the_limit() {
+        let policy = ApprovalPolicy {
+            auto_approve_limit: BigUint::from(100u32),
+        };
+        assert!(!policy.requires_approval(&BigUint::from(100u32)));
+        assert!(policy.requires_approval(&BigUint::from(101u32)));
+        assert!(ApprovalPolicy::default().requires_approval(&BigUint::from(1u32)));
+    }
+}
@@ -0,0 +1,71 @@
+use super::store::{FiatStore, Payout};
+use async_trait::async_trait;
+use interledger_settlement_engine::{EngineStore, Ledger, LedgerError};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How a peer pays us
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentDetails {
+    /// Instructions for paying the operator, e.g. bank details
+    pub payee: String,
+    /// Reference which must be attached to the payments, so that the payment
+    /// processor's events can be attributed to the account
+    pub reference: String,
+}
+
+/// Ledger whose payments are made outside of the engine. Settlements to peers are
+/// recorded as payouts, which the operator (or a payment processor integration) pays
+/// and then marks as completed.
+pub struct FiatLedger {
+    store: EngineStore,
+    payee: String,
+    asset_scale: u8,
+}
+
+impl FiatLedger {
+    /// Creates a ledger of an asset with the provided scale (e.g. 2 for cents), which
+    /// tells peers to pay with the provided instructions
+    pub fn new(store: EngineStore, payee: String, asset_scale: u8) -> Self {
+        FiatLedger {
+            store,
+            payee,
+            asset_scale,
+        }
+    }
+}
+
+#[async_trait]
+impl Ledger for FiatLedger {
+    type PaymentDetails = PaymentDetails;
+
+    fn asset_scale(&self) -> u8 {
+        self.asset_scale
+    }
+
+    async fn payment_details(&self, account_id: &str) -> Result<PaymentDetails, LedgerError> {
+        Ok(PaymentDetails {
+            payee: self.payee.clone(),
+            reference: account_id.to_string(),
+        })
+    }
+
+    async fn pay(
+        &self,
+        account_id: &str,
+        details: &PaymentDetails,
+        amount: BigUint,
+    ) -> Result<String, LedgerError> {
+        let payout = Payout {
+            id: Uuid::new_v4().to_string(),
+            account_id: account_id.to_string(),
+            payee: details.payee.clone(),
+            reference: details.reference.clone(),
+            amount: amount.to_string(),
+        };
+        self.store.save_payout(&payout).await?;
+        Ok(payout.id)
+    }
+}
@@ -0,0 +1,25 @@
+//! # ilp-settlement-fiat
+//!
+//! Settlement engine which settles Interledger balances with fiat payments, e.g. card,
+//! ACH or SEPA payments. The payments are made outside of the engine:
+//!
+//! - Peers are told to pay the operator with the configured payment instructions and
+//!   their account id as the reference. The payment processor reports their payments
+//!   with signed webhooks. Payments up to a limit are credited right away, larger ones
+//!   once the operator approved them.
+//! - Settlements to peers are recorded as payouts, which the operator pays and marks
+//!   as completed.
+//!
+//! It is built on [`interledger_settlement_engine`], so it implements the
+//! [Settlement Engine API](https://interledger.org/rfcs/0038-settlement-engines/).
+
+/// Webhooks of the payment processor and the operator's approvals
+pub mod api;
+/// The ledger which records payouts
+pub mod ledger;
+/// Storage of the incoming payments and payouts
+pub mod store;
+
+pub use api::{fiat_api, ApprovalPolicy, FiatEngine, WebhookEvent};
+pub use ledger::{FiatLedger, PaymentDetails};
+pub use store::{FiatStore, IncomingPayment, Payout};
@@ -0,0 +1,116 @@
+use clap::{value_t, App, Arg};
+use ilp_settlement_fiat::{fiat_api, ApprovalPolicy, FiatEngine, FiatLedger};
+use interledger_settlement_engine::EngineStore;
+use num_bigint::BigUint;
+use redis_crate::IntoConnectionInfo;
+use std::{net::SocketAddr, str::FromStr};
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+use url::Url;
+
+fn exit_with_error(message: String) -> ! {
+    error!("{}", message);
+    std::process::exit(1);
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let matches = App::new("ilp-settlement-fiat")
+        .about("Settlement engine which settles Interledger balances with fiat payments reported by a payment processor")
+        .version(env!("CARGO_PKG_VERSION"))
+        .args(&[
+            Arg::with_name("payee")
+                .long("payee")
+                .takes_value(true)
+                .required(true)
+                .help("Instructions with which peers pay the operator, e.g. bank details"),
+            Arg::with_name("asset_scale")
+                .long("asset_scale")
+                .takes_value(true)
+                .default_value("2")
+                .help("Scale of the amounts of the payment processor, e.g. 2 for cents"),
+            Arg::with_name("webhook_secret")
+                .long("webhook_secret")
+                .takes_value(true)
+                .required(true)
+                .help("Secret with which the payment processor's webhooks are signed"),
+            Arg::with_name("admin_auth_token")
+                .long("admin_auth_token")
+                .takes_value(true)
+                .required(true)
+                .help("Bearer token with which the operator approves payments and completes payouts"),
+            Arg::with_name("auto_approve_limit")
+                .long("auto_approve_limit")
+                .takes_value(true)
+                .default_value("0")
+                .help("Incoming payments up to this amount are credited without approval"),
+            Arg::with_name("connector_url")
+                .long("connector_url")
+                .takes_value(true)
+                .default_value("http://127.0.0.1:7771")
+                .help("URL of the settlement API of the connector"),
+            Arg::with_name("redis_url")
+                .long("redis_url")
+                .takes_value(true)
+                .default_value("redis://127.0.0.1:6379")
+                .help("Redis database in which the engine's data is stored"),
+            Arg::with_name("settlement_api_bind_address")
+                .long("settlement_api_bind_address")
+                .takes_value(true)
+                .default_value("127.0.0.1:3000")
+                .help("Address to which the settlement engine API is bound"),
+            Arg::with_name("webhook_api_bind_address")
+                .long("webhook_api_bind_address")
+                .takes_value(true)
+                .default_value("127.0.0.1:3001")
+                .help("Address to which the API for the webhooks and the operator is bound"),
+        ])
+        .get_matches();
+
+    let payee = matches.value_of("payee").unwrap().to_string();
+    let asset_scale = value_t!(matches, "asset_scale", u8).unwrap_or_else(|e| e.exit());
+    let webhook_secret = matches
+        .value_of("webhook_secret")
+        .unwrap()
+        .as_bytes()
+        .to_vec();
+    let admin_auth_token = matches.value_of("admin_auth_token").unwrap().to_string();
+    let auto_approve_limit = matches.value_of("auto_approve_limit").unwrap();
+    let auto_approve_limit = BigUint::from_str(auto_approve_limit).unwrap_or_else(|_| {
+        exit_with_error(format!("Invalid auto approve limit {}", auto_approve_limit))
+    });
+    let connector_url = value_t!(matches, "connector_url", Url).unwrap_or_else(|e| e.exit());
+    let settlement_bind_address =
+        value_t!(matches, "settlement_api_bind_address", SocketAddr).unwrap_or_else(|e| e.exit());
+    let webhook_bind_address =
+        value_t!(matches, "webhook_api_bind_address", SocketAddr).unwrap_or_else(|e| e.exit());
+    let redis_info = matches
+        .value_of("redis_url")
+        .unwrap()
+        .into_connection_info()
+        .unwrap_or_else(|err| exit_with_error(format!("Invalid Redis URL: {}", err)));
+
+    let store = EngineStore::connect(redis_info)
+        .await
+        .unwrap_or_else(|_| exit_with_error("Unable to connect to Redis".to_string()));
+    let engine: FiatEngine = FiatEngine::new(
+        FiatLedger::new(store.clone(), payee, asset_scale),
+        store.clone(),
+        connector_url,
+    );
+
+    let api = fiat_api(
+        engine.clone(),
+        store,
+        ApprovalPolicy { auto_approve_limit },
+        webhook_secret,
+        admin_auth_token,
+    );
+    info!("Webhook API listening on {}", webhook_bind_address);
+    tokio::spawn(warp::serve(api).bind(webhook_bind_address));
+    engine.serve(settlement_bind_address).await;
+}
@@ -0,0 +1,133 @@
+use async_trait::async_trait;
+use interledger_settlement_engine::EngineStore;
+use redis_crate::{AsyncCommands, RedisError};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Map of payment id -> incoming payment waiting for approval
+static INCOMING_PAYMENTS_KEY: &str = "fiat:incoming_payments";
+/// Ids of the processed payment processor events
+static SEEN_EVENTS_KEY: &str = "fiat:seen_events";
+/// Map of payout id -> payout which was not completed yet
+static PAYOUTS_KEY: &str = "fiat:payouts";
+
+/// A payment from a peer, reported by the payment processor
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncomingPayment {
+    /// Id of the payment processor's event, which is also used to credit the payment
+    /// at most once
+    pub id: String,
+    pub account_id: String,
+    /// Amount in the engine's asset scale
+    pub amount: String,
+}
+
+/// A settlement to a peer, which must be paid outside of the engine
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Payout {
+    pub id: String,
+    pub account_id: String,
+    /// Payment instructions of the peer
+    pub payee: String,
+    /// Reference which the peer expects with the payment
+    pub reference: String,
+    /// Amount in the engine's asset scale
+    pub amount: String,
+}
+
+/// Store of the fiat specific data of the engine
+#[async_trait]
+pub trait FiatStore {
+    /// Returns true if the event was already processed
+    async fn is_event_seen(&self, event_id: &str) -> Result<bool, RedisError>;
+
+    /// Records that the event was processed, so that it is ignored if it is delivered again
+    async fn mark_event_seen(&self, event_id: &str) -> Result<(), RedisError>;
+
+    /// Saves an incoming payment which waits for approval
+    async fn save_incoming_payment(&self, payment: &IncomingPayment) -> Result<(), RedisError>;
+
+    /// Loads the incoming payment which waits for approval
+    async fn load_incoming_payment(&self, id: &str) -> Result<Option<IncomingPayment>, RedisError>;
+
+    /// Loads all incoming payments which wait for approval
+    async fn load_incoming_payments(&self) -> Result<Vec<IncomingPayment>, RedisError>;
+
+    /// Removes an incoming payment which was approved or rejected. Returns false if
+    /// it did not wait for approval.
+    async fn remove_incoming_payment(&self, id: &str) -> Result<bool, RedisError>;
+
+    /// Saves a payout which must be paid
+    async fn save_payout(&self, payout: &Payout) -> Result<(), RedisError>;
+
+    /// Loads all payouts which were not completed yet
+    async fn load_payouts(&self) -> Result<Vec<Payout>, RedisError>;
+
+    /// Removes a completed payout. Returns false if it did not exist.
+    async fn remove_payout(&self, id: &str) -> Result<bool, RedisError>;
+}
+
+fn decode_all<T: serde::de::DeserializeOwned>(entries: Vec<String>) -> Vec<T> {
+    entries
+        .iter()
+        .filter_map(|entry| {
+            serde_json::from_str(entry)
+                .map_err(|err| warn!("Ignoring invalid entry {}: {}", entry, err))
+                .ok()
+        })
+        .collect()
+}
+
+#[async_trait]
+impl FiatStore for EngineStore {
+    async fn is_event_seen(&self, event_id: &str) -> Result<bool, RedisError> {
+        self.connection().sismember(SEEN_EVENTS_KEY, event_id).await
+    }
+
+    async fn mark_event_seen(&self, event_id: &str) -> Result<(), RedisError> {
+        self.connection().sadd(SEEN_EVENTS_KEY, event_id).await
+    }
+
+    async fn save_incoming_payment(&self, payment: &IncomingPayment) -> Result<(), RedisError> {
+        self.connection()
+            .hset(
+                INCOMING_PAYMENTS_KEY,
+                &payment.id,
+                serde_json::to_string(payment).unwrap(),
+            )
+            .await
+    }
+
+    async fn load_incoming_payment(&self, id: &str) -> Result<Option<IncomingPayment>, RedisError> {
+        let entry: Option<String> = self.connection().hget(INCOMING_PAYMENTS_KEY, id).await?;
+        Ok(entry.and_then(|entry| decode_all(vec![entry]).pop()))
+    }
+
+    async fn load_incoming_payments(&self) -> Result<Vec<IncomingPayment>, RedisError> {
+        let entries: Vec<String> = self.connection().hvals(INCOMING_PAYMENTS_KEY).await?;
+        Ok(decode_all(entries))
+    }
+
+    async fn remove_incoming_payment(&self, id: &str) -> Result<bool, RedisError> {
+        self.connection().hdel(INCOMING_PAYMENTS_KEY, id).await
+    }
+
+    async fn save_payout(&self, payout: &Payout) -> Result<(), RedisError> {
+        self.connection()
+            .hset(
+                PAYOUTS_KEY,
+                &payout.id,
+                serde_json::to_string(payout).unwrap(),
+            )
+            .await
+    }
+
+    async fn load_payouts(&self) -> Result<Vec<Payout>, RedisError> {
+        let entries: Vec<String> = self.connection().hvals(PAYOUTS_KEY).await?;
+        Ok(decode_all(entries))
+    }
+
+    async fn remove_payout(&self, id: &str) -> Result<bool, RedisError> {
+        self.connection().hdel(PAYOUTS_KEY, id).await
+    }
+}
@@ -0,0 +1,337 @@
+use super::{
+    rpc::{IncomingPayment, PaymentDetails, RippledClient, DROPS_SCALE},
+    store::XrpStore,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use interledger_errors::ApiError;
+use interledger_settlement::core::{
+    scale_with_precision_loss,
+    types::{ApiResponse, ApiResult, LeftoversStore, Quantity, SettlementEngine},
+};
+use num_bigint::BigUint;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{str::FromStr, time::Duration};
+use tracing::{debug, error, trace, warn};
+use url::Url;
+
+/// Number of attempts to find a destination tag which is not used yet
+const MAX_TAG_ATTEMPTS: usize = 10;
+
+/// Messages exchanged with the peer's engine (compatible with the JavaScript XRP engine)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Message {
+    /// Requests the address and destination tag to which settlements are paid
+    PaymentDetails,
+}
+
+/// Settlement engine which settles with direct XRP payments. Every account is
+/// assigned a destination tag, which identifies the account when its peer pays us.
+#[derive(Clone)]
+pub struct XrpSettlementEngine<S> {
+    store: S,
+    rippled: RippledClient,
+    http_client: reqwest::Client,
+    connector_url: Url,
+    address: String,
+    secret: String,
+}
+
+impl<S> XrpSettlementEngine<S>
+where
+    S: XrpStore + LeftoversStore<AccountId = String, AssetType = BigUint> + Clone + Send + Sync,
+{
+    pub fn new(
+        store: S,
+        rippled: RippledClient,
+        connector_url: Url,
+        address: String,
+        secret: String,
+    ) -> Self {
+        XrpSettlementEngine {
+            store,
+            rippled,
+            http_client: reqwest::Client::new(),
+            connector_url,
+            address,
+            secret,
+        }
+    }
+
+    /// Returns the payment details with which the account's peer pays us
+    async fn own_payment_details(&self, account_id: &str) -> Result<PaymentDetails, ApiError> {
+        let destination_tag = self
+            .store
+            .load_destination_tag(account_id)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| ApiError::account_not_found().detail(account_id.to_string()))?;
+        Ok(PaymentDetails {
+            xrp_address: self.address.clone(),
+            destination_tag,
+        })
+    }
+
+    /// Returns the peer's payment details, asking the peer's engine for them
+    /// (via the connector) if we don't know them yet
+    async fn peer_payment_details(&self, account_id: &str) -> Result<PaymentDetails, ApiError> {
+        if let Some(details) = self
+            .store
+            .load_peer_details(account_id)
+            .await
+            .map_err(internal_error)?
+        {
+            return Ok(details);
+        }
+
+        let mut url = self.connector_url.clone();
+        url.path_segments_mut()
+            .expect("Invalid connector URL")
+            .push("accounts")
+            .push(account_id)
+            .push("messages");
+        let message = serde_json::to_vec(&Message::PaymentDetails).unwrap();
+        let response = self
+            .http_client
+            .post(url.as_ref())
+            .header("Content-Type", "application/octet-stream")
+            .body(message)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(internal_error)?;
+        let body = response.bytes().await.map_err(internal_error)?;
+        let details: PaymentDetails = serde_json::from_slice(&body).map_err(|err| {
+            ApiError::internal_server_error().detail(format!(
+                "Invalid payment details from the peer's engine: {}",
+                err
+            ))
+        })?;
+        self.store
+            .save_peer_details(account_id, &details)
+            .await
+            .map_err(internal_error)?;
+        debug!(
+            "Account {} is settled to {} with destination tag {}",
+            account_id, details.xrp_address, details.destination_tag
+        );
+        Ok(details)
+    }
+
+    /// Notifies the connector of an incoming payment. The transaction hash is used
+    /// as the idempotency key, so a payment is credited at most once.
+    pub(crate) async fn notify_connector(
+        &self,
+        account_id: &str,
+        payment: &IncomingPayment,
+    ) -> Result<(), reqwest::Error> {
+        let mut url = self.connector_url.clone();
+        url.path_segments_mut()
+            .expect("Invalid connector URL")
+            .push("accounts")
+            .push(account_id)
+            .push("settlements");
+        trace!(
+            "Notifying connector of incoming payment {} of {} drops to account {}",
+            payment.hash,
+            payment.delivered_drops,
+            account_id
+        );
+        self.http_client
+            .post(url.as_ref())
+            .header("Idempotency-Key", payment.hash.as_str())
+            .json(&Quantity::new(payment.delivered_drops, DROPS_SCALE))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Polls the XRP Ledger for incoming payments at the provided interval, forever
+    pub async fn watch_incoming_payments(self, poll_interval: Duration) {
+        let mut interval = tokio::time::interval(poll_interval);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.process_incoming_payments().await {
+                warn!("Error processing incoming XRP payments: {}", err);
+            }
+        }
+    }
+
+    /// Credits the payments which were validated since the last processed ledger.
+    /// The last processed ledger is only advanced once all payments of a page were
+    /// credited, so that failed notifications are retried on the next poll.
+    async fn process_incoming_payments(
+        &self,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let start = self
+            .store
+            .load_last_processed_ledger()
+            .await?
+            .map(|ledger| ledger + 1)
+            .unwrap_or(0);
+        let mut marker = None;
+        loop {
+            let page = self
+                .rippled
+                .account_payments(&self.address, start, marker)
+                .await?;
+            for payment in page.payments.iter() {
+                match self
+                    .store
+                    .load_account_id_from_tag(payment.destination_tag)
+                    .await?
+                {
+                    Some(account_id) => self.notify_connector(&account_id, payment).await?,
+                    None => warn!(
+                        "Ignoring payment {} with unknown destination tag {}",
+                        payment.hash, payment.destination_tag
+                    ),
+                }
+            }
+            if page.marker.is_none() {
+                self.store
+                    .save_last_processed_ledger(page.ledger_index_max)
+                    .await?;
+                return Ok(());
+            }
+            marker = page.marker;
+        }
+    }
+}
+
+#[async_trait]
+impl<S> SettlementEngine for XrpSettlementEngine<S>
+where
+    S: XrpStore + LeftoversStore<AccountId = String, AssetType = BigUint> + Clone + Send + Sync,
+{
+    async fn create_account(&self, account_id: String) -> ApiResult {
+        // The tags are random so that they cannot be guessed, e.g. to attribute payments
+        // of one peer to another
+        let mut attempts = 0;
+        loop {
+            let tag = rand::thread_rng().gen::<u32>();
+            if self
+                .store
+                .save_destination_tag(&account_id, tag)
+                .await
+                .map_err(internal_error)?
+            {
+                break;
+            }
+            attempts += 1;
+            if attempts == MAX_TAG_ATTEMPTS {
+                return Err(ApiError::internal_server_error()
+                    .detail("Unable to find an unused destination tag"));
+            }
+        }
+
+        // The peer's engine may not be set up yet, in which case the details
+        // are requested again before the first settlement
+        if let Err(err) = self.peer_payment_details(&account_id).await {
+            debug!(
+                "Could not get payment details of account {} yet: {:?}",
+                account_id, err
+            );
+        }
+        Ok(ApiResponse::Default)
+    }
+
+    async fn delete_account(&self, account_id: String) -> ApiResult {
+        self.store
+            .delete_account(&account_id)
+            .await
+            .map_err(internal_error)?;
+        Ok(ApiResponse::Default)
+    }
+
+    async fn send_money(&self, account_id: String, money: Quantity) -> ApiResult {
+        let amount = BigUint::from_str(&money.amount).map_err(|err| {
+            ApiError::bad_request().detail(format!("Invalid amount {}: {}", money.amount, err))
+        })?;
+        // Amounts which are too small to be paid in drops are added to the next settlement
+        let (drops, precision_loss) = scale_with_precision_loss(amount, DROPS_SCALE, money.scale);
+        let leftovers = self
+            .store
+            .load_uncredited_settlement_amount(account_id.clone(), DROPS_SCALE)
+            .await
+            .map_err(internal_error)?;
+        let drops = drops + leftovers;
+        if precision_loss > BigUint::from(0u32) {
+            self.store
+                .save_uncredited_settlement_amount(
+                    account_id.clone(),
+                    (precision_loss, std::cmp::max(money.scale, DROPS_SCALE)),
+                )
+                .await
+                .map_err(internal_error)?;
+        }
+        let drops = u64::from_str(&drops.to_string()).map_err(|_| {
+            ApiError::bad_request().detail(format!("Amount {} is too large", money.amount))
+        })?;
+        if drops == 0 {
+            return Ok(ApiResponse::Default);
+        }
+
+        let peer = self.peer_payment_details(&account_id).await?;
+        let hash = self
+            .rippled
+            .submit_payment(
+                &self.secret,
+                &self.address,
+                &peer.xrp_address,
+                peer.destination_tag,
+                drops,
+            )
+            .await
+            .map_err(|err| {
+                error!("Error settling {} drops to {}: {}", drops, account_id, err);
+                internal_error(err)
+            })?;
+        debug!(
+            "Settled {} drops to account {} in transaction {}",
+            drops, account_id, hash
+        );
+        Ok(ApiResponse::Default)
+    }
+
+    async fn receive_message(&self, account_id: String, message: Vec<u8>) -> ApiResult {
+        let message: Message = serde_json::from_slice(&message)
+            .map_err(|err| ApiError::bad_request().detail(format!("Invalid message: {}", err)))?;
+        match message {
+            Message::PaymentDetails => {
+                let details = self.own_payment_details(&account_id).await?;
+                Ok(ApiResponse::Data(Bytes::from(
+                    serde_json::to_vec(&details).unwrap(),
+                )))
+            }
+        }
+    }
+}
+
+fn internal_error<E: std::fmt::Display>(err: E) -> ApiError {
+    ApiError::internal_server_error().detail(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_messages_like_the_javascript_engine() {
+        assert_eq!(
+            serde_json::to_string(&Message::PaymentDetails).unwrap(),
+            r#"{"type":"paymentDetails"}"#
+        );
+        let details = PaymentDetails {
+            xrp_address: "rPEPPER7kfTD9w2To4CQk6UCfuHM9c6GDY".to_string(),
+            destination_tag: 12,
+        };
+        assert_eq!(
+            serde_json::to_string(&details).unwrap(),
+            r#"{"xrpAddress":"rPEPPER7kfTD9w2To4CQk6UCfuHM9c6GDY","destinationTag":12}"#
+        );
+    }
+}
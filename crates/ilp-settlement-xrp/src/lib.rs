@@ -0,0 +1,18 @@
+//! # ilp-settlement-xrp
+//!
+//! Settlement engine which settles Interledger balances with direct payments on the
+//! [XRP Ledger](https://xrpl.org). It implements the
+//! [Settlement Engine API](https://interledger.org/rfcs/0038-settlement-engines/) and
+//! exchanges payment details with peers which run this engine or the JavaScript
+//! `ilp-settlement-xrp` engine.
+
+/// The settlement engine and the watcher of incoming payments
+pub mod engine;
+/// Client of the rippled JSON-RPC API
+pub mod rpc;
+/// Storage of the engine's accounts and processed ledgers
+pub mod store;
+
+pub use engine::XrpSettlementEngine;
+pub use rpc::RippledClient;
+pub use store::{XrpRedisStore, XrpStore};
@@ -0,0 +1,104 @@
+use clap::{value_t, App, Arg};
+use ilp_settlement_xrp::{RippledClient, XrpRedisStore, XrpSettlementEngine};
+use interledger_settlement::core::engines_api::create_settlement_engine_filter;
+use redis_crate::IntoConnectionInfo;
+use std::{net::SocketAddr, time::Duration};
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+use url::Url;
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let matches = App::new("ilp-settlement-xrp")
+        .about("Settlement engine which settles Interledger balances on the XRP Ledger")
+        .version(env!("CARGO_PKG_VERSION"))
+        .args(&[
+            Arg::with_name("xrp_address")
+                .long("xrp_address")
+                .takes_value(true)
+                .required(true)
+                .help("XRP Ledger address from which settlements are paid and to which peers pay"),
+            Arg::with_name("xrp_secret")
+                .long("xrp_secret")
+                .takes_value(true)
+                .required(true)
+                .help("Secret of the XRP Ledger address. It is sent to rippled, which signs the transactions"),
+            Arg::with_name("rippled_url")
+                .long("rippled_url")
+                .takes_value(true)
+                .default_value("http://127.0.0.1:5005")
+                .help("URL of the JSON-RPC API of a rippled server which is trusted with the secret"),
+            Arg::with_name("connector_url")
+                .long("connector_url")
+                .takes_value(true)
+                .default_value("http://127.0.0.1:7771")
+                .help("URL of the settlement API of the connector"),
+            Arg::with_name("redis_url")
+                .long("redis_url")
+                .takes_value(true)
+                .default_value("redis://127.0.0.1:6379")
+                .help("Redis database in which the engine's data is stored"),
+            Arg::with_name("settlement_api_bind_address")
+                .long("settlement_api_bind_address")
+                .takes_value(true)
+                .default_value("127.0.0.1:3000")
+                .help("Address to which the settlement engine API is bound"),
+            Arg::with_name("poll_interval")
+                .long("poll_interval")
+                .takes_value(true)
+                .default_value("5000")
+                .help("Interval, in milliseconds, at which the XRP Ledger is checked for incoming payments"),
+        ])
+        .get_matches();
+
+    let address = matches.value_of("xrp_address").unwrap().to_string();
+    let secret = matches.value_of("xrp_secret").unwrap().to_string();
+    let rippled_url = value_t!(matches, "rippled_url", Url).unwrap_or_else(|e| e.exit());
+    let connector_url = value_t!(matches, "connector_url", Url).unwrap_or_else(|e| e.exit());
+    let bind_address =
+        value_t!(matches, "settlement_api_bind_address", SocketAddr).unwrap_or_else(|e| e.exit());
+    let poll_interval = value_t!(matches, "poll_interval", u64).unwrap_or_else(|e| e.exit());
+    let redis_info = match matches
+        .value_of("redis_url")
+        .unwrap()
+        .into_connection_info()
+    {
+        Ok(redis_info) => redis_info,
+        Err(err) => {
+            error!("Invalid Redis URL: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let store = match XrpRedisStore::connect(redis_info).await {
+        Ok(store) => store,
+        Err(_) => {
+            error!("Unable to connect to Redis");
+            std::process::exit(1);
+        }
+    };
+    let engine = XrpSettlementEngine::new(
+        store.clone(),
+        RippledClient::new(rippled_url),
+        connector_url,
+        address.clone(),
+        secret,
+    );
+
+    tokio::spawn(
+        engine
+            .clone()
+            .watch_incoming_payments(Duration::from_millis(poll_interval)),
+    );
+
+    info!(
+        "XRP settlement engine for {} listening on {}",
+        address, bind_address
+    );
+    let api = create_settlement_engine_filter(engine, store);
+    warp::serve(api).bind(bind_address).await;
+}
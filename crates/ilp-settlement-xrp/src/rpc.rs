@@ -0,0 +1,265 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use url::Url;
+
+/// Number of drops in one XRP. Amounts are exchanged with the connector in drops.
+pub const DROPS_SCALE: u8 = 6;
+
+/// Transaction results with which a submitted transaction may still succeed
+const PENDING_RESULTS: &[&str] = &["tesSUCCESS", "terQUEUED"];
+
+/// Errors returned by the rippled JSON-RPC API
+#[derive(Debug, Error)]
+pub enum RpcError {
+    #[error("error sending request to rippled: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("rippled returned error {error}: {message}")]
+    Rippled { error: String, message: String },
+    #[error("transaction was rejected with {0}")]
+    TransactionRejected(String),
+    #[error("unexpected response from rippled: {0}")]
+    UnexpectedResponse(String),
+}
+
+/// A validated XRP payment to the engine's address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncomingPayment {
+    /// Hash of the transaction, which is unique and used as the idempotency key
+    pub hash: String,
+    /// The ledger in which the transaction was included
+    pub ledger_index: u64,
+    /// The destination tag, which identifies the account which paid
+    pub destination_tag: u32,
+    /// The amount which was delivered, in drops
+    pub delivered_drops: u64,
+}
+
+/// One page of transactions of an XRP Ledger account
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionsPage {
+    pub payments: Vec<IncomingPayment>,
+    /// The highest ledger which was searched
+    pub ledger_index_max: u64,
+    /// Set if there are more transactions to fetch
+    pub marker: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    method: &'a str,
+    params: [Value; 1],
+}
+
+/// Minimal client of the [rippled JSON-RPC API](https://xrpl.org/http-websocket-apis.html)
+#[derive(Clone)]
+pub struct RippledClient {
+    client: reqwest::Client,
+    url: Url,
+}
+
+impl RippledClient {
+    pub fn new(url: Url) -> Self {
+        RippledClient {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let response: Value = self
+            .client
+            .post(self.url.as_ref())
+            .json(&Request {
+                method,
+                params: [params],
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let result = response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| RpcError::UnexpectedResponse(response.to_string()))?;
+        if result.get("status").and_then(Value::as_str) == Some("error") {
+            return Err(RpcError::Rippled {
+                error: result["error"].as_str().unwrap_or_default().to_string(),
+                message: result["error_message"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            });
+        }
+        Ok(result)
+    }
+
+    /// Signs and submits a direct XRP payment. The transaction is signed by rippled,
+    /// so the secret is sent to it and rippled MUST be trusted (e.g. run locally).
+    /// Returns the hash of the submitted transaction.
+    pub async fn submit_payment(
+        &self,
+        secret: &str,
+        address: &str,
+        destination: &str,
+        destination_tag: u32,
+        drops: u64,
+    ) -> Result<String, RpcError> {
+        let result = self
+            .call(
+                "submit",
+                json!({
+                    "secret": secret,
+                    "fee_mult_max": 1000,
+                    "tx_json": {
+                        "TransactionType": "Payment",
+                        "Account": address,
+                        "Destination": destination,
+                        "DestinationTag": destination_tag,
+                        "Amount": drops.to_string(),
+                    },
+                }),
+            )
+            .await?;
+        parse_submit_result(&result)
+    }
+
+    /// Loads the validated transactions of the account, starting at the provided ledger
+    pub async fn account_payments(
+        &self,
+        address: &str,
+        ledger_index_min: u64,
+        marker: Option<Value>,
+    ) -> Result<TransactionsPage, RpcError> {
+        let mut params = json!({
+            "account": address,
+            "ledger_index_min": ledger_index_min,
+            "ledger_index_max": -1,
+            "forward": true,
+            "limit": 200,
+        });
+        if let Some(marker) = marker {
+            params["marker"] = marker;
+        }
+        let result = self.call("account_tx", params).await?;
+        parse_account_payments(address, &result)
+    }
+}
+
+fn parse_submit_result(result: &Value) -> Result<String, RpcError> {
+    let engine_result = result["engine_result"]
+        .as_str()
+        .ok_or_else(|| RpcError::UnexpectedResponse(result.to_string()))?;
+    if !PENDING_RESULTS.contains(&engine_result) {
+        return Err(RpcError::TransactionRejected(engine_result.to_string()));
+    }
+    result["tx_json"]["hash"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| RpcError::UnexpectedResponse(result.to_string()))
+}
+
+/// Extracts the successful, validated XRP payments to `address` which have a destination tag
+fn parse_account_payments(address: &str, result: &Value) -> Result<TransactionsPage, RpcError> {
+    let ledger_index_max = result["ledger_index_max"]
+        .as_u64()
+        .ok_or_else(|| RpcError::UnexpectedResponse(result.to_string()))?;
+    let transactions = result["transactions"]
+        .as_array()
+        .ok_or_else(|| RpcError::UnexpectedResponse(result.to_string()))?;
+
+    let payments = transactions
+        .iter()
+        .filter(|entry| entry["validated"].as_bool() == Some(true))
+        .filter(|entry| entry["meta"]["TransactionResult"].as_str() == Some("tesSUCCESS"))
+        .filter_map(|entry| {
+            let tx = &entry["tx"];
+            if tx["TransactionType"].as_str() != Some("Payment")
+                || tx["Destination"].as_str() != Some(address)
+            {
+                return None;
+            }
+            // Issued currencies are objects, only XRP amounts (in drops) are strings
+            let delivered_drops = entry["meta"]["delivered_amount"].as_str()?.parse().ok()?;
+            Some(IncomingPayment {
+                hash: tx["hash"].as_str()?.to_string(),
+                ledger_index: tx["ledger_index"].as_u64()?,
+                destination_tag: tx["DestinationTag"].as_u64()? as u32,
+                delivered_drops,
+            })
+        })
+        .collect();
+
+    Ok(TransactionsPage {
+        payments,
+        ledger_index_max,
+        marker: result.get("marker").cloned(),
+    })
+}
+
+/// Payment details exchanged by the engines of two peers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentDetails {
+    pub xrp_address: String,
+    pub destination_tag: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "rPEPPER7kfTD9w2To4CQk6UCfuHM9c6GDY";
+
+    fn payment(validated: bool, result: &str, amount: Value) -> Value {
+        json!({
+            "validated": validated,
+            "meta": { "TransactionResult": result, "delivered_amount": amount },
+            "tx": {
+                "TransactionType": "Payment",
+                "Destination": ADDRESS,
+                "DestinationTag": 12,
+                "hash": "ABCD",
+                "ledger_index": 100,
+            },
+        })
+    }
+
+    #[test]
+    fn parses_incoming_xrp_payments() {
+        let result = json!({
+            "ledger_index_max": 105,
+            "transactions": [
+                payment(true, "tesSUCCESS", json!("1000")),
+                payment(false, "tesSUCCESS", json!("1000")),
+                payment(true, "tecPATH_DRY", json!("1000")),
+                payment(true, "tesSUCCESS", json!({ "currency": "USD", "value": "1" })),
+            ],
+            "marker": { "ledger": 100, "seq": 1 },
+        });
+        let page = parse_account_payments(ADDRESS, &result).unwrap();
+        assert_eq!(
+            page.payments,
+            vec![IncomingPayment {
+                hash: "ABCD".to_string(),
+                ledger_index: 100,
+                destination_tag: 12,
+                delivered_drops: 1000,
+            }]
+        );
+        assert_eq!(page.ledger_index_max, 105);
+        assert!(page.marker.is_some());
+    }
+
+    #[test]
+    fn only_accepts_pending_submissions() {
+        let result = json!({ "engine_result": "tesSUCCESS", "tx_json": { "hash": "ABCD" } });
+        assert_eq!(parse_submit_result(&result).unwrap(), "ABCD");
+        let result = json!({ "engine_result": "tecUNFUNDED_PAYMENT", "tx_json": {} });
+        assert!(matches!(
+            parse_submit_result(&result),
+            Err(RpcError::TransactionRejected(_))
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+use super::rpc::PaymentDetails;
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::StatusCode;
+use interledger_errors::{IdempotentStoreError, LeftoversStoreError};
+use interledger_settlement::core::{
+    backends_common::redis::{EngineRedisStore, EngineRedisStoreBuilder},
+    idempotency::{IdempotentData, IdempotentStore},
+    types::LeftoversStore,
+};
+use num_bigint::BigUint;
+use redis_crate::{AsyncCommands, ConnectionInfo, RedisError};
+
+/// Map of destination tag -> account id
+static DESTINATION_TAGS_KEY: &str = "xrp:destination_tags";
+/// Ledger up to which incoming payments were processed
+static LAST_LEDGER_KEY: &str = "xrp:last_processed_ledger";
+
+fn account_key(account_id: &str) -> String {
+    format!("xrp:accounts:{}", account_id)
+}
+
+/// Store of the XRP specific data of the engine
+#[async_trait]
+pub trait XrpStore {
+    /// Assigns the destination tag with which the account's peer pays us, unless the
+    /// account already has one. Returns false if the tag is used by another account.
+    async fn save_destination_tag(&self, account_id: &str, tag: u32) -> Result<bool, RedisError>;
+
+    /// Loads the destination tag with which the account's peer pays us
+    async fn load_destination_tag(&self, account_id: &str) -> Result<Option<u32>, RedisError>;
+
+    /// Loads the account to which the destination tag was assigned
+    async fn load_account_id_from_tag(&self, tag: u32) -> Result<Option<String>, RedisError>;
+
+    /// Saves the address and destination tag to which settlements to the peer are paid
+    async fn save_peer_details(
+        &self,
+        account_id: &str,
+        details: &PaymentDetails,
+    ) -> Result<(), RedisError>;
+
+    /// Loads the address and destination tag to which settlements to the peer are paid
+    async fn load_peer_details(
+        &self,
+        account_id: &str,
+    ) -> Result<Option<PaymentDetails>, RedisError>;
+
+    /// Removes all data of the account
+    async fn delete_account(&self, account_id: &str) -> Result<(), RedisError>;
+
+    /// Loads the ledger up to which incoming payments were processed
+    async fn load_last_processed_ledger(&self) -> Result<Option<u64>, RedisError>;
+
+    /// Saves the ledger up to which incoming payments were processed
+    async fn save_last_processed_ledger(&self, ledger_index: u64) -> Result<(), RedisError>;
+}
+
+/// Store of the XRP engine, which keeps the idempotency and leftover data
+/// in the common engine store
+#[derive(Clone)]
+pub struct XrpRedisStore {
+    redis_store: EngineRedisStore,
+}
+
+impl XrpRedisStore {
+    /// Connects to the provided redis_url
+    pub async fn connect(redis_url: ConnectionInfo) -> Result<Self, ()> {
+        let redis_store = EngineRedisStoreBuilder::new(redis_url).connect().await?;
+        Ok(XrpRedisStore { redis_store })
+    }
+}
+
+#[async_trait]
+impl XrpStore for XrpRedisStore {
+    async fn save_destination_tag(&self, account_id: &str, tag: u32) -> Result<bool, RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        if self.load_destination_tag(account_id).await?.is_some() {
+            return Ok(true);
+        }
+        let assigned: bool = connection
+            .hset_nx(DESTINATION_TAGS_KEY, tag, account_id)
+            .await?;
+        if assigned {
+            let _: () = connection
+                .hset(account_key(account_id), "destination_tag", tag)
+                .await?;
+        }
+        Ok(assigned)
+    }
+
+    async fn load_destination_tag(&self, account_id: &str) -> Result<Option<u32>, RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        connection
+            .hget(account_key(account_id), "destination_tag")
+            .await
+    }
+
+    async fn load_account_id_from_tag(&self, tag: u32) -> Result<Option<String>, RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        connection.hget(DESTINATION_TAGS_KEY, tag).await
+    }
+
+    async fn save_peer_details(
+        &self,
+        account_id: &str,
+        details: &PaymentDetails,
+    ) -> Result<(), RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        connection
+            .hset_multiple(
+                account_key(account_id),
+                &[
+                    ("peer_address", details.xrp_address.clone()),
+                    ("peer_destination_tag", details.destination_tag.to_string()),
+                ],
+            )
+            .await
+    }
+
+    async fn load_peer_details(
+        &self,
+        account_id: &str,
+    ) -> Result<Option<PaymentDetails>, RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        let (xrp_address, destination_tag): (Option<String>, Option<u32>) =
+            redis_crate::cmd("HMGET")
+                .arg(account_key(account_id))
+                .arg("peer_address")
+                .arg("peer_destination_tag")
+                .query_async(&mut connection)
+                .await?;
+        Ok(match (xrp_address, destination_tag) {
+            (Some(xrp_address), Some(destination_tag)) => Some(PaymentDetails {
+                xrp_address,
+                destination_tag,
+            }),
+            _ => None,
+        })
+    }
+
+    async fn delete_account(&self, account_id: &str) -> Result<(), RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        let tag = self.load_destination_tag(account_id).await?;
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        pipe.del(account_key(account_id)).ignore();
+        if let Some(tag) = tag {
+            pipe.hdel(DESTINATION_TAGS_KEY, tag).ignore();
+        }
+        pipe.query_async(&mut connection).await
+    }
+
+    async fn load_last_processed_ledger(&self) -> Result<Option<u64>, RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        connection.get(LAST_LEDGER_KEY).await
+    }
+
+    async fn save_last_processed_ledger(&self, ledger_index: u64) -> Result<(), RedisError> {
+        let mut connection = self.redis_store.connection.clone();
+        connection.set(LAST_LEDGER_KEY, ledger_index).await
+    }
+}
+
+#[async_trait]
+impl IdempotentStore for XrpRedisStore {
+    async fn load_idempotent_data(
+        &self,
+        idempotency_key: String,
+    ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        self.redis_store.load_idempotent_data(idempotency_key).await
+    }
+
+    async fn save_idempotent_data(
+        &self,
+        idempotency_key: String,
+        input_hash: [u8; 32],
+        status_code: StatusCode,
+        data: Bytes,
+    ) -> Result<(), IdempotentStoreError> {
+        self.redis_store
+            .save_idempotent_data(idempotency_key, input_hash, status_code, data)
+            .await
+    }
+}
+
+#[async_trait]
+impl LeftoversStore for XrpRedisStore {
+    type AccountId = String;
+    type AssetType = BigUint;
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+        uncredited_settlement_amount: (Self::AssetType, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        self.redis_store
+            .save_uncredited_settlement_amount(account_id, uncredited_settlement_amount)
+            .await
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+        local_scale: u8,
+    ) -> Result<Self::AssetType, LeftoversStoreError> {
+        self.redis_store
+            .load_uncredited_settlement_amount(account_id, local_scale)
+            .await
+    }
+
+    async fn clear_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+    ) -> Result<(), LeftoversStoreError> {
+        self.redis_store
+            .clear_uncredited_settlement_amount(account_id)
+            .await
+    }
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        self.redis_store
+            .get_uncredited_settlement_amount(account_id)
+            .await
+    }
+}
@@ -2,8 +2,8 @@
 use async_trait::async_trait;
 use bytes::Bytes;
 use interledger_btp::{BtpAccount, BtpOutgoingService};
-use interledger_ccp::CcpRoutingAccount;
-use interledger_errors::NodeStoreError;
+use interledger_ccp::{CcpRoutingAccount, RouteChanges, RoutingRelation};
+use interledger_errors::{AccountStoreError, NodeStoreError};
 use interledger_http::{HttpAccount, HttpStore};
 use interledger_packet::Address;
 use interledger_rates::ExchangeRateStore;
@@ -11,17 +11,23 @@ use interledger_router::RouterStore;
 use interledger_service::{
     Account, AccountStore, AddressStore, IncomingService, OutgoingService, Username,
 };
-use interledger_service_util::BalanceStore;
+use interledger_service_util::{BalanceStore, PacketHistoryStore, VolumeLimitAccount};
 use interledger_settlement::core::types::{SettlementAccount, SettlementStore};
-use interledger_stream::StreamNotificationsStore;
+use interledger_stream::{
+    ConnectionGenerator, IncomingPaymentsStore, SecretGeneration, StreamNotificationsStore,
+};
 use secrecy::SecretString;
 use serde::{de, Deserialize, Serialize};
-use std::{boxed::*, collections::HashMap, fmt::Display, net::SocketAddr, str::FromStr};
+use std::{
+    boxed::*, collections::HashMap, fmt::Display, net::SocketAddr, str::FromStr, sync::Arc,
+    time::Duration,
+};
 use url::Url;
 use uuid::Uuid;
 use warp::{self, Filter};
 
 mod routes;
+pub use routes::{ApiAuth, ApiKey, ApiScope, ConnectionCount, EventAccount, NodeEvent, NodeEvents};
 
 // This enum and the following functions are used to allow clients to send either
 // numbers or strings and have them be properly deserialized into the appropriate
@@ -97,6 +103,14 @@ pub trait NodeStore: Clone + Send + Sync + 'static {
         account: AccountDetails,
     ) -> Result<Self::Account, NodeStoreError>;
 
+    /// Inserts a batch of accounts, either all of them or none of them. Fails with
+    /// `NodeStoreError::InvalidAccounts` listing every account which could not be inserted,
+    /// e.g. because it is invalid or its username is already taken.
+    async fn insert_accounts(
+        &self,
+        accounts: Vec<AccountDetails>,
+    ) -> Result<Vec<Self::Account>, NodeStoreError>;
+
     /// Deletes the account corresponding to the provided id and returns it
     async fn delete_account(&self, id: Uuid) -> Result<Self::Account, NodeStoreError>;
 
@@ -117,10 +131,20 @@ pub trait NodeStore: Clone + Send + Sync + 'static {
         settings: AccountSettings,
     ) -> Result<Self::Account, NodeStoreError>;
 
-    // TODO limit the number of results and page through them
-    /// Gets all stored accounts
+    /// Gets all stored accounts. Use `list_accounts` to page through them instead
+    /// if the store may contain many accounts.
     async fn get_all_accounts(&self) -> Result<Vec<Self::Account>, NodeStoreError>;
 
+    /// Lists the accounts matching the filter, ordered by id. Returns at most `limit`
+    /// accounts whose ids are greater than the `after` cursor, along with the cursor
+    /// of the next page.
+    async fn list_accounts(
+        &self,
+        after: Option<Uuid>,
+        limit: usize,
+        filter: AccountFilter,
+    ) -> Result<AccountPage<Self::Account>, NodeStoreError>;
+
     /// Sets the static routes for routing
     async fn set_static_routes<R>(&self, routes: R) -> Result<(), NodeStoreError>
     where
@@ -152,6 +176,34 @@ pub trait NodeStore: Clone + Send + Sync + 'static {
         &self,
         asset_code: &str,
     ) -> Result<Option<Url>, NodeStoreError>;
+
+    /// Exports all accounts (including their balances), static routes and settlement
+    /// engines, e.g. to back up the store or to migrate it to another backend.
+    /// Note that the exported tokens are NOT encrypted.
+    async fn export_node(&self) -> Result<NodeExport, NodeStoreError>;
+
+    /// Imports the output of `export_node`, keeping the ids of the accounts.
+    /// Fails if the store already contains accounts.
+    async fn import_node(&self, export: NodeExport) -> Result<(), NodeStoreError>;
+
+    /// Gets the generation of the STREAM server secret, which is the first one if the
+    /// secret was never rotated
+    async fn get_secret_generation(&self) -> Result<SecretGeneration, NodeStoreError>;
+
+    /// Sets the generation of the STREAM server secret
+    async fn set_secret_generation(
+        &self,
+        generation: SecretGeneration,
+    ) -> Result<(), NodeStoreError>;
+
+    /// Claims the leadership of the nodes sharing the store for the node with the given id,
+    /// or renews it if the node already holds it, until `lease` from now. Returns false if
+    /// another node holds it and its lease has not expired.
+    async fn claim_leadership(
+        &self,
+        node_id: &str,
+        lease: Duration,
+    ) -> Result<bool, NodeStoreError>;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -193,10 +245,14 @@ pub struct AccountSettings {
     /// would pre-fund with the user)
     #[serde(default, deserialize_with = "optional_number_or_string")]
     pub settle_to: Option<u64>,
+    /// The maximum time in seconds for which a positive balance under the settlement
+    /// threshold is left unsettled. Overrides the node's `settle_every` for this account.
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub settle_every: Option<u32>,
 }
 
-/// EncryptedAccountSettings is created by encrypting the incoming and outgoing
-/// HTTP and BTP tokens of an AccountSettings object. The rest of the fields
+/// EncryptedAccountSettings is created by hashing the incoming and encrypting the
+/// outgoing HTTP and BTP tokens of an AccountSettings object. The rest of the fields
 /// remain the same. It is intended to be consumed by the internal store
 /// implementation which operates only on encrypted data.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -213,6 +269,9 @@ pub struct EncryptedAccountSettings {
     #[serde(default, deserialize_with = "optional_number_or_string")]
     /// The amount which the balance service will attempt to settle down to
     pub settle_to: Option<u64>,
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    /// The maximum time in seconds for which a positive balance is left unsettled
+    pub settle_every: Option<u32>,
 }
 
 /// The Account type for the RedisStore.
@@ -231,6 +290,10 @@ pub struct AccountDetails {
     #[serde(default = "u64::max_value", deserialize_with = "number_or_string")]
     /// The max amount per packet which can be routed for this account
     pub max_packet_amount: u64,
+    /// The min amount per packet which is forwarded for this account. Smaller packets,
+    /// except those without an amount, are rejected.
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub min_packet_amount: Option<u64>,
     /// The minimum balance this account can have (consider this as a credit/trust limit)
     #[serde(default, deserialize_with = "optional_number_or_string")]
     pub min_balance: Option<i64>,
@@ -266,8 +329,18 @@ pub struct AccountDetails {
     /// The amount which the balance service will attempt to settle down to
     #[serde(default, deserialize_with = "optional_number_or_string")]
     pub settle_to: Option<i64>,
+    /// The maximum time in seconds for which a positive balance under the settlement
+    /// threshold is left unsettled, which batches many small payments into fewer
+    /// settlements. Overrides the node's `settle_every` for this account.
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub settle_every: Option<u32>,
     /// The routing relation of the account
     pub routing_relation: Option<String>,
+    /// How much the routes through the account are preferred over equally good routes
+    /// through other accounts with the same routing relation, e.g. to choose between
+    /// parents. Higher is preferred. Defaults to 0.
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub routing_preference: Option<u32>,
     /// The round trip time of the account (should be set depending on how
     /// well the network connectivity of the account and the node is)
     #[serde(default, deserialize_with = "optional_number_or_string")]
@@ -275,6 +348,13 @@ pub struct AccountDetails {
     /// The maximum amount the account can send per minute
     #[serde(default, deserialize_with = "optional_number_or_string")]
     pub amount_per_minute_limit: Option<u64>,
+    /// The maximum amount of a payment the account can send via the API
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub max_payment_amount: Option<u64>,
+    /// The maximum amount the account can send per day (UTC), counting only the
+    /// packets which were fulfilled
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub amount_per_day_limit: Option<u64>,
     /// The limit of packets the account can send per minute
     #[serde(default, deserialize_with = "optional_number_or_string")]
     pub packets_per_minute_limit: Option<u32>,
@@ -284,11 +364,86 @@ pub struct AccountDetails {
     pub settlement_engine_url: Option<String>,
 }
 
+/// An account and its balance, as exported by `NodeStore::export_node`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedAccount {
+    /// The account's id, which is kept when importing it
+    pub id: Uuid,
+    /// The account's details, including its tokens. The incoming tokens are
+    /// exported as their hashes, which are kept as they are when importing.
+    pub details: AccountDetails,
+    /// The account's balance
+    #[serde(default)]
+    pub balance: i64,
+    /// The amount which was prepaid by the account
+    #[serde(default)]
+    pub prepaid_amount: i64,
+}
+
+/// A portable representation of the contents of a store, used to back up a store
+/// or to migrate to another store backend.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NodeExport {
+    /// The node's ILP address, if it was assigned by a parent
+    #[serde(default)]
+    pub ilp_address: Option<Address>,
+    /// All accounts with their balances
+    #[serde(default)]
+    pub accounts: Vec<ExportedAccount>,
+    /// Map of ILP Address prefix -> account id
+    #[serde(default)]
+    pub static_routes: HashMap<String, Uuid>,
+    /// The account which is used if no other route matches
+    #[serde(default)]
+    pub default_route: Option<Uuid>,
+    /// Map of asset code -> settlement engine URL
+    #[serde(default)]
+    pub settlement_engines: HashMap<String, Url>,
+}
+
+/// Filters applied when listing accounts. Accounts have to match all of the filters which are set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountFilter {
+    /// Only accounts with this asset code
+    pub asset_code: Option<String>,
+    /// Only accounts with this routing relation
+    pub routing_relation: Option<RoutingRelation>,
+    /// Only accounts whose ILP address starts with this prefix
+    pub ilp_address_prefix: Option<String>,
+}
+
+impl AccountFilter {
+    /// Returns whether the account matches all of the filters which are set
+    pub fn matches<A: CcpRoutingAccount>(&self, account: &A) -> bool {
+        self.asset_code
+            .as_ref()
+            .map_or(true, |asset_code| account.asset_code() == asset_code)
+            && self
+                .routing_relation
+                .map_or(true, |relation| account.routing_relation() == relation)
+            && self.ilp_address_prefix.as_ref().map_or(true, |prefix| {
+                let address: &[u8] = account.ilp_address().as_ref();
+                address.starts_with(prefix.as_bytes())
+            })
+    }
+}
+
+/// A page of accounts returned by `NodeStore::list_accounts`
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountPage<A> {
+    /// The accounts of this page, ordered by id
+    pub accounts: Vec<A>,
+    /// Cursor to pass as `after` to get the next page, or `None` if this is the last page
+    pub next: Option<Uuid>,
+}
+
 pub struct NodeApi<S, I, O, B, A: Account> {
     store: S,
     /// The admin's API token, used to make admin-only changes
     // TODO: Make this a SecretString
     admin_api_token: String,
+    /// API keys which authorize a subset of the admin routes
+    api_keys: Vec<ApiKey>,
     default_spsp_account: Option<Username>,
     incoming_handler: I,
     // The outgoing service is included so that the API can send outgoing
@@ -299,7 +454,17 @@ pub struct NodeApi<S, I, O, B, A: Account> {
     btp: BtpOutgoingService<B, A>,
     /// Server secret used to instantiate SPSP/Stream connections
     server_secret: Bytes,
+    /// Generates the SPSP/Stream connections. Defaults to a generator which uses the
+    /// `server_secret` and is not shared with the STREAM receiver.
+    connection_generator: Option<ConnectionGenerator>,
     node_version: Option<String>,
+    /// Counts the open peer connections for the readiness endpoint. Defaults to the
+    /// connections of the BTP service.
+    connection_count: Option<ConnectionCount>,
+    /// Publishes the events streamed by the WebSocket endpoints
+    events: Option<NodeEvents>,
+    /// Publishes the changes of the routing table streamed by `/routes/watch`
+    route_changes: Option<RouteChanges>,
 }
 
 impl<S, I, O, B, A> NodeApi<S, I, O, B, A>
@@ -309,8 +474,10 @@ where
         + AddressStore
         + HttpStore<Account = A>
         + BalanceStore
+        + PacketHistoryStore
         + SettlementStore<Account = A>
         + StreamNotificationsStore<Account = A>
+        + IncomingPaymentsStore
         + RouterStore
         + ExchangeRateStore,
     I: IncomingService<A> + Clone + Send + Sync + 'static,
@@ -321,6 +488,7 @@ where
         + Account
         + HttpAccount
         + SettlementAccount
+        + VolumeLimitAccount
         + Serialize
         + Send
         + Sync
@@ -337,12 +505,17 @@ where
         NodeApi {
             store,
             admin_api_token,
+            api_keys: Vec::new(),
             default_spsp_account: None,
             incoming_handler,
             outgoing_handler,
             btp,
             server_secret,
+            connection_generator: None,
             node_version: None,
+            connection_count: None,
+            events: None,
+            route_changes: None,
         }
     }
 
@@ -354,28 +527,137 @@ where
         self
     }
 
+    /// Sets the API keys which, next to the admin API token, authorize the requests
+    /// to the routes allowed by their scopes
+    pub fn api_keys(&mut self, api_keys: Vec<ApiKey>) -> &mut Self {
+        self.api_keys = api_keys;
+        self
+    }
+
+    /// Sets the connection generator shared with the STREAM receiver, whose server secret
+    /// is derived from the `server_secret` and rotated with `POST /secrets/rotate`
+    pub fn connection_generator(&mut self, connection_generator: ConnectionGenerator) -> &mut Self {
+        self.connection_generator = Some(connection_generator);
+        self
+    }
+
     /// Sets the node version
     pub fn node_version(&mut self, version: String) -> &mut Self {
         self.node_version = Some(version);
         self
     }
 
-    /// Returns a Warp Filter which exposes the accounts and admin APIs
+    /// Sets how the open peer connections are counted by the readiness endpoint,
+    /// e.g. if there are BTP services other than the one passed to the API
+    pub fn connection_count(&mut self, connection_count: ConnectionCount) -> &mut Self {
+        self.connection_count = Some(connection_count);
+        self
+    }
+
+    /// Sets the sender whose events are streamed to the subscribers of `/events` and
+    /// `/accounts/:username/events`. Without it, no events are sent.
+    pub fn events(&mut self, events: NodeEvents) -> &mut Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Sets the sender whose changes of the routing table are streamed to the subscribers
+    /// of `/routes/watch`, which is usually the one of the `CcpRouteManager`. Without it,
+    /// no changes are sent.
+    pub fn route_changes(&mut self, route_changes: RouteChanges) -> &mut Self {
+        self.route_changes = Some(route_changes);
+        self
+    }
+
+    /// Creates the accounts whose usernames are not taken yet and connects them to their
+    /// BTP servers, parents and settlement engines, like the accounts created with
+    /// `POST /accounts`. Returns the number of created accounts.
+    pub async fn bootstrap_accounts(
+        &self,
+        accounts: Vec<AccountDetails>,
+    ) -> Result<usize, warp::Rejection> {
+        let mut created = 0;
+        for details in accounts {
+            match self
+                .store
+                .get_account_id_from_username(&details.username)
+                .await
+            {
+                Ok(_) => continue,
+                Err(AccountStoreError::AccountNotFound(_)) => {}
+                Err(err) => return Err(err.into()),
+            }
+            let account = self.store.insert_account(details).await?;
+            routes::connect_to_external_services(
+                self.outgoing_handler.clone(),
+                account,
+                self.store.clone(),
+                self.btp.clone(),
+            )
+            .await?;
+            created += 1;
+        }
+        Ok(created)
+    }
+
+    /// Returns a Warp Filter which exposes the accounts, peering and admin APIs, the event
+    /// streams, the secret rotation, the health and readiness endpoints and the OpenAPI
+    /// description of the API
     pub fn into_warp_filter(self) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
+        let connection_count = self.connection_count.unwrap_or_else(|| {
+            let btp = self.btp.clone();
+            Arc::new(move || btp.connection_count())
+        });
+        let events = self
+            .events
+            .unwrap_or_else(|| tokio::sync::broadcast::channel(1).0);
+        let route_changes = self
+            .route_changes
+            .unwrap_or_else(|| tokio::sync::broadcast::channel(1).0);
+        let connection_generator = self
+            .connection_generator
+            .unwrap_or_else(|| ConnectionGenerator::new(self.server_secret.clone()));
+        let api_auth = ApiAuth::new(self.admin_api_token, self.api_keys);
         routes::accounts_api(
-            self.server_secret,
-            self.admin_api_token.clone(),
+            connection_generator.clone(),
+            api_auth.clone(),
             self.default_spsp_account,
             self.incoming_handler,
+            self.outgoing_handler.clone(),
+            self.btp.clone(),
+            self.store.clone(),
+        )
+        .or(routes::peering_api(
+            api_auth.clone(),
+            self.outgoing_handler.clone(),
+            self.btp.clone(),
+            self.store.clone(),
+        ))
+        .or(routes::children_api(
+            api_auth.clone(),
             self.outgoing_handler,
             self.btp,
             self.store.clone(),
-        )
+        ))
+        .or(routes::events_api(
+            api_auth.clone(),
+            self.store.clone(),
+            events,
+            route_changes,
+        ))
+        .or(routes::secrets_api(
+            api_auth.clone(),
+            self.server_secret,
+            connection_generator,
+            self.store.clone(),
+        ))
         .or(routes::node_settings_api(
-            self.admin_api_token,
+            api_auth,
             self.node_version,
-            self.store,
+            self.store.clone(),
         ))
+        .or(routes::health_api(self.store, connection_count))
+        .or(routes::openapi_api())
         .boxed()
     }
 
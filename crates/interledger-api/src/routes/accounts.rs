@@ -1,4 +1,8 @@
-use crate::{number_or_string, AccountDetails, AccountSettings, NodeStore};
+use super::auth::{ApiAuth, ApiScope};
+use crate::{
+    number_or_string, optional_number_or_string, AccountDetails, AccountFilter, AccountSettings,
+    NodeStore,
+};
 use bytes::Bytes;
 use futures::{Future, FutureExt, StreamExt, TryFutureExt};
 use interledger_btp::{connect_to_service_account, BtpAccount, BtpOutgoingService};
@@ -7,21 +11,35 @@ use interledger_errors::*;
 use interledger_http::{deserialize_json, HttpAccount, HttpStore};
 use interledger_ildcp::IldcpRequest;
 use interledger_ildcp::IldcpResponse;
+use interledger_packet::Address;
 use interledger_rates::ExchangeRateStore;
 use interledger_router::RouterStore;
 use interledger_service::{
-    Account, AccountStore, AddressStore, IncomingService, OutgoingRequest, OutgoingService,
-    Username,
+    runtime, Account, AccountStore, AddressStore, IncomingService, OutgoingRequest,
+    OutgoingService, Username,
+};
+use interledger_service_util::{
+    BalanceStore, PacketDirection, PacketHistoryFilter, PacketHistoryStore, PacketOutcome,
+    PacketStatistics, ReconciliationReport, VolumeLimitAccount,
+};
+use interledger_settlement::core::{
+    scale::to_base_unit, types::SettlementAccount, SettlementClient,
+};
+use interledger_spsp::{pay, stream_pay, SpspResponder};
+use interledger_stream::{
+    send_money, stream_money, ConnectionGenerator, IncomingPaymentsStore, PaymentNotification,
+    StreamDelivery, StreamHandle, StreamNotificationsStore,
 };
-use interledger_service_util::BalanceStore;
-use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
-use interledger_spsp::{pay, SpspResponder};
-use interledger_stream::{PaymentNotification, StreamNotificationsStore};
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
 use tracing::{debug, error, trace};
 use uuid::Uuid;
 use warp::{self, reply::Json, Filter, Rejection};
@@ -72,9 +90,18 @@ const fn get_default_max_slippage() -> f64 {
     0.015
 }
 
+/// Either the `receiver`, or the `destination_account` and `shared_secret` must be given
 #[derive(Deserialize, Debug)]
 struct SpspPayRequest {
-    receiver: String,
+    /// Payment pointer or SPSP URL of the receiver
+    #[serde(default)]
+    receiver: Option<String>,
+    /// ILP address of a STREAM receiver whose details were already queried
+    #[serde(default)]
+    destination_account: Option<String>,
+    /// Base64-encoded STREAM shared secret for the `destination_account`
+    #[serde(default)]
+    shared_secret: Option<String>,
     #[serde(deserialize_with = "number_or_string")]
     source_amount: u64,
     #[serde(
@@ -84,9 +111,200 @@ struct SpspPayRequest {
     slippage: f64,
 }
 
+/// Either the `receiver`, or the `destination_account` and `shared_secret` must be given
+#[derive(Deserialize, Debug)]
+struct StreamPayRequest {
+    /// Payment pointer or SPSP URL of the receiver
+    #[serde(default)]
+    receiver: Option<String>,
+    /// ILP address of a STREAM receiver whose details were already queried
+    #[serde(default)]
+    destination_account: Option<String>,
+    /// Base64-encoded STREAM shared secret for the `destination_account`
+    #[serde(default)]
+    shared_secret: Option<String>,
+    /// Amount sent per second, in the account's units
+    #[serde(deserialize_with = "number_or_string")]
+    rate_per_second: u64,
+    /// Amount after which the payment stops on its own
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    max_amount: Option<u64>,
+    #[serde(
+        deserialize_with = "number_or_string",
+        default = "get_default_max_slippage"
+    )]
+    slippage: f64,
+}
+
+/// Payment sent continuously by the node until it is stopped through the API
+struct StreamingPayment {
+    account_id: Uuid,
+    rate_per_second: u64,
+    max_amount: Option<u64>,
+    handle: StreamHandle,
+    /// Set once the payment finished, with the error if it failed
+    outcome: watch::Receiver<Option<Result<(), String>>>,
+}
+
+impl StreamingPayment {
+    fn to_json(&self, id: Uuid) -> serde_json::Value {
+        let (status, error) = match &*self.outcome.borrow() {
+            None if self.handle.is_stopped() => ("stopping", None),
+            None => ("streaming", None),
+            Some(Ok(())) => ("finished", None),
+            Some(Err(err)) => ("failed", Some(err.clone())),
+        };
+        json!({
+            "id": id,
+            "rate_per_second": self.rate_per_second,
+            "max_amount": self.max_amount,
+            "status": status,
+            "error": error,
+            "receipt": self.handle.receipt(),
+        })
+    }
+}
+
+type StreamingPayments = Arc<Mutex<HashMap<Uuid, StreamingPayment>>>;
+
+/// Number of accounts returned per page if the request does not specify a limit
+const DEFAULT_ACCOUNTS_LIMIT: usize = 100;
+/// Maximum number of accounts returned per page
+const MAX_ACCOUNTS_LIMIT: usize = 1000;
+/// Response header containing the cursor of the next page of accounts
+const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+struct AccountsQuery {
+    /// Cursor returned in the `x-next-cursor` header of the previous page
+    after: Option<Uuid>,
+    limit: Option<usize>,
+    asset_code: Option<String>,
+    routing_relation: Option<String>,
+    ilp_address_prefix: Option<String>,
+}
+
+/// Number of balance changes returned if the request does not specify a limit
+const DEFAULT_BALANCE_CHANGES_LIMIT: usize = 100;
+/// Maximum number of balance changes returned by a single request
+const MAX_BALANCE_CHANGES_LIMIT: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+struct BalanceChangesQuery {
+    /// Position of the first returned entry in the account's balance change log
+    from: Option<u64>,
+    limit: Option<usize>,
+}
+
+/// Number of payment history records returned per page if the request does not specify a limit
+const DEFAULT_PAYMENTS_LIMIT: usize = 100;
+/// Maximum number of payment history records returned per page
+const MAX_PAYMENTS_LIMIT: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+struct PaymentsQuery {
+    /// Cursor returned in the `x-next-cursor` header of the previous page
+    before: Option<u64>,
+    limit: Option<usize>,
+    /// Only return the records at or after this time, in milliseconds since the UNIX epoch
+    since: Option<u64>,
+    /// Only return the records before this time, in milliseconds since the UNIX epoch
+    until: Option<u64>,
+    direction: Option<String>,
+    outcome: Option<String>,
+}
+
+/// Period covered by the statistics if the request does not specify one, in milliseconds
+const DEFAULT_STATISTICS_PERIOD: u64 = 24 * 3600 * 1000;
+/// Length of the statistics' intervals if the request does not specify one, in milliseconds
+const DEFAULT_STATISTICS_INTERVAL: u64 = 3600 * 1000;
+/// Maximum number of intervals of the statistics
+const MAX_STATISTICS_BUCKETS: u64 = 1000;
+/// Number of top destinations returned if the request does not specify one
+const DEFAULT_TOP_DESTINATIONS: usize = 10;
+/// Maximum number of top destinations returned
+const MAX_TOP_DESTINATIONS: usize = 100;
+
+#[derive(Deserialize, Debug)]
+struct StatisticsQuery {
+    /// Start of the period, in milliseconds since the UNIX epoch. Defaults to a day before `until`
+    since: Option<u64>,
+    /// End of the period, in milliseconds since the UNIX epoch. Defaults to now
+    until: Option<u64>,
+    /// Length of the intervals, in milliseconds
+    interval: Option<u64>,
+    /// Number of top destinations
+    top: Option<usize>,
+}
+
+impl StatisticsQuery {
+    /// Returns the period and interval of the statistics, or an error if there would be
+    /// no intervals or too many of them
+    fn window(&self) -> Result<(u64, u64, u64), Rejection> {
+        let until = self.until.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_millis() as u64)
+                .unwrap_or_default()
+        });
+        let since = self
+            .since
+            .unwrap_or_else(|| until.saturating_sub(DEFAULT_STATISTICS_PERIOD));
+        let interval = self.interval.unwrap_or(DEFAULT_STATISTICS_INTERVAL);
+        if since >= until || interval == 0 {
+            return Err(Rejection::from(ApiError::bad_request().detail(
+                "since must be before until and interval must be positive",
+            )));
+        }
+        if (until - since - 1) / interval >= MAX_STATISTICS_BUCKETS {
+            return Err(Rejection::from(ApiError::bad_request().detail(format!(
+                "the period can be split in at most {} intervals",
+                MAX_STATISTICS_BUCKETS
+            ))));
+        }
+        Ok((since, until, interval))
+    }
+}
+
+/// Aggregates the records of the account's packet history within the period,
+/// loading them one page at a time
+async fn packet_statistics<S: PacketHistoryStore>(
+    store: &S,
+    account_id: Uuid,
+    (since, until, interval): (u64, u64, u64),
+    top: usize,
+) -> Result<PacketStatistics, Rejection> {
+    let mut filter = PacketHistoryFilter {
+        since: Some(since),
+        until: Some(until),
+        ..Default::default()
+    };
+    let mut records = Vec::new();
+    loop {
+        let page = store
+            .get_packet_history(account_id, &filter, MAX_PAYMENTS_LIMIT)
+            .await?;
+        let full = page.len() == MAX_PAYMENTS_LIMIT;
+        filter.before = page.last().map(|record| record.id);
+        records.extend(page);
+        if !full {
+            break;
+        }
+    }
+    Ok(PacketStatistics::new(
+        account_id, since, until, interval, &records, top,
+    ))
+}
+
+#[derive(Deserialize, Debug)]
+struct ReconciliationQuery {
+    /// The largest absolute drift (in the account's asset scale) which is not flagged
+    tolerance: Option<u64>,
+}
+
 pub fn accounts_api<I, O, S, A, B>(
-    server_secret: Bytes,
-    admin_api_token: String,
+    connection_generator: ConnectionGenerator,
+    api_auth: ApiAuth,
     default_spsp_account: Option<Username>,
     incoming_handler: I,
     outgoing_handler: O,
@@ -102,7 +320,9 @@ where
         + AddressStore
         + HttpStore<Account = A>
         + BalanceStore
+        + PacketHistoryStore
         + StreamNotificationsStore<Account = A>
+        + IncomingPaymentsStore
         + ExchangeRateStore
         + RouterStore,
     A: BtpAccount
@@ -110,6 +330,7 @@ where
         + SettlementAccount
         + Account
         + HttpAccount
+        + VolumeLimitAccount
         + Serialize
         + Send
         + Sync
@@ -118,25 +339,13 @@ where
     // TODO can we make any of the Filters const or put them in once_cell?
     let with_store = warp::any().map(move || store.clone());
     let with_incoming_handler = warp::any().map(move || incoming_handler.clone());
+    let streaming_payments = StreamingPayments::default();
+    let with_streaming_payments = warp::any().map(move || streaming_payments.clone());
 
     // Helper filters
-    let admin_auth_header = format!("Bearer {}", admin_api_token);
-    let admin_auth_header_clone = admin_auth_header.clone();
-    let with_admin_auth_header = warp::any().map(move || admin_auth_header.clone());
-    let admin_only = warp::header::<SecretString>("authorization")
-        .and_then(move |authorization: SecretString| {
-            let admin_auth_header = admin_auth_header_clone.clone();
-            async move {
-                if authorization.expose_secret() == &admin_auth_header {
-                    Ok::<(), Rejection>(())
-                } else {
-                    Err(Rejection::from(ApiError::unauthorized()))
-                }
-            }
-        })
-        // This call makes it so we do not pass on a () value on
-        // success to the next filter, it just gets rid of it
-        .untuple_one();
+    let read_only = api_auth.require(ApiScope::ReadOnly);
+    let metrics_only = api_auth.require(ApiScope::Metrics);
+    let accounts_write_only = api_auth.require(ApiScope::AccountsWrite);
 
     // Converts an account username to an account id or errors out
     let account_username_to_id = warp::path::param::<Username>()
@@ -170,46 +379,61 @@ where
         }
     };
 
-    // Checks if the account is an admin or if they have provided a valid password
-    let admin_or_authorized_user_only = warp::path::param::<Username>()
+    // Checks if the request is made by the admin or with an API key which has the scope,
+    // or if the account has provided a valid password
+    let api_auth_clone = api_auth.clone();
+    let with_store_clone = with_store.clone();
+    let admin_or_authorized_user_only = move |scope: ApiScope| {
+        let api_auth = api_auth_clone.clone();
+        warp::path::param::<Username>()
+            .and(warp::header::<SecretString>("authorization"))
+            .and(with_store_clone.clone())
+            .and_then(
+                move |path_username: Username, auth_string: SecretString, store: S| {
+                    let authorized = api_auth.authorize(auth_string.expose_secret(), scope);
+                    async move {
+                        // If it's an admin or an API key, there's no need for more checks
+                        if authorized {
+                            let account_id =
+                                store.get_account_id_from_username(&path_username).await?;
+                            return Ok(account_id);
+                        }
+                        let account = is_authorized_user(store, path_username, auth_string).await?;
+                        Ok::<Uuid, Rejection>(account.id())
+                    }
+                },
+            )
+    };
+
+    // Checks if the account has provided a valid password, or if the request is made with
+    // an API key which may send payments
+    let payments_auth = api_auth;
+    let authorized_user_only = warp::path::param::<Username>()
         .and(warp::header::<SecretString>("authorization"))
         .and(with_store.clone())
-        .and(with_admin_auth_header)
         .and_then(
-            move |path_username: Username,
-                  auth_string: SecretString,
-                  store: S,
-                  admin_auth_header: String| {
+            move |path_username: Username, auth_string: SecretString, store: S| {
+                let authorized =
+                    payments_auth.authorize(auth_string.expose_secret(), ApiScope::PaymentsSend);
                 async move {
-                    // If it's an admin, there's no need for more checks
-                    if auth_string.expose_secret() == &admin_auth_header {
-                        let account_id = store.get_account_id_from_username(&path_username).await?;
-                        return Ok(account_id);
+                    if authorized {
+                        let id = store.get_account_id_from_username(&path_username).await?;
+                        let mut accounts = store.get_accounts(vec![id]).await?;
+                        return Ok(accounts.pop().unwrap());
                     }
                     let account = is_authorized_user(store, path_username, auth_string).await?;
-                    Ok::<Uuid, Rejection>(account.id())
+                    Ok::<A, Rejection>(account)
                 }
             },
         );
 
-    // Checks if the account has provided a valid password (same as admin-or-auth call, minus one call, can we refactor them together?)
-    let authorized_user_only = warp::path::param::<Username>()
-        .and(warp::header::<SecretString>("authorization"))
-        .and(with_store.clone())
-        .and_then(
-            move |path_username: Username, auth_string: SecretString, store: S| async move {
-                let account = is_authorized_user(store, path_username, auth_string).await?;
-                Ok::<A, Rejection>(account)
-            },
-        );
-
     // POST /accounts
     let btp_clone = btp.clone();
     let outgoing_handler_clone = outgoing_handler.clone();
     let post_accounts = warp::post()
         .and(warp::path("accounts"))
         .and(warp::path::end())
-        .and(admin_only.clone())
+        .and(accounts_write_only.clone())
         .and(deserialize_json()) // Why does warp::body::json not work?
         .and(with_store.clone())
         .and_then(move |account_details: AccountDetails, store: S| {
@@ -224,15 +448,93 @@ where
             }
         });
 
+    // POST /accounts/batch
+    // Body: A JSON array of account details, or a CSV file with a header row naming the fields
+    let btp_clone = btp.clone();
+    let outgoing_handler_clone = outgoing_handler.clone();
+    let post_accounts_batch = warp::post()
+        .and(warp::path("accounts"))
+        .and(warp::path("batch"))
+        .and(warp::path::end())
+        .and(accounts_write_only.clone())
+        .and(warp::header::<String>("content-type"))
+        .and(warp::body::bytes())
+        .and(with_store.clone())
+        .and_then(move |content_type: String, body: Bytes, store: S| {
+            let handler = outgoing_handler_clone.clone();
+            let btp = btp_clone.clone();
+            async move {
+                let batch = parse_account_batch(&content_type, &body)?;
+                let accounts = store.insert_accounts(batch).await?;
+                debug!("Inserted a batch of {} accounts", accounts.len());
+
+                // The accounts were already inserted, so the accounts which cannot be
+                // connected are only reported, like when they are connected on startup
+                for account in accounts.iter() {
+                    if let Err(err) = connect_to_external_services(
+                        handler.clone(),
+                        account.clone(),
+                        store.clone(),
+                        btp.clone(),
+                    )
+                    .await
+                    {
+                        error!(
+                            "Error connecting inserted account {} to external services: {:?}",
+                            account.username(),
+                            err
+                        );
+                    }
+                }
+                Ok::<Json, Rejection>(warp::reply::json(&accounts))
+            }
+        });
+
     // GET /accounts
     let get_accounts = warp::get()
         .and(warp::path("accounts"))
         .and(warp::path::end())
-        .and(admin_only.clone())
+        .and(read_only.clone())
+        .and(warp::query::<AccountsQuery>())
         .and(with_store.clone())
-        .and_then(|store: S| async move {
-            let accounts = store.get_all_accounts().await?;
-            Ok::<Json, Rejection>(warp::reply::json(&accounts))
+        .and_then(|query: AccountsQuery, store: S| async move {
+            // Without any parameters all accounts are returned at once, as before
+            // pagination was supported
+            if query == AccountsQuery::default() {
+                let accounts = store.get_all_accounts().await?;
+                return Ok::<Box<dyn warp::Reply>, Rejection>(Box::new(warp::reply::json(
+                    &accounts,
+                )));
+            }
+
+            let routing_relation = match query.routing_relation {
+                Some(ref relation) => Some(RoutingRelation::from_str(relation).map_err(|_| {
+                    Rejection::from(
+                        ApiError::bad_request()
+                            .detail(format!("invalid routing relation: {}", relation)),
+                    )
+                })?),
+                None => None,
+            };
+            let filter = AccountFilter {
+                asset_code: query.asset_code,
+                routing_relation,
+                ilp_address_prefix: query.ilp_address_prefix,
+            };
+            let limit = query
+                .limit
+                .unwrap_or(DEFAULT_ACCOUNTS_LIMIT)
+                .min(MAX_ACCOUNTS_LIMIT);
+            let page = store.list_accounts(query.after, limit, filter).await?;
+            let reply = warp::reply::json(&page.accounts);
+            Ok(match page.next {
+                Some(next) => Box::new(warp::reply::with_header(
+                    reply,
+                    NEXT_CURSOR_HEADER,
+                    next.to_string(),
+                )),
+                None => Box::new(reply),
+            })
         });
 
     // PUT /accounts/:username
@@ -242,7 +544,7 @@ where
         .and(warp::path("accounts"))
         .and(account_username_to_id.clone())
         .and(warp::path::end())
-        .and(admin_only.clone())
+        .and(accounts_write_only.clone())
         .and(deserialize_json()) // warp::body::json() is not able to decode this!
         .and(with_store.clone())
         .and_then(move |id: Uuid, account_details: AccountDetails, store: S| {
@@ -267,7 +569,7 @@ where
     let get_account = warp::get()
         .and(warp::path("accounts"))
         // takes the username and the authorization header and checks if it's authorized, returns the uid
-        .and(admin_or_authorized_user_only.clone())
+        .and(admin_or_authorized_user_only(ApiScope::ReadOnly))
         .and(warp::path::end())
         .and(with_store.clone())
         .and_then(|id: Uuid, store: S| async move {
@@ -280,7 +582,7 @@ where
     let get_account_balance = warp::get()
         .and(warp::path("accounts"))
         // takes the username and the authorization header and checks if it's authorized, returns the uid
-        .and(admin_or_authorized_user_only.clone())
+        .and(admin_or_authorized_user_only(ApiScope::Metrics))
         .and(warp::path("balance"))
         .and(warp::path::end())
         .and(with_store.clone())
@@ -296,19 +598,175 @@ where
                 let asset_code = account.asset_code().to_owned();
                 Ok::<Json, Rejection>(warp::reply::json(&json!({
                     // normalize to the base unit
-                    "balance": balance as f64 / 10_u64.pow(asset_scale.into()) as f64,
+                    "balance": to_base_unit(balance, asset_scale),
                     "asset_code": asset_code,
                 })))
             }
         });
 
+    // GET /accounts/:username/balance/changes
+    let get_account_balance_changes = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only(ApiScope::ReadOnly))
+        .and(warp::path("balance"))
+        .and(warp::path("changes"))
+        .and(warp::path::end())
+        .and(warp::query::<BalanceChangesQuery>())
+        .and(with_store.clone())
+        .and_then(
+            |id: Uuid, query: BalanceChangesQuery, store: S| async move {
+                let limit = query
+                    .limit
+                    .unwrap_or(DEFAULT_BALANCE_CHANGES_LIMIT)
+                    .min(MAX_BALANCE_CHANGES_LIMIT);
+                let changes = store
+                    .get_balance_changes(id, query.from.unwrap_or(0), limit)
+                    .await?;
+                Ok::<Json, Rejection>(warp::reply::json(&changes))
+            },
+        );
+
+    // GET /accounts/:username/payments
+    // Returns the account's packet history, newest first
+    let get_account_payments = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only(ApiScope::ReadOnly))
+        .and(warp::path("payments"))
+        .and(warp::path::end())
+        .and(warp::query::<PaymentsQuery>())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, query: PaymentsQuery, store: S| async move {
+            let limit = query
+                .limit
+                .unwrap_or(DEFAULT_PAYMENTS_LIMIT)
+                .min(MAX_PAYMENTS_LIMIT);
+            let direction = match query.direction {
+                Some(ref direction) => {
+                    Some(PacketDirection::from_str(direction).map_err(|_| {
+                        Rejection::from(
+                            ApiError::bad_request()
+                                .detail(format!("invalid direction: {}", direction)),
+                        )
+                    })?)
+                }
+                None => None,
+            };
+            let outcome = match query.outcome {
+                Some(ref outcome) => Some(PacketOutcome::from_str(outcome).map_err(|_| {
+                    Rejection::from(
+                        ApiError::bad_request().detail(format!("invalid outcome: {}", outcome)),
+                    )
+                })?),
+                None => None,
+            };
+            let filter = PacketHistoryFilter {
+                before: query.before,
+                since: query.since,
+                until: query.until,
+                direction,
+                outcome,
+            };
+            let records = store.get_packet_history(id, &filter, limit).await?;
+            // A full page may be followed by more records
+            let next = match records.last() {
+                Some(last) if records.len() == limit => Some(last.id),
+                _ => None,
+            };
+            let reply = warp::reply::json(&records);
+            Ok::<Box<dyn warp::Reply>, Rejection>(match next {
+                Some(next) => Box::new(warp::reply::with_header(
+                    reply,
+                    NEXT_CURSOR_HEADER,
+                    next.to_string(),
+                )),
+                None => Box::new(reply),
+            })
+        });
+
+    // GET /accounts/:username/statistics
+    // Returns aggregates of the account's packet history
+    let get_account_statistics = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only(ApiScope::Metrics))
+        .and(warp::path("statistics"))
+        .and(warp::path::end())
+        .and(warp::query::<StatisticsQuery>())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, query: StatisticsQuery, store: S| async move {
+            let window = query.window()?;
+            let top = query
+                .top
+                .unwrap_or(DEFAULT_TOP_DESTINATIONS)
+                .min(MAX_TOP_DESTINATIONS);
+            let statistics = packet_statistics(&store, id, window, top).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&statistics))
+        });
+
+    // GET /statistics
+    // Returns aggregates of the packet history of every account
+    let get_statistics = warp::get()
+        .and(warp::path("statistics"))
+        .and(warp::path::end())
+        .and(metrics_only.clone())
+        .and(warp::query::<StatisticsQuery>())
+        .and(with_store.clone())
+        .and_then(|query: StatisticsQuery, store: S| async move {
+            let window = query.window()?;
+            let top = query
+                .top
+                .unwrap_or(DEFAULT_TOP_DESTINATIONS)
+                .min(MAX_TOP_DESTINATIONS);
+            let accounts = store.get_all_accounts().await?;
+            let mut statistics = Vec::with_capacity(accounts.len());
+            for account in accounts {
+                statistics.push(packet_statistics(&store, account.id(), window, top).await?);
+            }
+            Ok::<Json, Rejection>(warp::reply::json(&statistics))
+        });
+
+    // GET /accounts/:username/balance/reconciliation
+    let get_account_reconciliation = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only(ApiScope::Metrics))
+        .and(warp::path("balance"))
+        .and(warp::path("reconciliation"))
+        .and(warp::path::end())
+        .and(warp::query::<ReconciliationQuery>())
+        .and(with_store.clone())
+        .and_then(
+            |id: Uuid, query: ReconciliationQuery, store: S| async move {
+                let totals = store.get_balance_totals(id).await?;
+                let report =
+                    ReconciliationReport::new(id, &totals, query.tolerance.unwrap_or_default());
+                Ok::<Json, Rejection>(warp::reply::json(&report))
+            },
+        );
+
+    // GET /reconciliation
+    let get_reconciliation = warp::get()
+        .and(warp::path("reconciliation"))
+        .and(warp::path::end())
+        .and(metrics_only)
+        .and(warp::query::<ReconciliationQuery>())
+        .and(with_store.clone())
+        .and_then(|query: ReconciliationQuery, store: S| async move {
+            let tolerance = query.tolerance.unwrap_or_default();
+            let accounts = store.get_all_accounts().await?;
+            let mut reports = Vec::with_capacity(accounts.len());
+            for account in accounts {
+                let totals = store.get_balance_totals(account.id()).await?;
+                reports.push(ReconciliationReport::new(account.id(), &totals, tolerance));
+            }
+            Ok::<Json, Rejection>(warp::reply::json(&reports))
+        });
+
     // DELETE /accounts/:username
     let btp_clone = btp.clone();
     let delete_account = warp::delete()
         .and(warp::path("accounts"))
         .and(account_username_to_id.clone())
         .and(warp::path::end())
-        .and(admin_only.clone())
+        .and(accounts_write_only)
         .and(with_store.clone())
         .and_then(move |id: Uuid, store: S| {
             let btp = btp_clone.clone();
@@ -316,6 +774,29 @@ where
                 let account = store.delete_account(id).await?;
                 // close the btp connection (if any)
                 btp.close_connection(&id);
+
+                // Notify the settlement engine (if any) that the account was deleted.
+                // The account is already gone, so failures are only logged.
+                let default_settlement_engine = store
+                    .get_asset_settlement_engine(account.asset_code())
+                    .await?;
+                let settlement_engine_url = account
+                    .settlement_engine_details()
+                    .map(|details| details.url)
+                    .or(default_settlement_engine);
+                if let Some(se_url) = settlement_engine_url {
+                    runtime::spawn(async move {
+                        if let Err(err) = SettlementClient::default()
+                            .delete_engine_account(id, se_url)
+                            .await
+                        {
+                            error!(
+                                "Error deleting account {} on the settlement engine: {}",
+                                id, err
+                            );
+                        }
+                    });
+                }
                 Ok::<Json, Rejection>(warp::reply::json(&account))
             }
         });
@@ -324,7 +805,7 @@ where
     let outgoing_handler_clone = outgoing_handler;
     let put_account_settings = warp::put()
         .and(warp::path("accounts"))
-        .and(admin_or_authorized_user_only.clone())
+        .and(admin_or_authorized_user_only(ApiScope::AccountsWrite))
         .and(warp::path("settings"))
         .and(warp::path::end())
         .and(deserialize_json())
@@ -356,7 +837,7 @@ where
 
     // (Websocket) /accounts/:username/payments/incoming
     let incoming_payment_notifications = warp::path("accounts")
-        .and(admin_or_authorized_user_only)
+        .and(admin_or_authorized_user_only(ApiScope::ReadOnly))
         .and(warp::path("payments"))
         .and(warp::path("incoming"))
         .and(warp::path::end())
@@ -365,14 +846,14 @@ where
         .map(|id: Uuid, ws: warp::ws::Ws, store: S| {
             ws.on_upgrade(move |ws: warp::ws::WebSocket| {
                 let (ws_tx, ws_rx) = ws.split();
-                tokio::task::spawn(notify_user(ws_tx, id, store).map(|result| result.unwrap()));
+                runtime::spawn(notify_user(ws_tx, id, store).map(|result| result.unwrap()));
                 consume_msg_drain(ws_rx)
             })
         });
 
     // (Websocket) /payments/incoming
     let all_payment_notifications = warp::path("payments")
-        .and(admin_only)
+        .and(read_only)
         .and(warp::path("incoming"))
         .and(warp::path::end())
         .and(warp::ws())
@@ -380,38 +861,112 @@ where
         .map(|ws: warp::ws::Ws, store: S| {
             ws.on_upgrade(move |ws: warp::ws::WebSocket| {
                 let (ws_tx, ws_rx) = ws.split();
-                tokio::task::spawn(notify_all_payments(ws_tx, store).map(|result| result.unwrap()));
+                runtime::spawn(notify_all_payments(ws_tx, store).map(|result| result.unwrap()));
                 consume_msg_drain(ws_rx)
             })
         });
 
+    // GET /accounts/:username/payments/incoming/unacknowledged
+    let get_unacknowledged_payments = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only(ApiScope::ReadOnly))
+        .and(warp::path("payments"))
+        .and(warp::path("incoming"))
+        .and(warp::path("unacknowledged"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, store: S| async move {
+            let payments = store.get_unacknowledged_payments(id).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&payments))
+        });
+
+    // PUT /accounts/:username/payments/incoming/:destination/acknowledgement
+    let put_payment_acknowledgement = warp::put()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only(ApiScope::AccountsWrite))
+        .and(warp::path("payments"))
+        .and(warp::path("incoming"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("acknowledgement"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, destination: String, store: S| async move {
+            let destination = Address::from_str(&destination).map_err(|_| {
+                Rejection::from(
+                    ApiError::bad_request().detail(format!("invalid address: {}", destination)),
+                )
+            })?;
+            match store.acknowledge_incoming_payment(id, &destination).await? {
+                Some(payment) => Ok::<Json, Rejection>(warp::reply::json(&payment)),
+                None => Err(Rejection::from(ApiError::not_found().detail(format!(
+                    "no payment was received on connection {}",
+                    destination
+                )))),
+            }
+        });
+
     // POST /accounts/:username/payments
     let post_payments = warp::post()
         .and(warp::path("accounts"))
-        .and(authorized_user_only)
+        .and(authorized_user_only.clone())
         .and(warp::path("payments"))
         .and(warp::path::end())
         .and(deserialize_json())
-        .and(with_incoming_handler)
+        .and(with_incoming_handler.clone())
         .and(with_store.clone())
         .and_then(
             move |account: A, pay_request: SpspPayRequest, incoming_handler: I, store: S| {
                 async move {
-                    let receipt = pay(
-                        incoming_handler,
-                        account.clone(),
-                        store,
-                        &pay_request.receiver,
-                        pay_request.source_amount,
-                        pay_request.slippage,
-                    )
-                    .map_err(|err| {
-                        let msg = format!("Error sending SPSP payment: {}", err);
-                        error!("{}", msg);
-                        // TODO give a different error message depending on what type of error it is
-                        Rejection::from(ApiError::internal_server_error().detail(msg))
-                    })
-                    .await?;
+                    // A connector cannot tell the packets of one payment from another's,
+                    // so the size of payments can only be capped where they are sent
+                    if let Some(max_payment_amount) = account.max_payment_amount() {
+                        if pay_request.source_amount > max_payment_amount {
+                            return Err(Rejection::from(ApiError::bad_request().detail(
+                                format!(
+                                    "source_amount exceeds the account's maximum payment amount of {}",
+                                    max_payment_amount
+                                ),
+                            )));
+                        }
+                    }
+                    let receipt = match (
+                        pay_request.receiver,
+                        pay_request.destination_account,
+                        pay_request.shared_secret,
+                    ) {
+                        (Some(receiver), None, None) => {
+                            pay(
+                                incoming_handler,
+                                account.clone(),
+                                store,
+                                &receiver,
+                                pay_request.source_amount,
+                                pay_request.slippage,
+                            )
+                            .map_err(payment_error)
+                            .await?
+                        }
+                        (None, Some(destination_account), Some(shared_secret)) => {
+                            let (destination_account, shared_secret) =
+                                parse_stream_destination(&destination_account, &shared_secret)?;
+                            send_money(
+                                incoming_handler,
+                                &account,
+                                store,
+                                destination_account,
+                                shared_secret,
+                                pay_request.source_amount,
+                                pay_request.slippage,
+                            )
+                            .map_err(payment_error)
+                            .await?
+                        }
+                        _ => {
+                            return Err(Rejection::from(ApiError::bad_request().detail(
+                                "either receiver, or destination_account and shared_secret must be given",
+                            )))
+                        }
+                    };
 
                     debug!("Sent SPSP payment, receipt: {:?}", receipt);
                     Ok::<Json, Rejection>(warp::reply::json(&json!(receipt)))
@@ -419,8 +974,189 @@ where
             },
         );
 
+    // POST /accounts/:username/payments/streams
+    let post_streaming_payments = warp::post()
+        .and(warp::path("accounts"))
+        .and(authorized_user_only.clone())
+        .and(warp::path("payments"))
+        .and(warp::path("streams"))
+        .and(warp::path::end())
+        .and(deserialize_json())
+        .and(with_incoming_handler)
+        .and(with_store.clone())
+        .and(with_streaming_payments.clone())
+        .and_then(
+            move |account: A,
+                  pay_request: StreamPayRequest,
+                  incoming_handler: I,
+                  store: S,
+                  streaming_payments: StreamingPayments| {
+                async move {
+                    if pay_request.rate_per_second == 0 {
+                        return Err(Rejection::from(
+                            ApiError::bad_request().detail("rate_per_second must be positive"),
+                        ));
+                    }
+                    // Without a maximum amount, a streaming payment stops at the account's
+                    // maximum payment amount
+                    let max_amount = match (pay_request.max_amount, account.max_payment_amount()) {
+                        (Some(max_amount), Some(max_payment_amount))
+                            if max_amount > max_payment_amount =>
+                        {
+                            return Err(Rejection::from(ApiError::bad_request().detail(
+                                format!(
+                                    "max_amount exceeds the account's maximum payment amount of {}",
+                                    max_payment_amount
+                                ),
+                            )));
+                        }
+                        (Some(max_amount), _) => Some(max_amount),
+                        (None, max_payment_amount) => max_payment_amount,
+                    };
+
+                    let handle = StreamHandle::new();
+                    let rate_per_second = pay_request.rate_per_second;
+                    let slippage = pay_request.slippage;
+                    let payment_handle = handle.clone();
+                    let account_id = account.id();
+                    let payment: futures::future::BoxFuture<'static, Result<StreamDelivery, String>> =
+                        match (
+                            pay_request.receiver,
+                            pay_request.destination_account,
+                            pay_request.shared_secret,
+                        ) {
+                            (Some(receiver), None, None) => async move {
+                                stream_pay(
+                                    incoming_handler,
+                                    account,
+                                    store,
+                                    &receiver,
+                                    rate_per_second,
+                                    max_amount,
+                                    slippage,
+                                    payment_handle,
+                                )
+                                .await
+                                .map_err(|err| err.to_string())
+                            }
+                            .boxed(),
+                            (None, Some(destination_account), Some(shared_secret)) => {
+                                let (destination_account, shared_secret) =
+                                    parse_stream_destination(&destination_account, &shared_secret)?;
+                                async move {
+                                    stream_money(
+                                        incoming_handler,
+                                        &account,
+                                        store,
+                                        destination_account,
+                                        shared_secret,
+                                        rate_per_second,
+                                        max_amount,
+                                        slippage,
+                                        payment_handle,
+                                    )
+                                    .await
+                                    .map_err(|err| err.to_string())
+                                }
+                                .boxed()
+                            }
+                            _ => {
+                                return Err(Rejection::from(ApiError::bad_request().detail(
+                                    "either receiver, or destination_account and shared_secret must be given",
+                                )))
+                            }
+                        };
+
+                    let id = Uuid::new_v4();
+                    let (outcome_tx, outcome) = watch::channel(None);
+                    runtime::spawn(async move {
+                        let outcome = match payment.await {
+                            Ok(receipt) => {
+                                debug!("Streaming payment {} finished, receipt: {:?}", id, receipt);
+                                Ok(())
+                            }
+                            Err(err) => {
+                                error!("Error sending streaming payment {}: {}", id, err);
+                                Err(err)
+                            }
+                        };
+                        let _ = outcome_tx.send(Some(outcome));
+                    });
+
+                    let streaming_payment = StreamingPayment {
+                        account_id,
+                        rate_per_second,
+                        max_amount,
+                        handle,
+                        outcome,
+                    };
+                    let response = streaming_payment.to_json(id);
+                    streaming_payments
+                        .lock()
+                        .unwrap()
+                        .insert(id, streaming_payment);
+                    Ok::<Json, Rejection>(warp::reply::json(&response))
+                }
+            },
+        );
+
+    // GET /accounts/:username/payments/streams/:id
+    let get_streaming_payment = warp::get()
+        .and(warp::path("accounts"))
+        .and(authorized_user_only.clone())
+        .and(warp::path("payments"))
+        .and(warp::path("streams"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(with_streaming_payments.clone())
+        .and_then(
+            move |account: A, id: Uuid, streaming_payments: StreamingPayments| async move {
+                match streaming_payments.lock().unwrap().get(&id) {
+                    Some(payment) if payment.account_id == account.id() => {
+                        Ok::<Json, Rejection>(warp::reply::json(&payment.to_json(id)))
+                    }
+                    _ => Err(Rejection::from(
+                        ApiError::not_found().detail("streaming payment not found"),
+                    )),
+                }
+            },
+        );
+
+    // DELETE /accounts/:username/payments/streams/:id
+    let delete_streaming_payment = warp::delete()
+        .and(warp::path("accounts"))
+        .and(authorized_user_only)
+        .and(warp::path("payments"))
+        .and(warp::path("streams"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(with_streaming_payments)
+        .and_then(
+            move |account: A, id: Uuid, streaming_payments: StreamingPayments| async move {
+                let payment = {
+                    let mut streaming_payments = streaming_payments.lock().unwrap();
+                    match streaming_payments.get(&id) {
+                        Some(payment) if payment.account_id == account.id() => {
+                            streaming_payments.remove(&id)
+                        }
+                        _ => None,
+                    }
+                };
+                let mut payment = payment
+                    .ok_or_else(|| ApiError::not_found().detail("streaming payment not found"))?;
+                // Wait for the packets in flight, so the response has the final receipt
+                payment.handle.stop();
+                while payment.outcome.borrow().is_none() {
+                    if payment.outcome.changed().await.is_err() {
+                        break;
+                    }
+                }
+                Ok::<Json, Rejection>(warp::reply::json(&payment.to_json(id)))
+            },
+        );
+
     // GET /accounts/:username/spsp
-    let server_secret_clone = server_secret.clone();
+    let connection_generator_clone = connection_generator.clone();
     let get_spsp = warp::get()
         .and(warp::path("accounts"))
         .and(account_username_to_id)
@@ -428,14 +1164,14 @@ where
         .and(warp::path::end())
         .and(with_store.clone())
         .and_then(move |id: Uuid, store: S| {
-            let server_secret_clone = server_secret_clone.clone();
+            let connection_generator = connection_generator_clone.clone();
             async move {
                 let accounts = store.get_accounts(vec![id]).await?;
                 // TODO return the response without instantiating an SpspResponder (use a simple fn)
                 Ok::<_, Rejection>(
-                    SpspResponder::new(
+                    SpspResponder::with_connection_generator(
                         accounts[0].ilp_address().clone(),
-                        server_secret_clone.clone(),
+                        connection_generator,
                     )
                     .generate_http_response(),
                 )
@@ -452,7 +1188,7 @@ where
         .and(with_store)
         .and_then(move |store: S| {
             let default_spsp_account = default_spsp_account.clone();
-            let server_secret_clone = server_secret.clone();
+            let connection_generator = connection_generator.clone();
             async move {
                 if let Some(ref username) = default_spsp_account {
                     let id = store.get_account_id_from_username(username).await?;
@@ -463,9 +1199,9 @@ where
                     let account = accounts.pop().unwrap();
                     // TODO return the response without instantiating an SpspResponder (use a simple fn)
                     Ok::<_, Rejection>(
-                        SpspResponder::new(
+                        SpspResponder::with_connection_generator(
                             account.ilp_address().clone(),
-                            server_secret_clone.clone(),
+                            connection_generator,
                         )
                         .generate_http_response(),
                     )
@@ -485,19 +1221,33 @@ where
         get_spsp,
         get_spsp_well_known,
         post_accounts,
+        post_accounts_batch,
         get_accounts,
         put_account,
         delete_account,
         get_account,
         get_account_balance,
+        get_account_balance_changes,
+        get_account_payments,
+        get_account_statistics,
+        get_statistics,
+        get_account_reconciliation,
+        get_reconciliation,
         put_account_settings,
         incoming_payment_notifications,
         all_payment_notifications,
+        get_unacknowledged_payments,
+        put_payment_acknowledgement,
         post_payments,
+        post_streaming_payments,
+        get_streaming_payment,
+        delete_streaming_payment,
     )
 }
 
-async fn consume_msg_drain(mut ws_rx: futures::stream::SplitStream<warp::ws::WebSocket>) {
+pub(super) async fn consume_msg_drain(
+    mut ws_rx: futures::stream::SplitStream<warp::ws::WebSocket>,
+) {
     while let Some(result) = ws_rx.next().await {
         if let Err(e) = result {
             debug!("consume msg drain read error: {}", e);
@@ -536,6 +1286,94 @@ fn notify_user(
 // it only assumes control of the store's all payment notification receiver; its messages
 // are published alongside account-specific notifications and the dedicated thread
 // owns the applicable sender.
+/// Parses the body of `POST /accounts/batch`. Fails with the errors of all rows which are not
+/// valid account details.
+fn parse_account_batch(content_type: &str, body: &[u8]) -> Result<Vec<AccountDetails>, Rejection> {
+    let mime_type = content_type.split(';').next().unwrap_or_default().trim();
+    let rows: Vec<Result<serde_json::Value, NodeStoreError>> = if mime_type
+        .eq_ignore_ascii_case("application/json")
+    {
+        serde_json::from_slice::<Vec<serde_json::Value>>(body)
+            .map_err(|err| {
+                ApiError::bad_request().detail(format!("Invalid JSON array of accounts: {}", err))
+            })?
+            .into_iter()
+            .map(Ok)
+            .collect()
+    } else if mime_type.eq_ignore_ascii_case("text/csv") {
+        // Every row is converted to an object of strings, which are accepted for the
+        // numeric fields too. Empty cells are left out.
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(body);
+        let headers = reader
+            .headers()
+            .map_err(|err| {
+                ApiError::bad_request().detail(format!("Invalid CSV header row: {}", err))
+            })?
+            .clone();
+        reader
+            .records()
+            .map(|record| {
+                let record = record.map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+                let row = headers
+                    .iter()
+                    .zip(record.iter())
+                    .filter(|(_, value)| !value.is_empty())
+                    .map(|(field, value)| {
+                        (field.to_owned(), serde_json::Value::from(value.to_owned()))
+                    })
+                    .collect();
+                Ok(serde_json::Value::Object(row))
+            })
+            .collect()
+    } else {
+        return Err(Rejection::from(ApiError::bad_request().detail(
+            "Invalid content-type, expected application/json or text/csv.",
+        )));
+    };
+    if rows.is_empty() {
+        return Err(Rejection::from(
+            ApiError::bad_request().detail("no accounts were given"),
+        ));
+    }
+
+    let mut batch = Vec::with_capacity(rows.len());
+    let mut errors = Vec::new();
+    for (index, row) in rows.into_iter().enumerate() {
+        match row.and_then(|row| {
+            serde_json::from_value(row).map_err(|err| NodeStoreError::Other(Box::new(err)))
+        }) {
+            Ok(details) => batch.push(details),
+            Err(err) => errors.push((index, err)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(NodeStoreError::InvalidAccounts(errors).into());
+    }
+    Ok(batch)
+}
+
+/// Parses the details of a STREAM receiver which were already queried
+fn parse_stream_destination(
+    destination_account: &str,
+    shared_secret: &str,
+) -> Result<(Address, Vec<u8>), Rejection> {
+    let destination_account = Address::from_str(destination_account).map_err(|_| {
+        ApiError::bad_request().detail("destination_account is not a valid ILP address")
+    })?;
+    let shared_secret = base64::decode(shared_secret)
+        .map_err(|_| ApiError::bad_request().detail("shared_secret must be base64-encoded"))?;
+    Ok((destination_account, shared_secret))
+}
+
+fn payment_error(err: impl Display) -> Rejection {
+    let msg = format!("Error sending SPSP payment: {}", err);
+    error!("{}", msg);
+    // TODO give a different error message depending on what type of error it is
+    Rejection::from(ApiError::internal_server_error().detail(msg))
+}
+
 fn notify_all_payments(
     ws_tx: futures::stream::SplitSink<warp::ws::WebSocket, warp::ws::Message>,
     store: impl StreamNotificationsStore,
@@ -596,7 +1434,6 @@ where
     debug!("Got ILDCP response from parent: {:?}", info);
     let ilp_address = info.ilp_address();
 
-    debug!("ILP address is now: {}", ilp_address);
     // TODO we may want to make this trigger the CcpRouteManager to request
     let prepare = RouteControlRequest {
         mode: Mode::Sync,
@@ -606,11 +1443,26 @@ where
     }
     .to_prepare();
 
-    // Set the parent to be the default route for everything
-    // that starts with their global prefix
-    store.set_default_route(parent.id()).await?;
-    // Update our store's address
-    store.set_ilp_address(ilp_address).await?;
+    // The default route goes through the best of the parents, which the CcpRouteManager
+    // picks once it gets their routes. Our address is the one assigned by the first parent.
+    let other_parents = store
+        .list_accounts(
+            None,
+            2,
+            AccountFilter {
+                routing_relation: Some(RoutingRelation::Parent),
+                ..Default::default()
+            },
+        )
+        .await?
+        .accounts
+        .into_iter()
+        .any(|account| account.id() != parent.id());
+    if !other_parents {
+        debug!("ILP address is now: {}", ilp_address);
+        // Update our store's address
+        store.set_ilp_address(ilp_address).await?;
+    }
 
     // Get the parent's routes for us
     debug!("Asking for routes from {:?}", parent.clone());
@@ -641,7 +1493,7 @@ where
 // 2b. Perform a RouteControl Request to make them send us any new routes
 // 3. If they have a settlement engine endpoitn configured: Make a POST to the
 //    engine's account creation endpoint with the account's id
-async fn connect_to_external_services<O, A, S, B>(
+pub(crate) async fn connect_to_external_services<O, A, S, B>(
     service: O,
     account: A,
     store: S,
@@ -699,7 +1551,7 @@ where
 
             // We will pre-fund our account with 0, which will return
             // the current settle_to value
-            let (_, amount_to_settle) = store.update_balances_for_fulfill(id, 0u64).await?;
+            let (_, amount_to_settle) = store.update_balances_for_fulfill(id, 0u64, None).await?;
 
             // prefund the absolute value
             if amount_to_settle > 0 {
@@ -756,6 +1608,38 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn inserts_batches_of_accounts() {
+        let api = test_accounts_api();
+        let batch = serde_json::Value::Array(vec![DETAILS.clone().unwrap(); 2]);
+        let resp = api_call(&api, "POST", "/accounts/batch", "admin", Some(batch)).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let accounts: Vec<serde_json::Value> = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(accounts.len(), 2);
+
+        // The rows which are not valid account details are reported
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/accounts/batch")
+            .header("Authorization", "Bearer admin")
+            .header("Content-type", "text/csv")
+            .body("username,asset_code,asset_scale\nalice,XYZ,9\nbob,XYZ,\ncharlie,XYZ,nine\n")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status().as_u16(), 400);
+        let error: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let indices: Vec<_> = error["invalid-accounts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| row["index"].as_u64().unwrap())
+            .collect();
+        assert_eq!(indices, vec![1, 2]);
+
+        let resp = api_call(&api, "POST", "/accounts/batch", "wrong", DETAILS.clone()).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_can_get_all_accounts() {
         let api = test_accounts_api();
@@ -766,6 +1650,39 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn lists_accounts_page_by_page() {
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts?limit=1&asset_code=XYZ&routing_relation=peer",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            resp.headers()[NEXT_CURSOR_HEADER],
+            Uuid::nil().to_string().as_str()
+        );
+        let accounts: Vec<serde_json::Value> = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(accounts.len(), 1);
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts?routing_relation=sibling",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(&api, "GET", "/accounts?limit=1", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_or_user_can_get_account() {
         let api = test_accounts_api();
@@ -794,6 +1711,183 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn only_admin_or_user_can_get_accounts_balance_changes() {
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/balance/changes?from=10&limit=5",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/balance/changes",
+            "password",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/balance/changes",
+            "wrong",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn pages_through_payment_history() {
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/payments?limit=2",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.headers()["x-next-cursor"], "2");
+        let records: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(records.as_array().unwrap().len(), 2);
+        assert_eq!(records[0]["direction"], "incoming");
+        assert_eq!(records[0]["outcome"], "fulfilled");
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/payments?limit=2&before=2",
+            "password",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert!(resp.headers().get("x-next-cursor").is_none());
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/payments?direction=outgoing&since=1000",
+            "read_only-key",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body(), "[]");
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/payments?outcome=pending",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(&api, "GET", "/accounts/alice/payments", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn aggregates_payment_history() {
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/statistics?since=0&until=4000&interval=2000",
+            "password",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let statistics: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let buckets = statistics["buckets"].as_array().unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0]["received"], 100);
+        assert_eq!(buckets[1]["received"], 200);
+        assert_eq!(buckets[1]["fulfilled"], 2);
+        assert_eq!(buckets[1]["success_rate"], 1.0);
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/statistics?since=0&until=4000&interval=2000",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        // The period can not be split in more intervals than the maximum
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/statistics?since=0&until=4000&interval=1",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(&api, "GET", "/statistics", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_or_user_can_get_accounts_reconciliation() {
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/balance/reconciliation?tolerance=100",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/balance/reconciliation",
+            "password",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/balance/reconciliation",
+            "wrong",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_get_reconciliation() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/reconciliation?tolerance=100", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "GET", "/reconciliation", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_or_user_can_modify_accounts_settings() {
         let api = test_accounts_api();
@@ -870,4 +1964,191 @@ mod tests {
         .await;
         assert_eq!(resp.status().as_u16(), 401);
     }
+
+    #[tokio::test]
+    async fn api_keys_need_the_scope_of_the_route() {
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts",
+            "accounts_write-key",
+            DETAILS.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "POST", "/accounts", "read_only-key", DETAILS.clone()).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/accounts", "read_only-key", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/accounts/alice", "metrics-key", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/accounts/alice/balance", "metrics-key", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/reconciliation", "metrics-key", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/reconciliation", "read_only-key", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/reconciliation", "payments_send-key", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/settings",
+            "accounts_write-key",
+            DETAILS.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/settings",
+            "metrics-key",
+            DETAILS.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn payments_send_key_can_send_payment() {
+        let payment: Option<serde_json::Value> = Some(serde_json::json!({
+            "receiver": "some_receiver",
+            "source_amount" : 10,
+        }));
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments",
+            "payments_send-key",
+            payment.clone(),
+        )
+        .await;
+        // The payment itself fails, like in only_admin_or_user_can_send_payment
+        assert_eq!(resp.status().as_u16(), 500);
+
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments",
+            "admin-key",
+            payment.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn payment_needs_a_receiver_or_destination() {
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments",
+            "password",
+            Some(serde_json::json!({ "source_amount": 10 })),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments",
+            "password",
+            Some(serde_json::json!({
+                "receiver": "$example.com",
+                "destination_account": "example.receiver",
+                "shared_secret": "AAAA",
+                "source_amount": 10,
+            })),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments",
+            "password",
+            Some(serde_json::json!({
+                "destination_account": "example.receiver",
+                "shared_secret": "not base64!",
+                "source_amount": 10,
+            })),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
+    #[tokio::test]
+    async fn payment_over_max_payment_amount_is_rejected() {
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments",
+            "password",
+            Some(serde_json::json!({
+                "receiver": "$example.com",
+                "source_amount": MAX_PAYMENT_AMOUNT + 1,
+            })),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
+    #[tokio::test]
+    async fn acknowledges_incoming_payments() {
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/payments/incoming/unacknowledged",
+            "password",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let payments: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(payments[0]["destination"], "example.alice.connection");
+        assert_eq!(payments[0]["acknowledged"], false);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/payments/incoming/example.alice.connection/acknowledgement",
+            "password",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let payment: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(payment["acknowledged"], true);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/payments/incoming/example.alice.other/acknowledgement",
+            "password",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 404);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/payments/incoming/example.alice.connection/acknowledgement",
+            "read_only-key",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
 }
@@ -0,0 +1,181 @@
+use interledger_errors::ApiError;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use tracing::debug;
+use warp::{Filter, Rejection};
+
+/// The permissions an API key can be given
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ApiScope {
+    /// Everything the admin auth token can do, including changing the node settings
+    /// (but not sending payments)
+    #[serde(rename = "admin")]
+    Admin,
+    /// Reading the accounts, balances and reconciliation reports, and subscribing to
+    /// the payment and event streams. Includes `metrics`.
+    #[serde(rename = "read_only")]
+    ReadOnly,
+    /// Creating, modifying and deleting accounts
+    #[serde(rename = "accounts:write")]
+    AccountsWrite,
+    /// Sending payments from any account. Unlike the other scopes, it is not included in
+    /// `admin` and has to be granted explicitly.
+    #[serde(rename = "payments:send")]
+    PaymentsSend,
+    /// Reading the balances and reconciliation reports, and scraping the Prometheus metrics
+    #[serde(rename = "metrics")]
+    Metrics,
+}
+
+impl ApiScope {
+    /// Returns true if a key with this scope may use the routes which require `required`
+    fn grants(self, required: ApiScope) -> bool {
+        self == required
+            || (self == ApiScope::Admin && required != ApiScope::PaymentsSend)
+            || (self == ApiScope::ReadOnly && required == ApiScope::Metrics)
+    }
+}
+
+/// An API key which is sent as a Bearer token, like the admin auth token, but only
+/// authorizes the requests allowed by its scopes
+#[derive(Clone, Debug, Deserialize)]
+pub struct ApiKey {
+    /// Name of the key, to tell the keys apart in the logs
+    pub name: String,
+    pub key: SecretString,
+    pub scopes: Vec<ApiScope>,
+}
+
+/// The admin auth token and the API keys which authorize requests to the admin routes
+#[derive(Clone)]
+pub struct ApiAuth {
+    /// Maps the authorization headers to the scopes they grant. The admin auth
+    /// token is stored as a key with the `admin` scope.
+    keys: Arc<HashMap<String, (String, Vec<ApiScope>)>>,
+}
+
+impl ApiAuth {
+    pub fn new(admin_api_token: String, api_keys: Vec<ApiKey>) -> Self {
+        let mut keys: HashMap<_, _> = api_keys
+            .into_iter()
+            .map(|api_key| {
+                (
+                    format!("Bearer {}", api_key.key.expose_secret()),
+                    (api_key.name, api_key.scopes),
+                )
+            })
+            .collect();
+        keys.insert(
+            format!("Bearer {}", admin_api_token),
+            ("admin".to_string(), vec![ApiScope::Admin]),
+        );
+        ApiAuth {
+            keys: Arc::new(keys),
+        }
+    }
+
+    /// Returns true if the authorization header carries the admin auth token or an
+    /// API key with a scope that grants `scope`
+    pub fn authorize(&self, authorization: &str, scope: ApiScope) -> bool {
+        match self.keys.get(authorization) {
+            Some((name, scopes)) => {
+                let authorized = scopes.iter().any(|granted| granted.grants(scope));
+                if !authorized {
+                    debug!("API key {} is missing the {:?} scope", name, scope);
+                }
+                authorized
+            }
+            None => false,
+        }
+    }
+
+    /// Returns a filter which rejects the requests whose authorization header does not
+    /// grant `scope`
+    pub(crate) fn require(
+        &self,
+        scope: ApiScope,
+    ) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        let auth = self.clone();
+        warp::header::<SecretString>("authorization")
+            .and_then(move |authorization: SecretString| {
+                let authorized = auth.authorize(authorization.expose_secret(), scope);
+                async move {
+                    if authorized {
+                        Ok::<(), Rejection>(())
+                    } else {
+                        Err(Rejection::from(ApiError::unauthorized()))
+                    }
+                }
+            })
+            // This call makes it so we do not pass on a () value on
+            // success to the next filter, it just gets rid of it
+            .untuple_one()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> ApiAuth {
+        ApiAuth::new(
+            "admin".to_string(),
+            vec![
+                ApiKey {
+                    name: "monitoring".to_string(),
+                    key: SecretString::new("monitoring-key".to_string()),
+                    scopes: vec![ApiScope::Metrics],
+                },
+                ApiKey {
+                    name: "dashboard".to_string(),
+                    key: SecretString::new("dashboard-key".to_string()),
+                    scopes: vec![ApiScope::ReadOnly, ApiScope::PaymentsSend],
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn admin_token_grants_every_scope() {
+        let auth = auth();
+        assert!(auth.authorize("Bearer admin", ApiScope::Admin));
+        assert!(auth.authorize("Bearer admin", ApiScope::AccountsWrite));
+        assert!(auth.authorize("Bearer admin", ApiScope::Metrics));
+        assert!(!auth.authorize("Bearer admin", ApiScope::PaymentsSend));
+    }
+
+    #[test]
+    fn keys_only_grant_their_scopes() {
+        let auth = auth();
+        assert!(auth.authorize("Bearer monitoring-key", ApiScope::Metrics));
+        assert!(!auth.authorize("Bearer monitoring-key", ApiScope::ReadOnly));
+        assert!(auth.authorize("Bearer dashboard-key", ApiScope::ReadOnly));
+        assert!(auth.authorize("Bearer dashboard-key", ApiScope::Metrics));
+        assert!(auth.authorize("Bearer dashboard-key", ApiScope::PaymentsSend));
+        assert!(!auth.authorize("Bearer dashboard-key", ApiScope::AccountsWrite));
+        assert!(!auth.authorize("Bearer dashboard-key", ApiScope::Admin));
+        assert!(!auth.authorize("Bearer unknown-key", ApiScope::Metrics));
+        assert!(!auth.authorize("monitoring-key", ApiScope::Metrics));
+    }
+
+    #[test]
+    fn deserializes_scopes() {
+        let key: ApiKey = serde_json::from_value(serde_json::json!({
+            "name": "ops",
+            "key": "secret",
+            "scopes": ["read_only", "accounts:write", "payments:send", "metrics", "admin"],
+        }))
+        .unwrap();
+        assert_eq!(
+            key.scopes,
+            vec![
+                ApiScope::ReadOnly,
+                ApiScope::AccountsWrite,
+                ApiScope::PaymentsSend,
+                ApiScope::Metrics,
+                ApiScope::Admin
+            ]
+        );
+    }
+}
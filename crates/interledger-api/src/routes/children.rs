@@ -0,0 +1,238 @@
+use super::accounts::connect_to_external_services;
+use super::auth::{ApiAuth, ApiScope};
+use crate::{AccountDetails, NodeStore};
+use interledger_btp::{BtpAccount, BtpOutgoingService};
+use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
+use interledger_errors::*;
+use interledger_http::deserialize_json;
+use interledger_packet::Address;
+use interledger_router::RouterStore;
+use interledger_service::{Account, AccountStore, AddressStore, OutgoingService, Username};
+use interledger_service_util::BalanceStore;
+use interledger_settlement::core::types::SettlementAccount;
+use serde::Serialize;
+use std::{collections::HashMap, str::FromStr};
+use tracing::{debug, warn};
+use uuid::Uuid;
+use warp::{self, reply::Json, Filter, Rejection};
+
+/// Longest username, see `Username`
+const MAX_USERNAME_LENGTH: usize = 32;
+/// Number of usernames which are tried before giving up, i.e. up to `alice_10`
+const MAX_CANDIDATES: usize = 10;
+
+/// Returns the usernames which are tried for a child, `alice`, `alice_2`, `alice_3` and so
+/// on, shortened to the maximum length of usernames
+fn candidate_usernames(username: &Username) -> impl Iterator<Item = String> + '_ {
+    std::iter::once(username.to_string()).chain((2..=MAX_CANDIDATES).map(move |n| {
+        let suffix = format!("_{}", n);
+        let base: String = username
+            .as_ref()
+            .chars()
+            .take(MAX_USERNAME_LENGTH - suffix.len())
+            .collect();
+        format!("{}{}", base, suffix)
+    }))
+}
+
+/// Returns true if packets for the address or for addresses under it are already routed,
+/// e.g. to an account, by a static route or by a route learned with CCP
+fn is_routed(routing_table: &HashMap<String, Uuid>, address: &Address) -> bool {
+    let address = address.to_string();
+    let children_prefix = format!("{}.", address);
+    routing_table
+        .keys()
+        .any(|prefix| prefix == &address || prefix.starts_with(&children_prefix))
+}
+
+/// Returns the endpoint which creates child accounts. Their ILP addresses, which they get
+/// with IL-DCP, are the node's address followed by their username. If that address or the
+/// username is taken, a number is appended to the username.
+pub fn children_api<O, S, A, B>(
+    api_auth: ApiAuth,
+    outgoing_handler: O,
+    btp: BtpOutgoingService<B, A>,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    B: OutgoingService<A> + Clone + Send + Sync + 'static,
+    S: NodeStore<Account = A>
+        + AccountStore<Account = A>
+        + AddressStore
+        + BalanceStore
+        + RouterStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    A: BtpAccount
+        + CcpRoutingAccount
+        + SettlementAccount
+        + Account
+        + Serialize
+        + Send
+        + Sync
+        + 'static,
+{
+    let with_store = warp::any().map(move || store.clone());
+
+    // POST /children
+    // Body: The account details of `POST /accounts`, without the ILP address
+    warp::post()
+        .and(warp::path("children"))
+        .and(warp::path::end())
+        .and(api_auth.require(ApiScope::AccountsWrite))
+        .and(deserialize_json())
+        .and(with_store)
+        .and_then(move |mut details: AccountDetails, store: S| {
+            let outgoing_handler = outgoing_handler.clone();
+            let btp = btp.clone();
+            async move {
+                if details.ilp_address.is_some() {
+                    return Err(Rejection::from(
+                        ApiError::bad_request()
+                            .detail("the ILP address of children is assigned by the node"),
+                    ));
+                }
+                match details
+                    .routing_relation
+                    .as_deref()
+                    .map(RoutingRelation::from_str)
+                {
+                    None | Some(Ok(RoutingRelation::Child)) => {}
+                    _ => {
+                        return Err(ApiError::bad_request()
+                            .detail("the routing relation of children must be Child")
+                            .into())
+                    }
+                }
+
+                let node_address = store.get_ilp_address();
+                let mut assigned = None;
+                for candidate in candidate_usernames(&details.username) {
+                    let address = node_address
+                        .with_suffix(candidate.as_bytes())
+                        .map_err(|_| {
+                            ApiError::bad_request()
+                                .detail("the username cannot be used in an ILP address")
+                        })?;
+                    let username = Username::from_str(&candidate)
+                        .map_err(|err| ApiError::bad_request().detail(err))?;
+                    if is_routed(&store.routing_table(), &address) {
+                        debug!("Address {} is already routed", address);
+                        continue;
+                    }
+                    match store.get_account_id_from_username(&username).await {
+                        Ok(_) => continue,
+                        Err(AccountStoreError::AccountNotFound(_)) => {
+                            assigned = Some((username, address));
+                            break;
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+                let (username, address) = assigned.ok_or_else(|| {
+                    ApiError::conflict().detail(format!(
+                        "the username and the ILP addresses of the first {} alternatives are taken",
+                        MAX_CANDIDATES
+                    ))
+                })?;
+
+                // The store derives the address from the username, and updates it
+                // if the node's address changes
+                details.username = username;
+                details.routing_relation = Some("Child".to_string());
+                let account = store.insert_account(details).await?;
+                if !store.routing_table().contains_key(&address.to_string()) {
+                    warn!(
+                        "Child account {} was created, but there is no route for {}",
+                        account.id(),
+                        address
+                    );
+                }
+                let account =
+                    connect_to_external_services(outgoing_handler, account, store, btp).await?;
+                Ok::<Json, Rejection>(warp::reply::json(&account))
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::test_helpers::{api_call, test_children_api, DETAILS};
+    use serde_json::json;
+
+    fn username(username: &str) -> Username {
+        Username::from_str(username).unwrap()
+    }
+
+    #[test]
+    fn appends_numbers_to_usernames() {
+        let candidates: Vec<String> = candidate_usernames(&username("alice")).collect();
+        assert_eq!(candidates.len(), MAX_CANDIDATES);
+        assert_eq!(candidates[0], "alice");
+        assert_eq!(candidates[1], "alice_2");
+        assert_eq!(candidates[9], "alice_10");
+
+        let long = "a".repeat(MAX_USERNAME_LENGTH);
+        let candidates: Vec<String> = candidate_usernames(&username(&long)).collect();
+        assert_eq!(candidates[0], long);
+        assert_eq!(candidates[9].len(), MAX_USERNAME_LENGTH);
+        assert!(candidates[9].ends_with("_10"));
+    }
+
+    #[test]
+    fn detects_routed_addresses() {
+        let mut routing_table = HashMap::new();
+        routing_table.insert("example.node.alice".to_string(), Uuid::nil());
+        routing_table.insert("example.node.bob.sub".to_string(), Uuid::nil());
+        let address = |address: &str| Address::from_str(address).unwrap();
+
+        assert!(is_routed(&routing_table, &address("example.node.alice")));
+        assert!(is_routed(&routing_table, &address("example.node.bob")));
+        assert!(!is_routed(&routing_table, &address("example.node.ali")));
+        assert!(!is_routed(&routing_table, &address("example.node.alice_2")));
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_create_children() {
+        let api = test_children_api();
+        let mut details = DETAILS.clone().unwrap();
+        details["username"] = json!("new_child");
+        details["ilp_address"] = json!(null);
+        details["routing_relation"] = json!(null);
+        let resp = api_call(&api, "POST", "/children", "admin", Some(details.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "POST", "/children", "wrong", Some(details)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn rejects_addresses_and_other_relations() {
+        let api = test_children_api();
+        let mut details = DETAILS.clone().unwrap();
+        details["username"] = json!("new_child");
+        details["ilp_address"] = json!("example.child");
+        let resp = api_call(&api, "POST", "/children", "admin", Some(details.clone())).await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        details["ilp_address"] = json!(null);
+        details["routing_relation"] = json!("Peer");
+        let resp = api_call(&api, "POST", "/children", "admin", Some(details)).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
+    #[tokio::test]
+    async fn gives_up_if_all_usernames_are_taken() {
+        let api = test_children_api();
+        let mut details = DETAILS.clone().unwrap();
+        details["ilp_address"] = json!(null);
+        details["routing_relation"] = json!(null);
+        // The test store has accounts with any username which doesn't start with "new_"
+        let resp = api_call(&api, "POST", "/children", "admin", Some(details)).await;
+        assert_eq!(resp.status().as_u16(), 409);
+    }
+}
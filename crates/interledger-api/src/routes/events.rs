@@ -0,0 +1,330 @@
+use super::accounts::{consume_msg_drain, BEARER_TOKEN_START};
+use super::auth::{ApiAuth, ApiScope};
+use crate::NodeStore;
+use futures::{FutureExt, StreamExt};
+use interledger_ccp::{RouteChange, RouteChanges};
+use interledger_errors::ApiError;
+use interledger_http::HttpStore;
+use interledger_service::{runtime, Account, AccountStore, Username};
+use interledger_settlement::core::SettlementEvent;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tracing::debug;
+use uuid::Uuid;
+use warp::{Filter, Rejection};
+
+/// Publishes the events of the node to the WebSocket subscribers
+pub type NodeEvents = broadcast::Sender<NodeEvent>;
+
+/// An account which took part in an event
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EventAccount {
+    pub id: Uuid,
+    pub username: Username,
+}
+
+impl EventAccount {
+    pub fn new<A: Account>(account: &A) -> Self {
+        EventAccount {
+            id: account.id(),
+            username: account.username().clone(),
+        }
+    }
+}
+
+/// An event of the node, sent to the subscribers of `/events` and
+/// `/accounts/:username/events` as a JSON message
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NodeEvent {
+    /// A packet from `from` was forwarded to `to` and fulfilled
+    PacketFulfilled {
+        from: EventAccount,
+        to: EventAccount,
+        destination: String,
+        /// The amount of the incoming packet, in the units of `from`
+        source_amount: u64,
+        /// The amount of the outgoing packet, in the units of `to`
+        amount: u64,
+    },
+    /// A packet from `from` to `to` was rejected
+    PacketRejected {
+        from: EventAccount,
+        to: EventAccount,
+        destination: String,
+        source_amount: u64,
+        amount: u64,
+        code: String,
+        message: String,
+    },
+    /// A peer opened a WebSocket connection to the node, or the node connected to it
+    PeerConnected { account: EventAccount },
+    /// The WebSocket connection to a peer was closed
+    PeerDisconnected { account: EventAccount },
+    /// The balance of an account changed
+    BalanceUpdated { account: EventAccount, balance: i64 },
+    /// A settlement with an account was sent to its settlement engine, succeeded or failed,
+    /// or a settlement from the account was received
+    Settlement {
+        account: EventAccount,
+        settlement: SettlementEvent,
+    },
+}
+
+impl NodeEvent {
+    /// Returns true if the account took part in the event
+    pub fn involves(&self, account_id: Uuid) -> bool {
+        match self {
+            NodeEvent::PacketFulfilled { from, to, .. }
+            | NodeEvent::PacketRejected { from, to, .. } => {
+                from.id == account_id || to.id == account_id
+            }
+            NodeEvent::PeerConnected { account }
+            | NodeEvent::PeerDisconnected { account }
+            | NodeEvent::BalanceUpdated { account, .. }
+            | NodeEvent::Settlement { account, .. } => account.id == account_id,
+        }
+    }
+}
+
+/// Returns the WebSocket endpoints which stream the events of the node and the changes
+/// of its routing table
+pub fn events_api<S>(
+    api_auth: ApiAuth,
+    store: S,
+    events: NodeEvents,
+    route_changes: RouteChanges,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: NodeStore + AccountStore + HttpStore + Clone + Send + Sync + 'static,
+{
+    let api_auth_clone = api_auth.clone();
+    let with_store = warp::any().map(move || store.clone());
+    let with_events = warp::any().map(move || events.subscribe());
+    let with_route_changes = warp::any().map(move || route_changes.subscribe());
+
+    // (Websocket) /events
+    let all_events = warp::path("events")
+        .and(warp::path::end())
+        .and(api_auth.require(ApiScope::ReadOnly))
+        .and(warp::ws())
+        .and(with_events.clone())
+        .map(|ws: warp::ws::Ws, events: broadcast::Receiver<NodeEvent>| {
+            ws.on_upgrade(move |ws| forward_events(ws, events, |_| true))
+        });
+
+    // (Websocket) /routes/watch
+    let watch_routes = warp::path("routes")
+        .and(warp::path("watch"))
+        .and(warp::path::end())
+        .and(api_auth.require(ApiScope::ReadOnly))
+        .and(warp::ws())
+        .and(with_route_changes)
+        .map(
+            |ws: warp::ws::Ws, route_changes: broadcast::Receiver<RouteChange>| {
+                ws.on_upgrade(move |ws| forward_events(ws, route_changes, |_| true))
+            },
+        );
+
+    // (Websocket) /accounts/:username/events
+    let account_events = warp::path("accounts")
+        .and(warp::path::param::<Username>())
+        .and(warp::path("events"))
+        .and(warp::path::end())
+        .and(warp::header::<SecretString>("authorization"))
+        .and(with_store)
+        .and_then(
+            move |username: Username, authorization: SecretString, store: S| {
+                let authorized =
+                    api_auth_clone.authorize(authorization.expose_secret(), ApiScope::ReadOnly);
+                async move {
+                    if authorized {
+                        return Ok(store.get_account_id_from_username(&username).await?);
+                    }
+                    let authorization = authorization.expose_secret();
+                    if authorization.len() < BEARER_TOKEN_START {
+                        return Err(Rejection::from(ApiError::bad_request()));
+                    }
+                    let account = store
+                        .get_account_from_http_auth(&username, &authorization[BEARER_TOKEN_START..])
+                        .await?;
+                    Ok::<Uuid, Rejection>(account.id())
+                }
+            },
+        )
+        .and(warp::ws())
+        .and(with_events)
+        .map(
+            |account_id: Uuid, ws: warp::ws::Ws, events: broadcast::Receiver<NodeEvent>| {
+                ws.on_upgrade(move |ws| {
+                    forward_events(ws, events, move |event: &NodeEvent| {
+                        event.involves(account_id)
+                    })
+                })
+            },
+        );
+
+    all_events.or(account_events).or(watch_routes)
+}
+
+/// Sends the events accepted by the filter to the WebSocket until it is closed.
+/// Subscribers which are too slow to receive the events miss some of them.
+async fn forward_events<T, F>(ws: warp::ws::WebSocket, events: broadcast::Receiver<T>, filter: F)
+where
+    T: Serialize + Clone + Send + 'static,
+    F: Fn(&T) -> bool + Send + 'static,
+{
+    let (ws_tx, ws_rx) = ws.split();
+    let messages = BroadcastStream::new(events).filter_map(move |event| {
+        let message = match event {
+            Ok(event) if filter(&event) => serde_json::to_string(&event).ok(),
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                debug!("WebSocket subscriber missed {} events", skipped);
+                None
+            }
+        };
+        futures::future::ready(message.map(|message| Ok(warp::ws::Message::text(message))))
+    });
+    // Sending stops with the first event after the client closed the connection
+    runtime::spawn(messages.forward(ws_tx).map(|result| {
+        if let Err(err) = result {
+            debug!("WebSocket send error: {}", err);
+        }
+    }));
+    consume_msg_drain(ws_rx).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::test_helpers::{test_events_api, USERNAME};
+    use interledger_ccp::NextHop;
+    use serde_json::{json, Value};
+
+    fn fulfilled(id: Uuid) -> NodeEvent {
+        NodeEvent::PacketFulfilled {
+            from: EventAccount {
+                id,
+                username: USERNAME.clone(),
+            },
+            to: EventAccount {
+                id: Uuid::nil(),
+                username: USERNAME.clone(),
+            },
+            destination: "example.bob".to_string(),
+            source_amount: 100,
+            amount: 200,
+        }
+    }
+
+    #[test]
+    fn events_involve_their_accounts() {
+        let id = Uuid::new_v4();
+        assert!(fulfilled(id).involves(id));
+        assert!(fulfilled(id).involves(Uuid::nil()));
+        assert!(!fulfilled(id).involves(Uuid::new_v4()));
+    }
+
+    #[tokio::test]
+    async fn streams_events_to_the_admin() {
+        let (events, _) = broadcast::channel(16);
+        let api = test_events_api(events.clone(), broadcast::channel(16).0);
+        let mut client = warp::test::ws()
+            .path("/events")
+            .header("authorization", "Bearer admin")
+            .handshake(api)
+            .await
+            .unwrap();
+
+        let id = Uuid::new_v4();
+        events.send(fulfilled(id)).unwrap();
+        let message = client.recv().await.unwrap();
+        let event: Value = serde_json::from_str(message.to_str().unwrap()).unwrap();
+        assert_eq!(
+            event,
+            json!({
+                "type": "packet_fulfilled",
+                "from": {"id": id, "username": "alice"},
+                "to": {"id": Uuid::nil(), "username": "alice"},
+                "destination": "example.bob",
+                "source_amount": 100,
+                "amount": 200,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn only_admin_or_user_can_subscribe() {
+        let (events, _) = broadcast::channel(16);
+        let api = test_events_api(events, broadcast::channel(16).0);
+        assert!(warp::test::ws()
+            .path("/events")
+            .header("authorization", "Bearer password")
+            .handshake(api.clone())
+            .await
+            .is_err());
+        assert!(warp::test::ws()
+            .path("/accounts/alice/events")
+            .header("authorization", "Bearer wrong")
+            .handshake(api.clone())
+            .await
+            .is_err());
+        assert!(warp::test::ws()
+            .path("/accounts/alice/events")
+            .header("authorization", "Bearer password")
+            .handshake(api)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn streams_route_changes_to_the_admin() {
+        let (route_changes, _) = broadcast::channel(16);
+        let api = test_events_api(broadcast::channel(16).0, route_changes.clone());
+        assert!(warp::test::ws()
+            .path("/routes/watch")
+            .header("authorization", "Bearer password")
+            .handshake(api.clone())
+            .await
+            .is_err());
+        let mut client = warp::test::ws()
+            .path("/routes/watch")
+            .header("authorization", "Bearer admin")
+            .handshake(api)
+            .await
+            .unwrap();
+
+        let id = Uuid::new_v4();
+        route_changes
+            .send(RouteChange::Added {
+                prefix: "example.bob".to_string(),
+                next_hop: NextHop {
+                    id,
+                    username: USERNAME.clone(),
+                    path: vec!["example.connector".to_string()],
+                },
+            })
+            .unwrap();
+        route_changes
+            .send(RouteChange::Removed {
+                prefix: "example.carl".to_string(),
+            })
+            .unwrap();
+        let message = client.recv().await.unwrap();
+        let change: Value = serde_json::from_str(message.to_str().unwrap()).unwrap();
+        assert_eq!(
+            change,
+            json!({
+                "type": "added",
+                "prefix": "example.bob",
+                "next_hop": {"id": id, "username": "alice", "path": ["example.connector"]},
+            })
+        );
+        let message = client.recv().await.unwrap();
+        let change: Value = serde_json::from_str(message.to_str().unwrap()).unwrap();
+        assert_eq!(change, json!({"type": "removed", "prefix": "example.carl"}));
+    }
+}
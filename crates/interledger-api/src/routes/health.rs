@@ -0,0 +1,174 @@
+use crate::{AccountFilter, NodeStore};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use warp::{http::StatusCode, Filter, Rejection};
+
+/// Returns the number of open connections to peers
+pub type ConnectionCount = Arc<dyn Fn() -> usize + Send + Sync>;
+
+#[derive(Debug, Deserialize)]
+struct ReadinessQuery {
+    /// Only report the node as ready if at least one peer is connected
+    #[serde(default)]
+    require_peer: bool,
+}
+
+#[derive(Clone, Serialize)]
+struct HealthResponse {
+    status: &'static str,
+}
+
+#[derive(Clone, Serialize)]
+struct Check {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+struct PeersCheck {
+    ok: bool,
+    required: bool,
+    connections: usize,
+}
+
+#[derive(Clone, Serialize)]
+struct ReadinessChecks {
+    store: Check,
+    http: Check,
+    peers: PeersCheck,
+}
+
+#[derive(Clone, Serialize)]
+struct ReadinessResponse {
+    status: &'static str,
+    checks: ReadinessChecks,
+}
+
+/// Returns the unauthenticated endpoints for liveness and readiness probes
+pub fn health_api<S>(
+    store: S,
+    connection_count: ConnectionCount,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: NodeStore,
+{
+    let with_store = warp::any().map(move || store.clone());
+
+    // GET /healthz
+    let get_healthz = warp::get()
+        .and(warp::path("healthz"))
+        .and(warp::path::end())
+        .map(|| warp::reply::json(&HealthResponse { status: "alive" }));
+
+    // GET /readyz
+    let get_readyz = warp::get()
+        .and(warp::path("readyz"))
+        .and(warp::path::end())
+        .and(warp::query::<ReadinessQuery>())
+        .and(with_store)
+        .and_then(move |query: ReadinessQuery, store: S| {
+            let connections = connection_count();
+            async move {
+                let store_check = match store.list_accounts(None, 1, AccountFilter::default()).await
+                {
+                    Ok(_) => Check {
+                        ok: true,
+                        detail: None,
+                    },
+                    Err(err) => Check {
+                        ok: false,
+                        detail: Some(err.to_string()),
+                    },
+                };
+                let peers = PeersCheck {
+                    ok: !query.require_peer || connections > 0,
+                    required: query.require_peer,
+                    connections,
+                };
+                let ready = store_check.ok && peers.ok;
+                let response = ReadinessResponse {
+                    status: if ready { "ready" } else { "not_ready" },
+                    checks: ReadinessChecks {
+                        store: store_check,
+                        // This response is served by the HTTP listener, so it is bound
+                        http: Check {
+                            ok: true,
+                            detail: None,
+                        },
+                        peers,
+                    },
+                };
+                let status = if ready {
+                    StatusCode::OK
+                } else {
+                    StatusCode::SERVICE_UNAVAILABLE
+                };
+                Ok::<_, Rejection>(warp::reply::with_status(
+                    warp::reply::json(&response),
+                    status,
+                ))
+            }
+        });
+
+    get_healthz.or(get_readyz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::test_helpers::{api_call, test_health_api};
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn gets_health() {
+        let api = test_health_api(0);
+        let resp = api_call(&api, "GET", "/healthz", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body(), &b"{\"status\":\"alive\"}"[..]);
+    }
+
+    #[tokio::test]
+    async fn gets_readiness() {
+        let api = test_health_api(0);
+        let resp = api_call(&api, "GET", "/readyz", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({
+                "status": "ready",
+                "checks": {
+                    "store": {"ok": true},
+                    "http": {"ok": true},
+                    "peers": {"ok": true, "required": false, "connections": 0},
+                },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn not_ready_without_a_required_peer() {
+        let resp = api_call(
+            &test_health_api(0),
+            "GET",
+            "/readyz?require_peer=true",
+            "",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 503);
+        let body: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["status"], "not_ready");
+        assert_eq!(body["checks"]["peers"]["ok"], false);
+
+        let resp = api_call(
+            &test_health_api(1),
+            "GET",
+            "/readyz?require_peer=true",
+            "",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+}
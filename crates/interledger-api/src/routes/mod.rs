@@ -1,8 +1,23 @@
 mod accounts;
+mod auth;
+mod children;
+mod events;
+mod health;
 mod node_settings;
+mod openapi;
+mod peering;
+mod secrets;
 
 pub use accounts::accounts_api;
+pub(crate) use accounts::connect_to_external_services;
+pub use auth::{ApiAuth, ApiKey, ApiScope};
+pub use children::children_api;
+pub use events::{events_api, EventAccount, NodeEvent, NodeEvents};
+pub use health::{health_api, ConnectionCount};
 pub use node_settings::node_settings_api;
+pub use openapi::openapi_api;
+pub use peering::peering_api;
+pub use secrets::secrets_api;
 
 #[cfg(test)]
 pub mod test_helpers;
@@ -1,4 +1,5 @@
-use crate::{ExchangeRates, NodeStore};
+use super::auth::{ApiAuth, ApiScope};
+use crate::{ExchangeRates, NodeExport, NodeStore};
 use bytes::Bytes;
 use futures::TryFutureExt;
 use interledger_errors::*;
@@ -8,7 +9,6 @@ use interledger_rates::ExchangeRateStore;
 use interledger_router::RouterStore;
 use interledger_service::{Account, AccountStore, AddressStore, Username};
 use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
-use secrecy::{ExposeSecret, SecretString};
 use serde::Serialize;
 use std::{
     collections::HashMap,
@@ -28,8 +28,14 @@ struct StatusResponse {
     version: Option<String>,
 }
 
+#[derive(Clone, Serialize)]
+struct ImportResponse {
+    accounts: usize,
+    static_routes: usize,
+}
+
 pub fn node_settings_api<S, A>(
-    admin_api_token: String,
+    api_auth: ApiAuth,
     node_version: Option<String>,
     store: S,
 ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
@@ -42,23 +48,7 @@ where
     A: Account + HttpAccount + Send + Sync + SettlementAccount + Serialize + 'static,
 {
     // Helper filters
-    let admin_auth_header = format!("Bearer {}", admin_api_token);
-    let admin_only = warp::header::<SecretString>("authorization")
-        .and_then(move |authorization: SecretString| {
-            let admin_auth_header = admin_auth_header.clone();
-            async move {
-                if authorization.expose_secret() == &admin_auth_header {
-                    Ok::<(), Rejection>(())
-                } else {
-                    Err(Rejection::from(
-                        ApiError::unauthorized().detail("invalid admin auth token provided"),
-                    ))
-                }
-            }
-        })
-        // This call makes it so we do not pass on a () value on
-        // success to the next filter, it just gets rid of it
-        .untuple_one();
+    let admin_only = api_auth.require(ApiScope::Admin);
     let with_store = warp::any().map(move || store.clone());
 
     // GET /
@@ -176,6 +166,36 @@ where
             }
         });
 
+    // GET /export
+    // Response: All accounts (including their tokens and balances), static routes and
+    // settlement engines, which can be imported into another (empty) store
+    let get_export = warp::get()
+        .and(warp::path("export"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let export = store.export_node().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&export))
+        });
+
+    // POST /import
+    // Body: The output of GET /export
+    let post_import = warp::post()
+        .and(warp::path("import"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(|export: NodeExport, store: S| async move {
+            let response = ImportResponse {
+                accounts: export.accounts.len(),
+                static_routes: export.static_routes.len(),
+            };
+            store.import_node(export).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&response))
+        });
+
     // PUT /settlement/engines
     let put_settlement_engines = warp::put()
         .and(warp::path("settlement"))
@@ -226,6 +246,8 @@ where
         .or(put_static_routes)
         .or(put_static_route)
         .or(put_settlement_engines)
+        .or(get_export)
+        .or(post_import)
 }
 
 #[cfg(test)]
@@ -273,6 +295,20 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn only_admin_keys_can_change_settings() {
+        let api = test_node_settings_api();
+        let rates = json!({"ABC": 1.0});
+        let resp = api_call(&api, "PUT", "/rates", "admin-key", Some(rates.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "PUT", "/rates", "accounts_write-key", Some(rates)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/export", "read_only-key", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_can_put_static_routes() {
         let api = test_node_settings_api();
@@ -324,4 +360,38 @@ mod tests {
         let resp = api_call(&api, "PUT", "/settlement/engines", "wrong", Some(engines)).await;
         assert_eq!(resp.status().as_u16(), 401);
     }
+
+    #[tokio::test]
+    async fn only_admin_can_export() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/export", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let export: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(export["accounts"], json!([]));
+
+        let resp = api_call(&api, "GET", "/export", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_import() {
+        let api = test_node_settings_api();
+        let export = json!({
+            "accounts": [{
+                "id": "9ee6e21a-1a32-4d14-a8f7-4d3a1b2b4c5d",
+                "details": {"username": "alice", "asset_code": "XYZ", "asset_scale": 9},
+                "balance": 100,
+            }],
+            "static_routes": {"example.alice": "9ee6e21a-1a32-4d14-a8f7-4d3a1b2b4c5d"},
+        });
+        let resp = api_call(&api, "POST", "/import", "admin", Some(export.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({"accounts": 1, "static_routes": 1})
+        );
+
+        let resp = api_call(&api, "POST", "/import", "wrong", Some(export)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
 }
@@ -0,0 +1,109 @@
+use warp::Filter;
+
+/// OpenAPI description of the routes of this crate and of the node's other HTTP endpoints,
+/// which is kept in sync with the routes by the tests below
+const OPENAPI_SPEC: &str = include_str!("../../../../docs/api.yml");
+
+/// Returns the unauthenticated endpoint which serves the OpenAPI description of the API,
+/// from which client SDKs can be generated
+pub fn openapi_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    // GET /openapi.yaml
+    warp::get()
+        .and(warp::path("openapi.yaml"))
+        .and(warp::path::end())
+        .map(|| warp::reply::with_header(OPENAPI_SPEC, "content-type", "application/yaml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::test_helpers::{api_call, test_api};
+    use serde_json::json;
+
+    /// Paths of the description which are served by other crates, e.g. ILP over HTTP
+    const SERVED_ELSEWHERE: &[&str] = &["/tracing-level", "/accounts/{username}/ilp"];
+
+    /// Paths of the description which open a WebSocket
+    const WEBSOCKETS: &[&str] = &[
+        "/events",
+        "/accounts/{username}/events",
+        "/payments/incoming",
+        "/accounts/{username}/payments/incoming",
+        "/routes/watch",
+    ];
+
+    /// Returns the method and path of every operation of the description
+    fn operations() -> Vec<(String, String)> {
+        let mut operations = Vec::new();
+        let mut path = None;
+        for line in OPENAPI_SPEC.lines() {
+            if line.starts_with("  /") && line.ends_with(':') {
+                path = Some(line.trim().trim_end_matches(':').to_string());
+            } else if !line.starts_with("  ") && !line.trim().is_empty() {
+                // The end of the `paths`
+                path = None;
+            } else if let Some(ref path) = path {
+                let method = line.trim_end_matches(':');
+                if ["    get", "    post", "    put", "    delete"].contains(&method) {
+                    operations.push((method.trim().to_uppercase(), path.clone()));
+                }
+            }
+        }
+        operations
+    }
+
+    #[test]
+    fn finds_operations() {
+        let operations = operations();
+        assert!(operations.contains(&("GET".to_string(), "/accounts".to_string())));
+        assert!(operations.contains(&("POST".to_string(), "/accounts".to_string())));
+        assert!(operations.contains(&("DELETE".to_string(), "/accounts/{username}".to_string())));
+        assert!(operations.contains(&("GET".to_string(), "/openapi.yaml".to_string())));
+    }
+
+    #[tokio::test]
+    async fn serves_the_description() {
+        let resp = api_call(&openapi_api(), "GET", "/openapi.yaml", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.headers()["content-type"], "application/yaml");
+        assert!(resp.body().starts_with(b"openapi: "));
+    }
+
+    #[tokio::test]
+    async fn every_operation_has_a_route() {
+        let api = test_api();
+        for (method, path) in operations() {
+            if SERVED_ELSEWHERE.contains(&path.as_str()) {
+                continue;
+            }
+            let uri = path
+                .replace("{username}", "alice")
+                .replace("{prefix}", "example.alice");
+            if WEBSOCKETS.contains(&path.as_str()) {
+                let handshake = warp::test::ws()
+                    .path(&uri)
+                    .header("authorization", "Bearer admin")
+                    .handshake(api.clone())
+                    .await;
+                assert!(handshake.is_ok(), "{} {} has no route", method, path);
+                continue;
+            }
+            let body = if method == "POST" || method == "PUT" {
+                Some(json!({}))
+            } else {
+                None
+            };
+            let resp = api_call(&api, &method, &uri, "admin", body).await;
+            // Requests which no route matches are rejected with 405, or with a 404
+            // without a body (a 404 of a route, e.g. for an unknown account, has a body)
+            let status = resp.status().as_u16();
+            assert!(
+                status != 405 && !(status == 404 && resp.body().is_empty()),
+                "{} {} has no route",
+                method,
+                path
+            );
+        }
+    }
+}
@@ -0,0 +1,487 @@
+use super::accounts::{connect_to_external_services, BEARER_TOKEN_START};
+use super::auth::{ApiAuth, ApiScope};
+use crate::{number_or_string, AccountDetails, NodeStore};
+use futures::TryFutureExt;
+use interledger_btp::{BtpAccount, BtpOutgoingService};
+use interledger_ccp::CcpRoutingAccount;
+use interledger_errors::*;
+use interledger_http::deserialize_json;
+use interledger_packet::Address;
+use interledger_service::{Account, AccountStore, AddressStore, OutgoingService, Username};
+use interledger_service_util::BalanceStore;
+use interledger_settlement::core::types::SettlementAccount;
+use once_cell::sync::Lazy;
+use ring::rand::{SecureRandom, SystemRandom};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{debug, info};
+use url::Url;
+use warp::{self, reply::Json, Filter, Rejection};
+
+static RANDOM: Lazy<SystemRandom> = Lazy::new(SystemRandom::new);
+
+/// Lifetime of the invitations which don't set `expires_in`, in seconds
+const DEFAULT_INVITATION_LIFETIME: u64 = 86_400;
+/// Timeout of the handshake request sent to the other node
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Body of `POST /peering/invitations`
+#[derive(Deserialize)]
+struct InvitationRequest {
+    /// Username of the account which is created for the node that accepts the invitation
+    username: Username,
+    asset_code: String,
+    #[serde(deserialize_with = "number_or_string")]
+    asset_scale: u8,
+    /// Base URL at which the other node reaches this node's HTTP API
+    public_url: Url,
+    /// Seconds after which the invitation can no longer be used
+    expires_in: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct InvitationResponse {
+    /// The secret which the other node's operator passes to `POST /peers`
+    secret: String,
+    expires_in: u64,
+}
+
+/// An invitation which was created but not used yet
+struct Invitation {
+    username: Username,
+    asset_code: String,
+    asset_scale: u8,
+    public_url: Url,
+    expires_at: Instant,
+}
+
+/// The invitations which were created but not used yet, by their secrets
+type Invitations = Arc<Mutex<HashMap<String, Invitation>>>;
+
+/// Sent by the node which peers to the node which created the invitation, with the
+/// invitation's secret as the Bearer token
+#[derive(Serialize, Deserialize)]
+struct HandshakeRequest {
+    ilp_address: Address,
+    /// The URL to which the packets for the node are sent
+    ilp_over_http_url: Url,
+    /// The token with which the packets for the node are sent
+    token: String,
+}
+
+/// The reply of the node which created the invitation
+#[derive(Serialize, Deserialize)]
+struct HandshakeResponse {
+    ilp_address: Address,
+    asset_code: String,
+    asset_scale: u8,
+    ilp_over_http_url: Url,
+    token: String,
+}
+
+/// Body of `POST /peers`
+#[derive(Deserialize)]
+struct PeerRequest {
+    /// Base URL of the other node's HTTP API
+    url: Url,
+    /// The secret of the invitation created on the other node
+    secret: SecretString,
+    /// Username of the account which is created for the other node
+    username: Username,
+    /// Base URL at which the other node reaches this node's HTTP API
+    public_url: Url,
+}
+
+/// Returns a random token for ILP-over-HTTP, encoded as URL-safe base64
+fn random_token() -> String {
+    let mut bytes = [0; 32];
+    RANDOM
+        .fill(&mut bytes)
+        .expect("Failed to generate a random token");
+    base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Appends the path segments to the base URL, which must be an HTTP(S) URL
+fn join_segments(base: &Url, segments: &[&str]) -> Url {
+    let mut url = base.clone();
+    url.path_segments_mut()
+        .expect("HTTP URLs can be a base")
+        .pop_if_empty()
+        .extend(segments);
+    url
+}
+
+fn check_http_url(url: &Url, field: &str) -> Result<(), Rejection> {
+    if url.scheme() == "http" || url.scheme() == "https" {
+        Ok(())
+    } else {
+        Err(ApiError::bad_request()
+            .detail(format!("{} must be an HTTP(S) URL", field))
+            .into())
+    }
+}
+
+/// Account of a peer which sends and receives packets over ILP-over-HTTP
+fn peer_account_details(
+    username: Username,
+    ilp_address: Address,
+    asset_code: String,
+    asset_scale: u8,
+    ilp_over_http_url: Url,
+    incoming_token: String,
+    outgoing_token: String,
+) -> AccountDetails {
+    AccountDetails {
+        ilp_address: Some(ilp_address),
+        username,
+        asset_code,
+        asset_scale,
+        max_packet_amount: u64::max_value(),
+        min_packet_amount: None,
+        min_balance: None,
+        ilp_over_http_url: Some(ilp_over_http_url.to_string()),
+        ilp_over_http_incoming_token: Some(SecretString::new(incoming_token)),
+        ilp_over_http_outgoing_token: Some(SecretString::new(outgoing_token)),
+        ilp_over_btp_url: None,
+        ilp_over_btp_outgoing_token: None,
+        ilp_over_btp_incoming_token: None,
+        settle_threshold: None,
+        settle_to: None,
+        settle_every: None,
+        routing_relation: Some("Peer".to_string()),
+        routing_preference: None,
+        round_trip_time: None,
+        amount_per_minute_limit: None,
+        max_payment_amount: None,
+        amount_per_day_limit: None,
+        packets_per_minute_limit: None,
+        settlement_engine_url: None,
+    }
+}
+
+/// Returns the endpoints with which two nodes peer with each other: the operator of one
+/// node creates an invitation and hands its secret to the operator of the other node, who
+/// passes it to `POST /peers`. The nodes then exchange their addresses, the asset and the
+/// ILP-over-HTTP URLs and tokens, and create each other's accounts.
+pub fn peering_api<O, S, A, B>(
+    api_auth: ApiAuth,
+    outgoing_handler: O,
+    btp: BtpOutgoingService<B, A>,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    B: OutgoingService<A> + Clone + Send + Sync + 'static,
+    S: NodeStore<Account = A>
+        + AccountStore<Account = A>
+        + AddressStore
+        + BalanceStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    A: BtpAccount
+        + CcpRoutingAccount
+        + SettlementAccount
+        + Account
+        + Serialize
+        + Send
+        + Sync
+        + 'static,
+{
+    let invitations: Invitations = Arc::new(Mutex::new(HashMap::new()));
+    let with_invitations = warp::any().map(move || invitations.clone());
+    let with_store = warp::any().map(move || store.clone());
+    let accounts_write_only = api_auth.require(ApiScope::AccountsWrite);
+
+    // POST /peering/invitations
+    let post_invitations = warp::post()
+        .and(warp::path("peering"))
+        .and(warp::path("invitations"))
+        .and(warp::path::end())
+        .and(accounts_write_only.clone())
+        .and(deserialize_json())
+        .and(with_invitations.clone())
+        .and_then(
+            |request: InvitationRequest, invitations: Invitations| async move {
+                check_http_url(&request.public_url, "public_url")?;
+                let expires_in = request.expires_in.unwrap_or(DEFAULT_INVITATION_LIFETIME);
+                let now = Instant::now();
+                let expires_at = now
+                    .checked_add(Duration::from_secs(expires_in))
+                    .ok_or_else(|| ApiError::bad_request().detail("expires_in is too large"))?;
+                let secret = random_token();
+                let mut invitations = invitations.lock().unwrap();
+                invitations.retain(|_, invitation| invitation.expires_at > now);
+                invitations.insert(
+                    secret.clone(),
+                    Invitation {
+                        username: request.username,
+                        asset_code: request.asset_code,
+                        asset_scale: request.asset_scale,
+                        public_url: request.public_url,
+                        expires_at,
+                    },
+                );
+                Ok::<Json, Rejection>(warp::reply::json(&InvitationResponse {
+                    secret,
+                    expires_in,
+                }))
+            },
+        );
+
+    // POST /peering/handshake
+    // Authorized by the secret of an invitation, which can only be used once
+    let outgoing_handler_clone = outgoing_handler.clone();
+    let btp_clone = btp.clone();
+    let post_handshake = warp::post()
+        .and(warp::path("peering"))
+        .and(warp::path("handshake"))
+        .and(warp::path::end())
+        .and(warp::header::<SecretString>("authorization"))
+        .and(deserialize_json())
+        .and(with_invitations)
+        .and(with_store.clone())
+        .and_then(
+            move |authorization: SecretString,
+                  request: HandshakeRequest,
+                  invitations: Invitations,
+                  store: S| {
+                let outgoing_handler = outgoing_handler_clone.clone();
+                let btp = btp_clone.clone();
+                let invitation = authorization
+                    .expose_secret()
+                    .get(BEARER_TOKEN_START..)
+                    .and_then(|secret| invitations.lock().unwrap().remove(secret))
+                    .filter(|invitation| invitation.expires_at > Instant::now());
+                async move {
+                    let invitation = invitation.ok_or_else(|| {
+                        Rejection::from(
+                            ApiError::unauthorized().detail("unknown or expired invitation"),
+                        )
+                    })?;
+                    check_http_url(&request.ilp_over_http_url, "ilp_over_http_url")?;
+
+                    let token = random_token();
+                    let details = peer_account_details(
+                        invitation.username.clone(),
+                        request.ilp_address.clone(),
+                        invitation.asset_code.clone(),
+                        invitation.asset_scale,
+                        request.ilp_over_http_url,
+                        token.clone(),
+                        request.token,
+                    );
+                    let account = store.insert_account(details).await?;
+                    connect_to_external_services(outgoing_handler, account, store.clone(), btp)
+                        .await?;
+                    info!(
+                        "Peered with {} as account {}",
+                        request.ilp_address, invitation.username
+                    );
+
+                    Ok::<Json, Rejection>(warp::reply::json(&HandshakeResponse {
+                        ilp_address: store.get_ilp_address(),
+                        asset_code: invitation.asset_code,
+                        asset_scale: invitation.asset_scale,
+                        ilp_over_http_url: join_segments(
+                            &invitation.public_url,
+                            &["accounts", invitation.username.as_ref(), "ilp"],
+                        ),
+                        token,
+                    }))
+                }
+            },
+        );
+
+    // POST /peers
+    let post_peers = warp::post()
+        .and(warp::path("peers"))
+        .and(warp::path::end())
+        .and(accounts_write_only)
+        .and(deserialize_json())
+        .and(with_store)
+        .and_then(move |request: PeerRequest, store: S| {
+            let outgoing_handler = outgoing_handler.clone();
+            let btp = btp.clone();
+            async move {
+                check_http_url(&request.url, "url")?;
+                check_http_url(&request.public_url, "public_url")?;
+                match store.get_account_id_from_username(&request.username).await {
+                    Ok(_) => {
+                        return Err(Rejection::from(
+                            ApiError::conflict()
+                                .detail(format!("account `{}` already exists", request.username)),
+                        ))
+                    }
+                    Err(AccountStoreError::AccountNotFound(_)) => {}
+                    Err(err) => return Err(err.into()),
+                }
+
+                let token = random_token();
+                let handshake = HandshakeRequest {
+                    ilp_address: store.get_ilp_address(),
+                    ilp_over_http_url: join_segments(
+                        &request.public_url,
+                        &["accounts", request.username.as_ref(), "ilp"],
+                    ),
+                    token: token.clone(),
+                };
+                let handshake_url = join_segments(&request.url, &["peering", "handshake"]);
+                debug!("Sending peering handshake to {}", handshake_url);
+                let response = reqwest::Client::new()
+                    .post(handshake_url)
+                    .bearer_auth(request.secret.expose_secret())
+                    .json(&handshake)
+                    .timeout(HANDSHAKE_TIMEOUT)
+                    .send()
+                    .map_err(|err| {
+                        Rejection::from(
+                            ApiError::internal_server_error()
+                                .detail(format!("could not reach the other node: {}", err)),
+                        )
+                    })
+                    .await?;
+                if !response.status().is_success() {
+                    return Err(ApiError::bad_request()
+                        .detail(format!(
+                            "the other node rejected the handshake with HTTP status {}",
+                            response.status()
+                        ))
+                        .into());
+                }
+                let response: HandshakeResponse = response
+                    .json()
+                    .map_err(|err| {
+                        Rejection::from(
+                            ApiError::internal_server_error()
+                                .detail(format!("invalid handshake response: {}", err)),
+                        )
+                    })
+                    .await?;
+
+                let details = peer_account_details(
+                    request.username.clone(),
+                    response.ilp_address.clone(),
+                    response.asset_code,
+                    response.asset_scale,
+                    response.ilp_over_http_url,
+                    token,
+                    response.token,
+                );
+                let account = store.insert_account(details).await?;
+                let account =
+                    connect_to_external_services(outgoing_handler, account, store, btp).await?;
+                info!(
+                    "Peered with {} as account {}",
+                    response.ilp_address, request.username
+                );
+                Ok::<Json, Rejection>(warp::reply::json(&account))
+            }
+        });
+
+    post_invitations.or(post_handshake).or(post_peers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::test_helpers::{api_call, test_peering_api};
+    use serde_json::{json, Value};
+
+    fn invitation() -> Option<Value> {
+        Some(json!({
+            "username": "bob",
+            "asset_code": "XYZ",
+            "asset_scale": 9,
+            "public_url": "https://alice.example/api",
+        }))
+    }
+
+    fn handshake() -> Option<Value> {
+        Some(json!({
+            "ilp_address": "example.bob",
+            "ilp_over_http_url": "https://bob.example/accounts/alice/ilp",
+            "token": "bob-token",
+        }))
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_create_invitations() {
+        let api = test_peering_api();
+        let resp = api_call(&api, "POST", "/peering/invitations", "admin", invitation()).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "POST", "/peering/invitations", "wrong", invitation()).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn invitations_can_be_used_once() {
+        let api = test_peering_api();
+        let resp = api_call(&api, "POST", "/peering/invitations", "admin", invitation()).await;
+        let secret = serde_json::from_slice::<Value>(resp.body()).unwrap()["secret"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let resp = api_call(&api, "POST", "/peering/handshake", "wrong", handshake()).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "POST", "/peering/handshake", &secret, handshake()).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let response: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(response["ilp_address"], "example.connector");
+        assert_eq!(response["asset_code"], "XYZ");
+        assert_eq!(response["asset_scale"], 9);
+        assert_eq!(
+            response["ilp_over_http_url"],
+            "https://alice.example/api/accounts/bob/ilp"
+        );
+        assert!(response["token"].as_str().unwrap().len() >= 32);
+
+        let resp = api_call(&api, "POST", "/peering/handshake", &secret, handshake()).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn expired_invitations_cannot_be_used() {
+        let api = test_peering_api();
+        let mut invitation = invitation().unwrap();
+        invitation["expires_in"] = json!(0);
+        let resp = api_call(
+            &api,
+            "POST",
+            "/peering/invitations",
+            "admin",
+            Some(invitation),
+        )
+        .await;
+        let secret = serde_json::from_slice::<Value>(resp.body()).unwrap()["secret"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let resp = api_call(&api, "POST", "/peering/handshake", &secret, handshake()).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[test]
+    fn joins_url_segments() {
+        let base = Url::parse("https://example.com/node/").unwrap();
+        assert_eq!(
+            join_segments(&base, &["peering", "handshake"]).as_str(),
+            "https://example.com/node/peering/handshake"
+        );
+        let base = Url::parse("https://example.com").unwrap();
+        assert_eq!(
+            join_segments(&base, &["accounts", "alice", "ilp"]).as_str(),
+            "https://example.com/accounts/alice/ilp"
+        );
+    }
+}
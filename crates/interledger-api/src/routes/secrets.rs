@@ -0,0 +1,115 @@
+use super::auth::{ApiAuth, ApiScope};
+use crate::NodeStore;
+use bytes::Bytes;
+use interledger_http::deserialize_json;
+use interledger_stream::{ConnectionGenerator, SecretGeneration};
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::info;
+use warp::{self, reply::Json, Filter, Rejection};
+
+/// Default time for which the previous server secret is still accepted (1 day)
+const DEFAULT_OVERLAP: u64 = 24 * 3600;
+
+#[derive(Debug, Deserialize)]
+struct RotationRequest {
+    /// Time, in seconds, for which the previous server secret is still accepted
+    #[serde(default = "default_overlap")]
+    overlap: u64,
+}
+
+fn default_overlap() -> u64 {
+    DEFAULT_OVERLAP
+}
+
+/// Returns the endpoints which show and rotate the STREAM server secret, from which the
+/// receiver addresses and shared secrets handed out by SPSP are derived. The secrets are
+/// derived from the secret seed, and only their generation is stored.
+pub fn secrets_api<S>(
+    api_auth: ApiAuth,
+    secret_seed: Bytes,
+    connection_generator: ConnectionGenerator,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: NodeStore,
+{
+    let with_store = warp::any().map(move || store.clone());
+
+    // GET /secrets
+    let get_secrets = warp::get()
+        .and(warp::path("secrets"))
+        .and(warp::path::end())
+        .and(api_auth.require(ApiScope::ReadOnly))
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let generation = store.get_secret_generation().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&generation))
+        });
+
+    // POST /secrets/rotate
+    // Body: { "overlap": seconds }
+    let rotate_secrets = warp::post()
+        .and(warp::path("secrets"))
+        .and(warp::path("rotate"))
+        .and(warp::path::end())
+        .and(api_auth.require(ApiScope::Admin))
+        .and(deserialize_json())
+        .and(with_store)
+        .and_then(move |request: RotationRequest, store: S| {
+            let secret_seed = secret_seed.clone();
+            let connection_generator = connection_generator.clone();
+            async move {
+                let generation: SecretGeneration = store
+                    .get_secret_generation()
+                    .await?
+                    .rotate(Duration::from_secs(request.overlap));
+                store.set_secret_generation(generation).await?;
+                connection_generator.set_generation(&secret_seed, &generation);
+                info!(
+                    "Rotated the STREAM server secret to generation {}, the previous one is accepted for {}s",
+                    generation.generation, request.overlap
+                );
+                Ok::<Json, Rejection>(warp::reply::json(&generation))
+            }
+        });
+
+    get_secrets.or(rotate_secrets)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::test_helpers::{api_call, test_secrets_api};
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn only_admin_can_rotate() {
+        let api = test_secrets_api();
+        let resp = api_call(&api, "POST", "/secrets/rotate", "admin", Some(json!({}))).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let generation: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(generation["generation"], 1);
+        assert!(generation["previous_valid_until"].as_u64().is_some());
+
+        let resp = api_call(
+            &api,
+            "POST",
+            "/secrets/rotate",
+            "read_only-key",
+            Some(json!({ "overlap": 60 })),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn shows_the_generation() {
+        let api = test_secrets_api();
+        let resp = api_call(&api, "GET", "/secrets", "read_only-key", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({ "generation": 0, "previous_valid_until": null })
+        );
+    }
+}
@@ -1,13 +1,16 @@
 use crate::{
-    routes::{accounts_api, node_settings_api},
-    AccountDetails, AccountSettings, NodeStore,
+    routes::{
+        accounts_api, children_api, events_api, health_api, node_settings_api, openapi_api,
+        peering_api, secrets_api, ApiAuth, ApiKey, ApiScope, NodeEvents,
+    },
+    AccountDetails, AccountFilter, AccountPage, AccountSettings, NodeExport, NodeStore,
 };
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures::channel::mpsc::UnboundedSender;
 use http::Response;
 use interledger_btp::{BtpAccount, BtpOutgoingService};
-use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
+use interledger_ccp::{CcpRoutingAccount, RouteChanges, RoutingRelation};
 use interledger_errors::*;
 use interledger_http::{HttpAccount, HttpStore};
 use interledger_packet::{Address, ErrorCode, FulfillBuilder, RejectBuilder};
@@ -16,9 +19,15 @@ use interledger_router::RouterStore;
 use interledger_service::{
     incoming_service_fn, outgoing_service_fn, Account, AccountStore, AddressStore, Username,
 };
-use interledger_service_util::BalanceStore;
+use interledger_service_util::{
+    BalanceChange, BalanceStore, BalanceTotals, PacketDirection, PacketHistoryFilter,
+    PacketHistoryStore, PacketId, PacketOutcome, PacketRecord, VolumeLimitAccount,
+};
 use interledger_settlement::core::types::{SettlementAccount, SettlementEngineDetails};
-use interledger_stream::{PaymentNotification, StreamNotificationsStore};
+use interledger_stream::{
+    ConnectionGenerator, IncomingPayment, IncomingPaymentsStore, PaymentNotification,
+    SecretGeneration, StreamNotificationsStore,
+};
 use once_cell::sync::Lazy;
 use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
@@ -26,11 +35,32 @@ use serde_json::Value;
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::broadcast;
 use url::Url;
 use uuid::Uuid;
 use warp::{self, Filter};
 
+/// The admin token `admin`, and an API key for each scope, named after the scope
+/// (e.g. `metrics`), whose key is the name followed by `-key`
+pub fn test_api_auth() -> ApiAuth {
+    let key = |name: &str, scope: ApiScope| ApiKey {
+        name: name.to_string(),
+        key: SecretString::new(format!("{}-key", name)),
+        scopes: vec![scope],
+    };
+    ApiAuth::new(
+        "admin".to_owned(),
+        vec![
+            key("admin", ApiScope::Admin),
+            key("read_only", ApiScope::ReadOnly),
+            key("accounts_write", ApiScope::AccountsWrite),
+            key("payments_send", ApiScope::PaymentsSend),
+            key("metrics", ApiScope::Metrics),
+        ],
+    )
+}
+
 pub async fn api_call<F, T: ToString>(
     api: &F,
     method: &str,
@@ -56,7 +86,20 @@ where
 
 pub fn test_node_settings_api(
 ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    node_settings_api("admin".to_owned(), None, TestStore).recover(default_rejection_handler)
+    node_settings_api(test_api_auth(), None, TestStore).recover(default_rejection_handler)
+}
+
+pub fn test_health_api(
+    connections: usize,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    health_api(TestStore, Arc::new(move || connections)).recover(default_rejection_handler)
+}
+
+pub fn test_events_api(
+    events: NodeEvents,
+    route_changes: RouteChanges,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    events_api(test_api_auth(), TestStore, events, route_changes).recover(default_rejection_handler)
 }
 
 pub fn test_accounts_api(
@@ -83,8 +126,8 @@ pub fn test_accounts_api(
     );
     let store = TestStore;
     accounts_api(
-        Bytes::from("admin"),
-        "admin".to_owned(),
+        ConnectionGenerator::new(Bytes::from(&[0; 32][..])),
+        test_api_auth(),
         None,
         incoming,
         outgoing,
@@ -94,6 +137,109 @@ pub fn test_accounts_api(
     .recover(default_rejection_handler)
 }
 
+pub fn test_peering_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let outgoing = outgoing_service_fn(move |_request| {
+        Ok(FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: b"hello!",
+        }
+        .build())
+    });
+    let btp = BtpOutgoingService::new(
+        Address::from_str("example.alice").unwrap(),
+        outgoing.clone(),
+    );
+    peering_api(test_api_auth(), outgoing, btp, TestStore).recover(default_rejection_handler)
+}
+
+pub fn test_children_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let outgoing = outgoing_service_fn(move |_request| {
+        Ok(FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: b"hello!",
+        }
+        .build())
+    });
+    let btp = BtpOutgoingService::new(
+        Address::from_str("example.alice").unwrap(),
+        outgoing.clone(),
+    );
+    children_api(test_api_auth(), outgoing, btp, TestStore).recover(default_rejection_handler)
+}
+
+pub fn test_secrets_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let secret_seed = Bytes::from(&[0; 32][..]);
+    secrets_api(
+        test_api_auth(),
+        secret_seed.clone(),
+        ConnectionGenerator::new(secret_seed),
+        TestStore,
+    )
+    .recover(default_rejection_handler)
+}
+
+/// All of the routes of the crate, like `NodeApi::into_warp_filter`, with `alice` as the
+/// default SPSP account
+pub fn test_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let incoming = incoming_service_fn(|_request| {
+        Err(RejectBuilder {
+            code: ErrorCode::F02_UNREACHABLE,
+            message: b"No other incoming handler!",
+            data: &[],
+            triggered_by: None,
+        }
+        .build())
+    });
+    let outgoing = outgoing_service_fn(move |_request| {
+        Ok(FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: b"hello!",
+        }
+        .build())
+    });
+    let btp = BtpOutgoingService::new(
+        Address::from_str("example.alice").unwrap(),
+        outgoing.clone(),
+    );
+    let (events, _) = broadcast::channel(16);
+    accounts_api(
+        ConnectionGenerator::new(Bytes::from(&[0; 32][..])),
+        test_api_auth(),
+        Some(USERNAME.clone()),
+        incoming,
+        outgoing.clone(),
+        btp.clone(),
+        TestStore,
+    )
+    .or(peering_api(
+        test_api_auth(),
+        outgoing.clone(),
+        btp.clone(),
+        TestStore,
+    ))
+    .or(children_api(test_api_auth(), outgoing, btp, TestStore))
+    .or(events_api(
+        test_api_auth(),
+        TestStore,
+        events,
+        broadcast::channel(1).0,
+    ))
+    .or(secrets_api(
+        test_api_auth(),
+        Bytes::from(&[0; 32][..]),
+        ConnectionGenerator::new(Bytes::from(&[0; 32][..])),
+        TestStore,
+    ))
+    .or(node_settings_api(test_api_auth(), None, TestStore))
+    .or(health_api(TestStore, Arc::new(|| 0)))
+    .or(openapi_api())
+    .recover(default_rejection_handler)
+}
+
 /*
  * Lots of boilerplate implementations of all necessary traits to launch
  * the crate's APIs in unit tests
@@ -117,6 +263,9 @@ pub static DETAILS: Lazy<Option<Value>> = Lazy::new(|| {
 });
 const AUTH_PASSWORD: &str = "password";
 
+/// The maximum payment amount of the test account
+pub const MAX_PAYMENT_AMOUNT: u64 = 1_000_000;
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct TestAccount;
 
@@ -174,6 +323,12 @@ impl CcpRoutingAccount for TestAccount {
     }
 }
 
+impl VolumeLimitAccount for TestAccount {
+    fn max_payment_amount(&self) -> Option<u64> {
+        Some(MAX_PAYMENT_AMOUNT)
+    }
+}
+
 #[async_trait]
 impl AccountStore for TestStore {
     type Account = TestAccount;
@@ -185,11 +340,14 @@ impl AccountStore for TestStore {
         Ok(vec![TestAccount])
     }
 
-    // stub implementation (not used in these tests)
+    // Every username is taken, except the ones starting with `new_`
     async fn get_account_id_from_username(
         &self,
-        _username: &Username,
+        username: &Username,
     ) -> Result<Uuid, AccountStoreError> {
+        if username.as_ref().starts_with("new_") {
+            return Err(AccountStoreError::AccountNotFound(username.to_string()));
+        }
         Ok(Uuid::new_v4())
     }
 }
@@ -234,6 +392,13 @@ impl NodeStore for TestStore {
         Ok(TestAccount)
     }
 
+    async fn insert_accounts(
+        &self,
+        accounts: Vec<AccountDetails>,
+    ) -> Result<Vec<Self::Account>, NodeStoreError> {
+        Ok(accounts.iter().map(|_| TestAccount).collect())
+    }
+
     async fn delete_account(&self, _id: Uuid) -> Result<Self::Account, NodeStoreError> {
         Ok(TestAccount)
     }
@@ -258,6 +423,18 @@ impl NodeStore for TestStore {
         Ok(vec![TestAccount, TestAccount])
     }
 
+    async fn list_accounts(
+        &self,
+        _after: Option<Uuid>,
+        _limit: usize,
+        _filter: AccountFilter,
+    ) -> Result<AccountPage<Self::Account>, NodeStoreError> {
+        Ok(AccountPage {
+            accounts: vec![TestAccount],
+            next: Some(Uuid::nil()),
+        })
+    }
+
     async fn set_static_routes<R>(&self, _routes: R) -> Result<(), NodeStoreError>
     where
         R: IntoIterator<Item = (String, Uuid)> + Send + 'async_trait,
@@ -290,6 +467,33 @@ impl NodeStore for TestStore {
     ) -> Result<Option<Url>, NodeStoreError> {
         Ok(None)
     }
+
+    async fn export_node(&self) -> Result<NodeExport, NodeStoreError> {
+        Ok(NodeExport::default())
+    }
+
+    async fn import_node(&self, _export: NodeExport) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    async fn get_secret_generation(&self) -> Result<SecretGeneration, NodeStoreError> {
+        Ok(SecretGeneration::default())
+    }
+
+    async fn set_secret_generation(
+        &self,
+        _generation: SecretGeneration,
+    ) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    async fn claim_leadership(
+        &self,
+        _node_id: &str,
+        _lease: Duration,
+    ) -> Result<bool, NodeStoreError> {
+        Ok(true)
+    }
 }
 
 #[async_trait]
@@ -329,6 +533,48 @@ impl StreamNotificationsStore for TestStore {
     }
 }
 
+/// The only payment of the store, which was received on `example.alice.connection`
+fn incoming_payment(account_id: Uuid, acknowledged: bool) -> IncomingPayment {
+    IncomingPayment {
+        account_id,
+        destination: Address::from_str("example.alice.connection").unwrap(),
+        amount: 100,
+        closed: true,
+        acknowledged,
+        updated_at: 1000,
+    }
+}
+
+#[async_trait]
+impl IncomingPaymentsStore for TestStore {
+    async fn record_incoming_payment(
+        &self,
+        _account_id: Uuid,
+        _destination: &Address,
+        _amount: u64,
+        _closed: bool,
+        _timestamp: u64,
+    ) -> Result<IncomingPayment, BalanceStoreError> {
+        unimplemented!()
+    }
+
+    async fn acknowledge_incoming_payment(
+        &self,
+        account_id: Uuid,
+        destination: &Address,
+    ) -> Result<Option<IncomingPayment>, BalanceStoreError> {
+        let payment = incoming_payment(account_id, true);
+        Ok(Some(payment).filter(|payment| &payment.destination == destination))
+    }
+
+    async fn get_unacknowledged_payments(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Vec<IncomingPayment>, BalanceStoreError> {
+        Ok(vec![incoming_payment(account_id, false)])
+    }
+}
+
 #[async_trait]
 impl BalanceStore for TestStore {
     async fn get_balance(&self, _: Uuid) -> Result<i64, BalanceStoreError> {
@@ -339,6 +585,7 @@ impl BalanceStore for TestStore {
         &self,
         _: Uuid,
         _incoming_amount: u64,
+        _: Option<PacketId>,
     ) -> Result<(), BalanceStoreError> {
         unimplemented!()
     }
@@ -347,6 +594,16 @@ impl BalanceStore for TestStore {
         &self,
         _: Uuid,
         _outgoing_amount: u64,
+        _: Option<PacketId>,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        unimplemented!()
+    }
+
+    async fn update_balances_for_fulfill_and_queue_settlement(
+        &self,
+        _: Uuid,
+        _outgoing_amount: u64,
+        _: Option<PacketId>,
     ) -> Result<(i64, u64), BalanceStoreError> {
         unimplemented!()
     }
@@ -355,6 +612,7 @@ impl BalanceStore for TestStore {
         &self,
         _: Uuid,
         _incoming_amount: u64,
+        _: Option<PacketId>,
     ) -> Result<(), BalanceStoreError> {
         unimplemented!()
     }
@@ -365,6 +623,54 @@ impl BalanceStore for TestStore {
     ) -> Result<(i64, u64), BalanceStoreError> {
         unimplemented!()
     }
+
+    async fn get_balance_changes(
+        &self,
+        _: Uuid,
+        _from: u64,
+        _limit: usize,
+    ) -> Result<Vec<BalanceChange>, BalanceStoreError> {
+        Ok(Vec::new())
+    }
+
+    async fn get_balance_totals(&self, _: Uuid) -> Result<BalanceTotals, BalanceStoreError> {
+        Ok(BalanceTotals::default())
+    }
+}
+
+// The history of every account has the records 3, 2 and 1
+#[async_trait]
+impl PacketHistoryStore for TestStore {
+    async fn record_packets(&self, _: Vec<PacketRecord>) -> Result<(), BalanceStoreError> {
+        unimplemented!()
+    }
+
+    async fn get_packet_history(
+        &self,
+        account_id: Uuid,
+        filter: &PacketHistoryFilter,
+        limit: usize,
+    ) -> Result<Vec<PacketRecord>, BalanceStoreError> {
+        Ok((1..=3)
+            .rev()
+            .map(|id| PacketRecord {
+                id,
+                timestamp: id * 1000,
+                account_id,
+                direction: PacketDirection::Incoming,
+                outcome: PacketOutcome::Fulfilled,
+                amount: 100,
+                destination: "example.alice".to_string(),
+                reject_code: None,
+            })
+            .filter(|record| filter.matches(record))
+            .take(limit)
+            .collect())
+    }
+
+    async fn prune_packet_history(&self, _: u64) -> Result<u64, BalanceStoreError> {
+        unimplemented!()
+    }
 }
 
 #[async_trait]
@@ -0,0 +1,54 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+
+const SALT_LENGTH: usize = 16;
+
+/// Hashes an incoming auth token with Argon2 and a random salt. The returned
+/// string (in the PHC string format) contains the parameters and the salt.
+pub fn hash_token(token: &[u8]) -> String {
+    let mut salt: [u8; SALT_LENGTH] = [0; SALT_LENGTH];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .expect("Unable to get sufficient entropy for salt");
+    let salt = SaltString::b64_encode(&salt).expect("Salt length is valid");
+    Argon2::default()
+        .hash_password(token, &salt)
+        .expect("Unable to hash token")
+        .to_string()
+}
+
+/// Returns whether the provided value was produced by [`hash_token`]
+pub fn is_token_hash(value: &[u8]) -> bool {
+    std::str::from_utf8(value)
+        .map(|value| value.starts_with("$argon2") && PasswordHash::new(value).is_ok())
+        .unwrap_or(false)
+}
+
+/// Checks whether the token matches the hash produced by [`hash_token`]
+pub fn verify_token(hash: &[u8], token: &[u8]) -> bool {
+    let hash = match std::str::from_utf8(hash).map(PasswordHash::new) {
+        Ok(Ok(hash)) => hash,
+        _ => return false,
+    };
+    Argon2::default().verify_password(token, &hash).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_and_verifies() {
+        let hash = hash_token(b"test test");
+        assert!(is_token_hash(hash.as_bytes()));
+        assert!(!is_token_hash(b"test test"));
+        assert!(verify_token(hash.as_bytes(), b"test test"));
+        assert!(!verify_token(hash.as_bytes(), b"test"));
+        assert!(!verify_token(b"test test", b"test test"));
+        // every hash uses a different salt
+        assert_ne!(hash, hash_token(b"test test"));
+    }
+}
@@ -0,0 +1,16 @@
+//! # interledger-auth
+//!
+//! Authentication of the accounts which connect over BTP or ILP over HTTP. The tokens sent by
+//! the clients are parsed, hashed and verified, and the accounts resolved, in the same way for
+//! both protocols, so that the credential policies are only implemented once.
+
+/// Hashing of the incoming tokens with Argon2
+mod hash;
+/// Parsing of the tokens sent by the clients
+mod token;
+/// Verification of the tokens against the hashes of the accounts
+mod verifier;
+
+pub use hash::{hash_token, is_token_hash, verify_token};
+pub use token::{bearer_token, token_candidates, BEARER_TOKEN_START};
+pub use verifier::{AuthAccount, AuthError, AuthProtocol, VerifiedTokens};
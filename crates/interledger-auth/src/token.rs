@@ -0,0 +1,52 @@
+use percent_encoding::percent_decode_str;
+use std::borrow::Cow;
+
+/// The offset after which the token is in the value of an `Authorization` header,
+/// e.g. in `Bearer MyAuthToken`, `MyAuthToken` can be taken via `value[BEARER_TOKEN_START..]`
+pub const BEARER_TOKEN_START: usize = 7;
+
+/// Returns the token of the value of an `Authorization` header, or `None` if the value is too
+/// short to be a bearer token
+pub fn bearer_token(authorization: &str) -> Option<&str> {
+    if authorization.len() < BEARER_TOKEN_START {
+        return None;
+    }
+    authorization.get(BEARER_TOKEN_START..)
+}
+
+/// Returns the tokens to check, in order, for a token sent by a client: the token itself and,
+/// if it contains percent-encoded characters, the decoded token. The JavaScript plugins send
+/// the password of their URL as the token, which remains percent-encoded if it contains
+/// characters not allowed in URLs.
+pub fn token_candidates(token: &str) -> Vec<Cow<'_, str>> {
+    let mut candidates = vec![Cow::Borrowed(token)];
+    if token.contains('%') {
+        if let Ok(decoded) = percent_decode_str(token).decode_utf8() {
+            if decoded != token {
+                candidates.push(decoded);
+            }
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bearer_tokens() {
+        assert_eq!(bearer_token("Bearer token"), Some("token"));
+        assert_eq!(bearer_token("Bearer"), None);
+        // the offset is not in the middle of a character
+        assert_eq!(bearer_token("Bearer\u{e9}token"), None);
+    }
+
+    #[test]
+    fn decodes_percent_encoded_tokens() {
+        assert_eq!(token_candidates("token"), vec!["token"]);
+        assert_eq!(token_candidates("to%2Fken"), vec!["to%2Fken", "to/ken"]);
+        // invalid encodings are only checked as they are
+        assert_eq!(token_candidates("100%"), vec!["100%"]);
+    }
+}
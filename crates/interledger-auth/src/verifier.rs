@@ -0,0 +1,195 @@
+use super::hash::verify_token;
+use interledger_service::{runtime, Username};
+use lru::LruCache;
+use parking_lot::Mutex;
+use ring::digest;
+use std::fmt;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Number of verified tokens which are remembered
+const VERIFIED_TOKENS_CAPACITY: usize = 1024;
+
+/// The protocols over which the accounts authenticate, each with its own incoming token
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthProtocol {
+    Btp,
+    Http,
+}
+
+impl fmt::Display for AuthProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            AuthProtocol::Btp => "BTP",
+            AuthProtocol::Http => "ILP over HTTP",
+        })
+    }
+}
+
+/// An account whose incoming tokens are stored as hashes produced by
+/// [`hash_token`](fn.hash_token.html)
+pub trait AuthAccount {
+    /// Returns the hash of the token with which the account authenticates over the protocol,
+    /// if it may connect over it
+    fn incoming_token_hash(&self, protocol: AuthProtocol) -> Option<&[u8]>;
+}
+
+/// Why an account could not be authenticated
+#[derive(Error, Debug, PartialEq)]
+pub enum AuthError {
+    #[error("account not found")]
+    AccountNotFound,
+    #[error("account has no incoming {0} token")]
+    NoIncomingToken(AuthProtocol),
+    #[error("wrong {0} token")]
+    WrongToken(AuthProtocol),
+}
+
+/// Cache of the incoming tokens which were recently verified against their hashes.
+///
+/// Verifying a token against its Argon2 hash is deliberately slow, which is too slow to
+/// do for every incoming ILP over HTTP packet. Instead of the tokens themselves, the
+/// cache stores a SHA-256 digest of each token together with the hash it matched, so
+/// changing an account's token (and therefore its hash) invalidates the cached entry.
+pub struct VerifiedTokens {
+    digests: Mutex<LruCache<[u8; 32], ()>>,
+}
+
+impl Default for VerifiedTokens {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VerifiedTokens {
+    pub fn new() -> Self {
+        VerifiedTokens {
+            digests: Mutex::new(LruCache::new(VERIFIED_TOKENS_CAPACITY)),
+        }
+    }
+
+    /// Checks whether the token matches the hash, using the cache if possible. Otherwise
+    /// the hash is verified on the runtime's threads for blocking work, so that it doesn't
+    /// hold up the packets being forwarded.
+    pub async fn verify(&self, hash: &[u8], token: &[u8]) -> bool {
+        let key = cache_key(hash, token);
+        if self.digests.lock().get(&key).is_some() {
+            return true;
+        }
+        let (hash, token) = (hash.to_vec(), token.to_vec());
+        if runtime::run_blocking(move || verify_token(&hash, &token)).await {
+            self.digests.lock().put(key, ());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the account loaded for the username if the token matches the hash of its
+    /// incoming token for the protocol
+    pub async fn authenticate<A: AuthAccount>(
+        &self,
+        protocol: AuthProtocol,
+        username: &Username,
+        account: Option<A>,
+        token: &str,
+    ) -> Result<A, AuthError> {
+        let account = account.ok_or_else(|| {
+            warn!("No account found with {} auth", protocol);
+            AuthError::AccountNotFound
+        })?;
+        let verified = match account.incoming_token_hash(protocol) {
+            Some(hash) => self.verify(hash, token.as_bytes()).await,
+            None => {
+                debug!(
+                    "Account {} does not have an incoming {} token configured",
+                    username, protocol
+                );
+                return Err(AuthError::NoIncomingToken(protocol));
+            }
+        };
+        if verified {
+            Ok(account)
+        } else {
+            debug!(
+                "Found account {} but {} auth token was wrong",
+                username, protocol
+            );
+            Err(AuthError::WrongToken(protocol))
+        }
+    }
+}
+
+fn cache_key(hash: &[u8], token: &[u8]) -> [u8; 32] {
+    let mut context = digest::Context::new(&digest::SHA256);
+    // the length prefix prevents ambiguous concatenations
+    context.update(&(hash.len() as u64).to_be_bytes());
+    context.update(hash);
+    context.update(token);
+    let mut key = [0; 32];
+    key.copy_from_slice(context.finish().as_ref());
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hash::hash_token;
+    use super::*;
+    use std::str::FromStr;
+
+    #[tokio::test]
+    async fn cached_tokens_only_match_their_hash() {
+        let verified = VerifiedTokens::new();
+        let hash = hash_token(b"token");
+        assert!(!verified.verify(hash.as_bytes(), b"other").await);
+        assert!(verified.verify(hash.as_bytes(), b"token").await);
+        assert!(verified.verify(hash.as_bytes(), b"token").await);
+
+        let new_hash = hash_token(b"new token");
+        assert!(!verified.verify(new_hash.as_bytes(), b"token").await);
+        assert!(verified.verify(new_hash.as_bytes(), b"new token").await);
+    }
+
+    struct TestAccount(Option<String>);
+
+    impl AuthAccount for TestAccount {
+        fn incoming_token_hash(&self, protocol: AuthProtocol) -> Option<&[u8]> {
+            match protocol {
+                AuthProtocol::Btp => self.0.as_ref().map(|hash| hash.as_bytes()),
+                AuthProtocol::Http => None,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn authenticates_accounts_per_protocol() {
+        let verified = VerifiedTokens::new();
+        let alice = Username::from_str("alice").unwrap();
+        let account = || Some(TestAccount(Some(hash_token(b"token"))));
+        assert!(verified
+            .authenticate(AuthProtocol::Btp, &alice, account(), "token")
+            .await
+            .is_ok());
+        assert_eq!(
+            verified
+                .authenticate(AuthProtocol::Btp, &alice, account(), "other")
+                .await
+                .unwrap_err(),
+            AuthError::WrongToken(AuthProtocol::Btp)
+        );
+        assert_eq!(
+            verified
+                .authenticate(AuthProtocol::Http, &alice, account(), "token")
+                .await
+                .unwrap_err(),
+            AuthError::NoIncomingToken(AuthProtocol::Http)
+        );
+        assert_eq!(
+            verified
+                .authenticate::<TestAccount>(AuthProtocol::Btp, &alice, None, "token")
+                .await
+                .unwrap_err(),
+            AuthError::AccountNotFound
+        );
+    }
+}
@@ -4,7 +4,7 @@ use super::BtpAccount;
 use futures::{future::join_all, SinkExt, StreamExt, TryFutureExt};
 use interledger_errors::ApiError;
 use interledger_packet::Address;
-use interledger_service::*;
+use interledger_service::{telemetry::RedactedUrl, *};
 use rand::random;
 use thiserror::Error;
 use tokio_tungstenite::connect_async;
@@ -44,7 +44,7 @@ where
 
 #[derive(Error, Debug)]
 pub enum BtpClientError {
-    #[error("Cannot connect to BTP url: {0}. Got error {1}")]
+    #[error("Cannot connect to BTP url {} of account {0}: {2}", RedactedUrl(.1.as_str()))]
     CannotConnect(String, Url, String),
     #[error("Account is unavailable. Error: {0}")]
     Unavailable(String),
@@ -91,7 +91,7 @@ where
         .get_ilp_over_btp_outgoing_token()
         .map(|s| s.to_vec())
         .unwrap_or_default();
-    debug!("Connecting to {}", url);
+    debug!("Connecting to {}", RedactedUrl(url.as_str()));
 
     let (mut connection, _) = connect_async(url.clone())
         .map_err(|err| {
@@ -107,7 +107,7 @@ where
         "Connected to account {} (UID: {}) (URI: {}), sending auth packet",
         account.username(),
         account_id,
-        url
+        RedactedUrl(url.as_str())
     );
 
     // Send BTP authentication
@@ -143,7 +143,11 @@ where
             Ok(())
         }
         Err(err) => {
-            let msg = format!("Error sending auth packet on connection {}: {}", url, err);
+            let msg = format!(
+                "Error sending auth packet on connection {}: {}",
+                RedactedUrl(url.as_str()),
+                err
+            );
             error!("{}", msg);
             if error_on_unavailable {
                 Err(BtpClientError::Unavailable(msg))
@@ -18,8 +18,8 @@ mod service;
 mod wrapped_ws;
 
 pub use self::client::{connect_client, connect_to_service_account};
-pub use self::server::btp_service_as_filter; // This is consumed only by the node.
-pub use self::service::{BtpOutgoingService, BtpService};
+pub use self::server::{btp_service_as_filter, btp_service_as_provisioning_filter}; // This is consumed only by the node.
+pub use self::service::{BtpOutgoingService, BtpService, ConnectionEvent};
 
 use interledger_errors::BtpStoreError;
 
@@ -48,11 +48,33 @@ pub trait BtpStore {
     async fn get_btp_outgoing_accounts(&self) -> Result<Vec<Self::Account>, BtpStoreError>;
 }
 
+/// The interface for Stores which create the account of a BTP client the first time it
+/// connects, rather than requiring it to be configured in advance.
+#[async_trait]
+pub trait BtpProvisioningStore {
+    type Account: BtpAccount;
+
+    /// Load the Account authenticated by the token received via BTP, creating it if it does
+    /// not exist. The username is the one the client requested, if any.
+    async fn get_or_create_btp_account(
+        &self,
+        username: Option<&Username>,
+        token: &str,
+    ) -> Result<Self::Account, BtpStoreError>;
+}
+
 #[cfg(fuzzing)]
 pub mod fuzzing {
     pub use crate::errors::BtpPacketError;
     pub use crate::packet::{BtpPacket, Serializable};
 
+    use crate::packet::{
+        BtpError, BtpMessage, BtpResponse, BtpTransfer, ContentType, ProtocolData,
+    };
+    use arbitrary::Unstructured;
+    use interledger_packet::oer::VariableLengthTimestamp;
+    use std::borrow::Cow;
+
     #[cfg(not(feature = "strict"))]
     pub fn roundtrip_btppacket(data: &[u8]) {
         panic!("you need to enable 'strict' feature for roundtrip fuzzing");
@@ -66,6 +88,75 @@ pub mod fuzzing {
             assert_eq!(data, out);
         }
     }
+
+    /// Checks that the ILP packets read in place out of the messages are the ones
+    /// the full parser finds in them
+    pub fn peek_ilp_packet(data: &[u8]) {
+        if let Some((request_id, ilp_packet)) = crate::packet::peek_ilp_packet(data) {
+            let (parsed_request_id, protocol_data) = match BtpPacket::from_bytes(data) {
+                Ok(BtpPacket::Message(message)) => (message.request_id, message.protocol_data),
+                Ok(BtpPacket::Response(response)) => (response.request_id, response.protocol_data),
+                other => panic!("peeked an ILP packet out of {:?}", other),
+            };
+            assert_eq!(request_id, parsed_request_id);
+            assert_eq!(protocol_data.len(), 1);
+            assert_eq!(protocol_data[0].protocol_name, "ilp");
+            assert_eq!(protocol_data[0].data, ilp_packet);
+        }
+    }
+
+    /// Builds a BTP packet out of the input rather than parsing it, so that the fuzzer explores
+    /// valid packets, and checks that it parses back into the same packet once serialized
+    pub fn roundtrip_structured_btppacket(data: &[u8]) {
+        let mut input = Unstructured::new(data);
+        if let Ok(packet) = arbitrary_btp_packet(&mut input) {
+            let bytes = packet.to_bytes();
+            assert_eq!(BtpPacket::from_bytes(&bytes).unwrap(), packet);
+        }
+    }
+
+    fn arbitrary_btp_packet(input: &mut Unstructured) -> arbitrary::Result<BtpPacket> {
+        let request_id = input.arbitrary()?;
+        let mut protocol_data = Vec::new();
+        for _ in 0..input.int_in_range(0..=4)? {
+            protocol_data.push(ProtocolData {
+                protocol_name: Cow::Owned(input.arbitrary()?),
+                content_type: ContentType::from(input.arbitrary::<u8>()?),
+                data: input.arbitrary()?,
+            });
+        }
+
+        Ok(match input.int_in_range(0..=3)? {
+            0 => BtpPacket::Message(BtpMessage {
+                request_id,
+                protocol_data,
+            }),
+            1 => BtpPacket::Response(BtpResponse {
+                request_id,
+                protocol_data,
+            }),
+            2 => BtpPacket::Transfer(BtpTransfer {
+                request_id,
+                amount: input.arbitrary()?,
+                protocol_data,
+            }),
+            _ => BtpPacket::Error(BtpError {
+                request_id,
+                code: input
+                    .arbitrary::<[u8; 3]>()?
+                    .iter()
+                    .map(|byte| char::from(b'A' + byte % 26))
+                    .collect(),
+                name: input.arbitrary()?,
+                triggered_at: VariableLengthTimestamp::parse_from_rfc3339(
+                    "2018-08-31T02:53:24.899Z",
+                )
+                .unwrap(),
+                data: input.arbitrary()?,
+                protocol_data,
+            }),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -219,6 +310,7 @@ mod client_server {
                 .build())
             }))
             .await;
+        let mut connection_events = btp_service.subscribe_connection_events();
         let filter = btp_service_as_filter(btp_service.clone(), server_store);
         let server = warp::serve(filter);
         // Spawn the server and listen for incoming connections
@@ -282,8 +374,17 @@ mod client_server {
             })
             .await;
         assert!(res.is_ok());
+        assert_eq!(btp_client.connection_count(), 1);
+        assert_eq!(
+            connection_events.recv().await.unwrap(),
+            ConnectionEvent::Connected(server_acc_id)
+        );
 
         btp_service.close_connection(&server_acc_id);
+        assert_eq!(
+            connection_events.recv().await.unwrap(),
+            ConnectionEvent::Disconnected(server_acc_id)
+        );
         // after removing the connection this will fail
         let mut btp_client_clone = btp_client.clone();
         let res = btp_client_clone
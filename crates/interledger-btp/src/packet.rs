@@ -23,6 +23,7 @@ enum PacketType {
     Message = 6,
     Response = 1,
     Error = 2,
+    Transfer = 7,
     Unknown,
 }
 
@@ -37,6 +38,7 @@ impl From<u8> for PacketType {
             6 => PacketType::Message,
             1 => PacketType::Response,
             2 => PacketType::Error,
+            7 => PacketType::Transfer,
             _ => PacketType::Unknown,
         }
     }
@@ -47,6 +49,7 @@ pub enum BtpPacket {
     Message(BtpMessage),
     Response(BtpResponse),
     Error(BtpError),
+    Transfer(BtpTransfer),
 }
 
 impl Serializable<BtpPacket> for BtpPacket {
@@ -58,6 +61,7 @@ impl Serializable<BtpPacket> for BtpPacket {
             PacketType::Message => Ok(BtpPacket::Message(BtpMessage::from_bytes(bytes)?)),
             PacketType::Response => Ok(BtpPacket::Response(BtpResponse::from_bytes(bytes)?)),
             PacketType::Error => Ok(BtpPacket::Error(BtpError::from_bytes(bytes)?)),
+            PacketType::Transfer => Ok(BtpPacket::Transfer(BtpTransfer::from_bytes(bytes)?)),
             PacketType::Unknown => Err(PacketTypeError::Unknown(bytes[0]).into()),
         }
     }
@@ -67,6 +71,7 @@ impl Serializable<BtpPacket> for BtpPacket {
             BtpPacket::Message(packet) => packet.to_bytes(),
             BtpPacket::Response(packet) => packet.to_bytes(),
             BtpPacket::Error(packet) => packet.to_bytes(),
+            BtpPacket::Transfer(packet) => packet.to_bytes(),
         }
     }
 }
@@ -156,6 +161,68 @@ fn put_protocol_data<T: BufMut>(buf: &mut T, protocol_data: &[ProtocolData]) {
     }
 }
 
+/// Serializes a BTP Message, or a BTP Response if `is_response`, carrying the ILP packet as its
+/// only `ilp` protocol data. The bytes are the same as the ones of the equivalent `BtpMessage` or
+/// `BtpResponse`, but the ILP packet is written straight into the envelope instead of being
+/// copied into the protocol data first.
+pub(crate) fn ilp_packet_to_bytes(
+    request_id: u32,
+    is_response: bool,
+    ilp_packet: &[u8],
+) -> Vec<u8> {
+    const ILP_PROTOCOL_NAME: &[u8] = b"ilp";
+    let contents_len = oer::MIN_VARUINT_LEN
+        + oer::predict_var_octet_string(ILP_PROTOCOL_NAME.len())
+        + ContentType::LEN
+        + oer::predict_var_octet_string(ilp_packet.len());
+    let packet_type = if is_response {
+        PacketType::Response
+    } else {
+        PacketType::Message
+    };
+
+    let mut buf = Vec::with_capacity(
+        PacketType::LEN + REQUEST_ID_LEN + oer::predict_var_octet_string(contents_len),
+    );
+    buf.put_u8(packet_type as u8);
+    buf.put_u32(request_id);
+    buf.put_var_octet_string_length(contents_len);
+    buf.put_var_uint(1);
+    buf.put_var_octet_string(ILP_PROTOCOL_NAME);
+    buf.put_u8(ContentType::ApplicationOctetStream.into());
+    buf.put_var_octet_string(ilp_packet);
+    buf
+}
+
+/// Reads the ILP packet out of a BTP Message or Response whose only protocol data is the `ilp`
+/// entry, as written by [`ilp_packet_to_bytes`], borrowing it from the buffer. Returns `None` for
+/// any other packet, which then has to be parsed in full.
+pub(crate) fn peek_ilp_packet(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    let mut reader = bytes;
+    if reader.remaining() < PacketType::LEN + REQUEST_ID_LEN {
+        return None;
+    }
+    match PacketType::from(reader.get_u8()) {
+        PacketType::Message | PacketType::Response => {}
+        _ => return None,
+    }
+    let request_id = reader.get_u32();
+    let mut contents = reader.read_var_octet_string().ok()?;
+    if !reader.is_empty()
+        || contents.read_var_uint().ok()? != 1
+        || contents.read_var_octet_string().ok()? != b"ilp"
+        || contents.remaining() < ContentType::LEN
+    {
+        return None;
+    }
+    contents.advance(ContentType::LEN);
+    let ilp_packet = contents.read_var_octet_string().ok()?;
+    if !contents.is_empty() {
+        return None;
+    }
+    Some((request_id, ilp_packet))
+}
+
 fn check_no_trailing_bytes(buf: &[u8]) -> Result<(), BtpPacketError> {
     // according to spec, there should not be room for trailing bytes.
     // this certainly helps with fuzzing.
@@ -324,6 +391,59 @@ impl Serializable<BtpError> for BtpError {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BtpTransfer {
+    pub request_id: u32,
+    pub amount: u64,
+    pub protocol_data: Vec<ProtocolData>,
+}
+
+impl Serializable<BtpTransfer> for BtpTransfer {
+    fn from_bytes(bytes: &[u8]) -> Result<BtpTransfer, BtpPacketError> {
+        let mut reader = bytes;
+
+        const MIN_LEN: usize = PacketType::LEN + REQUEST_ID_LEN + oer::EMPTY_VARLEN_OCTETS_LEN;
+
+        if reader.remaining() < MIN_LEN {
+            return Err(OerError::UnexpectedEof.into());
+        }
+        let packet_type = reader.get_u8();
+        if PacketType::from(packet_type) != PacketType::Transfer {
+            return Err(
+                PacketTypeError::Unexpected(packet_type, PacketType::Transfer as u8).into(),
+            );
+        }
+        let request_id = reader.get_u32();
+        let mut contents = reader.read_var_octet_string()?;
+
+        check_no_trailing_bytes(reader)?;
+
+        const AMOUNT_LEN: usize = 8;
+
+        if contents.remaining() < AMOUNT_LEN {
+            return Err(OerError::UnexpectedEof.into());
+        }
+        let amount = contents.get_u64();
+        let protocol_data = read_protocol_data(&mut contents)?;
+        Ok(BtpTransfer {
+            request_id,
+            amount,
+            protocol_data,
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.put_u8(PacketType::Transfer as u8);
+        buf.put_u32(self.request_id);
+        let mut contents = Vec::new();
+        contents.put_u64(self.amount);
+        put_protocol_data(&mut contents, &self.protocol_data);
+        buf.put_var_octet_string(&*contents);
+        buf
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -608,6 +728,75 @@ mod tests {
         }
     }
 
+    mod ilp_packet {
+        use super::*;
+
+        fn with_ilp_data(data: &[u8]) -> Vec<ProtocolData> {
+            vec![ProtocolData {
+                protocol_name: "ilp".into(),
+                content_type: ContentType::ApplicationOctetStream,
+                data: data.to_vec(),
+            }]
+        }
+
+        #[test]
+        fn serializes_like_a_message() {
+            for data in &[&[0xaa; 3][..], &[0xbb; 200][..], &[0xcc; 40000][..]] {
+                let message = BtpMessage {
+                    request_id: 2,
+                    protocol_data: with_ilp_data(data),
+                };
+                assert_eq!(ilp_packet_to_bytes(2, false, data), message.to_bytes());
+            }
+        }
+
+        #[test]
+        fn serializes_like_a_response() {
+            for data in &[&[0xaa; 3][..], &[0xbb; 200][..], &[0xcc; 40000][..]] {
+                let response = BtpResponse {
+                    request_id: 129,
+                    protocol_data: with_ilp_data(data),
+                };
+                assert_eq!(ilp_packet_to_bytes(129, true, data), response.to_bytes());
+            }
+        }
+
+        #[test]
+        fn peeks_the_ilp_packet() {
+            let data = [0xbb; 200];
+            let message = ilp_packet_to_bytes(2, false, &data);
+            assert_eq!(peek_ilp_packet(&message), Some((2, &data[..])));
+            let response = ilp_packet_to_bytes(129, true, &data);
+            assert_eq!(peek_ilp_packet(&response), Some((129, &data[..])));
+        }
+
+        #[test]
+        fn does_not_peek_other_packets() {
+            let mut protocol_data = with_ilp_data(&[0xaa; 3]);
+            protocol_data.push(ProtocolData {
+                protocol_name: "other".into(),
+                content_type: ContentType::TextPlainUtf8,
+                data: b"text".to_vec(),
+            });
+            let message = BtpMessage {
+                request_id: 2,
+                protocol_data,
+            };
+            assert_eq!(peek_ilp_packet(&message.to_bytes()), None);
+
+            let mut trailing = ilp_packet_to_bytes(2, false, &[0xaa; 3]);
+            trailing.push(0);
+            assert_eq!(peek_ilp_packet(&trailing), None);
+
+            let transfer = BtpTransfer {
+                request_id: 3,
+                amount: 100,
+                protocol_data: Vec::new(),
+            };
+            assert_eq!(peek_ilp_packet(&transfer.to_bytes()), None);
+        }
+    }
+
     mod btp_error {
         use super::*;
 
@@ -633,4 +822,29 @@ mod tests {
             assert_eq!(ERROR_1.to_bytes(), *ERROR_1_SERIALIZED);
         }
     }
+
+    mod btp_transfer {
+        use super::*;
+
+        static TRANSFER_1: Lazy<BtpTransfer> = Lazy::new(|| BtpTransfer {
+            request_id: 3,
+            amount: 10,
+            protocol_data: vec![],
+        });
+        static TRANSFER_1_SERIALIZED: &[u8] =
+            &hex_literal::hex!("07000000030a000000000000000a0100");
+
+        #[test]
+        fn from_bytes() {
+            assert_eq!(
+                BtpTransfer::from_bytes(TRANSFER_1_SERIALIZED).unwrap(),
+                *TRANSFER_1
+            );
+        }
+
+        #[test]
+        fn to_bytes() {
+            assert_eq!(TRANSFER_1.to_bytes(), *TRANSFER_1_SERIALIZED);
+        }
+    }
 }
@@ -1,9 +1,11 @@
-use super::{packet::*, BtpAccount, BtpStore};
+use super::{packet::*, BtpAccount, BtpProvisioningStore, BtpStore};
 use super::{service::BtpOutgoingService, wrapped_ws::WsWrap};
-use futures::{FutureExt, Sink, Stream};
+use futures::{Future, FutureExt, Sink, Stream};
 use futures::{SinkExt, StreamExt, TryFutureExt};
+use interledger_auth::token_candidates;
 use interledger_service::*;
 use secrecy::{ExposeSecret, SecretString};
+use std::str::FromStr;
 use std::time::Duration;
 use tracing::{debug, error, warn};
 use warp::{
@@ -43,8 +45,57 @@ where
             ws.max_message_size(MAX_MESSAGE_SIZE)
                 .on_upgrade(|socket: WebSocket| {
                     // wrapper over tungstenite Websocket
-                    add_connections(socket, username, service_clone, store_clone)
-                        .map(|result| result.unwrap())
+                    add_connections(socket, service_clone, move |auth| {
+                        authenticate(store_clone, username, auth)
+                    })
+                    .map(|result| result.unwrap())
+                })
+        })
+        .boxed()
+}
+
+/// Returns a Warp Filter which, unlike the one returned by
+/// [`btp_service_as_filter`](fn.btp_service_as_filter.html), creates the account of each
+/// client the first time it connects.
+///
+/// Clients may connect to `/accounts/:username/ilp/btp` or to the root path, in which case
+/// the username is the one sent in the auth message, if any.
+pub fn btp_service_as_provisioning_filter<O, S, A>(
+    service: BtpOutgoingService<O, A>,
+    store: S,
+) -> warp::filters::BoxedFilter<(impl warp::Reply,)>
+where
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    S: BtpProvisioningStore<Account = A> + Clone + Send + Sync + 'static,
+    A: BtpAccount + Send + Sync + 'static,
+{
+    let with_username = warp::path("accounts")
+        .and(warp::path::param::<Username>())
+        .and(warp::path("ilp"))
+        .and(warp::path("btp"))
+        .and(warp::path::end())
+        .map(Some);
+    let without_username = warp::path::end().map(|| None);
+    with_username
+        .or(without_username)
+        .unify()
+        .and(warp::ws())
+        .map(move |username: Option<Username>, ws: Ws| {
+            let service_clone = service.clone();
+            let store_clone = store.clone();
+            ws.max_message_size(MAX_MESSAGE_SIZE)
+                .on_upgrade(|socket: WebSocket| {
+                    add_connections(socket, service_clone, move |auth: Auth| async move {
+                        let username = username.or(auth.username);
+                        store_clone
+                            .get_or_create_btp_account(
+                                username.as_ref(),
+                                auth.token.expose_secret(),
+                            )
+                            .map_err(|err| warn!("Could not provision BTP account: {}", err))
+                            .await
+                    })
+                    .map(|result| result.unwrap())
                 })
         })
         .boxed()
@@ -54,22 +105,21 @@ where
 /// tungstenite Websocket connection. It is needed for
 /// compatibility with the BTP service that interacts with the
 /// websocket implementation from warp and tokio-tungstenite
-async fn add_connections<O, S, A>(
+async fn add_connections<O, A, F, Fut>(
     socket: WebSocket,
-    username: Username,
     service: BtpOutgoingService<O, A>,
-    store: S,
+    authenticate: F,
 ) -> Result<(), ()>
 where
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
-    S: BtpStore<Account = A> + Clone + Send + Sync + 'static,
     A: BtpAccount + Send + Sync + 'static,
+    F: FnOnce(Auth) -> Fut,
+    Fut: Future<Output = Result<A, ()>>,
 {
     // We ignore all the errors
     let socket = socket.filter_map(|v| async move { v.ok() });
     let (account, connection) =
-        match tokio::time::timeout(WEBSOCKET_TIMEOUT, validate_auth(store, username, socket)).await
-        {
+        match tokio::time::timeout(WEBSOCKET_TIMEOUT, validate_auth(socket, authenticate)).await {
             Ok(res) => match res {
                 Ok(res) => res,
                 Err(_) => {
@@ -98,28 +148,42 @@ where
 
 struct Auth {
     request_id: u32,
+    /// The username sent as "auth_username", which the JavaScript plugin takes from its URL
+    username: Option<Username>,
     token: SecretString,
 }
 
-async fn validate_auth<S, A>(
-    store: S,
-    username: Username,
+/// Loads the account of the username in the path from the store
+async fn authenticate<S, A>(store: S, username: Username, auth: Auth) -> Result<A, ()>
+where
+    S: BtpStore<Account = A>,
+    A: BtpAccount,
+{
+    debug!("Got BTP connection for username: {}", username);
+    for token in token_candidates(auth.token.expose_secret()) {
+        if let Ok(account) = store.get_account_from_btp_auth(&username, &token).await {
+            return Ok(account);
+        }
+    }
+    warn!("BTP connection does not correspond to an account");
+    Err(())
+}
+
+async fn validate_auth<A, F, Fut>(
     connection: impl Stream<Item = Message> + Sink<Message>,
+    authenticate: F,
 ) -> Result<(A, impl Stream<Item = Message> + Sink<Message>), ()>
 where
-    S: BtpStore<Account = A> + 'static,
-    A: BtpAccount + 'static,
+    F: FnOnce(Auth) -> Fut,
+    Fut: Future<Output = Result<A, ()>>,
 {
     let (auth, mut connection) = get_auth(Box::pin(connection)).await?;
-    debug!("Got BTP connection for username: {}", username);
-    let account = store
-        .get_account_from_btp_auth(&username, auth.token.expose_secret())
-        .map_err(move |_| warn!("BTP connection does not correspond to an account"))
-        .await?;
+    let request_id = auth.request_id;
+    let account = authenticate(auth).await?;
 
     let auth_response = Message::binary(
         BtpResponse {
-            request_id: auth.request_id,
+            request_id,
             protocol_data: Vec::new(),
         }
         .to_bytes(),
@@ -166,19 +230,27 @@ fn parse_auth(ws_packet: Option<Message>) -> Option<Auth> {
                 Ok(message) => {
                     let request_id = message.request_id;
                     let mut token: Option<String> = None;
+                    let mut username: Option<Username> = None;
                     // The primary data should be the "auth" with empty data
                     // The secondary data MUST have the "auth_token" with the authorization
                     // token set as the data field
+                    // The JavaScript plugin also sends the username of its URL, possibly
+                    // empty, as "auth_username", which is only used when the path has none
                     for protocol_data in message.protocol_data.iter() {
                         let protocol_name: &str = protocol_data.protocol_name.as_ref();
                         if protocol_name == "auth_token" {
                             token = String::from_utf8(protocol_data.data.clone()).ok();
+                        } else if protocol_name == "auth_username" {
+                            username = std::str::from_utf8(&protocol_data.data)
+                                .ok()
+                                .and_then(|username| Username::from_str(username).ok());
                         }
                     }
 
                     if let Some(token) = token {
                         return Some(Auth {
                             request_id,
+                            username,
                             token: SecretString::new(token),
                         });
                     } else {
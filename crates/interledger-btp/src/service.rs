@@ -1,22 +1,38 @@
 use super::{packet::*, BtpAccount};
-use async_trait::async_trait;
 use bytes::BytesMut;
 use futures::{
     channel::{
         mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
         oneshot,
     },
-    future, FutureExt, Sink, Stream, StreamExt,
+    future::{self, Either},
+    FutureExt, Sink, SinkExt, Stream, StreamExt,
+};
+use interledger_packet::{
+    pool, Address, ErrorCode, Fulfill, Packet, Prepare, Reject, RejectBuilder,
+};
+use interledger_service::{
+    metrics,
+    telemetry::{propagated_correlation_id, sample_packet, Correlate, CorrelationId, Redacted},
+    *,
 };
-use interledger_packet::{Address, ErrorCode, Fulfill, Packet, Prepare, Reject, RejectBuilder};
-use interledger_service::*;
 use once_cell::sync::Lazy;
 use parking_lot::{Mutex, RwLock};
 use rand::random;
 use std::collections::HashMap;
-use std::{convert::TryFrom, iter::IntoIterator, marker::PhantomData, sync::Arc, time::Duration};
+use std::{
+    convert::TryFrom,
+    iter::IntoIterator,
+    marker::PhantomData,
+    str,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use stream_cancel::{Trigger, Valve};
-use tokio::time;
+use tokio::{sync::broadcast, time};
 use tokio_tungstenite::tungstenite::Message;
 use tracing::{debug, error, trace, warn};
 use uuid::Uuid;
@@ -31,8 +47,84 @@ static PONG: Lazy<Message> = Lazy::new(|| Message::Pong(Vec::with_capacity(0)));
 // with us
 const SEND_MSG_TIMEOUT: Duration = Duration::from_secs(30);
 
+// Number of connection events buffered for subscribers which are slow to receive them
+const CONNECTION_EVENTS_CAPACITY: usize = 64;
+
+/// A WebSocket connection to an account was opened or closed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionEvent {
+    Connected(Uuid),
+    Disconnected(Uuid),
+}
+
 type IlpResultChannel = oneshot::Sender<Result<Fulfill, Reject>>;
-type IncomingRequestBuffer<A> = UnboundedReceiver<(A, u32, Prepare)>;
+/// An incoming Prepare packet, with the ID of the connection it was received on, its request ID
+/// and the correlation ID sent by the peer, if any
+type IncomingPrepare<A> = (A, u64, u32, Prepare, Option<CorrelationId>);
+type IncomingRequestBuffer<A> = UnboundedReceiver<IncomingPrepare<A>>;
+
+/// The queues of the messages written to a WebSocket connection. When the connection is
+/// backed up, the responses are written before the requests queued at the same time,
+/// because a delayed Fulfill or Reject may expire upstream while a delayed Prepare only
+/// adds latency.
+#[derive(Clone)]
+struct ConnectionQueues {
+    /// Fulfills, Rejects, BTP responses, Pings and Pongs
+    responses: UnboundedSender<Message>,
+    /// Prepares and the Close frame
+    requests: UnboundedSender<Message>,
+}
+
+/// One of the WebSocket connections to an account
+#[derive(Clone)]
+struct Connection {
+    /// Tells apart the connections to the same account. The responses to the Prepare
+    /// packets are sent on the connection they were received on.
+    id: u64,
+    queues: ConnectionQueues,
+}
+
+/// The open connections of each account
+type Connections = RwLock<HashMap<Uuid, Vec<Connection>>>;
+
+/// Removes the connection from the account's connections. The account is disconnected
+/// once none of its connections are left
+fn remove_connection(
+    connections: &Connections,
+    connection_events: &broadcast::Sender<ConnectionEvent>,
+    account_id: Uuid,
+    connection_id: u64,
+) {
+    let mut connections = connections.write();
+    if let Some(account_connections) = connections.get_mut(&account_id) {
+        let count = account_connections.len();
+        account_connections.retain(|connection| connection.id != connection_id);
+        metrics::increment_counter(
+            "btp.connections.closed",
+            &[],
+            (count - account_connections.len()) as u64,
+        );
+        if account_connections.is_empty() {
+            connections.remove(&account_id);
+            // There may be no subscribers
+            let _ = connection_events.send(ConnectionEvent::Disconnected(account_id));
+        }
+    }
+}
+
+/// Returns the connection of the account with the given id, if it is still open
+fn find_connection(
+    connections: &Connections,
+    account_id: Uuid,
+    connection_id: u64,
+) -> Option<Connection> {
+    connections
+        .read()
+        .get(&account_id)?
+        .iter()
+        .find(|connection| connection.id == connection_id)
+        .cloned()
+}
 
 /// The BtpOutgoingService wraps all BTP/WebSocket connections that come
 /// in on the given address. It implements OutgoingService for sending
@@ -45,14 +137,18 @@ type IncomingRequestBuffer<A> = UnboundedReceiver<(A, u32, Prepare)>;
 #[derive(Clone)]
 pub struct BtpOutgoingService<O, A: Account> {
     ilp_address: Address,
-    /// Outgoing messages for the receiver of the websocket indexed by account uid
-    connections: Arc<RwLock<HashMap<Uuid, UnboundedSender<Message>>>>,
+    /// Outgoing messages for the receivers of the websockets indexed by account uid.
+    /// An account may be connected more than once, e.g. from several processes of the peer.
+    connections: Arc<Connections>,
+    /// Spreads the outgoing requests over the connections of each account in turn
+    next_connection: Arc<AtomicUsize>,
     pending_outgoing: Arc<Mutex<HashMap<u32, IlpResultChannel>>>,
     pending_incoming: Arc<Mutex<Option<IncomingRequestBuffer<A>>>>,
-    incoming_sender: UnboundedSender<(A, u32, Prepare)>,
+    incoming_sender: UnboundedSender<IncomingPrepare<A>>,
     next: O,
     close_all_connections: Arc<Mutex<Option<Trigger>>>,
     stream_valve: Arc<Valve>,
+    connection_events: broadcast::Sender<ConnectionEvent>,
 }
 
 /// Handle the packets based on whether they are an incoming request or a response to something we sent.
@@ -66,38 +162,45 @@ pub struct BtpOutgoingService<O, A: Account> {
 #[inline]
 async fn handle_message<A: BtpAccount>(
     message: Message,
+    connection_id: u64,
     tx_clone: UnboundedSender<Message>,
     account: A,
     pending_requests: Arc<Mutex<HashMap<u32, IlpResultChannel>>>,
-    incoming_sender: UnboundedSender<(A, u32, Prepare)>,
+    incoming_sender: UnboundedSender<IncomingPrepare<A>>,
 ) {
     if message.is_binary() {
-        match parse_ilp_packet(message) {
+        match parse_btp_packet(message) {
             // Queues up the prepare packet
-            Ok((request_id, Packet::Prepare(prepare))) => {
-                trace!(
-                    "Got incoming Prepare packet on request ID: {} {:?}",
-                    request_id,
-                    prepare
-                );
+            Ok((request_id, BtpContents::Ilp(Packet::Prepare(prepare), correlation_id))) => {
+                if sample_packet() {
+                    trace!(
+                        "Got incoming Prepare packet on request ID: {} {:?}",
+                        request_id,
+                        prepare
+                    );
+                }
                 let _ = incoming_sender
-                    .unbounded_send((account, request_id, prepare))
+                    .unbounded_send((account, connection_id, request_id, prepare, correlation_id))
                     .map_err(|err| error!("Unable to buffer incoming request: {:?}", err));
             }
             // Sends the fulfill/reject to the outgoing service
-            Ok((request_id, Packet::Fulfill(fulfill))) => {
-                trace!("Got fulfill response to request id {}", request_id);
+            Ok((request_id, BtpContents::Ilp(Packet::Fulfill(fulfill), _))) => {
+                if sample_packet() {
+                    trace!("Got fulfill response to request id {}", request_id);
+                }
                 if let Some(channel) = (*pending_requests.lock()).remove(&request_id) {
-                    let _ = channel.send(Ok(fulfill)).map_err(|fulfill| error!("Error forwarding Fulfill packet back to the Future that sent the Prepare: {:?}", fulfill));
+                    let _ = channel.send(Ok(fulfill)).map_err(|fulfill| error!("Error forwarding Fulfill packet back to the Future that sent the Prepare: {:?}", Redacted(fulfill)));
                 } else {
                     warn!(
                         "Got Fulfill packet that does not match an outgoing Prepare we sent: {:?}",
-                        fulfill
+                        Redacted(fulfill)
                     );
                 }
             }
-            Ok((request_id, Packet::Reject(reject))) => {
-                trace!("Got reject response to request id {}", request_id);
+            Ok((request_id, BtpContents::Ilp(Packet::Reject(reject), _))) => {
+                if sample_packet() {
+                    trace!("Got reject response to request id {}", request_id);
+                }
                 if let Some(channel) = (*pending_requests.lock()).remove(&request_id) {
                     let _ = channel.send(Err(reject)).map_err(|reject| error!("Error forwarding Reject packet back to the Future that sent the Prepare: {:?}", reject));
                 } else {
@@ -107,6 +210,22 @@ async fn handle_message<A: BtpAccount>(
                     );
                 }
             }
+            // Payments are settled by the settlement engines rather than with BTP Transfers,
+            // but clients like the JavaScript ilp-plugin-btp wait for the Transfer to be acknowledged
+            Ok((request_id, BtpContents::Transfer(amount))) => {
+                debug!(
+                    "Acknowledging BTP Transfer of {} from account {} without crediting it",
+                    amount,
+                    account.id()
+                );
+                let response = BtpResponse {
+                    request_id,
+                    protocol_data: Vec::new(),
+                };
+                let _ = tx_clone
+                    .unbounded_send(Message::binary(response.to_bytes()))
+                    .map_err(|err| error!("Error acknowledging BTP Transfer: {:?}", err));
+            }
             Err(_) => {
                 debug!("Unable to parse ILP packet from BTP packet (if this is the first time this appears, the packet was probably the auth response)");
                 // TODO Send error back
@@ -121,6 +240,42 @@ async fn handle_message<A: BtpAccount>(
     }
 }
 
+/// Writes the queued messages to the WebSocket connection, the responses first, until both
+/// queues are closed or the connection fails. The connection is only flushed once both
+/// queues are empty, so that the messages queued together are sent together
+async fn write_prioritized<W>(
+    mut write: W,
+    mut responses: UnboundedReceiver<Message>,
+    mut requests: UnboundedReceiver<Message>,
+) where
+    W: Sink<Message> + Unpin,
+{
+    loop {
+        let queued = match responses.try_next() {
+            Ok(Some(message)) => Some(message),
+            _ => requests.try_next().ok().flatten(),
+        };
+        let message = match queued {
+            Some(message) => message,
+            None => {
+                if write.flush().await.is_err() {
+                    return;
+                }
+                tokio::select! {
+                    biased;
+                    Some(message) = responses.next() => message,
+                    Some(message) = requests.next() => message,
+                    else => break,
+                }
+            }
+        };
+        if write.feed(message).await.is_err() {
+            return;
+        }
+    }
+    let _ = write.close().await;
+}
+
 impl<O, A> BtpOutgoingService<O, A>
 where
     O: OutgoingService<A> + Clone,
@@ -132,18 +287,47 @@ where
         BtpOutgoingService {
             ilp_address,
             connections: Arc::new(RwLock::new(HashMap::new())),
+            next_connection: Arc::new(AtomicUsize::new(0)),
             pending_outgoing: Arc::new(Mutex::new(HashMap::new())),
             pending_incoming: Arc::new(Mutex::new(Some(incoming_receiver))),
             incoming_sender,
             next,
             close_all_connections: Arc::new(Mutex::new(Some(close_all_connections))),
             stream_valve: Arc::new(stream_valve),
+            connection_events: broadcast::channel(CONNECTION_EVENTS_CAPACITY).0,
         }
     }
 
-    /// Deletes the websocket associated with the provided `account_id`
+    /// Deletes the websockets associated with the provided `account_id`
     pub fn close_connection(&self, account_id: &Uuid) {
-        self.connections.write().remove(account_id);
+        if let Some(connections) = self.connections.write().remove(account_id) {
+            metrics::increment_counter("btp.connections.closed", &[], connections.len() as u64);
+            // There may be no subscribers
+            let _ = self
+                .connection_events
+                .send(ConnectionEvent::Disconnected(*account_id));
+        }
+    }
+
+    /// Returns the number of open WebSocket connections
+    pub fn connection_count(&self) -> usize {
+        self.connections.read().values().map(Vec::len).sum()
+    }
+
+    /// Picks the connection to the account which the next outgoing request is sent on
+    fn pick_connection(&self, account_id: &Uuid) -> Option<Connection> {
+        let connections = self.connections.read();
+        let connections = connections.get(account_id)?;
+        if connections.is_empty() {
+            return None;
+        }
+        let index = self.next_connection.fetch_add(1, Ordering::Relaxed) % connections.len();
+        Some(connections[index].clone())
+    }
+
+    /// Subscribes to the events of WebSocket connections being opened and closed
+    pub fn subscribe_connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.connection_events.subscribe()
     }
 
     /// Close all of the open WebSocket connections
@@ -151,6 +335,26 @@ where
     // The problem is that the WS client can be a server too, so it's not clear when we are done with it
     pub fn close(&self) {
         debug!("Closing all WebSocket connections");
+        // Tell the peers that the connections are closing. The Close frames are written
+        // after the queued messages, before the writers stop once the other senders of
+        // each connection are dropped
+        for (account_id, connections) in self.connections.write().drain() {
+            metrics::increment_counter("btp.connections.closed", &[], connections.len() as u64);
+            for connection in connections {
+                if connection
+                    .queues
+                    .requests
+                    .unbounded_send(Message::Close(None))
+                    .is_err()
+                {
+                    trace!("Connection to account {} was already closed", account_id);
+                }
+            }
+            // There may be no subscribers
+            let _ = self
+                .connection_events
+                .send(ConnectionEvent::Disconnected(account_id));
+        }
         self.close_all_connections.lock().take();
     }
 
@@ -158,20 +362,52 @@ where
     // incoming Prepare packets are buffered in a channel (until an IncomingService is added
     // via the handle_incoming method), and ILP Fulfill and Reject packets will be
     // sent back to the Future that sent the outgoing request originally.
+    // The connection is added to the ones already open to the account, if any, and it is
+    // removed once it closes.
     pub(crate) fn add_connection(
         &self,
         account: A,
         ws_stream: impl Stream<Item = Message> + Sink<Message> + Send + 'static,
     ) {
         let account_id = account.id();
-        // Set up a channel to forward outgoing packets to the WebSocket connection
+        let connection_id = random::<u64>();
+        // Set up the channels to forward outgoing packets to the WebSocket connection
         let (client_tx, client_rx) = unbounded();
+        let (requests_tx, requests_rx) = unbounded();
+
+        // Save the sender side of the channel so we have a way to forward outgoing requests to the WebSocket,
+        // before reading from it so that it is removed once the connection closes
+        let connection = Connection {
+            id: connection_id,
+            queues: ConnectionQueues {
+                responses: client_tx.clone(),
+                requests: requests_tx,
+            },
+        };
+        let first_connection = {
+            let mut connections = self.connections.write();
+            let account_connections = connections.entry(account_id).or_insert_with(Vec::new);
+            account_connections.push(connection);
+            account_connections.len() == 1
+        };
+        metrics::increment_counter("btp.connections.opened", &[], 1);
+        if first_connection {
+            let _ = self
+                .connection_events
+                .send(ConnectionEvent::Connected(account_id));
+        } else {
+            debug!(
+                "Added another WebSocket connection for account: {}",
+                account_id
+            );
+        }
+
         let (write, read) = ws_stream.split();
         let (close_connection, valve) = Valve::new();
 
         // tx -> rx -> write -> our peer
         // Responsible mainly for responding to Pings
-        let write_to_ws = client_rx.map(Ok).forward(write).then(move |_| {
+        let write_to_ws = write_prioritized(write, client_rx, requests_rx).then(move |_| {
             async move {
                 debug!(
                     "Finished forwarding to WebSocket stream for account: {}",
@@ -191,6 +427,7 @@ where
         let handle_message_fn = move |msg: Message| {
             handle_message(
                 msg,
+                connection_id,
                 client_tx_clone.clone(),
                 account.clone(),
                 pending_outgoing.clone(),
@@ -201,11 +438,15 @@ where
         // Close connections trigger
         let read = valve.wrap(read); // close when `write_to_ws` calls `drop(connection)`
         let read = self.stream_valve.wrap(read);
+        let connection_events = self.connection_events.clone();
+        let connections = self.connections.clone();
         let read_from_ws = read.for_each(handle_message_fn).then(move |_| async move {
             debug!(
                 "Finished reading from WebSocket stream for account: {}",
                 account_id
             );
+            // The account is only disconnected once its last connection closed
+            remove_connection(&connections, &connection_events, account_id, connection_id);
             Ok::<(), ()>(())
         });
         tokio::spawn(read_from_ws);
@@ -228,14 +469,26 @@ where
             future::ready(())
         });
         tokio::spawn(send_pings);
-
-        // Save the sender side of the channel so we have a way to forward outgoing requests to the WebSocket
-        self.connections.write().insert(account_id, client_tx);
     }
 
     /// Convert this BtpOutgoingService into a bidirectional BtpService by adding a handler for incoming requests.
     /// This will automatically pull all incoming Prepare packets from the channel buffer and call the IncomingService with them.
+    /// The packets are handled one at a time.
     pub async fn handle_incoming<I>(self, incoming_handler: I) -> BtpService<I, O, A>
+    where
+        I: IncomingService<A> + Clone + Send + 'static,
+    {
+        self.handle_incoming_concurrently(incoming_handler, 1).await
+    }
+
+    /// Like `handle_incoming`, but handles up to `concurrency` incoming Prepare packets at
+    /// the same time. The responses may be sent in a different order than the Prepare
+    /// packets were received.
+    pub async fn handle_incoming_concurrently<I>(
+        self,
+        incoming_handler: I,
+        concurrency: usize,
+    ) -> BtpService<I, O, A>
     where
         I: IncomingService<A> + Clone + Send + 'static,
     {
@@ -244,46 +497,65 @@ where
         // Now that we're adding an incoming handler, this will spawn a task to read
         // all Prepare packets from the buffer, handle them, and send the responses back
         let connections_clone = self.connections.clone();
-        let mut handle_pending_incoming = self
+        let handle_pending_incoming = self
             .pending_incoming
             .lock()
             .take()
             .expect("handle_incoming can only be called once");
         let handle_pending_incoming_fut = async move {
-            while let Some((account, request_id, prepare)) = handle_pending_incoming.next().await {
-                let account_id = account.id();
-                let connections_clone = connections_clone.clone();
-                let request = IncomingRequest {
-                    from: account,
-                    prepare,
-                };
-                trace!(
-                    "Handling incoming request {} from account: {} (id: {})",
-                    request_id,
-                    request.from.username(),
-                    request.from.id()
-                );
-                let mut handler = incoming_handler.clone();
-                let packet = match handler.handle_request(request).await {
-                    Ok(fulfill) => Packet::Fulfill(fulfill),
-                    Err(reject) => Packet::Reject(reject),
-                };
+            handle_pending_incoming
+                .for_each_concurrent(concurrency.max(1), |incoming_prepare| {
+                    let (account, connection_id, request_id, prepare, correlation_id) =
+                        incoming_prepare;
+                    let account_id = account.id();
+                    let connections_clone = connections_clone.clone();
+                    let incoming_handler = incoming_handler.clone();
+                    async move {
+                        let request = IncomingRequest {
+                            from: account,
+                            prepare,
+                        };
+                        if sample_packet() {
+                            trace!(
+                                "Handling incoming request {} from account: {} (id: {})",
+                                request_id,
+                                request.from.username(),
+                                request.from.id()
+                            );
+                        }
+                        let mut handler = incoming_handler;
+                        // The services handling the request log it with the peer's correlation ID
+                        let result = match correlation_id {
+                            Some(id) => handler.handle_request(request).correlated(id).await,
+                            None => handler.handle_request(request).await,
+                        };
+                        let packet = match result {
+                            Ok(fulfill) => Packet::Fulfill(fulfill),
+                            Err(reject) => Packet::Reject(reject),
+                        };
 
-                if let Some(connection) = connections_clone.clone().read().get(&account_id) {
-                    let message = ilp_packet_to_ws_message(request_id, packet);
-                    let _ = connection.unbounded_send(message).map_err(move |err| {
-                        error!(
-                            "Error sending response to account: {} {:?}",
-                            account_id, err
-                        )
-                    });
-                } else {
-                    error!(
-                        "Error sending response to account: {}, connection was closed. {:?}",
-                        account_id, packet
-                    );
-                }
-            }
+                        // The request ID only identifies the request on the connection it was
+                        // received on, so the response can't be sent on another one
+                        let connection =
+                            find_connection(&connections_clone, account_id, connection_id);
+                        if let Some(connection) = connection {
+                            let message = ilp_packet_to_ws_message(request_id, packet);
+                            if let Err(err) = connection.queues.responses.unbounded_send(message) {
+                                error!(
+                                    "Error sending response to account: {} {:?}",
+                                    account_id, err
+                                );
+                            }
+                        } else {
+                            error!(
+                                "Error sending response to account: {}, connection was closed. {:?}",
+                                account_id,
+                                Redacted(packet)
+                            );
+                        }
+                    }
+                })
+                .await;
 
             trace!("Finished reading from pending_incoming buffer");
             Ok::<(), ()>(())
@@ -298,22 +570,39 @@ where
     }
 }
 
-#[async_trait]
 impl<O, A> OutgoingService<A> for BtpOutgoingService<O, A>
 where
     O: OutgoingService<A> + Send + Sync + Clone + 'static,
     A: BtpAccount + Send + Sync + Clone + 'static,
 {
+    /// Requests which are passed through to the `next` handler return its future, the
+    /// ones sent over a connection are boxed.
+    type Future<'a> = Either<O::Future<'a>, BoxedIlpFuture<'a>>;
+
     /// Send an outgoing request to one of the open connections.
     ///
-    /// If there is no open connection for the Account specified in `request.to`, the
-    /// request will be passed through to the `next` handler.
-    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+    /// If the Account specified in `request.to` has several open connections, they are
+    /// used in turn, skipping the ones which were closed. If there is no open connection
+    /// for the Account, the request will be passed through to the `next` handler.
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
         let account_id = request.to.id();
 
-        let found = self.connections.read().get(&account_id).cloned();
+        let mut connection = match self.pick_connection(&account_id) {
+            Some(connection) => connection,
+            None => {
+                if request.to.get_ilp_over_btp_url().is_some()
+                    || request.to.get_ilp_over_btp_outgoing_token().is_some()
+                {
+                    trace!(
+                        "No open connection for account: {}, forwarding request to the next service",
+                        request.to.username()
+                    );
+                }
+                return Either::Left(self.next.send_request(request));
+            }
+        };
 
-        if let Some(connection) = found {
+        Either::Right(Box::pin(async move {
             let request_id = random::<u32>();
             let ilp_address = self.ilp_address.clone();
 
@@ -330,64 +619,89 @@ where
 
             // Connection is an unbounded sender which sends to the rx that
             // forwards to the sink which sends the data over
-            match connection.unbounded_send(ilp_packet_to_ws_message(
-                request_id,
-                Packet::Prepare(request.prepare),
-            )) {
-                Ok(_) => {
-                    let (sender, receiver) = oneshot::channel();
-                    (*self.pending_outgoing.lock()).insert(request_id, sender);
-
-                    // Wrap the receiver with a timeout to ensure we do not
-                    // wait too long if the other party has disconnected
-                    // FIXME: this causes the test case to take 30s
-                    let result = tokio::time::timeout(SEND_MSG_TIMEOUT, receiver).await;
-
-                    let result = match result {
-                        Ok(packet) => packet,
-                        Err(err) => {
-                            error!("Request timed out. Did the peer disconnect? Err: {}", err);
-                            // Assume that such a long timeout means that the peer closed their
-                            // connection with us, so we'll remove the pending request and the websocket
-                            (*self.pending_outgoing.lock()).remove(&request_id);
-                            self.close_connection(&request.to.id());
-
-                            return Err(RejectBuilder {
-                                code: ErrorCode::R00_TRANSFER_TIMED_OUT,
-                                message: &[],
-                                triggered_by: Some(&ilp_address),
-                                data: &[],
-                            }
-                            .build());
-                        }
-                    };
-
-                    // Drop the trigger here since we've gotten the response
-                    // and don't need to keep the connections open if this was the
-                    // last thing we were waiting for
-                    drop(keep_connections_open);
-                    match result {
-                        // This can be either a reject or a fulfill packet
-                        Ok(packet) => packet,
-                        Err(err) => {
-                            error!(
-                                "Sending request {} to account {} failed: {:?}",
-                                request_id, account_id, err
-                            );
-                            Err(RejectBuilder {
-                                code: ErrorCode::T00_INTERNAL_ERROR,
-                                message: &[],
-                                triggered_by: Some(&ilp_address),
-                                data: &[],
-                            }
-                            .build())
+            let mut message = match propagated_correlation_id() {
+                Some(id) => {
+                    prepare_to_ws_message_with_correlation_id(request_id, request.prepare, id)
+                }
+                None => ilp_packet_to_ws_message(request_id, Packet::Prepare(request.prepare)),
+            };
+            // If the connection was closed, fail over to the other connections of the account
+            while let Err(send_error) = connection.queues.requests.unbounded_send(message) {
+                remove_connection(
+                    &self.connections,
+                    &self.connection_events,
+                    account_id,
+                    connection.id,
+                );
+                connection = match self.pick_connection(&account_id) {
+                    Some(connection) => {
+                        debug!(
+                            "Connection to account {} was closed, failing over to another one",
+                            account_id
+                        );
+                        connection
+                    }
+                    None => {
+                        error!(
+                            "Error sending websocket message for request {} to account {}: {:?}",
+                            request_id, account_id, send_error
+                        );
+                        return Err(RejectBuilder {
+                            code: ErrorCode::T00_INTERNAL_ERROR,
+                            message: &[],
+                            triggered_by: Some(&ilp_address),
+                            data: &[],
                         }
+                        .build());
                     }
+                };
+                message = send_error.into_inner();
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            (*self.pending_outgoing.lock()).insert(request_id, sender);
+
+            // Wrap the receiver with a timeout to ensure we do not
+            // wait too long if the other party has disconnected
+            // FIXME: this causes the test case to take 30s
+            let result = tokio::time::timeout(SEND_MSG_TIMEOUT, receiver).await;
+
+            let result = match result {
+                Ok(packet) => packet,
+                Err(err) => {
+                    error!("Request timed out. Did the peer disconnect? Err: {}", err);
+                    // Assume that such a long timeout means that the peer closed their
+                    // connection with us, so we'll remove the pending request and the websocket.
+                    // The account's other connections, if any, are used for the next requests
+                    (*self.pending_outgoing.lock()).remove(&request_id);
+                    remove_connection(
+                        &self.connections,
+                        &self.connection_events,
+                        account_id,
+                        connection.id,
+                    );
+
+                    return Err(RejectBuilder {
+                        code: ErrorCode::R00_TRANSFER_TIMED_OUT,
+                        message: &[],
+                        triggered_by: Some(&ilp_address),
+                        data: &[],
+                    }
+                    .build());
                 }
-                Err(send_error) => {
+            };
+
+            // Drop the trigger here since we've gotten the response
+            // and don't need to keep the connections open if this was the
+            // last thing we were waiting for
+            drop(keep_connections_open);
+            match result {
+                // This can be either a reject or a fulfill packet
+                Ok(packet) => packet,
+                Err(err) => {
                     error!(
-                        "Error sending websocket message for request {} to account {}: {:?}",
-                        request_id, account_id, send_error
+                        "Sending request {} to account {} failed: {:?}",
+                        request_id, account_id, err
                     );
                     Err(RejectBuilder {
                         code: ErrorCode::T00_INTERNAL_ERROR,
@@ -398,17 +712,7 @@ where
                     .build())
                 }
             }
-        } else {
-            if request.to.get_ilp_over_btp_url().is_some()
-                || request.to.get_ilp_over_btp_outgoing_token().is_some()
-            {
-                trace!(
-                    "No open connection for account: {}, forwarding request to the next service",
-                    request.to.username()
-                );
-            }
-            self.next.send_request(request).await
-        }
+        }))
     }
 }
 
@@ -432,36 +736,73 @@ where
     pub fn close_connection(&self, account_id: &Uuid) {
         self.outgoing.close_connection(account_id);
     }
+
+    /// Returns the number of open WebSocket connections
+    pub fn connection_count(&self) -> usize {
+        self.outgoing.connection_count()
+    }
+
+    /// Subscribes to the events of WebSocket connections being opened and closed
+    pub fn subscribe_connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.outgoing.subscribe_connection_events()
+    }
 }
 
-#[async_trait]
 impl<I, O, A> OutgoingService<A> for BtpService<I, O, A>
 where
     I: Send, // This is a async/await requirement
     O: OutgoingService<A> + Send + Sync + Clone + 'static,
     A: BtpAccount + Send + Sync + Clone + 'static,
 {
+    type Future<'a>
+        = <BtpOutgoingService<O, A> as OutgoingService<A>>::Future<'a>
+    where
+        Self: 'a;
+
     /// Send an outgoing request to one of the open connections.
     ///
     /// If there is no open connection for the Account specified in `request.to`, the
     /// request will be passed through to the `next` handler.
-    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
-        self.outgoing.send_request(request).await
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        self.outgoing.send_request(request)
     }
 }
 
+/// Name of the protocol data in which the correlation ID of a Prepare is sent along with it
+const CORRELATION_ID_PROTOCOL_NAME: &str = "correlation_id";
+
+/// The contents of a BTP packet which was received from a peer
+enum BtpContents {
+    /// An ILP packet carried in the `ilp` protocol data of a Message or Response, and the
+    /// correlation ID carried in its `correlation_id` protocol data, if any
+    Ilp(Packet, Option<CorrelationId>),
+    /// The amount of a Transfer
+    Transfer(u64),
+}
+
 #[allow(clippy::cognitive_complexity)]
-fn parse_ilp_packet(message: Message) -> Result<(u32, Packet), ()> {
+fn parse_btp_packet(message: Message) -> Result<(u32, BtpContents), ()> {
     if let Message::Binary(data) = message {
-        let (request_id, ilp_data) = match BtpPacket::from_bytes(&data) {
+        // Most packets only carry an ILP packet, which is read straight out of the message
+        if let Some((request_id, ilp_data)) = peek_ilp_packet(&data) {
+            return ilp_packet_from_slice(ilp_data)
+                .map(|packet| (request_id, BtpContents::Ilp(packet, None)));
+        }
+
+        let (request_id, ilp_data, correlation_id) = match BtpPacket::from_bytes(&data) {
             Ok(BtpPacket::Message(message)) => {
+                let correlation_id = message
+                    .protocol_data
+                    .iter()
+                    .find(|proto| proto.protocol_name == CORRELATION_ID_PROTOCOL_NAME)
+                    .and_then(|proto| str::from_utf8(&proto.data).ok()?.parse().ok());
                 let ilp_data = message
                     .protocol_data
                     .into_iter()
                     .find(|proto| proto.protocol_name == "ilp")
                     .ok_or(())?
                     .data;
-                (message.request_id, ilp_data)
+                (message.request_id, ilp_data, correlation_id)
             }
             Ok(BtpPacket::Response(response)) => {
                 let ilp_data = response
@@ -470,7 +811,10 @@ fn parse_ilp_packet(message: Message) -> Result<(u32, Packet), ()> {
                     .find(|proto| proto.protocol_name == "ilp")
                     .ok_or(())?
                     .data;
-                (response.request_id, ilp_data)
+                (response.request_id, ilp_data, None)
+            }
+            Ok(BtpPacket::Transfer(transfer)) => {
+                return Ok((transfer.request_id, BtpContents::Transfer(transfer.amount)));
             }
             Ok(BtpPacket::Error(error)) => {
                 error!("Got BTP error: {:?}", error);
@@ -481,43 +825,337 @@ fn parse_ilp_packet(message: Message) -> Result<(u32, Packet), ()> {
                 return Err(());
             }
         };
-        if let Ok(packet) = Packet::try_from(BytesMut::from(ilp_data.as_slice())) {
-            Ok((request_id, packet))
-        } else {
-            Err(())
-        }
+        ilp_packet_from_slice(&ilp_data)
+            .map(|packet| (request_id, BtpContents::Ilp(packet, correlation_id)))
     } else {
         error!("Got a non-binary WebSocket message");
         Err(())
     }
 }
 
-fn ilp_packet_to_ws_message(request_id: u32, packet: Packet) -> Message {
-    let (data, is_response) = match packet {
-        Packet::Prepare(prepare) => (BytesMut::from(prepare).to_vec(), false),
-        Packet::Fulfill(fulfill) => (BytesMut::from(fulfill).to_vec(), true),
-        Packet::Reject(reject) => (BytesMut::from(reject).to_vec(), true),
-    };
-    let btp_packet = if is_response {
-        BtpMessage {
-            request_id,
-            protocol_data: vec![ProtocolData {
-                protocol_name: "ilp".into(),
-                content_type: ContentType::ApplicationOctetStream,
-                data,
-            }],
-        }
-        .to_bytes()
-    } else {
-        BtpResponse {
-            request_id,
-            protocol_data: vec![ProtocolData {
+fn ilp_packet_from_slice(ilp_data: &[u8]) -> Result<Packet, ()> {
+    let mut buffer = pool::take(ilp_data.len());
+    buffer.extend_from_slice(ilp_data);
+    Packet::try_from(buffer).map_err(|_| ())
+}
+
+/// Sends the correlation ID in a protocol data entry after the `ilp` one, which peers that
+/// don't know it ignore
+fn prepare_to_ws_message_with_correlation_id(
+    request_id: u32,
+    prepare: Prepare,
+    correlation_id: CorrelationId,
+) -> Message {
+    let message = BtpMessage {
+        request_id,
+        protocol_data: vec![
+            ProtocolData {
                 protocol_name: "ilp".into(),
                 content_type: ContentType::ApplicationOctetStream,
-                data,
-            }],
-        }
-        .to_bytes()
+                data: BytesMut::from(prepare).to_vec(),
+            },
+            ProtocolData {
+                protocol_name: CORRELATION_ID_PROTOCOL_NAME.into(),
+                content_type: ContentType::TextPlainUtf8,
+                data: correlation_id.to_string().into_bytes(),
+            },
+        ],
+    };
+    Message::binary(message.to_bytes())
+}
+
+fn ilp_packet_to_ws_message(request_id: u32, packet: Packet) -> Message {
+    // Prepare packets are sent in a Message and the Fulfill or Reject packets in the
+    // Response with the same request ID
+    let (buffer, is_response) = match packet {
+        Packet::Prepare(prepare) => (BytesMut::from(prepare), false),
+        Packet::Fulfill(fulfill) => (BytesMut::from(fulfill), true),
+        Packet::Reject(reject) => (BytesMut::from(reject), true),
     };
+    // The websocket messages own their payload, so the packet is written into it
+    // along with the BTP envelope and its buffer reused for the next one
+    let btp_packet = ilp_packet_to_bytes(request_id, is_response, &buffer);
+    pool::recycle(buffer);
     Message::binary(btp_packet)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::{FulfillBuilder, PrepareBuilder};
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    fn prepare() -> Prepare {
+        PrepareBuilder {
+            destination: Address::from_str("example.destination").unwrap(),
+            amount: 100,
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            execution_condition: &[0; 32],
+            data: b"test data",
+        }
+        .build()
+    }
+
+    #[tokio::test]
+    async fn writes_responses_before_requests() {
+        let (responses_tx, responses_rx) = unbounded();
+        let (requests_tx, requests_rx) = unbounded();
+        let (write, written) = unbounded();
+        requests_tx
+            .unbounded_send(ilp_packet_to_ws_message(1, Packet::Prepare(prepare())))
+            .unwrap();
+        requests_tx.unbounded_send(Message::Close(None)).unwrap();
+        responses_tx.unbounded_send(PONG.clone()).unwrap();
+        drop(requests_tx);
+        drop(responses_tx);
+
+        write_prioritized(write, responses_rx, requests_rx).await;
+        let written: Vec<Message> = written.collect().await;
+        assert_eq!(written.len(), 3);
+        assert!(written[0].is_pong());
+        assert!(written[1].is_binary());
+        assert!(written[2].is_close());
+    }
+
+    #[test]
+    fn reads_the_correlation_id_sent_with_a_prepare() {
+        let correlation_id = CorrelationId::new();
+        let message = prepare_to_ws_message_with_correlation_id(1, prepare(), correlation_id);
+        match parse_btp_packet(message) {
+            Ok((1, BtpContents::Ilp(Packet::Prepare(parsed), Some(id)))) => {
+                assert_eq!(parsed.amount(), 100);
+                assert_eq!(parsed.data(), b"test data");
+                assert_eq!(id, correlation_id);
+            }
+            _ => panic!("The Prepare and its correlation ID were not parsed"),
+        }
+
+        let message = ilp_packet_to_ws_message(2, Packet::Prepare(prepare()));
+        assert!(matches!(
+            parse_btp_packet(message),
+            Ok((2, BtpContents::Ilp(Packet::Prepare(_), None)))
+        ));
+    }
+
+    /// One side of an in-memory WebSocket connection
+    struct TestConnection {
+        incoming: UnboundedReceiver<Message>,
+        outgoing: UnboundedSender<Message>,
+    }
+
+    impl Stream for TestConnection {
+        type Item = Message;
+
+        fn poll_next(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Option<Message>> {
+            self.incoming.poll_next_unpin(cx)
+        }
+    }
+
+    impl Sink<Message> for TestConnection {
+        type Error = futures::channel::mpsc::SendError;
+
+        fn poll_ready(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            self.outgoing.poll_ready_unpin(cx)
+        }
+
+        fn start_send(
+            mut self: std::pin::Pin<&mut Self>,
+            item: Message,
+        ) -> Result<(), Self::Error> {
+            self.outgoing.start_send_unpin(item)
+        }
+
+        fn poll_flush(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            self.outgoing.poll_flush_unpin(cx)
+        }
+
+        fn poll_close(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            self.outgoing.poll_close_unpin(cx)
+        }
+    }
+
+    /// Returns the connection to give the service and the peer's ends of it
+    fn test_connection() -> (
+        TestConnection,
+        UnboundedSender<Message>,
+        UnboundedReceiver<Message>,
+    ) {
+        let (peer_tx, incoming) = unbounded();
+        let (outgoing, peer_rx) = unbounded();
+        (TestConnection { incoming, outgoing }, peer_tx, peer_rx)
+    }
+
+    #[tokio::test]
+    async fn stays_connected_until_the_last_connection_closes() {
+        let service = BtpOutgoingService::new(
+            Address::from_str("example.connector").unwrap(),
+            outgoing_service_fn(|_| -> IlpResult { panic!("No packets should be forwarded") }),
+        );
+        let mut events = service.subscribe_connection_events();
+        let account = test_account();
+        let (first, first_peer_tx, _first_peer_rx) = test_connection();
+        let (second, second_peer_tx, _second_peer_rx) = test_connection();
+        service.add_connection(account.clone(), first);
+        service.add_connection(account.clone(), second);
+        assert_eq!(service.connection_count(), 2);
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ConnectionEvent::Connected(id)) if id == account.id
+        ));
+        assert!(events.try_recv().is_err());
+
+        drop(first_peer_tx);
+        time::timeout(Duration::from_secs(5), async {
+            while service.connection_count() > 1 {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("The closed connection was not removed");
+        assert!(events.try_recv().is_err());
+
+        drop(second_peer_tx);
+        assert!(matches!(
+            events.recv().await,
+            Ok(ConnectionEvent::Disconnected(id)) if id == account.id
+        ));
+        assert_eq!(service.connection_count(), 0);
+    }
+
+    fn test_account() -> crate::client_server::TestAccount {
+        crate::client_server::TestAccount {
+            id: Uuid::new_v4(),
+            ilp_over_btp_incoming_token: None,
+            ilp_over_btp_outgoing_token: None,
+            ilp_over_btp_url: None,
+        }
+    }
+
+    /// Answers the Prepares sent on the connection with a Fulfill carrying the name of the
+    /// connection, and forwards the request IDs of the Fulfills received on it
+    fn spawn_peer(
+        name: &'static [u8],
+        peer_tx: UnboundedSender<Message>,
+        mut peer_rx: UnboundedReceiver<Message>,
+        fulfills: UnboundedSender<(&'static [u8], u32)>,
+    ) {
+        tokio::spawn(async move {
+            while let Some(message) = peer_rx.next().await {
+                match parse_btp_packet(message) {
+                    Ok((request_id, BtpContents::Ilp(Packet::Prepare(_), _))) => {
+                        let fulfill = FulfillBuilder {
+                            fulfillment: &[0; 32],
+                            data: name,
+                        }
+                        .build();
+                        let message =
+                            ilp_packet_to_ws_message(request_id, Packet::Fulfill(fulfill));
+                        let _ = peer_tx.unbounded_send(message);
+                    }
+                    Ok((request_id, BtpContents::Ilp(Packet::Fulfill(_), _))) => {
+                        let _ = fulfills.unbounded_send((name, request_id));
+                    }
+                    // Pings
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn balances_and_fails_over_between_the_connections_of_an_account() {
+        let service = BtpOutgoingService::new(
+            Address::from_str("example.connector").unwrap(),
+            outgoing_service_fn(|_| -> IlpResult { panic!("No packets should be forwarded") }),
+        );
+        let mut service = service
+            .handle_incoming(incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"incoming",
+                }
+                .build())
+            }))
+            .await;
+        let account = test_account();
+        let (fulfills_tx, mut fulfills_rx) = unbounded();
+        let (first, first_peer_tx, first_peer_rx) = test_connection();
+        let (second, second_peer_tx, second_peer_rx) = test_connection();
+        service.outgoing.add_connection(account.clone(), first);
+        service.outgoing.add_connection(account.clone(), second);
+        spawn_peer(b"first", first_peer_tx, first_peer_rx, fulfills_tx.clone());
+        spawn_peer(
+            b"second",
+            second_peer_tx.clone(),
+            second_peer_rx,
+            fulfills_tx,
+        );
+        let request = || OutgoingRequest {
+            from: account.clone(),
+            to: account.clone(),
+            original_amount: 100,
+            prepare: prepare(),
+        };
+
+        // The requests take turns on the connections
+        let mut data = Vec::new();
+        for _ in 0..4 {
+            let fulfill = service.send_request(request()).await.unwrap();
+            data.push(fulfill.data().to_vec());
+        }
+        assert_eq!(
+            data,
+            vec![
+                b"first".to_vec(),
+                b"second".to_vec(),
+                b"first".to_vec(),
+                b"second".to_vec()
+            ]
+        );
+
+        // A closed connection which is picked is removed and the request is sent on another one
+        let (requests, _) = unbounded();
+        service
+            .outgoing
+            .connections
+            .write()
+            .get_mut(&account.id)
+            .unwrap()
+            .insert(
+                1,
+                Connection {
+                    id: 0,
+                    queues: ConnectionQueues {
+                        responses: unbounded().0,
+                        requests,
+                    },
+                },
+            );
+        // The counter is at 4, so the closed connection is picked first
+        let fulfill = service.send_request(request()).await.unwrap();
+        assert_eq!(fulfill.data(), b"second");
+        assert_eq!(service.connection_count(), 2);
+
+        // The response to an incoming Prepare is sent on the connection it was received on
+        second_peer_tx
+            .unbounded_send(ilp_packet_to_ws_message(7, Packet::Prepare(prepare())))
+            .unwrap();
+        let fulfill = time::timeout(Duration::from_secs(5), fulfills_rx.next())
+            .await
+            .expect("No response was sent");
+        assert_eq!(fulfill, Some((&b"second"[..], 7)));
+    }
+}
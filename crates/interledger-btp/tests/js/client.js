@@ -0,0 +1,41 @@
+// Connects to the BTP server at the URL given as the first argument with the JavaScript
+// ilp-plugin-btp, sends a Prepare packet and a Transfer to it, prints the response to the
+// Prepare packet as JSON and then fulfills the Prepare packets it receives until it is killed.
+const BtpPlugin = require('ilp-plugin-btp')
+const IlpPacket = require('ilp-packet')
+const crypto = require('crypto')
+
+const FULFILLMENT = Buffer.alloc(32)
+const CONDITION = crypto.createHash('sha256').update(FULFILLMENT).digest()
+
+async function run () {
+  const plugin = new BtpPlugin({ server: process.argv[2] })
+  plugin.registerDataHandler(async (data) => {
+    const prepare = IlpPacket.deserializeIlpPrepare(data)
+    return IlpPacket.serializeIlpFulfill({
+      fulfillment: FULFILLMENT,
+      data: Buffer.from(`fulfilled ${prepare.amount} by js`)
+    })
+  })
+  plugin.registerMoneyHandler(async () => {})
+  await plugin.connect()
+
+  const response = IlpPacket.deserializeIlpPacket(await plugin.sendData(IlpPacket.serializeIlpPrepare({
+    amount: '100',
+    executionCondition: CONDITION,
+    expiresAt: new Date(Date.now() + 30000),
+    destination: 'example.rust',
+    data: Buffer.from('hello from js')
+  })))
+  await plugin.sendMoney('10')
+
+  console.log(JSON.stringify({
+    type: response.typeString,
+    data: response.data.data.toString()
+  }))
+}
+
+run().catch((err) => {
+  console.error(err)
+  process.exit(1)
+})
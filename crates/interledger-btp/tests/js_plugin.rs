@@ -0,0 +1,178 @@
+//! Tests the BTP server with the JavaScript `ilp-plugin-btp`, which JavaScript connectors use to
+//! peer with Interledger.rs nodes. The test requires Node.js and the dependencies of the client
+//! in `tests/js`, which are installed with `npm install --prefix crates/interledger-btp/tests/js`,
+//! so it is ignored unless it is run with `cargo test -p interledger-btp -- --ignored`.
+use async_trait::async_trait;
+use interledger_btp::{btp_service_as_filter, BtpAccount, BtpOutgoingService, BtpStore};
+use interledger_errors::BtpStoreError;
+use interledger_packet::{Address, ErrorCode, FulfillBuilder, PrepareBuilder, RejectBuilder};
+use interledger_service::*;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use url::Url;
+use uuid::Uuid;
+
+static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+static ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+/// The token contains a character which the plugin sends percent-encoded
+const TOKEN: &str = "secret/token";
+
+#[derive(Clone, Debug)]
+struct TestAccount {
+    id: Uuid,
+}
+
+impl Account for TestAccount {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn username(&self) -> &Username {
+        &ALICE
+    }
+
+    fn asset_scale(&self) -> u8 {
+        9
+    }
+
+    fn asset_code(&self) -> &str {
+        "XYZ"
+    }
+
+    fn ilp_address(&self) -> &Address {
+        &ILP_ADDRESS
+    }
+}
+
+impl BtpAccount for TestAccount {
+    fn get_ilp_over_btp_url(&self) -> Option<&Url> {
+        None
+    }
+
+    fn get_ilp_over_btp_outgoing_token(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+#[derive(Clone)]
+struct TestStore {
+    account: TestAccount,
+}
+
+#[async_trait]
+impl BtpStore for TestStore {
+    type Account = TestAccount;
+
+    async fn get_account_from_btp_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, BtpStoreError> {
+        if username == &*ALICE && token == TOKEN {
+            Ok(self.account.clone())
+        } else {
+            Err(BtpStoreError::Unauthorized(username.to_string()))
+        }
+    }
+
+    async fn get_btp_outgoing_accounts(&self) -> Result<Vec<TestAccount>, BtpStoreError> {
+        Ok(Vec::new())
+    }
+}
+
+/// The response to the Prepare packet sent by the JavaScript client
+#[derive(Deserialize)]
+struct ClientOutput {
+    #[serde(rename = "type")]
+    packet_type: String,
+    data: String,
+}
+
+#[tokio::test]
+#[ignore]
+async fn serves_js_plugin_clients() {
+    let account = TestAccount { id: Uuid::new_v4() };
+    let server_address = Address::from_str("example.server").unwrap();
+    let btp_service = BtpOutgoingService::new(
+        server_address.clone(),
+        outgoing_service_fn(move |_| {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: b"No other outgoing handler",
+                triggered_by: Some(&server_address),
+                data: &[],
+            }
+            .build())
+        }),
+    );
+    let mut btp_service = btp_service
+        .handle_incoming(incoming_service_fn(|request| {
+            assert_eq!(request.prepare.data(), b"hello from js");
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"fulfilled by rust",
+            }
+            .build())
+        }))
+        .await;
+    let mut connection_events = btp_service.subscribe_connection_events();
+    let filter = btp_service_as_filter(
+        btp_service.clone(),
+        TestStore {
+            account: account.clone(),
+        },
+    );
+    let (addr, server) = warp::serve(filter).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+
+    let mut client = Command::new("node")
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/js/client.js"))
+        .arg(format!(
+            "btp+ws://alice:secret%2Ftoken@{}/accounts/alice/ilp/btp",
+            addr
+        ))
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("Node.js must be installed");
+    connection_events.recv().await.unwrap();
+
+    // The client prints the response once its Prepare packet and Transfer were answered
+    let mut lines = BufReader::new(client.stdout.take().unwrap()).lines();
+    let line = tokio::time::timeout(Duration::from_secs(30), lines.next_line())
+        .await
+        .expect("JavaScript client did not respond")
+        .unwrap()
+        .expect("JavaScript client failed");
+    let output: ClientOutput = serde_json::from_str(&line).unwrap();
+    assert_eq!(output.packet_type, "ilp_fulfill");
+    assert_eq!(output.data, "fulfilled by rust");
+
+    let fulfill = btp_service
+        .send_request(OutgoingRequest {
+            from: account.clone(),
+            to: account,
+            original_amount: 50,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.alice.js").unwrap(),
+                amount: 50,
+                execution_condition: &hex_literal::hex!(
+                    "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925"
+                ),
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                data: &[],
+            }
+            .build(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(fulfill.data(), b"fulfilled 50 by js");
+
+    btp_service.close();
+}
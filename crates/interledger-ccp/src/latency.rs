@@ -0,0 +1,176 @@
+use interledger_service::{
+    Account, AddressStore, BoxedIlpFuture, OutgoingRequest, OutgoingService,
+};
+use parking_lot::RwLock;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Weight of the latest round trip time in the latency score of a next hop
+const SMOOTHING_FACTOR: f64 = 0.2;
+
+/// Latency scores of the next hops: the exponentially weighted moving averages of the round
+/// trip times of the requests sent to each of them, in milliseconds. They are measured by the
+/// [`LatencyService`] and used by the `CcpRouteManager` to choose between routes which are
+/// otherwise equally good.
+#[derive(Clone, Default)]
+pub struct LatencyScores(Arc<RwLock<HashMap<Uuid, f64>>>);
+
+impl LatencyScores {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the round trip time of a request to the score of the account
+    pub fn record(&self, account_id: Uuid, round_trip_time: Duration) {
+        let millis = round_trip_time.as_secs_f64() * 1000.0;
+        self.0
+            .write()
+            .entry(account_id)
+            .and_modify(|score| *score += SMOOTHING_FACTOR * (millis - *score))
+            .or_insert(millis);
+    }
+
+    /// The score of the account, if any request to it was measured
+    pub fn get(&self, account_id: Uuid) -> Option<f64> {
+        self.0.read().get(&account_id).cloned()
+    }
+
+    /// Orders the accounts from the fastest to the slowest. The accounts which were not
+    /// measured yet come last.
+    pub(crate) fn compare(&self, a: Uuid, b: Uuid) -> Ordering {
+        let scores = self.0.read();
+        match (scores.get(&a), scores.get(&b)) {
+            (Some(a), Some(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+/// # Latency Service
+///
+/// Outgoing Service which measures the time between sending each request and receiving its
+/// response, and adds it to the latency score of the next hop. The rejects triggered by this
+/// node, e.g. because the next hop could not be reached, are not measured.
+#[derive(Clone)]
+pub struct LatencyService<S, O> {
+    scores: LatencyScores,
+    store: S,
+    next: O,
+}
+
+impl<S, O> LatencyService<S, O> {
+    pub fn new(scores: LatencyScores, store: S, next: O) -> Self {
+        LatencyService {
+            scores,
+            store,
+            next,
+        }
+    }
+}
+
+impl<S, O, A> OutgoingService<A> for LatencyService<S, O>
+where
+    S: AddressStore + Send + Sync + 'static,
+    O: OutgoingService<A> + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let account_id = request.to.id();
+            let sent_at = Instant::now();
+            let result = self.next.send_request(request).await;
+            let from_next_hop = match result {
+                Ok(_) => true,
+                Err(ref reject) => reject.triggered_by().map_or(false, |triggered_by| {
+                    triggered_by != self.store.get_ilp_address()
+                }),
+            };
+            if from_next_hop {
+                self.scores.record(account_id, sent_at.elapsed());
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use interledger_packet::{Address, ErrorCode, FulfillBuilder, PrepareBuilder, RejectBuilder};
+    use interledger_service::outgoing_service_fn;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    #[test]
+    fn averages_the_round_trip_times() {
+        let scores = LatencyScores::new();
+        let (fast, slow) = (Uuid::new_v4(), Uuid::new_v4());
+        scores.record(fast, Duration::from_millis(100));
+        scores.record(fast, Duration::from_millis(200));
+        assert!((scores.get(fast).unwrap() - 120.0).abs() < 1e-9);
+
+        scores.record(slow, Duration::from_millis(500));
+        assert_eq!(scores.compare(fast, slow), Ordering::Less);
+        assert_eq!(scores.compare(slow, Uuid::new_v4()), Ordering::Less);
+    }
+
+    async fn send(service: &mut impl OutgoingService<TestAccount>) {
+        let _ = service
+            .send_request(OutgoingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                to: ROUTING_ACCOUNT.clone(),
+                original_amount: 100,
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount: 100,
+                    expires_at: SystemTime::now() + Duration::from_secs(30),
+                    execution_condition: &[0; 32],
+                    data: &[],
+                }
+                .build(),
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn only_measures_responses_of_the_next_hop() {
+        let scores = LatencyScores::new();
+        let mut service = LatencyService::new(
+            scores.clone(),
+            TestStore::new(),
+            outgoing_service_fn(|_: OutgoingRequest<TestAccount>| {
+                Err(RejectBuilder {
+                    code: ErrorCode::T01_PEER_UNREACHABLE,
+                    message: &[],
+                    triggered_by: Some(&Address::from_str("example.connector").unwrap()),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        send(&mut service).await;
+        assert_eq!(scores.get(ROUTING_ACCOUNT.id()), None);
+
+        let mut service = LatencyService::new(
+            scores.clone(),
+            TestStore::new(),
+            outgoing_service_fn(|_: OutgoingRequest<TestAccount>| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        send(&mut service).await;
+        assert!(scores.get(ROUTING_ACCOUNT.id()).is_some());
+    }
+}
@@ -18,14 +18,18 @@ use uuid::Uuid;
 
 #[cfg(test)]
 mod fixtures;
+mod latency;
 mod packet;
 mod routing_table;
 mod server;
 #[cfg(test)]
 mod test_helpers;
+mod watch;
 
+pub use latency::{LatencyScores, LatencyService};
 pub use packet::{Mode, RouteControlRequest};
 pub use server::{CcpRouteManager, CcpRouteManagerBuilder};
+pub use watch::{NextHop, RouteChange, RouteChanges};
 
 use serde::{Deserialize, Serialize};
 
@@ -39,6 +43,11 @@ pub fn fuzz_update_request(data: &[u8]) {
     packet::RouteUpdateRequest::fuzz_from_prepare_data(data);
 }
 
+#[cfg(fuzzing)]
+pub fn fuzz_structured_update_request(data: &[u8]) {
+    packet::RouteUpdateRequest::fuzz_structured(data);
+}
+
 /// Data structure used to describe the routing relation of an account with its peers.
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Serialize, Deserialize, Ord, Eq)]
@@ -92,6 +101,12 @@ pub trait CcpRoutingAccount: Account {
     /// The type of relationship we have with this account
     fn routing_relation(&self) -> RoutingRelation;
 
+    /// Breaks the ties between the routes of accounts with the same relation,
+    /// the account with the highest preference being preferred
+    fn routing_preference(&self) -> u32 {
+        0
+    }
+
     /// Indicates whether we should send CCP Route Updates to this account
     fn should_send_routes(&self) -> bool {
         self.routing_relation() == RoutingRelation::Child
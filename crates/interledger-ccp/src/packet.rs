@@ -152,14 +152,19 @@ impl TryFrom<&Prepare> for RouteControlRequest {
     type Error = CcpPacketError;
 
     fn try_from(prepare: &Prepare) -> Result<Self, Self::Error> {
-        if prepare.expires_at() < SystemTime::now() {
+        RouteControlRequest::try_from_prepare_at(prepare, SystemTime::now())
+    }
+}
+
+impl RouteControlRequest {
+    /// Parses the request, rejecting the packet if it expired before `now`
+    pub fn try_from_prepare_at(prepare: &Prepare, now: SystemTime) -> Result<Self, CcpPacketError> {
+        if prepare.expires_at() < now {
             return Err(CcpPacketError::PacketExpired);
         }
         RouteControlRequest::try_from_without_expiry(prepare)
     }
-}
 
-impl RouteControlRequest {
     pub(crate) fn try_from_without_expiry(prepare: &Prepare) -> Result<Self, CcpPacketError> {
         let destination = prepare.destination();
         if destination != *CCP_CONTROL_DESTINATION {
@@ -220,6 +225,11 @@ impl RouteControlRequest {
     }
 
     pub fn to_prepare(&self) -> Prepare {
+        self.to_prepare_at(SystemTime::now())
+    }
+
+    /// Builds the packet with an expiry relative to `now`
+    pub fn to_prepare_at(&self, now: SystemTime) -> Prepare {
         let mut data = Vec::new();
 
         data.put_u8(self.mode as u8);
@@ -233,7 +243,7 @@ impl RouteControlRequest {
         PrepareBuilder {
             destination: CCP_CONTROL_DESTINATION.clone(),
             amount: 0,
-            expires_at: SystemTime::now() + Duration::from_millis(PEER_PROTOCOL_EXPIRY_DURATION),
+            expires_at: now + Duration::from_millis(PEER_PROTOCOL_EXPIRY_DURATION),
             execution_condition: &PEER_PROTOCOL_CONDITION,
             data: &data[..],
         }
@@ -386,6 +396,33 @@ impl TryFrom<&mut &[u8]> for Route {
 }
 
 impl Route {
+    #[cfg(fuzzing)]
+    fn arbitrary(input: &mut arbitrary::Unstructured) -> arbitrary::Result<Self> {
+        let prefix = input.arbitrary()?;
+        let mut path = Vec::new();
+        for _ in 0..input.int_in_range(0..=4)? {
+            path.push(input.arbitrary()?);
+        }
+        let auth = input.arbitrary()?;
+        let mut props = Vec::new();
+        for _ in 0..input.int_in_range(0..=4)? {
+            props.push(RouteProp {
+                is_optional: input.arbitrary()?,
+                is_transitive: input.arbitrary()?,
+                is_partial: input.arbitrary()?,
+                id: input.arbitrary()?,
+                is_utf8: input.arbitrary()?,
+                value: Bytes::copy_from_slice(input.arbitrary()?),
+            });
+        }
+        Ok(Route {
+            prefix,
+            path,
+            auth,
+            props,
+        })
+    }
+
     pub fn write_to<B>(&self, buf: &mut B)
     where
         B: BufMut,
@@ -434,14 +471,19 @@ impl TryFrom<&Prepare> for RouteUpdateRequest {
     type Error = CcpPacketError;
 
     fn try_from(prepare: &Prepare) -> Result<Self, Self::Error> {
-        if prepare.expires_at() < SystemTime::now() {
+        RouteUpdateRequest::try_from_prepare_at(prepare, SystemTime::now())
+    }
+}
+
+impl RouteUpdateRequest {
+    /// Parses the request, rejecting the packet if it expired before `now`
+    pub fn try_from_prepare_at(prepare: &Prepare, now: SystemTime) -> Result<Self, CcpPacketError> {
+        if prepare.expires_at() < now {
             return Err(CcpPacketError::PacketExpired);
         }
         RouteUpdateRequest::try_from_without_expiry(prepare)
     }
-}
 
-impl RouteUpdateRequest {
     pub(crate) fn try_from_without_expiry(prepare: &Prepare) -> Result<Self, CcpPacketError> {
         let destination = prepare.destination();
         if destination != *CCP_UPDATE_DESTINATION {
@@ -470,6 +512,52 @@ impl RouteUpdateRequest {
         }
     }
 
+    /// Builds an update out of the input rather than parsing it, so that the fuzzer explores
+    /// valid updates, checks that it is read back the same and applies it to a routing table
+    #[cfg(fuzzing)]
+    pub fn fuzz_structured(data: &[u8]) {
+        let _ = Self::roundtrip_structured(&mut arbitrary::Unstructured::new(data));
+    }
+
+    #[cfg(fuzzing)]
+    fn roundtrip_structured(input: &mut arbitrary::Unstructured) -> arbitrary::Result<()> {
+        let mut new_routes = Vec::new();
+        for _ in 0..input.int_in_range(0..=4)? {
+            new_routes.push(Route::arbitrary(input)?);
+        }
+        let mut withdrawn_routes = Vec::new();
+        for _ in 0..input.int_in_range(0..=4)? {
+            withdrawn_routes.push(input.arbitrary()?);
+        }
+        let request = RouteUpdateRequest {
+            routing_table_id: input.arbitrary()?,
+            current_epoch_index: input.arbitrary()?,
+            from_epoch_index: input.arbitrary()?,
+            to_epoch_index: input.arbitrary()?,
+            hold_down_time: input.arbitrary()?,
+            speaker: Address::try_from(input.arbitrary::<&[u8]>()?)
+                .unwrap_or_else(|_| Address::try_from(&b"example.fuzz"[..]).unwrap()),
+            new_routes,
+            withdrawn_routes,
+        };
+
+        let roundtripped = Self::try_from_without_expiry(&request.to_prepare()).unwrap();
+        assert_eq!(roundtripped, request);
+
+        let mut table = crate::routing_table::RoutingTable::new(request.routing_table_id);
+        let from_start = request.from_epoch_index == 0;
+        let new_routes = request.new_routes.clone();
+        match table.handle_update_request((), request) {
+            Ok(_) => {
+                for route in new_routes {
+                    assert!(table.get_route(&route.prefix).is_some());
+                }
+            }
+            Err(_) => assert!(!from_start),
+        }
+        Ok(())
+    }
+
     fn try_from_data(mut data: &[u8]) -> Result<Self, CcpPacketError> {
         const HOLD_DOWN_TIME_LEN: usize = 4;
 
@@ -521,6 +609,11 @@ impl RouteUpdateRequest {
     }
 
     pub fn to_prepare(&self) -> Prepare {
+        self.to_prepare_at(SystemTime::now())
+    }
+
+    /// Builds the packet with an expiry relative to `now`
+    pub fn to_prepare_at(&self, now: SystemTime) -> Prepare {
         let mut data = Vec::new();
         data.put(&self.routing_table_id[..]);
         data.put_u32(self.current_epoch_index);
@@ -540,7 +633,7 @@ impl RouteUpdateRequest {
         PrepareBuilder {
             destination: CCP_UPDATE_DESTINATION.clone(),
             amount: 0,
-            expires_at: SystemTime::now() + Duration::from_millis(PEER_PROTOCOL_EXPIRY_DURATION),
+            expires_at: now + Duration::from_millis(PEER_PROTOCOL_EXPIRY_DURATION),
             execution_condition: &PEER_PROTOCOL_CONDITION,
             data: &data[..],
         }
@@ -1,5 +1,5 @@
 use crate::packet::{Route, RouteUpdateRequest};
-use interledger_packet::hex::HexString;
+use interledger_packet::{has_prefix, hex::HexString};
 use once_cell::sync::Lazy;
 use ring::rand::{SecureRandom, SystemRandom};
 use std::collections::HashMap;
@@ -31,7 +31,8 @@ impl<T> PrefixMap<T> {
         // TODO use parallel iterator
         self.map
             .iter()
-            .filter(|(p, _)| prefix.starts_with(p.as_str()))
+            // The default route ("") is only returned when it is asked for
+            .filter(|(p, _)| has_prefix(prefix, p) && (!p.is_empty() || prefix.is_empty()))
             .max_by_key(|(p, _)| p.len())
             .map(|(_prefix, item)| item)
     }
@@ -85,10 +86,15 @@ where
         epoch
     }
 
-    /// Set a particular route, overwriting the one that was there before
-    pub(crate) fn set_route(&mut self, prefix: String, account: A, route: Route) {
-        self.prefix_map.remove(&prefix);
-        self.prefix_map.insert(prefix, (account, route));
+    /// Set a particular route, overwriting the one that was there before.
+    /// Returns the route that was overwritten, if any
+    pub(crate) fn set_route(
+        &mut self,
+        prefix: String,
+        account: A,
+        route: Route,
+    ) -> Option<(A, Route)> {
+        self.prefix_map.map.insert(prefix, (account, route))
     }
 
     /// Remove the route for the given prefix. Returns true if that route existed before
@@ -107,6 +113,11 @@ where
         self.prefix_map.resolve(prefix)
     }
 
+    /// The prefixes the table has routes for
+    pub(crate) fn prefixes(&self) -> impl Iterator<Item = &String> {
+        self.prefix_map.map.keys()
+    }
+
     pub(crate) fn get_simplified_table(&self) -> HashMap<String, A> {
         self.prefix_map
             .map
@@ -229,6 +240,7 @@ mod prefix_map {
         assert_eq!(map.resolve("example.a.b.c").unwrap(), &2);
         assert_eq!(map.resolve("example.a.b.c.d.e").unwrap(), &2);
         assert!(map.resolve("example.other").is_none());
+        assert_eq!(map.resolve("example.a.bc").unwrap(), &1);
     }
 }
 
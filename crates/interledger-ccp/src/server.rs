@@ -1,33 +1,34 @@
 use crate::{
+    latency::LatencyScores,
     packet::{
         Mode, Route, RouteControlRequest, RouteUpdateRequest, CCP_CONTROL_DESTINATION,
         CCP_RESPONSE, CCP_UPDATE_DESTINATION,
     },
     routing_table::RoutingTable,
+    watch::{NextHop, RouteChange, RouteChanges},
     CcpRoutingAccount, CcpRoutingStore, RoutingRelation,
 };
-use async_trait::async_trait;
 use futures::future::join_all;
 use interledger_errors::CcpRoutingStoreError;
-use interledger_packet::{hex::HexString, Address, ErrorCode, RejectBuilder};
+use interledger_packet::{has_prefix, hex::HexString, prefixes, Address, ErrorCode, RejectBuilder};
 use interledger_service::{
-    Account, AddressStore, IlpResult, IncomingRequest, IncomingService, OutgoingRequest,
-    OutgoingService,
+    clock::SharedClock, runtime, Account, AddressStore, BoxedIlpFuture, IlpResult, IncomingRequest,
+    IncomingService, OutgoingRequest, OutgoingService,
 };
 use parking_lot::{Mutex, RwLock};
 use ring::digest::{digest, SHA256};
 use std::cmp::Ordering as StdOrdering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::{
     cmp::min,
-    convert::TryFrom,
-    str,
+    iter, str,
     sync::{
         atomic::{AtomicU32, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, SystemTime},
 };
+use tokio::sync::broadcast;
 use tracing::{debug, error, trace, warn};
 use uuid::Uuid;
 
@@ -37,6 +38,8 @@ use crate::packet::PEER_PROTOCOL_CONDITION;
 use futures::TryFutureExt;
 #[cfg(test)]
 use once_cell::sync::Lazy;
+#[cfg(test)]
+use std::convert::TryFrom;
 
 // TODO should the route expiry be longer? we use 30 seconds now
 // because the expiry shortener will lower the expiry to 30 seconds
@@ -45,6 +48,7 @@ use once_cell::sync::Lazy;
 const DEFAULT_ROUTE_EXPIRY_TIME: u32 = 30000;
 const DEFAULT_BROADCAST_INTERVAL: u64 = 30000;
 const DUMMY_ROUTING_TABLE_ID: [u8; 16] = [0; 16];
+const DEFAULT_ROUTE_CHANGES_CAPACITY: usize = 256;
 
 fn hash(preimage: &[u8; 32]) -> [u8; 32] {
     let mut out = [0; 32];
@@ -68,6 +72,10 @@ pub struct CcpRouteManagerBuilder<I, O, S> {
     store: S,
     ilp_address: Address,
     broadcast_interval: u64,
+    clock: SharedClock,
+    skew_tolerance: Duration,
+    latency: Option<LatencyScores>,
+    route_changes: RouteChanges,
 }
 
 impl<I, O, S, A> CcpRouteManagerBuilder<I, O, S>
@@ -84,6 +92,10 @@ where
             outgoing,
             store,
             broadcast_interval: DEFAULT_BROADCAST_INTERVAL,
+            clock: SharedClock::default(),
+            skew_tolerance: Duration::from_secs(0),
+            latency: None,
+            route_changes: broadcast::channel(DEFAULT_ROUTE_CHANGES_CAPACITY).0,
         }
     }
 
@@ -98,6 +110,34 @@ where
         self
     }
 
+    /// Set the clock which the expiry of the CCP messages is relative to
+    pub fn clock(&mut self, clock: SharedClock) -> &mut Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Accept the CCP messages which expired less than the tolerance ago, in case the
+    /// clocks of the peers are slightly behind
+    pub fn skew_tolerance(&mut self, tolerance: Duration) -> &mut Self {
+        self.skew_tolerance = tolerance;
+        self
+    }
+
+    /// Break the ties between equally good routes in favor of the next hop with the lowest
+    /// latency score, and re-evaluate all the routes at each broadcast as the scores change
+    pub fn latency_scores(&mut self, scores: LatencyScores) -> &mut Self {
+        self.latency = Some(scores);
+        self
+    }
+
+    /// Publish the changes of the local routing table with the given sender, e.g. to share
+    /// them with the subscribers of the API. Each change is also broadcast to the peers
+    /// right away, instead of at the next broadcast interval.
+    pub fn route_changes(&mut self, route_changes: RouteChanges) -> &mut Self {
+        self.route_changes = route_changes;
+        self
+    }
+
     pub fn to_service(&self) -> CcpRouteManager<I, O, S, A> {
         #[allow(clippy::let_and_return)]
         let service = CcpRouteManager {
@@ -110,14 +150,19 @@ where
             last_epoch_updates_sent_for: Arc::new(AtomicU32::new(0)),
             local_table: Arc::new(RwLock::new(RoutingTable::default())),
             incoming_tables: Arc::new(RwLock::new(HashMap::new())),
+            parents: Arc::new(RwLock::new(HashMap::new())),
             unavailable_accounts: Arc::new(Mutex::new(HashMap::new())),
+            clock: self.clock.clone(),
+            skew_tolerance: self.skew_tolerance,
+            latency: self.latency.clone(),
+            route_changes: self.route_changes.clone(),
         };
 
         #[cfg(not(test))]
         {
             let broadcast_interval = self.broadcast_interval;
             let service_clone = service.clone();
-            tokio::spawn(async move {
+            runtime::spawn(async move {
                 service_clone
                     .start_broadcast_interval(broadcast_interval)
                     .await
@@ -169,12 +214,23 @@ pub struct CcpRouteManager<I, O, S, A: Account> {
     /// Updates from peers are applied to our local_table if they are better than the
     /// existing best route and if they do not attempt to overwrite configured routes.
     incoming_tables: Arc<RwLock<HashMap<Uuid, RoutingTable<A>>>>,
+    /// The parents we have received a routing table from. The default route goes through
+    /// the best of them.
+    parents: Arc<RwLock<HashMap<Uuid, A>>>,
     store: S,
     /// If we get final errors while sending to specific accounts, we'll
     /// wait before trying to broadcast to them
     /// This maps the account ID to the number of route brodcast intervals
     /// we should wait before trying again
     unavailable_accounts: Arc<Mutex<HashMap<Uuid, BackoffParams>>>,
+    /// The expiry of the CCP messages we send and receive is relative to this clock
+    clock: SharedClock,
+    /// How long after their expiry the CCP messages we receive are still accepted
+    skew_tolerance: Duration,
+    /// Latency scores of the next hops, which break the ties between equally good routes
+    latency: Option<LatencyScores>,
+    /// Publishes the changes of the local table
+    route_changes: RouteChanges,
 }
 
 impl<I, O, S, A> CcpRouteManager<I, O, S, A>
@@ -187,17 +243,40 @@ where
     /// Returns a future that will trigger this service to update its routes and broadcast
     /// updates to peers on the given interval. `interval` is in milliseconds
     pub async fn start_broadcast_interval(&self, interval: u64) {
+        let mut changes = self.watch();
         self.request_all_routes().await;
-        let mut interval = tokio::time::interval(Duration::from_millis(interval));
+        let mut interval = runtime::interval(Duration::from_millis(interval));
         loop {
-            interval.tick().await;
-            // ensure we have the latest ILP Address from the store
-            self.update_ilp_address();
-            // Do not consume the result if an error since we want to keep the loop going
-            let _ = self.broadcast_routes().await;
+            tokio::select! {
+                _ = interval.tick() => {
+                    // ensure we have the latest ILP Address from the store
+                    self.update_ilp_address();
+                    // Do not consume the result if an error since we want to keep the loop going
+                    let _ = self.broadcast_routes().await;
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) = changes.recv() => {
+                    // Send the changes made since the last broadcast right away, together
+                    // with the ones which are already queued
+                    while let Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) =
+                        changes.try_recv()
+                    {}
+                    let _ = self.send_route_updates().await;
+                }
+            }
         }
     }
 
+    /// Subscribes to the changes of the best routes to each prefix, which are published
+    /// as soon as they are applied to the local routing table
+    pub fn watch(&self) -> broadcast::Receiver<RouteChange> {
+        self.route_changes.subscribe()
+    }
+
+    /// The time before which the CCP messages we receive have expired
+    fn expiry_cutoff(&self) -> SystemTime {
+        self.clock.now() - self.skew_tolerance
+    }
+
     fn update_ilp_address(&self) {
         let current_ilp_address = self.ilp_address.read();
         let ilp_address = self.store.get_ilp_address();
@@ -243,7 +322,8 @@ where
             .build());
         }
 
-        let control = RouteControlRequest::try_from(&request.prepare);
+        let control =
+            RouteControlRequest::try_from_prepare_at(&request.prepare, self.expiry_cutoff());
         if control.is_err() {
             return Err(RejectBuilder {
                 code: ErrorCode::F00_BAD_REQUEST,
@@ -294,7 +374,7 @@ where
 
             #[cfg(not(test))]
             {
-                tokio::spawn({
+                runtime::spawn({
                     let self_clone = self.clone();
                     async move {
                         self_clone
@@ -320,14 +400,14 @@ where
             .filter(|route| {
                 let ilp_address = self.ilp_address.read();
                 let address_scheme = (*ilp_address).scheme();
-                if !route.prefix.starts_with(address_scheme) {
+                if !has_prefix(&route.prefix, address_scheme) {
                     warn!("Got route for a different global prefix: {:?}", route);
                     false
                 } else if route.prefix.len() <= address_scheme.len() + 1 {
                     // note the + 1 is due to address_scheme not including a trailing "."
                     warn!("Got route broadcast for the global prefix: {:?}", route);
                     false
-                } else if route.prefix.starts_with(&ilp_address as &str) {
+                } else if has_prefix(&route.prefix, &ilp_address) {
                     trace!("Ignoring route broadcast for a prefix that starts with our own address: {:?}", route);
                     false
                 } else if route.path.iter().any(|p| p == &ilp_address as &str) {
@@ -360,7 +440,8 @@ where
             .build());
         }
 
-        let update = RouteUpdateRequest::try_from(&request.prepare);
+        let update =
+            RouteUpdateRequest::try_from_prepare_at(&request.prepare, self.expiry_cutoff());
         if update.is_err() {
             return Err(RejectBuilder {
                 code: ErrorCode::F00_BAD_REQUEST,
@@ -381,7 +462,7 @@ where
         let update = self.filter_routes(update);
 
         // Ensure the mutex gets dropped before the async block
-        let result = {
+        let (result, new_parent) = {
             let mut incoming_tables = self.incoming_tables.write();
             if !&incoming_tables.contains_key(&request.from.id()) {
                 incoming_tables.insert(
@@ -389,17 +470,28 @@ where
                     RoutingTable::new(update.routing_table_id),
                 );
             }
-            incoming_tables
+            let result = incoming_tables
                 .get_mut(&request.from.id())
                 .expect("Should have inserted a routing table for this account")
-                .handle_update_request(request.from.clone(), update)
+                .handle_update_request(request.from.clone(), update);
+            let new_parent = request.from.routing_relation() == RoutingRelation::Parent
+                && self
+                    .parents
+                    .write()
+                    .insert(request.from.id(), request.from.clone())
+                    .is_none();
+            (result, new_parent)
         };
 
         // Update the routing table we maintain for the account we got this from.
         // Figure out whether we need to update our routes for any of the prefixes
         // that were included in this route update.
         match result {
-            Ok(prefixes_updated) => {
+            Ok(mut prefixes_updated) => {
+                if new_parent {
+                    // The default route may now go through this parent
+                    prefixes_updated.push(String::new());
+                }
                 if prefixes_updated.is_empty() {
                     trace!("Route update request did not contain any prefixes we need to update our routes for");
                     return Ok(CCP_RESPONSE.clone());
@@ -412,9 +504,11 @@ where
 
                 #[cfg(not(test))]
                 {
-                    tokio::spawn({
+                    runtime::spawn({
                         let self_clone = self.clone();
-                        async move { self_clone.update_best_routes(Some(prefixes_updated)).await }
+                        async move {
+                            let _ = self_clone.update_best_routes(Some(prefixes_updated)).await;
+                        }
                     });
                 }
 
@@ -448,7 +542,7 @@ where
                 let table = &self.incoming_tables.read().clone()[&request.from.id()];
 
                 #[cfg(not(test))]
-                tokio::spawn({
+                runtime::spawn({
                     let table = table.clone();
                     let self_clone = self.clone();
                     async move {
@@ -490,7 +584,7 @@ where
             account_id,
             HexString(&last_known_routing_table_id[..]),
             last_known_epoch);
-        let prepare = control.to_prepare();
+        let prepare = control.to_prepare_at(self.clock.now());
         let result = self
             .clone()
             .outgoing
@@ -517,7 +611,9 @@ where
     /// given prefixes. This is triggered when we get an incoming Route Update Request
     /// with some new or modified routes that might be better than our existing ones.
     ///
-    /// If prefixes is None, this will check the best routes for all local and configured prefixes.
+    /// If prefixes is None, this will check the best routes for all local and configured prefixes,
+    /// the default route, and the ones received from peers if the ties between routes are
+    /// broken by latency.
     async fn update_best_routes(
         &self,
         prefixes: Option<Vec<String>>,
@@ -537,6 +633,7 @@ where
             // Note we only use a read lock here and later get a write lock if we need to update the table
             let local_table = local_table.read();
             let incoming_tables = incoming_tables.read();
+            let parents = self.parents.read();
 
             // Either check the given prefixes or check all of our local and configured routes
             let prefixes_to_check: Box<dyn Iterator<Item = &str>> =
@@ -544,7 +641,33 @@ where
                     Box::new(prefixes.iter().map(|prefix| prefix.as_str()))
                 } else {
                     let routes = configured_routes.iter().chain(local_routes.iter());
-                    Box::new(routes.map(|(prefix, _account)| prefix.as_str()))
+                    let routes = routes
+                        .map(|(prefix, _account)| prefix.as_str())
+                        .chain(iter::once(""));
+                    let not_checked = |prefix: &&str| {
+                        !prefix.is_empty()
+                            && !configured_routes.contains_key(*prefix)
+                            && !local_routes.contains_key(*prefix)
+                    };
+                    // The routes to the previous addresses of our accounts, e.g. of the
+                    // children after our address changed, are withdrawn
+                    let mut others = local_table
+                        .prefixes()
+                        .map(|prefix| prefix.as_str())
+                        .filter(not_checked)
+                        .collect::<HashSet<&str>>();
+                    if self.latency.is_some() {
+                        // The best routes to the prefixes of our peers may have changed with
+                        // their latency scores
+                        others.extend(
+                            incoming_tables
+                                .values()
+                                .flat_map(|table| table.prefixes())
+                                .map(|prefix| prefix.as_str())
+                                .filter(not_checked),
+                        );
+                    }
+                    Box::new(routes.chain(others))
                 };
 
             // Check all the prefixes to see which ones we have different routes for
@@ -554,12 +677,21 @@ where
             let mut withdrawn_routes: Vec<&str> = Vec::new();
             for prefix in prefixes_to_check {
                 // See which prefixes there is now a better route for
-                if let Some((best_next_account, best_route)) = get_best_route_for_prefix(
+                let best = get_best_route_for_prefix(
                     &local_routes,
                     &configured_routes,
                     &incoming_tables,
+                    self.latency.as_ref(),
                     prefix,
-                ) {
+                )
+                .or_else(|| {
+                    if prefix.is_empty() {
+                        get_best_default_route(&parents)
+                    } else {
+                        None
+                    }
+                });
+                if let Some((best_next_account, best_route)) = best {
                     if let Some((ref next_account, ref _route)) = local_table.get_route(prefix) {
                         if next_account.id() == best_next_account.id() {
                             continue;
@@ -573,6 +705,9 @@ where
                     } else {
                         better_routes.push((prefix, best_next_account, best_route));
                     }
+                } else if prefix.is_empty() && local_table.get_route(prefix).is_none() {
+                    // There is no default route to withdraw
+                    continue;
                 } else {
                     // No longer have a route to this prefix
                     withdrawn_routes.push(prefix);
@@ -583,12 +718,14 @@ where
 
         // Update the local and forwarding tables
         if !better_routes.is_empty() || !withdrawn_routes.is_empty() {
-            let update_routes = {
+            let (update_routes, changes) = {
                 let mut local_table = local_table.write();
                 let mut forwarding_table = forwarding_table.write();
                 let mut forwarding_table_updates = forwarding_table_updates.write();
 
                 let mut new_routes: Vec<Route> = Vec::with_capacity(better_routes.len());
+                let mut changes: Vec<RouteChange> =
+                    Vec::with_capacity(better_routes.len() + withdrawn_routes.len());
 
                 for (prefix, account, mut route) in better_routes {
                     debug!(
@@ -597,7 +734,21 @@ where
                         account.username(),
                         account.id(),
                     );
-                    local_table.set_route(prefix.to_string(), account.clone(), route.clone());
+                    let next_hop = NextHop::new(&account, &route);
+                    let previous =
+                        local_table.set_route(prefix.to_string(), account.clone(), route.clone());
+                    let prefix_string = prefix.to_string();
+                    changes.push(if previous.is_some() {
+                        RouteChange::Updated {
+                            prefix: prefix_string,
+                            next_hop,
+                        }
+                    } else {
+                        RouteChange::Added {
+                            prefix: prefix_string,
+                            next_hop,
+                        }
+                    });
 
                     // Update the forwarding table
 
@@ -605,14 +756,14 @@ where
                     // or that advertise the whole global prefix
                     let address_scheme = ilp_address.scheme();
                     let correct_address_scheme =
-                        route.prefix.starts_with(address_scheme) && route.prefix != address_scheme;
+                        has_prefix(&route.prefix, address_scheme) && route.prefix != address_scheme;
                     // We do want to advertise our address
                     let is_our_address = route.prefix == &ilp_address as &str;
                     // Don't advertise local routes because advertising only our address
                     // will be enough to ensure the packet gets to us and we can route it
                     // to the correct account on our node
                     let is_local_route =
-                        route.prefix.starts_with(&ilp_address as &str) && route.path.is_empty();
+                        has_prefix(&route.prefix, &ilp_address) && route.path.is_empty();
                     let not_local_route = is_our_address || !is_local_route;
                     // Don't include routes we're also withdrawing
                     let not_withdrawn_route = !withdrawn_routes.contains(&prefix);
@@ -635,7 +786,11 @@ where
 
                 for prefix in withdrawn_routes.iter() {
                     debug!("Removed route for prefix: {}", prefix);
-                    local_table.delete_route(prefix);
+                    if local_table.delete_route(prefix) {
+                        changes.push(RouteChange::Removed {
+                            prefix: prefix.to_string(),
+                        });
+                    }
                     forwarding_table.delete_route(prefix);
                 }
 
@@ -644,21 +799,95 @@ where
                     new_routes,
                     withdrawn_routes
                         .into_iter()
+                        // The default route is never advertised
+                        .filter(|prefix| !prefix.is_empty())
                         .map(|s| s.to_string())
                         .collect(),
                 ));
                 debug_assert_eq!(epoch as usize + 1, forwarding_table_updates.len());
 
-                store.set_routes(local_table.get_simplified_table())
+                (
+                    store.set_routes(local_table.get_simplified_table()),
+                    changes,
+                )
             };
 
-            update_routes.await
+            let result = update_routes.await;
+            for change in changes {
+                // There may be no one watching the table
+                let _ = self.route_changes.send(change);
+            }
+            result
         } else {
             // The routing table hasn't changed
             Ok(())
         }
     }
 
+    /// Forget the routes received from the account and fail over to the next best routes
+    /// right away, instead of waiting for the account to withdraw them. This is meant to be
+    /// called when the connection to the account is lost.
+    pub async fn next_hop_disconnected(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let prefixes: Vec<String> = {
+            let mut incoming_tables = self.incoming_tables.write();
+            let table = incoming_tables.remove(&account_id);
+            let was_parent = self.parents.write().remove(&account_id).is_some();
+            table
+                .iter()
+                .flat_map(|table| table.prefixes().cloned())
+                .chain(if was_parent {
+                    Some(String::new())
+                } else {
+                    None
+                })
+                .collect()
+        };
+        if prefixes.is_empty() {
+            return Ok(());
+        }
+
+        debug!(
+            "Account {} disconnected, recalculating best routes for prefixes: {}",
+            account_id,
+            prefixes.join(", ")
+        );
+        self.update_best_routes(Some(prefixes)).await
+    }
+
+    /// Request the whole routing table of the account, if we receive routes from it.
+    /// This is meant to be called when the connection to the account is (re)established.
+    pub async fn next_hop_connected(&self, account_id: Uuid) {
+        let result = self.store.get_accounts_to_receive_routes_from().await;
+        let account = result
+            .unwrap_or_else(|_| Vec::new())
+            .into_iter()
+            .find(|account| account.id() == account_id);
+        if let Some(account) = account {
+            self.send_route_control_request(account, DUMMY_ROUTING_TABLE_ID, 0)
+                .await;
+        }
+    }
+
+    /// Install the routes to the addresses of a local account which the routing table is
+    /// missing. This is meant to be called when a child account connects, so that it can be
+    /// paid at the address it gets with IL-DCP.
+    pub async fn update_local_routes(&self, account_id: Uuid) -> Result<(), CcpRoutingStoreError> {
+        let (local_routes, _configured_routes) =
+            self.store.get_local_and_configured_routes().await?;
+        let prefixes: Vec<String> = local_routes
+            .into_iter()
+            .filter(|(_prefix, account)| account.id() == account_id)
+            .map(|(prefix, _account)| prefix)
+            .collect();
+        if prefixes.is_empty() {
+            return Ok(());
+        }
+        self.update_best_routes(Some(prefixes)).await
+    }
+
     /// Send RouteUpdateRequests to all peers that we send routing messages to
     async fn send_route_updates(&self) -> Result<(), CcpRoutingStoreError> {
         let self_clone = self.clone();
@@ -690,7 +919,7 @@ where
 
         let route_update_request = self_clone.create_route_update(from_epoch_index, to_epoch_index);
 
-        let prepare = route_update_request.to_prepare();
+        let prepare = route_update_request.to_prepare_at(self_clone.clock.now());
         accounts.sort_unstable_by_key(|a| a.id().to_string());
         accounts.dedup_by_key(|a| a.id());
 
@@ -864,7 +1093,7 @@ where
     async fn send_route_update(&self, account: A, from_epoch_index: u32, to_epoch_index: u32) {
         let prepare = self
             .create_route_update(from_epoch_index, to_epoch_index)
-            .to_prepare();
+            .to_prepare_at(self.clock.now());
         let account_id = account.id();
         debug!(
             "Sending individual route update to account: {} for epochs from: {} to: {}",
@@ -894,16 +1123,12 @@ fn get_best_route_for_prefix<A: CcpRoutingAccount>(
     local_routes: &HashMap<String, A>,
     configured_routes: &HashMap<String, A>,
     incoming_tables: &HashMap<Uuid, RoutingTable<A>>,
+    latency: Option<&LatencyScores>,
     prefix: &str,
 ) -> Option<(A, Route)> {
     // Check if we have a configured route for that specific prefix
     // or any shorter prefix ("example.a.b.c" will match "example.a.b" and "example.a")
-    // Note that this logic is duplicated from the Address type. We are not using
-    // Addresses here because the prefixes may not be valid ILP addresses ("example." is
-    // a valid prefix but not a valid address)
-    let segments: Vec<&str> = prefix.split(|c| c == '.').collect();
-    for i in 0..segments.len() {
-        let prefix = &segments[0..segments.len() - i].join(".");
+    for prefix in prefixes(prefix) {
         if let Some(account) = configured_routes.get(prefix) {
             return Some((
                 account.clone(),
@@ -937,27 +1162,29 @@ fn get_best_route_for_prefix<A: CcpRoutingAccount>(
             (account, route),
             |(best_account, best_route), (account, route)| {
                 // Prioritize child > peer > parent
-                match best_account
+                let ordering = best_account
                     .routing_relation()
                     .cmp(&account.routing_relation())
-                {
-                    StdOrdering::Greater => (best_account, best_route),
-                    StdOrdering::Less => (account, route),
-                    _ => {
-                        // Prioritize shortest path
-                        match best_route.path.len().cmp(&route.path.len()) {
-                            StdOrdering::Less => (best_account, best_route),
-                            StdOrdering::Greater => (account, route),
-                            _ => {
-                                // Finally base it on account ID
-                                if best_account.id().to_string() < account.id().to_string() {
-                                    (best_account, best_route)
-                                } else {
-                                    (account, route)
-                                }
-                            }
-                        }
-                    }
+                    // Then prioritize the account with the highest routing preference
+                    .then_with(|| {
+                        best_account
+                            .routing_preference()
+                            .cmp(&account.routing_preference())
+                    })
+                    // Then prioritize shortest path
+                    .then_with(|| route.path.len().cmp(&best_route.path.len()))
+                    // Then prioritize the next hop with the lowest latency
+                    .then_with(|| {
+                        latency.map_or(StdOrdering::Equal, |latency| {
+                            latency.compare(account.id(), best_account.id())
+                        })
+                    })
+                    // Finally base it on account ID
+                    .then_with(|| account.id().to_string().cmp(&best_account.id().to_string()));
+                if ordering == StdOrdering::Greater {
+                    (best_account, best_route)
+                } else {
+                    (account, route)
                 }
             },
         );
@@ -967,7 +1194,29 @@ fn get_best_route_for_prefix<A: CcpRoutingAccount>(
     }
 }
 
-#[async_trait]
+/// The default route goes through the parent with the highest routing preference,
+/// or the one with the lowest account ID among the equally preferred ones
+fn get_best_default_route<A: CcpRoutingAccount>(parents: &HashMap<Uuid, A>) -> Option<(A, Route)> {
+    parents
+        .values()
+        .max_by(|a, b| {
+            a.routing_preference()
+                .cmp(&b.routing_preference())
+                .then_with(|| b.id().to_string().cmp(&a.id().to_string()))
+        })
+        .map(|parent| {
+            (
+                parent.clone(),
+                Route {
+                    prefix: String::new(),
+                    auth: [0; 32],
+                    path: Vec::new(),
+                    props: Vec::new(),
+                },
+            )
+        })
+}
+
 impl<I, O, S, A> IncomingService<A> for CcpRouteManager<I, O, S, A>
 where
     I: IncomingService<A> + Clone + Send + Sync + 'static,
@@ -975,17 +1224,21 @@ where
     S: AddressStore + CcpRoutingStore<Account = A> + Clone + Send + Sync + 'static,
     A: CcpRoutingAccount + Send + Sync + 'static,
 {
+    type Future<'a> = BoxedIlpFuture<'a>;
+
     /// Handle the IncomingRequest if it is a CCP protocol message or
     /// pass it on to the next handler if not
-    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
-        let destination = request.prepare.destination();
-        if destination == *CCP_CONTROL_DESTINATION {
-            self.handle_route_control_request(request).await
-        } else if destination == *CCP_UPDATE_DESTINATION {
-            self.handle_route_update_request(request).await
-        } else {
-            self.next_incoming.handle_request(request).await
-        }
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let destination = request.prepare.destination();
+            if destination == *CCP_CONTROL_DESTINATION {
+                self.handle_route_control_request(request).await
+            } else if destination == *CCP_UPDATE_DESTINATION {
+                self.handle_route_update_request(request).await
+            } else {
+                self.next_incoming.handle_request(request).await
+            }
+        })
     }
 }
 
@@ -1057,6 +1310,15 @@ mod ranking_routes {
                 props: Vec::new(),
             },
         );
+        peer_table_1.add_route(
+            peer_1.clone(),
+            Route {
+                prefix: "example.f".to_string(),
+                path: vec!["example.one".to_string()],
+                auth: [0; 32],
+                props: Vec::new(),
+            },
+        );
         peer_table_1.add_route(
             peer_1,
             Route {
@@ -1070,7 +1332,7 @@ mod ranking_routes {
         let mut peer_table_2 = RoutingTable::default();
         let peer_2 = TestAccount::new(Uuid::from_slice(&[8; 16]).unwrap(), "example.peer2");
         peer_table_2.add_route(
-            peer_2,
+            peer_2.clone(),
             Route {
                 prefix: "example.e".to_string(),
                 path: vec!["example.one".to_string(), "example.two".to_string()],
@@ -1078,6 +1340,15 @@ mod ranking_routes {
                 props: Vec::new(),
             },
         );
+        peer_table_2.add_route(
+            peer_2,
+            Route {
+                prefix: "example.f".to_string(),
+                path: vec!["example.two".to_string()],
+                auth: [0; 32],
+                props: Vec::new(),
+            },
+        );
         HashMap::from_iter(vec![
             (Uuid::from_slice(&[6; 16]).unwrap(), child_table),
             (Uuid::from_slice(&[7; 16]).unwrap(), peer_table_1),
@@ -1087,7 +1358,8 @@ mod ranking_routes {
 
     #[test]
     fn prioritizes_configured_routes() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.a");
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, None, "example.a");
         assert_eq!(
             best_route.unwrap().0.id(),
             Uuid::from_slice(&[4; 16]).unwrap()
@@ -1097,7 +1369,7 @@ mod ranking_routes {
     #[test]
     fn prioritizes_shorter_configured_routes() {
         let best_route =
-            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.a.sub-prefix");
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, None, "example.a.sub-prefix");
         assert_eq!(
             best_route.unwrap().0.id(),
             Uuid::from_slice(&[4; 16]).unwrap()
@@ -1106,7 +1378,8 @@ mod ranking_routes {
 
     #[test]
     fn prioritizes_local_routes_over_broadcasted_ones() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.c");
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, None, "example.c");
         assert_eq!(
             best_route.unwrap().0.id(),
             Uuid::from_slice(&[3; 16]).unwrap()
@@ -1115,7 +1388,8 @@ mod ranking_routes {
 
     #[test]
     fn prioritizes_children_over_peers() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.d");
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, None, "example.d");
         assert_eq!(
             best_route.unwrap().0.id(),
             Uuid::from_slice(&[6; 16]).unwrap()
@@ -1124,16 +1398,72 @@ mod ranking_routes {
 
     #[test]
     fn prioritizes_shorter_paths() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.e");
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, None, "example.e");
         assert_eq!(
             best_route.unwrap().0.id(),
             Uuid::from_slice(&[7; 16]).unwrap()
         );
     }
 
+    #[test]
+    fn breaks_ties_by_latency() {
+        let peer_1 = Uuid::from_slice(&[7; 16]).unwrap();
+        let peer_2 = Uuid::from_slice(&[8; 16]).unwrap();
+        // Without latency scores, the lowest account ID wins
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, None, "example.f");
+        assert_eq!(best_route.unwrap().0.id(), peer_1);
+
+        let latency = LatencyScores::new();
+        latency.record(peer_2, Duration::from_millis(50));
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, Some(&latency), "example.f");
+        assert_eq!(best_route.unwrap().0.id(), peer_2);
+
+        latency.record(peer_1, Duration::from_millis(10));
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, Some(&latency), "example.f");
+        assert_eq!(best_route.unwrap().0.id(), peer_1);
+
+        // Latency does not override shorter paths
+        let latency = LatencyScores::new();
+        latency.record(peer_2, Duration::from_millis(1));
+        latency.record(peer_1, Duration::from_millis(100));
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, Some(&latency), "example.e");
+        assert_eq!(best_route.unwrap().0.id(), peer_1);
+    }
+
+    #[test]
+    fn prioritizes_preferred_accounts_over_shorter_paths() {
+        let mut preferred = TestAccount::new(Uuid::from_slice(&[9; 16]).unwrap(), "example.peer3");
+        preferred.preference = 10;
+        let mut preferred_table = RoutingTable::default();
+        preferred_table.add_route(
+            preferred.clone(),
+            Route {
+                prefix: "example.e".to_string(),
+                path: vec![
+                    "example.one".to_string(),
+                    "example.two".to_string(),
+                    "example.three".to_string(),
+                ],
+                auth: [0; 32],
+                props: Vec::new(),
+            },
+        );
+        let mut incoming = (*INCOMING).clone();
+        incoming.insert(preferred.id(), preferred_table);
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &incoming, None, "example.e");
+        assert_eq!(best_route.unwrap().0.id(), preferred.id());
+    }
+
     #[test]
     fn returns_none_for_no_route() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.z");
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, None, "example.z");
         assert!(best_route.is_none());
     }
 }
@@ -1144,6 +1474,7 @@ mod handle_route_control_request {
     use crate::fixtures::*;
     use crate::test_helpers::*;
     use interledger_packet::PrepareBuilder;
+    use interledger_service::clock::{Clock, ManualClock};
     use std::time::{Duration, SystemTime};
 
     #[tokio::test]
@@ -1250,6 +1581,72 @@ mod handle_route_control_request {
         assert_eq!(update.current_epoch_index, 1);
         assert_eq!(update.new_routes.len(), 3);
     }
+
+    #[tokio::test]
+    async fn checks_expiry_against_the_clock() {
+        // Packets carry their expiry in milliseconds
+        let clock = ManualClock::new(std::time::UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        let (mut service, outgoing_requests) = test_service_with_routes();
+        service.clock = clock.clone().into();
+        let prepare = RouteControlRequest {
+            last_known_routing_table_id: [0; 16],
+            mode: Mode::Sync,
+            last_known_epoch: 0,
+            features: Vec::new(),
+        }
+        .to_prepare_at(clock.now());
+
+        // The update sent in response expires relative to the clock as well
+        clock.advance(Duration::from_secs(60));
+        service
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: prepare.clone(),
+            })
+            .await
+            .unwrap();
+        let request: &OutgoingRequest<TestAccount> = &outgoing_requests.lock()[0];
+        assert_eq!(
+            request.prepare.expires_at(),
+            clock.now() + Duration::from_secs(60)
+        );
+
+        clock.advance(Duration::from_millis(1));
+        let result = service
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare,
+            })
+            .await;
+        assert_eq!(
+            str::from_utf8(result.unwrap_err().message()).unwrap(),
+            "Invalid route control request"
+        );
+    }
+    #[tokio::test]
+    async fn tolerates_clock_skew() {
+        let clock = ManualClock::new(std::time::UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        let (mut service, _outgoing_requests) = test_service_with_routes();
+        service.clock = clock.clone().into();
+        service.skew_tolerance = Duration::from_secs(2);
+        let prepare = RouteControlRequest {
+            last_known_routing_table_id: [0; 16],
+            mode: Mode::Sync,
+            last_known_epoch: 0,
+            features: Vec::new(),
+        }
+        .to_prepare_at(clock.now());
+
+        // The request expires 60 seconds after it is sent
+        clock.advance(Duration::from_secs(62));
+        let request = IncomingRequest {
+            from: ROUTING_ACCOUNT.clone(),
+            prepare,
+        };
+        assert!(service.handle_request(request.clone()).await.is_ok());
+        clock.advance(Duration::from_millis(1));
+        assert!(service.handle_request(request).await.is_err());
+    }
 }
 
 #[cfg(test)]
@@ -1591,6 +1988,126 @@ mod handle_route_update_request {
             .is_none());
     }
 
+    #[tokio::test]
+    async fn publishes_changes_to_watchers() {
+        let mut service = test_service();
+        let mut changes = service.watch();
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.to_epoch_index = 1;
+        request.from_epoch_index = 0;
+        service
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: request.to_prepare(),
+            })
+            .await
+            .unwrap();
+        let mut added = vec![changes.try_recv().unwrap(), changes.try_recv().unwrap()];
+        added.sort_by(|a, b| a.prefix().cmp(b.prefix()));
+        assert_eq!(
+            added[1],
+            RouteChange::Added {
+                prefix: "example.prefix2".to_string(),
+                next_hop: NextHop {
+                    id: ROUTING_ACCOUNT.id(),
+                    username: ROUTING_ACCOUNT.username().clone(),
+                    path: vec![
+                        "example.connector1".to_string(),
+                        "example.prefix2".to_string()
+                    ],
+                },
+            }
+        );
+        assert_eq!(added[0].prefix(), "example.prefix1");
+        assert!(changes.try_recv().is_err());
+
+        service
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: RouteUpdateRequest {
+                    routing_table_id: UPDATE_REQUEST_COMPLEX.routing_table_id,
+                    from_epoch_index: 1,
+                    to_epoch_index: 3,
+                    current_epoch_index: 3,
+                    hold_down_time: 45000,
+                    speaker: UPDATE_REQUEST_COMPLEX.speaker.clone(),
+                    new_routes: Vec::new(),
+                    withdrawn_routes: vec!["example.prefix2".to_string()],
+                }
+                .to_prepare(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            changes.try_recv().unwrap(),
+            RouteChange::Removed {
+                prefix: "example.prefix2".to_string()
+            }
+        );
+        assert!(changes.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn fails_over_between_parents() {
+        let mut service = test_service();
+        let mut parent_1 = TestAccount::new(Uuid::from_slice(&[1; 16]).unwrap(), "example.parent1");
+        parent_1.relation = RoutingRelation::Parent;
+        let mut parent_2 = TestAccount::new(Uuid::from_slice(&[2; 16]).unwrap(), "example.parent2");
+        parent_2.relation = RoutingRelation::Parent;
+        parent_2.preference = 10;
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.to_epoch_index = 1;
+        request.from_epoch_index = 0;
+        for parent in vec![parent_1.clone(), parent_2.clone()] {
+            service
+                .handle_request(IncomingRequest {
+                    from: parent,
+                    prepare: request.to_prepare(),
+                })
+                .await
+                .unwrap();
+        }
+
+        // The default route goes through the preferred parent, but does not
+        // match the prefixes which have no route
+        assert_eq!(
+            service.local_table.read().get_route("").unwrap().0.id(),
+            parent_2.id()
+        );
+        assert!(service
+            .local_table
+            .read()
+            .get_route("example.unknown")
+            .is_none());
+        assert_eq!(service.store.routes.lock()[""].id(), parent_2.id());
+
+        service.next_hop_disconnected(parent_2.id()).await.unwrap();
+        assert_eq!(
+            service.local_table.read().get_route("").unwrap().0.id(),
+            parent_1.id()
+        );
+        assert_eq!(
+            service
+                .local_table
+                .read()
+                .get_route("example.prefix1")
+                .unwrap()
+                .0
+                .id(),
+            parent_1.id()
+        );
+        assert_eq!(service.store.routes.lock()[""].id(), parent_1.id());
+
+        service.next_hop_disconnected(parent_1.id()).await.unwrap();
+        assert!(service.local_table.read().get_route("").is_none());
+        assert!(service
+            .local_table
+            .read()
+            .get_route("example.prefix1")
+            .is_none());
+        assert!(service.store.routes.lock().is_empty());
+    }
+
     #[tokio::test]
     async fn sends_control_request_if_routing_table_id_changed() {
         let (mut service, outgoing_requests) = test_service_with_routes();
@@ -1831,6 +2348,34 @@ mod send_route_updates {
         assert!(prefixes.contains(&"example.remote"));
     }
 
+    #[tokio::test]
+    async fn installs_the_local_routes_of_a_connected_account() {
+        let (service, _outgoing_requests) = test_service_with_routes();
+        let id = Uuid::from_slice(&[3; 16]).unwrap();
+        service.update_local_routes(id).await.unwrap();
+        let routes = service.store.routes.lock();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes["example.connector.other-local"].id, id);
+    }
+
+    #[tokio::test]
+    async fn withdraws_the_routes_to_previous_local_addresses() {
+        let (mut service, _outgoing_requests) = test_service_with_routes();
+        service.update_best_routes(None).await.unwrap();
+        assert!(service.store.routes.lock().contains_key("example.local.1"));
+
+        // The address of the account changed
+        let account = service.store.local.remove("example.local.1").unwrap();
+        service
+            .store
+            .local
+            .insert("example.local.2".to_string(), account);
+        service.update_best_routes(None).await.unwrap();
+        let routes = service.store.routes.lock();
+        assert!(!routes.contains_key("example.local.1"));
+        assert!(routes.contains_key("example.local.2"));
+    }
+
     #[tokio::test]
     async fn broadcasts_withdrawn_routes() {
         let id10 = Uuid::from_slice(&[10; 16]).unwrap();
@@ -1909,6 +2454,7 @@ mod send_route_updates {
                     id: id2,
                     ilp_address: Address::from_str("example.connector.other-local").unwrap(),
                     relation: RoutingRelation::Child,
+                    preference: 0,
                 },
             ),
         ]);
@@ -1997,6 +2543,7 @@ mod send_route_updates {
             id: id2,
             ilp_address: Address::from_str("example.connector.other-local").unwrap(),
             relation: RoutingRelation::Child,
+            preference: 0,
         };
         let local_routes = HashMap::from_iter(vec![
             (
@@ -0,0 +1,50 @@
+use crate::packet::Route;
+use interledger_service::{Account, Username};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Publishes the changes of the local routing table to its watchers
+pub type RouteChanges = broadcast::Sender<RouteChange>;
+
+/// The account a route goes through
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NextHop {
+    pub id: Uuid,
+    pub username: Username,
+    /// The nodes the route goes through after the account, as advertised by it
+    pub path: Vec<String>,
+}
+
+impl NextHop {
+    pub(crate) fn new<A: Account>(account: &A, route: &Route) -> Self {
+        NextHop {
+            id: account.id(),
+            username: account.username().clone(),
+            path: route.path.clone(),
+        }
+    }
+}
+
+/// A change of the best route to a prefix, serialized as a JSON object tagged with its `type`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RouteChange {
+    /// There was no route to the prefix before
+    Added { prefix: String, next_hop: NextHop },
+    /// The best route to the prefix now goes through another account
+    Updated { prefix: String, next_hop: NextHop },
+    /// There is no route to the prefix anymore
+    Removed { prefix: String },
+}
+
+impl RouteChange {
+    /// The prefix whose route changed
+    pub fn prefix(&self) -> &str {
+        match self {
+            RouteChange::Added { prefix, .. }
+            | RouteChange::Updated { prefix, .. }
+            | RouteChange::Removed { prefix } => prefix,
+        }
+    }
+}
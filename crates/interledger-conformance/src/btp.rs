@@ -0,0 +1,313 @@
+//! [RFC 23: Bilateral Transfer Protocol 2.0](https://interledger.org/rfcs/0023-bilateral-transfer-protocol/)
+//!
+//! The checks play the part of a BTP client connected to the BTP server of `interledger-btp`,
+//! with the messages written out byte by byte as the RFC specifies them.
+
+use crate::peer::{PeerAccount, PeerStore, Unreachable, PEER};
+use crate::report::{expect_eq, CheckResult, Report};
+use bytes::{Buf, BufMut, BytesMut};
+use interledger_btp::{btp_service_as_filter, BtpOutgoingService};
+use interledger_packet::{
+    oer::{BufOerExt, MutBufOerExt},
+    Address, Fulfill, FulfillBuilder, Prepare, PrepareBuilder,
+};
+use interledger_service::{incoming_service_fn, OutgoingRequest, OutgoingService};
+use once_cell::sync::Lazy;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use warp::{test::WsClient, ws::Message};
+
+const BTP: &str = "RFC 23";
+
+const RESPONSE: u8 = 1;
+const MESSAGE: u8 = 6;
+
+/// How long the server gets to answer a message
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+static SERVER_ADDRESS: Lazy<Address> =
+    Lazy::new(|| Address::from_str("example.conformance").unwrap());
+
+/// The auth message with request id 1: an `auth` entry with no data, followed by the token
+/// as the UTF-8 text of the `auth_token` entry
+static AUTH_MESSAGE: &[u8] = b"\
+    \x06\x00\x00\x00\x01\x27\
+    \x01\x02\
+    \x04auth\x00\x00\
+    \x0aauth_token\x01\x11conformance_token\
+";
+
+/// The response to the auth message, with no protocol data
+static AUTH_RESPONSE: &[u8] = b"\x01\x00\x00\x00\x01\x02\x01\x00";
+
+/// A Transfer of 10 units with request id 3 and no protocol data
+static TRANSFER: &[u8] = b"\x07\x00\x00\x00\x03\x0a\x00\x00\x00\x00\x00\x00\x00\x0a\x01\x00";
+
+/// The response to the transfer
+static TRANSFER_RESPONSE: &[u8] = b"\x01\x00\x00\x00\x03\x02\x01\x00";
+
+pub(crate) async fn check(report: &mut Report) {
+    report.record(
+        BTP,
+        "authenticates the connection with the auth_token",
+        authenticates().await,
+    );
+    report.record(
+        BTP,
+        "closes connections with an invalid auth_token",
+        closes_unauthenticated().await,
+    );
+    report.record(
+        BTP,
+        "closes connections whose first message is not the auth message",
+        closes_without_auth_message().await,
+    );
+    report.record(
+        BTP,
+        "answers a Prepare in a Response with the same request id",
+        answers_prepares().await,
+    );
+    report.record(
+        BTP,
+        "sends Prepares as Messages with ilp protocol data",
+        sends_prepares().await,
+    );
+    report.record(
+        BTP,
+        "acknowledges Transfers",
+        acknowledges_transfers().await,
+    );
+}
+
+/// One entry of the protocol data of a BTP packet
+struct ProtocolData {
+    protocol_name: String,
+    content_type: u8,
+    data: Vec<u8>,
+}
+
+/// Writes a BTP Message or Response whose only protocol data is the ILP packet
+fn ilp_packet(packet_type: u8, request_id: u32, ilp_packet: &[u8]) -> Vec<u8> {
+    let mut contents = BytesMut::new();
+    contents.put_var_uint(1);
+    contents.put_var_octet_string(&b"ilp"[..]);
+    // application/octet-stream
+    contents.put_u8(0);
+    contents.put_var_octet_string(ilp_packet);
+
+    let mut packet = Vec::new();
+    packet.put_u8(packet_type);
+    packet.put_u32(request_id);
+    packet.put_var_octet_string(&contents[..]);
+    packet
+}
+
+/// Reads a BTP Message or Response into its type, request id and protocol data
+fn read_packet(mut packet: &[u8]) -> Result<(u8, u32, Vec<ProtocolData>), String> {
+    if packet.len() < 5 {
+        return Err(format!("a packet of {} bytes is too short", packet.len()));
+    }
+    let packet_type = packet.get_u8();
+    let request_id = packet.get_u32();
+    let mut contents = packet
+        .read_var_octet_string()
+        .map_err(|err| format!("the contents could not be read: {}", err))?;
+    if !packet.is_empty() {
+        return Err("the packet has trailing bytes".to_string());
+    }
+
+    let read_protocol_data = |contents: &mut &[u8]| -> Result<ProtocolData, String> {
+        let protocol_name = contents
+            .read_var_octet_string()
+            .map_err(|e| e.to_string())?;
+        if contents.is_empty() {
+            return Err("an entry has no content type".to_string());
+        }
+        let content_type = contents.get_u8();
+        let data = contents
+            .read_var_octet_string()
+            .map_err(|e| e.to_string())?;
+        Ok(ProtocolData {
+            protocol_name: String::from_utf8_lossy(protocol_name).into_owned(),
+            content_type,
+            data: data.to_vec(),
+        })
+    };
+    let count = contents
+        .read_var_uint()
+        .map_err(|err| format!("the protocol data could not be read: {}", err))?;
+    let mut protocol_data = Vec::new();
+    for _ in 0..count {
+        protocol_data.push(read_protocol_data(&mut contents)?);
+    }
+    Ok((packet_type, request_id, protocol_data))
+}
+
+/// Reads the ILP packet out of the protocol data of a BTP packet
+fn ilp_protocol_data(protocol_data: Vec<ProtocolData>) -> Result<BytesMut, String> {
+    let ilp = protocol_data
+        .into_iter()
+        .find(|entry| entry.protocol_name == "ilp")
+        .ok_or_else(|| "there is no ilp protocol data".to_string())?;
+    expect_eq(
+        "the content type of the ilp protocol data",
+        ilp.content_type,
+        0,
+    )?;
+    Ok(BytesMut::from(&ilp.data[..]))
+}
+
+fn prepare() -> Prepare {
+    PrepareBuilder {
+        destination: Address::from_str("example.conformance.receiver").unwrap(),
+        amount: 100,
+        expires_at: SystemTime::now() + Duration::from_secs(30),
+        execution_condition: &[1; 32],
+        data: b"conformance",
+    }
+    .build()
+}
+
+type Server = BtpOutgoingService<Unreachable, PeerAccount>;
+
+/// A BTP server whose incoming handler fulfills all Prepares with a fulfillment of ones
+async fn server() -> Server {
+    let service = BtpOutgoingService::new(SERVER_ADDRESS.clone(), Unreachable);
+    service
+        .clone()
+        .handle_incoming(incoming_service_fn(|_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[1; 32],
+                data: b"conformance",
+            }
+            .build())
+        }))
+        .await;
+    service
+}
+
+async fn connect(service: &Server) -> Result<WsClient, String> {
+    warp::test::ws()
+        .path("/accounts/peer/ilp/btp")
+        .handshake(btp_service_as_filter(service.clone(), PeerStore))
+        .await
+        .map_err(|err| format!("the WebSocket handshake failed: {}", err))
+}
+
+async fn recv(client: &mut WsClient) -> Result<Vec<u8>, String> {
+    loop {
+        let message = tokio::time::timeout(RESPONSE_TIMEOUT, client.recv())
+            .await
+            .map_err(|_| "the server did not answer".to_string())?
+            .map_err(|err| format!("the connection was closed: {}", err))?;
+        // Pings may come before the answer
+        if message.is_binary() {
+            return Ok(message.into_bytes());
+        }
+    }
+}
+
+/// Passes if the server closes the connection without answering
+async fn expect_closed(client: &mut WsClient) -> CheckResult {
+    match tokio::time::timeout(RESPONSE_TIMEOUT, client.recv()).await {
+        Err(_) => Err("the connection was left open".to_string()),
+        Ok(Ok(message)) if message.is_binary() => {
+            Err(format!("the server answered with {:?}", message.as_bytes()))
+        }
+        Ok(_) => Ok(()),
+    }
+}
+
+async fn authenticated_client(service: &Server) -> Result<WsClient, String> {
+    let mut client = connect(service).await?;
+    client.send(Message::binary(AUTH_MESSAGE)).await;
+    let response = recv(&mut client).await?;
+    expect_eq("the auth response", &response[..], AUTH_RESPONSE)?;
+    Ok(client)
+}
+
+async fn authenticates() -> CheckResult {
+    authenticated_client(&server().await).await.map(|_| ())
+}
+
+async fn closes_unauthenticated() -> CheckResult {
+    let mut client = connect(&server().await).await?;
+    let mut message = AUTH_MESSAGE.to_vec();
+    // Changes the last character of the token
+    *message.last_mut().unwrap() = b'x';
+    client.send(Message::binary(message)).await;
+    expect_closed(&mut client).await
+}
+
+async fn closes_without_auth_message() -> CheckResult {
+    let mut client = connect(&server().await).await?;
+    client
+        .send(Message::binary(ilp_packet(MESSAGE, 1, prepare().as_ref())))
+        .await;
+    expect_closed(&mut client).await
+}
+
+async fn answers_prepares() -> CheckResult {
+    let mut client = authenticated_client(&server().await).await?;
+    client
+        .send(Message::binary(ilp_packet(MESSAGE, 2, prepare().as_ref())))
+        .await;
+
+    let (packet_type, request_id, protocol_data) = read_packet(&recv(&mut client).await?)?;
+    expect_eq("the packet type", packet_type, RESPONSE)?;
+    expect_eq("the request id", request_id, 2)?;
+    let fulfill = Fulfill::try_from(ilp_protocol_data(protocol_data)?)
+        .map_err(|err| format!("the response is not a Fulfill: {}", err))?;
+    expect_eq("the fulfillment", fulfill.fulfillment(), &[1; 32][..])
+}
+
+async fn sends_prepares() -> CheckResult {
+    let service = server().await;
+    let mut client = authenticated_client(&service).await?;
+    let mut outgoing = service.clone();
+    let sent = tokio::spawn(async move {
+        outgoing
+            .send_request(OutgoingRequest {
+                from: PEER.clone(),
+                to: PEER.clone(),
+                original_amount: 100,
+                prepare: prepare(),
+            })
+            .await
+    });
+
+    let (packet_type, request_id, protocol_data) = read_packet(&recv(&mut client).await?)?;
+    expect_eq("the packet type", packet_type, MESSAGE)?;
+    let prepare = Prepare::try_from(ilp_protocol_data(protocol_data)?)
+        .map_err(|err| format!("the message is not a Prepare: {}", err))?;
+    expect_eq("the amount", prepare.amount(), 100)?;
+
+    let fulfill = FulfillBuilder {
+        fulfillment: &[1; 32],
+        data: &[],
+    }
+    .build();
+    client
+        .send(Message::binary(ilp_packet(
+            RESPONSE,
+            request_id,
+            fulfill.as_ref(),
+        )))
+        .await;
+    match tokio::time::timeout(RESPONSE_TIMEOUT, sent).await {
+        Ok(Ok(Ok(_))) => Ok(()),
+        Ok(Ok(Err(reject))) => Err(format!(
+            "the response was not matched to the Prepare, which was rejected with {}",
+            reject.code()
+        )),
+        _ => Err("the response was not matched to the Prepare".to_string()),
+    }
+}
+
+async fn acknowledges_transfers() -> CheckResult {
+    let mut client = authenticated_client(&server().await).await?;
+    client.send(Message::binary(TRANSFER)).await;
+    let response = recv(&mut client).await?;
+    expect_eq("the response", &response[..], TRANSFER_RESPONSE)
+}
@@ -0,0 +1,126 @@
+//! [RFC 31: Interledger Dynamic Configuration Protocol](https://interledger.org/rfcs/0031-dynamic-configuration-protocol/)
+//!
+//! The checks play the part of a child asking the `IldcpService` of its parent for its address
+//! and asset.
+
+use crate::peer::{PeerAccount, PEER};
+use crate::report::{expect_eq, CheckResult, Report};
+use crate::vectors;
+use bytes::Bytes;
+use interledger_ildcp::{IldcpRequest, IldcpResponse, IldcpService};
+use interledger_packet::{ErrorCode, Prepare, PrepareBuilder, RejectBuilder};
+use interledger_service::{incoming_service_fn, IncomingRequest, IncomingService};
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime};
+
+const ILDCP: &str = "RFC 31";
+
+/// The response to the peer: its address, its asset scale and its asset code
+static RESPONSE: &[u8] = b"\x18example.conformance.peer\x09\x03XYZ";
+
+pub(crate) async fn check(report: &mut Report) {
+    report.record(
+        ILDCP,
+        "requests are zero-amount Prepares to peer.config",
+        writes_requests(),
+    );
+    report.record(ILDCP, "decodes the response", decodes_response());
+    report.record(
+        ILDCP,
+        "answers requests with the account's address and asset",
+        answers_requests().await,
+    );
+    report.record(
+        ILDCP,
+        "passes on Prepares which are not requests",
+        passes_on_other_packets().await,
+    );
+}
+
+/// An IL-DCP server whose next service rejects everything with F02
+fn server() -> impl IncomingService<PeerAccount> {
+    IldcpService::new(incoming_service_fn(|_: IncomingRequest<PeerAccount>| {
+        Err(RejectBuilder {
+            code: ErrorCode::F02_UNREACHABLE,
+            message: &[],
+            triggered_by: None,
+            data: &[],
+        }
+        .build())
+    }))
+}
+
+fn writes_requests() -> CheckResult {
+    let prepare = IldcpRequest::new().to_prepare();
+    expect_eq(
+        "the destination",
+        prepare.destination().to_string(),
+        "peer.config".to_string(),
+    )?;
+    expect_eq("the amount", prepare.amount(), 0)?;
+    expect_eq(
+        "the execution condition",
+        prepare.execution_condition(),
+        &vectors::PEER_PROTOCOL_CONDITION[..],
+    )?;
+    expect_eq("the data", prepare.data(), &[][..])
+}
+
+fn decodes_response() -> CheckResult {
+    let response = IldcpResponse::try_from(Bytes::from_static(RESPONSE))
+        .map_err(|err| format!("the response could not be decoded: {}", err))?;
+    expect_eq(
+        "the address",
+        response.ilp_address(),
+        PEER.ilp_address().clone(),
+    )?;
+    expect_eq("the asset scale", response.asset_scale(), 9)?;
+    expect_eq("the asset code", response.asset_code(), &b"XYZ"[..])
+}
+
+async fn answers_requests() -> CheckResult {
+    let prepare = PrepareBuilder {
+        destination: "peer.config".parse().unwrap(),
+        amount: 0,
+        expires_at: SystemTime::now() + Duration::from_secs(30),
+        execution_condition: &vectors::PEER_PROTOCOL_CONDITION,
+        data: &[],
+    }
+    .build();
+    let fulfill = server()
+        .handle_request(IncomingRequest {
+            from: PEER.clone(),
+            prepare,
+        })
+        .await
+        .map_err(|reject| format!("the request was rejected with {}", reject.code()))?;
+    expect_eq(
+        "the fulfillment",
+        fulfill.fulfillment(),
+        &vectors::PEER_PROTOCOL_FULFILLMENT[..],
+    )?;
+    expect_eq("the response", fulfill.data(), RESPONSE)
+}
+
+async fn passes_on_other_packets() -> CheckResult {
+    // Addressed to peer.config, but not with the peer protocol condition
+    let prepare: Prepare = PrepareBuilder {
+        destination: "peer.config".parse().unwrap(),
+        amount: 0,
+        expires_at: SystemTime::now() + Duration::from_secs(30),
+        execution_condition: &[1; 32],
+        data: &[],
+    }
+    .build();
+    match server()
+        .handle_request(IncomingRequest {
+            from: PEER.clone(),
+            prepare,
+        })
+        .await
+    {
+        Err(reject) if reject.code() == ErrorCode::F02_UNREACHABLE => Ok(()),
+        Err(reject) => Err(format!("the Prepare was rejected with {}", reject.code())),
+        Ok(_) => Err("the Prepare was answered as a request".to_string()),
+    }
+}
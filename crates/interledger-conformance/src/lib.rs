@@ -0,0 +1,98 @@
+//! # interledger-conformance
+//!
+//! Checks the services of Interledger.rs against the test vectors and the behavior specified
+//! by the Interledger RFCs, and reports which checks passed.
+//!
+//! The checks encode and decode the messages themselves, byte by byte as the RFCs lay them
+//! out, rather than with the codecs under test:
+//!
+//! - [RFC 27](https://interledger.org/rfcs/0027-interledger-protocol-4/) and
+//!   [RFC 15](https://interledger.org/rfcs/0015-ilp-addresses/): the Prepare, Fulfill and
+//!   Reject vectors, malformed packets and ILP addresses
+//! - [RFC 23](https://interledger.org/rfcs/0023-bilateral-transfer-protocol/): the BTP auth
+//!   flow and the exchange of ILP packets over a WebSocket
+//! - [RFC 29](https://interledger.org/rfcs/0029-stream/): STREAM packets sent to the receiver,
+//!   their encryption, fulfillments and frames
+//! - [RFC 31](https://interledger.org/rfcs/0031-dynamic-configuration-protocol/): IL-DCP
+//!   requests and responses
+//!
+//! The vectors are exported in [`vectors`](./vectors/index.html), so other implementations can
+//! check their codecs against the same bytes.
+//!
+//! ```no_run
+//! # async fn run() {
+//! use interledger_conformance::{run, Suite};
+//!
+//! let report = run(Suite::ALL).await;
+//! println!("{}", report);
+//! assert!(report.is_conformant());
+//! # }
+//! ```
+
+mod btp;
+mod ildcp;
+mod packet;
+mod peer;
+mod report;
+mod stream;
+pub mod vectors;
+
+pub use self::report::{Check, Report};
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A group of checks, one for each protocol
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Suite {
+    Packet,
+    Btp,
+    Stream,
+    Ildcp,
+}
+
+impl Suite {
+    pub const ALL: &'static [Suite] = &[Suite::Packet, Suite::Btp, Suite::Stream, Suite::Ildcp];
+}
+
+impl FromStr for Suite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "packet" => Ok(Suite::Packet),
+            "btp" => Ok(Suite::Btp),
+            "stream" => Ok(Suite::Stream),
+            "ildcp" => Ok(Suite::Ildcp),
+            _ => Err(format!(
+                "unknown suite {:?}, expected one of packet, btp, stream or ildcp",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Suite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Suite::Packet => "packet",
+            Suite::Btp => "btp",
+            Suite::Stream => "stream",
+            Suite::Ildcp => "ildcp",
+        })
+    }
+}
+
+/// Runs the checks of the suites, in order
+pub async fn run(suites: &[Suite]) -> Report {
+    let mut report = Report::default();
+    for suite in suites {
+        match suite {
+            Suite::Packet => packet::check(&mut report),
+            Suite::Btp => btp::check(&mut report).await,
+            Suite::Stream => stream::check(&mut report).await,
+            Suite::Ildcp => ildcp::check(&mut report).await,
+        }
+    }
+    report
+}
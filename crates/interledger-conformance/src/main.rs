@@ -0,0 +1,26 @@
+use interledger_conformance::{run, Suite};
+use std::process::exit;
+
+/// Runs the suites named on the command line, or all of them, and exits with 1 if any check
+/// failed
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let suites = match std::env::args()
+        .skip(1)
+        .map(|arg| arg.parse())
+        .collect::<Result<Vec<Suite>, _>>()
+    {
+        Ok(suites) if suites.is_empty() => Suite::ALL.to_vec(),
+        Ok(suites) => suites,
+        Err(err) => {
+            eprintln!("interledger-conformance error: {}", err);
+            exit(2);
+        }
+    };
+
+    let report = run(&suites).await;
+    println!("{}", report);
+    if !report.is_conformant() {
+        exit(1);
+    }
+}
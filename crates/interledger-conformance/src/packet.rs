@@ -0,0 +1,216 @@
+//! [RFC 27: Interledger Protocol V4](https://interledger.org/rfcs/0027-interledger-protocol-4/)
+//! and [RFC 15: ILP Addresses](https://interledger.org/rfcs/0015-ilp-addresses/)
+
+use crate::report::{expect_eq, CheckResult, Report};
+use crate::vectors;
+use bytes::BytesMut;
+use chrono::DateTime;
+use interledger_packet::{
+    Address, ErrorCode, Fulfill, FulfillBuilder, MaxPacketAmountDetails, Prepare, PrepareBuilder,
+    Reject, RejectBuilder,
+};
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+const ILPV4: &str = "RFC 27";
+const ADDRESSES: &str = "RFC 15";
+
+/// Addresses from the examples of RFC 15, one for each allocation scheme
+static VALID_ADDRESSES: &[&str] = &[
+    "g.acme.bob",
+    "g.us-fed.ach.0.acmebank.swx0a0.acmecorp.sales.199.~ipr.cdfa5e16-e759-4ba3-88f6-8b9dc83c1868.2",
+    "private.acme.bob",
+    "example.alice",
+    "peer.config",
+    "self.rates",
+    "test.bob_1",
+    "test1.bob",
+    "test2.bob",
+    "test3.bob",
+    "local.node",
+];
+
+static INVALID_ADDRESSES: &[&str] = &[
+    "",
+    "g",
+    "what.acme",
+    "test4.bob",
+    "g.acme..bob",
+    "g.acme.bob.",
+    ".g.acme",
+    "g.acme bob",
+    "g.acme!bob",
+];
+
+pub(crate) fn check(report: &mut Report) {
+    report.record(ILPV4, "decodes the Prepare vector", decodes_prepare());
+    report.record(ILPV4, "encodes the Prepare vector", encodes_prepare());
+    report.record(ILPV4, "decodes the Fulfill vector", decodes_fulfill());
+    report.record(ILPV4, "encodes the Fulfill vector", encodes_fulfill());
+    report.record(ILPV4, "decodes the Reject vector", decodes_reject());
+    report.record(ILPV4, "encodes the Reject vector", encodes_reject());
+    report.record(
+        ILPV4,
+        "rejects packets of an unknown type",
+        rejects_unknown_type(),
+    );
+    report.record(ILPV4, "rejects truncated packets", rejects_truncated());
+    report.record(
+        ILPV4,
+        "encodes the F08 amount details",
+        encodes_max_packet_amount(),
+    );
+    report.record(
+        ADDRESSES,
+        "accepts the addresses of all allocation schemes",
+        accepts_valid_addresses(),
+    );
+    report.record(
+        ADDRESSES,
+        "rejects malformed addresses",
+        rejects_invalid_addresses(),
+    );
+}
+
+fn expires_at() -> SystemTime {
+    DateTime::parse_from_rfc3339(vectors::PREPARE_EXPIRES_AT)
+        .unwrap()
+        .into()
+}
+
+fn decodes_prepare() -> CheckResult {
+    let prepare = Prepare::try_from(BytesMut::from(vectors::PREPARE))
+        .map_err(|err| format!("Prepare could not be decoded: {}", err))?;
+    expect_eq("amount", prepare.amount(), vectors::PREPARE_AMOUNT)?;
+    expect_eq(
+        "destination",
+        prepare.destination().to_string(),
+        vectors::PREPARE_DESTINATION.to_string(),
+    )?;
+    expect_eq("expiry", prepare.expires_at(), expires_at())?;
+    expect_eq(
+        "execution condition",
+        prepare.execution_condition(),
+        &vectors::CONDITION[..],
+    )?;
+    expect_eq("data", prepare.data(), vectors::DATA)
+}
+
+fn encodes_prepare() -> CheckResult {
+    let prepare = PrepareBuilder {
+        amount: vectors::PREPARE_AMOUNT,
+        destination: Address::from_str(vectors::PREPARE_DESTINATION).unwrap(),
+        expires_at: expires_at(),
+        execution_condition: &vectors::CONDITION,
+        data: vectors::DATA,
+    }
+    .build();
+    expect_eq("encoding", prepare.as_ref(), vectors::PREPARE)
+}
+
+fn decodes_fulfill() -> CheckResult {
+    let fulfill = Fulfill::try_from(BytesMut::from(vectors::FULFILL))
+        .map_err(|err| format!("Fulfill could not be decoded: {}", err))?;
+    expect_eq(
+        "fulfillment",
+        fulfill.fulfillment(),
+        &vectors::CONDITION[..],
+    )?;
+    expect_eq("data", fulfill.data(), vectors::DATA)
+}
+
+fn encodes_fulfill() -> CheckResult {
+    let fulfill = FulfillBuilder {
+        fulfillment: &vectors::CONDITION,
+        data: vectors::DATA,
+    }
+    .build();
+    expect_eq("encoding", fulfill.as_ref(), vectors::FULFILL)
+}
+
+fn decodes_reject() -> CheckResult {
+    let reject = Reject::try_from(BytesMut::from(vectors::REJECT))
+        .map_err(|err| format!("Reject could not be decoded: {}", err))?;
+    expect_eq(
+        "code",
+        reject.code().to_string(),
+        vectors::REJECT_CODE.to_string(),
+    )?;
+    expect_eq(
+        "triggered by",
+        reject.triggered_by().map(|address| address.to_string()),
+        Some(vectors::REJECT_TRIGGERED_BY.to_string()),
+    )?;
+    expect_eq("message", reject.message(), vectors::REJECT_MESSAGE)?;
+    expect_eq("data", reject.data(), vectors::DATA)
+}
+
+fn encodes_reject() -> CheckResult {
+    let triggered_by = Address::from_str(vectors::REJECT_TRIGGERED_BY).unwrap();
+    let reject = RejectBuilder {
+        code: ErrorCode::F99_APPLICATION_ERROR,
+        message: vectors::REJECT_MESSAGE,
+        triggered_by: Some(&triggered_by),
+        data: vectors::DATA,
+    }
+    .build();
+    expect_eq("encoding", reject.as_ref(), vectors::REJECT)
+}
+
+fn rejects_unknown_type() -> CheckResult {
+    let mut bytes = BytesMut::from(vectors::PREPARE);
+    // Type 15 is not assigned to any ILPv4 packet
+    bytes[0] = 15;
+    match Prepare::try_from(bytes) {
+        Ok(_) => Err("a packet of type 15 was decoded as a Prepare".to_string()),
+        Err(_) => Ok(()),
+    }
+}
+
+fn rejects_truncated() -> CheckResult {
+    for (name, vector) in &[
+        ("Prepare", vectors::PREPARE),
+        ("Fulfill", vectors::FULFILL),
+        ("Reject", vectors::REJECT),
+    ] {
+        let truncated = BytesMut::from(&vector[..vector.len() - 1]);
+        let decoded = match vector[0] {
+            12 => Prepare::try_from(truncated).is_ok(),
+            13 => Fulfill::try_from(truncated).is_ok(),
+            _ => Reject::try_from(truncated).is_ok(),
+        };
+        if decoded {
+            return Err(format!("a {} missing its last byte was decoded", name));
+        }
+    }
+    Ok(())
+}
+
+fn encodes_max_packet_amount() -> CheckResult {
+    // Two unsigned 64-bit big-endian integers: the amount received, then the maximum amount
+    let details = MaxPacketAmountDetails::new(1000, 100);
+    expect_eq(
+        "encoding",
+        &details.to_bytes()[..],
+        &b"\x00\x00\x00\x00\x00\x00\x03\xe8\x00\x00\x00\x00\x00\x00\x00\x64"[..],
+    )
+}
+
+fn accepts_valid_addresses() -> CheckResult {
+    for address in VALID_ADDRESSES {
+        if let Err(err) = Address::from_str(address) {
+            return Err(format!("{:?} was rejected: {}", address, err));
+        }
+    }
+    Ok(())
+}
+
+fn rejects_invalid_addresses() -> CheckResult {
+    for address in INVALID_ADDRESSES {
+        if Address::from_str(address).is_ok() {
+            return Err(format!("{:?} was accepted", address));
+        }
+    }
+    Ok(())
+}
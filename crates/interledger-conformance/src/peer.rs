@@ -0,0 +1,124 @@
+use async_trait::async_trait;
+use futures::channel::mpsc::UnboundedSender;
+use interledger_btp::{BtpAccount, BtpStore};
+use interledger_errors::BtpStoreError;
+use interledger_packet::{Address, ErrorCode, RejectBuilder};
+use interledger_service::{Account, BoxedIlpFuture, OutgoingRequest, OutgoingService, Username};
+use interledger_stream::{PaymentNotification, StreamNotificationsStore};
+use once_cell::sync::Lazy;
+use std::str::FromStr;
+use tokio::sync::broadcast;
+use url::Url;
+use uuid::Uuid;
+
+/// The token the peer authenticates its BTP connections with
+pub(crate) const BTP_TOKEN: &str = "conformance_token";
+
+pub(crate) static PEER: Lazy<PeerAccount> = Lazy::new(|| PeerAccount {
+    id: Uuid::from_u128(1),
+    username: Username::from_str("peer").unwrap(),
+    ilp_address: Address::from_str("example.conformance.peer").unwrap(),
+});
+
+/// The account of the peer which the checks play the part of. Its asset is the one
+/// IL-DCP reports to it and the STREAM receiver reports to its senders.
+#[derive(Clone, Debug)]
+pub(crate) struct PeerAccount {
+    id: Uuid,
+    username: Username,
+    ilp_address: Address,
+}
+
+impl Account for PeerAccount {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn username(&self) -> &Username {
+        &self.username
+    }
+
+    fn ilp_address(&self) -> &Address {
+        &self.ilp_address
+    }
+
+    fn asset_scale(&self) -> u8 {
+        9
+    }
+
+    fn asset_code(&self) -> &str {
+        "XYZ"
+    }
+}
+
+impl BtpAccount for PeerAccount {
+    fn get_ilp_over_btp_url(&self) -> Option<&Url> {
+        None
+    }
+
+    fn get_ilp_over_btp_outgoing_token(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+/// Holds only the peer's account
+#[derive(Clone)]
+pub(crate) struct PeerStore;
+
+#[async_trait]
+impl BtpStore for PeerStore {
+    type Account = PeerAccount;
+
+    async fn get_account_from_btp_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<PeerAccount, BtpStoreError> {
+        if *username == PEER.username && token == BTP_TOKEN {
+            Ok(PEER.clone())
+        } else {
+            Err(BtpStoreError::Unauthorized(username.to_string()))
+        }
+    }
+
+    async fn get_btp_outgoing_accounts(&self) -> Result<Vec<PeerAccount>, BtpStoreError> {
+        Ok(Vec::new())
+    }
+}
+
+impl StreamNotificationsStore for PeerStore {
+    type Account = PeerAccount;
+
+    fn add_payment_notification_subscription(
+        &self,
+        _account_id: Uuid,
+        _sender: UnboundedSender<PaymentNotification>,
+    ) {
+    }
+
+    fn publish_payment_notification(&self, _payment: PaymentNotification) {}
+
+    fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
+        broadcast::channel(1).1
+    }
+}
+
+/// The next outgoing service of the services under test, which rejects all Prepares
+#[derive(Clone)]
+pub(crate) struct Unreachable;
+
+impl OutgoingService<PeerAccount> for Unreachable {
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    fn send_request(&mut self, _request: OutgoingRequest<PeerAccount>) -> Self::Future<'_> {
+        Box::pin(async move {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: &[],
+                triggered_by: None,
+                data: &[],
+            }
+            .build())
+        })
+    }
+}
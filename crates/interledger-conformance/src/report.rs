@@ -0,0 +1,102 @@
+use std::fmt::{self, Debug, Display};
+
+/// The outcome of a single check
+#[derive(Clone, Debug, PartialEq)]
+pub struct Check {
+    /// The RFC which the checked behavior is specified in, e.g. `RFC 27`
+    pub rfc: &'static str,
+    /// What was checked
+    pub name: &'static str,
+    /// Why the check failed, or `None` if it passed
+    pub failure: Option<String>,
+}
+
+impl Check {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// The outcomes of all the checks which were run, in the order they were run in
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    checks: Vec<Check>,
+}
+
+impl Report {
+    pub(crate) fn record(&mut self, rfc: &'static str, name: &'static str, result: CheckResult) {
+        self.checks.push(Check {
+            rfc,
+            name,
+            failure: result.err(),
+        });
+    }
+
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks.iter().filter(|check| !check.passed())
+    }
+
+    /// Whether all checks passed
+    pub fn is_conformant(&self) -> bool {
+        self.checks.iter().all(Check::passed)
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for check in &self.checks {
+            match check.failure {
+                None => writeln!(f, "PASS  {:<7} {}", check.rfc, check.name)?,
+                Some(ref failure) => {
+                    writeln!(f, "FAIL  {:<7} {}: {}", check.rfc, check.name, failure)?
+                }
+            }
+        }
+        let failed = self.failures().count();
+        write!(
+            f,
+            "{} checks, {} passed, {} failed",
+            self.checks.len(),
+            self.checks.len() - failed,
+            failed
+        )
+    }
+}
+
+/// A check fails with a description of what differed from the RFC
+pub(crate) type CheckResult = Result<(), String>;
+
+pub(crate) fn expect_eq<T: PartialEq + Debug>(what: &str, actual: T, expected: T) -> CheckResult {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} was {:?}, expected {:?}",
+            what, actual, expected
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_the_checks() {
+        let mut report = Report::default();
+        report.record("RFC 27", "passes", Ok(()));
+        report.record("RFC 27", "fails", expect_eq("amount", 1, 2));
+        assert!(!report.is_conformant());
+        assert_eq!(report.failures().count(), 1);
+        assert_eq!(
+            report.to_string(),
+            "PASS  RFC 27  passes\n\
+             FAIL  RFC 27  fails: amount was 1, expected 2\n\
+             2 checks, 1 passed, 1 failed"
+        );
+    }
+}
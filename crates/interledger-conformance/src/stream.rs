@@ -0,0 +1,326 @@
+//! [RFC 29: STREAM](https://interledger.org/rfcs/0029-stream/)
+//!
+//! The checks play the part of a STREAM sender paying the STREAM receiver of
+//! `interledger-stream`. The packets are encrypted, and the fulfillments derived, as the RFC
+//! specifies rather than with the receiver's own code.
+
+use crate::peer::{PeerStore, Unreachable, PEER};
+use crate::report::{expect_eq, CheckResult, Report};
+use bytes::{Buf, BufMut, Bytes};
+use interledger_packet::{
+    oer::{BufOerExt, MutBufOerExt},
+    ErrorCode, PrepareBuilder,
+};
+use interledger_service::{Account, IlpResult, OutgoingRequest, OutgoingService};
+use interledger_stream::{ConnectionGenerator, StreamReceiverService};
+use ring::{aead, digest, hmac};
+use std::time::{Duration, SystemTime};
+
+const STREAM: &str = "RFC 29";
+
+const VERSION: u8 = 1;
+const PREPARE: u8 = 12;
+const FULFILL: u8 = 13;
+const REJECT: u8 = 14;
+
+const CONNECTION_NEW_ADDRESS: u8 = 0x02;
+const CONNECTION_ASSET_DETAILS: u8 = 0x07;
+const STREAM_MONEY: u8 = 0x11;
+const STREAM_MAX_MONEY: u8 = 0x12;
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+static SERVER_SECRET: [u8; 32] = [7; 32];
+
+pub(crate) async fn check(report: &mut Report) {
+    report.record(
+        STREAM,
+        "fulfills Prepares carrying at least the minimum amount",
+        fulfills_money().await,
+    );
+    report.record(
+        STREAM,
+        "rejects Prepares carrying less than the minimum amount",
+        rejects_too_little_money().await,
+    );
+    report.record(
+        STREAM,
+        "answers ConnectionNewAddress with ConnectionAssetDetails",
+        sends_asset_details().await,
+    );
+    report.record(
+        STREAM,
+        "passes on Prepares whose data does not decrypt",
+        passes_on_other_packets().await,
+    );
+}
+
+/// A STREAM packet, with the contents of each frame left encoded
+#[derive(Debug)]
+struct StreamPacket {
+    packet_type: u8,
+    sequence: u64,
+    prepare_amount: u64,
+    frames: Vec<(u8, Vec<u8>)>,
+}
+
+impl StreamPacket {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.put_u8(VERSION);
+        bytes.put_u8(self.packet_type);
+        bytes.put_var_uint(self.sequence);
+        bytes.put_var_uint(self.prepare_amount);
+        bytes.put_var_uint(self.frames.len() as u64);
+        for (frame_type, contents) in &self.frames {
+            bytes.put_u8(*frame_type);
+            bytes.put_var_octet_string(&contents[..]);
+        }
+        bytes
+    }
+
+    fn from_bytes(mut bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 2 {
+            return Err("the STREAM packet is too short".to_string());
+        }
+        expect_eq("the STREAM version", bytes.get_u8(), VERSION)?;
+        let packet_type = bytes.get_u8();
+        let mut read_var_uint = || {
+            bytes
+                .read_var_uint()
+                .map_err(|err| format!("the STREAM packet could not be read: {}", err))
+        };
+        let sequence = read_var_uint()?;
+        let prepare_amount = read_var_uint()?;
+        let frame_count = read_var_uint()?;
+        let mut frames = Vec::new();
+        for _ in 0..frame_count {
+            if bytes.is_empty() {
+                return Err("the STREAM packet has fewer frames than it counts".to_string());
+            }
+            let frame_type = bytes.get_u8();
+            let contents = bytes
+                .read_var_octet_string()
+                .map_err(|err| format!("a frame could not be read: {}", err))?;
+            frames.push((frame_type, contents.to_vec()));
+        }
+        Ok(StreamPacket {
+            packet_type,
+            sequence,
+            prepare_amount,
+            frames,
+        })
+    }
+
+    /// The contents of the first frame of the type
+    fn frame(&self, frame_type: u8) -> Result<&[u8], String> {
+        self.frames
+            .iter()
+            .find(|(found, _)| *found == frame_type)
+            .map(|(_, contents)| &contents[..])
+            .ok_or_else(|| format!("there is no frame of type {:#04x}", frame_type))
+    }
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::sign(&key, message).as_ref().to_vec()
+}
+
+fn encryption_key(shared_secret: &[u8]) -> aead::LessSafeKey {
+    let key = hmac_sha256(shared_secret, b"ilp_stream_encryption");
+    aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &key).unwrap())
+}
+
+/// AES-256-GCM, laid out as the nonce, the authentication tag and then the ciphertext
+fn encrypt(shared_secret: &[u8], mut plaintext: Vec<u8>) -> Vec<u8> {
+    let nonce = [3; NONCE_LEN];
+    encryption_key(shared_secret)
+        .seal_in_place_append_tag(
+            aead::Nonce::assume_unique_for_key(nonce),
+            aead::Aad::empty(),
+            &mut plaintext,
+        )
+        .unwrap();
+    let tag = plaintext.split_off(plaintext.len() - TAG_LEN);
+    [&nonce[..], &tag[..], &plaintext[..]].concat()
+}
+
+fn decrypt(shared_secret: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < NONCE_LEN + TAG_LEN {
+        return Err("the response data is too short to be encrypted".to_string());
+    }
+    let mut nonce = [0; NONCE_LEN];
+    nonce.copy_from_slice(&data[..NONCE_LEN]);
+    let tag = &data[NONCE_LEN..NONCE_LEN + TAG_LEN];
+    let mut ciphertext = [&data[NONCE_LEN + TAG_LEN..], tag].concat();
+    let plaintext = encryption_key(shared_secret)
+        .open_in_place(
+            aead::Nonce::assume_unique_for_key(nonce),
+            aead::Aad::empty(),
+            &mut ciphertext,
+        )
+        .map_err(|_| "the response data could not be decrypted".to_string())?;
+    Ok(plaintext.to_vec())
+}
+
+fn fulfillment(shared_secret: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac_sha256(&hmac_sha256(shared_secret, b"ilp_stream_fulfillment"), data)
+}
+
+/// A Prepare sent to the receiver, and the receiver's answer
+struct Sent {
+    shared_secret: [u8; 32],
+    data: Vec<u8>,
+    result: IlpResult,
+}
+
+/// Sends a Prepare for the amount carrying the STREAM packet to the receiver, with a
+/// condition it can fulfill unless `fulfillable` is false
+async fn send(packet: StreamPacket, amount: u64, fulfillable: bool) -> Sent {
+    let (destination, shared_secret) = ConnectionGenerator::new(Bytes::from_static(&SERVER_SECRET))
+        .generate_address_and_secret(PEER.ilp_address());
+    let data = encrypt(&shared_secret, packet.to_bytes());
+    let mut condition = [0; 32];
+    if fulfillable {
+        let fulfillment = fulfillment(&shared_secret, &data);
+        condition.copy_from_slice(digest::digest(&digest::SHA256, &fulfillment).as_ref());
+    }
+
+    let mut receiver =
+        StreamReceiverService::new(Bytes::from_static(&SERVER_SECRET), PeerStore, Unreachable);
+    let result = receiver
+        .send_request(OutgoingRequest {
+            from: PEER.clone(),
+            to: PEER.clone(),
+            original_amount: amount,
+            prepare: PrepareBuilder {
+                destination,
+                amount,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &condition,
+                data: &data,
+            }
+            .build(),
+        })
+        .await;
+    Sent {
+        shared_secret,
+        data,
+        result,
+    }
+}
+
+fn money(prepare_amount: u64) -> StreamPacket {
+    let mut stream_money = Vec::new();
+    // Stream 1 gets all of the shares
+    stream_money.put_var_uint(1);
+    stream_money.put_var_uint(1);
+    StreamPacket {
+        packet_type: PREPARE,
+        sequence: 1,
+        prepare_amount,
+        frames: vec![(STREAM_MONEY, stream_money)],
+    }
+}
+
+async fn fulfills_money() -> CheckResult {
+    let sent = send(money(100), 100, true).await;
+    let fulfill = sent
+        .result
+        .map_err(|reject| format!("the Prepare was rejected: {:?}", reject))?;
+    expect_eq(
+        "the fulfillment",
+        fulfill.fulfillment(),
+        &fulfillment(&sent.shared_secret, &sent.data)[..],
+    )?;
+    let response = StreamPacket::from_bytes(&decrypt(&sent.shared_secret, fulfill.data())?)?;
+    expect_eq("the response type", response.packet_type, FULFILL)?;
+    expect_eq("the sequence", response.sequence, 1)?;
+    expect_eq("the amount received", response.prepare_amount, 100)?;
+    let mut max_money = response.frame(STREAM_MAX_MONEY)?;
+    expect_eq(
+        "the stream id of StreamMaxMoney",
+        max_money.read_var_uint().ok(),
+        Some(1),
+    )
+}
+
+async fn rejects_too_little_money() -> CheckResult {
+    let sent = send(money(200), 100, true).await;
+    let reject = match sent.result {
+        Ok(_) => return Err("the Prepare was fulfilled".to_string()),
+        Err(reject) => reject,
+    };
+    expect_eq(
+        "the reject code",
+        reject.code(),
+        ErrorCode::F99_APPLICATION_ERROR,
+    )?;
+    let response = StreamPacket::from_bytes(&decrypt(&sent.shared_secret, reject.data())?)?;
+    expect_eq("the response type", response.packet_type, REJECT)?;
+    expect_eq("the amount received", response.prepare_amount, 100)
+}
+
+async fn sends_asset_details() -> CheckResult {
+    let mut new_address = Vec::new();
+    new_address.put_var_octet_string(&b"example.conformance.sender"[..]);
+    let packet = StreamPacket {
+        packet_type: PREPARE,
+        sequence: 1,
+        prepare_amount: 0,
+        frames: vec![(CONNECTION_NEW_ADDRESS, new_address)],
+    };
+    // Senders announce their address in a Prepare the receiver cannot fulfill
+    let sent = send(packet, 0, false).await;
+    let reject = match sent.result {
+        Ok(_) => return Err("the unfulfillable Prepare was fulfilled".to_string()),
+        Err(reject) => reject,
+    };
+    let response = StreamPacket::from_bytes(&decrypt(&sent.shared_secret, reject.data())?)?;
+    expect_eq("the response type", response.packet_type, REJECT)?;
+
+    let mut asset_details = response.frame(CONNECTION_ASSET_DETAILS)?;
+    let asset_code = asset_details
+        .read_var_octet_string()
+        .map_err(|err| format!("the asset code could not be read: {}", err))?;
+    expect_eq("the asset code", asset_code, PEER.asset_code().as_bytes())?;
+    expect_eq(
+        "the asset scale",
+        asset_details.first().copied(),
+        Some(PEER.asset_scale()),
+    )
+}
+
+async fn passes_on_other_packets() -> CheckResult {
+    let (destination, _) = ConnectionGenerator::new(Bytes::from_static(&SERVER_SECRET))
+        .generate_address_and_secret(PEER.ilp_address());
+    let mut receiver =
+        StreamReceiverService::new(Bytes::from_static(&SERVER_SECRET), PeerStore, Unreachable);
+    let result = receiver
+        .send_request(OutgoingRequest {
+            from: PEER.clone(),
+            to: PEER.clone(),
+            original_amount: 100,
+            prepare: PrepareBuilder {
+                destination: destination.clone(),
+                amount: 100,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[0; 64],
+            }
+            .build(),
+        })
+        .await;
+    match result {
+        // Unreachable rejects everything the receiver passes on
+        Err(reject) if reject.code() == ErrorCode::F02_UNREACHABLE => Ok(()),
+        Err(reject) => Err(format!(
+            "the Prepare to {} was rejected with {}",
+            destination,
+            reject.code()
+        )),
+        Ok(_) => Err("the Prepare was fulfilled".to_string()),
+    }
+}
@@ -0,0 +1,17 @@
+use interledger_conformance::{run, Suite};
+
+#[tokio::test]
+async fn conforms_to_the_rfcs() {
+    let report = run(Suite::ALL).await;
+    assert!(report.is_conformant(), "\n{}", report);
+    assert!(!report.checks().is_empty());
+}
+
+#[tokio::test]
+async fn runs_only_the_selected_suites() {
+    let report = run(&[Suite::Packet]).await;
+    assert!(report
+        .checks()
+        .iter()
+        .all(|check| check.rfc == "RFC 27" || check.rfc == "RFC 15"));
+}
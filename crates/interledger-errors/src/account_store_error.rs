@@ -54,3 +54,13 @@ impl From<RedisError> for AccountStoreError {
         AccountStoreError::Other(Box::new(err))
     }
 }
+
+#[cfg(feature = "sqlite_errors")]
+use rusqlite::Error as SqliteError;
+
+#[cfg(feature = "sqlite_errors")]
+impl From<SqliteError> for AccountStoreError {
+    fn from(src: SqliteError) -> Self {
+        AccountStoreError::Other(Box::new(src))
+    }
+}
@@ -43,3 +43,13 @@ impl From<AddressStoreError> for warp::Rejection {
         ApiError::from(src).into()
     }
 }
+
+#[cfg(feature = "sqlite_errors")]
+use rusqlite::Error as SqliteError;
+
+#[cfg(feature = "sqlite_errors")]
+impl From<SqliteError> for AddressStoreError {
+    fn from(src: SqliteError) -> Self {
+        AddressStoreError::Other(Box::new(src))
+    }
+}
@@ -32,3 +32,13 @@ impl From<RedisError> for BalanceStoreError {
         BalanceStoreError::Other(Box::new(src))
     }
 }
+
+#[cfg(feature = "sqlite_errors")]
+use rusqlite::Error as SqliteError;
+
+#[cfg(feature = "sqlite_errors")]
+impl From<SqliteError> for BalanceStoreError {
+    fn from(src: SqliteError) -> Self {
+        BalanceStoreError::Other(Box::new(src))
+    }
+}
@@ -42,3 +42,13 @@ impl From<RedisError> for BtpStoreError {
         BtpStoreError::Other(Box::new(src))
     }
 }
+
+#[cfg(feature = "sqlite_errors")]
+use rusqlite::Error as SqliteError;
+
+#[cfg(feature = "sqlite_errors")]
+impl From<SqliteError> for BtpStoreError {
+    fn from(src: SqliteError) -> Self {
+        BtpStoreError::Other(Box::new(src))
+    }
+}
@@ -45,3 +45,13 @@ impl From<RedisError> for CcpRoutingStoreError {
         CcpRoutingStoreError::Other(Box::new(src))
     }
 }
+
+#[cfg(feature = "sqlite_errors")]
+use rusqlite::Error as SqliteError;
+
+#[cfg(feature = "sqlite_errors")]
+impl From<SqliteError> for CcpRoutingStoreError {
+    fn from(src: SqliteError) -> Self {
+        CcpRoutingStoreError::Other(Box::new(src))
+    }
+}
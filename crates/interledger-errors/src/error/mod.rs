@@ -4,8 +4,11 @@ mod error_types;
 pub use error_types::*;
 
 use chrono::{DateTime, Local};
+#[cfg(not(target_arch = "wasm32"))]
 use http::header::HeaderValue;
+#[cfg(not(target_arch = "wasm32"))]
 use once_cell::sync::Lazy;
+#[cfg(not(target_arch = "wasm32"))]
 use regex::Regex;
 use serde::{ser::Serializer, Serialize};
 use serde_json::error::Category;
@@ -14,6 +17,7 @@ use std::{
     error::Error as StdError,
     fmt::{self, Display},
 };
+#[cfg(not(target_arch = "wasm32"))]
 use warp::{reject::Reject, reply::json, reply::Response, Rejection, Reply};
 
 /// API error type prefix of problems.
@@ -244,6 +248,7 @@ impl Display for ApiError {
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl Reply for ApiError {
     fn into_response(self) -> Response {
         let res = json(&self);
@@ -259,8 +264,10 @@ impl Reply for ApiError {
 
 impl StdError for ApiError {}
 
+#[cfg(not(target_arch = "wasm32"))]
 impl Reject for ApiError {}
 
+#[cfg(not(target_arch = "wasm32"))]
 static MISSING_FIELD_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new("missing field `(.*)`").unwrap());
 
 #[derive(Clone, Debug)]
@@ -271,6 +278,7 @@ pub struct JsonDeserializeError {
 }
 
 impl StdError for JsonDeserializeError {}
+#[cfg(not(target_arch = "wasm32"))]
 impl Reject for JsonDeserializeError {}
 
 impl Display for JsonDeserializeError {
@@ -279,6 +287,7 @@ impl Display for JsonDeserializeError {
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl Reply for JsonDeserializeError {
     fn into_response(self) -> Response {
         let mut extension_members = Map::new();
@@ -326,6 +335,7 @@ impl Reply for JsonDeserializeError {
 }
 
 // Receives `ApiError`s and `JsonDeserializeError` and return it in the RFC7807 format.
+#[cfg(not(target_arch = "wasm32"))]
 pub async fn default_rejection_handler(err: warp::Rejection) -> Result<impl Reply, Rejection> {
     if let Some(api_error) = err.find::<ApiError>() {
         Ok(api_error.clone().into_response())
@@ -42,3 +42,13 @@ impl From<RedisError> for HttpStoreError {
         HttpStoreError::Other(Box::new(src))
     }
 }
+
+#[cfg(feature = "sqlite_errors")]
+use rusqlite::Error as SqliteError;
+
+#[cfg(feature = "sqlite_errors")]
+impl From<SqliteError> for HttpStoreError {
+    fn from(src: SqliteError) -> Self {
+        HttpStoreError::Other(Box::new(src))
+    }
+}
@@ -14,6 +14,12 @@ pub use http_store_error::HttpStoreError;
 mod btp_store_error;
 pub use btp_store_error::BtpStoreError;
 
+mod grpc_store_error;
+pub use grpc_store_error::GrpcStoreError;
+
+mod quic_store_error;
+pub use quic_store_error::QuicStoreError;
+
 mod ccprouting_store_error;
 pub use ccprouting_store_error::CcpRoutingStoreError;
 
@@ -1,5 +1,6 @@
 use super::{AccountStoreError, BtpStoreError, CreateAccountError};
 use crate::error::ApiError;
+use serde_json::{json, Map, Value};
 use std::error::Error as StdError;
 use thiserror::Error;
 
@@ -19,6 +20,12 @@ pub enum NodeStoreError {
     MissingAccounts,
     #[error("invalid account: {0}")]
     InvalidAccount(CreateAccountError),
+    #[error("the store already contains accounts")]
+    NotEmpty,
+    /// The errors of the accounts of a batch which could not be inserted, along with their
+    /// positions in the batch
+    #[error("{} of the accounts could not be inserted", .0.len())]
+    InvalidAccounts(Vec<(usize, NodeStoreError)>),
 }
 
 impl From<NodeStoreError> for BtpStoreError {
@@ -48,6 +55,21 @@ impl From<NodeStoreError> for ApiError {
             NodeStoreError::InvalidAccount(_) | NodeStoreError::InvalidEngineUrl(_) => {
                 ApiError::bad_request().detail(src.to_string())
             }
+            NodeStoreError::NotEmpty => ApiError::conflict().detail(src.to_string()),
+            NodeStoreError::InvalidAccounts(ref errors) => {
+                let invalid_accounts = errors
+                    .iter()
+                    .map(|(index, err)| json!({ "index": index, "error": err.to_string() }))
+                    .collect();
+                let mut extension_members = Map::new();
+                extension_members.insert(
+                    "invalid-accounts".to_owned(),
+                    Value::Array(invalid_accounts),
+                );
+                ApiError::bad_request()
+                    .detail(src.to_string())
+                    .extension_members(extension_members)
+            }
             _ => ApiError::internal_server_error().detail(src.to_string()),
         }
     }
@@ -68,3 +90,13 @@ impl From<RedisError> for NodeStoreError {
         NodeStoreError::Other(Box::new(src))
     }
 }
+
+#[cfg(feature = "sqlite_errors")]
+use rusqlite::Error as SqliteError;
+
+#[cfg(feature = "sqlite_errors")]
+impl From<SqliteError> for NodeStoreError {
+    fn from(src: SqliteError) -> Self {
+        NodeStoreError::Other(Box::new(src))
+    }
+}
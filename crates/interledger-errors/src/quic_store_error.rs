@@ -0,0 +1,34 @@
+use std::error::Error as StdError;
+use thiserror::Error;
+
+/// Errors for the QuicStore
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum QuicStoreError {
+    #[error("{0}")]
+    Other(#[from] Box<dyn StdError + Send + 'static>),
+    #[error("account `{0}` was not found")]
+    AccountNotFound(String),
+    #[error("account `{0}` is not authorized for this action")]
+    Unauthorized(String),
+}
+
+#[cfg(feature = "redis_errors")]
+use redis::RedisError;
+
+#[cfg(feature = "redis_errors")]
+impl From<RedisError> for QuicStoreError {
+    fn from(src: RedisError) -> QuicStoreError {
+        QuicStoreError::Other(Box::new(src))
+    }
+}
+
+#[cfg(feature = "sqlite_errors")]
+use rusqlite::Error as SqliteError;
+
+#[cfg(feature = "sqlite_errors")]
+impl From<SqliteError> for QuicStoreError {
+    fn from(src: SqliteError) -> Self {
+        QuicStoreError::Other(Box::new(src))
+    }
+}
@@ -98,3 +98,27 @@ impl From<RedisError> for IdempotentStoreError {
         IdempotentStoreError::Other(Box::new(src))
     }
 }
+
+#[cfg(feature = "sqlite_errors")]
+use rusqlite::Error as SqliteError;
+
+#[cfg(feature = "sqlite_errors")]
+impl From<SqliteError> for SettlementStoreError {
+    fn from(src: SqliteError) -> Self {
+        SettlementStoreError::Other(Box::new(src))
+    }
+}
+
+#[cfg(feature = "sqlite_errors")]
+impl From<SqliteError> for LeftoversStoreError {
+    fn from(src: SqliteError) -> Self {
+        LeftoversStoreError::Other(Box::new(src))
+    }
+}
+
+#[cfg(feature = "sqlite_errors")]
+impl From<SqliteError> for IdempotentStoreError {
+    fn from(src: SqliteError) -> Self {
+        IdempotentStoreError::Other(Box::new(src))
+    }
+}
@@ -0,0 +1,60 @@
+/* C bindings for sending and receiving Interledger payments, see src/lib.rs */
+
+#ifndef INTERLEDGER_H
+#define INTERLEDGER_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum IlpStatus {
+    ILP_STATUS_OK = 0,
+    ILP_STATUS_INVALID_ARGUMENT = 1,
+    ILP_STATUS_SPSP_ERROR = 2,
+    ILP_STATUS_PAYMENT_ERROR = 3,
+    ILP_STATUS_PANIC = 4,
+} IlpStatus;
+
+typedef struct IlpAccount {
+    const char *ilp_address;
+    const char *asset_code;
+    uint8_t asset_scale;
+    const char *http_url;
+    const char *http_token;
+} IlpAccount;
+
+typedef struct IlpDelivery {
+    uint64_t sent_amount;
+    uint64_t delivered_amount;
+    /* NULL if the receiver did not send its asset details, otherwise released with ilp_string_free */
+    char *destination_asset_code;
+    uint8_t destination_asset_scale;
+} IlpDelivery;
+
+/* Message of the last error on the calling thread, valid until the next call on the thread */
+const char *ilp_last_error(void);
+
+void ilp_string_free(char *string);
+
+IlpStatus ilp_spsp_query(const char *receiver,
+                         char **destination_account,
+                         uint8_t shared_secret[32]);
+
+/* delivery is zeroed if the payment fails, even if part of the amount was delivered */
+IlpStatus ilp_pay(const IlpAccount *account,
+                  const char *receiver,
+                  uint64_t source_amount,
+                  IlpDelivery *delivery);
+
+IlpStatus ilp_generate_receiver(const uint8_t server_secret[32],
+                                const char *base_address,
+                                char **destination_account,
+                                uint8_t shared_secret[32]);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* INTERLEDGER_H */
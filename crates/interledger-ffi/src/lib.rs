@@ -0,0 +1,471 @@
+//! # interledger-ffi
+//!
+//! C bindings for the client side of Interledger.rs, so that mobile apps and programs written
+//! in other languages can query SPSP receivers, send STREAM payments and generate the
+//! addresses and shared secrets of their own receivers without running a node.
+//!
+//! The functions are declared in `include/interledger.h`. They return an [`IlpStatus`](./enum.IlpStatus.html)
+//! and write their results to the pointers they are given. The message of the last error on the
+//! calling thread is returned by [`ilp_last_error`](./fn.ilp_last_error.html). Strings returned by
+//! the functions are owned by the caller and must be released with
+//! [`ilp_string_free`](./fn.ilp_string_free.html). Panics are caught at the boundary and
+//! returned as [`IlpStatus::Panic`](./enum.IlpStatus.html#variant.Panic), since unwinding into
+//! the caller is undefined behavior.
+use bytes::Bytes;
+use interledger_errors::ExchangeRateStoreError;
+use interledger_packet::Address;
+use interledger_rates::ExchangeRateStore;
+use interledger_service::{Account, Username};
+use interledger_spsp::{pay, query, HttpTransport};
+use interledger_stream::ConnectionGenerator;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ffi::{CStr, CString},
+    future::Future,
+    os::raw::c_char,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    str::FromStr,
+};
+use uuid::Uuid;
+
+/// Result of the functions
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IlpStatus {
+    Ok = 0,
+    /// A pointer was null, a string was not valid UTF-8 or a value was invalid
+    InvalidArgument = 1,
+    /// The SPSP receiver could not be queried
+    SpspError = 2,
+    /// The payment failed, possibly after delivering part of the amount. How much was
+    /// delivered is not reported.
+    PaymentError = 3,
+    /// The library panicked, which is a bug in it
+    Panic = 4,
+}
+
+/// Account on a connector which the payments are sent from over ILP-over-HTTP
+#[repr(C)]
+pub struct IlpAccount {
+    /// ILP Address of the account
+    pub ilp_address: *const c_char,
+    /// Asset code of the account
+    pub asset_code: *const c_char,
+    /// Asset scale of the account
+    pub asset_scale: u8,
+    /// ILP-over-HTTP URL of the account on the connector, e.g. `https://node.example/accounts/alice/ilp`
+    pub http_url: *const c_char,
+    /// Token the account authenticates with on the connector
+    pub http_token: *const c_char,
+}
+
+/// Receipt of a payment, in the units of the sending and receiving accounts
+#[repr(C)]
+pub struct IlpDelivery {
+    /// Amount fulfilled, in the units of the sending account
+    pub sent_amount: u64,
+    /// Amount received by the receiver, in the units of the receiving account
+    pub delivered_amount: u64,
+    /// Asset code of the receiving account, or null if the receiver did not send it
+    pub destination_asset_code: *mut c_char,
+    /// Asset scale of the receiving account, if `destination_asset_code` is not null
+    pub destination_asset_scale: u8,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn fail(status: IlpStatus, message: impl ToString) -> IlpStatus {
+    let message =
+        CString::new(message.to_string().replace('\0', "")).expect("the nul bytes were removed");
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+    status
+}
+
+/// Runs the body of an exported function, returning a panic as `IlpStatus::Panic`
+/// instead of unwinding into the caller
+fn catch_panic(f: impl FnOnce() -> IlpStatus) -> IlpStatus {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        fail(IlpStatus::Panic, format!("Panicked: {}", message))
+    })
+}
+
+/// Returns the message of the last error on the calling thread, or null if no
+/// function failed on it. The message is valid until the next call on the thread.
+#[no_mangle]
+pub extern "C" fn ilp_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| match *last_error.borrow() {
+        Some(ref message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Releases a string returned by one of the functions
+///
+/// # Safety
+///
+/// The string must have been returned by this library and not released before.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        let _ = panic::catch_unwind(|| drop(CString::from_raw(string)));
+    }
+}
+
+unsafe fn read_str<'a>(string: *const c_char, name: &str) -> Result<&'a str, IlpStatus> {
+    if string.is_null() {
+        return Err(fail(
+            IlpStatus::InvalidArgument,
+            format!("{} must not be null", name),
+        ));
+    }
+    CStr::from_ptr(string).to_str().map_err(|_| {
+        fail(
+            IlpStatus::InvalidArgument,
+            format!("{} is not valid UTF-8", name),
+        )
+    })
+}
+
+fn into_c_string(string: String) -> *mut c_char {
+    CString::new(string)
+        .map(CString::into_raw)
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Runs the request to completion on a runtime of its own, since the callers have none
+fn block_on<F: Future>(future: F) -> Result<F::Output, IlpStatus> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|err| {
+            fail(
+                IlpStatus::PaymentError,
+                format!("Error starting runtime: {}", err),
+            )
+        })?;
+    Ok(runtime.block_on(future))
+}
+
+/// Queries the SPSP receiver at the payment pointer or URL, writing the ILP Address to send
+/// the payment to in `destination_account` and the 32 bytes of the shared secret in `shared_secret`.
+///
+/// # Safety
+///
+/// `receiver` must be a nul-terminated string, `destination_account` must be valid for a write
+/// and `shared_secret` must be valid for 32 bytes of writes.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_spsp_query(
+    receiver: *const c_char,
+    destination_account: *mut *mut c_char,
+    shared_secret: *mut u8,
+) -> IlpStatus {
+    catch_panic(|| {
+        let receiver = match read_str(receiver, "receiver") {
+            Ok(receiver) => receiver,
+            Err(status) => return status,
+        };
+        if destination_account.is_null() || shared_secret.is_null() {
+            return fail(
+                IlpStatus::InvalidArgument,
+                "Output pointers must not be null",
+            );
+        }
+
+        let response = match block_on(query(receiver)) {
+            Ok(Ok(response)) => response,
+            Ok(Err(err)) => return fail(IlpStatus::SpspError, err),
+            Err(status) => return status,
+        };
+        if response.shared_secret().len() != 32 {
+            return fail(
+                IlpStatus::SpspError,
+                "The receiver returned a shared secret which is not 32 bytes long",
+            );
+        }
+        *destination_account = into_c_string(response.destination_account().to_string());
+        slice::from_raw_parts_mut(shared_secret, 32).copy_from_slice(response.shared_secret());
+        IlpStatus::Ok
+    })
+}
+
+/// Account the payments are sent from
+#[derive(Clone, Debug)]
+struct SenderAccount {
+    username: Username,
+    ilp_address: Address,
+    asset_code: String,
+    asset_scale: u8,
+}
+
+impl Account for SenderAccount {
+    fn id(&self) -> Uuid {
+        Uuid::nil()
+    }
+
+    fn username(&self) -> &Username {
+        &self.username
+    }
+
+    fn ilp_address(&self) -> &Address {
+        &self.ilp_address
+    }
+
+    fn asset_scale(&self) -> u8 {
+        self.asset_scale
+    }
+
+    fn asset_code(&self) -> &str {
+        &self.asset_code
+    }
+}
+
+/// Exchange rate store without rates, so that the minimum destination amounts
+/// of the packets are left to the connectors
+#[derive(Clone)]
+struct NoRates;
+
+impl ExchangeRateStore for NoRates {
+    fn set_exchange_rates(
+        &self,
+        _rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        Ok(())
+    }
+
+    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        Err(ExchangeRateStoreError::PairNotFound {
+            from: asset_codes.first().unwrap_or(&"").to_string(),
+            to: asset_codes.last().unwrap_or(&"").to_string(),
+        })
+    }
+
+    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        Ok(HashMap::new())
+    }
+}
+
+/// Sends `source_amount`, in the units of the account, to the SPSP receiver at the payment
+/// pointer or URL. The packets are sent to the connector of the account over ILP-over-HTTP.
+/// The payment does not enforce an exchange rate, so the receiver should only be paid
+/// through trusted connectors.
+///
+/// The receipt is written to `delivery` when the payment completes. If the payment fails,
+/// `delivery` is zeroed (with a null `destination_asset_code`), even if part of the amount
+/// was delivered, since the payment does not report how much of it was.
+///
+/// # Safety
+///
+/// The strings of `account` and `receiver` must be nul-terminated, and `account` and
+/// `delivery` must be valid for a read and a write respectively.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_pay(
+    account: *const IlpAccount,
+    receiver: *const c_char,
+    source_amount: u64,
+    delivery: *mut IlpDelivery,
+) -> IlpStatus {
+    catch_panic(|| {
+        if account.is_null() || delivery.is_null() {
+            return fail(IlpStatus::InvalidArgument, "Pointers must not be null");
+        }
+        *delivery = IlpDelivery {
+            sent_amount: 0,
+            delivered_amount: 0,
+            destination_asset_code: ptr::null_mut(),
+            destination_asset_scale: 0,
+        };
+        let account = &*account;
+        let (receiver, ilp_address, asset_code, http_url, http_token) = match (
+            read_str(receiver, "receiver"),
+            read_str(account.ilp_address, "ilp_address"),
+            read_str(account.asset_code, "asset_code"),
+            read_str(account.http_url, "http_url"),
+            read_str(account.http_token, "http_token"),
+        ) {
+            (Ok(receiver), Ok(ilp_address), Ok(asset_code), Ok(http_url), Ok(http_token)) => {
+                (receiver, ilp_address, asset_code, http_url, http_token)
+            }
+            _ => return IlpStatus::InvalidArgument,
+        };
+        let ilp_address = match Address::from_str(ilp_address) {
+            Ok(ilp_address) => ilp_address,
+            Err(err) => return fail(IlpStatus::InvalidArgument, err),
+        };
+        let from_account = SenderAccount {
+            username: Username::from_str("ffi").expect("the username is valid"),
+            ilp_address,
+            asset_code: asset_code.to_string(),
+            asset_scale: account.asset_scale,
+        };
+
+        let transport = HttpTransport::new(http_url, http_token);
+        let payment = pay(
+            transport,
+            from_account,
+            NoRates,
+            receiver,
+            source_amount,
+            0.0,
+        );
+        match block_on(payment) {
+            Ok(Ok(receipt)) => {
+                *delivery = IlpDelivery {
+                    sent_amount: receipt.sent_amount,
+                    delivered_amount: receipt.delivered_amount,
+                    destination_asset_code: receipt
+                        .destination_asset_code
+                        .map(into_c_string)
+                        .unwrap_or(ptr::null_mut()),
+                    destination_asset_scale: receipt.destination_asset_scale.unwrap_or(0),
+                };
+                IlpStatus::Ok
+            }
+            Ok(Err(err)) => fail(IlpStatus::PaymentError, err),
+            Err(status) => status,
+        }
+    })
+}
+
+/// Generates the ILP Address and shared secret of a new STREAM connection of a receiver,
+/// as an SPSP server would. The receiver derives the shared secrets of the payments it
+/// receives from the 32 bytes of `server_secret`, and its address is under `base_address`.
+///
+/// # Safety
+///
+/// `server_secret` must be valid for 32 bytes of reads, `base_address` must be a
+/// nul-terminated string, `destination_account` must be valid for a write and
+/// `shared_secret` must be valid for 32 bytes of writes.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_generate_receiver(
+    server_secret: *const u8,
+    base_address: *const c_char,
+    destination_account: *mut *mut c_char,
+    shared_secret: *mut u8,
+) -> IlpStatus {
+    catch_panic(|| {
+        let base_address = match read_str(base_address, "base_address") {
+            Ok(base_address) => base_address,
+            Err(status) => return status,
+        };
+        if server_secret.is_null() || destination_account.is_null() || shared_secret.is_null() {
+            return fail(IlpStatus::InvalidArgument, "Pointers must not be null");
+        }
+        let base_address = match Address::from_str(base_address) {
+            Ok(base_address) => base_address,
+            Err(err) => return fail(IlpStatus::InvalidArgument, err),
+        };
+
+        let server_secret = Bytes::copy_from_slice(slice::from_raw_parts(server_secret, 32));
+        let generator = ConnectionGenerator::new(server_secret);
+        let (address, secret) = generator.generate_address_and_secret(&base_address);
+        *destination_account = into_c_string(address.to_string());
+        slice::from_raw_parts_mut(shared_secret, 32).copy_from_slice(&secret);
+        IlpStatus::Ok
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(ilp_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn generates_receivers() {
+        let server_secret = [1; 32];
+        let base_address = CString::new("example.receiver").unwrap();
+        let mut destination_account = ptr::null_mut();
+        let mut shared_secret = [0; 32];
+        let status = unsafe {
+            ilp_generate_receiver(
+                server_secret.as_ptr(),
+                base_address.as_ptr(),
+                &mut destination_account,
+                shared_secret.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, IlpStatus::Ok);
+
+        let address = unsafe { CStr::from_ptr(destination_account) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(address.starts_with("example.receiver."));
+        let generator = ConnectionGenerator::new(Bytes::copy_from_slice(&server_secret));
+        let expected = generator.rederive_secret(&Address::from_str(&address).unwrap());
+        assert_eq!(shared_secret, expected);
+        unsafe { ilp_string_free(destination_account) };
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        let base_address = CString::new("not an address").unwrap();
+        let mut destination_account = ptr::null_mut();
+        let mut shared_secret = [0; 32];
+        let status = unsafe {
+            ilp_generate_receiver(
+                [1; 32].as_ptr(),
+                base_address.as_ptr(),
+                &mut destination_account,
+                shared_secret.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, IlpStatus::InvalidArgument);
+        assert!(destination_account.is_null());
+
+        let status = unsafe {
+            ilp_spsp_query(
+                ptr::null(),
+                &mut destination_account,
+                shared_secret.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, IlpStatus::InvalidArgument);
+        assert_eq!(last_error(), "receiver must not be null");
+    }
+
+    #[test]
+    fn zeroes_the_delivery_of_failed_payments() {
+        let empty = CString::new("").unwrap();
+        let receiver = CString::new("$receiver.example").unwrap();
+        let account = IlpAccount {
+            ilp_address: empty.as_ptr(),
+            asset_code: empty.as_ptr(),
+            asset_scale: 9,
+            http_url: empty.as_ptr(),
+            http_token: empty.as_ptr(),
+        };
+        let mut delivery = IlpDelivery {
+            sent_amount: 1,
+            delivered_amount: 1,
+            destination_asset_code: ptr::null_mut(),
+            destination_asset_scale: 1,
+        };
+        let status = unsafe { ilp_pay(&account, receiver.as_ptr(), 100, &mut delivery) };
+        assert_eq!(status, IlpStatus::InvalidArgument);
+        assert_eq!(delivery.sent_amount, 0);
+        assert_eq!(delivery.delivered_amount, 0);
+        assert!(delivery.destination_asset_code.is_null());
+        assert_eq!(delivery.destination_asset_scale, 0);
+    }
+
+    #[test]
+    fn returns_panics_as_errors() {
+        let status = catch_panic(|| panic!("boom"));
+        assert_eq!(status, IlpStatus::Panic);
+        assert_eq!(last_error(), "Panicked: boom");
+    }
+}
@@ -0,0 +1,4 @@
+fn main() {
+    tonic_build::compile_protos("proto/interledger.proto")
+        .expect("the protocol buffers definition is valid");
+}
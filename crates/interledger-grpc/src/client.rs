@@ -0,0 +1,451 @@
+use super::proto::{ilp_client::IlpClient, IlpPacket};
+use super::{GrpcAccount, AUTHORIZATION_METADATA_KEY, USERNAME_METADATA_KEY};
+use async_trait::async_trait;
+use bytes::BytesMut;
+use interledger_packet::{Address, ErrorCode, Packet, Reject, RejectBuilder};
+use interledger_service::*;
+use parking_lot::Mutex;
+use secrecy::ExposeSecret;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{
+    metadata::MetadataValue,
+    transport::{ClientTlsConfig, Endpoint},
+    Request,
+};
+use tracing::{debug, error, trace};
+use url::Url;
+use uuid::Uuid;
+
+/// Number of Prepare packets which are buffered per stream before sending further
+/// packets waits for the HTTP/2 flow control to let them through
+const REQUEST_BUFFER: usize = 64;
+
+/// Responses which are awaited on a stream, by the id of their Prepare packet
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<BytesMut>>>>;
+
+/// A stream opened to the gRPC server of a peer
+#[derive(Clone)]
+struct Connection {
+    /// Distinguishes the connection from the ones which replaced it
+    generation: u64,
+    sender: mpsc::Sender<IlpPacket>,
+    pending: Pending,
+}
+
+/// The GrpcClientService implements [OutgoingService](../../interledger_service/trait.OutgoingService)
+/// for sending ILP Prepare packets over a gRPC stream to the URL associated with the provided account.
+/// The stream of an account is opened when its first packet is sent, and is opened again
+/// after it was closed.
+/// If no gRPC URL is specified for the account in the request, then it is forwarded to the next service.
+#[derive(Clone)]
+pub struct GrpcClientService<S, O, A> {
+    /// The store used by the client to get the node's ILP Address,
+    /// used to populate the `triggered_by` field in Reject packets
+    store: S,
+    /// The next outgoing service to which non gRPC requests should be forwarded to
+    next: O,
+    /// The TLS configuration with which the streams are opened, e.g. with the node's
+    /// certificate for mutual TLS. The peer's certificate is verified with the system's roots if not set.
+    tls: Option<ClientTlsConfig>,
+    connections: Arc<Mutex<HashMap<Uuid, Connection>>>,
+    /// Used to assign the ids of the Prepare packets and of the connections
+    next_id: Arc<AtomicU64>,
+    account_type: PhantomData<A>,
+}
+
+impl<S, O, A> GrpcClientService<S, O, A>
+where
+    S: AddressStore,
+    O: OutgoingService<A> + Clone,
+    A: GrpcAccount,
+{
+    /// Constructs the GrpcClientService
+    pub fn new(store: S, next: O) -> Self {
+        GrpcClientService {
+            store,
+            next,
+            tls: None,
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
+            account_type: PhantomData,
+        }
+    }
+
+    /// Sets the TLS configuration with which the streams are opened
+    pub fn tls_config(mut self, tls: ClientTlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Returns the account's stream, opening it if there is none
+    async fn connection(&self, account: &A, url: &Url) -> Result<Connection, String> {
+        let existing = self.connections.lock().get(&account.id()).cloned();
+        if let Some(connection) = existing {
+            return Ok(connection);
+        }
+
+        // The username and token are sent as metadata rather than in the URL
+        let mut endpoint_url = url.clone();
+        let _ = endpoint_url.set_username("");
+        let mut endpoint =
+            Endpoint::from_shared(endpoint_url.to_string()).map_err(|err| err.to_string())?;
+        if let Some(ref tls) = self.tls {
+            endpoint = endpoint
+                .tls_config(tls.clone())
+                .map_err(|err| err.to_string())?;
+        }
+        let channel = endpoint.connect().await.map_err(|err| err.to_string())?;
+
+        let (sender, receiver) = mpsc::channel(REQUEST_BUFFER);
+        let mut request = Request::new(ReceiverStream::new(receiver));
+        let metadata = request.metadata_mut();
+        let username = MetadataValue::from_str(url.username())
+            .map_err(|_| "invalid username in the gRPC URL".to_string())?;
+        metadata.insert(USERNAME_METADATA_KEY, username);
+        if let Some(token) = account.get_grpc_auth_token() {
+            let token = MetadataValue::from_str(&format!("Bearer {}", token.expose_secret()))
+                .map_err(|_| "invalid gRPC token".to_string())?;
+            metadata.insert(AUTHORIZATION_METADATA_KEY, token);
+        }
+        let mut responses = IlpClient::new(channel)
+            .stream(request)
+            .await
+            .map_err(|status| status.to_string())?
+            .into_inner();
+
+        let connection = Connection {
+            generation: self.next_id.fetch_add(1, Ordering::Relaxed),
+            sender,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        };
+        let account_id = account.id();
+        let generation = connection.generation;
+        let pending = connection.pending.clone();
+        let connections = self.connections.clone();
+        tokio::spawn(async move {
+            loop {
+                match responses.message().await {
+                    Ok(Some(packet)) => {
+                        if let Some(response) = pending.lock().remove(&packet.id) {
+                            let _ = response.send(BytesMut::from(&packet.data[..]));
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(status) => {
+                        error!("gRPC stream of account {} failed: {}", account_id, status);
+                        break;
+                    }
+                }
+            }
+            debug!("gRPC stream of account {} closed", account_id);
+            // The stream is opened again by the next packet, unless it was replaced already
+            let mut connections = connections.lock();
+            if connections
+                .get(&account_id)
+                .map_or(false, |connection| connection.generation == generation)
+            {
+                connections.remove(&account_id);
+            }
+            // Dropping the senders rejects the packets which are still waiting
+            pending.lock().clear();
+        });
+
+        let mut connections = self.connections.lock();
+        Ok(connections.entry(account_id).or_insert(connection).clone())
+    }
+}
+
+/// Returns a Reject packet from the node
+fn reject(code: ErrorCode, message: &str, ilp_address: &Address) -> Reject {
+    RejectBuilder {
+        code,
+        message: message.as_bytes(),
+        triggered_by: Some(ilp_address),
+        data: &[],
+    }
+    .build()
+}
+
+impl<S, O, A> OutgoingService<A> for GrpcClientService<S, O, A>
+where
+    S: AddressStore + Clone + Send + Sync,
+    O: OutgoingService<A> + Clone + Sync + Send,
+    A: GrpcAccount + Clone + Sync + Send,
+{
+    type Future<'a>
+        = BoxedIlpFuture<'a>
+    where
+        Self: 'a;
+
+    /// Send an OutgoingRequest to a peer over its gRPC stream and wait for the
+    /// Fulfill or Reject packet until the Prepare packet expires.
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let url = match request.to.get_grpc_url() {
+                Some(url) => url.clone(),
+                None => return self.next.send_request(request).await,
+            };
+            let ilp_address = self.store.get_ilp_address();
+            trace!(to.id = %request.to.id(), url = url.as_str(), "Sending ILP over gRPC packet");
+
+            let connection = self.connection(&request.to, &url).await.map_err(|err| {
+                error!("Error opening gRPC stream: {}", err);
+                reject(
+                    ErrorCode::T01_PEER_UNREACHABLE,
+                    &format!("Error opening gRPC stream: {}", err),
+                    &ilp_address,
+                )
+            })?;
+
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let (response_sender, response) = oneshot::channel();
+            connection.pending.lock().insert(id, response_sender);
+            let expires_in = request
+                .prepare
+                .expires_at()
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+            let packet = IlpPacket {
+                id,
+                data: BytesMut::from(request.prepare).to_vec(),
+            };
+            if connection.sender.send(packet).await.is_err() {
+                connection.pending.lock().remove(&id);
+                return Err(reject(
+                    ErrorCode::T01_PEER_UNREACHABLE,
+                    "gRPC stream closed",
+                    &ilp_address,
+                ));
+            }
+
+            let data = match tokio::time::timeout(expires_in, response).await {
+                Ok(Ok(data)) => data,
+                Ok(Err(_)) => {
+                    return Err(reject(
+                        ErrorCode::T01_PEER_UNREACHABLE,
+                        "gRPC stream closed",
+                        &ilp_address,
+                    ))
+                }
+                Err(_) => {
+                    connection.pending.lock().remove(&id);
+                    return Err(reject(ErrorCode::R00_TRANSFER_TIMED_OUT, "", &ilp_address));
+                }
+            };
+            match Packet::try_from(data) {
+                Ok(Packet::Fulfill(fulfill)) => Ok(fulfill),
+                Ok(Packet::Reject(reject)) => Err(reject),
+                _ => Err(reject(ErrorCode::T01_PEER_UNREACHABLE, "", &ilp_address)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrpcServer, GrpcStore};
+    use interledger_errors::{AddressStoreError, GrpcStoreError};
+    use interledger_packet::{FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use secrecy::SecretString;
+    use std::str::FromStr;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    static USERNAME: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    const AUTH_PASSWORD: &str = "password";
+
+    #[derive(Debug, Clone)]
+    struct TestAccount {
+        id: Uuid,
+        url: Url,
+        token: &'static str,
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.id
+        }
+
+        fn username(&self) -> &Username {
+            &USERNAME
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &ILP_ADDRESS
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+    }
+
+    impl GrpcAccount for TestAccount {
+        fn get_grpc_url(&self) -> Option<&Url> {
+            Some(&self.url)
+        }
+
+        fn get_grpc_auth_token(&self) -> Option<SecretString> {
+            Some(SecretString::new(self.token.to_string()))
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestStore {
+        url: Url,
+    }
+
+    #[async_trait]
+    impl GrpcStore for TestStore {
+        type Account = TestAccount;
+
+        async fn get_account_from_grpc_auth(
+            &self,
+            username: &Username,
+            token: &str,
+        ) -> Result<Self::Account, GrpcStoreError> {
+            if username == &*USERNAME && token == AUTH_PASSWORD {
+                Ok(TestAccount {
+                    id: Uuid::new_v4(),
+                    url: self.url.clone(),
+                    token: AUTH_PASSWORD,
+                })
+            } else {
+                Err(GrpcStoreError::Unauthorized(username.to_string()))
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    /// Starts a server which fulfills the packets whose amount is even and rejects the others,
+    /// returning its URL
+    async fn start_server() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://alice@{}", listener.local_addr().unwrap())).unwrap();
+        let incoming = incoming_service_fn(|request: IncomingRequest<TestAccount>| {
+            if request.prepare.amount() % 2 == 0 {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &request.prepare.amount().to_be_bytes(),
+                }
+                .build())
+            } else {
+                Err(RejectBuilder {
+                    code: ErrorCode::F99_APPLICATION_ERROR,
+                    message: &[],
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build())
+            }
+        });
+        let server = GrpcServer::new(incoming, TestStore { url: url.clone() });
+        tokio::spawn(
+            Server::builder()
+                .add_service(server.into_service())
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        url
+    }
+
+    fn client(
+        url: &Url,
+    ) -> GrpcClientService<TestStore, impl OutgoingService<TestAccount> + Clone, TestAccount> {
+        let next = outgoing_service_fn(|_: OutgoingRequest<TestAccount>| -> IlpResult {
+            panic!("not sent over gRPC")
+        });
+        GrpcClientService::new(TestStore { url: url.clone() }, next)
+    }
+
+    fn request(to: TestAccount, amount: u64) -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: to.clone(),
+            to,
+            original_amount: amount,
+            prepare: PrepareBuilder {
+                destination: ILP_ADDRESS.clone(),
+                amount,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sends_packets_over_a_stream() {
+        let url = start_server().await;
+        let mut client = client(&url);
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            url,
+            token: AUTH_PASSWORD,
+        };
+
+        let results = futures::future::join_all((0..10u64).map(|amount| {
+            let mut client = client.clone();
+            let request = request(account.clone(), amount);
+            async move { client.send_request(request).await }
+        }))
+        .await;
+        for (amount, result) in results.into_iter().enumerate() {
+            if amount % 2 == 0 {
+                assert_eq!(result.unwrap().data(), &(amount as u64).to_be_bytes()[..]);
+            } else {
+                assert_eq!(result.unwrap_err().code(), ErrorCode::F99_APPLICATION_ERROR);
+            }
+        }
+        // All packets were sent over the same stream
+        assert_eq!(client.connections.lock().len(), 1);
+        assert!(client.send_request(request(account, 2)).await.is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_packets_if_the_stream_is_not_authenticated() {
+        let url = start_server().await;
+        let mut client = client(&url);
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            url,
+            token: "wrong",
+        };
+        let reject = client.send_request(request(account, 2)).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T01_PEER_UNREACHABLE);
+        assert!(client.connections.lock().is_empty());
+    }
+}
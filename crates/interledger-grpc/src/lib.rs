@@ -0,0 +1,55 @@
+//! # interledger-grpc
+//!
+//! Client and server implementations of a transport for ILP packets over a bidirectional
+//! [gRPC](https://grpc.io/) stream. It is intended for peers which already standardize on
+//! gRPC, which then get HTTP/2 flow control and mutual TLS from their gRPC stack.
+use async_trait::async_trait;
+use interledger_errors::GrpcStoreError;
+use interledger_service::{Account, Username};
+use secrecy::SecretString;
+use url::Url;
+
+/// gRPC client which sends the Prepare packets of the accounts with a gRPC URL
+mod client;
+/// gRPC server which passes the Prepare packets it receives to an incoming service
+mod server;
+
+/// Code generated from the protocol buffers definition in `proto/interledger.proto`
+#[allow(clippy::all)]
+mod proto {
+    tonic::include_proto!("interledger");
+}
+
+pub use self::client::GrpcClientService;
+pub use self::server::GrpcServer;
+pub use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+
+/// Metadata key of the username with which the client authenticates, which is the
+/// username of its account on the server's node
+pub const USERNAME_METADATA_KEY: &str = "ilp-username";
+/// Metadata key of the bearer token with which the client authenticates
+pub const AUTHORIZATION_METADATA_KEY: &str = "authorization";
+
+/// Extension trait for [Account](../interledger_service/trait.Account.html) with the
+/// details of the peer's gRPC server
+pub trait GrpcAccount: Account {
+    /// Returns the URL of the peer's gRPC server. Its username is the account's username
+    /// on the peer's node, e.g. `https://alice@peer.example.com`.
+    fn get_grpc_url(&self) -> Option<&Url>;
+    /// Returns the token which is sent as the bearer token when the stream is opened
+    fn get_grpc_auth_token(&self) -> Option<SecretString>;
+}
+
+/// The interface for Stores that can be used with the GrpcServer.
+#[async_trait]
+pub trait GrpcStore: Clone + Send + Sync + 'static {
+    type Account: GrpcAccount;
+
+    /// Load account details based on the username and bearer token
+    /// which the client sent when opening the stream
+    async fn get_account_from_grpc_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, GrpcStoreError>;
+}
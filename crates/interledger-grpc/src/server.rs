@@ -0,0 +1,167 @@
+use super::proto::{
+    ilp_server::{Ilp, IlpServer},
+    IlpPacket,
+};
+use super::{GrpcStore, AUTHORIZATION_METADATA_KEY, USERNAME_METADATA_KEY};
+use bytes::BytesMut;
+use interledger_packet::Prepare;
+use interledger_service::{Account, IncomingRequest, IncomingService, Username};
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{
+    transport::{Server, ServerTlsConfig},
+    Request, Response, Status, Streaming,
+};
+use tracing::{debug, error, trace};
+
+/// Number of responses which are buffered per stream before the handling of
+/// further Prepare packets waits for the client to read them
+const RESPONSE_BUFFER: usize = 64;
+
+/// The prefix of the bearer token in the authorization metadata
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// gRPC service which authenticates the streams opened by the peers and passes the
+/// Prepare packets they send to an IncomingService handler. The Prepare packets of a
+/// stream are handled concurrently.
+#[derive(Clone)]
+pub struct GrpcServer<I, S> {
+    /// The next [incoming service](../interledger_service/trait.IncomingService.html)
+    incoming: I,
+    /// A store which implements [`GrpcStore`](trait.GrpcStore.html)
+    store: S,
+}
+
+impl<I, S> GrpcServer<I, S>
+where
+    I: IncomingService<S::Account> + Clone + Send + Sync + 'static,
+    S: GrpcStore,
+    S::Account: Send + Sync + 'static,
+{
+    pub fn new(incoming: I, store: S) -> Self {
+        GrpcServer { incoming, store }
+    }
+
+    /// Returns the tonic service, which can be added to a server next to other services
+    pub fn into_service(self) -> IlpServer<Self> {
+        IlpServer::new(self)
+    }
+
+    /// Serves the gRPC service on the address. If a TLS configuration is given, the
+    /// connections are encrypted, and the clients' certificates are verified if it
+    /// has a client CA root.
+    pub async fn bind(
+        self,
+        addr: SocketAddr,
+        tls: Option<ServerTlsConfig>,
+    ) -> Result<(), tonic::transport::Error> {
+        let mut server = Server::builder();
+        if let Some(tls) = tls {
+            server = server.tls_config(tls)?;
+        }
+        server.add_service(self.into_service()).serve(addr).await
+    }
+
+    /// Returns the account which authenticated with the metadata of the request
+    async fn authenticate<T>(&self, request: &Request<T>) -> Result<S::Account, Status> {
+        let metadata = request.metadata();
+        let username = metadata
+            .get(USERNAME_METADATA_KEY)
+            .and_then(|username| username.to_str().ok())
+            .and_then(|username| Username::from_str(username).ok())
+            .ok_or_else(|| Status::unauthenticated("missing or invalid username"))?;
+        let token = metadata
+            .get(AUTHORIZATION_METADATA_KEY)
+            .and_then(|token| token.to_str().ok())
+            .filter(|token| token.starts_with(BEARER_PREFIX))
+            .map(|token| &token[BEARER_PREFIX.len()..])
+            .ok_or_else(|| Status::unauthenticated("provided token was not a bearer token"))?;
+        self.store
+            .get_account_from_grpc_auth(&username, token)
+            .await
+            .map_err(|err| {
+                debug!("Rejecting gRPC stream of {}: {}", username, err);
+                Status::unauthenticated(err.to_string())
+            })
+    }
+}
+
+#[tonic::async_trait]
+impl<I, S> Ilp for GrpcServer<I, S>
+where
+    I: IncomingService<S::Account> + Clone + Send + Sync + 'static,
+    S: GrpcStore,
+    S::Account: Send + Sync + 'static,
+{
+    type StreamStream = ReceiverStream<Result<IlpPacket, Status>>;
+
+    /// Authenticates the stream and handles the Prepare packets received on it,
+    /// responding to each of them with the Fulfill or Reject packet with the same id.
+    ///
+    /// # Errors
+    /// 1. Unauthenticated if invalid credentials are provided
+    /// 1. The stream is ended with an invalid argument error if a packet is not a Prepare packet
+    async fn stream(
+        &self,
+        request: Request<Streaming<IlpPacket>>,
+    ) -> Result<Response<Self::StreamStream>, Status> {
+        let account = self.authenticate(&request).await?;
+        trace!("Opened gRPC stream of {}", account.username());
+        let mut packets = request.into_inner();
+        let (sender, receiver) = mpsc::channel(RESPONSE_BUFFER);
+        let incoming = self.incoming.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let packet = match packets.message().await {
+                    Ok(Some(packet)) => packet,
+                    Ok(None) => break,
+                    Err(status) => {
+                        debug!("gRPC stream closed with an error: {}", status);
+                        break;
+                    }
+                };
+                let prepare = match Prepare::try_from(BytesMut::from(&packet.data[..])) {
+                    Ok(prepare) => prepare,
+                    Err(err) => {
+                        error!(
+                            "Packet {} was not a valid Prepare packet: {:?}",
+                            packet.id, err
+                        );
+                        let _ = sender
+                            .send(Err(Status::invalid_argument(
+                                "packets must be valid Prepare packets",
+                            )))
+                            .await;
+                        break;
+                    }
+                };
+
+                let mut incoming = incoming.clone();
+                let sender = sender.clone();
+                let from = account.clone();
+                tokio::spawn(async move {
+                    let result = incoming
+                        .handle_request(IncomingRequest { from, prepare })
+                        .await;
+                    let data: BytesMut = match result {
+                        Ok(fulfill) => fulfill.into(),
+                        Err(reject) => reject.into(),
+                    };
+                    // The client is gone if the stream was closed
+                    let _ = sender
+                        .send(Ok(IlpPacket {
+                            id: packet.id,
+                            data: data.to_vec(),
+                        }))
+                        .await;
+                });
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
@@ -1,15 +1,18 @@
-use super::{HttpAccount, HttpStore};
-use async_trait::async_trait;
+use super::{HttpAccount, HttpStore, CORRELATION_ID_HEADER};
 use bytes::BytesMut;
-use futures::future::TryFutureExt;
-use interledger_packet::{Address, ErrorCode, Packet, RejectBuilder};
-use interledger_service::*;
+use futures::future::{Either, TryFutureExt};
+use interledger_packet::{pool, Address, ErrorCode, Packet, RejectBuilder};
+use interledger_service::{
+    telemetry::{propagated_correlation_id, sample_packet, RedactedUrl},
+    *,
+};
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue},
     Client, ClientBuilder, Response as HttpResponse,
 };
 use secrecy::{ExposeSecret, SecretString};
 use std::{convert::TryFrom, marker::PhantomData, sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
 use tracing::{error, trace};
 
 /// The HttpClientService implements [OutgoingService](../../interledger_service/trait.OutgoingService)
@@ -27,6 +30,9 @@ pub struct HttpClientService<S, O, A> {
     /// The next outgoing service to which non ILP-over-HTTP requests should
     /// be forwarded to
     next: O,
+    /// Limits the number of ILP over HTTP requests which are in flight at the same time,
+    /// if set. Requests wait for a permit before they are sent.
+    in_flight: Option<Arc<Semaphore>>,
     account_type: PhantomData<A>,
 }
 
@@ -53,43 +59,77 @@ where
             client,
             store: Arc::new(store),
             next,
+            in_flight: None,
             account_type: PhantomData,
         }
     }
+
+    /// Limits the number of ILP over HTTP requests which are in flight at the same time.
+    /// A limit of 0 is treated as 1.
+    pub fn max_in_flight(mut self, limit: usize) -> Self {
+        self.in_flight = Some(Arc::new(Semaphore::new(limit.max(1))));
+        self
+    }
 }
 
-#[async_trait]
 impl<S, O, A> OutgoingService<A> for HttpClientService<S, O, A>
 where
     S: AddressStore + HttpStore + Clone,
     O: OutgoingService<A> + Clone + Sync + Send,
     A: HttpAccount + Clone + Sync + Send,
 {
+    /// Requests for accounts without an HTTP URL return the next service's future, the ones
+    /// sent over HTTP are boxed
+    type Future<'a>
+        = Either<O::Future<'a>, BoxedIlpFuture<'a>>
+    where
+        Self: 'a;
+
     /// Send an OutgoingRequest to a peer that implements the ILP-Over-HTTP.
-    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
-        let ilp_address = self.store.get_ilp_address();
-        let ilp_address_clone = ilp_address.clone();
-        let self_clone = self.clone();
-        if let Some(url) = request.to.get_http_url() {
-            trace!(
-                "Sending outgoing ILP over HTTP packet to account: {} (URL: {})",
-                request.to.id(),
-                url.as_str()
-            );
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        let url = match request.to.get_http_url() {
+            Some(url) => url.clone(),
+            None => return Either::Left(self.next.send_request(request)),
+        };
+        Either::Right(Box::pin(async move {
+            let ilp_address = self.store.get_ilp_address();
+            let ilp_address_clone = ilp_address.clone();
+            let self_clone = self.clone();
+            if sample_packet() {
+                trace!(to.id = %request.to.id(), url = %RedactedUrl(url.as_str()), "Sending ILP over HTTP packet");
+            }
             let token = request
                 .to
                 .get_http_auth_token()
                 .unwrap_or_else(|| SecretString::new("".to_owned()));
             let header = format!("Bearer {}", token.expose_secret());
-            let body = request.prepare.as_ref().to_owned();
-            let resp = self_clone
+            // The packet's buffer becomes the body as is, without copying it
+            let body = BytesMut::from(request.prepare).freeze();
+            // The permit is held until the response has been read
+            let _permit = match self_clone.in_flight {
+                Some(ref semaphore) => Some(
+                    semaphore
+                        .clone()
+                        .acquire_owned()
+                        .await
+                        .expect("the semaphore is never closed"),
+                ),
+                None => None,
+            };
+            let mut http_request = self_clone
                 .client
                 .post(url.as_ref())
-                .header("authorization", &header)
+                .header("authorization", &header);
+            if let Some(correlation_id) = propagated_correlation_id() {
+                http_request =
+                    http_request.header(CORRELATION_ID_HEADER, correlation_id.to_string());
+            }
+            let resp = http_request
                 .body(body)
                 .send()
                 .map_err(move |err| {
-                    error!("Error sending HTTP request: {:?}", err);
+                    let description = describe_error(&err);
+                    error!("Error sending HTTP request: {}", description);
                     let mut code = ErrorCode::T01_PEER_UNREACHABLE;
                     if let Some(status) = err.status() {
                         if status.is_client_error() {
@@ -97,7 +137,7 @@ where
                         }
                     };
 
-                    let message = format!("Error sending ILP over HTTP request: {}", err);
+                    let message = format!("Error sending ILP over HTTP request: {}", description);
                     RejectBuilder {
                         code,
                         message: message.as_bytes(),
@@ -108,9 +148,19 @@ where
                 })
                 .await?;
             parse_packet_from_response(resp, ilp_address_clone).await
-        } else {
-            self.next.send_request(request).await
+        }))
+    }
+}
+
+/// Describes the error, with the password of the peer's URL redacted, since it may be the
+/// account's auth token
+fn describe_error(err: &reqwest::Error) -> String {
+    let description = err.to_string();
+    match err.url() {
+        Some(url) if url.password().is_some() => {
+            description.replace(url.as_str(), &RedactedUrl(url.as_str()).to_string())
         }
+        _ => description,
     }
 }
 
@@ -123,7 +173,10 @@ where
 /// 1. If the packet is a Reject packet
 async fn parse_packet_from_response(response: HttpResponse, ilp_address: Address) -> IlpResult {
     let response = response.error_for_status().map_err(|err| {
-        error!("HTTP error sending ILP over HTTP packet: {:?}", err);
+        error!(
+            "HTTP error sending ILP over HTTP packet: {}",
+            describe_error(&err)
+        );
         let code = if let Some(status) = err.status() {
             if status.is_client_error() {
                 ErrorCode::F02_UNREACHABLE
@@ -147,7 +200,7 @@ async fn parse_packet_from_response(response: HttpResponse, ilp_address: Address
     let body = response
         .bytes()
         .map_err(|err| {
-            error!("Error getting HTTP response body: {:?}", err);
+            error!("Error getting HTTP response body: {}", describe_error(&err));
             RejectBuilder {
                 code: ErrorCode::T01_PEER_UNREACHABLE,
                 message: &[],
@@ -158,8 +211,9 @@ async fn parse_packet_from_response(response: HttpResponse, ilp_address: Address
         })
         .await?;
 
-    let body = body.into_iter().collect::<BytesMut>();
-    match Packet::try_from(body) {
+    let mut buffer = pool::take(body.len());
+    buffer.extend_from_slice(&body);
+    match Packet::try_from(buffer) {
         Ok(Packet::Fulfill(fulfill)) => Ok(fulfill),
         Ok(Packet::Reject(reject)) => Err(reject),
         _ => Err(RejectBuilder {
@@ -20,6 +20,10 @@ mod server;
 pub use self::client::HttpClientService;
 pub use self::server::HttpServer;
 
+/// Header in which the correlation ID of a Prepare is sent along with it, if the correlation
+/// IDs are propagated
+pub const CORRELATION_ID_HEADER: &str = "ILP-Correlation-Id";
+
 /// Extension trait for [Account](../interledger_service/trait.Account.html) with [ILP over HTTP](https://interledger.org/rfcs/0035-ilp-over-http/) related information
 pub trait HttpAccount: Account {
     /// Returns the HTTP URL corresponding to this account
@@ -1,7 +1,9 @@
-use super::HttpStore;
+use super::{HttpStore, CORRELATION_ID_HEADER};
 use bytes::{Bytes, BytesMut};
+use interledger_auth::{bearer_token, token_candidates};
 use interledger_errors::ApiError;
-use interledger_packet::Prepare;
+use interledger_packet::{pool, Prepare};
+use interledger_service::telemetry::{Correlate, CorrelationId};
 use interledger_service::Username;
 use interledger_service::{IncomingRequest, IncomingService};
 use secrecy::{ExposeSecret, SecretString};
@@ -12,9 +14,6 @@ use warp::{Filter, Rejection};
 
 /// Max message size that is allowed to transfer from a request or a message.
 pub const MAX_PACKET_SIZE: u64 = 40000;
-/// The offset after which the bearer token should be in an ILP over HTTP request
-/// e.g. in `token = "Bearer: MyAuthToken"`, `MyAuthToken` can be taken via token[BEARER_TOKEN_START..]
-pub const BEARER_TOKEN_START: usize = 7;
 
 /// A warp filter that parses incoming ILP-Over-HTTP requests, validates the authorization,
 /// and passes the request to an IncomingService handler.
@@ -38,15 +37,22 @@ async fn get_account<S>(
 where
     S: HttpStore,
 {
-    if password.expose_secret().len() < BEARER_TOKEN_START {
-        return Err(ApiError::unauthorized().detail("provided token was not a bearer token"));
+    let token = bearer_token(password.expose_secret())
+        .ok_or_else(|| ApiError::unauthorized().detail("provided token was not a bearer token"))?;
+    let mut last_error = None;
+    for candidate in token_candidates(token) {
+        match store
+            .get_account_from_http_auth(path_username, &candidate)
+            .await
+        {
+            Ok(account) => return Ok(account),
+            Err(err) => last_error = Some(err),
+        }
     }
-    Ok(store
-        .get_account_from_http_auth(
-            path_username,
-            &password.expose_secret()[BEARER_TOKEN_START..],
-        )
-        .await?)
+    // token_candidates always yields at least the token itself
+    Err(last_error
+        .map(ApiError::from)
+        .unwrap_or_else(ApiError::unauthorized))
 }
 
 #[inline]
@@ -63,6 +69,7 @@ where
 async fn ilp_over_http<S, I>(
     path_username: Username,
     password: SecretString,
+    correlation_id: Option<String>,
     body: Bytes,
     store: S,
     mut incoming: I,
@@ -73,14 +80,19 @@ where
 {
     let account = get_account(store, &path_username, &password).await?;
 
-    let buffer = bytes::BytesMut::from(body.as_ref());
+    let mut buffer = pool::take(body.len());
+    buffer.extend_from_slice(&body);
     if let Ok(prepare) = Prepare::try_from(buffer) {
-        let result = incoming
-            .handle_request(IncomingRequest {
-                from: account,
-                prepare,
-            })
-            .await;
+        let request = IncomingRequest {
+            from: account,
+            prepare,
+        };
+        // The services handling the request log it with the peer's correlation ID.
+        // An invalid one is ignored rather than failing the packet.
+        let result = match correlation_id.and_then(|id| id.parse::<CorrelationId>().ok()) {
+            Some(id) => incoming.handle_request(request).correlated(id).await,
+            None => incoming.handle_request(request).await,
+        };
 
         let bytes: BytesMut = match result {
             Ok(fulfill) => fulfill.into(),
@@ -122,6 +134,7 @@ where
             .and(warp::path("ilp"))
             .and(warp::path::end())
             .and(warp::header::<SecretString>("authorization"))
+            .and(warp::header::optional::<String>(CORRELATION_ID_HEADER))
             .and(warp::body::content_length_limit(MAX_PACKET_SIZE))
             .and(warp::body::bytes())
             .and(with_store)
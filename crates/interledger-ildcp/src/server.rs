@@ -1,6 +1,5 @@
 use super::packet::*;
 use super::Account;
-use async_trait::async_trait;
 use interledger_packet::*;
 use interledger_service::*;
 use std::marker::PhantomData;
@@ -27,26 +26,32 @@ where
     }
 }
 
-#[async_trait]
 impl<I, A> IncomingService<A> for IldcpService<I, A>
 where
     I: IncomingService<A> + Send,
     A: Account,
 {
-    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
-        if is_ildcp_request(&request.prepare) {
-            let from = request.from.ilp_address();
-            let builder = IldcpResponseBuilder {
-                ilp_address: from,
-                asset_code: request.from.asset_code(),
-                asset_scale: request.from.asset_scale(),
-            };
-            debug!("Responding to query for ildcp info by account: {:?}", from);
-            let response = builder.build();
-            Ok(Fulfill::from(response))
-        } else {
-            self.next.handle_request(request).await
-        }
+    type Future<'a>
+        = BoxedIlpFuture<'a>
+    where
+        Self: 'a;
+
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            if is_ildcp_request(&request.prepare) {
+                let from = request.from.ilp_address();
+                let builder = IldcpResponseBuilder {
+                    ilp_address: from,
+                    asset_code: request.from.asset_code(),
+                    asset_scale: request.from.asset_scale(),
+                };
+                debug!("Responding to query for ildcp info by account: {:?}", from);
+                let response = builder.build();
+                Ok(Fulfill::from(response))
+            } else {
+                self.next.handle_request(request).await
+            }
+        })
     }
 }
 
@@ -4,10 +4,11 @@ use bytes::BytesMut;
 use chrono::{DateTime, Utc};
 use criterion::{criterion_group, criterion_main, Criterion};
 use once_cell::sync::Lazy;
-use std::convert::TryFrom;
+use std::convert::{TryFrom, TryInto};
+use std::time::Duration;
 
 use ilp::Address;
-use ilp::{ErrorCode, Fulfill, Prepare, Reject};
+use ilp::{pool, ErrorCode, Fulfill, Prepare, Reject};
 use ilp::{FulfillBuilder, PrepareBuilder, RejectBuilder};
 use interledger_packet as ilp;
 use std::str::FromStr;
@@ -71,6 +72,15 @@ fn benchmark_serialize(c: &mut Criterion) {
             assert_eq!(BytesMut::from(REJECT.build()), reject_bytes);
         });
     });
+
+    let prepare_bytes = BytesMut::from(PREPARE.build());
+    c.bench_function("Prepare (serialize, recycled)", move |b| {
+        b.iter(|| {
+            let buffer = BytesMut::from(PREPARE.build());
+            assert_eq!(buffer, prepare_bytes);
+            pool::recycle(buffer);
+        });
+    });
 }
 
 fn benchmark_deserialize(c: &mut Criterion) {
@@ -100,6 +110,36 @@ fn benchmark_deserialize(c: &mut Criterion) {
     });
 }
 
+/// What a connector does to a Prepare it forwards: parse it, lower the amount and the expiry,
+/// and serialize it for the next hop
+fn benchmark_forward(c: &mut Criterion) {
+    let prepare_bytes = BytesMut::from(PREPARE.build());
+    c.bench_function("Prepare (forward, in place)", move |b| {
+        b.iter(|| {
+            let mut prepare = Prepare::try_from(prepare_bytes.clone()).unwrap();
+            prepare.set_amount(prepare.amount() - 1);
+            prepare.set_expires_at(prepare.expires_at() - Duration::from_secs(1));
+            pool::recycle(BytesMut::from(prepare));
+        });
+    });
+
+    let prepare_bytes = BytesMut::from(PREPARE.build());
+    c.bench_function("Prepare (forward, rebuilt)", move |b| {
+        b.iter(|| {
+            let prepare = Prepare::try_from(prepare_bytes.clone()).unwrap();
+            let forwarded = PrepareBuilder {
+                amount: prepare.amount() - 1,
+                expires_at: prepare.expires_at() - Duration::from_secs(1),
+                execution_condition: prepare.execution_condition().try_into().unwrap(),
+                destination: prepare.destination(),
+                data: prepare.data(),
+            }
+            .build();
+            pool::recycle(BytesMut::from(forwarded));
+        });
+    });
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default()
@@ -107,6 +147,7 @@ criterion_group! {
     targets =
         benchmark_serialize,
         benchmark_deserialize,
+        benchmark_forward,
 }
 
 criterion_main!(benches);
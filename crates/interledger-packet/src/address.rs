@@ -184,6 +184,60 @@ impl Address {
 
         Address::try_from(new_address.freeze())
     }
+
+    /// Returns whether the address starts with the prefix at a segment boundary.
+    /// See [`has_prefix`].
+    pub fn has_prefix(&self, prefix: &str) -> bool {
+        has_prefix(self, prefix)
+    }
+
+    /// Returns the longest prefix made of whole segments which the address has in common
+    /// with the other one. See [`common_prefix`].
+    pub fn common_prefix<'a>(&'a self, other: &str) -> &'a str {
+        common_prefix(self, other)
+    }
+
+    /// Returns the address and each of its shorter prefixes, longest first.
+    /// See [`prefixes`].
+    pub fn prefixes(&self) -> impl Iterator<Item = &str> {
+        prefixes(self)
+    }
+}
+
+// The helpers below take strings, because routing prefixes and the prefixes of the
+// destination rules are not always valid addresses (e.g. `example.` or the empty prefix).
+
+/// Returns whether the address starts with the prefix at a segment boundary: `g.bank` is a
+/// prefix of `g.bank` and `g.bank.alice` but not of `g.banking`. A prefix which ends with a
+/// `.` only matches the addresses under it, and the empty prefix matches every address.
+pub fn has_prefix(address: &str, prefix: &str) -> bool {
+    prefix.is_empty()
+        || (address.starts_with(prefix)
+            && (address.len() == prefix.len()
+                || prefix.ends_with('.')
+                || address.as_bytes()[prefix.len()] == b'.'))
+}
+
+/// Returns the longest prefix made of whole segments which both addresses start with,
+/// e.g. `g.bank` for `g.bank.alice` and `g.bank.bob`. It is empty if their schemes differ.
+pub fn common_prefix<'a>(address: &'a str, other: &str) -> &'a str {
+    let mut len = 0;
+    for (segment, other_segment) in address.split('.').zip(other.split('.')) {
+        if segment != other_segment {
+            break;
+        }
+        // the segment and the separator which follows it
+        len += segment.len() + 1;
+    }
+    &address[..len.saturating_sub(1)]
+}
+
+/// Returns the prefix and each of its shorter prefixes made of whole segments, longest
+/// first, e.g. `g.bank.alice`, `g.bank` and `g`
+pub fn prefixes(prefix: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(prefix), |prefix| {
+        prefix.rfind('.').map(|end| &prefix[..end])
+    })
 }
 
 impl PartialEq<[u8]> for Address {
@@ -371,6 +425,37 @@ mod test_address {
         );
     }
 
+    #[test]
+    fn test_has_prefix() {
+        let addr = Address::from_str("g.bank.alice").unwrap();
+        assert!(addr.has_prefix("g.bank"));
+        assert!(addr.has_prefix("g.bank."));
+        assert!(addr.has_prefix("g.bank.alice"));
+        assert!(addr.has_prefix(""));
+        assert!(!addr.has_prefix("g.ban"));
+        assert!(!addr.has_prefix("g.bank.alice."));
+        assert!(!addr.has_prefix("g.bank.alice.1"));
+        assert!(!has_prefix("g.banking", "g.bank"));
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        let addr = Address::from_str("g.bank.alice").unwrap();
+        assert_eq!(addr.common_prefix("g.bank.bob"), "g.bank");
+        assert_eq!(addr.common_prefix("g.bank.alice.1"), "g.bank.alice");
+        assert_eq!(addr.common_prefix("g.banking"), "g");
+        assert_eq!(addr.common_prefix("test.bank"), "");
+        assert_eq!(common_prefix("example.", "example.a"), "example");
+    }
+
+    #[test]
+    fn test_prefixes() {
+        let addr = Address::from_str("g.bank.alice").unwrap();
+        assert!(addr.prefixes().eq(vec!["g.bank.alice", "g.bank", "g"]));
+        assert!(prefixes("example.").eq(vec!["example.", "example"]));
+        assert!(prefixes("").eq(vec![""]));
+    }
+
     fn make_address(length: usize) -> Vec<u8> {
         let mut addr = b"test.".to_vec();
         addr.resize(length, b'_');
@@ -11,8 +11,9 @@ mod fixtures;
 pub mod hex;
 pub mod oer;
 mod packet;
+pub mod pool;
 
-pub use self::address::{Address, AddressError};
+pub use self::address::{common_prefix, has_prefix, prefixes, Address, AddressError};
 pub use self::error::{ErrorClass, ErrorCode};
 pub use self::errors::{OerError, PacketTypeError, ParseError, TrailingBytesError};
 
@@ -3,9 +3,10 @@ use std::str;
 use std::time::SystemTime;
 
 use bytes::{Buf, BufMut, BytesMut};
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeZone, Timelike, Utc};
 
 use crate::oer::{self, BufOerExt, MutBufOerExt};
+use crate::pool;
 use crate::{hex::HexString, OerError};
 use crate::{Address, ErrorCode, PacketTypeError, ParseError, TrailingBytesError};
 use std::convert::TryFrom;
@@ -158,11 +159,16 @@ impl TryFrom<BytesMut> for Prepare {
             return Err(ParseError::TimestampConversion);
         }
 
-        let expires_at = str::from_utf8(&read_expires_at[..])
-            .expect("read_expires_at matches only ascii, utf8 conversion must succeed");
-        let expires_at: DateTime<Utc> =
-            Utc.datetime_from_str(expires_at, INTERLEDGER_TIMESTAMP_FORMAT)?;
-        let expires_at = SystemTime::from(expires_at);
+        let expires_at = match parse_expiry(&read_expires_at) {
+            Some(expires_at) => expires_at,
+            None => {
+                let expires_at = str::from_utf8(&read_expires_at[..])
+                    .expect("read_expires_at matches only ascii, utf8 conversion must succeed");
+                let expires_at: DateTime<Utc> =
+                    Utc.datetime_from_str(expires_at, INTERLEDGER_TIMESTAMP_FORMAT)?;
+                SystemTime::from(expires_at)
+            }
+        };
 
         #[cfg(feature = "roundtrip-only")]
         {
@@ -226,12 +232,15 @@ impl Prepare {
     pub fn set_expires_at(&mut self, expires_at: SystemTime) {
         self.expires_at = expires_at;
         let offset = self.content_offset + AMOUNT_LEN;
-        write!(
-            &mut self.buffer[offset..offset + EXPIRY_LEN],
-            "{}",
-            DateTime::<Utc>::from(expires_at).format(INTERLEDGER_TIMESTAMP_FORMAT),
-        )
-        .unwrap();
+        match format_expiry(expires_at) {
+            Some(expiry) => self.buffer[offset..offset + EXPIRY_LEN].copy_from_slice(&expiry),
+            None => write!(
+                &mut self.buffer[offset..offset + EXPIRY_LEN],
+                "{}",
+                DateTime::<Utc>::from(expires_at).format(INTERLEDGER_TIMESTAMP_FORMAT),
+            )
+            .unwrap(),
+        }
     }
 
     /// The returned value always has a length of 32.
@@ -286,6 +295,61 @@ impl fmt::Debug for Prepare {
     }
 }
 
+/// Parses the digits of an `INTERLEDGER_TIMESTAMP_FORMAT` timestamp like chrono does, leap
+/// seconds included, without interpreting the format string for every packet forwarded.
+/// Returns `None` if they are not a valid date and time, for chrono to report the error.
+fn parse_expiry(digits: &[u8; EXPIRY_LEN]) -> Option<SystemTime> {
+    let number = |from: usize, to: usize| {
+        digits[from..to]
+            .iter()
+            .fold(0, |number, digit| number * 10 + u32::from(digit - b'0'))
+    };
+    let date = NaiveDate::from_ymd_opt(number(0, 4) as i32, number(4, 6), number(6, 8))?;
+    let (second, leap_millis) = match number(12, 14) {
+        60 => (59, 1000),
+        second => (second, 0),
+    };
+    let time = NaiveTime::from_hms_milli_opt(
+        number(8, 10),
+        number(10, 12),
+        second,
+        leap_millis + number(14, 17),
+    )?;
+    Some(SystemTime::from(
+        Utc.from_utc_datetime(&date.and_time(time)),
+    ))
+}
+
+/// Formats the expiry of a Prepare like `INTERLEDGER_TIMESTAMP_FORMAT` does, without
+/// interpreting the format string for every packet forwarded. Returns `None` for the times
+/// which do not fit in the fixed length, for chrono to format them.
+fn format_expiry(expires_at: SystemTime) -> Option<[u8; EXPIRY_LEN]> {
+    let expires_at = DateTime::<Utc>::from(expires_at);
+    if !(0..=9999).contains(&expires_at.year()) || expires_at.nanosecond() >= 1_000_000_000 {
+        return None;
+    }
+
+    let fields = [
+        (expires_at.year() as u32, 4),
+        (expires_at.month(), 2),
+        (expires_at.day(), 2),
+        (expires_at.hour(), 2),
+        (expires_at.minute(), 2),
+        (expires_at.second(), 2),
+        (expires_at.nanosecond() / 1_000_000, 3),
+    ];
+    let mut digits = [0; EXPIRY_LEN];
+    let mut offset = 0;
+    for &(mut value, width) in &fields {
+        for digit in digits[offset..offset + width].iter_mut().rev() {
+            *digit = b'0' + (value % 10) as u8;
+            value /= 10;
+        }
+        offset += width;
+    }
+    Some(digits)
+}
+
 impl<'a> PrepareBuilder<'a> {
     pub fn build(&self) -> Prepare {
         const STATIC_LEN: usize = AMOUNT_LEN + EXPIRY_LEN + CONDITION_LEN;
@@ -293,21 +357,29 @@ impl<'a> PrepareBuilder<'a> {
         let data_size = oer::predict_var_octet_string(self.data.len());
         let content_len = STATIC_LEN + destination_size + data_size;
         let buf_size = 1 + oer::predict_var_octet_string(content_len);
-        let mut buffer = BytesMut::with_capacity(buf_size);
+        let mut buffer = pool::take(buf_size);
 
         buffer.put_u8(PacketType::Prepare as u8);
         buffer.put_var_octet_string_length(content_len);
         let content_offset = buffer.len();
         buffer.put_u64(self.amount);
 
-        let mut writer = buffer.writer();
-        write!(
-            writer,
-            "{}",
-            DateTime::<Utc>::from(self.expires_at).format(INTERLEDGER_TIMESTAMP_FORMAT),
-        )
-        .unwrap();
-        let mut buffer = writer.into_inner();
+        let mut buffer = match format_expiry(self.expires_at) {
+            Some(expiry) => {
+                buffer.put_slice(&expiry);
+                buffer
+            }
+            None => {
+                let mut writer = buffer.writer();
+                write!(
+                    writer,
+                    "{}",
+                    DateTime::<Utc>::from(self.expires_at).format(INTERLEDGER_TIMESTAMP_FORMAT),
+                )
+                .unwrap();
+                writer.into_inner()
+            }
+        };
 
         buffer.put_slice(&self.execution_condition[..]);
         buffer.put_var_octet_string::<&[u8]>(self.destination.as_ref());
@@ -407,7 +479,7 @@ impl<'a> FulfillBuilder<'a> {
         let data_size = oer::predict_var_octet_string(self.data.len());
         let content_len = FULFILLMENT_LEN + data_size;
         let buf_size = 1 + oer::predict_var_octet_string(content_len);
-        let mut buffer = BytesMut::with_capacity(buf_size);
+        let mut buffer = pool::take(buf_size);
 
         buffer.put_u8(PacketType::Fulfill as u8);
         buffer.put_var_octet_string_length(content_len);
@@ -552,7 +624,7 @@ impl<'a> RejectBuilder<'a> {
         let data_size = oer::predict_var_octet_string(self.data.len());
         let content_len = ERROR_CODE_LEN + triggered_by_size + message_size + data_size;
         let buf_size = 1 + oer::predict_var_octet_string(content_len);
-        let mut buffer = BytesMut::with_capacity(buf_size);
+        let mut buffer = pool::take(buf_size);
 
         buffer.put_u8(PacketType::Reject as u8);
         buffer.put_var_octet_string_length(content_len);
@@ -848,6 +920,8 @@ mod test_packet {
 mod test_prepare {
     use super::*;
     use crate::fixtures::{self, PREPARE, PREPARE_BUILDER, PREPARE_BYTES};
+    use std::convert::TryInto;
+    use std::time::Duration;
 
     #[test]
     fn test_invalid_address() {
@@ -957,6 +1031,48 @@ mod test_prepare {
         assert_eq!(BytesMut::from(prepare), PREPARE_BYTES);
     }
 
+    #[test]
+    fn test_expiry_matches_chrono() {
+        let start = DateTime::parse_from_rfc3339("1999-12-31T23:59:58.123Z").unwrap();
+        for step in 0..5000 {
+            let expires_at = SystemTime::from(start) + Duration::from_millis(step * 7_919_311_117);
+            let formatted = DateTime::<Utc>::from(expires_at)
+                .format(INTERLEDGER_TIMESTAMP_FORMAT)
+                .to_string();
+            assert_eq!(
+                &format_expiry(expires_at).unwrap()[..],
+                formatted.as_bytes()
+            );
+
+            let parsed = Utc
+                .datetime_from_str(&formatted, INTERLEDGER_TIMESTAMP_FORMAT)
+                .unwrap();
+            let digits = formatted.as_bytes().try_into().unwrap();
+            assert_eq!(parse_expiry(digits), Some(SystemTime::from(parsed)));
+        }
+    }
+
+    #[test]
+    fn test_expiry_leap_seconds_and_invalid_dates() {
+        let leap_second = b"20000531160160251";
+        let parsed = Utc
+            .datetime_from_str("20000531160160251", INTERLEDGER_TIMESTAMP_FORMAT)
+            .unwrap();
+        assert_eq!(parse_expiry(leap_second), Some(SystemTime::from(parsed)));
+
+        for invalid in &[
+            b"20000230000000000",
+            b"20001301000000000",
+            b"20000101240000000",
+        ] {
+            assert_eq!(parse_expiry(invalid), None);
+            let invalid = str::from_utf8(&invalid[..]).unwrap();
+            assert!(Utc
+                .datetime_from_str(invalid, INTERLEDGER_TIMESTAMP_FORMAT)
+                .is_err());
+        }
+    }
+
     #[test]
     fn test_execution_condition() {
         assert_eq!(PREPARE.execution_condition(), fixtures::EXECUTION_CONDITION,);
@@ -0,0 +1,89 @@
+//! A pool of the buffers packets are serialized into.
+//!
+//! Building a packet for every request allocates a buffer which is freed soon after, once the
+//! packet has been written to the transport. Under load this churns the allocator with buffers of
+//! about the same size, so the transports and the STREAM implementation hand the buffers back with
+//! [`recycle`](./fn.recycle.html) once they are done with them, and the packet builders
+//! [`take`](./fn.take.html) them out again instead of allocating.
+//!
+//! The pool is kept per thread, so taking and recycling buffers never contends on a lock.
+use bytes::BytesMut;
+use std::cell::RefCell;
+
+/// The most buffers kept per thread; more are freed when recycled
+pub const MAX_POOLED_BUFFERS: usize = 256;
+
+/// Buffers larger than this are freed instead of being kept, so the pool does not hold on to the
+/// memory of a few unusually large packets. ILP packets can carry at most 32767 bytes of data.
+pub const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    static POOL: RefCell<Vec<BytesMut>> = RefCell::new(Vec::new());
+}
+
+/// Returns an empty buffer with room for at least `capacity` bytes, reusing a recycled one if
+/// there is any
+pub fn take(capacity: usize) -> BytesMut {
+    let buffer = POOL.try_with(|pool| pool.borrow_mut().pop()).ok().flatten();
+    match buffer {
+        Some(mut buffer) => {
+            buffer.reserve(capacity);
+            buffer
+        }
+        None => BytesMut::with_capacity(capacity),
+    }
+}
+
+/// Hands a buffer which is no longer needed back to the pool of the current thread
+pub fn recycle(mut buffer: BytesMut) {
+    if buffer.capacity() == 0 || buffer.capacity() > MAX_POOLED_CAPACITY {
+        return;
+    }
+    buffer.clear();
+    let _ = POOL.try_with(|pool| {
+        let mut pool = pool.borrow_mut();
+        if pool.len() < MAX_POOLED_BUFFERS {
+            pool.push(buffer);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BufMut;
+
+    #[test]
+    fn reuses_recycled_buffers() {
+        let mut buffer = take(100);
+        buffer.put_slice(&[1; 100]);
+        let pointer = buffer.as_ptr();
+        recycle(buffer);
+
+        let buffer = take(50);
+        assert!(buffer.is_empty());
+        assert!(buffer.capacity() >= 100);
+        assert_eq!(buffer.as_ptr(), pointer);
+    }
+
+    #[test]
+    fn grows_recycled_buffers_which_are_too_small() {
+        recycle(BytesMut::with_capacity(10));
+        let buffer = take(1000);
+        assert!(buffer.capacity() >= 1000);
+    }
+
+    #[test]
+    fn frees_large_buffers() {
+        recycle(BytesMut::with_capacity(MAX_POOLED_CAPACITY + 1));
+        assert_eq!(POOL.with(|pool| pool.borrow().len()), 0);
+    }
+
+    #[test]
+    fn keeps_a_bounded_number_of_buffers() {
+        for _ in 0..MAX_POOLED_BUFFERS + 10 {
+            recycle(BytesMut::with_capacity(10));
+        }
+        assert_eq!(POOL.with(|pool| pool.borrow().len()), MAX_POOLED_BUFFERS);
+    }
+}
@@ -0,0 +1,499 @@
+use super::{decode_frame, encode_auth, encode_frame, send_stream, QuicAccount, MAX_FRAME_SIZE};
+use async_trait::async_trait;
+use bytes::BytesMut;
+use futures::StreamExt;
+use interledger_packet::{Address, ErrorCode, Packet, Reject, RejectBuilder};
+use interledger_service::*;
+use parking_lot::Mutex;
+use quinn::{Endpoint, NewConnection};
+use secrecy::ExposeSecret;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+use tokio::sync::oneshot;
+use tracing::{debug, error, trace};
+use url::Url;
+use uuid::Uuid;
+
+/// Responses which are awaited on a connection, by the id of their Prepare packet.
+/// None once the connection was closed.
+type Pending = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<BytesMut>>>>>;
+
+/// A connection opened to the QUIC server of a peer
+#[derive(Clone)]
+struct Connection {
+    /// Distinguishes the connection from the ones which replaced it
+    generation: u64,
+    connection: quinn::Connection,
+    pending: Pending,
+}
+
+/// The QuicClientService implements [OutgoingService](../../interledger_service/trait.OutgoingService)
+/// for sending ILP Prepare packets over a QUIC connection to the URL associated with the provided account.
+/// The connection of an account is opened when its first packet is sent, and is opened again
+/// after it was closed.
+/// If no QUIC URL is specified for the account in the request, then it is forwarded to the next service.
+#[derive(Clone)]
+pub struct QuicClientService<S, O, A> {
+    /// The store used by the client to get the node's ILP Address,
+    /// used to populate the `triggered_by` field in Reject packets
+    store: S,
+    /// The next outgoing service to which non QUIC requests should be forwarded to
+    next: O,
+    /// The endpoint from which the connections are opened. Its default client configuration
+    /// determines the certificates with which the peers' certificates are verified.
+    endpoint: Endpoint,
+    connections: Arc<Mutex<HashMap<Uuid, Connection>>>,
+    /// Used to assign the ids of the Prepare packets and of the connections
+    next_id: Arc<AtomicU64>,
+    account_type: PhantomData<A>,
+}
+
+impl<S, O, A> QuicClientService<S, O, A>
+where
+    S: AddressStore,
+    O: OutgoingService<A> + Clone,
+    A: QuicAccount,
+{
+    /// Constructs the QuicClientService. The endpoint must have a default client configuration.
+    pub fn new(store: S, next: O, endpoint: Endpoint) -> Self {
+        QuicClientService {
+            store,
+            next,
+            endpoint,
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
+            account_type: PhantomData,
+        }
+    }
+
+    /// Returns the account's connection, opening it if there is none
+    async fn connection(&self, account: &A, url: &Url) -> Result<Connection, String> {
+        let existing = self.connections.lock().get(&account.id()).cloned();
+        if let Some(connection) = existing {
+            return Ok(connection);
+        }
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| "QUIC URL has no host".to_string())?;
+        let port = url
+            .port()
+            .ok_or_else(|| "QUIC URL has no port".to_string())?;
+        // The peer's address must be of the same family as the endpoint's
+        let is_ipv4 = self
+            .endpoint
+            .local_addr()
+            .map_err(|err| err.to_string())?
+            .is_ipv4();
+        let addr = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|err| err.to_string())?
+            .find(|addr| addr.is_ipv4() == is_ipv4)
+            .ok_or_else(|| format!("could not resolve {}", host))?;
+
+        let connecting = self
+            .endpoint
+            .connect(&addr, host)
+            .map_err(|err| err.to_string())?;
+        // Reconnects resume the session with 0-RTT if the server issued a session ticket
+        // on a previous connection. Only the credentials are sent as 0-RTT data, as it can
+        // be replayed, so the Prepare packets wait until the handshake is completed.
+        let new_connection = match connecting.into_0rtt() {
+            Ok((new_connection, _)) => new_connection,
+            Err(connecting) => connecting.await.map_err(|err| err.to_string())?,
+        };
+        let NewConnection {
+            connection,
+            mut uni_streams,
+            ..
+        } = new_connection;
+        let token = account
+            .get_quic_auth_token()
+            .map(|token| token.expose_secret().clone())
+            .unwrap_or_default();
+        let auth = encode_auth(url.username(), &token);
+        // The authentication stream is sent again if the server rejected the 0-RTT data
+        if send_stream(&connection, &auth).await.is_err() {
+            send_stream(&connection, &auth).await?;
+        }
+
+        let connection = Connection {
+            generation: self.next_id.fetch_add(1, Ordering::Relaxed),
+            connection,
+            pending: Arc::new(Mutex::new(Some(HashMap::new()))),
+        };
+        let account_id = account.id();
+        let generation = connection.generation;
+        let pending = connection.pending.clone();
+        let connections = self.connections.clone();
+        tokio::spawn(async move {
+            while let Some(stream) = uni_streams.next().await {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        debug!("QUIC connection of account {} closed: {}", account_id, err);
+                        break;
+                    }
+                };
+                let pending = pending.clone();
+                tokio::spawn(async move {
+                    match stream.read_to_end(MAX_FRAME_SIZE).await {
+                        Ok(data) => {
+                            if let Some((id, packet)) = decode_frame(&data) {
+                                let response = pending
+                                    .lock()
+                                    .as_mut()
+                                    .and_then(|pending| pending.remove(&id));
+                                if let Some(response) = response {
+                                    let _ = response.send(packet);
+                                }
+                            }
+                        }
+                        Err(err) => {
+                            error!(
+                                "Error reading QUIC stream of account {}: {}",
+                                account_id, err
+                            )
+                        }
+                    }
+                });
+            }
+            // The connection is opened again by the next packet, unless it was replaced already
+            let mut connections = connections.lock();
+            if connections
+                .get(&account_id)
+                .map_or(false, |connection| connection.generation == generation)
+            {
+                connections.remove(&account_id);
+            }
+            // Dropping the senders rejects the packets which are still waiting
+            pending.lock().take();
+        });
+
+        let mut connections = self.connections.lock();
+        Ok(connections.entry(account_id).or_insert(connection).clone())
+    }
+}
+
+/// Returns a Reject packet from the node
+fn reject(code: ErrorCode, message: &str, ilp_address: &Address) -> Reject {
+    RejectBuilder {
+        code,
+        message: message.as_bytes(),
+        triggered_by: Some(ilp_address),
+        data: &[],
+    }
+    .build()
+}
+
+impl<S, O, A> OutgoingService<A> for QuicClientService<S, O, A>
+where
+    S: AddressStore + Clone + Send + Sync,
+    O: OutgoingService<A> + Clone + Sync + Send,
+    A: QuicAccount + Clone + Sync + Send,
+{
+    type Future<'a>
+        = BoxedIlpFuture<'a>
+    where
+        Self: 'a;
+
+    /// Send an OutgoingRequest to a peer on a new stream of its QUIC connection and wait
+    /// for the Fulfill or Reject packet until the Prepare packet expires.
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let url = match request.to.get_quic_url() {
+                Some(url) => url.clone(),
+                None => return self.next.send_request(request).await,
+            };
+            let ilp_address = self.store.get_ilp_address();
+            trace!(to.id = %request.to.id(), url = url.as_str(), "Sending ILP over QUIC packet");
+
+            let connection = self.connection(&request.to, &url).await.map_err(|err| {
+                error!("Error opening QUIC connection: {}", err);
+                reject(
+                    ErrorCode::T01_PEER_UNREACHABLE,
+                    &format!("Error opening QUIC connection: {}", err),
+                    &ilp_address,
+                )
+            })?;
+
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let (response_sender, response) = oneshot::channel();
+            match connection.pending.lock().as_mut() {
+                Some(pending) => pending.insert(id, response_sender),
+                None => {
+                    return Err(reject(
+                        ErrorCode::T01_PEER_UNREACHABLE,
+                        "QUIC connection closed",
+                        &ilp_address,
+                    ))
+                }
+            };
+            let expires_in = request
+                .prepare
+                .expires_at()
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+            let frame = encode_frame(id, BytesMut::from(request.prepare));
+
+            let result = tokio::time::timeout(expires_in, async {
+                send_stream(&connection.connection, &frame).await?;
+                response
+                    .await
+                    .map_err(|_| "QUIC connection closed".to_string())
+            })
+            .await;
+            let data = match result {
+                Ok(Ok(data)) => data,
+                Ok(Err(err)) => {
+                    if let Some(pending) = connection.pending.lock().as_mut() {
+                        pending.remove(&id);
+                    }
+                    return Err(reject(ErrorCode::T01_PEER_UNREACHABLE, &err, &ilp_address));
+                }
+                Err(_) => {
+                    if let Some(pending) = connection.pending.lock().as_mut() {
+                        pending.remove(&id);
+                    }
+                    return Err(reject(ErrorCode::R00_TRANSFER_TIMED_OUT, "", &ilp_address));
+                }
+            };
+            match Packet::try_from(data) {
+                Ok(Packet::Fulfill(fulfill)) => Ok(fulfill),
+                Ok(Packet::Reject(reject)) => Err(reject),
+                _ => Err(reject(ErrorCode::T01_PEER_UNREACHABLE, "", &ilp_address)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Certificate, CertificateChain, ClientConfigBuilder, PrivateKey, QuicServer, QuicStore,
+        ServerConfigBuilder,
+    };
+    use interledger_errors::{AddressStoreError, QuicStoreError};
+    use interledger_packet::{FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use secrecy::SecretString;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    static USERNAME: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    const AUTH_PASSWORD: &str = "password";
+
+    #[derive(Debug, Clone)]
+    struct TestAccount {
+        id: Uuid,
+        url: Url,
+        token: &'static str,
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.id
+        }
+
+        fn username(&self) -> &Username {
+            &USERNAME
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &ILP_ADDRESS
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+    }
+
+    impl QuicAccount for TestAccount {
+        fn get_quic_url(&self) -> Option<&Url> {
+            Some(&self.url)
+        }
+
+        fn get_quic_auth_token(&self) -> Option<SecretString> {
+            Some(SecretString::new(self.token.to_string()))
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestStore {
+        url: Url,
+    }
+
+    #[async_trait]
+    impl QuicStore for TestStore {
+        type Account = TestAccount;
+
+        async fn get_account_from_quic_auth(
+            &self,
+            username: &Username,
+            token: &str,
+        ) -> Result<Self::Account, QuicStoreError> {
+            if username == &*USERNAME && token == AUTH_PASSWORD {
+                Ok(TestAccount {
+                    id: Uuid::new_v4(),
+                    url: self.url.clone(),
+                    token: AUTH_PASSWORD,
+                })
+            } else {
+                Err(QuicStoreError::Unauthorized(username.to_string()))
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    /// Starts a server with a self-signed certificate for localhost, which fulfills the
+    /// packets whose amount is even and rejects the others. Returns its URL and a client
+    /// endpoint which trusts its certificate.
+    async fn start_server() -> (Url, Endpoint) {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let certificate = Certificate::from_der(&generated.serialize_der().unwrap()).unwrap();
+        let key = PrivateKey::from_der(&generated.serialize_private_key_der()).unwrap();
+
+        let mut server_config = ServerConfigBuilder::default();
+        server_config
+            .certificate(CertificateChain::from_certs(vec![certificate.clone()]), key)
+            .unwrap();
+        let mut server_endpoint = Endpoint::builder();
+        server_endpoint.listen(server_config.build());
+        let (server_endpoint, incoming) = server_endpoint
+            .bind(&"127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        let url = Url::parse(&format!(
+            "quic://alice@localhost:{}",
+            server_endpoint.local_addr().unwrap().port()
+        ))
+        .unwrap();
+
+        let incoming_service = incoming_service_fn(|request: IncomingRequest<TestAccount>| {
+            if request.prepare.amount() % 2 == 0 {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &request.prepare.amount().to_be_bytes(),
+                }
+                .build())
+            } else {
+                Err(RejectBuilder {
+                    code: ErrorCode::F99_APPLICATION_ERROR,
+                    message: &[],
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build())
+            }
+        });
+        let server = QuicServer::new(incoming_service, TestStore { url: url.clone() });
+        tokio::spawn(server.serve(incoming));
+
+        let mut client_config = ClientConfigBuilder::default();
+        client_config
+            .add_certificate_authority(certificate)
+            .unwrap();
+        client_config.enable_0rtt();
+        let mut client_endpoint = Endpoint::builder();
+        client_endpoint.default_client_config(client_config.build());
+        let (client_endpoint, _) = client_endpoint
+            .bind(&"127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        (url, client_endpoint)
+    }
+
+    fn client(
+        url: &Url,
+        endpoint: Endpoint,
+    ) -> QuicClientService<TestStore, impl OutgoingService<TestAccount> + Clone, TestAccount> {
+        let next = outgoing_service_fn(|_: OutgoingRequest<TestAccount>| -> IlpResult {
+            panic!("not sent over QUIC")
+        });
+        QuicClientService::new(TestStore { url: url.clone() }, next, endpoint)
+    }
+
+    fn request(to: TestAccount, amount: u64) -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: to.clone(),
+            to,
+            original_amount: amount,
+            prepare: PrepareBuilder {
+                destination: ILP_ADDRESS.clone(),
+                amount,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sends_packets_over_a_connection() {
+        let (url, endpoint) = start_server().await;
+        let mut client = client(&url, endpoint);
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            url,
+            token: AUTH_PASSWORD,
+        };
+
+        let results = futures::future::join_all((0..10u64).map(|amount| {
+            let mut client = client.clone();
+            let request = request(account.clone(), amount);
+            async move { client.send_request(request).await }
+        }))
+        .await;
+        for (amount, result) in results.into_iter().enumerate() {
+            if amount % 2 == 0 {
+                assert_eq!(result.unwrap().data(), &(amount as u64).to_be_bytes()[..]);
+            } else {
+                assert_eq!(result.unwrap_err().code(), ErrorCode::F99_APPLICATION_ERROR);
+            }
+        }
+        // All packets were sent over the same connection
+        assert_eq!(client.connections.lock().len(), 1);
+        assert!(client.send_request(request(account, 2)).await.is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_packets_if_the_connection_is_not_authenticated() {
+        let (url, endpoint) = start_server().await;
+        let mut client = client(&url, endpoint);
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            url,
+            token: "wrong",
+        };
+        let reject = client.send_request(request(account, 2)).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T01_PEER_UNREACHABLE);
+    }
+}
@@ -0,0 +1,135 @@
+//! # interledger-quic
+//!
+//! Experimental client and server implementations of a transport for ILP packets over
+//! [QUIC](https://www.rfc-editor.org/rfc/rfc9000.html). Every packet is carried on its own
+//! unidirectional stream, so a lost datagram only delays the packets whose data it carried
+//! rather than every packet behind it, as it does on the single TCP stream used by BTP.
+//! QUIC connections also survive changes of the client's address and can be resumed with
+//! 0-RTT after a reconnect.
+use async_trait::async_trait;
+use bytes::{Buf, BufMut, BytesMut};
+use interledger_errors::QuicStoreError;
+use interledger_service::{Account, Username};
+use quinn::Connection;
+use secrecy::SecretString;
+use std::str::FromStr;
+use url::Url;
+
+/// QUIC client which sends the Prepare packets of the accounts with a QUIC URL
+mod client;
+/// QUIC server which passes the Prepare packets it receives to an incoming service
+mod server;
+
+pub use self::client::QuicClientService;
+pub use self::server::QuicServer;
+pub use quinn::{
+    Certificate, CertificateChain, ClientConfig, ClientConfigBuilder, Endpoint, PrivateKey,
+    ServerConfig, ServerConfigBuilder,
+};
+
+/// Maximum size of the data of a stream. The largest ILP packets are a little over 32KB.
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// Application error code with which the server closes connections that failed to authenticate
+const UNAUTHORIZED_ERROR_CODE: u32 = 1;
+/// Application error code with which the server closes connections that sent an invalid packet
+const INVALID_PACKET_ERROR_CODE: u32 = 2;
+
+/// Extension trait for [Account](../interledger_service/trait.Account.html) with the
+/// details of the peer's QUIC server
+pub trait QuicAccount: Account {
+    /// Returns the URL of the peer's QUIC server. Its username is the account's username
+    /// on the peer's node, e.g. `quic://alice@peer.example.com:7771`.
+    fn get_quic_url(&self) -> Option<&Url>;
+    /// Returns the token with which the connection is authenticated
+    fn get_quic_auth_token(&self) -> Option<SecretString>;
+}
+
+/// The interface for Stores that can be used with the QuicServer.
+#[async_trait]
+pub trait QuicStore: Clone + Send + Sync + 'static {
+    type Account: QuicAccount;
+
+    /// Load account details based on the username and token
+    /// which the client sent when opening the connection
+    async fn get_account_from_quic_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, QuicStoreError>;
+}
+
+/// Encodes the data of the stream on which the client authenticates: the length of the
+/// username as a single byte, the username and the token
+fn encode_auth(username: &str, token: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(1 + username.len() + token.len());
+    data.put_u8(username.len() as u8);
+    data.put_slice(username.as_bytes());
+    data.put_slice(token.as_bytes());
+    data
+}
+
+/// Decodes the username and token of the stream on which the client authenticates
+fn decode_auth(data: &[u8]) -> Option<(Username, &str)> {
+    let (length, data) = data.split_first()?;
+    if data.len() < *length as usize {
+        return None;
+    }
+    let (username, token) = data.split_at(*length as usize);
+    let username = Username::from_str(std::str::from_utf8(username).ok()?).ok()?;
+    Some((username, std::str::from_utf8(token).ok()?))
+}
+
+/// Encodes the data of a stream carrying an ILP packet: the id which matches the response
+/// to its Prepare packet as a big-endian u64, followed by the OER-encoded packet
+fn encode_frame(id: u64, packet: BytesMut) -> Vec<u8> {
+    let mut data = Vec::with_capacity(8 + packet.len());
+    data.put_u64(id);
+    data.put_slice(&packet);
+    data
+}
+
+/// Sends the data on a new unidirectional stream, returning once the peer acknowledged it
+async fn send_stream(connection: &Connection, data: &[u8]) -> Result<(), String> {
+    let mut stream = connection.open_uni().await.map_err(|err| err.to_string())?;
+    stream
+        .write_all(data)
+        .await
+        .map_err(|err| err.to_string())?;
+    stream.finish().await.map_err(|err| err.to_string())
+}
+
+/// Decodes the id and the packet of a stream carrying an ILP packet
+fn decode_frame(mut data: &[u8]) -> Option<(u64, BytesMut)> {
+    if data.len() < 8 {
+        return None;
+    }
+    let id = data.get_u64();
+    Some((id, BytesMut::from(data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_auth() {
+        let data = encode_auth("alice", "password");
+        let (username, token) = decode_auth(&data).unwrap();
+        assert_eq!(username.as_ref(), "alice");
+        assert_eq!(token, "password");
+
+        assert!(decode_auth(&[]).is_none());
+        assert!(decode_auth(&data[..3]).is_none());
+    }
+
+    #[test]
+    fn decodes_frames() {
+        let data = encode_frame(7, BytesMut::from(&b"packet"[..]));
+        let (id, packet) = decode_frame(&data).unwrap();
+        assert_eq!(id, 7);
+        assert_eq!(&packet[..], b"packet");
+
+        assert!(decode_frame(&data[..7]).is_none());
+    }
+}
@@ -0,0 +1,159 @@
+use super::{
+    decode_auth, decode_frame, encode_frame, send_stream, QuicStore, INVALID_PACKET_ERROR_CODE,
+    MAX_FRAME_SIZE, UNAUTHORIZED_ERROR_CODE,
+};
+use bytes::BytesMut;
+use futures::StreamExt;
+use interledger_packet::Prepare;
+use interledger_service::{Account, IncomingRequest, IncomingService};
+use quinn::{
+    Connecting, Connection, Endpoint, EndpointError, Incoming, NewConnection, ServerConfig, VarInt,
+};
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use tracing::{debug, error, trace};
+
+/// QUIC server which authenticates the connections opened by the peers and passes the
+/// Prepare packets they send to an IncomingService handler. Each Prepare packet arrives
+/// on its own stream and is handled concurrently with the others.
+#[derive(Clone)]
+pub struct QuicServer<I, S> {
+    /// The next [incoming service](../interledger_service/trait.IncomingService.html)
+    incoming: I,
+    /// A store which implements [`QuicStore`](trait.QuicStore.html)
+    store: S,
+}
+
+impl<I, S> QuicServer<I, S>
+where
+    I: IncomingService<S::Account> + Clone + Send + Sync + 'static,
+    S: QuicStore,
+    S::Account: Send + Sync + 'static,
+{
+    pub fn new(incoming: I, store: S) -> Self {
+        QuicServer { incoming, store }
+    }
+
+    /// Binds an endpoint with the server configuration, which contains the node's
+    /// certificate, to the address and serves the connections opened to it
+    pub async fn bind(self, addr: SocketAddr, config: ServerConfig) -> Result<(), EndpointError> {
+        let mut builder = Endpoint::builder();
+        builder.listen(config);
+        let (_endpoint, incoming) = builder.bind(&addr)?;
+        self.serve(incoming).await;
+        Ok(())
+    }
+
+    /// Serves the connections opened to an endpoint which was bound with a server configuration
+    pub async fn serve(self, mut incoming: Incoming) {
+        while let Some(connecting) = incoming.next().await {
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(err) = server.handle_connection(connecting).await {
+                    debug!("QUIC connection closed: {}", err);
+                }
+            });
+        }
+    }
+
+    /// Authenticates the connection with its first stream and handles the Prepare
+    /// packets received on the streams after it, responding to each of them with the
+    /// Fulfill or Reject packet with the same id.
+    ///
+    /// # Errors
+    /// 1. The connection is closed with the unauthorized error code if invalid credentials are provided
+    /// 1. The connection is closed with the invalid packet error code if a packet is not a Prepare packet
+    async fn handle_connection(self, connecting: Connecting) -> Result<(), String> {
+        let remote_address = connecting.remote_address();
+        let NewConnection {
+            connection,
+            mut uni_streams,
+            ..
+        } = connecting.await.map_err(|err| err.to_string())?;
+
+        let auth = match uni_streams.next().await {
+            Some(stream) => stream.map_err(|err| err.to_string())?,
+            None => return Ok(()),
+        };
+        let auth = auth
+            .read_to_end(MAX_FRAME_SIZE)
+            .await
+            .map_err(|err| err.to_string())?;
+        let account = match decode_auth(&auth) {
+            Some((username, token)) => self
+                .store
+                .get_account_from_quic_auth(&username, token)
+                .await
+                .map_err(|err| err.to_string()),
+            None => Err("invalid authentication stream".to_string()),
+        };
+        let account = match account {
+            Ok(account) => account,
+            Err(err) => {
+                connection.close(VarInt::from_u32(UNAUTHORIZED_ERROR_CODE), b"unauthorized");
+                return Err(format!(
+                    "rejected connection from {}: {}",
+                    remote_address, err
+                ));
+            }
+        };
+        trace!(
+            "Opened QUIC connection of {} from {}",
+            account.username(),
+            remote_address
+        );
+
+        while let Some(stream) = uni_streams.next().await {
+            let stream = stream.map_err(|err| err.to_string())?;
+            let incoming = self.incoming.clone();
+            let connection = connection.clone();
+            let from = account.clone();
+            tokio::spawn(async move {
+                let data = match stream.read_to_end(MAX_FRAME_SIZE).await {
+                    Ok(data) => data,
+                    Err(err) => {
+                        debug!("Error reading QUIC stream: {}", err);
+                        return;
+                    }
+                };
+                match decode_frame(&data)
+                    .and_then(|(id, packet)| Some((id, Prepare::try_from(packet).ok()?)))
+                {
+                    Some((id, prepare)) => {
+                        handle_prepare(incoming, connection, IncomingRequest { from, prepare }, id)
+                            .await
+                    }
+                    None => {
+                        error!("Stream did not carry a valid Prepare packet");
+                        connection.close(
+                            VarInt::from_u32(INVALID_PACKET_ERROR_CODE),
+                            b"packets must be valid Prepare packets",
+                        );
+                    }
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Passes the Prepare packet to the incoming service and sends its result back
+/// on a new stream
+async fn handle_prepare<I, A>(
+    mut incoming: I,
+    connection: Connection,
+    request: IncomingRequest<A>,
+    id: u64,
+) where
+    I: IncomingService<A>,
+    A: Account,
+{
+    let data: BytesMut = match incoming.handle_request(request).await {
+        Ok(fulfill) => fulfill.into(),
+        Err(reject) => reject.into(),
+    };
+    // The client is gone if the connection was closed
+    if let Err(err) = send_stream(&connection, &encode_frame(id, data)).await {
+        debug!("Error sending response over QUIC: {}", err);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::ExchangeRateProvider;
+use async_trait::async_trait;
 use futures::TryFutureExt;
 use once_cell::sync::Lazy;
 use reqwest::{Client, Url};
@@ -25,7 +27,26 @@ struct RateResponse {
     data: Vec<Rate>,
 }
 
-pub async fn query_coincap(client: &Client) -> Result<HashMap<String, f64>, ()> {
+/// Fetches the rates from the [CoinCap](https://docs.coincap.io) API
+#[derive(Clone, Debug, Default)]
+pub struct CoinCapProvider {
+    client: Client,
+}
+
+impl CoinCapProvider {
+    pub fn new() -> Self {
+        CoinCapProvider::default()
+    }
+}
+
+#[async_trait]
+impl ExchangeRateProvider for CoinCapProvider {
+    async fn fetch_rates(&self) -> Result<HashMap<String, f64>, ()> {
+        query_coincap(&self.client).await
+    }
+}
+
+async fn query_coincap(client: &Client) -> Result<HashMap<String, f64>, ()> {
     let (assets, rates) = futures::future::join(
         query_coincap_endpoint(client, COINCAP_ASSETS_URL.clone()),
         query_coincap_endpoint(client, COINCAP_RATES_URL.clone()),
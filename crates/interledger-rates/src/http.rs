@@ -0,0 +1,58 @@
+use crate::ExchangeRateProvider;
+use async_trait::async_trait;
+use futures::TryFutureExt;
+use reqwest::{Client, Url};
+use std::collections::HashMap;
+use tracing::error;
+
+/// Fetches the rates from an HTTP endpoint which responds to `GET` requests with a
+/// JSON object of the assets' prices in USD, e.g. `{"EUR": 1.17, "XRP": 0.25}`
+#[derive(Clone, Debug)]
+pub struct HttpRateProvider {
+    client: Client,
+    url: Url,
+}
+
+impl HttpRateProvider {
+    pub fn new(url: Url) -> Self {
+        HttpRateProvider {
+            client: Client::new(),
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl ExchangeRateProvider for HttpRateProvider {
+    async fn fetch_rates(&self) -> Result<HashMap<String, f64>, ()> {
+        let res = self
+            .client
+            .get(self.url.clone())
+            .send()
+            .map_err(|err| {
+                error!("Error fetching exchange rates from {}: {:?}", self.url, err);
+            })
+            .await?;
+
+        let res = res.error_for_status().map_err(|err| {
+            error!(
+                "HTTP error getting exchange rates from {}: {:?}",
+                self.url, err
+            );
+        })?;
+
+        let rates: HashMap<String, f64> = res
+            .json()
+            .map_err(|err| {
+                error!(
+                    "Error getting exchange rate response body from {}, incorrect type: {:?}",
+                    self.url, err
+                );
+            })
+            .await?;
+        Ok(rates
+            .into_iter()
+            .map(|(asset_code, rate)| (asset_code.to_uppercase(), rate))
+            .collect())
+    }
+}
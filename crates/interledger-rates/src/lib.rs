@@ -1,17 +1,27 @@
+use async_trait::async_trait;
 use futures::TryFutureExt;
 use interledger_errors::ExchangeRateStoreError;
-use reqwest::Client;
+#[cfg(not(target_arch = "wasm32"))]
+use interledger_service::runtime::{self, TaskHandle};
 use secrecy::SecretString;
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::fmt::Debug;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use tracing::{debug, error, trace, warn};
+use url::Url;
 
+mod coincap;
 mod cryptocompare;
+mod fixed;
+mod http;
 
-mod coincap;
+pub use coincap::CoinCapProvider;
+pub use cryptocompare::CryptoCompareProvider;
+pub use fixed::FixedRateProvider;
+pub use http::HttpRateProvider;
 
 pub trait ExchangeRateStore: Clone {
     // TODO we may want to make this async if/when we use pubsub to broadcast
@@ -28,11 +38,25 @@ pub trait ExchangeRateStore: Clone {
     // but in the normal case of getting the rate between two assets, we don't want to
     // copy all the rate data
     fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError>;
+
+    /// Returns when the oldest of the given rates was last set, so that stale rates can be
+    /// detected. Stores which do not track the age of their rates return `None`.
+    fn get_exchange_rates_updated_at(&self, _asset_codes: &[&str]) -> Option<SystemTime> {
+        None
+    }
+}
+
+/// A source of exchange rates, which the [`ExchangeRateFetcher`](./struct.ExchangeRateFetcher.html)
+/// polls on an interval
+#[async_trait]
+pub trait ExchangeRateProvider: Debug + Send + Sync {
+    /// Returns the current price of each asset in USD, keyed by the asset code in uppercase
+    async fn fetch_rates(&self) -> Result<HashMap<String, f64>, ()>;
 }
 
-/// This determines which external API service to poll for exchange rates.
+/// This determines which of the built-in exchange rate providers to poll for exchange rates.
 #[derive(Debug, Clone, Deserialize)]
-pub enum ExchangeRateProvider {
+pub enum ExchangeRateProviderConfig {
     /// Use the [CoinCap] API.
     ///
     /// Note that when configured with YAML, this MUST be specified as
@@ -51,20 +75,50 @@ pub enum ExchangeRateProvider {
     /// [CryptoCompare]: https://cryptocompare.com
     #[serde(alias = "cryptocompare")]
     CryptoCompare(SecretString),
+    /// Use fixed rates, given as the price of each asset in USD.
+    ///
+    /// Note that when configured with YAML, this MUST be specified as
+    /// "Fixed", not "fixed".
+    #[serde(alias = "fixed")]
+    Fixed(HashMap<String, f64>),
+    /// Use an HTTP endpoint which responds to `GET` requests with a JSON object
+    /// of the assets' prices in USD, e.g. `{"EUR": 1.17, "XRP": 0.25}`.
+    ///
+    /// Note that when configured with YAML, this MUST be specified as
+    /// "Http", not "http".
+    #[serde(alias = "http")]
+    Http(Url),
 }
 
-impl PartialEq<ExchangeRateProvider> for ExchangeRateProvider {
+impl ExchangeRateProviderConfig {
+    /// Creates the configured provider
+    pub fn build(&self) -> Arc<dyn ExchangeRateProvider> {
+        match self {
+            ExchangeRateProviderConfig::CoinCap => Arc::new(CoinCapProvider::new()),
+            ExchangeRateProviderConfig::CryptoCompare(api_key) => {
+                Arc::new(CryptoCompareProvider::new(api_key.clone()))
+            }
+            ExchangeRateProviderConfig::Fixed(rates) => {
+                Arc::new(FixedRateProvider::new(rates.clone()))
+            }
+            ExchangeRateProviderConfig::Http(url) => Arc::new(HttpRateProvider::new(url.clone())),
+        }
+    }
+}
+
+impl PartialEq<ExchangeRateProviderConfig> for ExchangeRateProviderConfig {
     fn eq(&self, other: &Self) -> bool {
         // this was originally added to be able to test the configurations, to have a PartialEq
         // derive on InterledgerNode.
         use secrecy::ExposeSecret;
         match (self, other) {
-            (ExchangeRateProvider::CoinCap, ExchangeRateProvider::CoinCap) => true,
-            (ExchangeRateProvider::CryptoCompare(l), ExchangeRateProvider::CryptoCompare(r))
-                if l.expose_secret() == r.expose_secret() =>
-            {
-                true
-            }
+            (ExchangeRateProviderConfig::CoinCap, ExchangeRateProviderConfig::CoinCap) => true,
+            (
+                ExchangeRateProviderConfig::CryptoCompare(l),
+                ExchangeRateProviderConfig::CryptoCompare(r),
+            ) if l.expose_secret() == r.expose_secret() => true,
+            (ExchangeRateProviderConfig::Fixed(l), ExchangeRateProviderConfig::Fixed(r)) => l == r,
+            (ExchangeRateProviderConfig::Http(l), ExchangeRateProviderConfig::Http(r)) => l == r,
             _ => false,
         }
     }
@@ -73,11 +127,10 @@ impl PartialEq<ExchangeRateProvider> for ExchangeRateProvider {
 /// Poll exchange rate providers for the current exchange rates
 #[derive(Clone)]
 pub struct ExchangeRateFetcher<S> {
-    provider: ExchangeRateProvider,
+    provider: Arc<dyn ExchangeRateProvider>,
     consecutive_failed_polls: Arc<AtomicU32>,
     failed_polls_before_invalidation: u32,
     store: S,
-    client: Client,
 }
 
 impl<S> ExchangeRateFetcher<S>
@@ -86,7 +139,7 @@ where
 {
     /// Simple constructor
     pub fn new(
-        provider: ExchangeRateProvider,
+        provider: Arc<dyn ExchangeRateProvider>,
         failed_polls_before_invalidation: u32,
         store: S,
     ) -> Self {
@@ -95,35 +148,26 @@ where
             consecutive_failed_polls: Arc::new(AtomicU32::new(0)),
             failed_polls_before_invalidation,
             store,
-            client: Client::new(),
         }
     }
 
-    /// Spawns a future which calls [`self.update_rates()`](./struct.ExchangeRateFetcher.html#method.update_rates) every `interval`
-    pub fn spawn_interval(self, interval: Duration) {
+    /// Spawns a future which calls [`self.update_rates()`](./struct.ExchangeRateFetcher.html#method.update_rates) every `interval`.
+    /// The polling stops when the returned handle is aborted.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn spawn_interval(self, interval: Duration) -> TaskHandle {
         debug!(
             "Starting interval to poll exchange rate provider: {:?} for rates",
             self.provider
         );
         let interval = async move {
-            let mut interval = tokio::time::interval(interval);
+            let mut interval = runtime::interval(interval);
             loop {
                 interval.tick().await;
                 // Ignore errors so that they don't cause the Interval to stop
                 let _ = self.update_rates().await;
             }
         };
-        tokio::spawn(interval);
-    }
-
-    /// Calls the proper exchange rate provider
-    async fn fetch_rates(&self) -> Result<HashMap<String, f64>, ()> {
-        match self.provider {
-            ExchangeRateProvider::CryptoCompare(ref api_key) => {
-                cryptocompare::query_cryptocompare(&self.client, api_key).await
-            }
-            ExchangeRateProvider::CoinCap => coincap::query_coincap(&self.client).await,
-        }
+        runtime::spawn_abortable(interval)
     }
 
     /// Gets the exchange rates and proceeds to update the store with the newly polled values
@@ -135,7 +179,7 @@ where
         let store_clone = self.store.clone();
         let provider = self.provider.clone();
         #[allow(clippy::cognitive_complexity)]
-        let mut rates = self.fetch_rates()
+        let mut rates = self.provider.fetch_rates()
             .map_err(move |_| {
                 // Note that a race between the read on this line and the check on the line after
                 // is quite unlikely as long as the interval between polls is reasonable.
@@ -18,7 +18,7 @@ use uuid::Uuid;
 
 mod router;
 
-pub use self::router::Router;
+pub use self::router::{Router, RouterFuture};
 
 /// A trait for Store implmentations that have ILP routing tables.
 pub trait RouterStore: AccountStore + Clone + Send + Sync + 'static {
@@ -1,9 +1,15 @@
 use super::RouterStore;
-use async_trait::async_trait;
-use interledger_packet::{ErrorCode, RejectBuilder};
+use futures::future::{ready, Either, Ready};
+use interledger_errors::AccountStoreError;
+use interledger_packet::{Address, ErrorCode, RejectBuilder};
 use interledger_service::*;
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
 use std::str;
+use std::task::{Context, Poll};
 use tracing::{error, trace};
+use uuid::Uuid;
 
 /// # Interledger Router
 ///
@@ -38,51 +44,47 @@ where
     }
 }
 
-#[async_trait]
 impl<S, O> IncomingService<S::Account> for Router<S, O>
 where
     S: AddressStore + RouterStore,
     O: OutgoingService<S::Account> + Clone + Send + 'static,
 {
+    type Future<'a> = Either<RouterFuture<'a, S::Account, O>, Ready<IlpResult>>;
+
     /// Figures out the next node to pass the received Prepare packet to.
     ///
     /// Firstly, it checks if there is a direct path for that account and uses that.
     /// If not it scans through the routing table and checks if the route prefix matches
     /// the prepare packet's destination or if it's a catch-all address (i.e. empty prefix)
-    async fn handle_request(&mut self, request: IncomingRequest<S::Account>) -> IlpResult {
+    fn handle_request(&mut self, request: IncomingRequest<S::Account>) -> Self::Future<'_> {
+        let Router { store, next } = self;
         let destination = request.prepare.destination();
         let mut next_hop = None;
-        let routing_table = self.store.routing_table();
-        let ilp_address = self.store.get_ilp_address();
+        let routing_table = store.routing_table();
+        let ilp_address = store.get_ilp_address();
 
         // Check if we have a direct path for that account or if we need to scan
         // through the routing table
         let dest: &str = &destination;
         if let Some(account_id) = routing_table.get(dest) {
-            trace!(
-                "Found direct route for address: \"{}\". Account: {}",
-                destination,
-                account_id
-            );
+            trace!(destination = %destination, to.id = %account_id, "Found direct route");
             next_hop = Some(*account_id);
         } else if !routing_table.is_empty() {
             let mut matching_prefix = "";
-            let routing_table = self.store.routing_table();
+            let routing_table = store.routing_table();
             for (prefix, account) in (*routing_table).iter() {
                 // Check if the route prefix matches or is empty (meaning it's a catch-all address)
-                if (prefix.is_empty() || dest.starts_with(prefix.as_str()))
-                    && prefix.len() >= matching_prefix.len()
-                {
+                if destination.has_prefix(prefix) && prefix.len() >= matching_prefix.len() {
                     next_hop.replace(*account);
                     matching_prefix = prefix.as_str();
                 }
             }
             if let Some(account_id) = next_hop {
                 trace!(
-                    "Found matching route for address: \"{}\". Prefix: \"{}\", account: {}",
-                    destination,
-                    matching_prefix,
-                    account_id,
+                    destination = %destination,
+                    prefix = matching_prefix,
+                    to.id = %account_id,
+                    "Found matching route"
                 );
             }
         } else {
@@ -90,23 +92,14 @@ where
         }
 
         if let Some(account_id) = next_hop {
-            let mut next = self.next.clone();
-            match self.store.get_accounts(vec![account_id]).await {
-                Ok(mut accounts) => {
-                    let request = request.into_outgoing(accounts.remove(0));
-                    next.send_request(request).await
-                }
-                Err(_) => {
-                    error!("No record found for account: {}", account_id);
-                    Err(RejectBuilder {
-                        code: ErrorCode::F02_UNREACHABLE,
-                        message: &[],
-                        triggered_by: Some(&ilp_address),
-                        data: &[],
-                    }
-                    .build())
-                }
-            }
+            Either::Left(RouterFuture {
+                next: Some(next),
+                request: Some(request),
+                account_id,
+                ilp_address,
+                lookup: Some(store.get_accounts(vec![account_id])),
+                sending: None,
+            })
         } else {
             error!(
                 "No route found for request {}: {:?}",
@@ -126,20 +119,79 @@ where
                 },
                 request
             );
-            Err(RejectBuilder {
+            Either::Right(ready(Err(RejectBuilder {
                 code: ErrorCode::F02_UNREACHABLE,
                 message: &[],
                 triggered_by: Some(&ilp_address),
                 data: &[],
             }
-            .build())
+            .build())))
+        }
+    }
+}
+
+/// The future of `AccountStore::get_accounts`
+type AccountsFuture<'a, A> =
+    Pin<Box<dyn Future<Output = Result<Vec<A>, AccountStoreError>> + Send + 'a>>;
+
+/// The future returned by the `Router` for the requests it found a route for. It loads
+/// the account of the next hop, then sends the request on with the future of the outgoing
+/// service, so that routing a request only allocates for the store lookup.
+#[pin_project]
+pub struct RouterFuture<'a, A: Account, O: OutgoingService<A> + 'a> {
+    next: Option<&'a mut O>,
+    request: Option<IncomingRequest<A>>,
+    account_id: Uuid,
+    ilp_address: Address,
+    lookup: Option<AccountsFuture<'a, A>>,
+    #[pin]
+    sending: Option<O::Future<'a>>,
+}
+
+impl<'a, A: Account, O: OutgoingService<A> + 'a> Future for RouterFuture<'a, A, O> {
+    type Output = IlpResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IlpResult> {
+        let mut this = self.project();
+        if let Some(lookup) = this.lookup.as_mut() {
+            let result = futures::ready!(lookup.as_mut().poll(cx));
+            *this.lookup = None;
+            match result {
+                Ok(mut accounts) => {
+                    let request = this
+                        .request
+                        .take()
+                        .expect("RouterFuture polled after completion")
+                        .into_outgoing(accounts.remove(0));
+                    let next = this
+                        .next
+                        .take()
+                        .expect("RouterFuture polled after completion");
+                    this.sending.set(Some(next.send_request(request)));
+                }
+                Err(_) => {
+                    error!("No record found for account: {}", this.account_id);
+                    return Poll::Ready(Err(RejectBuilder {
+                        code: ErrorCode::F02_UNREACHABLE,
+                        message: &[],
+                        triggered_by: Some(this.ilp_address),
+                        data: &[],
+                    }
+                    .build()));
+                }
+            }
         }
+        this.sending
+            .as_pin_mut()
+            .expect("RouterFuture polled after completion")
+            .poll(cx)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use async_trait::async_trait;
     use interledger_errors::*;
     use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
     use interledger_service::outgoing_service_fn;
@@ -292,6 +344,39 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn prefix_matches_whole_segments() {
+        let mut router = Router::new(
+            TestStore {
+                routes: vec![("example.dest".to_string(), Uuid::new_v4())]
+                    .into_iter()
+                    .collect(),
+            },
+            outgoing_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+
+        let result = router
+            .handle_request(IncomingRequest {
+                from: TestAccount(Uuid::new_v4()),
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount: 100,
+                    execution_condition: &[1; 32],
+                    expires_at: UNIX_EPOCH,
+                    data: &[],
+                }
+                .build(),
+            })
+            .await;
+        assert!(result.is_err());
+    }
+
     #[tokio::test]
     async fn finds_exact_route() {
         let mut router = Router::new(
@@ -0,0 +1,653 @@
+use super::{
+    current_day, BalanceStore, PacketId, RateLimitAccount, RateLimitError, RateLimitStore,
+    VolumeLimitAccount, VolumeLimitError, VolumeLimitStore,
+};
+use async_trait::async_trait;
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_service::{
+    runtime, Account, AddressStore, BoxedIlpFuture, IncomingRequest, IncomingService,
+};
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use tracing::{debug, error, warn};
+
+/// Reasons for which a Prepare packet is not admitted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdmissionError {
+    /// Account exceeded their packet limit
+    PacketLimitExceeded,
+    /// Account exceeded their amount limit
+    ThroughputLimitExceeded,
+    /// Account exceeded their daily volume limit
+    DailyLimitExceeded,
+    /// The packet would bring the account under its minimum balance
+    InsufficientBalance,
+    /// There was an internal error when trying to connect to the store
+    StoreError,
+}
+
+impl From<RateLimitError> for AdmissionError {
+    fn from(err: RateLimitError) -> Self {
+        match err {
+            RateLimitError::PacketLimitExceeded => AdmissionError::PacketLimitExceeded,
+            RateLimitError::ThroughputLimitExceeded => AdmissionError::ThroughputLimitExceeded,
+            RateLimitError::StoreError => AdmissionError::StoreError,
+        }
+    }
+}
+
+impl From<VolumeLimitError> for AdmissionError {
+    fn from(err: VolumeLimitError) -> Self {
+        match err {
+            VolumeLimitError::DailyLimitExceeded => AdmissionError::DailyLimitExceeded,
+            VolumeLimitError::StoreError => AdmissionError::StoreError,
+        }
+    }
+}
+
+/// Store trait which applies everything the node checks and charges before forwarding a
+/// Prepare packet: the rate limits, the daily volume limit and, if the balances are tracked,
+/// the balance of the sender. The provided methods make one call to the store for each of
+/// them, which stores should override to do it all in a single round trip.
+#[async_trait]
+pub trait AdmissionStore:
+    BalanceStore + RateLimitStore + VolumeLimitStore<Account = <Self as RateLimitStore>::Account> + Sync
+where
+    <Self as RateLimitStore>::Account: VolumeLimitAccount + Sync + 'static,
+{
+    /// Charges the packet to the rate limits and the daily volume of the account and, if
+    /// `update_balance` is set, deducts the amount from its balance. Nothing is charged if the
+    /// packet is not admitted, except to the packet limit, as with the separate calls.
+    async fn admit_prepare(
+        &self,
+        account: <Self as RateLimitStore>::Account,
+        amount: u64,
+        day: u64,
+        packet_id: Option<PacketId>,
+        update_balance: bool,
+    ) -> Result<(), AdmissionError> {
+        self.apply_rate_limits(account.clone(), amount).await?;
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let charge_volume = account.amount_per_day_limit().is_some();
+        if charge_volume {
+            if let Err(err) = self.charge_daily_volume(account.clone(), day, amount).await {
+                let _ = self.refund_throughput_limit(account, amount).await;
+                return Err(err.into());
+            }
+        }
+        if update_balance
+            && self
+                .update_balances_for_prepare(account.id(), amount, packet_id)
+                .await
+                .is_err()
+        {
+            if charge_volume {
+                let _ = self.refund_daily_volume(account.clone(), day, amount).await;
+            }
+            let _ = self.refund_throughput_limit(account, amount).await;
+            return Err(AdmissionError::InsufficientBalance);
+        }
+        Ok(())
+    }
+
+    /// Refunds everything [`admit_prepare`](#method.admit_prepare) charged for a packet
+    /// which was rejected, so that only fulfilled packets count towards the limits
+    async fn refund_prepare(
+        &self,
+        account: <Self as RateLimitStore>::Account,
+        amount: u64,
+        day: u64,
+        packet_id: Option<PacketId>,
+        update_balance: bool,
+    ) -> Result<(), AdmissionError> {
+        if amount == 0 {
+            return Ok(());
+        }
+        if update_balance {
+            self.update_balances_for_reject(account.id(), amount, packet_id)
+                .await
+                .map_err(|_| AdmissionError::StoreError)?;
+        }
+        if account.amount_per_day_limit().is_some() {
+            self.refund_daily_volume(account.clone(), day, amount)
+                .await?;
+        }
+        self.refund_throughput_limit(account, amount).await?;
+        Ok(())
+    }
+}
+
+/// # Admission Service
+///
+/// Incoming Service which combines the [`RateLimitService`](./struct.RateLimitService.html),
+/// the [`VolumeLimitService`](./struct.VolumeLimitService.html) and the Prepare side of the
+/// [`BalanceService`](./struct.BalanceService.html), so that a packet takes a single call
+/// to the store before it is forwarded. If the packet is rejected further down the line,
+/// everything is refunded with a single call too, which the reject does not wait for.
+///
+/// Balances are only updated if [`track_balances`](#method.track_balances) is set, in which
+/// case the `BalanceService` must be told with
+/// [`admitted_prepares`](./struct.BalanceService.html#method.admitted_prepares) not to
+/// update them again. If the `BalanceService` journals the packets, it refunds the balances
+/// of the rejected ones itself, and this service must be told so with
+/// [`leave_balance_refunds`](#method.leave_balance_refunds).
+///
+/// Requires a `RateLimitAccount`, a `VolumeLimitAccount` and an `AdmissionStore`.
+/// It is an IncomingService.
+#[derive(Clone)]
+pub struct AdmissionService<S, I, A> {
+    store: S,
+    next: I,
+    track_balances: bool,
+    refund_balances: bool,
+    account_type: PhantomData<A>,
+}
+
+impl<S, I, A> AdmissionService<S, I, A>
+where
+    S: AddressStore + AdmissionStore + RateLimitStore<Account = A> + Send + Sync,
+    I: IncomingService<A> + Send + Sync,
+    A: RateLimitAccount + VolumeLimitAccount + Sync,
+{
+    pub fn new(store: S, next: I) -> Self {
+        AdmissionService {
+            store,
+            next,
+            track_balances: false,
+            refund_balances: true,
+            account_type: PhantomData,
+        }
+    }
+
+    /// Deduct the amounts of the packets from the balances of their senders when they are
+    /// admitted, and add them back when they are rejected
+    pub fn track_balances(mut self) -> Self {
+        self.track_balances = true;
+        self
+    }
+
+    /// Leave the refunds of the balances of the rejected packets to the `BalanceService`,
+    /// which only removes the packets from its journal once their senders were refunded.
+    /// The limits are still refunded here.
+    pub fn leave_balance_refunds(mut self) -> Self {
+        self.refund_balances = false;
+        self
+    }
+}
+
+impl<S, I, A> IncomingService<A> for AdmissionService<S, I, A>
+where
+    S: AddressStore + AdmissionStore + RateLimitStore<Account = A> + Clone + Send + Sync + 'static,
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: RateLimitAccount + VolumeLimitAccount + Sync + 'static,
+{
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    /// On receiving a request:
+    /// 1. Admit the packet, charging it to the sender's limits and balance in a single call to the store
+    /// 1. If it was admitted forward the request
+    ///     - If the request forwarding failed, spawn a task refunding the sender and return the reject
+    /// 1. If it was not admitted, return a reject with the appropriate ErrorCode.
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let account = request.from.clone();
+            let amount = request.prepare.amount();
+            let packet_id = PacketId::try_from(request.prepare.execution_condition()).ok();
+            let day = current_day();
+
+            match self
+                .store
+                .admit_prepare(account.clone(), amount, day, packet_id, self.track_balances)
+                .await
+            {
+                Ok(()) => {
+                    let packet = self.next.handle_request(request).await;
+                    if packet.is_err() && amount > 0 {
+                        // The reject is relayed without waiting for the store, as the
+                        // BalanceService does for its own updates
+                        let store = self.store.clone();
+                        let refund_balance = self.track_balances && self.refund_balances;
+                        runtime::spawn(async move {
+                            if let Err(err) = store
+                                .refund_prepare(account, amount, day, packet_id, refund_balance)
+                                .await
+                            {
+                                error!("Error refunding rejected packet: {:?}", err);
+                            }
+                        });
+                    }
+                    packet
+                }
+                Err(err) => {
+                    let (code, message) = match err {
+                        AdmissionError::PacketLimitExceeded => {
+                            if let Some(limit) = account.packets_per_minute_limit() {
+                                warn!("Account {} was rate limited for sending too many packets. Limit is: {} per minute", account.id(), limit);
+                            }
+                            (ErrorCode::T05_RATE_LIMITED, String::new())
+                        }
+                        AdmissionError::ThroughputLimitExceeded => {
+                            if let Some(limit) = account.amount_per_minute_limit() {
+                                warn!("Account {} was throughput limited for trying to send too much money. Limit is: {} per minute", account.id(), limit);
+                            }
+                            (ErrorCode::T04_INSUFFICIENT_LIQUIDITY, String::new())
+                        }
+                        AdmissionError::DailyLimitExceeded => {
+                            let limit = account.amount_per_day_limit().unwrap_or_default();
+                            warn!(
+                                "Account {} reached its daily volume limit of {}",
+                                account.id(),
+                                limit
+                            );
+                            (
+                                ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                                format!("Daily volume limit of {} exceeded", limit),
+                            )
+                        }
+                        AdmissionError::InsufficientBalance => {
+                            debug!(
+                                from.id = %account.id(),
+                                reject.code = %ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                                "Rejecting packet because it would exceed a balance limit"
+                            );
+                            (ErrorCode::T04_INSUFFICIENT_LIQUIDITY, String::new())
+                        }
+                        AdmissionError::StoreError => {
+                            (ErrorCode::T00_INTERNAL_ERROR, String::new())
+                        }
+                    };
+                    Err(RejectBuilder {
+                        code,
+                        message: message.as_bytes(),
+                        triggered_by: Some(&self.store.get_ilp_address()),
+                        data: &[],
+                    }
+                    .build())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BalanceChange, BalanceTotals};
+    use interledger_errors::{AddressStoreError, BalanceStoreError};
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use interledger_service::{incoming_service_fn, Username};
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn admits_packets_and_updates_balances() {
+        let store = TestStore::new(None);
+        let mut service = AdmissionService::new(store.clone(), fulfill_service()).track_balances();
+        service.handle_request(TEST_REQUEST.clone()).await.unwrap();
+        assert_eq!(
+            *store.calls.lock(),
+            vec!["apply_rate_limits", "charge_daily_volume", "prepare"]
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_balances_unless_tracked() {
+        let store = TestStore::new(None);
+        let mut service = AdmissionService::new(store.clone(), fulfill_service());
+        service.handle_request(TEST_REQUEST.clone()).await.unwrap();
+        assert_eq!(
+            *store.calls.lock(),
+            vec!["apply_rate_limits", "charge_daily_volume"]
+        );
+    }
+
+    #[tokio::test]
+    async fn refunds_rejected_packets() {
+        let store = TestStore::new(None);
+        let next = incoming_service_fn(move |_| {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: &[],
+                triggered_by: None,
+                data: &[],
+            }
+            .build())
+        });
+        let mut service = AdmissionService::new(store.clone(), next).track_balances();
+        let reject = service
+            .handle_request(TEST_REQUEST.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+
+        // The refund is done in the background
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(
+            *store.calls.lock(),
+            vec![
+                "apply_rate_limits",
+                "charge_daily_volume",
+                "prepare",
+                "reject",
+                "refund_daily_volume",
+                "refund_throughput_limit"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_balance_refunds_to_the_balance_service() {
+        let store = TestStore::new(None);
+        let next = incoming_service_fn(move |_| {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: &[],
+                triggered_by: None,
+                data: &[],
+            }
+            .build())
+        });
+        let mut service = AdmissionService::new(store.clone(), next)
+            .track_balances()
+            .leave_balance_refunds();
+        service
+            .handle_request(TEST_REQUEST.clone())
+            .await
+            .unwrap_err();
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(
+            *store.calls.lock(),
+            vec![
+                "apply_rate_limits",
+                "charge_daily_volume",
+                "prepare",
+                "refund_daily_volume",
+                "refund_throughput_limit"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_over_the_minimum_balance() {
+        let store = TestStore::new(Some("prepare"));
+        let mut service = AdmissionService::new(store.clone(), fulfill_service()).track_balances();
+        let reject = service
+            .handle_request(TEST_REQUEST.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T04_INSUFFICIENT_LIQUIDITY);
+        // The limits which were charged are refunded
+        assert_eq!(
+            *store.calls.lock(),
+            vec![
+                "apply_rate_limits",
+                "charge_daily_volume",
+                "prepare",
+                "refund_daily_volume",
+                "refund_throughput_limit"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_rate_limited_packets() {
+        let store = TestStore::new(Some("apply_rate_limits"));
+        let mut service = AdmissionService::new(store.clone(), fulfill_service()).track_balances();
+        let reject = service
+            .handle_request(TEST_REQUEST.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T05_RATE_LIMITED);
+        assert_eq!(*store.calls.lock(), vec!["apply_rate_limits"]);
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_over_the_daily_volume_limit() {
+        let store = TestStore::new(Some("charge_daily_volume"));
+        let mut service = AdmissionService::new(store.clone(), fulfill_service()).track_balances();
+        let reject = service
+            .handle_request(TEST_REQUEST.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T04_INSUFFICIENT_LIQUIDITY);
+        assert_eq!(reject.message(), b"Daily volume limit of 1000 exceeded");
+        assert_eq!(
+            *store.calls.lock(),
+            vec![
+                "apply_rate_limits",
+                "charge_daily_volume",
+                "refund_throughput_limit"
+            ]
+        );
+    }
+
+    fn fulfill_service() -> impl IncomingService<TestAccount> + Clone {
+        incoming_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        })
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestAccount;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    impl RateLimitAccount for TestAccount {
+        fn packets_per_minute_limit(&self) -> Option<u32> {
+            Some(100)
+        }
+    }
+
+    impl VolumeLimitAccount for TestAccount {
+        fn amount_per_day_limit(&self) -> Option<u64> {
+            Some(1000)
+        }
+    }
+
+    /// Records the calls made to it, and fails the one with the given name
+    #[derive(Clone)]
+    struct TestStore {
+        calls: Arc<Mutex<Vec<&'static str>>>,
+        fail: Option<&'static str>,
+    }
+
+    impl TestStore {
+        fn new(fail: Option<&'static str>) -> Self {
+            TestStore {
+                calls: Arc::new(Mutex::new(Vec::new())),
+                fail,
+            }
+        }
+
+        fn call(&self, name: &'static str) -> bool {
+            self.calls.lock().push(name);
+            self.fail != Some(name)
+        }
+    }
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl RateLimitStore for TestStore {
+        type Account = TestAccount;
+
+        async fn apply_rate_limits(&self, _: TestAccount, _: u64) -> Result<(), RateLimitError> {
+            if self.call("apply_rate_limits") {
+                Ok(())
+            } else {
+                Err(RateLimitError::PacketLimitExceeded)
+            }
+        }
+
+        async fn refund_throughput_limit(
+            &self,
+            _: TestAccount,
+            _: u64,
+        ) -> Result<(), RateLimitError> {
+            self.call("refund_throughput_limit");
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl VolumeLimitStore for TestStore {
+        type Account = TestAccount;
+
+        async fn charge_daily_volume(
+            &self,
+            _: TestAccount,
+            _: u64,
+            _: u64,
+        ) -> Result<(), VolumeLimitError> {
+            if self.call("charge_daily_volume") {
+                Ok(())
+            } else {
+                Err(VolumeLimitError::DailyLimitExceeded)
+            }
+        }
+
+        async fn refund_daily_volume(
+            &self,
+            _: TestAccount,
+            _: u64,
+            _: u64,
+        ) -> Result<(), VolumeLimitError> {
+            self.call("refund_daily_volume");
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl BalanceStore for TestStore {
+        async fn get_balance(&self, _: Uuid) -> Result<i64, BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn update_balances_for_prepare(
+            &self,
+            _: Uuid,
+            _: u64,
+            _: Option<PacketId>,
+        ) -> Result<(), BalanceStoreError> {
+            if self.call("prepare") {
+                Ok(())
+            } else {
+                Err(BalanceStoreError::Other(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "under the minimum balance",
+                ))))
+            }
+        }
+
+        async fn update_balances_for_fulfill(
+            &self,
+            _: Uuid,
+            _: u64,
+            _: Option<PacketId>,
+        ) -> Result<(i64, u64), BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn update_balances_for_fulfill_and_queue_settlement(
+            &self,
+            _: Uuid,
+            _: u64,
+            _: Option<PacketId>,
+        ) -> Result<(i64, u64), BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn update_balances_for_reject(
+            &self,
+            _: Uuid,
+            _: u64,
+            _: Option<PacketId>,
+        ) -> Result<(), BalanceStoreError> {
+            self.call("reject");
+            Ok(())
+        }
+
+        async fn update_balances_for_delayed_settlement(
+            &self,
+            _: Uuid,
+        ) -> Result<(i64, u64), BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn get_balance_changes(
+            &self,
+            _: Uuid,
+            _: u64,
+            _: usize,
+        ) -> Result<Vec<BalanceChange>, BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn get_balance_totals(&self, _: Uuid) -> Result<BalanceTotals, BalanceStoreError> {
+            unimplemented!()
+        }
+    }
+
+    impl AdmissionStore for TestStore {}
+
+    static TEST_REQUEST: Lazy<IncomingRequest<TestAccount>> = Lazy::new(|| IncomingRequest {
+        from: TestAccount,
+        prepare: PrepareBuilder {
+            destination: Address::from_str("example.destination").unwrap(),
+            amount: 100,
+            expires_at: std::time::SystemTime::now() + std::time::Duration::from_secs(30),
+            execution_condition: &[0; 32],
+            data: b"test data",
+        }
+        .build(),
+    });
+}
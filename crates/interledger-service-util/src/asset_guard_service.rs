@@ -0,0 +1,290 @@
+use futures::future::{ready, Either, Ready};
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_rates::ExchangeRateStore;
+use interledger_service::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::error;
+use uuid::Uuid;
+
+/// How often the misconfiguration of each pair of accounts is reported
+const MISMATCH_ALERT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// # Asset Guard Service
+///
+/// Outgoing Service which rejects the packets between two accounts whose assets the
+/// `ExchangeRateService` could only convert at a nonsense rate, rather than letting the
+/// amounts be silently mangled. The packets are rejected with `T00: Internal Error` when:
+/// - the asset codes of the two accounts only differ in case or surrounding whitespace,
+///   which is almost certainly a typo in the configuration of one of them
+/// - the accounts have different asset codes and one of them has no exchange rate, or one
+///   which is zero, negative or not a number
+///
+/// The misconfiguration is also logged as an error, at most once a minute per pair of accounts.
+/// Packets of 0 are forwarded as they are not converted.
+/// Requires an `ExchangeRateStore` and an `AddressStore`.
+#[derive(Clone)]
+pub struct AssetGuardService<S, O> {
+    store: S,
+    next: O,
+    /// When the misconfiguration of each pair of accounts was last reported
+    last_alerts: Arc<Mutex<HashMap<(Uuid, Uuid), Instant>>>,
+}
+
+impl<S, O> AssetGuardService<S, O> {
+    pub fn new(store: S, next: O) -> Self {
+        AssetGuardService {
+            store,
+            next,
+            last_alerts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Logs the misconfiguration, unless it was logged recently for the same accounts
+    fn alert<A: Account>(&self, from: &A, to: &A, problem: &str) {
+        let now = Instant::now();
+        let key = (from.id(), to.id());
+        let mut last_alerts = self.last_alerts.lock().unwrap();
+        if let Some(last_alert) = last_alerts.get(&key) {
+            if now.duration_since(*last_alert) < MISMATCH_ALERT_INTERVAL {
+                return;
+            }
+        }
+        last_alerts.insert(key, now);
+        error!(
+            "Rejecting packets from account {} ({}:{}) to account {} ({}:{}) because {}. Check the asset configuration of the accounts.",
+            from.username(),
+            from.asset_code(),
+            from.asset_scale(),
+            to.username(),
+            to.asset_code(),
+            to.asset_scale(),
+            problem
+        );
+    }
+}
+
+impl<S, O> AssetGuardService<S, O>
+where
+    S: ExchangeRateStore,
+{
+    /// Describes why the assets of the accounts cannot be converted, if they cannot
+    fn check<A: Account>(&self, from: &A, to: &A) -> Option<String> {
+        let (from_code, to_code) = (from.asset_code(), to.asset_code());
+        if from_code == to_code {
+            return None;
+        }
+        if from_code.trim().eq_ignore_ascii_case(to_code.trim()) {
+            return Some(format!(
+                "asset codes {:?} and {:?} only differ in case or whitespace",
+                from_code, to_code
+            ));
+        }
+        let rates = match self.store.get_exchange_rates(&[from_code, to_code]) {
+            Ok(rates) => rates,
+            Err(_) => {
+                return Some(format!(
+                    "there is no exchange rate for asset {} or {}",
+                    from_code, to_code
+                ))
+            }
+        };
+        rates
+            .iter()
+            .zip(&[from_code, to_code])
+            .find(|(rate, _)| !(rate.is_finite() && **rate > 0.0))
+            .map(|(rate, code)| format!("the exchange rate of asset {} is {}", code, rate))
+    }
+}
+
+impl<S, O, A> OutgoingService<A> for AssetGuardService<S, O>
+where
+    S: AddressStore + ExchangeRateStore + Send + Sync + 'static,
+    O: OutgoingService<A> + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    type Future<'a> = Either<O::Future<'a>, Ready<IlpResult>>;
+
+    /// On send request:
+    /// 1. If the packet's amount is 0, or the assets of `request.from` and `request.to` can
+    ///    be converted, forward the request
+    /// 1. Otherwise report the misconfiguration and reject the request with T00
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        if request.prepare.amount() > 0 {
+            if let Some(problem) = self.check(&request.from, &request.to) {
+                self.alert(&request.from, &request.to, &problem);
+                return Either::Right(ready(Err(RejectBuilder {
+                    code: ErrorCode::T00_INTERNAL_ERROR,
+                    message: format!(
+                        "Cannot convert from asset: {} to: {}, {}",
+                        request.from.asset_code(),
+                        request.to.asset_code(),
+                        problem
+                    )
+                    .as_bytes(),
+                    triggered_by: Some(&self.store.get_ilp_address()),
+                    data: &[],
+                }
+                .build())));
+            }
+        }
+        Either::Left(self.next.send_request(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use interledger_errors::{AddressStoreError, ExchangeRateStoreError};
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    #[derive(Debug, Clone)]
+    struct TestAccount(&'static str);
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            self.0
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore {
+        rates: HashMap<String, f64>,
+    }
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    impl ExchangeRateStore for TestStore {
+        fn get_exchange_rates(
+            &self,
+            asset_codes: &[&str],
+        ) -> Result<Vec<f64>, ExchangeRateStoreError> {
+            asset_codes
+                .iter()
+                .map(|code| {
+                    self.rates.get(*code).cloned().ok_or_else(|| {
+                        ExchangeRateStoreError::PairNotFound {
+                            from: asset_codes[0].to_owned(),
+                            to: asset_codes[1].to_owned(),
+                        }
+                    })
+                })
+                .collect()
+        }
+
+        fn set_exchange_rates(
+            &self,
+            _: HashMap<String, f64>,
+        ) -> Result<(), ExchangeRateStoreError> {
+            unimplemented!()
+        }
+
+        fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+            unimplemented!()
+        }
+    }
+
+    async fn send(from: &'static str, to: &'static str, amount: u64) -> IlpResult {
+        let mut rates = HashMap::new();
+        rates.insert("USD".to_owned(), 1.0);
+        rates.insert("EUR".to_owned(), 1.1);
+        rates.insert("XYZ".to_owned(), 0.0);
+        let mut service = AssetGuardService::new(
+            TestStore { rates },
+            outgoing_service_fn(|_: OutgoingRequest<TestAccount>| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"test data",
+                }
+                .build())
+            }),
+        );
+        service
+            .send_request(OutgoingRequest {
+                from: TestAccount(from),
+                to: TestAccount(to),
+                original_amount: amount,
+                prepare: PrepareBuilder {
+                    destination: EXAMPLE_ADDRESS.clone(),
+                    amount,
+                    expires_at: SystemTime::now() + Duration::from_secs(30),
+                    execution_condition: &[0; 32],
+                    data: b"test data",
+                }
+                .build(),
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn forwards_convertible_assets() {
+        assert!(send("USD", "USD", 100).await.is_ok());
+        assert!(send("USD", "EUR", 100).await.is_ok());
+        // Unknown assets do not matter if nothing is converted
+        assert!(send("ABC", "DEF", 0).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_asset_codes_differing_in_case() {
+        let reject = send("USD", "usd ", 100).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T00_INTERNAL_ERROR);
+        assert_eq!(
+            reject.message(),
+            &b"Cannot convert from asset: USD to: usd , asset codes \"USD\" and \"usd \" only differ in case or whitespace"[..]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_or_invalid_rates() {
+        let reject = send("USD", "ABC", 100).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T00_INTERNAL_ERROR);
+        assert_eq!(
+            reject.message(),
+            &b"Cannot convert from asset: USD to: ABC, there is no exchange rate for asset USD or ABC"[..]
+        );
+
+        let reject = send("XYZ", "USD", 100).await.unwrap_err();
+        assert_eq!(
+            reject.message(),
+            &b"Cannot convert from asset: XYZ to: USD, the exchange rate of asset XYZ is 0"[..]
+        );
+    }
+}
@@ -1,19 +1,196 @@
+use crate::packet_journal::{JournaledPacket, PacketJournalStore};
 use async_trait::async_trait;
-use futures::TryFutureExt;
-use interledger_errors::BalanceStoreError;
+use futures::{FutureExt, TryFutureExt};
+use interledger_errors::{AccountStoreError, BalanceStoreError};
 use interledger_packet::{ErrorCode, RejectBuilder};
-use interledger_service::*;
+use interledger_service::{clock::SharedClock, *};
 use interledger_settlement::core::{
-    types::{SettlementAccount, SettlementStore},
-    SettlementClient,
+    types::{
+        OutgoingSettlement, OutgoingSettlementStatus, OutgoingSettlementStore, SettlementAccount,
+        SettlementStore,
+    },
+    SettlementClient, SettlementEvents,
 };
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 use std::marker::PhantomData;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
-use std::{fmt, time::Duration, time::Instant};
+use std::{fmt, time::Duration, time::Instant, time::UNIX_EPOCH};
 use tokio::sync::mpsc::error::TrySendError;
 use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
+/// Time after which an outgoing settlement is retried by the task started with
+/// [`start_settlement_retries`]. It is longer than the retries of the first attempt
+/// take, so the task only retries settlements whose first attempt failed or was
+/// interrupted by a restart.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(300);
+/// The longest time between two attempts of an outgoing settlement
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+/// Timeout of the requests of the retried settlements
+const RETRY_HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Identifies the packet which caused a balance change, using the execution condition of its Prepare
+pub type PacketId = [u8; 32];
+
+/// The operation which changed an account's balance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceChangeReason {
+    /// A Prepare packet from the account was forwarded
+    Prepare,
+    /// A Prepare packet to the account was fulfilled
+    Fulfill,
+    /// A Prepare packet from the account was rejected
+    Reject,
+    /// The amount owed to the account was sent to the settlement engine
+    Settlement,
+    /// An outgoing settlement failed and its amount was credited back
+    SettlementRefund,
+    /// A settlement from the account was received
+    IncomingSettlement,
+}
+
+impl BalanceChangeReason {
+    /// The name of the reason, as it is serialized and stored
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BalanceChangeReason::Prepare => "prepare",
+            BalanceChangeReason::Fulfill => "fulfill",
+            BalanceChangeReason::Reject => "reject",
+            BalanceChangeReason::Settlement => "settlement",
+            BalanceChangeReason::SettlementRefund => "settlement_refund",
+            BalanceChangeReason::IncomingSettlement => "incoming_settlement",
+        }
+    }
+}
+
+impl FromStr for BalanceChangeReason {
+    type Err = ();
+
+    fn from_str(string: &str) -> Result<Self, ()> {
+        match string {
+            "prepare" => Ok(BalanceChangeReason::Prepare),
+            "fulfill" => Ok(BalanceChangeReason::Fulfill),
+            "reject" => Ok(BalanceChangeReason::Reject),
+            "settlement" => Ok(BalanceChangeReason::Settlement),
+            "settlement_refund" => Ok(BalanceChangeReason::SettlementRefund),
+            "incoming_settlement" => Ok(BalanceChangeReason::IncomingSettlement),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for BalanceChangeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An entry of an account's balance change log. The log is append-only, so the
+/// balance of an account can be reconstructed by replaying its entries in order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BalanceChange {
+    /// Position of the entry in the account's log, starting at 0
+    pub sequence: u64,
+    /// Time of the change, in milliseconds since the UNIX epoch
+    pub timestamp: u64,
+    /// The account whose balance changed
+    pub account_id: Uuid,
+    /// Hex-encoded id of the packet which caused the change, if it was caused by a packet
+    pub packet_id: Option<String>,
+    /// The change of the balance, from the account holder's perspective
+    pub delta: i64,
+    /// The balance (including the prepaid amount) after the change
+    pub balance: i64,
+    /// The operation which changed the balance
+    pub reason: BalanceChangeReason,
+}
+
+/// The cumulative changes of an account's balance, summed per reason
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceTotals {
+    pub prepare: i64,
+    pub fulfill: i64,
+    pub reject: i64,
+    pub settlement: i64,
+    pub settlement_refund: i64,
+    pub incoming_settlement: i64,
+}
+
+impl BalanceTotals {
+    /// Adds a balance change to the total of its reason
+    pub fn add(&mut self, reason: BalanceChangeReason, delta: i64) {
+        let total = match reason {
+            BalanceChangeReason::Prepare => &mut self.prepare,
+            BalanceChangeReason::Fulfill => &mut self.fulfill,
+            BalanceChangeReason::Reject => &mut self.reject,
+            BalanceChangeReason::Settlement => &mut self.settlement,
+            BalanceChangeReason::SettlementRefund => &mut self.settlement_refund,
+            BalanceChangeReason::IncomingSettlement => &mut self.incoming_settlement,
+        };
+        *total = total.saturating_add(delta);
+    }
+
+    /// Net amount cleared with packets, from the account holder's perspective
+    pub fn cleared(&self) -> i64 {
+        self.prepare
+            .saturating_add(self.reject)
+            .saturating_add(self.fulfill)
+    }
+
+    /// Net amount sent to the settlement engine, minus the refunded settlements
+    pub fn settled_outgoing(&self) -> i64 {
+        self.settlement
+            .saturating_add(self.settlement_refund)
+            .saturating_neg()
+    }
+}
+
+/// Compares the amounts cleared with an account to the amounts settled with it.
+///
+/// Every cleared amount should eventually be settled, so the drift (the cleared
+/// amount which was not settled) stays within the account's settlement thresholds.
+/// A growing drift points at settlements which are not sent or incoming settlements
+/// which the engine did not credit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    pub account_id: Uuid,
+    /// Net amount cleared with packets, from the account holder's perspective
+    pub cleared: i64,
+    /// Net amount sent to the settlement engine for settlements to the account
+    pub settled_outgoing: i64,
+    /// Amount credited for settlements received from the account
+    pub settled_incoming: i64,
+    /// Cleared amount which was not settled, from the account holder's perspective
+    pub drift: i64,
+    /// The largest absolute drift which is not flagged
+    pub tolerance: u64,
+    /// Whether the absolute drift is larger than the tolerance
+    pub exceeds_tolerance: bool,
+}
+
+impl ReconciliationReport {
+    pub fn new(account_id: Uuid, totals: &BalanceTotals, tolerance: u64) -> Self {
+        let cleared = totals.cleared();
+        let settled_outgoing = totals.settled_outgoing();
+        let settled_incoming = totals.incoming_settlement;
+        let drift = cleared
+            .saturating_sub(settled_outgoing)
+            .saturating_add(settled_incoming);
+        ReconciliationReport {
+            account_id,
+            cleared,
+            settled_outgoing,
+            settled_incoming,
+            drift,
+            tolerance,
+            exceeds_tolerance: i128::from(drift).abs() > i128::from(tolerance),
+        }
+    }
+}
+
 // TODO: Remove AccountStore dependency, use `AccountId: ToString` as associated type
 /// Trait responsible for managing an account's balance in the store
 /// as ILP Packets get routed
@@ -27,32 +204,66 @@ pub trait BalanceStore {
         &self,
         from_account_id: Uuid,
         incoming_amount: u64,
+        packet_id: Option<PacketId>,
     ) -> Result<(), BalanceStoreError>;
 
     /// Increases the receiving account's balance, and returns the updated balance
-    /// along with the amount which should be settled
+    /// along with the amount which should be settled. The caller is responsible for
+    /// sending that amount to the settlement engine.
     async fn update_balances_for_fulfill(
         &self,
         to_account_id: Uuid,
         outgoing_amount: u64,
+        packet_id: Option<PacketId>,
+    ) -> Result<(i64, u64), BalanceStoreError>;
+
+    /// Increases the receiving account's balance like `update_balances_for_fulfill`, and adds
+    /// the amount which should be settled to the account's queued outgoing settlement in the
+    /// same transaction, so that it is sent (or refunded) by the settlement retries even if this
+    /// program stops before sending it.
+    ///
+    /// Returns (balance, amount_to_settle).
+    async fn update_balances_for_fulfill_and_queue_settlement(
+        &self,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+        packet_id: Option<PacketId>,
     ) -> Result<(i64, u64), BalanceStoreError>;
 
     async fn update_balances_for_reject(
         &self,
         from_account_id: Uuid,
         incoming_amount: u64,
+        packet_id: Option<PacketId>,
     ) -> Result<(), BalanceStoreError>;
 
-    /// Removes any positive amount to settle over `settle_to` from `balance`. Similarly to other
-    /// balance updates once this call succeeds the amount needed to be settled is only in the
-    /// interledger node so crashing while the HTTP request to settlement-engine hasn't gone
-    /// through or this balance hasn't been refunded will lead to loss of the "amount to settle".
+    /// Removes any positive amount to settle over `settle_to` from `balance`, and adds it to the
+    /// account's queued outgoing settlement in the same transaction, like
+    /// `update_balances_for_fulfill_and_queue_settlement`.
     ///
     /// Returns (balance, amount_to_settle).
     async fn update_balances_for_delayed_settlement(
         &self,
         to_account_id: Uuid,
     ) -> Result<(i64, u64), BalanceStoreError>;
+
+    /// Returns at most `limit` entries of the account's balance change log, oldest
+    /// first, starting with the entry at position `from`.
+    ///
+    /// Every balance update (including settlements) appends to the log in the same
+    /// transaction as the update itself.
+    async fn get_balance_changes(
+        &self,
+        account_id: Uuid,
+        from: u64,
+        limit: usize,
+    ) -> Result<Vec<BalanceChange>, BalanceStoreError>;
+
+    /// Returns the sums of all changes in the account's balance change log, per reason.
+    async fn get_balance_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<BalanceTotals, BalanceStoreError>;
 }
 
 /// # Balance Service
@@ -68,6 +279,28 @@ pub struct BalanceService<S, O, A> {
     policy: Policy,
     account_type: PhantomData<A>,
     channel_last_fail: Arc<Mutex<Instant>>,
+    admitted_prepares: bool,
+    clock: SharedClock,
+    journal: Option<Arc<dyn PacketJournalStore + Send + Sync>>,
+}
+
+/// The entry of a packet in the packet journal, which is removed once the balances were
+/// updated for its response
+#[derive(Clone)]
+struct JournalEntry {
+    journal: Arc<dyn PacketJournalStore + Send + Sync>,
+    id: Uuid,
+}
+
+impl JournalEntry {
+    async fn remove(self) {
+        if let Err(err) = self.journal.remove_journaled_packet(self.id).await {
+            error!(
+                "Error removing packet {} from the journal, its balance changes will be applied again once it is reconciled: {}",
+                self.id, err
+            );
+        }
+    }
 }
 
 impl<S, O, A> BalanceService<S, O, A>
@@ -91,119 +324,238 @@ where
             },
             account_type: PhantomData,
             channel_last_fail: Arc::new(Mutex::new(Instant::now())),
+            admitted_prepares: false,
+            clock: SharedClock::default(),
+            journal: None,
         }
     }
+
+    /// Leave the balance changes of the Prepare packets and their Rejects to the
+    /// [`AdmissionService`](./struct.AdmissionService.html), which already applied them
+    /// when it admitted the packets. Only the Fulfills are applied, and the Rejects too if
+    /// the packets are [journaled](#method.journal).
+    pub fn admitted_prepares(mut self) -> Self {
+        self.admitted_prepares = true;
+        self
+    }
+
+    /// Schedules the retries of the settlements with the clock instead of the system's clock
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Journal the packets while they are in flight, so that the balance changes of their
+    /// responses can be applied with
+    /// [`reconcile_journaled_packets`](./fn.reconcile_journaled_packets.html) if the node
+    /// crashes before it applied them. This costs two writes to the journal per packet,
+    /// one of which is made before the Fulfill is passed back.
+    ///
+    /// With [`admitted_prepares`](#method.admitted_prepares), the senders of the rejected
+    /// packets are refunded here too, before their entries are removed from the journal, so
+    /// the `AdmissionService` must be told with
+    /// [`leave_balance_refunds`](./struct.AdmissionService.html#method.leave_balance_refunds)
+    /// not to refund them again.
+    pub fn journal<J>(mut self, journal: J) -> Self
+    where
+        J: PacketJournalStore + Send + Sync + 'static,
+    {
+        self.journal = Some(Arc::new(journal));
+        self
+    }
+
+    /// Publishes the settlements triggered by the packets, and their results
+    pub fn settlement_events(mut self, settlement_events: SettlementEvents) -> Self {
+        self.settlement_client = self.settlement_client.events(settlement_events);
+        self
+    }
 }
 
-#[async_trait]
 impl<S, O, A> OutgoingService<A> for BalanceService<S, O, A>
 where
-    S: AddressStore + BalanceStore + SettlementStore<Account = A> + Clone + Send + Sync + 'static,
+    S: AddressStore
+        + BalanceStore
+        + SettlementStore<Account = A>
+        + OutgoingSettlementStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     O: OutgoingService<A> + Send + Clone + 'static,
     A: SettlementAccount + Send + Sync + 'static,
 {
+    type Future<'a> = BoxedIlpFuture<'a>;
+
     /// On send message:
     /// 1. Calls `store.update_balances_for_prepare` with the prepare.
     /// If it fails, it replies with a reject
     /// 1. Tries to forward the request:
-    ///     - If it returns a fullfil, calls `store.update_balances_for_fulfill` and replies with the fulfill
+    ///     - If it returns a fullfil, calls `store.update_balances_for_fulfill_and_queue_settlement` and replies with the fulfill
     ///       INDEPENDENTLY of if the call suceeds or fails. This makes a `sendMoney` call if the fulfill puts the account's balance over the `settle_threshold`
     ///     - if it returns an reject calls `store.update_balances_for_reject` and replies with the fulfill
     ///       INDEPENDENTLY of if the call suceeds or fails
-    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
-        // Don't bother touching the store for zero-amount packets.
-        // Note that it is possible for the original_amount to be >0 while the
-        // prepare.amount is 0, because the original amount could be rounded down
-        // to 0 when exchange rate and scale change are applied.
-        if request.prepare.amount() == 0 && request.original_amount == 0 {
-            // wonder if timeout should still be set here?
-            return self.next.send_request(request).await;
-        }
-
-        let mut next = self.next.clone();
-        let store = self.store.clone();
-        let from = request.from.clone();
-        let from_clone = from.clone();
-        let from_id = from.id();
-        let to = request.to.clone();
-        let to_clone = to.clone();
-        let incoming_amount = request.original_amount;
-        let outgoing_amount = request.prepare.amount();
-        let ilp_address = self.store.get_ilp_address();
-        let settlement_client = self.settlement_client.clone();
-
-        // Update the balance _before_ sending the settlement so that we don't accidentally send
-        // multiple settlements for the same balance. While there will be a small moment of time (the delta
-        // between this balance change and the moment that the settlement-engine accepts the request for
-        // settlement payment) where the actual balance in Redis is less than it should be, this is tolerable
-        // because this amount of time will always be small. This is because the design of the settlement
-        // engine API is asynchronous, meaning when a request is made to the settlement engine, it will
-        // accept the request and return (milliseconds) with a guarantee that the settlement payment will
-        //  _eventually_ be completed. Because of this settlement_engine guarantee, the Connector can
-        // operate as-if the settlement engine has completed. Finally, if the request to the settlement-engine
-        // fails, this amount will be re-added back to balance.
-        self.store
-            .update_balances_for_prepare(from_id, incoming_amount)
-            .map_err(move |_| {
-                debug!("Rejecting packet because it would exceed a balance limit");
-                RejectBuilder {
-                    code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
-                    message: &[],
-                    triggered_by: Some(&ilp_address),
-                    data: &[],
-                }
-                .build()
-            })
-            .await?;
-
-        match next.send_request(request).await {
-            Ok(fulfill) => {
-                if outgoing_amount > 0 {
-                    // We will spawn a task to update the balances in the database
-                    // so that we DO NOT wait for the database before sending the
-                    // Fulfill packet back to our peer. Due to how the flow of ILP
-                    // packets work, once we get the Fulfill back from the next node
-                    // we need to propagate it backwards ASAP. If we do not give the
-                    // previous node the fulfillment in time, they won't pay us back
-                    // for the packet we forwarded. Note this means that we will
-                    // relay the fulfillment _even if saving to the DB fails._
-                    settle_or_rollback_later(
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            // Don't bother touching the store for zero-amount packets.
+            // Note that it is possible for the original_amount to be >0 while the
+            // prepare.amount is 0, because the original amount could be rounded down
+            // to 0 when exchange rate and scale change are applied.
+            if request.prepare.amount() == 0 && request.original_amount == 0 {
+                // wonder if timeout should still be set here?
+                return self.next.send_request(request).await;
+            }
+
+            let mut next = self.next.clone();
+            let store = self.store.clone();
+            let from = request.from.clone();
+            let from_clone = from.clone();
+            let from_id = from.id();
+            let to = request.to.clone();
+            let to_clone = to.clone();
+            let incoming_amount = request.original_amount;
+            let outgoing_amount = request.prepare.amount();
+            let packet_id = PacketId::try_from(request.prepare.execution_condition()).ok();
+            let ilp_address = self.store.get_ilp_address();
+            let settlement_client = self.settlement_client.clone();
+
+            // Update the balance _before_ sending the settlement so that we don't accidentally send
+            // multiple settlements for the same balance. While there will be a small moment of time (the delta
+            // between this balance change and the moment that the settlement-engine accepts the request for
+            // settlement payment) where the actual balance in Redis is less than it should be, this is tolerable
+            // because this amount of time will always be small. This is because the design of the settlement
+            // engine API is asynchronous, meaning when a request is made to the settlement engine, it will
+            // accept the request and return (milliseconds) with a guarantee that the settlement payment will
+            //  _eventually_ be completed. Because of this settlement_engine guarantee, the Connector can
+            // operate as-if the settlement engine has completed. Finally, if the request to the settlement-engine
+            // fails, this amount will be re-added back to balance.
+            if !self.admitted_prepares {
+                self.store
+                    .update_balances_for_prepare(from_id, incoming_amount, packet_id)
+                    .map_err(move |_| {
+                        debug!(
+                            from.id = %from_id,
+                            reject.code = %ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                            "Rejecting packet because it would exceed a balance limit"
+                        );
+                        RejectBuilder {
+                            code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                            message: &[],
+                            triggered_by: Some(&ilp_address),
+                            data: &[],
+                        }
+                        .build()
+                    })
+                    .await?;
+            }
+
+            let journal_entry = match self.journal {
+                Some(ref journal) => {
+                    let packet = JournaledPacket {
+                        id: Uuid::new_v4(),
+                        from_id,
+                        to_id: to.id(),
                         incoming_amount,
                         outgoing_amount,
-                        store,
-                        from_id,
-                        to,
-                        settlement_client,
-                        self.policy.clone(),
-                        self.channel_last_fail.clone(),
-                    );
+                        packet_id,
+                        expires_at: request
+                            .prepare
+                            .expires_at()
+                            .duration_since(UNIX_EPOCH)
+                            .map(|expires_at| expires_at.as_millis() as u64)
+                            .unwrap_or_default(),
+                        fulfilled: false,
+                    };
+                    let id = packet.id;
+                    match journal.journal_packet(packet).await {
+                        Ok(()) => Some(JournalEntry {
+                            journal: journal.clone(),
+                            id,
+                        }),
+                        Err(err) => {
+                            error!("Error journaling packet from account {}: {}", from_id, err);
+                            None
+                        }
+                    }
                 }
-
-                Ok(fulfill)
-            }
-            Err(reject) => {
-                // Similar to the logic for handling the Fulfill packet above, we
-                // spawn a task to update the balance for the Reject in parallel
-                // rather than waiting for the database to update before relaying
-                // the packet back. In this case, the only substantive difference
-                // would come from if the DB operation fails or takes too long.
-                // The packet is already rejected so it's more useful for the sender
-                // to get the error message from the original Reject packet rather
-                // than a less specific one saying that this node had an "internal
-                // error" caused by a database issue.
-                tokio::spawn({
-                    let store_clone = self.store.clone();
-                    async move {
-                        store_clone.update_balances_for_reject(
-                            from_clone.id(),
+                None => None,
+            };
+
+            match next.send_request(request).await {
+                Ok(fulfill) => {
+                    // The receiver must be credited even if the node crashes before it applied
+                    // the Fulfill, since the Fulfill is passed back to the sender
+                    if let Some(ref entry) = journal_entry {
+                        if let Err(err) = entry
+                            .journal
+                            .mark_journaled_packet_fulfilled(entry.id)
+                            .await
+                        {
+                            error!(
+                                "Error marking journaled packet {} as fulfilled: {}",
+                                entry.id, err
+                            );
+                        }
+                    }
+                    if outgoing_amount > 0 {
+                        // We will spawn a task to update the balances in the database
+                        // so that we DO NOT wait for the database before sending the
+                        // Fulfill packet back to our peer. Due to how the flow of ILP
+                        // packets work, once we get the Fulfill back from the next node
+                        // we need to propagate it backwards ASAP. If we do not give the
+                        // previous node the fulfillment in time, they won't pay us back
+                        // for the packet we forwarded. Note this means that we will
+                        // relay the fulfillment _even if saving to the DB fails._
+                        settle_or_rollback_later(
                             incoming_amount,
-                        ).map_err(move |_| error!("Error rolling back balance change for accounts: {} and {}. Incoming amount was: {}, outgoing amount was: {}", from_clone.id(), to_clone.id(), incoming_amount, outgoing_amount)).await
+                            outgoing_amount,
+                            packet_id,
+                            store,
+                            from_id,
+                            to,
+                            settlement_client,
+                            self.policy.clone(),
+                            self.channel_last_fail.clone(),
+                            self.clock.clone(),
+                            journal_entry,
+                        );
+                    } else if let Some(entry) = journal_entry {
+                        runtime::spawn(entry.remove());
                     }
-                });
 
-                Err(reject)
+                    Ok(fulfill)
+                }
+                // The AdmissionService refunds the rejected packets, unless they are journaled:
+                // their entries are only removed once their senders were refunded below, so that
+                // they are refunded by the reconciliation if the node stops before that
+                Err(reject) if self.admitted_prepares && self.journal.is_none() => Err(reject),
+                Err(reject) => {
+                    // Similar to the logic for handling the Fulfill packet above, we
+                    // spawn a task to update the balance for the Reject in parallel
+                    // rather than waiting for the database to update before relaying
+                    // the packet back. In this case, the only substantive difference
+                    // would come from if the DB operation fails or takes too long.
+                    // The packet is already rejected so it's more useful for the sender
+                    // to get the error message from the original Reject packet rather
+                    // than a less specific one saying that this node had an "internal
+                    // error" caused by a database issue.
+                    runtime::spawn({
+                        let store_clone = self.store.clone();
+                        async move {
+                            let result = store_clone.update_balances_for_reject(
+                                from_clone.id(),
+                                incoming_amount,
+                                packet_id,
+                            ).map_err(move |_| error!("Error rolling back balance change for accounts: {} and {}. Incoming amount was: {}, outgoing amount was: {}", from_clone.id(), to_clone.id(), incoming_amount, outgoing_amount)).await;
+                            if let (Ok(()), Some(entry)) = (result, journal_entry) {
+                                entry.remove().await;
+                            }
+                        }
+                    });
+
+                    Err(reject)
+                }
             }
-        }
+        })
     }
 }
 
@@ -212,55 +564,79 @@ where
 fn settle_or_rollback_later<Acct, Store>(
     incoming_amount: u64,
     outgoing_amount: u64,
+    packet_id: Option<PacketId>,
     store: Store,
     from_id: Uuid,
     to: Acct,
     settlement_client: SettlementClient,
     policy: Policy,
     channel_last_fail: Arc<Mutex<Instant>>,
+    clock: SharedClock,
+    journal_entry: Option<JournalEntry>,
 ) where
     Acct: SettlementAccount + Send + Sync + 'static,
-    Store: BalanceStore + SettlementStore<Account = Acct> + Send + Sync + 'static,
+    Store: BalanceStore
+        + SettlementStore<Account = Acct>
+        + OutgoingSettlementStore
+        + Send
+        + Sync
+        + 'static,
 {
-    tokio::spawn(settle_or_rollback_now(
-        incoming_amount,
-        outgoing_amount,
-        store,
-        from_id,
-        to,
-        settlement_client,
-        policy,
-        channel_last_fail,
-    ));
+    runtime::spawn(
+        settle_or_rollback_now(
+            incoming_amount,
+            outgoing_amount,
+            packet_id,
+            store,
+            from_id,
+            to,
+            settlement_client,
+            policy,
+            channel_last_fail,
+            clock,
+            journal_entry,
+        )
+        .map(|_| ()),
+    );
 }
 
 #[allow(clippy::too_many_arguments)]
 async fn settle_or_rollback_now<Acct, Store>(
     incoming_amount: u64,
     outgoing_amount: u64,
+    packet_id: Option<PacketId>,
     store: Store,
     from_id: Uuid,
     to: Acct,
     settlement_client: SettlementClient,
     mut policy: Policy,
     channel_last_fail: Arc<Mutex<Instant>>,
+    clock: SharedClock,
+    journal_entry: Option<JournalEntry>,
 ) -> Result<(), ()>
 where
     Acct: SettlementAccount + Send + Sync + 'static,
-    Store: BalanceStore + SettlementStore<Account = Acct> + Send + Sync + 'static,
+    Store: BalanceStore
+        + SettlementStore<Account = Acct>
+        + OutgoingSettlementStore
+        + Send
+        + Sync
+        + 'static,
 {
+    let to_id = to.id();
     let (balance, amount_to_settle) = store
-        .update_balances_for_fulfill(to.id(), outgoing_amount)
-        .map_err(|err| error!("Error applying balance changes for fulfill from account: {} to account: {}. Incoming amount was: {}, outgoing amount was: {}. Error: {}", from_id, to.id(), incoming_amount, outgoing_amount, err))
+        .update_balances_for_fulfill_and_queue_settlement(to_id, outgoing_amount, packet_id)
+        .map_err(|err| error!("Error applying balance changes for fulfill from account: {} to account: {}. Incoming amount was: {}, outgoing amount was: {}. Error: {}", from_id, to_id, incoming_amount, outgoing_amount, err))
         .await?;
+    if let Some(entry) = journal_entry {
+        entry.remove().await;
+    }
 
     // this message is really important, if you want to recover the balance after a crash; all of
     // the "amount that need to be settled" must be summed and added to the account's "balance".
     debug!(
         "Account {} balance after fulfill: {}. Amount that needs to be settled: {}",
-        to.id(),
-        balance,
-        amount_to_settle
+        to_id, balance, amount_to_settle
     );
 
     if amount_to_settle == 0 {
@@ -276,79 +652,319 @@ where
             // One way to avoid this would be to record a last_settled_at timestamp, making sure it
             // is always older than our settlement period, and rescheduling a timeout whenever it
             // would had been too early to settle.
-            policy.settle_later(to.id(), channel_last_fail);
+            policy.settle_later(to_id, to.settle_every(), channel_last_fail);
         }
         return Ok(());
     }
 
     // cancel a pending settlement always before trying it
-    policy.clear_later(to.id(), channel_last_fail);
+    policy.clear_later(to_id, channel_last_fail);
 
-    settle_or_rollback(store, to, amount_to_settle, settlement_client).await
+    settle(store, to_id, to, amount_to_settle, settlement_client, clock).await
 }
 
-async fn settle_or_rollback<Store, Acct>(
+/// Sends the amount which the store queued for the account when it was removed from its
+/// balance. The store saved it along with the balance change, so if it can't be sent now
+/// it is sent (or refunded) by the settlement retries.
+async fn settle<Store, Acct>(
     store: Store,
+    account_id: Uuid,
     to: Acct,
     amount: u64,
     client: SettlementClient,
+    clock: SharedClock,
 ) -> Result<(), ()>
 where
-    Store: SettlementStore<Account = Acct> + 'static,
+    Store: OutgoingSettlementStore + 'static,
     Acct: SettlementAccount + 'static,
 {
     if amount == 0 {
-        debug!("Nothing to settle for account {}", to.id());
+        debug!("Nothing to settle for account {}", account_id);
         return Ok(());
     }
 
-    if let Some(engine_details) = to.settlement_engine_details() {
-        let engine_url = engine_details.url;
-        // Note that if this program crashes after changing the balance (in the PROCESS_FULFILL
-        // script) and the send_settlement fails but the program isn't alive to hear that, the
-        // balance will be incorrect. No other instance will know that it was trying to send an
-        // outgoing settlement. We could make this more robust by saving something to the DB about
-        // the outgoing settlement when we change the balance but then we would also need to
-        // prevent a situation where every connector instance is polling the settlement engine for
-        // the status of each outgoing settlement and putting unnecessary load on the settlement
-        // engine.
+    if to.settlement_engine_details().is_some() {
+        send_queued_settlements(&store, &client, &clock, account_id, &to).await;
+    } else {
+        debug!("Settlement for account {} for {} failed as the account has no settlement engine details, it will be refunded",
+            account_id, amount);
+    }
 
+    Ok(())
+}
+
+/// Sends the amounts which were queued for the account while its settlement was in flight,
+/// one at a time, until nothing is queued or the engine did not answer a settlement
+async fn send_queued_settlements<Store, Acct>(
+    store: &Store,
+    client: &SettlementClient,
+    clock: &SharedClock,
+    account_id: Uuid,
+    account: &Acct,
+) where
+    Store: OutgoingSettlementStore,
+    Acct: SettlementAccount,
+{
+    let engine_url = match account.settlement_engine_details() {
+        Some(engine_details) => engine_details.url,
+        None => return,
+    };
+    loop {
+        let next_attempt_at = clock.unix_millis() + FIRST_RETRY_DELAY.as_millis() as u64;
+        // Returns None while the previous settlement is retried
+        let settlement = match store
+            .dequeue_outgoing_settlement(account_id, new_idempotency_key(), next_attempt_at)
+            .await
+        {
+            Ok(Some(settlement)) => settlement,
+            Ok(None) => return,
+            Err(e) => {
+                warn!(
+                    "Failed to dequeue settlement for account {}: {}",
+                    account_id, e
+                );
+                return;
+            }
+        };
+        debug!(
+            "Sending queued settlement {} for account {} for {}",
+            settlement.idempotency_key, account_id, settlement.amount
+        );
         let result = client
-            .send_settlement(to.id(), engine_url, amount, to.asset_scale())
+            .send_settlement_with_key(
+                account_id,
+                engine_url.clone(),
+                settlement.amount,
+                account.asset_scale(),
+                &settlement.idempotency_key,
+            )
             .await;
+        if handle_settlement_result(store, settlement, result, clock)
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
 
-        if let Err(client_error) = result {
+/// Removes the saved outgoing settlement once the engine accepted it and refunds it once the
+/// engine rejected it. Any other failure leaves it unknown whether the engine received the
+/// settlement, so it is retried later with the same idempotency key.
+async fn handle_settlement_result<Store>(
+    store: &Store,
+    mut settlement: OutgoingSettlement,
+    result: Result<reqwest::Response, reqwest::Error>,
+    clock: &SharedClock,
+) -> Result<(), ()>
+where
+    Store: OutgoingSettlementStore,
+{
+    let account_id = settlement.account_id;
+    let amount = settlement.amount;
+    match result {
+        Ok(_) => {
+            info!(
+                "Settlement for account {} for {} succeeded",
+                account_id, amount
+            );
+            store
+                .remove_outgoing_settlement(&settlement.idempotency_key)
+                .map_err(|e| {
+                    error!(
+                        "Error removing settlement {} after it succeeded: {}",
+                        settlement.idempotency_key, e
+                    )
+                })
+                .await
+        }
+        // The engine answered, so the settlement was definitely not executed
+        Err(client_error)
+            if client_error
+                .status()
+                .map_or(false, |status| status.is_client_error()) =>
+        {
             warn!(
-                "Settlement for account {} for {} failed: {}",
-                to.id(),
-                amount,
-                client_error
+                "Settlement for account {} for {} was rejected: {}",
+                account_id, amount, client_error
             );
-
             store
-                .refund_settlement(to.id(), amount)
+                .refund_outgoing_settlement(&settlement.idempotency_key)
                 .map_err(|e| {
                     error!(
                         "Refunding account {} after failed settlement failed, amount: {}: {}",
-                        to.id(),
-                        amount,
-                        e
+                        account_id, amount, e
                     )
                 })
-                .await?;
-        } else {
-            info!(
-                "Settlement for account {} for {} succeeded",
-                to.id(),
-                amount
+                .await
+        }
+        Err(client_error) => {
+            let delay = retry_delay(settlement.attempts);
+            settlement.attempts += 1;
+            settlement.status = OutgoingSettlementStatus::Unknown;
+            settlement.next_attempt_at = clock.unix_millis() + delay.as_millis() as u64;
+            warn!(
+                "Settlement for account {} for {} failed: {}. Retrying in {:?}",
+                account_id, amount, client_error, delay
             );
+            store
+                .save_outgoing_settlement(settlement)
+                .map_err(|e| {
+                    error!(
+                        "Error saving failed settlement for account {} for {}: {}",
+                        account_id, amount, e
+                    )
+                })
+                .await
         }
-    } else {
-        debug!("Settlement for account {} for {} failed as the account has no settlement engine details",
-            to.id(), amount);
     }
+}
 
-    Ok(())
+/// Returns the delay before the next attempt of a settlement, which doubles with every attempt
+fn retry_delay(attempts: u32) -> Duration {
+    FIRST_RETRY_DELAY
+        .checked_mul(1 << attempts.min(16))
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+}
+
+fn new_idempotency_key() -> String {
+    Uuid::new_v4().to_hyphenated().to_string()
+}
+
+/// Start a background task which retries the outgoing settlements whose sending failed without
+/// an answer from the engine, or was interrupted by a restart. They are retried with their
+/// original idempotency key, so the engine executes them only once, and every `interval` the
+/// task checks for settlements whose next attempt is due. The amounts which were queued for an
+/// account while its settlement was in flight are sent after that settlement was answered.
+/// The retries and their results are published to the `settlement_events`, if any.
+pub fn start_settlement_retries<Store, Acct>(
+    interval: Duration,
+    store: Store,
+    clock: SharedClock,
+    settlement_events: Option<SettlementEvents>,
+) -> runtime::TaskHandle
+where
+    Store: SettlementStore<Account = Acct>
+        + OutgoingSettlementStore
+        + AccountStore<Account = Acct>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    Acct: SettlementAccount + Send + Sync + 'static,
+{
+    // The task retries the settlements itself, so the client must not retry them too
+    let mut client = SettlementClient::new(RETRY_HTTP_TIMEOUT, 0);
+    if let Some(settlement_events) = settlement_events {
+        client = client.events(settlement_events);
+    }
+    runtime::spawn_abortable(async move {
+        info!(
+            "Starting to retry outgoing settlements every {:?}",
+            interval
+        );
+        let mut interval = runtime::interval(interval);
+        loop {
+            interval.tick().await;
+            retry_outgoing_settlements(&store, &client, &clock).await;
+        }
+    })
+}
+
+async fn retry_outgoing_settlements<Store, Acct>(
+    store: &Store,
+    client: &SettlementClient,
+    clock: &SharedClock,
+) where
+    Store: SettlementStore<Account = Acct> + OutgoingSettlementStore + AccountStore<Account = Acct>,
+    Acct: SettlementAccount,
+{
+    // Amounts are left queued if this program stopped before the settlement in
+    // flight was answered, so they are made due for sending now
+    let now = clock.unix_millis();
+    match store.load_queued_settlement_accounts().await {
+        Ok(account_ids) => {
+            for account_id in account_ids {
+                if let Err(e) = store
+                    .dequeue_outgoing_settlement(account_id, new_idempotency_key(), now)
+                    .await
+                {
+                    warn!(
+                        "Failed to dequeue settlement for account {}: {}",
+                        account_id, e
+                    );
+                }
+            }
+        }
+        Err(e) => warn!("Failed to load queued settlements: {}", e),
+    }
+
+    let settlements = match store.load_outgoing_settlements().await {
+        Ok(settlements) => settlements,
+        Err(e) => {
+            warn!("Failed to load outgoing settlements: {}", e);
+            return;
+        }
+    };
+    for settlement in settlements {
+        if settlement.next_attempt_at > now {
+            continue;
+        }
+        let account_id = settlement.account_id;
+        let account = match store.get_accounts(vec![account_id]).await {
+            Ok(mut accounts) if accounts.len() == 1 => accounts.pop().unwrap(),
+            Err(AccountStoreError::AccountNotFound(_)) => {
+                // There is nobody left to settle with or to refund
+                warn!(
+                    "Dropping settlement {} for {} as account {} was deleted",
+                    settlement.idempotency_key, settlement.amount, account_id
+                );
+                let _ = store
+                    .remove_outgoing_settlement(&settlement.idempotency_key)
+                    .await;
+                continue;
+            }
+            _ => {
+                warn!(
+                    "Failed to load account {} to retry settlement {}",
+                    account_id, settlement.idempotency_key
+                );
+                continue;
+            }
+        };
+        let engine_url = match account.settlement_engine_details() {
+            Some(engine_details) => engine_details.url,
+            None => {
+                warn!(
+                    "Refunding settlement {} for {} as account {} has no settlement engine anymore",
+                    settlement.idempotency_key, settlement.amount, account_id
+                );
+                let _ = store
+                    .refund_outgoing_settlement(&settlement.idempotency_key)
+                    .await;
+                continue;
+            }
+        };
+        debug!(
+            "Retrying settlement {} for account {} for {}",
+            settlement.idempotency_key, account_id, settlement.amount
+        );
+        let result = client
+            .send_settlement_with_key(
+                account_id,
+                engine_url,
+                settlement.amount,
+                account.asset_scale(),
+                &settlement.idempotency_key,
+            )
+            .await;
+        if handle_settlement_result(store, settlement, result, clock)
+            .await
+            .is_ok()
+        {
+            send_queued_settlements(store, client, clock, account_id, &account).await;
+        }
+    }
 }
 
 /// Captures the behaviour of either operating in a delayed settlement or threshold-only
@@ -371,12 +987,18 @@ impl Policy {
         }
     }
 
-    /// Called to signal this account id needs to be settled later
-    fn settle_later(&mut self, account_id: Uuid, channel_last_fail: Arc<Mutex<Instant>>) {
+    /// Called to signal this account id needs to be settled later, after the account's own
+    /// delay if it has one
+    fn settle_later(
+        &mut self,
+        account_id: Uuid,
+        delay: Option<Duration>,
+        channel_last_fail: Arc<Mutex<Instant>>,
+    ) {
         match *self {
             Policy::ThresholdOnly => (),
             Policy::TimeBased(ref mut sender) => Policy::drop_error(
-                sender.try_send(ManageTimeout::Set(account_id)),
+                sender.try_send(ManageTimeout::Set(account_id, delay)),
                 channel_last_fail,
             ),
         }
@@ -443,14 +1065,16 @@ impl Policy {
 /// over to background task to manage the timeouts.
 pub enum ManageTimeout {
     Clear(Uuid),
-    Set(Uuid),
+    /// Settles the account after the provided delay, or after the default delay of the task if
+    /// the account has none
+    Set(Uuid, Option<Duration>),
 }
 
 impl ManageTimeout {
     fn uuid(&self) -> Uuid {
         match *self {
             ManageTimeout::Clear(uuid) => uuid,
-            ManageTimeout::Set(uuid) => uuid,
+            ManageTimeout::Set(uuid, _) => uuid,
         }
     }
 }
@@ -458,19 +1082,14 @@ impl ManageTimeout {
 #[derive(Debug)]
 enum ExitReason {
     InputClosed,
-    Shutdown,
-    Capacity,
-    Other(tokio::time::error::Error),
 }
 
 impl fmt::Display for ExitReason {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        use ExitReason::*;
         match *self {
-            InputClosed => write!(fmt, "Input was closed and ran out of timed settlements"),
-            Shutdown => write!(fmt, "Tokio is shutting down"),
-            Capacity => write!(fmt, "Timer service capacity exceeded"),
-            Other(ref e) => write!(fmt, "Other: {}", e),
+            ExitReason::InputClosed => {
+                write!(fmt, "Input was closed and ran out of timed settlements")
+            }
         }
     }
 }
@@ -478,15 +1097,22 @@ impl fmt::Display for ExitReason {
 /// Start a background task for time based settlement. If time based settlement is configured but
 /// this task is never started, the time-based settlement does not happen and a warning is logged
 /// every minute on eligble random peering account.
+///
+/// Accounts are settled after their own `settle_every` delay, or after the default `delay`.
+/// Accounts without either are only settled when they exceed their settlement threshold.
+/// The settlements and their results are published to the `settlement_events`, if any.
 pub fn start_delayed_settlement<St, Store, Acct>(
-    delay: Duration,
+    delay: Option<Duration>,
     cmds: St,
     store: Store,
-) -> tokio::task::JoinHandle<()>
+    clock: SharedClock,
+    settlement_events: Option<SettlementEvents>,
+) -> runtime::TaskHandle
 where
     St: futures::stream::FusedStream<Item = ManageTimeout> + Send + Sync + 'static + Unpin,
     Store: BalanceStore
         + SettlementStore<Account = Acct>
+        + OutgoingSettlementStore
         + AccountStore<Account = Acct>
         + Clone
         + Send
@@ -494,14 +1120,17 @@ where
         + 'static,
     Acct: SettlementAccount + Send + Sync + 'static,
 {
-    let client = SettlementClient::default();
-    tokio::spawn(async move {
+    let mut client = SettlementClient::default();
+    if let Some(settlement_events) = settlement_events {
+        client = client.events(settlement_events);
+    }
+    runtime::spawn_abortable(async move {
         info!(
-            "Starting to run delayed settlements with a timeout of {:?}",
+            "Starting to run delayed settlements with a default timeout of {:?}",
             delay
         );
 
-        let exit_reason = run_timeouts_and_settle_on_delay(delay, cmds, store, client).await;
+        let exit_reason = run_timeouts_and_settle_on_delay(delay, cmds, store, client, clock).await;
 
         info!(
             "Stopped running timeouts and delayed settlements: {}",
@@ -511,15 +1140,17 @@ where
 }
 
 async fn run_timeouts_and_settle_on_delay<St, Store, Acct>(
-    delay: Duration,
+    default_delay: Option<Duration>,
     mut cmds: St,
     store: Store,
     client: SettlementClient,
+    clock: SharedClock,
 ) -> ExitReason
 where
     St: futures::stream::FusedStream<Item = ManageTimeout> + Send + Sync + 'static + Unpin,
     Store: BalanceStore
         + SettlementStore<Account = Acct>
+        + OutgoingSettlementStore
         + AccountStore<Account = Acct>
         + Clone
         + Send
@@ -527,11 +1158,12 @@ where
         + 'static,
     Acct: SettlementAccount + Send + Sync + 'static,
 {
-    use futures::stream::StreamExt;
+    use futures::future::{self, Aborted};
+    use futures::stream::{FuturesUnordered, StreamExt};
     use std::collections::HashMap;
-    use tokio_util::time::DelayQueue;
 
-    let mut timeouts = DelayQueue::new();
+    // The timeouts complete with the id of their account, or are aborted when cleared
+    let mut timeouts = FuturesUnordered::new();
     let mut in_queue = HashMap::new();
 
     loop {
@@ -539,39 +1171,44 @@ where
             cmd = cmds.select_next_some() => {
                 match cmd {
                     ManageTimeout::Clear(id) => {
-                        let key = in_queue.remove(&id);
+                        let handle = in_queue.remove(&id);
 
-                        if let Some(key) = key {
+                        if let Some(handle) = handle {
                             // this should only be removed when the settle_threshold was achieved
                             // while processing a fulfill.
-                            timeouts.remove(&key);
+                            handle.abort();
                             trace!("Cleared pending settlement timeout for account: {}", id);
                         }
                     }
-                    ManageTimeout::Set(id) => {
-                        let timeouts = &mut timeouts;
-                        in_queue.entry(id).or_insert_with(move || {
-                            let key = timeouts.insert(id, delay);
-
-                            trace!("Setting pending settlement timeout for account: {}", id);
-
-                            key
-                        });
+                    ManageTimeout::Set(id, delay) => {
+                        // accounts without any delay are only settled over their threshold
+                        if let Some(delay) = delay.or(default_delay) {
+                            let timeouts = &mut timeouts;
+                            in_queue.entry(id).or_insert_with(move || {
+                                let (timeout, handle) =
+                                    future::abortable(runtime::sleep(delay).map(move |_| id));
+                                timeouts.push(timeout);
+
+                                trace!("Setting pending settlement timeout for account: {}", id);
+
+                                handle
+                            });
+                        }
                     }
                 }
             },
             next = timeouts.next(), if !timeouts.is_empty() || cmds.is_terminated() => {
                 match next {
-                    Some(Ok(expired)) => {
-                        let id = expired.into_inner();
+                    Some(Ok(id)) => {
                         in_queue.remove(&id); // unsure if this can ever be none
 
                         trace!("Delayed settlement for account {} expired", id);
 
                         let client = client.clone();
                         let store = store.clone();
+                        let clock = clock.clone();
 
-                        tokio::spawn(async move {
+                        runtime::spawn(async move {
                             // bailing out instead of not re-scheduling on failing to load the
                             // account: it is assumed that if this account is valid and should be
                             // settled there is near-continouos traffic which would trigger either
@@ -604,19 +1241,11 @@ where
                                 to.id(), balance, amount_to_settle
                             );
 
-                            settle_or_rollback(store, to, amount_to_settle, client).await
-                        });
+                            settle(store, id, to, amount_to_settle, client, clock).await
+                        }.map(|_: Result<(), ()>| ()));
                     },
-                    Some(Err(e)) if e.is_shutdown() => {
-                        // we probably cant do much better than to exit here (and to drop the
-                        // stream)
-                        return ExitReason::Shutdown;
-                    },
-                    Some(Err(e)) if e.is_at_capacity() => {
-                        return ExitReason::Capacity;
-                    },
-                    Some(Err(e)) => {
-                        return ExitReason::Other(e);
+                    Some(Err(Aborted)) => {
+                        // the timeout was cleared
                     }
                     None => {
                         // no more timeouts currently
@@ -635,9 +1264,11 @@ mod tests {
     use super::*;
     use interledger_errors::{AddressStoreError, SettlementStoreError};
     use interledger_packet::{Address, FulfillBuilder, PrepareBuilder, RejectBuilder};
+    use interledger_service::clock::ManualClock;
     use interledger_settlement::core::types::SettlementEngineDetails;
     use once_cell::sync::Lazy;
     use parking_lot::RwLock;
+    use std::collections::HashMap;
     use std::str::FromStr;
     use std::sync::Arc;
     use std::time::Duration;
@@ -737,6 +1368,322 @@ mod tests {
         assert!(*store.rejected_message.read());
     }
 
+    #[tokio::test]
+    async fn keeps_admitted_rejects_journaled_until_the_sender_was_refunded() {
+        let next = outgoing_service_fn(move |_| {
+            Err(RejectBuilder {
+                code: ErrorCode::T00_INTERNAL_ERROR,
+                message: &[],
+                triggered_by: None,
+                data: &[],
+            }
+            .build())
+        });
+        // The refund is not written, as if the node stopped before
+        let store = TestStore {
+            fail_rejects: true,
+            ..TestStore::new(1)
+        };
+        let journal = TestJournal::default();
+        let mut service = BalanceService::new(store.clone(), None, next.clone())
+            .admitted_prepares()
+            .journal(journal.clone());
+        service
+            .send_request(TEST_REQUEST.clone())
+            .await
+            .unwrap_err();
+
+        tokio::time::sleep(Duration::from_millis(100u64)).await;
+        // It is left to the reconciliation, which refunds the sender
+        let packets = journal.load_journaled_packets().await.unwrap();
+        assert_eq!(packets.len(), 1);
+        assert!(!packets[0].fulfilled);
+
+        let store = TestStore::new(1);
+        let journal = TestJournal::default();
+        let mut service = BalanceService::new(store.clone(), None, next)
+            .admitted_prepares()
+            .journal(journal.clone());
+        service
+            .send_request(TEST_REQUEST.clone())
+            .await
+            .unwrap_err();
+
+        tokio::time::sleep(Duration::from_millis(100u64)).await;
+        assert!(*store.rejected_message.read());
+        assert!(journal.load_journaled_packets().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn retries_unanswered_settlements() {
+        let mock = mockito::mock("POST", mockito::Matcher::Any)
+            .match_header("Idempotency-Key", "settlement-key")
+            .create();
+        let store = TestStore::new(0);
+        store
+            .save_outgoing_settlement(OutgoingSettlement {
+                idempotency_key: "settlement-key".to_string(),
+                account_id: Uuid::new_v4(),
+                amount: 100,
+                status: OutgoingSettlementStatus::Unknown,
+                attempts: 1,
+                next_attempt_at: 0,
+            })
+            .await
+            .unwrap();
+
+        retry_outgoing_settlements(
+            &store,
+            &SettlementClient::new(Duration::from_secs(1), 0),
+            &SharedClock::default(),
+        )
+        .await;
+
+        mock.assert();
+        assert!(store.load_outgoing_settlements().await.unwrap().is_empty());
+        assert!(!*store.refunded_settlement.read());
+    }
+
+    #[tokio::test]
+    async fn sends_settlements_queued_before_a_restart() {
+        let mock = mockito::mock("POST", mockito::Matcher::Any).create();
+        let store = TestStore::new(100);
+        let account_id = Uuid::new_v4();
+        // The node stops right after the fulfill, before it sent the settlement
+        store
+            .update_balances_for_fulfill_and_queue_settlement(account_id, 100, None)
+            .await
+            .unwrap();
+
+        retry_outgoing_settlements(
+            &store,
+            &SettlementClient::new(Duration::from_secs(1), 0),
+            &SharedClock::default(),
+        )
+        .await;
+
+        mock.assert();
+        assert!(store.load_outgoing_settlements().await.unwrap().is_empty());
+        assert!(store.queued_settlements.read().is_empty());
+        assert!(!*store.refunded_settlement.read());
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_settlements_before_they_are_due() {
+        let mock = mockito::mock("POST", mockito::Matcher::Any)
+            .create()
+            .expect(0);
+        let store = TestStore::new(0);
+        let settlement = OutgoingSettlement {
+            idempotency_key: "settlement-key".to_string(),
+            account_id: Uuid::new_v4(),
+            amount: 100,
+            status: OutgoingSettlementStatus::Pending,
+            attempts: 0,
+            next_attempt_at: SharedClock::default().unix_millis() + 60_000,
+        };
+        store
+            .save_outgoing_settlement(settlement.clone())
+            .await
+            .unwrap();
+
+        retry_outgoing_settlements(
+            &store,
+            &SettlementClient::new(Duration::from_secs(1), 0),
+            &SharedClock::default(),
+        )
+        .await;
+
+        mock.assert();
+        assert_eq!(
+            store.load_outgoing_settlements().await.unwrap(),
+            vec![settlement]
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_settlements_once_the_clock_reaches_them() {
+        let mock = mockito::mock("POST", mockito::Matcher::Any)
+            .match_header("Idempotency-Key", "delayed-settlement-key")
+            .create()
+            .expect(1);
+        let clock = ManualClock::default();
+        let shared_clock = SharedClock::from(clock.clone());
+        let store = TestStore::new(0);
+        store
+            .save_outgoing_settlement(OutgoingSettlement {
+                idempotency_key: "delayed-settlement-key".to_string(),
+                account_id: Uuid::new_v4(),
+                amount: 100,
+                status: OutgoingSettlementStatus::Unknown,
+                attempts: 1,
+                next_attempt_at: shared_clock.unix_millis() + 60_000,
+            })
+            .await
+            .unwrap();
+        let client = SettlementClient::new(Duration::from_secs(1), 0);
+
+        retry_outgoing_settlements(&store, &client, &shared_clock).await;
+        assert_eq!(store.load_outgoing_settlements().await.unwrap().len(), 1);
+
+        clock.advance(Duration::from_secs(60));
+        retry_outgoing_settlements(&store, &client, &shared_clock).await;
+        mock.assert();
+        assert!(store.load_outgoing_settlements().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sends_queued_settlements_once_the_one_in_flight_was_answered() {
+        let mock = mockito::mock("POST", mockito::Matcher::Any)
+            .create()
+            .expect(2);
+        let store = TestStore::new(0);
+        let account_id = Uuid::new_v4();
+        let settlement = OutgoingSettlement {
+            idempotency_key: "settlement-key".to_string(),
+            account_id,
+            amount: 100,
+            status: OutgoingSettlementStatus::Unknown,
+            attempts: 1,
+            next_attempt_at: 0,
+        };
+        assert!(store
+            .queue_outgoing_settlement(settlement.clone())
+            .await
+            .unwrap());
+        for key in &["queued-key-1", "queued-key-2"] {
+            let queued = OutgoingSettlement {
+                idempotency_key: key.to_string(),
+                ..settlement.clone()
+            };
+            assert!(!store.queue_outgoing_settlement(queued).await.unwrap());
+        }
+        assert_eq!(store.queued_settlements.read().get(&account_id), Some(&200));
+
+        retry_outgoing_settlements(
+            &store,
+            &SettlementClient::new(Duration::from_secs(1), 0),
+            &SharedClock::default(),
+        )
+        .await;
+
+        mock.assert();
+        assert!(store.load_outgoing_settlements().await.unwrap().is_empty());
+        assert!(store
+            .load_queued_settlement_accounts()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn does_not_send_queued_settlements_while_one_is_in_flight() {
+        let mock = mockito::mock("POST", mockito::Matcher::Any)
+            .create()
+            .expect(0);
+        let store = TestStore::new(0);
+        let account_id = Uuid::new_v4();
+        let settlement = OutgoingSettlement {
+            idempotency_key: "settlement-key".to_string(),
+            account_id,
+            amount: 100,
+            status: OutgoingSettlementStatus::Unknown,
+            attempts: 1,
+            next_attempt_at: SharedClock::default().unix_millis() + 60_000,
+        };
+        store
+            .queue_outgoing_settlement(settlement.clone())
+            .await
+            .unwrap();
+        store
+            .queue_outgoing_settlement(OutgoingSettlement {
+                idempotency_key: "queued-key".to_string(),
+                ..settlement.clone()
+            })
+            .await
+            .unwrap();
+
+        retry_outgoing_settlements(
+            &store,
+            &SettlementClient::new(Duration::from_secs(1), 0),
+            &SharedClock::default(),
+        )
+        .await;
+
+        mock.assert();
+        assert_eq!(
+            store.load_outgoing_settlements().await.unwrap(),
+            vec![settlement]
+        );
+        assert_eq!(
+            store.load_queued_settlement_accounts().await.unwrap(),
+            vec![account_id]
+        );
+    }
+
+    #[tokio::test]
+    async fn settles_after_the_delay_of_the_account() {
+        use futures::stream::StreamExt;
+
+        let mock = mockito::mock("POST", mockito::Matcher::Any)
+            .create()
+            .expect(1);
+        let store = TestStore::new(1);
+        // The first account has no delay of its own and there is no default delay
+        let cmds = futures::stream::iter(vec![
+            ManageTimeout::Set(Uuid::new_v4(), None),
+            ManageTimeout::Set(Uuid::new_v4(), Some(Duration::from_millis(10))),
+        ])
+        .fuse();
+
+        let exit_reason = run_timeouts_and_settle_on_delay(
+            None,
+            cmds,
+            store,
+            SettlementClient::default(),
+            SharedClock::default(),
+        )
+        .await;
+        assert!(matches!(exit_reason, ExitReason::InputClosed));
+
+        tokio::time::sleep(Duration::from_millis(100u64)).await;
+        mock.assert();
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_maximum() {
+        assert_eq!(retry_delay(0), FIRST_RETRY_DELAY);
+        assert_eq!(retry_delay(1), FIRST_RETRY_DELAY * 2);
+        assert_eq!(retry_delay(10), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn reconciliation_flags_unsettled_amounts_over_the_tolerance() {
+        let mut totals = BalanceTotals::default();
+        totals.add(BalanceChangeReason::Fulfill, 1000);
+        totals.add(BalanceChangeReason::Settlement, -800);
+        totals.add(BalanceChangeReason::SettlementRefund, 300);
+        totals.add(BalanceChangeReason::Prepare, -200);
+        totals.add(BalanceChangeReason::Reject, 50);
+        totals.add(BalanceChangeReason::IncomingSettlement, 100);
+
+        let report = ReconciliationReport::new(Uuid::nil(), &totals, 500);
+        assert_eq!(report.cleared, 850);
+        assert_eq!(report.settled_outgoing, 500);
+        assert_eq!(report.settled_incoming, 100);
+        assert_eq!(report.drift, 450);
+        assert!(!report.exceeds_tolerance);
+
+        let report = ReconciliationReport::new(Uuid::nil(), &totals, 449);
+        assert!(report.exceeds_tolerance);
+
+        totals.add(BalanceChangeReason::Prepare, -1000);
+        let report = ReconciliationReport::new(Uuid::nil(), &totals, 500);
+        assert_eq!(report.drift, -550);
+        assert!(report.exceeds_tolerance);
+    }
+
     #[derive(Debug, Clone)]
     struct TestAccount {
         pub engine_url: Url,
@@ -782,6 +1729,9 @@ mod tests {
         amount_to_settle: u64,
         rejected_message: Arc<RwLock<bool>>,
         refunded_settlement: Arc<RwLock<bool>>,
+        outgoing_settlements: Arc<RwLock<HashMap<String, OutgoingSettlement>>>,
+        queued_settlements: Arc<RwLock<HashMap<Uuid, u64>>>,
+        fail_rejects: bool,
     }
 
     impl TestStore {
@@ -790,7 +1740,39 @@ mod tests {
                 amount_to_settle,
                 rejected_message: Arc::new(RwLock::new(false)),
                 refunded_settlement: Arc::new(RwLock::new(false)),
+                outgoing_settlements: Arc::new(RwLock::new(HashMap::new())),
+                queued_settlements: Arc::new(RwLock::new(HashMap::new())),
+                fail_rejects: false,
+            }
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct TestJournal {
+        packets: Arc<RwLock<HashMap<Uuid, JournaledPacket>>>,
+    }
+
+    #[async_trait]
+    impl PacketJournalStore for TestJournal {
+        async fn journal_packet(&self, packet: JournaledPacket) -> Result<(), BalanceStoreError> {
+            self.packets.write().insert(packet.id, packet);
+            Ok(())
+        }
+
+        async fn mark_journaled_packet_fulfilled(&self, id: Uuid) -> Result<(), BalanceStoreError> {
+            if let Some(packet) = self.packets.write().get_mut(&id) {
+                packet.fulfilled = true;
             }
+            Ok(())
+        }
+
+        async fn remove_journaled_packet(&self, id: Uuid) -> Result<(), BalanceStoreError> {
+            self.packets.write().remove(&id);
+            Ok(())
+        }
+
+        async fn load_journaled_packets(&self) -> Result<Vec<JournaledPacket>, BalanceStoreError> {
+            Ok(self.packets.read().values().cloned().collect())
         }
     }
 
@@ -819,6 +1801,7 @@ mod tests {
             &self,
             _: Uuid,
             _: u64,
+            _: Option<PacketId>,
         ) -> Result<(), BalanceStoreError> {
             Ok(())
         }
@@ -827,25 +1810,62 @@ mod tests {
             &self,
             _: Uuid,
             _: u64,
+            _: Option<PacketId>,
         ) -> Result<(i64, u64), BalanceStoreError> {
             Ok((0, self.amount_to_settle))
         }
 
+        async fn update_balances_for_fulfill_and_queue_settlement(
+            &self,
+            to_account_id: Uuid,
+            _: u64,
+            _: Option<PacketId>,
+        ) -> Result<(i64, u64), BalanceStoreError> {
+            self.update_balances_for_delayed_settlement(to_account_id)
+                .await
+        }
+
         async fn update_balances_for_reject(
             &self,
             _: Uuid,
             _: u64,
+            _: Option<PacketId>,
         ) -> Result<(), BalanceStoreError> {
+            if self.fail_rejects {
+                return Err(BalanceStoreError::Other(Box::new(std::io::Error::from(
+                    std::io::ErrorKind::Interrupted,
+                ))));
+            }
             *self.rejected_message.write() = true;
             Ok(())
         }
 
         async fn update_balances_for_delayed_settlement(
             &self,
-            _: Uuid,
+            to_account_id: Uuid,
         ) -> Result<(i64, u64), BalanceStoreError> {
+            if self.amount_to_settle > 0 {
+                *self
+                    .queued_settlements
+                    .write()
+                    .entry(to_account_id)
+                    .or_default() += self.amount_to_settle;
+            }
             Ok((0, self.amount_to_settle))
         }
+
+        async fn get_balance_changes(
+            &self,
+            _: Uuid,
+            _: u64,
+            _: usize,
+        ) -> Result<Vec<BalanceChange>, BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn get_balance_totals(&self, _: Uuid) -> Result<BalanceTotals, BalanceStoreError> {
+            unimplemented!()
+        }
     }
 
     #[async_trait]
@@ -867,6 +1887,112 @@ mod tests {
         }
     }
 
+    #[async_trait]
+    impl OutgoingSettlementStore for TestStore {
+        async fn queue_outgoing_settlement(
+            &self,
+            settlement: OutgoingSettlement,
+        ) -> Result<bool, SettlementStoreError> {
+            let mut settlements = self.outgoing_settlements.write();
+            if settlements
+                .values()
+                .any(|in_flight| in_flight.account_id == settlement.account_id)
+            {
+                *self
+                    .queued_settlements
+                    .write()
+                    .entry(settlement.account_id)
+                    .or_default() += settlement.amount;
+                return Ok(false);
+            }
+            settlements.insert(settlement.idempotency_key.clone(), settlement);
+            Ok(true)
+        }
+
+        async fn dequeue_outgoing_settlement(
+            &self,
+            account_id: Uuid,
+            idempotency_key: String,
+            next_attempt_at: u64,
+        ) -> Result<Option<OutgoingSettlement>, SettlementStoreError> {
+            let mut settlements = self.outgoing_settlements.write();
+            if settlements
+                .values()
+                .any(|in_flight| in_flight.account_id == account_id)
+            {
+                return Ok(None);
+            }
+            let amount = match self.queued_settlements.write().remove(&account_id) {
+                Some(amount) => amount,
+                None => return Ok(None),
+            };
+            let settlement = OutgoingSettlement {
+                idempotency_key,
+                account_id,
+                amount,
+                status: OutgoingSettlementStatus::Pending,
+                attempts: 0,
+                next_attempt_at,
+            };
+            settlements.insert(settlement.idempotency_key.clone(), settlement.clone());
+            Ok(Some(settlement))
+        }
+
+        async fn load_queued_settlement_accounts(&self) -> Result<Vec<Uuid>, SettlementStoreError> {
+            Ok(self.queued_settlements.read().keys().cloned().collect())
+        }
+
+        async fn save_outgoing_settlement(
+            &self,
+            settlement: OutgoingSettlement,
+        ) -> Result<(), SettlementStoreError> {
+            self.outgoing_settlements
+                .write()
+                .insert(settlement.idempotency_key.clone(), settlement);
+            Ok(())
+        }
+
+        async fn load_outgoing_settlements(
+            &self,
+        ) -> Result<Vec<OutgoingSettlement>, SettlementStoreError> {
+            Ok(self.outgoing_settlements.read().values().cloned().collect())
+        }
+
+        async fn remove_outgoing_settlement(&self, key: &str) -> Result<(), SettlementStoreError> {
+            self.outgoing_settlements.write().remove(key);
+            Ok(())
+        }
+
+        async fn refund_outgoing_settlement(&self, key: &str) -> Result<(), SettlementStoreError> {
+            if self.outgoing_settlements.write().remove(key).is_some() {
+                *self.refunded_settlement.write() = true;
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl AccountStore for TestStore {
+        type Account = TestAccount;
+
+        async fn get_accounts(
+            &self,
+            account_ids: Vec<Uuid>,
+        ) -> Result<Vec<TestAccount>, AccountStoreError> {
+            Ok(account_ids
+                .iter()
+                .map(|_| TEST_REQUEST.to.clone())
+                .collect())
+        }
+
+        async fn get_account_id_from_username(
+            &self,
+            _: &Username,
+        ) -> Result<Uuid, AccountStoreError> {
+            unimplemented!()
+        }
+    }
+
     static TEST_REQUEST: Lazy<OutgoingRequest<TestAccount>> = Lazy::new(|| {
         let url = mockito::server_url();
         OutgoingRequest {
@@ -0,0 +1,263 @@
+use futures::future::{ready, Either, Ready};
+use interledger_packet::{has_prefix, Address, ErrorCode, RejectBuilder};
+use interledger_service::*;
+use serde::Deserialize;
+use std::sync::{Arc, RwLock};
+use tracing::debug;
+
+/// Prefixes of the destinations to which the packets of an account, or of all accounts,
+/// may or may not be sent. A prefix matches the addresses which are equal to it or which
+/// start with it followed by a `.`, e.g. `g.bank` matches `g.bank.alice` but not `g.banking`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct DestinationRule {
+    /// Username of the account whose packets the rule applies to. Applies to all accounts
+    /// if not set.
+    #[serde(default)]
+    pub from: Option<Username>,
+    /// If not empty, packets may only be sent to destinations with one of these prefixes
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Packets may not be sent to destinations with any of these prefixes
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl DestinationRule {
+    /// Returns whether the rule lets packets be sent to the destination
+    fn permits(&self, destination: &str) -> bool {
+        let allowed = self.allow.is_empty()
+            || self
+                .allow
+                .iter()
+                .any(|prefix| has_prefix(destination, prefix));
+        allowed
+            && !self
+                .deny
+                .iter()
+                .any(|prefix| has_prefix(destination, prefix))
+    }
+}
+
+/// The destination rules applied by a [`DestinationFilterService`], which can be changed
+/// while the service is running, e.g. when the node's configuration is reloaded
+#[derive(Debug, Clone, Default)]
+pub struct DestinationRules(Arc<RwLock<Vec<DestinationRule>>>);
+
+impl DestinationRules {
+    pub fn new(rules: Vec<DestinationRule>) -> Self {
+        DestinationRules(Arc::new(RwLock::new(rules)))
+    }
+
+    /// Returns whether every rule which applies to the account lets it send packets
+    /// to the destination
+    pub fn permits(&self, from: &Username, destination: &Address) -> bool {
+        let destination: &str = destination;
+        self.0
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|rule| rule.from.as_ref().map_or(true, |username| username == from))
+            .all(|rule| rule.permits(destination))
+    }
+
+    /// Changes the rules of all services which share them
+    pub fn set(&self, rules: Vec<DestinationRule>) {
+        *self.0.write().unwrap() = rules;
+    }
+}
+
+/// # Destination Filter Service
+///
+/// Rejects the packets whose destinations are not permitted by the [`DestinationRule`]s
+/// which apply to the account which sent them, before they are routed. This lets operators
+/// block payments to sanctioned or abusive destinations, or restrict an account to paying
+/// only some destinations. Packets to `peer.` addresses, which are exchanged by the
+/// protocols between peers (e.g. ILDCP, CCP and settlement messages), are never filtered.
+/// Rejected packets get an `F02: Unreachable`.
+/// Requires an `AddressStore`.
+#[derive(Clone)]
+pub struct DestinationFilterService<I, S> {
+    rules: DestinationRules,
+    store: S,
+    next: I,
+}
+
+impl<I, S> DestinationFilterService<I, S> {
+    pub fn new(rules: DestinationRules, store: S, next: I) -> Self {
+        DestinationFilterService { rules, store, next }
+    }
+}
+
+impl<I, S, A> IncomingService<A> for DestinationFilterService<I, S>
+where
+    I: IncomingService<A> + Send + Sync + 'static,
+    S: AddressStore + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    type Future<'a> = Either<I::Future<'a>, Ready<IlpResult>>;
+
+    /// On receive request:
+    /// 1. if the destination is a `peer.` address or is permitted for request.from forward the request, else error
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        let destination = request.prepare.destination();
+        if destination.scheme() == "peer"
+            || self.rules.permits(request.from.username(), &destination)
+        {
+            Either::Left(self.next.handle_request(request))
+        } else {
+            debug!(
+                "Rejecting packet from account {} to destination {} which is not permitted",
+                request.from.username(),
+                destination
+            );
+            Either::Right(ready(Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: format!("Destination {} is not permitted", destination).as_bytes(),
+                triggered_by: Some(&self.store.get_ilp_address()),
+                data: &[],
+            }
+            .build())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    #[derive(Debug, Clone)]
+    struct TestAccount(Username);
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::new_v4()
+        }
+
+        fn username(&self) -> &Username {
+            &self.0
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    fn rule(from: Option<&str>, allow: &[&str], deny: &[&str]) -> DestinationRule {
+        DestinationRule {
+            from: from.map(|username| Username::from_str(username).unwrap()),
+            allow: allow.iter().map(|prefix| prefix.to_string()).collect(),
+            deny: deny.iter().map(|prefix| prefix.to_string()).collect(),
+        }
+    }
+
+    /// Sends a packet from the account to the destination, returning whether it was forwarded
+    async fn send(rules: &DestinationRules, from: &str, destination: &str) -> bool {
+        let next = incoming_service_fn(|_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let mut service = DestinationFilterService::new(rules.clone(), TestStore, next);
+        let result = service
+            .handle_request(IncomingRequest {
+                from: TestAccount(Username::from_str(from).unwrap()),
+                prepare: PrepareBuilder {
+                    destination: Address::from_str(destination).unwrap(),
+                    amount: 100,
+                    expires_at: std::time::SystemTime::now() + std::time::Duration::from_secs(30),
+                    execution_condition: &[0; 32],
+                    data: b"test data",
+                }
+                .build(),
+            })
+            .await;
+        if let Err(ref reject) = result {
+            assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+        }
+        result.is_ok()
+    }
+
+    #[test]
+    fn matches_whole_segments() {
+        assert!(has_prefix("g.bank", "g.bank"));
+        assert!(has_prefix("g.bank.alice", "g.bank"));
+        assert!(has_prefix("g.bank.alice", "g.bank."));
+        assert!(!has_prefix("g.banking.alice", "g.bank"));
+        assert!(!has_prefix("g.ban", "g.bank"));
+    }
+
+    #[tokio::test]
+    async fn rejects_denied_destinations() {
+        let rules = DestinationRules::new(vec![rule(None, &[], &["g.blocked"])]);
+        assert!(!send(&rules, "alice", "g.blocked.bob").await);
+        assert!(send(&rules, "alice", "g.blockedx.bob").await);
+        assert!(send(&rules, "alice", "g.other.bob").await);
+    }
+
+    #[tokio::test]
+    async fn restricts_accounts_to_allowed_destinations() {
+        let rules = DestinationRules::new(vec![
+            rule(Some("alice"), &["g.shop", "g.utility"], &["g.shop.closed"]),
+            rule(None, &[], &["g.blocked"]),
+        ]);
+        assert!(send(&rules, "alice", "g.shop.bob").await);
+        assert!(send(&rules, "alice", "g.utility").await);
+        assert!(!send(&rules, "alice", "g.shop.closed.bob").await);
+        assert!(!send(&rules, "alice", "g.other.bob").await);
+        // Other accounts are only subject to the rules without an account
+        assert!(send(&rules, "carol", "g.other.bob").await);
+        assert!(!send(&rules, "carol", "g.blocked.bob").await);
+    }
+
+    #[tokio::test]
+    async fn does_not_filter_peer_protocols() {
+        let rules = DestinationRules::new(vec![rule(None, &["g.shop"], &["peer"])]);
+        assert!(send(&rules, "alice", "peer.config").await);
+        assert!(send(&rules, "alice", "peer.route.control").await);
+    }
+
+    #[tokio::test]
+    async fn applies_changed_rules() {
+        let rules = DestinationRules::default();
+        assert!(send(&rules, "alice", "g.blocked.bob").await);
+        rules.set(vec![rule(None, &[], &["g.blocked"])]);
+        assert!(!send(&rules, "alice", "g.blocked.bob").await);
+    }
+}
@@ -0,0 +1,380 @@
+use interledger_packet::{Address, ErrorCode, RejectBuilder};
+use interledger_service::*;
+use serde::Deserialize;
+use std::collections::{hash_map::Entry, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::sync::oneshot;
+use tracing::debug;
+use uuid::Uuid;
+
+/// What to do with a Prepare which is received again while the original is still pending
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePreparePolicy {
+    /// Answer the duplicate with the Fulfill or Reject of the original, once it is known
+    Attach,
+    /// Reject the duplicate with F00 (Bad Request)
+    Reject,
+}
+
+impl Default for DuplicatePreparePolicy {
+    fn default() -> Self {
+        DuplicatePreparePolicy::Attach
+    }
+}
+
+/// What makes two Prepares the same
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct PrepareKey {
+    from: Uuid,
+    execution_condition: [u8; 32],
+    destination: Address,
+    amount: u64,
+    expires_at: SystemTime,
+}
+
+impl PrepareKey {
+    fn new<A: Account>(request: &IncomingRequest<A>) -> Self {
+        let mut execution_condition = [0; 32];
+        execution_condition.copy_from_slice(request.prepare.execution_condition());
+        PrepareKey {
+            from: request.from.id(),
+            execution_condition,
+            destination: request.prepare.destination(),
+            amount: request.prepare.amount(),
+            expires_at: request.prepare.expires_at(),
+        }
+    }
+}
+
+/// The senders through which the duplicates of each pending Prepare get its result
+type PendingPrepares = Arc<Mutex<HashMap<PrepareKey, Vec<oneshot::Sender<IlpResult>>>>>;
+
+/// Removes a pending Prepare once its result is known. If the request handling the original
+/// is dropped before then, the senders are dropped with it and its duplicates are rejected.
+struct PendingGuard {
+    pending: PendingPrepares,
+    key: Option<PrepareKey>,
+}
+
+impl PendingGuard {
+    fn finish(mut self, result: &IlpResult) {
+        let waiters = self
+            .key
+            .take()
+            .and_then(|key| self.pending.lock().unwrap().remove(&key))
+            .unwrap_or_default();
+        for waiter in waiters {
+            // The duplicate may have been dropped in the meantime
+            let _ = waiter.send(result.clone());
+        }
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.pending.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+/// How a Prepare was registered with the pending ones
+enum Arrival {
+    Original,
+    Attached(oneshot::Receiver<IlpResult>),
+    Rejected,
+}
+
+/// # Duplicate Prepare Service
+///
+/// Incoming Service which detects when an account sends the exact same Prepare (same execution
+/// condition, destination, amount and expiry) again while the original is still pending, e.g.
+/// because of a retry storm upstream, and short-circuits it according to the
+/// `DuplicatePreparePolicy` instead of forwarding it, so the account is not debited twice.
+/// The pending Prepares are shared by all the clones of the service, so the duplicates are
+/// detected whichever protocol they arrive over.
+/// Only the Prepares of the same account are compared, since another account would get the
+/// fulfillment of a packet it did not pay for. Packets without an amount are always forwarded,
+/// as they do not change balances.
+/// Requires an `AddressStore`.
+#[derive(Clone)]
+pub struct DuplicatePrepareService<I, S> {
+    policy: DuplicatePreparePolicy,
+    pending: PendingPrepares,
+    store: S,
+    next: I,
+}
+
+impl<I, S> DuplicatePrepareService<I, S> {
+    /// Simple constructor
+    pub fn new(policy: DuplicatePreparePolicy, store: S, next: I) -> Self {
+        DuplicatePrepareService {
+            policy,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            store,
+            next,
+        }
+    }
+}
+
+impl<I, S, A> IncomingService<A> for DuplicatePrepareService<I, S>
+where
+    I: IncomingService<A> + Send + Sync + 'static,
+    S: AddressStore + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    /// On receive request:
+    /// 1. if the same Prepare of the same account is pending, attach to its result or reject it
+    /// 1. otherwise forward the request, and pass its result on to the duplicates received in the meantime
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            if request.prepare.amount() == 0 {
+                return self.next.handle_request(request).await;
+            }
+
+            let key = PrepareKey::new(&request);
+            let arrival = match self.pending.lock().unwrap().entry(key.clone()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(Vec::new());
+                    Arrival::Original
+                }
+                Entry::Occupied(mut entry) => match self.policy {
+                    DuplicatePreparePolicy::Attach => {
+                        let (sender, receiver) = oneshot::channel();
+                        entry.get_mut().push(sender);
+                        Arrival::Attached(receiver)
+                    }
+                    DuplicatePreparePolicy::Reject => Arrival::Rejected,
+                },
+            };
+
+            let (code, message) = match arrival {
+                Arrival::Original => {
+                    let guard = PendingGuard {
+                        pending: self.pending.clone(),
+                        key: Some(key),
+                    };
+                    let result = self.next.handle_request(request).await;
+                    guard.finish(&result);
+                    return result;
+                }
+                Arrival::Attached(receiver) => {
+                    debug!(
+                        "Prepare from account {} is a duplicate of a pending one, waiting for its result",
+                        request.from.id()
+                    );
+                    match receiver.await {
+                        Ok(result) => return result,
+                        Err(_) => (
+                            ErrorCode::T00_INTERNAL_ERROR,
+                            &b"Original of the duplicate Prepare was not answered"[..],
+                        ),
+                    }
+                }
+                Arrival::Rejected => {
+                    debug!(
+                        "Rejecting Prepare from account {} because it is a duplicate of a pending one",
+                        request.from.id()
+                    );
+                    (
+                        ErrorCode::F00_BAD_REQUEST,
+                        &b"Duplicate of a pending Prepare"[..],
+                    )
+                }
+            };
+            let ilp_address = self.store.get_ilp_address();
+            Err(RejectBuilder {
+                code,
+                message,
+                triggered_by: Some(&ilp_address),
+                data: &[],
+            }
+            .build())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use futures::future::join;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[derive(Debug, Clone)]
+    struct TestAccount(Uuid);
+
+    static EXPIRES_AT: Lazy<SystemTime> = Lazy::new(|| SystemTime::now() + Duration::from_secs(30));
+
+    fn request(account: &TestAccount, amount: u64) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: account.clone(),
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at: *EXPIRES_AT,
+                execution_condition: &[0; 32],
+                data: b"test data",
+            }
+            .build(),
+        }
+    }
+
+    /// Fulfills the packets after a while, and counts them
+    #[derive(Clone)]
+    struct SlowService {
+        forwarded: Arc<AtomicUsize>,
+    }
+
+    impl IncomingService<TestAccount> for SlowService {
+        type Future<'a> = BoxedIlpFuture<'a>;
+
+        fn handle_request(&mut self, _: IncomingRequest<TestAccount>) -> Self::Future<'_> {
+            Box::pin(async move {
+                self.forwarded.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"test data",
+                }
+                .build())
+            })
+        }
+    }
+
+    fn service(
+        policy: DuplicatePreparePolicy,
+    ) -> (
+        DuplicatePrepareService<SlowService, TestStore>,
+        Arc<AtomicUsize>,
+    ) {
+        let forwarded = Arc::new(AtomicUsize::new(0));
+        let next = SlowService {
+            forwarded: forwarded.clone(),
+        };
+        (
+            DuplicatePrepareService::new(policy, TestStore, next),
+            forwarded,
+        )
+    }
+
+    /// Sends the second request while the first one is pending
+    async fn send_twice(
+        service: &DuplicatePrepareService<SlowService, TestStore>,
+        first: IncomingRequest<TestAccount>,
+        second: IncomingRequest<TestAccount>,
+    ) -> (IlpResult, IlpResult) {
+        let mut original = service.clone();
+        let mut duplicate = service.clone();
+        join(original.handle_request(first), async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            duplicate.handle_request(second).await
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn attaches_duplicates_to_the_original() {
+        let (service, forwarded) = service(DuplicatePreparePolicy::Attach);
+        let alice = TestAccount(Uuid::new_v4());
+
+        let (original, duplicate) =
+            send_twice(&service, request(&alice, 100), request(&alice, 100)).await;
+        assert_eq!(original.unwrap().data(), b"test data");
+        assert_eq!(duplicate.unwrap().data(), b"test data");
+        assert_eq!(forwarded.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_duplicates() {
+        let (service, forwarded) = service(DuplicatePreparePolicy::Reject);
+        let alice = TestAccount(Uuid::new_v4());
+
+        let (original, duplicate) =
+            send_twice(&service, request(&alice, 100), request(&alice, 100)).await;
+        assert!(original.is_ok());
+        let reject = duplicate.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F00_BAD_REQUEST);
+        assert_eq!(reject.message(), &b"Duplicate of a pending Prepare"[..]);
+        assert_eq!(forwarded.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn forwards_different_prepares() {
+        let (service, forwarded) = service(DuplicatePreparePolicy::Reject);
+        let alice = TestAccount(Uuid::new_v4());
+        let bob = TestAccount(Uuid::new_v4());
+
+        let (first, second) =
+            send_twice(&service, request(&alice, 100), request(&alice, 101)).await;
+        assert!(first.is_ok() && second.is_ok());
+        let (first, second) = send_twice(&service, request(&alice, 100), request(&bob, 100)).await;
+        assert!(first.is_ok() && second.is_ok());
+        let (first, second) = send_twice(&service, request(&alice, 0), request(&alice, 0)).await;
+        assert!(first.is_ok() && second.is_ok());
+        assert_eq!(forwarded.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test]
+    async fn forwards_prepares_again_once_answered() {
+        let (mut service, forwarded) = service(DuplicatePreparePolicy::Reject);
+        let alice = TestAccount(Uuid::new_v4());
+
+        assert!(service.handle_request(request(&alice, 100)).await.is_ok());
+        assert!(service.handle_request(request(&alice, 100)).await.is_ok());
+        assert_eq!(forwarded.load(Ordering::SeqCst), 2);
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.0
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+}
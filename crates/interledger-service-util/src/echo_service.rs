@@ -1,6 +1,6 @@
-use async_trait::async_trait;
 use bytes::{Buf, BufMut, BytesMut};
 use core::borrow::Borrow;
+use futures::future::{ready, Either, Ready};
 use interledger_packet::{
     oer::BufOerExt, Address, ErrorCode, Prepare, PrepareBuilder, RejectBuilder,
 };
@@ -48,19 +48,23 @@ where
     }
 }
 
-#[async_trait]
 impl<I, S, A> IncomingService<A> for EchoService<I, S, A>
 where
     I: IncomingService<A> + Send,
     S: AddressStore + Send,
     A: Account + Send,
 {
-    async fn handle_request(&mut self, mut request: IncomingRequest<A>) -> IlpResult {
+    type Future<'a>
+        = Either<I::Future<'a>, Ready<IlpResult>>
+    where
+        Self: 'a;
+
+    fn handle_request(&mut self, mut request: IncomingRequest<A>) -> Self::Future<'_> {
         let ilp_address = self.store.get_ilp_address();
         let should_echo = request.prepare.destination() == ilp_address
             && request.prepare.data().starts_with(ECHO_PREFIX.as_bytes());
         if !should_echo {
-            return self.next.handle_request(request).await;
+            return Either::Left(self.next.handle_request(request));
         }
         debug!("Responding to Echo protocol request: {:?}", request);
 
@@ -74,23 +78,23 @@ where
         // check echo packet type
         if reader.remaining() < 1 {
             eprintln!("Could not read packet type: Unexpected Eof");
-            return Err(RejectBuilder {
+            return Either::Right(ready(Err(RejectBuilder {
                 code: ErrorCode::F01_INVALID_PACKET,
                 message: b"Could not read echo packet type.",
                 triggered_by: Some(&ilp_address),
                 data: &[],
             }
-            .build());
+            .build())));
         }
         let echo_packet_type = reader.get_u8();
         if echo_packet_type == EchoPacketType::Response as u8 {
             // if the echo packet type is Response, just pass it to the next service
             // so that the initiator could handle this packet
-            return self.next.handle_request(request).await;
+            return Either::Left(self.next.handle_request(request));
         }
         if echo_packet_type != EchoPacketType::Request as u8 {
             eprintln!("The packet type is not acceptable: {}", echo_packet_type);
-            return Err(RejectBuilder {
+            return Either::Right(ready(Err(RejectBuilder {
                 code: ErrorCode::F01_INVALID_PACKET,
                 message: format!(
                     "The echo packet type: {} is not acceptable.",
@@ -100,7 +104,7 @@ where
                 triggered_by: Some(&ilp_address),
                 data: &[],
             }
-            .build());
+            .build())));
         }
 
         // check source address
@@ -112,24 +116,24 @@ where
                         "Could not parse source address from echo packet: {:?}",
                         error
                     );
-                    return Err(RejectBuilder {
+                    return Either::Right(ready(Err(RejectBuilder {
                         code: ErrorCode::F01_INVALID_PACKET,
                         message: b"Could not parse source address from Echo packet",
                         triggered_by: Some(&ilp_address),
                         data: &[],
                     }
-                    .build());
+                    .build())));
                 }
             },
             Err(error) => {
                 eprintln!("Could not read source address: {:?}", error);
-                return Err(RejectBuilder {
+                return Either::Right(ready(Err(RejectBuilder {
                     code: ErrorCode::F01_INVALID_PACKET,
                     message: b"Could not read source address.",
                     triggered_by: Some(&ilp_address),
                     data: &[],
                 }
-                .build());
+                .build())));
             }
         };
 
@@ -144,7 +148,7 @@ where
         }
         .build();
 
-        self.next.handle_request(request).await
+        Either::Left(self.next.handle_request(request))
     }
 }
 
@@ -208,6 +212,7 @@ impl<'a> EchoResponseBuilder<'a> {
 #[cfg(test)]
 mod echo_tests {
     use super::*;
+    use async_trait::async_trait;
     use interledger_errors::AddressStoreError;
     use interledger_packet::{FulfillBuilder, PrepareBuilder};
     use interledger_service::incoming_service_fn;
@@ -1,18 +1,80 @@
-use async_trait::async_trait;
+use futures::future::{ready, Either, Ready};
 use interledger_packet::{ErrorCode, RejectBuilder};
 use interledger_rates::ExchangeRateStore;
-use interledger_service::*;
-use interledger_settlement::core::types::{ConversionError, Convert, ConvertDetails};
-use std::marker::PhantomData;
+use interledger_service::{clock::SharedClock, *};
+use interledger_settlement::core::scale::{
+    apply_rate_unrounded, round_amount, OverflowMode, RoundingMode,
+};
+use serde::Deserialize;
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tracing::{error, trace, warn};
 
+/// How often stale rates are reported with the `Alert` policy
+const STALE_RATE_ALERT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The spread applied by an [`ExchangeRateService`], which can be changed while the
+/// service is running, e.g. when the node's configuration is reloaded
+#[derive(Debug, Clone)]
+pub struct Spread(Arc<AtomicU64>);
+
+impl Spread {
+    pub fn new(spread: f64) -> Self {
+        Spread(Arc::new(AtomicU64::new(spread.to_bits())))
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Changes the spread of all services which share it
+    pub fn set(&self, spread: f64) {
+        self.0.store(spread.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// What an [`ExchangeRateService`] does when the rates of a packet's assets are older
+/// than the configured maximum age, e.g. because the rate provider stopped responding
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StaleRatePolicy {
+    /// Reject the packets with T00 (Internal Error)
+    Reject,
+    /// Use the last known rates, with this spread added to the configured one
+    Spread(f64),
+    /// Use the last known rates, and log an error (at most once a minute)
+    Alert,
+}
+
+impl Default for StaleRatePolicy {
+    fn default() -> Self {
+        StaleRatePolicy::Reject
+    }
+}
+
+#[derive(Clone)]
+struct StaleRates {
+    max_age: Duration,
+    policy: StaleRatePolicy,
+    /// Seconds since the UNIX epoch of the last alert
+    last_alert: Arc<AtomicU64>,
+}
+
 /// # Exchange Rates Service
 ///
 /// Responsible for getting the exchange rates for the two assets in the outgoing request (`request.from.asset_code`, `request.to.asset_code`).
 /// Requires a `ExchangeRateStore`
 #[derive(Clone)]
 pub struct ExchangeRateService<S, O, A> {
-    spread: f64,
+    spread: Spread,
+    stale_rates: Option<StaleRates>,
+    clock: SharedClock,
     store: S,
     next: O,
     account_type: PhantomData<A>,
@@ -25,16 +87,39 @@ where
     A: Account,
 {
     pub fn new(spread: f64, store: S, next: O) -> Self {
+        Self::with_spread(Spread::new(spread), store, next)
+    }
+
+    /// Creates a service whose spread can be changed while it is running
+    pub fn with_spread(spread: Spread, store: S, next: O) -> Self {
         ExchangeRateService {
             spread,
+            stale_rates: None,
+            clock: SharedClock::default(),
             store,
             next,
             account_type: PhantomData,
         }
     }
+
+    /// Applies the policy to the packets whose rates were set more than `max_age` ago.
+    /// Rates of stores which do not track their age are never stale.
+    pub fn stale_rates(mut self, max_age: Duration, policy: StaleRatePolicy) -> Self {
+        self.stale_rates = Some(StaleRates {
+            max_age,
+            policy,
+            last_alert: Arc::new(AtomicU64::new(0)),
+        });
+        self
+    }
+
+    /// Measures the age of the rates with the clock instead of the system's clock
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
 }
 
-#[async_trait]
 impl<S, O, A> OutgoingService<A> for ExchangeRateService<S, O, A>
 where
     // TODO can we make these non-'static?
@@ -42,15 +127,18 @@ where
     O: OutgoingService<A> + Send + Sync + Clone + 'static,
     A: Account + Send + Sync + 'static,
 {
+    type Future<'a> = Either<O::Future<'a>, Ready<IlpResult>>;
+
     /// On send request:
     /// 1. If the prepare packet's amount is 0, it just forwards
     /// 1. Retrieves the exchange rate from the store (the store independently is responsible for polling the rates)
     ///     - return reject if the call to the store fails
     /// 1. Calculates the exchange rate AND scales it up/down depending on how many decimals each asset requires
     /// 1. Updates the amount in the prepare packet and forwards it
-    async fn send_request(&mut self, mut request: OutgoingRequest<A>) -> IlpResult {
+    fn send_request(&mut self, mut request: OutgoingRequest<A>) -> Self::Future<'_> {
         let ilp_address = self.store.get_ilp_address();
         if request.prepare.amount() > 0 {
+            let mut spread = self.spread.get();
             let rates: (f64, f64) = if request.from.asset_code() == request.to.asset_code() {
                 (1f64, 1f64)
             } else if let Ok(rates) = self
@@ -68,7 +156,7 @@ where
                     request.from.asset_code(),
                     request.to.asset_code()
                 );
-                return Err(RejectBuilder {
+                return Either::Right(ready(Err(RejectBuilder {
                     code: ErrorCode::T00_INTERNAL_ERROR,
                     message: format!(
                         "No exchange rate available from asset: {} to: {}",
@@ -79,13 +167,49 @@ where
                     triggered_by: Some(&ilp_address),
                     data: &[],
                 }
-                .build());
+                .build())));
             };
 
+            if let Some(ref stale_rates) = self.stale_rates {
+                let asset_codes = [request.from.asset_code(), request.to.asset_code()];
+                let now = self.clock.now();
+                let age = self
+                    .store
+                    .get_exchange_rates_updated_at(&asset_codes)
+                    .and_then(|updated_at| now.duration_since(updated_at).ok());
+                if asset_codes[0] != asset_codes[1]
+                    && age.map_or(false, |age| age > stale_rates.max_age)
+                {
+                    match stale_rates.policy {
+                        StaleRatePolicy::Reject => {
+                            warn!(
+                                "Rejecting packet because the exchange rates for assets {}, {} are stale",
+                                asset_codes[0], asset_codes[1]
+                            );
+                            return Either::Right(ready(Err(RejectBuilder {
+                                code: ErrorCode::T00_INTERNAL_ERROR,
+                                message: format!(
+                                    "Exchange rate from asset: {} to: {} is stale",
+                                    asset_codes[0], asset_codes[1]
+                                )
+                                .as_bytes(),
+                                triggered_by: Some(&ilp_address),
+                                data: &[],
+                            }
+                            .build())));
+                        }
+                        StaleRatePolicy::Spread(extra_spread) => spread += extra_spread,
+                        StaleRatePolicy::Alert => {
+                            stale_rates.alert(&asset_codes, age.unwrap(), now)
+                        }
+                    }
+                }
+            }
+
             // Can we overflow here?
             let outgoing_amount = calculate_outgoing_amount(
                 request.prepare.amount(),
-                self.spread,
+                spread,
                 rates,
                 (request.from.asset_scale(), request.to.asset_scale()),
             );
@@ -93,9 +217,17 @@ where
             match outgoing_amount {
                 Ok(outgoing_amount) => {
                     request.prepare.set_amount(outgoing_amount as u64);
-                    trace!("Converted incoming amount of: {} {} (scale {}) from account {} to outgoing amount of: {} {} (scale {}) for account {}",
-                        request.original_amount, request.from.asset_code(), request.from.asset_scale(), request.from.id(),
-                        outgoing_amount, request.to.asset_code(), request.to.asset_scale(), request.to.id());
+                    trace!(
+                        from.id = %request.from.id(),
+                        from.amount = request.original_amount,
+                        from.asset_code = request.from.asset_code(),
+                        from.asset_scale = request.from.asset_scale(),
+                        to.id = %request.to.id(),
+                        to.amount = outgoing_amount as u64,
+                        to.asset_code = request.to.asset_code(),
+                        to.asset_scale = request.to.asset_scale(),
+                        "Converted amount"
+                    );
                 }
                 Err(outgoing_amount_error) => {
                     let (code, message) = match outgoing_amount_error {
@@ -129,18 +261,39 @@ where
                             ),
                         ),
                     };
-                    return Err(RejectBuilder {
+                    return Either::Right(ready(Err(RejectBuilder {
                         code,
                         message: message.as_bytes(),
                         triggered_by: Some(&ilp_address),
                         data: &[],
                     }
-                    .build());
+                    .build())));
                 }
             };
         }
 
-        self.next.send_request(request).await
+        Either::Left(self.next.send_request(request))
+    }
+}
+
+impl StaleRates {
+    /// Logs an error about the stale rates, unless one was logged recently
+    fn alert(&self, asset_codes: &[&str], age: Duration, now: SystemTime) {
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let last_alert = self.last_alert.load(Ordering::Relaxed);
+        if now.saturating_sub(last_alert) >= STALE_RATE_ALERT_INTERVAL.as_secs()
+            && self
+                .last_alert
+                .compare_exchange(last_alert, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            error!(
+                "Using exchange rates for assets {}, {} which were set {}s ago",
+                asset_codes[0],
+                asset_codes[1],
+                age.as_secs()
+            );
+        }
     }
 }
 
@@ -170,41 +323,32 @@ fn calculate_outgoing_amount(
         );
         0.0
     };
-    // Can we overflow here?
-    let outgoing_amount = 1.0f64
-        .normalize_scale(ConvertDetails {
-            from: asset_scale_src,
-            to: asset_scale_dest,
-        })
-        .map(|scale| rate * scale * (input as f64));
+    let outgoing_amount = apply_rate_unrounded(input, rate, asset_scale_src, asset_scale_dest);
 
     match outgoing_amount {
         // Happens when rate == 0 or spread >= 1
         // In latter case the node takes everything to itself
         Ok(x) if x == 0.0f64 => Ok(0),
         Ok(x) if x < 1.0f64 => Err(OutgoingAmountError::LessThanOne(x)),
-        Ok(x) if !x.is_finite() => Err(OutgoingAmountError::FloatOverflow),
-        // FIXME: u64::MAX is higher than 2^53 or whatever is the max integer precision in f64
-        Ok(x) if x > u64::MAX as f64 => Err(OutgoingAmountError::ToU64ConvertOverflow(x)),
-        Ok(x) => Ok(x as u64),
+        Ok(x) => round_amount(x, RoundingMode::Down, OverflowMode::Reject)
+            .map_err(|_| OutgoingAmountError::ToU64ConvertOverflow(x)),
         // Error happens if float happens to be std::f64::INFINITY after conversion
-        Err(ConversionError) => Err(OutgoingAmountError::FloatOverflow),
+        Err(_) => Err(OutgoingAmountError::FloatOverflow),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use async_trait::async_trait;
     use interledger_errors::{AddressStoreError, ExchangeRateStoreError};
     use interledger_packet::{Address, Fulfill, FulfillBuilder, PrepareBuilder, Reject};
+    use interledger_service::clock::{Clock, ManualClock};
     use interledger_service::{outgoing_service_fn, Account};
     use once_cell::sync::Lazy;
     use std::collections::HashMap;
     use std::str::FromStr;
-    use std::{
-        sync::{Arc, Mutex},
-        time::SystemTime,
-    };
+    use std::sync::{Arc, Mutex};
     use uuid::Uuid;
 
     pub static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
@@ -238,7 +382,7 @@ mod tests {
             .message()
             .starts_with(b"Could not cast to f64, amount too small"));
 
-        // `Convert` errored
+        // Conversion overflowed f64
         let ret = exchange_rate(std::u64::MAX, 1, std::f64::MAX, 255, 1.0, 0.0).await;
         let reject = ret.0.unwrap_err();
         assert_eq!(reject.code(), ErrorCode::F08_AMOUNT_TOO_LARGE);
@@ -268,6 +412,141 @@ mod tests {
         assert_eq!(ret.1[0].prepare.amount(), 0);
     }
 
+    #[tokio::test]
+    async fn applies_changed_spread() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_clone = requests.clone();
+        let outgoing = outgoing_service_fn(move |request| {
+            requests_clone.lock().unwrap().push(request);
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"hello!",
+            }
+            .build())
+        });
+        let spread = Spread::new(0.0);
+        let mut service =
+            ExchangeRateService::with_spread(spread.clone(), test_store(1.0, 2.0), outgoing);
+
+        let request = || OutgoingRequest {
+            from: TestAccount::new("ABC".to_owned(), 1),
+            to: TestAccount::new("XYZ".to_owned(), 1),
+            original_amount: 100,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now(),
+                execution_condition: &[1; 32],
+                data: b"hello",
+            }
+            .build(),
+        };
+        service.send_request(request()).await.unwrap();
+        spread.set(0.01);
+        service.send_request(request()).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].prepare.amount(), 50);
+        assert_eq!(requests[1].prepare.amount(), 49);
+    }
+
+    #[tokio::test]
+    async fn applies_stale_rate_policy() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_clone = requests.clone();
+        let outgoing = outgoing_service_fn(move |request| {
+            requests_clone.lock().unwrap().push(request);
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"hello!",
+            }
+            .build())
+        });
+        let store = |age: u64| TestStore {
+            updated_at: Some(SystemTime::now() - Duration::from_secs(age)),
+            ..test_store(1.0, 2.0)
+        };
+        let service = |age: u64, policy: StaleRatePolicy| {
+            ExchangeRateService::new(0.0, store(age), outgoing.clone())
+                .stale_rates(Duration::from_secs(60), policy)
+        };
+        let request = || OutgoingRequest {
+            from: TestAccount::new("ABC".to_owned(), 1),
+            to: TestAccount::new("XYZ".to_owned(), 1),
+            original_amount: 100,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now(),
+                execution_condition: &[1; 32],
+                data: b"hello",
+            }
+            .build(),
+        };
+
+        // Fresh rates are used with any policy
+        service(10, StaleRatePolicy::Reject)
+            .send_request(request())
+            .await
+            .unwrap();
+        let reject = service(120, StaleRatePolicy::Reject)
+            .send_request(request())
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T00_INTERNAL_ERROR);
+        assert!(reject.message().ends_with(b"is stale"));
+        service(120, StaleRatePolicy::Spread(0.5))
+            .send_request(request())
+            .await
+            .unwrap();
+        service(120, StaleRatePolicy::Alert)
+            .send_request(request())
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        let amounts: Vec<u64> = requests.iter().map(|r| r.prepare.amount()).collect();
+        assert_eq!(amounts, vec![50, 25, 50]);
+    }
+
+    #[tokio::test]
+    async fn measures_rate_age_with_the_clock() {
+        let clock = ManualClock::default();
+        let store = TestStore {
+            updated_at: Some(clock.now()),
+            ..test_store(1.0, 2.0)
+        };
+        let outgoing = outgoing_service_fn(|_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        });
+        let mut service = ExchangeRateService::new(0.0, store, outgoing)
+            .stale_rates(Duration::from_secs(60), StaleRatePolicy::Reject)
+            .clock(clock.clone().into());
+        let request = || OutgoingRequest {
+            from: TestAccount::new("ABC".to_owned(), 1),
+            to: TestAccount::new("XYZ".to_owned(), 1),
+            original_amount: 100,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now(),
+                execution_condition: &[1; 32],
+                data: &[],
+            }
+            .build(),
+        };
+
+        clock.advance(Duration::from_secs(60));
+        service.send_request(request()).await.unwrap();
+        clock.advance(Duration::from_secs(1));
+        let reject = service.send_request(request()).await.unwrap_err();
+        assert!(reject.message().ends_with(b"is stale"));
+    }
+
     // Errors most likely are caused by floating point errors
     #[test]
     fn calculates_with_small_input() {
@@ -444,6 +723,7 @@ mod tests {
     #[derive(Debug, Clone)]
     struct TestStore {
         rates: HashMap<Vec<String>, (f64, f64)>,
+        updated_at: Option<SystemTime>,
     }
 
     impl ExchangeRateStore for TestStore {
@@ -476,12 +756,19 @@ mod tests {
         fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
             unimplemented!()
         }
+
+        fn get_exchange_rates_updated_at(&self, _asset_codes: &[&str]) -> Option<SystemTime> {
+            self.updated_at
+        }
     }
 
     fn test_store(rate1: f64, rate2: f64) -> TestStore {
         let mut rates = HashMap::new();
         rates.insert(vec!["ABC".to_owned(), "XYZ".to_owned()], (rate1, rate2));
-        TestStore { rates }
+        TestStore {
+            rates,
+            updated_at: None,
+        }
     }
 
     fn test_service(
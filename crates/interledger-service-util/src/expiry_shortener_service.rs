@@ -1,6 +1,5 @@
-use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
-use interledger_service::{Account, IlpResult, OutgoingRequest, OutgoingService};
+use interledger_service::{clock::SharedClock, Account, OutgoingRequest, OutgoingService};
 use tracing::trace;
 
 pub const DEFAULT_ROUND_TRIP_TIME: u32 = 500;
@@ -27,6 +26,7 @@ pub trait RoundTripTimeAccount: Account {
 pub struct ExpiryShortenerService<O> {
     next: O,
     max_expiry_duration: u32,
+    clock: SharedClock,
 }
 
 impl<O> ExpiryShortenerService<O> {
@@ -34,9 +34,16 @@ impl<O> ExpiryShortenerService<O> {
         ExpiryShortenerService {
             next,
             max_expiry_duration: DEFAULT_MAX_EXPIRY_DURATION,
+            clock: SharedClock::default(),
         }
     }
 
+    /// Limits the expiry of the packets relative to the clock instead of the system's clock
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
     // TODO: This isn't used anywhere, should we remove it?
     /// Sets the service's max expiry duration
     pub fn max_expiry_duration(&mut self, milliseconds: u32) -> &mut Self {
@@ -45,25 +52,26 @@ impl<O> ExpiryShortenerService<O> {
     }
 }
 
-#[async_trait]
 impl<O, A> OutgoingService<A> for ExpiryShortenerService<O>
 where
     O: OutgoingService<A> + Send + Sync + 'static,
     A: RoundTripTimeAccount + Send + Sync + 'static,
 {
+    type Future<'a> = O::Future<'a>;
+
     /// On send request:
     /// 1. Get the sender and receiver's roundtrip time (default 1000ms)
     /// 2. Reduce the packet's expiry by that amount
     /// 3. Ensure that the packet expiry does not exceed the maximum expiry duration
     /// 4. Forward the request
-    async fn send_request(&mut self, mut request: OutgoingRequest<A>) -> IlpResult {
+    fn send_request(&mut self, mut request: OutgoingRequest<A>) -> Self::Future<'_> {
         let time_to_subtract =
             i64::from(request.from.round_trip_time() + request.to.round_trip_time());
         let new_expiry = DateTime::<Utc>::from(request.prepare.expires_at())
             - Duration::milliseconds(time_to_subtract);
 
-        let latest_allowable_expiry =
-            Utc::now() + Duration::milliseconds(i64::from(self.max_expiry_duration));
+        let latest_allowable_expiry = DateTime::<Utc>::from(self.clock.now())
+            + Duration::milliseconds(i64::from(self.max_expiry_duration));
         let new_expiry = if new_expiry > latest_allowable_expiry {
             trace!(
                 "Shortening packet expiry duration to {}ms in the future",
@@ -75,7 +83,7 @@ where
         };
 
         request.prepare.set_expires_at(new_expiry.into());
-        self.next.send_request(request).await
+        self.next.send_request(request)
     }
 }
 
@@ -83,6 +91,7 @@ where
 mod tests {
     use super::*;
     use interledger_packet::{Address, ErrorCode, FulfillBuilder, PrepareBuilder, RejectBuilder};
+    use interledger_service::clock::{Clock, ManualClock};
     use interledger_service::{outgoing_service_fn, Username};
     use std::str::FromStr;
     use uuid::Uuid;
@@ -202,4 +211,40 @@ mod tests {
             .await
             .expect("Should have shortened expiry");
     }
+
+    #[tokio::test]
+    async fn reduces_expiry_relative_to_the_clock() {
+        // Packets carry their expiry in milliseconds
+        let clock =
+            ManualClock::new(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000));
+        let latest_expiry = DateTime::<Utc>::from(clock.now()) + Duration::milliseconds(30000);
+        let mut service = ExpiryShortenerService::new(outgoing_service_fn(move |request| {
+            assert_eq!(
+                DateTime::<Utc>::from(request.prepare.expires_at()),
+                latest_expiry
+            );
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        }))
+        .clock(clock.clone().into());
+        service
+            .send_request(OutgoingRequest {
+                from: TestAccount(Uuid::new_v4(), 500),
+                to: TestAccount(Uuid::new_v4(), 500),
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount: 10,
+                    expires_at: clock.now() + std::time::Duration::from_secs(45),
+                    data: &[],
+                    execution_condition: &[0; 32],
+                }
+                .build(),
+                original_amount: 10,
+            })
+            .await
+            .unwrap();
+    }
 }
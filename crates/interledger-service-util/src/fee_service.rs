@@ -0,0 +1,316 @@
+use futures::future::{ready, Either, Ready};
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_service::*;
+use serde::Deserialize;
+use std::{
+    marker::PhantomData,
+    sync::{Arc, RwLock},
+};
+use tracing::{debug, trace};
+
+/// Fee charged for forwarding the packets of an account or of a pair of accounts. Fees are
+/// denominated in the asset of the account which sent the packet.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct FeeSchedule {
+    /// Username of the account which sent the packets. Applies to all accounts if not set.
+    #[serde(default)]
+    pub from: Option<Username>,
+    /// Username of the account to which the packets are forwarded. Applies to all accounts
+    /// if not set.
+    #[serde(default)]
+    pub to: Option<Username>,
+    /// Fee charged per packet
+    #[serde(default)]
+    pub flat: u64,
+    /// Fee charged on the forwarded amount, as a fraction, e.g. 0.001 for 0.1%
+    #[serde(default)]
+    pub percentage: f64,
+}
+
+impl FeeSchedule {
+    fn matches(&self, from: &Username, to: &Username) -> bool {
+        self.from.as_ref().map_or(true, |username| username == from)
+            && self.to.as_ref().map_or(true, |username| username == to)
+    }
+
+    /// Rank of the schedule, the most specific one which matches a packet applies
+    fn specificity(&self) -> u8 {
+        match (&self.from, &self.to) {
+            (Some(_), Some(_)) => 3,
+            (Some(_), None) => 2,
+            (None, Some(_)) => 1,
+            (None, None) => 0,
+        }
+    }
+
+    /// Returns the fee charged on a packet of the given amount, rounded up
+    pub fn fee(&self, amount: u64) -> u64 {
+        let percentage_fee = (amount as f64 * self.percentage).ceil();
+        // Casting saturates for amounts above u64::MAX
+        self.flat.saturating_add(percentage_fee.max(0.0) as u64)
+    }
+}
+
+/// The fee schedules applied by a [`FeeService`], which can be changed while the service
+/// is running, e.g. when the node's configuration is reloaded
+#[derive(Debug, Clone, Default)]
+pub struct Fees(Arc<RwLock<Vec<FeeSchedule>>>);
+
+impl Fees {
+    pub fn new(schedules: Vec<FeeSchedule>) -> Self {
+        Fees(Arc::new(RwLock::new(schedules)))
+    }
+
+    /// Returns the most specific schedule which applies to packets from one account to the other
+    pub fn get(&self, from: &Username, to: &Username) -> Option<FeeSchedule> {
+        self.0
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|schedule| schedule.matches(from, to))
+            .max_by_key(|schedule| schedule.specificity())
+            .cloned()
+    }
+
+    /// Changes the schedules of all services which share them
+    pub fn set(&self, schedules: Vec<FeeSchedule>) {
+        *self.0.write().unwrap() = schedules;
+    }
+}
+
+/// # Fee Service
+///
+/// Deducts the fee of the most specific matching [`FeeSchedule`] from the amount of the
+/// forwarded packets. It must come before the `ExchangeRateService`, so that the fee is
+/// deducted in the asset of the sending account, and before the `BalanceService`, which
+/// then debits the sending account the full amount and credits the receiving one without the fee.
+/// Packets whose amount does not cover the fee are rejected with R01 (Insufficient Source Amount).
+/// Requires an `AddressStore`.
+#[derive(Clone)]
+pub struct FeeService<S, O, A> {
+    fees: Fees,
+    store: S,
+    next: O,
+    account_type: PhantomData<A>,
+}
+
+impl<S, O, A> FeeService<S, O, A>
+where
+    S: AddressStore,
+    O: OutgoingService<A>,
+    A: Account,
+{
+    pub fn new(fees: Fees, store: S, next: O) -> Self {
+        FeeService {
+            fees,
+            store,
+            next,
+            account_type: PhantomData,
+        }
+    }
+}
+
+impl<S, O, A> OutgoingService<A> for FeeService<S, O, A>
+where
+    S: AddressStore + Clone + Send + Sync + 'static,
+    O: OutgoingService<A> + Send + Sync + Clone + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    type Future<'a> = Either<O::Future<'a>, Ready<IlpResult>>;
+
+    /// On send request:
+    /// 1. If the prepare packet's amount is 0 or no schedule matches its accounts, it just forwards
+    /// 1. Rejects the packet if its amount does not exceed the fee
+    /// 1. Deducts the fee from the amount in the prepare packet and forwards it
+    fn send_request(&mut self, mut request: OutgoingRequest<A>) -> Self::Future<'_> {
+        let amount = request.prepare.amount();
+        if amount == 0 {
+            return Either::Left(self.next.send_request(request));
+        }
+        let schedule = match self
+            .fees
+            .get(request.from.username(), request.to.username())
+        {
+            Some(schedule) => schedule,
+            None => return Either::Left(self.next.send_request(request)),
+        };
+
+        let fee = schedule.fee(amount);
+        if fee >= amount {
+            debug!(
+                "Rejecting packet of amount {} which does not cover the fee of {}",
+                amount, fee
+            );
+            return Either::Right(ready(Err(RejectBuilder {
+                code: ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
+                message: format!("Amount does not cover the forwarding fee of {}", fee).as_bytes(),
+                triggered_by: Some(&self.store.get_ilp_address()),
+                data: &[],
+            }
+            .build())));
+        }
+
+        request.prepare.set_amount(amount - fee);
+        trace!(
+            from.id = %request.from.id(),
+            to.id = %request.to.id(),
+            amount,
+            fee,
+            "Deducted forwarding fee"
+        );
+        Either::Left(self.next.send_request(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::sync::Mutex;
+    use std::time::SystemTime;
+    use uuid::Uuid;
+
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    #[derive(Debug, Clone)]
+    struct TestAccount(Username);
+
+    impl TestAccount {
+        fn new(username: &str) -> Self {
+            TestAccount(Username::from_str(username).unwrap())
+        }
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::new_v4()
+        }
+
+        fn username(&self) -> &Username {
+            &self.0
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    fn schedule(from: Option<&str>, to: Option<&str>, flat: u64, percentage: f64) -> FeeSchedule {
+        FeeSchedule {
+            from: from.map(|username| Username::from_str(username).unwrap()),
+            to: to.map(|username| Username::from_str(username).unwrap()),
+            flat,
+            percentage,
+        }
+    }
+
+    /// Sends a packet of the given amount from `from` to `to`, returning the forwarded amount
+    async fn forward(fees: &Fees, from: &str, to: &str, amount: u64) -> Result<u64, ErrorCode> {
+        let amounts = Arc::new(Mutex::new(Vec::new()));
+        let amounts_clone = amounts.clone();
+        let next = outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+            amounts_clone.lock().unwrap().push(request.prepare.amount());
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"hello!",
+            }
+            .build())
+        });
+        let mut service = FeeService::new(fees.clone(), TestStore, next);
+        let result = service
+            .send_request(OutgoingRequest {
+                from: TestAccount::new(from),
+                to: TestAccount::new(to),
+                original_amount: amount,
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount,
+                    expires_at: SystemTime::now(),
+                    execution_condition: &[1; 32],
+                    data: b"hello",
+                }
+                .build(),
+            })
+            .await;
+        match result {
+            Ok(_) => Ok(amounts.lock().unwrap()[0]),
+            Err(reject) => Err(reject.code()),
+        }
+    }
+
+    #[tokio::test]
+    async fn deducts_flat_and_percentage_fees() {
+        let fees = Fees::new(vec![schedule(None, None, 10, 0.01)]);
+        assert_eq!(forward(&fees, "alice", "bob", 1000).await, Ok(980));
+        // The percentage fee is rounded up
+        assert_eq!(forward(&fees, "alice", "bob", 101).await, Ok(89));
+        // Packets without an amount are forwarded as they are
+        assert_eq!(forward(&fees, "alice", "bob", 0).await, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn applies_the_most_specific_schedule() {
+        let fees = Fees::new(vec![
+            schedule(None, None, 1, 0.0),
+            schedule(None, Some("bob"), 2, 0.0),
+            schedule(Some("alice"), None, 3, 0.0),
+            schedule(Some("alice"), Some("bob"), 4, 0.0),
+        ]);
+        assert_eq!(forward(&fees, "alice", "bob", 100).await, Ok(96));
+        assert_eq!(forward(&fees, "alice", "carol", 100).await, Ok(97));
+        assert_eq!(forward(&fees, "carol", "bob", 100).await, Ok(98));
+        assert_eq!(forward(&fees, "carol", "dave", 100).await, Ok(99));
+
+        let fees = Fees::new(vec![schedule(Some("alice"), None, 3, 0.0)]);
+        assert_eq!(forward(&fees, "carol", "bob", 100).await, Ok(100));
+    }
+
+    #[tokio::test]
+    async fn rejects_amounts_which_do_not_cover_the_fee() {
+        let fees = Fees::new(vec![schedule(None, None, 10, 0.0)]);
+        assert_eq!(
+            forward(&fees, "alice", "bob", 10).await,
+            Err(ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT)
+        );
+        assert_eq!(forward(&fees, "alice", "bob", 11).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn applies_changed_schedules() {
+        let fees = Fees::default();
+        assert_eq!(forward(&fees, "alice", "bob", 100).await, Ok(100));
+        fees.set(vec![schedule(Some("alice"), Some("bob"), 5, 0.0)]);
+        assert_eq!(forward(&fees, "alice", "bob", 100).await, Ok(95));
+    }
+}
@@ -2,8 +2,16 @@
 //!
 //! Miscellaneous, small Interledger Services.
 
+/// Service which applies the limits and the balance change of a Prepare packet in a single call to the store
+mod admission_service;
+/// Service responsible for rejecting packets between accounts whose assets cannot be converted
+mod asset_guard_service;
 /// Balance tracking service
 mod balance_service;
+/// Service responsible for rejecting packets to destinations which are not permitted
+mod destination_filter_service;
+/// Service which short-circuits the Prepare packets received again while the original is pending
+mod duplicate_prepare_service;
 /// Service which implements the echo protocol
 mod echo_service;
 /// Service responsible for setting and fetching dollar denominated exchange rates
@@ -11,22 +19,57 @@ mod exchange_rates_service;
 /// Service responsible for shortening the expiry time of packets,
 /// to take into account for network latency
 mod expiry_shortener_service;
+/// Service which deducts the configured forwarding fees from the packets
+mod fee_service;
 /// Service responsible for capping the amount an account can send in a packet
 mod max_packet_amount_service;
+/// Service responsible for rejecting packets whose amount is too small to be worth forwarding
+mod min_packet_amount_service;
+/// Summaries of the packets sent by and to the accounts
+mod packet_history;
+/// Journal of the outgoing packets in flight, for recovering their balance changes after a crash
+mod packet_journal;
+/// Service responsible for capping the number of requests in flight to each peer
+mod peer_concurrency_service;
 /// Service responsible for capping the amount of packets and amount in packets an account can send
 mod rate_limit_service;
 /// Service responsible for checking that packets are not expired and that prepare packets' fulfillment conditions
 /// match the fulfillment inside the incoming fulfills
 mod validator_service;
+/// Service responsible for capping the amount an account can send per day
+mod volume_limit_service;
 
-pub use self::balance_service::{start_delayed_settlement, BalanceService, BalanceStore};
+pub use self::admission_service::{AdmissionError, AdmissionService, AdmissionStore};
+pub use self::asset_guard_service::AssetGuardService;
+pub use self::balance_service::{
+    start_delayed_settlement, start_settlement_retries, BalanceChange, BalanceChangeReason,
+    BalanceService, BalanceStore, BalanceTotals, PacketId, ReconciliationReport,
+};
+pub use self::destination_filter_service::{
+    DestinationFilterService, DestinationRule, DestinationRules,
+};
+pub use self::duplicate_prepare_service::{DuplicatePreparePolicy, DuplicatePrepareService};
 pub use self::echo_service::EchoService;
-pub use self::exchange_rates_service::ExchangeRateService;
+pub use self::exchange_rates_service::{ExchangeRateService, Spread, StaleRatePolicy};
 pub use self::expiry_shortener_service::{
     ExpiryShortenerService, RoundTripTimeAccount, DEFAULT_ROUND_TRIP_TIME,
 };
+pub use self::fee_service::{FeeSchedule, FeeService, Fees};
 pub use self::max_packet_amount_service::{MaxPacketAmountAccount, MaxPacketAmountService};
+pub use self::min_packet_amount_service::{MinPacketAmountAccount, MinPacketAmountService};
+pub use self::packet_history::{
+    DestinationVolume, PacketDirection, PacketHistoryFilter, PacketHistoryStore, PacketOutcome,
+    PacketRecord, PacketStatistics, StatisticsBucket,
+};
+pub use self::packet_journal::{
+    reconcile_journaled_packets, start_journal_reconciliation, JournaledPacket, PacketJournalStore,
+    JOURNAL_RECONCILIATION_DELAY,
+};
+pub use self::peer_concurrency_service::PeerConcurrencyService;
 pub use self::rate_limit_service::{
     RateLimitAccount, RateLimitError, RateLimitService, RateLimitStore,
 };
 pub use self::validator_service::ValidatorService;
+pub use self::volume_limit_service::{
+    current_day, VolumeLimitAccount, VolumeLimitError, VolumeLimitService, VolumeLimitStore,
+};
@@ -1,4 +1,4 @@
-use async_trait::async_trait;
+use futures::future::{ready, Either, Ready};
 use interledger_packet::{ErrorCode, MaxPacketAmountDetails, RejectBuilder};
 use interledger_service::*;
 use tracing::debug;
@@ -30,20 +30,21 @@ impl<I, S> MaxPacketAmountService<I, S> {
     }
 }
 
-#[async_trait]
 impl<I, S, A> IncomingService<A> for MaxPacketAmountService<I, S>
 where
     I: IncomingService<A> + Send + Sync + 'static,
     S: AddressStore + Send + Sync + 'static,
     A: MaxPacketAmountAccount + Send + Sync + 'static,
 {
+    type Future<'a> = Either<I::Future<'a>, Ready<IlpResult>>;
+
     /// On receive request:
     /// 1. if request.prepare.amount <= request.from.max_packet_amount forward the request, else error
-    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
         let ilp_address = self.store.get_ilp_address();
         let max_packet_amount = request.from.max_packet_amount();
         if request.prepare.amount() <= max_packet_amount {
-            self.next.handle_request(request).await
+            Either::Left(self.next.handle_request(request))
         } else {
             debug!(
                 "Prepare amount:{} exceeds max_packet_amount: {}",
@@ -52,13 +53,13 @@ where
             );
             let details =
                 MaxPacketAmountDetails::new(request.prepare.amount(), max_packet_amount).to_bytes();
-            Err(RejectBuilder {
+            Either::Right(ready(Err(RejectBuilder {
                 code: ErrorCode::F08_AMOUNT_TOO_LARGE,
                 message: &[],
                 triggered_by: Some(&ilp_address),
                 data: &details[..],
             }
-            .build())
+            .build())))
         }
     }
 }
@@ -66,6 +67,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use async_trait::async_trait;
     use interledger_errors::AddressStoreError;
     use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
     use once_cell::sync::Lazy;
@@ -0,0 +1,181 @@
+use futures::future::{ready, Either, Ready};
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_service::*;
+use tracing::debug;
+
+/// Extension trait for [`Account`](../interledger_service/trait.Account.html) with the min packet amount
+/// forwarded for this account
+pub trait MinPacketAmountAccount: Account {
+    fn min_packet_amount(&self) -> u64;
+}
+
+/// # MinPacketAmount Service
+///
+/// This service is used by nodes to reject dust packets, whose amounts are too small to be worth
+/// the balance updates and store writes which forwarding them takes.
+/// Packets without an amount are always forwarded, as they are used by protocols such as ILDCP,
+/// CCP and STREAM to exchange messages rather than money.
+/// Rejected packets get an `F99: Application Error` with a message stating the minimum amount,
+/// as there is no error code for amounts which are too small.
+/// Requires a `MinPacketAmountAccount` and an `AddressStore`.
+#[derive(Clone)]
+pub struct MinPacketAmountService<I, S> {
+    next: I,
+    store: S,
+}
+
+impl<I, S> MinPacketAmountService<I, S> {
+    /// Simple constructor
+    pub fn new(store: S, next: I) -> Self {
+        MinPacketAmountService { next, store }
+    }
+}
+
+impl<I, S, A> IncomingService<A> for MinPacketAmountService<I, S>
+where
+    I: IncomingService<A> + Send + Sync + 'static,
+    S: AddressStore + Send + Sync + 'static,
+    A: MinPacketAmountAccount + Send + Sync + 'static,
+{
+    type Future<'a> = Either<I::Future<'a>, Ready<IlpResult>>;
+
+    /// On receive request:
+    /// 1. if request.prepare.amount is 0 or >= request.from.min_packet_amount forward the request, else error
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        let amount = request.prepare.amount();
+        let min_packet_amount = request.from.min_packet_amount();
+        if amount == 0 || amount >= min_packet_amount {
+            Either::Left(self.next.handle_request(request))
+        } else {
+            debug!(
+                "Prepare amount: {} is below min_packet_amount: {}",
+                amount, min_packet_amount
+            );
+            let ilp_address = self.store.get_ilp_address();
+            Either::Right(ready(Err(RejectBuilder {
+                code: ErrorCode::F99_APPLICATION_ERROR,
+                message: format!(
+                    "Packet amount {} is below the minimum of {}",
+                    amount, min_packet_amount
+                )
+                .as_bytes(),
+                triggered_by: Some(&ilp_address),
+                data: &[],
+            }
+            .build())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    #[derive(Debug, Clone)]
+    struct TestAccount(u64);
+
+    impl MinPacketAmountAccount for TestAccount {
+        fn min_packet_amount(&self) -> u64 {
+            self.0
+        }
+    }
+
+    fn request(min_packet_amount: u64, amount: u64) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount(min_packet_amount),
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at: std::time::SystemTime::now() + std::time::Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: b"test data",
+            }
+            .build(),
+        }
+    }
+
+    fn service() -> MinPacketAmountService<impl IncomingService<TestAccount>, TestStore> {
+        let next = incoming_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        MinPacketAmountService::new(TestStore, next)
+    }
+
+    #[tokio::test]
+    async fn above_min_amount() {
+        let fulfill = service().handle_request(request(100, 100)).await.unwrap();
+        assert_eq!(fulfill.data(), b"test data");
+    }
+
+    #[tokio::test]
+    async fn below_min_amount() {
+        let reject = service()
+            .handle_request(request(100, 99))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F99_APPLICATION_ERROR);
+        assert_eq!(
+            reject.message(),
+            &b"Packet amount 99 is below the minimum of 100"[..]
+        );
+    }
+
+    #[tokio::test]
+    async fn forwards_packets_without_amount() {
+        assert!(service().handle_request(request(100, 0)).await.is_ok());
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::new_v4()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+}
@@ -0,0 +1,392 @@
+use async_trait::async_trait;
+use interledger_errors::BalanceStoreError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Direction of a packet, from the account holder's perspective
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PacketDirection {
+    /// The packet was sent to the account
+    Incoming,
+    /// The packet was sent by the account
+    Outgoing,
+}
+
+impl PacketDirection {
+    /// The name of the direction, as it is serialized and stored
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PacketDirection::Incoming => "incoming",
+            PacketDirection::Outgoing => "outgoing",
+        }
+    }
+}
+
+impl FromStr for PacketDirection {
+    type Err = ();
+
+    fn from_str(string: &str) -> Result<Self, ()> {
+        match string {
+            "incoming" => Ok(PacketDirection::Incoming),
+            "outgoing" => Ok(PacketDirection::Outgoing),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for PacketDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Whether a packet was fulfilled or rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PacketOutcome {
+    Fulfilled,
+    Rejected,
+}
+
+impl PacketOutcome {
+    /// The name of the outcome, as it is serialized and stored
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PacketOutcome::Fulfilled => "fulfilled",
+            PacketOutcome::Rejected => "rejected",
+        }
+    }
+}
+
+impl FromStr for PacketOutcome {
+    type Err = ();
+
+    fn from_str(string: &str) -> Result<Self, ()> {
+        match string {
+            "fulfilled" => Ok(PacketOutcome::Fulfilled),
+            "rejected" => Ok(PacketOutcome::Rejected),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for PacketOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Summary of a packet which was sent by or to an account. A packet forwarded by the
+/// node has a record for each of the two accounts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketRecord {
+    /// Identifies the record, and is used as the cursor of the history. It is assigned by
+    /// the store, which ignores the value of the records it is given.
+    pub id: u64,
+    /// Time at which the packet was fulfilled or rejected, in milliseconds since the UNIX epoch
+    pub timestamp: u64,
+    pub account_id: Uuid,
+    pub direction: PacketDirection,
+    pub outcome: PacketOutcome,
+    /// Amount of the packet, in the account's asset
+    pub amount: u64,
+    /// ILP address of the packet's destination
+    pub destination: String,
+    /// ILP error code of the Reject packet, if the packet was rejected
+    pub reject_code: Option<String>,
+}
+
+/// Selects the records which are returned from an account's packet history
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PacketHistoryFilter {
+    /// Only return the records older than the one with this id
+    pub before: Option<u64>,
+    /// Only return the records with a timestamp greater than or equal to this one
+    pub since: Option<u64>,
+    /// Only return the records with a timestamp less than this one
+    pub until: Option<u64>,
+    pub direction: Option<PacketDirection>,
+    pub outcome: Option<PacketOutcome>,
+}
+
+impl PacketHistoryFilter {
+    /// Returns true if the record is selected by the filter
+    pub fn matches(&self, record: &PacketRecord) -> bool {
+        self.before.map_or(true, |before| record.id < before)
+            && self.since.map_or(true, |since| record.timestamp >= since)
+            && self.until.map_or(true, |until| record.timestamp < until)
+            && self
+                .direction
+                .map_or(true, |direction| record.direction == direction)
+            && self
+                .outcome
+                .map_or(true, |outcome| record.outcome == outcome)
+    }
+}
+
+/// Totals of an account's packets which were fulfilled or rejected during an interval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatisticsBucket {
+    /// Start of the interval, in milliseconds since the UNIX epoch
+    pub start: u64,
+    /// Amount of the fulfilled packets sent by the account
+    pub sent: u64,
+    /// Amount of the fulfilled packets sent to the account
+    pub received: u64,
+    pub fulfilled: u64,
+    pub rejected: u64,
+    /// Share of the packets which were fulfilled, or `None` if there were no packets
+    pub success_rate: Option<f64>,
+}
+
+/// Total amount of the fulfilled packets an account sent to a destination
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DestinationVolume {
+    pub destination: String,
+    pub amount: u64,
+    pub packets: u64,
+}
+
+/// Aggregates of an account's packet history over a period, split in intervals of equal
+/// length, from which dashboards can chart the account's activity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PacketStatistics {
+    pub account_id: Uuid,
+    /// Start of the period, in milliseconds since the UNIX epoch
+    pub since: u64,
+    /// End of the period (exclusive), in milliseconds since the UNIX epoch
+    pub until: u64,
+    /// Length of the intervals, in milliseconds
+    pub interval: u64,
+    /// The totals of each interval, oldest first
+    pub buckets: Vec<StatisticsBucket>,
+    /// The destinations to which the account sent the largest amounts during the period
+    pub top_destinations: Vec<DestinationVolume>,
+}
+
+impl PacketStatistics {
+    /// Aggregates the account's records from `since` until `until`. The last interval is
+    /// cut short if the period is not a multiple of the interval, and records outside of
+    /// the period are ignored. At most `top` destinations are returned.
+    pub fn new<'a>(
+        account_id: Uuid,
+        since: u64,
+        until: u64,
+        interval: u64,
+        records: impl IntoIterator<Item = &'a PacketRecord>,
+        top: usize,
+    ) -> Self {
+        let interval = interval.max(1);
+        let mut buckets: Vec<StatisticsBucket> = (since..until)
+            .step_by(interval as usize)
+            .map(|start| StatisticsBucket {
+                start,
+                sent: 0,
+                received: 0,
+                fulfilled: 0,
+                rejected: 0,
+                success_rate: None,
+            })
+            .collect();
+        let mut destinations: HashMap<&str, DestinationVolume> = HashMap::new();
+
+        for record in records {
+            if record.timestamp < since || record.timestamp >= until {
+                continue;
+            }
+            let bucket = &mut buckets[((record.timestamp - since) / interval) as usize];
+            if record.outcome == PacketOutcome::Rejected {
+                bucket.rejected += 1;
+                continue;
+            }
+            bucket.fulfilled += 1;
+            match record.direction {
+                PacketDirection::Incoming => {
+                    bucket.received = bucket.received.saturating_add(record.amount)
+                }
+                PacketDirection::Outgoing => {
+                    bucket.sent = bucket.sent.saturating_add(record.amount);
+                    let volume = destinations.entry(&record.destination).or_insert_with(|| {
+                        DestinationVolume {
+                            destination: record.destination.clone(),
+                            amount: 0,
+                            packets: 0,
+                        }
+                    });
+                    volume.amount = volume.amount.saturating_add(record.amount);
+                    volume.packets += 1;
+                }
+            }
+        }
+
+        for bucket in buckets.iter_mut() {
+            let packets = bucket.fulfilled + bucket.rejected;
+            if packets > 0 {
+                bucket.success_rate = Some(bucket.fulfilled as f64 / packets as f64);
+            }
+        }
+        let mut top_destinations: Vec<DestinationVolume> =
+            destinations.into_iter().map(|(_, volume)| volume).collect();
+        top_destinations.sort_by(|a, b| {
+            b.amount
+                .cmp(&a.amount)
+                .then_with(|| a.destination.cmp(&b.destination))
+        });
+        top_destinations.truncate(top);
+
+        PacketStatistics {
+            account_id,
+            since,
+            until,
+            interval,
+            buckets,
+            top_destinations,
+        }
+    }
+}
+
+/// Store for the summaries of the packets sent by and to the accounts, which wallets
+/// use to show the transaction history
+#[async_trait]
+pub trait PacketHistoryStore {
+    /// Appends the records to the history of their accounts
+    async fn record_packets(&self, records: Vec<PacketRecord>) -> Result<(), BalanceStoreError>;
+
+    /// Returns at most `limit` records of the account's history which match the
+    /// filter, newest first
+    async fn get_packet_history(
+        &self,
+        account_id: Uuid,
+        filter: &PacketHistoryFilter,
+        limit: usize,
+    ) -> Result<Vec<PacketRecord>, BalanceStoreError>;
+
+    /// Deletes the records with a timestamp older than `older_than` and returns how
+    /// many were deleted
+    async fn prune_packet_history(&self, older_than: u64) -> Result<u64, BalanceStoreError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: u64, timestamp: u64) -> PacketRecord {
+        PacketRecord {
+            id,
+            timestamp,
+            account_id: Uuid::nil(),
+            direction: PacketDirection::Incoming,
+            outcome: PacketOutcome::Fulfilled,
+            amount: 100,
+            destination: "example.alice".to_string(),
+            reject_code: None,
+        }
+    }
+
+    #[test]
+    fn filters_records() {
+        let filter = PacketHistoryFilter::default();
+        assert!(filter.matches(&record(1, 1000)));
+
+        let filter = PacketHistoryFilter {
+            before: Some(5),
+            since: Some(1000),
+            until: Some(2000),
+            ..Default::default()
+        };
+        assert!(filter.matches(&record(4, 1000)));
+        assert!(!filter.matches(&record(5, 1500)));
+        assert!(!filter.matches(&record(4, 999)));
+        assert!(!filter.matches(&record(4, 2000)));
+
+        let filter = PacketHistoryFilter {
+            direction: Some(PacketDirection::Outgoing),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&record(1, 1000)));
+        let filter = PacketHistoryFilter {
+            outcome: Some(PacketOutcome::Fulfilled),
+            ..Default::default()
+        };
+        assert!(filter.matches(&record(1, 1000)));
+    }
+
+    #[test]
+    fn aggregates_records() {
+        let mut records = vec![
+            record(1, 1000),
+            record(2, 1500),
+            record(3, 2500),
+            record(4, 4000),
+            record(5, 500),
+        ];
+        records[1].direction = PacketDirection::Outgoing;
+        records[1].destination = "example.bob".to_string();
+        records[2].direction = PacketDirection::Outgoing;
+        records[2].outcome = PacketOutcome::Rejected;
+        let mut record_6 = record(6, 2999);
+        record_6.direction = PacketDirection::Outgoing;
+        record_6.amount = 300;
+        records.push(record_6);
+
+        let statistics = PacketStatistics::new(Uuid::nil(), 1000, 3500, 1000, &records, 10);
+        let buckets: Vec<(u64, u64, u64, u64, u64, Option<f64>)> = statistics
+            .buckets
+            .iter()
+            .map(|bucket| {
+                (
+                    bucket.start,
+                    bucket.sent,
+                    bucket.received,
+                    bucket.fulfilled,
+                    bucket.rejected,
+                    bucket.success_rate,
+                )
+            })
+            .collect();
+        assert_eq!(
+            buckets,
+            vec![
+                (1000, 100, 100, 2, 0, Some(1.0)),
+                (2000, 300, 0, 1, 1, Some(0.5)),
+                (3000, 0, 0, 0, 0, None),
+            ]
+        );
+        assert_eq!(
+            statistics.top_destinations,
+            vec![
+                DestinationVolume {
+                    destination: "example.alice".to_string(),
+                    amount: 300,
+                    packets: 1,
+                },
+                DestinationVolume {
+                    destination: "example.bob".to_string(),
+                    amount: 100,
+                    packets: 1,
+                },
+            ]
+        );
+
+        let statistics = PacketStatistics::new(Uuid::nil(), 1000, 3500, 1000, &records, 1);
+        assert_eq!(statistics.top_destinations.len(), 1);
+    }
+
+    #[test]
+    fn parses_names() {
+        for direction in &[PacketDirection::Incoming, PacketDirection::Outgoing] {
+            assert_eq!(
+                PacketDirection::from_str(direction.as_str()),
+                Ok(*direction)
+            );
+        }
+        for outcome in &[PacketOutcome::Fulfilled, PacketOutcome::Rejected] {
+            assert_eq!(PacketOutcome::from_str(outcome.as_str()), Ok(*outcome));
+        }
+        assert!(PacketOutcome::from_str("pending").is_err());
+    }
+}
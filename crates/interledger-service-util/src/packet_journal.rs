@@ -0,0 +1,299 @@
+use super::balance_service::{BalanceStore, PacketId};
+use async_trait::async_trait;
+use interledger_errors::BalanceStoreError;
+use interledger_service::runtime;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{error, info};
+use uuid::Uuid;
+
+/// An outgoing Prepare packet whose response was not applied to the balances yet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournaledPacket {
+    /// Id of the entry in the journal
+    pub id: Uuid,
+    /// The account which sent the packet
+    pub from_id: Uuid,
+    /// The account to which the packet was forwarded
+    pub to_id: Uuid,
+    /// The amount of the packet the sender was charged
+    pub incoming_amount: u64,
+    /// The amount of the packet which was forwarded
+    pub outgoing_amount: u64,
+    /// The execution condition of the packet
+    pub packet_id: Option<PacketId>,
+    /// Expiry of the packet, in milliseconds since the UNIX epoch
+    pub expires_at: u64,
+    /// Whether the packet was fulfilled. Packets which were rejected are removed from the
+    /// journal right away.
+    pub fulfilled: bool,
+}
+
+/// Journal of the outgoing Prepare packets which are in flight, so that the balance changes
+/// of their responses can be applied after a crash
+#[async_trait]
+pub trait PacketJournalStore {
+    /// Adds a packet to the journal before it is forwarded
+    async fn journal_packet(&self, packet: JournaledPacket) -> Result<(), BalanceStoreError>;
+
+    /// Records that the packet was fulfilled, before the Fulfill is passed back
+    async fn mark_journaled_packet_fulfilled(&self, id: Uuid) -> Result<(), BalanceStoreError>;
+
+    /// Removes the packet from the journal once the balances were updated for its response
+    async fn remove_journaled_packet(&self, id: Uuid) -> Result<(), BalanceStoreError>;
+
+    /// Returns all the packets in the journal
+    async fn load_journaled_packets(&self) -> Result<Vec<JournaledPacket>, BalanceStoreError>;
+}
+
+/// Time after the expiry of a packet until its entry is reconciled, which leaves the node
+/// which forwarded it enough time to apply the balance changes of its response
+pub const JOURNAL_RECONCILIATION_DELAY: Duration = Duration::from_secs(60);
+
+/// Applies the balance changes of the journaled packets which expired more than
+/// [`JOURNAL_RECONCILIATION_DELAY`] ago, which the node forwarding them did not apply,
+/// e.g. because it crashed while they were in flight. The receivers of the packets which
+/// were fulfilled are credited, and the senders of the others are refunded, since their
+/// packets expired. Returns the number of packets which were reconciled.
+///
+/// The settlements the credited amounts may call for are left to the next fulfilled packet
+/// or the delayed settlement of the account.
+pub async fn reconcile_journaled_packets<S>(store: &S, now: SystemTime) -> usize
+where
+    S: BalanceStore + PacketJournalStore,
+{
+    let packets = match store.load_journaled_packets().await {
+        Ok(packets) => packets,
+        Err(err) => {
+            error!("Error loading the journaled packets: {}", err);
+            return 0;
+        }
+    };
+    let cutoff = now
+        .checked_sub(JOURNAL_RECONCILIATION_DELAY)
+        .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+        .map(|cutoff| cutoff.as_millis() as u64)
+        .unwrap_or_default();
+
+    let mut reconciled = 0;
+    for packet in packets
+        .into_iter()
+        .filter(|packet| packet.expires_at < cutoff)
+    {
+        let result = if packet.fulfilled {
+            store
+                .update_balances_for_fulfill_and_queue_settlement(
+                    packet.to_id,
+                    packet.outgoing_amount,
+                    packet.packet_id,
+                )
+                .await
+                .map(|_| ())
+        } else {
+            store
+                .update_balances_for_reject(
+                    packet.from_id,
+                    packet.incoming_amount,
+                    packet.packet_id,
+                )
+                .await
+        };
+        if let Err(err) = result {
+            error!(
+                "Error applying the balance changes of journaled packet {}: {}",
+                packet.id, err
+            );
+            continue;
+        }
+        if let Err(err) = store.remove_journaled_packet(packet.id).await {
+            // The balance changes would be applied again at the next reconciliation
+            error!(
+                "Error removing reconciled packet {} from the journal: {}",
+                packet.id, err
+            );
+            continue;
+        }
+        info!(
+            "Reconciled journaled packet {} from account {} to account {}, which was {}",
+            packet.id,
+            packet.from_id,
+            packet.to_id,
+            if packet.fulfilled {
+                "fulfilled"
+            } else {
+                "not fulfilled before it expired"
+            }
+        );
+        reconciled += 1;
+    }
+    reconciled
+}
+
+/// Start a background task which reconciles the journaled packets every `interval`, starting
+/// right away so that the packets left in flight by a crash are reconciled once the node is
+/// restarted.
+pub fn start_journal_reconciliation<S>(interval: Duration, store: S) -> runtime::TaskHandle
+where
+    S: BalanceStore + PacketJournalStore + Send + Sync + 'static,
+{
+    runtime::spawn_abortable(async move {
+        info!(
+            "Starting to reconcile the journaled packets every {:?}",
+            interval
+        );
+        let mut interval = runtime::interval(interval);
+        loop {
+            interval.tick().await;
+            reconcile_journaled_packets(&store, SystemTime::now()).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BalanceChange, BalanceTotals};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Journal which records the balance changes applied by the reconciliation
+    #[derive(Default)]
+    struct TestStore {
+        journal: Mutex<HashMap<Uuid, JournaledPacket>>,
+        /// (account, delta) of the balance changes
+        changes: Mutex<Vec<(Uuid, i64)>>,
+    }
+
+    #[async_trait]
+    impl PacketJournalStore for TestStore {
+        async fn journal_packet(&self, packet: JournaledPacket) -> Result<(), BalanceStoreError> {
+            self.journal.lock().unwrap().insert(packet.id, packet);
+            Ok(())
+        }
+
+        async fn mark_journaled_packet_fulfilled(&self, id: Uuid) -> Result<(), BalanceStoreError> {
+            if let Some(packet) = self.journal.lock().unwrap().get_mut(&id) {
+                packet.fulfilled = true;
+            }
+            Ok(())
+        }
+
+        async fn remove_journaled_packet(&self, id: Uuid) -> Result<(), BalanceStoreError> {
+            self.journal.lock().unwrap().remove(&id);
+            Ok(())
+        }
+
+        async fn load_journaled_packets(&self) -> Result<Vec<JournaledPacket>, BalanceStoreError> {
+            Ok(self.journal.lock().unwrap().values().cloned().collect())
+        }
+    }
+
+    #[async_trait]
+    impl BalanceStore for TestStore {
+        async fn get_balance(&self, _: Uuid) -> Result<i64, BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn update_balances_for_prepare(
+            &self,
+            _: Uuid,
+            _: u64,
+            _: Option<PacketId>,
+        ) -> Result<(), BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn update_balances_for_fulfill(
+            &self,
+            _: Uuid,
+            _: u64,
+            _: Option<PacketId>,
+        ) -> Result<(i64, u64), BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn update_balances_for_fulfill_and_queue_settlement(
+            &self,
+            to_account_id: Uuid,
+            outgoing_amount: u64,
+            _: Option<PacketId>,
+        ) -> Result<(i64, u64), BalanceStoreError> {
+            self.changes
+                .lock()
+                .unwrap()
+                .push((to_account_id, outgoing_amount as i64));
+            Ok((outgoing_amount as i64, 0))
+        }
+
+        async fn update_balances_for_reject(
+            &self,
+            from_account_id: Uuid,
+            incoming_amount: u64,
+            _: Option<PacketId>,
+        ) -> Result<(), BalanceStoreError> {
+            self.changes
+                .lock()
+                .unwrap()
+                .push((from_account_id, incoming_amount as i64));
+            Ok(())
+        }
+
+        async fn update_balances_for_delayed_settlement(
+            &self,
+            _: Uuid,
+        ) -> Result<(i64, u64), BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn get_balance_changes(
+            &self,
+            _: Uuid,
+            _: u64,
+            _: usize,
+        ) -> Result<Vec<BalanceChange>, BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn get_balance_totals(&self, _: Uuid) -> Result<BalanceTotals, BalanceStoreError> {
+            unimplemented!()
+        }
+    }
+
+    fn packet(expires_at: SystemTime, fulfilled: bool) -> JournaledPacket {
+        JournaledPacket {
+            id: Uuid::new_v4(),
+            from_id: Uuid::new_v4(),
+            to_id: Uuid::new_v4(),
+            incoming_amount: 100,
+            outgoing_amount: 90,
+            packet_id: Some([0; 32]),
+            expires_at: expires_at.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64,
+            fulfilled,
+        }
+    }
+
+    #[tokio::test]
+    async fn reconciles_the_packets_which_expired_a_while_ago() {
+        let store = TestStore::default();
+        let now = SystemTime::now();
+        let long_ago = now - JOURNAL_RECONCILIATION_DELAY * 2;
+        let fulfilled = packet(long_ago, true);
+        let expired = packet(long_ago, false);
+        // It may still be answered by the node which forwarded it
+        let in_flight = packet(now, false);
+        for packet in &[&fulfilled, &expired, &in_flight] {
+            store.journal_packet((*packet).clone()).await.unwrap();
+        }
+
+        assert_eq!(reconcile_journaled_packets(&store, now).await, 2);
+        let mut changes = store.changes.lock().unwrap().clone();
+        changes.sort();
+        let mut expected = vec![(fulfilled.to_id, 90), (expired.from_id, 100)];
+        expected.sort();
+        assert_eq!(changes, expected);
+        assert_eq!(
+            store.load_journaled_packets().await.unwrap(),
+            vec![in_flight]
+        );
+    }
+}
@@ -0,0 +1,252 @@
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_service::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+use uuid::Uuid;
+
+/// # Peer Concurrency Service
+///
+/// Outgoing Service which limits the number of requests in flight to each next-hop account,
+/// so that a slow peer can only hold up its own share of the requests rather than all of
+/// the ones the node can have in flight. Nothing is limited unless a maximum is set.
+/// A request beyond the limit waits for one of the account's requests to complete, for up to
+/// the configured wait (none by default), and is then rejected with `T03: Connector Busy`.
+/// Requires an `AddressStore`.
+#[derive(Clone)]
+pub struct PeerConcurrencyService<O, S> {
+    max_in_flight: Option<usize>,
+    wait: Duration,
+    in_flight: Arc<Mutex<HashMap<Uuid, Arc<Semaphore>>>>,
+    store: S,
+    next: O,
+}
+
+impl<O, S> PeerConcurrencyService<O, S> {
+    /// Simple constructor
+    pub fn new(store: S, next: O) -> Self {
+        PeerConcurrencyService {
+            max_in_flight: None,
+            wait: Duration::from_secs(0),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            store,
+            next,
+        }
+    }
+
+    /// Limits the number of requests in flight to each account.
+    /// A limit of 0 is treated as 1.
+    pub fn max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = Some(limit.max(1));
+        self
+    }
+
+    /// Lets the requests beyond the limit wait this long for a request to the same account
+    /// to complete before they are rejected
+    pub fn wait(mut self, wait: Duration) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    async fn acquire(&self, account_id: Uuid, limit: usize) -> Option<OwnedSemaphorePermit> {
+        let semaphore = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(account_id)
+            .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+            .clone();
+        if let Ok(permit) = semaphore.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+        if self.wait == Duration::from_secs(0) {
+            return None;
+        }
+        match runtime::timeout(self.wait, semaphore.acquire_owned()).await {
+            Ok(permit) => Some(permit.expect("the semaphore is never closed")),
+            Err(_) => None,
+        }
+    }
+}
+
+impl<O, S, A> OutgoingService<A> for PeerConcurrencyService<O, S>
+where
+    O: OutgoingService<A> + Send + Sync + 'static,
+    S: AddressStore + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    /// On send request:
+    /// 1. If fewer than the maximum requests to `request.to` are in flight, or one completes
+    ///    within the wait, forward the request
+    /// 1. Otherwise reject it with T03
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let limit = match self.max_in_flight {
+                Some(limit) => limit,
+                None => return self.next.send_request(request).await,
+            };
+            let account_id = request.to.id();
+            // The permit is held until the response has been received
+            let _permit = match self.acquire(account_id, limit).await {
+                Some(permit) => permit,
+                None => {
+                    warn!(
+                        "Rejecting packet because {} requests to account {} are already in flight",
+                        limit, account_id
+                    );
+                    return Err(RejectBuilder {
+                        code: ErrorCode::T03_CONNECTOR_BUSY,
+                        message: b"Too many requests in flight to the next hop",
+                        triggered_by: Some(&self.store.get_ilp_address()),
+                        data: &[],
+                    }
+                    .build());
+                }
+            };
+            self.next.send_request(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use futures::future::join_all;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    #[derive(Debug, Clone)]
+    struct TestAccount(Uuid);
+
+    fn request(to: &TestAccount) -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: TestAccount(Uuid::new_v4()),
+            to: to.clone(),
+            original_amount: 100,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: b"test data",
+            }
+            .build(),
+        }
+    }
+
+    /// Fulfills the packets after a while
+    #[derive(Clone)]
+    struct SlowService;
+
+    impl OutgoingService<TestAccount> for SlowService {
+        type Future<'a> = BoxedIlpFuture<'a>;
+
+        fn send_request(&mut self, _: OutgoingRequest<TestAccount>) -> Self::Future<'_> {
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"test data",
+                }
+                .build())
+            })
+        }
+    }
+
+    async fn send_all(
+        service: &PeerConcurrencyService<SlowService, TestStore>,
+        requests: Vec<OutgoingRequest<TestAccount>>,
+    ) -> Vec<IlpResult> {
+        join_all(requests.into_iter().map(|request| {
+            let mut service = service.clone();
+            async move { service.send_request(request).await }
+        }))
+        .await
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_beyond_the_limit_of_each_peer() {
+        let service = PeerConcurrencyService::new(TestStore, SlowService).max_in_flight(2);
+        let bob = TestAccount(Uuid::new_v4());
+        let carl = TestAccount(Uuid::new_v4());
+
+        let results = send_all(
+            &service,
+            vec![request(&bob), request(&bob), request(&bob), request(&carl)],
+        )
+        .await;
+        assert!(results[0].is_ok() && results[1].is_ok());
+        assert_eq!(
+            results[2].as_ref().unwrap_err().code(),
+            ErrorCode::T03_CONNECTOR_BUSY
+        );
+        // The other peer is not affected
+        assert!(results[3].is_ok());
+
+        // The slots are freed once the responses are received
+        assert!(send_all(&service, vec![request(&bob)]).await[0].is_ok());
+    }
+
+    #[tokio::test]
+    async fn queues_requests_for_the_wait() {
+        let service = PeerConcurrencyService::new(TestStore, SlowService)
+            .max_in_flight(1)
+            .wait(Duration::from_secs(1));
+        let bob = TestAccount(Uuid::new_v4());
+
+        let results = send_all(&service, vec![request(&bob), request(&bob)]).await;
+        assert!(results.iter().all(Result::is_ok));
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.0
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+}
@@ -1,6 +1,8 @@
 use async_trait::async_trait;
 use interledger_packet::{ErrorCode, RejectBuilder};
-use interledger_service::{Account, AddressStore, IlpResult, IncomingRequest, IncomingService};
+use interledger_service::{
+    Account, AddressStore, BoxedIlpFuture, IncomingRequest, IncomingService,
+};
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use tracing::{error, warn};
@@ -87,78 +89,81 @@ where
     }
 }
 
-#[async_trait]
 impl<S, I, A> IncomingService<A> for RateLimitService<S, I, A>
 where
     S: AddressStore + RateLimitStore<Account = A> + Send + Sync + 'static,
     I: IncomingService<A> + Send + Sync + 'static,
     A: RateLimitAccount + Sync + 'static,
 {
+    type Future<'a> = BoxedIlpFuture<'a>;
+
     /// On receiving a request:
     /// 1. Apply rate limit based on the sender of the request and the amount in the prepare packet in the request
     /// 1. If no limits were hit forward the request
     ///     - If it succeeds, OK
     ///     - If the request forwarding failed, the client should not be charged towards their throughput limit, so they are refunded, and return a reject
     /// 1. If the limit was hit, return a reject with the appropriate ErrorCode.
-    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
-        let ilp_address = self.store.get_ilp_address();
-        let account = request.from.clone();
-        let account_clone = account.clone();
-        let prepare_amount = request.prepare.amount();
-        let has_throughput_limit = account.amount_per_minute_limit().is_some();
-        // request.from and request.amount are used for apply_rate_limits, can't the previous service
-        // always set the account to have None for both?
-        match self
-            .store
-            .apply_rate_limits(request.from.clone(), request.prepare.amount())
-            .await
-        {
-            Ok(_) => {
-                let packet = self.next.handle_request(request).await;
-                // If we did not get a fulfill, we should refund the sender
-                if packet.is_err() && has_throughput_limit {
-                    let refunded = self
-                        .store
-                        .refund_throughput_limit(account_clone, prepare_amount)
-                        .await;
-                    // if refunding failed, that's too bad, we will just return the reject
-                    // from the peer
-                    if let Err(err) = refunded {
-                        error!("Error refunding throughput limit: {:?}", err);
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let ilp_address = self.store.get_ilp_address();
+            let account = request.from.clone();
+            let account_clone = account.clone();
+            let prepare_amount = request.prepare.amount();
+            let has_throughput_limit = account.amount_per_minute_limit().is_some();
+            // request.from and request.amount are used for apply_rate_limits, can't the previous service
+            // always set the account to have None for both?
+            match self
+                .store
+                .apply_rate_limits(request.from.clone(), request.prepare.amount())
+                .await
+            {
+                Ok(_) => {
+                    let packet = self.next.handle_request(request).await;
+                    // If we did not get a fulfill, we should refund the sender
+                    if packet.is_err() && has_throughput_limit {
+                        let refunded = self
+                            .store
+                            .refund_throughput_limit(account_clone, prepare_amount)
+                            .await;
+                        // if refunding failed, that's too bad, we will just return the reject
+                        // from the peer
+                        if let Err(err) = refunded {
+                            error!("Error refunding throughput limit: {:?}", err);
+                        }
                     }
-                }
 
-                // return the packet
-                packet
-            }
-            Err(err) => {
-                let code = match err {
-                    RateLimitError::PacketLimitExceeded => {
-                        if let Some(limit) = account.packets_per_minute_limit() {
-                            warn!("Account {} was rate limited for sending too many packets. Limit is: {} per minute", account.id(), limit);
+                    // return the packet
+                    packet
+                }
+                Err(err) => {
+                    let code = match err {
+                        RateLimitError::PacketLimitExceeded => {
+                            if let Some(limit) = account.packets_per_minute_limit() {
+                                warn!("Account {} was rate limited for sending too many packets. Limit is: {} per minute", account.id(), limit);
+                            }
+                            ErrorCode::T05_RATE_LIMITED
                         }
-                        ErrorCode::T05_RATE_LIMITED
-                    }
-                    RateLimitError::ThroughputLimitExceeded => {
-                        if let Some(limit) = account.amount_per_minute_limit() {
-                            warn!("Account {} was throughput limited for trying to send too much money. Limit is: {} per minute", account.id(), limit);
+                        RateLimitError::ThroughputLimitExceeded => {
+                            if let Some(limit) = account.amount_per_minute_limit() {
+                                warn!("Account {} was throughput limited for trying to send too much money. Limit is: {} per minute", account.id(), limit);
+                            }
+                            ErrorCode::T04_INSUFFICIENT_LIQUIDITY
                         }
-                        ErrorCode::T04_INSUFFICIENT_LIQUIDITY
+                        RateLimitError::StoreError => ErrorCode::T00_INTERNAL_ERROR,
+                    };
+
+                    let reject = RejectBuilder {
+                        code,
+                        triggered_by: Some(&ilp_address),
+                        message: &[],
+                        data: &[],
                     }
-                    RateLimitError::StoreError => ErrorCode::T00_INTERNAL_ERROR,
-                };
-
-                let reject = RejectBuilder {
-                    code,
-                    triggered_by: Some(&ilp_address),
-                    message: &[],
-                    data: &[],
-                }
-                .build();
+                    .build();
 
-                Err(reject)
+                    Err(reject)
+                }
             }
-        }
+        })
     }
 }
 
@@ -1,10 +1,9 @@
-use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
+use futures::future::{ready, Either, Ready};
 use interledger_packet::{hex::HexString, ErrorCode, RejectBuilder};
-use interledger_service::*;
+use interledger_service::{clock::SharedClock, *};
 use ring::digest::{digest, SHA256};
 use std::marker::PhantomData;
-use tokio::time::timeout;
 use tracing::error;
 
 /// # Validator Service
@@ -16,6 +15,8 @@ use tracing::error;
 pub struct ValidatorService<IO, S, A> {
     store: S,
     next: IO,
+    clock: SharedClock,
+    skew_tolerance: Duration,
     account_type: PhantomData<A>,
 }
 
@@ -31,6 +32,8 @@ where
         ValidatorService {
             store,
             next,
+            clock: SharedClock::default(),
+            skew_tolerance: Duration::zero(),
             account_type: PhantomData,
         }
     }
@@ -49,25 +52,47 @@ where
         ValidatorService {
             store,
             next,
+            clock: SharedClock::default(),
+            skew_tolerance: Duration::zero(),
             account_type: PhantomData,
         }
     }
 }
 
-#[async_trait]
+impl<IO, S, A> ValidatorService<IO, S, A> {
+    /// Checks the expiry of the packets against the clock instead of the system's clock
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Treats the packets as expired only once their expiry is more than the tolerance in the
+    /// past, so the packets of peers whose clocks are slightly behind are not rejected with R00
+    pub fn skew_tolerance(mut self, tolerance: std::time::Duration) -> Self {
+        self.skew_tolerance =
+            Duration::from_std(tolerance).expect("Clock skew tolerance is out of range");
+        self
+    }
+}
+
 impl<I, S, A> IncomingService<A> for ValidatorService<I, S, A>
 where
     I: IncomingService<A> + Send + Sync,
     S: AddressStore + Send + Sync,
     A: Account + Send + Sync,
 {
+    type Future<'a>
+        = Either<I::Future<'a>, Ready<IlpResult>>
+    where
+        Self: 'a;
+
     /// On receiving a request:
-    /// 1. If the prepare packet in the request is not expired, forward it, otherwise return a reject
-    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+    /// 1. If the prepare packet in the request is not expired (give or take the clock skew tolerance), forward it, otherwise return a reject
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
         let expires_at = DateTime::<Utc>::from(request.prepare.expires_at());
-        let now = Utc::now();
-        if expires_at >= now {
-            self.next.handle_request(request).await
+        let now = DateTime::<Utc>::from(self.clock.now());
+        if expires_at + self.skew_tolerance >= now {
+            Either::Left(self.next.handle_request(request))
         } else {
             error!(
                 "Incoming packet expired {}ms ago at {:?} (time now: {:?})",
@@ -75,96 +100,102 @@ where
                 expires_at.to_rfc3339(),
                 expires_at.to_rfc3339(),
             );
-            Err(RejectBuilder {
+            Either::Right(ready(Err(RejectBuilder {
                 code: ErrorCode::R00_TRANSFER_TIMED_OUT,
                 message: &[],
                 triggered_by: Some(&self.store.get_ilp_address()),
                 data: &[],
             }
-            .build())
+            .build())))
         }
     }
 }
 
-#[async_trait]
 impl<O, S, A> OutgoingService<A> for ValidatorService<O, S, A>
 where
     O: OutgoingService<A> + Send + Sync,
     S: AddressStore + Send + Sync,
     A: Account + Send + Sync,
 {
+    type Future<'a>
+        = BoxedIlpFuture<'a>
+    where
+        Self: 'a;
+
     /// On sending a request:
-    /// 1. If the outgoing packet has expired, return a reject with the appropriate ErrorCode
+    /// 1. If the outgoing packet has expired (give or take the clock skew tolerance), return a reject with the appropriate ErrorCode
     /// 1. Tries to forward the request
     ///     - If no response is received before the prepare packet's expiration, it assumes that the outgoing request has timed out.
     ///     - If no timeout occurred, but still errored it will just return the reject
     ///     - If the forwarding is successful, it should receive a fulfill packet. Depending on if the hash of the fulfillment condition inside the fulfill is a preimage of the condition of the prepare:
     ///         - return the fulfill if it matches
     ///         - otherwise reject
-    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
-        let mut condition: [u8; 32] = [0; 32];
-        condition[..].copy_from_slice(request.prepare.execution_condition()); // why?
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let mut condition: [u8; 32] = [0; 32];
+            condition[..].copy_from_slice(request.prepare.execution_condition()); // why?
 
-        let expires_at = DateTime::<Utc>::from(request.prepare.expires_at());
-        let now = Utc::now();
-        let time_left = expires_at - now;
-        let ilp_address = self.store.get_ilp_address();
-        if time_left > Duration::zero() {
-            // Result of the future
-            let result = timeout(
-                time_left.to_std().expect("Time left must be positive"),
-                self.next.send_request(request),
-            )
-            .await;
+            let expires_at = DateTime::<Utc>::from(request.prepare.expires_at());
+            let now = DateTime::<Utc>::from(self.clock.now());
+            let time_left = expires_at + self.skew_tolerance - now;
+            let ilp_address = self.store.get_ilp_address();
+            if time_left > Duration::zero() {
+                // Result of the future
+                let result = runtime::timeout(
+                    time_left.to_std().expect("Time left must be positive"),
+                    self.next.send_request(request),
+                )
+                .await;
 
-            let fulfill = match result {
-                // If the future completed in time, it returns an IlpResult,
-                // which gives us the fulfill packet
-                Ok(packet) => packet?,
-                // If the future timed out, then it results in an error
-                Err(_) => {
-                    error!(
-                        "Outgoing request timed out after {}ms (expiry was: {})",
-                        time_left.num_milliseconds(),
-                        expires_at,
-                    );
-                    return Err(RejectBuilder {
-                        code: ErrorCode::R00_TRANSFER_TIMED_OUT,
-                        message: &[],
+                let fulfill = match result {
+                    // If the future completed in time, it returns an IlpResult,
+                    // which gives us the fulfill packet
+                    Ok(packet) => packet?,
+                    // If the future timed out, then it results in an error
+                    Err(_) => {
+                        error!(
+                            "Outgoing request timed out after {}ms (expiry was: {})",
+                            time_left.num_milliseconds(),
+                            expires_at,
+                        );
+                        return Err(RejectBuilder {
+                            code: ErrorCode::R00_TRANSFER_TIMED_OUT,
+                            message: &[],
+                            triggered_by: Some(&ilp_address),
+                            data: &[],
+                        }
+                        .build());
+                    }
+                };
+
+                let generated_condition = digest(&SHA256, fulfill.fulfillment());
+                if generated_condition.as_ref() == condition {
+                    Ok(fulfill)
+                } else {
+                    error!("Fulfillment did not match condition. Fulfillment: {:?}, hash: {:?}, actual condition: {:?}", HexString(fulfill.fulfillment()), HexString(generated_condition.as_ref()), HexString(&condition[..]));
+                    Err(RejectBuilder {
+                        code: ErrorCode::F09_INVALID_PEER_RESPONSE,
+                        message: b"Fulfillment did not match condition",
                         triggered_by: Some(&ilp_address),
                         data: &[],
                     }
-                    .build());
+                    .build())
                 }
-            };
-
-            let generated_condition = digest(&SHA256, fulfill.fulfillment());
-            if generated_condition.as_ref() == condition {
-                Ok(fulfill)
             } else {
-                error!("Fulfillment did not match condition. Fulfillment: {:?}, hash: {:?}, actual condition: {:?}", HexString(fulfill.fulfillment()), HexString(generated_condition.as_ref()), HexString(&condition[..]));
+                error!(
+                    "Outgoing packet expired {}ms ago",
+                    (Duration::zero() - time_left).num_milliseconds(),
+                );
+                // Already expired
                 Err(RejectBuilder {
-                    code: ErrorCode::F09_INVALID_PEER_RESPONSE,
-                    message: b"Fulfillment did not match condition",
+                    code: ErrorCode::R00_TRANSFER_TIMED_OUT,
+                    message: &[],
                     triggered_by: Some(&ilp_address),
                     data: &[],
                 }
                 .build())
             }
-        } else {
-            error!(
-                "Outgoing packet expired {}ms ago",
-                (Duration::zero() - time_left).num_milliseconds(),
-            );
-            // Already expired
-            Err(RejectBuilder {
-                code: ErrorCode::R00_TRANSFER_TIMED_OUT,
-                message: &[],
-                triggered_by: Some(&ilp_address),
-                data: &[],
-            }
-            .build())
-        }
+        })
     }
 }
 
@@ -212,6 +243,8 @@ impl Account for TestAccount {
 #[derive(Clone)]
 struct TestStore;
 
+#[cfg(test)]
+use async_trait::async_trait;
 #[cfg(test)]
 use interledger_errors::AddressStoreError;
 
@@ -237,6 +270,7 @@ impl AddressStore for TestStore {
 mod incoming {
     use super::*;
     use interledger_packet::*;
+    use interledger_service::clock::{Clock, ManualClock};
     use interledger_service::incoming_service_fn;
     use std::{
         sync::{Arc, Mutex},
@@ -318,6 +352,80 @@ mod incoming {
             ErrorCode::R00_TRANSFER_TIMED_OUT
         );
     }
+
+    #[tokio::test]
+    async fn checks_expiry_against_the_clock() {
+        // Packets carry their expiry in milliseconds
+        let clock = ManualClock::new(std::time::UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        let mut validator = ValidatorService::incoming(
+            TestStore,
+            incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        )
+        .clock(clock.clone().into());
+        let prepare = PrepareBuilder {
+            destination: Address::from_str("example.destination").unwrap(),
+            amount: 100,
+            expires_at: clock.now() + Duration::from_secs(30),
+            execution_condition: &[0; 32],
+            data: &[],
+        }
+        .build();
+        let request = IncomingRequest {
+            from: TestAccount(Uuid::new_v4()),
+            prepare,
+        };
+
+        clock.advance(Duration::from_secs(30));
+        assert!(validator.handle_request(request.clone()).await.is_ok());
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(
+            validator.handle_request(request).await.unwrap_err().code(),
+            ErrorCode::R00_TRANSFER_TIMED_OUT
+        );
+    }
+
+    #[tokio::test]
+    async fn tolerates_clock_skew() {
+        let clock = ManualClock::new(std::time::UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        let mut validator = ValidatorService::incoming(
+            TestStore,
+            incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        )
+        .clock(clock.clone().into())
+        .skew_tolerance(Duration::from_secs(2));
+        let prepare = PrepareBuilder {
+            destination: Address::from_str("example.destination").unwrap(),
+            amount: 100,
+            expires_at: clock.now(),
+            execution_condition: &[0; 32],
+            data: &[],
+        }
+        .build();
+        let request = IncomingRequest {
+            from: TestAccount(Uuid::new_v4()),
+            prepare,
+        };
+
+        clock.advance(Duration::from_secs(2));
+        assert!(validator.handle_request(request.clone()).await.is_ok());
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(
+            validator.handle_request(request).await.unwrap_err().code(),
+            ErrorCode::R00_TRANSFER_TIMED_OUT
+        );
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,333 @@
+use async_trait::async_trait;
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_service::{
+    Account, AddressStore, BoxedIlpFuture, IncomingRequest, IncomingService,
+};
+use std::marker::PhantomData;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, warn};
+
+/// Number of seconds in a day, over which the daily volumes are counted
+const SECONDS_PER_DAY: u64 = 24 * 3600;
+
+/// Extension trait for [`Account`](../interledger_service/trait.Account.html) with the caps
+/// on the amounts which the account may send
+pub trait VolumeLimitAccount: Account {
+    /// The maximum amount of a payment sent by this account via the node's API
+    fn max_payment_amount(&self) -> Option<u64> {
+        None
+    }
+
+    /// The maximum amount this account can send per day (UTC)
+    fn amount_per_day_limit(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Volume limiting related errors
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VolumeLimitError {
+    /// Account exceeded their daily volume limit
+    DailyLimitExceeded,
+    /// There was an internal error when trying to connect to the store
+    StoreError,
+}
+
+/// Store trait which keeps the totals of the amounts the accounts sent per day. Unlike the
+/// rate limits, the totals must be persisted, so that they survive restarts of the node.
+#[async_trait]
+pub trait VolumeLimitStore {
+    /// The provided account must implement [`VolumeLimitAccount`](./trait.VolumeLimitAccount.html)
+    type Account: VolumeLimitAccount;
+
+    /// Adds the amount to the total of the account for the given day (counted in days since
+    /// the UNIX epoch), unless that would exceed the account's daily volume limit
+    async fn charge_daily_volume(
+        &self,
+        account: Self::Account,
+        day: u64,
+        amount: u64,
+    ) -> Result<(), VolumeLimitError>;
+
+    /// Subtracts the amount which was charged to the account for the given day.
+    /// Called if the packet was rejected, so that only fulfilled packets count towards the
+    /// daily volume limit.
+    async fn refund_daily_volume(
+        &self,
+        account: Self::Account,
+        day: u64,
+        amount: u64,
+    ) -> Result<(), VolumeLimitError>;
+}
+
+/// Returns the current day, counted in days since the UNIX epoch (UTC)
+pub fn current_day() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs() / SECONDS_PER_DAY)
+        .unwrap_or_default()
+}
+
+/// # Volume Limit Service
+///
+/// Incoming Service responsible for rejecting the packets of accounts which have reached
+/// their daily volume limit. The totals are kept in the store, so that all nodes which
+/// share it enforce the limit together.
+/// Packets which are rejected further down the line are refunded.
+///
+/// Forwards everything else.
+/// Requires a `VolumeLimitAccount` and a `VolumeLimitStore`.
+/// It is an IncomingService.
+#[derive(Clone)]
+pub struct VolumeLimitService<S, I, A> {
+    store: S,
+    next: I,
+    account_type: PhantomData<A>,
+}
+
+impl<S, I, A> VolumeLimitService<S, I, A>
+where
+    S: AddressStore + VolumeLimitStore<Account = A> + Send + Sync,
+    I: IncomingService<A> + Send + Sync,
+    A: VolumeLimitAccount + Sync,
+{
+    pub fn new(store: S, next: I) -> Self {
+        VolumeLimitService {
+            store,
+            next,
+            account_type: PhantomData,
+        }
+    }
+}
+
+impl<S, I, A> IncomingService<A> for VolumeLimitService<S, I, A>
+where
+    S: AddressStore + VolumeLimitStore<Account = A> + Send + Sync + 'static,
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: VolumeLimitAccount + Sync + 'static,
+{
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    /// On receiving a request:
+    /// 1. If the sender has no daily volume limit or the packet has no amount, forward the request
+    /// 1. Charge the amount in the prepare packet to the sender's total of the day
+    /// 1. If the limit was not exceeded forward the request
+    ///     - If the request forwarding failed, the amount is refunded
+    /// 1. If the limit was exceeded, return a reject
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let amount = request.prepare.amount();
+            let limit = match request.from.amount_per_day_limit() {
+                Some(limit) if amount > 0 => limit,
+                _ => return self.next.handle_request(request).await,
+            };
+            let account = request.from.clone();
+            let day = current_day();
+
+            match self
+                .store
+                .charge_daily_volume(account.clone(), day, amount)
+                .await
+            {
+                Ok(()) => {
+                    let packet = self.next.handle_request(request).await;
+                    if packet.is_err() {
+                        if let Err(err) = self.store.refund_daily_volume(account, day, amount).await
+                        {
+                            error!("Error refunding daily volume: {:?}", err);
+                        }
+                    }
+                    packet
+                }
+                Err(err) => {
+                    let (code, message) = match err {
+                        VolumeLimitError::DailyLimitExceeded => {
+                            warn!(
+                                "Account {} reached its daily volume limit of {}",
+                                account.id(),
+                                limit
+                            );
+                            (
+                                ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                                format!("Daily volume limit of {} exceeded", limit),
+                            )
+                        }
+                        VolumeLimitError::StoreError => {
+                            (ErrorCode::T00_INTERNAL_ERROR, String::new())
+                        }
+                    };
+                    Err(RejectBuilder {
+                        code,
+                        message: message.as_bytes(),
+                        triggered_by: Some(&self.store.get_ilp_address()),
+                        data: &[],
+                    }
+                    .build())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use interledger_service::{incoming_service_fn, IlpResult, Username};
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    #[derive(Debug, Clone)]
+    struct TestAccount(Option<u64>);
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    impl VolumeLimitAccount for TestAccount {
+        fn amount_per_day_limit(&self) -> Option<u64> {
+            self.0
+        }
+    }
+
+    /// Keeps the daily totals in memory
+    #[derive(Clone, Default)]
+    struct TestStore {
+        volumes: Arc<Mutex<HashMap<u64, u64>>>,
+    }
+
+    #[async_trait]
+    impl VolumeLimitStore for TestStore {
+        type Account = TestAccount;
+
+        async fn charge_daily_volume(
+            &self,
+            account: TestAccount,
+            day: u64,
+            amount: u64,
+        ) -> Result<(), VolumeLimitError> {
+            let mut volumes = self.volumes.lock();
+            let volume = volumes.entry(day).or_insert(0);
+            if *volume + amount > account.0.unwrap() {
+                return Err(VolumeLimitError::DailyLimitExceeded);
+            }
+            *volume += amount;
+            Ok(())
+        }
+
+        async fn refund_daily_volume(
+            &self,
+            _: TestAccount,
+            day: u64,
+            amount: u64,
+        ) -> Result<(), VolumeLimitError> {
+            *self.volumes.lock().get_mut(&day).unwrap() -= amount;
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    fn request(limit: Option<u64>, amount: u64) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount(limit),
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at: SystemTime::now() + std::time::Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    fn fulfill() -> IlpResult {
+        Ok(FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: &[],
+        }
+        .build())
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_over_the_daily_limit() {
+        let store = TestStore::default();
+        let mut service =
+            VolumeLimitService::new(store.clone(), incoming_service_fn(|_| fulfill()));
+        assert!(service.handle_request(request(Some(100), 60)).await.is_ok());
+        let reject = service
+            .handle_request(request(Some(100), 50))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T04_INSUFFICIENT_LIQUIDITY);
+        assert!(service.handle_request(request(Some(100), 40)).await.is_ok());
+        assert_eq!(store.volumes.lock()[&current_day()], 100);
+
+        // Accounts without a limit are not charged
+        assert!(service.handle_request(request(None, 1000)).await.is_ok());
+        assert_eq!(store.volumes.lock()[&current_day()], 100);
+    }
+
+    #[tokio::test]
+    async fn refunds_rejected_packets() {
+        let store = TestStore::default();
+        let mut service = VolumeLimitService::new(
+            store.clone(),
+            incoming_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::T00_INTERNAL_ERROR,
+                    message: &[],
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        assert!(service
+            .handle_request(request(Some(100), 60))
+            .await
+            .is_err());
+        assert_eq!(store.volumes.lock()[&current_day()], 0);
+    }
+}
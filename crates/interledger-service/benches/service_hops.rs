@@ -0,0 +1,189 @@
+//! Benchmark the cost of each service a packet goes through.
+//!
+//! The services implemented as types which pass the request on return the future of the
+//! next service, so they don't allocate: this is checked before the benchmarks run. The
+//! services built with `wrap` box the next service on every request, box its future, and
+//! box once more if the closure returns a boxed future. Their allocations per hop are printed.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures::executor::block_on;
+use interledger_packet::{Address, Fulfill, FulfillBuilder, PrepareBuilder};
+use interledger_service::{
+    outgoing_service_fn, Account, BoxedIlpFuture, BoxedOutgoingService, OutgoingRequest,
+    OutgoingService, Username,
+};
+use once_cell::sync::Lazy;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+/// Counts the allocations, to tell how many each hop makes
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const HOPS: usize = 8;
+
+static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+static EXAMPLE_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.alice").unwrap());
+static FULFILL: Lazy<Fulfill> = Lazy::new(|| {
+    FulfillBuilder {
+        fulfillment: &[0; 32],
+        data: &[],
+    }
+    .build()
+});
+
+#[derive(Clone, Debug)]
+struct TestAccount;
+
+impl Account for TestAccount {
+    fn id(&self) -> Uuid {
+        Uuid::nil()
+    }
+
+    fn username(&self) -> &Username {
+        &ALICE
+    }
+
+    fn ilp_address(&self) -> &Address {
+        &EXAMPLE_ADDRESS
+    }
+
+    fn asset_scale(&self) -> u8 {
+        9
+    }
+
+    fn asset_code(&self) -> &str {
+        "XYZ"
+    }
+}
+
+/// A hop implemented as a type, which passes the request on
+#[derive(Clone)]
+struct ForwardingService<O> {
+    next: O,
+}
+
+impl<O> OutgoingService<TestAccount> for ForwardingService<O>
+where
+    O: OutgoingService<TestAccount> + Send,
+{
+    type Future<'a>
+        = O::Future<'a>
+    where
+        Self: 'a;
+
+    fn send_request(&mut self, request: OutgoingRequest<TestAccount>) -> Self::Future<'_> {
+        self.next.send_request(request)
+    }
+}
+
+/// A hop built with `wrap`, with a closure returning a boxed future
+fn forwarding_wrapper(
+    request: OutgoingRequest<TestAccount>,
+    mut next: BoxedOutgoingService<TestAccount>,
+) -> BoxedIlpFuture<'static> {
+    Box::pin(async move { next.send_request(request).await })
+}
+
+fn leaf() -> impl OutgoingService<TestAccount> + Clone + Send + Sync + 'static {
+    outgoing_service_fn(|_| Ok(FULFILL.clone()))
+}
+
+fn forwarding_services() -> impl OutgoingService<TestAccount> + Clone + Send + Sync + 'static {
+    let service = ForwardingService { next: leaf() };
+    let service = ForwardingService { next: service };
+    let service = ForwardingService { next: service };
+    let service = ForwardingService { next: service };
+    let service = ForwardingService { next: service };
+    let service = ForwardingService { next: service };
+    let service = ForwardingService { next: service };
+    ForwardingService { next: service }
+}
+
+fn wrapped_services() -> impl OutgoingService<TestAccount> + Clone + Send + Sync + 'static {
+    leaf()
+        .wrap(forwarding_wrapper)
+        .wrap(forwarding_wrapper)
+        .wrap(forwarding_wrapper)
+        .wrap(forwarding_wrapper)
+        .wrap(forwarding_wrapper)
+        .wrap(forwarding_wrapper)
+        .wrap(forwarding_wrapper)
+        .wrap(forwarding_wrapper)
+}
+
+fn request() -> OutgoingRequest<TestAccount> {
+    OutgoingRequest {
+        from: TestAccount,
+        to: TestAccount,
+        original_amount: 100,
+        prepare: PrepareBuilder {
+            destination: EXAMPLE_ADDRESS.clone(),
+            amount: 100,
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            execution_condition: &[0; 32],
+            data: &[],
+        }
+        .build(),
+    }
+}
+
+/// Returns the number of allocations made while the service handles one request
+fn allocations<O: OutgoingService<TestAccount>>(service: &mut O) -> usize {
+    let request = request();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let result = block_on(service.send_request(request));
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    assert!(result.is_ok());
+    allocations
+}
+
+fn check_allocations_per_hop() {
+    // Warm up the lazy statics so that they are not counted
+    let baseline = allocations(&mut leaf()).min(allocations(&mut leaf()));
+    let per_hop = |allocations: usize| (allocations - baseline) as f64 / HOPS as f64;
+    let forwarding = per_hop(allocations(&mut forwarding_services()));
+    assert_eq!(
+        forwarding, 0.0,
+        "the services implemented as types must not allocate"
+    );
+    println!(
+        "Allocations per hop: {} for the services implemented as types, {} for the ones built with wrap",
+        forwarding,
+        per_hop(allocations(&mut wrapped_services())),
+    );
+}
+
+fn service_hops(c: &mut Criterion) {
+    check_allocations_per_hop();
+
+    let mut service = forwarding_services();
+    c.bench_function(&format!("{} services implemented as types", HOPS), |b| {
+        b.iter(|| block_on(service.send_request(request())))
+    });
+    let mut service = wrapped_services();
+    c.bench_function(&format!("{} services built with wrap", HOPS), |b| {
+        b.iter(|| block_on(service.send_request(request())))
+    });
+}
+
+criterion_group!(benches, service_hops);
+criterion_main!(benches);
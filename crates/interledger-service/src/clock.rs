@@ -0,0 +1,147 @@
+//! The clock which the services read the time from.
+//!
+//! The services check the expiry of the packets, the age of the exchange rates and when
+//! settlements are due against a [`SharedClock`](./struct.SharedClock.html) instead of
+//! reading the system's clock directly. A node hands the same clock to all of its services,
+//! so they agree on the time, and tests can hand them a
+//! [`ManualClock`](./struct.ManualClock.html) which they advance, instead of sleeping until
+//! a packet expires.
+//!
+//! Timers still run on the [`runtime`](../runtime/index.html), so a test which advances the
+//! clock past a deadline that is awaited should also pause and advance the runtime's timers.
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A source of the current time
+pub trait Clock: Send + Sync + 'static {
+    /// The current time
+    fn now(&self) -> SystemTime;
+}
+
+/// Reads the system's clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock which stands still until it is set or advanced, so the time read by the
+/// services is the same on every run of a test
+#[derive(Clone, Debug)]
+pub struct ManualClock(Arc<Mutex<SystemTime>>);
+
+impl ManualClock {
+    pub fn new(now: SystemTime) -> Self {
+        ManualClock(Arc::new(Mutex::new(now)))
+    }
+
+    /// Moves the time of the clock, and all of its clones, forward
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap() += duration;
+    }
+
+    /// Sets the time of the clock, and all of its clones
+    pub fn set(&self, now: SystemTime) {
+        *self.0.lock().unwrap() = now;
+    }
+}
+
+impl Default for ManualClock {
+    /// Starts at the current system time, so packets built with the system's clock are
+    /// not already expired
+    fn default() -> Self {
+        ManualClock::new(SystemTime::now())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// A handle to a clock which the services of a node share. The default one reads the
+/// system's clock.
+#[derive(Clone, Default)]
+pub struct SharedClock(Option<Arc<dyn Clock>>);
+
+impl SharedClock {
+    pub fn new<C: Clock>(clock: C) -> Self {
+        SharedClock(Some(Arc::new(clock)))
+    }
+
+    pub fn now(&self) -> SystemTime {
+        match self.0 {
+            Some(ref clock) => clock.now(),
+            None => SystemTime::now(),
+        }
+    }
+
+    /// Milliseconds since the UNIX epoch, or 0 if the clock is set before it
+    pub fn unix_millis(&self) -> u64 {
+        self.now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("SharedClock(custom)"),
+            None => f.write_str("SharedClock(system)"),
+        }
+    }
+}
+
+impl PartialEq for SharedClock {
+    /// Clocks are equal if both read the system's clock, or if they are the same clock
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (None, None) => true,
+            (Some(clock), Some(other)) => Arc::ptr_eq(clock, other),
+            _ => false,
+        }
+    }
+}
+
+impl From<ManualClock> for SharedClock {
+    fn from(clock: ManualClock) -> Self {
+        SharedClock::new(clock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_only_moves_when_advanced() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let clock = ManualClock::new(start);
+        let shared = SharedClock::from(clock.clone());
+        assert_eq!(shared.now(), start);
+        assert_eq!(shared.unix_millis(), 1_000_000_000);
+
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(shared.now(), start + Duration::from_millis(1500));
+        clock.set(start);
+        assert_eq!(shared.now(), start);
+    }
+
+    #[test]
+    fn compares_clocks_by_identity() {
+        let clock = SharedClock::new(ManualClock::default());
+        assert_eq!(SharedClock::default(), SharedClock::default());
+        assert_eq!(clock, clock.clone());
+        assert_ne!(clock, SharedClock::new(ManualClock::default()));
+        assert_ne!(clock, SharedClock::default());
+    }
+}
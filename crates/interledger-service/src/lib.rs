@@ -27,16 +27,22 @@
 //! HttpServerService --> ValidatorService --> StreamReceiverService
 
 use async_trait::async_trait;
+use futures::future::{ready, Ready};
 use interledger_errors::{AccountStoreError, AddressStoreError};
 use interledger_packet::{Address, Fulfill, Prepare, Reject};
 use std::{
     fmt::{self, Debug},
     future::Future,
     marker::PhantomData,
+    pin::Pin,
     sync::Arc,
 };
 use uuid::Uuid;
 
+pub mod clock;
+pub mod metrics;
+pub mod runtime;
+pub mod telemetry;
 mod username;
 pub use username::Username;
 #[cfg(feature = "trace")]
@@ -52,7 +58,7 @@ pub type IlpResult = Result<Fulfill, Reject>;
 /// Each service can extend the Account type to include additional details they require.
 /// Store implementations will implement these Account traits for a concrete type that
 /// they will load from the database.
-pub trait Account: Clone + Send + Sized + Debug {
+pub trait Account: Clone + Send + Sized + Debug + 'static {
     fn id(&self) -> Uuid;
     fn username(&self) -> &Username;
     fn ilp_address(&self) -> &Address;
@@ -127,13 +133,23 @@ where
     }
 }
 
+/// A boxed future returned by services whose response is not known to be ready, for example
+/// because it awaits a store or the network. Services which only pass the request on return
+/// the future of the next service instead, so that the chain doesn't allocate for them.
+pub type BoxedIlpFuture<'a> = Pin<Box<dyn Future<Output = IlpResult> + Send + 'a>>;
+
 /// Core service trait for handling IncomingRequests that asynchronously returns an ILP Fulfill or Reject packet.
-#[async_trait]
 pub trait IncomingService<A: Account> {
+    /// The future returned by `handle_request`. It may borrow the service, so that
+    /// each service can return the future of the next one without boxing it.
+    type Future<'a>: Future<Output = IlpResult> + Send + 'a
+    where
+        Self: 'a;
+
     /// Receives an Incoming request, and modifies it in place and passes it
     /// to the next service. Alternatively, if the packet was intended for the service,
     /// it returns an ILP Fulfill or Reject packet.
-    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult;
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_>;
 
     /// Wrap the given service such that the provided function will
     /// be called to handle each request. That function can
@@ -141,7 +157,7 @@ pub trait IncomingService<A: Account> {
     /// and/or handle the result of calling the inner service.
     fn wrap<F, R>(self, f: F) -> WrappedService<F, Self, A>
     where
-        F: Send + Sync + Fn(IncomingRequest<A>, Box<dyn IncomingService<A> + Send>) -> R,
+        F: Send + Sync + Fn(IncomingRequest<A>, BoxedIncomingService<A>) -> R,
         R: Future<Output = IlpResult>,
         Self: Clone + Sized,
     {
@@ -150,12 +166,17 @@ pub trait IncomingService<A: Account> {
 }
 
 /// Core service trait for sending OutgoingRequests that asynchronously returns an ILP Fulfill or Reject packet.
-#[async_trait]
 pub trait OutgoingService<A: Account> {
+    /// The future returned by `send_request`. It may borrow the service, so that
+    /// each service can return the future of the next one without boxing it.
+    type Future<'a>: Future<Output = IlpResult> + Send + 'a
+    where
+        Self: 'a;
+
     /// Receives an Outgoing request, and modifies it in place and passes it
     /// to the next service. Alternatively, if the packet was intended for the service,
     /// it returns an ILP Fulfill or Reject packet.
-    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult;
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_>;
 
     /// Wrap the given service such that the provided function will
     /// be called to handle each request. That function can
@@ -163,7 +184,7 @@ pub trait OutgoingService<A: Account> {
     /// and/or handle the result of calling the inner service.
     fn wrap<F, R>(self, f: F) -> WrappedService<F, Self, A>
     where
-        F: Send + Sync + Fn(OutgoingRequest<A>, Box<dyn OutgoingService<A> + Send>) -> R,
+        F: Send + Sync + Fn(OutgoingRequest<A>, BoxedOutgoingService<A>) -> R,
         R: Future<Output = IlpResult>,
         Self: Clone + Sized,
     {
@@ -223,25 +244,33 @@ pub struct ServiceFn<F, A> {
     account_type: PhantomData<A>,
 }
 
-#[async_trait]
 impl<F, A> IncomingService<A> for ServiceFn<F, A>
 where
     A: Account,
     F: FnMut(IncomingRequest<A>) -> IlpResult + Send,
 {
-    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
-        (self.handler)(request)
+    type Future<'a>
+        = Ready<IlpResult>
+    where
+        Self: 'a;
+
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        ready((self.handler)(request))
     }
 }
 
-#[async_trait]
 impl<F, A> OutgoingService<A> for ServiceFn<F, A>
 where
     A: Account,
     F: FnMut(OutgoingRequest<A>) -> IlpResult + Send,
 {
-    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
-        (self.handler)(request)
+    type Future<'a>
+        = Ready<IlpResult>
+    where
+        Self: 'a;
+
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        ready((self.handler)(request))
     }
 }
 
@@ -260,7 +289,7 @@ pub struct WrappedService<F, I, A> {
 
 impl<F, I, A, R> WrappedService<F, I, A>
 where
-    F: Send + Sync + Fn(IncomingRequest<A>, Box<dyn IncomingService<A> + Send>) -> R,
+    F: Send + Sync + Fn(IncomingRequest<A>, BoxedIncomingService<A>) -> R,
     R: Future<Output = IlpResult>,
     I: IncomingService<A> + Clone,
     A: Account,
@@ -278,22 +307,26 @@ where
     }
 }
 
-#[async_trait]
 impl<F, I, A, R> IncomingService<A> for WrappedService<F, I, A>
 where
-    F: Send + Sync + Fn(IncomingRequest<A>, Box<dyn IncomingService<A> + Send>) -> R,
+    F: Send + Sync + Fn(IncomingRequest<A>, BoxedIncomingService<A>) -> R,
     R: Future<Output = IlpResult> + Send + 'static,
     I: IncomingService<A> + Send + Sync + Clone + 'static,
     A: Account + Sync,
 {
-    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
-        (self.f)(request, Box::new((*self.inner).clone())).await
+    type Future<'a>
+        = R
+    where
+        Self: 'a;
+
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        (self.f)(request, BoxedIncomingService::new((*self.inner).clone()))
     }
 }
 
 impl<F, O, A, R> WrappedService<F, O, A>
 where
-    F: Send + Sync + Fn(OutgoingRequest<A>, Box<dyn OutgoingService<A> + Send>) -> R,
+    F: Send + Sync + Fn(OutgoingRequest<A>, BoxedOutgoingService<A>) -> R,
     R: Future<Output = IlpResult>,
     O: OutgoingService<A> + Clone,
     A: Account,
@@ -311,16 +344,240 @@ where
     }
 }
 
-#[async_trait]
 impl<F, O, A, R> OutgoingService<A> for WrappedService<F, O, A>
 where
-    F: Send + Sync + Fn(OutgoingRequest<A>, Box<dyn OutgoingService<A> + Send>) -> R,
+    F: Send + Sync + Fn(OutgoingRequest<A>, BoxedOutgoingService<A>) -> R,
     R: Future<Output = IlpResult> + Send + 'static,
     O: OutgoingService<A> + Send + Sync + Clone + 'static,
     A: Account,
 {
-    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
-        (self.f)(request, Box::new((*self.inner).clone())).await
+    type Future<'a>
+        = R
+    where
+        Self: 'a;
+
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        (self.f)(request, BoxedOutgoingService::new((*self.inner).clone()))
+    }
+}
+
+/// An incoming service whose concrete type is erased, so that services can be
+/// chained in an order which is only known at runtime (for example from a config file).
+/// It can be cloned like the services it wraps, and boxes the future of each request.
+pub struct BoxedIncomingService<A: Account> {
+    inner: Box<dyn DynIncomingService<A>>,
+}
+
+impl<A: Account> BoxedIncomingService<A> {
+    pub fn new<I>(service: I) -> Self
+    where
+        I: IncomingService<A> + Clone + Send + Sync + 'static,
+    {
+        BoxedIncomingService {
+            inner: Box::new(service),
+        }
+    }
+}
+
+impl<A: Account> Clone for BoxedIncomingService<A> {
+    fn clone(&self) -> Self {
+        BoxedIncomingService {
+            inner: self.inner.clone_box(),
+        }
+    }
+}
+
+impl<A: Account> IncomingService<A> for BoxedIncomingService<A> {
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        self.inner.handle_request_boxed(request)
+    }
+}
+
+// The service traits can't be made into objects because their futures are associated
+// types, so the boxed services erase them through these traits instead
+trait DynIncomingService<A: Account>: Send + Sync {
+    fn handle_request_boxed(&mut self, request: IncomingRequest<A>) -> BoxedIlpFuture<'_>;
+
+    fn clone_box(&self) -> Box<dyn DynIncomingService<A>>;
+}
+
+impl<I, A> DynIncomingService<A> for I
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account,
+{
+    fn handle_request_boxed(&mut self, request: IncomingRequest<A>) -> BoxedIlpFuture<'_> {
+        Box::pin(self.handle_request(request))
+    }
+
+    fn clone_box(&self) -> Box<dyn DynIncomingService<A>> {
+        Box::new(self.clone())
+    }
+}
+
+/// An outgoing service whose concrete type is erased, so that services can be
+/// chained in an order which is only known at runtime (for example from a config file).
+/// It can be cloned like the services it wraps, and boxes the future of each request.
+pub struct BoxedOutgoingService<A: Account> {
+    inner: Box<dyn DynOutgoingService<A>>,
+}
+
+impl<A: Account> BoxedOutgoingService<A> {
+    pub fn new<O>(service: O) -> Self
+    where
+        O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    {
+        BoxedOutgoingService {
+            inner: Box::new(service),
+        }
+    }
+}
+
+impl<A: Account> Clone for BoxedOutgoingService<A> {
+    fn clone(&self) -> Self {
+        BoxedOutgoingService {
+            inner: self.inner.clone_box(),
+        }
+    }
+}
+
+impl<A: Account> OutgoingService<A> for BoxedOutgoingService<A> {
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        self.inner.send_request_boxed(request)
+    }
+}
+
+trait DynOutgoingService<A: Account>: Send + Sync {
+    fn send_request_boxed(&mut self, request: OutgoingRequest<A>) -> BoxedIlpFuture<'_>;
+
+    fn clone_box(&self) -> Box<dyn DynOutgoingService<A>>;
+}
+
+impl<O, A> DynOutgoingService<A> for O
+where
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    A: Account,
+{
+    fn send_request_boxed(&mut self, request: OutgoingRequest<A>) -> BoxedIlpFuture<'_> {
+        Box::pin(self.send_request(request))
+    }
+
+    fn clone_box(&self) -> Box<dyn DynOutgoingService<A>> {
+        Box::new(self.clone())
+    }
+}
+
+/// Builds a chain of services whose middleware are only known at runtime, without the type
+/// of the chain changing with every middleware added to it. The chain is built from the
+/// last service the requests pass through: each middleware wraps the ones added before it.
+/// Middleware which are disabled are skipped with `wrap_if`, so the chain only boxes the
+/// services it contains once each.
+pub struct ServiceChainBuilder<S> {
+    service: S,
+}
+
+impl<A: Account + 'static> ServiceChainBuilder<BoxedIncomingService<A>> {
+    /// Starts a chain of incoming services with the last service the requests pass through
+    pub fn incoming<I>(service: I) -> Self
+    where
+        I: IncomingService<A> + Clone + Send + Sync + 'static,
+    {
+        ServiceChainBuilder {
+            service: BoxedIncomingService::new(service),
+        }
+    }
+
+    /// Adds the middleware returned by the function, which is given the chain built so far
+    pub fn wrap<F, I>(self, middleware: F) -> Self
+    where
+        F: FnOnce(BoxedIncomingService<A>) -> I,
+        I: IncomingService<A> + Clone + Send + Sync + 'static,
+    {
+        ServiceChainBuilder {
+            service: BoxedIncomingService::new(middleware(self.service)),
+        }
+    }
+
+    /// Like `wrap`, for functions which return a `BoxedIncomingService`, e.g. because they
+    /// choose between middleware of different types. The service is not boxed again.
+    pub fn wrap_boxed<F>(self, middleware: F) -> Self
+    where
+        F: FnOnce(BoxedIncomingService<A>) -> BoxedIncomingService<A>,
+    {
+        ServiceChainBuilder {
+            service: middleware(self.service),
+        }
+    }
+
+    /// Adds the middleware only if the condition is true
+    pub fn wrap_if<F, I>(self, condition: bool, middleware: F) -> Self
+    where
+        F: FnOnce(BoxedIncomingService<A>) -> I,
+        I: IncomingService<A> + Clone + Send + Sync + 'static,
+    {
+        if condition {
+            self.wrap(middleware)
+        } else {
+            self
+        }
+    }
+}
+
+impl<A: Account + 'static> ServiceChainBuilder<BoxedOutgoingService<A>> {
+    /// Starts a chain of outgoing services with the last service the requests pass through
+    pub fn outgoing<O>(service: O) -> Self
+    where
+        O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    {
+        ServiceChainBuilder {
+            service: BoxedOutgoingService::new(service),
+        }
+    }
+
+    /// Adds the middleware returned by the function, which is given the chain built so far
+    pub fn wrap<F, O>(self, middleware: F) -> Self
+    where
+        F: FnOnce(BoxedOutgoingService<A>) -> O,
+        O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    {
+        ServiceChainBuilder {
+            service: BoxedOutgoingService::new(middleware(self.service)),
+        }
+    }
+
+    /// Like `wrap`, for functions which return a `BoxedOutgoingService`, e.g. because they
+    /// choose between middleware of different types. The service is not boxed again.
+    pub fn wrap_boxed<F>(self, middleware: F) -> Self
+    where
+        F: FnOnce(BoxedOutgoingService<A>) -> BoxedOutgoingService<A>,
+    {
+        ServiceChainBuilder {
+            service: middleware(self.service),
+        }
+    }
+
+    /// Adds the middleware only if the condition is true
+    pub fn wrap_if<F, O>(self, condition: bool, middleware: F) -> Self
+    where
+        F: FnOnce(BoxedOutgoingService<A>) -> O,
+        O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    {
+        if condition {
+            self.wrap(middleware)
+        } else {
+            self
+        }
+    }
+}
+
+impl<S> ServiceChainBuilder<S> {
+    /// Returns the chain, whose first service is the middleware added last
+    pub fn build(self) -> S {
+        self.service
     }
 }
 
@@ -349,7 +606,7 @@ pub trait AddressStore {
 
 // Even though we wrap the types _a lot_ of times in multiple configurations
 // the tests still build nearly instantly. The trick is to make the wrapping function
-// take a trait object instead of the concrete type
+// take a boxed service instead of the concrete type
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -361,13 +618,13 @@ mod tests {
         // a normal async function
         async fn foo<A: Account>(
             request: IncomingRequest<A>,
-            mut next: Box<dyn IncomingService<A> + Send>,
+            mut next: BoxedIncomingService<A>,
         ) -> IlpResult {
             next.handle_request(request).await
         }
 
         // and with a closure (async closure are unstable)
-        let foo2 = move |request, mut next: Box<dyn IncomingService<TestAccount> + Send>| async move {
+        let foo2 = move |request, mut next: BoxedIncomingService<TestAccount>| async move {
             next.handle_request(request).await
         };
 
@@ -408,13 +665,13 @@ mod tests {
         // a normal async function
         async fn foo<A: Account>(
             request: OutgoingRequest<A>,
-            mut next: Box<dyn OutgoingService<A> + Send>,
+            mut next: BoxedOutgoingService<A>,
         ) -> IlpResult {
             next.send_request(request).await
         }
 
         // and with a closure (async closure are unstable)
-        let foo2 = move |request, mut next: Box<dyn OutgoingService<TestAccount> + Send>| async move {
+        let foo2 = move |request, mut next: BoxedOutgoingService<TestAccount>| async move {
             next.send_request(request).await
         };
 
@@ -450,6 +707,75 @@ mod tests {
         let _s = s.wrap(foo2);
     }
 
+    #[test]
+    fn boxed_services_can_be_chained_and_cloned() {
+        let s: LayeredService<_, TestAccount> = LayeredService::new_incoming(BaseService);
+        let s = BoxedIncomingService::new(s);
+        let s = BoxedIncomingService::new(LayeredService::new_incoming(s));
+        let _s = s.clone().wrap(
+            |request, mut next: BoxedIncomingService<TestAccount>| async move {
+                next.handle_request(request).await
+            },
+        );
+
+        let s: LayeredService<_, TestAccount> = LayeredService::new_outgoing(BaseService);
+        let s = BoxedOutgoingService::new(s);
+        let s = BoxedOutgoingService::new(LayeredService::new_outgoing(s));
+        let _s = s.clone().wrap(
+            |request, mut next: BoxedOutgoingService<TestAccount>| async move {
+                next.send_request(request).await
+            },
+        );
+    }
+
+    #[test]
+    fn chain_builder_adds_middleware_at_runtime() {
+        use interledger_packet::{FulfillBuilder, PrepareBuilder};
+        use std::sync::Mutex;
+        use std::time::{Duration, SystemTime};
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let layer = |name: &'static str| {
+            let calls = calls.clone();
+            move |next: BoxedIncomingService<TestAccount>| {
+                next.wrap(
+                    move |request, mut next: BoxedIncomingService<TestAccount>| {
+                        calls.lock().unwrap().push(name);
+                        async move { next.handle_request(request).await }
+                    },
+                )
+            }
+        };
+        let mut chain = ServiceChainBuilder::incoming(incoming_service_fn(|_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        }))
+        .wrap(layer("last"))
+        .wrap_if(false, layer("skipped"))
+        .wrap_boxed(|next| BoxedIncomingService::new(layer("boxed")(next)))
+        .wrap_if(true, layer("first"))
+        .build();
+
+        let result = futures::executor::block_on(
+            chain.handle_request(IncomingRequest {
+                from: TestAccount,
+                prepare: PrepareBuilder {
+                    destination: EXAMPLE_ADDRESS.clone(),
+                    amount: 100,
+                    expires_at: SystemTime::now() + Duration::from_secs(30),
+                    execution_condition: &[0; 32],
+                    data: &[],
+                }
+                .build(),
+            }),
+        );
+        assert!(result.is_ok());
+        assert_eq!(*calls.lock().unwrap(), vec!["first", "boxed", "last"]);
+    }
+
     #[derive(Clone)]
     struct BaseService;
 
@@ -485,38 +811,42 @@ mod tests {
         }
     }
 
-    #[async_trait]
-    impl<A: Account + 'static> OutgoingService<A> for BaseService {
-        async fn send_request(&mut self, _request: OutgoingRequest<A>) -> IlpResult {
+    impl<A: Account> OutgoingService<A> for BaseService {
+        type Future<'a> = Ready<IlpResult>;
+
+        fn send_request(&mut self, _request: OutgoingRequest<A>) -> Self::Future<'_> {
             unimplemented!()
         }
     }
 
-    #[async_trait]
-    impl<A: Account + 'static> IncomingService<A> for BaseService {
-        async fn handle_request(&mut self, _request: IncomingRequest<A>) -> IlpResult {
+    impl<A: Account> IncomingService<A> for BaseService {
+        type Future<'a> = Ready<IlpResult>;
+
+        fn handle_request(&mut self, _request: IncomingRequest<A>) -> Self::Future<'_> {
             unimplemented!()
         }
     }
 
-    #[async_trait]
     impl<I, A> OutgoingService<A> for LayeredService<I, A>
     where
         I: OutgoingService<A> + Send + Sync + 'static,
-        A: Account + Send + Sync + 'static,
+        A: Account + Send + Sync,
     {
-        async fn send_request(&mut self, _request: OutgoingRequest<A>) -> IlpResult {
+        type Future<'a> = Ready<IlpResult>;
+
+        fn send_request(&mut self, _request: OutgoingRequest<A>) -> Self::Future<'_> {
             unimplemented!()
         }
     }
 
-    #[async_trait]
     impl<I, A> IncomingService<A> for LayeredService<I, A>
     where
         I: IncomingService<A> + Send + Sync + 'static,
-        A: Account + Send + Sync + 'static,
+        A: Account + Send + Sync,
     {
-        async fn handle_request(&mut self, _request: IncomingRequest<A>) -> IlpResult {
+        type Future<'a> = Ready<IlpResult>;
+
+        fn handle_request(&mut self, _request: IncomingRequest<A>) -> Self::Future<'_> {
             unimplemented!()
         }
     }
@@ -0,0 +1,44 @@
+//! Metrics recorded by the services, shared by all the Interledger.rs crates.
+//!
+//! The metrics go to the process-wide recorder of the [`metrics`](https://docs.rs/metrics)
+//! crate, which the node installs along with its Prometheus endpoint, so that the store,
+//! BTP, CCP and the node's own services all report to the same registry. Without the
+//! `metrics` feature the functions do nothing and the crates don't depend on `metrics`.
+//!
+//! The names are dot-separated, e.g. `store.operation.duration`, and the labels are
+//! `(name, value)` pairs.
+
+/// Labels of a metric, as `(name, value)` pairs
+pub type Labels<'a> = &'a [(&'static str, &'a str)];
+
+#[cfg(feature = "metrics")]
+fn key(name: &'static str, labels: Labels<'_>) -> metrics_crate::Key {
+    metrics_crate::Key::from_name_and_labels(
+        name,
+        labels
+            .iter()
+            .map(|(label, value)| metrics_crate::Label::new(*label, value.to_string()))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Adds `value` to the counter
+#[allow(unused_variables)]
+pub fn increment_counter(name: &'static str, labels: Labels<'_>, value: u64) {
+    #[cfg(feature = "metrics")]
+    metrics_crate::recorder().increment_counter(key(name, labels), value);
+}
+
+/// Sets the gauge to `value`
+#[allow(unused_variables)]
+pub fn update_gauge(name: &'static str, labels: Labels<'_>, value: i64) {
+    #[cfg(feature = "metrics")]
+    metrics_crate::recorder().update_gauge(key(name, labels), value);
+}
+
+/// Records `value` in the histogram. Durations are recorded in nanoseconds.
+#[allow(unused_variables)]
+pub fn record_histogram(name: &'static str, labels: Labels<'_>, value: u64) {
+    #[cfg(feature = "metrics")]
+    metrics_crate::recorder().record_histogram(key(name, labels), value);
+}
@@ -0,0 +1,375 @@
+//! The executor which the services spawn their background tasks and timers on.
+//!
+//! The library crates do not depend on an executor directly, so that they can be driven
+//! by tokio, async-std or an embedded executor. The executor is set once per process with
+//! [`set_runtime`](./fn.set_runtime.html), before the services are started. If none is set,
+//! tokio's is used when the `tokio` feature is enabled. The stores and the node check that
+//! there is an executor with [`ensure`](./fn.ensure.html) when they are built, so that a
+//! missing one is reported then instead of when the first task is spawned.
+//!
+//! Work which blocks the thread it runs on, such as hashing passwords or writing files, is
+//! run with [`run_blocking`](./fn.run_blocking.html) on the executor's threads for blocking
+//! work, so that the threads forwarding the packets are not held up by it.
+//!
+//! Transports can open their TCP connections with [`connect`](./fn.connect.html), which uses
+//! the executor's networking. The transports built on tokio's networking (BTP, ILP-over-HTTP,
+//! gRPC, QUIC) and the Redis store still require a tokio runtime.
+use futures::channel::oneshot;
+use futures::future::{self, AbortHandle, BoxFuture, Either, FutureExt};
+use futures::io::{AsyncRead, AsyncWrite};
+use once_cell::sync::OnceCell;
+use std::{
+    fmt,
+    future::Future,
+    io,
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A connection opened by the executor
+pub trait Connection: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Connection for T {}
+
+/// An executor able to run tasks in the background and to wake them up after a delay
+pub trait Runtime: Send + Sync + 'static {
+    /// Runs the future in the background until it completes
+    fn spawn(&self, future: BoxFuture<'static, ()>);
+
+    /// Returns a future which completes once the duration has passed
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    /// Runs the blocking closure away from the threads which run the tasks. By default it
+    /// is run on a thread of its own.
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        thread::spawn(task);
+    }
+
+    /// Opens a TCP connection to the address. By default the executor can't open any.
+    fn connect(&self, address: SocketAddr) -> BoxFuture<'static, io::Result<Box<dyn Connection>>> {
+        let _ = address;
+        Box::pin(future::ready(Err(io::Error::new(
+            io::ErrorKind::Other,
+            "The runtime can't open connections",
+        ))))
+    }
+}
+
+static RUNTIME: OnceCell<Box<dyn Runtime>> = OnceCell::new();
+
+/// No runtime was set and the `tokio` feature is disabled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoRuntime;
+
+impl fmt::Display for NoRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("No runtime was set with interledger_service::runtime::set_runtime and the tokio feature is disabled")
+    }
+}
+
+impl std::error::Error for NoRuntime {}
+
+/// Sets the executor of the process. It can only be set once, and only before
+/// a task is spawned, so the runtime is returned back if one is already in use.
+pub fn set_runtime<R: Runtime>(runtime: R) -> Result<(), Box<dyn Runtime>> {
+    RUNTIME.set(Box::new(runtime))
+}
+
+/// Returns an error if no runtime was set and there is no default one. The stores and the
+/// node call it when they are built, before they spawn any task, which the other functions
+/// of this module then assume.
+pub fn ensure() -> Result<(), NoRuntime> {
+    current().map(|_| ())
+}
+
+fn current() -> Result<&'static dyn Runtime, NoRuntime> {
+    RUNTIME
+        .get_or_try_init(|| default_runtime().ok_or(NoRuntime))
+        .map(|runtime| runtime.as_ref())
+}
+
+fn runtime() -> &'static dyn Runtime {
+    current().expect("The runtime is checked with runtime::ensure before tasks are spawned")
+}
+
+#[cfg(feature = "tokio")]
+fn default_runtime() -> Option<Box<dyn Runtime>> {
+    Some(Box::new(TokioRuntime))
+}
+
+#[cfg(not(feature = "tokio"))]
+fn default_runtime() -> Option<Box<dyn Runtime>> {
+    None
+}
+
+/// Runs the future in the background on the executor of the process
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    runtime().spawn(Box::pin(future))
+}
+
+/// Handle of a task spawned with [`spawn_abortable`](./fn.spawn_abortable.html)
+#[derive(Clone, Debug)]
+pub struct TaskHandle(AbortHandle);
+
+impl TaskHandle {
+    /// Stops the task the next time it is polled
+    pub fn abort(&self) {
+        self.0.abort()
+    }
+}
+
+/// Runs the future in the background, like [`spawn`](./fn.spawn.html), until it
+/// completes or it is aborted through the returned handle
+pub fn spawn_abortable<F>(future: F) -> TaskHandle
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (future, handle) = future::abortable(future);
+    spawn(future.map(|_| ()));
+    TaskHandle(handle)
+}
+
+/// Completes once the duration has passed
+pub fn sleep(duration: Duration) -> BoxFuture<'static, ()> {
+    runtime().sleep(duration)
+}
+
+/// Runs the blocking closure on the executor's threads for blocking work and returns its result.
+/// If the closure panics, so does the returned future.
+pub async fn run_blocking<F, T>(task: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    runtime().spawn_blocking(Box::new(move || {
+        let _ = sender.send(task());
+    }));
+    receiver.await.expect("The blocking task panicked")
+}
+
+/// Ticks at a fixed period, see [`interval`](./fn.interval.html)
+#[derive(Debug)]
+pub struct Interval {
+    period: Duration,
+    next_tick: Instant,
+}
+
+impl Interval {
+    /// Completes at the next tick. The first tick completes immediately, and the
+    /// ticks which were missed complete immediately one after the other.
+    pub async fn tick(&mut self) {
+        let now = Instant::now();
+        if self.next_tick > now {
+            sleep(self.next_tick - now).await;
+        }
+        self.next_tick += self.period;
+    }
+}
+
+/// Returns an interval which ticks immediately and then every `period`
+pub fn interval(period: Duration) -> Interval {
+    Interval {
+        period,
+        next_tick: Instant::now(),
+    }
+}
+
+/// Opens a TCP connection to the address with the executor's networking
+pub fn connect(address: SocketAddr) -> BoxFuture<'static, io::Result<Box<dyn Connection>>> {
+    runtime().connect(address)
+}
+
+/// The future did not complete before the timeout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+/// Waits for the future for at most the duration
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, Elapsed> {
+    futures::pin_mut!(future);
+    match future::select(future, sleep(duration)).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => Err(Elapsed),
+    }
+}
+
+/// Spawns the tasks on the tokio runtime the services are called from
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioRuntime;
+
+#[cfg(feature = "tokio")]
+impl Runtime for TokioRuntime {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        tokio_crate::spawn(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio_crate::time::sleep(duration))
+    }
+
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        tokio_crate::task::spawn_blocking(task);
+    }
+
+    fn connect(&self, address: SocketAddr) -> BoxFuture<'static, io::Result<Box<dyn Connection>>> {
+        Box::pin(async move {
+            let stream = tokio_crate::net::TcpStream::connect(address).await?;
+            Ok(Box::new(TokioConnection(stream)) as Box<dyn Connection>)
+        })
+    }
+}
+
+/// Adapts tokio's TCP stream to the I/O traits of the futures crate
+#[cfg(feature = "tokio")]
+struct TokioConnection(tokio_crate::net::TcpStream);
+
+#[cfg(feature = "tokio")]
+impl AsyncRead for TokioConnection {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        let mut read_buf = tokio_crate::io::ReadBuf::new(buf);
+        futures::ready!(tokio_crate::io::AsyncRead::poll_read(
+            std::pin::Pin::new(&mut self.0),
+            cx,
+            &mut read_buf
+        ))?;
+        std::task::Poll::Ready(Ok(read_buf.filled().len()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncWrite for TokioConnection {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        tokio_crate::io::AsyncWrite::poll_write(std::pin::Pin::new(&mut self.0), cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        tokio_crate::io::AsyncWrite::poll_flush(std::pin::Pin::new(&mut self.0), cx)
+    }
+
+    fn poll_close(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        tokio_crate::io::AsyncWrite::poll_shutdown(std::pin::Pin::new(&mut self.0), cx)
+    }
+}
+
+/// Spawns the tasks on the global async-std executor
+#[cfg(feature = "async-std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStdRuntime;
+
+#[cfg(feature = "async-std")]
+impl Runtime for AsyncStdRuntime {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        async_std::task::spawn(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        async_std::task::spawn_blocking(task);
+    }
+
+    fn connect(&self, address: SocketAddr) -> BoxFuture<'static, io::Result<Box<dyn Connection>>> {
+        Box::pin(async move {
+            let stream = async_std::net::TcpStream::connect(address).await?;
+            Ok(Box::new(stream) as Box<dyn Connection>)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs each task on a thread of its own, like an embedded executor might
+    struct ThreadRuntime;
+
+    impl Runtime for ThreadRuntime {
+        fn spawn(&self, future: BoxFuture<'static, ()>) {
+            thread::spawn(move || futures::executor::block_on(future));
+        }
+
+        fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+            let (sender, receiver) = oneshot::channel();
+            thread::spawn(move || {
+                thread::sleep(duration);
+                let _ = sender.send(());
+            });
+            Box::pin(async move {
+                let _ = receiver.await;
+            })
+        }
+    }
+
+    #[test]
+    fn uses_the_runtime_which_was_set() {
+        assert!(set_runtime(ThreadRuntime).is_ok());
+        assert!(set_runtime(ThreadRuntime).is_err());
+
+        let (sender, receiver) = oneshot::channel();
+        spawn(async move {
+            sleep(Duration::from_millis(10)).await;
+            let _ = sender.send("done");
+        });
+        assert_eq!(futures::executor::block_on(receiver), Ok("done"));
+
+        let never = future::pending::<()>();
+        let result = futures::executor::block_on(timeout(Duration::from_millis(10), never));
+        assert_eq!(result, Err(Elapsed));
+        let result = futures::executor::block_on(timeout(Duration::from_secs(1), async { 1 }));
+        assert_eq!(result, Ok(1));
+
+        let caller = thread::current().id();
+        let worker = futures::executor::block_on(run_blocking(|| thread::current().id()));
+        assert_ne!(worker, caller);
+
+        assert_eq!(ensure(), Ok(()));
+        let address = "127.0.0.1:1".parse().unwrap();
+        let result = futures::executor::block_on(connect(address));
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::Other);
+
+        let mut interval = interval(Duration::from_millis(20));
+        let start = Instant::now();
+        futures::executor::block_on(interval.tick());
+        assert!(start.elapsed() < Duration::from_millis(20));
+        futures::executor::block_on(interval.tick());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        let (sender, receiver) = oneshot::channel::<()>();
+        let task = spawn_abortable(async move {
+            future::pending::<()>().await;
+            drop(sender);
+        });
+        task.abort();
+        // The sender is dropped along with the aborted task
+        assert!(futures::executor::block_on(receiver).is_err());
+    }
+}
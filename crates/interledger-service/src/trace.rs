@@ -1,32 +1,33 @@
 use crate::*;
-use async_trait::async_trait;
 use tracing_futures::{Instrument, Instrumented};
 
 // TODO see if we can replace this with the tower tracing later
-#[async_trait]
 impl<IO, A> IncomingService<A> for Instrumented<IO>
 where
     IO: IncomingService<A> + Clone + Send,
-    A: Account + 'static,
+    A: Account,
 {
-    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
-        self.inner_mut()
-            .handle_request(request)
-            .in_current_span()
-            .await
+    type Future<'a>
+        = Instrumented<IO::Future<'a>>
+    where
+        Self: 'a;
+
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        self.inner_mut().handle_request(request).in_current_span()
     }
 }
 
-#[async_trait]
 impl<IO, A> OutgoingService<A> for Instrumented<IO>
 where
     IO: OutgoingService<A> + Clone + Send,
-    A: Account + 'static,
+    A: Account,
 {
-    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
-        self.inner_mut()
-            .send_request(request)
-            .in_current_span()
-            .await
+    type Future<'a>
+        = Instrumented<IO::Future<'a>>
+    where
+        Self: 'a;
+
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        self.inner_mut().send_request(request).in_current_span()
     }
 }
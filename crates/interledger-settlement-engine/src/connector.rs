@@ -0,0 +1,76 @@
+use bytes::Bytes;
+use interledger_settlement::core::types::Quantity;
+use num_bigint::BigUint;
+use tracing::trace;
+use url::Url;
+
+/// Client of the settlement API which the connector exposes to its engines
+#[derive(Clone, Debug)]
+pub struct ConnectorClient {
+    client: reqwest::Client,
+    url: Url,
+}
+
+impl ConnectorClient {
+    /// Creates a client of the connector's settlement API at the provided URL
+    pub fn new(url: Url) -> Self {
+        ConnectorClient {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+
+    fn account_url(&self, account_id: &str, endpoint: &str) -> Url {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .expect("Invalid connector URL")
+            .push("accounts")
+            .push(account_id)
+            .push(endpoint);
+        url
+    }
+
+    /// Sends a message to the engine of the account's peer (via the connector)
+    /// and returns the peer's response
+    pub async fn send_message(
+        &self,
+        account_id: &str,
+        message: Vec<u8>,
+    ) -> Result<Bytes, reqwest::Error> {
+        self.client
+            .post(self.account_url(account_id, "messages"))
+            .header("Content-Type", "application/octet-stream")
+            .body(message)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await
+    }
+
+    /// Notifies the connector of a settlement received from the account's peer.
+    /// The connector credits every idempotency key at most once, so the ledger's
+    /// identifier of the payment should be used.
+    pub async fn notify_settlement(
+        &self,
+        account_id: &str,
+        idempotency_key: &str,
+        amount: &BigUint,
+        asset_scale: u8,
+    ) -> Result<(), reqwest::Error> {
+        trace!(
+            "Notifying connector of incoming settlement {} of {} to account {}",
+            idempotency_key,
+            amount,
+            account_id
+        );
+        self.client
+            .post(self.account_url(account_id, "settlements"))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&Quantity::new(amount, asset_scale))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,462 @@
+use super::{connector::ConnectorClient, ledger::Ledger, store::PeerDetailsStore};
+use async_trait::async_trait;
+use bytes::Bytes;
+use interledger_errors::ApiError;
+use interledger_settlement::core::{
+    engines_api::create_settlement_engine_filter,
+    idempotency::IdempotentStore,
+    scale_with_precision_loss,
+    types::{ApiResponse, ApiResult, LeftoversStore, Quantity, SettlementEngine},
+};
+use num_bigint::BigUint;
+use num_traits::Zero;
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, str::FromStr, sync::Arc};
+use tracing::{debug, error, info};
+use url::Url;
+use warp::Filter;
+
+/// Messages exchanged with the peer's engine
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Message {
+    /// Requests the details with which the peer is paid
+    PaymentDetails,
+}
+
+/// Settlement engine which implements the
+/// [Settlement Engine API](https://interledger.org/rfcs/0038-settlement-engines/)
+/// on top of a [`Ledger`].
+///
+/// The payment details of the peers are requested from their engines with a
+/// `{"type":"paymentDetails"}` message and saved in the store. Amounts which are too
+/// small to be paid in the ledger's asset scale are carried over to the next settlement.
+pub struct LedgerSettlementEngine<L, S> {
+    ledger: Arc<L>,
+    store: S,
+    connector: ConnectorClient,
+}
+
+impl<L, S: Clone> Clone for LedgerSettlementEngine<L, S> {
+    fn clone(&self) -> Self {
+        LedgerSettlementEngine {
+            ledger: self.ledger.clone(),
+            store: self.store.clone(),
+            connector: self.connector.clone(),
+        }
+    }
+}
+
+impl<L, S> LedgerSettlementEngine<L, S>
+where
+    L: Ledger + Send + Sync + 'static,
+    S: PeerDetailsStore
+        + LeftoversStore<AccountId = String, AssetType = BigUint>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    /// Creates an engine which pays on the ledger and talks to the connector's
+    /// settlement API at the provided URL
+    pub fn new(ledger: L, store: S, connector_url: Url) -> Self {
+        LedgerSettlementEngine {
+            ledger: Arc::new(ledger),
+            store,
+            connector: ConnectorClient::new(connector_url),
+        }
+    }
+
+    pub fn ledger(&self) -> &L {
+        &self.ledger
+    }
+
+    /// Notifies the connector of a payment which the account's peer made on the ledger.
+    /// The id of the payment is used as the idempotency key, so a payment is credited
+    /// at most once and failed notifications can be retried.
+    pub async fn credit_incoming_payment(
+        &self,
+        account_id: &str,
+        payment_id: &str,
+        amount: &BigUint,
+    ) -> Result<(), reqwest::Error> {
+        self.connector
+            .notify_settlement(account_id, payment_id, amount, self.ledger.asset_scale())
+            .await
+    }
+
+    /// Returns the peer's payment details, asking the peer's engine for them
+    /// (via the connector) if we don't know them yet
+    async fn peer_payment_details(&self, account_id: &str) -> Result<L::PaymentDetails, ApiError> {
+        if let Some(details) = self
+            .store
+            .load_peer_details(account_id)
+            .await
+            .map_err(internal_error)?
+        {
+            return serde_json::from_str(&details).map_err(internal_error);
+        }
+
+        let message = serde_json::to_vec(&Message::PaymentDetails).unwrap();
+        let response = self
+            .connector
+            .send_message(account_id, message)
+            .await
+            .map_err(internal_error)?;
+        let details: L::PaymentDetails = serde_json::from_slice(&response).map_err(|err| {
+            ApiError::internal_server_error().detail(format!(
+                "Invalid payment details from the peer's engine: {}",
+                err
+            ))
+        })?;
+        self.ledger
+            .validate_peer_details(account_id, &details)
+            .await
+            .map_err(|err| {
+                ApiError::internal_server_error().detail(format!(
+                    "Invalid payment details from the peer's engine: {}",
+                    err
+                ))
+            })?;
+        self.store
+            .save_peer_details(account_id, &serde_json::to_string(&details).unwrap())
+            .await
+            .map_err(internal_error)?;
+        debug!("Saved payment details of account {}", account_id);
+        Ok(details)
+    }
+}
+
+impl<L, S> LedgerSettlementEngine<L, S>
+where
+    L: Ledger + Send + Sync + 'static,
+    S: PeerDetailsStore
+        + LeftoversStore<AccountId = String, AssetType = BigUint>
+        + IdempotentStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    /// Returns the idempotent Settlement Engine API of the engine
+    pub fn api(
+        &self,
+    ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        create_settlement_engine_filter(self.clone(), self.store.clone())
+    }
+
+    /// Serves the Settlement Engine API at the provided address, forever
+    pub async fn serve(self, bind_address: SocketAddr) {
+        info!("Settlement engine listening on {}", bind_address);
+        warp::serve(self.api()).bind(bind_address).await;
+    }
+}
+
+#[async_trait]
+impl<L, S> SettlementEngine for LedgerSettlementEngine<L, S>
+where
+    L: Ledger + Send + Sync + 'static,
+    S: PeerDetailsStore
+        + LeftoversStore<AccountId = String, AssetType = BigUint>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    async fn create_account(&self, account_id: String) -> ApiResult {
+        // The peer's engine may not be set up yet, in which case the details are
+        // requested when the peer asks for ours or before the first settlement
+        if let Err(err) = self.peer_payment_details(&account_id).await {
+            debug!(
+                "Could not get payment details of account {} yet: {:?}",
+                account_id, err
+            );
+        }
+        Ok(ApiResponse::Default)
+    }
+
+    async fn delete_account(&self, account_id: String) -> ApiResult {
+        self.store
+            .delete_peer_details(&account_id)
+            .await
+            .map_err(internal_error)?;
+        self.store
+            .clear_uncredited_settlement_amount(account_id)
+            .await
+            .map_err(internal_error)?;
+        Ok(ApiResponse::Default)
+    }
+
+    async fn send_money(&self, account_id: String, money: Quantity) -> ApiResult {
+        let amount = BigUint::from_str(&money.amount).map_err(|err| {
+            ApiError::bad_request().detail(format!("Invalid amount {}: {}", money.amount, err))
+        })?;
+        let asset_scale = self.ledger.asset_scale();
+        let (amount, precision_loss) = scale_with_precision_loss(amount, asset_scale, money.scale);
+        let leftovers = self
+            .store
+            .load_uncredited_settlement_amount(account_id.clone(), asset_scale)
+            .await
+            .map_err(internal_error)?;
+        let amount = amount + leftovers;
+        if !precision_loss.is_zero() {
+            self.store
+                .save_uncredited_settlement_amount(
+                    account_id.clone(),
+                    (precision_loss, std::cmp::max(money.scale, asset_scale)),
+                )
+                .await
+                .map_err(internal_error)?;
+        }
+        if amount.is_zero() {
+            return Ok(ApiResponse::Default);
+        }
+
+        let details = self.peer_payment_details(&account_id).await?;
+        let payment_id = self
+            .ledger
+            .pay(&account_id, &details, amount.clone())
+            .await
+            .map_err(|err| {
+                error!("Error settling {} to {}: {}", amount, account_id, err);
+                internal_error(err)
+            })?;
+        debug!(
+            "Settled {} to account {} in payment {}",
+            amount, account_id, payment_id
+        );
+        Ok(ApiResponse::Default)
+    }
+
+    async fn receive_message(&self, account_id: String, message: Vec<u8>) -> ApiResult {
+        let message: Message = serde_json::from_slice(&message)
+            .map_err(|err| ApiError::bad_request().detail(format!("Invalid message: {}", err)))?;
+        match message {
+            Message::PaymentDetails => {
+                let details = self
+                    .ledger
+                    .payment_details(&account_id)
+                    .await
+                    .map_err(internal_error)?;
+                // The peer is set up now, so we can ask for its details if we couldn't
+                // when the account was created
+                let engine = self.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = engine.peer_payment_details(&account_id).await {
+                        debug!(
+                            "Could not get payment details of account {}: {:?}",
+                            account_id, err
+                        );
+                    }
+                });
+                Ok(ApiResponse::Data(Bytes::from(
+                    serde_json::to_vec(&details).map_err(internal_error)?,
+                )))
+            }
+        }
+    }
+}
+
+fn internal_error<E: std::fmt::Display>(err: E) -> ApiError {
+    ApiError::internal_server_error().detail(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::LedgerError;
+    use interledger_errors::LeftoversStoreError;
+    use mockito::mock;
+    use parking_lot::RwLock;
+    use redis_crate::RedisError;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct TestDetails {
+        address: String,
+    }
+
+    #[derive(Default)]
+    struct TestLedger {
+        payments: RwLock<Vec<(String, BigUint)>>,
+    }
+
+    #[async_trait]
+    impl Ledger for TestLedger {
+        type PaymentDetails = TestDetails;
+
+        fn asset_scale(&self) -> u8 {
+            6
+        }
+
+        async fn payment_details(&self, account_id: &str) -> Result<TestDetails, LedgerError> {
+            Ok(TestDetails {
+                address: format!("own:{}", account_id),
+            })
+        }
+
+        async fn pay(
+            &self,
+            _: &str,
+            details: &TestDetails,
+            amount: BigUint,
+        ) -> Result<String, LedgerError> {
+            let mut payments = self.payments.write();
+            payments.push((details.address.clone(), amount));
+            Ok(payments.len().to_string())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct TestStore {
+        peer_details: Arc<RwLock<HashMap<String, String>>>,
+        leftovers: Arc<RwLock<HashMap<String, (BigUint, u8)>>>,
+    }
+
+    #[async_trait]
+    impl PeerDetailsStore for TestStore {
+        async fn save_peer_details(
+            &self,
+            account_id: &str,
+            details: &str,
+        ) -> Result<(), RedisError> {
+            self.peer_details
+                .write()
+                .insert(account_id.to_string(), details.to_string());
+            Ok(())
+        }
+
+        async fn load_peer_details(&self, account_id: &str) -> Result<Option<String>, RedisError> {
+            Ok(self.peer_details.read().get(account_id).cloned())
+        }
+
+        async fn delete_peer_details(&self, account_id: &str) -> Result<(), RedisError> {
+            self.peer_details.write().remove(account_id);
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl LeftoversStore for TestStore {
+        type AccountId = String;
+        type AssetType = BigUint;
+
+        async fn save_uncredited_settlement_amount(
+            &self,
+            account_id: String,
+            uncredited_settlement_amount: (BigUint, u8),
+        ) -> Result<(), LeftoversStoreError> {
+            self.leftovers
+                .write()
+                .insert(account_id, uncredited_settlement_amount);
+            Ok(())
+        }
+
+        async fn load_uncredited_settlement_amount(
+            &self,
+            account_id: String,
+            local_scale: u8,
+        ) -> Result<BigUint, LeftoversStoreError> {
+            Ok(match self.leftovers.write().remove(&account_id) {
+                Some((amount, scale)) => scale_with_precision_loss(amount, local_scale, scale).0,
+                None => BigUint::zero(),
+            })
+        }
+
+        async fn clear_uncredited_settlement_amount(
+            &self,
+            account_id: String,
+        ) -> Result<(), LeftoversStoreError> {
+            self.leftovers.write().remove(&account_id);
+            Ok(())
+        }
+
+        async fn get_uncredited_settlement_amount(
+            &self,
+            account_id: String,
+        ) -> Result<(BigUint, u8), LeftoversStoreError> {
+            Ok(self
+                .leftovers
+                .read()
+                .get(&account_id)
+                .cloned()
+                .unwrap_or((BigUint::zero(), 0)))
+        }
+    }
+
+    fn test_engine(store: TestStore) -> LedgerSettlementEngine<TestLedger, TestStore> {
+        LedgerSettlementEngine::new(
+            TestLedger::default(),
+            store,
+            Url::parse(&mockito::server_url()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn pays_the_peer_with_the_details_from_its_engine() {
+        let m = mock("POST", "/accounts/alice/messages")
+            .match_header("Content-Type", "application/octet-stream")
+            .match_body(r#"{"type":"paymentDetails"}"#)
+            .with_body(r#"{"address":"peer"}"#)
+            .create();
+        let engine = test_engine(TestStore::default());
+
+        engine
+            .send_money("alice".to_string(), Quantity::new(1_234_567, 9))
+            .await
+            .unwrap();
+        // The details are only requested once
+        engine
+            .send_money("alice".to_string(), Quantity::new(2_000_000, 9))
+            .await
+            .unwrap();
+
+        m.assert();
+        assert_eq!(
+            *engine.ledger().payments.read(),
+            vec![
+                ("peer".to_string(), BigUint::from(1234u32)),
+                ("peer".to_string(), BigUint::from(2000u32)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn carries_over_amounts_too_small_to_be_paid() {
+        let store = TestStore::default();
+        store
+            .save_peer_details("bob", r#"{"address":"peer"}"#)
+            .await
+            .unwrap();
+        let engine = test_engine(store.clone());
+
+        engine
+            .send_money("bob".to_string(), Quantity::new(999, 9))
+            .await
+            .unwrap();
+        assert!(engine.ledger().payments.read().is_empty());
+        assert_eq!(
+            store
+                .get_uncredited_settlement_amount("bob".to_string())
+                .await
+                .unwrap(),
+            (BigUint::from(999u32), 9)
+        );
+    }
+
+    #[tokio::test]
+    async fn answers_requests_for_payment_details() {
+        let engine = test_engine(TestStore::default());
+        let response = engine
+            .receive_message(
+                "carol".to_string(),
+                br#"{"type":"paymentDetails"}"#.to_vec(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response,
+            ApiResponse::Data(Bytes::from(r#"{"address":"own:carol"}"#))
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use async_trait::async_trait;
+use num_bigint::BigUint;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Error returned by the operations of a [`Ledger`]
+pub type LedgerError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The ledger-specific part of a settlement engine. Everything else (the HTTP API,
+/// idempotency, scale conversion and the communication with the connector and the
+/// peer's engine) is provided by the [`LedgerSettlementEngine`](crate::LedgerSettlementEngine).
+#[async_trait]
+pub trait Ledger {
+    /// What a peer needs to know to pay us on the ledger, e.g. an address and a
+    /// destination tag. It is exchanged with the peer's engine as JSON.
+    type PaymentDetails: Serialize + DeserializeOwned + Send + Sync;
+
+    /// Scale of the amounts which are paid on the ledger
+    fn asset_scale(&self) -> u8;
+
+    /// Returns the details with which the peer of the account pays us. Payments which
+    /// are made with these details must be attributable to the account, so that they
+    /// can be credited with
+    /// [`credit_incoming_payment`](crate::LedgerSettlementEngine::credit_incoming_payment).
+    async fn payment_details(&self, account_id: &str) -> Result<Self::PaymentDetails, LedgerError>;
+
+    /// Checks the details which the peer's engine sent for the account, before they are
+    /// saved. The default implementation accepts all details which could be deserialized.
+    async fn validate_peer_details(
+        &self,
+        _account_id: &str,
+        _details: &Self::PaymentDetails,
+    ) -> Result<(), LedgerError> {
+        Ok(())
+    }
+
+    /// Pays the amount, in the ledger's asset scale, to the peer with the provided
+    /// details and returns the ledger's identifier of the payment
+    async fn pay(
+        &self,
+        account_id: &str,
+        details: &Self::PaymentDetails,
+        amount: BigUint,
+    ) -> Result<String, LedgerError>;
+}
@@ -0,0 +1,33 @@
+//! # interledger-settlement-engine
+//!
+//! Scaffolding for settlement engines which implement the
+//! [Settlement Engine API](https://interledger.org/rfcs/0038-settlement-engines/).
+//! An engine for a new ledger only implements the [`Ledger`] trait, which describes how
+//! the peers are paid, and wraps it in a [`LedgerSettlementEngine`]. The engine provides
+//! the idempotent HTTP API, the conversion of the connector's amounts to the ledger's
+//! asset scale and the exchange of payment details with the peers' engines. Incoming
+//! payments are credited with
+//! [`credit_incoming_payment`](LedgerSettlementEngine::credit_incoming_payment).
+
+/// Client of the connector's settlement API
+mod connector;
+/// The generic engine which exposes a ledger over the Settlement Engine API
+mod engine;
+/// The trait which is implemented for every ledger
+mod ledger;
+/// Settlement engine without a ledger, for tests
+mod mock;
+/// Storage of the engine's data
+mod store;
+
+pub use connector::ConnectorClient;
+pub use engine::{LedgerSettlementEngine, Message};
+pub use ledger::{Ledger, LedgerError};
+pub use mock::MockSettlementEngine;
+pub use store::{EngineStore, PeerDetailsStore};
+
+pub use interledger_settlement::core::{
+    idempotency::IdempotentStore,
+    scale_with_precision_loss,
+    types::{LeftoversStore, Quantity},
+};
@@ -0,0 +1,270 @@
+use super::connector::ConnectorClient;
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::StatusCode;
+use interledger_errors::{ApiError, IdempotentStoreError};
+use interledger_settlement::core::{
+    engines_api::create_settlement_engine_filter,
+    idempotency::{IdempotentData, IdempotentStore},
+    types::{ApiResponse, ApiResult, Quantity, SettlementEngine},
+};
+use num_bigint::BigUint;
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{debug, info};
+use url::Url;
+use warp::Filter;
+
+#[derive(Default)]
+struct MockState {
+    delay: Duration,
+    failure_rate: f64,
+    failures: u32,
+    accounts: HashSet<String>,
+    settlements: Vec<(String, Quantity)>,
+    messages: Vec<(String, Vec<u8>)>,
+    idempotent_data: HashMap<String, IdempotentData>,
+}
+
+/// Settlement engine which does not touch a ledger, for testing settlement flows.
+///
+/// It implements the Settlement Engine API and records the accounts, settlements and
+/// messages it received. Every call can be delayed and made to fail, either for a number
+/// of calls or at random. Incoming settlements are simulated with
+/// [`credit_incoming_settlement`](MockSettlementEngine::credit_incoming_settlement).
+/// Like a real engine, it stores the response of every idempotency key, so failed calls
+/// only succeed if they are retried with a new key.
+#[derive(Clone, Default)]
+pub struct MockSettlementEngine {
+    state: Arc<Mutex<MockState>>,
+    connector: Option<ConnectorClient>,
+}
+
+impl MockSettlementEngine {
+    /// Creates an engine which accepts every call
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an engine which can credit incoming settlements with the connector's
+    /// settlement API at the provided URL
+    pub fn with_connector(connector_url: Url) -> Self {
+        MockSettlementEngine {
+            state: Arc::new(Mutex::new(MockState::default())),
+            connector: Some(ConnectorClient::new(connector_url)),
+        }
+    }
+
+    /// Delays the responses to all following calls
+    pub fn set_delay(&self, delay: Duration) {
+        self.state.lock().unwrap().delay = delay;
+    }
+
+    /// Makes calls fail with the probability (between 0 and 1)
+    pub fn set_failure_rate(&self, failure_rate: f64) {
+        self.state.lock().unwrap().failure_rate = failure_rate;
+    }
+
+    /// Makes the next calls fail
+    pub fn fail_next(&self, calls: u32) {
+        self.state.lock().unwrap().failures = calls;
+    }
+
+    /// Returns the accounts which were created and not deleted
+    pub fn accounts(&self) -> HashSet<String> {
+        self.state.lock().unwrap().accounts.clone()
+    }
+
+    /// Returns the accepted settlements, oldest first
+    pub fn settlements(&self) -> Vec<(String, Quantity)> {
+        self.state.lock().unwrap().settlements.clone()
+    }
+
+    /// Returns the accepted messages, oldest first
+    pub fn messages(&self) -> Vec<(String, Vec<u8>)> {
+        self.state.lock().unwrap().messages.clone()
+    }
+
+    /// Notifies the connector of a settlement from the account's peer
+    pub async fn credit_incoming_settlement(
+        &self,
+        account_id: &str,
+        idempotency_key: &str,
+        amount: u64,
+        asset_scale: u8,
+    ) -> Result<(), reqwest::Error> {
+        let connector = self
+            .connector
+            .as_ref()
+            .expect("The mock engine was created without a connector URL");
+        connector
+            .notify_settlement(
+                account_id,
+                idempotency_key,
+                &BigUint::from(amount),
+                asset_scale,
+            )
+            .await
+    }
+
+    /// Returns the idempotent Settlement Engine API of the engine
+    pub fn api(
+        &self,
+    ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        create_settlement_engine_filter(self.clone(), self.clone())
+    }
+
+    /// Serves the Settlement Engine API at the provided address, forever
+    pub async fn serve(self, bind_address: SocketAddr) {
+        info!("Mock settlement engine listening on {}", bind_address);
+        warp::serve(self.api()).bind(bind_address).await;
+    }
+
+    /// Waits for the configured delay and returns an error if the call should fail
+    async fn inject_failure(&self, call: &str) -> Result<(), ApiError> {
+        let (delay, fail) = {
+            let mut state = self.state.lock().unwrap();
+            let fail = if state.failures > 0 {
+                state.failures -= 1;
+                true
+            } else {
+                state.failure_rate > 0.0 && rand::random::<f64>() < state.failure_rate
+            };
+            (state.delay, fail)
+        };
+        if delay > Duration::from_secs(0) {
+            tokio::time::sleep(delay).await;
+        }
+        if fail {
+            debug!("Injecting failure of {}", call);
+            Err(ApiError::internal_server_error().detail(format!("Injected failure of {}", call)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[async_trait]
+impl SettlementEngine for MockSettlementEngine {
+    async fn create_account(&self, account_id: String) -> ApiResult {
+        self.inject_failure("create_account").await?;
+        self.state.lock().unwrap().accounts.insert(account_id);
+        Ok(ApiResponse::Default)
+    }
+
+    async fn delete_account(&self, account_id: String) -> ApiResult {
+        self.inject_failure("delete_account").await?;
+        self.state.lock().unwrap().accounts.remove(&account_id);
+        Ok(ApiResponse::Default)
+    }
+
+    async fn send_money(&self, account_id: String, money: Quantity) -> ApiResult {
+        self.inject_failure("send_money").await?;
+        self.state
+            .lock()
+            .unwrap()
+            .settlements
+            .push((account_id, money));
+        Ok(ApiResponse::Default)
+    }
+
+    async fn receive_message(&self, account_id: String, message: Vec<u8>) -> ApiResult {
+        self.inject_failure("receive_message").await?;
+        self.state
+            .lock()
+            .unwrap()
+            .messages
+            .push((account_id, message));
+        Ok(ApiResponse::Default)
+    }
+}
+
+#[async_trait]
+impl IdempotentStore for MockSettlementEngine {
+    async fn load_idempotent_data(
+        &self,
+        idempotency_key: String,
+    ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .idempotent_data
+            .get(&idempotency_key)
+            .cloned())
+    }
+
+    async fn save_idempotent_data(
+        &self,
+        idempotency_key: String,
+        input_hash: [u8; 32],
+        status_code: StatusCode,
+        data: Bytes,
+    ) -> Result<(), IdempotentStoreError> {
+        self.state.lock().unwrap().idempotent_data.insert(
+            idempotency_key,
+            IdempotentData::new(status_code, data, input_hash),
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    async fn settle(engine: &MockSettlementEngine, key: &str) -> u16 {
+        warp::test::request()
+            .method("POST")
+            .path("/accounts/alice/settlements")
+            .header("Idempotency-Key", key)
+            .json(&Quantity::new(100, 9))
+            .reply(&engine.api())
+            .await
+            .status()
+            .as_u16()
+    }
+
+    #[tokio::test]
+    async fn fails_the_next_calls() {
+        let engine = MockSettlementEngine::new();
+        engine.fail_next(1);
+
+        assert_eq!(settle(&engine, "a").await, 500);
+        // The failure is stored for the idempotency key
+        assert_eq!(settle(&engine, "a").await, 500);
+        assert_eq!(settle(&engine, "b").await, 201);
+        assert_eq!(settle(&engine, "b").await, 201);
+        assert_eq!(
+            engine.settlements(),
+            vec![("alice".to_string(), Quantity::new(100, 9))]
+        );
+    }
+
+    #[tokio::test]
+    async fn delays_the_responses() {
+        let engine = MockSettlementEngine::new();
+        engine.set_delay(Duration::from_millis(100));
+
+        let start = Instant::now();
+        engine.create_account("alice".to_string()).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(engine.accounts().contains("alice"));
+    }
+
+    #[tokio::test]
+    async fn fails_every_call_at_the_maximum_failure_rate() {
+        let engine = MockSettlementEngine::new();
+        engine.set_failure_rate(1.0);
+        assert!(engine
+            .send_money("alice".to_string(), Quantity::new(100, 9))
+            .await
+            .is_err());
+        assert!(engine.settlements().is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::StatusCode;
+use interledger_errors::{IdempotentStoreError, LeftoversStoreError};
+use interledger_settlement::core::{
+    backends_common::redis::{EngineRedisStore, EngineRedisStoreBuilder},
+    idempotency::{IdempotentData, IdempotentStore},
+    types::LeftoversStore,
+};
+use num_bigint::BigUint;
+use redis_crate::{aio::MultiplexedConnection, AsyncCommands, ConnectionInfo, RedisError};
+
+fn peer_details_key(account_id: &str) -> String {
+    format!("engine:peer_details:{}", account_id)
+}
+
+/// Store of the payment details which the peers' engines sent
+#[async_trait]
+pub trait PeerDetailsStore {
+    /// Saves the JSON encoded details with which settlements to the account's peer are paid
+    async fn save_peer_details(&self, account_id: &str, details: &str) -> Result<(), RedisError>;
+
+    /// Loads the JSON encoded details with which settlements to the account's peer are paid
+    async fn load_peer_details(&self, account_id: &str) -> Result<Option<String>, RedisError>;
+
+    /// Removes the details of the account's peer
+    async fn delete_peer_details(&self, account_id: &str) -> Result<(), RedisError>;
+}
+
+/// Store of a [`LedgerSettlementEngine`](crate::LedgerSettlementEngine), which keeps the
+/// idempotency and leftover data in the common engine store
+#[derive(Clone)]
+pub struct EngineStore {
+    redis_store: EngineRedisStore,
+}
+
+impl EngineStore {
+    /// Connects to the provided redis_url
+    pub async fn connect(redis_url: ConnectionInfo) -> Result<Self, ()> {
+        let redis_store = EngineRedisStoreBuilder::new(redis_url).connect().await?;
+        Ok(EngineStore { redis_store })
+    }
+
+    /// Returns a connection to the database, with which a ledger can store its own data.
+    /// Its keys should not start with `engine:`, which is used by the store.
+    pub fn connection(&self) -> MultiplexedConnection {
+        self.redis_store.connection.clone()
+    }
+}
+
+#[async_trait]
+impl PeerDetailsStore for EngineStore {
+    async fn save_peer_details(&self, account_id: &str, details: &str) -> Result<(), RedisError> {
+        self.connection()
+            .set(peer_details_key(account_id), details)
+            .await
+    }
+
+    async fn load_peer_details(&self, account_id: &str) -> Result<Option<String>, RedisError> {
+        self.connection().get(peer_details_key(account_id)).await
+    }
+
+    async fn delete_peer_details(&self, account_id: &str) -> Result<(), RedisError> {
+        self.connection().del(peer_details_key(account_id)).await
+    }
+}
+
+#[async_trait]
+impl IdempotentStore for EngineStore {
+    async fn load_idempotent_data(
+        &self,
+        idempotency_key: String,
+    ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        self.redis_store.load_idempotent_data(idempotency_key).await
+    }
+
+    async fn save_idempotent_data(
+        &self,
+        idempotency_key: String,
+        input_hash: [u8; 32],
+        status_code: StatusCode,
+        data: Bytes,
+    ) -> Result<(), IdempotentStoreError> {
+        self.redis_store
+            .save_idempotent_data(idempotency_key, input_hash, status_code, data)
+            .await
+    }
+}
+
+#[async_trait]
+impl LeftoversStore for EngineStore {
+    type AccountId = String;
+    type AssetType = BigUint;
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+        uncredited_settlement_amount: (Self::AssetType, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        self.redis_store
+            .save_uncredited_settlement_amount(account_id, uncredited_settlement_amount)
+            .await
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+        local_scale: u8,
+    ) -> Result<Self::AssetType, LeftoversStoreError> {
+        self.redis_store
+            .load_uncredited_settlement_amount(account_id, local_scale)
+            .await
+    }
+
+    async fn clear_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+    ) -> Result<(), LeftoversStoreError> {
+        self.redis_store
+            .clear_uncredited_settlement_amount(account_id)
+            .await
+    }
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        account_id: Self::AccountId,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        self.redis_store
+            .get_uncredited_settlement_amount(account_id)
+            .await
+    }
+}
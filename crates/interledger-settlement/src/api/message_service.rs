@@ -2,10 +2,9 @@ use crate::core::{
     types::{SettlementAccount, SE_ILP_ADDRESS},
     SettlementClient,
 };
-use async_trait::async_trait;
 use futures::TryFutureExt;
 use interledger_packet::{ErrorCode, FulfillBuilder, RejectBuilder};
-use interledger_service::{Account, IlpResult, IncomingRequest, IncomingService};
+use interledger_service::{Account, BoxedIlpFuture, IncomingRequest, IncomingService};
 use std::marker::PhantomData;
 use tracing::error;
 
@@ -38,49 +37,35 @@ where
     }
 }
 
-#[async_trait]
 impl<I, A> IncomingService<A> for SettlementMessageService<I, A>
 where
     I: IncomingService<A> + Send,
     A: SettlementAccount + Account + Send + Sync,
 {
-    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
-        // Only handle the request if the destination address matches the ILP address
-        // of the settlement engine being used for this account
-        if let Some(settlement_engine_details) = request.from.settlement_engine_details() {
-            if request.prepare.destination() == SE_ILP_ADDRESS.clone() {
-                // Send a messsage to the engine (with retries)
-                let response = self
-                    .client
-                    .send_message(
-                        request.from.id(),
-                        settlement_engine_details.url,
-                        request.prepare.data().to_vec(),
-                    )
-                    .map_err(move |error| {
-                        error!("Error sending message to settlement engine: {:?}", error);
-                        RejectBuilder {
-                            code: ErrorCode::T00_INTERNAL_ERROR,
-                            message: b"Error sending message to settlement engine",
-                            data: &[],
-                            triggered_by: Some(&SE_ILP_ADDRESS),
-                        }
-                        .build()
-                    })
-                    .await?;
-
-                let status = response.status();
-                if status.is_success() {
-                    let body = response
-                        .bytes()
-                        .map_err(|err| {
-                            error!(
-                                "Error concatenating settlement engine response body: {:?}",
-                                err
-                            );
+    type Future<'a>
+        = BoxedIlpFuture<'a>
+    where
+        Self: 'a;
+
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            // Only handle the request if the destination address matches the ILP address
+            // of the settlement engine being used for this account
+            if let Some(settlement_engine_details) = request.from.settlement_engine_details() {
+                if request.prepare.destination() == SE_ILP_ADDRESS.clone() {
+                    // Send a messsage to the engine (with retries)
+                    let response = self
+                        .client
+                        .send_message(
+                            request.from.id(),
+                            settlement_engine_details.url,
+                            request.prepare.data().to_vec(),
+                        )
+                        .map_err(move |error| {
+                            error!("Error sending message to settlement engine: {:?}", error);
                             RejectBuilder {
                                 code: ErrorCode::T00_INTERNAL_ERROR,
-                                message: b"Error getting settlement engine response",
+                                message: b"Error sending message to settlement engine",
                                 data: &[],
                                 triggered_by: Some(&SE_ILP_ADDRESS),
                             }
@@ -88,38 +73,58 @@ where
                         })
                         .await?;
 
-                    return Ok(FulfillBuilder {
-                        fulfillment: &PEER_FULFILLMENT,
-                        data: body.as_ref(),
-                    }
-                    .build());
-                } else {
-                    error!(
-                        "Settlement engine rejected message with HTTP error code: {}",
-                        response.status()
-                    );
-                    let code = if status.is_client_error() {
-                        ErrorCode::F00_BAD_REQUEST
+                    let status = response.status();
+                    if status.is_success() {
+                        let body = response
+                            .bytes()
+                            .map_err(|err| {
+                                error!(
+                                    "Error concatenating settlement engine response body: {:?}",
+                                    err
+                                );
+                                RejectBuilder {
+                                    code: ErrorCode::T00_INTERNAL_ERROR,
+                                    message: b"Error getting settlement engine response",
+                                    data: &[],
+                                    triggered_by: Some(&SE_ILP_ADDRESS),
+                                }
+                                .build()
+                            })
+                            .await?;
+
+                        return Ok(FulfillBuilder {
+                            fulfillment: &PEER_FULFILLMENT,
+                            data: body.as_ref(),
+                        }
+                        .build());
                     } else {
-                        ErrorCode::T00_INTERNAL_ERROR
-                    };
-
-                    return Err(RejectBuilder {
-                        code,
-                        message: format!(
-                            "Settlement engine rejected request with error code: {}",
+                        error!(
+                            "Settlement engine rejected message with HTTP error code: {}",
                             response.status()
-                        )
-                        .as_str()
-                        .as_ref(),
-                        data: &[],
-                        triggered_by: Some(&SE_ILP_ADDRESS),
+                        );
+                        let code = if status.is_client_error() {
+                            ErrorCode::F00_BAD_REQUEST
+                        } else {
+                            ErrorCode::T00_INTERNAL_ERROR
+                        };
+
+                        return Err(RejectBuilder {
+                            code,
+                            message: format!(
+                                "Settlement engine rejected request with error code: {}",
+                                response.status()
+                            )
+                            .as_str()
+                            .as_ref(),
+                            data: &[],
+                            triggered_by: Some(&SE_ILP_ADDRESS),
+                        }
+                        .build());
                     }
-                    .build());
                 }
             }
-        }
-        self.next.handle_request(request).await
+            self.next.handle_request(request).await
+        })
     }
 }
 
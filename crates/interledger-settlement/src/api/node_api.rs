@@ -1,11 +1,13 @@
 use crate::core::{
     get_hash_of,
     idempotency::*,
+    scale::{biguint_to_u64, OverflowMode},
     scale_with_precision_loss,
     types::{
         ApiResponse, ApiResult, LeftoversStore, Quantity, SettlementAccount, SettlementStore,
         CONVERSION_ERROR_TYPE, SE_ILP_ADDRESS,
     },
+    SettlementEvent, SettlementEvents,
 };
 use bytes::Bytes;
 use futures::future::Either;
@@ -15,7 +17,6 @@ use interledger_errors::*;
 use interledger_packet::PrepareBuilder;
 use interledger_service::{Account, AccountStore, OutgoingRequest, OutgoingService};
 use num_bigint::BigUint;
-use num_traits::cast::ToPrimitive;
 use std::{
     str::{self, FromStr},
     time::{Duration, SystemTime},
@@ -37,6 +38,7 @@ async fn receive_settlement<S, A>(
     idempotency_key: Option<String>,
     quantity: Quantity,
     store: S,
+    settlement_events: SettlementEvents,
 ) -> Result<impl warp::Reply, Rejection>
 where
     S: LeftoversStore<AccountId = Uuid, AssetType = BigUint>
@@ -56,7 +58,13 @@ where
     let store_clone = store.clone();
     let (status_code, message) = make_idempotent_call(
         store,
-        do_receive_settlement(store_clone, account_id, quantity, idempotency_key_clone),
+        do_receive_settlement(
+            store_clone,
+            account_id,
+            quantity,
+            idempotency_key_clone,
+            settlement_events,
+        ),
         input_hash,
         idempotency_key,
         StatusCode::CREATED,
@@ -111,12 +119,14 @@ where
 
 /// Returns a Node Settlement filter which exposes a Warp-compatible
 /// idempotent API which
-/// 1. receives messages about incoming settlements from the engine
+/// 1. receives messages about incoming settlements from the engine, and publishes
+///    them to the `settlement_events` subscribers once they are credited
 /// 1. sends messages from the connector's engine to the peer's
 ///    message service which are sent to the peer's engine
 pub fn create_settlements_filter<S, O, A>(
     store: S,
     outgoing_handler: O,
+    settlement_events: SettlementEvents,
 ) -> warp::filters::BoxedFilter<(impl warp::Reply,)>
 where
     S: LeftoversStore<AccountId = Uuid, AssetType = BigUint>
@@ -143,6 +153,7 @@ where
         .and(idempotency)
         .and(warp::body::json())
         .and(with_store.clone())
+        .and(warp::any().map(move || settlement_events.clone()))
         .and_then(receive_settlement);
 
     // POST /accounts/:account_id/messages (optional idempotency-key header)
@@ -173,6 +184,7 @@ async fn do_receive_settlement<S, A>(
     account_id: String,
     body: Quantity,
     idempotency_key: Option<String>,
+    settlement_events: SettlementEvents,
 ) -> ApiResult
 where
     S: LeftoversStore<AccountId = Uuid, AssetType = BigUint>
@@ -252,14 +264,15 @@ where
 
     // add the leftovers to the scaled engine amount
     let total_amount = scaled_engine_amount.clone() + scaled_leftover_amount;
-    let engine_amount_u64 = total_amount.to_u64().unwrap_or(std::u64::MAX);
+    // It's safe to unwrap here since saturating conversions cannot fail
+    let engine_amount_u64 = biguint_to_u64(&total_amount, OverflowMode::Saturate).unwrap();
 
     let ret = futures::future::join_all(vec![
         // update the account's balance in the store
         Either::Left(store.update_balance_for_incoming_settlement(
             account_id,
             engine_amount_u64,
-            idempotency_key,
+            idempotency_key.clone(),
         )),
         // save any precision loss that occurred during the
         // scaling of the engine's amount to the account's scale
@@ -286,6 +299,13 @@ where
         return Err(ApiError::from_api_error_type(&error_type).detail(error_msg));
     }
 
+    // There may be no one subscribed
+    let _ = settlement_events.send(SettlementEvent::Received {
+        account_id,
+        amount: engine_amount_u64,
+        asset_scale,
+        idempotency_key,
+    });
     Ok(ApiResponse::Default)
 }
 
@@ -442,7 +462,8 @@ mod tests {
         async fn settlement_ok() {
             let id = TEST_ACCOUNT_0.clone().id.to_string();
             let store = test_store(false, true);
-            let api = test_api(store.clone(), false);
+            let (events, mut subscriber) = tokio::sync::broadcast::channel(8);
+            let api = test_api_with_events(store.clone(), false, events);
 
             // The operator accounts are configured to work with CONNECTOR_SCALE
             // = 9. When
@@ -451,11 +472,22 @@ mod tests {
             let response = settlement_call(&api, &id, 200, OUR_SCALE, Some(IDEMPOTENCY)).await;
             assert_eq!(response.body(), &Bytes::from("RECEIVED"));
             assert_eq!(response.status(), StatusCode::CREATED);
+            assert_eq!(
+                subscriber.try_recv().unwrap(),
+                SettlementEvent::Received {
+                    account_id: TEST_ACCOUNT_0.id,
+                    amount: 2,
+                    asset_scale: 9,
+                    idempotency_key: Some(IDEMPOTENCY.to_string()),
+                }
+            );
 
             // check that it's idempotent
             let response = settlement_call(&api, &id, 200, OUR_SCALE, Some(IDEMPOTENCY)).await;
             assert_eq!(response.body(), &Bytes::from("RECEIVED"));
             assert_eq!(response.status(), StatusCode::CREATED);
+            // the settlement was only credited once
+            assert!(subscriber.try_recv().is_err());
 
             // fails with different account id
             let response = settlement_call(&api, "2", 200, OUR_SCALE, Some(IDEMPOTENCY)).await;
@@ -6,6 +6,7 @@ use crate::core::{
         Convert, ConvertDetails, LeftoversStore, SettlementAccount, SettlementEngineDetails,
         SettlementStore,
     },
+    SettlementEvents,
 };
 use bytes::Bytes;
 use hyper::StatusCode;
@@ -348,6 +349,18 @@ pub fn test_store(store_fails: bool, account_has_engine: bool) -> TestStore {
 pub fn test_api(
     test_store: TestStore,
     should_fulfill: bool,
+) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
+    test_api_with_events(
+        test_store,
+        should_fulfill,
+        tokio::sync::broadcast::channel(1).0,
+    )
+}
+
+pub fn test_api_with_events(
+    test_store: TestStore,
+    should_fulfill: bool,
+    settlement_events: SettlementEvents,
 ) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
     let outgoing = outgoing_service_fn(move |_| {
         if should_fulfill {
@@ -366,5 +379,5 @@ pub fn test_api(
             .build())
         }
     });
-    create_settlements_filter(test_store, outgoing)
+    create_settlements_filter(test_store, outgoing, settlement_events)
 }
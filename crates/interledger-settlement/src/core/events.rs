@@ -0,0 +1,57 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Publishes the settlements sent to and received from the settlement engines
+pub type SettlementEvents = broadcast::Sender<SettlementEvent>;
+
+/// A step of a settlement, serialized as a JSON object tagged with its `status`.
+/// The amounts are in the units of the account.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SettlementEvent {
+    /// A settlement to the account was sent to the settlement engine. A settlement is
+    /// sent again, with the same idempotency key, when its previous attempt failed.
+    Initiated {
+        account_id: Uuid,
+        amount: u64,
+        asset_scale: u8,
+        idempotency_key: String,
+    },
+    /// The settlement engine accepted the settlement
+    Succeeded {
+        account_id: Uuid,
+        amount: u64,
+        asset_scale: u8,
+        idempotency_key: String,
+    },
+    /// The settlement failed. It is retried later if it is unknown whether the engine
+    /// executed it, or else its amount is credited back to the account.
+    Failed {
+        account_id: Uuid,
+        amount: u64,
+        asset_scale: u8,
+        idempotency_key: String,
+        will_retry: bool,
+        error: String,
+    },
+    /// The settlement engine credited a settlement received from the account
+    Received {
+        account_id: Uuid,
+        amount: u64,
+        asset_scale: u8,
+        idempotency_key: Option<String>,
+    },
+}
+
+impl SettlementEvent {
+    /// The account which is settled with
+    pub fn account_id(&self) -> Uuid {
+        match self {
+            SettlementEvent::Initiated { account_id, .. }
+            | SettlementEvent::Succeeded { account_id, .. }
+            | SettlementEvent::Failed { account_id, .. }
+            | SettlementEvent::Received { account_id, .. } => *account_id,
+        }
+    }
+}
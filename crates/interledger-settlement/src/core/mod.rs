@@ -10,12 +10,18 @@ pub mod engines_api;
 mod settlement_client;
 pub use settlement_client::SettlementClient;
 
+mod events;
+pub use events::{SettlementEvent, SettlementEvents};
+
 /// Expose useful utilities for implementing idempotent functionalities
 pub mod idempotency;
 
 /// Expose useful traits
 pub mod types;
 
+/// Conversions of amounts between asset scales, with explicit rounding and overflow handling
+pub mod scale;
+
 use num_bigint::BigUint;
 use num_traits::Zero;
 use ring::digest::{digest, SHA256};
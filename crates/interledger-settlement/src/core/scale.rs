@@ -0,0 +1,316 @@
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use std::{convert::TryFrom, fmt};
+
+/// `u64::MAX` is smaller than 10^20, so scaling by 10^20 or more gives the same results as
+/// scaling by 10^20: the amount overflows, or rounds to 0 or 1
+const MAX_U64_SCALE_DIFF: u8 = 20;
+
+/// How to round amounts which cannot be represented exactly in the target asset scale
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Round towards zero, e.g. 1.9 becomes 1
+    Down,
+    /// Round away from zero, e.g. 1.1 becomes 2
+    Up,
+    /// Round to the nearest integer, and halves away from zero, e.g. 1.5 becomes 2
+    HalfUp,
+}
+
+/// What to do when a converted amount does not fit in a `u64`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowMode {
+    /// Use `u64::MAX` instead
+    Saturate,
+    /// Return [`ScaleError::Overflow`]
+    Reject,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScaleError {
+    /// The converted amount does not fit in a `u64`
+    Overflow,
+    /// The rate or the amount is negative or not a number
+    InvalidAmount,
+}
+
+impl fmt::Display for ScaleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScaleError::Overflow => write!(f, "Converted amount does not fit in a u64"),
+            ScaleError::InvalidAmount => write!(f, "Rate or amount is negative or not a number"),
+        }
+    }
+}
+
+impl std::error::Error for ScaleError {}
+
+fn overflowed(overflow: OverflowMode) -> Result<u64, ScaleError> {
+    match overflow {
+        OverflowMode::Saturate => Ok(u64::MAX),
+        OverflowMode::Reject => Err(ScaleError::Overflow),
+    }
+}
+
+/// Converts an amount from an asset scale to another without going through floating
+/// point numbers, e.g. 1 with scale 2 is 10 with scale 3, and 15 with scale 3 is 1 with
+/// scale 2 when rounding down
+///
+/// # Examples
+/// ```rust
+/// # use interledger_settlement::core::scale::{scale_amount, OverflowMode, RoundingMode};
+/// assert_eq!(
+///     scale_amount(15, 3, 2, RoundingMode::HalfUp, OverflowMode::Reject),
+///     Ok(2)
+/// );
+///
+/// assert_eq!(
+///     scale_amount(u64::MAX, 0, 1, RoundingMode::Down, OverflowMode::Saturate),
+///     Ok(u64::MAX)
+/// );
+/// ```
+pub fn scale_amount(
+    amount: u64,
+    from: u8,
+    to: u8,
+    rounding: RoundingMode,
+    overflow: OverflowMode,
+) -> Result<u64, ScaleError> {
+    let diff = (from.max(to) - from.min(to)).min(MAX_U64_SCALE_DIFF);
+    let factor = 10u128.pow(diff.into());
+    let amount = u128::from(amount);
+
+    let scaled = if to >= from {
+        amount.checked_mul(factor)
+    } else {
+        let (quotient, remainder) = (amount / factor, amount % factor);
+        let round_up = match rounding {
+            RoundingMode::Down => false,
+            RoundingMode::Up => remainder > 0,
+            RoundingMode::HalfUp => remainder * 2 >= factor,
+        };
+        Some(quotient + round_up as u128)
+    };
+
+    match scaled.and_then(|scaled| u64::try_from(scaled).ok()) {
+        Some(scaled) => Ok(scaled),
+        None => overflowed(overflow),
+    }
+}
+
+/// Applies an exchange rate to an amount and converts it from an asset scale to another,
+/// without rounding the result. The caller can then tell apart amounts which round to zero,
+/// and round the result with [`round_amount`].
+///
+/// Large amounts lose precision, because `f64` has 53 bits of precision.
+pub fn apply_rate_unrounded(amount: u64, rate: f64, from: u8, to: u8) -> Result<f64, ScaleError> {
+    if rate.is_nan() || rate.is_sign_negative() {
+        return Err(ScaleError::InvalidAmount);
+    }
+    let factor = 10f64.powi(i32::from(to) - i32::from(from));
+    let scaled = rate * factor * (amount as f64);
+    if factor.is_finite() && scaled.is_finite() {
+        Ok(scaled)
+    } else {
+        Err(ScaleError::Overflow)
+    }
+}
+
+/// Rounds an amount to a `u64`
+pub fn round_amount(
+    amount: f64,
+    rounding: RoundingMode,
+    overflow: OverflowMode,
+) -> Result<u64, ScaleError> {
+    if amount.is_nan() || (amount.is_sign_negative() && amount != 0.0) {
+        return Err(ScaleError::InvalidAmount);
+    }
+    let rounded = match rounding {
+        RoundingMode::Down => amount.floor(),
+        RoundingMode::Up => amount.ceil(),
+        RoundingMode::HalfUp => amount.round(),
+    };
+    // u64::MAX is not representable as an f64, the next float is 2^64
+    if rounded >= u64::MAX as f64 {
+        return overflowed(overflow);
+    }
+    Ok(rounded as u64)
+}
+
+/// Applies an exchange rate to an amount, converts it from an asset scale to another and
+/// rounds the result
+///
+/// # Examples
+/// ```rust
+/// # use interledger_settlement::core::scale::{apply_rate, OverflowMode, RoundingMode};
+/// // 100 units with scale 2 at a rate of 0.5 are 500 units with scale 3
+/// assert_eq!(
+///     apply_rate(100, 0.5, 2, 3, RoundingMode::Down, OverflowMode::Reject),
+///     Ok(500)
+/// );
+/// ```
+pub fn apply_rate(
+    amount: u64,
+    rate: f64,
+    from: u8,
+    to: u8,
+    rounding: RoundingMode,
+    overflow: OverflowMode,
+) -> Result<u64, ScaleError> {
+    match apply_rate_unrounded(amount, rate, from, to) {
+        Ok(scaled) => round_amount(scaled, rounding, overflow),
+        Err(ScaleError::Overflow) => overflowed(overflow),
+        Err(err) => Err(err),
+    }
+}
+
+/// Converts a big amount, such as a settlement amount which was scaled to the account's
+/// asset scale, to a `u64`
+pub fn biguint_to_u64(amount: &BigUint, overflow: OverflowMode) -> Result<u64, ScaleError> {
+    match amount.to_u64() {
+        Some(amount) => Ok(amount),
+        None => overflowed(overflow),
+    }
+}
+
+/// Converts an amount in an asset scale to the asset's base unit, e.g. 150 with scale 2
+/// is 1.5, for displaying it
+pub fn to_base_unit(amount: i64, scale: u8) -> f64 {
+    amount as f64 / 10f64.powi(scale.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RoundingMode::*;
+    use super::*;
+
+    #[test]
+    fn scales_up() {
+        assert_eq!(
+            scale_amount(1, 9, 18, Down, OverflowMode::Reject),
+            Ok(1_000_000_000)
+        );
+        assert_eq!(scale_amount(0, 0, 255, Down, OverflowMode::Reject), Ok(0));
+        assert_eq!(scale_amount(7, 3, 3, Up, OverflowMode::Reject), Ok(7));
+    }
+
+    #[test]
+    fn scales_down_with_rounding() {
+        assert_eq!(scale_amount(15, 3, 2, Down, OverflowMode::Reject), Ok(1));
+        assert_eq!(scale_amount(15, 3, 2, Up, OverflowMode::Reject), Ok(2));
+        assert_eq!(scale_amount(15, 3, 2, HalfUp, OverflowMode::Reject), Ok(2));
+        assert_eq!(scale_amount(14, 3, 2, HalfUp, OverflowMode::Reject), Ok(1));
+        assert_eq!(scale_amount(10, 3, 2, Up, OverflowMode::Reject), Ok(1));
+    }
+
+    #[test]
+    fn scales_down_by_more_than_a_u64() {
+        assert_eq!(
+            scale_amount(u64::MAX, 255, 0, Down, OverflowMode::Reject),
+            Ok(0)
+        );
+        assert_eq!(scale_amount(1, 255, 0, Up, OverflowMode::Reject), Ok(1));
+        assert_eq!(
+            scale_amount(u64::MAX, 20, 0, HalfUp, OverflowMode::Reject),
+            Ok(0)
+        );
+        assert_eq!(
+            scale_amount(u64::MAX, 19, 0, HalfUp, OverflowMode::Reject),
+            Ok(2)
+        );
+    }
+
+    #[test]
+    fn handles_overflow() {
+        assert_eq!(
+            scale_amount(u64::MAX / 10 + 1, 0, 1, Down, OverflowMode::Reject),
+            Err(ScaleError::Overflow)
+        );
+        assert_eq!(
+            scale_amount(u64::MAX / 10 + 1, 0, 1, Down, OverflowMode::Saturate),
+            Ok(u64::MAX)
+        );
+        assert_eq!(
+            scale_amount(u64::MAX / 10, 0, 1, Down, OverflowMode::Reject),
+            Ok(u64::MAX / 10 * 10)
+        );
+        // Would overflow the intermediate u128
+        assert_eq!(
+            scale_amount(u64::MAX, 0, 255, Down, OverflowMode::Reject),
+            Err(ScaleError::Overflow)
+        );
+    }
+
+    #[allow(clippy::float_cmp)]
+    #[test]
+    fn applies_rates() {
+        assert_eq!(
+            apply_rate(200, 0.5, 1, 1, Down, OverflowMode::Reject),
+            Ok(100)
+        );
+        assert_eq!(apply_rate(3, 0.5, 0, 0, Down, OverflowMode::Reject), Ok(1));
+        assert_eq!(apply_rate(3, 0.5, 0, 0, Up, OverflowMode::Reject), Ok(2));
+        assert_eq!(
+            apply_rate(3, 0.5, 0, 0, HalfUp, OverflowMode::Reject),
+            Ok(2)
+        );
+        assert_eq!(apply_rate(1, 0.5, 0, 0, Down, OverflowMode::Reject), Ok(0));
+        assert_eq!(apply_unrounded(1, 0.5), Ok(0.5));
+    }
+
+    fn apply_unrounded(amount: u64, rate: f64) -> Result<f64, ScaleError> {
+        apply_rate_unrounded(amount, rate, 0, 0)
+    }
+
+    #[test]
+    fn rejects_invalid_rates() {
+        assert_eq!(apply_unrounded(1, -0.5), Err(ScaleError::InvalidAmount));
+        assert_eq!(apply_unrounded(1, f64::NAN), Err(ScaleError::InvalidAmount));
+        assert_eq!(
+            round_amount(-1.0, Down, OverflowMode::Saturate),
+            Err(ScaleError::InvalidAmount)
+        );
+    }
+
+    #[test]
+    fn handles_rate_overflow() {
+        assert_eq!(
+            apply_rate(u64::MAX, 2.0, 0, 0, Down, OverflowMode::Reject),
+            Err(ScaleError::Overflow)
+        );
+        assert_eq!(
+            apply_rate(u64::MAX, 2.0, 0, 0, Down, OverflowMode::Saturate),
+            Ok(u64::MAX)
+        );
+        assert_eq!(
+            apply_rate(u64::MAX, f64::MAX, 0, 255, Down, OverflowMode::Reject),
+            Err(ScaleError::Overflow)
+        );
+        assert_eq!(
+            apply_rate(1, f64::INFINITY, 0, 0, Down, OverflowMode::Saturate),
+            Ok(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn converts_big_amounts() {
+        let big = BigUint::from(u64::MAX) + 1u32;
+        assert_eq!(
+            biguint_to_u64(&big, OverflowMode::Reject),
+            Err(ScaleError::Overflow)
+        );
+        assert_eq!(biguint_to_u64(&big, OverflowMode::Saturate), Ok(u64::MAX));
+        assert_eq!(
+            biguint_to_u64(&BigUint::from(5u32), OverflowMode::Reject),
+            Ok(5)
+        );
+    }
+
+    #[allow(clippy::float_cmp)]
+    #[test]
+    fn converts_to_base_unit() {
+        assert_eq!(to_base_unit(150, 2), 1.5);
+        assert_eq!(to_base_unit(-150, 2), -1.5);
+        assert!(to_base_unit(1, 255) > 0.0);
+    }
+}
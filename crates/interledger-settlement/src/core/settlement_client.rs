@@ -1,4 +1,4 @@
-use crate::core::types::Quantity;
+use crate::core::{types::Quantity, SettlementEvent, SettlementEvents};
 use futures_retry::{ErrorHandler, FutureRetry, RetryPolicy};
 use reqwest::Client;
 use serde_json::json;
@@ -20,6 +20,7 @@ pub struct SettlementClient {
     /// Asynchronous reqwest client
     client: Client,
     max_retries: usize,
+    events: Option<SettlementEvents>,
 }
 
 impl SettlementClient {
@@ -28,6 +29,20 @@ impl SettlementClient {
         SettlementClient {
             client: Client::builder().timeout(timeout).build().unwrap(),
             max_retries,
+            events: None,
+        }
+    }
+
+    /// Publishes the settlements sent by this client, and their results
+    pub fn events(mut self, events: SettlementEvents) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    fn publish(&self, event: SettlementEvent) {
+        if let Some(ref events) = self.events {
+            // There may be no one subscribed
+            let _ = events.send(event);
         }
     }
 
@@ -43,12 +58,29 @@ impl SettlementClient {
         .map_err(|(err, _attempts)| err)
     }
 
+    /// Sends an idempotent account deletion request to the engine (will retry if it fails)
+    /// This is done by sending a DELETE to /accounts/:id
+    pub async fn delete_engine_account(&self, id: Uuid, engine_url: Url) -> Response {
+        FutureRetry::new(
+            move || self.delete_engine_account_once(id, engine_url.clone()),
+            RequestErrorHandler::new(self.max_retries),
+        )
+        .await
+        .map(|(response, _attempts)| response)
+        .map_err(|(err, _attempts)| err)
+    }
+
     /// Sends a message to the engine (will retry idempotently if it fails) which will get forwarded to the peer's engine
     /// This is done by sending a POST to /accounts/:id/messages with the provided `message`
     /// as the request's body
     pub async fn send_message(&self, id: Uuid, engine_url: Url, message: Vec<u8>) -> Response {
+        // The same key is used for all attempts so that the engine processes the message only once
+        let idempotency_key = new_idempotency_key();
+        let idempotency_key = idempotency_key.as_str();
         FutureRetry::new(
-            move || self.send_message_once(id, engine_url.clone(), message.clone()),
+            move || {
+                self.send_message_once(id, engine_url.clone(), message.clone(), idempotency_key)
+            },
             RequestErrorHandler::new(self.max_retries),
         )
         .await
@@ -56,7 +88,13 @@ impl SettlementClient {
         .map_err(|(err, _attempts)| err)
     }
 
-    async fn send_message_once(&self, id: Uuid, engine_url: Url, message: Vec<u8>) -> Response {
+    async fn send_message_once(
+        &self,
+        id: Uuid,
+        engine_url: Url,
+        message: Vec<u8>,
+        idempotency_key: &str,
+    ) -> Response {
         // The `Prepare` packet's data was sent by the peer's settlement
         // engine so we assume it is in a format that our settlement engine
         // will understand
@@ -68,11 +106,10 @@ impl SettlementClient {
             .push("accounts")
             .push(&id.to_string())
             .push("messages");
-        let idempotency_uuid = uuid::Uuid::new_v4().to_hyphenated().to_string();
         self.client
             .post(settlement_engine_url.as_ref())
             .header("Content-Type", "application/octet-stream")
-            .header("Idempotency-Key", idempotency_uuid.clone())
+            .header("Idempotency-Key", idempotency_key)
             .body(message.clone())
             .send()
             .await
@@ -88,13 +125,62 @@ impl SettlementClient {
         amount: u64,
         asset_scale: u8,
     ) -> Response {
-        FutureRetry::new(
-            move || self.send_settlement_once(id, engine_url.clone(), amount, asset_scale),
+        self.send_settlement_with_key(id, engine_url, amount, asset_scale, &new_idempotency_key())
+            .await
+    }
+
+    /// Sends a settlement request with the provided idempotency key to the engine (will retry
+    /// if it fails). The key must be the same for all attempts of a settlement, including the
+    /// attempts after a restart, so that the engine settles the amount only once.
+    pub async fn send_settlement_with_key(
+        &self,
+        id: Uuid,
+        engine_url: Url,
+        amount: u64,
+        asset_scale: u8,
+        idempotency_key: &str,
+    ) -> Response {
+        self.publish(SettlementEvent::Initiated {
+            account_id: id,
+            amount,
+            asset_scale,
+            idempotency_key: idempotency_key.to_string(),
+        });
+        let result = FutureRetry::new(
+            move || {
+                self.send_settlement_once(
+                    id,
+                    engine_url.clone(),
+                    amount,
+                    asset_scale,
+                    idempotency_key,
+                )
+            },
             RequestErrorHandler::new(self.max_retries),
         )
         .await
         .map(|(response, _attempts)| response)
-        .map_err(|(err, _attempts)| err)
+        .map_err(|(err, _attempts)| err);
+        self.publish(match result {
+            Ok(_) => SettlementEvent::Succeeded {
+                account_id: id,
+                amount,
+                asset_scale,
+                idempotency_key: idempotency_key.to_string(),
+            },
+            Err(ref err) => SettlementEvent::Failed {
+                account_id: id,
+                amount,
+                asset_scale,
+                idempotency_key: idempotency_key.to_string(),
+                // The engine answered, so the settlement was definitely not executed
+                will_retry: !err
+                    .status()
+                    .map_or(false, |status| status.is_client_error()),
+                error: err.to_string(),
+            },
+        });
+        result
     }
 
     async fn create_engine_account_once(&self, id: Uuid, engine_url: Url) -> Response {
@@ -117,12 +203,33 @@ impl SettlementClient {
             .await
     }
 
+    async fn delete_engine_account_once(&self, id: Uuid, engine_url: Url) -> Response {
+        let mut se_url = engine_url;
+        // $URL/accounts/:account_id
+        se_url
+            .path_segments_mut()
+            .expect("Invalid settlement engine URL")
+            .push(ACCOUNTS_ENDPOINT)
+            .push(&id.to_string());
+        trace!(
+            "Sending account {} deletion request to settlement engine: {:?}",
+            id,
+            se_url.clone()
+        );
+
+        let response = self.client.delete(se_url.as_ref()).send().await?;
+        response.error_for_status()
+    }
+
+    /// Sends a single settlement request to the engine. Retries of the same settlement
+    /// must use the same `idempotency_key`.
     pub async fn send_settlement_once(
         &self,
         id: Uuid,
         engine_url: Url,
         amount: u64,
         asset_scale: u8,
+        idempotency_key: &str,
     ) -> Response {
         let mut settlement_engine_url = engine_url;
 
@@ -138,14 +245,11 @@ impl SettlementClient {
             amount, settlement_engine_url
         );
 
-        // Mark the request as idempotent
-        let idempotency_uuid = Uuid::new_v4().to_hyphenated().to_string();
-
         // Make the POST request future
         let response = self
             .client
             .post(settlement_engine_url.as_ref())
-            .header("Idempotency-Key", idempotency_uuid)
+            .header("Idempotency-Key", idempotency_key)
             .json(&json!(Quantity::new(amount, asset_scale)))
             .send()
             .await?;
@@ -154,6 +258,11 @@ impl SettlementClient {
     }
 }
 
+/// Generates the key which marks a request (and its retries) as idempotent
+fn new_idempotency_key() -> String {
+    Uuid::new_v4().to_hyphenated().to_string()
+}
+
 struct RequestErrorHandler {
     max_retries: usize,
 }
@@ -258,4 +367,58 @@ mod tests {
         m.assert();
         assert!(ret.is_err());
     }
+
+    #[tokio::test]
+    async fn publishes_settlement_events() {
+        let m = mock_settlement(400)
+            .match_header("Idempotency-Key", "key")
+            .create();
+        let (events, mut subscriber) = tokio::sync::broadcast::channel(8);
+        let client = SettlementClient::default().events(events);
+        let id = Uuid::new_v4();
+
+        let ret = client
+            .send_settlement_with_key(id, "http://localhost:1234".parse().unwrap(), 100, 6, "key")
+            .await;
+
+        m.assert();
+        assert!(ret.is_err());
+        assert_eq!(
+            subscriber.try_recv().unwrap(),
+            SettlementEvent::Initiated {
+                account_id: id,
+                amount: 100,
+                asset_scale: 6,
+                idempotency_key: "key".to_string(),
+            }
+        );
+        match subscriber.try_recv().unwrap() {
+            SettlementEvent::Failed {
+                will_retry,
+                idempotency_key,
+                ..
+            } => {
+                // The engine rejected the settlement, so it is refunded
+                assert!(!will_retry);
+                assert_eq!(idempotency_key, "key");
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn deletes_engine_account() {
+        let id = Uuid::new_v4();
+        let m = mock("DELETE", format!("/accounts/{}", id).as_str())
+            .with_status(204)
+            .create();
+        let client = SettlementClient::default();
+
+        let ret = client
+            .delete_engine_account(id, "http://localhost:1234".parse().unwrap())
+            .await;
+
+        m.assert();
+        assert!(ret.is_ok());
+    }
 }
@@ -1,3 +1,4 @@
+use super::scale::{scale_amount, OverflowMode, RoundingMode};
 use async_trait::async_trait;
 use bytes::Bytes;
 use http::StatusCode;
@@ -11,6 +12,7 @@ use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::ops::{Div, Mul};
 use std::str::FromStr;
+use std::time::Duration;
 use url::Url;
 use uuid::Uuid;
 
@@ -108,6 +110,22 @@ pub trait SettlementAccount: Account {
     fn settlement_engine_details(&self) -> Option<SettlementEngineDetails> {
         None
     }
+
+    /// The maximum time for which a positive balance under the settlement threshold is left
+    /// unsettled. If this is not set, the node-wide delay of time-based settlement (if any) is used.
+    fn settle_every(&self) -> Option<Duration> {
+        None
+    }
+
+    /// The balance above which the balance service triggers a settlement, if any
+    fn settle_threshold(&self) -> Option<i64> {
+        None
+    }
+
+    /// The lowest balance the account may reach, under which its packets are rejected, if any
+    fn min_balance(&self) -> Option<i64> {
+        None
+    }
 }
 
 #[async_trait]
@@ -137,6 +155,90 @@ pub trait SettlementStore {
     ) -> Result<(), SettlementStoreError>;
 }
 
+/// Whether an outgoing settlement was already sent to the engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutgoingSettlementStatus {
+    /// The settlement is being sent to the engine for the first time
+    Pending,
+    /// Sending the settlement failed without a definite answer from the engine,
+    /// so it is retried with the same idempotency key
+    Unknown,
+}
+
+/// A settlement whose amount was deducted from the account's balance, but which was
+/// not yet accepted or rejected by the settlement engine
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutgoingSettlement {
+    /// The key with which the settlement is sent to the engine. It stays the same for
+    /// all attempts, so the engine settles the amount only once.
+    pub idempotency_key: String,
+    pub account_id: Uuid,
+    pub amount: u64,
+    pub status: OutgoingSettlementStatus,
+    /// Number of attempts which failed without a definite answer
+    pub attempts: u32,
+    /// Time after which the settlement is retried, in milliseconds since the UNIX epoch
+    pub next_attempt_at: u64,
+}
+
+#[async_trait]
+/// Trait used by the connector to persist outgoing settlements until the engine
+/// answered them, so that they are retried after a restart instead of being lost.
+///
+/// Every account has at most one settlement in flight. Settlements of the account which
+/// are triggered in the meantime are queued, and their amounts are summed up, so that the
+/// engine receives one request at a time and in order.
+pub trait OutgoingSettlementStore {
+    /// Saves the settlement as the account's settlement in flight and returns true,
+    /// unless the account already has a settlement in flight. In that case its amount
+    /// is added to the amount queued for the account and false is returned.
+    async fn queue_outgoing_settlement(
+        &self,
+        settlement: OutgoingSettlement,
+    ) -> Result<bool, SettlementStoreError>;
+
+    /// Turns the amount queued for the account into its settlement in flight, with the
+    /// provided idempotency key and time of the next attempt, and returns it. Returns
+    /// None if nothing is queued or another settlement is still in flight.
+    async fn dequeue_outgoing_settlement(
+        &self,
+        account_id: Uuid,
+        idempotency_key: String,
+        next_attempt_at: u64,
+    ) -> Result<Option<OutgoingSettlement>, SettlementStoreError>;
+
+    /// Loads the accounts which have an amount queued for settlement
+    async fn load_queued_settlement_accounts(&self) -> Result<Vec<Uuid>, SettlementStoreError>;
+
+    /// Saves the outgoing settlement, replacing the one with the same idempotency key.
+    /// Used to update the account's settlement in flight before it is retried.
+    async fn save_outgoing_settlement(
+        &self,
+        settlement: OutgoingSettlement,
+    ) -> Result<(), SettlementStoreError>;
+
+    /// Loads all outgoing settlements which were not answered yet
+    async fn load_outgoing_settlements(
+        &self,
+    ) -> Result<Vec<OutgoingSettlement>, SettlementStoreError>;
+
+    /// Removes the outgoing settlement, so the amount queued for the account can be
+    /// settled next. Only call this once the engine accepted it.
+    async fn remove_outgoing_settlement(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<(), SettlementStoreError>;
+
+    /// Removes the outgoing settlement and credits its amount back to the account, in one
+    /// transaction. Does nothing if the settlement was already removed, so the amount is
+    /// refunded at most once. Only call this once the engine rejected it.
+    async fn refund_outgoing_settlement(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<(), SettlementStoreError>;
+}
+
 /// Trait used by the connector and engine to track amounts which should have been
 /// settled but were not due to precision loss
 #[async_trait]
@@ -216,20 +318,14 @@ impl Convert for u64 {
     type Item = u64;
 
     fn normalize_scale(&self, details: ConvertDetails) -> Result<Self::Item, ConversionError> {
-        // FIXME: it's a bit sketchy how this was planned to work with i8 substraction overflow,
-        // widened to i32 for now, which might not be right either.
-        let scale_diff = (details.from as i32 - details.to as i32).unsigned_abs();
-        let scale = 10u64.pow(scale_diff);
-        let (res, overflow) = if details.to >= details.from {
-            self.overflowing_mul(scale)
-        } else {
-            self.overflowing_div(scale)
-        };
-        if overflow {
-            Err(ConversionError)
-        } else {
-            Ok(res)
-        }
+        scale_amount(
+            *self,
+            details.from,
+            details.to,
+            RoundingMode::Down,
+            OverflowMode::Reject,
+        )
+        .map_err(|_| ConversionError)
     }
 }
 
@@ -252,9 +348,8 @@ impl Convert for BigUint {
     type Item = BigUint;
 
     fn normalize_scale(&self, details: ConvertDetails) -> Result<Self::Item, ConversionError> {
-        // FIXME: see u64::normalize_scale
-        let scale_diff = (details.from as i32 - details.to as i32).unsigned_abs();
-        let scale = 10u64.pow(scale_diff);
+        let scale_diff = details.from.max(details.to) - details.from.min(details.to);
+        let scale = num_traits::pow(BigUint::from(10u32), scale_diff.into());
         if details.to >= details.from {
             Ok(self.mul(scale))
         } else {
@@ -280,6 +375,14 @@ mod tests {
                 .to_string(),
             BigUint::from_u64(100u64).unwrap().to_string(),
         );
+        // Scales which differ by more than a u64 can hold
+        assert_eq!(
+            BigUint::from(1u32)
+                .normalize_scale(ConvertDetails { from: 0, to: 30 })
+                .unwrap()
+                .to_string(),
+            "1000000000000000000000000000000",
+        );
     }
 
     #[test]
@@ -289,6 +392,15 @@ mod tests {
         assert!(huge_number
             .normalize_scale(ConvertDetails { from: 1, to: 18 })
             .is_err(),);
+        assert!(1u64
+            .normalize_scale(ConvertDetails { from: 0, to: 30 })
+            .is_err());
+        assert_eq!(
+            std::u64::MAX
+                .normalize_scale(ConvertDetails { from: 30, to: 0 })
+                .unwrap(),
+            0
+        );
         // 1 unit with scale 1, is 1 unit with scale 1
         assert_eq!(
             1u64.normalize_scale(ConvertDetails { from: 1, to: 1 })
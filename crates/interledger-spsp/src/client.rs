@@ -2,7 +2,7 @@ use super::{Error, SpspResponse};
 use futures::TryFutureExt;
 use interledger_rates::ExchangeRateStore;
 use interledger_service::{Account, IncomingService};
-use interledger_stream::{send_money, StreamDelivery};
+use interledger_stream::{send_money, stream_money, StreamDelivery, StreamHandle};
 use reqwest::Client;
 use tracing::{debug, error, trace};
 
@@ -68,7 +68,53 @@ where
     Ok(receipt)
 }
 
-fn payment_pointer_to_url(payment_pointer: &str) -> String {
+/// Query the details of the given Payment Pointer and send money to it continuously using the
+/// STREAM protocol, at `rate_per_second` source units per second, until the handle is stopped
+/// or `max_amount` was sent.
+///
+/// This returns the receipt of the money sent until the payment was stopped.
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_pay<I, A, S>(
+    service: I,
+    from_account: A,
+    store: S,
+    receiver: &str,
+    rate_per_second: u64,
+    max_amount: Option<u64>,
+    slippage: f64,
+    handle: StreamHandle,
+) -> Result<StreamDelivery, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    let spsp = query(receiver).await?;
+    debug!(
+        "Streaming SPSP payment to address: {}",
+        spsp.destination_account
+    );
+
+    let receipt = stream_money(
+        service,
+        &from_account,
+        store,
+        spsp.destination_account,
+        spsp.shared_secret,
+        rate_per_second,
+        max_amount,
+        slippage,
+        handle,
+    )
+    .await?;
+
+    debug!("Streamed SPSP payment. StreamDelivery: {:?}", receipt);
+    Ok(receipt)
+}
+
+/// Returns the SPSP URL of a Payment Pointer (`$example.com/alice` is queried at
+/// `https://example.com/alice`), or the given URL if it is not a Payment Pointer
+pub fn payment_pointer_to_url(payment_pointer: &str) -> String {
     let mut url: String = if let Some(suffix) = payment_pointer.strip_prefix('$') {
         let prefix = "https://";
         let mut url = String::with_capacity(prefix.len() + suffix.len());
@@ -12,10 +12,15 @@ use serde::{Deserialize, Serialize};
 /// An SPSP client which can query an SPSP Server's payment pointer and initiate a STREAM payment
 mod client;
 /// An SPSP Server implementing an HTTP Service which generates ILP Addresses and Shared Secrets
+#[cfg(not(target_arch = "wasm32"))]
 mod server;
+/// An ILP-over-HTTP transport for sending SPSP payments without a node
+mod transport;
 
-pub use client::{pay, query};
+pub use client::{pay, payment_pointer_to_url, query, stream_pay};
+#[cfg(not(target_arch = "wasm32"))]
 pub use server::SpspResponder;
+pub use transport::HttpTransport;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -44,6 +49,18 @@ pub struct SpspResponse {
     shared_secret: Vec<u8>,
 }
 
+impl SpspResponse {
+    /// The ILP Address to send the STREAM payment to
+    pub fn destination_account(&self) -> &Address {
+        &self.destination_account
+    }
+
+    /// The shared secret of the STREAM connection
+    pub fn shared_secret(&self) -> &[u8] {
+        &self.shared_secret
+    }
+}
+
 // From https://github.com/serde-rs/json/issues/360#issuecomment-330095360
 #[doc(hidden)]
 mod serde_base64 {
@@ -28,6 +28,18 @@ impl SpspResponder {
         }
     }
 
+    /// Constructs a new SPSP Responder which shares the connection generator, e.g. with
+    /// the STREAM receiver, so that the server secret can be rotated
+    pub fn with_connection_generator(
+        ilp_address: Address,
+        connection_generator: ConnectionGenerator,
+    ) -> Self {
+        SpspResponder {
+            ilp_address,
+            connection_generator,
+        }
+    }
+
     /// Returns an HTTP Response containing the destination account
     /// and shared secret for this connection
     /// These fields are generated via [Stream's `ConnectionGenerator`](../interledger_stream/struct.ConnectionGenerator.html#method.generate_address_and_secret)
@@ -0,0 +1,185 @@
+use bytes::BytesMut;
+use interledger_packet::{ErrorCode, Packet, Reject, RejectBuilder};
+use interledger_service::{Account, BoxedIlpFuture, IlpResult, IncomingRequest, IncomingService};
+use reqwest::Client;
+use std::{convert::TryFrom, future::Future};
+use tracing::{error, trace};
+
+/// Sends the packets of a payment to a connector over [ILP-over-HTTP](https://interledger.org/rfcs/0035-ilp-over-http),
+/// as the parent of the account the payment is sent from.
+///
+/// It is the service to give [`pay`](./fn.pay.html) in environments without a node of their own,
+/// such as browsers, where the requests are made with `fetch`. Any other
+/// [`IncomingService`](../interledger_service/trait.IncomingService.html) can be given instead
+/// to send the packets over another transport.
+#[derive(Clone)]
+pub struct HttpTransport {
+    client: Client,
+    url: String,
+    authorization: String,
+}
+
+impl HttpTransport {
+    /// Sends the packets to the ILP-over-HTTP endpoint at `url`, authenticated with
+    /// the incoming token of the account on the connector
+    pub fn new(url: &str, token: &str) -> Self {
+        HttpTransport {
+            client: Client::new(),
+            url: url.to_string(),
+            authorization: format!("Bearer {}", token),
+        }
+    }
+
+    async fn send(self, prepare: Vec<u8>) -> IlpResult {
+        trace!(url = self.url.as_str(), "Sending ILP over HTTP packet");
+        let response = self
+            .client
+            .post(&self.url)
+            .header("authorization", &self.authorization)
+            .header("content-type", "application/octet-stream")
+            .body(prepare)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| {
+                error!("Error sending ILP over HTTP request: {:?}", err);
+                let code = match err.status() {
+                    Some(status) if status.is_client_error() => ErrorCode::F02_UNREACHABLE,
+                    _ => ErrorCode::T01_PEER_UNREACHABLE,
+                };
+                reject(
+                    code,
+                    &format!("Error sending ILP over HTTP request: {}", err),
+                )
+            })?;
+
+        let body = response.bytes().await.map_err(|err| {
+            error!("Error getting HTTP response body: {:?}", err);
+            reject(ErrorCode::T01_PEER_UNREACHABLE, "")
+        })?;
+        match Packet::try_from(BytesMut::from(body.as_ref())) {
+            Ok(Packet::Fulfill(fulfill)) => Ok(fulfill),
+            Ok(Packet::Reject(reject)) => Err(reject),
+            _ => Err(reject(ErrorCode::T01_PEER_UNREACHABLE, "")),
+        }
+    }
+}
+
+impl<A> IncomingService<A> for HttpTransport
+where
+    A: Account + 'static,
+{
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move { run(self.clone().send(request.prepare.as_ref().to_owned())).await })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn run<F: Future<Output = IlpResult>>(request: F) -> IlpResult {
+    request.await
+}
+
+/// The futures of `fetch` cannot leave the browser's thread, so the request runs
+/// as a local task and only its result is awaited
+#[cfg(target_arch = "wasm32")]
+async fn run<F: Future<Output = IlpResult> + 'static>(request: F) -> IlpResult {
+    let (sender, receiver) = futures::channel::oneshot::channel();
+    wasm_bindgen_futures::spawn_local(async move {
+        let _ = sender.send(request.await);
+    });
+    receiver
+        .await
+        .unwrap_or_else(|_| Err(reject(ErrorCode::T00_INTERNAL_ERROR, "")))
+}
+
+fn reject(code: ErrorCode, message: &str) -> Reject {
+    RejectBuilder {
+        code,
+        message: message.as_bytes(),
+        triggered_by: None,
+        data: &[],
+    }
+    .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use interledger_service::Username;
+    use std::str::FromStr;
+    use std::time::{Duration, SystemTime};
+    use uuid::Uuid;
+
+    #[derive(Clone, Debug)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            unimplemented!()
+        }
+
+        fn ilp_address(&self) -> &Address {
+            unimplemented!()
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+    }
+
+    fn request() -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.receiver").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[tokio::test]
+    async fn returns_fulfills() {
+        let fulfill = FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: b"test data",
+        }
+        .build();
+        let mock = mockito::mock("POST", "/ilp")
+            .match_header("authorization", "Bearer token")
+            .with_body(fulfill.as_ref())
+            .create();
+
+        let mut transport = HttpTransport::new(&format!("{}/ilp", mockito::server_url()), "token");
+        let result = transport.handle_request(request()).await.unwrap();
+        assert_eq!(result.data(), b"test data");
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn rejects_when_the_connector_errors() {
+        let mock = mockito::mock("POST", "/unauthorized")
+            .with_status(401)
+            .create();
+
+        let mut transport =
+            HttpTransport::new(&format!("{}/unauthorized", mockito::server_url()), "token");
+        let reject = transport.handle_request(request()).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+        mock.assert();
+    }
+}
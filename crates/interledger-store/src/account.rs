@@ -1,5 +1,7 @@
-use super::crypto::{decrypt_token, encrypt_token};
+use super::crypto::{decrypt_token, encrypt_token, hash_token, is_token_hash};
+use bytes::BytesMut;
 use interledger_api::AccountDetails;
+use interledger_auth::{AuthAccount, AuthProtocol};
 use interledger_btp::BtpAccount;
 use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
 use interledger_errors::CreateAccountError;
@@ -7,7 +9,8 @@ use interledger_http::HttpAccount;
 use interledger_packet::Address;
 use interledger_service::{Account as AccountTrait, Username};
 use interledger_service_util::{
-    MaxPacketAmountAccount, RateLimitAccount, RoundTripTimeAccount, DEFAULT_ROUND_TRIP_TIME,
+    MaxPacketAmountAccount, MinPacketAmountAccount, RateLimitAccount, RoundTripTimeAccount,
+    VolumeLimitAccount, DEFAULT_ROUND_TRIP_TIME,
 };
 use interledger_settlement::core::types::{SettlementAccount, SettlementEngineDetails};
 use ring::aead;
@@ -15,6 +18,7 @@ use secrecy::{ExposeSecret, SecretBytesMut, SecretString};
 use serde::Serializer;
 use serde::{Deserialize, Serialize};
 use std::str::{self, FromStr};
+use std::time::Duration;
 use tracing::error;
 use url::Url;
 use uuid::Uuid;
@@ -38,13 +42,15 @@ pub struct Account {
     pub(crate) asset_scale: u8,
     /// The max amount per packet which can be routed for this account
     pub(crate) max_packet_amount: u64,
+    /// The min amount per packet which is forwarded for this account
+    pub(crate) min_packet_amount: Option<u64>,
     /// The minimum balance this account can have (consider this as a credit/trust limit)
     pub(crate) min_balance: Option<i64>,
     /// The account's ILP over HTTP URL (this is where packets are sent over HTTP from your node)
     pub(crate) ilp_over_http_url: Option<Url>,
     #[serde(serialize_with = "optional_secret_bytes_to_utf8")]
-    /// The account's API and incoming ILP over HTTP token.
-    /// This must match the ILP over HTTP outgoing token on the peer's node if receiving
+    /// The hash of the account's API and incoming ILP over HTTP token.
+    /// The token must match the ILP over HTTP outgoing token on the peer's node if receiving
     /// packets from that peer
     // TODO: The incoming token is used for both ILP over HTTP, and for authorizing actions from the HTTP API.
     // Should we add 1 more token, for more granular permissioning?
@@ -57,8 +63,8 @@ pub struct Account {
     /// The account's ILP over BTP URL (this is where packets are sent over WebSockets from your node)
     pub(crate) ilp_over_btp_url: Option<Url>,
     #[serde(serialize_with = "optional_secret_bytes_to_utf8")]
-    /// The account's incoming ILP over BTP token.
-    /// This must match the ILP over BTP outgoing token on the peer's node if exchanging
+    /// The hash of the account's incoming ILP over BTP token.
+    /// The token must match the ILP over BTP outgoing token on the peer's node if exchanging
     /// packets with that peer
     pub(crate) ilp_over_btp_incoming_token: Option<SecretBytesMut>,
     #[serde(serialize_with = "optional_secret_bytes_to_utf8")]
@@ -70,8 +76,13 @@ pub struct Account {
     pub(crate) settle_threshold: Option<i64>,
     /// The amount which the balance service will attempt to settle down to
     pub(crate) settle_to: Option<i64>,
+    /// The maximum time in seconds for which a positive balance is left unsettled
+    pub(crate) settle_every: Option<u32>,
     /// The routing relation of the account
     pub(crate) routing_relation: RoutingRelation,
+    /// How much the routes through the account are preferred over equally good routes
+    /// through other accounts with the same routing relation
+    pub(crate) routing_preference: Option<u32>,
     /// The round trip time of the account (should be set depending on how
     /// well the network connectivity of the account and the node is)
     pub(crate) round_trip_time: u32,
@@ -79,6 +90,10 @@ pub struct Account {
     pub(crate) packets_per_minute_limit: Option<u32>,
     /// The maximum amount the account can send per minute
     pub(crate) amount_per_minute_limit: Option<u64>,
+    /// The maximum amount of a payment sent by the account via the API
+    pub(crate) max_payment_amount: Option<u64>,
+    /// The maximum amount the account can send per day
+    pub(crate) amount_per_day_limit: Option<u64>,
     /// The account's settlement engine URL. If a global engine url is configured
     /// for the account's asset code,  that will be used instead (even if the account is
     /// configured with a specific one)
@@ -102,11 +117,22 @@ where
     serializer.serialize_str("SECRET")
 }
 
+/// Hashes an incoming token, unless it is already a hash (e.g. in an exported account)
+fn incoming_token_hash(token: &SecretString) -> SecretBytesMut {
+    let token = token.expose_secret().as_bytes();
+    if is_token_hash(token) {
+        SecretBytesMut::new(token)
+    } else {
+        SecretBytesMut::new(hash_token(token).as_bytes())
+    }
+}
+
 impl Account {
     /// Creates an account from the provided id and details. If there is no ILP Address
     /// in the provided details, then the account's ILP Address is generated by appending
     /// the `details.username` to the provided `node_ilp_address`.
-    /// The default RoutingRelation is `NonRoutingAccount`
+    /// The default RoutingRelation is `NonRoutingAccount`.
+    /// Only the hashes of the incoming tokens are kept.
     pub fn try_from(
         id: Uuid,
         details: AccountDetails,
@@ -151,32 +177,76 @@ impl Account {
             asset_code: details.asset_code.to_uppercase(),
             asset_scale: details.asset_scale,
             max_packet_amount: details.max_packet_amount,
+            min_packet_amount: details.min_packet_amount,
             min_balance: details.min_balance,
             ilp_over_http_url,
             ilp_over_http_incoming_token: details
                 .ilp_over_http_incoming_token
-                .map(|token| SecretBytesMut::new(token.expose_secret().as_str())),
+                .as_ref()
+                .map(incoming_token_hash),
             ilp_over_http_outgoing_token: details
                 .ilp_over_http_outgoing_token
                 .map(|token| SecretBytesMut::new(token.expose_secret().as_str())),
             ilp_over_btp_url,
             ilp_over_btp_incoming_token: details
                 .ilp_over_btp_incoming_token
-                .map(|token| SecretBytesMut::new(token.expose_secret().as_str())),
+                .as_ref()
+                .map(incoming_token_hash),
             ilp_over_btp_outgoing_token: details
                 .ilp_over_btp_outgoing_token
                 .map(|token| SecretBytesMut::new(token.expose_secret().as_str())),
             settle_to: details.settle_to,
             settle_threshold: details.settle_threshold,
+            settle_every: details.settle_every,
             routing_relation,
+            routing_preference: details.routing_preference,
             round_trip_time: details.round_trip_time.unwrap_or(DEFAULT_ROUND_TRIP_TIME),
             packets_per_minute_limit: details.packets_per_minute_limit,
             amount_per_minute_limit: details.amount_per_minute_limit,
+            max_payment_amount: details.max_payment_amount,
+            amount_per_day_limit: details.amount_per_day_limit,
             settlement_engine_url,
         })
     }
 
-    /// Encrypts the account's incoming/outgoing BTP and HTTP keys with the provided encryption key
+    /// Returns the details from which an identical account can be created, e.g. in another store.
+    /// The incoming tokens are returned as their hashes.
+    pub(crate) fn to_details(&self) -> AccountDetails {
+        let token = |token: &Option<SecretBytesMut>| {
+            token.as_ref().map(|token| {
+                SecretString::new(String::from_utf8_lossy(token.expose_secret()).into_owned())
+            })
+        };
+        AccountDetails {
+            ilp_address: Some(self.ilp_address.clone()),
+            username: self.username.clone(),
+            asset_code: self.asset_code.clone(),
+            asset_scale: self.asset_scale,
+            max_packet_amount: self.max_packet_amount,
+            min_packet_amount: self.min_packet_amount,
+            min_balance: self.min_balance,
+            ilp_over_http_url: self.ilp_over_http_url.as_ref().map(Url::to_string),
+            ilp_over_http_incoming_token: token(&self.ilp_over_http_incoming_token),
+            ilp_over_http_outgoing_token: token(&self.ilp_over_http_outgoing_token),
+            ilp_over_btp_url: self.ilp_over_btp_url.as_ref().map(Url::to_string),
+            ilp_over_btp_outgoing_token: token(&self.ilp_over_btp_outgoing_token),
+            ilp_over_btp_incoming_token: token(&self.ilp_over_btp_incoming_token),
+            settle_threshold: self.settle_threshold,
+            settle_to: self.settle_to,
+            settle_every: self.settle_every,
+            routing_relation: Some(self.routing_relation.to_string()),
+            routing_preference: self.routing_preference,
+            round_trip_time: Some(self.round_trip_time),
+            amount_per_minute_limit: self.amount_per_minute_limit,
+            max_payment_amount: self.max_payment_amount,
+            amount_per_day_limit: self.amount_per_day_limit,
+            packets_per_minute_limit: self.packets_per_minute_limit,
+            settlement_engine_url: self.settlement_engine_url.as_ref().map(Url::to_string),
+        }
+    }
+
+    /// Encrypts the account's outgoing BTP and HTTP keys with the provided encryption key.
+    /// The incoming keys are only stored as hashes and do not need to be encrypted.
     pub fn encrypt_tokens(
         mut self,
         encryption_key: &aead::LessSafeKey,
@@ -193,18 +263,6 @@ impl Account {
                 token.expose_secret(),
             )));
         }
-        if let Some(ref token) = self.ilp_over_btp_incoming_token {
-            self.ilp_over_btp_incoming_token = Some(SecretBytesMut::from(encrypt_token(
-                encryption_key,
-                token.expose_secret(),
-            )));
-        }
-        if let Some(ref token) = self.ilp_over_http_incoming_token {
-            self.ilp_over_http_incoming_token = Some(SecretBytesMut::from(encrypt_token(
-                encryption_key,
-                token.expose_secret(),
-            )));
-        }
         AccountWithEncryptedTokens { account: self }
     }
 }
@@ -216,7 +274,7 @@ pub struct AccountWithEncryptedTokens {
 }
 
 impl AccountWithEncryptedTokens {
-    /// Decrypts the account's incoming/outgoing BTP and HTTP keys with the provided decryption key
+    /// Decrypts the account's outgoing BTP and HTTP keys with the provided decryption key
     pub fn decrypt_tokens(mut self, decryption_key: &aead::LessSafeKey) -> Account {
         if let Some(ref encrypted) = self.account.ilp_over_btp_outgoing_token {
             self.account.ilp_over_btp_outgoing_token =
@@ -240,31 +298,100 @@ impl AccountWithEncryptedTokens {
                     })
                     .ok();
         }
-        if let Some(ref encrypted) = self.account.ilp_over_btp_incoming_token {
-            self.account.ilp_over_btp_incoming_token =
-                decrypt_token(decryption_key, encrypted.expose_secret())
-                    .map_err(|_| {
-                        error!(
-                            "Unable to decrypt ilp_over_btp_incoming_token for account {}",
-                            self.account.id
-                        )
-                    })
-                    .ok();
-        }
-        if let Some(ref encrypted) = self.account.ilp_over_http_incoming_token {
-            self.account.ilp_over_http_incoming_token =
-                decrypt_token(decryption_key, encrypted.expose_secret())
-                    .map_err(|_| {
-                        error!(
-                            "Unable to decrypt ilp_over_http_incoming_token for account {}",
-                            self.account.id
-                        )
-                    })
-                    .ok();
-        }
 
         self.account
     }
+
+    /// Re-encrypts the tokens which were encrypted with a previous key, so that they can
+    /// be decrypted with `decryption_key`. Tokens which can already be decrypted with
+    /// `decryption_key` are left untouched, which makes it safe to run this repeatedly.
+    /// Returns the names and new values of the tokens which were re-encrypted.
+    pub(crate) fn reencrypt_tokens(
+        &mut self,
+        previous_decryption_key: &aead::LessSafeKey,
+        decryption_key: &aead::LessSafeKey,
+        encryption_key: &aead::LessSafeKey,
+    ) -> Vec<(&'static str, BytesMut)> {
+        let id = self.account.id;
+        let account = &mut self.account;
+        let tokens = vec![
+            (
+                "ilp_over_http_outgoing_token",
+                &mut account.ilp_over_http_outgoing_token,
+            ),
+            (
+                "ilp_over_btp_outgoing_token",
+                &mut account.ilp_over_btp_outgoing_token,
+            ),
+        ];
+
+        let mut reencrypted = Vec::new();
+        for (field, token) in tokens {
+            let encrypted = match token.as_ref() {
+                Some(encrypted) => encrypted.expose_secret().clone(),
+                None => continue,
+            };
+            if decrypt_token(decryption_key, &encrypted).is_ok() {
+                continue;
+            }
+            match decrypt_token(previous_decryption_key, &encrypted) {
+                Ok(decrypted) => {
+                    let new = encrypt_token(encryption_key, decrypted.expose_secret());
+                    *token = Some(SecretBytesMut::new(new.clone()));
+                    reencrypted.push((field, new));
+                }
+                Err(_) => error!(
+                    "Unable to decrypt {} for account {} with either the current or the previous key",
+                    field, id
+                ),
+            }
+        }
+        reencrypted
+    }
+
+    /// Replaces the incoming tokens which were stored encrypted (before only their
+    /// hashes were stored) with their hashes. The tokens are decrypted with the first of
+    /// the `decryption_keys` which works. Tokens which are already hashed are left
+    /// untouched. Returns the names and hashes of the tokens which were replaced.
+    pub(crate) fn hash_incoming_tokens(
+        &mut self,
+        decryption_keys: &[&aead::LessSafeKey],
+    ) -> Vec<(&'static str, BytesMut)> {
+        let id = self.account.id;
+        let account = &mut self.account;
+        let tokens = vec![
+            (
+                "ilp_over_http_incoming_token",
+                &mut account.ilp_over_http_incoming_token,
+            ),
+            (
+                "ilp_over_btp_incoming_token",
+                &mut account.ilp_over_btp_incoming_token,
+            ),
+        ];
+
+        let mut hashed = Vec::new();
+        for (field, token) in tokens {
+            let encrypted = match token.as_ref() {
+                Some(encrypted) if !is_token_hash(encrypted.expose_secret()) => {
+                    encrypted.expose_secret().clone()
+                }
+                _ => continue,
+            };
+            let decrypted = decryption_keys
+                .iter()
+                .find_map(|key| decrypt_token(key, &encrypted).ok());
+            match decrypted {
+                Some(decrypted) => {
+                    let hash = BytesMut::from(hash_token(decrypted.expose_secret()).as_bytes());
+                    *token = Some(SecretBytesMut::new(hash.clone()));
+                    hashed.push((field, hash));
+                }
+                None => error!("Unable to decrypt {} for account {}", field, id),
+            }
+        }
+        hashed
+    }
 }
 
 // The following trait implementations are simple accessors to the Account's fields
@@ -319,16 +446,36 @@ impl BtpAccount for Account {
     }
 }
 
+impl AuthAccount for Account {
+    fn incoming_token_hash(&self, protocol: AuthProtocol) -> Option<&[u8]> {
+        let hash = match protocol {
+            AuthProtocol::Btp => &self.ilp_over_btp_incoming_token,
+            AuthProtocol::Http => &self.ilp_over_http_incoming_token,
+        };
+        hash.as_ref().map(|hash| &**hash.expose_secret())
+    }
+}
+
 impl MaxPacketAmountAccount for Account {
     fn max_packet_amount(&self) -> u64 {
         self.max_packet_amount
     }
 }
 
+impl MinPacketAmountAccount for Account {
+    fn min_packet_amount(&self) -> u64 {
+        self.min_packet_amount.unwrap_or(0)
+    }
+}
+
 impl CcpRoutingAccount for Account {
     fn routing_relation(&self) -> RoutingRelation {
         self.routing_relation
     }
+
+    fn routing_preference(&self) -> u32 {
+        self.routing_preference.unwrap_or(0)
+    }
 }
 
 impl RoundTripTimeAccount for Account {
@@ -347,16 +494,40 @@ impl RateLimitAccount for Account {
     }
 }
 
+impl VolumeLimitAccount for Account {
+    fn max_payment_amount(&self) -> Option<u64> {
+        self.max_payment_amount
+    }
+
+    fn amount_per_day_limit(&self) -> Option<u64> {
+        self.amount_per_day_limit
+    }
+}
+
 impl SettlementAccount for Account {
     fn settlement_engine_details(&self) -> Option<SettlementEngineDetails> {
         self.settlement_engine_url
             .as_ref()
             .map(|url| SettlementEngineDetails { url: url.clone() })
     }
+
+    fn settle_every(&self) -> Option<Duration> {
+        self.settle_every
+            .map(|seconds| Duration::from_secs(seconds.into()))
+    }
+
+    fn settle_threshold(&self) -> Option<i64> {
+        self.settle_threshold
+    }
+
+    fn min_balance(&self) -> Option<i64> {
+        self.min_balance
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use super::super::crypto::{generate_keys, verify_token};
     use super::*;
     use once_cell::sync::Lazy;
     use secrecy::SecretString;
@@ -367,6 +538,7 @@ mod test {
         asset_scale: 6,
         asset_code: "XYZ".to_string(),
         max_packet_amount: 1000,
+        min_packet_amount: None,
         min_balance: Some(-1000),
         // we are Bob and we're using this account to peer with Alice
         ilp_over_http_url: Some("http://example.com/accounts/bob/ilp".to_string()),
@@ -377,9 +549,13 @@ mod test {
         ilp_over_btp_outgoing_token: Some(SecretString::new("outgoing_btp_token".to_string())),
         settle_threshold: Some(0),
         settle_to: Some(-1000),
+        settle_every: None,
         routing_relation: Some("Peer".to_string()),
+        routing_preference: None,
         round_trip_time: Some(600),
         amount_per_minute_limit: None,
+        max_payment_amount: None,
+        amount_per_day_limit: None,
         packets_per_minute_limit: None,
         settlement_engine_url: None,
     });
@@ -394,6 +570,17 @@ mod test {
         )
         .unwrap();
         assert_eq!(account.id(), id);
+        // only the hashes of the incoming tokens are kept
+        let incoming = account.ilp_over_http_incoming_token.as_ref().unwrap();
+        assert!(verify_token(
+            incoming.expose_secret(),
+            b"incoming_auth_token"
+        ));
+        let incoming = account.ilp_over_btp_incoming_token.as_ref().unwrap();
+        assert!(verify_token(
+            incoming.expose_secret(),
+            b"incoming_btp_token"
+        ));
         // the HTTP token does not contain the username
         assert_eq!(
             account.get_http_auth_token().unwrap().expose_secret(),
@@ -413,4 +600,34 @@ mod test {
         );
         assert_eq!(account.routing_relation(), RoutingRelation::Peer);
     }
+
+    #[test]
+    fn hashes_encrypted_incoming_tokens() {
+        let (encryption_key, decryption_key) = generate_keys(&[0; 32]);
+        let (_, other_decryption_key) = generate_keys(&[1; 32]);
+        let mut account = Account::try_from(
+            Uuid::new_v4(),
+            ACCOUNT_DETAILS.clone(),
+            Address::from_str("example.account").unwrap(),
+        )
+        .unwrap();
+        // tokens stored by older versions were encrypted instead of hashed
+        account.ilp_over_http_incoming_token = Some(SecretBytesMut::from(encrypt_token(
+            &encryption_key.expose_secret().0,
+            b"incoming_auth_token",
+        )));
+        let mut encrypted = account.encrypt_tokens(&encryption_key.expose_secret().0);
+
+        let hashed = encrypted.hash_incoming_tokens(&[
+            &other_decryption_key.expose_secret().0,
+            &decryption_key.expose_secret().0,
+        ]);
+        assert_eq!(hashed.len(), 1);
+        assert_eq!(hashed[0].0, "ilp_over_http_incoming_token");
+        assert!(verify_token(&hashed[0].1, b"incoming_auth_token"));
+        // hashed tokens are left untouched
+        assert!(encrypted
+            .hash_incoming_tokens(&[&decryption_key.expose_secret().0])
+            .is_empty());
+    }
 }
@@ -0,0 +1,147 @@
+use super::account::Account;
+use interledger_service::{Account as AccountTrait, Username};
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// In-process LRU cache of recently used accounts, so that processing a packet
+/// does not require a round trip to the database for every account lookup.
+///
+/// Entries expire after the configured TTL, which bounds how long changes made
+/// by other processes sharing the same database go unnoticed. Changes made
+/// through the store itself invalidate the affected entries immediately.
+pub(crate) struct AccountCache {
+    ttl: Duration,
+    accounts: Mutex<LruCache<Uuid, (Account, Instant)>>,
+    usernames: Mutex<LruCache<String, (Uuid, Instant)>>,
+}
+
+impl AccountCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        AccountCache {
+            ttl,
+            accounts: Mutex::new(LruCache::new(capacity)),
+            usernames: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Returns the cached account, unless it is missing or expired
+    pub fn get(&self, id: Uuid) -> Option<Account> {
+        let mut accounts = self.accounts.lock();
+        match accounts.get(&id) {
+            Some((account, inserted_at)) if inserted_at.elapsed() < self.ttl => {
+                Some(account.clone())
+            }
+            Some(_) => {
+                accounts.pop(&id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the cached id of the account with the given username, unless it is missing or expired
+    pub fn get_id(&self, username: &Username) -> Option<Uuid> {
+        let mut usernames = self.usernames.lock();
+        match usernames.get(username.as_ref()) {
+            Some((id, inserted_at)) if inserted_at.elapsed() < self.ttl => Some(*id),
+            Some(_) => {
+                usernames.pop(username.as_ref());
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, account: Account) {
+        let now = Instant::now();
+        self.usernames
+            .lock()
+            .put(account.username().to_string(), (account.id(), now));
+        self.accounts.lock().put(account.id(), (account, now));
+    }
+
+    /// Removes the account with the given id (and its username) from the cache
+    pub fn invalidate(&self, id: Uuid) {
+        if let Some((account, _)) = self.accounts.lock().pop(&id) {
+            self.usernames.lock().pop(account.username().as_ref());
+        }
+    }
+
+    /// Removes all entries, e.g. after a change which affects many accounts
+    pub fn clear(&self) {
+        self.accounts.lock().clear();
+        self.usernames.lock().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_api::AccountDetails;
+    use interledger_packet::Address;
+    use std::str::FromStr;
+
+    fn account(username: &str) -> Account {
+        Account::try_from(
+            Uuid::new_v4(),
+            AccountDetails {
+                ilp_address: None,
+                username: Username::from_str(username).unwrap(),
+                asset_scale: 9,
+                asset_code: "XRP".to_string(),
+                max_packet_amount: 1000,
+                min_packet_amount: None,
+                min_balance: None,
+                ilp_over_http_url: None,
+                ilp_over_http_incoming_token: None,
+                ilp_over_http_outgoing_token: None,
+                ilp_over_btp_url: None,
+                ilp_over_btp_incoming_token: None,
+                ilp_over_btp_outgoing_token: None,
+                settle_threshold: None,
+                settle_to: None,
+                settle_every: None,
+                routing_relation: None,
+                routing_preference: None,
+                round_trip_time: None,
+                amount_per_minute_limit: None,
+                max_payment_amount: None,
+                amount_per_day_limit: None,
+                packets_per_minute_limit: None,
+                settlement_engine_url: None,
+            },
+            Address::from_str("example.node").unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = AccountCache::new(2, Duration::from_secs(60));
+        let (alice, bob, charlie) = (account("alice"), account("bob"), account("charlie"));
+        cache.insert(alice.clone());
+        cache.insert(bob.clone());
+        assert!(cache.get(alice.id()).is_some());
+        cache.insert(charlie.clone());
+        assert!(cache.get(alice.id()).is_some());
+        assert!(cache.get(bob.id()).is_none());
+        assert_eq!(cache.get_id(charlie.username()), Some(charlie.id()));
+    }
+
+    #[test]
+    fn expires_and_invalidates_entries() {
+        let cache = AccountCache::new(10, Duration::from_secs(0));
+        let alice = account("alice");
+        cache.insert(alice.clone());
+        assert!(cache.get(alice.id()).is_none());
+        assert!(cache.get_id(alice.username()).is_none());
+
+        let cache = AccountCache::new(10, Duration::from_secs(60));
+        cache.insert(alice.clone());
+        cache.invalidate(alice.id());
+        assert!(cache.get(alice.id()).is_none());
+        assert!(cache.get_id(alice.username()).is_none());
+    }
+}
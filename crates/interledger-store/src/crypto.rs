@@ -167,6 +167,8 @@ pub fn decrypt_token(
     }
 }
 
+pub use interledger_auth::{hash_token, is_token_hash, verify_token};
+
 #[cfg(test)]
 mod encryption {
     use super::*;
@@ -0,0 +1,102 @@
+use interledger_service::metrics;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// Records how long each store operation takes and logs the operations
+/// which exceed their configured threshold
+#[derive(Debug, Clone)]
+pub(crate) struct OperationTimers {
+    /// The store backend, used to label the recorded metrics
+    backend: &'static str,
+    /// Threshold applied to all methods without a threshold of their own
+    default_threshold: Option<Duration>,
+    /// Map of store method name -> threshold
+    thresholds: HashMap<String, Duration>,
+}
+
+impl OperationTimers {
+    pub(crate) fn new(backend: &'static str) -> Self {
+        OperationTimers {
+            backend,
+            default_threshold: None,
+            thresholds: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn set_default_threshold(&mut self, threshold: Duration) {
+        self.default_threshold = Some(threshold);
+    }
+
+    pub(crate) fn set_threshold(&mut self, method: &str, threshold: Duration) {
+        self.thresholds.insert(method.to_string(), threshold);
+    }
+
+    fn threshold(&self, method: &str) -> Option<Duration> {
+        self.thresholds
+            .get(method)
+            .copied()
+            .or(self.default_threshold)
+    }
+
+    /// Starts timing the given store method. The duration is recorded when the
+    /// returned timer is dropped, i.e. when the operation completes or is cancelled.
+    pub(crate) fn start(&self, method: &'static str) -> OperationTimer<'_> {
+        OperationTimer {
+            timers: self,
+            method,
+            started_at: Instant::now(),
+        }
+    }
+}
+
+pub(crate) struct OperationTimer<'a> {
+    timers: &'a OperationTimers,
+    method: &'static str,
+    started_at: Instant,
+}
+
+impl Drop for OperationTimer<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.started_at.elapsed();
+        metrics::record_histogram(
+            "store.operation.duration",
+            &[("backend", self.timers.backend), ("method", self.method)],
+            elapsed.as_nanos() as u64,
+        );
+        if let Some(threshold) = self.timers.threshold(self.method) {
+            if elapsed >= threshold {
+                warn!(
+                    "Slow {} store operation: {} took {}ms (threshold: {}ms)",
+                    self.timers.backend,
+                    self.method,
+                    elapsed.as_millis(),
+                    threshold.as_millis()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn method_threshold_overrides_default() {
+        let mut timers = OperationTimers::new("test");
+        assert_eq!(timers.threshold("get_accounts"), None);
+        timers.set_default_threshold(Duration::from_millis(100));
+        timers.set_threshold("get_accounts", Duration::from_millis(5));
+        assert_eq!(
+            timers.threshold("get_accounts"),
+            Some(Duration::from_millis(5))
+        );
+        assert_eq!(
+            timers.threshold("get_balance"),
+            Some(Duration::from_millis(100))
+        );
+    }
+}
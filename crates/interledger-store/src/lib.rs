@@ -4,8 +4,23 @@
 
 /// A module to define the primitive `Account` struct which implements `Account` related traits.
 pub mod account;
+/// In-process cache of recently used accounts
+#[cfg(feature = "redis")]
+mod cache;
 /// Cryptographic utilities for encrypting/decrypting data as well as clearing data from memory
 pub mod crypto;
+/// Cache of recently verified incoming tokens
+/// Timing of store operations
+#[cfg(any(feature = "redis", feature = "sqlite", feature = "sled"))]
+mod instrumentation;
+/// An in-memory backend holding a fixed set of accounts and routes, for nodes which only relay packets
+pub mod memory;
+/// An embedded key-value backend using [sled](https://github.com/spacejam/sled)
+#[cfg(feature = "sled")]
+pub mod sled;
 /// A redis backend using [redis-rs](https://github.com/mitsuhiko/redis-rs/)
 #[cfg(feature = "redis")]
 pub mod redis;
+/// An embedded SQLite backend using [rusqlite](https://github.com/rusqlite/rusqlite)
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
@@ -0,0 +1,259 @@
+//! An in-memory backend for nodes which only relay packets. The accounts and routes are
+//! given when the store is created, typically from the node's configuration file, and
+//! nothing is persisted: there are no balances, settlements or other state to keep.
+
+use super::account::Account;
+use async_trait::async_trait;
+use interledger_api::AccountDetails;
+use interledger_auth::{AuthError, AuthProtocol, VerifiedTokens};
+use interledger_errors::*;
+use interledger_http::HttpStore;
+use interledger_packet::Address;
+use interledger_router::RouterStore;
+use interledger_service::{AccountStore, AddressStore, Username};
+use parking_lot::RwLock;
+use std::{collections::HashMap, sync::Arc};
+use tracing::debug;
+use uuid::Uuid;
+
+/// Store which keeps a fixed set of accounts and routes in memory
+#[derive(Clone)]
+pub struct MemoryStore {
+    ilp_address: Arc<RwLock<Address>>,
+    accounts: Arc<HashMap<Uuid, Account>>,
+    usernames: Arc<HashMap<Username, Uuid>>,
+    routes: Arc<HashMap<String, Uuid>>,
+    verified_tokens: Arc<VerifiedTokens>,
+}
+
+impl MemoryStore {
+    /// Creates the store with the given accounts and static routes, which map ILP address
+    /// prefixes to the usernames of the accounts the packets are routed to. Every account
+    /// is also routed the packets to its own address, which is derived from the node's
+    /// address and its username if it does not have one.
+    pub fn new(
+        ilp_address: Address,
+        accounts: Vec<AccountDetails>,
+        static_routes: HashMap<String, Username>,
+    ) -> Result<Self, NodeStoreError> {
+        let mut by_id = HashMap::with_capacity(accounts.len());
+        let mut usernames = HashMap::with_capacity(accounts.len());
+        let mut routes = HashMap::with_capacity(accounts.len() + static_routes.len());
+        for details in accounts {
+            if usernames.contains_key(&details.username) {
+                return Err(NodeStoreError::AccountExists(details.username.to_string()));
+            }
+            let account = Account::try_from(Uuid::new_v4(), details, ilp_address.clone())
+                .map_err(NodeStoreError::InvalidAccount)?;
+            debug!(
+                "Loaded account {} with ILP address {}",
+                account.username, account.ilp_address
+            );
+            usernames.insert(account.username.clone(), account.id);
+            routes.insert(account.ilp_address.to_string(), account.id);
+            by_id.insert(account.id, account);
+        }
+        // The static routes take precedence over the addresses of the accounts
+        for (prefix, username) in static_routes {
+            let id = usernames
+                .get(&username)
+                .ok_or_else(|| NodeStoreError::AccountNotFound(username.to_string()))?;
+            routes.insert(prefix, *id);
+        }
+
+        Ok(MemoryStore {
+            ilp_address: Arc::new(RwLock::new(ilp_address)),
+            accounts: Arc::new(by_id),
+            usernames: Arc::new(usernames),
+            routes: Arc::new(routes),
+            verified_tokens: Arc::new(VerifiedTokens::new()),
+        })
+    }
+}
+
+#[async_trait]
+impl AccountStore for MemoryStore {
+    type Account = Account;
+
+    async fn get_accounts(
+        &self,
+        account_ids: Vec<Uuid>,
+    ) -> Result<Vec<Account>, AccountStoreError> {
+        let accounts: Vec<Account> = account_ids
+            .iter()
+            .filter_map(|id| self.accounts.get(id).cloned())
+            .collect();
+        if accounts.len() == account_ids.len() {
+            Ok(accounts)
+        } else {
+            Err(AccountStoreError::WrongLength {
+                expected: account_ids.len(),
+                actual: accounts.len(),
+            })
+        }
+    }
+
+    async fn get_account_id_from_username(
+        &self,
+        username: &Username,
+    ) -> Result<Uuid, AccountStoreError> {
+        match self.usernames.get(username) {
+            Some(id) => Ok(*id),
+            None => {
+                debug!("Username not found: {}", username);
+                Err(AccountStoreError::AccountNotFound(username.to_string()))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AddressStore for MemoryStore {
+    /// Only changes the node's address: the addresses of the accounts and the routes
+    /// are those the store was created with
+    async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+        debug!("Setting ILP address to: {}", ilp_address);
+        *self.ilp_address.write() = ilp_address;
+        Ok(())
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        // There is no parent to have assigned another address
+        Ok(())
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        self.ilp_address.read().clone()
+    }
+}
+
+#[async_trait]
+impl HttpStore for MemoryStore {
+    type Account = Account;
+
+    async fn get_account_from_http_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Account, HttpStoreError> {
+        let account = self
+            .usernames
+            .get(username)
+            .and_then(|id| self.accounts.get(id))
+            .cloned();
+        self.verified_tokens
+            .authenticate(AuthProtocol::Http, username, account, token)
+            .await
+            .map_err(|err| match err {
+                AuthError::AccountNotFound => HttpStoreError::AccountNotFound(username.to_string()),
+                _ => HttpStoreError::Unauthorized(username.to_string()),
+            })
+    }
+}
+
+impl RouterStore for MemoryStore {
+    fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
+        self.routes.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_service::Account as AccountTrait;
+    use secrecy::SecretString;
+    use std::str::FromStr;
+
+    fn details(username: &str, token: &str) -> AccountDetails {
+        AccountDetails {
+            ilp_address: None,
+            username: Username::from_str(username).unwrap(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            max_packet_amount: u64::max_value(),
+            min_packet_amount: None,
+            min_balance: None,
+            ilp_over_http_url: Some(format!("http://{}.example/ilp", username)),
+            ilp_over_http_incoming_token: Some(SecretString::new(token.to_string())),
+            ilp_over_http_outgoing_token: None,
+            ilp_over_btp_url: None,
+            ilp_over_btp_outgoing_token: None,
+            ilp_over_btp_incoming_token: None,
+            settle_threshold: None,
+            settle_to: None,
+            settle_every: None,
+            routing_relation: Some("Peer".to_string()),
+            routing_preference: None,
+            round_trip_time: None,
+            amount_per_minute_limit: None,
+            max_payment_amount: None,
+            amount_per_day_limit: None,
+            packets_per_minute_limit: None,
+            settlement_engine_url: None,
+        }
+    }
+
+    fn store() -> MemoryStore {
+        let mut routes = HashMap::new();
+        routes.insert("g.bob".to_string(), Username::from_str("bob").unwrap());
+        MemoryStore::new(
+            Address::from_str("example.relay").unwrap(),
+            vec![details("alice", "alice_token"), details("bob", "bob_token")],
+            routes,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn routes_to_accounts_and_static_routes() {
+        let store = store();
+        let alice = store
+            .get_account_id_from_username(&Username::from_str("alice").unwrap())
+            .await
+            .unwrap();
+        let bob = store
+            .get_account_id_from_username(&Username::from_str("bob").unwrap())
+            .await
+            .unwrap();
+        let routes = store.routing_table();
+        assert_eq!(routes.get("example.relay.alice"), Some(&alice));
+        assert_eq!(routes.get("example.relay.bob"), Some(&bob));
+        assert_eq!(routes.get("g.bob"), Some(&bob));
+
+        let accounts = store.get_accounts(vec![bob, alice]).await.unwrap();
+        assert_eq!(accounts[0].username().as_ref(), "bob");
+        assert_eq!(accounts[1].username().as_ref(), "alice");
+        assert!(store.get_accounts(vec![Uuid::new_v4()]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn authenticates_http_requests() {
+        let store = store();
+        let alice = Username::from_str("alice").unwrap();
+        let account = store
+            .get_account_from_http_auth(&alice, "alice_token")
+            .await
+            .unwrap();
+        assert_eq!(account.username(), &alice);
+        assert!(store
+            .get_account_from_http_auth(&alice, "bob_token")
+            .await
+            .is_err());
+        assert!(store
+            .get_account_from_http_auth(&Username::from_str("carol").unwrap(), "alice_token")
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_routes_to_unknown_accounts() {
+        let mut routes = HashMap::new();
+        routes.insert("g.carol".to_string(), Username::from_str("carol").unwrap());
+        let result = MemoryStore::new(
+            Address::from_str("example.relay").unwrap(),
+            vec![details("alice", "alice_token")],
+            routes,
+        );
+        assert!(matches!(result, Err(NodeStoreError::AccountNotFound(_))));
+    }
+}
@@ -0,0 +1,15 @@
+local payments_key, unacknowledged_key, destination = ARGV[1], ARGV[2], ARGV[3]
+
+local function field(name)
+    return destination .. ':' .. name
+end
+
+-- Nothing was received on the connection
+if redis.call('HEXISTS', payments_key, field('amount')) == 0 then
+    return nil
+end
+
+redis.call('HSET', payments_key, field('acknowledged'), '1')
+redis.call('ZREM', unacknowledged_key, destination)
+
+return redis.call('HMGET', payments_key, field('amount'), field('closed'), field('acknowledged'), field('updated_at'))
@@ -0,0 +1,102 @@
+local accounts_key = ARGV[1]
+local from_id = ARGV[2]
+local from_account = accounts_key .. ':' .. from_id
+local from_amount = tonumber(ARGV[3])
+local log_key = ARGV[4]
+local timestamp = ARGV[5]
+local now = tonumber(timestamp)
+local packet_id = ARGV[6]
+local update_balance = ARGV[7] == '1'
+-- The limits are empty strings for accounts which do not have them
+local packets_key = ARGV[8]
+local packets_limit = tonumber(ARGV[9])
+local throughput_key = ARGV[10]
+local throughput_limit = tonumber(ARGV[11])
+local volume_key = ARGV[12]
+local volume_limit = tonumber(ARGV[13])
+local volume_ttl = tonumber(ARGV[14])
+
+-- Result codes, which the store turns into an AdmissionError
+local ADMITTED = 0
+local PACKET_LIMIT_EXCEEDED = 1
+local THROUGHPUT_LIMIT_EXCEEDED = 2
+local DAILY_LIMIT_EXCEEDED = 3
+local INSUFFICIENT_BALANCE = 4
+
+-- Appends an entry to the account's balance change log and adds the change
+-- to the account's total of the reason
+local function log_change(reason, delta, balance)
+    redis.call('RPUSH', log_key, table.concat({timestamp, reason, string.format('%d', delta), string.format('%d', balance), packet_id}, '|'))
+    redis.call('HINCRBY', log_key .. ':totals', reason, string.format('%d', delta))
+end
+
+-- Gives back the amount charged to the throughput limit if the packet is not admitted
+local function refund_throughput()
+    if throughput_limit then
+        take_tokens(throughput_key, throughput_limit, 0 - from_amount, now)
+    end
+end
+
+-- Every packet counts towards the packet limit, even the ones which are not admitted
+if packets_limit and not take_tokens(packets_key, packets_limit, 1, now) then
+    return PACKET_LIMIT_EXCEEDED
+end
+
+if from_amount == 0 then
+    return ADMITTED
+end
+
+if throughput_limit and not take_tokens(throughput_key, throughput_limit, from_amount, now) then
+    return THROUGHPUT_LIMIT_EXCEEDED
+end
+
+-- Everything else is checked before anything is written, so that there is nothing
+-- to undo when the packet is not admitted
+local volume = 0
+if volume_limit then
+    volume = tonumber(redis.call('GET', volume_key) or '0')
+    if volume + from_amount > volume_limit then
+        refund_throughput()
+        return DAILY_LIMIT_EXCEEDED
+    end
+end
+
+local balance, prepaid_amount
+if update_balance then
+    if redis.call('EXISTS', from_account) == 0 then
+        refund_throughput()
+        error('Account ' .. from_id .. ' does not exist')
+    end
+
+    local min_balance
+    min_balance, balance, prepaid_amount = unpack(redis.call('HMGET', from_account, 'min_balance', 'balance', 'prepaid_amount'))
+    balance = tonumber(balance)
+    prepaid_amount = tonumber(prepaid_amount)
+    if min_balance and balance + prepaid_amount - from_amount < tonumber(min_balance) then
+        refund_throughput()
+        return INSUFFICIENT_BALANCE
+    end
+end
+
+if volume_limit then
+    redis.call('INCRBY', volume_key, from_amount)
+    redis.call('EXPIRE', volume_key, volume_ttl)
+end
+
+if update_balance then
+    -- Deduct the from_amount from the prepaid_amount and/or the balance
+    if prepaid_amount >= from_amount then
+        prepaid_amount = redis.call('HINCRBY', from_account, 'prepaid_amount', 0 - from_amount)
+    elseif prepaid_amount > 0 then
+        local sub_from_balance = from_amount - prepaid_amount
+        prepaid_amount = 0
+        redis.call('HSET', from_account, 'prepaid_amount', 0)
+        balance = redis.call('HINCRBY', from_account, 'balance', 0 - sub_from_balance)
+    else
+        balance = redis.call('HINCRBY', from_account, 'balance', 0 - from_amount)
+    end
+
+    log_change('prepare', 0 - from_amount, balance + prepaid_amount)
+end
+
+return ADMITTED
@@ -0,0 +1,14 @@
+local volume_key = KEYS[1]
+local amount = tonumber(ARGV[1])
+local limit = tonumber(ARGV[2])
+local ttl = tonumber(ARGV[3])
+
+-- The packet is rejected without changing the total if it would exceed the limit
+local volume = tonumber(redis.call('GET', volume_key) or '0')
+if volume + amount > limit then
+    return 0
+end
+
+redis.call('INCRBY', volume_key, amount)
+redis.call('EXPIRE', volume_key, ttl)
+return 1
@@ -0,0 +1,11 @@
+local leader_key = ARGV[1]
+local node_id = ARGV[2]
+local lease = ARGV[3]
+
+local leader = redis.call('GET', leader_key)
+if leader and leader ~= node_id then
+    return 0
+end
+
+redis.call('SET', leader_key, node_id, 'PX', lease)
+return 1
@@ -0,0 +1,34 @@
+local accounts_set_key, account_key, usernames_key = ARGV[1], ARGV[2], ARGV[3]
+local send_routes_key, receive_routes_key, btp_outgoing_key = ARGV[4], ARGV[5], ARGV[6]
+local routes_key, static_routes_key, default_route_key = ARGV[7], ARGV[8], ARGV[9]
+local parent_key, uncredited_amount_key = ARGV[10], ARGV[11]
+local packets_limit_key, throughput_limit_key = ARGV[12], ARGV[13]
+local account_id, username, is_last_parent = ARGV[14], ARGV[15], ARGV[16]
+local payments_key, unacknowledged_payments_key = ARGV[17], ARGV[18]
+
+-- Remove the account itself and everything which is keyed by its id
+redis.call('SREM', accounts_set_key, account_id)
+redis.call('DEL', account_key, uncredited_amount_key, packets_limit_key, throughput_limit_key)
+redis.call('DEL', payments_key, unacknowledged_payments_key)
+redis.call('HDEL', usernames_key, username)
+redis.call('SREM', send_routes_key, account_id)
+redis.call('SREM', receive_routes_key, account_id)
+redis.call('SREM', btp_outgoing_key, account_id)
+
+-- Remove all routes which point to the account, including the ones learned via CCP
+for _, table_key in ipairs({routes_key, static_routes_key}) do
+    local routes = redis.call('HGETALL', table_key)
+    for i = 1, #routes, 2 do
+        if routes[i + 1] == account_id then
+            redis.call('HDEL', table_key, routes[i])
+        end
+    end
+end
+if redis.call('GET', default_route_key) == account_id then
+    redis.call('DEL', default_route_key)
+end
+
+-- Forget the address assigned by the parents once the last one is gone
+if is_last_parent == '1' then
+    redis.call('DEL', parent_key)
+end
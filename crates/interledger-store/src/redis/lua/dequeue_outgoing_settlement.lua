@@ -0,0 +1,26 @@
+local settlements_key = ARGV[1]
+local in_flight_key = ARGV[2]
+local queued_key = ARGV[3]
+local account_id = ARGV[4]
+local idempotency_key = ARGV[5]
+local expected_amount = ARGV[6]
+local settlement = ARGV[7]
+
+if redis.call('HEXISTS', in_flight_key, account_id) == 1 then
+    return 0
+end
+
+local queued_amount = redis.call('HGET', queued_key, account_id)
+if not queued_amount then
+    return 0
+end
+-- More was queued since the caller read the amount, so the settlement
+-- must be created again with the new amount
+if queued_amount ~= expected_amount then
+    return -1
+end
+
+redis.call('HDEL', queued_key, account_id)
+redis.call('HSET', in_flight_key, account_id, idempotency_key)
+redis.call('HSET', settlements_key, idempotency_key, settlement)
+return 1
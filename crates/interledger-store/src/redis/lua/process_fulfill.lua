@@ -1,9 +1,29 @@
 local accounts_key = ARGV[1]
 local to_account = accounts_key .. ':' .. ARGV[2]
 local to_amount = tonumber(ARGV[3])
+local log_key = ARGV[4]
+local timestamp = ARGV[5]
+local packet_id = ARGV[6]
+-- Empty if the caller sends the settlement itself
+local queued_key = ARGV[7]
+
+-- Appends an entry to the account's balance change log and adds the change
+-- to the account's total of the reason
+local function log_change(reason, delta, balance)
+    redis.call('RPUSH', log_key, table.concat({timestamp, reason, string.format('%d', delta), string.format('%d', balance), packet_id}, '|'))
+    redis.call('HINCRBY', log_key .. ':totals', reason, string.format('%d', delta))
+end
+
+if redis.call('EXISTS', to_account) == 0 then
+    error('Account ' .. ARGV[2] .. ' does not exist')
+end
 
 local balance = redis.call('HINCRBY', to_account, 'balance', to_amount)
 local prepaid_amount, settle_threshold, settle_to = unpack(redis.call('HMGET', to_account, 'prepaid_amount', 'settle_threshold', 'settle_to'))
+prepaid_amount = tonumber(prepaid_amount)
+if to_amount > 0 then
+    log_change('fulfill', to_amount, balance + prepaid_amount)
+end
 
 -- The logic for trigerring settlement is as follows:
 --  1. settle_threshold must be non-nil (if it's nil, then settlement was perhaps disabled on the account).
@@ -16,8 +36,14 @@ if (settle_threshold and settle_to) and (balance >= tonumber(settle_threshold))
     -- Update the balance _before_ sending the settlement so that we don't accidentally send
     -- multiple settlements for the same balance. If the settlement fails we'll roll back
     -- the balance change by re-adding the amount back to the balance
-    balance = settle_to
+    balance = tonumber(settle_to)
     redis.call('HSET', to_account, 'balance', balance)
+    log_change('settlement', 0 - settle_amount, balance + prepaid_amount)
+    -- Queued along with the balance change, so that the amount is settled
+    -- even if the node stops before sending the settlement
+    if queued_key ~= '' then
+        redis.call('HINCRBY', queued_key, ARGV[2], settle_amount)
+    end
 end
 
 return {balance + prepaid_amount, settle_amount}
@@ -2,6 +2,19 @@ local accounts_key = ARGV[1]
 local account = accounts_key .. ':' .. ARGV[2]
 local amount = tonumber(ARGV[3])
 local idempotency_key = ARGV[4]
+local log_key = ARGV[5]
+local timestamp = ARGV[6]
+
+-- Appends an entry to the account's balance change log and adds the change
+-- to the account's total of the reason
+local function log_change(reason, delta, balance)
+    redis.call('RPUSH', log_key, table.concat({timestamp, reason, string.format('%d', delta), string.format('%d', balance), ''}, '|'))
+    redis.call('HINCRBY', log_key .. ':totals', reason, string.format('%d', delta))
+end
+
+if redis.call('EXISTS', account) == 0 then
+    error('Account ' .. ARGV[2] .. ' does not exist')
+end
 
 local balance, prepaid_amount = unpack(redis.call('HMGET', account, 'balance', 'prepaid_amount'))
 
@@ -24,5 +37,6 @@ else
     balance = 0
     redis.call('HSET', account, 'balance', 0)
 end
+log_change('incoming_settlement', amount, balance + prepaid_amount)
 
 return balance + prepaid_amount
\ No newline at end of file
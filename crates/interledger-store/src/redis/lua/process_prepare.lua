@@ -2,6 +2,21 @@ local accounts_key = ARGV[1]
 local from_id = ARGV[2]
 local from_account = accounts_key .. ':' .. from_id
 local from_amount = tonumber(ARGV[3])
+local log_key = ARGV[4]
+local timestamp = ARGV[5]
+local packet_id = ARGV[6]
+
+-- Appends an entry to the account's balance change log and adds the change
+-- to the account's total of the reason
+local function log_change(reason, delta, balance)
+    redis.call('RPUSH', log_key, table.concat({timestamp, reason, string.format('%d', delta), string.format('%d', balance), packet_id}, '|'))
+    redis.call('HINCRBY', log_key .. ':totals', reason, string.format('%d', delta))
+end
+
+if redis.call('EXISTS', from_account) == 0 then
+    error('Account ' .. ARGV[2] .. ' does not exist')
+end
+
 local min_balance, balance, prepaid_amount = unpack(redis.call('HMGET', from_account, 'min_balance', 'balance', 'prepaid_amount'))
 balance = tonumber(balance)
 prepaid_amount = tonumber(prepaid_amount)
@@ -26,4 +41,6 @@ else
     balance = redis.call('HINCRBY', from_account, 'balance', 0 - from_amount)
 end
 
+log_change('prepare', 0 - from_amount, balance + prepaid_amount)
+
 return balance + prepaid_amount
\ No newline at end of file
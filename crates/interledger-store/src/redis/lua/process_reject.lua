@@ -1,7 +1,22 @@
 local accounts_key = ARGV[1]
 local from_account = accounts_key .. ':' .. ARGV[2]
 local from_amount = tonumber(ARGV[3])
+local log_key = ARGV[4]
+local timestamp = ARGV[5]
+local packet_id = ARGV[6]
 
-local prepaid_amount = redis.call('HGET', from_account, 'prepaid_amount')
+-- Appends an entry to the account's balance change log and adds the change
+-- to the account's total of the reason
+local function log_change(reason, delta, balance)
+    redis.call('RPUSH', log_key, table.concat({timestamp, reason, string.format('%d', delta), string.format('%d', balance), packet_id}, '|'))
+    redis.call('HINCRBY', log_key .. ':totals', reason, string.format('%d', delta))
+end
+
+if redis.call('EXISTS', from_account) == 0 then
+    error('Account ' .. ARGV[2] .. ' does not exist')
+end
+
+local prepaid_amount = tonumber(redis.call('HGET', from_account, 'prepaid_amount'))
 local balance = redis.call('HINCRBY', from_account, 'balance', from_amount)
+log_change('reject', from_amount, balance + prepaid_amount)
 return balance + prepaid_amount
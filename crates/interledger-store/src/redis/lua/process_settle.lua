@@ -1,16 +1,36 @@
 -- almost the same as process_fulfill.lua which is used to settle when balance is over the settlement threshold:
 -- returns similarly the balance and the amount to settle, but is called with only the account id as the only argument.
 --
--- upon completion the `balance` is at the level of `settle_to`
+-- upon completion the `balance` is at the level of `settle_to` and the amount to settle
+-- is added to the account's queued outgoing settlement
 local accounts_key = ARGV[1]
 local to_account = accounts_key .. ':' .. ARGV[2]
+local log_key = ARGV[3]
+local timestamp = ARGV[4]
+local queued_key = ARGV[5]
+
+-- Appends an entry to the account's balance change log and adds the change
+-- to the account's total of the reason
+local function log_change(reason, delta, balance)
+    redis.call('RPUSH', log_key, table.concat({timestamp, reason, string.format('%d', delta), string.format('%d', balance), ''}, '|'))
+    redis.call('HINCRBY', log_key .. ':totals', reason, string.format('%d', delta))
+end
+
+if redis.call('EXISTS', to_account) == 0 then
+    error('Account ' .. ARGV[2] .. ' does not exist')
+end
+
 local balance, prepaid_amount, settle_threshold, settle_to = unpack(redis.call('HMGET', to_account, 'balance', 'prepaid_amount', 'settle_threshold', 'settle_to'))
 local settle_amount = 0
 
 if (settle_threshold and settle_to) and (tonumber(settle_threshold) > tonumber(settle_to)) and tonumber(balance) >= tonumber(settle_to) then
     settle_amount = tonumber(balance) - tonumber(settle_to)
-    balance = settle_to
+    balance = tonumber(settle_to)
     redis.call('HSET', to_account, 'balance', balance)
+    log_change('settlement', 0 - settle_amount, balance + tonumber(prepaid_amount))
+    if settle_amount > 0 then
+        redis.call('HINCRBY', queued_key, ARGV[2], settle_amount)
+    end
 end
 
 return {balance + prepaid_amount, settle_amount}
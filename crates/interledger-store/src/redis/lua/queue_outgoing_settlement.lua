@@ -0,0 +1,18 @@
+local settlements_key = ARGV[1]
+local in_flight_key = ARGV[2]
+local queued_key = ARGV[3]
+local account_id = ARGV[4]
+local idempotency_key = ARGV[5]
+local amount = ARGV[6]
+local settlement = ARGV[7]
+
+-- The settlements of an account are sent one at a time, so the amount
+-- waits until the engine answered the settlement in flight
+if redis.call('HEXISTS', in_flight_key, account_id) == 1 then
+    redis.call('HINCRBY', queued_key, account_id, amount)
+    return 0
+end
+
+redis.call('HSET', in_flight_key, account_id, idempotency_key)
+redis.call('HSET', settlements_key, idempotency_key, settlement)
+return 1
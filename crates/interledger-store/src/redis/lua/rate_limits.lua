@@ -0,0 +1,23 @@
+-- The limits are empty strings for accounts which do not have them
+local packets_key = ARGV[1]
+local packets_limit = tonumber(ARGV[2])
+local throughput_key = ARGV[3]
+local throughput_limit = tonumber(ARGV[4])
+local amount = tonumber(ARGV[5])
+local now = tonumber(ARGV[6])
+
+-- Result codes, which the store turns into a RateLimitError
+local ADMITTED = 0
+local PACKET_LIMIT_EXCEEDED = 1
+local THROUGHPUT_LIMIT_EXCEEDED = 2
+
+-- Every packet counts towards the packet limit, even the ones which exceed the throughput limit
+if packets_limit and not take_tokens(packets_key, packets_limit, 1, now) then
+    return PACKET_LIMIT_EXCEEDED
+end
+
+if throughput_limit and not take_tokens(throughput_key, throughput_limit, amount, now) then
+    return THROUGHPUT_LIMIT_EXCEEDED
+end
+
+return ADMITTED
@@ -0,0 +1,24 @@
+local payments_key, unacknowledged_key = ARGV[1], ARGV[2]
+local destination, amount, closed, timestamp = ARGV[3], ARGV[4], ARGV[5], ARGV[6]
+
+-- The fields of every payment of the account are prefixed with the address of its
+-- connection, which cannot contain a ':'
+local function field(name)
+    return destination .. ':' .. name
+end
+
+redis.call('HINCRBY', payments_key, field('amount'), amount)
+redis.call('HSET', payments_key, field('updated_at'), timestamp)
+if closed == '1' then
+    redis.call('HSET', payments_key, field('closed'), '1')
+else
+    redis.call('HSETNX', payments_key, field('closed'), '0')
+end
+
+-- Receiving money clears the acknowledgement of the payment
+if tonumber(amount) > 0 or redis.call('HEXISTS', payments_key, field('acknowledged')) == 0 then
+    redis.call('HSET', payments_key, field('acknowledged'), '0')
+    redis.call('ZADD', unacknowledged_key, timestamp, destination)
+end
+
+return redis.call('HMGET', payments_key, field('amount'), field('closed'), field('acknowledged'), field('updated_at'))
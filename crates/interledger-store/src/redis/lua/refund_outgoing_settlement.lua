@@ -0,0 +1,33 @@
+local accounts_key = ARGV[1]
+local account = accounts_key .. ':' .. ARGV[2]
+local settle_amount = tonumber(ARGV[3])
+local log_key = ARGV[4]
+local timestamp = ARGV[5]
+local settlements_key = ARGV[6]
+local idempotency_key = ARGV[7]
+local in_flight_key = ARGV[8]
+
+-- Appends an entry to the account's balance change log and adds the change
+-- to the account's total of the reason
+local function log_change(reason, delta, balance)
+    redis.call('RPUSH', log_key, table.concat({timestamp, reason, string.format('%d', delta), string.format('%d', balance), ''}, '|'))
+    redis.call('HINCRBY', log_key .. ':totals', reason, string.format('%d', delta))
+end
+
+-- The settlement was already refunded (or accepted), so it must not be refunded again
+if redis.call('HDEL', settlements_key, idempotency_key) == 0 then
+    return 0
+end
+if redis.call('HGET', in_flight_key, ARGV[2]) == idempotency_key then
+    redis.call('HDEL', in_flight_key, ARGV[2])
+end
+
+-- There is nothing to refund if the account was deleted concurrently
+if redis.call('EXISTS', account) == 0 then
+    return 0
+end
+
+local balance = redis.call('HINCRBY', account, 'balance', settle_amount)
+local prepaid_amount = tonumber(redis.call('HGET', account, 'prepaid_amount'))
+log_change('settlement_refund', settle_amount, balance + prepaid_amount)
+return 1
@@ -0,0 +1,40 @@
+local accounts_key = ARGV[1]
+local from_id = ARGV[2]
+local from_account = accounts_key .. ':' .. from_id
+local from_amount = tonumber(ARGV[3])
+local log_key = ARGV[4]
+local timestamp = ARGV[5]
+local now = tonumber(timestamp)
+local packet_id = ARGV[6]
+local update_balance = ARGV[7] == '1'
+-- The limits are empty strings for accounts which do not have them
+local throughput_key = ARGV[8]
+local throughput_limit = tonumber(ARGV[9])
+local volume_key = ARGV[10]
+
+-- Appends an entry to the account's balance change log and adds the change
+-- to the account's total of the reason
+local function log_change(reason, delta, balance)
+    redis.call('RPUSH', log_key, table.concat({timestamp, reason, string.format('%d', delta), string.format('%d', balance), packet_id}, '|'))
+    redis.call('HINCRBY', log_key .. ':totals', reason, string.format('%d', delta))
+end
+
+if update_balance then
+    if redis.call('EXISTS', from_account) == 0 then
+        error('Account ' .. from_id .. ' does not exist')
+    end
+
+    local prepaid_amount = tonumber(redis.call('HGET', from_account, 'prepaid_amount'))
+    local balance = redis.call('HINCRBY', from_account, 'balance', from_amount)
+    log_change('reject', from_amount, balance + prepaid_amount)
+end
+
+if volume_key ~= '' then
+    redis.call('DECRBY', volume_key, from_amount)
+end
+
+if throughput_limit then
+    take_tokens(throughput_key, throughput_limit, 0 - from_amount, now)
+end
+
+return 0
@@ -1,6 +1,21 @@
 local accounts_key = ARGV[1]
 local account = accounts_key .. ':' .. ARGV[2]
 local settle_amount = tonumber(ARGV[3])
+local log_key = ARGV[4]
+local timestamp = ARGV[5]
+
+-- Appends an entry to the account's balance change log and adds the change
+-- to the account's total of the reason
+local function log_change(reason, delta, balance)
+    redis.call('RPUSH', log_key, table.concat({timestamp, reason, string.format('%d', delta), string.format('%d', balance), ''}, '|'))
+    redis.call('HINCRBY', log_key .. ':totals', reason, string.format('%d', delta))
+end
+
+if redis.call('EXISTS', account) == 0 then
+    error('Account ' .. ARGV[2] .. ' does not exist')
+end
 
 local balance = redis.call('HINCRBY', account, 'balance', settle_amount)
+local prepaid_amount = tonumber(redis.call('HGET', account, 'prepaid_amount'))
+log_change('settlement_refund', settle_amount, balance + prepaid_amount)
 return balance
\ No newline at end of file
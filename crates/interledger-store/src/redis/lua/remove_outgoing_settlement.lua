@@ -0,0 +1,15 @@
+local settlements_key = ARGV[1]
+local in_flight_key = ARGV[2]
+local idempotency_key = ARGV[3]
+
+local settlement = redis.call('HGET', settlements_key, idempotency_key)
+if not settlement then
+    return 0
+end
+
+redis.call('HDEL', settlements_key, idempotency_key)
+local account_id = cjson.decode(settlement)['account_id']
+if redis.call('HGET', in_flight_key, account_id) == idempotency_key then
+    redis.call('HDEL', in_flight_key, account_id)
+end
+return 1
@@ -0,0 +1,27 @@
+-- Takes `amount` tokens from the bucket stored at `key`, which holds up to `capacity`
+-- tokens and is refilled with `capacity` tokens per minute, unless the bucket holds
+-- fewer tokens than that. Negative amounts give tokens back. Returns true if the tokens
+-- were taken. `now` is the time in milliseconds; the bucket's time never goes backwards,
+-- so the clocks of the nodes sharing the store do not need to be exactly in sync.
+local function take_tokens(key, capacity, amount, now)
+    local bucket = redis.call('HMGET', key, 'tokens', 'updated_at')
+    local tokens = tonumber(bucket[1])
+    local updated_at = tonumber(bucket[2])
+    if not tokens then
+        tokens = capacity
+        updated_at = now
+    elseif now > updated_at then
+        tokens = math.min(capacity, tokens + (now - updated_at) * capacity / 60000)
+        updated_at = now
+    end
+    if amount > tokens then
+        return false
+    end
+
+    tokens = math.min(capacity, tokens - amount)
+    redis.call('HMSET', key, 'tokens', string.format('%.17g', tokens), 'updated_at', string.format('%d', updated_at))
+    -- A bucket which was not used for a minute is full, which is the same as not having one
+    redis.call('PEXPIRE', key, 60000)
+    return true
+end
+
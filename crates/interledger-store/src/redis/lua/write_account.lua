@@ -0,0 +1,58 @@
+local accounts_set_key, account_key, usernames_key = ARGV[1], ARGV[2], ARGV[3]
+local send_routes_key, receive_routes_key, btp_outgoing_key = ARGV[4], ARGV[5], ARGV[6]
+local routes_key, account_id, username, ilp_address = ARGV[7], ARGV[8], ARGV[9], ARGV[10]
+local is_insert, send_routes, receive_routes, btp_outgoing = ARGV[11], ARGV[12], ARGV[13], ARGV[14]
+local unset_fields_count = tonumber(ARGV[15])
+
+-- Everything is checked and written at once, so that two writes can't both claim
+-- the same username or clean up after each other
+local exists = redis.call('EXISTS', account_key) == 1
+if is_insert == '1' and exists then
+    return 'EXISTS'
+elseif is_insert ~= '1' and not exists then
+    return 'NOT_FOUND'
+end
+local owner = redis.call('HGET', usernames_key, username)
+if owner and owner ~= account_id then
+    return 'EXISTS'
+end
+
+if is_insert == '1' then
+    redis.call('HMSET', account_key, 'balance', 0, 'prepaid_amount', 0)
+else
+    -- Remove the username and route of the previous details, unless they were
+    -- taken by another account since
+    local old_username = redis.call('HGET', account_key, 'username')
+    if old_username and old_username ~= username
+        and redis.call('HGET', usernames_key, old_username) == account_id then
+        redis.call('HDEL', usernames_key, old_username)
+    end
+    local old_address = redis.call('HGET', account_key, 'ilp_address')
+    if old_address and old_address ~= ilp_address
+        and redis.call('HGET', routes_key, old_address) == account_id then
+        redis.call('HDEL', routes_key, old_address)
+    end
+    redis.call('SREM', send_routes_key, account_id)
+    redis.call('SREM', receive_routes_key, account_id)
+    redis.call('SREM', btp_outgoing_key, account_id)
+    -- HMSET only writes the fields which are set, so the optional fields
+    -- which were unset by the update are removed first
+    if unset_fields_count > 0 then
+        redis.call('HDEL', account_key, unpack(ARGV, 16, 15 + unset_fields_count))
+    end
+end
+
+redis.call('SADD', accounts_set_key, account_id)
+redis.call('HSET', usernames_key, username, account_id)
+redis.call('HMSET', account_key, unpack(ARGV, 16 + unset_fields_count))
+if send_routes == '1' then
+    redis.call('SADD', send_routes_key, account_id)
+end
+if receive_routes == '1' then
+    redis.call('SADD', receive_routes_key, account_id)
+end
+if btp_outgoing == '1' then
+    redis.call('SADD', btp_outgoing_key, account_id)
+end
+redis.call('HSET', routes_key, ilp_address, account_id)
+return 'OK'
@@ -9,6 +9,7 @@
 //   accounts               set
 //   usernames              hash
 //   btp_outgoing
+//   incoming_payments:<id> hash     money received on each STREAM connection of the account
 // For interactive exploration of the store,
 // use the redis-cli tool included with your redis install.
 // Within redis-cli:
@@ -17,15 +18,21 @@
 //    get <key>             get the value of a key
 //    hgetall <key>         the flattened list of every key/value entry within a hash
 mod reconnect;
-use reconnect::RedisReconnect;
+use reconnect::{PoolConfig, RedisReconnect};
 
 use super::account::{Account, AccountWithEncryptedTokens};
-use super::crypto::{encrypt_token, generate_keys, DecryptionKey, EncryptionKey};
+use super::cache::AccountCache;
+use super::crypto::{encrypt_token, generate_keys, hash_token, DecryptionKey, EncryptionKey};
+use super::instrumentation::OperationTimers;
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
 use futures::channel::mpsc::UnboundedSender;
 use http::StatusCode;
-use interledger_api::{AccountDetails, AccountSettings, EncryptedAccountSettings, NodeStore};
+use interledger_api::{
+    AccountDetails, AccountFilter, AccountPage, AccountSettings, EncryptedAccountSettings,
+    ExportedAccount, NodeExport, NodeStore,
+};
+use interledger_auth::{AuthError, AuthProtocol, VerifiedTokens};
 use interledger_btp::BtpStore;
 use interledger_ccp::{CcpRoutingAccount, CcpRoutingStore, RoutingRelation};
 use interledger_errors::*;
@@ -33,28 +40,47 @@ use interledger_http::HttpStore;
 use interledger_packet::Address;
 use interledger_rates::ExchangeRateStore;
 use interledger_router::RouterStore;
-use interledger_service::{Account as AccountTrait, AccountStore, AddressStore, Username};
+use interledger_service::{runtime, Account as AccountTrait, AccountStore, AddressStore, Username};
 use interledger_service_util::{
-    BalanceStore, RateLimitError, RateLimitStore, DEFAULT_ROUND_TRIP_TIME,
+    AdmissionError, AdmissionStore, BalanceChange, BalanceChangeReason, BalanceStore,
+    BalanceTotals, JournaledPacket, PacketDirection, PacketHistoryFilter, PacketHistoryStore,
+    PacketId, PacketJournalStore, PacketOutcome, PacketRecord, RateLimitError, RateLimitStore,
+    VolumeLimitError, VolumeLimitStore, DEFAULT_ROUND_TRIP_TIME,
 };
 use interledger_settlement::core::{
     idempotency::{IdempotentData, IdempotentStore},
     scale_with_precision_loss,
-    types::{Convert, ConvertDetails, LeftoversStore, SettlementStore},
+    types::{
+        Convert, ConvertDetails, LeftoversStore, OutgoingSettlement, OutgoingSettlementStatus,
+        OutgoingSettlementStore, SettlementStore,
+    },
+};
+use interledger_stream::{
+    IncomingPayment, IncomingPaymentsStore, PaymentNotification, SecretGeneration,
+    StreamNotificationsStore,
 };
-use interledger_stream::{PaymentNotification, StreamNotificationsStore};
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
 use parking_lot::{Mutex, RwLock};
 use redis_crate::{
-    self, cmd, from_redis_value, Client, ConnectionInfo, ControlFlow, ErrorKind, FromRedisValue,
+    self, from_redis_value, Client, ConnectionInfo, ControlFlow, ErrorKind, FromRedisValue,
     PubSubCommands, RedisError, RedisWrite, ToRedisArgs, Value,
 };
 use redis_crate::{AsyncCommands, Script};
+use ring::aead;
 use secrecy::{ExposeSecret, Secret, SecretBytesMut};
 use serde::{Deserialize, Serialize};
-use std::{borrow::Cow, str, str::FromStr, sync::Arc, time::Duration};
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    borrow::Cow,
+    str,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 use tokio::sync::broadcast;
 use tracing::{debug, error, trace, warn};
 use url::Url;
@@ -62,8 +88,9 @@ use uuid::Uuid;
 use zeroize::Zeroize;
 
 const DEFAULT_POLL_INTERVAL: u64 = 30000; // 30 seconds
-const ACCOUNT_DETAILS_FIELDS: usize = 21;
+const ACCOUNT_DETAILS_FIELDS: usize = 25;
 const DEFAULT_DB_PREFIX: &str = "";
+const DEFAULT_ACCOUNT_CACHE_TTL: u64 = 1000; // 1 second
 
 static PARENT_ILP_KEY: &str = "parent_node_account_address";
 static ROUTES_KEY: &str = "routes:current";
@@ -76,6 +103,25 @@ static ACCOUNTS_KEY: &str = "accounts";
 static SEND_ROUTES_KEY: &str = "send_routes_to";
 static RECEIVE_ROUTES_FROM_KEY: &str = "receive_routes_from";
 static BPT_OUTGOING: &str = "btp_outgoing";
+static OUTGOING_SETTLEMENTS_KEY: &str = "outgoing_settlements";
+static IN_FLIGHT_SETTLEMENTS_KEY: &str = "outgoing_settlements:in_flight";
+static QUEUED_SETTLEMENTS_KEY: &str = "outgoing_settlements:queued";
+/// Generation of the STREAM server secret, as JSON
+static SECRET_GENERATION_KEY: &str = "secret_generation";
+/// Counter from which the ids of the packet history records are taken
+static PACKET_HISTORY_ID_KEY: &str = "packet_history_id";
+/// Set of the accounts which have a packet history, so that it can be pruned
+static PACKET_HISTORY_ACCOUNTS_KEY: &str = "packet_history_accounts";
+/// Hash of the journaled packets, as JSON, by the id of their entry
+static PACKET_JOURNAL_KEY: &str = "packet_journal";
+/// Set of the ids of the journaled packets which were fulfilled
+static FULFILLED_PACKETS_KEY: &str = "packet_journal:fulfilled";
+/// Id of the node which holds the leadership of the nodes sharing the store, which
+/// expires with its lease
+static LEADER_KEY: &str = "leader";
+/// Number of packet history records which are read at a time when the history is
+/// filtered or pruned
+const PACKET_HISTORY_BATCH_SIZE: usize = 100;
 
 /// Domain separator for leftover amounts
 fn uncredited_amount_key(prefix: &str, account_id: impl ToString) -> String {
@@ -95,6 +141,54 @@ fn prefixed_idempotency_key(prefix: &str, idempotency_key: &str) -> String {
     .into_owned()
 }
 
+/// First segments of the keys used by a node without a db prefix. A prefix must not be
+/// one of these, otherwise its keys could be equal to the keys of such a node.
+/// The tests check that every key of this module is covered.
+static RESERVED_DB_PREFIXES: &[&str] = &[
+    "accounts",
+    "balance_log",
+    "btp_outgoing",
+    "idempotency-key",
+    "incoming_payments",
+    "leader",
+    "limit",
+    "outgoing_settlements",
+    "packet_history",
+    "packet_history_accounts",
+    "packet_history_id",
+    "packet_journal",
+    "parent_node_account_address",
+    "receive_routes_from",
+    "routes",
+    "secret_generation",
+    "send_routes_to",
+    "settlement_engines",
+    "stream_notifications",
+    "uncredited-amount",
+    "usernames",
+];
+
+/// Checks that the keys of the given prefix can never be equal to the keys of another
+/// prefix (or of a node without a prefix). Since prefixed keys are `prefix:key`, this
+/// holds as long as the prefix contains no `:` and does not clash with the first
+/// segment of an unprefixed key. Restricting the characters also keeps the prefix
+/// from being interpreted as a pattern when subscribing to notifications.
+fn validate_db_prefix(prefix: &str) -> Result<(), &'static str> {
+    if prefix.is_empty() {
+        return Ok(());
+    }
+    if !prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("it may only contain ASCII letters, digits, '_' and '-'");
+    }
+    if RESERVED_DB_PREFIXES.contains(&prefix) {
+        return Err("it is reserved for keys of nodes without a prefix");
+    }
+    Ok(())
+}
+
 fn prefixed_key<'a>(prefix: &str, key: &'a str) -> Cow<'a, str> {
     if prefix.is_empty() {
         Cow::Borrowed(key)
@@ -108,6 +202,109 @@ fn accounts_key(prefix: &str, account_id: Uuid) -> String {
     prefixed_key(prefix, &format!("accounts:{}", account_id)).into_owned()
 }
 
+/// Key of the hash holding the amount, state and update time of the payments received on
+/// each STREAM connection of an account, in fields prefixed with the connection's address
+fn incoming_payments_key(prefix: &str, account_id: Uuid) -> String {
+    prefixed_key(prefix, &format!("incoming_payments:{}", account_id)).into_owned()
+}
+
+/// Key of the sorted set of the connections whose payment was not acknowledged, by the
+/// time of their last update
+fn unacknowledged_payments_key(prefix: &str, account_id: Uuid) -> String {
+    prefixed_key(
+        prefix,
+        &format!("incoming_payments:{}:unacknowledged", account_id),
+    )
+    .into_owned()
+}
+
+/// Key of the list holding an account's balance change log. The log is kept
+/// when the account is deleted, so that its history can still be audited.
+fn balance_log_key(prefix: &str, account_id: Uuid) -> String {
+    prefixed_key(prefix, &format!("balance_log:{}", account_id)).into_owned()
+}
+
+/// Key of the hash holding the sums of an account's balance changes per reason.
+/// The Lua scripts derive it from the key of the balance change log.
+fn balance_totals_key(prefix: &str, account_id: Uuid) -> String {
+    format!("{}:totals", balance_log_key(prefix, account_id))
+}
+
+/// Key of the sorted set holding an account's packet history, scored by the ids of
+/// the records
+fn packet_history_key(prefix: &str, account_id: Uuid) -> String {
+    prefixed_key(prefix, &format!("packet_history:{}", account_id)).into_owned()
+}
+
+/// Formats a record of the packet history as `id|timestamp|direction|outcome|amount|reject_code|destination`
+/// (the reject code may be empty). The id keeps the entries of equal packets apart.
+fn format_packet_record(record: &PacketRecord) -> String {
+    format!(
+        "{}|{}|{}|{}|{}|{}|{}",
+        record.id,
+        record.timestamp,
+        record.direction,
+        record.outcome,
+        record.amount,
+        record.reject_code.as_deref().unwrap_or_default(),
+        record.destination
+    )
+}
+
+fn parse_packet_record(account_id: Uuid, entry: &str) -> Option<PacketRecord> {
+    let mut fields = entry.splitn(7, '|');
+    let id = fields.next()?.parse().ok()?;
+    let timestamp = fields.next()?.parse().ok()?;
+    let direction = PacketDirection::from_str(fields.next()?).ok()?;
+    let outcome = PacketOutcome::from_str(fields.next()?).ok()?;
+    let amount = fields.next()?.parse().ok()?;
+    let reject_code = Some(fields.next()?)
+        .filter(|reject_code| !reject_code.is_empty())
+        .map(str::to_string);
+    let destination = fields.next()?.to_string();
+    Some(PacketRecord {
+        id,
+        timestamp,
+        account_id,
+        direction,
+        outcome,
+        amount,
+        destination,
+        reject_code,
+    })
+}
+
+/// Milliseconds since the UNIX epoch, used to timestamp balance changes
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Parses an entry of the balance change log, which the Lua scripts write as
+/// `timestamp|reason|delta|balance|packet_id` (the packet id may be empty)
+fn parse_balance_change(account_id: Uuid, sequence: u64, entry: &str) -> Option<BalanceChange> {
+    let mut fields = entry.split('|');
+    let timestamp = fields.next()?.parse().ok()?;
+    let reason = BalanceChangeReason::from_str(fields.next()?).ok()?;
+    let delta = fields.next()?.parse().ok()?;
+    let balance = fields.next()?.parse().ok()?;
+    let packet_id = fields
+        .next()
+        .filter(|packet_id| !packet_id.is_empty())
+        .map(str::to_string);
+    Some(BalanceChange {
+        sequence,
+        timestamp,
+        account_id,
+        packet_id,
+        delta,
+        balance,
+        reason,
+    })
+}
+
 // TODO: Add descriptive errors inside the lua scripts!
 
 // The following are Lua scripts that are used to atomically execute the given logic
@@ -131,6 +328,10 @@ static ACCOUNT_FROM_USERNAME: Lazy<Script> =
 static LOAD_ACCOUNTS: Lazy<Script> =
     Lazy::new(|| Script::new(include_str!("lua/load_accounts.lua")));
 
+// The scripts which change the balance of an account first check that its hash exists,
+// since the account may have been deleted concurrently and HINCRBY would recreate a
+// partial account hash.
+
 /// Lua script which reduces the provided account's balance before sending a Prepare packet
 static PROCESS_PREPARE: Lazy<Script> =
     Lazy::new(|| Script::new(include_str!("lua/process_prepare.lua")));
@@ -150,18 +351,94 @@ static PROCESS_DELAYED_SETTLEMENT: Lazy<Script> =
 static REFUND_SETTLEMENT: Lazy<Script> =
     Lazy::new(|| Script::new(include_str!("lua/refund_settlement.lua")));
 
+/// Lua script which removes an outgoing settlement which the engine rejected and
+/// increases the account's balance by its amount
+static REFUND_OUTGOING_SETTLEMENT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/refund_outgoing_settlement.lua")));
+
+/// Lua script which makes an outgoing settlement the account's settlement in flight,
+/// or adds its amount to the account's queued amount if another one is in flight
+static QUEUE_OUTGOING_SETTLEMENT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/queue_outgoing_settlement.lua")));
+
+/// Lua script which turns the account's queued amount into its settlement in flight
+static DEQUEUE_OUTGOING_SETTLEMENT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/dequeue_outgoing_settlement.lua")));
+
+/// Lua script which adds the amount of a packet to the account's total of the day,
+/// unless that would exceed its daily volume limit
+static CHARGE_DAILY_VOLUME: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/charge_daily_volume.lua")));
+
+/// Lua script which applies the rate limits, the daily volume limit and the balance
+/// change of a Prepare packet, unless any of them does not admit it
+static ADMIT_PREPARE: Lazy<Script> = Lazy::new(|| {
+    Script::new(concat!(
+        include_str!("lua/token_bucket.lua"),
+        include_str!("lua/admit_prepare.lua")
+    ))
+});
+
+/// Lua script which refunds everything `ADMIT_PREPARE` charged for a rejected packet
+static REFUND_PREPARE: Lazy<Script> = Lazy::new(|| {
+    Script::new(concat!(
+        include_str!("lua/token_bucket.lua"),
+        include_str!("lua/refund_prepare.lua")
+    ))
+});
+
+/// Lua script which charges a packet to the token buckets of the account's packet and
+/// throughput limits, or refunds the throughput limit if the amount is negative
+static RATE_LIMITS: Lazy<Script> = Lazy::new(|| {
+    Script::new(concat!(
+        include_str!("lua/token_bucket.lua"),
+        include_str!("lua/rate_limits.lua")
+    ))
+});
+
+/// Lua script which makes the node the leader, unless another node holds an unexpired lease
+static CLAIM_LEADERSHIP: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/claim_leadership.lua")));
+
+/// Lua script which removes an outgoing settlement which the engine accepted
+static REMOVE_OUTGOING_SETTLEMENT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/remove_outgoing_settlement.lua")));
+
 /// Lua script which increases the provided account's balance after an incoming settlement succeeded
 static PROCESS_INCOMING_SETTLEMENT: Lazy<Script> =
     Lazy::new(|| Script::new(include_str!("lua/process_incoming_settlement.lua")));
 
+/// Lua script which inserts or updates the provided account, checking that its username
+/// is free in the same step
+static WRITE_ACCOUNT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/write_account.lua")));
+
+/// Lua script which deletes the provided account along with all routes,
+/// rate limits and leftovers which belong to it
+static DELETE_ACCOUNT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/delete_account.lua")));
+
+/// Lua script which adds the amount of a fulfilled packet to the payment of its connection
+static RECORD_INCOMING_PAYMENT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/record_incoming_payment.lua")));
+
+/// Lua script which marks the payment of a connection as acknowledged, if it exists
+static ACKNOWLEDGE_INCOMING_PAYMENT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/acknowledge_incoming_payment.lua")));
+
 /// Builder for the Redis Store
 pub struct RedisStoreBuilder {
     redis_url: ConnectionInfo,
     secret: [u8; 32],
+    previous_secret: Option<[u8; 32]>,
     poll_interval: u64,
     /// Connector's ILP Address. Used to insert `Child` accounts as
     node_ilp_address: Address,
     db_prefix: String,
+    account_cache_capacity: usize,
+    account_cache_ttl: u64,
+    pool_config: PoolConfig,
+    timers: OperationTimers,
 }
 
 impl RedisStoreBuilder {
@@ -170,9 +447,14 @@ impl RedisStoreBuilder {
         RedisStoreBuilder {
             redis_url,
             secret,
+            previous_secret: None,
             poll_interval: DEFAULT_POLL_INTERVAL,
             node_ilp_address: DEFAULT_ILP_ADDRESS.clone(),
             db_prefix: DEFAULT_DB_PREFIX.to_string(),
+            account_cache_capacity: 0,
+            account_cache_ttl: DEFAULT_ACCOUNT_CACHE_TTL,
+            pool_config: PoolConfig::default(),
+            timers: OperationTimers::new("redis"),
         }
     }
 
@@ -190,30 +472,125 @@ impl RedisStoreBuilder {
 
     /// Sets the redis db prefix that will be used for top level keys for this node
     /// It can be used if there is a need for the same redis db to be shared by multiple nodes
+    ///
+    /// The prefix may only contain ASCII letters, digits, `_` and `-`, which guarantees
+    /// that nodes with different prefixes never read or write each other's keys.
+    /// Connecting fails if the prefix is invalid.
     pub fn with_db_prefix(&mut self, prefix: &str) -> &mut Self {
         self.db_prefix = prefix.to_string();
         self
     }
 
+    /// Sets the secret which was used before the current one, in order to rotate the key
+    /// with which the account tokens are encrypted. On connecting, all tokens which were
+    /// encrypted with the previous secret are re-encrypted with the current one.
+    pub fn previous_secret(&mut self, previous_secret: [u8; 32]) -> &mut Self {
+        self.previous_secret = Some(previous_secret);
+        self
+    }
+
+    /// Sets the maximum number of accounts kept in the in-process account cache.
+    /// The cache is disabled if this is 0, which is the default.
+    ///
+    /// Account changes made through this store are applied to the cache immediately,
+    /// but changes made by other nodes sharing the same database are only picked up
+    /// once the cached entries expire (see `account_cache_ttl`).
+    pub fn account_cache_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.account_cache_capacity = capacity;
+        self
+    }
+
+    /// Sets the time (in milliseconds) after which cached accounts are reloaded from Redis
+    pub fn account_cache_ttl(&mut self, ttl: u64) -> &mut Self {
+        self.account_cache_ttl = ttl;
+        self
+    }
+
+    /// Sets the number of connections opened to Redis (defaults to 1). Every connection
+    /// is multiplexed, so this only needs to be increased if a single connection becomes
+    /// the bottleneck of a heavily loaded node.
+    pub fn pool_size(&mut self, size: usize) -> &mut Self {
+        self.pool_config.size = size.max(1);
+        self
+    }
+
+    /// Limits the number of commands (or pipelines) which are sent to Redis at the same
+    /// time. Further commands wait until one of them completes. There is no limit by default.
+    pub fn max_concurrent_commands(&mut self, limit: usize) -> &mut Self {
+        self.pool_config.max_concurrent_commands = Some(limit.max(1));
+        self
+    }
+
+    /// Sets the time (in milliseconds) after which connecting to Redis is abandoned.
+    /// There is no timeout by default.
+    pub fn connection_timeout(&mut self, timeout: u64) -> &mut Self {
+        self.pool_config.connect_timeout = Some(Duration::from_millis(timeout));
+        self
+    }
+
+    /// Sets how many times connecting and commands failing with a transient error
+    /// (e.g. Redis is still loading its dataset) are retried. Nothing is retried by default.
+    ///
+    /// Commands which fail because the connection dropped are never retried, because
+    /// they may already have been executed.
+    pub fn max_retries(&mut self, max_retries: u32) -> &mut Self {
+        self.pool_config.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the time (in milliseconds) to wait before the first retry, which is doubled
+    /// for every further retry. Defaults to 100ms.
+    pub fn retry_backoff(&mut self, backoff: u64) -> &mut Self {
+        self.pool_config.retry_backoff = Duration::from_millis(backoff);
+        self
+    }
+
+    /// Sets the duration (in milliseconds) after which store operations are logged
+    /// as slow. Slow operations are not logged by default.
+    pub fn slow_operation_threshold(&mut self, threshold: u64) -> &mut Self {
+        self.timers
+            .set_default_threshold(Duration::from_millis(threshold));
+        self
+    }
+
+    /// Sets the slow operation threshold (in milliseconds) of a single store method,
+    /// e.g. `update_balances_for_prepare`, overriding the `slow_operation_threshold`
+    pub fn method_slow_operation_threshold(&mut self, method: &str, threshold: u64) -> &mut Self {
+        self.timers
+            .set_threshold(method, Duration::from_millis(threshold));
+        self
+    }
+
     /// Connects to the Redis Store
     ///
     /// Specifically
+    /// 1. Validates the db prefix
     /// 1. Generates encryption and decryption keys
-    /// 1. Connects to the redis store (ensuring that it reconnects in case of drop)
+    /// 1. Opens the pool of connections to the redis store (ensuring that they reconnect in case of drop)
     /// 1. Gets the Node address assigned to us by our parent (if it exists)
+    /// 1. Re-encrypts the tokens encrypted with the previous secret (if configured)
+    /// 1. Replaces incoming tokens stored by older versions with their hashes
     /// 1. Starts polling for routing table updates
     /// 1. Spawns a thread to notify incoming payments over WebSockets
     pub async fn connect(&mut self) -> Result<RedisStore, ()> {
+        runtime::ensure().map_err(|err| error!("{}", err))?;
+        validate_db_prefix(&self.db_prefix)
+            .map_err(|err| error!("Invalid db prefix {:?}: {}", self.db_prefix, err))?;
         let redis_info = self.redis_url.clone();
         let (encryption_key, decryption_key) = generate_keys(&self.secret[..]);
         self.secret.zeroize(); // clear the secret after it has been used for key generation
+        let previous_decryption_key = self.previous_secret.as_mut().map(|previous_secret| {
+            let (_, decryption_key) = generate_keys(&previous_secret[..]);
+            previous_secret.zeroize();
+            decryption_key
+        });
         let poll_interval = self.poll_interval;
         let ilp_address = self.node_ilp_address.clone();
 
         let client = Client::open(redis_info.clone())
             .map_err(|err| error!("Error creating subscription Redis client: {:?}", err))?;
         debug!("Connected subscription client to redis: {:?}", client);
-        let mut connection = RedisReconnect::connect(redis_info.clone())
+        let mut connection = RedisReconnect::connect(redis_info.clone(), self.pool_config.clone())
             .map_err(|_| ())
             .await?;
         let mut sub_connection = client
@@ -246,43 +623,55 @@ impl RedisStoreBuilder {
             subscriptions: Arc::new(Mutex::new(HashMap::new())),
             payment_publisher: all_payment_publisher,
             exchange_rates: Arc::new(RwLock::new(HashMap::new())),
+            exchange_rates_updated_at: Arc::new(RwLock::new(HashMap::new())),
             routes: Arc::new(RwLock::new(Arc::new(HashMap::new()))),
             encryption_key: Arc::new(encryption_key),
             decryption_key: Arc::new(decryption_key),
             db_prefix: self.db_prefix.clone(),
+            account_cache: if self.account_cache_capacity > 0 {
+                Some(Arc::new(AccountCache::new(
+                    self.account_cache_capacity,
+                    Duration::from_millis(self.account_cache_ttl),
+                )))
+            } else {
+                None
+            },
+            timers: Arc::new(self.timers.clone()),
+            verified_tokens: Arc::new(VerifiedTokens::new()),
         };
 
+        store
+            .migrate_tokens(
+                previous_decryption_key
+                    .as_ref()
+                    .map(|key| &key.expose_secret().0),
+            )
+            .map_err(|err| error!("Error migrating account tokens: {}", err))
+            .await?;
+
         // Poll for routing table updates
         // Note: if this behavior changes, make sure to update the Drop implementation
-        let connection_clone = Arc::downgrade(&store.connection.conn);
-        let redis_info = store.connection.redis_info.clone();
+        let connection_clone = Arc::downgrade(&store.connection.pool);
         let routing_table = store.routes.clone();
 
         let db_prefix = self.db_prefix.clone();
         let poll_routes = async move {
-            let mut interval = tokio::time::interval(Duration::from_millis(poll_interval));
+            let mut interval = runtime::interval(Duration::from_millis(poll_interval));
             // Irrefutable while pattern, can we do something here?
             loop {
                 interval.tick().await;
-                if let Some(conn) = connection_clone.upgrade() {
-                    let _ = update_routes(
-                        RedisReconnect {
-                            conn,
-                            redis_info: redis_info.clone(),
-                        },
-                        routing_table.clone(),
-                        &db_prefix,
-                    )
-                    .map_err(|err| error!("{}", err))
-                    .await;
+                if let Some(pool) = connection_clone.upgrade() {
+                    let _ =
+                        update_routes(RedisReconnect { pool }, routing_table.clone(), &db_prefix)
+                            .map_err(|err| error!("{}", err))
+                            .await;
                 } else {
                     debug!("Not polling routes anymore because connection was closed");
                     break;
                 }
             }
-            Ok::<(), ()>(())
         };
-        tokio::spawn(poll_routes);
+        runtime::spawn(poll_routes);
 
         // Here we spawn a worker thread to listen for incoming messages on Redis pub/sub,
         // running a callback for each message received.
@@ -369,6 +758,8 @@ pub struct RedisStore {
     /// A subscriber to all payment notifications, exposed via a WebSocket
     payment_publisher: broadcast::Sender<PaymentNotification>,
     exchange_rates: Arc<RwLock<HashMap<String, f64>>>,
+    /// When each of the exchange rates was last set
+    exchange_rates_updated_at: Arc<RwLock<HashMap<String, SystemTime>>>,
     /// The store keeps the routing table in memory so that it can be returned
     /// synchronously while the Router is processing packets.
     /// The outer `Arc<RwLock>` is used so that we can update the stored routing
@@ -382,110 +773,177 @@ pub struct RedisStore {
     decryption_key: Arc<Secret<DecryptionKey>>,
     /// Prefix for all top level keys. This enables multiple nodes to use the same db instance.
     db_prefix: String,
+    /// Recently used accounts, so that packets can be processed without loading
+    /// the accounts from Redis every time. Disabled if `None`.
+    account_cache: Option<Arc<AccountCache>>,
+    /// Records the duration of store operations
+    timers: Arc<OperationTimers>,
+    /// Incoming tokens which were recently verified against their hashes
+    verified_tokens: Arc<VerifiedTokens>,
 }
 
 impl RedisStore {
-    /// Gets all the account ids from Redis
-    async fn get_all_accounts_ids(&self) -> Result<Vec<Uuid>, NodeStoreError> {
-        let mut connection = self.connection.clone();
-        let account_ids: Vec<RedisAccountId> = connection
-            .smembers(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
-            .await?;
-        Ok(account_ids.iter().map(|rid| rid.0).collect())
+    /// Removes the account with the given id from the account cache, so that it is
+    /// reloaded from Redis on its next use. Only needed if the account was modified
+    /// without going through this store, e.g. by another node sharing the database.
+    pub fn invalidate_cached_account(&self, id: Uuid) {
+        if let Some(ref cache) = self.account_cache {
+            cache.invalidate(id);
+        }
     }
 
-    /// Inserts the account corresponding to the provided `AccountWithEncryptedtokens`
-    /// in Redis. Returns the provided account (tokens remain encrypted)
-    async fn redis_insert_account(
+    /// Removes all accounts from the account cache
+    pub fn clear_account_cache(&self) {
+        if let Some(ref cache) = self.account_cache {
+            cache.clear();
+        }
+    }
+
+    /// Re-encrypts the tokens of all accounts which can only be decrypted with the given
+    /// key (derived from the previous secret) with the current encryption key, and replaces
+    /// the incoming tokens which are still stored encrypted with their hashes
+    async fn migrate_tokens(
         &self,
-        encrypted: &AccountWithEncryptedTokens,
+        previous_decryption_key: Option<&aead::LessSafeKey>,
     ) -> Result<(), NodeStoreError> {
-        let account = &encrypted.account;
-        let id = accounts_key(&self.db_prefix, account.id);
-        let mut connection = self.connection.clone();
-        let routing_table = self.routes.clone();
-        // Check that there isn't already an account with values that MUST be unique
-        let mut pipe = redis_crate::pipe();
-        pipe.exists(&id);
-        pipe.hexists(
-            &*prefixed_key(&self.db_prefix, USERNAMES_KEY),
-            account.username().as_ref(),
-        );
-        if account.routing_relation == RoutingRelation::Parent {
-            pipe.exists(&*prefixed_key(&self.db_prefix, PARENT_ILP_KEY));
+        let account_ids = self.get_all_accounts_ids().await?;
+        if account_ids.is_empty() {
+            return Ok(());
         }
-
-        let results: Vec<bool> = pipe.query_async(&mut connection).await?;
-        if results.iter().any(|val| *val) {
-            warn!(
-                "An account already exists with the same {}. Cannot insert account: {:?}",
-                account.id, account
-            );
-            return Err(NodeStoreError::AccountExists(account.username.to_string()));
+        let mut script = LOAD_ACCOUNTS.prepare_invoke();
+        script.arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY));
+        script.arg(&*prefixed_key(&self.db_prefix, SETTLEMENT_ENGINES_KEY));
+        for id in account_ids.iter() {
+            script.arg(RedisAccountId(*id));
         }
+        let accounts: Vec<AccountWithEncryptedTokens> =
+            script.invoke_async(&mut self.connection.clone()).await?;
 
+        // Only the token fields are written so that concurrent changes
+        // to the other account details are not overwritten
+        let decryption_key = &self.decryption_key.expose_secret().0;
+        let mut decryption_keys = vec![decryption_key];
+        decryption_keys.extend(previous_decryption_key);
         let mut pipe = redis_crate::pipe();
-        pipe.atomic();
+        let mut num_tokens = 0;
+        for mut account in accounts {
+            let mut migrated = account.hash_incoming_tokens(&decryption_keys);
+            if let Some(previous_decryption_key) = previous_decryption_key {
+                migrated.extend(account.reencrypt_tokens(
+                    previous_decryption_key,
+                    decryption_key,
+                    &self.encryption_key.expose_secret().0,
+                ));
+            }
+            for (field, token) in migrated {
+                pipe.hset(
+                    accounts_key(&self.db_prefix, account.account.id),
+                    field,
+                    token.as_ref(),
+                )
+                .ignore();
+                num_tokens += 1;
+            }
+        }
+        if num_tokens > 0 {
+            pipe.query_async::<_, ()>(&mut self.connection.clone())
+                .await?;
+            debug!("Migrated {} account tokens", num_tokens);
+        }
+        Ok(())
+    }
 
-        // Add the account key to the list of accounts
-        pipe.sadd(
-            &*prefixed_key(&self.db_prefix, ACCOUNTS_KEY),
-            RedisAccountId(account.id),
-        )
-        .ignore();
+    /// Loads the accounts with the given ids from Redis, bypassing the cache
+    async fn redis_get_accounts(
+        &self,
+        account_ids: Vec<Uuid>,
+    ) -> Result<Vec<Account>, AccountStoreError> {
+        let num_accounts = account_ids.len();
+        let mut script = LOAD_ACCOUNTS.prepare_invoke();
+        script.arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY));
+        script.arg(&*prefixed_key(&self.db_prefix, SETTLEMENT_ENGINES_KEY));
 
-        // Save map for Username -> Account ID
-        pipe.hset(
-            &*prefixed_key(&self.db_prefix, USERNAMES_KEY),
-            account.username().as_ref(),
-            RedisAccountId(account.id),
-        )
-        .ignore();
+        for id in account_ids.iter() {
+            script.arg(id.to_string());
+        }
 
-        // Set balance-related details
-        pipe.hset_multiple(&id, &[("balance", 0), ("prepaid_amount", 0)])
-            .ignore();
+        // Need to clone the connection here to avoid lifetime errors
+        let accounts: Vec<AccountWithEncryptedTokens> =
+            script.invoke_async(&mut self.connection.clone()).await?;
 
-        if account.should_send_routes() {
-            pipe.sadd(
-                &*prefixed_key(&self.db_prefix, SEND_ROUTES_KEY),
-                RedisAccountId(account.id),
-            )
-            .ignore();
+        // Decrypt the accounts. TODO: This functionality should be
+        // decoupled from redis so that it gets reused by the other backends
+        if accounts.len() == num_accounts {
+            let accounts = accounts
+                .into_iter()
+                .map(|account| account.decrypt_tokens(&self.decryption_key.expose_secret().0))
+                .collect();
+            Ok(accounts)
+        } else {
+            Err(AccountStoreError::WrongLength {
+                expected: num_accounts,
+                actual: accounts.len(),
+            })
         }
+    }
 
-        if account.should_receive_routes() {
-            pipe.sadd(
-                &*prefixed_key(&self.db_prefix, RECEIVE_ROUTES_FROM_KEY),
-                RedisAccountId(account.id),
-            )
-            .ignore();
+    /// Gets the account with the given username, from the cache if possible.
+    /// The caller MUST ensure that the returned account is authenticated.
+    async fn get_account_by_username(
+        &self,
+        username: &Username,
+    ) -> Result<Option<Account>, RedisError> {
+        if let Some(ref cache) = self.account_cache {
+            if let Some(account) = cache.get_id(username).and_then(|id| cache.get(id)) {
+                // The username of a cached id may have changed in the meantime
+                if account.username() == username {
+                    return Ok(Some(account));
+                }
+            }
         }
 
-        if account.ilp_over_btp_url.is_some() {
-            pipe.sadd(
-                &*prefixed_key(&self.db_prefix, BPT_OUTGOING),
-                RedisAccountId(account.id),
-            )
-            .ignore();
+        // TODO make sure it can't do script injection!
+        let account: Option<AccountWithEncryptedTokens> = ACCOUNT_FROM_USERNAME
+            .arg(&*prefixed_key(&self.db_prefix, USERNAMES_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
+            .arg(username.as_ref())
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        let account =
+            account.map(|account| account.decrypt_tokens(&self.decryption_key.expose_secret().0));
+        if let (Some(ref cache), Some(ref account)) = (&self.account_cache, &account) {
+            cache.insert(account.clone());
         }
+        Ok(account)
+    }
 
-        // Add route to routing table
-        pipe.hset(
-            &*prefixed_key(&self.db_prefix, ROUTES_KEY),
-            account.ilp_address.as_bytes(),
-            RedisAccountId(account.id),
-        )
-        .ignore();
-
-        // Set account details
-        pipe.cmd("HMSET").arg(&id).arg(encrypted).ignore();
-
-        // The parent account settings are done via the API. We just
-        // had to check for the existence of a parent
-        pipe.query_async(&mut connection).await?;
+    /// Gets all the account ids from Redis
+    async fn get_all_accounts_ids(&self) -> Result<Vec<Uuid>, NodeStoreError> {
+        let mut connection = self.connection.clone();
+        let account_ids: Vec<RedisAccountId> = connection
+            .smembers(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
+            .await?;
+        Ok(account_ids.iter().map(|rid| rid.0).collect())
+    }
 
-        update_routes(connection, routing_table, &self.db_prefix).await?;
+    /// Inserts the account corresponding to the provided `AccountWithEncryptedtokens`
+    /// in Redis. Returns the provided account (tokens remain encrypted)
+    async fn redis_insert_account(
+        &self,
+        encrypted: &AccountWithEncryptedTokens,
+    ) -> Result<(), NodeStoreError> {
+        let account = &encrypted.account;
+        match self.redis_write_account(encrypted, true).await? {
+            AccountWrite::Written => {}
+            _ => {
+                warn!(
+                    "An account already exists with the same {}. Cannot insert account: {:?}",
+                    account.id, account
+                );
+                return Err(NodeStoreError::AccountExists(account.username.to_string()));
+            }
+        }
+        // The parent account settings are done via the API
         debug!(
             "Inserted account {} (ILP address: {})",
             account.id, account.ilp_address
@@ -499,94 +957,92 @@ impl RedisStore {
         &self,
         encrypted: &AccountWithEncryptedTokens,
     ) -> Result<(), NodeStoreError> {
-        let account = encrypted.account.clone();
-        let mut connection = self.connection.clone();
-        let routing_table = self.routes.clone();
-
-        // Check to make sure an account with this ID already exists
-        // TODO this needs to be atomic with the insertions later,
-        // waiting on #186
         // TODO: Do not allow this update to happen if
         // AccountDetails.RoutingRelation == Parent and parent is
         // already set
-        let exists: bool = connection
-            .exists(accounts_key(&self.db_prefix, account.id))
-            .await?;
-
-        if !exists {
-            warn!(
-                "No account exists with ID {}, cannot update account {:?}",
-                account.id, account
-            );
-            return Err(NodeStoreError::AccountNotFound(account.id.to_string()));
-        }
-        let mut pipe = redis_crate::pipe();
-        pipe.atomic();
-
-        // Add the account key to the list of accounts
-        pipe.sadd(
-            &*prefixed_key(&self.db_prefix, ACCOUNTS_KEY),
-            RedisAccountId(account.id),
-        )
-        .ignore();
-
-        // Set account details
-        pipe.cmd("HMSET")
-            .arg(accounts_key(&self.db_prefix, account.id))
-            .arg(encrypted)
-            .ignore();
-
-        if account.should_send_routes() {
-            pipe.sadd(
-                &*prefixed_key(&self.db_prefix, SEND_ROUTES_KEY),
-                RedisAccountId(account.id),
-            )
-            .ignore();
-        }
-
-        if account.should_receive_routes() {
-            pipe.sadd(
-                &*prefixed_key(&self.db_prefix, RECEIVE_ROUTES_FROM_KEY),
-                RedisAccountId(account.id),
-            )
-            .ignore();
-        }
-
-        if account.ilp_over_btp_url.is_some() {
-            pipe.sadd(
-                &*prefixed_key(&self.db_prefix, BPT_OUTGOING),
-                RedisAccountId(account.id),
-            )
-            .ignore();
+        let account = &encrypted.account;
+        match self.redis_write_account(encrypted, false).await? {
+            AccountWrite::Written => {}
+            AccountWrite::NotFound => {
+                warn!(
+                    "No account exists with ID {}, cannot update account {:?}",
+                    account.id, account
+                );
+                return Err(NodeStoreError::AccountNotFound(account.id.to_string()));
+            }
+            AccountWrite::Exists => {
+                warn!(
+                    "Username {} is already taken, cannot update account {}",
+                    account.username, account.id
+                );
+                return Err(NodeStoreError::AccountExists(account.username.to_string()));
+            }
         }
-
-        // Add route to routing table
-        pipe.hset(
-            &*prefixed_key(&self.db_prefix, ROUTES_KEY),
-            account.ilp_address.to_bytes().to_vec(),
-            RedisAccountId(account.id),
-        )
-        .ignore();
-
-        pipe.query_async(&mut connection).await?;
-        update_routes(connection, routing_table, &self.db_prefix).await?;
+        self.invalidate_cached_account(account.id);
         debug!(
-            "Inserted account {} (id: {}, ILP address: {})",
+            "Updated account {} (id: {}, ILP address: {})",
             account.username, account.id, account.ilp_address
         );
         Ok(())
     }
 
-    /// Modifies the account corresponding to the provided `id` with the provided `settings`
-    /// in Redis. Returns the modified account (tokens remain encrypted)
-    async fn redis_modify_account(
+    /// Writes the account with a single script, which checks that the account exists (or
+    /// doesn't, for an insert) and that its username is free, then replaces the username,
+    /// route and route settings of the previous details. Reloads the routing table if the
+    /// account was written.
+    async fn redis_write_account(
         &self,
-        id: Uuid,
-        settings: EncryptedAccountSettings,
-    ) -> Result<AccountWithEncryptedTokens, NodeStoreError> {
-        let mut pipe = redis_crate::pipe();
-        pipe.atomic();
-
+        encrypted: &AccountWithEncryptedTokens,
+        is_insert: bool,
+    ) -> Result<AccountWrite, NodeStoreError> {
+        let account = &encrypted.account;
+        let mut connection = self.connection.clone();
+        let unset_fields = if is_insert {
+            Vec::new()
+        } else {
+            unset_optional_fields(account)
+        };
+        let result: String = WRITE_ACCOUNT
+            .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
+            .arg(accounts_key(&self.db_prefix, account.id))
+            .arg(&*prefixed_key(&self.db_prefix, USERNAMES_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, SEND_ROUTES_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, RECEIVE_ROUTES_FROM_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, BPT_OUTGOING))
+            .arg(&*prefixed_key(&self.db_prefix, ROUTES_KEY))
+            .arg(RedisAccountId(account.id))
+            .arg(account.username().as_ref())
+            .arg(account.ilp_address.to_bytes().to_vec())
+            .arg(is_insert as u8)
+            .arg(account.should_send_routes() as u8)
+            .arg(account.should_receive_routes() as u8)
+            .arg(account.ilp_over_btp_url.is_some() as u8)
+            .arg(unset_fields.len())
+            .arg(unset_fields)
+            .arg(encrypted)
+            .invoke_async(&mut connection)
+            .await?;
+        let result = match result.as_str() {
+            "OK" => AccountWrite::Written,
+            "NOT_FOUND" => AccountWrite::NotFound,
+            _ => AccountWrite::Exists,
+        };
+        if let AccountWrite::Written = result {
+            update_routes(connection, self.routes.clone(), &self.db_prefix).await?;
+        }
+        Ok(result)
+    }
+
+    /// Modifies the account corresponding to the provided `id` with the provided `settings`
+    /// in Redis. Returns the modified account (tokens remain encrypted)
+    async fn redis_modify_account(
+        &self,
+        id: Uuid,
+        settings: EncryptedAccountSettings,
+    ) -> Result<AccountWithEncryptedTokens, NodeStoreError> {
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+
         let accounts_key = accounts_key(&self.db_prefix, id);
         if let Some(ref endpoint) = settings.ilp_over_btp_url {
             pipe.hset(&accounts_key, "ilp_over_btp_url", endpoint);
@@ -634,7 +1090,12 @@ impl RedisStore {
             pipe.hset(&accounts_key, "settle_to", settle_to);
         }
 
+        if let Some(settle_every) = settings.settle_every {
+            pipe.hset(&accounts_key, "settle_every", settle_every);
+        }
+
         pipe.query_async(&mut self.connection.clone()).await?;
+        self.invalidate_cached_account(id);
 
         // return the updated account
         self.redis_get_account(id).await
@@ -664,104 +1125,139 @@ impl RedisStore {
     ) -> Result<AccountWithEncryptedTokens, NodeStoreError> {
         let encrypted = self.redis_get_account(id).await?;
         let account = &encrypted.account;
-        let mut pipe = redis_crate::pipe();
-        pipe.atomic();
-
-        pipe.srem(
-            &*prefixed_key(&self.db_prefix, ACCOUNTS_KEY),
-            RedisAccountId(account.id),
-        )
-        .ignore();
-        pipe.del(&*accounts_key(&self.db_prefix, account.id))
-            .ignore();
-        pipe.hdel(
-            &*prefixed_key(&self.db_prefix, USERNAMES_KEY),
-            account.username().as_ref(),
-        )
-        .ignore();
-
-        if account.should_send_routes() {
-            pipe.srem(
-                &*prefixed_key(&self.db_prefix, SEND_ROUTES_KEY),
-                RedisAccountId(account.id),
-            )
-            .ignore();
-        }
-
-        if account.should_receive_routes() {
-            pipe.srem(
-                &*prefixed_key(&self.db_prefix, RECEIVE_ROUTES_FROM_KEY),
-                RedisAccountId(account.id),
-            )
-            .ignore();
-        }
-
-        if account.ilp_over_btp_url.is_some() {
-            pipe.srem(
-                &*prefixed_key(&self.db_prefix, BPT_OUTGOING),
-                RedisAccountId(account.id),
-            )
-            .ignore();
-        }
-
-        pipe.hdel(
-            &*prefixed_key(&self.db_prefix, ROUTES_KEY),
-            account.ilp_address.to_bytes().to_vec(),
-        )
-        .ignore();
-
-        pipe.del(uncredited_amount_key(&self.db_prefix, id));
+        // Forget the address assigned by the parents once the last one is gone
+        let is_last_parent = if account.routing_relation == RoutingRelation::Parent {
+            let account_ids: Vec<RedisAccountId> = self
+                .connection
+                .clone()
+                .smembers(&*prefixed_key(&self.db_prefix, RECEIVE_ROUTES_FROM_KEY))
+                .await?;
+            let other_ids: Vec<Uuid> = account_ids
+                .into_iter()
+                .map(|id| id.0)
+                .filter(|other| *other != id)
+                .collect();
+            let others = if other_ids.is_empty() {
+                Vec::new()
+            } else {
+                self.redis_get_accounts(other_ids).await?
+            };
+            !others
+                .iter()
+                .any(|other| other.routing_relation == RoutingRelation::Parent)
+        } else {
+            false
+        };
 
+        // Everything is removed in a single script so that no dangling
+        // routes or usernames are left behind if the deletion is interrupted
         let mut connection = self.connection.clone();
-        pipe.query_async(&mut connection).await?;
+        DELETE_ACCOUNT
+            .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
+            .arg(accounts_key(&self.db_prefix, account.id))
+            .arg(&*prefixed_key(&self.db_prefix, USERNAMES_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, SEND_ROUTES_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, RECEIVE_ROUTES_FROM_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, BPT_OUTGOING))
+            .arg(&*prefixed_key(&self.db_prefix, ROUTES_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, STATIC_ROUTES_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, DEFAULT_ROUTE_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, PARENT_ILP_KEY))
+            .arg(uncredited_amount_key(&self.db_prefix, id))
+            .arg(&*prefixed_key(
+                &self.db_prefix,
+                &format!("limit:packets:{}", account.id),
+            ))
+            .arg(&*prefixed_key(
+                &self.db_prefix,
+                &format!("limit:throughput:{}", account.id),
+            ))
+            .arg(RedisAccountId(account.id))
+            .arg(account.username().as_ref())
+            .arg(is_last_parent as u8)
+            .arg(incoming_payments_key(&self.db_prefix, account.id))
+            .arg(unacknowledged_payments_key(&self.db_prefix, account.id))
+            .invoke_async::<_, ()>(&mut connection)
+            .await?;
+        self.invalidate_cached_account(account.id);
         update_routes(connection, self.routes.clone(), &self.db_prefix).await?;
         debug!("Deleted account {}", account.id);
         Ok(encrypted)
     }
+
+    /// Runs PROCESS_FULFILL, which adds the amount to settle to `queued_key`
+    /// unless it is empty
+    async fn redis_process_fulfill(
+        &self,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+        packet_id: Option<PacketId>,
+        queued_key: &str,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        let (balance, amount_to_settle): (i64, u64) = PROCESS_FULFILL
+            .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
+            .arg(RedisAccountId(to_account_id))
+            .arg(outgoing_amount)
+            .arg(balance_log_key(&self.db_prefix, to_account_id))
+            .arg(now_millis())
+            .arg(packet_id.map(hex::encode).unwrap_or_default())
+            .arg(queued_key)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+
+        trace!(
+            "Processed fulfill for account {} for outgoing amount {}. Fulfill call result: {} {}",
+            to_account_id,
+            outgoing_amount,
+            balance,
+            amount_to_settle,
+        );
+        Ok((balance, amount_to_settle))
+    }
 }
 
 #[async_trait]
 impl AccountStore for RedisStore {
     type Account = Account;
 
-    // TODO cache results to avoid hitting Redis for each packet
     async fn get_accounts(
         &self,
         account_ids: Vec<Uuid>,
     ) -> Result<Vec<Account>, AccountStoreError> {
-        let num_accounts = account_ids.len();
-        let mut script = LOAD_ACCOUNTS.prepare_invoke();
-        script.arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY));
-        script.arg(&*prefixed_key(&self.db_prefix, SETTLEMENT_ENGINES_KEY));
+        let _timer = self.timers.start("get_accounts");
+        let cache = match self.account_cache {
+            Some(ref cache) => cache,
+            None => return self.redis_get_accounts(account_ids).await,
+        };
 
-        for id in account_ids.iter() {
-            script.arg(id.to_string());
+        let cached: Vec<Option<Account>> = account_ids.iter().map(|id| cache.get(*id)).collect();
+        let missing: Vec<Uuid> = account_ids
+            .iter()
+            .zip(cached.iter())
+            .filter(|(_, account)| account.is_none())
+            .map(|(id, _)| *id)
+            .collect();
+        if missing.is_empty() {
+            return Ok(cached.into_iter().flatten().collect());
         }
 
-        // Need to clone the connection here to avoid lifetime errors
-        let accounts: Vec<AccountWithEncryptedTokens> =
-            script.invoke_async(&mut self.connection.clone()).await?;
-
-        // Decrypt the accounts. TODO: This functionality should be
-        // decoupled from redis so that it gets reused by the other backends
-        if accounts.len() == num_accounts {
-            let accounts = accounts
-                .into_iter()
-                .map(|account| account.decrypt_tokens(&self.decryption_key.expose_secret().0))
-                .collect();
-            Ok(accounts)
-        } else {
-            Err(AccountStoreError::WrongLength {
-                expected: num_accounts,
-                actual: accounts.len(),
-            })
+        let loaded = self.redis_get_accounts(missing).await?;
+        for account in loaded.iter() {
+            cache.insert(account.clone());
         }
+        // Fill the gaps in the order in which the accounts were requested
+        let mut loaded = loaded.into_iter();
+        Ok(cached
+            .into_iter()
+            .filter_map(|account| account.or_else(|| loaded.next()))
+            .collect())
     }
 
     async fn get_account_id_from_username(
         &self,
         username: &Username,
     ) -> Result<Uuid, AccountStoreError> {
+        let _timer = self.timers.start("get_account_id_from_username");
         let username = username.clone();
         let id: Option<RedisAccountId> = self
             .connection
@@ -802,36 +1298,39 @@ impl StreamNotificationsStore for RedisStore {
         let message = serde_json::to_string(&payment).unwrap();
         let mut connection = self.connection.clone();
         let self_clone = self.clone();
-        tokio::spawn(async move {
-            let account_id = self_clone
-                .get_account_id_from_username(&username)
-                .map_err(|_| {
-                    error!(
-                        "Failed to find account ID corresponding to username: {}",
-                        username
-                    )
-                })
-                .await?;
-
-            debug!(
-                "Publishing payment notification {} for account {}",
-                message, account_id
-            );
-            // https://github.com/rust-lang/rust/issues/64960#issuecomment-544219926
-            let published_args = format!(
-                "{}{}",
-                prefixed_key(&self_clone.db_prefix, STREAM_NOTIFICATIONS_PREFIX),
-                account_id.clone()
-            );
-            redis_crate::cmd("PUBLISH")
-                .arg(published_args)
-                .arg(message)
-                .query_async(&mut connection)
-                .map_err(move |err| error!("Error publish message to Redis: {:?}", err))
-                .await?;
+        runtime::spawn(
+            async move {
+                let account_id = self_clone
+                    .get_account_id_from_username(&username)
+                    .map_err(|_| {
+                        error!(
+                            "Failed to find account ID corresponding to username: {}",
+                            username
+                        )
+                    })
+                    .await?;
 
-            Ok::<(), ()>(())
-        });
+                debug!(
+                    "Publishing payment notification {} for account {}",
+                    message, account_id
+                );
+                // https://github.com/rust-lang/rust/issues/64960#issuecomment-544219926
+                let published_args = format!(
+                    "{}{}",
+                    prefixed_key(&self_clone.db_prefix, STREAM_NOTIFICATIONS_PREFIX),
+                    account_id.clone()
+                );
+                redis_crate::cmd("PUBLISH")
+                    .arg(published_args)
+                    .arg(message)
+                    .query_async(&mut connection)
+                    .map_err(move |err| error!("Error publish message to Redis: {:?}", err))
+                    .await?;
+
+                Ok::<(), ()>(())
+            }
+            .map(|_| ()),
+        );
     }
 
     fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
@@ -839,11 +1338,106 @@ impl StreamNotificationsStore for RedisStore {
     }
 }
 
+/// Builds the payment from the amount, closed and acknowledged flags and update time
+/// returned by the incoming payment scripts
+fn incoming_payment(
+    account_id: Uuid,
+    destination: Address,
+    (amount, closed, acknowledged, updated_at): (u64, u8, u8, u64),
+) -> IncomingPayment {
+    IncomingPayment {
+        account_id,
+        destination,
+        amount,
+        closed: closed == 1,
+        acknowledged: acknowledged == 1,
+        updated_at,
+    }
+}
+
+#[async_trait]
+impl IncomingPaymentsStore for RedisStore {
+    async fn record_incoming_payment(
+        &self,
+        account_id: Uuid,
+        destination: &Address,
+        amount: u64,
+        closed: bool,
+        timestamp: u64,
+    ) -> Result<IncomingPayment, BalanceStoreError> {
+        let _timer = self.timers.start("record_incoming_payment");
+        let fields = RECORD_INCOMING_PAYMENT
+            .arg(incoming_payments_key(&self.db_prefix, account_id))
+            .arg(unacknowledged_payments_key(&self.db_prefix, account_id))
+            .arg(destination.to_string())
+            .arg(amount)
+            .arg(closed as u8)
+            .arg(timestamp)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(incoming_payment(account_id, destination.clone(), fields))
+    }
+
+    async fn acknowledge_incoming_payment(
+        &self,
+        account_id: Uuid,
+        destination: &Address,
+    ) -> Result<Option<IncomingPayment>, BalanceStoreError> {
+        let _timer = self.timers.start("acknowledge_incoming_payment");
+        let fields: Option<(u64, u8, u8, u64)> = ACKNOWLEDGE_INCOMING_PAYMENT
+            .arg(incoming_payments_key(&self.db_prefix, account_id))
+            .arg(unacknowledged_payments_key(&self.db_prefix, account_id))
+            .arg(destination.to_string())
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(fields.map(|fields| incoming_payment(account_id, destination.clone(), fields)))
+    }
+
+    async fn get_unacknowledged_payments(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Vec<IncomingPayment>, BalanceStoreError> {
+        let _timer = self.timers.start("get_unacknowledged_payments");
+        let mut connection = self.connection.clone();
+        let destinations: Vec<String> = connection
+            .zrange(
+                unacknowledged_payments_key(&self.db_prefix, account_id),
+                0,
+                -1,
+            )
+            .await?;
+        if destinations.is_empty() {
+            return Ok(Vec::new());
+        }
+        let payments_key = incoming_payments_key(&self.db_prefix, account_id);
+        let mut pipe = redis_crate::pipe();
+        for destination in destinations.iter() {
+            pipe.cmd("HMGET")
+                .arg(&payments_key)
+                .arg(format!("{}:amount", destination))
+                .arg(format!("{}:closed", destination))
+                .arg(format!("{}:acknowledged", destination))
+                .arg(format!("{}:updated_at", destination));
+        }
+        let fields: Vec<(u64, u8, u8, u64)> = pipe.query_async(&mut connection).await?;
+        destinations
+            .into_iter()
+            .zip(fields)
+            .map(|(destination, fields)| {
+                let destination = Address::from_str(&destination)
+                    .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+                Ok(incoming_payment(account_id, destination, fields))
+            })
+            .collect()
+    }
+}
+
 #[async_trait]
 impl BalanceStore for RedisStore {
     /// Returns the balance **from the account holder's perspective**, meaning the sum of
     /// the Payable Balance and Pending Outgoing minus the Receivable Balance and the Pending Incoming.
     async fn get_balance(&self, account_id: Uuid) -> Result<i64, BalanceStoreError> {
+        let _timer = self.timers.start("get_balance");
         let values: Vec<i64> = self
             .connection
             .clone()
@@ -862,7 +1456,9 @@ impl BalanceStore for RedisStore {
         &self,
         from_account_id: Uuid,
         incoming_amount: u64,
+        packet_id: Option<PacketId>,
     ) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("update_balances_for_prepare");
         // Don't do anything if the amount was 0
         if incoming_amount == 0 {
             return Ok(());
@@ -872,6 +1468,9 @@ impl BalanceStore for RedisStore {
             .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
             .arg(RedisAccountId(from_account_id))
             .arg(incoming_amount)
+            .arg(balance_log_key(&self.db_prefix, from_account_id))
+            .arg(now_millis())
+            .arg(packet_id.map(hex::encode).unwrap_or_default())
             .invoke_async(&mut self.connection.clone())
             .await?;
 
@@ -886,29 +1485,38 @@ impl BalanceStore for RedisStore {
         &self,
         to_account_id: Uuid,
         outgoing_amount: u64,
+        packet_id: Option<PacketId>,
     ) -> Result<(i64, u64), BalanceStoreError> {
-        let (balance, amount_to_settle): (i64, u64) = PROCESS_FULFILL
-            .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
-            .arg(RedisAccountId(to_account_id))
-            .arg(outgoing_amount)
-            .invoke_async(&mut self.connection.clone())
-            .await?;
+        let _timer = self.timers.start("update_balances_for_fulfill");
+        self.redis_process_fulfill(to_account_id, outgoing_amount, packet_id, "")
+            .await
+    }
 
-        trace!(
-            "Processed fulfill for account {} for outgoing amount {}. Fulfill call result: {} {}",
+    async fn update_balances_for_fulfill_and_queue_settlement(
+        &self,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+        packet_id: Option<PacketId>,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        let _timer = self
+            .timers
+            .start("update_balances_for_fulfill_and_queue_settlement");
+        self.redis_process_fulfill(
             to_account_id,
             outgoing_amount,
-            balance,
-            amount_to_settle,
-        );
-        Ok((balance, amount_to_settle))
+            packet_id,
+            &prefixed_key(&self.db_prefix, QUEUED_SETTLEMENTS_KEY),
+        )
+        .await
     }
 
     async fn update_balances_for_reject(
         &self,
         from_account_id: Uuid,
         incoming_amount: u64,
+        packet_id: Option<PacketId>,
     ) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("update_balances_for_reject");
         if incoming_amount == 0 {
             return Ok(());
         }
@@ -917,6 +1525,9 @@ impl BalanceStore for RedisStore {
             .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
             .arg(RedisAccountId(from_account_id))
             .arg(incoming_amount)
+            .arg(balance_log_key(&self.db_prefix, from_account_id))
+            .arg(now_millis())
+            .arg(packet_id.map(hex::encode).unwrap_or_default())
             .invoke_async(&mut self.connection.clone())
             .await?;
 
@@ -932,9 +1543,13 @@ impl BalanceStore for RedisStore {
         &self,
         to_account_id: Uuid,
     ) -> Result<(i64, u64), BalanceStoreError> {
+        let _timer = self.timers.start("update_balances_for_delayed_settlement");
         let (balance, amount_to_settle): (i64, u64) = PROCESS_DELAYED_SETTLEMENT
             .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
             .arg(RedisAccountId(to_account_id))
+            .arg(balance_log_key(&self.db_prefix, to_account_id))
+            .arg(now_millis())
+            .arg(&*prefixed_key(&self.db_prefix, QUEUED_SETTLEMENTS_KEY))
             .invoke_async(&mut self.connection.clone())
             .await?;
 
@@ -947,6 +1562,245 @@ impl BalanceStore for RedisStore {
 
         Ok((balance, amount_to_settle))
     }
+
+    async fn get_balance_changes(
+        &self,
+        account_id: Uuid,
+        from: u64,
+        limit: usize,
+    ) -> Result<Vec<BalanceChange>, BalanceStoreError> {
+        let _timer = self.timers.start("get_balance_changes");
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        // LRANGE treats negative indices as offsets from the end of the list
+        let start = from.min(isize::MAX as u64) as isize;
+        let stop = from.saturating_add(limit as u64 - 1).min(isize::MAX as u64) as isize;
+        let entries: Vec<String> = self
+            .connection
+            .clone()
+            .lrange(balance_log_key(&self.db_prefix, account_id), start, stop)
+            .await?;
+        Ok(entries
+            .iter()
+            .zip(from..)
+            .filter_map(|(entry, sequence)| {
+                let change = parse_balance_change(account_id, sequence, entry);
+                if change.is_none() {
+                    warn!("Ignoring invalid balance change log entry: {}", entry);
+                }
+                change
+            })
+            .collect())
+    }
+
+    async fn get_balance_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<BalanceTotals, BalanceStoreError> {
+        let _timer = self.timers.start("get_balance_totals");
+        let sums: HashMap<String, i64> = self
+            .connection
+            .clone()
+            .hgetall(balance_totals_key(&self.db_prefix, account_id))
+            .await?;
+        let mut totals = BalanceTotals::default();
+        for (reason, sum) in sums {
+            match BalanceChangeReason::from_str(&reason) {
+                Ok(reason) => totals.add(reason, sum),
+                Err(_) => warn!("Ignoring balance total with invalid reason: {}", reason),
+            }
+        }
+        Ok(totals)
+    }
+}
+
+#[async_trait]
+impl PacketJournalStore for RedisStore {
+    async fn journal_packet(&self, packet: JournaledPacket) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("journal_packet");
+        let serialized = serde_json::to_string(&packet)
+            .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+        self.connection
+            .clone()
+            .hset::<_, _, _, ()>(
+                &*prefixed_key(&self.db_prefix, PACKET_JOURNAL_KEY),
+                packet.id.to_string(),
+                serialized,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn mark_journaled_packet_fulfilled(&self, id: Uuid) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("mark_journaled_packet_fulfilled");
+        // The flag is kept apart from the packet so that it can be set without reading it
+        self.connection
+            .clone()
+            .sadd::<_, _, ()>(
+                &*prefixed_key(&self.db_prefix, FULFILLED_PACKETS_KEY),
+                id.to_string(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_journaled_packet(&self, id: Uuid) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("remove_journaled_packet");
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic()
+            .hdel(
+                &*prefixed_key(&self.db_prefix, PACKET_JOURNAL_KEY),
+                id.to_string(),
+            )
+            .ignore()
+            .srem(
+                &*prefixed_key(&self.db_prefix, FULFILLED_PACKETS_KEY),
+                id.to_string(),
+            )
+            .ignore();
+        pipe.query_async::<_, ()>(&mut self.connection.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn load_journaled_packets(&self) -> Result<Vec<JournaledPacket>, BalanceStoreError> {
+        let _timer = self.timers.start("load_journaled_packets");
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic()
+            .hvals(&*prefixed_key(&self.db_prefix, PACKET_JOURNAL_KEY))
+            .smembers(&*prefixed_key(&self.db_prefix, FULFILLED_PACKETS_KEY));
+        let (serialized, fulfilled): (Vec<String>, HashSet<String>) =
+            pipe.query_async(&mut self.connection.clone()).await?;
+        serialized
+            .iter()
+            .map(|packet| {
+                let mut packet: JournaledPacket = serde_json::from_str(packet)
+                    .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+                packet.fulfilled = fulfilled.contains(&packet.id.to_string());
+                Ok(packet)
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl PacketHistoryStore for RedisStore {
+    async fn record_packets(&self, records: Vec<PacketRecord>) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("record_packets");
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut connection = self.connection.clone();
+        let last_id: u64 = connection
+            .incr(
+                &*prefixed_key(&self.db_prefix, PACKET_HISTORY_ID_KEY),
+                records.len() as u64,
+            )
+            .await?;
+        let first_id = last_id + 1 - records.len() as u64;
+
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        for (mut record, id) in records.into_iter().zip(first_id..) {
+            record.id = id;
+            pipe.zadd(
+                packet_history_key(&self.db_prefix, record.account_id),
+                format_packet_record(&record),
+                id,
+            )
+            .ignore();
+            pipe.sadd(
+                &*prefixed_key(&self.db_prefix, PACKET_HISTORY_ACCOUNTS_KEY),
+                RedisAccountId(record.account_id),
+            )
+            .ignore();
+        }
+        pipe.query_async::<_, ()>(&mut connection).await?;
+        Ok(())
+    }
+
+    async fn get_packet_history(
+        &self,
+        account_id: Uuid,
+        filter: &PacketHistoryFilter,
+        limit: usize,
+    ) -> Result<Vec<PacketRecord>, BalanceStoreError> {
+        let _timer = self.timers.start("get_packet_history");
+        let key = packet_history_key(&self.db_prefix, account_id);
+        let mut connection = self.connection.clone();
+        // The entries are read newest first, in batches, until enough of them match the filter
+        let mut max = match filter.before {
+            Some(before) => format!("({}", before),
+            None => "+inf".to_string(),
+        };
+        let mut records = Vec::new();
+        while records.len() < limit {
+            let entries: Vec<String> = connection
+                .zrevrangebyscore_limit(&key, &max, "-inf", 0, PACKET_HISTORY_BATCH_SIZE as isize)
+                .await?;
+            for entry in &entries {
+                let record = match parse_packet_record(account_id, entry) {
+                    Some(record) => record,
+                    None => {
+                        warn!("Ignoring invalid packet history entry: {}", entry);
+                        continue;
+                    }
+                };
+                max = format!("({}", record.id);
+                if filter.matches(&record) {
+                    records.push(record);
+                    if records.len() == limit {
+                        break;
+                    }
+                }
+            }
+            if entries.len() < PACKET_HISTORY_BATCH_SIZE {
+                break;
+            }
+        }
+        Ok(records)
+    }
+
+    async fn prune_packet_history(&self, older_than: u64) -> Result<u64, BalanceStoreError> {
+        let _timer = self.timers.start("prune_packet_history");
+        let accounts_key = prefixed_key(&self.db_prefix, PACKET_HISTORY_ACCOUNTS_KEY);
+        let mut connection = self.connection.clone();
+        let account_ids: Vec<RedisAccountId> = connection.smembers(&*accounts_key).await?;
+        let mut deleted = 0;
+        for account_id in account_ids {
+            let key = packet_history_key(&self.db_prefix, account_id.0);
+            // The oldest entries come first, as the ids of the records increase with time
+            loop {
+                let entries: Vec<String> = connection
+                    .zrange(&key, 0, PACKET_HISTORY_BATCH_SIZE as isize - 1)
+                    .await?;
+                let expired = entries
+                    .iter()
+                    .take_while(|entry| {
+                        parse_packet_record(account_id.0, entry)
+                            .map_or(true, |record| record.timestamp < older_than)
+                    })
+                    .count();
+                if expired > 0 {
+                    connection
+                        .zremrangebyrank::<_, ()>(&key, 0, expired as isize - 1)
+                        .await?;
+                    deleted += expired as u64;
+                }
+                if expired < PACKET_HISTORY_BATCH_SIZE {
+                    break;
+                }
+            }
+            let remaining: u64 = connection.zcard(&key).await?;
+            if remaining == 0 {
+                connection
+                    .srem::<_, _, ()>(&*accounts_key, account_id)
+                    .await?;
+            }
+        }
+        Ok(deleted)
+    }
 }
 
 impl ExchangeRateStore for RedisStore {
@@ -975,9 +1829,20 @@ impl ExchangeRateStore for RedisStore {
         rates: HashMap<String, f64>,
     ) -> Result<(), ExchangeRateStoreError> {
         // TODO publish rate updates through a pubsub mechanism to support horizontally scaling nodes
+        let now = SystemTime::now();
+        (*self.exchange_rates_updated_at.write()) =
+            rates.keys().map(|code| (code.clone(), now)).collect();
         (*self.exchange_rates.write()) = rates;
         Ok(())
     }
+
+    fn get_exchange_rates_updated_at(&self, asset_codes: &[&str]) -> Option<SystemTime> {
+        let updated_at = self.exchange_rates_updated_at.read();
+        asset_codes
+            .iter()
+            .filter_map(|code| updated_at.get(*code).cloned())
+            .min()
+    }
 }
 
 #[async_trait]
@@ -989,43 +1854,19 @@ impl BtpStore for RedisStore {
         username: &Username,
         token: &str,
     ) -> Result<Self::Account, BtpStoreError> {
-        // TODO make sure it can't do script injection!
-        // TODO cache the result so we don't hit redis for every packet (is that
-        // necessary if redis is often used as a cache?)
-        let account: Option<AccountWithEncryptedTokens> = ACCOUNT_FROM_USERNAME
-            .arg(&*prefixed_key(&self.db_prefix, USERNAMES_KEY))
-            .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
-            .arg(username.as_ref())
-            .invoke_async(&mut self.connection.clone())
-            .await?;
-
-        if let Some(account) = account {
-            let account = account.decrypt_tokens(&self.decryption_key.expose_secret().0);
-            if let Some(ref t) = account.ilp_over_btp_incoming_token {
-                let t = t.expose_secret();
-                if t.as_ref() == token.as_bytes() {
-                    Ok(account)
-                } else {
-                    debug!(
-                        "Found account {} but BTP auth token was wrong",
-                        account.username
-                    );
-                    Err(BtpStoreError::Unauthorized(username.to_string()))
-                }
-            } else {
-                debug!(
-                    "Account {} does not have an incoming btp token configured",
-                    account.username
-                );
-                Err(BtpStoreError::Unauthorized(username.to_string()))
-            }
-        } else {
-            warn!("No account found with BTP token");
-            Err(BtpStoreError::AccountNotFound(username.to_string()))
-        }
+        let _timer = self.timers.start("get_account_from_btp_auth");
+        let account = self.get_account_by_username(username).await?;
+        self.verified_tokens
+            .authenticate(AuthProtocol::Btp, username, account, token)
+            .await
+            .map_err(|err| match err {
+                AuthError::AccountNotFound => BtpStoreError::AccountNotFound(username.to_string()),
+                _ => BtpStoreError::Unauthorized(username.to_string()),
+            })
     }
 
     async fn get_btp_outgoing_accounts(&self) -> Result<Vec<Self::Account>, BtpStoreError> {
+        let _timer = self.timers.start("get_btp_outgoing_accounts");
         let account_ids: Vec<RedisAccountId> = self
             .connection
             .clone()
@@ -1053,30 +1894,15 @@ impl HttpStore for RedisStore {
         username: &Username,
         token: &str,
     ) -> Result<Self::Account, HttpStoreError> {
-        // TODO make sure it can't do script injection!
-        let account: Option<AccountWithEncryptedTokens> = ACCOUNT_FROM_USERNAME
-            .arg(&*prefixed_key(&self.db_prefix, USERNAMES_KEY))
-            .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
-            .arg(username.as_ref())
-            .invoke_async(&mut self.connection.clone())
-            .await?;
-
-        if let Some(account) = account {
-            let account = account.decrypt_tokens(&self.decryption_key.expose_secret().0);
-            if let Some(ref t) = account.ilp_over_http_incoming_token {
-                let t = t.expose_secret();
-                if t.as_ref() == token.as_bytes() {
-                    Ok(account)
-                } else {
-                    Err(HttpStoreError::Unauthorized(username.to_string()))
-                }
-            } else {
-                Err(HttpStoreError::Unauthorized(username.to_string()))
-            }
-        } else {
-            warn!("No account found with given HTTP auth");
-            Err(HttpStoreError::AccountNotFound(username.to_string()))
-        }
+        let _timer = self.timers.start("get_account_from_http_auth");
+        let account = self.get_account_by_username(username).await?;
+        self.verified_tokens
+            .authenticate(AuthProtocol::Http, username, account, token)
+            .await
+            .map_err(|err| match err {
+                AuthError::AccountNotFound => HttpStoreError::AccountNotFound(username.to_string()),
+                _ => HttpStoreError::Unauthorized(username.to_string()),
+            })
     }
 }
 
@@ -1094,6 +1920,7 @@ impl NodeStore for RedisStore {
         &self,
         account: AccountDetails,
     ) -> Result<Self::Account, NodeStoreError> {
+        let _timer = self.timers.start("insert_account");
         let id = Uuid::new_v4();
         let account = Account::try_from(id, account, self.get_ilp_address())
             .map_err(NodeStoreError::InvalidAccount)?;
@@ -1109,7 +1936,70 @@ impl NodeStore for RedisStore {
         Ok(account)
     }
 
+    async fn insert_accounts(
+        &self,
+        accounts: Vec<AccountDetails>,
+    ) -> Result<Vec<Self::Account>, NodeStoreError> {
+        let _timer = self.timers.start("insert_accounts");
+        let mut errors = Vec::new();
+        let mut valid = Vec::with_capacity(accounts.len());
+        for (index, details) in accounts.into_iter().enumerate() {
+            match Account::try_from(Uuid::new_v4(), details, self.get_ilp_address()) {
+                Ok(account) => valid.push((index, account)),
+                Err(err) => errors.push((index, NodeStoreError::InvalidAccount(err))),
+            }
+        }
+
+        // Redis cannot check and write the whole batch atomically, so the values which MUST be
+        // unique are checked before any account is written, and the accounts which were
+        // written are deleted again if writing another one fails
+        let mut pipe = redis_crate::pipe();
+        for (_, account) in valid.iter() {
+            pipe.hexists(
+                &*prefixed_key(&self.db_prefix, USERNAMES_KEY),
+                account.username().as_ref(),
+            );
+        }
+        let results: Vec<bool> = pipe.query_async(&mut self.connection.clone()).await?;
+        let mut usernames = HashSet::new();
+        for ((index, account), exists) in valid.iter().zip(results.iter()) {
+            if *exists || !usernames.insert(account.username.to_string()) {
+                errors.push((
+                    *index,
+                    NodeStoreError::AccountExists(account.username.to_string()),
+                ));
+            }
+        }
+        if !errors.is_empty() {
+            errors.sort_by_key(|(index, _)| *index);
+            warn!(
+                "Cannot insert the batch of accounts, {} of them are invalid",
+                errors.len()
+            );
+            return Err(NodeStoreError::InvalidAccounts(errors));
+        }
+
+        let mut inserted = Vec::with_capacity(valid.len());
+        for (_, account) in valid.iter() {
+            let encrypted = account
+                .clone()
+                .encrypt_tokens(&self.encryption_key.expose_secret().0);
+            if let Err(err) = self.redis_insert_account(&encrypted).await {
+                for id in inserted {
+                    if let Err(err) = self.redis_delete_account(id).await {
+                        error!("Error deleting account {} of a failed batch: {}", id, err);
+                    }
+                }
+                return Err(err);
+            }
+            inserted.push(account.id);
+        }
+        debug!("Inserted a batch of {} accounts", valid.len());
+        Ok(valid.into_iter().map(|(_, account)| account).collect())
+    }
+
     async fn delete_account(&self, id: Uuid) -> Result<Account, NodeStoreError> {
+        let _timer = self.timers.start("delete_account");
         let account = self.redis_delete_account(id).await?;
         Ok(account.decrypt_tokens(&self.decryption_key.expose_secret().0))
     }
@@ -1119,6 +2009,7 @@ impl NodeStore for RedisStore {
         id: Uuid,
         account: AccountDetails,
     ) -> Result<Self::Account, NodeStoreError> {
+        let _timer = self.timers.start("update_account");
         let account = Account::try_from(id, account, self.get_ilp_address())
             .map_err(NodeStoreError::InvalidAccount)?;
 
@@ -1139,25 +2030,19 @@ impl NodeStore for RedisStore {
         id: Uuid,
         settings: AccountSettings,
     ) -> Result<Self::Account, NodeStoreError> {
+        let _timer = self.timers.start("modify_account_settings");
         let settings = EncryptedAccountSettings {
             settle_to: settings.settle_to,
             settle_threshold: settings.settle_threshold,
+            settle_every: settings.settle_every,
             ilp_over_btp_url: settings.ilp_over_btp_url,
             ilp_over_http_url: settings.ilp_over_http_url,
-            ilp_over_btp_incoming_token: settings.ilp_over_btp_incoming_token.map(|token| {
-                encrypt_token(
-                    &self.encryption_key.expose_secret().0,
-                    token.expose_secret().as_bytes(),
-                )
-                .freeze()
-            }),
-            ilp_over_http_incoming_token: settings.ilp_over_http_incoming_token.map(|token| {
-                encrypt_token(
-                    &self.encryption_key.expose_secret().0,
-                    token.expose_secret().as_bytes(),
-                )
-                .freeze()
-            }),
+            ilp_over_btp_incoming_token: settings
+                .ilp_over_btp_incoming_token
+                .map(|token| Bytes::from(hash_token(token.expose_secret().as_bytes()))),
+            ilp_over_http_incoming_token: settings
+                .ilp_over_http_incoming_token
+                .map(|token| Bytes::from(hash_token(token.expose_secret().as_bytes()))),
             ilp_over_btp_outgoing_token: settings.ilp_over_btp_outgoing_token.map(|token| {
                 encrypt_token(
                     &self.encryption_key.expose_secret().0,
@@ -1180,6 +2065,7 @@ impl NodeStore for RedisStore {
 
     // TODO limit the number of results and page through them
     async fn get_all_accounts(&self) -> Result<Vec<Self::Account>, NodeStoreError> {
+        let _timer = self.timers.start("get_all_accounts");
         let mut connection = self.connection.clone();
 
         let account_ids = self.get_all_accounts_ids().await?;
@@ -1203,10 +2089,56 @@ impl NodeStore for RedisStore {
         Ok(accounts)
     }
 
+    async fn list_accounts(
+        &self,
+        after: Option<Uuid>,
+        limit: usize,
+        filter: AccountFilter,
+    ) -> Result<AccountPage<Self::Account>, NodeStoreError> {
+        let _timer = self.timers.start("list_accounts");
+        let mut account_ids = self.get_all_accounts_ids().await?;
+        account_ids.sort_unstable();
+        if let Some(after) = after {
+            account_ids.retain(|id| *id > after);
+        }
+
+        // Load the accounts in batches until one more than a full page matches the
+        // filter, so that we know whether there is a next page
+        let mut accounts = Vec::new();
+        for ids in account_ids.chunks(limit.saturating_add(1)) {
+            let mut script = LOAD_ACCOUNTS.prepare_invoke();
+            script.arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY));
+            script.arg(&*prefixed_key(&self.db_prefix, SETTLEMENT_ENGINES_KEY));
+            for id in ids {
+                script.arg(id.to_string());
+            }
+            let batch: Vec<AccountWithEncryptedTokens> =
+                script.invoke_async(&mut self.connection.clone()).await?;
+            accounts.extend(
+                batch
+                    .into_iter()
+                    .map(|account| account.decrypt_tokens(&self.decryption_key.expose_secret().0))
+                    .filter(|account| filter.matches(account)),
+            );
+            if accounts.len() > limit {
+                break;
+            }
+        }
+
+        let next = if accounts.len() > limit {
+            accounts.truncate(limit);
+            accounts.last().map(|account| account.id())
+        } else {
+            None
+        };
+        Ok(AccountPage { accounts, next })
+    }
+
     async fn set_static_routes<R>(&self, routes: R) -> Result<(), NodeStoreError>
     where
         R: IntoIterator<Item = (String, Uuid)> + Send + 'async_trait,
     {
+        let _timer = self.timers.start("set_static_routes");
         let mut connection = self.connection.clone();
         let routes: Vec<(String, RedisAccountId)> = routes
             .into_iter()
@@ -1246,6 +2178,7 @@ impl NodeStore for RedisStore {
         prefix: String,
         account_id: Uuid,
     ) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("set_static_route");
         let routing_table = self.routes.clone();
         let mut connection = self.connection.clone();
 
@@ -1274,6 +2207,7 @@ impl NodeStore for RedisStore {
     }
 
     async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("set_default_route");
         let routing_table = self.routes.clone();
         // TODO replace this with a lua script to do both calls at once
         let mut connection = self.connection.clone();
@@ -1288,62 +2222,223 @@ impl NodeStore for RedisStore {
             return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
         }
 
-        connection
-            .set(
-                &*prefixed_key(&self.db_prefix, DEFAULT_ROUTE_KEY),
-                RedisAccountId(account_id),
-            )
-            .await?;
-        debug!("Set default route to account id: {}", account_id);
-        update_routes(connection, routing_table, &self.db_prefix).await?;
+        connection
+            .set(
+                &*prefixed_key(&self.db_prefix, DEFAULT_ROUTE_KEY),
+                RedisAccountId(account_id),
+            )
+            .await?;
+        debug!("Set default route to account id: {}", account_id);
+        update_routes(connection, routing_table, &self.db_prefix).await?;
+        Ok(())
+    }
+
+    async fn set_settlement_engines(
+        &self,
+        asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
+    ) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("set_settlement_engines");
+        let mut connection = self.connection.clone();
+        let asset_to_url_map: Vec<(String, String)> = asset_to_url_map
+            .into_iter()
+            .map(|(asset_code, url)| (asset_code, url.to_string()))
+            .collect();
+        debug!("Setting settlement engines to {:?}", asset_to_url_map);
+        connection
+            .hset_multiple(
+                &*prefixed_key(&self.db_prefix, SETTLEMENT_ENGINES_KEY),
+                &asset_to_url_map,
+            )
+            .await?;
+        // Accounts without their own engine url are loaded with the one of their asset
+        self.clear_account_cache();
+        Ok(())
+    }
+
+    async fn get_asset_settlement_engine(
+        &self,
+        asset_code: &str,
+    ) -> Result<Option<Url>, NodeStoreError> {
+        let _timer = self.timers.start("get_asset_settlement_engine");
+        let url: Option<String> = self
+            .connection
+            .clone()
+            .hget(
+                &*prefixed_key(&self.db_prefix, SETTLEMENT_ENGINES_KEY),
+                asset_code,
+            )
+            .await?;
+        if let Some(url) = url {
+            match Url::parse(url.as_str()) {
+                Ok(url) => Ok(Some(url)),
+                Err(err) => {
+                    error!(
+                        "Settlement engine URL loaded from Redis was not a valid URL: {:?}",
+                        err
+                    );
+                    Err(NodeStoreError::InvalidEngineUrl(err.to_string()))
+                }
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn export_node(&self) -> Result<NodeExport, NodeStoreError> {
+        let _timer = self.timers.start("export_node");
+        let accounts = self.get_all_accounts().await?;
+        let mut connection = self.connection.clone();
+
+        let balances: Vec<(Option<i64>, Option<i64>)> = if accounts.is_empty() {
+            Vec::new()
+        } else {
+            let mut pipe = redis_crate::pipe();
+            for account in accounts.iter() {
+                pipe.cmd("HMGET")
+                    .arg(accounts_key(&self.db_prefix, account.id))
+                    .arg("balance")
+                    .arg("prepaid_amount");
+            }
+            pipe.query_async(&mut connection).await?
+        };
+        let accounts = accounts
+            .iter()
+            .zip(balances.into_iter())
+            .map(|(account, (balance, prepaid_amount))| ExportedAccount {
+                id: account.id,
+                details: account.to_details(),
+                balance: balance.unwrap_or(0),
+                prepaid_amount: prepaid_amount.unwrap_or(0),
+            })
+            .collect();
+
+        let static_routes: HashMap<String, RedisAccountId> = connection
+            .hgetall(&*prefixed_key(&self.db_prefix, STATIC_ROUTES_KEY))
+            .await?;
+        let default_route: Option<RedisAccountId> = connection
+            .get(&*prefixed_key(&self.db_prefix, DEFAULT_ROUTE_KEY))
+            .await?;
+        let settlement_engines: HashMap<String, String> = connection
+            .hgetall(&*prefixed_key(&self.db_prefix, SETTLEMENT_ENGINES_KEY))
+            .await?;
+        let settlement_engines = settlement_engines
+            .into_iter()
+            .map(|(asset_code, url)| {
+                Url::parse(&url)
+                    .map(|url| (asset_code, url))
+                    .map_err(|err| NodeStoreError::InvalidEngineUrl(err.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        let ilp_address: Option<String> = connection
+            .get(&*prefixed_key(&self.db_prefix, PARENT_ILP_KEY))
+            .await?;
+
+        Ok(NodeExport {
+            ilp_address: ilp_address.and_then(|address| Address::from_str(&address).ok()),
+            accounts,
+            static_routes: static_routes
+                .into_iter()
+                .map(|(prefix, id)| (prefix, id.0))
+                .collect(),
+            default_route: default_route.map(|id| id.0),
+            settlement_engines,
+        })
+    }
+
+    async fn import_node(&self, export: NodeExport) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("import_node");
+        if !self.get_all_accounts_ids().await?.is_empty() {
+            warn!("Cannot import into a store which already contains accounts");
+            return Err(NodeStoreError::NotEmpty);
+        }
+
+        let mut pipe = redis_crate::pipe();
+        for exported in export.accounts.iter() {
+            let account = Account::try_from(
+                exported.id,
+                exported.details.clone(),
+                self.get_ilp_address(),
+            )
+            .map_err(NodeStoreError::InvalidAccount)?;
+            let encrypted = account.encrypt_tokens(&self.encryption_key.expose_secret().0);
+            self.redis_insert_account(&encrypted).await?;
+            pipe.hset_multiple(
+                accounts_key(&self.db_prefix, exported.id),
+                &[
+                    ("balance", exported.balance),
+                    ("prepaid_amount", exported.prepaid_amount),
+                ],
+            )
+            .ignore();
+        }
+        if !export.accounts.is_empty() {
+            pipe.query_async::<_, ()>(&mut self.connection.clone())
+                .await?;
+        }
+
+        if !export.static_routes.is_empty() {
+            self.set_static_routes(export.static_routes).await?;
+        }
+        if let Some(account_id) = export.default_route {
+            self.set_default_route(account_id).await?;
+        }
+        if !export.settlement_engines.is_empty() {
+            self.set_settlement_engines(export.settlement_engines)
+                .await?;
+        }
+        if let Some(ilp_address) = export.ilp_address {
+            self.set_ilp_address(ilp_address)
+                .await
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+        }
+        debug!("Imported {} accounts", export.accounts.len());
         Ok(())
     }
 
-    async fn set_settlement_engines(
+    async fn get_secret_generation(&self) -> Result<SecretGeneration, NodeStoreError> {
+        let _timer = self.timers.start("get_secret_generation");
+        let serialized: Option<String> = self
+            .connection
+            .clone()
+            .get(&*prefixed_key(&self.db_prefix, SECRET_GENERATION_KEY))
+            .await?;
+        match serialized {
+            Some(serialized) => serde_json::from_str(&serialized)
+                .map_err(|err| NodeStoreError::Other(Box::new(err))),
+            None => Ok(SecretGeneration::default()),
+        }
+    }
+
+    async fn set_secret_generation(
         &self,
-        asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
+        generation: SecretGeneration,
     ) -> Result<(), NodeStoreError> {
-        let mut connection = self.connection.clone();
-        let asset_to_url_map: Vec<(String, String)> = asset_to_url_map
-            .into_iter()
-            .map(|(asset_code, url)| (asset_code, url.to_string()))
-            .collect();
-        debug!("Setting settlement engines to {:?}", asset_to_url_map);
-        connection
-            .hset_multiple(
-                &*prefixed_key(&self.db_prefix, SETTLEMENT_ENGINES_KEY),
-                &asset_to_url_map,
+        let _timer = self.timers.start("set_secret_generation");
+        let serialized = serde_json::to_string(&generation)
+            .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+        self.connection
+            .clone()
+            .set::<_, _, ()>(
+                &*prefixed_key(&self.db_prefix, SECRET_GENERATION_KEY),
+                serialized,
             )
             .await?;
         Ok(())
     }
 
-    async fn get_asset_settlement_engine(
+    async fn claim_leadership(
         &self,
-        asset_code: &str,
-    ) -> Result<Option<Url>, NodeStoreError> {
-        let url: Option<String> = self
-            .connection
-            .clone()
-            .hget(
-                &*prefixed_key(&self.db_prefix, SETTLEMENT_ENGINES_KEY),
-                asset_code,
-            )
+        node_id: &str,
+        lease: Duration,
+    ) -> Result<bool, NodeStoreError> {
+        let _timer = self.timers.start("claim_leadership");
+        let claimed: bool = CLAIM_LEADERSHIP
+            .arg(&*prefixed_key(&self.db_prefix, LEADER_KEY))
+            .arg(node_id)
+            .arg(lease.as_millis() as u64)
+            .invoke_async(&mut self.connection.clone())
             .await?;
-        if let Some(url) = url {
-            match Url::parse(url.as_str()) {
-                Ok(url) => Ok(Some(url)),
-                Err(err) => {
-                    error!(
-                        "Settlement engine URL loaded from Redis was not a valid URL: {:?}",
-                        err
-                    );
-                    Err(NodeStoreError::InvalidEngineUrl(err.to_string()))
-                }
-            }
-        } else {
-            Ok(None)
-        }
+        Ok(claimed)
     }
 }
 
@@ -1352,6 +2447,7 @@ impl AddressStore for RedisStore {
     // Updates the ILP address of the store & iterates over all children and
     // updates their ILP Address to match the new address.
     async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+        let _timer = self.timers.start("set_ilp_address");
         debug!("Setting ILP address to: {}", ilp_address);
         let routing_table = self.routes.clone();
         let mut connection = self.connection.clone();
@@ -1421,11 +2517,14 @@ impl AddressStore for RedisStore {
         }
 
         pipe.query_async(&mut connection.clone()).await?;
+        // The addresses of the children have changed
+        self.clear_account_cache();
         update_routes(connection, routing_table, &self.db_prefix).await?;
         Ok(())
     }
 
     async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        let _timer = self.timers.start("clear_ilp_address");
         self.connection
             .clone()
             .del(&*prefixed_key(&self.db_prefix, PARENT_ILP_KEY))
@@ -1453,6 +2552,7 @@ impl CcpRoutingStore for RedisStore {
         &self,
         ignore_accounts: Vec<Uuid>,
     ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        let _timer = self.timers.start("get_accounts_to_send_routes_to");
         let account_ids: Vec<RedisAccountId> = self
             .connection
             .clone()
@@ -1474,6 +2574,7 @@ impl CcpRoutingStore for RedisStore {
     async fn get_accounts_to_receive_routes_from(
         &self,
     ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        let _timer = self.timers.start("get_accounts_to_receive_routes_from");
         let account_ids: Vec<RedisAccountId> = self
             .connection
             .clone()
@@ -1492,6 +2593,7 @@ impl CcpRoutingStore for RedisStore {
     async fn get_local_and_configured_routes(
         &self,
     ) -> Result<(RoutingTable<Account>, RoutingTable<Account>), CcpRoutingStoreError> {
+        let _timer = self.timers.start("get_local_and_configured_routes");
         let static_routes: Vec<(String, RedisAccountId)> = self
             .connection
             .clone()
@@ -1531,6 +2633,7 @@ impl CcpRoutingStore for RedisStore {
         &mut self,
         routes: impl IntoIterator<Item = (String, Account)> + Send + 'async_trait,
     ) -> Result<(), CcpRoutingStoreError> {
+        let _timer = self.timers.start("set_routes");
         let routes: Vec<(String, RedisAccountId)> = routes
             .into_iter()
             .map(|(prefix, account)| (prefix, RedisAccountId(account.id)))
@@ -1560,94 +2663,272 @@ impl RateLimitStore for RedisStore {
 
     /// Apply rate limits for number of packets per minute and amount of money per minute
     ///
-    /// This uses https://github.com/brandur/redis-cell so the redis-cell module MUST be loaded into redis before this is run
+    /// The limits are token buckets stored in Redis, so they are shared by all the nodes
+    /// using the same Redis instance
     async fn apply_rate_limits(
         &self,
         account: Account,
         prepare_amount: u64,
     ) -> Result<(), RateLimitError> {
-        if account.amount_per_minute_limit.is_some() || account.packets_per_minute_limit.is_some() {
-            let mut pipe = redis_crate::pipe();
-            let packet_limit = account.packets_per_minute_limit.is_some();
-            let amount_limit = account.amount_per_minute_limit.is_some();
-
-            if let Some(limit) = account.packets_per_minute_limit {
-                let limit = limit - 1;
-                let packets_limit =
-                    prefixed_key(&self.db_prefix, &format!("limit:packets:{}", account.id))
-                        .into_owned();
-                pipe.cmd("CL.THROTTLE")
-                    .arg(&packets_limit)
-                    .arg(limit)
-                    .arg(limit)
-                    .arg(60)
-                    .arg(1);
-            }
+        let _timer = self.timers.start("apply_rate_limits");
+        if account.amount_per_minute_limit.is_none() && account.packets_per_minute_limit.is_none() {
+            return Ok(());
+        }
+        let (packets_key, packets_limit) = throttle_args(
+            &self.db_prefix,
+            "packets",
+            account.id,
+            account.packets_per_minute_limit.map(u64::from),
+        );
+        let (throughput_key, throughput_limit) = throttle_args(
+            &self.db_prefix,
+            "throughput",
+            account.id,
+            account.amount_per_minute_limit,
+        );
+        let result: i64 = RATE_LIMITS
+            .arg(packets_key)
+            .arg(packets_limit)
+            .arg(throughput_key)
+            .arg(throughput_limit)
+            .arg(prepare_amount)
+            .arg(now_millis())
+            .invoke_async(&mut self.connection.clone())
+            .map_err(|err| {
+                error!("Error applying rate limits: {:?}", err);
+                RateLimitError::StoreError
+            })
+            .await?;
+        match result {
+            0 => Ok(()),
+            1 => Err(RateLimitError::PacketLimitExceeded),
+            _ => Err(RateLimitError::ThroughputLimitExceeded),
+        }
+    }
 
-            if let Some(limit) = account.amount_per_minute_limit {
-                let limit = limit - 1;
-                let throughput_limit =
-                    prefixed_key(&self.db_prefix, &format!("limit:throughput:{}", account.id))
-                        .into_owned();
-                pipe.cmd("CL.THROTTLE")
-                    .arg(&throughput_limit)
-                    // TODO allow separate configuration for burst limit
-                    .arg(limit)
-                    .arg(limit)
-                    .arg(60)
-                    .arg(prepare_amount);
-            }
+    async fn refund_throughput_limit(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        let _timer = self.timers.start("refund_throughput_limit");
+        if account.amount_per_minute_limit.is_none() {
+            return Ok(());
+        }
+        let (throughput_key, throughput_limit) = throttle_args(
+            &self.db_prefix,
+            "throughput",
+            account.id,
+            account.amount_per_minute_limit,
+        );
+        let _: i64 = RATE_LIMITS
+            .arg("")
+            .arg("")
+            .arg(throughput_key)
+            .arg(throughput_limit)
+            .arg(0i64.saturating_sub(prepare_amount as i64))
+            .arg(now_millis())
+            .invoke_async(&mut self.connection.clone())
+            .map_err(|err| {
+                error!("Error refunding throughput limit: {:?}", err);
+                RateLimitError::StoreError
+            })
+            .await?;
+        Ok(())
+    }
+}
 
-            let results: Vec<Vec<i64>> = pipe
-                .query_async(&mut self.connection.clone())
-                .map_err(|err| {
-                    error!("Error applying rate limits: {:?}", err);
-                    RateLimitError::StoreError
-                })
-                .await?;
+/// The daily totals are kept a day longer than the day they count, so that packets
+/// which are refunded just after midnight still find their total
+const DAILY_VOLUME_TTL: u64 = 2 * 24 * 3600;
 
-            if packet_limit && amount_limit {
-                if results[0][0] == 1 {
-                    Err(RateLimitError::PacketLimitExceeded)
-                } else if results[1][0] == 1 {
-                    Err(RateLimitError::ThroughputLimitExceeded)
-                } else {
-                    Ok(())
-                }
-            } else if packet_limit && results[0][0] == 1 {
-                Err(RateLimitError::PacketLimitExceeded)
-            } else if amount_limit && results[0][0] == 1 {
-                Err(RateLimitError::ThroughputLimitExceeded)
-            } else {
-                Ok(())
-            }
-        } else {
+#[async_trait]
+impl VolumeLimitStore for RedisStore {
+    type Account = Account;
+
+    async fn charge_daily_volume(
+        &self,
+        account: Account,
+        day: u64,
+        amount: u64,
+    ) -> Result<(), VolumeLimitError> {
+        let _timer = self.timers.start("charge_daily_volume");
+        let limit = match account.amount_per_day_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let volume_key = prefixed_key(
+            &self.db_prefix,
+            &format!("limit:volume:{}:{}", account.id, day),
+        )
+        .into_owned();
+        let charged: bool = CHARGE_DAILY_VOLUME
+            .key(volume_key)
+            .arg(amount)
+            .arg(limit)
+            .arg(DAILY_VOLUME_TTL)
+            .invoke_async(&mut self.connection.clone())
+            .map_err(|err| {
+                error!("Error charging daily volume: {:?}", err);
+                VolumeLimitError::StoreError
+            })
+            .await?;
+        if charged {
             Ok(())
+        } else {
+            Err(VolumeLimitError::DailyLimitExceeded)
         }
     }
 
-    async fn refund_throughput_limit(
+    async fn refund_daily_volume(
         &self,
         account: Account,
-        prepare_amount: u64,
-    ) -> Result<(), RateLimitError> {
-        if let Some(limit) = account.amount_per_minute_limit {
-            let limit = limit - 1;
-            let throughput_limit =
-                prefixed_key(&self.db_prefix, &format!("limit:throughput:{}", account.id))
-                    .into_owned();
-            cmd("CL.THROTTLE")
-                .arg(&throughput_limit)
-                .arg(limit)
-                .arg(limit)
-                .arg(60)
-                // TODO make sure this doesn't overflow
-                .arg(0i64 - (prepare_amount as i64))
-                .query_async(&mut self.connection.clone())
-                .map_err(|_| RateLimitError::StoreError)
-                .await?;
+        day: u64,
+        amount: u64,
+    ) -> Result<(), VolumeLimitError> {
+        let _timer = self.timers.start("refund_daily_volume");
+        let volume_key = prefixed_key(
+            &self.db_prefix,
+            &format!("limit:volume:{}:{}", account.id, day),
+        )
+        .into_owned();
+        let _: i64 = self
+            .connection
+            .clone()
+            .decr(volume_key, amount)
+            .map_err(|err| {
+                error!("Error refunding daily volume: {:?}", err);
+                VolumeLimitError::StoreError
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+/// Returns the key of the token bucket of the account's limit and the capacity of the bucket,
+/// or empty strings if the account does not have the limit
+fn throttle_args(db_prefix: &str, name: &str, id: Uuid, limit: Option<u64>) -> (String, String) {
+    match limit {
+        Some(limit) => (
+            prefixed_key(db_prefix, &format!("limit:bucket:{}:{}", name, id)).into_owned(),
+            limit.to_string(),
+        ),
+        None => (String::new(), String::new()),
+    }
+}
+
+#[async_trait]
+impl AdmissionStore for RedisStore {
+    /// Applies the limits and the balance change in a single Lua script
+    async fn admit_prepare(
+        &self,
+        account: Account,
+        amount: u64,
+        day: u64,
+        packet_id: Option<PacketId>,
+        update_balance: bool,
+    ) -> Result<(), AdmissionError> {
+        let _timer = self.timers.start("admit_prepare");
+        let (packets_key, packets_limit) = throttle_args(
+            &self.db_prefix,
+            "packets",
+            account.id,
+            account.packets_per_minute_limit.map(u64::from),
+        );
+        let (throughput_key, throughput_limit) = throttle_args(
+            &self.db_prefix,
+            "throughput",
+            account.id,
+            account.amount_per_minute_limit,
+        );
+        let volume_key = prefixed_key(
+            &self.db_prefix,
+            &format!("limit:volume:{}:{}", account.id, day),
+        )
+        .into_owned();
+        let volume_limit = account
+            .amount_per_day_limit
+            .map(|limit| limit.to_string())
+            .unwrap_or_default();
+
+        let result: i64 = ADMIT_PREPARE
+            .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
+            .arg(RedisAccountId(account.id))
+            .arg(amount)
+            .arg(balance_log_key(&self.db_prefix, account.id))
+            .arg(now_millis())
+            .arg(packet_id.map(hex::encode).unwrap_or_default())
+            .arg(update_balance as u8)
+            .arg(packets_key)
+            .arg(packets_limit)
+            .arg(throughput_key)
+            .arg(throughput_limit)
+            .arg(volume_key)
+            .arg(volume_limit)
+            .arg(DAILY_VOLUME_TTL)
+            .invoke_async(&mut self.connection.clone())
+            .map_err(|err| {
+                error!("Error admitting prepare: {:?}", err);
+                AdmissionError::StoreError
+            })
+            .await?;
+
+        match result {
+            0 => Ok(()),
+            1 => Err(AdmissionError::PacketLimitExceeded),
+            2 => Err(AdmissionError::ThroughputLimitExceeded),
+            3 => Err(AdmissionError::DailyLimitExceeded),
+            4 => Err(AdmissionError::InsufficientBalance),
+            _ => Err(AdmissionError::StoreError),
+        }
+    }
+
+    async fn refund_prepare(
+        &self,
+        account: Account,
+        amount: u64,
+        day: u64,
+        packet_id: Option<PacketId>,
+        update_balance: bool,
+    ) -> Result<(), AdmissionError> {
+        let _timer = self.timers.start("refund_prepare");
+        if amount == 0 {
+            return Ok(());
         }
 
+        let (throughput_key, throughput_limit) = throttle_args(
+            &self.db_prefix,
+            "throughput",
+            account.id,
+            account.amount_per_minute_limit,
+        );
+        let volume_key = if account.amount_per_day_limit.is_some() {
+            prefixed_key(
+                &self.db_prefix,
+                &format!("limit:volume:{}:{}", account.id, day),
+            )
+            .into_owned()
+        } else {
+            String::new()
+        };
+
+        let _: i64 = REFUND_PREPARE
+            .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
+            .arg(RedisAccountId(account.id))
+            .arg(amount)
+            .arg(balance_log_key(&self.db_prefix, account.id))
+            .arg(now_millis())
+            .arg(packet_id.map(hex::encode).unwrap_or_default())
+            .arg(update_balance as u8)
+            .arg(throughput_key)
+            .arg(throughput_limit)
+            .arg(volume_key)
+            .invoke_async(&mut self.connection.clone())
+            .map_err(|err| {
+                error!("Error refunding prepare: {:?}", err);
+                AdmissionError::StoreError
+            })
+            .await?;
         Ok(())
     }
 }
@@ -1658,6 +2939,7 @@ impl IdempotentStore for RedisStore {
         &self,
         idempotency_key: String,
     ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        let _timer = self.timers.start("load_idempotent_data");
         let mut connection = self.connection.clone();
         let ret: HashMap<String, String> = connection
             .hgetall(prefixed_idempotency_key(&self.db_prefix, &idempotency_key))
@@ -1688,6 +2970,7 @@ impl IdempotentStore for RedisStore {
         status_code: StatusCode,
         data: Bytes,
     ) -> Result<(), IdempotentStoreError> {
+        let _timer = self.timers.start("save_idempotent_data");
         let mut pipe = redis_crate::pipe();
         let mut connection = self.connection.clone();
         pipe.atomic()
@@ -1727,12 +3010,15 @@ impl SettlementStore for RedisStore {
         amount: u64,
         idempotency_key: Option<String>,
     ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("update_balance_for_incoming_settlement");
         let idempotency_key = idempotency_key.unwrap();
         let balance: i64 = PROCESS_INCOMING_SETTLEMENT
             .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
             .arg(RedisAccountId(account_id))
             .arg(amount)
             .arg(&*prefixed_key(&self.db_prefix, idempotency_key.as_str()))
+            .arg(balance_log_key(&self.db_prefix, account_id))
+            .arg(now_millis())
             .invoke_async(&mut self.connection.clone())
             .await?;
         trace!(
@@ -1749,6 +3035,7 @@ impl SettlementStore for RedisStore {
         account_id: Uuid,
         settle_amount: u64,
     ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("refund_settlement");
         trace!(
             "Refunding settlement for account: {} of amount: {}",
             account_id,
@@ -1758,6 +3045,8 @@ impl SettlementStore for RedisStore {
             .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
             .arg(RedisAccountId(account_id))
             .arg(settle_amount)
+            .arg(balance_log_key(&self.db_prefix, account_id))
+            .arg(now_millis())
             .invoke_async(&mut self.connection.clone())
             .await?;
 
@@ -1771,6 +3060,179 @@ impl SettlementStore for RedisStore {
     }
 }
 
+#[async_trait]
+impl OutgoingSettlementStore for RedisStore {
+    async fn queue_outgoing_settlement(
+        &self,
+        settlement: OutgoingSettlement,
+    ) -> Result<bool, SettlementStoreError> {
+        let _timer = self.timers.start("queue_outgoing_settlement");
+        let serialized = serde_json::to_string(&settlement)
+            .map_err(|e| SettlementStoreError::Other(Box::new(e)))?;
+        let in_flight: bool = QUEUE_OUTGOING_SETTLEMENT
+            .arg(&*prefixed_key(&self.db_prefix, OUTGOING_SETTLEMENTS_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, IN_FLIGHT_SETTLEMENTS_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, QUEUED_SETTLEMENTS_KEY))
+            .arg(RedisAccountId(settlement.account_id))
+            .arg(&settlement.idempotency_key)
+            .arg(settlement.amount)
+            .arg(serialized)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(in_flight)
+    }
+
+    async fn dequeue_outgoing_settlement(
+        &self,
+        account_id: Uuid,
+        idempotency_key: String,
+        next_attempt_at: u64,
+    ) -> Result<Option<OutgoingSettlement>, SettlementStoreError> {
+        let _timer = self.timers.start("dequeue_outgoing_settlement");
+        let queued_key = prefixed_key(&self.db_prefix, QUEUED_SETTLEMENTS_KEY);
+        loop {
+            let amount: Option<u64> = self
+                .connection
+                .clone()
+                .hget(&*queued_key, RedisAccountId(account_id))
+                .await?;
+            let amount = match amount {
+                Some(amount) => amount,
+                None => return Ok(None),
+            };
+            let settlement = OutgoingSettlement {
+                idempotency_key: idempotency_key.clone(),
+                account_id,
+                amount,
+                status: OutgoingSettlementStatus::Pending,
+                attempts: 0,
+                next_attempt_at,
+            };
+            let serialized = serde_json::to_string(&settlement)
+                .map_err(|e| SettlementStoreError::Other(Box::new(e)))?;
+            // The script only dequeues the amount if it did not change in the meantime,
+            // otherwise the settlement is created again with the new amount
+            let result: i64 = DEQUEUE_OUTGOING_SETTLEMENT
+                .arg(&*prefixed_key(&self.db_prefix, OUTGOING_SETTLEMENTS_KEY))
+                .arg(&*prefixed_key(&self.db_prefix, IN_FLIGHT_SETTLEMENTS_KEY))
+                .arg(&*queued_key)
+                .arg(RedisAccountId(account_id))
+                .arg(&settlement.idempotency_key)
+                .arg(amount)
+                .arg(serialized)
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+            match result {
+                1 => return Ok(Some(settlement)),
+                0 => return Ok(None),
+                _ => continue,
+            }
+        }
+    }
+
+    async fn load_queued_settlement_accounts(&self) -> Result<Vec<Uuid>, SettlementStoreError> {
+        let _timer = self.timers.start("load_queued_settlement_accounts");
+        let account_ids: Vec<RedisAccountId> = self
+            .connection
+            .clone()
+            .hkeys(&*prefixed_key(&self.db_prefix, QUEUED_SETTLEMENTS_KEY))
+            .await?;
+        Ok(account_ids.into_iter().map(|id| id.0).collect())
+    }
+
+    async fn save_outgoing_settlement(
+        &self,
+        settlement: OutgoingSettlement,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("save_outgoing_settlement");
+        let serialized = serde_json::to_string(&settlement)
+            .map_err(|e| SettlementStoreError::Other(Box::new(e)))?;
+        self.connection
+            .clone()
+            .hset(
+                &*prefixed_key(&self.db_prefix, OUTGOING_SETTLEMENTS_KEY),
+                settlement.idempotency_key,
+                serialized,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn load_outgoing_settlements(
+        &self,
+    ) -> Result<Vec<OutgoingSettlement>, SettlementStoreError> {
+        let _timer = self.timers.start("load_outgoing_settlements");
+        let serialized: Vec<String> = self
+            .connection
+            .clone()
+            .hvals(&*prefixed_key(&self.db_prefix, OUTGOING_SETTLEMENTS_KEY))
+            .await?;
+        serialized
+            .iter()
+            .map(|settlement| {
+                serde_json::from_str(settlement)
+                    .map_err(|e| SettlementStoreError::Other(Box::new(e)))
+            })
+            .collect()
+    }
+
+    async fn remove_outgoing_settlement(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("remove_outgoing_settlement");
+        REMOVE_OUTGOING_SETTLEMENT
+            .arg(&*prefixed_key(&self.db_prefix, OUTGOING_SETTLEMENTS_KEY))
+            .arg(&*prefixed_key(&self.db_prefix, IN_FLIGHT_SETTLEMENTS_KEY))
+            .arg(idempotency_key)
+            .invoke_async::<_, ()>(&mut self.connection.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn refund_outgoing_settlement(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("refund_outgoing_settlement");
+        let settlements_key = prefixed_key(&self.db_prefix, OUTGOING_SETTLEMENTS_KEY);
+        let serialized: Option<String> = self
+            .connection
+            .clone()
+            .hget(&*settlements_key, idempotency_key)
+            .await?;
+        let settlement: OutgoingSettlement = match serialized {
+            Some(serialized) => serde_json::from_str(&serialized)
+                .map_err(|e| SettlementStoreError::Other(Box::new(e)))?,
+            // Already refunded or accepted
+            None => return Ok(()),
+        };
+
+        // The script only refunds if it removes the settlement, so a concurrent
+        // refund of the same settlement does not credit the account twice
+        let refunded: bool = REFUND_OUTGOING_SETTLEMENT
+            .arg(&*prefixed_key(&self.db_prefix, ACCOUNTS_KEY))
+            .arg(RedisAccountId(settlement.account_id))
+            .arg(settlement.amount)
+            .arg(balance_log_key(&self.db_prefix, settlement.account_id))
+            .arg(now_millis())
+            .arg(&*settlements_key)
+            .arg(idempotency_key)
+            .arg(&*prefixed_key(&self.db_prefix, IN_FLIGHT_SETTLEMENTS_KEY))
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        if refunded {
+            trace!(
+                "Refunded settlement {} for account: {} of amount: {}",
+                idempotency_key,
+                settlement.account_id,
+                settlement.amount
+            );
+        }
+        Ok(())
+    }
+}
+
 // TODO: AmountWithScale is re-implemented on Interledger-Settlement. It'd be nice
 // if we could deduplicate this by extracting it to a separate crate which would make
 // logical sense
@@ -1869,6 +3331,7 @@ impl LeftoversStore for RedisStore {
         &self,
         account_id: Uuid,
     ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        let _timer = self.timers.start("get_uncredited_settlement_amount");
         let mut pipe = redis_crate::pipe();
         pipe.atomic();
         // get the amounts and instantly delete them
@@ -1895,6 +3358,7 @@ impl LeftoversStore for RedisStore {
         account_id: Uuid,
         uncredited_settlement_amount: (Self::AssetType, u8),
     ) -> Result<(), LeftoversStoreError> {
+        let _timer = self.timers.start("save_uncredited_settlement_amount");
         trace!(
             "Saving uncredited_settlement_amount {:?} {:?}",
             account_id,
@@ -1923,6 +3387,7 @@ impl LeftoversStore for RedisStore {
         account_id: Uuid,
         local_scale: u8,
     ) -> Result<Self::AssetType, LeftoversStoreError> {
+        let _timer = self.timers.start("load_uncredited_settlement_amount");
         trace!("Loading uncredited_settlement_amount {:?}", account_id);
         let amount = self.get_uncredited_settlement_amount(account_id).await?;
         // scale the amount from the max scale to the local scale, and then
@@ -1950,6 +3415,7 @@ impl LeftoversStore for RedisStore {
         &self,
         account_id: Uuid,
     ) -> Result<(), LeftoversStoreError> {
+        let _timer = self.timers.start("clear_uncredited_settlement_amount");
         trace!("Clearing uncredited_settlement_amount {:?}", account_id);
         self.connection
             .clone()
@@ -1961,7 +3427,7 @@ impl LeftoversStore for RedisStore {
 
 type RouteVec = Vec<(String, RedisAccountId)>;
 
-use futures::future::TryFutureExt;
+use futures::future::{FutureExt, TryFutureExt};
 
 // TODO replace this with pubsub when async pubsub is added upstream: https://github.com/mitsuhiko/redis-rs/issues/183
 async fn update_routes(
@@ -2003,6 +3469,13 @@ async fn update_routes(
 // Rust does not allow implementing foreign traits on foreign data types.
 // As a result, we wrap Uuid in a local data type, and implement the necessary
 // traits for that.
+/// Outcome of the script which writes an account
+enum AccountWrite {
+    Written,
+    NotFound,
+    Exists,
+}
+
 #[derive(Eq, PartialEq, Hash, Debug, Default, Serialize, Deserialize, Copy, Clone)]
 struct RedisAccountId(Uuid);
 
@@ -2114,6 +3587,18 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
             "settle_to".write_redis_args(&mut rv);
             settle_to.write_redis_args(&mut rv);
         }
+        if let Some(settle_every) = account.settle_every {
+            "settle_every".write_redis_args(&mut rv);
+            settle_every.write_redis_args(&mut rv);
+        }
+        if let Some(min_packet_amount) = account.min_packet_amount {
+            "min_packet_amount".write_redis_args(&mut rv);
+            min_packet_amount.write_redis_args(&mut rv);
+        }
+        if let Some(routing_preference) = account.routing_preference {
+            "routing_preference".write_redis_args(&mut rv);
+            routing_preference.write_redis_args(&mut rv);
+        }
         if let Some(limit) = account.packets_per_minute_limit {
             "packets_per_minute_limit".write_redis_args(&mut rv);
             limit.write_redis_args(&mut rv);
@@ -2122,6 +3607,14 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
             "amount_per_minute_limit".write_redis_args(&mut rv);
             limit.write_redis_args(&mut rv);
         }
+        if let Some(max_payment_amount) = account.max_payment_amount {
+            "max_payment_amount".write_redis_args(&mut rv);
+            max_payment_amount.write_redis_args(&mut rv);
+        }
+        if let Some(limit) = account.amount_per_day_limit {
+            "amount_per_day_limit".write_redis_args(&mut rv);
+            limit.write_redis_args(&mut rv);
+        }
         if let Some(min_balance) = account.min_balance {
             "min_balance".write_redis_args(&mut rv);
             min_balance.write_redis_args(&mut rv);
@@ -2138,6 +3631,59 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
     }
 }
 
+/// Returns the names of the optional account fields which are not set
+/// (and are therefore not written to Redis) for the provided account
+fn unset_optional_fields(account: &Account) -> Vec<&'static str> {
+    let fields = [
+        ("ilp_over_http_url", account.ilp_over_http_url.is_none()),
+        (
+            "ilp_over_http_incoming_token",
+            account.ilp_over_http_incoming_token.is_none(),
+        ),
+        (
+            "ilp_over_http_outgoing_token",
+            account.ilp_over_http_outgoing_token.is_none(),
+        ),
+        ("ilp_over_btp_url", account.ilp_over_btp_url.is_none()),
+        (
+            "ilp_over_btp_incoming_token",
+            account.ilp_over_btp_incoming_token.is_none(),
+        ),
+        (
+            "ilp_over_btp_outgoing_token",
+            account.ilp_over_btp_outgoing_token.is_none(),
+        ),
+        ("settle_threshold", account.settle_threshold.is_none()),
+        ("settle_to", account.settle_to.is_none()),
+        ("settle_every", account.settle_every.is_none()),
+        ("min_packet_amount", account.min_packet_amount.is_none()),
+        ("routing_preference", account.routing_preference.is_none()),
+        (
+            "packets_per_minute_limit",
+            account.packets_per_minute_limit.is_none(),
+        ),
+        (
+            "amount_per_minute_limit",
+            account.amount_per_minute_limit.is_none(),
+        ),
+        ("max_payment_amount", account.max_payment_amount.is_none()),
+        (
+            "amount_per_day_limit",
+            account.amount_per_day_limit.is_none(),
+        ),
+        ("min_balance", account.min_balance.is_none()),
+        (
+            "settlement_engine_url",
+            account.settlement_engine_url.is_none(),
+        ),
+    ];
+    fields
+        .iter()
+        .filter(|(_, unset)| *unset)
+        .map(|(field, _)| *field)
+        .collect()
+}
+
 impl FromRedisValue for AccountWithEncryptedTokens {
     fn from_redis_value(v: &Value) -> Result<Self, RedisError> {
         let hash: HashMap<String, Value> = HashMap::from_redis_value(v)?;
@@ -2189,13 +3735,18 @@ impl FromRedisValue for AccountWithEncryptedTokens {
                 )?
                 .map(SecretBytesMut::from),
                 max_packet_amount: get_value("max_packet_amount", &hash)?,
+                min_packet_amount: get_value_option("min_packet_amount", &hash)?,
                 min_balance: get_value_option("min_balance", &hash)?,
                 settle_threshold: get_value_option("settle_threshold", &hash)?,
                 settle_to: get_value_option("settle_to", &hash)?,
+                settle_every: get_value_option("settle_every", &hash)?,
                 routing_relation,
+                routing_preference: get_value_option("routing_preference", &hash)?,
                 round_trip_time,
                 packets_per_minute_limit: get_value_option("packets_per_minute_limit", &hash)?,
                 amount_per_minute_limit: get_value_option("amount_per_minute_limit", &hash)?,
+                max_payment_amount: get_value_option("max_payment_amount", &hash)?,
+                amount_per_day_limit: get_value_option("amount_per_day_limit", &hash)?,
                 settlement_engine_url: get_url_option("settlement_engine_url", &hash)?,
             },
         })
@@ -2268,4 +3819,60 @@ mod tests {
         .await;
         assert!(result.is_err());
     }
+
+    #[test]
+    fn validates_db_prefix() {
+        assert!(validate_db_prefix("").is_ok());
+        assert!(validate_db_prefix("node-a_1").is_ok());
+        // could clash with the keys of other prefixes
+        assert!(validate_db_prefix("node:a").is_err());
+        // could be interpreted as a pattern when subscribing
+        assert!(validate_db_prefix("node*").is_err());
+        // could clash with the keys of a node without a prefix
+        assert!(validate_db_prefix("accounts").is_err());
+    }
+
+    /// Returns the first segment of a key, if the key starts with a literal segment
+    fn first_segment(key: &str) -> Option<&str> {
+        let segment = key.split(|c| c == ':' || c == '"').next()?;
+        if !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c == '_' || c == '-')
+        {
+            Some(segment)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn reserves_the_first_segment_of_every_key() {
+        // Scan the source so that keys added later are checked as well
+        let source = include_str!("mod.rs");
+        let mut segments = Vec::new();
+        for line in source.lines() {
+            if line.starts_with("static ") {
+                if let Some(index) = line.find(": &str = \"") {
+                    segments.extend(first_segment(&line[index + 10..]));
+                }
+            }
+        }
+        for (index, _) in source.match_indices("prefixed_key(") {
+            let call = &source[index..];
+            let end = call.find(')').unwrap_or(call.len());
+            if let Some(format_index) = call[..end].find("format!(\"") {
+                segments.extend(first_segment(&call[format_index + 9..]));
+            }
+        }
+        // The keys of the accounts, the settlements, the domain separators, ...
+        assert!(segments.len() > 20);
+        for segment in segments {
+            assert!(
+                RESERVED_DB_PREFIXES.contains(&segment),
+                "{} is not a reserved db prefix",
+                segment
+            );
+        }
+    }
 }
@@ -1,75 +1,232 @@
-use futures::future::{FutureExt, TryFutureExt};
+use futures::future::FutureExt;
+use interledger_service::runtime;
 use parking_lot::RwLock;
 use redis_crate::{
     aio::{ConnectionLike, MultiplexedConnection},
-    Client, Cmd, ConnectionInfo, Pipeline, RedisError, RedisFuture, Value,
+    Client, Cmd, ConnectionInfo, ErrorKind, Pipeline, RedisError, RedisFuture, Value,
 };
-use std::sync::Arc;
-use tracing::{debug, error};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::{debug, error, warn};
 
 type Result<T> = std::result::Result<T, RedisError>;
 
-/// Wrapper around a Redis MultiplexedConnection that automatically
-/// attempts to reconnect to the DB if the connection is dropped
+/// The longest time to wait between two attempts, regardless of the number of retries
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Settings of the Redis connection pool
+#[derive(Debug, Clone)]
+pub(crate) struct PoolConfig {
+    /// Number of multiplexed connections over which the commands are spread
+    pub(crate) size: usize,
+    /// Time after which establishing a connection is abandoned
+    pub(crate) connect_timeout: Option<Duration>,
+    /// Number of times a command failing with a transient error is retried
+    pub(crate) max_retries: u32,
+    /// Time to wait before the first retry. It is doubled for every further retry.
+    pub(crate) retry_backoff: Duration,
+    /// Maximum number of commands which are sent to Redis at the same time, if set
+    pub(crate) max_concurrent_commands: Option<usize>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            size: 1,
+            connect_timeout: None,
+            max_retries: 0,
+            retry_backoff: Duration::from_millis(100),
+            max_concurrent_commands: None,
+        }
+    }
+}
+
+impl PoolConfig {
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.retry_backoff
+            .checked_mul(1 << attempt.min(16))
+            .map_or(MAX_RETRY_BACKOFF, |backoff| backoff.min(MAX_RETRY_BACKOFF))
+    }
+}
+
+/// Whether the error is guaranteed to have happened before the command was executed,
+/// which makes it safe to retry the command.
+///
+/// Commands which fail because the connection was dropped or timed out are never retried,
+/// because they may have been executed already and most of the store's commands (e.g.
+/// balance updates) are not idempotent.
+fn is_retryable(error: &RedisError) -> bool {
+    error.is_connection_refusal()
+        || matches!(
+            error.kind(),
+            ErrorKind::BusyLoadingError | ErrorKind::TryAgain | ErrorKind::ClusterDown
+        )
+}
+
+pub(crate) struct Pool {
+    redis_info: ConnectionInfo,
+    config: PoolConfig,
+    connections: Vec<RwLock<MultiplexedConnection>>,
+    next: AtomicUsize,
+    /// Limits the commands in flight to `config.max_concurrent_commands`
+    commands: Option<Semaphore>,
+}
+
+/// Wrapper around a pool of Redis MultiplexedConnections that automatically
+/// attempts to reconnect to the DB if a connection is dropped and retries
+/// the commands which failed due to transient errors
 #[derive(Clone)]
 pub struct RedisReconnect {
-    pub(crate) redis_info: Arc<ConnectionInfo>,
-    pub(crate) conn: Arc<RwLock<MultiplexedConnection>>,
+    pub(crate) pool: Arc<Pool>,
 }
 
-async fn get_shared_connection(redis_info: Arc<ConnectionInfo>) -> Result<MultiplexedConnection> {
-    let client = Client::open((*redis_info).clone())?;
-    client
-        .get_multiplexed_tokio_connection()
-        .map_err(|e| {
-            error!("Error connecting to Redis: {:?}", e);
-            e
-        })
-        .await
+async fn get_shared_connection(
+    redis_info: &ConnectionInfo,
+    config: &PoolConfig,
+) -> Result<MultiplexedConnection> {
+    let mut attempt = 0;
+    loop {
+        let connection = async {
+            let client = Client::open(redis_info.clone())?;
+            match config.connect_timeout {
+                Some(connect_timeout) => {
+                    runtime::timeout(connect_timeout, client.get_multiplexed_tokio_connection())
+                        .await
+                        .unwrap_or_else(|_| {
+                            Err(RedisError::from((
+                                ErrorKind::IoError,
+                                "Timed out connecting to Redis",
+                            )))
+                        })
+                }
+                None => client.get_multiplexed_tokio_connection().await,
+            }
+        };
+        match connection.await {
+            Ok(connection) => return Ok(connection),
+            // Nothing was sent yet, so any error is safe to retry
+            Err(e) if attempt < config.max_retries => {
+                let backoff = config.backoff(attempt);
+                warn!(
+                    "Error connecting to Redis: {:?}, retrying in {}ms",
+                    e,
+                    backoff.as_millis()
+                );
+                runtime::sleep(backoff).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                error!("Error connecting to Redis: {:?}", e);
+                return Err(e);
+            }
+        }
+    }
 }
 
 impl RedisReconnect {
-    /// Connects to redis with the provided [`ConnectionInfo`](redis_crate::ConnectionInfo)
-    pub async fn connect(redis_info: ConnectionInfo) -> Result<RedisReconnect> {
-        let redis_info = Arc::new(redis_info);
-        let conn = get_shared_connection(redis_info.clone()).await?;
+    /// Opens `config.size` connections to redis with the provided
+    /// [`ConnectionInfo`](redis_crate::ConnectionInfo)
+    pub(crate) async fn connect(
+        redis_info: ConnectionInfo,
+        config: PoolConfig,
+    ) -> Result<RedisReconnect> {
+        let mut connections = Vec::with_capacity(config.size.max(1));
+        for _ in 0..config.size.max(1) {
+            let conn = get_shared_connection(&redis_info, &config).await?;
+            connections.push(RwLock::new(conn));
+        }
+        let commands = config
+            .max_concurrent_commands
+            .map(|limit| Semaphore::new(limit.max(1)));
         Ok(RedisReconnect {
-            conn: Arc::new(RwLock::new(conn)),
-            redis_info,
+            pool: Arc::new(Pool {
+                redis_info,
+                config,
+                connections,
+                next: AtomicUsize::new(0),
+                commands,
+            }),
         })
     }
 
-    /// Reconnects to redis
-    pub async fn reconnect(&self) -> Result<()> {
-        let shared_connection = get_shared_connection(self.redis_info.clone()).await?;
-        (*self.conn.write()) = shared_connection;
+    /// Reconnects the connection with the given index to redis
+    async fn reconnect(&self, index: usize) -> Result<()> {
+        let shared_connection =
+            get_shared_connection(&self.pool.redis_info, &self.pool.config).await?;
+        (*self.pool.connections[index].write()) = shared_connection;
         debug!("Reconnected to Redis");
         Ok(())
     }
 
-    fn get_shared_connection(&self) -> MultiplexedConnection {
-        self.conn.read().clone()
+    /// Picks the connections of the pool in turn
+    fn get_shared_connection(&self) -> (usize, MultiplexedConnection) {
+        let index = self.pool.next.fetch_add(1, Ordering::Relaxed) % self.pool.connections.len();
+        (index, self.pool.connections[index].read().clone())
+    }
+
+    /// Waits until another command may be sent, if the number of commands in flight is limited
+    async fn acquire_permit(&self) -> Option<SemaphorePermit<'_>> {
+        match self.pool.commands {
+            Some(ref commands) => Some(
+                commands
+                    .acquire()
+                    .await
+                    .expect("the semaphore is never closed"),
+            ),
+            None => None,
+        }
+    }
+
+    /// Handles a failed command: reconnects if the connection was dropped and
+    /// returns whether the command should be retried after waiting
+    async fn should_retry(&self, index: usize, error: &RedisError, attempt: u32) -> bool {
+        if error.is_connection_dropped() {
+            debug!("Redis connection was dropped, attempting to reconnect");
+            // FIXME: this conceals potential reconnect errors
+            let _ = self.reconnect(index).await;
+        }
+        if attempt >= self.pool.config.max_retries || !is_retryable(error) {
+            return false;
+        }
+        let backoff = self.pool.config.backoff(attempt);
+        debug!(
+            "Retrying Redis command in {}ms after error: {:?}",
+            backoff.as_millis(),
+            error
+        );
+        runtime::sleep(backoff).await;
+        true
     }
 }
 
 impl ConnectionLike for RedisReconnect {
     fn get_db(&self) -> i64 {
-        self.conn.read().get_db()
+        self.pool.connections[0].read().get_db()
     }
 
     fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
         // This is how it is implemented in the redis-rs repository
         (async move {
-            let mut connection = self.get_shared_connection();
-            match connection.req_packed_command(cmd).await {
-                Ok(res) => Ok(res),
-                Err(error) => {
-                    if error.is_connection_dropped() {
-                        debug!("Redis connection was dropped, attempting to reconnect");
-                        // FIXME: this conceals potential reconnect errors
-                        let _ = self.reconnect().await;
+            let mut attempt = 0;
+            loop {
+                let (index, mut connection) = self.get_shared_connection();
+                let permit = self.acquire_permit().await;
+                let result = connection.req_packed_command(cmd).await;
+                drop(permit);
+                match result {
+                    Ok(res) => return Ok(res),
+                    Err(error) => {
+                        if !self.should_retry(index, &error, attempt).await {
+                            return Err(error);
+                        }
+                        attempt += 1;
                     }
-                    Err(error)
                 }
             }
         })
@@ -84,19 +241,53 @@ impl ConnectionLike for RedisReconnect {
     ) -> RedisFuture<'a, Vec<Value>> {
         // This is how it is implemented in the redis-rs repository
         (async move {
-            let mut connection = self.get_shared_connection();
-            match connection.req_packed_commands(cmd, offset, count).await {
-                Ok(res) => Ok(res),
-                Err(error) => {
-                    if error.is_connection_dropped() {
-                        debug!("Redis connection was dropped, attempting to reconnect");
-                        // FIXME: this conceals potential reconnect errors
-                        let _ = self.reconnect().await;
+            let mut attempt = 0;
+            loop {
+                let (index, mut connection) = self.get_shared_connection();
+                let permit = self.acquire_permit().await;
+                let result = connection.req_packed_commands(cmd, offset, count).await;
+                drop(permit);
+                match result {
+                    Ok(res) => return Ok(res),
+                    Err(error) => {
+                        if !self.should_retry(index, &error, attempt).await {
+                            return Err(error);
+                        }
+                        attempt += 1;
                     }
-                    Err(error)
                 }
             }
         })
         .boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let config = PoolConfig::default();
+        assert_eq!(config.backoff(0), Duration::from_millis(100));
+        assert_eq!(config.backoff(1), Duration::from_millis(200));
+        assert_eq!(config.backoff(3), Duration::from_millis(800));
+        assert_eq!(config.backoff(10), MAX_RETRY_BACKOFF);
+        assert_eq!(config.backoff(u32::MAX), MAX_RETRY_BACKOFF);
+    }
+
+    #[test]
+    fn only_errors_before_execution_are_retryable() {
+        assert!(is_retryable(&RedisError::from((
+            ErrorKind::BusyLoadingError,
+            "loading"
+        ))));
+        assert!(!is_retryable(&RedisError::from((
+            ErrorKind::ResponseError,
+            "error"
+        ))));
+        assert!(!is_retryable(&RedisError::from(std::io::Error::from(
+            std::io::ErrorKind::BrokenPipe
+        ))));
+    }
+}
@@ -0,0 +1,3148 @@
+// The embedded sled store keeps all of its data in a single ordered key-value tree.
+// The keys start with the kind of record they hold, so that the records of each kind
+// (or of each account) are read with a range scan:
+//   accounts/<id>                         account details, tokens are stored encrypted
+//   usernames/<username>                  id of the account with the username
+//   balances/<id>                         balance and prepaid amount of the account
+//   routes/<prefix>                       dynamic routing table
+//   static_routes/<prefix>                static routing table
+//   settings/<key>                        parent-assigned ILP address, default route,
+//                                         generation of the STREAM server secret and leader
+//   settlement_engines/<asset code>       globally configured settlement engines
+//   idempotency_keys/<key>                cached settlement API responses
+//   incoming_settlements/<key>            idempotency keys of credited incoming settlements
+//   uncredited_amounts/<id>/<n>           leftovers from settlements with precision loss
+//   daily_volumes/<id>/<day>              amounts the account sent per day
+//   balance_log/<id>/<sequence>           append-only log of every balance change
+//   balance_log_sequences/<id>            sequence of the next entry of the balance log
+//   outgoing_settlements/<key>            settlements the engine has not answered yet
+//   queued_settlements/<id>               amount waiting for the settlement in flight
+//   packet_history/<id>/<n>               summaries of the packets sent by and to the account
+//   packet_journal/<id>                   outgoing packets whose response was not applied yet
+//   incoming_payments/<id>/<destination>  money received on each STREAM connection
+// Numbers in keys are big-endian, so that they sort in numeric order. Values are JSON.
+// Exchange rates, rate limit counters and payment notification subscriptions are kept
+// in memory, like in the SQLite store, since an embedded store is only ever used by a
+// single node process.
+// Every operation which writes applies its changes atomically, either as a transaction
+// on the records it changes or as a single batch. sled appends them to its log, which is
+// flushed to disk every `flush_interval` or, with `sync_writes`, on the blocking threads
+// of the runtime before the operation returns.
+
+use super::account::{Account, AccountWithEncryptedTokens};
+use super::crypto::{encrypt_token, generate_keys, hash_token, DecryptionKey, EncryptionKey};
+use super::instrumentation::OperationTimers;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::channel::mpsc::UnboundedSender;
+use http::StatusCode;
+use interledger_api::{
+    AccountDetails, AccountFilter, AccountPage, AccountSettings, ExportedAccount, NodeExport,
+    NodeStore,
+};
+use interledger_auth::{AuthError, AuthProtocol, VerifiedTokens};
+use interledger_btp::BtpStore;
+use interledger_ccp::{CcpRoutingStore, RoutingRelation};
+use interledger_errors::*;
+use interledger_http::HttpStore;
+use interledger_packet::Address;
+use interledger_rates::ExchangeRateStore;
+use interledger_router::RouterStore;
+use interledger_service::{runtime, AccountStore, AddressStore, Username};
+use interledger_service_util::{
+    AdmissionStore, BalanceChange, BalanceChangeReason, BalanceStore, BalanceTotals,
+    JournaledPacket, PacketHistoryFilter, PacketHistoryStore, PacketId, PacketJournalStore,
+    PacketRecord, RateLimitError, RateLimitStore, VolumeLimitError, VolumeLimitStore,
+};
+use interledger_settlement::core::{
+    idempotency::{IdempotentData, IdempotentStore},
+    scale_with_precision_loss,
+    types::{
+        Convert, ConvertDetails, LeftoversStore, OutgoingSettlement, OutgoingSettlementStatus,
+        OutgoingSettlementStore, SettlementStore,
+    },
+};
+use interledger_stream::{
+    IncomingPayment, IncomingPaymentsStore, PaymentNotification, SecretGeneration,
+    StreamNotificationsStore,
+};
+use num_bigint::BigUint;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use ring::aead;
+use secrecy::{ExposeSecret, Secret, SecretBytesMut, SecretString};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sled_crate::{
+    transaction::{
+        ConflictableTransactionError, ConflictableTransactionResult, TransactionError,
+        TransactionalTree, UnabortableTransactionError,
+    },
+    Batch, Config, Db, Mode,
+};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    ops::Bound,
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tokio::sync::broadcast;
+use tracing::{debug, error, trace, warn};
+use url::Url;
+use uuid::Uuid;
+use zeroize::Zeroize;
+
+/// Default interval at which sled flushes its log to disk, in milliseconds
+const DEFAULT_FLUSH_INTERVAL: u64 = 500;
+
+static ACCOUNTS: &str = "accounts";
+static USERNAMES: &str = "usernames";
+static BALANCES: &str = "balances";
+static ROUTES: &str = "routes";
+static STATIC_ROUTES: &str = "static_routes";
+static SETTINGS: &str = "settings";
+static SETTLEMENT_ENGINES: &str = "settlement_engines";
+static IDEMPOTENCY_KEYS: &str = "idempotency_keys";
+static INCOMING_SETTLEMENTS: &str = "incoming_settlements";
+static UNCREDITED_AMOUNTS: &str = "uncredited_amounts";
+static DAILY_VOLUMES: &str = "daily_volumes";
+static BALANCE_LOG: &str = "balance_log";
+static BALANCE_LOG_SEQUENCES: &str = "balance_log_sequences";
+static OUTGOING_SETTLEMENTS: &str = "outgoing_settlements";
+static QUEUED_SETTLEMENTS: &str = "queued_settlements";
+static PACKET_HISTORY: &str = "packet_history";
+static PACKET_JOURNAL: &str = "packet_journal";
+static INCOMING_PAYMENTS: &str = "incoming_payments";
+
+static PARENT_ILP_KEY: &str = "parent_node_account_address";
+static DEFAULT_ROUTE_KEY: &str = "default_route";
+static SECRET_GENERATION_KEY: &str = "secret_generation";
+/// Id of the node which holds the leadership, and when its lease expires
+static LEADER_KEY: &str = "leader";
+
+/// How long idempotency keys are kept around (24 hours, same as the Redis store)
+const IDEMPOTENCY_KEY_TTL: u64 = 86400;
+/// The window over which the per-minute rate limits are counted
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// The node's default ILP Address
+static DEFAULT_ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("local.host").unwrap());
+
+/// Errors of the sled store's operations
+#[derive(Debug, Error)]
+pub enum SledStoreError {
+    #[error("Database error: {0}")]
+    Db(#[from] sled_crate::Error),
+    #[error("Invalid record in the database: {0}")]
+    InvalidRecord(String),
+    #[error("Account {0} was not found")]
+    AccountNotFound(Uuid),
+}
+
+macro_rules! impl_from_sled_store_error {
+    ($($error:ident),*) => {
+        $(
+            impl From<SledStoreError> for $error {
+                fn from(err: SledStoreError) -> Self {
+                    $error::Other(Box::new(err))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_sled_store_error!(
+    AccountStoreError,
+    AddressStoreError,
+    BalanceStoreError,
+    BtpStoreError,
+    CcpRoutingStoreError,
+    HttpStoreError,
+    IdempotentStoreError,
+    LeftoversStoreError,
+    NodeStoreError,
+    SettlementStoreError
+);
+
+type StoreResult<T> = Result<T, SledStoreError>;
+
+/// Error returned when a balance update is not allowed
+#[derive(Debug, Error)]
+#[error("Incoming prepare of {amount} would bring account {account_id} under its minimum balance. Current balance: {balance}, min balance: {min_balance}")]
+pub struct MinBalanceExceeded {
+    account_id: Uuid,
+    amount: u64,
+    balance: i64,
+    min_balance: i64,
+}
+
+/// Builder for the sled Store
+pub struct SledStoreBuilder {
+    /// Directory of the database, or `None` for a database which is deleted when it is closed
+    path: Option<PathBuf>,
+    secret: [u8; 32],
+    previous_secret: Option<[u8; 32]>,
+    /// Connector's ILP Address. Used to insert `Child` accounts as
+    node_ilp_address: Address,
+    /// Interval at which the log is flushed to disk, in milliseconds
+    flush_interval: u64,
+    /// Whether every write is flushed to disk before it returns
+    sync_writes: bool,
+    /// Size of the page cache, in bytes
+    cache_capacity: Option<u64>,
+    timers: OperationTimers,
+}
+
+impl SledStoreBuilder {
+    /// Simple Constructor. The database is kept in the given directory.
+    pub fn new<P: Into<PathBuf>>(path: P, secret: [u8; 32]) -> Self {
+        SledStoreBuilder {
+            path: Some(path.into()),
+            secret,
+            previous_secret: None,
+            node_ilp_address: DEFAULT_ILP_ADDRESS.clone(),
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+            sync_writes: false,
+            cache_capacity: None,
+            timers: OperationTimers::new("sled"),
+        }
+    }
+
+    /// Constructor for a store whose database is deleted once the store is dropped
+    pub fn temporary(secret: [u8; 32]) -> Self {
+        SledStoreBuilder {
+            path: None,
+            ..SledStoreBuilder::new(PathBuf::new(), secret)
+        }
+    }
+
+    /// Sets the ILP Address corresponding to the node
+    pub fn node_ilp_address(&mut self, node_ilp_address: Address) -> &mut Self {
+        self.node_ilp_address = node_ilp_address;
+        self
+    }
+
+    /// Sets the secret which was used before the current one, in order to rotate the key
+    /// with which the account tokens are encrypted. On opening, all tokens which were
+    /// encrypted with the previous secret are re-encrypted with the current one.
+    pub fn previous_secret(&mut self, previous_secret: [u8; 32]) -> &mut Self {
+        self.previous_secret = Some(previous_secret);
+        self
+    }
+
+    /// Sets the interval (in milliseconds) at which the writes are flushed to disk.
+    /// The writes of the last interval are lost if the machine crashes.
+    pub fn flush_interval(&mut self, flush_interval: u64) -> &mut Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    /// Flushes every write to disk before the operation returns, so that no write is
+    /// lost if the machine crashes, at the cost of a disk sync per write
+    pub fn sync_writes(&mut self, sync_writes: bool) -> &mut Self {
+        self.sync_writes = sync_writes;
+        self
+    }
+
+    /// Sets the size (in bytes) of the cache of database pages kept in memory
+    pub fn cache_capacity(&mut self, cache_capacity: u64) -> &mut Self {
+        self.cache_capacity = Some(cache_capacity);
+        self
+    }
+
+    /// Sets the duration (in milliseconds) after which store operations are logged
+    /// as slow. Slow operations are not logged by default.
+    pub fn slow_operation_threshold(&mut self, threshold: u64) -> &mut Self {
+        self.timers
+            .set_default_threshold(Duration::from_millis(threshold));
+        self
+    }
+
+    /// Sets the slow operation threshold (in milliseconds) of a single store method,
+    /// e.g. `update_balances_for_prepare`, overriding the `slow_operation_threshold`
+    pub fn method_slow_operation_threshold(&mut self, method: &str, threshold: u64) -> &mut Self {
+        self.timers
+            .set_threshold(method, Duration::from_millis(threshold));
+        self
+    }
+
+    /// Opens the sled Store
+    ///
+    /// Specifically
+    /// 1. Generates encryption and decryption keys
+    /// 1. Opens (or creates) the database, recovering the writes from its log
+    /// 1. Gets the Node address assigned to us by our parent (if it exists)
+    /// 1. Loads the routing table into memory
+    /// 1. Re-encrypts the tokens encrypted with the previous secret (if configured)
+    /// 1. Stores the sequences of the balance logs written by older versions
+    pub async fn connect(&mut self) -> Result<SledStore, ()> {
+        runtime::ensure().map_err(|err| error!("{}", err))?;
+        let (encryption_key, decryption_key) = generate_keys(&self.secret[..]);
+        self.secret.zeroize(); // clear the secret after it has been used for key generation
+        let previous_decryption_key = self.previous_secret.as_mut().map(|previous_secret| {
+            let (_, decryption_key) = generate_keys(&previous_secret[..]);
+            previous_secret.zeroize();
+            decryption_key
+        });
+
+        let mut config = Config::new()
+            .mode(Mode::HighThroughput)
+            .flush_every_ms(Some(self.flush_interval));
+        config = match self.path {
+            Some(ref path) => config.path(path),
+            None => config.temporary(true),
+        };
+        if let Some(cache_capacity) = self.cache_capacity {
+            config = config.cache_capacity(cache_capacity);
+        }
+        let description = self.path.as_ref().map_or_else(
+            || "(temporary)".to_string(),
+            |path| path.display().to_string(),
+        );
+        let db = config
+            .open()
+            .map_err(|err| error!("Error opening sled database at {}: {:?}", description, err))?;
+        debug!("Opened sled database at {}", description);
+
+        // Before initializing the store, check if we have an address
+        // that was configured due to adding a parent. If no parent was
+        // found, use the builder's provided address (local.host) or the
+        // one we decided to override it with
+        let address = get_setting(&db, PARENT_ILP_KEY).map_err(|err| {
+            error!(
+                "Error checking whether we have a parent configured: {:?}",
+                err
+            )
+        })?;
+        let node_ilp_address = if let Some(address) = address {
+            Address::from_str(&address).unwrap()
+        } else {
+            self.node_ilp_address.clone()
+        };
+
+        let routes =
+            load_routes(&db).map_err(|err| error!("Error loading routing table: {:?}", err))?;
+
+        let (all_payment_publisher, _) = broadcast::channel::<PaymentNotification>(256);
+
+        let store = SledStore {
+            ilp_address: Arc::new(RwLock::new(node_ilp_address)),
+            db,
+            write_lock: Arc::new(RwLock::new(())),
+            sync_writes: self.sync_writes,
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            payment_publisher: all_payment_publisher,
+            exchange_rates: Arc::new(RwLock::new(HashMap::new())),
+            exchange_rates_updated_at: Arc::new(RwLock::new(HashMap::new())),
+            routes: Arc::new(RwLock::new(Arc::new(routes))),
+            rate_limits: Arc::new(Mutex::new(HashMap::new())),
+            encryption_key: Arc::new(encryption_key),
+            decryption_key: Arc::new(decryption_key),
+            timers: Arc::new(self.timers.clone()),
+            verified_tokens: Arc::new(VerifiedTokens::new()),
+        };
+
+        store
+            .migrate_tokens(
+                previous_decryption_key
+                    .as_ref()
+                    .map(|key| &key.expose_secret().0),
+            )
+            .await
+            .map_err(|err| error!("Error migrating account tokens: {:?}", err))?;
+        store
+            .migrate_balance_log_sequences()
+            .await
+            .map_err(|err| error!("Error migrating balance log sequences: {:?}", err))?;
+
+        Ok(store)
+    }
+}
+
+/// Amount consumed from a rate limit during the current window
+#[derive(Debug, Clone, Copy)]
+struct RateLimitWindow {
+    started_at: Instant,
+    used: u64,
+}
+
+/// The details of an account as they are stored. Only the hashes of the incoming
+/// tokens are kept and the outgoing tokens are encrypted.
+#[derive(Serialize, Deserialize)]
+struct AccountRecord {
+    id: Uuid,
+    username: String,
+    ilp_address: String,
+    asset_code: String,
+    asset_scale: u8,
+    max_packet_amount: u64,
+    min_packet_amount: Option<u64>,
+    min_balance: Option<i64>,
+    ilp_over_http_url: Option<String>,
+    ilp_over_http_incoming_token: Option<Vec<u8>>,
+    ilp_over_http_outgoing_token: Option<Vec<u8>>,
+    ilp_over_btp_url: Option<String>,
+    ilp_over_btp_incoming_token: Option<Vec<u8>>,
+    ilp_over_btp_outgoing_token: Option<Vec<u8>>,
+    settle_threshold: Option<i64>,
+    settle_to: Option<i64>,
+    settle_every: Option<u32>,
+    routing_relation: String,
+    routing_preference: Option<u32>,
+    round_trip_time: u32,
+    packets_per_minute_limit: Option<u32>,
+    amount_per_minute_limit: Option<u64>,
+    max_payment_amount: Option<u64>,
+    amount_per_day_limit: Option<u64>,
+    settlement_engine_url: Option<String>,
+}
+
+impl AccountRecord {
+    fn new(encrypted: &AccountWithEncryptedTokens) -> Self {
+        let account = &encrypted.account;
+        let token =
+            |token: &Option<SecretBytesMut>| token.as_ref().map(|t| t.expose_secret().to_vec());
+        AccountRecord {
+            id: account.id,
+            username: account.username.to_string(),
+            ilp_address: account.ilp_address.to_string(),
+            asset_code: account.asset_code.clone(),
+            asset_scale: account.asset_scale,
+            max_packet_amount: account.max_packet_amount,
+            min_packet_amount: account.min_packet_amount,
+            min_balance: account.min_balance,
+            ilp_over_http_url: account.ilp_over_http_url.as_ref().map(Url::to_string),
+            ilp_over_http_incoming_token: token(&account.ilp_over_http_incoming_token),
+            ilp_over_http_outgoing_token: token(&account.ilp_over_http_outgoing_token),
+            ilp_over_btp_url: account.ilp_over_btp_url.as_ref().map(Url::to_string),
+            ilp_over_btp_incoming_token: token(&account.ilp_over_btp_incoming_token),
+            ilp_over_btp_outgoing_token: token(&account.ilp_over_btp_outgoing_token),
+            settle_threshold: account.settle_threshold,
+            settle_to: account.settle_to,
+            settle_every: account.settle_every,
+            routing_relation: account.routing_relation.as_ref().to_string(),
+            routing_preference: account.routing_preference,
+            round_trip_time: account.round_trip_time,
+            packets_per_minute_limit: account.packets_per_minute_limit,
+            amount_per_minute_limit: account.amount_per_minute_limit,
+            max_payment_amount: account.max_payment_amount,
+            amount_per_day_limit: account.amount_per_day_limit,
+            settlement_engine_url: account.settlement_engine_url.as_ref().map(Url::to_string),
+        }
+    }
+
+    fn into_account(self) -> StoreResult<AccountWithEncryptedTokens> {
+        let url = |url: Option<String>| {
+            url.map(|url| Url::parse(&url))
+                .transpose()
+                .map_err(|_| invalid_record("Invalid URL"))
+        };
+        let token = |token: Option<Vec<u8>>| {
+            token.map(|token| SecretBytesMut::from(BytesMut::from(token.as_slice())))
+        };
+        Ok(AccountWithEncryptedTokens {
+            account: Account {
+                id: self.id,
+                username: Username::from_str(&self.username)
+                    .map_err(|_| invalid_record("Invalid username"))?,
+                ilp_address: Address::from_str(&self.ilp_address)
+                    .map_err(|_| invalid_record("Invalid ILP address"))?,
+                asset_code: self.asset_code,
+                asset_scale: self.asset_scale,
+                max_packet_amount: self.max_packet_amount,
+                min_packet_amount: self.min_packet_amount,
+                min_balance: self.min_balance,
+                ilp_over_http_url: url(self.ilp_over_http_url)?,
+                ilp_over_http_incoming_token: token(self.ilp_over_http_incoming_token),
+                ilp_over_http_outgoing_token: token(self.ilp_over_http_outgoing_token),
+                ilp_over_btp_url: url(self.ilp_over_btp_url)?,
+                ilp_over_btp_incoming_token: token(self.ilp_over_btp_incoming_token),
+                ilp_over_btp_outgoing_token: token(self.ilp_over_btp_outgoing_token),
+                settle_threshold: self.settle_threshold,
+                settle_to: self.settle_to,
+                settle_every: self.settle_every,
+                routing_relation: RoutingRelation::from_str(&self.routing_relation)
+                    .map_err(|_| invalid_record("Invalid Routing Relation"))?,
+                routing_preference: self.routing_preference,
+                round_trip_time: self.round_trip_time,
+                packets_per_minute_limit: self.packets_per_minute_limit,
+                amount_per_minute_limit: self.amount_per_minute_limit,
+                max_payment_amount: self.max_payment_amount,
+                amount_per_day_limit: self.amount_per_day_limit,
+                settlement_engine_url: url(self.settlement_engine_url)?,
+            },
+        })
+    }
+}
+
+/// The balance of an account, which is stored apart from its details so that
+/// balance updates don't rewrite them
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Balances {
+    balance: i64,
+    prepaid_amount: i64,
+}
+
+/// A cached settlement API response
+#[derive(Serialize, Deserialize)]
+struct IdempotencyRecord {
+    status_code: u16,
+    data: Vec<u8>,
+    input_hash: [u8; 32],
+    expires_at: u64,
+}
+
+/// A settlement amount which could not be credited due to precision loss. The amount
+/// is kept as a string, as it may not fit into 64 bits.
+#[derive(Serialize, Deserialize)]
+struct UncreditedAmount {
+    amount: String,
+    scale: u8,
+}
+
+/// A Store that uses an embedded sled database.
+///
+/// The balance updates, the packet journal and the daily volumes are written in
+/// sled transactions on the records they change, so that the packets of different
+/// accounts are processed concurrently. The other operations which write read the
+/// records with scans, which transactions don't support, so they run while holding
+/// a lock and apply their changes in a single atomic batch. Either way they are
+/// atomic with respect to the other operations on the same store. Reads don't take
+/// the lock.
+///
+/// Like the SqliteStore, this store is meant to be used by a single node
+/// process, so the routing table is reloaded whenever it is modified rather
+/// than polled for.
+#[derive(Clone)]
+pub struct SledStore {
+    /// The Store's ILP Address
+    ilp_address: Arc<RwLock<Address>>,
+    /// The database, which can be shared between threads
+    db: Db,
+    /// Held exclusively by the operations which write in batches, so that their reads
+    /// are not outdated by the time their writes are applied, and shared by the
+    /// transactions, which sled only isolates from each other
+    write_lock: Arc<RwLock<()>>,
+    /// Whether the writes are flushed to disk before the operations return
+    sync_writes: bool,
+    /// WebSocket senders which publish incoming payment updates
+    subscriptions: Arc<Mutex<HashMap<Uuid, Vec<UnboundedSender<PaymentNotification>>>>>,
+    /// A subscriber to all payment notifications, exposed via a WebSocket
+    payment_publisher: broadcast::Sender<PaymentNotification>,
+    exchange_rates: Arc<RwLock<HashMap<String, f64>>>,
+    /// When each of the exchange rates was last set
+    exchange_rates_updated_at: Arc<RwLock<HashMap<String, SystemTime>>>,
+    /// The store keeps the routing table in memory so that it can be returned
+    /// synchronously while the Router is processing packets.
+    routes: Arc<RwLock<Arc<HashMap<String, Uuid>>>>,
+    /// Packet and throughput counters for the per-minute rate limits
+    rate_limits: Arc<Mutex<HashMap<String, RateLimitWindow>>>,
+    /// Encryption Key so that the no cleartext data are stored
+    encryption_key: Arc<Secret<EncryptionKey>>,
+    /// Decryption Key to provide cleartext data to users
+    decryption_key: Arc<Secret<DecryptionKey>>,
+    /// Records the duration of store operations
+    timers: Arc<OperationTimers>,
+    /// Incoming tokens which were recently verified against their hashes
+    verified_tokens: Arc<VerifiedTokens>,
+}
+
+impl SledStore {
+    /// Runs the provided read-modify-write operation. The writes it adds to the batch
+    /// are applied atomically once it returns, and no other operation writes meanwhile.
+    /// Nothing is written if it returns an error.
+    async fn write<T, F>(&self, f: F) -> StoreResult<T>
+    where
+        F: FnOnce(&Db, &mut Batch) -> StoreResult<T>,
+    {
+        let result = {
+            let _guard = self.write_lock.write();
+            let mut batch = Batch::default();
+            let result = f(&self.db, &mut batch)?;
+            self.db.apply_batch(batch)?;
+            result
+        };
+        self.sync().await?;
+        Ok(result)
+    }
+
+    /// Runs the provided read-modify-write operation in a transaction on the records it
+    /// reads and writes, concurrently with the other transactions. sled runs it again if
+    /// another transaction changed one of these records meanwhile, so it must not have
+    /// side effects. Nothing is written if it aborts.
+    async fn transact<T, F>(&self, f: F) -> StoreResult<T>
+    where
+        F: Fn(&TransactionalTree) -> ConflictableTransactionResult<T, SledStoreError>,
+    {
+        let result = {
+            let _guard = self.write_lock.read();
+            self.db.transaction(f)
+        };
+        let result = result.map_err(|err| match err {
+            TransactionError::Abort(err) => err,
+            TransactionError::Storage(err) => SledStoreError::Db(err),
+        })?;
+        self.sync().await?;
+        Ok(result)
+    }
+
+    /// With `sync_writes`, flushes the writes to disk before the operation returns. sled
+    /// blocks until the log was written, so this runs on the blocking threads.
+    async fn sync(&self) -> StoreResult<()> {
+        if self.sync_writes {
+            let db = self.db.clone();
+            runtime::run_blocking(move || db.flush()).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes all changes to disk. The node calls this on shutdown, so that the writes
+    /// since the last periodic flush are not lost.
+    pub fn flush(&self) -> Result<(), ()> {
+        self.db
+            .flush()
+            .map(|bytes| trace!("Flushed {} bytes to disk", bytes))
+            .map_err(|err| error!("Error flushing sled database: {:?}", err))
+    }
+
+    /// Reloads the in-memory routing table from the database
+    fn update_routes(&self) -> StoreResult<()> {
+        let routes = load_routes(&self.db)?;
+        // TODO we may not want to print this because the routing table will be very big
+        // if the node has a lot of local accounts
+        trace!("Routing table is: {:?}", routes);
+        *self.routes.write() = Arc::new(routes);
+        Ok(())
+    }
+
+    /// Re-encrypts the tokens of all accounts which can only be decrypted with the given
+    /// key (derived from the previous secret) with the current encryption key, and replaces
+    /// the incoming tokens which are still stored encrypted with their hashes
+    async fn migrate_tokens(
+        &self,
+        previous_decryption_key: Option<&aead::LessSafeKey>,
+    ) -> StoreResult<()> {
+        let decryption_key = &self.decryption_key.expose_secret().0;
+        let mut decryption_keys = vec![decryption_key];
+        decryption_keys.extend(previous_decryption_key);
+        let num_tokens = self
+            .write(|db, batch| {
+                let mut num_tokens = 0;
+                for mut account in load_accounts(db, None, usize::MAX, |_| true)? {
+                    let mut migrated = account.hash_incoming_tokens(&decryption_keys);
+                    if let Some(previous_decryption_key) = previous_decryption_key {
+                        migrated.extend(account.reencrypt_tokens(
+                            previous_decryption_key,
+                            decryption_key,
+                            &self.encryption_key.expose_secret().0,
+                        ));
+                    }
+                    if !migrated.is_empty() {
+                        num_tokens += migrated.len();
+                        write_account(batch, &account);
+                    }
+                }
+                Ok(num_tokens)
+            })
+            .await?;
+        if num_tokens > 0 {
+            debug!("Migrated {} account tokens", num_tokens);
+        }
+        Ok(())
+    }
+
+    /// Stores the sequence of the next entry of the balance logs written by older versions,
+    /// which found it with a scan of the log. Scans are not supported in the transactions
+    /// which update the balances.
+    async fn migrate_balance_log_sequences(&self) -> StoreResult<()> {
+        let num_accounts = self
+            .write(|db, batch| {
+                let mut num_accounts = 0;
+                for (_, account) in scan::<AccountRecord>(db, &table_prefix(ACCOUNTS))? {
+                    let sequence_key = key(BALANCE_LOG_SEQUENCES, account.id);
+                    if db.contains_key(&sequence_key)? {
+                        continue;
+                    }
+                    let last = db
+                        .scan_prefix(account_prefix(BALANCE_LOG, account.id))
+                        .next_back()
+                        .transpose()?;
+                    if let Some((_, value)) = last {
+                        let next_sequence = decode::<BalanceChange>(&value)?.sequence + 1;
+                        batch.insert(sequence_key, encode(&next_sequence));
+                        num_accounts += 1;
+                    }
+                }
+                Ok(num_accounts)
+            })
+            .await?;
+        if num_accounts > 0 {
+            debug!(
+                "Migrated the balance log sequences of {} accounts",
+                num_accounts
+            );
+        }
+        Ok(())
+    }
+
+    /// Gets the account (tokens remain encrypted) corresponding to the provided `id`
+    fn sled_get_account(&self, id: Uuid) -> Result<AccountWithEncryptedTokens, NodeStoreError> {
+        load_account(&self.db, id)?.ok_or_else(|| NodeStoreError::AccountNotFound(id.to_string()))
+    }
+
+    /// Gets the account (tokens remain encrypted) corresponding to the provided `username`
+    fn sled_get_account_by_username(
+        &self,
+        username: &Username,
+    ) -> StoreResult<Option<AccountWithEncryptedTokens>> {
+        match get::<Uuid>(&self.db, &key(USERNAMES, username))? {
+            Some(id) => load_account(&self.db, id),
+            None => Ok(None),
+        }
+    }
+
+    /// Inserts the account corresponding to the provided `AccountWithEncryptedtokens`
+    async fn sled_insert_account(
+        &self,
+        encrypted: &AccountWithEncryptedTokens,
+    ) -> Result<(), NodeStoreError> {
+        let account = &encrypted.account;
+        let inserted = self
+            .write(|db, batch| {
+                // Check that there isn't already an account with values that MUST be unique
+                if db.contains_key(key(ACCOUNTS, account.id))?
+                    || db.contains_key(key(USERNAMES, &account.username))?
+                {
+                    return Ok(false);
+                }
+
+                write_account(batch, encrypted);
+                batch.insert(key(BALANCES, account.id), encode(&Balances::default()));
+                batch.insert(key(ROUTES, &account.ilp_address), encode(&account.id));
+                Ok(true)
+            })
+            .await?;
+        if !inserted {
+            warn!(
+                "An account already exists with the same {}. Cannot insert account: {:?}",
+                account.id, account
+            );
+            return Err(NodeStoreError::AccountExists(account.username.to_string()));
+        }
+
+        self.update_routes()?;
+        debug!(
+            "Inserted account {} (ILP address: {})",
+            account.id, account.ilp_address
+        );
+        Ok(())
+    }
+
+    /// Overwrites the account corresponding to the provided `AccountWithEncryptedtokens`
+    async fn sled_update_account(
+        &self,
+        encrypted: &AccountWithEncryptedTokens,
+    ) -> Result<(), NodeStoreError> {
+        let account = &encrypted.account;
+        self.write(|db, batch| {
+            let old = match get::<AccountRecord>(db, &key(ACCOUNTS, account.id))? {
+                Some(old) => old,
+                None => {
+                    warn!(
+                        "No account exists with ID {}, cannot update account {:?}",
+                        account.id, account
+                    );
+                    return Ok(Err(NodeStoreError::AccountNotFound(account.id.to_string())));
+                }
+            };
+            let owner = get::<Uuid>(db, &key(USERNAMES, &account.username))?;
+            if owner.map_or(false, |owner| owner != account.id) {
+                warn!(
+                    "Username {} is already taken, cannot update account {}",
+                    account.username, account.id
+                );
+                return Ok(Err(NodeStoreError::AccountExists(
+                    account.username.to_string(),
+                )));
+            }
+
+            batch.remove(key(USERNAMES, &old.username));
+            write_account(batch, encrypted);
+            // Replace the route to the previous address
+            if get::<Uuid>(db, &key(ROUTES, &old.ilp_address))? == Some(account.id) {
+                batch.remove(key(ROUTES, &old.ilp_address));
+            }
+            batch.insert(key(ROUTES, &account.ilp_address), encode(&account.id));
+            Ok(Ok(()))
+        })
+        .await??;
+
+        self.update_routes()?;
+        debug!(
+            "Updated account {} (id: {}, ILP address: {})",
+            account.username, account.id, account.ilp_address
+        );
+        Ok(())
+    }
+
+    /// Deletes the account corresponding to the provided `id`.
+    /// Returns the deleted account (tokens remain encrypted)
+    async fn sled_delete_account(
+        &self,
+        id: Uuid,
+    ) -> Result<AccountWithEncryptedTokens, NodeStoreError> {
+        let encrypted = self.sled_get_account(id)?;
+        let account = &encrypted.account;
+        self.write(|db, batch| {
+            batch.remove(key(ACCOUNTS, id));
+            batch.remove(key(USERNAMES, &account.username));
+            batch.remove(key(BALANCES, id));
+            // Remove all routes which point to the account, including the ones learned via CCP
+            for table in &[ROUTES, STATIC_ROUTES] {
+                for (prefix, account_id) in scan::<Uuid>(db, &table_prefix(table))? {
+                    if account_id == id {
+                        batch.remove(key(table, prefix));
+                    }
+                }
+            }
+            if get::<Uuid>(db, &key(SETTINGS, DEFAULT_ROUTE_KEY))? == Some(id) {
+                batch.remove(key(SETTINGS, DEFAULT_ROUTE_KEY));
+            }
+            // Forget the address assigned by the parents once the last one is gone
+            if account.routing_relation == RoutingRelation::Parent {
+                let parent = RoutingRelation::Parent.as_ref();
+                let other_parents = load_accounts(db, None, 1, |record| {
+                    record.id != id && record.routing_relation == parent
+                })?;
+                if other_parents.is_empty() {
+                    batch.remove(key(SETTINGS, PARENT_ILP_KEY));
+                }
+            }
+            for table in &[UNCREDITED_AMOUNTS, DAILY_VOLUMES, INCOMING_PAYMENTS] {
+                for entry in db.scan_prefix(account_prefix(table, id)) {
+                    batch.remove(entry?.0);
+                }
+            }
+            Ok(())
+        })
+        .await?;
+        {
+            let mut rate_limits = self.rate_limits.lock();
+            rate_limits.remove(&format!("limit:packets:{}", account.id));
+            rate_limits.remove(&format!("limit:throughput:{}", account.id));
+        }
+
+        self.update_routes()?;
+        debug!("Deleted account {}", account.id);
+        Ok(encrypted)
+    }
+
+    /// Updates the balance of the account with the provided function, which returns the
+    /// new balance and the entries to add to the account's balance change log
+    async fn update_balance<T, F>(
+        &self,
+        account_id: Uuid,
+        packet_id: Option<PacketId>,
+        f: F,
+    ) -> StoreResult<T>
+    where
+        F: Fn(&AccountRecord, &mut Balances, &mut BalanceLog) -> T,
+    {
+        self.transact(|tx| {
+            let (account, mut balances, mut log) = load_balance_in(tx, account_id, packet_id)?;
+            let result = f(&account, &mut balances, &mut log);
+            if !log.changes.is_empty() {
+                tx.insert(key(BALANCES, account_id), encode(&balances))?;
+                log.write_in(tx)?;
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// Same as `update_balance` for changes which return (balance, amount_to_settle), and
+    /// adds the amount to settle to the account's queued outgoing settlement in the same
+    /// write, so that it is settled even if the node stops before sending the settlement
+    async fn update_balance_and_queue_settlement<F>(
+        &self,
+        account_id: Uuid,
+        packet_id: Option<PacketId>,
+        f: F,
+    ) -> StoreResult<(i64, u64)>
+    where
+        F: Fn(&AccountRecord, &mut Balances, &mut BalanceLog) -> (i64, u64),
+    {
+        self.transact(|tx| {
+            let (account, mut balances, mut log) = load_balance_in(tx, account_id, packet_id)?;
+            let (balance, settle_amount) = f(&account, &mut balances, &mut log);
+            if !log.changes.is_empty() {
+                tx.insert(key(BALANCES, account_id), encode(&balances))?;
+                log.write_in(tx)?;
+            }
+            if settle_amount > 0 {
+                let queued_key = key(QUEUED_SETTLEMENTS, account_id);
+                let queued = tx_get::<u64>(tx, &queued_key)?.unwrap_or(0);
+                tx.insert(queued_key, encode(&(queued + settle_amount)))?;
+            }
+            Ok((balance, settle_amount))
+        })
+        .await
+    }
+
+    fn decrypt(&self, encrypted: AccountWithEncryptedTokens) -> Account {
+        encrypted.decrypt_tokens(&self.decryption_key.expose_secret().0)
+    }
+
+    fn encrypt(&self, token: &SecretString) -> Bytes {
+        encrypt_token(
+            &self.encryption_key.expose_secret().0,
+            token.expose_secret().as_bytes(),
+        )
+        .freeze()
+    }
+}
+
+#[async_trait]
+impl AccountStore for SledStore {
+    type Account = Account;
+
+    async fn get_accounts(
+        &self,
+        account_ids: Vec<Uuid>,
+    ) -> Result<Vec<Account>, AccountStoreError> {
+        let _timer = self.timers.start("get_accounts");
+        let num_accounts = account_ids.len();
+        let mut accounts = Vec::with_capacity(num_accounts);
+        for id in account_ids.iter() {
+            if let Some(account) = load_account(&self.db, *id)? {
+                accounts.push(self.decrypt(account));
+            }
+        }
+
+        if accounts.len() == num_accounts {
+            Ok(accounts)
+        } else {
+            Err(AccountStoreError::WrongLength {
+                expected: num_accounts,
+                actual: accounts.len(),
+            })
+        }
+    }
+
+    async fn get_account_id_from_username(
+        &self,
+        username: &Username,
+    ) -> Result<Uuid, AccountStoreError> {
+        let _timer = self.timers.start("get_account_id_from_username");
+        match get::<Uuid>(&self.db, &key(USERNAMES, username))? {
+            Some(id) => Ok(id),
+            None => {
+                debug!("Username not found: {}", username);
+                Err(AccountStoreError::AccountNotFound(username.to_string()))
+            }
+        }
+    }
+}
+
+impl StreamNotificationsStore for SledStore {
+    type Account = Account;
+
+    fn add_payment_notification_subscription(
+        &self,
+        id: Uuid,
+        sender: UnboundedSender<PaymentNotification>,
+    ) {
+        trace!("Added payment notification listener for {}", id);
+        self.subscriptions
+            .lock()
+            .entry(id)
+            .or_insert_with(Vec::new)
+            .push(sender);
+    }
+
+    fn publish_payment_notification(&self, payment: PaymentNotification) {
+        // There is no pubsub to go through, so the notification is delivered
+        // to the in-process subscribers directly
+        let account_id = match get::<Uuid>(&self.db, &key(USERNAMES, &payment.to_username)) {
+            Ok(Some(account_id)) => account_id,
+            _ => {
+                error!(
+                    "Failed to find account ID corresponding to username: {}",
+                    payment.to_username
+                );
+                return;
+            }
+        };
+
+        trace!(
+            "Publishing payment notification {:?} for account {}",
+            payment,
+            account_id
+        );
+        if self.payment_publisher.receiver_count() > 0 {
+            if let Err(err) = self.payment_publisher.send(payment.clone()) {
+                error!("Failed to send a node-wide payment notification: {:?}", err);
+            }
+        }
+        match self.subscriptions.lock().get_mut(&account_id) {
+            Some(senders) => {
+                senders.retain(|sender| {
+                    if let Err(err) = sender.unbounded_send(payment.clone()) {
+                        debug!("Failed to send message: {}", err);
+                        false
+                    } else {
+                        true
+                    }
+                });
+            }
+            None => trace!(
+                "Ignoring message for account {} because there were no open subscriptions",
+                account_id
+            ),
+        }
+    }
+
+    fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
+        self.payment_publisher.subscribe()
+    }
+}
+
+#[async_trait]
+impl IncomingPaymentsStore for SledStore {
+    async fn record_incoming_payment(
+        &self,
+        account_id: Uuid,
+        destination: &Address,
+        amount: u64,
+        closed: bool,
+        timestamp: u64,
+    ) -> Result<IncomingPayment, BalanceStoreError> {
+        let _timer = self.timers.start("record_incoming_payment");
+        let payment = self
+            .write(|db, batch| {
+                let payment_key = account_key(INCOMING_PAYMENTS, account_id, destination);
+                let payment = match get::<IncomingPayment>(db, &payment_key)? {
+                    Some(payment) => IncomingPayment {
+                        amount: payment.amount.saturating_add(amount),
+                        closed: payment.closed || closed,
+                        acknowledged: payment.acknowledged && amount == 0,
+                        updated_at: timestamp,
+                        ..payment
+                    },
+                    None => IncomingPayment {
+                        account_id,
+                        destination: destination.clone(),
+                        amount,
+                        closed,
+                        acknowledged: false,
+                        updated_at: timestamp,
+                    },
+                };
+                batch.insert(payment_key, encode(&payment));
+                Ok(payment)
+            })
+            .await?;
+        Ok(payment)
+    }
+
+    async fn acknowledge_incoming_payment(
+        &self,
+        account_id: Uuid,
+        destination: &Address,
+    ) -> Result<Option<IncomingPayment>, BalanceStoreError> {
+        let _timer = self.timers.start("acknowledge_incoming_payment");
+        let payment = self
+            .write(|db, batch| {
+                let payment_key = account_key(INCOMING_PAYMENTS, account_id, destination);
+                let payment =
+                    get::<IncomingPayment>(db, &payment_key)?.map(|payment| IncomingPayment {
+                        acknowledged: true,
+                        ..payment
+                    });
+                if let Some(ref payment) = payment {
+                    batch.insert(payment_key, encode(payment));
+                }
+                Ok(payment)
+            })
+            .await?;
+        Ok(payment)
+    }
+
+    async fn get_unacknowledged_payments(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Vec<IncomingPayment>, BalanceStoreError> {
+        let _timer = self.timers.start("get_unacknowledged_payments");
+        let mut payments: Vec<IncomingPayment> =
+            scan::<IncomingPayment>(&self.db, &account_prefix(INCOMING_PAYMENTS, account_id))?
+                .into_iter()
+                .map(|(_, payment)| payment)
+                .filter(|payment| !payment.acknowledged)
+                .collect();
+        payments.sort_by_key(|payment| payment.updated_at);
+        Ok(payments)
+    }
+}
+
+#[async_trait]
+impl BalanceStore for SledStore {
+    /// Returns the balance **from the account holder's perspective**, meaning the sum of
+    /// the Payable Balance and Pending Outgoing minus the Receivable Balance and the Pending Incoming.
+    async fn get_balance(&self, account_id: Uuid) -> Result<i64, BalanceStoreError> {
+        let _timer = self.timers.start("get_balance");
+        let balances = get_balances(&self.db, account_id)?;
+        Ok(balances.balance + balances.prepaid_amount)
+    }
+
+    async fn update_balances_for_prepare(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+        packet_id: Option<PacketId>,
+    ) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("update_balances_for_prepare");
+        // Don't do anything if the amount was 0
+        if incoming_amount == 0 {
+            return Ok(());
+        }
+
+        let result = self
+            .update_balance(from_account_id, packet_id, |account, balances, log| {
+                let amount = incoming_amount as i64;
+                let Balances {
+                    balance,
+                    prepaid_amount,
+                } = *balances;
+
+                // Check that the prepare wouldn't go under the account's minimum balance
+                if let Some(min_balance) = account.min_balance {
+                    if balance + prepaid_amount - amount < min_balance {
+                        return Err(MinBalanceExceeded {
+                            account_id: from_account_id,
+                            amount: incoming_amount,
+                            balance,
+                            min_balance,
+                        });
+                    }
+                }
+
+                // Deduct the amount from the prepaid_amount and/or the balance
+                *balances = if prepaid_amount >= amount {
+                    Balances {
+                        balance,
+                        prepaid_amount: prepaid_amount - amount,
+                    }
+                } else if prepaid_amount > 0 {
+                    Balances {
+                        balance: balance - (amount - prepaid_amount),
+                        prepaid_amount: 0,
+                    }
+                } else {
+                    Balances {
+                        balance: balance - amount,
+                        prepaid_amount,
+                    }
+                };
+                log.push(BalanceChangeReason::Prepare, -amount, balances);
+                Ok(balances.balance + balances.prepaid_amount)
+            })
+            .await?;
+
+        let balance = result.map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+        trace!(
+            "Processed prepare with incoming amount: {}. Account {} has balance (including prepaid amount): {} ",
+            incoming_amount, from_account_id, balance
+        );
+        Ok(())
+    }
+
+    async fn update_balances_for_fulfill(
+        &self,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+        packet_id: Option<PacketId>,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        let _timer = self.timers.start("update_balances_for_fulfill");
+        let (balance, amount_to_settle) = self
+            .update_balance(to_account_id, packet_id, |account, balances, log| {
+                process_fulfill(account, balances, log, outgoing_amount)
+            })
+            .await?;
+
+        trace!(
+            "Processed fulfill for account {} for outgoing amount {}. Fulfill call result: {} {}",
+            to_account_id,
+            outgoing_amount,
+            balance,
+            amount_to_settle,
+        );
+        Ok((balance, amount_to_settle))
+    }
+
+    async fn update_balances_for_fulfill_and_queue_settlement(
+        &self,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+        packet_id: Option<PacketId>,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        let _timer = self
+            .timers
+            .start("update_balances_for_fulfill_and_queue_settlement");
+        let (balance, amount_to_settle) = self
+            .update_balance_and_queue_settlement(
+                to_account_id,
+                packet_id,
+                |account, balances, log| process_fulfill(account, balances, log, outgoing_amount),
+            )
+            .await?;
+
+        trace!(
+            "Processed fulfill for account {} for outgoing amount {}. Fulfill call result: {} {}",
+            to_account_id,
+            outgoing_amount,
+            balance,
+            amount_to_settle,
+        );
+        Ok((balance, amount_to_settle))
+    }
+
+    async fn update_balances_for_reject(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+        packet_id: Option<PacketId>,
+    ) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("update_balances_for_reject");
+        if incoming_amount == 0 {
+            return Ok(());
+        }
+
+        let balance = self
+            .update_balance(from_account_id, packet_id, |_, balances, log| {
+                balances.balance += incoming_amount as i64;
+                log.push(
+                    BalanceChangeReason::Reject,
+                    incoming_amount as i64,
+                    balances,
+                );
+                balances.balance + balances.prepaid_amount
+            })
+            .await?;
+
+        trace!(
+            "Processed reject for incoming amount: {}. Account {} has balance (including prepaid amount): {}",
+            incoming_amount, from_account_id, balance
+        );
+
+        Ok(())
+    }
+
+    async fn update_balances_for_delayed_settlement(
+        &self,
+        to_account_id: Uuid,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        let _timer = self.timers.start("update_balances_for_delayed_settlement");
+        let (balance, amount_to_settle) = self
+            .update_balance_and_queue_settlement(to_account_id, None, |account, balances, log| {
+                // upon completion the `balance` is at the level of `settle_to`
+                let mut settle_amount = 0;
+                if let (Some(settle_threshold), Some(settle_to)) =
+                    (account.settle_threshold, account.settle_to)
+                {
+                    if settle_threshold > settle_to && balances.balance >= settle_to {
+                        settle_amount = (balances.balance - settle_to) as u64;
+                        balances.balance = settle_to;
+                        log.push(
+                            BalanceChangeReason::Settlement,
+                            -(settle_amount as i64),
+                            balances,
+                        );
+                    }
+                }
+                (balances.balance + balances.prepaid_amount, settle_amount)
+            })
+            .await?;
+
+        trace!(
+            "Processed account {} for delayed settlement, balance: {}, to_settle: {}",
+            to_account_id,
+            balance,
+            amount_to_settle
+        );
+
+        Ok((balance, amount_to_settle))
+    }
+
+    async fn get_balance_changes(
+        &self,
+        account_id: Uuid,
+        from: u64,
+        limit: usize,
+    ) -> Result<Vec<BalanceChange>, BalanceStoreError> {
+        let _timer = self.timers.start("get_balance_changes");
+        let prefix = account_prefix(BALANCE_LOG, account_id);
+        let start = numbered_key(BALANCE_LOG, account_id, from);
+        let mut changes = Vec::new();
+        for entry in self.db.range(start..).take(limit) {
+            let (key, value) = entry.map_err(SledStoreError::from)?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            changes.push(decode::<BalanceChange>(&value)?);
+        }
+        Ok(changes)
+    }
+
+    async fn get_balance_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<BalanceTotals, BalanceStoreError> {
+        let _timer = self.timers.start("get_balance_totals");
+        let mut totals = BalanceTotals::default();
+        for (_, change) in
+            scan::<BalanceChange>(&self.db, &account_prefix(BALANCE_LOG, account_id))?
+        {
+            totals.add(change.reason, change.delta);
+        }
+        Ok(totals)
+    }
+}
+
+#[async_trait]
+impl PacketJournalStore for SledStore {
+    async fn journal_packet(&self, packet: JournaledPacket) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("journal_packet");
+        self.transact(|tx| {
+            tx.insert(key(PACKET_JOURNAL, packet.id), encode(&packet))?;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn mark_journaled_packet_fulfilled(&self, id: Uuid) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("mark_journaled_packet_fulfilled");
+        self.transact(|tx| {
+            if let Some(packet) = tx_get::<JournaledPacket>(tx, &key(PACKET_JOURNAL, id))? {
+                let packet = JournaledPacket {
+                    fulfilled: true,
+                    ..packet
+                };
+                tx.insert(key(PACKET_JOURNAL, id), encode(&packet))?;
+            }
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_journaled_packet(&self, id: Uuid) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("remove_journaled_packet");
+        self.transact(|tx| {
+            tx.remove(key(PACKET_JOURNAL, id))?;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn load_journaled_packets(&self) -> Result<Vec<JournaledPacket>, BalanceStoreError> {
+        let _timer = self.timers.start("load_journaled_packets");
+        let packets = scan::<JournaledPacket>(&self.db, &table_prefix(PACKET_JOURNAL))?;
+        Ok(packets.into_iter().map(|(_, packet)| packet).collect())
+    }
+}
+
+#[async_trait]
+impl PacketHistoryStore for SledStore {
+    async fn record_packets(&self, records: Vec<PacketRecord>) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("record_packets");
+        self.write(|db, batch| {
+            for record in records {
+                // The ids only increase, so each account's records are sorted oldest first
+                let id = db.generate_id()?;
+                let record = PacketRecord { id, ..record };
+                batch.insert(
+                    numbered_key(PACKET_HISTORY, record.account_id, id),
+                    encode(&record),
+                );
+            }
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn get_packet_history(
+        &self,
+        account_id: Uuid,
+        filter: &PacketHistoryFilter,
+        limit: usize,
+    ) -> Result<Vec<PacketRecord>, BalanceStoreError> {
+        let _timer = self.timers.start("get_packet_history");
+        let start = Bound::Included(account_prefix(PACKET_HISTORY, account_id));
+        let end = match filter.before {
+            Some(before) => Bound::Excluded(numbered_key(PACKET_HISTORY, account_id, before)),
+            None => Bound::Included(numbered_key(PACKET_HISTORY, account_id, u64::MAX)),
+        };
+        let mut records = Vec::new();
+        // Newest first
+        for entry in self.db.range((start, end)).rev() {
+            if records.len() == limit {
+                break;
+            }
+            let (_, value) = entry.map_err(SledStoreError::from)?;
+            let record: PacketRecord = decode(&value)?;
+            if filter.matches(&record) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    async fn prune_packet_history(&self, older_than: u64) -> Result<u64, BalanceStoreError> {
+        let _timer = self.timers.start("prune_packet_history");
+        let deleted = self
+            .write(|db, batch| {
+                let mut deleted = 0;
+                for entry in db.scan_prefix(table_prefix(PACKET_HISTORY)) {
+                    let (key, value) = entry?;
+                    if decode::<PacketRecord>(&value)?.timestamp < older_than {
+                        batch.remove(key);
+                        deleted += 1;
+                    }
+                }
+                Ok(deleted)
+            })
+            .await?;
+        Ok(deleted)
+    }
+}
+
+impl ExchangeRateStore for SledStore {
+    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        let rates: Vec<f64> = asset_codes
+            .iter()
+            .filter_map(|code| (*self.exchange_rates.read()).get(*code).cloned())
+            .collect();
+        if rates.len() == asset_codes.len() {
+            Ok(rates)
+        } else {
+            Err(ExchangeRateStoreError::PairNotFound {
+                from: asset_codes[0].to_string(),
+                to: asset_codes[1].to_string(),
+            })
+        }
+    }
+
+    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        Ok((*self.exchange_rates.read()).clone())
+    }
+
+    fn set_exchange_rates(
+        &self,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        let now = SystemTime::now();
+        (*self.exchange_rates_updated_at.write()) =
+            rates.keys().map(|code| (code.clone(), now)).collect();
+        (*self.exchange_rates.write()) = rates;
+        Ok(())
+    }
+
+    fn get_exchange_rates_updated_at(&self, asset_codes: &[&str]) -> Option<SystemTime> {
+        let updated_at = self.exchange_rates_updated_at.read();
+        asset_codes
+            .iter()
+            .filter_map(|code| updated_at.get(*code).cloned())
+            .min()
+    }
+}
+
+#[async_trait]
+impl BtpStore for SledStore {
+    type Account = Account;
+
+    async fn get_account_from_btp_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, BtpStoreError> {
+        let _timer = self.timers.start("get_account_from_btp_auth");
+        let account = self
+            .sled_get_account_by_username(username)?
+            .map(|account| self.decrypt(account));
+        self.verified_tokens
+            .authenticate(AuthProtocol::Btp, username, account, token)
+            .await
+            .map_err(|err| match err {
+                AuthError::AccountNotFound => BtpStoreError::AccountNotFound(username.to_string()),
+                _ => BtpStoreError::Unauthorized(username.to_string()),
+            })
+    }
+
+    async fn get_btp_outgoing_accounts(&self) -> Result<Vec<Self::Account>, BtpStoreError> {
+        let _timer = self.timers.start("get_btp_outgoing_accounts");
+        let accounts = load_accounts(&self.db, None, usize::MAX, |record| {
+            record.ilp_over_btp_url.is_some()
+        })?;
+        Ok(accounts
+            .into_iter()
+            .map(|account| self.decrypt(account))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl HttpStore for SledStore {
+    type Account = Account;
+
+    /// Checks if the stored token for the provided account id matches the
+    /// provided token, and if so, returns the account associated with that token
+    async fn get_account_from_http_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, HttpStoreError> {
+        let _timer = self.timers.start("get_account_from_http_auth");
+        let account = self
+            .sled_get_account_by_username(username)?
+            .map(|account| self.decrypt(account));
+        self.verified_tokens
+            .authenticate(AuthProtocol::Http, username, account, token)
+            .await
+            .map_err(|err| match err {
+                AuthError::AccountNotFound => HttpStoreError::AccountNotFound(username.to_string()),
+                _ => HttpStoreError::Unauthorized(username.to_string()),
+            })
+    }
+}
+
+impl RouterStore for SledStore {
+    fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
+        self.routes.read().clone()
+    }
+}
+
+#[async_trait]
+impl NodeStore for SledStore {
+    type Account = Account;
+
+    async fn insert_account(
+        &self,
+        account: AccountDetails,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let _timer = self.timers.start("insert_account");
+        let id = Uuid::new_v4();
+        let account = Account::try_from(id, account, self.get_ilp_address())
+            .map_err(NodeStoreError::InvalidAccount)?;
+        debug!(
+            "Generated account id for {}: {}",
+            account.username, account.id
+        );
+        let encrypted = account
+            .clone()
+            .encrypt_tokens(&self.encryption_key.expose_secret().0);
+
+        self.sled_insert_account(&encrypted).await?;
+        Ok(account)
+    }
+
+    async fn insert_accounts(
+        &self,
+        accounts: Vec<AccountDetails>,
+    ) -> Result<Vec<Self::Account>, NodeStoreError> {
+        let _timer = self.timers.start("insert_accounts");
+        let mut errors = Vec::new();
+        let mut valid = Vec::with_capacity(accounts.len());
+        for (index, details) in accounts.into_iter().enumerate() {
+            match Account::try_from(Uuid::new_v4(), details, self.get_ilp_address()) {
+                Ok(account) => valid.push((index, account)),
+                Err(err) => errors.push((index, NodeStoreError::InvalidAccount(err))),
+            }
+        }
+        let encrypted: Vec<AccountWithEncryptedTokens> = valid
+            .iter()
+            .map(|(_, account)| {
+                account
+                    .clone()
+                    .encrypt_tokens(&self.encryption_key.expose_secret().0)
+            })
+            .collect();
+
+        // The accounts are written in a single batch, which is only applied if all of
+        // them are valid. The accounts before count towards the unique values, so the
+        // duplicates within the batch are reported too.
+        let conflicts = self
+            .write(|db, batch| {
+                let mut conflicts = Vec::new();
+                let mut usernames = Vec::with_capacity(valid.len());
+                for (index, account) in valid.iter() {
+                    if db.contains_key(key(USERNAMES, &account.username))?
+                        || usernames.contains(&&account.username)
+                    {
+                        conflicts.push((
+                            *index,
+                            NodeStoreError::AccountExists(account.username.to_string()),
+                        ));
+                    }
+                    usernames.push(&account.username);
+                }
+                if !conflicts.is_empty() || !errors.is_empty() {
+                    return Ok(conflicts);
+                }
+
+                for encrypted in encrypted.iter() {
+                    let account = &encrypted.account;
+                    write_account(batch, encrypted);
+                    batch.insert(key(BALANCES, account.id), encode(&Balances::default()));
+                    batch.insert(key(ROUTES, &account.ilp_address), encode(&account.id));
+                }
+                Ok(conflicts)
+            })
+            .await?;
+
+        if !errors.is_empty() || !conflicts.is_empty() {
+            errors.extend(conflicts);
+            errors.sort_by_key(|(index, _)| *index);
+            warn!(
+                "Cannot insert the batch of accounts, {} of them are invalid",
+                errors.len()
+            );
+            return Err(NodeStoreError::InvalidAccounts(errors));
+        }
+
+        self.update_routes()?;
+        debug!("Inserted a batch of {} accounts", valid.len());
+        Ok(valid.into_iter().map(|(_, account)| account).collect())
+    }
+
+    async fn delete_account(&self, id: Uuid) -> Result<Account, NodeStoreError> {
+        let _timer = self.timers.start("delete_account");
+        let account = self.sled_delete_account(id).await?;
+        Ok(self.decrypt(account))
+    }
+
+    async fn update_account(
+        &self,
+        id: Uuid,
+        account: AccountDetails,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let _timer = self.timers.start("update_account");
+        let account = Account::try_from(id, account, self.get_ilp_address())
+            .map_err(NodeStoreError::InvalidAccount)?;
+        let encrypted = account
+            .clone()
+            .encrypt_tokens(&self.encryption_key.expose_secret().0);
+
+        self.sled_update_account(&encrypted).await?;
+        Ok(account)
+    }
+
+    async fn modify_account_settings(
+        &self,
+        id: Uuid,
+        settings: AccountSettings,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let _timer = self.timers.start("modify_account_settings");
+        if let Some(settle_to) = settings.settle_to {
+            if settle_to > std::i64::MAX as u64 {
+                // Balances are signed 64 bit values
+                return Err(NodeStoreError::InvalidAccount(
+                    CreateAccountError::ParamTooLarge("settle_to".to_owned()),
+                ));
+            }
+        }
+
+        let mut encrypted = self.sled_get_account(id)?;
+        let account = &mut encrypted.account;
+        if let Some(ref endpoint) = settings.ilp_over_btp_url {
+            account.ilp_over_btp_url = Some(Url::parse(endpoint).map_err(|err| {
+                NodeStoreError::InvalidAccount(CreateAccountError::InvalidBtpUrl(err))
+            })?);
+        }
+        if let Some(ref endpoint) = settings.ilp_over_http_url {
+            account.ilp_over_http_url = Some(Url::parse(endpoint).map_err(|err| {
+                NodeStoreError::InvalidAccount(CreateAccountError::InvalidHttpUrl(err))
+            })?);
+        }
+        if let Some(ref token) = settings.ilp_over_btp_outgoing_token {
+            account.ilp_over_btp_outgoing_token = Some(SecretBytesMut::from(BytesMut::from(
+                &self.encrypt(token)[..],
+            )));
+        }
+        if let Some(ref token) = settings.ilp_over_http_outgoing_token {
+            account.ilp_over_http_outgoing_token = Some(SecretBytesMut::from(BytesMut::from(
+                &self.encrypt(token)[..],
+            )));
+        }
+        if let Some(ref token) = settings.ilp_over_btp_incoming_token {
+            account.ilp_over_btp_incoming_token = Some(SecretBytesMut::new(
+                hash_token(token.expose_secret().as_bytes()).as_str(),
+            ));
+        }
+        if let Some(ref token) = settings.ilp_over_http_incoming_token {
+            account.ilp_over_http_incoming_token = Some(SecretBytesMut::new(
+                hash_token(token.expose_secret().as_bytes()).as_str(),
+            ));
+        }
+        if let Some(settle_threshold) = settings.settle_threshold {
+            account.settle_threshold = Some(settle_threshold);
+        }
+        if let Some(settle_to) = settings.settle_to {
+            account.settle_to = Some(settle_to as i64);
+        }
+        if let Some(settle_every) = settings.settle_every {
+            account.settle_every = Some(settle_every);
+        }
+
+        self.write(|_, batch| {
+            write_account(batch, &encrypted);
+            Ok(())
+        })
+        .await?;
+
+        // return the updated account
+        let account = self.sled_get_account(id)?;
+        Ok(self.decrypt(account))
+    }
+
+    async fn get_all_accounts(&self) -> Result<Vec<Self::Account>, NodeStoreError> {
+        let _timer = self.timers.start("get_all_accounts");
+        let accounts = load_accounts(&self.db, None, usize::MAX, |_| true)?;
+        Ok(accounts
+            .into_iter()
+            .map(|account| self.decrypt(account))
+            .collect())
+    }
+
+    async fn list_accounts(
+        &self,
+        after: Option<Uuid>,
+        limit: usize,
+        filter: AccountFilter,
+    ) -> Result<AccountPage<Self::Account>, NodeStoreError> {
+        let _timer = self.timers.start("list_accounts");
+        let routing_relation = filter
+            .routing_relation
+            .map(|relation| relation.as_ref().to_string());
+        // Load one more than a full page to know whether there is a next page
+        let mut accounts = load_accounts(&self.db, after, limit.saturating_add(1), |record| {
+            filter
+                .asset_code
+                .as_ref()
+                .map_or(true, |asset_code| record.asset_code == *asset_code)
+                && routing_relation
+                    .as_ref()
+                    .map_or(true, |relation| record.routing_relation == *relation)
+                && filter.ilp_address_prefix.as_ref().map_or(true, |prefix| {
+                    record.ilp_address.starts_with(prefix.as_str())
+                })
+        })?;
+
+        let next = if accounts.len() > limit {
+            accounts.truncate(limit);
+            accounts.last().map(|account| account.account.id)
+        } else {
+            None
+        };
+        Ok(AccountPage {
+            accounts: accounts
+                .into_iter()
+                .map(|account| self.decrypt(account))
+                .collect(),
+            next,
+        })
+    }
+
+    async fn set_static_routes<R>(&self, routes: R) -> Result<(), NodeStoreError>
+    where
+        R: IntoIterator<Item = (String, Uuid)> + Send + 'async_trait,
+    {
+        let _timer = self.timers.start("set_static_routes");
+        let routes: Vec<(String, Uuid)> = routes.into_iter().collect();
+        let all_exist = self
+            .write(|db, batch| {
+                for (_, account_id) in routes.iter() {
+                    if !db.contains_key(key(ACCOUNTS, account_id))? {
+                        return Ok(false);
+                    }
+                }
+
+                for (prefix, _) in scan::<Uuid>(db, &table_prefix(STATIC_ROUTES))? {
+                    batch.remove(key(STATIC_ROUTES, prefix));
+                }
+                for (prefix, account_id) in routes.iter() {
+                    batch.insert(key(STATIC_ROUTES, prefix), encode(account_id));
+                }
+                Ok(true)
+            })
+            .await?;
+
+        if !all_exist {
+            error!("Error setting static routes because not all of the given accounts exist");
+            return Err(NodeStoreError::MissingAccounts);
+        }
+
+        self.update_routes()?;
+        Ok(())
+    }
+
+    async fn set_static_route(
+        &self,
+        prefix: String,
+        account_id: Uuid,
+    ) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("set_static_route");
+        let exists = self
+            .write(|db, batch| {
+                if !db.contains_key(key(ACCOUNTS, account_id))? {
+                    return Ok(false);
+                }
+                batch.insert(key(STATIC_ROUTES, &prefix), encode(&account_id));
+                Ok(true)
+            })
+            .await?;
+
+        if !exists {
+            error!(
+                "Cannot set static route for prefix: {} because account {} does not exist",
+                prefix, account_id
+            );
+            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+        }
+
+        self.update_routes()?;
+        Ok(())
+    }
+
+    async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("set_default_route");
+        let exists = self
+            .write(|db, batch| {
+                if !db.contains_key(key(ACCOUNTS, account_id))? {
+                    return Ok(false);
+                }
+                batch.insert(key(SETTINGS, DEFAULT_ROUTE_KEY), encode(&account_id));
+                Ok(true)
+            })
+            .await?;
+
+        if !exists {
+            error!(
+                "Cannot set default route because account {} does not exist",
+                account_id
+            );
+            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+        }
+
+        debug!("Set default route to account id: {}", account_id);
+        self.update_routes()?;
+        Ok(())
+    }
+
+    async fn set_settlement_engines(
+        &self,
+        asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
+    ) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("set_settlement_engines");
+        let asset_to_url_map: Vec<(String, String)> = asset_to_url_map
+            .into_iter()
+            .map(|(asset_code, url)| (asset_code, url.to_string()))
+            .collect();
+        debug!("Setting settlement engines to {:?}", asset_to_url_map);
+        self.write(|_, batch| {
+            for (asset_code, url) in asset_to_url_map.iter() {
+                batch.insert(key(SETTLEMENT_ENGINES, asset_code), encode(url));
+            }
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn get_asset_settlement_engine(
+        &self,
+        asset_code: &str,
+    ) -> Result<Option<Url>, NodeStoreError> {
+        let _timer = self.timers.start("get_asset_settlement_engine");
+        let url = get::<String>(&self.db, &key(SETTLEMENT_ENGINES, asset_code))?;
+        if let Some(url) = url {
+            match Url::parse(url.as_str()) {
+                Ok(url) => Ok(Some(url)),
+                Err(err) => {
+                    error!(
+                        "Settlement engine URL loaded from sled was not a valid URL: {:?}",
+                        err
+                    );
+                    Err(NodeStoreError::InvalidEngineUrl(err.to_string()))
+                }
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn export_node(&self) -> Result<NodeExport, NodeStoreError> {
+        let _timer = self.timers.start("export_node");
+        let accounts = self.get_all_accounts().await?;
+        let mut exported = Vec::with_capacity(accounts.len());
+        for account in accounts.iter() {
+            let balances = get_balances(&self.db, account.id)?;
+            exported.push(ExportedAccount {
+                id: account.id,
+                details: account.to_details(),
+                balance: balances.balance,
+                prepaid_amount: balances.prepaid_amount,
+            });
+        }
+        let static_routes = scan::<Uuid>(&self.db, &table_prefix(STATIC_ROUTES))?
+            .into_iter()
+            .collect();
+        let settlement_engines = scan::<String>(&self.db, &table_prefix(SETTLEMENT_ENGINES))?
+            .into_iter()
+            .map(|(asset_code, url)| {
+                Url::parse(&url)
+                    .map(|url| (asset_code, url))
+                    .map_err(|err| NodeStoreError::InvalidEngineUrl(err.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(NodeExport {
+            ilp_address: get_setting(&self.db, PARENT_ILP_KEY)?
+                .and_then(|address| Address::from_str(&address).ok()),
+            accounts: exported,
+            static_routes,
+            default_route: get::<Uuid>(&self.db, &key(SETTINGS, DEFAULT_ROUTE_KEY))?,
+            settlement_engines,
+        })
+    }
+
+    async fn import_node(&self, export: NodeExport) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("import_node");
+        let mut accounts = Vec::with_capacity(export.accounts.len());
+        for exported in export.accounts.iter() {
+            let account = Account::try_from(
+                exported.id,
+                exported.details.clone(),
+                self.get_ilp_address(),
+            )
+            .map_err(NodeStoreError::InvalidAccount)?;
+            accounts.push(account.encrypt_tokens(&self.encryption_key.expose_secret().0));
+        }
+
+        // The routes may only point to the imported accounts
+        let imported: Vec<Uuid> = export.accounts.iter().map(|account| account.id).collect();
+        let mut result = Ok(());
+        if !export
+            .static_routes
+            .values()
+            .all(|account_id| imported.contains(account_id))
+        {
+            result = Err(NodeStoreError::MissingAccounts);
+        } else if let Some(account_id) = export.default_route {
+            if !imported.contains(&account_id) {
+                result = Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+            }
+        }
+
+        // Everything but the node's address is imported in a single batch,
+        // so that a failed import leaves the store empty
+        if result.is_ok() {
+            result = self
+                .write(|db, batch| {
+                    if db.scan_prefix(table_prefix(ACCOUNTS)).next().is_some() {
+                        return Ok(Err(NodeStoreError::NotEmpty));
+                    }
+
+                    for (encrypted, exported) in accounts.iter().zip(export.accounts.iter()) {
+                        let account = &encrypted.account;
+                        write_account(batch, encrypted);
+                        batch.insert(key(ROUTES, &account.ilp_address), encode(&account.id));
+                        batch.insert(
+                            key(BALANCES, account.id),
+                            encode(&Balances {
+                                balance: exported.balance,
+                                prepaid_amount: exported.prepaid_amount,
+                            }),
+                        );
+                    }
+                    for (prefix, account_id) in export.static_routes.iter() {
+                        batch.insert(key(STATIC_ROUTES, prefix), encode(account_id));
+                    }
+                    if let Some(account_id) = export.default_route {
+                        batch.insert(key(SETTINGS, DEFAULT_ROUTE_KEY), encode(&account_id));
+                    }
+                    for (asset_code, url) in export.settlement_engines.iter() {
+                        batch.insert(key(SETTLEMENT_ENGINES, asset_code), encode(url.as_str()));
+                    }
+                    Ok(Ok(()))
+                })
+                .await?;
+        }
+        if let Err(err) = result {
+            warn!("Error importing accounts: {}", err);
+            return Err(err);
+        }
+        self.update_routes()?;
+
+        if let Some(ilp_address) = export.ilp_address {
+            self.set_ilp_address(ilp_address)
+                .await
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+        }
+        debug!("Imported {} accounts", export.accounts.len());
+        Ok(())
+    }
+
+    async fn get_secret_generation(&self) -> Result<SecretGeneration, NodeStoreError> {
+        let _timer = self.timers.start("get_secret_generation");
+        let generation = get::<SecretGeneration>(&self.db, &key(SETTINGS, SECRET_GENERATION_KEY))?;
+        Ok(generation.unwrap_or_default())
+    }
+
+    async fn set_secret_generation(
+        &self,
+        generation: SecretGeneration,
+    ) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("set_secret_generation");
+        self.write(|_, batch| {
+            batch.insert(key(SETTINGS, SECRET_GENERATION_KEY), encode(&generation));
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn claim_leadership(
+        &self,
+        node_id: &str,
+        lease: Duration,
+    ) -> Result<bool, NodeStoreError> {
+        let _timer = self.timers.start("claim_leadership");
+        let now = now_millis();
+        let claimed = self
+            .write(|db, batch| {
+                let leader = get::<(String, u64)>(db, &key(SETTINGS, LEADER_KEY))?;
+                let held_by_another = leader.map_or(false, |(leader_id, expires_at)| {
+                    leader_id != node_id && expires_at > now
+                });
+                if held_by_another {
+                    return Ok(false);
+                }
+                let expires_at = now + lease.as_millis() as u64;
+                batch.insert(key(SETTINGS, LEADER_KEY), encode(&(node_id, expires_at)));
+                Ok(true)
+            })
+            .await?;
+        Ok(claimed)
+    }
+}
+
+#[async_trait]
+impl AddressStore for SledStore {
+    // Updates the ILP address of the store & iterates over all children and
+    // updates their ILP Address to match the new address.
+    async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+        let _timer = self.timers.start("set_ilp_address");
+        debug!("Setting ILP address to: {}", ilp_address);
+
+        // Set the ILP address we have in memory
+        (*self.ilp_address.write()) = ilp_address.clone();
+
+        let first_segment = ilp_address
+            .segments()
+            .rev()
+            .next()
+            .expect("address did not have a first segment, this should be impossible");
+
+        self.write(|db, batch| {
+            batch.insert(
+                key(SETTINGS, PARENT_ILP_KEY),
+                encode(&ilp_address.to_string()),
+            );
+            for mut encrypted in load_accounts(db, None, usize::MAX, |_| true)? {
+                let account = &mut encrypted.account;
+                // Update the address and routes of all children and non-routing accounts.
+                if account.routing_relation != RoutingRelation::Parent
+                    && account.routing_relation != RoutingRelation::Peer
+                {
+                    // if the username of the account ends with the
+                    // node's address, we're already configured so no
+                    // need to append anything.
+                    let new_ilp_address = if first_segment == account.username.to_string() {
+                        ilp_address.clone()
+                    } else {
+                        ilp_address
+                            .with_suffix(account.username.as_bytes())
+                            .unwrap()
+                    };
+                    batch.remove(key(ROUTES, &account.ilp_address));
+                    batch.insert(key(ROUTES, &new_ilp_address), encode(&account.id));
+                    account.ilp_address = new_ilp_address;
+                    write_account(batch, &encrypted);
+                }
+            }
+            Ok(())
+        })
+        .await?;
+
+        self.update_routes()?;
+        Ok(())
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        let _timer = self.timers.start("clear_ilp_address");
+        self.write(|_, batch| {
+            batch.remove(key(SETTINGS, PARENT_ILP_KEY));
+            Ok(())
+        })
+        .await?;
+
+        // overwrite the ilp address with the default value
+        *(self.ilp_address.write()) = DEFAULT_ILP_ADDRESS.clone();
+        Ok(())
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        // read consumes the Arc<RwLock<T>> so we cannot return a reference
+        self.ilp_address.read().clone()
+    }
+}
+
+type RoutingTable<A> = HashMap<String, A>;
+
+#[async_trait]
+impl CcpRoutingStore for SledStore {
+    type Account = Account;
+
+    async fn get_accounts_to_send_routes_to(
+        &self,
+        ignore_accounts: Vec<Uuid>,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        let _timer = self.timers.start("get_accounts_to_send_routes_to");
+        let relations = [
+            RoutingRelation::Child.as_ref(),
+            RoutingRelation::Peer.as_ref(),
+        ];
+        let accounts = load_accounts(&self.db, None, usize::MAX, |record| {
+            relations.contains(&record.routing_relation.as_str())
+                && !ignore_accounts.contains(&record.id)
+        })?;
+        Ok(accounts
+            .into_iter()
+            .map(|account| self.decrypt(account))
+            .collect())
+    }
+
+    async fn get_accounts_to_receive_routes_from(
+        &self,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        let _timer = self.timers.start("get_accounts_to_receive_routes_from");
+        let relations = [
+            RoutingRelation::Parent.as_ref(),
+            RoutingRelation::Peer.as_ref(),
+        ];
+        let accounts = load_accounts(&self.db, None, usize::MAX, |record| {
+            relations.contains(&record.routing_relation.as_str())
+        })?;
+        Ok(accounts
+            .into_iter()
+            .map(|account| self.decrypt(account))
+            .collect())
+    }
+
+    async fn get_local_and_configured_routes(
+        &self,
+    ) -> Result<(RoutingTable<Account>, RoutingTable<Account>), CcpRoutingStoreError> {
+        let _timer = self.timers.start("get_local_and_configured_routes");
+        let static_routes = scan::<Uuid>(&self.db, &table_prefix(STATIC_ROUTES))?;
+
+        let accounts = self.get_all_accounts().await?;
+
+        let local_table: HashMap<String, Account> = accounts
+            .iter()
+            .map(|account| (account.ilp_address.to_string(), account.clone()))
+            .collect();
+
+        let account_map: HashMap<Uuid, &Account> = accounts
+            .iter()
+            .map(|account| (account.id, account))
+            .collect();
+        let configured_table: HashMap<String, Account> = static_routes
+            .into_iter()
+            .filter_map(|(prefix, account_id)| {
+                if let Some(account) = account_map.get(&account_id) {
+                    Some((prefix, (*account).clone()))
+                } else {
+                    warn!(
+                        "No account for ID: {}, ignoring configured route for prefix: {}",
+                        account_id, prefix
+                    );
+                    None
+                }
+            })
+            .collect();
+
+        Ok((local_table, configured_table))
+    }
+
+    async fn set_routes(
+        &mut self,
+        routes: impl IntoIterator<Item = (String, Account)> + Send + 'async_trait,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let _timer = self.timers.start("set_routes");
+        let routes: Vec<(String, Uuid)> = routes
+            .into_iter()
+            .map(|(prefix, account)| (prefix, account.id))
+            .collect();
+        let num_routes = routes.len();
+
+        self.write(|db, batch| {
+            for (prefix, _) in scan::<Uuid>(db, &table_prefix(ROUTES))? {
+                batch.remove(key(ROUTES, prefix));
+            }
+            for (prefix, account_id) in routes.iter() {
+                batch.insert(key(ROUTES, prefix), encode(account_id));
+            }
+            Ok(())
+        })
+        .await?;
+        trace!("Saved {} routes to sled", num_routes);
+
+        self.update_routes()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for SledStore {
+    type Account = Account;
+
+    /// Apply rate limits for number of packets per minute and amount of money per minute
+    ///
+    /// The limits are counted in memory over fixed one minute windows, so they are
+    /// not shared between multiple node processes
+    async fn apply_rate_limits(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        let _timer = self.timers.start("apply_rate_limits");
+        let now = Instant::now();
+        let mut limits = self.rate_limits.lock();
+
+        let packets_key = format!("limit:packets:{}", account.id);
+        let throughput_key = format!("limit:throughput:{}", account.id);
+
+        // Check both limits before charging either of them
+        let current = |limits: &HashMap<String, RateLimitWindow>, key: &str| match limits.get(key) {
+            Some(window) if now.duration_since(window.started_at) < RATE_LIMIT_WINDOW => {
+                window.used
+            }
+            _ => 0,
+        };
+        if let Some(limit) = account.packets_per_minute_limit {
+            if current(&limits, &packets_key) + 1 > u64::from(limit) {
+                return Err(RateLimitError::PacketLimitExceeded);
+            }
+        }
+        if let Some(limit) = account.amount_per_minute_limit {
+            if current(&limits, &throughput_key).saturating_add(prepare_amount) > limit {
+                return Err(RateLimitError::ThroughputLimitExceeded);
+            }
+        }
+
+        let mut charge = |key: String, amount: u64| {
+            let window = limits.entry(key).or_insert(RateLimitWindow {
+                started_at: now,
+                used: 0,
+            });
+            if now.duration_since(window.started_at) >= RATE_LIMIT_WINDOW {
+                *window = RateLimitWindow {
+                    started_at: now,
+                    used: 0,
+                };
+            }
+            window.used = window.used.saturating_add(amount);
+        };
+        if account.packets_per_minute_limit.is_some() {
+            charge(packets_key, 1);
+        }
+        if account.amount_per_minute_limit.is_some() {
+            charge(throughput_key, prepare_amount);
+        }
+        Ok(())
+    }
+
+    async fn refund_throughput_limit(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        let _timer = self.timers.start("refund_throughput_limit");
+        if account.amount_per_minute_limit.is_some() {
+            let throughput_key = format!("limit:throughput:{}", account.id);
+            if let Some(window) = self.rate_limits.lock().get_mut(&throughput_key) {
+                window.used = window.used.saturating_sub(prepare_amount);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VolumeLimitStore for SledStore {
+    type Account = Account;
+
+    async fn charge_daily_volume(
+        &self,
+        account: Account,
+        day: u64,
+        amount: u64,
+    ) -> Result<(), VolumeLimitError> {
+        let _timer = self.timers.start("charge_daily_volume");
+        let limit = match account.amount_per_day_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        // The totals of the previous days are no longer needed. They are found before the
+        // transaction, which can't scan, and removing them twice does no harm.
+        let end = numbered_key(DAILY_VOLUMES, account.id, day.saturating_sub(1));
+        let previous_days = self
+            .db
+            .range(account_prefix(DAILY_VOLUMES, account.id)..end)
+            .keys()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| {
+                error!("Error charging daily volume: {:?}", err);
+                VolumeLimitError::StoreError
+            })?;
+        let charged = self
+            .transact(|tx| {
+                for previous_day in &previous_days {
+                    tx.remove(previous_day.clone())?;
+                }
+                let volume_key = numbered_key(DAILY_VOLUMES, account.id, day);
+                let volume = tx_get::<u64>(tx, &volume_key)?.unwrap_or(0);
+                let total = volume.saturating_add(amount);
+                if total > limit {
+                    return Ok(false);
+                }
+                tx.insert(volume_key, encode(&total))?;
+                Ok(true)
+            })
+            .await
+            .map_err(|err| {
+                error!("Error charging daily volume: {:?}", err);
+                VolumeLimitError::StoreError
+            })?;
+        if charged {
+            Ok(())
+        } else {
+            Err(VolumeLimitError::DailyLimitExceeded)
+        }
+    }
+
+    async fn refund_daily_volume(
+        &self,
+        account: Account,
+        day: u64,
+        amount: u64,
+    ) -> Result<(), VolumeLimitError> {
+        let _timer = self.timers.start("refund_daily_volume");
+        self.transact(|tx| {
+            let volume_key = numbered_key(DAILY_VOLUMES, account.id, day);
+            if let Some(volume) = tx_get::<u64>(tx, &volume_key)? {
+                tx.insert(volume_key, encode(&volume.saturating_sub(amount)))?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|err| {
+            error!("Error refunding daily volume: {:?}", err);
+            VolumeLimitError::StoreError
+        })
+    }
+}
+
+/// The database is local, so the provided methods making one call per limit are kept
+impl AdmissionStore for SledStore {}
+
+#[async_trait]
+impl IdempotentStore for SledStore {
+    async fn load_idempotent_data(
+        &self,
+        idempotency_key: String,
+    ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        let _timer = self.timers.start("load_idempotent_data");
+        let record = get::<IdempotencyRecord>(&self.db, &key(IDEMPOTENCY_KEYS, &idempotency_key))?
+            .filter(|record| record.expires_at > now_secs());
+
+        if let Some(record) = record {
+            trace!("Loaded idempotency key {:?}", idempotency_key);
+            Ok(Some(IdempotentData::new(
+                StatusCode::from_u16(record.status_code).unwrap(),
+                Bytes::from(record.data),
+                record.input_hash,
+            )))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn save_idempotent_data(
+        &self,
+        idempotency_key: String,
+        input_hash: [u8; 32],
+        status_code: StatusCode,
+        data: Bytes,
+    ) -> Result<(), IdempotentStoreError> {
+        let _timer = self.timers.start("save_idempotent_data");
+        let record = IdempotencyRecord {
+            status_code: status_code.as_u16(),
+            data: data.to_vec(),
+            input_hash,
+            expires_at: now_secs() + IDEMPOTENCY_KEY_TTL,
+        };
+        self.write(|db, batch| {
+            // Expired keys are removed as new ones are saved
+            let now = now_secs();
+            for (expired, record) in scan::<IdempotencyRecord>(db, &table_prefix(IDEMPOTENCY_KEYS))?
+            {
+                if record.expires_at <= now {
+                    batch.remove(key(IDEMPOTENCY_KEYS, expired));
+                }
+            }
+            batch.insert(key(IDEMPOTENCY_KEYS, &idempotency_key), encode(&record));
+            Ok(())
+        })
+        .await?;
+
+        trace!(
+            "Cached {:?}: {:?}, {:?}",
+            idempotency_key,
+            status_code,
+            data,
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SettlementStore for SledStore {
+    type Account = Account;
+
+    async fn update_balance_for_incoming_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: Option<String>,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("update_balance_for_incoming_settlement");
+        let balance = self
+            .write(|db, batch| {
+                let mut balances = get_balances(db, account_id)?;
+
+                // If idempotency key has been used, then do not perform any operations
+                if let Some(ref idempotency_key) = idempotency_key {
+                    let settlement_key = key(INCOMING_SETTLEMENTS, idempotency_key);
+                    let used = get::<u64>(db, &settlement_key)?
+                        .map_or(false, |expires_at| expires_at > now_secs());
+                    if used {
+                        return Ok(balances.balance + balances.prepaid_amount);
+                    }
+                    batch.insert(settlement_key, encode(&(now_secs() + IDEMPOTENCY_KEY_TTL)));
+                }
+
+                // Credit the incoming settlement to the balance and/or prepaid amount,
+                // depending on whether that account currently owes money or not
+                let amount = amount as i64;
+                let Balances {
+                    balance,
+                    prepaid_amount,
+                } = balances;
+                balances = if balance >= 0 {
+                    Balances {
+                        balance,
+                        prepaid_amount: prepaid_amount + amount,
+                    }
+                } else if balance.abs() >= amount {
+                    Balances {
+                        balance: balance + amount,
+                        prepaid_amount,
+                    }
+                } else {
+                    Balances {
+                        balance: 0,
+                        prepaid_amount: prepaid_amount + amount + balance,
+                    }
+                };
+                batch.insert(key(BALANCES, account_id), encode(&balances));
+                let mut log = BalanceLog::new(db, account_id, None)?;
+                log.push(BalanceChangeReason::IncomingSettlement, amount, &balances);
+                log.write(batch);
+                Ok(balances.balance + balances.prepaid_amount)
+            })
+            .await?;
+        trace!(
+            "Processed incoming settlement from account: {} for amount: {}. Balance is now: {}",
+            account_id,
+            amount,
+            balance
+        );
+        Ok(())
+    }
+
+    async fn refund_settlement(
+        &self,
+        account_id: Uuid,
+        settle_amount: u64,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("refund_settlement");
+        trace!(
+            "Refunding settlement for account: {} of amount: {}",
+            account_id,
+            settle_amount
+        );
+        let balance = self
+            .update_balance(account_id, None, |_, balances, log| {
+                balances.balance += settle_amount as i64;
+                log.push(
+                    BalanceChangeReason::SettlementRefund,
+                    settle_amount as i64,
+                    balances,
+                );
+                balances.balance
+            })
+            .await?;
+
+        trace!(
+            "Refunded settlement for account: {} of amount: {}. Balance is now: {}",
+            account_id,
+            settle_amount,
+            balance
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutgoingSettlementStore for SledStore {
+    async fn queue_outgoing_settlement(
+        &self,
+        settlement: OutgoingSettlement,
+    ) -> Result<bool, SettlementStoreError> {
+        let _timer = self.timers.start("queue_outgoing_settlement");
+        let in_flight = self
+            .write(|db, batch| {
+                // The settlements of an account are sent one at a time, so the amount
+                // waits until the engine answered the settlement in flight
+                if has_settlement_in_flight(db, settlement.account_id)? {
+                    queue_settlement_amount(db, batch, settlement.account_id, settlement.amount)?;
+                    return Ok(false);
+                }
+                batch.insert(
+                    key(OUTGOING_SETTLEMENTS, &settlement.idempotency_key),
+                    encode(&settlement),
+                );
+                Ok(true)
+            })
+            .await?;
+        Ok(in_flight)
+    }
+
+    async fn dequeue_outgoing_settlement(
+        &self,
+        account_id: Uuid,
+        idempotency_key: String,
+        next_attempt_at: u64,
+    ) -> Result<Option<OutgoingSettlement>, SettlementStoreError> {
+        let _timer = self.timers.start("dequeue_outgoing_settlement");
+        let settlement = self
+            .write(|db, batch| {
+                if has_settlement_in_flight(db, account_id)? {
+                    return Ok(None);
+                }
+                let queued_key = key(QUEUED_SETTLEMENTS, account_id);
+                let amount = match get::<u64>(db, &queued_key)? {
+                    Some(amount) => amount,
+                    None => return Ok(None),
+                };
+                batch.remove(queued_key);
+                let settlement = OutgoingSettlement {
+                    idempotency_key,
+                    account_id,
+                    amount,
+                    status: OutgoingSettlementStatus::Pending,
+                    attempts: 0,
+                    next_attempt_at,
+                };
+                batch.insert(
+                    key(OUTGOING_SETTLEMENTS, &settlement.idempotency_key),
+                    encode(&settlement),
+                );
+                Ok(Some(settlement))
+            })
+            .await?;
+        Ok(settlement)
+    }
+
+    async fn load_queued_settlement_accounts(&self) -> Result<Vec<Uuid>, SettlementStoreError> {
+        let _timer = self.timers.start("load_queued_settlement_accounts");
+        scan::<u64>(&self.db, &table_prefix(QUEUED_SETTLEMENTS))?
+            .into_iter()
+            .map(|(account_id, _)| {
+                Uuid::from_str(&account_id).map_err(|_| invalid_record("Invalid account id").into())
+            })
+            .collect()
+    }
+
+    async fn save_outgoing_settlement(
+        &self,
+        settlement: OutgoingSettlement,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("save_outgoing_settlement");
+        self.write(|_, batch| {
+            batch.insert(
+                key(OUTGOING_SETTLEMENTS, &settlement.idempotency_key),
+                encode(&settlement),
+            );
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn load_outgoing_settlements(
+        &self,
+    ) -> Result<Vec<OutgoingSettlement>, SettlementStoreError> {
+        let _timer = self.timers.start("load_outgoing_settlements");
+        let settlements =
+            scan::<OutgoingSettlement>(&self.db, &table_prefix(OUTGOING_SETTLEMENTS))?;
+        Ok(settlements
+            .into_iter()
+            .map(|(_, settlement)| settlement)
+            .collect())
+    }
+
+    async fn remove_outgoing_settlement(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("remove_outgoing_settlement");
+        self.write(|_, batch| {
+            batch.remove(key(OUTGOING_SETTLEMENTS, idempotency_key));
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn refund_outgoing_settlement(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("refund_outgoing_settlement");
+        let refunded: Option<(Uuid, u64)> = self
+            .write(|db, batch| {
+                let settlement_key = key(OUTGOING_SETTLEMENTS, idempotency_key);
+                // Already refunded or accepted
+                let settlement = match get::<OutgoingSettlement>(db, &settlement_key)? {
+                    Some(settlement) => settlement,
+                    None => return Ok(None),
+                };
+                batch.remove(settlement_key);
+                // There is nothing to refund if the account was deleted in the meantime
+                if !db.contains_key(key(ACCOUNTS, settlement.account_id))? {
+                    return Ok(None);
+                }
+
+                let mut balances = get_balances(db, settlement.account_id)?;
+                balances.balance += settlement.amount as i64;
+                batch.insert(key(BALANCES, settlement.account_id), encode(&balances));
+                let mut log = BalanceLog::new(db, settlement.account_id, None)?;
+                log.push(
+                    BalanceChangeReason::SettlementRefund,
+                    settlement.amount as i64,
+                    &balances,
+                );
+                log.write(batch);
+                Ok(Some((settlement.account_id, settlement.amount)))
+            })
+            .await?;
+
+        if let Some((account_id, amount)) = refunded {
+            trace!(
+                "Refunded settlement {} for account: {} of amount: {}",
+                idempotency_key,
+                account_id,
+                amount
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LeftoversStore for SledStore {
+    type AccountId = Uuid;
+    type AssetType = BigUint;
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        let _timer = self.timers.start("get_uncredited_settlement_amount");
+        // get the amounts and instantly delete them
+        let amounts = self
+            .write(|db, batch| {
+                let prefix = account_prefix(UNCREDITED_AMOUNTS, account_id);
+                let mut amounts = Vec::new();
+                for entry in db.scan_prefix(prefix) {
+                    let (key, value) = entry?;
+                    amounts.push(decode::<UncreditedAmount>(&value)?);
+                    batch.remove(key);
+                }
+                Ok(amounts)
+            })
+            .await?;
+
+        // We must scale them to the largest scale, and then add them together
+        let max_scale = amounts.iter().map(|amount| amount.scale).max().unwrap_or(0);
+        let mut sum = BigUint::from(0u32);
+        for UncreditedAmount { amount, scale } in amounts {
+            let num = BigUint::from_str(&amount)
+                .map_err(|err| LeftoversStoreError::Other(Box::new(err)))?;
+            sum += num
+                .normalize_scale(ConvertDetails {
+                    from: scale,
+                    to: max_scale,
+                })
+                .unwrap();
+        }
+        Ok((sum, max_scale))
+    }
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+        uncredited_settlement_amount: (Self::AssetType, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        let _timer = self.timers.start("save_uncredited_settlement_amount");
+        trace!(
+            "Saving uncredited_settlement_amount {:?} {:?}",
+            account_id,
+            uncredited_settlement_amount
+        );
+        let amount = UncreditedAmount {
+            amount: uncredited_settlement_amount.0.to_string(),
+            scale: uncredited_settlement_amount.1,
+        };
+        self.write(|db, batch| {
+            let id = db.generate_id()?;
+            batch.insert(
+                numbered_key(UNCREDITED_AMOUNTS, account_id, id),
+                encode(&amount),
+            );
+            Ok(())
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+        local_scale: u8,
+    ) -> Result<Self::AssetType, LeftoversStoreError> {
+        let _timer = self.timers.start("load_uncredited_settlement_amount");
+        trace!("Loading uncredited_settlement_amount {:?}", account_id);
+        let amount = self.get_uncredited_settlement_amount(account_id).await?;
+        // scale the amount from the max scale to the local scale, and then
+        // save any potential leftovers to the store
+        let (scaled_amount, precision_loss) =
+            scale_with_precision_loss(amount.0, local_scale, amount.1);
+
+        if precision_loss > BigUint::from(0u32) {
+            self.save_uncredited_settlement_amount(
+                account_id,
+                (precision_loss, std::cmp::max(local_scale, amount.1)),
+            )
+            .await?;
+        }
+
+        Ok(scaled_amount)
+    }
+
+    async fn clear_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(), LeftoversStoreError> {
+        let _timer = self.timers.start("clear_uncredited_settlement_amount");
+        trace!("Clearing uncredited_settlement_amount {:?}", account_id);
+        self.write(|db, batch| {
+            for entry in db.scan_prefix(account_prefix(UNCREDITED_AMOUNTS, account_id)) {
+                batch.remove(entry?.0);
+            }
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+/// Entries to append to an account's balance change log
+struct BalanceLog {
+    account_id: Uuid,
+    packet_id: Option<String>,
+    next_sequence: u64,
+    changes: Vec<BalanceChange>,
+}
+
+impl BalanceLog {
+    /// Continues the account's log after its last entry
+    fn new(db: &Db, account_id: Uuid, packet_id: Option<PacketId>) -> StoreResult<Self> {
+        let next_sequence = get(db, &key(BALANCE_LOG_SEQUENCES, account_id))?;
+        Ok(Self::starting_at(account_id, packet_id, next_sequence))
+    }
+
+    /// Same as `new`, in a transaction
+    fn new_in(
+        tx: &TransactionalTree,
+        account_id: Uuid,
+        packet_id: Option<PacketId>,
+    ) -> ConflictableTransactionResult<Self, SledStoreError> {
+        let next_sequence = tx_get(tx, &key(BALANCE_LOG_SEQUENCES, account_id))?;
+        Ok(Self::starting_at(account_id, packet_id, next_sequence))
+    }
+
+    fn starting_at(
+        account_id: Uuid,
+        packet_id: Option<PacketId>,
+        next_sequence: Option<u64>,
+    ) -> Self {
+        BalanceLog {
+            account_id,
+            packet_id: packet_id.map(hex::encode),
+            next_sequence: next_sequence.unwrap_or(0),
+            changes: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, reason: BalanceChangeReason, delta: i64, balances: &Balances) {
+        self.changes.push(BalanceChange {
+            sequence: self.next_sequence,
+            timestamp: now_millis(),
+            account_id: self.account_id,
+            packet_id: self.packet_id.clone(),
+            delta,
+            balance: balances.balance + balances.prepaid_amount,
+            reason,
+        });
+        self.next_sequence += 1;
+    }
+
+    fn write(self, batch: &mut Batch) {
+        batch.insert(
+            key(BALANCE_LOG_SEQUENCES, self.account_id),
+            encode(&self.next_sequence),
+        );
+        for change in self.changes {
+            batch.insert(
+                numbered_key(BALANCE_LOG, self.account_id, change.sequence),
+                encode(&change),
+            );
+        }
+    }
+
+    /// Same as `write`, in a transaction
+    fn write_in(self, tx: &TransactionalTree) -> Result<(), UnabortableTransactionError> {
+        tx.insert(
+            key(BALANCE_LOG_SEQUENCES, self.account_id),
+            encode(&self.next_sequence),
+        )?;
+        for change in self.changes {
+            tx.insert(
+                numbered_key(BALANCE_LOG, self.account_id, change.sequence),
+                encode(&change),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn invalid_record(message: &str) -> SledStoreError {
+    SledStoreError::InvalidRecord(message.to_string())
+}
+
+/// Key of a record which is identified by a single value, e.g. `accounts/<id>`
+fn key(table: &str, id: impl Display) -> Vec<u8> {
+    format!("{}/{}", table, id).into_bytes()
+}
+
+/// Prefix of all the records of a kind
+fn table_prefix(table: &str) -> Vec<u8> {
+    format!("{}/", table).into_bytes()
+}
+
+/// Prefix of the records of a kind which belong to an account
+fn account_prefix(table: &str, account_id: Uuid) -> Vec<u8> {
+    format!("{}/{}/", table, account_id).into_bytes()
+}
+
+/// Key of an account's record which is identified by a string, e.g. `incoming_payments/<id>/<destination>`
+fn account_key(table: &str, account_id: Uuid, id: impl Display) -> Vec<u8> {
+    format!("{}/{}/{}", table, account_id, id).into_bytes()
+}
+
+/// Key of an account's record which is identified by a number, e.g. `balance_log/<id>/<sequence>`
+fn numbered_key(table: &str, account_id: Uuid, n: u64) -> Vec<u8> {
+    let mut key = account_prefix(table, account_id);
+    key.extend_from_slice(&n.to_be_bytes());
+    key
+}
+
+fn encode<T: Serialize + ?Sized>(value: &T) -> Vec<u8> {
+    // The records are structs and plain values, which can always be serialized
+    serde_json::to_vec(value).expect("records are always serializable")
+}
+
+fn decode<T: DeserializeOwned>(value: &[u8]) -> StoreResult<T> {
+    serde_json::from_slice(value).map_err(|err| SledStoreError::InvalidRecord(err.to_string()))
+}
+
+fn get<T: DeserializeOwned>(db: &Db, key: &[u8]) -> StoreResult<Option<T>> {
+    db.get(key)?.map(|value| decode(&value)).transpose()
+}
+
+/// Loads the records whose keys start with the prefix, together with the rest of
+/// their keys (e.g. the ILP address prefixes of `routes/<prefix>`)
+fn scan<T: DeserializeOwned>(db: &Db, prefix: &[u8]) -> StoreResult<Vec<(String, T)>> {
+    db.scan_prefix(prefix)
+        .map(|entry| {
+            let (key, value) = entry?;
+            let id = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+            Ok((id, decode(&value)?))
+        })
+        .collect()
+}
+
+fn get_setting(db: &Db, key_name: &str) -> StoreResult<Option<String>> {
+    get(db, &key(SETTINGS, key_name))
+}
+
+/// Every account has its balances written when it is inserted or imported
+fn get_balances(db: &Db, account_id: Uuid) -> StoreResult<Balances> {
+    get(db, &key(BALANCES, account_id))?.ok_or(SledStoreError::AccountNotFound(account_id))
+}
+
+/// Same as `get`, in a transaction
+fn tx_get<T: DeserializeOwned>(
+    tx: &TransactionalTree,
+    key: &[u8],
+) -> ConflictableTransactionResult<Option<T>, SledStoreError> {
+    match tx.get(key)? {
+        Some(value) => decode(&value)
+            .map(Some)
+            .map_err(ConflictableTransactionError::Abort),
+        None => Ok(None),
+    }
+}
+
+/// Reads the details and balances of the account in a transaction which updates them,
+/// and continues its balance change log
+fn load_balance_in(
+    tx: &TransactionalTree,
+    account_id: Uuid,
+    packet_id: Option<PacketId>,
+) -> ConflictableTransactionResult<(AccountRecord, Balances, BalanceLog), SledStoreError> {
+    let not_found =
+        || ConflictableTransactionError::Abort(SledStoreError::AccountNotFound(account_id));
+    let account = tx_get::<AccountRecord>(tx, &key(ACCOUNTS, account_id))?.ok_or_else(not_found)?;
+    let balances = tx_get::<Balances>(tx, &key(BALANCES, account_id))?.ok_or_else(not_found)?;
+    let log = BalanceLog::new_in(tx, account_id, packet_id)?;
+    Ok((account, balances, log))
+}
+
+/// Converts a stored account. If the account does not have a settlement_engine_url set
+/// but there is one configured for that account's currency, the globally configured url
+/// is used
+fn account_from_record(db: &Db, record: AccountRecord) -> StoreResult<AccountWithEncryptedTokens> {
+    let mut encrypted = record.into_account()?;
+    if encrypted.account.settlement_engine_url.is_none() {
+        encrypted.account.settlement_engine_url =
+            get::<String>(db, &key(SETTLEMENT_ENGINES, &encrypted.account.asset_code))?
+                .map(|url| Url::parse(&url))
+                .transpose()
+                .map_err(|_| invalid_record("Invalid settlement engine URL"))?;
+    }
+    Ok(encrypted)
+}
+
+fn load_account(db: &Db, id: Uuid) -> StoreResult<Option<AccountWithEncryptedTokens>> {
+    get::<AccountRecord>(db, &key(ACCOUNTS, id))?
+        .map(|record| account_from_record(db, record))
+        .transpose()
+}
+
+/// Loads up to `limit` accounts (tokens remain encrypted) which match the filter, in the
+/// order of their ids, starting after the account with the id `after`
+fn load_accounts<F>(
+    db: &Db,
+    after: Option<Uuid>,
+    limit: usize,
+    filter: F,
+) -> StoreResult<Vec<AccountWithEncryptedTokens>>
+where
+    F: Fn(&AccountRecord) -> bool,
+{
+    let prefix = table_prefix(ACCOUNTS);
+    let start = match after {
+        Some(after) => Bound::Excluded(key(ACCOUNTS, after)),
+        None => Bound::Included(prefix.clone()),
+    };
+    let mut accounts = Vec::new();
+    for entry in db.range((start, Bound::Unbounded)) {
+        if accounts.len() == limit {
+            break;
+        }
+        let (key, value) = entry?;
+        if !key.starts_with(&prefix) {
+            break;
+        }
+        let record: AccountRecord = decode(&value)?;
+        if filter(&record) {
+            accounts.push(account_from_record(db, record)?);
+        }
+    }
+    Ok(accounts)
+}
+
+/// Writes the details of the provided account and the username index
+fn write_account(batch: &mut Batch, encrypted: &AccountWithEncryptedTokens) {
+    let record = AccountRecord::new(encrypted);
+    batch.insert(key(ACCOUNTS, record.id), encode(&record));
+    batch.insert(key(USERNAMES, &record.username), encode(&record.id));
+}
+
+fn load_routes(db: &Db) -> StoreResult<HashMap<String, Uuid>> {
+    let routes = scan::<Uuid>(db, &table_prefix(ROUTES))?;
+    let static_routes = scan::<Uuid>(db, &table_prefix(STATIC_ROUTES))?;
+    let default_route = get::<Uuid>(db, &key(SETTINGS, DEFAULT_ROUTE_KEY))?;
+    trace!(
+        "Loaded routes from sled. Static routes: {:?}, default route: {:?}, other routes: {:?}",
+        static_routes,
+        default_route,
+        routes
+    );
+
+    Ok(routes
+        .into_iter()
+        // Include the default route if there is one
+        .chain(default_route.map(|id| (String::new(), id)))
+        // Having the static_routes inserted after ensures that they will overwrite
+        // any routes with the same prefix from the first set
+        .chain(static_routes.into_iter())
+        .collect())
+}
+
+fn has_settlement_in_flight(db: &Db, account_id: Uuid) -> StoreResult<bool> {
+    let settlements = scan::<OutgoingSettlement>(db, &table_prefix(OUTGOING_SETTLEMENTS))?;
+    Ok(settlements
+        .iter()
+        .any(|(_, settlement)| settlement.account_id == account_id))
+}
+
+/// Increases the balance by the fulfilled amount, and removes the amount to settle from it
+/// once it crossed the settle threshold. Returns (balance, amount_to_settle).
+fn process_fulfill(
+    account: &AccountRecord,
+    balances: &mut Balances,
+    log: &mut BalanceLog,
+    outgoing_amount: u64,
+) -> (i64, u64) {
+    balances.balance += outgoing_amount as i64;
+    if outgoing_amount > 0 {
+        log.push(
+            BalanceChangeReason::Fulfill,
+            outgoing_amount as i64,
+            balances,
+        );
+    }
+
+    // The logic for triggering settlement is as follows:
+    //  1. settle_threshold must be set (if it's not, then settlement was perhaps disabled on the account).
+    //  2. balance must be greater than settle_threshold (this is the core of the 'should I settle logic')
+    //  3. settle_threshold must be greater than settle_to (e.g., settleTo=5, settleThreshold=6)
+    let mut settle_amount = 0;
+    if let (Some(settle_threshold), Some(settle_to)) = (account.settle_threshold, account.settle_to)
+    {
+        if balances.balance >= settle_threshold && settle_threshold > settle_to {
+            settle_amount = (balances.balance - settle_to) as u64;
+            // Update the balance _before_ sending the settlement so that we don't accidentally send
+            // multiple settlements for the same balance. If the settlement fails we'll roll back
+            // the balance change by re-adding the amount back to the balance
+            balances.balance = settle_to;
+            log.push(
+                BalanceChangeReason::Settlement,
+                -(settle_amount as i64),
+                balances,
+            );
+        }
+    }
+    (balances.balance + balances.prepaid_amount, settle_amount)
+}
+
+/// Adds the amount to the account's queued outgoing settlement, which is sent once
+/// the settlement in flight (if any) was answered
+fn queue_settlement_amount(
+    db: &Db,
+    batch: &mut Batch,
+    account_id: Uuid,
+    amount: u64,
+) -> StoreResult<()> {
+    let queued_key = key(QUEUED_SETTLEMENTS, account_id);
+    let queued = get::<u64>(db, &queued_key)?.unwrap_or(0);
+    batch.insert(queued_key, encode(&(queued + amount)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn connect_fails_if_path_is_invalid() {
+        let result = SledStoreBuilder::new("/dev/null/ilp.sled", [0; 32])
+            .connect()
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn numbered_keys_sort_numerically() {
+        let id = Uuid::new_v4();
+        assert!(numbered_key(BALANCE_LOG, id, 255) < numbered_key(BALANCE_LOG, id, 256));
+        assert!(numbered_key(BALANCE_LOG, id, 1).starts_with(&account_prefix(BALANCE_LOG, id)));
+    }
+}
@@ -0,0 +1,3538 @@
+// The schema of our data in SQLite is defined in schema.sql:
+//   accounts               table       account details, encrypted tokens and balances
+//   routes                 table       dynamic routing table
+//   static_routes          table       static routing table
+//   node_settings          table       parent-assigned ILP address, default route and
+//                                      generation of the STREAM server secret
+//   settlement_engines     table       globally configured settlement engines
+//   idempotency_keys       table       cached settlement API responses
+//   incoming_settlements   table       idempotency keys of credited incoming settlements
+//   uncredited_amounts     table       leftovers from settlements with precision loss
+//   incoming_payments      table       money received on each STREAM connection
+// Exchange rates, rate limit counters and payment notification subscriptions are
+// kept in memory, since an embedded store is only ever used by a single node process.
+// The database may also live purely in memory, optionally with periodic snapshots
+// written to disk (using SQLite's online backup API) which are restored on startup.
+// For interactive exploration of the store (or a snapshot), use the sqlite3 command line tool:
+//    .tables               list all tables
+//    .schema accounts      show the definition of a table
+//    select * from routes; dump the contents of a table
+
+use super::account::{Account, AccountWithEncryptedTokens};
+use super::crypto::{encrypt_token, generate_keys, hash_token, DecryptionKey, EncryptionKey};
+use super::instrumentation::OperationTimers;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::channel::mpsc::UnboundedSender;
+use hex::FromHex;
+use http::StatusCode;
+use interledger_api::{
+    AccountDetails, AccountFilter, AccountPage, AccountSettings, ExportedAccount, NodeExport,
+    NodeStore,
+};
+use interledger_auth::{AuthError, AuthProtocol, VerifiedTokens};
+use interledger_btp::BtpStore;
+use interledger_ccp::{CcpRoutingAccount, CcpRoutingStore, RoutingRelation};
+use interledger_errors::*;
+use interledger_http::HttpStore;
+use interledger_packet::Address;
+use interledger_rates::ExchangeRateStore;
+use interledger_router::RouterStore;
+use interledger_service::{runtime, Account as AccountTrait, AccountStore, AddressStore, Username};
+use interledger_service_util::{
+    AdmissionStore, BalanceChange, BalanceChangeReason, BalanceStore, BalanceTotals,
+    JournaledPacket, PacketDirection, PacketHistoryFilter, PacketHistoryStore, PacketId,
+    PacketJournalStore, PacketOutcome, PacketRecord, RateLimitError, RateLimitStore,
+    VolumeLimitError, VolumeLimitStore,
+};
+use interledger_settlement::core::{
+    idempotency::{IdempotentData, IdempotentStore},
+    scale_with_precision_loss,
+    types::{
+        Convert, ConvertDetails, LeftoversStore, OutgoingSettlement, OutgoingSettlementStatus,
+        OutgoingSettlementStore, SettlementStore,
+    },
+};
+use interledger_stream::{
+    IncomingPayment, IncomingPaymentsStore, PaymentNotification, SecretGeneration,
+    StreamNotificationsStore,
+};
+use num_bigint::BigUint;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use ring::aead;
+use rusqlite::{
+    backup::{Backup, Progress},
+    params,
+    types::{Type, Value},
+    Connection, DatabaseName, OptionalExtension, Row, Transaction,
+};
+use secrecy::{ExposeSecret, Secret, SecretBytesMut, SecretString};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tokio::sync::broadcast;
+use tracing::{debug, error, trace, warn};
+use url::Url;
+use uuid::Uuid;
+use zeroize::Zeroize;
+
+/// Path which makes SQLite open a database that only lives in memory
+static IN_MEMORY_PATH: &str = ":memory:";
+/// Default interval at which snapshots are written, in milliseconds
+const DEFAULT_SNAPSHOT_INTERVAL: u64 = 60_000;
+/// Number of pages written to the snapshot file in each step
+const SNAPSHOT_STEP_PAGES: i32 = 100;
+
+/// Statements creating all tables used by the store
+static SCHEMA: &str = include_str!("schema.sql");
+
+static PARENT_ILP_KEY: &str = "parent_node_account_address";
+static DEFAULT_ROUTE_KEY: &str = "default_route";
+static SECRET_GENERATION_KEY: &str = "secret_generation";
+/// Id of the node which holds the leadership, and when its lease expires (in milliseconds
+/// since the epoch), separated by a space
+static LEADER_KEY: &str = "leader";
+
+/// How long idempotency keys are kept around (24 hours, same as the Redis store)
+const IDEMPOTENCY_KEY_TTL: i64 = 86400;
+/// The window over which the per-minute rate limits are counted
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// The node's default ILP Address
+static DEFAULT_ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("local.host").unwrap());
+
+/// Columns of the accounts table which hold the account details, in the order
+/// they are bound when writing and read when loading an account
+static ACCOUNT_COLUMNS: [&str; 25] = [
+    "id",
+    "username",
+    "ilp_address",
+    "asset_code",
+    "asset_scale",
+    "max_packet_amount",
+    "min_balance",
+    "ilp_over_http_url",
+    "ilp_over_http_incoming_token",
+    "ilp_over_http_outgoing_token",
+    "ilp_over_btp_url",
+    "ilp_over_btp_incoming_token",
+    "ilp_over_btp_outgoing_token",
+    "settle_threshold",
+    "settle_to",
+    "routing_relation",
+    "round_trip_time",
+    "packets_per_minute_limit",
+    "amount_per_minute_limit",
+    "settle_every",
+    "min_packet_amount",
+    "max_payment_amount",
+    "amount_per_day_limit",
+    "routing_preference",
+    "settlement_engine_url",
+];
+
+/// Inserts or overwrites the details of an account, leaving its balance untouched
+static UPSERT_ACCOUNT: Lazy<String> = Lazy::new(|| {
+    let placeholders: Vec<String> = (1..=ACCOUNT_COLUMNS.len())
+        .map(|i| format!("?{}", i))
+        .collect();
+    let updates: Vec<String> = ACCOUNT_COLUMNS[1..]
+        .iter()
+        .map(|column| format!("{0} = excluded.{0}", column))
+        .collect();
+    format!(
+        "INSERT INTO accounts ({}) VALUES ({}) ON CONFLICT(id) DO UPDATE SET {}",
+        ACCOUNT_COLUMNS.join(", "),
+        placeholders.join(", "),
+        updates.join(", ")
+    )
+});
+
+/// Loads accounts. If an account does not have a settlement_engine_url set
+/// but there is one configured for that account's currency, the globally
+/// configured url is used
+static SELECT_ACCOUNTS: Lazy<String> = Lazy::new(|| {
+    let last = ACCOUNT_COLUMNS.len() - 1;
+    let columns: Vec<String> = ACCOUNT_COLUMNS[..last]
+        .iter()
+        .map(|column| format!("a.{}", column))
+        .chain(std::iter::once(
+            "COALESCE(a.settlement_engine_url, e.url)".to_string(),
+        ))
+        .collect();
+    format!(
+        "SELECT {} FROM accounts a LEFT JOIN settlement_engines e ON e.asset_code = a.asset_code",
+        columns.join(", ")
+    )
+});
+
+/// Error returned when a balance update is not allowed
+#[derive(Debug, Error)]
+#[error("Incoming prepare of {amount} would bring account {account_id} under its minimum balance. Current balance: {balance}, min balance: {min_balance}")]
+pub struct MinBalanceExceeded {
+    account_id: Uuid,
+    amount: u64,
+    balance: i64,
+    min_balance: i64,
+}
+
+/// Builder for the SQLite Store
+pub struct SqliteStoreBuilder {
+    path: PathBuf,
+    secret: [u8; 32],
+    previous_secret: Option<[u8; 32]>,
+    /// Connector's ILP Address. Used to insert `Child` accounts as
+    node_ilp_address: Address,
+    /// File to which snapshots of the database are written
+    snapshot_path: Option<PathBuf>,
+    /// Interval at which snapshots are written, in milliseconds
+    snapshot_interval: u64,
+    timers: OperationTimers,
+}
+
+impl SqliteStoreBuilder {
+    /// Simple Constructor. The path `:memory:` opens a database which only lives in memory.
+    pub fn new<P: Into<PathBuf>>(path: P, secret: [u8; 32]) -> Self {
+        SqliteStoreBuilder {
+            path: path.into(),
+            secret,
+            previous_secret: None,
+            node_ilp_address: DEFAULT_ILP_ADDRESS.clone(),
+            snapshot_path: None,
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            timers: OperationTimers::new("sqlite"),
+        }
+    }
+
+    /// Constructor for a store whose database only lives in memory.
+    /// Use `snapshot_path` to persist it across restarts.
+    pub fn in_memory(secret: [u8; 32]) -> Self {
+        SqliteStoreBuilder::new(IN_MEMORY_PATH, secret)
+    }
+
+    /// Sets the ILP Address corresponding to the node
+    pub fn node_ilp_address(&mut self, node_ilp_address: Address) -> &mut Self {
+        self.node_ilp_address = node_ilp_address;
+        self
+    }
+
+    /// Sets the secret which was used before the current one, in order to rotate the key
+    /// with which the account tokens are encrypted. On opening, all tokens which were
+    /// encrypted with the previous secret are re-encrypted with the current one.
+    pub fn previous_secret(&mut self, previous_secret: [u8; 32]) -> &mut Self {
+        self.previous_secret = Some(previous_secret);
+        self
+    }
+
+    /// Sets the file to which snapshots of the database are periodically written.
+    /// If the file exists when the store is opened, the database is restored from it.
+    pub fn snapshot_path<P: Into<PathBuf>>(&mut self, snapshot_path: P) -> &mut Self {
+        self.snapshot_path = Some(snapshot_path.into());
+        self
+    }
+
+    /// Sets the interval (in milliseconds) at which snapshots are written
+    pub fn snapshot_interval(&mut self, snapshot_interval: u64) -> &mut Self {
+        self.snapshot_interval = snapshot_interval;
+        self
+    }
+
+    /// Sets the duration (in milliseconds) after which store operations are logged
+    /// as slow. Slow operations are not logged by default.
+    pub fn slow_operation_threshold(&mut self, threshold: u64) -> &mut Self {
+        self.timers
+            .set_default_threshold(Duration::from_millis(threshold));
+        self
+    }
+
+    /// Sets the slow operation threshold (in milliseconds) of a single store method,
+    /// e.g. `update_balances_for_prepare`, overriding the `slow_operation_threshold`
+    pub fn method_slow_operation_threshold(&mut self, method: &str, threshold: u64) -> &mut Self {
+        self.timers
+            .set_threshold(method, Duration::from_millis(threshold));
+        self
+    }
+
+    /// Opens the SQLite Store
+    ///
+    /// Specifically
+    /// 1. Generates encryption and decryption keys
+    /// 1. Opens (or creates) the database file, restores the last snapshot (if configured)
+    ///    and ensures the schema exists
+    /// 1. Gets the Node address assigned to us by our parent (if it exists)
+    /// 1. Loads the routing table into memory
+    /// 1. Re-encrypts the tokens encrypted with the previous secret (if configured)
+    /// 1. Spawns a task which periodically writes snapshots (if configured)
+    pub async fn connect(&mut self) -> Result<SqliteStore, ()> {
+        runtime::ensure().map_err(|err| error!("{}", err))?;
+        let (encryption_key, decryption_key) = generate_keys(&self.secret[..]);
+        self.secret.zeroize(); // clear the secret after it has been used for key generation
+        let previous_decryption_key = self.previous_secret.as_mut().map(|previous_secret| {
+            let (_, decryption_key) = generate_keys(&previous_secret[..]);
+            previous_secret.zeroize();
+            decryption_key
+        });
+
+        let mut connection = Connection::open(&self.path).map_err(|err| {
+            error!(
+                "Error opening SQLite database at {}: {:?}",
+                self.path.display(),
+                err
+            )
+        })?;
+        if let Some(ref snapshot_path) = self.snapshot_path {
+            if snapshot_path.exists() {
+                connection
+                    .restore(DatabaseName::Main, snapshot_path, None::<fn(Progress)>)
+                    .map_err(|err| {
+                        error!(
+                            "Error restoring snapshot from {}: {:?}",
+                            snapshot_path.display(),
+                            err
+                        )
+                    })?;
+                debug!("Restored snapshot from {}", snapshot_path.display());
+            }
+        }
+        connection
+            .execute_batch(SCHEMA)
+            .and_then(|_| migrate_schema(&connection))
+            .map_err(|err| error!("Error creating SQLite schema: {:?}", err))?;
+        debug!("Opened SQLite database at {}", self.path.display());
+
+        // Before initializing the store, check if we have an address
+        // that was configured due to adding a parent. If no parent was
+        // found, use the builder's provided address (local.host) or the
+        // one we decided to override it with
+        let address = get_setting(&connection, PARENT_ILP_KEY).map_err(|err| {
+            error!(
+                "Error checking whether we have a parent configured: {:?}",
+                err
+            )
+        })?;
+        let node_ilp_address = if let Some(address) = address {
+            Address::from_str(&address).unwrap()
+        } else {
+            self.node_ilp_address.clone()
+        };
+
+        let routes = load_routes(&connection)
+            .map_err(|err| error!("Error loading routing table: {:?}", err))?;
+
+        let (all_payment_publisher, _) = broadcast::channel::<PaymentNotification>(256);
+
+        let store = SqliteStore {
+            ilp_address: Arc::new(RwLock::new(node_ilp_address)),
+            connection: Arc::new(Mutex::new(connection)),
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            payment_publisher: all_payment_publisher,
+            exchange_rates: Arc::new(RwLock::new(HashMap::new())),
+            exchange_rates_updated_at: Arc::new(RwLock::new(HashMap::new())),
+            routes: Arc::new(RwLock::new(Arc::new(routes))),
+            rate_limits: Arc::new(Mutex::new(HashMap::new())),
+            encryption_key: Arc::new(encryption_key),
+            decryption_key: Arc::new(decryption_key),
+            timers: Arc::new(self.timers.clone()),
+            verified_tokens: Arc::new(VerifiedTokens::new()),
+        };
+
+        store
+            .migrate_tokens(
+                previous_decryption_key
+                    .as_ref()
+                    .map(|key| &key.expose_secret().0),
+            )
+            .await
+            .map_err(|err| error!("Error migrating account tokens: {:?}", err))?;
+
+        // Write snapshots until the store is dropped
+        if let Some(snapshot_path) = self.snapshot_path.clone() {
+            let connection = Arc::downgrade(&store.connection);
+            let snapshot_interval = self.snapshot_interval;
+            runtime::spawn(async move {
+                let mut interval = runtime::interval(Duration::from_millis(snapshot_interval));
+                // The first tick completes immediately and there is nothing new to save yet
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    if let Some(connection) = connection.upgrade() {
+                        // Writing the file blocks, so it is kept off the threads forwarding packets
+                        let snapshot_path = snapshot_path.clone();
+                        let _ = runtime::run_blocking(move || {
+                            write_snapshot(&connection, &snapshot_path)
+                        })
+                        .await;
+                    } else {
+                        debug!("Not writing snapshots anymore because the store was dropped");
+                        break;
+                    }
+                }
+            });
+        }
+
+        Ok(store)
+    }
+}
+
+/// Amount consumed from a rate limit during the current window
+#[derive(Debug, Clone, Copy)]
+struct RateLimitWindow {
+    started_at: Instant,
+    used: u64,
+}
+
+/// A Store that uses an embedded SQLite database.
+///
+/// Balance updates are executed inside SQLite transactions, so they are atomic
+/// with respect to other operations on the same store.
+///
+/// Unlike the RedisStore, this store is meant to be used by a single node
+/// process, so the routing table is reloaded whenever it is modified rather
+/// than polled for.
+#[derive(Clone)]
+pub struct SqliteStore {
+    /// The Store's ILP Address
+    ilp_address: Arc<RwLock<Address>>,
+    /// The database connection. SQLite serializes writes anyway, so a single
+    /// connection behind a lock is sufficient
+    connection: Arc<Mutex<Connection>>,
+    /// WebSocket senders which publish incoming payment updates
+    subscriptions: Arc<Mutex<HashMap<Uuid, Vec<UnboundedSender<PaymentNotification>>>>>,
+    /// A subscriber to all payment notifications, exposed via a WebSocket
+    payment_publisher: broadcast::Sender<PaymentNotification>,
+    exchange_rates: Arc<RwLock<HashMap<String, f64>>>,
+    /// When each of the exchange rates was last set
+    exchange_rates_updated_at: Arc<RwLock<HashMap<String, SystemTime>>>,
+    /// The store keeps the routing table in memory so that it can be returned
+    /// synchronously while the Router is processing packets.
+    routes: Arc<RwLock<Arc<HashMap<String, Uuid>>>>,
+    /// Packet and throughput counters for the per-minute rate limits
+    rate_limits: Arc<Mutex<HashMap<String, RateLimitWindow>>>,
+    /// Encryption Key so that the no cleartext data are stored
+    encryption_key: Arc<Secret<EncryptionKey>>,
+    /// Decryption Key to provide cleartext data to users
+    decryption_key: Arc<Secret<DecryptionKey>>,
+    /// Records the duration of store operations
+    timers: Arc<OperationTimers>,
+    /// Incoming tokens which were recently verified against their hashes
+    verified_tokens: Arc<VerifiedTokens>,
+}
+
+impl SqliteStore {
+    /// Runs the provided closure with exclusive access to the database connection. SQLite
+    /// blocks the thread while it waits for the lock and for the disk, so the closure is
+    /// run on the runtime's threads for blocking work.
+    async fn with_connection<T, F>(&self, f: F) -> rusqlite::Result<T>
+    where
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+        runtime::run_blocking(move || f(&mut connection.lock())).await
+    }
+
+    /// Writes a snapshot of the database to the given file. This can be used to
+    /// persist an in-memory store on shutdown, in addition to the periodic snapshots.
+    pub fn save_snapshot<P: AsRef<Path>>(&self, snapshot_path: P) -> Result<(), ()> {
+        write_snapshot(&self.connection, snapshot_path.as_ref())
+    }
+
+    /// Reloads the in-memory routing table from the database
+    async fn update_routes(&self) -> rusqlite::Result<()> {
+        let routes = self.with_connection(|conn| load_routes(conn)).await?;
+        // TODO we may not want to print this because the routing table will be very big
+        // if the node has a lot of local accounts
+        trace!("Routing table is: {:?}", routes);
+        *self.routes.write() = Arc::new(routes);
+        Ok(())
+    }
+
+    /// Re-encrypts the tokens of all accounts which can only be decrypted with the given
+    /// key (derived from the previous secret) with the current encryption key, and replaces
+    /// the incoming tokens which are still stored encrypted with their hashes
+    async fn migrate_tokens(
+        &self,
+        previous_decryption_key: Option<&aead::LessSafeKey>,
+    ) -> rusqlite::Result<()> {
+        let accounts = self.sqlite_load_accounts("", Vec::new()).await?;
+        let decryption_key = &self.decryption_key.expose_secret().0;
+        let mut decryption_keys = vec![decryption_key];
+        decryption_keys.extend(previous_decryption_key);
+        let mut updates = Vec::new();
+        for mut account in accounts {
+            let mut migrated = account.hash_incoming_tokens(&decryption_keys);
+            if let Some(previous_decryption_key) = previous_decryption_key {
+                migrated.extend(account.reencrypt_tokens(
+                    previous_decryption_key,
+                    decryption_key,
+                    &self.encryption_key.expose_secret().0,
+                ));
+            }
+            for (column, token) in migrated {
+                updates.push((column, token.to_vec(), account.account.id.to_string()));
+            }
+        }
+        let num_tokens = updates.len();
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            for (column, token, id) in updates {
+                tx.execute(
+                    &format!("UPDATE accounts SET {} = ?1 WHERE id = ?2", column),
+                    params![token, id],
+                )?;
+            }
+            tx.commit()
+        })
+        .await
+        .await?;
+        if num_tokens > 0 {
+            debug!("Migrated {} account tokens", num_tokens);
+        }
+        Ok(())
+    }
+
+    /// Loads the accounts (tokens remain encrypted) matching the provided `WHERE` clause
+    async fn sqlite_load_accounts(
+        &self,
+        filter: &str,
+        params: Vec<Value>,
+    ) -> rusqlite::Result<Vec<AccountWithEncryptedTokens>> {
+        let query = format!("{} {}", *SELECT_ACCOUNTS, filter);
+        self.with_connection(move |conn| {
+            let mut statement = conn.prepare(&query)?;
+            let params: Vec<&dyn rusqlite::ToSql> = params
+                .iter()
+                .map(|value| value as &dyn rusqlite::ToSql)
+                .collect();
+            let accounts = statement
+                .query_map(&params[..], account_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(accounts)
+        })
+        .await
+        .await
+    }
+
+    /// Gets the account (tokens remain encrypted) corresponding to the provided `id`
+    async fn sqlite_get_account(
+        &self,
+        id: Uuid,
+    ) -> Result<AccountWithEncryptedTokens, NodeStoreError> {
+        let mut accounts = self
+            .sqlite_load_accounts("WHERE a.id = ?1", vec![Value::Text(id.to_string())])
+            .await?;
+        accounts
+            .pop()
+            .ok_or_else(|| NodeStoreError::AccountNotFound(id.to_string()))
+    }
+
+    /// Gets the account (tokens remain encrypted) corresponding to the provided `username`
+    async fn sqlite_get_account_by_username(
+        &self,
+        username: &Username,
+    ) -> rusqlite::Result<Option<AccountWithEncryptedTokens>> {
+        let mut accounts = self
+            .sqlite_load_accounts(
+                "WHERE a.username = ?1",
+                vec![Value::Text(username.to_string())],
+            )
+            .await?;
+        Ok(accounts.pop())
+    }
+
+    /// Inserts the account corresponding to the provided `AccountWithEncryptedtokens`
+    async fn sqlite_insert_account(
+        &self,
+        encrypted: AccountWithEncryptedTokens,
+    ) -> Result<(), NodeStoreError> {
+        let (id, username, ilp_address) = (
+            encrypted.account.id,
+            encrypted.account.username.clone(),
+            encrypted.account.ilp_address.clone(),
+        );
+        let inserted = self
+            .with_connection(move |conn| {
+                let account = &encrypted.account;
+                let tx = conn.transaction()?;
+                // Check that there isn't already an account with values that MUST be unique
+                let exists: bool = tx.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM accounts WHERE id = ?1 OR username = ?2)",
+                    params![account.id.to_string(), account.username.to_string()],
+                    |row| row.get(0),
+                )?;
+                if exists {
+                    warn!(
+                        "An account already exists with the same {}. Cannot insert account: {:?}",
+                        account.id, account
+                    );
+                    return Ok(false);
+                }
+
+                write_account(&tx, &encrypted)?;
+                tx.execute(
+                    "INSERT OR REPLACE INTO routes (prefix, account_id) VALUES (?1, ?2)",
+                    params![account.ilp_address.to_string(), account.id.to_string()],
+                )?;
+                tx.commit()?;
+                Ok(true)
+            })
+            .await
+            .await?;
+        if !inserted {
+            return Err(NodeStoreError::AccountExists(username.to_string()));
+        }
+
+        self.update_routes().await?;
+        debug!("Inserted account {} (ILP address: {})", id, ilp_address);
+        Ok(())
+    }
+
+    /// Overwrites the account corresponding to the provided `AccountWithEncryptedtokens`
+    async fn sqlite_update_account(
+        &self,
+        encrypted: AccountWithEncryptedTokens,
+    ) -> Result<(), NodeStoreError> {
+        let (id, username, ilp_address) = (
+            encrypted.account.id,
+            encrypted.account.username.clone(),
+            encrypted.account.ilp_address.clone(),
+        );
+        self.with_connection(move |conn| {
+            let account = &encrypted.account;
+            let tx = conn.transaction()?;
+            let old_address: Option<String> = tx
+                .query_row(
+                    "SELECT ilp_address FROM accounts WHERE id = ?1",
+                    params![account.id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let old_address = match old_address {
+                Some(old_address) => old_address,
+                None => {
+                    warn!(
+                        "No account exists with ID {}, cannot update account {:?}",
+                        account.id, account
+                    );
+                    return Ok(Err(NodeStoreError::AccountNotFound(account.id.to_string())));
+                }
+            };
+            let taken: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM accounts WHERE username = ?1 AND id != ?2)",
+                params![account.username.to_string(), account.id.to_string()],
+                |row| row.get(0),
+            )?;
+            if taken {
+                warn!(
+                    "Username {} is already taken, cannot update account {}",
+                    account.username, account.id
+                );
+                return Ok(Err(NodeStoreError::AccountExists(
+                    account.username.to_string(),
+                )));
+            }
+
+            write_account(&tx, &encrypted)?;
+            // Replace the route to the previous address
+            tx.execute(
+                "DELETE FROM routes WHERE prefix = ?1 AND account_id = ?2",
+                params![old_address, account.id.to_string()],
+            )?;
+            tx.execute(
+                "INSERT OR REPLACE INTO routes (prefix, account_id) VALUES (?1, ?2)",
+                params![account.ilp_address.to_string(), account.id.to_string()],
+            )?;
+            tx.commit()?;
+            Ok(Ok(()))
+        })
+        .await
+        .await??;
+
+        self.update_routes().await?;
+        debug!(
+            "Updated account {} (id: {}, ILP address: {})",
+            username, id, ilp_address
+        );
+        Ok(())
+    }
+
+    /// Deletes the account corresponding to the provided `id`.
+    /// Returns the deleted account (tokens remain encrypted)
+    async fn sqlite_delete_account(
+        &self,
+        id: Uuid,
+    ) -> Result<AccountWithEncryptedTokens, NodeStoreError> {
+        let encrypted = self.sqlite_get_account(id).await?;
+        let account_id = encrypted.account.id.to_string();
+        let is_parent = encrypted.account.routing_relation == RoutingRelation::Parent;
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM accounts WHERE id = ?1", params![account_id])?;
+            // Remove all routes which point to the account, including the ones learned via CCP
+            tx.execute(
+                "DELETE FROM routes WHERE account_id = ?1",
+                params![account_id],
+            )?;
+            tx.execute(
+                "DELETE FROM static_routes WHERE account_id = ?1",
+                params![account_id],
+            )?;
+            tx.execute(
+                "DELETE FROM node_settings WHERE key = ?1 AND value = ?2",
+                params![DEFAULT_ROUTE_KEY, account_id],
+            )?;
+            // Forget the address assigned by the parents once the last one is gone
+            let other_parents: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM accounts WHERE routing_relation = ?1)",
+                params![RoutingRelation::Parent.as_ref()],
+                |row| row.get(0),
+            )?;
+            if is_parent && !other_parents {
+                tx.execute(
+                    "DELETE FROM node_settings WHERE key = ?1",
+                    params![PARENT_ILP_KEY],
+                )?;
+            }
+            tx.execute(
+                "DELETE FROM uncredited_amounts WHERE account_id = ?1",
+                params![account_id],
+            )?;
+            tx.execute(
+                "DELETE FROM daily_volumes WHERE account_id = ?1",
+                params![account_id],
+            )?;
+            tx.execute(
+                "DELETE FROM incoming_payments WHERE account_id = ?1",
+                params![account_id],
+            )?;
+            tx.commit()
+        })
+        .await
+        .await?;
+        {
+            let mut rate_limits = self.rate_limits.lock();
+            rate_limits.remove(&format!("limit:packets:{}", id));
+            rate_limits.remove(&format!("limit:throughput:{}", id));
+        }
+
+        self.update_routes().await?;
+        debug!("Deleted account {}", id);
+        Ok(encrypted)
+    }
+
+    /// Applies a fulfill, and adds the amount to settle to the account's queued
+    /// outgoing settlement if `queue_settlement` is set
+    async fn sqlite_process_fulfill(
+        &self,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+        packet_id: Option<PacketId>,
+        queue_settlement: bool,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        let (balance, amount_to_settle) = self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            let (balance, prepaid_amount, settle_threshold, settle_to): (
+                i64,
+                i64,
+                Option<i64>,
+                Option<i64>,
+            ) = tx.query_row(
+                "SELECT balance, prepaid_amount, settle_threshold, settle_to FROM accounts WHERE id = ?1",
+                params![to_account_id.to_string()],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )?;
+            let mut balance = balance + outgoing_amount as i64;
+            if outgoing_amount > 0 {
+                log_balance_change(
+                    &tx,
+                    to_account_id,
+                    BalanceChangeReason::Fulfill,
+                    outgoing_amount as i64,
+                    balance + prepaid_amount,
+                    packet_id,
+                )?;
+            }
+
+            // The logic for triggering settlement is as follows:
+            //  1. settle_threshold must be set (if it's not, then settlement was perhaps disabled on the account).
+            //  2. balance must be greater than settle_threshold (this is the core of the 'should I settle logic')
+            //  3. settle_threshold must be greater than settle_to (e.g., settleTo=5, settleThreshold=6)
+            let mut settle_amount = 0;
+            if let (Some(settle_threshold), Some(settle_to)) = (settle_threshold, settle_to) {
+                if balance >= settle_threshold && settle_threshold > settle_to {
+                    settle_amount = (balance - settle_to) as u64;
+                    // Update the balance _before_ sending the settlement so that we don't accidentally send
+                    // multiple settlements for the same balance. If the settlement fails we'll roll back
+                    // the balance change by re-adding the amount back to the balance
+                    balance = settle_to;
+                    log_balance_change(
+                        &tx,
+                        to_account_id,
+                        BalanceChangeReason::Settlement,
+                        -(settle_amount as i64),
+                        balance + prepaid_amount,
+                        packet_id,
+                    )?;
+                }
+            }
+            set_balance(&tx, to_account_id, balance, prepaid_amount)?;
+            // Queued along with the balance change, so that the amount is settled
+            // even if the node stops before sending the settlement
+            if queue_settlement && settle_amount > 0 {
+                queue_settlement_amount(&tx, to_account_id, settle_amount)?;
+            }
+            tx.commit()?;
+            Ok((balance + prepaid_amount, settle_amount))
+        }).await?;
+
+        trace!(
+            "Processed fulfill for account {} for outgoing amount {}. Fulfill call result: {} {}",
+            to_account_id,
+            outgoing_amount,
+            balance,
+            amount_to_settle,
+        );
+        Ok((balance, amount_to_settle))
+    }
+
+    fn decrypt(&self, encrypted: AccountWithEncryptedTokens) -> Account {
+        encrypted.decrypt_tokens(&self.decryption_key.expose_secret().0)
+    }
+
+    fn encrypt(&self, token: &SecretString) -> Bytes {
+        encrypt_token(
+            &self.encryption_key.expose_secret().0,
+            token.expose_secret().as_bytes(),
+        )
+        .freeze()
+    }
+}
+
+#[async_trait]
+impl AccountStore for SqliteStore {
+    type Account = Account;
+
+    async fn get_accounts(
+        &self,
+        account_ids: Vec<Uuid>,
+    ) -> Result<Vec<Account>, AccountStoreError> {
+        let _timer = self.timers.start("get_accounts");
+        let num_accounts = account_ids.len();
+        let mut accounts = Vec::with_capacity(num_accounts);
+        // Load the accounts one by one to return them in the order of the provided ids
+        for id in account_ids.iter() {
+            let mut loaded = self
+                .sqlite_load_accounts("WHERE a.id = ?1", vec![Value::Text(id.to_string())])
+                .await?;
+            if let Some(account) = loaded.pop() {
+                accounts.push(self.decrypt(account));
+            }
+        }
+
+        if accounts.len() == num_accounts {
+            Ok(accounts)
+        } else {
+            Err(AccountStoreError::WrongLength {
+                expected: num_accounts,
+                actual: accounts.len(),
+            })
+        }
+    }
+
+    async fn get_account_id_from_username(
+        &self,
+        username: &Username,
+    ) -> Result<Uuid, AccountStoreError> {
+        let _timer = self.timers.start("get_account_id_from_username");
+        let username_str = username.to_string();
+        let id: Option<String> = self
+            .with_connection(move |conn| {
+                conn.query_row(
+                    "SELECT id FROM accounts WHERE username = ?1",
+                    params![username_str],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+            .await?;
+        match id.and_then(|id| Uuid::from_str(&id).ok()) {
+            Some(id) => Ok(id),
+            None => {
+                debug!("Username not found: {}", username);
+                Err(AccountStoreError::AccountNotFound(username.to_string()))
+            }
+        }
+    }
+}
+
+impl StreamNotificationsStore for SqliteStore {
+    type Account = Account;
+
+    fn add_payment_notification_subscription(
+        &self,
+        id: Uuid,
+        sender: UnboundedSender<PaymentNotification>,
+    ) {
+        trace!("Added payment notification listener for {}", id);
+        self.subscriptions
+            .lock()
+            .entry(id)
+            .or_insert_with(Vec::new)
+            .push(sender);
+    }
+
+    fn publish_payment_notification(&self, payment: PaymentNotification) {
+        // There is no pubsub to go through, so the notification is delivered
+        // to the in-process subscribers directly, once the account is looked up
+        let store = self.clone();
+        runtime::spawn(async move {
+            let account_id = match store
+                .sqlite_get_account_by_username(&payment.to_username)
+                .await
+            {
+                Ok(Some(account)) => account.account.id,
+                _ => {
+                    error!(
+                        "Failed to find account ID corresponding to username: {}",
+                        payment.to_username
+                    );
+                    return;
+                }
+            };
+
+            trace!(
+                "Publishing payment notification {:?} for account {}",
+                payment,
+                account_id
+            );
+            if store.payment_publisher.receiver_count() > 0 {
+                if let Err(err) = store.payment_publisher.send(payment.clone()) {
+                    error!("Failed to send a node-wide payment notification: {:?}", err);
+                }
+            }
+            match store.subscriptions.lock().get_mut(&account_id) {
+                Some(senders) => {
+                    senders.retain(|sender| {
+                        if let Err(err) = sender.unbounded_send(payment.clone()) {
+                            debug!("Failed to send message: {}", err);
+                            false
+                        } else {
+                            true
+                        }
+                    });
+                }
+                None => trace!(
+                    "Ignoring message for account {} because there were no open subscriptions",
+                    account_id
+                ),
+            }
+        });
+    }
+
+    fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
+        self.payment_publisher.subscribe()
+    }
+}
+
+/// A row of the `incoming_payments` table selected with `INCOMING_PAYMENT_COLUMNS`
+type IncomingPaymentRow = (String, String, i64, bool, bool, i64);
+
+const INCOMING_PAYMENT_COLUMNS: &str =
+    "account_id, destination, amount, closed, acknowledged, updated_at";
+
+fn incoming_payment_from_row(row: &Row) -> rusqlite::Result<IncomingPaymentRow> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+        row.get(5)?,
+    ))
+}
+
+fn parse_incoming_payment(
+    (account_id, destination, amount, closed, acknowledged, updated_at): IncomingPaymentRow,
+) -> Result<IncomingPayment, BalanceStoreError> {
+    Ok(IncomingPayment {
+        account_id: Uuid::from_str(&account_id)
+            .map_err(|err| BalanceStoreError::Other(Box::new(err)))?,
+        destination: Address::from_str(&destination)
+            .map_err(|err| BalanceStoreError::Other(Box::new(err)))?,
+        amount: amount as u64,
+        closed,
+        acknowledged,
+        updated_at: updated_at as u64,
+    })
+}
+
+#[async_trait]
+impl IncomingPaymentsStore for SqliteStore {
+    async fn record_incoming_payment(
+        &self,
+        account_id: Uuid,
+        destination: &Address,
+        amount: u64,
+        closed: bool,
+        timestamp: u64,
+    ) -> Result<IncomingPayment, BalanceStoreError> {
+        let _timer = self.timers.start("record_incoming_payment");
+        let destination = destination.to_string();
+        let row = self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO incoming_payments (account_id, destination, amount, closed, acknowledged, updated_at) \
+                 VALUES (?1, ?2, ?3, ?4, 0, ?5) \
+                 ON CONFLICT (account_id, destination) DO UPDATE SET amount = amount + excluded.amount, \
+                 closed = closed OR excluded.closed, acknowledged = acknowledged AND excluded.amount = 0, \
+                 updated_at = excluded.updated_at",
+                params![
+                    account_id.to_string(),
+                    destination,
+                    amount.min(i64::MAX as u64) as i64,
+                    closed,
+                    timestamp.min(i64::MAX as u64) as i64
+                ],
+            )?;
+            let row = tx.query_row(
+                &format!(
+                    "SELECT {} FROM incoming_payments WHERE account_id = ?1 AND destination = ?2",
+                    INCOMING_PAYMENT_COLUMNS
+                ),
+                params![account_id.to_string(), destination],
+                incoming_payment_from_row,
+            )?;
+            tx.commit()?;
+            Ok(row)
+        }).await?;
+        parse_incoming_payment(row)
+    }
+
+    async fn acknowledge_incoming_payment(
+        &self,
+        account_id: Uuid,
+        destination: &Address,
+    ) -> Result<Option<IncomingPayment>, BalanceStoreError> {
+        let _timer = self.timers.start("acknowledge_incoming_payment");
+        let destination = destination.to_string();
+        let row = self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "UPDATE incoming_payments SET acknowledged = 1 WHERE account_id = ?1 AND destination = ?2",
+                params![account_id.to_string(), destination],
+            )?;
+            let row = tx
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM incoming_payments WHERE account_id = ?1 AND destination = ?2",
+                        INCOMING_PAYMENT_COLUMNS
+                    ),
+                    params![account_id.to_string(), destination],
+                    incoming_payment_from_row,
+                )
+                .optional()?;
+            tx.commit()?;
+            Ok(row)
+        }).await?;
+        row.map(parse_incoming_payment).transpose()
+    }
+
+    async fn get_unacknowledged_payments(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Vec<IncomingPayment>, BalanceStoreError> {
+        let _timer = self.timers.start("get_unacknowledged_payments");
+        let rows = self.with_connection(move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM incoming_payments WHERE account_id = ?1 AND acknowledged = 0 ORDER BY updated_at",
+                INCOMING_PAYMENT_COLUMNS
+            ))?;
+            let rows = stmt
+                .query_map(params![account_id.to_string()], incoming_payment_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows)
+        }).await?;
+        rows.into_iter().map(parse_incoming_payment).collect()
+    }
+}
+
+#[async_trait]
+impl BalanceStore for SqliteStore {
+    /// Returns the balance **from the account holder's perspective**, meaning the sum of
+    /// the Payable Balance and Pending Outgoing minus the Receivable Balance and the Pending Incoming.
+    async fn get_balance(&self, account_id: Uuid) -> Result<i64, BalanceStoreError> {
+        let _timer = self.timers.start("get_balance");
+        let balance = self
+            .with_connection(move |conn| {
+                conn.query_row(
+                    "SELECT balance + prepaid_amount FROM accounts WHERE id = ?1",
+                    params![account_id.to_string()],
+                    |row| row.get(0),
+                )
+            })
+            .await?;
+        Ok(balance)
+    }
+
+    async fn update_balances_for_prepare(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+        packet_id: Option<PacketId>,
+    ) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("update_balances_for_prepare");
+        // Don't do anything if the amount was 0
+        if incoming_amount == 0 {
+            return Ok(());
+        }
+
+        let result = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                let (min_balance, balance, prepaid_amount): (Option<i64>, i64, i64) = tx
+                    .query_row(
+                        "SELECT min_balance, balance, prepaid_amount FROM accounts WHERE id = ?1",
+                        params![from_account_id.to_string()],
+                        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                    )?;
+                let amount = incoming_amount as i64;
+
+                // Check that the prepare wouldn't go under the account's minimum balance
+                if let Some(min_balance) = min_balance {
+                    if balance + prepaid_amount - amount < min_balance {
+                        return Ok(Err(MinBalanceExceeded {
+                            account_id: from_account_id,
+                            amount: incoming_amount,
+                            balance,
+                            min_balance,
+                        }));
+                    }
+                }
+
+                // Deduct the amount from the prepaid_amount and/or the balance
+                let (balance, prepaid_amount) = if prepaid_amount >= amount {
+                    (balance, prepaid_amount - amount)
+                } else if prepaid_amount > 0 {
+                    (balance - (amount - prepaid_amount), 0)
+                } else {
+                    (balance - amount, prepaid_amount)
+                };
+                set_balance(&tx, from_account_id, balance, prepaid_amount)?;
+                log_balance_change(
+                    &tx,
+                    from_account_id,
+                    BalanceChangeReason::Prepare,
+                    -amount,
+                    balance + prepaid_amount,
+                    packet_id,
+                )?;
+                tx.commit()?;
+                Ok(Ok(balance + prepaid_amount))
+            })
+            .await?;
+
+        let balance = result.map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+        trace!(
+            "Processed prepare with incoming amount: {}. Account {} has balance (including prepaid amount): {} ",
+            incoming_amount, from_account_id, balance
+        );
+        Ok(())
+    }
+
+    async fn update_balances_for_fulfill(
+        &self,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+        packet_id: Option<PacketId>,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        let _timer = self.timers.start("update_balances_for_fulfill");
+        self.sqlite_process_fulfill(to_account_id, outgoing_amount, packet_id, false)
+            .await
+    }
+
+    async fn update_balances_for_fulfill_and_queue_settlement(
+        &self,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+        packet_id: Option<PacketId>,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        let _timer = self
+            .timers
+            .start("update_balances_for_fulfill_and_queue_settlement");
+        self.sqlite_process_fulfill(to_account_id, outgoing_amount, packet_id, true)
+            .await
+    }
+
+    async fn update_balances_for_reject(
+        &self,
+        from_account_id: Uuid,
+        incoming_amount: u64,
+        packet_id: Option<PacketId>,
+    ) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("update_balances_for_reject");
+        if incoming_amount == 0 {
+            return Ok(());
+        }
+
+        let balance: i64 = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                tx.execute(
+                    "UPDATE accounts SET balance = balance + ?2 WHERE id = ?1",
+                    params![from_account_id.to_string(), incoming_amount as i64],
+                )?;
+                let balance = tx.query_row(
+                    "SELECT balance + prepaid_amount FROM accounts WHERE id = ?1",
+                    params![from_account_id.to_string()],
+                    |row| row.get(0),
+                )?;
+                log_balance_change(
+                    &tx,
+                    from_account_id,
+                    BalanceChangeReason::Reject,
+                    incoming_amount as i64,
+                    balance,
+                    packet_id,
+                )?;
+                tx.commit()?;
+                Ok(balance)
+            })
+            .await?;
+
+        trace!(
+            "Processed reject for incoming amount: {}. Account {} has balance (including prepaid amount): {}",
+            incoming_amount, from_account_id, balance
+        );
+
+        Ok(())
+    }
+
+    async fn update_balances_for_delayed_settlement(
+        &self,
+        to_account_id: Uuid,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        let _timer = self.timers.start("update_balances_for_delayed_settlement");
+        let (balance, amount_to_settle) = self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            let (balance, prepaid_amount, settle_threshold, settle_to): (
+                i64,
+                i64,
+                Option<i64>,
+                Option<i64>,
+            ) = tx.query_row(
+                "SELECT balance, prepaid_amount, settle_threshold, settle_to FROM accounts WHERE id = ?1",
+                params![to_account_id.to_string()],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )?;
+
+            // upon completion the `balance` is at the level of `settle_to`
+            let mut balance = balance;
+            let mut settle_amount = 0;
+            if let (Some(settle_threshold), Some(settle_to)) = (settle_threshold, settle_to) {
+                if settle_threshold > settle_to && balance >= settle_to {
+                    settle_amount = (balance - settle_to) as u64;
+                    balance = settle_to;
+                    set_balance(&tx, to_account_id, balance, prepaid_amount)?;
+                    if settle_amount > 0 {
+                        queue_settlement_amount(&tx, to_account_id, settle_amount)?;
+                    }
+                    log_balance_change(
+                        &tx,
+                        to_account_id,
+                        BalanceChangeReason::Settlement,
+                        -(settle_amount as i64),
+                        balance + prepaid_amount,
+                        None,
+                    )?;
+                }
+            }
+            tx.commit()?;
+            Ok((balance + prepaid_amount, settle_amount))
+        }).await?;
+
+        trace!(
+            "Processed account {} for delayed settlement, balance: {}, to_settle: {}",
+            to_account_id,
+            balance,
+            amount_to_settle
+        );
+
+        Ok((balance, amount_to_settle))
+    }
+
+    async fn get_balance_changes(
+        &self,
+        account_id: Uuid,
+        from: u64,
+        limit: usize,
+    ) -> Result<Vec<BalanceChange>, BalanceStoreError> {
+        let _timer = self.timers.start("get_balance_changes");
+        let entries: Vec<(i64, i64, String, i64, i64, Option<String>)> =
+            self.with_connection(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT sequence, timestamp, reason, delta, balance, packet_id FROM balance_log \
+                     WHERE account_id = ?1 AND sequence >= ?2 ORDER BY sequence LIMIT ?3",
+                )?;
+                let entries = stmt
+                    .query_map(
+                        params![
+                            account_id.to_string(),
+                            from.min(i64::MAX as u64) as i64,
+                            limit.min(i64::MAX as usize) as i64
+                        ],
+                        |row| {
+                            Ok((
+                                row.get(0)?,
+                                row.get(1)?,
+                                row.get(2)?,
+                                row.get(3)?,
+                                row.get(4)?,
+                                row.get(5)?,
+                            ))
+                        },
+                    )?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(entries)
+            }).await?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|(sequence, timestamp, reason, delta, balance, packet_id)| {
+                let reason = BalanceChangeReason::from_str(&reason)
+                    .map_err(|_| warn!("Ignoring balance change with invalid reason: {}", reason))
+                    .ok()?;
+                Some(BalanceChange {
+                    sequence: sequence as u64,
+                    timestamp: timestamp as u64,
+                    account_id,
+                    packet_id,
+                    delta,
+                    balance,
+                    reason,
+                })
+            })
+            .collect())
+    }
+
+    async fn get_balance_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<BalanceTotals, BalanceStoreError> {
+        let _timer = self.timers.start("get_balance_totals");
+        let sums: Vec<(String, i64)> = self
+            .with_connection(move |conn| {
+                let mut stmt = conn.prepare(
+                "SELECT reason, SUM(delta) FROM balance_log WHERE account_id = ?1 GROUP BY reason",
+            )?;
+                let sums = stmt
+                    .query_map(params![account_id.to_string()], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(sums)
+            })
+            .await?;
+        let mut totals = BalanceTotals::default();
+        for (reason, sum) in sums {
+            match BalanceChangeReason::from_str(&reason) {
+                Ok(reason) => totals.add(reason, sum),
+                Err(_) => warn!("Ignoring balance total with invalid reason: {}", reason),
+            }
+        }
+        Ok(totals)
+    }
+}
+
+#[async_trait]
+impl PacketJournalStore for SqliteStore {
+    async fn journal_packet(&self, packet: JournaledPacket) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("journal_packet");
+        self.with_connection(move |conn| {
+            conn.execute(
+                "INSERT INTO packet_journal (id, from_id, to_id, incoming_amount, outgoing_amount, packet_id, expires_at, fulfilled) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    packet.id.to_string(),
+                    packet.from_id.to_string(),
+                    packet.to_id.to_string(),
+                    packet.incoming_amount as i64,
+                    packet.outgoing_amount as i64,
+                    packet.packet_id.map(hex::encode),
+                    packet.expires_at as i64,
+                    packet.fulfilled
+                ],
+            )
+        }).await?;
+        Ok(())
+    }
+
+    async fn mark_journaled_packet_fulfilled(&self, id: Uuid) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("mark_journaled_packet_fulfilled");
+        self.with_connection(move |conn| {
+            conn.execute(
+                "UPDATE packet_journal SET fulfilled = 1 WHERE id = ?1",
+                params![id.to_string()],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_journaled_packet(&self, id: Uuid) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("remove_journaled_packet");
+        self.with_connection(move |conn| {
+            conn.execute(
+                "DELETE FROM packet_journal WHERE id = ?1",
+                params![id.to_string()],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn load_journaled_packets(&self) -> Result<Vec<JournaledPacket>, BalanceStoreError> {
+        let _timer = self.timers.start("load_journaled_packets");
+        #[allow(clippy::type_complexity)]
+        let entries: Vec<(String, String, String, i64, i64, Option<String>, i64, bool)> = self
+            .with_connection(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT id, from_id, to_id, incoming_amount, outgoing_amount, packet_id, expires_at, fulfilled FROM packet_journal",
+                )?;
+                let entries = stmt
+                    .query_map([], |row| {
+                        Ok((
+                            row.get(0)?,
+                            row.get(1)?,
+                            row.get(2)?,
+                            row.get(3)?,
+                            row.get(4)?,
+                            row.get(5)?,
+                            row.get(6)?,
+                            row.get(7)?,
+                        ))
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(entries)
+            }).await?;
+        entries
+            .into_iter()
+            .map(
+                |(
+                    id,
+                    from_id,
+                    to_id,
+                    incoming_amount,
+                    outgoing_amount,
+                    packet_id,
+                    expires_at,
+                    fulfilled,
+                )| {
+                    let parse = |id: &str| {
+                        Uuid::from_str(id).map_err(|err| BalanceStoreError::Other(Box::new(err)))
+                    };
+                    Ok(JournaledPacket {
+                        id: parse(&id)?,
+                        from_id: parse(&from_id)?,
+                        to_id: parse(&to_id)?,
+                        incoming_amount: incoming_amount as u64,
+                        outgoing_amount: outgoing_amount as u64,
+                        packet_id: packet_id
+                            .and_then(|packet_id| PacketId::from_hex(packet_id).ok()),
+                        expires_at: expires_at as u64,
+                        fulfilled,
+                    })
+                },
+            )
+            .collect()
+    }
+}
+
+#[async_trait]
+impl PacketHistoryStore for SqliteStore {
+    async fn record_packets(&self, records: Vec<PacketRecord>) -> Result<(), BalanceStoreError> {
+        let _timer = self.timers.start("record_packets");
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            for record in &records {
+                tx.execute(
+                    "INSERT INTO packet_history (account_id, timestamp, direction, outcome, amount, destination, reject_code) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        record.account_id.to_string(),
+                        record.timestamp as i64,
+                        record.direction.as_str(),
+                        record.outcome.as_str(),
+                        record.amount as i64,
+                        record.destination,
+                        record.reject_code
+                    ],
+                )?;
+            }
+            tx.commit()
+        }).await?;
+        Ok(())
+    }
+
+    async fn get_packet_history(
+        &self,
+        account_id: Uuid,
+        filter: &PacketHistoryFilter,
+        limit: usize,
+    ) -> Result<Vec<PacketRecord>, BalanceStoreError> {
+        let _timer = self.timers.start("get_packet_history");
+        let filter = filter.clone();
+        #[allow(clippy::type_complexity)]
+        let entries: Vec<(i64, i64, String, String, i64, String, Option<String>)> = self
+            .with_connection(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT id, timestamp, direction, outcome, amount, destination, reject_code FROM packet_history \
+                     WHERE account_id = ?1 AND (?2 IS NULL OR id < ?2) \
+                     AND (?3 IS NULL OR timestamp >= ?3) AND (?4 IS NULL OR timestamp < ?4) \
+                     AND (?5 IS NULL OR direction = ?5) AND (?6 IS NULL OR outcome = ?6) \
+                     ORDER BY id DESC LIMIT ?7",
+                )?;
+                let entries = stmt
+                    .query_map(
+                        params![
+                            account_id.to_string(),
+                            filter.before.map(|before| before.min(i64::MAX as u64) as i64),
+                            filter.since.map(|since| since.min(i64::MAX as u64) as i64),
+                            filter.until.map(|until| until.min(i64::MAX as u64) as i64),
+                            filter.direction.map(|direction| direction.as_str()),
+                            filter.outcome.map(|outcome| outcome.as_str()),
+                            limit.min(i64::MAX as usize) as i64
+                        ],
+                        |row| {
+                            Ok((
+                                row.get(0)?,
+                                row.get(1)?,
+                                row.get(2)?,
+                                row.get(3)?,
+                                row.get(4)?,
+                                row.get(5)?,
+                                row.get(6)?,
+                            ))
+                        },
+                    )?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(entries)
+            }).await?;
+        Ok(entries
+            .into_iter()
+            .filter_map(
+                |(id, timestamp, direction, outcome, amount, destination, reject_code)| {
+                    let direction = PacketDirection::from_str(&direction)
+                        .map_err(|_| {
+                            warn!(
+                                "Ignoring packet record with invalid direction: {}",
+                                direction
+                            )
+                        })
+                        .ok()?;
+                    let outcome = PacketOutcome::from_str(&outcome)
+                        .map_err(|_| {
+                            warn!("Ignoring packet record with invalid outcome: {}", outcome)
+                        })
+                        .ok()?;
+                    Some(PacketRecord {
+                        id: id as u64,
+                        timestamp: timestamp as u64,
+                        account_id,
+                        direction,
+                        outcome,
+                        amount: amount as u64,
+                        destination,
+                        reject_code,
+                    })
+                },
+            )
+            .collect())
+    }
+
+    async fn prune_packet_history(&self, older_than: u64) -> Result<u64, BalanceStoreError> {
+        let _timer = self.timers.start("prune_packet_history");
+        let deleted = self
+            .with_connection(move |conn| {
+                conn.execute(
+                    "DELETE FROM packet_history WHERE timestamp < ?1",
+                    params![older_than.min(i64::MAX as u64) as i64],
+                )
+            })
+            .await?;
+        Ok(deleted as u64)
+    }
+}
+
+impl ExchangeRateStore for SqliteStore {
+    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        let rates: Vec<f64> = asset_codes
+            .iter()
+            .filter_map(|code| (*self.exchange_rates.read()).get(*code).cloned())
+            .collect();
+        if rates.len() == asset_codes.len() {
+            Ok(rates)
+        } else {
+            Err(ExchangeRateStoreError::PairNotFound {
+                from: asset_codes[0].to_string(),
+                to: asset_codes[1].to_string(),
+            })
+        }
+    }
+
+    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        Ok((*self.exchange_rates.read()).clone())
+    }
+
+    fn set_exchange_rates(
+        &self,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        let now = SystemTime::now();
+        (*self.exchange_rates_updated_at.write()) =
+            rates.keys().map(|code| (code.clone(), now)).collect();
+        (*self.exchange_rates.write()) = rates;
+        Ok(())
+    }
+
+    fn get_exchange_rates_updated_at(&self, asset_codes: &[&str]) -> Option<SystemTime> {
+        let updated_at = self.exchange_rates_updated_at.read();
+        asset_codes
+            .iter()
+            .filter_map(|code| updated_at.get(*code).cloned())
+            .min()
+    }
+}
+
+#[async_trait]
+impl BtpStore for SqliteStore {
+    type Account = Account;
+
+    async fn get_account_from_btp_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, BtpStoreError> {
+        let _timer = self.timers.start("get_account_from_btp_auth");
+        let account = self
+            .sqlite_get_account_by_username(username)
+            .await?
+            .map(|account| self.decrypt(account));
+        self.verified_tokens
+            .authenticate(AuthProtocol::Btp, username, account, token)
+            .await
+            .map_err(|err| match err {
+                AuthError::AccountNotFound => BtpStoreError::AccountNotFound(username.to_string()),
+                _ => BtpStoreError::Unauthorized(username.to_string()),
+            })
+    }
+
+    async fn get_btp_outgoing_accounts(&self) -> Result<Vec<Self::Account>, BtpStoreError> {
+        let _timer = self.timers.start("get_btp_outgoing_accounts");
+        let accounts = self
+            .sqlite_load_accounts("WHERE a.ilp_over_btp_url IS NOT NULL", Vec::new())
+            .await?;
+        Ok(accounts
+            .into_iter()
+            .map(|account| self.decrypt(account))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl HttpStore for SqliteStore {
+    type Account = Account;
+
+    /// Checks if the stored token for the provided account id matches the
+    /// provided token, and if so, returns the account associated with that token
+    async fn get_account_from_http_auth(
+        &self,
+        username: &Username,
+        token: &str,
+    ) -> Result<Self::Account, HttpStoreError> {
+        let _timer = self.timers.start("get_account_from_http_auth");
+        let account = self
+            .sqlite_get_account_by_username(username)
+            .await?
+            .map(|account| self.decrypt(account));
+        self.verified_tokens
+            .authenticate(AuthProtocol::Http, username, account, token)
+            .await
+            .map_err(|err| match err {
+                AuthError::AccountNotFound => HttpStoreError::AccountNotFound(username.to_string()),
+                _ => HttpStoreError::Unauthorized(username.to_string()),
+            })
+    }
+}
+
+impl RouterStore for SqliteStore {
+    fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
+        self.routes.read().clone()
+    }
+}
+
+#[async_trait]
+impl NodeStore for SqliteStore {
+    type Account = Account;
+
+    async fn insert_account(
+        &self,
+        account: AccountDetails,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let _timer = self.timers.start("insert_account");
+        let id = Uuid::new_v4();
+        let account = Account::try_from(id, account, self.get_ilp_address())
+            .map_err(NodeStoreError::InvalidAccount)?;
+        debug!(
+            "Generated account id for {}: {}",
+            account.username, account.id
+        );
+        let encrypted = account
+            .clone()
+            .encrypt_tokens(&self.encryption_key.expose_secret().0);
+
+        self.sqlite_insert_account(encrypted).await?;
+        Ok(account)
+    }
+
+    async fn insert_accounts(
+        &self,
+        accounts: Vec<AccountDetails>,
+    ) -> Result<Vec<Self::Account>, NodeStoreError> {
+        let _timer = self.timers.start("insert_accounts");
+        let mut errors = Vec::new();
+        let mut valid = Vec::with_capacity(accounts.len());
+        for (index, details) in accounts.into_iter().enumerate() {
+            match Account::try_from(Uuid::new_v4(), details, self.get_ilp_address()) {
+                Ok(account) => valid.push((index, account)),
+                Err(err) => errors.push((index, NodeStoreError::InvalidAccount(err))),
+            }
+        }
+        let encrypted: Vec<AccountWithEncryptedTokens> = valid
+            .iter()
+            .map(|(_, account)| {
+                account
+                    .clone()
+                    .encrypt_tokens(&self.encryption_key.expose_secret().0)
+            })
+            .collect();
+
+        // The accounts are written in a single transaction, which is only committed if all
+        // of them are valid. The accounts written before count towards the unique values, so
+        // the duplicates within the batch are reported too.
+        let has_errors = !errors.is_empty();
+        let indices: Vec<usize> = valid.iter().map(|(index, _)| *index).collect();
+        let conflicts = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                let mut conflicts = Vec::new();
+                for (index, encrypted) in indices.into_iter().zip(encrypted.iter()) {
+                    let account = &encrypted.account;
+                    let exists: bool = tx.query_row(
+                        "SELECT EXISTS(SELECT 1 FROM accounts WHERE username = ?1)",
+                        params![account.username.to_string()],
+                        |row| row.get(0),
+                    )?;
+                    if exists {
+                        conflicts.push((
+                            index,
+                            NodeStoreError::AccountExists(account.username.to_string()),
+                        ));
+                        continue;
+                    }
+
+                    write_account(&tx, encrypted)?;
+                    tx.execute(
+                        "INSERT OR REPLACE INTO routes (prefix, account_id) VALUES (?1, ?2)",
+                        params![account.ilp_address.to_string(), account.id.to_string()],
+                    )?;
+                }
+                if conflicts.is_empty() && !has_errors {
+                    tx.commit()?;
+                }
+                Ok(conflicts)
+            })
+            .await?;
+
+        if !errors.is_empty() || !conflicts.is_empty() {
+            errors.extend(conflicts);
+            errors.sort_by_key(|(index, _)| *index);
+            warn!(
+                "Cannot insert the batch of accounts, {} of them are invalid",
+                errors.len()
+            );
+            return Err(NodeStoreError::InvalidAccounts(errors));
+        }
+
+        self.update_routes().await?;
+        debug!("Inserted a batch of {} accounts", valid.len());
+        Ok(valid.into_iter().map(|(_, account)| account).collect())
+    }
+
+    async fn delete_account(&self, id: Uuid) -> Result<Account, NodeStoreError> {
+        let _timer = self.timers.start("delete_account");
+        let account = self.sqlite_delete_account(id).await?;
+        Ok(self.decrypt(account))
+    }
+
+    async fn update_account(
+        &self,
+        id: Uuid,
+        account: AccountDetails,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let _timer = self.timers.start("update_account");
+        let account = Account::try_from(id, account, self.get_ilp_address())
+            .map_err(NodeStoreError::InvalidAccount)?;
+        let encrypted = account
+            .clone()
+            .encrypt_tokens(&self.encryption_key.expose_secret().0);
+
+        self.sqlite_update_account(encrypted).await?;
+        Ok(account)
+    }
+
+    async fn modify_account_settings(
+        &self,
+        id: Uuid,
+        settings: AccountSettings,
+    ) -> Result<Self::Account, NodeStoreError> {
+        let _timer = self.timers.start("modify_account_settings");
+        if let Some(settle_to) = settings.settle_to {
+            if settle_to > std::i64::MAX as u64 {
+                // SQLite integers are signed 64 bit values
+                return Err(NodeStoreError::InvalidAccount(
+                    CreateAccountError::ParamTooLarge("settle_to".to_owned()),
+                ));
+            }
+        }
+
+        let mut encrypted = self.sqlite_get_account(id).await?;
+        let account = &mut encrypted.account;
+        if let Some(ref endpoint) = settings.ilp_over_btp_url {
+            account.ilp_over_btp_url = Some(Url::parse(endpoint).map_err(|err| {
+                NodeStoreError::InvalidAccount(CreateAccountError::InvalidBtpUrl(err))
+            })?);
+        }
+        if let Some(ref endpoint) = settings.ilp_over_http_url {
+            account.ilp_over_http_url = Some(Url::parse(endpoint).map_err(|err| {
+                NodeStoreError::InvalidAccount(CreateAccountError::InvalidHttpUrl(err))
+            })?);
+        }
+        if let Some(ref token) = settings.ilp_over_btp_outgoing_token {
+            account.ilp_over_btp_outgoing_token = Some(SecretBytesMut::from(BytesMut::from(
+                &self.encrypt(token)[..],
+            )));
+        }
+        if let Some(ref token) = settings.ilp_over_http_outgoing_token {
+            account.ilp_over_http_outgoing_token = Some(SecretBytesMut::from(BytesMut::from(
+                &self.encrypt(token)[..],
+            )));
+        }
+        if let Some(ref token) = settings.ilp_over_btp_incoming_token {
+            account.ilp_over_btp_incoming_token = Some(SecretBytesMut::new(
+                hash_token(token.expose_secret().as_bytes()).as_str(),
+            ));
+        }
+        if let Some(ref token) = settings.ilp_over_http_incoming_token {
+            account.ilp_over_http_incoming_token = Some(SecretBytesMut::new(
+                hash_token(token.expose_secret().as_bytes()).as_str(),
+            ));
+        }
+        if let Some(settle_threshold) = settings.settle_threshold {
+            account.settle_threshold = Some(settle_threshold);
+        }
+        if let Some(settle_to) = settings.settle_to {
+            account.settle_to = Some(settle_to as i64);
+        }
+        if let Some(settle_every) = settings.settle_every {
+            account.settle_every = Some(settle_every);
+        }
+
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            write_account(&tx, &encrypted)?;
+            tx.commit()
+        })
+        .await?;
+
+        // return the updated account
+        let account = self.sqlite_get_account(id).await?;
+        Ok(self.decrypt(account))
+    }
+
+    async fn get_all_accounts(&self) -> Result<Vec<Self::Account>, NodeStoreError> {
+        let _timer = self.timers.start("get_all_accounts");
+        let accounts = self.sqlite_load_accounts("", Vec::new()).await?;
+        Ok(accounts
+            .into_iter()
+            .map(|account| self.decrypt(account))
+            .collect())
+    }
+
+    async fn list_accounts(
+        &self,
+        after: Option<Uuid>,
+        limit: usize,
+        filter: AccountFilter,
+    ) -> Result<AccountPage<Self::Account>, NodeStoreError> {
+        let _timer = self.timers.start("list_accounts");
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        if let Some(after) = after {
+            values.push(Value::Text(after.to_string()));
+            conditions.push(format!("a.id > ?{}", values.len()));
+        }
+        if let Some(asset_code) = filter.asset_code {
+            values.push(Value::Text(asset_code));
+            conditions.push(format!("a.asset_code = ?{}", values.len()));
+        }
+        if let Some(routing_relation) = filter.routing_relation {
+            values.push(Value::Text(routing_relation.as_ref().to_string()));
+            conditions.push(format!("a.routing_relation = ?{}", values.len()));
+        }
+        if let Some(prefix) = filter.ilp_address_prefix {
+            values.push(Value::Text(prefix));
+            conditions.push(format!(
+                "substr(a.ilp_address, 1, length(?{0})) = ?{0}",
+                values.len()
+            ));
+        }
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        // Load one more than a full page to know whether there is a next page
+        let fetch = limit.saturating_add(1).min(i64::MAX as usize) as i64;
+        values.push(Value::Integer(fetch));
+        let mut accounts = self
+            .sqlite_load_accounts(
+                &format!("{} ORDER BY a.id LIMIT ?{}", where_clause, values.len()),
+                values,
+            )
+            .await?;
+
+        let next = if accounts.len() > limit {
+            accounts.truncate(limit);
+            accounts.last().map(|account| account.account.id)
+        } else {
+            None
+        };
+        Ok(AccountPage {
+            accounts: accounts
+                .into_iter()
+                .map(|account| self.decrypt(account))
+                .collect(),
+            next,
+        })
+    }
+
+    async fn set_static_routes<R>(&self, routes: R) -> Result<(), NodeStoreError>
+    where
+        R: IntoIterator<Item = (String, Uuid)> + Send + 'async_trait,
+    {
+        let _timer = self.timers.start("set_static_routes");
+        let routes: Vec<(String, Uuid)> = routes.into_iter().collect();
+        let all_exist = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                for (_, account_id) in routes.iter() {
+                    if !account_exists(&tx, *account_id)? {
+                        return Ok(false);
+                    }
+                }
+
+                tx.execute("DELETE FROM static_routes", [])?;
+                for (prefix, account_id) in routes.iter() {
+                    tx.execute(
+                        "INSERT INTO static_routes (prefix, account_id) VALUES (?1, ?2)",
+                        params![prefix, account_id.to_string()],
+                    )?;
+                }
+                tx.commit()?;
+                Ok(true)
+            })
+            .await?;
+
+        if !all_exist {
+            error!("Error setting static routes because not all of the given accounts exist");
+            return Err(NodeStoreError::MissingAccounts);
+        }
+
+        self.update_routes().await?;
+        Ok(())
+    }
+
+    async fn set_static_route(
+        &self,
+        prefix: String,
+        account_id: Uuid,
+    ) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("set_static_route");
+        let route_prefix = prefix.clone();
+        let exists = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                if !account_exists(&tx, account_id)? {
+                    return Ok(false);
+                }
+                tx.execute(
+                    "INSERT OR REPLACE INTO static_routes (prefix, account_id) VALUES (?1, ?2)",
+                    params![route_prefix, account_id.to_string()],
+                )?;
+                tx.commit()?;
+                Ok(true)
+            })
+            .await?;
+
+        if !exists {
+            error!(
+                "Cannot set static route for prefix: {} because account {} does not exist",
+                prefix, account_id
+            );
+            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+        }
+
+        self.update_routes().await?;
+        Ok(())
+    }
+
+    async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("set_default_route");
+        let exists = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                if !account_exists(&tx, account_id)? {
+                    return Ok(false);
+                }
+                set_setting(&tx, DEFAULT_ROUTE_KEY, &account_id.to_string())?;
+                tx.commit()?;
+                Ok(true)
+            })
+            .await?;
+
+        if !exists {
+            error!(
+                "Cannot set default route because account {} does not exist",
+                account_id
+            );
+            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+        }
+
+        debug!("Set default route to account id: {}", account_id);
+        self.update_routes().await?;
+        Ok(())
+    }
+
+    async fn set_settlement_engines(
+        &self,
+        asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
+    ) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("set_settlement_engines");
+        let asset_to_url_map: Vec<(String, String)> = asset_to_url_map
+            .into_iter()
+            .map(|(asset_code, url)| (asset_code, url.to_string()))
+            .collect();
+        debug!("Setting settlement engines to {:?}", asset_to_url_map);
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            for (asset_code, url) in asset_to_url_map.iter() {
+                tx.execute(
+                    "INSERT OR REPLACE INTO settlement_engines (asset_code, url) VALUES (?1, ?2)",
+                    params![asset_code, url],
+                )?;
+            }
+            tx.commit()
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn get_asset_settlement_engine(
+        &self,
+        asset_code: &str,
+    ) -> Result<Option<Url>, NodeStoreError> {
+        let _timer = self.timers.start("get_asset_settlement_engine");
+        let asset_code = asset_code.to_string();
+        let url: Option<String> = self
+            .with_connection(move |conn| {
+                conn.query_row(
+                    "SELECT url FROM settlement_engines WHERE asset_code = ?1",
+                    params![asset_code],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+            .await?;
+        if let Some(url) = url {
+            match Url::parse(url.as_str()) {
+                Ok(url) => Ok(Some(url)),
+                Err(err) => {
+                    error!(
+                        "Settlement engine URL loaded from SQLite was not a valid URL: {:?}",
+                        err
+                    );
+                    Err(NodeStoreError::InvalidEngineUrl(err.to_string()))
+                }
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn export_node(&self) -> Result<NodeExport, NodeStoreError> {
+        let _timer = self.timers.start("export_node");
+        let accounts = self.get_all_accounts().await?;
+        let (balances, static_routes, default_route, settlement_engines, ilp_address) = self
+            .with_connection(move |conn| {
+                let mut statement =
+                    conn.prepare("SELECT id, balance, prepaid_amount FROM accounts")?;
+                let balances = statement
+                    .query_map([], |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            (row.get::<_, i64>(1)?, row.get::<_, i64>(2)?),
+                        ))
+                    })?
+                    .collect::<rusqlite::Result<HashMap<_, _>>>()?;
+                let mut statement = conn.prepare("SELECT prefix, account_id FROM static_routes")?;
+                let static_routes = statement
+                    .query_map([], |row| {
+                        let account_id: String = row.get(1)?;
+                        let account_id = Uuid::from_str(&account_id)
+                            .map_err(|_| invalid_column(1, "Invalid account id"))?;
+                        Ok((row.get::<_, String>(0)?, account_id))
+                    })?
+                    .collect::<rusqlite::Result<HashMap<_, _>>>()?;
+                let mut statement =
+                    conn.prepare("SELECT asset_code, url FROM settlement_engines")?;
+                let settlement_engines = statement
+                    .query_map([], |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok((
+                    balances,
+                    static_routes,
+                    get_setting(conn, DEFAULT_ROUTE_KEY)?,
+                    settlement_engines,
+                    get_setting(conn, PARENT_ILP_KEY)?,
+                ))
+            })
+            .await?;
+
+        let accounts = accounts
+            .iter()
+            .map(|account| {
+                let (balance, prepaid_amount) = balances
+                    .get(&account.id.to_string())
+                    .cloned()
+                    .unwrap_or_default();
+                ExportedAccount {
+                    id: account.id,
+                    details: account.to_details(),
+                    balance,
+                    prepaid_amount,
+                }
+            })
+            .collect();
+        let settlement_engines = settlement_engines
+            .into_iter()
+            .map(|(asset_code, url)| {
+                Url::parse(&url)
+                    .map(|url| (asset_code, url))
+                    .map_err(|err| NodeStoreError::InvalidEngineUrl(err.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(NodeExport {
+            ilp_address: ilp_address.and_then(|address| Address::from_str(&address).ok()),
+            accounts,
+            static_routes,
+            default_route: default_route.and_then(|id| Uuid::from_str(&id).ok()),
+            settlement_engines,
+        })
+    }
+
+    async fn import_node(&self, export: NodeExport) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("import_node");
+        let mut accounts = Vec::with_capacity(export.accounts.len());
+        for exported in export.accounts.iter() {
+            let account = Account::try_from(
+                exported.id,
+                exported.details.clone(),
+                self.get_ilp_address(),
+            )
+            .map_err(NodeStoreError::InvalidAccount)?;
+            accounts.push(account.encrypt_tokens(&self.encryption_key.expose_secret().0));
+        }
+        let ilp_address = export.ilp_address.clone();
+        let num_accounts = export.accounts.len();
+
+        // Everything but the node's address is imported in a single transaction,
+        // so that a failed import leaves the store empty
+        let result = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                let has_accounts: bool =
+                    tx.query_row("SELECT EXISTS(SELECT 1 FROM accounts)", [], |row| {
+                        row.get(0)
+                    })?;
+                if has_accounts {
+                    return Ok(Err(NodeStoreError::NotEmpty));
+                }
+
+                for (encrypted, exported) in accounts.iter().zip(export.accounts.iter()) {
+                    let account = &encrypted.account;
+                    write_account(&tx, encrypted)?;
+                    tx.execute(
+                        "INSERT OR REPLACE INTO routes (prefix, account_id) VALUES (?1, ?2)",
+                        params![account.ilp_address.to_string(), account.id.to_string()],
+                    )?;
+                    set_balance(&tx, account.id, exported.balance, exported.prepaid_amount)?;
+                }
+
+                for (prefix, account_id) in export.static_routes.iter() {
+                    if !account_exists(&tx, *account_id)? {
+                        return Ok(Err(NodeStoreError::MissingAccounts));
+                    }
+                    tx.execute(
+                        "INSERT INTO static_routes (prefix, account_id) VALUES (?1, ?2)",
+                        params![prefix, account_id.to_string()],
+                    )?;
+                }
+                if let Some(account_id) = export.default_route {
+                    if !account_exists(&tx, account_id)? {
+                        return Ok(Err(NodeStoreError::AccountNotFound(account_id.to_string())));
+                    }
+                    set_setting(&tx, DEFAULT_ROUTE_KEY, &account_id.to_string())?;
+                }
+                for (asset_code, url) in export.settlement_engines.iter() {
+                    tx.execute(
+                    "INSERT OR REPLACE INTO settlement_engines (asset_code, url) VALUES (?1, ?2)",
+                    params![asset_code, url.as_str()],
+                )?;
+                }
+                tx.commit()?;
+                Ok(Ok(()))
+            })
+            .await?;
+        if let Err(err) = result {
+            warn!("Error importing accounts: {}", err);
+            return Err(err);
+        }
+        self.update_routes().await?;
+
+        if let Some(ilp_address) = ilp_address {
+            self.set_ilp_address(ilp_address)
+                .await
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+        }
+        debug!("Imported {} accounts", num_accounts);
+        Ok(())
+    }
+
+    async fn get_secret_generation(&self) -> Result<SecretGeneration, NodeStoreError> {
+        let _timer = self.timers.start("get_secret_generation");
+        let serialized = self
+            .with_connection(move |conn| get_setting(conn, SECRET_GENERATION_KEY))
+            .await?;
+        match serialized {
+            Some(serialized) => serde_json::from_str(&serialized)
+                .map_err(|err| NodeStoreError::Other(Box::new(err))),
+            None => Ok(SecretGeneration::default()),
+        }
+    }
+
+    async fn set_secret_generation(
+        &self,
+        generation: SecretGeneration,
+    ) -> Result<(), NodeStoreError> {
+        let _timer = self.timers.start("set_secret_generation");
+        let serialized = serde_json::to_string(&generation)
+            .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            set_setting(&tx, SECRET_GENERATION_KEY, &serialized)?;
+            tx.commit()
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn claim_leadership(
+        &self,
+        node_id: &str,
+        lease: Duration,
+    ) -> Result<bool, NodeStoreError> {
+        let _timer = self.timers.start("claim_leadership");
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let node_id = node_id.to_string();
+        let claimed = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                let held_by_another = get_setting(&tx, LEADER_KEY)?.map_or(false, |leader| {
+                    let mut parts = leader.splitn(2, ' ');
+                    let leader_id = parts.next().unwrap_or_default();
+                    let expires_at = parts.next().and_then(|expires_at| expires_at.parse().ok());
+                    leader_id != node_id
+                        && expires_at.map_or(false, |expires_at: u64| expires_at > now)
+                });
+                if held_by_another {
+                    return Ok(false);
+                }
+                let expires_at = now + lease.as_millis() as u64;
+                set_setting(&tx, LEADER_KEY, &format!("{} {}", node_id, expires_at))?;
+                tx.commit()?;
+                Ok(true)
+            })
+            .await?;
+        Ok(claimed)
+    }
+}
+
+#[async_trait]
+impl AddressStore for SqliteStore {
+    // Updates the ILP address of the store & iterates over all children and
+    // updates their ILP Address to match the new address.
+    async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+        let _timer = self.timers.start("set_ilp_address");
+        debug!("Setting ILP address to: {}", ilp_address);
+
+        // Set the ILP address we have in memory
+        (*self.ilp_address.write()) = ilp_address.clone();
+
+        let accounts = self.get_all_accounts().await?;
+        let first_segment = ilp_address
+            .segments()
+            .rev()
+            .next()
+            .expect("address did not have a first segment, this should be impossible")
+            .to_string();
+
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            set_setting(&tx, PARENT_ILP_KEY, &ilp_address.to_string())?;
+            for account in &accounts {
+                // Update the address and routes of all children and non-routing accounts.
+                if account.routing_relation() != RoutingRelation::Parent
+                    && account.routing_relation() != RoutingRelation::Peer
+                {
+                    // if the username of the account ends with the
+                    // node's address, we're already configured so no
+                    // need to append anything.
+                    let new_ilp_address = if first_segment == account.username().to_string() {
+                        ilp_address.clone()
+                    } else {
+                        ilp_address
+                            .with_suffix(account.username().as_bytes())
+                            .unwrap()
+                    };
+                    tx.execute(
+                        "DELETE FROM routes WHERE prefix = ?1",
+                        params![account.ilp_address.to_string()],
+                    )?;
+                    tx.execute(
+                        "UPDATE accounts SET ilp_address = ?2 WHERE id = ?1",
+                        params![account.id.to_string(), new_ilp_address.to_string()],
+                    )?;
+                    tx.execute(
+                        "INSERT OR REPLACE INTO routes (prefix, account_id) VALUES (?1, ?2)",
+                        params![new_ilp_address.to_string(), account.id.to_string()],
+                    )?;
+                }
+            }
+            tx.commit()
+        })
+        .await?;
+
+        self.update_routes().await?;
+        Ok(())
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        let _timer = self.timers.start("clear_ilp_address");
+        self.with_connection(move |conn| {
+            conn.execute(
+                "DELETE FROM node_settings WHERE key = ?1",
+                params![PARENT_ILP_KEY],
+            )
+        })
+        .await?;
+
+        // overwrite the ilp address with the default value
+        *(self.ilp_address.write()) = DEFAULT_ILP_ADDRESS.clone();
+        Ok(())
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        // read consumes the Arc<RwLock<T>> so we cannot return a reference
+        self.ilp_address.read().clone()
+    }
+}
+
+type RoutingTable<A> = HashMap<String, A>;
+
+#[async_trait]
+impl CcpRoutingStore for SqliteStore {
+    type Account = Account;
+
+    async fn get_accounts_to_send_routes_to(
+        &self,
+        ignore_accounts: Vec<Uuid>,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        let _timer = self.timers.start("get_accounts_to_send_routes_to");
+        let accounts = self
+            .sqlite_load_accounts(
+                "WHERE a.routing_relation IN (?1, ?2)",
+                vec![
+                    Value::Text(RoutingRelation::Child.as_ref().to_string()),
+                    Value::Text(RoutingRelation::Peer.as_ref().to_string()),
+                ],
+            )
+            .await?;
+        Ok(accounts
+            .into_iter()
+            .filter(|account| !ignore_accounts.contains(&account.account.id))
+            .map(|account| self.decrypt(account))
+            .collect())
+    }
+
+    async fn get_accounts_to_receive_routes_from(
+        &self,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        let _timer = self.timers.start("get_accounts_to_receive_routes_from");
+        let accounts = self
+            .sqlite_load_accounts(
+                "WHERE a.routing_relation IN (?1, ?2)",
+                vec![
+                    Value::Text(RoutingRelation::Parent.as_ref().to_string()),
+                    Value::Text(RoutingRelation::Peer.as_ref().to_string()),
+                ],
+            )
+            .await?;
+        Ok(accounts
+            .into_iter()
+            .map(|account| self.decrypt(account))
+            .collect())
+    }
+
+    async fn get_local_and_configured_routes(
+        &self,
+    ) -> Result<(RoutingTable<Account>, RoutingTable<Account>), CcpRoutingStoreError> {
+        let _timer = self.timers.start("get_local_and_configured_routes");
+        let static_routes: Vec<(String, String)> = self
+            .with_connection(move |conn| {
+                let mut statement = conn.prepare("SELECT prefix, account_id FROM static_routes")?;
+                let routes = statement
+                    .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(routes)
+            })
+            .await?;
+
+        let accounts = self.get_all_accounts().await?;
+
+        let local_table: HashMap<String, Account> = accounts
+            .iter()
+            .map(|account| (account.ilp_address.to_string(), account.clone()))
+            .collect();
+
+        let account_map: HashMap<String, &Account> = accounts
+            .iter()
+            .map(|account| (account.id.to_string(), account))
+            .collect();
+        let configured_table: HashMap<String, Account> = static_routes
+            .into_iter()
+            .filter_map(|(prefix, account_id)| {
+                if let Some(account) = account_map.get(&account_id) {
+                    Some((prefix, (*account).clone()))
+                } else {
+                    warn!(
+                        "No account for ID: {}, ignoring configured route for prefix: {}",
+                        account_id, prefix
+                    );
+                    None
+                }
+            })
+            .collect();
+
+        Ok((local_table, configured_table))
+    }
+
+    async fn set_routes(
+        &mut self,
+        routes: impl IntoIterator<Item = (String, Account)> + Send + 'async_trait,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let _timer = self.timers.start("set_routes");
+        let routes: Vec<(String, String)> = routes
+            .into_iter()
+            .map(|(prefix, account)| (prefix, account.id.to_string()))
+            .collect();
+        let num_routes = routes.len();
+
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM routes", [])?;
+            for (prefix, account_id) in routes.iter() {
+                tx.execute(
+                    "INSERT OR REPLACE INTO routes (prefix, account_id) VALUES (?1, ?2)",
+                    params![prefix, account_id],
+                )?;
+            }
+            tx.commit()
+        })
+        .await?;
+        trace!("Saved {} routes to SQLite", num_routes);
+
+        self.update_routes().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for SqliteStore {
+    type Account = Account;
+
+    /// Apply rate limits for number of packets per minute and amount of money per minute
+    ///
+    /// The limits are counted in memory over fixed one minute windows, so they are
+    /// not shared between multiple node processes
+    async fn apply_rate_limits(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        let _timer = self.timers.start("apply_rate_limits");
+        let now = Instant::now();
+        let mut limits = self.rate_limits.lock();
+
+        let packets_key = format!("limit:packets:{}", account.id);
+        let throughput_key = format!("limit:throughput:{}", account.id);
+
+        // Check both limits before charging either of them
+        let current = |limits: &HashMap<String, RateLimitWindow>, key: &str| match limits.get(key) {
+            Some(window) if now.duration_since(window.started_at) < RATE_LIMIT_WINDOW => {
+                window.used
+            }
+            _ => 0,
+        };
+        if let Some(limit) = account.packets_per_minute_limit {
+            if current(&limits, &packets_key) + 1 > u64::from(limit) {
+                return Err(RateLimitError::PacketLimitExceeded);
+            }
+        }
+        if let Some(limit) = account.amount_per_minute_limit {
+            if current(&limits, &throughput_key).saturating_add(prepare_amount) > limit {
+                return Err(RateLimitError::ThroughputLimitExceeded);
+            }
+        }
+
+        let mut charge = |key: String, amount: u64| {
+            let window = limits.entry(key).or_insert(RateLimitWindow {
+                started_at: now,
+                used: 0,
+            });
+            if now.duration_since(window.started_at) >= RATE_LIMIT_WINDOW {
+                *window = RateLimitWindow {
+                    started_at: now,
+                    used: 0,
+                };
+            }
+            window.used = window.used.saturating_add(amount);
+        };
+        if account.packets_per_minute_limit.is_some() {
+            charge(packets_key, 1);
+        }
+        if account.amount_per_minute_limit.is_some() {
+            charge(throughput_key, prepare_amount);
+        }
+        Ok(())
+    }
+
+    async fn refund_throughput_limit(
+        &self,
+        account: Account,
+        prepare_amount: u64,
+    ) -> Result<(), RateLimitError> {
+        let _timer = self.timers.start("refund_throughput_limit");
+        if account.amount_per_minute_limit.is_some() {
+            let throughput_key = format!("limit:throughput:{}", account.id);
+            if let Some(window) = self.rate_limits.lock().get_mut(&throughput_key) {
+                window.used = window.used.saturating_sub(prepare_amount);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VolumeLimitStore for SqliteStore {
+    type Account = Account;
+
+    async fn charge_daily_volume(
+        &self,
+        account: Account,
+        day: u64,
+        amount: u64,
+    ) -> Result<(), VolumeLimitError> {
+        let _timer = self.timers.start("charge_daily_volume");
+        let limit = match account.amount_per_day_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let account_id = account.id.to_string();
+        let charged = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                // The totals of the previous days are no longer needed
+                tx.execute(
+                    "DELETE FROM daily_volumes WHERE account_id = ?1 AND day < ?2",
+                    params![account_id, day.saturating_sub(1) as i64],
+                )?;
+                let volume: Option<String> = tx
+                    .query_row(
+                        "SELECT amount FROM daily_volumes WHERE account_id = ?1 AND day = ?2",
+                        params![account_id, day as i64],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                let volume = volume
+                    .map(|volume| u64::from_str(&volume))
+                    .transpose()
+                    .map_err(|_| invalid_column(0, "Invalid daily volume"))?
+                    .unwrap_or(0);
+                let total = volume.saturating_add(amount);
+                if total > limit {
+                    return Ok(false);
+                }
+                tx.execute(
+                    "INSERT OR REPLACE INTO daily_volumes (account_id, day, amount) VALUES (?1, ?2, ?3)",
+                    params![account_id, day as i64, total.to_string()],
+                )?;
+                tx.commit()?;
+                Ok(true)
+            }).await
+            .map_err(|err| {
+                error!("Error charging daily volume: {:?}", err);
+                VolumeLimitError::StoreError
+            })?;
+        if charged {
+            Ok(())
+        } else {
+            Err(VolumeLimitError::DailyLimitExceeded)
+        }
+    }
+
+    async fn refund_daily_volume(
+        &self,
+        account: Account,
+        day: u64,
+        amount: u64,
+    ) -> Result<(), VolumeLimitError> {
+        let _timer = self.timers.start("refund_daily_volume");
+        let account_id = account.id.to_string();
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            let volume: Option<String> = tx
+                .query_row(
+                    "SELECT amount FROM daily_volumes WHERE account_id = ?1 AND day = ?2",
+                    params![account_id, day as i64],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(volume) = volume {
+                let volume = u64::from_str(&volume)
+                    .map_err(|_| invalid_column(0, "Invalid daily volume"))?;
+                tx.execute(
+                    "UPDATE daily_volumes SET amount = ?3 WHERE account_id = ?1 AND day = ?2",
+                    params![
+                        account_id,
+                        day as i64,
+                        volume.saturating_sub(amount).to_string()
+                    ],
+                )?;
+            }
+            tx.commit()
+        })
+        .await
+        .map_err(|err| {
+            error!("Error refunding daily volume: {:?}", err);
+            VolumeLimitError::StoreError
+        })
+    }
+}
+
+/// The connection is local, so the provided methods making one call per limit are kept
+impl AdmissionStore for SqliteStore {}
+
+#[async_trait]
+impl IdempotentStore for SqliteStore {
+    async fn load_idempotent_data(
+        &self,
+        idempotency_key: String,
+    ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
+        let _timer = self.timers.start("load_idempotent_data");
+        let key = idempotency_key.clone();
+        let ret: Option<(u16, Vec<u8>, Vec<u8>)> = self.with_connection(move |conn| {
+            conn.query_row(
+                "SELECT status_code, data, input_hash FROM idempotency_keys WHERE key = ?1 AND expires_at > ?2",
+                params![key, now_secs()],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()
+        }).await?;
+
+        if let Some((status_code, data, input_hash_slice)) = ret {
+            trace!("Loaded idempotency key {:?}", idempotency_key);
+            let mut input_hash: [u8; 32] = Default::default();
+            input_hash.copy_from_slice(input_hash_slice.as_ref());
+            Ok(Some(IdempotentData::new(
+                StatusCode::from_u16(status_code).unwrap(),
+                Bytes::from(data),
+                input_hash,
+            )))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn save_idempotent_data(
+        &self,
+        idempotency_key: String,
+        input_hash: [u8; 32],
+        status_code: StatusCode,
+        data: Bytes,
+    ) -> Result<(), IdempotentStoreError> {
+        let _timer = self.timers.start("save_idempotent_data");
+        let (key, saved) = (idempotency_key.clone(), data.clone());
+        self.with_connection(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO idempotency_keys (key, status_code, data, input_hash, expires_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    key,
+                    status_code.as_u16(),
+                    saved.as_ref(),
+                    &input_hash[..],
+                    now_secs() + IDEMPOTENCY_KEY_TTL
+                ],
+            )
+        }).await?;
+
+        trace!(
+            "Cached {:?}: {:?}, {:?}",
+            idempotency_key,
+            status_code,
+            data,
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SettlementStore for SqliteStore {
+    type Account = Account;
+
+    async fn update_balance_for_incoming_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: Option<String>,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("update_balance_for_incoming_settlement");
+        let idempotency_key = idempotency_key.unwrap();
+        let balance: i64 = self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            let (balance, prepaid_amount): (i64, i64) = tx.query_row(
+                "SELECT balance, prepaid_amount FROM accounts WHERE id = ?1",
+                params![account_id.to_string()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+
+            // If idempotency key has been used, then do not perform any operations
+            let used: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM incoming_settlements WHERE key = ?1 AND expires_at > ?2)",
+                params![idempotency_key, now_secs()],
+                |row| row.get(0),
+            )?;
+            if used {
+                return Ok(balance + prepaid_amount);
+            }
+            tx.execute(
+                "INSERT OR REPLACE INTO incoming_settlements (key, expires_at) VALUES (?1, ?2)",
+                params![idempotency_key, now_secs() + IDEMPOTENCY_KEY_TTL],
+            )?;
+
+            // Credit the incoming settlement to the balance and/or prepaid amount,
+            // depending on whether that account currently owes money or not
+            let amount = amount as i64;
+            let (balance, prepaid_amount) = if balance >= 0 {
+                (balance, prepaid_amount + amount)
+            } else if balance.abs() >= amount {
+                (balance + amount, prepaid_amount)
+            } else {
+                (0, prepaid_amount + amount + balance)
+            };
+            set_balance(&tx, account_id, balance, prepaid_amount)?;
+            log_balance_change(
+                &tx,
+                account_id,
+                BalanceChangeReason::IncomingSettlement,
+                amount,
+                balance + prepaid_amount,
+                None,
+            )?;
+            tx.commit()?;
+            Ok(balance + prepaid_amount)
+        }).await?;
+        trace!(
+            "Processed incoming settlement from account: {} for amount: {}. Balance is now: {}",
+            account_id,
+            amount,
+            balance
+        );
+        Ok(())
+    }
+
+    async fn refund_settlement(
+        &self,
+        account_id: Uuid,
+        settle_amount: u64,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("refund_settlement");
+        trace!(
+            "Refunding settlement for account: {} of amount: {}",
+            account_id,
+            settle_amount
+        );
+        let balance: i64 = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                tx.execute(
+                    "UPDATE accounts SET balance = balance + ?2 WHERE id = ?1",
+                    params![account_id.to_string(), settle_amount as i64],
+                )?;
+                let (balance, prepaid_amount): (i64, i64) = tx.query_row(
+                    "SELECT balance, prepaid_amount FROM accounts WHERE id = ?1",
+                    params![account_id.to_string()],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )?;
+                log_balance_change(
+                    &tx,
+                    account_id,
+                    BalanceChangeReason::SettlementRefund,
+                    settle_amount as i64,
+                    balance + prepaid_amount,
+                    None,
+                )?;
+                tx.commit()?;
+                Ok(balance)
+            })
+            .await?;
+
+        trace!(
+            "Refunded settlement for account: {} of amount: {}. Balance is now: {}",
+            account_id,
+            settle_amount,
+            balance
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutgoingSettlementStore for SqliteStore {
+    async fn queue_outgoing_settlement(
+        &self,
+        settlement: OutgoingSettlement,
+    ) -> Result<bool, SettlementStoreError> {
+        let _timer = self.timers.start("queue_outgoing_settlement");
+        let in_flight = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                // The settlements of an account are sent one at a time, so the amount
+                // waits until the engine answered the settlement in flight
+                if has_settlement_in_flight(&tx, settlement.account_id)? {
+                    queue_settlement_amount(&tx, settlement.account_id, settlement.amount)?;
+                    tx.commit()?;
+                    return Ok(false);
+                }
+                save_outgoing_settlement(&tx, &settlement)?;
+                tx.commit()?;
+                Ok(true)
+            })
+            .await?;
+        Ok(in_flight)
+    }
+
+    async fn dequeue_outgoing_settlement(
+        &self,
+        account_id: Uuid,
+        idempotency_key: String,
+        next_attempt_at: u64,
+    ) -> Result<Option<OutgoingSettlement>, SettlementStoreError> {
+        let _timer = self.timers.start("dequeue_outgoing_settlement");
+        let settlement = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                if has_settlement_in_flight(&tx, account_id)? {
+                    return Ok(None);
+                }
+                let amount: Option<i64> = tx
+                    .query_row(
+                        "SELECT amount FROM queued_settlements WHERE account_id = ?1",
+                        params![account_id.to_string()],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                let amount = match amount {
+                    Some(amount) => amount,
+                    None => return Ok(None),
+                };
+                tx.execute(
+                    "DELETE FROM queued_settlements WHERE account_id = ?1",
+                    params![account_id.to_string()],
+                )?;
+                let settlement = OutgoingSettlement {
+                    idempotency_key,
+                    account_id,
+                    amount: amount as u64,
+                    status: OutgoingSettlementStatus::Pending,
+                    attempts: 0,
+                    next_attempt_at,
+                };
+                save_outgoing_settlement(&tx, &settlement)?;
+                tx.commit()?;
+                Ok(Some(settlement))
+            })
+            .await?;
+        Ok(settlement)
+    }
+
+    async fn load_queued_settlement_accounts(&self) -> Result<Vec<Uuid>, SettlementStoreError> {
+        let _timer = self.timers.start("load_queued_settlement_accounts");
+        let account_ids = self
+            .with_connection(move |conn| {
+                conn.prepare("SELECT account_id FROM queued_settlements")?
+                    .query_map(params![], |row| {
+                        let account_id: String = row.get(0)?;
+                        Uuid::from_str(&account_id)
+                            .map_err(|_| invalid_column(0, "Invalid account id"))
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .await?;
+        Ok(account_ids)
+    }
+
+    async fn save_outgoing_settlement(
+        &self,
+        settlement: OutgoingSettlement,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("save_outgoing_settlement");
+        self.with_connection(move |conn| save_outgoing_settlement(conn, &settlement))
+            .await?;
+        Ok(())
+    }
+
+    async fn load_outgoing_settlements(
+        &self,
+    ) -> Result<Vec<OutgoingSettlement>, SettlementStoreError> {
+        let _timer = self.timers.start("load_outgoing_settlements");
+        let settlements = self
+            .with_connection(move |conn| {
+                conn.prepare(
+                    "SELECT idempotency_key, account_id, amount, status, attempts, next_attempt_at
+                    FROM outgoing_settlements",
+                )?
+                .query_map(params![], outgoing_settlement_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .await?;
+        Ok(settlements)
+    }
+
+    async fn remove_outgoing_settlement(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("remove_outgoing_settlement");
+        let idempotency_key = idempotency_key.to_string();
+        self.with_connection(move |conn| {
+            conn.execute(
+                "DELETE FROM outgoing_settlements WHERE idempotency_key = ?1",
+                params![idempotency_key],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn refund_outgoing_settlement(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<(), SettlementStoreError> {
+        let _timer = self.timers.start("refund_outgoing_settlement");
+        let key = idempotency_key.to_string();
+        let refunded: Option<(Uuid, u64)> = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                let settlement = tx
+                .query_row(
+                    "SELECT idempotency_key, account_id, amount, status, attempts, next_attempt_at
+                        FROM outgoing_settlements WHERE idempotency_key = ?1",
+                    params![key],
+                    outgoing_settlement_from_row,
+                )
+                .optional()?;
+                // Already refunded or accepted
+                let settlement = match settlement {
+                    Some(settlement) => settlement,
+                    None => return Ok(None),
+                };
+                tx.execute(
+                    "DELETE FROM outgoing_settlements WHERE idempotency_key = ?1",
+                    params![key],
+                )?;
+                // There is nothing to refund if the account was deleted in the meantime
+                if !account_exists(&tx, settlement.account_id)? {
+                    tx.commit()?;
+                    return Ok(None);
+                }
+
+                tx.execute(
+                    "UPDATE accounts SET balance = balance + ?2 WHERE id = ?1",
+                    params![settlement.account_id.to_string(), settlement.amount as i64],
+                )?;
+                let (balance, prepaid_amount): (i64, i64) = tx.query_row(
+                    "SELECT balance, prepaid_amount FROM accounts WHERE id = ?1",
+                    params![settlement.account_id.to_string()],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )?;
+                log_balance_change(
+                    &tx,
+                    settlement.account_id,
+                    BalanceChangeReason::SettlementRefund,
+                    settlement.amount as i64,
+                    balance + prepaid_amount,
+                    None,
+                )?;
+                tx.commit()?;
+                Ok(Some((settlement.account_id, settlement.amount)))
+            })
+            .await?;
+
+        if let Some((account_id, amount)) = refunded {
+            trace!(
+                "Refunded settlement {} for account: {} of amount: {}",
+                idempotency_key,
+                account_id,
+                amount
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LeftoversStore for SqliteStore {
+    type AccountId = Uuid;
+    type AssetType = BigUint;
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
+        let _timer = self.timers.start("get_uncredited_settlement_amount");
+        // get the amounts and instantly delete them
+        let amounts: Vec<(String, u8)> = self
+            .with_connection(move |conn| {
+                let tx = conn.transaction()?;
+                let amounts = tx
+                    .prepare("SELECT amount, scale FROM uncredited_amounts WHERE account_id = ?1")?
+                    .query_map(params![account_id.to_string()], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                tx.execute(
+                    "DELETE FROM uncredited_amounts WHERE account_id = ?1",
+                    params![account_id.to_string()],
+                )?;
+                tx.commit()?;
+                Ok(amounts)
+            })
+            .await?;
+
+        // We must scale them to the largest scale, and then add them together
+        let max_scale = amounts.iter().map(|(_, scale)| *scale).max().unwrap_or(0);
+        let mut sum = BigUint::from(0u32);
+        for (num, scale) in amounts {
+            let num =
+                BigUint::from_str(&num).map_err(|err| LeftoversStoreError::Other(Box::new(err)))?;
+            sum += num
+                .normalize_scale(ConvertDetails {
+                    from: scale,
+                    to: max_scale,
+                })
+                .unwrap();
+        }
+        Ok((sum, max_scale))
+    }
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+        uncredited_settlement_amount: (Self::AssetType, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        let _timer = self.timers.start("save_uncredited_settlement_amount");
+        trace!(
+            "Saving uncredited_settlement_amount {:?} {:?}",
+            account_id,
+            uncredited_settlement_amount
+        );
+        // We store these amounts as strings because SQLite cannot do
+        // BigNumber arithmetic. When loading the amounts, we convert
+        // them to the appropriate data type and sum them up.
+        self.with_connection(move |conn| {
+            conn.execute(
+                "INSERT INTO uncredited_amounts (account_id, amount, scale) VALUES (?1, ?2, ?3)",
+                params![
+                    account_id.to_string(),
+                    uncredited_settlement_amount.0.to_string(),
+                    uncredited_settlement_amount.1
+                ],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+        local_scale: u8,
+    ) -> Result<Self::AssetType, LeftoversStoreError> {
+        let _timer = self.timers.start("load_uncredited_settlement_amount");
+        trace!("Loading uncredited_settlement_amount {:?}", account_id);
+        let amount = self.get_uncredited_settlement_amount(account_id).await?;
+        // scale the amount from the max scale to the local scale, and then
+        // save any potential leftovers to the store
+        let (scaled_amount, precision_loss) =
+            scale_with_precision_loss(amount.0, local_scale, amount.1);
+
+        if precision_loss > BigUint::from(0u32) {
+            self.save_uncredited_settlement_amount(
+                account_id,
+                (precision_loss, std::cmp::max(local_scale, amount.1)),
+            )
+            .await?;
+        }
+
+        Ok(scaled_amount)
+    }
+
+    async fn clear_uncredited_settlement_amount(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(), LeftoversStoreError> {
+        let _timer = self.timers.start("clear_uncredited_settlement_amount");
+        trace!("Clearing uncredited_settlement_amount {:?}", account_id);
+        self.with_connection(move |conn| {
+            conn.execute(
+                "DELETE FROM uncredited_amounts WHERE account_id = ?1",
+                params![account_id.to_string()],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Backs the database up to a temporary file which is then moved into place, so that
+/// a crash while writing never leaves a corrupt snapshot behind.
+///
+/// The database is first copied to memory in one step while holding the lock of the
+/// connection, which gives a consistent copy without waiting for the disk. The copy
+/// is then written to the file in steps, while the store keeps being used.
+fn write_snapshot(connection: &Mutex<Connection>, snapshot_path: &Path) -> Result<(), ()> {
+    let mut copy = Connection::open_in_memory()
+        .map_err(|err| error!("Error opening the snapshot copy: {:?}", err))?;
+    {
+        let conn = connection.lock();
+        Backup::new(&conn, &mut copy)
+            .and_then(|backup| backup.run_to_completion(-1, Duration::from_millis(0), None))
+            .map_err(|err| error!("Error copying the database for a snapshot: {:?}", err))?;
+    }
+
+    // Append to the file name rather than replacing its extension, which could name the
+    // snapshot itself or an unrelated file next to it
+    let mut tmp_path = snapshot_path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let mut file = Connection::open(&tmp_path)
+        .map_err(|err| error!("Error opening {}: {:?}", tmp_path.display(), err))?;
+    Backup::new(&copy, &mut file)
+        .and_then(|backup| {
+            backup.run_to_completion(SNAPSHOT_STEP_PAGES, Duration::from_millis(0), None)
+        })
+        .map_err(|err| error!("Error writing snapshot: {:?}", err))?;
+    drop(file);
+    std::fs::rename(&tmp_path, snapshot_path).map_err(|err| {
+        error!(
+            "Error moving snapshot to {}: {:?}",
+            snapshot_path.display(),
+            err
+        )
+    })?;
+    trace!("Wrote snapshot to {}", snapshot_path.display());
+    Ok(())
+}
+
+fn get_setting(conn: &Connection, key: &str) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM node_settings WHERE key = ?1",
+        params![key],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+fn set_setting(tx: &Transaction<'_>, key: &str, value: &str) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT OR REPLACE INTO node_settings (key, value) VALUES (?1, ?2)",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+/// Adds the columns which were added to the schema after the database was created,
+/// as `CREATE TABLE IF NOT EXISTS` leaves existing tables untouched
+fn migrate_schema(conn: &Connection) -> rusqlite::Result<()> {
+    for (column, definition) in &[
+        ("settle_every", "settle_every INTEGER"),
+        ("min_packet_amount", "min_packet_amount TEXT"),
+        ("max_payment_amount", "max_payment_amount TEXT"),
+        ("amount_per_day_limit", "amount_per_day_limit TEXT"),
+        ("routing_preference", "routing_preference INTEGER"),
+    ] {
+        let has_column: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM pragma_table_info('accounts') WHERE name = ?1)",
+            params![column],
+            |row| row.get(0),
+        )?;
+        if !has_column {
+            conn.execute_batch(&format!("ALTER TABLE accounts ADD COLUMN {}", definition))?;
+        }
+    }
+    Ok(())
+}
+
+fn account_exists(tx: &Transaction<'_>, account_id: Uuid) -> rusqlite::Result<bool> {
+    tx.query_row(
+        "SELECT EXISTS(SELECT 1 FROM accounts WHERE id = ?1)",
+        params![account_id.to_string()],
+        |row| row.get(0),
+    )
+}
+
+/// Appends an entry to the account's balance change log
+fn log_balance_change(
+    tx: &Transaction<'_>,
+    account_id: Uuid,
+    reason: BalanceChangeReason,
+    delta: i64,
+    balance: i64,
+    packet_id: Option<PacketId>,
+) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT INTO balance_log (account_id, sequence, timestamp, reason, delta, balance, packet_id) \
+         SELECT ?1, COALESCE(MAX(sequence) + 1, 0), ?2, ?3, ?4, ?5, ?6 FROM balance_log WHERE account_id = ?1",
+        params![
+            account_id.to_string(),
+            now_millis(),
+            reason.as_str(),
+            delta,
+            balance,
+            packet_id.map(hex::encode)
+        ],
+    )?;
+    Ok(())
+}
+
+fn set_balance(
+    tx: &Transaction<'_>,
+    account_id: Uuid,
+    balance: i64,
+    prepaid_amount: i64,
+) -> rusqlite::Result<()> {
+    tx.execute(
+        "UPDATE accounts SET balance = ?2, prepaid_amount = ?3 WHERE id = ?1",
+        params![account_id.to_string(), balance, prepaid_amount],
+    )?;
+    Ok(())
+}
+
+/// Writes the details of the provided account, keeping its balance if it already exists
+fn write_account(
+    tx: &Transaction<'_>,
+    encrypted: &AccountWithEncryptedTokens,
+) -> rusqlite::Result<()> {
+    let account = &encrypted.account;
+    let token = |token: &Option<SecretBytesMut>| token.as_ref().map(|t| t.expose_secret().to_vec());
+    tx.execute(
+        UPSERT_ACCOUNT.as_str(),
+        params![
+            account.id.to_string(),
+            account.username.to_string(),
+            account.ilp_address.to_string(),
+            account.asset_code,
+            account.asset_scale,
+            account.max_packet_amount.to_string(),
+            account.min_balance,
+            account.ilp_over_http_url.as_ref().map(Url::as_str),
+            token(&account.ilp_over_http_incoming_token),
+            token(&account.ilp_over_http_outgoing_token),
+            account.ilp_over_btp_url.as_ref().map(Url::as_str),
+            token(&account.ilp_over_btp_incoming_token),
+            token(&account.ilp_over_btp_outgoing_token),
+            account.settle_threshold,
+            account.settle_to,
+            account.routing_relation.as_ref(),
+            account.round_trip_time,
+            account.packets_per_minute_limit,
+            account
+                .amount_per_minute_limit
+                .map(|limit| limit.to_string()),
+            account.settle_every,
+            account.min_packet_amount.map(|amount| amount.to_string()),
+            account.max_payment_amount.map(|amount| amount.to_string()),
+            account.amount_per_day_limit.map(|limit| limit.to_string()),
+            account.routing_preference,
+            account.settlement_engine_url.as_ref().map(Url::as_str),
+        ],
+    )?;
+    Ok(())
+}
+
+fn load_routes(conn: &Connection) -> rusqlite::Result<HashMap<String, Uuid>> {
+    let load = |sql: &str| -> rusqlite::Result<Vec<(String, Uuid)>> {
+        let mut statement = conn.prepare(sql)?;
+        let rows = statement.query_map([], |row| {
+            let prefix: String = row.get(0)?;
+            let account_id: String = row.get(1)?;
+            let account_id =
+                Uuid::from_str(&account_id).map_err(|_| invalid_column(1, "Invalid account id"))?;
+            Ok((prefix, account_id))
+        })?;
+        rows.collect()
+    };
+    let routes = load("SELECT prefix, account_id FROM routes")?;
+    let static_routes = load("SELECT prefix, account_id FROM static_routes")?;
+    let default_route = get_setting(conn, DEFAULT_ROUTE_KEY)?
+        .and_then(|account_id| Uuid::from_str(&account_id).ok());
+    trace!(
+        "Loaded routes from SQLite. Static routes: {:?}, default route: {:?}, other routes: {:?}",
+        static_routes,
+        default_route,
+        routes
+    );
+
+    Ok(routes
+        .into_iter()
+        // Include the default route if there is one
+        .chain(default_route.map(|id| (String::new(), id)))
+        // Having the static_routes inserted after ensures that they will overwrite
+        // any routes with the same prefix from the first set
+        .chain(static_routes.into_iter())
+        .collect())
+}
+
+fn save_outgoing_settlement(
+    conn: &Connection,
+    settlement: &OutgoingSettlement,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO outgoing_settlements
+            (idempotency_key, account_id, amount, status, attempts, next_attempt_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            settlement.idempotency_key,
+            settlement.account_id.to_string(),
+            settlement.amount as i64,
+            status_to_str(settlement.status),
+            settlement.attempts,
+            settlement.next_attempt_at as i64,
+        ],
+    )?;
+    Ok(())
+}
+
+fn has_settlement_in_flight(tx: &Transaction<'_>, account_id: Uuid) -> rusqlite::Result<bool> {
+    tx.query_row(
+        "SELECT EXISTS(SELECT 1 FROM outgoing_settlements WHERE account_id = ?1)",
+        params![account_id.to_string()],
+        |row| row.get(0),
+    )
+}
+
+/// Adds the amount to the account's queued outgoing settlement, which is sent once
+/// the settlement in flight (if any) was answered
+fn queue_settlement_amount(
+    tx: &Transaction<'_>,
+    account_id: Uuid,
+    amount: u64,
+) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT INTO queued_settlements (account_id, amount) VALUES (?1, ?2)
+            ON CONFLICT (account_id) DO UPDATE SET amount = amount + ?2",
+        params![account_id.to_string(), amount as i64],
+    )?;
+    Ok(())
+}
+
+fn status_to_str(status: OutgoingSettlementStatus) -> &'static str {
+    match status {
+        OutgoingSettlementStatus::Pending => "pending",
+        OutgoingSettlementStatus::Unknown => "unknown",
+    }
+}
+
+fn outgoing_settlement_from_row(row: &Row<'_>) -> rusqlite::Result<OutgoingSettlement> {
+    let account_id: String = row.get(1)?;
+    let amount: i64 = row.get(2)?;
+    let status: String = row.get(3)?;
+    let next_attempt_at: i64 = row.get(5)?;
+    Ok(OutgoingSettlement {
+        idempotency_key: row.get(0)?,
+        account_id: Uuid::from_str(&account_id)
+            .map_err(|_| invalid_column(1, "Invalid account id"))?,
+        amount: amount as u64,
+        status: match status.as_str() {
+            "pending" => OutgoingSettlementStatus::Pending,
+            "unknown" => OutgoingSettlementStatus::Unknown,
+            _ => return Err(invalid_column(3, "Invalid settlement status")),
+        },
+        attempts: row.get(4)?,
+        next_attempt_at: next_attempt_at as u64,
+    })
+}
+
+fn invalid_column(index: usize, message: &str) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(index, Type::Text, message.into())
+}
+
+fn get_url_option(row: &Row<'_>, index: usize) -> rusqlite::Result<Option<Url>> {
+    let value: Option<String> = row.get(index)?;
+    value
+        .map(|value| Url::parse(&value))
+        .transpose()
+        .map_err(|_| invalid_column(index, "Invalid URL"))
+}
+
+fn get_bytes_option(row: &Row<'_>, index: usize) -> rusqlite::Result<Option<SecretBytesMut>> {
+    let value: Option<Vec<u8>> = row.get(index)?;
+    Ok(value.map(|value| SecretBytesMut::from(BytesMut::from(value.as_slice()))))
+}
+
+fn account_from_row(row: &Row<'_>) -> rusqlite::Result<AccountWithEncryptedTokens> {
+    let id: String = row.get(0)?;
+    let id = Uuid::from_str(&id).map_err(|_| invalid_column(0, "Invalid account id"))?;
+    let username: String = row.get(1)?;
+    let username =
+        Username::from_str(&username).map_err(|_| invalid_column(1, "Invalid username"))?;
+    let ilp_address: String = row.get(2)?;
+    let ilp_address =
+        Address::from_str(&ilp_address).map_err(|_| invalid_column(2, "Invalid ILP address"))?;
+    let max_packet_amount: String = row.get(5)?;
+    let max_packet_amount = u64::from_str(&max_packet_amount)
+        .map_err(|_| invalid_column(5, "Invalid max packet amount"))?;
+    let routing_relation: String = row.get(15)?;
+    let routing_relation = RoutingRelation::from_str(&routing_relation)
+        .map_err(|_| invalid_column(15, "Invalid Routing Relation"))?;
+    let amount_per_minute_limit: Option<String> = row.get(18)?;
+    let amount_per_minute_limit = amount_per_minute_limit
+        .map(|limit| u64::from_str(&limit))
+        .transpose()
+        .map_err(|_| invalid_column(18, "Invalid amount per minute limit"))?;
+    let min_packet_amount: Option<String> = row.get(20)?;
+    let min_packet_amount = min_packet_amount
+        .map(|amount| u64::from_str(&amount))
+        .transpose()
+        .map_err(|_| invalid_column(20, "Invalid min packet amount"))?;
+    let max_payment_amount: Option<String> = row.get(21)?;
+    let max_payment_amount = max_payment_amount
+        .map(|amount| u64::from_str(&amount))
+        .transpose()
+        .map_err(|_| invalid_column(21, "Invalid max payment amount"))?;
+    let amount_per_day_limit: Option<String> = row.get(22)?;
+    let amount_per_day_limit = amount_per_day_limit
+        .map(|limit| u64::from_str(&limit))
+        .transpose()
+        .map_err(|_| invalid_column(22, "Invalid amount per day limit"))?;
+
+    Ok(AccountWithEncryptedTokens {
+        account: Account {
+            id,
+            username,
+            ilp_address,
+            asset_code: row.get(3)?,
+            asset_scale: row.get(4)?,
+            max_packet_amount,
+            min_packet_amount,
+            min_balance: row.get(6)?,
+            ilp_over_http_url: get_url_option(row, 7)?,
+            ilp_over_http_incoming_token: get_bytes_option(row, 8)?,
+            ilp_over_http_outgoing_token: get_bytes_option(row, 9)?,
+            ilp_over_btp_url: get_url_option(row, 10)?,
+            ilp_over_btp_incoming_token: get_bytes_option(row, 11)?,
+            ilp_over_btp_outgoing_token: get_bytes_option(row, 12)?,
+            settle_threshold: row.get(13)?,
+            settle_to: row.get(14)?,
+            routing_relation,
+            routing_preference: row.get(23)?,
+            round_trip_time: row.get(16)?,
+            packets_per_minute_limit: row.get(17)?,
+            amount_per_minute_limit,
+            settle_every: row.get(19)?,
+            max_payment_amount,
+            amount_per_day_limit,
+            settlement_engine_url: get_url_option(row, 24)?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn connect_fails_if_path_is_invalid() {
+        let result = SqliteStoreBuilder::new("/nonexistent-directory/ilp.db", [0; 32])
+            .connect()
+            .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,165 @@
+-- Schema for the embedded SQLite store. Every statement is idempotent so that
+-- this can be executed each time the store is opened.
+
+-- Account details and balances. Tokens are stored encrypted.
+-- Amounts which may exceed i64::MAX are stored as TEXT.
+CREATE TABLE IF NOT EXISTS accounts (
+    id TEXT PRIMARY KEY NOT NULL,
+    username TEXT NOT NULL UNIQUE,
+    ilp_address TEXT NOT NULL,
+    asset_code TEXT NOT NULL,
+    asset_scale INTEGER NOT NULL,
+    max_packet_amount TEXT NOT NULL,
+    min_balance INTEGER,
+    ilp_over_http_url TEXT,
+    ilp_over_http_incoming_token BLOB,
+    ilp_over_http_outgoing_token BLOB,
+    ilp_over_btp_url TEXT,
+    ilp_over_btp_incoming_token BLOB,
+    ilp_over_btp_outgoing_token BLOB,
+    settle_threshold INTEGER,
+    settle_to INTEGER,
+    routing_relation TEXT NOT NULL,
+    round_trip_time INTEGER NOT NULL,
+    packets_per_minute_limit INTEGER,
+    amount_per_minute_limit TEXT,
+    settle_every INTEGER,
+    min_packet_amount TEXT,
+    max_payment_amount TEXT,
+    amount_per_day_limit TEXT,
+    settlement_engine_url TEXT,
+    routing_preference INTEGER,
+    balance INTEGER NOT NULL DEFAULT 0,
+    prepaid_amount INTEGER NOT NULL DEFAULT 0
+);
+
+-- Dynamic routing table (local accounts and routes learned via CCP)
+CREATE TABLE IF NOT EXISTS routes (
+    prefix TEXT PRIMARY KEY NOT NULL,
+    account_id TEXT NOT NULL
+);
+
+-- Static routing table configured via the API
+CREATE TABLE IF NOT EXISTS static_routes (
+    prefix TEXT PRIMARY KEY NOT NULL,
+    account_id TEXT NOT NULL
+);
+
+-- Node-wide single values (parent-assigned ILP address, default route, generation of
+-- the STREAM server secret)
+CREATE TABLE IF NOT EXISTS node_settings (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL
+);
+
+-- Globally configured settlement engines per asset code
+CREATE TABLE IF NOT EXISTS settlement_engines (
+    asset_code TEXT PRIMARY KEY NOT NULL,
+    url TEXT NOT NULL
+);
+
+-- Cached settlement API responses
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    key TEXT PRIMARY KEY NOT NULL,
+    status_code INTEGER NOT NULL,
+    data BLOB NOT NULL,
+    input_hash BLOB NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+-- Idempotency keys of incoming settlements which were already credited
+CREATE TABLE IF NOT EXISTS incoming_settlements (
+    key TEXT PRIMARY KEY NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+-- Settlement amounts which could not be credited due to precision loss
+CREATE TABLE IF NOT EXISTS uncredited_amounts (
+    account_id TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    scale INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS uncredited_amounts_account ON uncredited_amounts (account_id);
+
+-- Amounts the accounts with a daily volume limit sent per day (counted in days since
+-- the UNIX epoch). Only the current and the previous day of an account are kept.
+CREATE TABLE IF NOT EXISTS daily_volumes (
+    account_id TEXT NOT NULL,
+    day INTEGER NOT NULL,
+    amount TEXT NOT NULL,
+    PRIMARY KEY (account_id, day)
+);
+
+-- Append-only log of every balance change. The entries of an account are numbered
+-- from 0 and are kept when the account is deleted, so that its history can be audited.
+CREATE TABLE IF NOT EXISTS balance_log (
+    account_id TEXT NOT NULL,
+    sequence INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    delta INTEGER NOT NULL,
+    balance INTEGER NOT NULL,
+    packet_id TEXT,
+    PRIMARY KEY (account_id, sequence)
+);
+
+-- Outgoing settlements which the engine has not answered yet. They are retried with
+-- the same idempotency key until the engine accepts or rejects them. Every account
+-- has at most one settlement in flight.
+CREATE TABLE IF NOT EXISTS outgoing_settlements (
+    idempotency_key TEXT PRIMARY KEY NOT NULL,
+    account_id TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    next_attempt_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS outgoing_settlements_account ON outgoing_settlements (account_id);
+
+-- Amounts to settle which wait for the account's settlement in flight to be answered
+CREATE TABLE IF NOT EXISTS queued_settlements (
+    account_id TEXT PRIMARY KEY NOT NULL,
+    amount INTEGER NOT NULL
+);
+
+-- Summaries of the packets sent by and to the accounts, newest last. They are deleted
+-- once they are older than the retention period configured on the node.
+CREATE TABLE IF NOT EXISTS packet_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account_id TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    direction TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    destination TEXT NOT NULL,
+    reject_code TEXT
+);
+CREATE INDEX IF NOT EXISTS packet_history_account ON packet_history (account_id, id);
+CREATE INDEX IF NOT EXISTS packet_history_timestamp ON packet_history (timestamp);
+
+-- Outgoing packets in flight whose response was not applied to the balances yet, if the
+-- node journals them. They are reconciled if the node crashed before it applied them.
+CREATE TABLE IF NOT EXISTS packet_journal (
+    id TEXT PRIMARY KEY NOT NULL,
+    from_id TEXT NOT NULL,
+    to_id TEXT NOT NULL,
+    incoming_amount INTEGER NOT NULL,
+    outgoing_amount INTEGER NOT NULL,
+    packet_id TEXT,
+    expires_at INTEGER NOT NULL,
+    fulfilled INTEGER NOT NULL
+);
+
+-- Money received by the STREAM receiver on each connection, if the node records it. The
+-- payments are kept until the account is deleted, so that later packets on a connection
+-- add to its total.
+CREATE TABLE IF NOT EXISTS incoming_payments (
+    account_id TEXT NOT NULL,
+    destination TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    closed INTEGER NOT NULL,
+    acknowledged INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (account_id, destination)
+);
+CREATE INDEX IF NOT EXISTS incoming_payments_unacknowledged ON incoming_payments (account_id, acknowledged, updated_at);
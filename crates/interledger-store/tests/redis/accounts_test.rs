@@ -1,18 +1,22 @@
 use super::{fixtures::*, redis_helpers::*, store_helpers::*};
-use interledger_api::{AccountSettings, NodeStore};
-use interledger_btp::BtpAccount;
-use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
-use interledger_http::HttpAccount;
+use interledger_api::{AccountFilter, AccountSettings, NodeStore};
+use interledger_btp::{BtpAccount, BtpStore};
+use interledger_ccp::{CcpRoutingAccount, CcpRoutingStore, RoutingRelation};
+use interledger_http::{HttpAccount, HttpStore};
 use interledger_packet::Address;
+use interledger_router::RouterStore;
 use interledger_service::Account as AccountTrait;
 use interledger_service::{AccountStore, AddressStore, Username};
-use interledger_service_util::BalanceStore;
+use interledger_service_util::{BalanceStore, MaxPacketAmountAccount, MinPacketAmountAccount};
+use interledger_settlement::core::types::SettlementAccount;
 use interledger_store::redis::RedisStoreBuilder;
+use interledger_stream::SecretGeneration;
 use redis_crate::Client;
 use secrecy::ExposeSecret;
 use secrecy::SecretString;
 use std::default::Default;
 use std::str::FromStr;
+use std::time::Duration;
 use uuid::Uuid;
 
 #[tokio::test]
@@ -139,21 +143,27 @@ async fn update_ilp_and_children_addresses() {
 }
 
 #[tokio::test]
-async fn only_one_parent_allowed() {
+async fn multiple_parents_allowed() {
     let mut acc = ACCOUNT_DETAILS_2.clone();
     acc.routing_relation = Some("Parent".to_owned());
+    acc.routing_preference = Some(10);
     acc.username = Username::from_str("another_name").unwrap();
     acc.ilp_address = Some(Address::from_str("example.another_name").unwrap());
-    let (store, _context, accs) = test_store().await.unwrap();
-    let res = store.insert_account(acc.clone()).await;
-    // This should fail
-    assert!(res.is_err());
-    store.delete_account(accs[0].id()).await.unwrap();
-    // must also clear the ILP Address to indicate that we no longer
-    // have a parent account configured
-    store.clear_ilp_address().await.unwrap();
-    let res = store.insert_account(acc).await;
-    assert!(res.is_ok());
+    let (store, _context, _accs) = test_store().await.unwrap();
+    let parent = store.insert_account(acc).await.unwrap();
+    assert_eq!(parent.routing_preference(), 10);
+    let parents = store
+        .list_accounts(
+            None,
+            10,
+            AccountFilter {
+                routing_relation: Some(RoutingRelation::Parent),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(parents.accounts.len(), 2);
 }
 
 #[tokio::test]
@@ -182,6 +192,120 @@ async fn delete_accounts() {
     assert_eq!(err.to_string(), "Broken pipe (os error 32)");
 }
 
+#[tokio::test]
+async fn delete_account_removes_routes_and_parent() {
+    let (store, _context, accounts) = test_store().await.unwrap();
+    // alice is the parent
+    let id = accounts[0].id();
+    store
+        .set_static_route("example.static".to_string(), id)
+        .await
+        .unwrap();
+    store.set_default_route(id).await.unwrap();
+    store.delete_account(id).await.unwrap();
+
+    let (_, configured_routes) = store.get_local_and_configured_routes().await.unwrap();
+    assert!(configured_routes.is_empty());
+    assert!(!store
+        .routing_table()
+        .values()
+        .any(|account_id| *account_id == id));
+
+    // a new parent can be inserted after the previous one was deleted
+    store
+        .insert_account(ACCOUNT_DETAILS_0.clone())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn update_account_changes_username_and_address() {
+    let (store, _context, accounts) = test_store().await.unwrap();
+    let id = accounts[1].id();
+    let mut new = ACCOUNT_DETAILS_1.clone();
+    new.username = Username::from_str("robert").unwrap();
+    new.ilp_address = Some(Address::from_str("example.robert").unwrap());
+    new.ilp_over_btp_url = None;
+    store.update_account(id, new.clone()).await.unwrap();
+
+    assert_eq!(
+        store
+            .get_account_id_from_username(&Username::from_str("robert").unwrap())
+            .await
+            .unwrap(),
+        id
+    );
+    assert!(store
+        .get_account_id_from_username(&Username::from_str("bob").unwrap())
+        .await
+        .is_err());
+    let routing_table = store.routing_table();
+    assert_eq!(routing_table.get("example.robert"), Some(&id));
+    assert!(!routing_table.contains_key(&accounts[1].ilp_address().to_string()));
+    assert!(!store
+        .get_btp_outgoing_accounts()
+        .await
+        .unwrap()
+        .iter()
+        .any(|account| account.id() == id));
+
+    // cannot take the username of another account
+    new.username = Username::from_str("alice").unwrap();
+    let err = store.update_account(id, new).await.unwrap_err();
+    assert_eq!(err.to_string(), "account `alice` already exists");
+}
+
+#[tokio::test]
+async fn concurrent_writes_cannot_claim_the_same_username() {
+    let (store, _context, accounts) = test_store().await.unwrap();
+    let mut alice = ACCOUNT_DETAILS_0.clone();
+    alice.username = Username::from_str("carol").unwrap();
+    let mut bob = ACCOUNT_DETAILS_1.clone();
+    bob.username = Username::from_str("carol").unwrap();
+    let mut charlie = ACCOUNT_DETAILS_2.clone();
+    charlie.username = Username::from_str("carol").unwrap();
+
+    let (alice, bob, charlie) = futures::future::join3(
+        store.update_account(accounts[0].id(), alice),
+        store.update_account(accounts[1].id(), bob),
+        store.insert_account(charlie),
+    )
+    .await;
+    let written: Vec<Uuid> = vec![
+        alice.map(|account| account.id()),
+        bob.map(|account| account.id()),
+        charlie.map(|account| account.id()),
+    ]
+    .into_iter()
+    .filter_map(Result::ok)
+    .collect();
+    assert_eq!(written.len(), 1);
+    assert_eq!(
+        store
+            .get_account_id_from_username(&Username::from_str("carol").unwrap())
+            .await
+            .unwrap(),
+        written[0]
+    );
+    // The accounts which lost keep their usernames
+    let stored = store.get_all_accounts().await.unwrap();
+    let count_username = |username: &str| {
+        stored
+            .iter()
+            .filter(|account| account.username().as_ref() == username)
+            .count()
+    };
+    assert_eq!(count_username("carol"), 1);
+    assert_eq!(
+        count_username("alice"),
+        (written[0] != accounts[0].id()) as usize
+    );
+    assert_eq!(
+        count_username("bob"),
+        (written[0] != accounts[1].id()) as usize
+    );
+}
+
 #[tokio::test]
 async fn update_accounts() {
     let (store, _context, accounts) = test_store().await.unwrap();
@@ -247,6 +371,7 @@ async fn modify_account_settings() {
         ilp_over_btp_url: Some("http://example.com/accounts/dylan/ilp/btp".to_owned()),
         settle_threshold: Some(-50),
         settle_to: Some(100),
+        settle_every: Some(60),
     };
     let account = accounts[0].clone();
 
@@ -263,6 +388,7 @@ async fn modify_account_settings() {
         ret.get_ilp_over_btp_outgoing_token().unwrap(),
         &b"dylan:test"[..],
     );
+    assert_eq!(ret.settle_every(), Some(Duration::from_secs(60)));
 
     let id = Uuid::new_v4();
     let err = store
@@ -346,3 +472,329 @@ async fn errors_for_unknown_accounts() {
         .unwrap_err();
     assert_eq!(err.to_string(), "wrong account length (expected 2, got 0)");
 }
+
+#[tokio::test]
+async fn accounts_are_isolated_between_db_prefixes() {
+    let context = TestContext::new();
+    let first = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .with_db_prefix("first")
+        .connect()
+        .await
+        .unwrap();
+    let second = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .with_db_prefix("second")
+        .connect()
+        .await
+        .unwrap();
+
+    let account = first
+        .insert_account(ACCOUNT_DETAILS_2.clone())
+        .await
+        .unwrap();
+    // the same username can be used in both namespaces
+    second
+        .insert_account(ACCOUNT_DETAILS_2.clone())
+        .await
+        .unwrap();
+    assert_eq!(first.get_all_accounts().await.unwrap().len(), 1);
+    assert!(second.get_accounts(vec![account.id()]).await.is_err());
+}
+
+#[tokio::test]
+async fn rejects_invalid_db_prefix() {
+    let context = TestContext::new();
+    let result = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .with_db_prefix("first:second")
+        .connect()
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn spreads_commands_over_connection_pool() {
+    let context = TestContext::new();
+    let store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .node_ilp_address(Address::from_str("example.node").unwrap())
+        .pool_size(3)
+        .connection_timeout(5000)
+        .max_retries(2)
+        .retry_backoff(10)
+        .connect()
+        .await
+        .unwrap();
+    let account = store
+        .insert_account(ACCOUNT_DETAILS_1.clone())
+        .await
+        .unwrap();
+    // consecutive commands use different connections
+    for _ in 0..6 {
+        let accounts = store.get_accounts(vec![account.id()]).await.unwrap();
+        assert_eq!(accounts[0].id(), account.id());
+    }
+    assert_eq!(store.get_all_accounts().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn account_cache_is_invalidated_on_changes() {
+    let context = TestContext::new();
+    let store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .node_ilp_address(Address::from_str("example.node").unwrap())
+        .account_cache_capacity(10)
+        .account_cache_ttl(60_000)
+        .connect()
+        .await
+        .unwrap();
+    let account = store
+        .insert_account(ACCOUNT_DETAILS_1.clone())
+        .await
+        .unwrap();
+    let id = account.id();
+    store
+        .get_account_from_http_auth(account.username(), "incoming_auth_token")
+        .await
+        .unwrap();
+
+    // changes made by others are only seen once the cached account is invalidated
+    let mut connection = context.async_connection().await.unwrap();
+    let _: redis_crate::Value = redis_crate::cmd("HSET")
+        .arg(format!("accounts:{}", id))
+        .arg("max_packet_amount")
+        .arg(5)
+        .query_async(&mut connection)
+        .await
+        .unwrap();
+    let cached = store.get_accounts(vec![id]).await.unwrap();
+    assert_eq!(cached[0].max_packet_amount(), 1_000_000);
+    store.invalidate_cached_account(id);
+    let reloaded = store.get_accounts(vec![id]).await.unwrap();
+    assert_eq!(reloaded[0].max_packet_amount(), 5);
+
+    // changes made through the store are seen immediately
+    let settings = AccountSettings {
+        ilp_over_http_incoming_token: Some(SecretString::new("http_in_new".to_owned())),
+        ..Default::default()
+    };
+    store.modify_account_settings(id, settings).await.unwrap();
+    assert!(store
+        .get_account_from_http_auth(account.username(), "incoming_auth_token")
+        .await
+        .is_err());
+    store
+        .get_account_from_http_auth(account.username(), "http_in_new")
+        .await
+        .unwrap();
+
+    store.delete_account(id).await.unwrap();
+    assert!(store.get_accounts(vec![id]).await.is_err());
+    assert!(store
+        .get_account_from_http_auth(account.username(), "http_in_new")
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn reencrypts_tokens_with_new_secret() {
+    let context = TestContext::new();
+    let store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    let account = store
+        .insert_account(ACCOUNT_DETAILS_1.clone())
+        .await
+        .unwrap();
+    drop(store);
+
+    let store = RedisStoreBuilder::new(context.get_client_connection_info(), [1; 32])
+        .previous_secret([0; 32])
+        .connect()
+        .await
+        .unwrap();
+    store
+        .get_account_from_http_auth(account.username(), "incoming_auth_token")
+        .await
+        .unwrap();
+    drop(store);
+
+    // connecting again with the same secrets leaves the tokens untouched
+    let store = RedisStoreBuilder::new(context.get_client_connection_info(), [1; 32])
+        .previous_secret([0; 32])
+        .connect()
+        .await
+        .unwrap();
+    let accounts = store.get_accounts(vec![account.id()]).await.unwrap();
+    assert_eq!(
+        accounts[0].get_http_auth_token().unwrap().expose_secret(),
+        "outgoing_auth_token",
+    );
+}
+
+#[tokio::test]
+async fn stores_only_hashes_of_incoming_tokens() {
+    let (store, context, accs) = test_store().await.unwrap();
+    let mut connection = context.async_connection().await.unwrap();
+    let stored: Vec<u8> = redis_crate::cmd("HGET")
+        .arg(format!("accounts:{}", accs[1].id()))
+        .arg("ilp_over_http_incoming_token")
+        .query_async(&mut connection)
+        .await
+        .unwrap();
+    assert!(stored.starts_with(b"$argon2"));
+    store
+        .get_account_from_http_auth(accs[1].username(), "incoming_auth_token")
+        .await
+        .unwrap();
+
+    store
+        .modify_account_settings(
+            accs[1].id(),
+            AccountSettings {
+                ilp_over_http_incoming_token: Some(SecretString::new("new_token".to_owned())),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    let stored: Vec<u8> = redis_crate::cmd("HGET")
+        .arg(format!("accounts:{}", accs[1].id()))
+        .arg("ilp_over_http_incoming_token")
+        .query_async(&mut connection)
+        .await
+        .unwrap();
+    assert!(stored.starts_with(b"$argon2"));
+    // the cached verification of the previous token is not used anymore
+    assert!(store
+        .get_account_from_http_auth(accs[1].username(), "incoming_auth_token")
+        .await
+        .is_err());
+    store
+        .get_account_from_http_auth(accs[1].username(), "new_token")
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn exports_and_imports_node() {
+    let (store, context, accs) = test_store().await.unwrap();
+    store
+        .set_static_route("example.other".to_string(), accs[1].id())
+        .await
+        .unwrap();
+    store
+        .update_balances_for_prepare(accs[0].id(), 100, None)
+        .await
+        .unwrap();
+    let export = store.export_node().await.unwrap();
+    assert_eq!(export.accounts.len(), 2);
+    assert!(store.import_node(export.clone()).await.is_err());
+
+    let imported = RedisStoreBuilder::new(context.get_client_connection_info(), [1; 32])
+        .with_db_prefix("imported")
+        .connect()
+        .await
+        .unwrap();
+    imported.import_node(export).await.unwrap();
+    assert_eq!(imported.get_ilp_address(), store.get_ilp_address());
+    assert_eq!(imported.get_balance(accs[0].id()).await.unwrap(), -100);
+    assert_eq!(
+        imported.routing_table().get("example.other"),
+        Some(&accs[1].id())
+    );
+    let account = imported
+        .get_account_from_http_auth(accs[1].username(), "incoming_auth_token")
+        .await
+        .unwrap();
+    assert_eq!(account.ilp_address(), accs[1].ilp_address());
+}
+
+#[tokio::test]
+async fn lists_accounts_page_by_page() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let mut ids: Vec<Uuid> = accs.iter().map(|account| account.id()).collect();
+    ids.sort();
+
+    let page = store
+        .list_accounts(None, 1, AccountFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(page.accounts.len(), 1);
+    assert_eq!(page.accounts[0].id(), ids[0]);
+    assert_eq!(page.next, Some(ids[0]));
+
+    let page = store
+        .list_accounts(page.next, 1, AccountFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(page.accounts.len(), 1);
+    assert_eq!(page.accounts[0].id(), ids[1]);
+    assert_eq!(page.next, None);
+
+    let page = store
+        .list_accounts(
+            None,
+            10,
+            AccountFilter {
+                asset_code: Some("ABC".to_string()),
+                routing_relation: Some(RoutingRelation::Child),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(page.accounts.len(), 1);
+    assert_eq!(page.accounts[0].id(), accs[1].id());
+    assert_eq!(page.next, None);
+
+    let page = store
+        .list_accounts(
+            None,
+            10,
+            AccountFilter {
+                ilp_address_prefix: Some("example.alice.user1".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(page.accounts.len(), 1);
+    assert_eq!(page.accounts[0].id(), accs[1].id());
+}
+
+#[tokio::test]
+async fn saves_secret_generation() {
+    let (store, _context, _accs) = test_store().await.unwrap();
+    assert_eq!(
+        store.get_secret_generation().await.unwrap(),
+        SecretGeneration::default()
+    );
+    let generation = SecretGeneration::default().rotate(Duration::from_secs(60));
+    store.set_secret_generation(generation).await.unwrap();
+    assert_eq!(store.get_secret_generation().await.unwrap(), generation);
+}
+
+#[tokio::test]
+async fn hands_over_leadership_once_the_lease_expires() {
+    let (store, _context, _accs) = test_store().await.unwrap();
+    let lease = Duration::from_millis(100);
+    assert!(store.claim_leadership("active", lease).await.unwrap());
+    assert!(!store.claim_leadership("standby", lease).await.unwrap());
+    // The leader renews its lease
+    assert!(store.claim_leadership("active", lease).await.unwrap());
+
+    tokio::time::sleep(lease * 2).await;
+    assert!(store.claim_leadership("standby", lease).await.unwrap());
+    assert!(!store.claim_leadership("active", lease).await.unwrap());
+}
+
+#[tokio::test]
+async fn saves_min_packet_amount() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.min_packet_amount = Some(10);
+    let account = store.insert_account(details).await.unwrap();
+    let accounts = store.get_accounts(vec![account.id()]).await.unwrap();
+    assert_eq!(accounts[0].min_packet_amount(), 10);
+
+    // Accounts without a minimum forward packets of any amount
+    assert_eq!(accs[0].min_packet_amount(), 0);
+}
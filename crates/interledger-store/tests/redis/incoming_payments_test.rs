@@ -0,0 +1,78 @@
+use super::store_helpers::*;
+
+use interledger_packet::Address;
+use interledger_service::Account as AccountTrait;
+use interledger_stream::IncomingPaymentsStore;
+use std::str::FromStr;
+
+#[tokio::test]
+async fn records_and_acknowledges_incoming_payments() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    let first = Address::from_str("example.alice.first").unwrap();
+    let second = Address::from_str("example.alice.second").unwrap();
+
+    store
+        .record_incoming_payment(id, &first, 100, false, 1000)
+        .await
+        .unwrap();
+    let payment = store
+        .record_incoming_payment(id, &first, 50, false, 2000)
+        .await
+        .unwrap();
+    assert_eq!(payment.amount, 150);
+    assert!(!payment.closed);
+    assert!(!payment.acknowledged);
+    store
+        .record_incoming_payment(id, &second, 10, false, 1500)
+        .await
+        .unwrap();
+    let payment = store
+        .record_incoming_payment(id, &second, 0, true, 1600)
+        .await
+        .unwrap();
+    assert_eq!(payment.amount, 10);
+    assert!(payment.closed);
+
+    let unacknowledged = store.get_unacknowledged_payments(id).await.unwrap();
+    assert_eq!(unacknowledged.len(), 2);
+    assert_eq!(unacknowledged[0].destination, second);
+    assert_eq!(unacknowledged[0].updated_at, 1600);
+    assert_eq!(unacknowledged[1].destination, first);
+    assert_eq!(unacknowledged[1].amount, 150);
+    assert!(store
+        .get_unacknowledged_payments(accs[1].id())
+        .await
+        .unwrap()
+        .is_empty());
+
+    let payment = store
+        .acknowledge_incoming_payment(id, &second)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(payment.acknowledged);
+    assert!(payment.closed);
+    let unacknowledged = store.get_unacknowledged_payments(id).await.unwrap();
+    assert_eq!(unacknowledged.len(), 1);
+    assert_eq!(unacknowledged[0].destination, first);
+
+    // Receiving more money requires another acknowledgement
+    store
+        .acknowledge_incoming_payment(id, &first)
+        .await
+        .unwrap();
+    let payment = store
+        .record_incoming_payment(id, &first, 1, false, 3000)
+        .await
+        .unwrap();
+    assert_eq!(payment.amount, 151);
+    assert!(!payment.acknowledged);
+
+    let unknown = Address::from_str("example.alice.unknown").unwrap();
+    assert!(store
+        .acknowledge_incoming_payment(id, &unknown)
+        .await
+        .unwrap()
+        .is_none());
+}
@@ -1,8 +1,14 @@
 use super::{fixtures::*, store_helpers::*};
 use futures::future::join_all;
-use interledger_service::AddressStore;
-use interledger_service_util::{RateLimitError, RateLimitStore};
+use interledger_api::NodeStore;
+use interledger_service::{Account as AccountTrait, AddressStore, Username};
+use interledger_service_util::{
+    current_day, AdmissionError, AdmissionStore, BalanceStore, RateLimitError, RateLimitStore,
+    VolumeLimitError, VolumeLimitStore,
+};
 use interledger_store::account::Account;
+use interledger_store::redis::RedisStoreBuilder;
+use std::str::FromStr;
 use uuid::Uuid;
 
 #[tokio::test]
@@ -28,6 +34,31 @@ async fn rate_limits_number_of_packets() {
     );
 }
 
+#[tokio::test]
+async fn shares_rate_limits_between_nodes() {
+    let (store, context, _) = test_store().await.unwrap();
+    let other_store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    let account = Account::try_from(
+        Uuid::new_v4(),
+        ACCOUNT_DETAILS_0.clone(),
+        store.get_ilp_address(),
+    )
+    .unwrap();
+    store.apply_rate_limits(account.clone(), 10).await.unwrap();
+    other_store
+        .apply_rate_limits(account.clone(), 10)
+        .await
+        .unwrap();
+    // The account is only allowed 2 packets per minute, whichever node receives them
+    assert_eq!(
+        store.apply_rate_limits(account, 10).await,
+        Err(RateLimitError::PacketLimitExceeded)
+    );
+}
+
 #[tokio::test]
 async fn limits_amount_throughput() {
     let (store, _context, _) = test_store().await.unwrap();
@@ -78,3 +109,95 @@ async fn refunds_throughput_limit_for_rejected_packets() {
     let result = store.apply_rate_limits(account.clone(), 1).await;
     assert_eq!(result.unwrap_err(), RateLimitError::ThroughputLimitExceeded);
 }
+
+#[tokio::test]
+async fn limits_daily_volume() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_0.clone();
+    details.amount_per_day_limit = Some(1000);
+    let account = Account::try_from(Uuid::new_v4(), details, store.get_ilp_address()).unwrap();
+    let day = current_day();
+
+    store
+        .charge_daily_volume(account.clone(), day, 600)
+        .await
+        .unwrap();
+    assert_eq!(
+        store.charge_daily_volume(account.clone(), day, 500).await,
+        Err(VolumeLimitError::DailyLimitExceeded)
+    );
+
+    // Refunded amounts can be sent again and the totals of other days are separate
+    store
+        .refund_daily_volume(account.clone(), day, 200)
+        .await
+        .unwrap();
+    store
+        .charge_daily_volume(account.clone(), day, 500)
+        .await
+        .unwrap();
+    store
+        .charge_daily_volume(account.clone(), day + 1, 1000)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn admits_prepares_in_a_single_call() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    // alice may only send 2 packets per minute, even without amounts
+    let results = join_all(vec![
+        store.admit_prepare(accs[0].clone(), 0, current_day(), None, true),
+        store.admit_prepare(accs[0].clone(), 0, current_day(), None, true),
+        store.admit_prepare(accs[0].clone(), 0, current_day(), None, true),
+    ])
+    .await;
+    assert_eq!(
+        results,
+        vec![Ok(()), Ok(()), Err(AdmissionError::PacketLimitExceeded)]
+    );
+
+    let mut details = ACCOUNT_DETAILS_0.clone();
+    details.username = Username::from_str("carol").unwrap();
+    details.ilp_address = None;
+    details.routing_relation = Some("NonRoutingAccount".to_owned());
+    details.packets_per_minute_limit = None;
+    details.amount_per_minute_limit = None;
+    details.amount_per_day_limit = Some(1500);
+    let account = store.insert_account(details).await.unwrap();
+    let day = current_day();
+
+    store
+        .admit_prepare(account.clone(), 600, day, None, true)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(account.id()).await.unwrap(), -600);
+
+    // The packet would bring carol under her minimum balance of -1000,
+    // so neither her balance nor her daily volume are charged
+    assert_eq!(
+        store
+            .admit_prepare(account.clone(), 500, day, None, true)
+            .await,
+        Err(AdmissionError::InsufficientBalance)
+    );
+    assert_eq!(store.get_balance(account.id()).await.unwrap(), -600);
+
+    store
+        .refund_prepare(account.clone(), 600, day, None, true)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(account.id()).await.unwrap(), 0);
+
+    store
+        .admit_prepare(account.clone(), 900, day, None, true)
+        .await
+        .unwrap();
+    assert_eq!(
+        store
+            .admit_prepare(account.clone(), 700, day, None, false)
+            .await,
+        Err(AdmissionError::DailyLimitExceeded)
+    );
+    assert_eq!(store.get_balance(account.id()).await.unwrap(), -900);
+}
@@ -20,3 +20,17 @@ async fn set_rates() {
     assert_eq!(rates[0].to_string(), "0.005");
     assert_eq!(rates[1].to_string(), "500");
 }
+
+#[tokio::test]
+async fn tracks_when_rates_were_set() {
+    let (store, _context, _) = test_store().await.unwrap();
+    assert!(store.get_exchange_rates_updated_at(&["ABC"]).is_none());
+
+    let before = std::time::SystemTime::now();
+    store
+        .set_exchange_rates([("ABC".to_string(), 500.0)].iter().cloned().collect())
+        .unwrap();
+    let updated_at = store.get_exchange_rates_updated_at(&["ABC"]).unwrap();
+    assert!(updated_at >= before);
+    assert!(store.get_exchange_rates_updated_at(&["XYZ"]).is_none());
+}
@@ -2,7 +2,9 @@ mod accounts_test;
 mod balances_test;
 mod btp_test;
 mod http_test;
+mod incoming_payments_test;
 mod notifications;
+mod packet_history_test;
 mod rate_limiting_test;
 mod rates_test;
 mod routing_test;
@@ -24,6 +26,7 @@ mod fixtures {
         asset_scale: 6,
         asset_code: "XYZ".to_string(),
         max_packet_amount: 1000,
+        min_packet_amount: None,
         min_balance: Some(-1000),
         ilp_over_http_url: Some("http://example.com/accounts/dylan/ilp".to_string()),
         ilp_over_http_incoming_token: Some(SecretString::new("incoming_auth_token".to_string())),
@@ -33,9 +36,13 @@ mod fixtures {
         ilp_over_btp_outgoing_token: Some(SecretString::new("btp_token".to_string())),
         settle_threshold: Some(0),
         settle_to: Some(-1000),
+        settle_every: None,
         routing_relation: Some("Parent".to_owned()),
+        routing_preference: None,
         round_trip_time: None,
         amount_per_minute_limit: Some(1000),
+        max_payment_amount: None,
+        amount_per_day_limit: None,
         packets_per_minute_limit: Some(2),
         settlement_engine_url: Some("http://settlement.example".to_string()),
     });
@@ -45,6 +52,7 @@ mod fixtures {
         asset_scale: 9,
         asset_code: "ABC".to_string(),
         max_packet_amount: 1_000_000,
+        min_packet_amount: None,
         min_balance: Some(0),
         ilp_over_http_url: Some("http://example.com/accounts/dylan/ilp".to_string()),
         // incoming token has is the account's username concatenated wiht the password
@@ -55,9 +63,13 @@ mod fixtures {
         ilp_over_btp_outgoing_token: Some(SecretString::new("btp_token".to_string())),
         settle_threshold: Some(0),
         settle_to: Some(-1000),
+        settle_every: None,
         routing_relation: Some("Child".to_owned()),
+        routing_preference: None,
         round_trip_time: None,
         amount_per_minute_limit: Some(1000),
+        max_payment_amount: None,
+        amount_per_day_limit: None,
         packets_per_minute_limit: Some(20),
         settlement_engine_url: None,
     });
@@ -67,6 +79,7 @@ mod fixtures {
         asset_scale: 9,
         asset_code: "XRP".to_string(),
         max_packet_amount: 1000,
+        min_packet_amount: None,
         min_balance: Some(0),
         ilp_over_http_url: None,
         ilp_over_http_incoming_token: None,
@@ -76,9 +89,13 @@ mod fixtures {
         ilp_over_btp_outgoing_token: None,
         settle_threshold: Some(0),
         settle_to: None,
+        settle_every: None,
         routing_relation: None,
+        routing_preference: None,
         round_trip_time: None,
         amount_per_minute_limit: None,
+        max_payment_amount: None,
+        amount_per_day_limit: None,
         packets_per_minute_limit: None,
         settlement_engine_url: None,
     });
@@ -127,19 +144,6 @@ mod redis_helpers {
         pub fn new() -> RedisServer {
             let server_type = ServerType::get_intended();
             let mut cmd = process::Command::new("redis-server");
-
-            let fname = if os_type::current_platform().os_type == os_type::OSType::OSX {
-                "libredis_cell.dylib"
-            } else {
-                "libredis_cell.so"
-            };
-
-            // Load redis_cell
-            let mut cell_module: PathBuf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-            cell_module.push("external");
-            cell_module.push(fname);
-            cmd.arg("--loadmodule").arg(cell_module.as_os_str());
-
             cmd.stdout(process::Stdio::null())
                 .stderr(process::Stdio::null());
 
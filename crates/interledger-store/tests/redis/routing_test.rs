@@ -38,6 +38,7 @@ async fn polls_for_route_updates() {
             asset_scale: 6,
             asset_code: "XYZ".to_string(),
             max_packet_amount: 1000,
+            min_packet_amount: None,
             min_balance: Some(-1000),
             ilp_over_http_url: None,
             ilp_over_http_incoming_token: None,
@@ -47,9 +48,13 @@ async fn polls_for_route_updates() {
             ilp_over_btp_incoming_token: None,
             settle_threshold: None,
             settle_to: None,
+            settle_every: None,
             routing_relation: Some("Peer".to_owned()),
+            routing_preference: None,
             round_trip_time: None,
             amount_per_minute_limit: None,
+            max_payment_amount: None,
+            amount_per_day_limit: None,
             packets_per_minute_limit: None,
             settlement_engine_url: None,
         })
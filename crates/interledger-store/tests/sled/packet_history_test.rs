@@ -0,0 +1,104 @@
+use super::store_helpers::*;
+
+use interledger_service::Account as AccountTrait;
+use interledger_service_util::{
+    PacketDirection, PacketHistoryFilter, PacketHistoryStore, PacketOutcome, PacketRecord,
+};
+use uuid::Uuid;
+
+fn record(account_id: Uuid, timestamp: u64, direction: PacketDirection) -> PacketRecord {
+    PacketRecord {
+        id: 0,
+        timestamp,
+        account_id,
+        direction,
+        outcome: PacketOutcome::Fulfilled,
+        amount: 100,
+        destination: "example.bob".to_string(),
+        reject_code: None,
+    }
+}
+
+#[tokio::test]
+async fn returns_filtered_history_newest_first() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    let mut rejected = record(id, 3000, PacketDirection::Outgoing);
+    rejected.outcome = PacketOutcome::Rejected;
+    rejected.reject_code = Some("F02".to_string());
+    store
+        .record_packets(vec![
+            record(id, 1000, PacketDirection::Incoming),
+            record(id, 2000, PacketDirection::Outgoing),
+            rejected,
+            record(accs[1].id(), 2000, PacketDirection::Incoming),
+        ])
+        .await
+        .unwrap();
+
+    let history = store
+        .get_packet_history(id, &PacketHistoryFilter::default(), 10)
+        .await
+        .unwrap();
+    let timestamps: Vec<u64> = history.iter().map(|record| record.timestamp).collect();
+    assert_eq!(timestamps, vec![3000, 2000, 1000]);
+    assert_eq!(history[0].reject_code.as_deref(), Some("F02"));
+
+    // The next page starts after the last record of the previous one
+    let page = store
+        .get_packet_history(id, &PacketHistoryFilter::default(), 2)
+        .await
+        .unwrap();
+    let filter = PacketHistoryFilter {
+        before: Some(page[1].id),
+        ..Default::default()
+    };
+    let next_page = store.get_packet_history(id, &filter, 2).await.unwrap();
+    assert_eq!(next_page.len(), 1);
+    assert_eq!(next_page[0].timestamp, 1000);
+
+    let filter = PacketHistoryFilter {
+        direction: Some(PacketDirection::Outgoing),
+        outcome: Some(PacketOutcome::Fulfilled),
+        ..Default::default()
+    };
+    let history = store.get_packet_history(id, &filter, 10).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].timestamp, 2000);
+
+    let filter = PacketHistoryFilter {
+        since: Some(2000),
+        until: Some(3000),
+        ..Default::default()
+    };
+    let history = store.get_packet_history(id, &filter, 10).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].timestamp, 2000);
+}
+
+#[tokio::test]
+async fn prunes_old_records() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    store
+        .record_packets(vec![
+            record(id, 1000, PacketDirection::Incoming),
+            record(id, 2000, PacketDirection::Incoming),
+            record(accs[1].id(), 1500, PacketDirection::Outgoing),
+        ])
+        .await
+        .unwrap();
+
+    assert_eq!(store.prune_packet_history(2000).await.unwrap(), 2);
+    let history = store
+        .get_packet_history(id, &PacketHistoryFilter::default(), 10)
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].timestamp, 2000);
+    assert!(store
+        .get_packet_history(accs[1].id(), &PacketHistoryFilter::default(), 10)
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,68 @@
+use super::fixtures::*;
+use interledger_api::NodeStore;
+use interledger_router::RouterStore;
+use interledger_service::{Account as AccountTrait, AccountStore, Username};
+use interledger_service_util::{BalanceChangeReason, BalanceStore};
+use interledger_store::sled::SledStoreBuilder;
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn keeps_accounts_and_balances_after_reopening() {
+    let path = std::env::temp_dir().join(format!("ilp-sled-test-{}", Uuid::new_v4()));
+    let account = {
+        let store = SledStoreBuilder::new(&path, [0; 32])
+            .sync_writes(true)
+            .connect()
+            .await
+            .unwrap();
+        let account = store
+            .insert_account(ACCOUNT_DETAILS_2.clone())
+            .await
+            .unwrap();
+        store
+            .update_balances_for_fulfill(account.id(), 100, None)
+            .await
+            .unwrap();
+        account
+    };
+
+    let store = SledStoreBuilder::new(&path, [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    let id = store
+        .get_account_id_from_username(&Username::from_str("charlie").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(id, account.id());
+    // charlie has a settle_threshold of 0 but no settle_to, so no settlement is triggered
+    assert_eq!(store.get_balance(id).await.unwrap(), 100);
+    assert_eq!(
+        store
+            .routing_table()
+            .get(&account.ilp_address().to_string()),
+        Some(&id)
+    );
+    // The balance log continues after the entries written before reopening
+    store
+        .update_balances_for_fulfill(id, 50, None)
+        .await
+        .unwrap();
+    let changes = store.get_balance_changes(id, 0, 10).await.unwrap();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[1].sequence, 1);
+    assert_eq!(changes[1].reason, BalanceChangeReason::Fulfill);
+    assert_eq!(changes[1].balance, 150);
+    drop(store);
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[tokio::test]
+async fn temporary_store_starts_empty() {
+    let store = SledStoreBuilder::temporary([0; 32])
+        .connect()
+        .await
+        .unwrap();
+    assert!(store.get_all_accounts().await.unwrap().is_empty());
+}
@@ -0,0 +1,420 @@
+use super::{fixtures::*, store_helpers::*};
+use interledger_api::{AccountFilter, AccountSettings, NodeStore};
+use interledger_btp::BtpStore;
+use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
+use interledger_errors::NodeStoreError;
+use interledger_http::HttpStore;
+use interledger_packet::Address;
+use interledger_router::RouterStore;
+use interledger_service::Account as AccountTrait;
+use interledger_service::{AccountStore, AddressStore, Username};
+use interledger_service_util::{
+    current_day, MinPacketAmountAccount, VolumeLimitAccount, VolumeLimitError, VolumeLimitStore,
+};
+use interledger_settlement::core::types::SettlementAccount;
+use interledger_store::sqlite::SqliteStoreBuilder;
+use interledger_stream::SecretGeneration;
+use secrecy::SecretString;
+use std::str::FromStr;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn picks_up_parent_after_reopening() {
+    let path = std::env::temp_dir().join(format!("ilp-sqlite-test-{}.db", Uuid::new_v4()));
+    {
+        let store = SqliteStoreBuilder::new(&path, [0; 32])
+            .connect()
+            .await
+            .unwrap();
+        store
+            .set_ilp_address(Address::from_str("example.bob.node").unwrap())
+            .await
+            .unwrap();
+    }
+
+    let store = SqliteStoreBuilder::new(&path, [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_ilp_address(),
+        Address::from_str("example.bob.node").unwrap()
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn insert_accounts() {
+    let (store, _) = test_store().await.unwrap();
+    let account = store
+        .insert_account(ACCOUNT_DETAILS_2.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        *account.ilp_address(),
+        Address::from_str("example.alice.user1.charlie").unwrap()
+    );
+    assert_eq!(
+        store.routing_table().get("example.alice.user1.charlie"),
+        Some(&account.id())
+    );
+
+    // cannot insert duplicate accounts
+    let err = store
+        .insert_account(ACCOUNT_DETAILS_2.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "account `charlie` already exists");
+}
+
+#[tokio::test]
+async fn inserts_batches_of_accounts_atomically() {
+    let (store, _) = test_store().await.unwrap();
+    let err = store
+        .insert_accounts(vec![
+            ACCOUNT_DETAILS_2.clone(),
+            ACCOUNT_DETAILS_0.clone(),
+            ACCOUNT_DETAILS_2.clone(),
+        ])
+        .await
+        .unwrap_err();
+    match err {
+        NodeStoreError::InvalidAccounts(errors) => {
+            let errors: Vec<_> = errors
+                .iter()
+                .map(|(index, err)| (*index, err.to_string()))
+                .collect();
+            assert_eq!(
+                errors,
+                vec![
+                    (1, "account `alice` already exists".to_owned()),
+                    (2, "account `charlie` already exists".to_owned()),
+                ]
+            );
+        }
+        err => panic!("unexpected error: {}", err),
+    }
+    // None of the accounts were inserted
+    assert_eq!(store.get_all_accounts().await.unwrap().len(), 2);
+
+    let accounts = store
+        .insert_accounts(vec![ACCOUNT_DETAILS_2.clone()])
+        .await
+        .unwrap();
+    assert_eq!(
+        store.routing_table().get("example.alice.user1.charlie"),
+        Some(&accounts[0].id())
+    );
+}
+
+#[tokio::test]
+async fn gets_accounts_in_order() {
+    let (store, accs) = test_store().await.unwrap();
+    let accounts = store
+        .get_accounts(vec![accs[1].id(), accs[0].id()])
+        .await
+        .unwrap();
+    assert_eq!(accounts[0].id(), accs[1].id());
+    assert_eq!(accounts[1].id(), accs[0].id());
+
+    let err = store
+        .get_accounts(vec![accs[0].id(), Uuid::new_v4()])
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "wrong account length (expected 2, got 1)");
+}
+
+#[tokio::test]
+async fn authenticates_with_tokens() {
+    let (store, accs) = test_store().await.unwrap();
+    let alice = Username::from_str("alice").unwrap();
+    let account = store
+        .get_account_from_http_auth(&alice, "incoming_auth_token")
+        .await
+        .unwrap();
+    assert_eq!(account.id(), accs[0].id());
+    let account = store
+        .get_account_from_btp_auth(&alice, "btp_token")
+        .await
+        .unwrap();
+    assert_eq!(account.id(), accs[0].id());
+
+    assert!(store
+        .get_account_from_http_auth(&alice, "wrong_token")
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn modifies_account_settings() {
+    let (store, accs) = test_store().await.unwrap();
+    let settings = AccountSettings {
+        ilp_over_http_incoming_token: Some(SecretString::new("new_token".to_owned())),
+        settle_threshold: Some(50),
+        settle_every: Some(60),
+        ..Default::default()
+    };
+    let account = store
+        .modify_account_settings(accs[0].id(), settings)
+        .await
+        .unwrap();
+    assert_eq!(account.settle_every(), Some(Duration::from_secs(60)));
+    let account = store
+        .get_account_from_http_auth(&Username::from_str("alice").unwrap(), "new_token")
+        .await
+        .unwrap();
+    assert_eq!(account.id(), accs[0].id());
+}
+
+#[tokio::test]
+async fn deletes_account() {
+    let (store, accs) = test_store().await.unwrap();
+    let account = store.delete_account(accs[1].id()).await.unwrap();
+    assert_eq!(account.id(), accs[1].id());
+    assert!(store
+        .get_account_id_from_username(&Username::from_str("bob").unwrap())
+        .await
+        .is_err());
+    assert!(store
+        .routing_table()
+        .get(&account.ilp_address().to_string())
+        .is_none());
+}
+
+#[tokio::test]
+async fn delete_account_removes_routes_and_parent() {
+    let (store, accs) = test_store().await.unwrap();
+    // alice is the parent
+    let id = accs[0].id();
+    store
+        .set_static_route("example.static".to_string(), id)
+        .await
+        .unwrap();
+    store.set_default_route(id).await.unwrap();
+    store.delete_account(id).await.unwrap();
+
+    assert!(!store
+        .routing_table()
+        .values()
+        .any(|account_id| *account_id == id));
+    // a new parent can be inserted after the previous one was deleted
+    store
+        .insert_account(ACCOUNT_DETAILS_0.clone())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn multiple_parents_allowed() {
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.routing_relation = Some("Parent".to_owned());
+    details.routing_preference = Some(10);
+    let (store, accs) = test_store().await.unwrap();
+    let parent = store.insert_account(details).await.unwrap();
+    assert_eq!(parent.routing_preference(), 10);
+    let parents = store
+        .list_accounts(
+            None,
+            10,
+            AccountFilter {
+                routing_relation: Some(RoutingRelation::Parent),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(parents.accounts.len(), 2);
+
+    // the address assigned by the parents is kept until the last one is deleted
+    let address = store.get_ilp_address();
+    store.delete_account(accs[0].id()).await.unwrap();
+    assert_eq!(store.get_ilp_address(), address);
+}
+
+#[tokio::test]
+async fn update_account_changes_username_and_address() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[1].id();
+    let mut new = ACCOUNT_DETAILS_1.clone();
+    new.username = Username::from_str("robert").unwrap();
+    new.ilp_address = Some(Address::from_str("example.robert").unwrap());
+    store.update_account(id, new.clone()).await.unwrap();
+
+    assert_eq!(
+        store
+            .get_account_id_from_username(&Username::from_str("robert").unwrap())
+            .await
+            .unwrap(),
+        id
+    );
+    let routing_table = store.routing_table();
+    assert_eq!(routing_table.get("example.robert"), Some(&id));
+    assert!(!routing_table.contains_key(&accs[1].ilp_address().to_string()));
+
+    // cannot take the username of another account
+    new.username = Username::from_str("alice").unwrap();
+    let err = store.update_account(id, new).await.unwrap_err();
+    assert_eq!(err.to_string(), "account `alice` already exists");
+}
+
+#[tokio::test]
+async fn exports_and_imports_node() {
+    let (store, accs) = test_store().await.unwrap();
+    store
+        .set_static_route("example.other".to_string(), accs[1].id())
+        .await
+        .unwrap();
+    store
+        .update_balances_for_prepare(accs[0].id(), 100, None)
+        .await
+        .unwrap();
+    let export = store.export_node().await.unwrap();
+    assert_eq!(export.accounts.len(), 2);
+    assert!(store.import_node(export.clone()).await.is_err());
+
+    let imported = SqliteStoreBuilder::in_memory([1; 32])
+        .connect()
+        .await
+        .unwrap();
+    imported.import_node(export).await.unwrap();
+    assert_eq!(imported.get_ilp_address(), store.get_ilp_address());
+    assert_eq!(imported.get_balance(accs[0].id()).await.unwrap(), -100);
+    assert_eq!(
+        imported.routing_table().get("example.other"),
+        Some(&accs[1].id())
+    );
+    let account = imported
+        .get_account_from_http_auth(accs[1].username(), "incoming_auth_token")
+        .await
+        .unwrap();
+    assert_eq!(account.ilp_address(), accs[1].ilp_address());
+}
+
+#[tokio::test]
+async fn lists_accounts_page_by_page() {
+    let (store, accs) = test_store().await.unwrap();
+    let mut ids: Vec<Uuid> = accs.iter().map(|account| account.id()).collect();
+    ids.sort();
+
+    let page = store
+        .list_accounts(None, 1, AccountFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(page.accounts.len(), 1);
+    assert_eq!(page.accounts[0].id(), ids[0]);
+    assert_eq!(page.next, Some(ids[0]));
+
+    let page = store
+        .list_accounts(page.next, 1, AccountFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(page.accounts.len(), 1);
+    assert_eq!(page.accounts[0].id(), ids[1]);
+    assert_eq!(page.next, None);
+
+    let page = store
+        .list_accounts(
+            None,
+            10,
+            AccountFilter {
+                asset_code: Some("ABC".to_string()),
+                routing_relation: Some(RoutingRelation::Child),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(page.accounts.len(), 1);
+    assert_eq!(page.accounts[0].id(), accs[1].id());
+    assert_eq!(page.next, None);
+
+    let page = store
+        .list_accounts(
+            None,
+            10,
+            AccountFilter {
+                ilp_address_prefix: Some("example.alice.user1".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(page.accounts.len(), 1);
+    assert_eq!(page.accounts[0].id(), accs[1].id());
+}
+
+#[tokio::test]
+async fn saves_secret_generation() {
+    let (store, _accs) = test_store().await.unwrap();
+    assert_eq!(
+        store.get_secret_generation().await.unwrap(),
+        SecretGeneration::default()
+    );
+    let generation = SecretGeneration::default().rotate(Duration::from_secs(60));
+    store.set_secret_generation(generation).await.unwrap();
+    assert_eq!(store.get_secret_generation().await.unwrap(), generation);
+}
+
+#[tokio::test]
+async fn hands_over_leadership_once_the_lease_expires() {
+    let (store, _accs) = test_store().await.unwrap();
+    let lease = Duration::from_millis(100);
+    assert!(store.claim_leadership("active", lease).await.unwrap());
+    assert!(!store.claim_leadership("standby", lease).await.unwrap());
+    // The leader renews its lease
+    assert!(store.claim_leadership("active", lease).await.unwrap());
+
+    tokio::time::sleep(lease * 2).await;
+    assert!(store.claim_leadership("standby", lease).await.unwrap());
+    assert!(!store.claim_leadership("active", lease).await.unwrap());
+}
+
+#[tokio::test]
+async fn saves_min_packet_amount() {
+    let (store, accs) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.min_packet_amount = Some(10);
+    let account = store.insert_account(details).await.unwrap();
+    let accounts = store.get_accounts(vec![account.id()]).await.unwrap();
+    assert_eq!(accounts[0].min_packet_amount(), 10);
+
+    // Accounts without a minimum forward packets of any amount
+    assert_eq!(accs[0].min_packet_amount(), 0);
+}
+
+#[tokio::test]
+async fn limits_daily_volume() {
+    let (store, _accs) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.max_payment_amount = Some(100);
+    details.amount_per_day_limit = Some(1000);
+    let account = store.insert_account(details).await.unwrap();
+    let account = store.get_accounts(vec![account.id()]).await.unwrap()[0].clone();
+    assert_eq!(account.max_payment_amount(), Some(100));
+    assert_eq!(account.amount_per_day_limit(), Some(1000));
+    let day = current_day();
+
+    store
+        .charge_daily_volume(account.clone(), day, 600)
+        .await
+        .unwrap();
+    assert_eq!(
+        store.charge_daily_volume(account.clone(), day, 500).await,
+        Err(VolumeLimitError::DailyLimitExceeded)
+    );
+
+    // Refunded amounts can be sent again and the totals of other days are separate
+    store
+        .refund_daily_volume(account.clone(), day, 200)
+        .await
+        .unwrap();
+    store
+        .charge_daily_volume(account.clone(), day, 500)
+        .await
+        .unwrap();
+    store
+        .charge_daily_volume(account.clone(), day + 1, 1000)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,402 @@
+use super::store_helpers::*;
+
+use interledger_service::Account as AccountTrait;
+use interledger_service_util::{
+    BalanceChangeReason, BalanceStore, BalanceTotals, JournaledPacket, PacketJournalStore,
+};
+use interledger_settlement::core::types::{
+    OutgoingSettlement, OutgoingSettlementStatus, OutgoingSettlementStore, SettlementStore,
+};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn prepare_then_fulfill_with_settlement() {
+    let (store, accs) = test_store().await.unwrap();
+    let account0_id = accs[0].id();
+    let account1_id = accs[1].id();
+    // reduce account 0's balance by 100
+    store
+        .update_balances_for_prepare(account0_id, 100, None)
+        .await
+        .unwrap();
+    let balance0 = store.get_balance(account0_id).await.unwrap();
+    let balance1 = store.get_balance(account1_id).await.unwrap();
+    assert_eq!(balance0, -100);
+    assert_eq!(balance1, 0);
+
+    // account 1 has settle_threshold 0 and settle_to -1000
+    let (balance, settle_amount) = store
+        .update_balances_for_fulfill(account1_id, 100, None)
+        .await
+        .unwrap();
+    assert_eq!(balance, -1000);
+    assert_eq!(settle_amount, 1100);
+}
+
+#[tokio::test]
+async fn prepare_fails_below_min_balance() {
+    let (store, accs) = test_store().await.unwrap();
+    // account 0 has a min balance of -1000
+    assert!(store
+        .update_balances_for_prepare(accs[0].id(), 1001, None)
+        .await
+        .is_err());
+    assert_eq!(store.get_balance(accs[0].id()).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn reject_reverts_prepare() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    store
+        .update_balances_for_prepare(id, 100, None)
+        .await
+        .unwrap();
+    store
+        .update_balances_for_reject(id, 100, None)
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn incoming_settlement_is_idempotent() {
+    let (store, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    for _ in 0..2 {
+        store
+            .update_balance_for_incoming_settlement(id, 100, Some("key".to_owned()))
+            .await
+            .unwrap();
+    }
+    assert_eq!(store.get_balance(id).await.unwrap(), 100);
+}
+
+#[tokio::test]
+async fn balance_changes_are_logged() {
+    let (store, accs) = test_store().await.unwrap();
+    let account0_id = accs[0].id();
+    let account1_id = accs[1].id();
+    store
+        .update_balances_for_prepare(account0_id, 100, Some([1; 32]))
+        .await
+        .unwrap();
+    store
+        .update_balances_for_reject(account0_id, 100, Some([1; 32]))
+        .await
+        .unwrap();
+    store
+        .update_balance_for_incoming_settlement(account0_id, 50, Some("key".to_owned()))
+        .await
+        .unwrap();
+    // account 1 has settle_threshold 0 and settle_to -1000
+    store
+        .update_balances_for_fulfill(account1_id, 100, Some([2; 32]))
+        .await
+        .unwrap();
+    store.refund_settlement(account1_id, 1100).await.unwrap();
+
+    let changes = store.get_balance_changes(account0_id, 0, 10).await.unwrap();
+    let summary: Vec<_> = changes
+        .iter()
+        .map(|change| (change.sequence, change.reason, change.delta, change.balance))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (0, BalanceChangeReason::Prepare, -100, -100),
+            (1, BalanceChangeReason::Reject, 100, 0),
+            (2, BalanceChangeReason::IncomingSettlement, 50, 50),
+        ]
+    );
+    assert_eq!(changes[0].packet_id, Some(hex::encode([1; 32])));
+    assert_eq!(changes[2].packet_id, None);
+
+    let changes = store.get_balance_changes(account1_id, 1, 10).await.unwrap();
+    let summary: Vec<_> = changes
+        .iter()
+        .map(|change| (change.sequence, change.reason, change.delta, change.balance))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (1, BalanceChangeReason::Settlement, -1100, -1000),
+            (2, BalanceChangeReason::SettlementRefund, 1100, 100),
+        ]
+    );
+    assert_eq!(changes[0].packet_id, Some(hex::encode([2; 32])));
+    assert!(store
+        .get_balance_changes(account1_id, 3, 10)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn balance_totals_are_summed_per_reason() {
+    let (store, accs) = test_store().await.unwrap();
+    let account0_id = accs[0].id();
+    let account1_id = accs[1].id();
+    for _ in 0..2 {
+        store
+            .update_balances_for_prepare(account0_id, 100, None)
+            .await
+            .unwrap();
+    }
+    store
+        .update_balances_for_reject(account0_id, 100, None)
+        .await
+        .unwrap();
+    store
+        .update_balance_for_incoming_settlement(account0_id, 50, None)
+        .await
+        .unwrap();
+    // account 1 has settle_threshold 0 and settle_to -1000
+    store
+        .update_balances_for_fulfill(account1_id, 100, None)
+        .await
+        .unwrap();
+    store.refund_settlement(account1_id, 1100).await.unwrap();
+
+    assert_eq!(
+        store.get_balance_totals(account0_id).await.unwrap(),
+        BalanceTotals {
+            prepare: -200,
+            reject: 100,
+            incoming_settlement: 50,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        store.get_balance_totals(account1_id).await.unwrap(),
+        BalanceTotals {
+            fulfill: 100,
+            settlement: -1100,
+            settlement_refund: 1100,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        store.get_balance_totals(Uuid::new_v4()).await.unwrap(),
+        BalanceTotals::default()
+    );
+}
+
+#[tokio::test]
+async fn outgoing_settlements_are_refunded_once() {
+    let (store, accs) = test_store().await.unwrap();
+    let account1_id = accs[1].id();
+    // account 1 has settle_threshold 0 and settle_to -1000
+    let (_, settle_amount) = store
+        .update_balances_for_fulfill(account1_id, 100, None)
+        .await
+        .unwrap();
+    let settlement = OutgoingSettlement {
+        idempotency_key: "key".to_owned(),
+        account_id: account1_id,
+        amount: settle_amount,
+        status: OutgoingSettlementStatus::Pending,
+        attempts: 0,
+        next_attempt_at: 1000,
+    };
+    store
+        .save_outgoing_settlement(settlement.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        store.load_outgoing_settlements().await.unwrap(),
+        vec![settlement]
+    );
+
+    store.refund_outgoing_settlement("key").await.unwrap();
+    store.refund_outgoing_settlement("key").await.unwrap();
+    assert_eq!(store.get_balance(account1_id).await.unwrap(), 100);
+    assert!(store.load_outgoing_settlements().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn removed_outgoing_settlements_are_not_refunded() {
+    let (store, accs) = test_store().await.unwrap();
+    let account1_id = accs[1].id();
+    let (_, settle_amount) = store
+        .update_balances_for_fulfill(account1_id, 100, None)
+        .await
+        .unwrap();
+    store
+        .save_outgoing_settlement(OutgoingSettlement {
+            idempotency_key: "key".to_owned(),
+            account_id: account1_id,
+            amount: settle_amount,
+            status: OutgoingSettlementStatus::Unknown,
+            attempts: 1,
+            next_attempt_at: 1000,
+        })
+        .await
+        .unwrap();
+
+    store.remove_outgoing_settlement("key").await.unwrap();
+    store.refund_outgoing_settlement("key").await.unwrap();
+    assert_eq!(store.get_balance(account1_id).await.unwrap(), -1000);
+    assert!(store.load_outgoing_settlements().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn outgoing_settlements_are_queued_while_one_is_in_flight() {
+    let (store, accs) = test_store().await.unwrap();
+    let account_id = accs[1].id();
+    let settlement = OutgoingSettlement {
+        idempotency_key: "first".to_owned(),
+        account_id,
+        amount: 100,
+        status: OutgoingSettlementStatus::Pending,
+        attempts: 0,
+        next_attempt_at: 1000,
+    };
+    assert!(store
+        .queue_outgoing_settlement(settlement.clone())
+        .await
+        .unwrap());
+    for key in &["second", "third"] {
+        let queued = OutgoingSettlement {
+            idempotency_key: key.to_string(),
+            ..settlement.clone()
+        };
+        assert!(!store.queue_outgoing_settlement(queued).await.unwrap());
+    }
+    assert_eq!(
+        store.load_outgoing_settlements().await.unwrap(),
+        vec![settlement]
+    );
+    assert_eq!(
+        store.load_queued_settlement_accounts().await.unwrap(),
+        vec![account_id]
+    );
+    // Nothing is dequeued while the first settlement is in flight
+    assert_eq!(
+        store
+            .dequeue_outgoing_settlement(account_id, "next".to_owned(), 2000)
+            .await
+            .unwrap(),
+        None
+    );
+
+    store.remove_outgoing_settlement("first").await.unwrap();
+    let next = OutgoingSettlement {
+        idempotency_key: "next".to_owned(),
+        account_id,
+        amount: 200,
+        status: OutgoingSettlementStatus::Pending,
+        attempts: 0,
+        next_attempt_at: 2000,
+    };
+    assert_eq!(
+        store
+            .dequeue_outgoing_settlement(account_id, "next".to_owned(), 2000)
+            .await
+            .unwrap(),
+        Some(next.clone())
+    );
+    assert_eq!(store.load_outgoing_settlements().await.unwrap(), vec![next]);
+    assert!(store
+        .load_queued_settlement_accounts()
+        .await
+        .unwrap()
+        .is_empty());
+
+    // The refunded settlement is not in flight anymore either
+    store.refund_outgoing_settlement("next").await.unwrap();
+    assert!(store
+        .queue_outgoing_settlement(OutgoingSettlement {
+            idempotency_key: "last".to_owned(),
+            account_id,
+            amount: 100,
+            status: OutgoingSettlementStatus::Pending,
+            attempts: 0,
+            next_attempt_at: 3000,
+        })
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn amounts_to_settle_are_queued_with_the_balance_change() {
+    let (store, accs) = test_store().await.unwrap();
+    let account_id = accs[1].id();
+    // account 1 has settle_threshold 0 and settle_to -1000
+    let (balance, settle_amount) = store
+        .update_balances_for_fulfill_and_queue_settlement(account_id, 100, None)
+        .await
+        .unwrap();
+    assert_eq!((balance, settle_amount), (-1000, 1100));
+    // Nothing but the store is needed to send the settlement, even if the node
+    // stopped right after the fulfill
+    assert_eq!(
+        store.load_queued_settlement_accounts().await.unwrap(),
+        vec![account_id]
+    );
+    let settlement = store
+        .dequeue_outgoing_settlement(account_id, "first".to_owned(), 1000)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(settlement.amount, 1100);
+
+    // The amount of a delayed settlement is queued the same way
+    store
+        .update_balances_for_fulfill(account_id, 100, None)
+        .await
+        .unwrap();
+    let (balance, settle_amount) = store
+        .update_balances_for_delayed_settlement(account_id)
+        .await
+        .unwrap();
+    assert_eq!((balance, settle_amount), (-1000, 100));
+    store.remove_outgoing_settlement("first").await.unwrap();
+    let settlement = store
+        .dequeue_outgoing_settlement(account_id, "second".to_owned(), 2000)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(settlement.amount, 100);
+
+    // The amount settled by the caller itself is not queued
+    store
+        .update_balances_for_fulfill(account_id, 1100, None)
+        .await
+        .unwrap();
+    assert!(store
+        .load_queued_settlement_accounts()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn journals_packets_in_flight() {
+    let (store, accs) = test_store().await.unwrap();
+    let packet = JournaledPacket {
+        id: Uuid::new_v4(),
+        from_id: accs[0].id(),
+        to_id: accs[1].id(),
+        incoming_amount: 100,
+        outgoing_amount: 90,
+        packet_id: Some([7; 32]),
+        expires_at: 1000,
+        fulfilled: false,
+    };
+    store.journal_packet(packet.clone()).await.unwrap();
+    assert_eq!(
+        store.load_journaled_packets().await.unwrap(),
+        vec![packet.clone()]
+    );
+
+    store
+        .mark_journaled_packet_fulfilled(packet.id)
+        .await
+        .unwrap();
+    let journaled = store.load_journaled_packets().await.unwrap();
+    assert!(journaled[0].fulfilled);
+
+    store.remove_journaled_packet(packet.id).await.unwrap();
+    assert!(store.load_journaled_packets().await.unwrap().is_empty());
+}
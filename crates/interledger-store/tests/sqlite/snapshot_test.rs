@@ -0,0 +1,74 @@
+use super::fixtures::*;
+use interledger_api::NodeStore;
+use interledger_service::{Account as AccountTrait, AccountStore, Username};
+use interledger_service_util::BalanceStore;
+use interledger_store::sqlite::SqliteStoreBuilder;
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn restores_in_memory_store_from_snapshot() {
+    let path = std::env::temp_dir().join(format!("ilp-snapshot-test-{}.db", Uuid::new_v4()));
+    let store = SqliteStoreBuilder::in_memory([0; 32])
+        .snapshot_path(&path)
+        .connect()
+        .await
+        .unwrap();
+    let account = store
+        .insert_account(ACCOUNT_DETAILS_2.clone())
+        .await
+        .unwrap();
+    store
+        .update_balances_for_fulfill(account.id(), 100, None)
+        .await
+        .unwrap();
+    store.save_snapshot(&path).unwrap();
+    drop(store);
+
+    let store = SqliteStoreBuilder::in_memory([0; 32])
+        .snapshot_path(&path)
+        .connect()
+        .await
+        .unwrap();
+    let id = store
+        .get_account_id_from_username(&Username::from_str("charlie").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(id, account.id());
+    // charlie has a settle_threshold of 0 but no settle_to, so no settlement is triggered
+    assert_eq!(store.get_balance(id).await.unwrap(), 100);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn in_memory_store_starts_empty_without_snapshot() {
+    let store = SqliteStoreBuilder::in_memory([0; 32])
+        .connect()
+        .await
+        .unwrap();
+    assert!(store.get_all_accounts().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn snapshot_leaves_files_with_the_same_stem_alone() {
+    let dir = std::env::temp_dir().join(format!("ilp-snapshot-test-{}", Uuid::new_v4()));
+    std::fs::create_dir(&dir).unwrap();
+    let path = dir.join("node.db");
+    let sibling = dir.join("node.tmp");
+    std::fs::write(&sibling, b"unrelated").unwrap();
+
+    let store = SqliteStoreBuilder::in_memory([0; 32])
+        .connect()
+        .await
+        .unwrap();
+    store.save_snapshot(&path).unwrap();
+    assert_eq!(std::fs::read(&sibling).unwrap(), b"unrelated");
+    assert!(path.exists());
+
+    // A snapshot whose extension is the temporary one is written too
+    let path = dir.join("snapshot.tmp");
+    store.save_snapshot(&path).unwrap();
+    assert!(path.exists());
+    assert!(!dir.join("snapshot.tmp.tmp").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
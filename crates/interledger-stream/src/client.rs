@@ -2,13 +2,12 @@ use super::congestion::CongestionController;
 use super::crypto::*;
 use super::error::Error;
 use super::packet::*;
+use super::retry::{RejectAction, RetryPolicy};
+use super::runtime::{self, timeout_at, Instant};
 use bytes::Bytes;
-use bytes::BytesMut;
+use futures::future;
 use futures::stream::{FuturesUnordered, StreamExt};
-use interledger_packet::{
-    Address, ErrorClass, ErrorCode as IlpErrorCode, PacketType as IlpPacketType, PrepareBuilder,
-    Reject,
-};
+use interledger_packet::{pool, Address, PacketType as IlpPacketType, PrepareBuilder, Reject};
 use interledger_rates::ExchangeRateStore;
 use interledger_service::*;
 use num::rational::BigRational;
@@ -19,20 +18,25 @@ use num::traits::pow::pow;
 use num::BigInt;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
-use tokio::time::timeout_at;
-use tokio::time::{Duration, Instant};
 use tracing::{debug, error, warn};
 
 use std::cmp::{max, min};
 use std::marker::{Send, Sync};
 use std::str;
-use std::sync::Arc;
-use std::time::SystemTime;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex as StdMutex,
+};
+use std::time::Duration;
 
 /// Maximum time we should wait since last fulfill before we error out to avoid
 /// getting into an infinite loop of sending packets and effectively DoSing ourselves
 const MAX_TIME_SINCE_LAST_FULFILL: Duration = Duration::from_secs(30);
 
+/// How often a payment sent by `stream_money` checks whether it was stopped and how much
+/// more money it may send
+const PACING_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Minimum number of packet attempts before defaulting to failure rate
 const FAIL_FAST_MINIMUM_PACKET_ATTEMPTS: u64 = 200;
 
@@ -101,6 +105,12 @@ struct StreamPayment {
     fail_fast_rejects: u64,
     /// Timestamp when a packet was last fulfilled for this payment
     last_fulfill_time: Instant,
+    /// How to react to the rejected packets
+    retry_policy: RetryPolicy,
+    /// Number of rejects since the last fulfill which made the payment back off
+    consecutive_backoffs: u32,
+    /// No packets are sent until then, after a reject which made the payment back off
+    backoff_until: Option<Instant>,
 }
 
 impl StreamPayment {
@@ -197,11 +207,13 @@ impl StreamPayment {
 
         self.last_fulfill_time = Instant::now();
         self.fulfilled_packets += 1;
+        self.consecutive_backoffs = 0;
     }
 
-    /// Account for a rejected packet and update flow control
+    /// Account for a rejected packet and update flow control.
+    /// Returns the action the retry policy takes for the reject
     #[inline]
-    fn apply_reject(&mut self, amount: u64, reject: &Reject) {
+    fn apply_reject(&mut self, amount: u64, reject: &Reject) -> RejectAction {
         self.congestion_controller.reject(amount, reject);
 
         self.receipt.sent_amount = self.receipt.sent_amount.saturating_sub(amount);
@@ -209,16 +221,31 @@ impl StreamPayment {
 
         self.rejected_packets += 1;
 
-        // Apply F99, T00, T01 to fail-fast threshold.
-        // Other final/relative errors should immediately fail; T02-T99 may be resolved with time.
-        let apply_to_fail_fast = matches!(
-            reject.code(),
-            IlpErrorCode::T00_INTERNAL_ERROR
-                | IlpErrorCode::T01_PEER_UNREACHABLE
-                | IlpErrorCode::F99_APPLICATION_ERROR
-        );
-        if apply_to_fail_fast {
-            self.fail_fast_rejects += 1;
+        let action = self.retry_policy.action(reject.code());
+        match action {
+            RejectAction::FailFast => self.fail_fast_rejects += 1,
+            RejectAction::HalvePacketSize => {
+                self.congestion_controller.halve_max_packet_amount(amount)
+            }
+            RejectAction::Backoff => {
+                self.consecutive_backoffs = self.consecutive_backoffs.saturating_add(1);
+                let delay = self.retry_policy.backoff_delay(self.consecutive_backoffs);
+                self.backoff_until = Instant::now().checked_add(delay);
+            }
+            RejectAction::Retry | RejectAction::Abort => {}
+        }
+        action
+    }
+
+    /// Deadline until which the payment backs off, if it does
+    #[inline]
+    fn backoff_deadline(&mut self) -> Option<Instant> {
+        match self.backoff_until {
+            Some(deadline) if deadline > Instant::now() => Some(deadline),
+            _ => {
+                self.backoff_until = None;
+                None
+            }
         }
     }
 
@@ -303,124 +330,177 @@ where
     A: Account + Send + Sync + 'static,
     S: ExchangeRateStore + Send + Sync + 'static,
 {
-    let shared_secret = Bytes::from(shared_secret);
-
-    let from = from_account.ilp_address();
-    if from.scheme() != destination_account.scheme() {
-        warn!(
-            "Destination ILP address starts with a different scheme prefix (\"{}\') than ours (\"{}\'), this probably won't work",
-            destination_account.scheme(),
-            from.scheme()
-        );
-    }
+    send_money_with_retry_policy(
+        service,
+        from_account,
+        store,
+        destination_account,
+        shared_secret,
+        source_amount,
+        slippage,
+        RetryPolicy::default(),
+    )
+    .await
+}
 
-    let mut sender = StreamSender {
-        next: service,
-        from_account: from_account.clone(),
+/// Like [`send_money`], but reacts to the rejected packets as the retry policy says
+#[allow(clippy::too_many_arguments)]
+pub async fn send_money_with_retry_policy<I, A, S>(
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: Vec<u8>,
+    source_amount: u64,
+    slippage: f64,
+    retry_policy: RetryPolicy,
+) -> Result<StreamDelivery, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    // TODO Make configurable to get money flowing ASAP vs as much as possible per-packet
+    let congestion_controller = CongestionController::new(source_amount, source_amount / 10, 2.0);
+    StreamSender::new(
+        service,
+        from_account,
+        store,
+        StreamDelivery::new(from_account, destination_account, source_amount),
+        congestion_controller,
         shared_secret,
+        slippage,
+        retry_policy,
+    )
+    .run(None)
+    .await
+}
+
+/// Send money continuously with the STREAM transport protocol, at `rate_per_second` source units
+/// per second, until the handle is stopped or `max_amount` was sent. The amounts of the packets
+/// adjust to the capacity of the path as they do in [`send_money`], and the money which could not
+/// be sent as fast as the rate allows is sent once the path catches up.
+/// Returns the receipt with sent & delivered amounts, asset & account details
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_money<I, A, S>(
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: Vec<u8>,
+    rate_per_second: u64,
+    max_amount: Option<u64>,
+    slippage: f64,
+    handle: StreamHandle,
+) -> Result<StreamDelivery, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    // The amount to send grows with the time the payment has been running
+    let congestion_controller =
+        CongestionController::new(rate_per_second, rate_per_second / 10, 2.0);
+    StreamSender::new(
+        service,
+        from_account,
         store,
+        StreamDelivery::new(from_account, destination_account, 0),
+        congestion_controller,
+        shared_secret,
         slippage,
-        payment: Arc::new(Mutex::new(StreamPayment {
-            // TODO Make configurable to get money flowing ASAP vs as much as possible per-packet
-            congestion_controller: CongestionController::new(
-                source_amount,
-                source_amount / 10,
-                2.0,
-            ),
-            receipt: StreamDelivery::new(from_account, destination_account, source_amount),
-            should_send_source_account: true,
-            sequence: 1,
-            fulfilled_packets: 0,
-            rejected_packets: 0,
-            fail_fast_rejects: 0,
-            last_fulfill_time: Instant::now(),
-        })),
-    };
+        handle.retry_policy.clone(),
+    )
+    .run(Some(Pace {
+        rate_per_second,
+        max_amount,
+        started_at: Instant::now(),
+        handle,
+    }))
+    .await
+}
+
+/// Handle of a payment sent by [`stream_money`], to follow its progress and to stop it
+#[derive(Clone, Default)]
+pub struct StreamHandle {
+    stopped: Arc<AtomicBool>,
+    receipt: Arc<StdMutex<Option<StreamDelivery>>>,
+    retry_policy: RetryPolicy,
+}
 
-    let mut pending_requests = FuturesUnordered::new();
-
-    /// Actions corresponding to the state of the payment
-    enum PaymentEvent {
-        /// Send more money: send a packet with the given source amount and minimum destination amount
-        SendMoney((u64, u64)),
-        /// Congestion controller limited in-flight amount: wait for pending requests until given deadline
-        MaxInFlight(Instant),
-        /// Sent full source amount: close the connection and return success
-        CloseConnection,
-        /// Maximum timeout since last fulfill has elapsed: terminate the payment
-        Timeout,
-        /// Too many packets are rejected, such as if the exchange rate is too low: terminate the payment
-        FailFast,
+impl StreamHandle {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    loop {
-        let event = {
-            let mut payment = sender.payment.lock().await;
-
-            if payment.last_fulfill_time.elapsed() >= MAX_TIME_SINCE_LAST_FULFILL {
-                PaymentEvent::Timeout
-            } else if payment.is_failing() {
-                PaymentEvent::FailFast
-            } else if payment.is_complete() {
-                PaymentEvent::CloseConnection
-            } else if payment.is_max_in_flight() {
-                let deadline = payment
-                    .last_fulfill_time
-                    .checked_add(MAX_TIME_SINCE_LAST_FULFILL)
-                    .unwrap();
-                PaymentEvent::MaxInFlight(deadline)
-            } else {
-                PaymentEvent::SendMoney(payment.apply_prepare(&sender.store, sender.slippage))
-            }
-        };
+    /// Makes the payment react to the rejected packets as the retry policy says
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
 
-        match event {
-            PaymentEvent::SendMoney((source_amount, dest_amount)) => {
-                let mut sender = sender.clone();
-                pending_requests.push(tokio::spawn(async move {
-                    sender.send_money_packet(source_amount, dest_amount).await
-                }));
-            }
-            PaymentEvent::MaxInFlight(deadline) => {
-                // Wait for any request to complete, or if after reach deadline since last fulfill,
-                // run loop again, which should timeout the payment
-                let result = timeout_at(deadline, pending_requests.select_next_some()).await;
-
-                if let Ok(Ok(Err(error))) = result {
-                    error!("Send money stopped because of error: {:?}", error);
-                    return Err(error);
-                }
-            }
-            PaymentEvent::CloseConnection => {
-                // Wait for all pending requests to complete before closing the connection
-                pending_requests.map(|_| ()).collect::<()>().await;
+    /// Stops sending money. The payment finishes once the packets in flight were answered.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
 
-                // Try to the tell the recipient the connection is closed
-                sender.try_send_connection_close().await;
+    /// Whether the payment was asked to stop
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
 
-                // Return final receipt
-                let payment = sender.payment.lock().await;
-                debug!(
-                    "Send money future finished. Delivered: {} ({} packets fulfilled, {} packets rejected)",
-                    payment.receipt.delivered_amount,
-                    payment.fulfilled_packets,
-                    payment.rejected_packets,
-                );
-                return Ok(payment.receipt.clone());
-            }
-            PaymentEvent::Timeout => {
-                // Error if we haven't received a fulfill over a timeout period
-                return Err(Error::Timeout);
-            }
-            PaymentEvent::FailFast => {
-                let payment = sender.payment.lock().await;
-                return Err(Error::PaymentFailFast(
-                    payment.fulfilled_packets,
-                    payment.rejected_packets,
-                ));
-            }
-        }
+    /// Receipt of the money sent so far, once the payment started
+    pub fn receipt(&self) -> Option<StreamDelivery> {
+        self.receipt.lock().unwrap().clone()
     }
+
+    fn update(&self, receipt: &StreamDelivery) {
+        *self.receipt.lock().unwrap() = Some(receipt.clone());
+    }
+}
+
+/// Pace of a payment sent by [`stream_money`]
+struct Pace {
+    rate_per_second: u64,
+    max_amount: Option<u64>,
+    started_at: Instant,
+    handle: StreamHandle,
+}
+
+impl Pace {
+    /// Amount which may have been sent by now
+    fn allowance(&self) -> u64 {
+        let amount = self.rate_per_second as f64 * self.started_at.elapsed().as_secs_f64();
+        let amount = amount.min(u64::MAX as f64) as u64;
+        self.max_amount
+            .map_or(amount, |max_amount| min(amount, max_amount))
+    }
+
+    /// Whether the payment was stopped or all of its maximum amount was fulfilled
+    fn is_finished(&self, payment: &StreamPayment) -> bool {
+        self.handle.is_stopped()
+            || self.max_amount.map_or(false, |max_amount| {
+                payment.get_fulfilled_amount() >= max_amount
+            })
+    }
+}
+
+/// Actions corresponding to the state of the payment
+enum PaymentEvent {
+    /// Send more money: send a packet with the given source amount and minimum destination amount
+    SendMoney((u64, u64)),
+    /// Congestion controller limited in-flight amount: wait for pending requests until given deadline
+    MaxInFlight(Instant),
+    /// A reject made the payment back off: wait for pending requests until given deadline
+    Backoff(Instant),
+    /// Streamed payment sent all the money it may send by now: wait until given deadline
+    Paced(Instant),
+    /// Sent full source amount: close the connection and return success
+    CloseConnection,
+    /// Maximum timeout since last fulfill has elapsed: terminate the payment
+    Timeout,
+    /// Too many packets are rejected, such as if the exchange rate is too low: terminate the payment
+    FailFast,
 }
 
 /// Sends and handles all ILP & STREAM packets, encapsulating all payment state
@@ -440,6 +520,166 @@ struct StreamSender<I, A, S> {
     payment: Arc<Mutex<StreamPayment>>,
 }
 
+impl<I, A, S> StreamSender<I, A, S>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore + Send + Sync + 'static,
+{
+    fn new(
+        next: I,
+        from_account: &A,
+        store: S,
+        receipt: StreamDelivery,
+        congestion_controller: CongestionController,
+        shared_secret: Vec<u8>,
+        slippage: f64,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        let from = from_account.ilp_address();
+        if from.scheme() != receipt.to.scheme() {
+            warn!(
+                "Destination ILP address starts with a different scheme prefix (\"{}\') than ours (\"{}\'), this probably won't work",
+                receipt.to.scheme(),
+                from.scheme()
+            );
+        }
+
+        StreamSender {
+            next,
+            from_account: from_account.clone(),
+            shared_secret: Bytes::from(shared_secret),
+            store,
+            slippage,
+            payment: Arc::new(Mutex::new(StreamPayment {
+                congestion_controller,
+                receipt,
+                should_send_source_account: true,
+                sequence: 1,
+                fulfilled_packets: 0,
+                rejected_packets: 0,
+                fail_fast_rejects: 0,
+                last_fulfill_time: Instant::now(),
+                retry_policy,
+                consecutive_backoffs: 0,
+                backoff_until: None,
+            })),
+        }
+    }
+
+    /// Send packets until the payment is complete, or, if it is paced, until it is finished
+    async fn run(mut self, pace: Option<Pace>) -> Result<StreamDelivery, Error> {
+        let mut pending_requests = FuturesUnordered::new();
+
+        loop {
+            let event = {
+                let mut payment = self.payment.lock().await;
+                if let Some(ref pace) = pace {
+                    payment.receipt.source_amount = pace.allowance();
+                    pace.handle.update(&payment.receipt);
+                }
+
+                if payment.last_fulfill_time.elapsed() >= MAX_TIME_SINCE_LAST_FULFILL {
+                    PaymentEvent::Timeout
+                } else if payment.is_failing() {
+                    PaymentEvent::FailFast
+                } else if pace
+                    .as_ref()
+                    .map_or_else(|| payment.is_complete(), |pace| pace.is_finished(&payment))
+                {
+                    PaymentEvent::CloseConnection
+                } else if let Some(deadline) = payment.backoff_deadline() {
+                    PaymentEvent::Backoff(deadline)
+                } else if pace.is_some()
+                    && pending_requests.is_empty()
+                    && payment.is_max_in_flight()
+                {
+                    // Waiting for the allowance to grow is not waiting for a fulfill
+                    payment.last_fulfill_time = Instant::now();
+                    PaymentEvent::Paced(next_pacing_deadline())
+                } else if payment.is_max_in_flight() {
+                    let deadline = payment
+                        .last_fulfill_time
+                        .checked_add(MAX_TIME_SINCE_LAST_FULFILL)
+                        .unwrap();
+                    match pace {
+                        // Check for a stop and a larger allowance while waiting
+                        Some(_) if next_pacing_deadline() < deadline => {
+                            PaymentEvent::MaxInFlight(next_pacing_deadline())
+                        }
+                        _ => PaymentEvent::MaxInFlight(deadline),
+                    }
+                } else {
+                    PaymentEvent::SendMoney(payment.apply_prepare(&self.store, self.slippage))
+                }
+            };
+
+            match event {
+                PaymentEvent::SendMoney((source_amount, dest_amount)) => {
+                    let mut sender = self.clone();
+                    pending_requests.push(runtime::spawn(async move {
+                        sender.send_money_packet(source_amount, dest_amount).await
+                    }));
+                }
+                PaymentEvent::Backoff(deadline) if pending_requests.is_empty() => {
+                    let _ = timeout_at(deadline, future::pending::<()>()).await;
+                }
+                PaymentEvent::MaxInFlight(deadline) | PaymentEvent::Backoff(deadline) => {
+                    // Wait for any request to complete, or if after reach deadline since last fulfill,
+                    // run loop again, which should timeout the payment
+                    let result = timeout_at(deadline, pending_requests.select_next_some()).await;
+
+                    if let Ok(Ok(Err(error))) = result {
+                        error!("Send money stopped because of error: {:?}", error);
+                        return Err(error);
+                    }
+                }
+                PaymentEvent::Paced(deadline) => {
+                    let _ = timeout_at(deadline, future::pending::<()>()).await;
+                }
+                PaymentEvent::CloseConnection => {
+                    // Wait for all pending requests to complete before closing the connection
+                    pending_requests.map(|_| ()).collect::<()>().await;
+
+                    // Try to the tell the recipient the connection is closed
+                    self.try_send_connection_close().await;
+
+                    // Return final receipt
+                    let mut payment = self.payment.lock().await;
+                    debug!(
+                        "Send money future finished. Delivered: {} ({} packets fulfilled, {} packets rejected)",
+                        payment.receipt.delivered_amount,
+                        payment.fulfilled_packets,
+                        payment.rejected_packets,
+                    );
+                    if let Some(ref pace) = pace {
+                        // The amount of a streamed payment is the amount it ended up sending
+                        payment.receipt.source_amount = payment.receipt.sent_amount;
+                        pace.handle.update(&payment.receipt);
+                    }
+                    return Ok(payment.receipt.clone());
+                }
+                PaymentEvent::Timeout => {
+                    // Error if we haven't received a fulfill over a timeout period
+                    return Err(Error::Timeout);
+                }
+                PaymentEvent::FailFast => {
+                    let payment = self.payment.lock().await;
+                    return Err(Error::PaymentFailFast(
+                        payment.fulfilled_packets,
+                        payment.rejected_packets,
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// When a paced payment next checks whether it was stopped and how much it may send
+fn next_pacing_deadline() -> Instant {
+    Instant::now().checked_add(PACING_INTERVAL).unwrap()
+}
+
 impl<I, A, S> StreamSender<I, A, S>
 where
     I: IncomingService<A>,
@@ -453,6 +693,7 @@ where
         source_amount: u64,
         min_destination_amount: u64,
     ) -> Result<(), Error> {
+        let verbose = telemetry::sample_packet();
         let (prepare, sequence) = {
             let mut payment = self.payment.lock().await;
 
@@ -475,10 +716,12 @@ where
             }
             .build();
 
-            debug!(
-                "Sending packet {} with amount: {} and encrypted STREAM packet: {:?}",
-                sequence, source_amount, stream_request_packet
-            );
+            if verbose {
+                debug!(
+                    "Sending packet {} with amount: {} and encrypted STREAM packet: {:?}",
+                    sequence, source_amount, stream_request_packet
+                );
+            }
 
             let prepare_data = stream_request_packet.into_encrypted(&self.shared_secret);
 
@@ -495,11 +738,12 @@ where
                 destination: payment.receipt.to.clone(),
                 amount: source_amount,
                 execution_condition: &execution_condition,
-                expires_at: SystemTime::now() + Duration::from_secs(30),
+                expires_at: runtime::now() + Duration::from_secs(30),
                 // TODO Don't copy the data
                 data: &prepare_data[..],
             }
             .build();
+            pool::recycle(prepare_data);
 
             (prepare, sequence)
         };
@@ -518,8 +762,9 @@ where
             Err(reject) => (IlpPacketType::Reject, reject.data()),
         };
 
-        let stream_reply_packet =
-            StreamPacket::from_encrypted(&self.shared_secret, BytesMut::from(reply_data));
+        let mut reply_buffer = pool::take(reply_data.len());
+        reply_buffer.extend_from_slice(reply_data);
+        let stream_reply_packet = StreamPacket::from_encrypted(&self.shared_secret, reply_buffer);
 
         let mut payment = self.payment.lock().await;
 
@@ -581,39 +826,38 @@ where
 
                 payment.apply_fulfill(source_amount, delivered_amount);
 
-                debug!(
-                    "Prepare {} with amount {} was fulfilled ({} left to send)",
-                    sequence,
-                    source_amount,
-                    payment.get_remaining_amount()
-                );
+                if verbose {
+                    debug!(
+                        "Prepare {} with amount {} was fulfilled ({} left to send)",
+                        sequence,
+                        source_amount,
+                        payment.get_remaining_amount()
+                    );
+                }
 
                 Ok(())
             }
             // Handle ILP Reject
             Err(reject) => {
-                payment.apply_reject(source_amount, &reject);
+                let action = payment.apply_reject(source_amount, &reject);
 
-                debug!(
-                    "Prepare {} with amount {} was rejected with code: {} ({} left to send)",
-                    sequence,
-                    source_amount,
-                    reject.code(),
-                    payment.get_remaining_amount()
-                );
+                if verbose {
+                    debug!(
+                        "Prepare {} with amount {} was rejected with code: {} ({} left to send)",
+                        sequence,
+                        source_amount,
+                        reject.code(),
+                        payment.get_remaining_amount()
+                    );
+                }
 
-                match (reject.code().class(), reject.code()) {
-                    (ErrorClass::Temporary, _) => Ok(()),
-                    (_, IlpErrorCode::F08_AMOUNT_TOO_LARGE) => Ok(()),
-                    (_, IlpErrorCode::F99_APPLICATION_ERROR) => Ok(()),
-                    // R01 is triggered by connector when the amount rounds to 0, so keep retrying
-                    // Other Rxx errors such as timeouts are likely terminal
-                    (_, IlpErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT) => Ok(()),
-                    // Any other error will stop the rest of the payment
-                    _ => Err(Error::UnexpectedRejection(
+                match action {
+                    RejectAction::Abort => Err(Error::UnexpectedRejection(
                         reject.code(),
                         String::from_utf8_lossy(reject.message()).into_owned(),
                     )),
+                    // The payment retries the money of the packet
+                    _ => Ok(()),
                 }
             }
         }
@@ -640,14 +884,16 @@ where
 
             // Create the ILP Prepare packet
             let data = stream_packet.into_encrypted(&self.shared_secret);
-            PrepareBuilder {
+            let prepare = PrepareBuilder {
                 destination: payment.receipt.to.clone(),
                 amount: 0,
                 execution_condition: &random_condition(),
-                expires_at: SystemTime::now() + Duration::from_secs(30),
+                expires_at: runtime::now() + Duration::from_secs(30),
                 data: &data[..],
             }
-            .build()
+            .build();
+            pool::recycle(data);
+            prepare
         };
 
         // Send it!
@@ -723,7 +969,6 @@ fn convert(source_amount: u64, rate: BigRational) -> Option<u64> {
 mod send_money_tests {
     use super::*;
     use crate::test_helpers::{TestAccount, TestStore, EXAMPLE_CONNECTOR};
-    use async_trait::async_trait;
     use interledger_packet::{ErrorCode as IlpErrorCode, RejectBuilder};
     use interledger_service::incoming_service_fn;
     use interledger_service_util::MaxPacketAmountService;
@@ -830,6 +1075,150 @@ mod send_money_tests {
         assert!(num_requests.load(Ordering::Relaxed) > 1000);
     }
 
+    fn t04_reject() -> Reject {
+        RejectBuilder {
+            code: IlpErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+            message: b"settle up!",
+            triggered_by: Some(&EXAMPLE_CONNECTOR),
+            data: &[],
+        }
+        .build()
+    }
+
+    fn f05_reject() -> Reject {
+        RejectBuilder {
+            code: IlpErrorCode::F05_WRONG_CONDITION,
+            message: b"just some final error",
+            triggered_by: Some(&EXAMPLE_CONNECTOR),
+            data: &[],
+        }
+        .build()
+    }
+
+    fn test_account() -> TestAccount {
+        TestAccount {
+            id: Uuid::new_v4(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            ilp_address: Address::from_str("example.receiver").unwrap(),
+            max_packet_amount: None,
+        }
+    }
+
+    fn empty_store() -> TestStore {
+        TestStore {
+            route: None,
+            price_1: None,
+            price_2: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn aborts_on_codes_the_policy_treats_as_fatal() {
+        let num_requests = Arc::new(AtomicUsize::new(0));
+        let num_requests_clone = num_requests.clone();
+        let result = send_money_with_retry_policy(
+            incoming_service_fn(move |_| {
+                num_requests_clone.fetch_add(1, Ordering::Relaxed);
+                Err(t04_reject())
+            }),
+            &test_account(),
+            empty_store(),
+            Address::from_str("example.receiver").unwrap(),
+            vec![0; 32],
+            100,
+            0.0,
+            RetryPolicy::new().code(
+                IlpErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                RejectAction::Abort,
+            ),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(Error::UnexpectedRejection(
+                IlpErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                _
+            ))
+        ));
+        assert_eq!(num_requests.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn halves_packets_rejected_by_busy_connectors() {
+        let amounts = Arc::new(Mutex::new(Vec::new()));
+        let amounts_clone = amounts.clone();
+        let result = send_money_with_retry_policy(
+            incoming_service_fn(move |request| {
+                let amount = request.prepare.amount();
+                amounts_clone.lock().push(amount);
+                if amount > 10 {
+                    // Unlike T04, T03 does not shrink the congestion window
+                    Err(RejectBuilder {
+                        code: IlpErrorCode::T03_CONNECTOR_BUSY,
+                        message: b"busy",
+                        triggered_by: Some(&EXAMPLE_CONNECTOR),
+                        data: &[],
+                    }
+                    .build())
+                } else {
+                    // Reject with final error to stop STREAM
+                    Err(f05_reject())
+                }
+            }),
+            &test_account(),
+            empty_store(),
+            Address::from_str("example.receiver").unwrap(),
+            vec![0; 32],
+            100,
+            0.0,
+            RetryPolicy::new().code(
+                IlpErrorCode::T03_CONNECTOR_BUSY,
+                RejectAction::HalvePacketSize,
+            ),
+        )
+        .await;
+        assert!(result.is_err());
+        let amounts = amounts.lock();
+        assert_eq!(amounts[0], 100);
+        assert!(amounts[1..].iter().all(|amount| *amount <= 50));
+        assert!(amounts.iter().any(|amount| *amount <= 10));
+    }
+
+    #[tokio::test]
+    async fn backs_off_after_liquidity_errors() {
+        let start_time = std::time::Instant::now();
+        let num_requests = Arc::new(AtomicUsize::new(0));
+        let num_requests_clone = num_requests.clone();
+        let result = send_money_with_retry_policy(
+            incoming_service_fn(move |_| {
+                if start_time.elapsed() > Duration::from_millis(500) {
+                    Err(f05_reject())
+                } else {
+                    num_requests_clone.fetch_add(1, Ordering::Relaxed);
+                    Err(t04_reject())
+                }
+            }),
+            &test_account(),
+            empty_store(),
+            Address::from_str("example.receiver").unwrap(),
+            vec![0; 32],
+            100,
+            0.0,
+            RetryPolicy::new()
+                .code(
+                    IlpErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                    RejectAction::Backoff,
+                )
+                .backoff(Duration::from_millis(100), Duration::from_millis(100)),
+        )
+        .await;
+        // Without backing off, the sender would send thousands of packets in that time
+        assert!(result.is_err());
+        let num_requests = num_requests.load(Ordering::Relaxed);
+        assert!(num_requests >= 3 && num_requests <= 10, "{}", num_requests);
+    }
+
     #[tokio::test]
     async fn sends_concurrent_packets() {
         let destination_address = Address::from_str("example.receiver").unwrap();
@@ -859,28 +1248,31 @@ mod send_money_tests {
             }
         }
 
-        #[async_trait]
         impl<A> IncomingService<A> for CounterService
         where
             A: Account + 'static,
         {
-            async fn handle_request(&mut self, _: IncomingRequest<A>) -> IlpResult {
-                self.num_requests_in_flight.fetch_add(1, Ordering::Relaxed);
-
-                // Wait for 100ms while all requests are received, then reject with final error to terminate stream
-                timeout(
-                    Duration::from_millis(100),
-                    futures::future::pending::<IlpResult>(),
-                )
-                .await
-                .unwrap_or_else(|_| {
-                    Err(RejectBuilder {
-                        code: IlpErrorCode::F00_BAD_REQUEST,
-                        message: b"some final error",
-                        triggered_by: Some(&EXAMPLE_CONNECTOR),
-                        data: &[],
-                    }
-                    .build())
+            type Future<'a> = BoxedIlpFuture<'a>;
+
+            fn handle_request(&mut self, _: IncomingRequest<A>) -> Self::Future<'_> {
+                Box::pin(async move {
+                    self.num_requests_in_flight.fetch_add(1, Ordering::Relaxed);
+
+                    // Wait for 100ms while all requests are received, then reject with final error to terminate stream
+                    timeout(
+                        Duration::from_millis(100),
+                        futures::future::pending::<IlpResult>(),
+                    )
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(RejectBuilder {
+                            code: IlpErrorCode::F00_BAD_REQUEST,
+                            message: b"some final error",
+                            triggered_by: Some(&EXAMPLE_CONNECTOR),
+                            data: &[],
+                        }
+                        .build())
+                    })
                 })
             }
         }
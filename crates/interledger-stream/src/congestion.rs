@@ -137,6 +137,19 @@ impl CongestionController {
         }
     }
 
+    /// Limits the following packets to half the amount of the rejected one
+    pub fn halve_max_packet_amount(&mut self, prepare_amount: u64) {
+        let halved = max(prepare_amount / 2, 1);
+        self.max_packet_amount = Some(
+            self.max_packet_amount
+                .map_or(halved, |max_packet_amount| min(max_packet_amount, halved)),
+        );
+        debug!(
+            "Halving packet amount after a reject, max packet amount is now: {}",
+            halved
+        );
+    }
+
     #[cfg(test)]
     fn set_max_packet_amount(&mut self, max_packet_amount: u64) {
         self.max_packet_amount = Some(max_packet_amount)
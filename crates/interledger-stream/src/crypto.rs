@@ -1,4 +1,5 @@
-use bytes::BytesMut;
+use bytes::{BufMut, BytesMut};
+use interledger_packet::pool;
 #[cfg(test)]
 use once_cell::sync::Lazy;
 use ring::rand::{SecureRandom, SystemRandom};
@@ -113,12 +114,13 @@ fn encrypt_with_nonce(
 
     // Rearrange the bytes so that the tag goes first (should have put it last in the JS implementation, but oh well)
     let auth_tag_position = plaintext.len() - AUTH_TAG_LENGTH;
-    let mut tag_data = plaintext.split_off(auth_tag_position);
-    tag_data.unsplit(plaintext);
 
     // The format is `nonce, auth tag, data`, in that order
-    let mut nonce_tag_data = BytesMut::from(&nonce[..]);
-    nonce_tag_data.unsplit(tag_data);
+    let mut nonce_tag_data = pool::take(NONCE_LENGTH + plaintext.len());
+    nonce_tag_data.put_slice(&nonce[..]);
+    nonce_tag_data.put_slice(&plaintext[auth_tag_position..]);
+    nonce_tag_data.put_slice(&plaintext[..auth_tag_position]);
+    pool::recycle(plaintext);
 
     nonce_tag_data
 }
@@ -14,13 +14,24 @@ mod crypto;
 mod error;
 /// Stream Packet implementation, [as specified in the RFC](https://interledger.org/rfcs/0029-stream/#5-packet-and-frame-specification)
 mod packet;
+/// How the [stream client](./client/fn.send_money.html) reacts to the rejected packets
+mod retry;
+/// Rotation of the STREAM server secret derived from a node's secret seed
+mod rotation;
+/// Timers and tasks of the stream client, which run on the runtime of the process or in the browser
+mod runtime;
 /// A stream server implementing an [Outgoing Service](../interledger_service/trait.OutgoingService.html) for receiving STREAM payments from peers
 mod server;
 
-pub use client::{send_money, StreamDelivery};
+pub use client::{
+    send_money, send_money_with_retry_policy, stream_money, StreamDelivery, StreamHandle,
+};
 pub use error::{Error, StreamPacketError};
+pub use retry::{RejectAction, RetryPolicy};
+pub use rotation::{derive_server_secret, SecretGeneration};
 pub use server::{
-    ConnectionGenerator, PaymentNotification, StreamNotificationsStore, StreamReceiverService,
+    ConnectionGenerator, IncomingPayment, IncomingPaymentsStore, PaymentNotification,
+    StreamNotificationsStore, StreamReceiverService,
 };
 
 #[cfg(fuzzing)]
@@ -39,6 +50,47 @@ pub fn fuzz_decrypted_stream_packet(data: &[u8]) {
     }
 }
 
+/// Encrypts the input with the shared secret it starts with and checks that decrypting it gives
+/// back the packet parsed out of the plaintext, while tampering with the ciphertext or decrypting
+/// it with another secret fails
+#[cfg(fuzzing)]
+pub fn fuzz_encrypted_stream_packet(data: &[u8]) {
+    use bytes::BytesMut;
+
+    if data.len() < 32 {
+        return;
+    }
+    let (shared_secret, plaintext) = data.split_at(32);
+
+    // Most untrusted ciphertexts are rejected by the decryption, which must not panic either
+    let _ = packet::StreamPacket::from_encrypted(shared_secret, BytesMut::from(plaintext));
+
+    let ciphertext = crypto::encrypt(shared_secret, BytesMut::from(plaintext));
+    let decrypted = packet::StreamPacket::from_encrypted(shared_secret, ciphertext.clone()).ok();
+    let parsed = packet::StreamPacket::from_decrypted(BytesMut::from(plaintext)).ok();
+    assert_eq!(decrypted, parsed);
+
+    let mut tampered = ciphertext.clone();
+    let index = plaintext.len() % tampered.len();
+    tampered[index] ^= 1;
+    assert!(matches!(
+        packet::StreamPacket::from_encrypted(shared_secret, tampered),
+        Err(StreamPacketError::FailedToDecrypt)
+    ));
+
+    let mut other_secret = shared_secret.to_vec();
+    other_secret[0] ^= 1;
+    assert!(matches!(
+        packet::StreamPacket::from_encrypted(&other_secret, ciphertext),
+        Err(StreamPacketError::FailedToDecrypt)
+    ));
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz_structured_stream_packet(data: &[u8]) {
+    packet::fuzz_structured_stream_packet(data);
+}
+
 #[cfg(test)]
 pub mod test_helpers {
     use super::*;
@@ -276,6 +328,78 @@ mod send_money_to_receiver {
         assert_eq!(receipt.delivered_amount, 100);
     }
 
+    #[tokio::test]
+    async fn stream_money_test() {
+        let server_secret = Bytes::from(&[0; 32][..]);
+        let destination_address = Address::from_str("example.receiver").unwrap();
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: destination_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+        };
+        let store = TestStore {
+            route: Some((destination_address.to_string(), account.clone())),
+            price_1: None,
+            price_2: None,
+        };
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let server = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&EXAMPLE_RECEIVER),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        let server = Router::new(store.clone(), server);
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&destination_address);
+
+        // Sends 1000 per second until the maximum amount was sent
+        let handle = StreamHandle::new();
+        let receipt = stream_money(
+            server.clone(),
+            &account,
+            store.clone(),
+            destination_account.clone(),
+            shared_secret.to_vec(),
+            1000,
+            Some(1500),
+            0.0,
+            handle.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(receipt.delivered_amount, 1500);
+        assert_eq!(receipt.source_amount, 1500);
+        assert_eq!(handle.receipt(), Some(receipt));
+
+        // Nothing is sent once the payment was stopped
+        let handle = StreamHandle::new();
+        handle.stop();
+        let receipt = stream_money(
+            server,
+            &account,
+            store,
+            destination_account,
+            shared_secret.to_vec(),
+            1000,
+            None,
+            0.0,
+            handle,
+        )
+        .await
+        .unwrap();
+        assert_eq!(receipt.delivered_amount, 0);
+    }
+
     #[tokio::test]
     async fn payment_fails_if_large_spread() {
         let server_secret = Bytes::from(&[0; 32][..]);
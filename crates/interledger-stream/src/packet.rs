@@ -5,7 +5,7 @@ use super::{
 use bytes::{Buf, BufMut, BytesMut};
 use interledger_packet::{
     oer::{self, BufOerExt, MutBufOerExt},
-    Address, OerError, PacketType as IlpPacketType,
+    pool, Address, OerError, PacketType as IlpPacketType,
 };
 #[cfg(test)]
 use once_cell::sync::Lazy;
@@ -35,7 +35,7 @@ pub struct StreamPacketBuilder<'a> {
 impl<'a> StreamPacketBuilder<'a> {
     /// Serializes the builder into a Stream Packet
     pub fn build(&self) -> StreamPacket {
-        let mut buffer_unencrypted = Vec::with_capacity(26);
+        let mut buffer_unencrypted = pool::take(26);
 
         buffer_unencrypted.put_u8(STREAM_VERSION);
         buffer_unencrypted.put_u8(self.ilp_packet_type as u8);
@@ -44,8 +44,9 @@ impl<'a> StreamPacketBuilder<'a> {
         buffer_unencrypted.put_var_uint(self.frames.len() as u64);
         let frames_offset = buffer_unencrypted.len();
 
+        let mut contents = pool::take(0);
         for frame in self.frames {
-            let mut contents = Vec::new();
+            contents.clear();
             match frame {
                 Frame::ConnectionClose(ref frame) => {
                     buffer_unencrypted.put_u8(FrameType::ConnectionClose as u8);
@@ -111,9 +112,10 @@ impl<'a> StreamPacketBuilder<'a> {
             }
             buffer_unencrypted.put_var_octet_string(&*contents);
         }
+        pool::recycle(contents);
 
         StreamPacket {
-            buffer_unencrypted: BytesMut::from(&buffer_unencrypted[..]),
+            buffer_unencrypted,
             sequence: self.sequence,
             ilp_packet_type: self.ilp_packet_type,
             prepare_amount: self.prepare_amount,
@@ -982,6 +984,111 @@ fn saturating_read_var_uint<'a>(reader: &mut impl BufOerExt<'a>) -> Result<u64,
     }
 }
 
+/// Builds a packet out of frames made from the input rather than parsing it, so that the fuzzer
+/// explores valid packets, and checks that the frames are read back the same once the packet has
+/// been encrypted and decrypted
+#[cfg(fuzzing)]
+pub(crate) fn fuzz_structured_stream_packet(data: &[u8]) {
+    let _ = roundtrip_structured_stream_packet(&mut arbitrary::Unstructured::new(data));
+}
+
+#[cfg(fuzzing)]
+fn roundtrip_structured_stream_packet(
+    input: &mut arbitrary::Unstructured,
+) -> arbitrary::Result<()> {
+    let shared_secret: [u8; 32] = input.arbitrary()?;
+    let sequence = input.arbitrary()?;
+    let ilp_packet_type = IlpPacketType::try_from(12 + input.int_in_range(0..=2u8)?).unwrap();
+    let prepare_amount = input.arbitrary()?;
+    let mut frames = Vec::new();
+    for _ in 0..input.int_in_range(0..=8)? {
+        frames.push(arbitrary_frame(input)?);
+    }
+
+    let packet = StreamPacketBuilder {
+        sequence,
+        ilp_packet_type,
+        prepare_amount,
+        frames: &frames,
+    }
+    .build();
+    let ciphertext = packet.clone().into_encrypted(&shared_secret);
+    let decrypted = StreamPacket::from_encrypted(&shared_secret, ciphertext).unwrap();
+    assert_eq!(decrypted, packet);
+    assert_eq!(decrypted.sequence(), sequence);
+    assert_eq!(decrypted.ilp_packet_type(), ilp_packet_type);
+    assert_eq!(decrypted.prepare_amount(), prepare_amount);
+    assert_eq!(decrypted.frames().collect::<Vec<_>>(), frames);
+    Ok(())
+}
+
+#[cfg(fuzzing)]
+fn arbitrary_frame<'a>(input: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Frame<'a>> {
+    Ok(match input.int_in_range(0..=14)? {
+        0 => Frame::ConnectionClose(ConnectionCloseFrame {
+            code: ErrorCode::from(input.arbitrary::<u8>()?),
+            message: input.arbitrary()?,
+        }),
+        1 => Frame::ConnectionNewAddress(ConnectionNewAddressFrame {
+            source_account: Address::try_from(input.arbitrary::<&[u8]>()?)
+                .unwrap_or_else(|_| Address::try_from(&b"example.fuzz"[..]).unwrap()),
+        }),
+        2 => Frame::ConnectionAssetDetails(ConnectionAssetDetailsFrame {
+            source_asset_code: input.arbitrary()?,
+            source_asset_scale: input.arbitrary()?,
+        }),
+        3 => Frame::ConnectionMaxData(ConnectionMaxDataFrame {
+            max_offset: input.arbitrary()?,
+        }),
+        4 => Frame::ConnectionDataBlocked(ConnectionDataBlockedFrame {
+            max_offset: input.arbitrary()?,
+        }),
+        5 => Frame::ConnectionMaxStreamId(ConnectionMaxStreamIdFrame {
+            max_stream_id: input.arbitrary()?,
+        }),
+        6 => Frame::ConnectionStreamIdBlocked(ConnectionStreamIdBlockedFrame {
+            max_stream_id: input.arbitrary()?,
+        }),
+        7 => Frame::StreamClose(StreamCloseFrame {
+            stream_id: input.arbitrary()?,
+            code: ErrorCode::from(input.arbitrary::<u8>()?),
+            message: input.arbitrary()?,
+        }),
+        8 => Frame::StreamMoney(StreamMoneyFrame {
+            stream_id: input.arbitrary()?,
+            shares: input.arbitrary()?,
+        }),
+        9 => Frame::StreamMaxMoney(StreamMaxMoneyFrame {
+            stream_id: input.arbitrary()?,
+            receive_max: input.arbitrary()?,
+            total_received: input.arbitrary()?,
+        }),
+        10 => Frame::StreamMoneyBlocked(StreamMoneyBlockedFrame {
+            stream_id: input.arbitrary()?,
+            send_max: input.arbitrary()?,
+            total_sent: input.arbitrary()?,
+        }),
+        11 => Frame::StreamData(StreamDataFrame {
+            stream_id: input.arbitrary()?,
+            offset: input.arbitrary()?,
+            data: input.arbitrary()?,
+        }),
+        12 => Frame::StreamMaxData(StreamMaxDataFrame {
+            stream_id: input.arbitrary()?,
+            max_offset: input.arbitrary()?,
+        }),
+        13 => Frame::StreamDataBlocked(StreamDataBlockedFrame {
+            stream_id: input.arbitrary()?,
+            max_offset: input.arbitrary()?,
+        }),
+        // The frame types above 0x16 are all unknown
+        _ => Frame::Unknown(UnknownFrameData {
+            frame_type: 0x80 | input.arbitrary::<u8>()?,
+            content: input.arbitrary()?,
+        }),
+    })
+}
+
 #[cfg(test)]
 mod fuzzing {
     use super::{StreamPacket, StreamPacketBuilder};
@@ -0,0 +1,189 @@
+use interledger_packet::{ErrorClass, ErrorCode};
+use std::time::Duration;
+
+/// How the STREAM sender reacts to a rejected packet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RejectAction {
+    /// Send the money of the packet again right away
+    Retry,
+    /// Send the money of the packet again once the sender backed off, for a delay which
+    /// doubles with each consecutive reject handled this way (until a packet is fulfilled)
+    Backoff,
+    /// Send the money of the packet again, in packets at most half as large as this one
+    HalvePacketSize,
+    /// Send the money of the packet again, but count the reject towards the fail-fast
+    /// threshold, which stops the payment once nearly all of its packets are rejected
+    FailFast,
+    /// Stop the payment with an error right away
+    Abort,
+}
+
+/// Which [`RejectAction`] the STREAM sender takes for each error code.
+///
+/// The action of a code is the one configured for that code, or else the one of its class.
+/// The default policy retries the temporary errors, the `F08` (Amount Too Large) and the
+/// `R01` (Insufficient Source Amount) errors, counts the `T00`, `T01` and `F99` errors towards
+/// the fail-fast threshold, and aborts on the other final and relative errors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    temporary: RejectAction,
+    final_errors: RejectAction,
+    relative: RejectAction,
+    unknown: RejectAction,
+    codes: Vec<(ErrorCode, RejectAction)>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            temporary: RejectAction::Retry,
+            final_errors: RejectAction::Abort,
+            relative: RejectAction::Abort,
+            unknown: RejectAction::Abort,
+            codes: vec![
+                // T02-T99 may be resolved with time, unlike the internal errors
+                (ErrorCode::T00_INTERNAL_ERROR, RejectAction::FailFast),
+                (ErrorCode::T01_PEER_UNREACHABLE, RejectAction::FailFast),
+                // The congestion controller reduces the packet amount to the one in the reject
+                (ErrorCode::F08_AMOUNT_TOO_LARGE, RejectAction::Retry),
+                (ErrorCode::F99_APPLICATION_ERROR, RejectAction::FailFast),
+                // R01 is triggered by connector when the amount rounds to 0
+                (
+                    ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
+                    RejectAction::Retry,
+                ),
+            ],
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the action for the codes of the class which have no action of their own
+    pub fn class(mut self, class: ErrorClass, action: RejectAction) -> Self {
+        match class {
+            ErrorClass::Temporary => self.temporary = action,
+            ErrorClass::Final => self.final_errors = action,
+            ErrorClass::Relative => self.relative = action,
+            ErrorClass::Unknown => self.unknown = action,
+        }
+        self
+    }
+
+    /// Sets the action for the code, taking precedence over the one of its class
+    pub fn code(mut self, code: ErrorCode, action: RejectAction) -> Self {
+        self.codes.retain(|(other, _)| *other != code);
+        self.codes.push((code, action));
+        self
+    }
+
+    /// Sets the delay of the first [`RejectAction::Backoff`], and the maximum it doubles up to.
+    /// Defaults to 100ms and 5s. The time spent backing off counts towards the time the
+    /// payment may go without a fulfilled packet.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Returns the action to take for a packet rejected with the code
+    pub fn action(&self, code: ErrorCode) -> RejectAction {
+        if let Some((_, action)) = self.codes.iter().find(|(other, _)| *other == code) {
+            return *action;
+        }
+        match code.class() {
+            ErrorClass::Temporary => self.temporary,
+            ErrorClass::Final => self.final_errors,
+            ErrorClass::Relative => self.relative,
+            ErrorClass::Unknown => self.unknown,
+        }
+    }
+
+    /// Returns how long to back off after the given number of consecutive backoffs
+    pub(crate) fn backoff_delay(&self, consecutive_backoffs: u32) -> Duration {
+        let factor = 2u32.saturating_pow(consecutive_backoffs.saturating_sub(1));
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_the_previous_behavior() {
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            policy.action(ErrorCode::T04_INSUFFICIENT_LIQUIDITY),
+            RejectAction::Retry
+        );
+        assert_eq!(
+            policy.action(ErrorCode::T01_PEER_UNREACHABLE),
+            RejectAction::FailFast
+        );
+        assert_eq!(
+            policy.action(ErrorCode::F08_AMOUNT_TOO_LARGE),
+            RejectAction::Retry
+        );
+        assert_eq!(
+            policy.action(ErrorCode::F00_BAD_REQUEST),
+            RejectAction::Abort
+        );
+        assert_eq!(
+            policy.action(ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT),
+            RejectAction::Retry
+        );
+        assert_eq!(
+            policy.action(ErrorCode::R00_TRANSFER_TIMED_OUT),
+            RejectAction::Abort
+        );
+    }
+
+    #[test]
+    fn codes_take_precedence_over_classes() {
+        let policy = RetryPolicy::new()
+            .class(ErrorClass::Temporary, RejectAction::Backoff)
+            .code(
+                ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                RejectAction::HalvePacketSize,
+            )
+            .code(ErrorCode::F99_APPLICATION_ERROR, RejectAction::Abort);
+        assert_eq!(
+            policy.action(ErrorCode::T04_INSUFFICIENT_LIQUIDITY),
+            RejectAction::HalvePacketSize
+        );
+        assert_eq!(
+            policy.action(ErrorCode::T03_CONNECTOR_BUSY),
+            RejectAction::Backoff
+        );
+        // the defaults of the codes are kept unless they are overridden
+        assert_eq!(
+            policy.action(ErrorCode::T00_INTERNAL_ERROR),
+            RejectAction::FailFast
+        );
+        assert_eq!(
+            policy.action(ErrorCode::F99_APPLICATION_ERROR),
+            RejectAction::Abort
+        );
+    }
+
+    #[test]
+    fn doubles_backoff_up_to_the_maximum() {
+        let policy =
+            RetryPolicy::new().backoff(Duration::from_millis(100), Duration::from_millis(500));
+        assert_eq!(policy.backoff_delay(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff_delay(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff_delay(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff_delay(4), Duration::from_millis(500));
+        assert_eq!(policy.backoff_delay(100), Duration::from_millis(500));
+    }
+}
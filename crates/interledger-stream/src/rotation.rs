@@ -0,0 +1,119 @@
+use super::crypto::hmac_sha256;
+use super::server::ConnectionGenerator;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Magic bytes from which the server secrets of the generations after the first are derived
+const ROTATED_SERVER_SECRET_GENERATOR: &[u8] = b"ilp_stream_rotated_server_secret";
+
+/// Identifies the STREAM server secret which is derived from a node's secret seed. Only
+/// this is stored, so that the secrets themselves never leave the node's memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretGeneration {
+    /// Number of times the secret was rotated. The first generation is the secret seed itself.
+    pub generation: u64,
+    /// Time, in milliseconds since the UNIX epoch, until which the secret of the previous
+    /// generation is still accepted
+    #[serde(default)]
+    pub previous_valid_until: Option<u64>,
+}
+
+impl SecretGeneration {
+    /// Returns the next generation, whose previous secret is accepted for `overlap`
+    pub fn rotate(&self, overlap: Duration) -> Self {
+        let valid_until = SystemTime::now() + overlap;
+        SecretGeneration {
+            generation: self.generation + 1,
+            previous_valid_until: Some(
+                valid_until
+                    .duration_since(UNIX_EPOCH)
+                    .map(|time| time.as_millis() as u64)
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+/// Derives the server secret of the given generation from the secret seed
+pub fn derive_server_secret(secret_seed: &[u8], generation: u64) -> Bytes {
+    if generation == 0 {
+        return Bytes::copy_from_slice(secret_seed);
+    }
+    let mut message = ROTATED_SERVER_SECRET_GENERATOR.to_vec();
+    message.extend_from_slice(&generation.to_be_bytes());
+    Bytes::copy_from_slice(&hmac_sha256(secret_seed, &message)[..])
+}
+
+impl ConnectionGenerator {
+    /// Creates a connection generator which uses the server secret of the given generation
+    pub fn with_generation(secret_seed: &[u8], generation: &SecretGeneration) -> Self {
+        let connection_generator =
+            ConnectionGenerator::new(derive_server_secret(secret_seed, generation.generation));
+        connection_generator.set_generation(secret_seed, generation);
+        connection_generator
+    }
+
+    /// Switches to the server secret of the given generation. The secret of the previous
+    /// generation is accepted until `previous_valid_until`.
+    pub fn set_generation(&self, secret_seed: &[u8], generation: &SecretGeneration) {
+        let previous = match (generation.generation, generation.previous_valid_until) {
+            (0, _) | (_, None) => None,
+            (number, Some(valid_until)) => Some((
+                derive_server_secret(secret_seed, number - 1),
+                UNIX_EPOCH + Duration::from_millis(valid_until),
+            )),
+        };
+        self.set_server_secret(
+            derive_server_secret(secret_seed, generation.generation),
+            previous,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::Address;
+    use std::str::FromStr;
+
+    const SEED: [u8; 32] = [7; 32];
+
+    #[test]
+    fn first_generation_is_the_seed() {
+        assert_eq!(derive_server_secret(&SEED, 0), Bytes::from(&SEED[..]));
+        assert_ne!(
+            derive_server_secret(&SEED, 1),
+            derive_server_secret(&SEED, 0)
+        );
+        assert_ne!(
+            derive_server_secret(&SEED, 1),
+            derive_server_secret(&SEED, 2)
+        );
+        assert_eq!(derive_server_secret(&SEED, 1).len(), 32);
+    }
+
+    #[test]
+    fn accepts_previous_secret_during_overlap() {
+        let base = Address::from_str("example.receiver").unwrap();
+        let generation = SecretGeneration::default();
+        let connection_generator = ConnectionGenerator::with_generation(&SEED, &generation);
+        let (destination, shared_secret) = connection_generator.generate_address_and_secret(&base);
+
+        let rotated = generation.rotate(Duration::from_secs(60));
+        assert_eq!(rotated.generation, 1);
+        connection_generator.set_generation(&SEED, &rotated);
+        let secrets = connection_generator.rederive_secrets(&destination);
+        assert_eq!(secrets.len(), 2);
+        assert_ne!(secrets[0], shared_secret);
+        assert_eq!(secrets[1], shared_secret);
+
+        // Once the overlap ended, only the new secret is accepted
+        let expired = SecretGeneration {
+            previous_valid_until: Some(0),
+            ..rotated
+        };
+        connection_generator.set_generation(&SEED, &expired);
+        assert_eq!(connection_generator.rederive_secrets(&destination).len(), 1);
+    }
+}
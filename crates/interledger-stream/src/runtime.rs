@@ -0,0 +1,102 @@
+use std::future::Future;
+use std::time::SystemTime;
+
+#[cfg(not(target_arch = "wasm32"))]
+use futures::channel::oneshot;
+#[cfg(not(target_arch = "wasm32"))]
+use interledger_service::runtime::{self, Elapsed};
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn timeout_at<F: Future>(deadline: Instant, future: F) -> Result<F::Output, Elapsed> {
+    runtime::timeout(deadline.saturating_duration_since(Instant::now()), future).await
+}
+
+/// Runs the future in the background, returning a future which resolves to its output,
+/// or to an error if the task was dropped before completing
+#[cfg(not(target_arch = "wasm32"))]
+pub fn spawn<F>(future: F) -> oneshot::Receiver<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    runtime::spawn(async move {
+        let _ = sender.send(future.await);
+    });
+    receiver
+}
+
+/// Current system time, for the expiry of the packets
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now() -> SystemTime {
+    SystemTime::now()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub use wasm::*;
+
+/// Browsers have neither tokio's reactor nor `std::time`, so the timers and the tasks are
+/// scheduled on the JavaScript event loop and the clock is read from `Date`
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use super::*;
+    use futures::{
+        channel::oneshot,
+        future::{select, Either},
+        pin_mut,
+    };
+    use futures_timer::Delay;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// Monotonic enough instant, in milliseconds since the Unix epoch
+    #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+    pub struct Instant(f64);
+
+    impl Instant {
+        pub fn now() -> Self {
+            Instant(js_sys::Date::now())
+        }
+
+        pub fn elapsed(&self) -> Duration {
+            Instant::now().duration_since(*self)
+        }
+
+        pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+            Some(Instant(self.0 + duration.as_secs_f64() * 1000.0))
+        }
+
+        fn duration_since(&self, earlier: Instant) -> Duration {
+            Duration::from_secs_f64((self.0 - earlier.0).max(0.0) / 1000.0)
+        }
+    }
+
+    /// The deadline passed before the future completed
+    #[derive(Debug)]
+    pub struct Elapsed;
+
+    pub async fn timeout_at<F: Future>(deadline: Instant, future: F) -> Result<F::Output, Elapsed> {
+        let delay = Delay::new(deadline.duration_since(Instant::now()));
+        pin_mut!(future);
+        match select(future, delay).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(_) => Err(Elapsed),
+        }
+    }
+
+    pub fn spawn<F>(future: F) -> oneshot::Receiver<F::Output>
+    where
+        F: Future + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        wasm_bindgen_futures::spawn_local(async move {
+            let _ = sender.send(future.await);
+        });
+        receiver
+    }
+
+    pub fn now() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(js_sys::Date::now() as u64)
+    }
+}
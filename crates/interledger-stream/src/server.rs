@@ -1,19 +1,24 @@
 use super::crypto::*;
 use super::packet::*;
 use async_trait::async_trait;
-use bytes::{Bytes, BytesMut};
+use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use futures::channel::mpsc::UnboundedSender;
+use interledger_errors::BalanceStoreError;
 use interledger_packet::{
-    hex::HexString, Address, ErrorCode, Fulfill, FulfillBuilder, PacketType as IlpPacketType,
+    hex::HexString, pool, Address, ErrorCode, Fulfill, FulfillBuilder, PacketType as IlpPacketType,
     Prepare, Reject, RejectBuilder,
 };
-use interledger_service::{Account, IlpResult, OutgoingRequest, OutgoingService, Username};
+use interledger_service::{
+    telemetry::{sample_packet, Redacted},
+    Account, BoxedIlpFuture, OutgoingRequest, OutgoingService, Username,
+};
 use serde::{Deserialize, Serialize};
 use std::marker::PhantomData;
-use std::time::SystemTime;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::broadcast;
-use tracing::debug;
+use tracing::{debug, error};
 use uuid::Uuid;
 
 // Note we are using the same magic bytes as the Javascript
@@ -30,22 +35,51 @@ const STREAM_SERVER_SECRET_GENERATOR: &[u8] = b"ilp_stream_shared_secret";
 ///
 /// This can be reused across multiple STREAM connections so that a single receiver can
 /// accept incoming packets for multiple connections.
+///
+/// The server secret can be replaced while the generator is in use (clones share it). The
+/// previous secret can be kept for a while, so that the connections which were set up
+/// with it still work.
 #[derive(Clone)]
 pub struct ConnectionGenerator {
-    secret_generator: [u8; 32],
+    secret_generators: Arc<RwLock<SecretGenerators>>,
 }
 
-impl ConnectionGenerator {
-    pub fn new(server_secret: Bytes) -> Self {
-        assert_eq!(server_secret.len(), 32, "Server secret must be 32 bytes");
+struct SecretGenerators {
+    current: [u8; 32],
+    /// The generator of the previous server secret, and until when it is accepted
+    previous: Option<([u8; 32], SystemTime)>,
+}
 
-        let secret = hmac_sha256(&server_secret[..], STREAM_SERVER_SECRET_GENERATOR);
+fn secret_generator(server_secret: &[u8]) -> [u8; 32] {
+    assert_eq!(server_secret.len(), 32, "Server secret must be 32 bytes");
+    hmac_sha256(server_secret, STREAM_SERVER_SECRET_GENERATOR)
+}
 
+impl ConnectionGenerator {
+    pub fn new(server_secret: Bytes) -> Self {
         ConnectionGenerator {
-            secret_generator: secret,
+            secret_generators: Arc::new(RwLock::new(SecretGenerators {
+                current: secret_generator(&server_secret[..]),
+                previous: None,
+            })),
         }
     }
 
+    /// Replaces the server secret. If `previous` is given, the shared secrets of the
+    /// connections set up with that server secret can still be rederived until the given time.
+    pub fn set_server_secret(&self, server_secret: Bytes, previous: Option<(Bytes, SystemTime)>) {
+        let current = secret_generator(&server_secret[..]);
+        let previous =
+            previous.map(|(secret, valid_until)| (secret_generator(&secret[..]), valid_until));
+        let mut generators = self.secret_generators.write().unwrap();
+        generators.current = current;
+        generators.previous = previous;
+    }
+
+    fn current_secret_generator(&self) -> [u8; 32] {
+        self.secret_generators.read().unwrap().current
+    }
+
     /// Generate the STREAM parameters for the given ILP address and the configured server secret.
     ///
     /// The `destination_account` is generated such that the `shared_secret` can be re-derived
@@ -54,7 +88,7 @@ impl ConnectionGenerator {
         let token = base64::encode_config(&generate_token(), base64::URL_SAFE_NO_PAD);
         // Note the shared secret is generated from the base64-encoded version of the token,
         // rather than from the unencoded bytes
-        let shared_secret = hmac_sha256(&self.secret_generator[..], token.as_bytes());
+        let shared_secret = hmac_sha256(&self.current_secret_generator()[..], token.as_bytes());
         // Note that the unwrap here is safe because we know the base_address
         // is valid and adding base64-url characters will always be valid
         let destination_account = base_address.with_suffix(token.as_ref()).unwrap();
@@ -80,7 +114,21 @@ impl ConnectionGenerator {
         let local_part = destination_account.segments().rev().next().unwrap();
         // Note this computes the HMAC with the token _encoded as UTF8_,
         // rather than decoding the base64 first.
-        hmac_sha256(&self.secret_generator[..], local_part.as_bytes())
+        hmac_sha256(&self.current_secret_generator()[..], local_part.as_bytes())
+    }
+
+    /// Rederive the `shared_secret` from a `destination_account` with the current server
+    /// secret, and with the previous one if it is still accepted
+    pub fn rederive_secrets(&self, destination_account: &Address) -> Vec<[u8; 32]> {
+        let local_part = destination_account.segments().rev().next().unwrap();
+        let generators = self.secret_generators.read().unwrap();
+        let mut secrets = vec![hmac_sha256(&generators.current[..], local_part.as_bytes())];
+        if let Some((previous, valid_until)) = generators.previous {
+            if SystemTime::now() < valid_until {
+                secrets.push(hmac_sha256(&previous[..], local_part.as_bytes()));
+            }
+        }
+        secrets
     }
 }
 
@@ -105,6 +153,56 @@ pub struct PaymentNotification {
     pub connection_closed: bool,
 }
 
+/// The money received on a STREAM connection, which the node records before fulfilling each
+/// packet so that it is not lost if the receiving application crashes before it recorded it
+/// itself. The application acknowledges the payment once it durably recorded it.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct IncomingPayment {
+    /// The id of the account that received the payment
+    pub account_id: Uuid,
+    /// The ILP Address of the STREAM connection
+    pub destination: Address,
+    /// The total amount received on the connection
+    pub amount: u64,
+    /// Whether the sender closed the connection, i.e. the payment is complete
+    pub closed: bool,
+    /// Whether the application acknowledged the payment since it last received money
+    pub acknowledged: bool,
+    /// When the payment last changed, in milliseconds since the UNIX epoch
+    pub updated_at: u64,
+}
+
+/// Store of the payments received by the STREAM receiver, for the applications to
+/// acknowledge them
+#[async_trait]
+pub trait IncomingPaymentsStore {
+    /// Adds the amount of a fulfilled packet to the payment of the connection, creating it
+    /// if needed, and marks it closed if `closed` is set. Receiving money clears the
+    /// acknowledgement of the payment.
+    async fn record_incoming_payment(
+        &self,
+        account_id: Uuid,
+        destination: &Address,
+        amount: u64,
+        closed: bool,
+        timestamp: u64,
+    ) -> Result<IncomingPayment, BalanceStoreError>;
+
+    /// Marks the payment of the connection as acknowledged by the application. Returns `None`
+    /// if no money was received on the connection.
+    async fn acknowledge_incoming_payment(
+        &self,
+        account_id: Uuid,
+        destination: &Address,
+    ) -> Result<Option<IncomingPayment>, BalanceStoreError>;
+
+    /// Loads the payments of the account which were not acknowledged yet, oldest first
+    async fn get_unacknowledged_payments(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Vec<IncomingPayment>, BalanceStoreError>;
+}
+
 /// The Ok(ReceiveOk) variant of receive_money(...) return result
 struct ReceiveOk {
     fulfill: Fulfill,
@@ -160,6 +258,7 @@ pub struct StreamReceiverService<S, O: OutgoingService<A>, A: Account> {
     next: O,
     account_type: PhantomData<A>,
     store: S,
+    payments: Option<Arc<dyn IncomingPaymentsStore + Send + Sync>>,
 }
 
 impl<S, O, A> StreamReceiverService<S, O, A>
@@ -169,97 +268,185 @@ where
     A: Account,
 {
     pub fn new(server_secret: Bytes, store: S, next: O) -> Self {
-        let connection_generator = ConnectionGenerator::new(server_secret);
+        Self::with_connection_generator(ConnectionGenerator::new(server_secret), store, next)
+    }
+
+    /// Like `new`, but shares the connection generator, e.g. with the SPSP server, so
+    /// that the server secret can be rotated
+    pub fn with_connection_generator(
+        connection_generator: ConnectionGenerator,
+        store: S,
+        next: O,
+    ) -> Self {
         StreamReceiverService {
             connection_generator,
             next,
             account_type: PhantomData,
             store,
+            payments: None,
         }
     }
+
+    /// Records the money received on each connection in the given store before fulfilling
+    /// the packets, for the applications to acknowledge the payments
+    pub fn record_payments<P>(mut self, payments: P) -> Self
+    where
+        P: IncomingPaymentsStore + Send + Sync + 'static,
+    {
+        self.payments = Some(Arc::new(payments));
+        self
+    }
 }
 
-#[async_trait]
 impl<S, O, A> OutgoingService<A> for StreamReceiverService<S, O, A>
 where
     S: StreamNotificationsStore + Send + Sync + 'static + Clone,
     O: OutgoingService<A> + Send + Sync + Clone,
     A: Account + Send + Sync + Clone,
 {
+    type Future<'a>
+        = BoxedIlpFuture<'a>
+    where
+        Self: 'a;
+
     /// Try fulfilling the request if it is for this STREAM server or pass it to the next
     /// outgoing handler if not.
-    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
-        let to_username = request.to.username().clone();
-        let from_username = request.from.username().clone();
-        let amount = request.prepare.amount();
-
-        let destination = request.prepare.destination();
-        let to_address = request.to.ilp_address();
-        let dest: &[u8] = destination.as_ref();
-
-        // The case where the request is bound for this server
-        if dest.starts_with(to_address.as_ref()) {
-            let shared_secret = self.connection_generator.rederive_secret(&destination);
-            let response = receive_money(
-                &shared_secret,
-                to_address,
-                request.to.asset_code(),
-                request.to.asset_scale(),
-                &request.prepare,
-            );
-            match response {
-                Ok(ReceiveOk { fulfill, sequence }) => {
-                    self.store
-                        .publish_payment_notification(PaymentNotification {
-                            to_username,
-                            from_username,
-                            amount,
-                            destination,
-                            timestamp: DateTime::<Utc>::from(SystemTime::now()).to_rfc3339(),
-                            sequence,
-                            connection_closed: false,
-                        });
-                    Ok(fulfill)
-                }
-                Err(ReceiveErr::InvalidPacket) => {
-                    // Additional context for the spared comment; the decryption or parsing fails
-                    // path used to be handled by signalling an F06 from the receive_money step:
-                    //
-                    // <historical_comment>
-                    // Assume the packet isn't for us if the decryption step fails. Note this means
-                    // that if the packet data is modified in any way, the sender will likely see
-                    // an error like F02: Unavailable (this is a bit confusing but the packet data
-                    // should not be modified at all under normal circumstances).
-                    // </historical_comment>
-                    self.next.send_request(request).await
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let to_username = request.to.username().clone();
+            let from_username = request.from.username().clone();
+            let amount = request.prepare.amount();
+
+            let destination = request.prepare.destination();
+            let to_address = request.to.ilp_address();
+            let dest: &[u8] = destination.as_ref();
+
+            // The case where the request is bound for this server
+            if dest.starts_with(to_address.as_ref()) {
+                // While a server secret is being rotated, the packet may be for a connection
+                // which was set up with the previous one
+                let mut response = Err(ReceiveErr::InvalidPacket);
+                for shared_secret in self.connection_generator.rederive_secrets(&destination) {
+                    response = receive_money(
+                        &shared_secret,
+                        to_address,
+                        request.to.asset_code(),
+                        request.to.asset_scale(),
+                        &request.prepare,
+                    );
+                    if !matches!(response, Err(ReceiveErr::InvalidPacket)) {
+                        break;
+                    }
                 }
-                Err(ReceiveErr::Rejection {
-                    reject,
-                    sequence,
-                    connection_closed,
-                }) => {
-                    if connection_closed {
+                match response {
+                    Ok(ReceiveOk { fulfill, sequence }) => {
+                        if let Some(ref payments) = self.payments {
+                            if amount > 0 {
+                                // Without the record, the money could be lost if the application
+                                // crashes, so the packet is only fulfilled once it is recorded
+                                if let Err(err) = payments
+                                    .record_incoming_payment(
+                                        request.to.id(),
+                                        &destination,
+                                        amount,
+                                        false,
+                                        now_millis(),
+                                    )
+                                    .await
+                                {
+                                    error!(
+                                        "Error recording incoming payment to account {}: {}",
+                                        request.to.id(),
+                                        err
+                                    );
+                                    return Err(RejectBuilder {
+                                        code: ErrorCode::T00_INTERNAL_ERROR,
+                                        message: b"Error recording payment",
+                                        triggered_by: Some(to_address),
+                                        data: &[],
+                                    }
+                                    .build());
+                                }
+                            }
+                        }
                         self.store
                             .publish_payment_notification(PaymentNotification {
                                 to_username,
                                 from_username,
-                                amount: 0,
+                                amount,
                                 destination,
                                 timestamp: DateTime::<Utc>::from(SystemTime::now()).to_rfc3339(),
                                 sequence,
-                                connection_closed: true,
+                                connection_closed: false,
                             });
+                        Ok(fulfill)
+                    }
+                    Err(ReceiveErr::InvalidPacket) => {
+                        // Additional context for the spared comment; the decryption or parsing fails
+                        // path used to be handled by signalling an F06 from the receive_money step:
+                        //
+                        // <historical_comment>
+                        // Assume the packet isn't for us if the decryption step fails. Note this means
+                        // that if the packet data is modified in any way, the sender will likely see
+                        // an error like F02: Unavailable (this is a bit confusing but the packet data
+                        // should not be modified at all under normal circumstances).
+                        // </historical_comment>
+                        self.next.send_request(request).await
+                    }
+                    Err(ReceiveErr::Rejection {
+                        reject,
+                        sequence,
+                        connection_closed,
+                    }) => {
+                        if connection_closed {
+                            if let Some(ref payments) = self.payments {
+                                if let Err(err) = payments
+                                    .record_incoming_payment(
+                                        request.to.id(),
+                                        &destination,
+                                        0,
+                                        true,
+                                        now_millis(),
+                                    )
+                                    .await
+                                {
+                                    error!(
+                                        "Error recording the close of incoming payment to account {}: {}",
+                                        request.to.id(),
+                                        err
+                                    );
+                                }
+                            }
+                            self.store
+                                .publish_payment_notification(PaymentNotification {
+                                    to_username,
+                                    from_username,
+                                    amount: 0,
+                                    destination,
+                                    timestamp: DateTime::<Utc>::from(SystemTime::now())
+                                        .to_rfc3339(),
+                                    sequence,
+                                    connection_closed: true,
+                                });
+                        }
+
+                        Err(reject)
                     }
-
-                    Err(reject)
                 }
+            } else {
+                self.next.send_request(request).await
             }
-        } else {
-            self.next.send_request(request).await
-        }
+        })
     }
 }
 
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or_default()
+}
+
 // TODO send asset code and scale back to sender also
 #[allow(clippy::cognitive_complexity)]
 fn receive_money(
@@ -280,13 +467,15 @@ fn receive_money(
 
     // Creating a copy for the prepare.data() cannot be avoided, as the decryption happens in place
     // while the outer Prepare needs to remain unchanged.
-    let copied_data = BytesMut::from(prepare.data());
+    let mut copied_data = pool::take(prepare.data().len());
+    copied_data.extend_from_slice(prepare.data());
 
     let stream_packet = StreamPacket::from_encrypted(shared_secret, copied_data)
         .map_err(|_| ReceiveErr::InvalidPacket)?;
 
     let mut response_frames: Vec<Frame> = Vec::new();
     let mut connection_closed = false;
+    let verbose = sample_packet();
 
     // Handle STREAM frames
     // TODO reject if they send data?
@@ -329,18 +518,21 @@ fn receive_money(
             frames: &response_frames,
         }
         .build();
-        debug!(
-            "Fulfilling prepare for amount {} with fulfillment: {:?} and encrypted stream packet: {:?}",
-            prepare_amount,
-            HexString(&fulfillment[..]),
-            response_packet
-        );
+        if verbose {
+            debug!(
+                "Fulfilling prepare for amount {} with fulfillment: {:?} and encrypted stream packet: {:?}",
+                prepare_amount,
+                Redacted(HexString(&fulfillment[..])),
+                response_packet
+            );
+        }
         let encrypted_response = response_packet.into_encrypted(shared_secret);
         let fulfill = FulfillBuilder {
             fulfillment: &fulfillment,
             data: &encrypted_response[..],
         }
         .build();
+        pool::recycle(encrypted_response);
         Ok(ReceiveOk {
             fulfill,
             sequence: stream_packet.sequence(),
@@ -353,19 +545,21 @@ fn receive_money(
             frames: &response_frames,
         }
         .build();
-        if !is_fulfillable {
-            debug!("Packet is unfulfillable");
-        } else if prepare_amount < stream_packet.prepare_amount() {
+        if verbose {
+            if !is_fulfillable {
+                debug!("Packet is unfulfillable");
+            } else if prepare_amount < stream_packet.prepare_amount() {
+                debug!(
+                    "Received only: {} when we should have received at least: {}",
+                    prepare_amount,
+                    stream_packet.prepare_amount()
+                );
+            }
             debug!(
-                "Received only: {} when we should have received at least: {}",
-                prepare_amount,
-                stream_packet.prepare_amount()
+                "Rejecting Prepare and including encrypted stream packet {:?}",
+                response_packet
             );
         }
-        debug!(
-            "Rejecting Prepare and including encrypted stream packet {:?}",
-            response_packet
-        );
         let encrypted_response = response_packet.into_encrypted(shared_secret);
         let reject = RejectBuilder {
             code: ErrorCode::F99_APPLICATION_ERROR,
@@ -374,6 +568,7 @@ fn receive_money(
             data: &encrypted_response[..],
         }
         .build();
+        pool::recycle(encrypted_response);
         Err(ReceiveErr::Rejection {
             reject,
             sequence: stream_packet.sequence(),
@@ -573,7 +768,7 @@ mod stream_receiver_service {
     use super::*;
     use crate::test_helpers::*;
     use interledger_packet::PrepareBuilder;
-    use interledger_service::outgoing_service_fn;
+    use interledger_service::{outgoing_service_fn, IlpResult};
 
     use std::str::FromStr;
     use std::time::UNIX_EPOCH;
@@ -752,4 +947,129 @@ mod stream_receiver_service {
             Address::from_str("example.other-receiver").unwrap(),
         );
     }
+
+    /// Records the payments in memory, or fails to if `fail` is set
+    #[derive(Clone, Default)]
+    struct TestPaymentsStore {
+        payments: Arc<parking_lot::Mutex<Vec<IncomingPayment>>>,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl IncomingPaymentsStore for TestPaymentsStore {
+        async fn record_incoming_payment(
+            &self,
+            account_id: Uuid,
+            destination: &Address,
+            amount: u64,
+            closed: bool,
+            timestamp: u64,
+        ) -> Result<IncomingPayment, BalanceStoreError> {
+            if self.fail {
+                return Err(BalanceStoreError::Other(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "store unavailable",
+                ))));
+            }
+            let payment = IncomingPayment {
+                account_id,
+                destination: destination.clone(),
+                amount,
+                closed,
+                acknowledged: false,
+                updated_at: timestamp,
+            };
+            self.payments.lock().push(payment.clone());
+            Ok(payment)
+        }
+
+        async fn acknowledge_incoming_payment(
+            &self,
+            _account_id: Uuid,
+            _destination: &Address,
+        ) -> Result<Option<IncomingPayment>, BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn get_unacknowledged_payments(
+            &self,
+            _account_id: Uuid,
+        ) -> Result<Vec<IncomingPayment>, BalanceStoreError> {
+            unimplemented!()
+        }
+    }
+
+    async fn receive(payments: TestPaymentsStore, to_id: Uuid) -> (Address, IlpResult) {
+        let ilp_address = Address::from_str("example.destination").unwrap();
+        let server_secret = Bytes::from(&[1; 32][..]);
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&ilp_address);
+        let data = test_stream_packet().into_encrypted(&shared_secret[..]);
+        let execution_condition = generate_condition(&shared_secret[..], &data);
+
+        let prepare = PrepareBuilder {
+            destination: destination_account.clone(),
+            amount: 100,
+            expires_at: UNIX_EPOCH,
+            data: &data[..],
+            execution_condition: &execution_condition,
+        }
+        .build();
+
+        let mut service = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_: OutgoingRequest<TestAccount>| -> IlpResult {
+                panic!("shouldn't get here")
+            }),
+        )
+        .record_payments(payments);
+
+        let result = service
+            .send_request(OutgoingRequest {
+                from: TestAccount {
+                    id: Uuid::new_v4(),
+                    ilp_address: Address::from_str("example.sender").unwrap(),
+                    asset_code: "XYZ".to_string(),
+                    asset_scale: 9,
+                    max_packet_amount: None,
+                },
+                to: TestAccount {
+                    id: to_id,
+                    ilp_address,
+                    asset_code: "XYZ".to_string(),
+                    asset_scale: 9,
+                    max_packet_amount: None,
+                },
+                original_amount: prepare.amount(),
+                prepare,
+            })
+            .await;
+        (destination_account, result)
+    }
+
+    #[tokio::test]
+    async fn records_payments_before_fulfilling() {
+        let payments = TestPaymentsStore::default();
+        let to_id = Uuid::new_v4();
+        let (destination, result) = receive(payments.clone(), to_id).await;
+        assert!(result.is_ok());
+        let recorded = payments.payments.lock().clone();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].account_id, to_id);
+        assert_eq!(recorded[0].destination, destination);
+        assert_eq!(recorded[0].amount, 100);
+        assert!(!recorded[0].closed);
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_which_cannot_be_recorded() {
+        let payments = TestPaymentsStore {
+            fail: true,
+            ..Default::default()
+        };
+        let (_, result) = receive(payments, Uuid::new_v4()).await;
+        assert_eq!(result.unwrap_err().code(), ErrorCode::T00_INTERNAL_ERROR);
+    }
 }
@@ -0,0 +1,39 @@
+use interledger_packet::Address;
+use interledger_service::{Account, Username};
+use interledger_service_util::RoundTripTimeAccount;
+use uuid::Uuid;
+
+/// An account of a node in a [`TestNetwork`](../struct.TestNetwork.html): either a local
+/// account which sends and receives payments, or the account of a peer the node is linked to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestAccount {
+    pub(crate) id: Uuid,
+    pub(crate) username: Username,
+    pub(crate) ilp_address: Address,
+    pub(crate) asset_code: String,
+    pub(crate) asset_scale: u8,
+}
+
+impl Account for TestAccount {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn username(&self) -> &Username {
+        &self.username
+    }
+
+    fn ilp_address(&self) -> &Address {
+        &self.ilp_address
+    }
+
+    fn asset_scale(&self) -> u8 {
+        self.asset_scale
+    }
+
+    fn asset_code(&self) -> &str {
+        &self.asset_code
+    }
+}
+
+impl RoundTripTimeAccount for TestAccount {}
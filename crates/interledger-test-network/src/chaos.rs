@@ -0,0 +1,211 @@
+use futures::future::join;
+use interledger_packet::{ErrorCode, Prepare, RejectBuilder};
+use interledger_service::{
+    runtime, Account, BoxedIlpFuture, IlpResult, IncomingRequest, IncomingService, OutgoingRequest,
+    OutgoingService,
+};
+use parking_lot::Mutex;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// The pathologies a [`ChaosService`](./struct.ChaosService.html) injects into the packets
+/// passing through it. Whether each packet is held back, dropped or duplicated is decided
+/// independently, by a random number generator seeded with the seed, so a failing run can
+/// be reproduced. The default injects nothing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChaosConfig {
+    seed: u64,
+    min_latency: Duration,
+    max_latency: Duration,
+    loss: f64,
+    duplication: f64,
+    reordering: f64,
+    reorder_delay: Duration,
+}
+
+impl ChaosConfig {
+    pub fn new(seed: u64) -> Self {
+        ChaosConfig {
+            seed,
+            ..ChaosConfig::default()
+        }
+    }
+
+    /// Delays each packet by a duration picked uniformly between the minimum and the maximum
+    pub fn latency(mut self, min: Duration, max: Duration) -> Self {
+        assert!(min <= max, "The minimum latency is above the maximum");
+        self.min_latency = min;
+        self.max_latency = max;
+        self
+    }
+
+    /// Drops packets with the probability. A dropped packet is never delivered, so its sender
+    /// gets an R00 Reject once the packet expires, as if the peer never answered.
+    pub fn loss(mut self, probability: f64) -> Self {
+        self.loss = check_probability(probability);
+        self
+    }
+
+    /// Delivers packets twice with the probability. The sender gets the response to the
+    /// first copy.
+    pub fn duplication(mut self, probability: f64) -> Self {
+        self.duplication = check_probability(probability);
+        self
+    }
+
+    /// Holds packets back by the delay, on top of their latency, with the probability, so the
+    /// packets sent after them overtake them
+    pub fn reordering(mut self, probability: f64, delay: Duration) -> Self {
+        self.reordering = check_probability(probability);
+        self.reorder_delay = delay;
+        self
+    }
+}
+
+fn check_probability(probability: f64) -> f64 {
+    assert!(
+        (0.0..=1.0).contains(&probability),
+        "{} is not a probability",
+        probability
+    );
+    probability
+}
+
+/// What happens to a packet on its way to the next service
+struct Fate {
+    delay: Duration,
+    dropped: bool,
+    duplicated: bool,
+}
+
+/// Injects delays, losses, duplicate deliveries and reordering into the packets passed to the
+/// next service, so that retries, congestion control and timeouts can be tested against the
+/// pathologies of a real network. It can wrap both incoming and outgoing services.
+#[derive(Clone)]
+pub struct ChaosService<S> {
+    config: ChaosConfig,
+    rng: Arc<Mutex<StdRng>>,
+    next: S,
+}
+
+impl<S> ChaosService<S> {
+    pub fn new(config: ChaosConfig, next: S) -> Self {
+        ChaosService {
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(config.seed))),
+            config,
+            next,
+        }
+    }
+
+    fn fate(&self) -> Fate {
+        let config = &self.config;
+        let mut rng = self.rng.lock();
+        // Every draw is made for every packet, so the fate of a packet only depends on the
+        // seed and the number of packets before it
+        let mut delay = if config.max_latency > config.min_latency {
+            let nanos = rng.gen_range(
+                config.min_latency.as_nanos() as u64,
+                config.max_latency.as_nanos() as u64 + 1,
+            );
+            Duration::from_nanos(nanos)
+        } else {
+            config.min_latency
+        };
+        if rng.gen_bool(config.reordering) {
+            delay += config.reorder_delay;
+        }
+        Fate {
+            delay,
+            dropped: rng.gen_bool(config.loss),
+            duplicated: rng.gen_bool(config.duplication),
+        }
+    }
+}
+
+/// Waits out the latency of the packet, and answers for the peer if the packet was lost
+async fn travel(fate: &Fate, prepare: &Prepare) -> Option<IlpResult> {
+    if fate.delay > Duration::from_secs(0) {
+        runtime::sleep(fate.delay).await;
+    }
+    if !fate.dropped {
+        return None;
+    }
+
+    if let Ok(time_left) = prepare.expires_at().duration_since(SystemTime::now()) {
+        runtime::sleep(time_left).await;
+    }
+    Some(Err(RejectBuilder {
+        code: ErrorCode::R00_TRANSFER_TIMED_OUT,
+        message: b"Packet was lost",
+        triggered_by: None,
+        data: &[],
+    }
+    .build()))
+}
+
+impl<S, A> IncomingService<A> for ChaosService<S>
+where
+    S: IncomingService<A> + Clone + Send,
+    A: Account,
+{
+    type Future<'a>
+        = BoxedIlpFuture<'a>
+    where
+        Self: 'a;
+
+    fn handle_request(&mut self, request: IncomingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let fate = self.fate();
+            if let Some(result) = travel(&fate, &request.prepare).await {
+                return result;
+            }
+
+            if fate.duplicated {
+                let mut duplicate = self.next.clone();
+                let (result, _) = join(
+                    self.next.handle_request(request.clone()),
+                    duplicate.handle_request(request),
+                )
+                .await;
+                result
+            } else {
+                self.next.handle_request(request).await
+            }
+        })
+    }
+}
+
+impl<S, A> OutgoingService<A> for ChaosService<S>
+where
+    S: OutgoingService<A> + Clone + Send,
+    A: Account,
+{
+    type Future<'a>
+        = BoxedIlpFuture<'a>
+    where
+        Self: 'a;
+
+    fn send_request(&mut self, request: OutgoingRequest<A>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let fate = self.fate();
+            if let Some(result) = travel(&fate, &request.prepare).await {
+                return result;
+            }
+
+            if fate.duplicated {
+                let mut duplicate = self.next.clone();
+                let (result, _) = join(
+                    self.next.send_request(request.clone()),
+                    duplicate.send_request(request),
+                )
+                .await;
+                result
+            } else {
+                self.next.send_request(request).await
+            }
+        })
+    }
+}
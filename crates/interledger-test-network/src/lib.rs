@@ -0,0 +1,49 @@
+//! # interledger-test-network
+//!
+//! Wires several Interledger nodes together in one process, so that integration tests can
+//! send payments across multi-hop paths without Docker, Redis or sockets.
+//!
+//! Each node has an in-memory [`TestStore`](./struct.TestStore.html) and the service chain
+//! of `ilp-node`: the validators, the router, the exchange rates, the STREAM receiver and
+//! the expiry shortener. In place of the BTP and ILP-over-HTTP clients, the
+//! [`LinkService`](./struct.LinkService.html) hands the packets for a peer straight to the
+//! incoming service of the peer's node. The routing tables are computed from the links when
+//! the network is built, and account ids and server secrets are handed out in order, so
+//! every run of a test takes the same paths.
+//!
+//! To test how the nodes cope with a real network, a
+//! [`ChaosConfig`](./struct.ChaosConfig.html) set with
+//! [`TestNetworkBuilder::chaos`](./struct.TestNetworkBuilder.html#method.chaos) delays, drops,
+//! duplicates and reorders the packets a node sends to its peers.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), interledger_stream::Error> {
+//! use interledger_test_network::TestNetwork;
+//!
+//! let network = TestNetwork::builder()
+//!     .node("n1", "XYZ", 9)
+//!     .node("n2", "XYZ", 9)
+//!     .node("n3", "XYZ", 9)
+//!     .link("n1", "n2")
+//!     .link("n2", "n3")
+//!     .account("n1", "alice")
+//!     .account("n3", "bob")
+//!     .build();
+//!
+//! network.pay("n1", "alice", "n3", "bob", 1000).await?;
+//! assert_eq!(network.node("n3").received("bob"), 1000);
+//! # Ok(())
+//! # }
+//! ```
+
+mod account;
+mod chaos;
+mod link;
+mod network;
+mod store;
+
+pub use self::account::TestAccount;
+pub use self::chaos::{ChaosConfig, ChaosService};
+pub use self::link::{Hop, LinkService};
+pub use self::network::{NodeService, TestNetwork, TestNetworkBuilder, TestNode};
+pub use self::store::TestStore;
@@ -0,0 +1,101 @@
+use crate::{NodeService, TestAccount};
+use interledger_packet::{Address, ErrorCode, RejectBuilder};
+use interledger_service::{
+    Account, BoxedIlpFuture, IncomingRequest, IncomingService, OutgoingRequest, OutgoingService,
+};
+use parking_lot::{Mutex, RwLock};
+use std::{collections::HashMap, sync::Arc};
+use uuid::Uuid;
+
+/// A Prepare packet which one node of the network handed to another
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hop {
+    /// The name of the node which sent the packet
+    pub from: String,
+    /// The name of the node which received the packet
+    pub to: String,
+    /// The destination of the packet
+    pub destination: Address,
+    /// The amount of the packet, in the asset of the link
+    pub amount: u64,
+}
+
+/// The other end of a link, as seen from one of the nodes
+#[derive(Clone)]
+pub(crate) struct Peer {
+    /// The name of the peer's node
+    pub(crate) node: String,
+    /// The incoming service of the peer's node
+    pub(crate) service: NodeService,
+    /// The account the peer's node keeps for the node which sends the packets
+    pub(crate) account: TestAccount,
+}
+
+/// The peers of all nodes, keyed by the id of the account which the sending node keeps for them
+pub(crate) type Peers = Arc<RwLock<HashMap<Uuid, Peer>>>;
+
+/// The outgoing service at the end of each node's chain, which takes the place of the BTP and
+/// ILP-over-HTTP clients. The packets are passed straight to the incoming service of the peer's
+/// node, as if they came in from the account which the peer keeps for this node.
+#[derive(Clone)]
+pub struct LinkService {
+    node: String,
+    ilp_address: Address,
+    peers: Peers,
+    hops: Arc<Mutex<Vec<Hop>>>,
+}
+
+impl LinkService {
+    pub(crate) fn new(
+        node: String,
+        ilp_address: Address,
+        peers: Peers,
+        hops: Arc<Mutex<Vec<Hop>>>,
+    ) -> Self {
+        LinkService {
+            node,
+            ilp_address,
+            peers,
+            hops,
+        }
+    }
+}
+
+impl OutgoingService<TestAccount> for LinkService {
+    type Future<'a> = BoxedIlpFuture<'a>;
+
+    fn send_request(&mut self, request: OutgoingRequest<TestAccount>) -> Self::Future<'_> {
+        Box::pin(async move {
+            let peer = self.peers.read().get(&request.to.id()).cloned();
+            let Peer {
+                node,
+                mut service,
+                account,
+            } = match peer {
+                Some(peer) => peer,
+                None => {
+                    return Err(RejectBuilder {
+                        code: ErrorCode::F02_UNREACHABLE,
+                        message: format!("No link to account {}", request.to.username()).as_bytes(),
+                        triggered_by: Some(&self.ilp_address),
+                        data: &[],
+                    }
+                    .build())
+                }
+            };
+
+            self.hops.lock().push(Hop {
+                from: self.node.clone(),
+                to: node,
+                destination: request.prepare.destination(),
+                amount: request.prepare.amount(),
+            });
+            service
+                .handle_request(IncomingRequest {
+                    from: account,
+                    prepare: request.prepare,
+                })
+                .await
+        })
+    }
+}
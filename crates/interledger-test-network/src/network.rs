@@ -0,0 +1,392 @@
+use crate::link::{Hop, LinkService, Peer, Peers};
+use crate::{ChaosConfig, ChaosService, TestAccount, TestStore};
+use bytes::Bytes;
+use interledger_packet::Address;
+use interledger_rates::ExchangeRateStore;
+use interledger_router::Router;
+use interledger_service::{clock::SharedClock, Account, AddressStore, Username};
+use interledger_service_util::{ExchangeRateService, ExpiryShortenerService, ValidatorService};
+use interledger_stream::{
+    send_money, ConnectionGenerator, Error as StreamError, StreamDelivery, StreamReceiverService,
+};
+use parking_lot::{Mutex, RwLock};
+use std::{
+    collections::{HashMap, VecDeque},
+    str::FromStr,
+    sync::Arc,
+};
+use uuid::Uuid;
+
+/// The slippage the payments sent with [`TestNetwork::pay`](./struct.TestNetwork.html#method.pay)
+/// accept, to allow for the rounding of the connectors which convert the amounts
+const SLIPPAGE: f64 = 0.015;
+
+/// The outgoing services of a node, in the order `ilp-node` chains them
+type NodeOutgoingService = ExchangeRateService<
+    TestStore,
+    StreamReceiverService<
+        TestStore,
+        ExpiryShortenerService<ValidatorService<ChaosService<LinkService>, TestStore, TestAccount>>,
+        TestAccount,
+    >,
+    TestAccount,
+>;
+
+/// The incoming service of a node, which its local accounts send payments through and its
+/// peers hand their packets to
+pub type NodeService =
+    ValidatorService<Router<TestStore, NodeOutgoingService>, TestStore, TestAccount>;
+
+/// A node of a [`TestNetwork`](./struct.TestNetwork.html)
+pub struct TestNode {
+    name: String,
+    store: TestStore,
+    service: NodeService,
+    connection_generator: ConnectionGenerator,
+}
+
+impl TestNode {
+    /// The name the node was added to the network with
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The node's ILP address, `example.<name>`
+    pub fn ilp_address(&self) -> Address {
+        self.store.get_ilp_address()
+    }
+
+    pub fn store(&self) -> &TestStore {
+        &self.store
+    }
+
+    /// The node's incoming service
+    pub fn service(&self) -> NodeService {
+        self.service.clone()
+    }
+
+    /// Returns the local account, or the account of a peer, with the username
+    ///
+    /// # Panics
+    ///
+    /// If the node has no such account
+    pub fn account(&self, username: &str) -> TestAccount {
+        self.store
+            .account(&parse_username(username))
+            .unwrap_or_else(|| panic!("Node {} has no account {}", self.name, username))
+    }
+
+    /// The total amount the node's STREAM receiver fulfilled for the account
+    pub fn received(&self, username: &str) -> u64 {
+        self.store.received(&parse_username(username))
+    }
+}
+
+/// Nodes connected by in-memory links, which packets cross deterministically and without
+/// any I/O
+pub struct TestNetwork {
+    nodes: Vec<TestNode>,
+    peers: Peers,
+    hops: Arc<Mutex<Vec<Hop>>>,
+}
+
+impl TestNetwork {
+    pub fn builder() -> TestNetworkBuilder {
+        TestNetworkBuilder::default()
+    }
+
+    /// Returns the node with the name
+    ///
+    /// # Panics
+    ///
+    /// If the network has no such node
+    pub fn node(&self, name: &str) -> &TestNode {
+        self.nodes
+            .iter()
+            .find(|node| node.name == name)
+            .unwrap_or_else(|| panic!("No node named {}", name))
+    }
+
+    /// Sends a STREAM payment from a local account of one node to a local account of another
+    pub async fn pay(
+        &self,
+        from_node: &str,
+        from_username: &str,
+        to_node: &str,
+        to_username: &str,
+        source_amount: u64,
+    ) -> Result<StreamDelivery, StreamError> {
+        let sender = self.node(from_node);
+        let from = sender.account(from_username);
+        let receiver = self.node(to_node);
+        let to = receiver.account(to_username);
+
+        let (destination, shared_secret) = receiver
+            .connection_generator
+            .generate_address_and_secret(to.ilp_address());
+        send_money(
+            sender.service(),
+            &from,
+            sender.store.clone(),
+            destination,
+            shared_secret.to_vec(),
+            source_amount,
+            SLIPPAGE,
+        )
+        .await
+    }
+
+    /// The packets handed from node to node so far, in the order they were sent
+    pub fn hops(&self) -> Vec<Hop> {
+        self.hops.lock().clone()
+    }
+
+    /// Forgets the packets sent so far
+    pub fn clear_hops(&self) {
+        self.hops.lock().clear();
+    }
+}
+
+/// Configures the nodes of a [`TestNetwork`](./struct.TestNetwork.html), the links between
+/// them and their local accounts
+#[derive(Default)]
+pub struct TestNetworkBuilder {
+    nodes: Vec<(String, String, u8)>,
+    links: Vec<(String, String)>,
+    accounts: Vec<(String, String)>,
+    rates: HashMap<String, f64>,
+    chaos: HashMap<String, ChaosConfig>,
+    clock: SharedClock,
+}
+
+impl TestNetworkBuilder {
+    /// Adds a node whose local accounts are denominated in the asset. The name is used as
+    /// the username of the accounts its peers keep for it, so it must be a valid username.
+    pub fn node(mut self, name: &str, asset_code: &str, asset_scale: u8) -> Self {
+        self.nodes
+            .push((name.to_string(), asset_code.to_string(), asset_scale));
+        self
+    }
+
+    /// Peers two nodes. The accounts on both ends of the link are denominated in the asset
+    /// of the first node.
+    pub fn link(mut self, node: &str, peer: &str) -> Self {
+        self.links.push((node.to_string(), peer.to_string()));
+        self
+    }
+
+    /// Adds a local account to the node, with the address `example.<node>.<username>`
+    pub fn account(mut self, node: &str, username: &str) -> Self {
+        self.accounts.push((node.to_string(), username.to_string()));
+        self
+    }
+
+    /// Sets the exchange rate of the asset on all nodes
+    pub fn rate(mut self, asset_code: &str, rate: f64) -> Self {
+        self.rates.insert(asset_code.to_string(), rate);
+        self
+    }
+
+    /// Injects the pathologies into the packets the node sends to its peers
+    pub fn chaos(mut self, node: &str, config: ChaosConfig) -> Self {
+        self.chaos.insert(node.to_string(), config);
+        self
+    }
+
+    /// Sets the clock which all nodes check the expiry of the packets and the age of the
+    /// rates against, so a test can expire packets by advancing it
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Creates the nodes and computes their routing tables. Each node routes packets along
+    /// the path with the fewest hops, preferring links which were added first.
+    ///
+    /// # Panics
+    ///
+    /// If a node name is used twice or is not a valid username, or a link, account or chaos
+    /// configuration refers to a node which was not added
+    pub fn build(self) -> TestNetwork {
+        let index_of = |name: &str| {
+            self.nodes
+                .iter()
+                .position(|(node, _, _)| node == name)
+                .unwrap_or_else(|| panic!("No node named {}", name))
+        };
+        // The ids are handed out in order so that every run of a test uses the same ones
+        let mut next_id = 0u128;
+        let mut new_id = || {
+            next_id += 1;
+            Uuid::from_u128(next_id)
+        };
+
+        let addresses: Vec<Address> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, (name, _, _))| {
+                assert_eq!(index_of(name), index, "Node {} was added twice", name);
+                parse_username(name);
+                Address::from_str(&format!("example.{}", name)).unwrap()
+            })
+            .collect();
+        for node in self.chaos.keys() {
+            index_of(node);
+        }
+        let stores: Vec<TestStore> = addresses
+            .iter()
+            .map(|address| {
+                let store = TestStore::new(address.clone());
+                store.set_exchange_rates(self.rates.clone()).unwrap();
+                store
+            })
+            .collect();
+        let mut routes: Vec<HashMap<String, Uuid>> = vec![HashMap::new(); self.nodes.len()];
+
+        for (node, username) in &self.accounts {
+            let index = index_of(node);
+            let (_, asset_code, asset_scale) = &self.nodes[index];
+            let account = TestAccount {
+                id: new_id(),
+                username: parse_username(username),
+                ilp_address: addresses[index].with_suffix(username.as_bytes()).unwrap(),
+                asset_code: asset_code.clone(),
+                asset_scale: *asset_scale,
+            };
+            routes[index].insert(account.ilp_address.to_string(), account.id);
+            stores[index].insert_account(account);
+        }
+
+        // The account each node keeps for each of its peers, in the order the links were added
+        let mut peer_accounts: Vec<Vec<(usize, TestAccount)>> = vec![Vec::new(); self.nodes.len()];
+        // For both ends of each link: the account the node keeps for its peer, the peer and the
+        // account the peer keeps for the node in return
+        let mut link_accounts: Vec<(TestAccount, usize, TestAccount)> = Vec::new();
+        for (node, peer) in &self.links {
+            let (node, peer) = (index_of(node), index_of(peer));
+            let (_, asset_code, asset_scale) = &self.nodes[node];
+            let mut peer_account = |local: usize, remote: usize| {
+                let account = TestAccount {
+                    id: new_id(),
+                    username: parse_username(&self.nodes[remote].0),
+                    ilp_address: addresses[remote].clone(),
+                    asset_code: asset_code.clone(),
+                    asset_scale: *asset_scale,
+                };
+                stores[local].insert_account(account.clone());
+                peer_accounts[local].push((remote, account.clone()));
+                account
+            };
+            let node_account = peer_account(node, peer);
+            let peer_account = peer_account(peer, node);
+            link_accounts.push((node_account.clone(), peer, peer_account.clone()));
+            link_accounts.push((peer_account, node, node_account));
+        }
+
+        for (index, routes) in routes.iter_mut().enumerate() {
+            for (destination, first_hop) in shortest_paths(index, &peer_accounts) {
+                routes.insert(addresses[destination].to_string(), first_hop);
+            }
+            stores[index].set_routes(routes.clone());
+        }
+
+        let peers: Peers = Arc::new(RwLock::new(HashMap::new()));
+        let hops = Arc::new(Mutex::new(Vec::new()));
+        let nodes: Vec<TestNode> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, (name, _, _))| {
+                let store = stores[index].clone();
+                let connection_generator =
+                    ConnectionGenerator::new(Bytes::from(vec![index as u8 + 1; 32]));
+
+                let outgoing_service = LinkService::new(
+                    name.clone(),
+                    addresses[index].clone(),
+                    peers.clone(),
+                    hops.clone(),
+                );
+                let chaos = self.chaos.get(name).cloned().unwrap_or_default();
+                let outgoing_service = ChaosService::new(chaos, outgoing_service);
+                let outgoing_service = ValidatorService::outgoing(store.clone(), outgoing_service)
+                    .clock(self.clock.clone());
+                let outgoing_service =
+                    ExpiryShortenerService::new(outgoing_service).clock(self.clock.clone());
+                let outgoing_service = StreamReceiverService::with_connection_generator(
+                    connection_generator.clone(),
+                    store.clone(),
+                    outgoing_service,
+                );
+                let outgoing_service =
+                    ExchangeRateService::new(0.0, store.clone(), outgoing_service)
+                        .clock(self.clock.clone());
+                let incoming_service = Router::new(store.clone(), outgoing_service);
+                let incoming_service = ValidatorService::incoming(store.clone(), incoming_service)
+                    .clock(self.clock.clone());
+
+                TestNode {
+                    name: name.clone(),
+                    store,
+                    service: incoming_service,
+                    connection_generator,
+                }
+            })
+            .collect();
+
+        // The links can only be connected once all nodes have their services
+        let mut links = peers.write();
+        for (account, remote, remote_account) in link_accounts {
+            let remote = &nodes[remote];
+            links.insert(
+                account.id,
+                Peer {
+                    node: remote.name.clone(),
+                    service: remote.service(),
+                    account: remote_account,
+                },
+            );
+        }
+        drop(links);
+
+        TestNetwork { nodes, peers, hops }
+    }
+}
+
+impl Drop for TestNetwork {
+    /// The nodes' services refer to each other through the links, so the links are cut for
+    /// the services to be freed
+    fn drop(&mut self) {
+        self.peers.write().clear();
+    }
+}
+
+/// Finds the peer account each node's packets go through first on the way to every other node
+fn shortest_paths(
+    source: usize,
+    peer_accounts: &[Vec<(usize, TestAccount)>],
+) -> HashMap<usize, Uuid> {
+    let mut first_hops = HashMap::new();
+    let mut queue = VecDeque::new();
+    for (peer, account) in &peer_accounts[source] {
+        if *peer != source && !first_hops.contains_key(peer) {
+            first_hops.insert(*peer, account.id);
+            queue.push_back(*peer);
+        }
+    }
+    while let Some(node) = queue.pop_front() {
+        let first_hop = first_hops[&node];
+        for (peer, _) in &peer_accounts[node] {
+            if *peer != source && !first_hops.contains_key(peer) {
+                first_hops.insert(*peer, first_hop);
+                queue.push_back(*peer);
+            }
+        }
+    }
+    first_hops
+}
+
+fn parse_username(username: &str) -> Username {
+    Username::from_str(username).unwrap_or_else(|_| panic!("{} is not a valid username", username))
+}
@@ -0,0 +1,203 @@
+use crate::TestAccount;
+use async_trait::async_trait;
+use futures::channel::mpsc::UnboundedSender;
+use interledger_errors::{AccountStoreError, AddressStoreError, ExchangeRateStoreError};
+use interledger_packet::Address;
+use interledger_rates::ExchangeRateStore;
+use interledger_router::RouterStore;
+use interledger_service::{Account, AccountStore, AddressStore, Username};
+use interledger_stream::{PaymentNotification, StreamNotificationsStore};
+use parking_lot::RwLock;
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+const NOTIFICATIONS_CAPACITY: usize = 256;
+
+/// The store of a node in a [`TestNetwork`](../struct.TestNetwork.html), which keeps
+/// everything in memory
+#[derive(Clone)]
+pub struct TestStore {
+    inner: Arc<RwLock<StoreInner>>,
+    notifications: broadcast::Sender<PaymentNotification>,
+}
+
+struct StoreInner {
+    ilp_address: Address,
+    accounts: HashMap<Uuid, TestAccount>,
+    usernames: HashMap<String, Uuid>,
+    routes: Arc<HashMap<String, Uuid>>,
+    rates: HashMap<String, f64>,
+    subscriptions: HashMap<Uuid, Vec<UnboundedSender<PaymentNotification>>>,
+    received: HashMap<String, u64>,
+}
+
+impl TestStore {
+    pub(crate) fn new(ilp_address: Address) -> Self {
+        TestStore {
+            inner: Arc::new(RwLock::new(StoreInner {
+                ilp_address,
+                accounts: HashMap::new(),
+                usernames: HashMap::new(),
+                routes: Arc::new(HashMap::new()),
+                rates: HashMap::new(),
+                subscriptions: HashMap::new(),
+                received: HashMap::new(),
+            })),
+            notifications: broadcast::channel(NOTIFICATIONS_CAPACITY).0,
+        }
+    }
+
+    pub(crate) fn insert_account(&self, account: TestAccount) {
+        let mut inner = self.inner.write();
+        inner
+            .usernames
+            .insert(account.username().to_string(), account.id());
+        inner.accounts.insert(account.id(), account);
+    }
+
+    pub(crate) fn set_routes(&self, routes: HashMap<String, Uuid>) {
+        self.inner.write().routes = Arc::new(routes);
+    }
+
+    /// Returns the account with the username
+    pub fn account(&self, username: &Username) -> Option<TestAccount> {
+        let inner = self.inner.read();
+        inner
+            .usernames
+            .get(&**username)
+            .and_then(|id| inner.accounts.get(id))
+            .cloned()
+    }
+
+    /// Returns the total amount the STREAM receiver of the node fulfilled for the account
+    pub fn received(&self, username: &Username) -> u64 {
+        self.inner
+            .read()
+            .received
+            .get(&**username)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+#[async_trait]
+impl AccountStore for TestStore {
+    type Account = TestAccount;
+
+    async fn get_accounts(
+        &self,
+        account_ids: Vec<Uuid>,
+    ) -> Result<Vec<Self::Account>, AccountStoreError> {
+        let inner = self.inner.read();
+        account_ids
+            .iter()
+            .map(|id| {
+                inner
+                    .accounts
+                    .get(id)
+                    .cloned()
+                    .ok_or_else(|| AccountStoreError::AccountNotFound(id.to_string()))
+            })
+            .collect()
+    }
+
+    async fn get_account_id_from_username(
+        &self,
+        username: &Username,
+    ) -> Result<Uuid, AccountStoreError> {
+        self.inner
+            .read()
+            .usernames
+            .get(&**username)
+            .copied()
+            .ok_or_else(|| AccountStoreError::AccountNotFound(username.to_string()))
+    }
+}
+
+#[async_trait]
+impl AddressStore for TestStore {
+    async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+        self.inner.write().ilp_address = ilp_address;
+        Ok(())
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        self.inner.write().ilp_address = Address::from_str("local.host").unwrap();
+        Ok(())
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        self.inner.read().ilp_address.clone()
+    }
+}
+
+impl RouterStore for TestStore {
+    fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
+        self.inner.read().routes.clone()
+    }
+}
+
+impl ExchangeRateStore for TestStore {
+    fn set_exchange_rates(
+        &self,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        self.inner.write().rates = rates;
+        Ok(())
+    }
+
+    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        let inner = self.inner.read();
+        asset_codes
+            .iter()
+            .map(|code| {
+                inner.rates.get(*code).copied().ok_or_else(|| {
+                    ExchangeRateStoreError::PairNotFound {
+                        from: asset_codes[0].to_string(),
+                        to: asset_codes[asset_codes.len() - 1].to_string(),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        Ok(self.inner.read().rates.clone())
+    }
+}
+
+impl StreamNotificationsStore for TestStore {
+    type Account = TestAccount;
+
+    fn add_payment_notification_subscription(
+        &self,
+        account_id: Uuid,
+        sender: UnboundedSender<PaymentNotification>,
+    ) {
+        self.inner
+            .write()
+            .subscriptions
+            .entry(account_id)
+            .or_default()
+            .push(sender);
+    }
+
+    fn publish_payment_notification(&self, payment: PaymentNotification) {
+        let mut inner = self.inner.write();
+        *inner
+            .received
+            .entry(payment.to_username.to_string())
+            .or_default() += payment.amount;
+        if let Some(id) = inner.usernames.get(&*payment.to_username).copied() {
+            if let Some(senders) = inner.subscriptions.get_mut(&id) {
+                senders.retain(|sender| sender.unbounded_send(payment.clone()).is_ok());
+            }
+        }
+        let _ = self.notifications.send(payment);
+    }
+
+    fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
+        self.notifications.subscribe()
+    }
+}
@@ -0,0 +1,160 @@
+use futures::future::join_all;
+use interledger_packet::{Address, ErrorCode, FulfillBuilder, PrepareBuilder};
+use interledger_service::{outgoing_service_fn, OutgoingRequest, OutgoingService};
+use interledger_test_network::{ChaosConfig, ChaosService, TestAccount, TestNetwork};
+use parking_lot::Mutex;
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+fn pair(chaos: ChaosConfig) -> TestNetwork {
+    TestNetwork::builder()
+        .node("n1", "XYZ", 9)
+        .node("n2", "XYZ", 9)
+        .link("n1", "n2")
+        .account("n1", "alice")
+        .account("n2", "bob")
+        .chaos("n1", chaos)
+        .build()
+}
+
+/// Wraps a service which fulfills every packet and records the amounts of the packets it
+/// was sent, in the order they arrived
+fn chaos(
+    config: ChaosConfig,
+) -> (
+    ChaosService<impl OutgoingService<TestAccount> + Clone>,
+    Arc<Mutex<Vec<u64>>>,
+) {
+    let arrived = Arc::new(Mutex::new(Vec::new()));
+    let recorded = arrived.clone();
+    let next = outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+        recorded.lock().push(request.prepare.amount());
+        Ok(FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: &[],
+        }
+        .build())
+    });
+    (ChaosService::new(config, next), arrived)
+}
+
+fn request(
+    account: &TestAccount,
+    amount: u64,
+    expires_in: Duration,
+) -> OutgoingRequest<TestAccount> {
+    OutgoingRequest {
+        from: account.clone(),
+        to: account.clone(),
+        original_amount: amount,
+        prepare: PrepareBuilder {
+            destination: Address::from_str("example.n2.bob").unwrap(),
+            amount,
+            expires_at: SystemTime::now() + expires_in,
+            execution_condition: &[0; 32],
+            data: &[],
+        }
+        .build(),
+    }
+}
+
+#[tokio::test]
+async fn delays_payments() {
+    let latency = Duration::from_millis(20);
+    let network = pair(ChaosConfig::new(1).latency(latency, latency * 2));
+
+    let start = Instant::now();
+    network.pay("n1", "alice", "n2", "bob", 1000).await.unwrap();
+    assert!(start.elapsed() >= latency);
+    assert_eq!(network.node("n2").received("bob"), 1000);
+}
+
+#[tokio::test]
+async fn drops_packets() {
+    let network = pair(ChaosConfig::default());
+    let account = network.node("n1").account("n2");
+    let (mut service, arrived) = chaos(ChaosConfig::new(1).loss(1.0));
+
+    let expires_in = Duration::from_millis(50);
+    let start = Instant::now();
+    let reject = service
+        .send_request(request(&account, 100, expires_in))
+        .await
+        .unwrap_err();
+    // The sender only finds out once the packet expires
+    assert!(start.elapsed() >= expires_in);
+    assert_eq!(reject.code(), ErrorCode::R00_TRANSFER_TIMED_OUT);
+    assert!(arrived.lock().is_empty());
+}
+
+#[tokio::test]
+async fn duplicates_packets() {
+    let network = pair(ChaosConfig::default());
+    let account = network.node("n1").account("n2");
+    let (mut service, arrived) = chaos(ChaosConfig::new(1).duplication(1.0));
+
+    service
+        .send_request(request(&account, 100, Duration::from_secs(30)))
+        .await
+        .unwrap();
+    assert_eq!(*arrived.lock(), vec![100, 100]);
+}
+
+#[tokio::test]
+async fn reorders_packets() {
+    let network = pair(ChaosConfig::default());
+    let account = network.node("n1").account("n2");
+    let (service, arrived) = chaos(ChaosConfig::new(1).reordering(0.5, Duration::from_millis(20)));
+
+    join_all((1..=20).map(|amount| {
+        let mut service = service.clone();
+        let request = request(&account, amount, Duration::from_secs(30));
+        async move { service.send_request(request).await }
+    }))
+    .await;
+    let mut arrived = arrived.lock().clone();
+    assert_ne!(arrived, (1..=20).collect::<Vec<_>>());
+    arrived.sort_unstable();
+    assert_eq!(arrived, (1..=20).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn injects_the_same_pathologies_for_the_same_seed() {
+    let network = pair(ChaosConfig::default());
+    let account = network.node("n1").account("n2");
+    let config = ChaosConfig::new(42)
+        .duplication(0.5)
+        .reordering(0.5, Duration::from_millis(10));
+
+    let mut runs = Vec::new();
+    for _ in 0..2 {
+        let (service, arrived) = chaos(config.clone());
+        join_all((1..=10).map(|amount| {
+            let mut service = service.clone();
+            let request = request(&account, amount, Duration::from_secs(30));
+            async move { service.send_request(request).await }
+        }))
+        .await;
+        runs.push(arrived.lock().clone());
+    }
+    assert_eq!(runs[0], runs[1]);
+}
+
+#[tokio::test]
+async fn payments_survive_latency_and_reordering() {
+    let network = pair(
+        ChaosConfig::new(7)
+            .latency(Duration::from_millis(1), Duration::from_millis(5))
+            .reordering(0.3, Duration::from_millis(10)),
+    );
+
+    let receipt = network
+        .pay("n1", "alice", "n2", "bob", 1_000_000)
+        .await
+        .unwrap();
+    assert_eq!(receipt.delivered_amount, 1_000_000);
+    assert_eq!(network.node("n2").received("bob"), 1_000_000);
+}
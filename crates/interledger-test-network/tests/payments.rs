@@ -0,0 +1,150 @@
+use interledger_packet::{Address, ErrorCode, PrepareBuilder};
+use interledger_service::{
+    clock::{Clock, ManualClock},
+    IncomingRequest, IncomingService,
+};
+use interledger_test_network::TestNetwork;
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+fn line(names: &[&str]) -> TestNetwork {
+    let mut builder = TestNetwork::builder();
+    for name in names {
+        builder = builder.node(name, "XYZ", 9);
+    }
+    for pair in names.windows(2) {
+        builder = builder.link(pair[0], pair[1]);
+    }
+    builder
+        .account(names[0], "alice")
+        .account(names[names.len() - 1], "bob")
+        .build()
+}
+
+/// Counts the packets sent over each link
+fn hops_per_link(network: &TestNetwork) -> HashMap<(String, String), usize> {
+    let mut counts = HashMap::new();
+    for hop in network.hops() {
+        *counts.entry((hop.from, hop.to)).or_insert(0) += 1;
+    }
+    counts
+}
+
+#[tokio::test]
+async fn five_hop_payment() {
+    let names = ["n1", "n2", "n3", "n4", "n5", "n6"];
+    let network = line(&names);
+
+    let receipt = network
+        .pay("n1", "alice", "n6", "bob", 1_000_000)
+        .await
+        .unwrap();
+    assert_eq!(receipt.sent_amount, 1_000_000);
+    assert_eq!(receipt.delivered_amount, 1_000_000);
+    assert_eq!(network.node("n6").received("bob"), 1_000_000);
+
+    // Every packet crossed all five links, and no other ones
+    let counts = hops_per_link(&network);
+    assert_eq!(counts.len(), 5);
+    let packets = counts[&("n1".to_string(), "n2".to_string())];
+    assert!(packets > 0);
+    for pair in names.windows(2) {
+        assert_eq!(counts[&(pair[0].to_string(), pair[1].to_string())], packets);
+    }
+    for hop in network.hops() {
+        assert!(hop.destination.to_string().starts_with("example.n6.bob."));
+    }
+}
+
+#[tokio::test]
+async fn routes_take_the_shortest_path() {
+    let network = TestNetwork::builder()
+        .node("n1", "XYZ", 9)
+        .node("n2", "XYZ", 9)
+        .node("n3", "XYZ", 9)
+        .node("n4", "XYZ", 9)
+        .link("n1", "n2")
+        .link("n2", "n3")
+        .link("n3", "n4")
+        .link("n1", "n4")
+        .account("n1", "alice")
+        .account("n3", "bob")
+        .account("n4", "carol")
+        .build();
+
+    network
+        .pay("n1", "alice", "n4", "carol", 1000)
+        .await
+        .unwrap();
+    assert_eq!(network.node("n4").received("carol"), 1000);
+    let counts = hops_per_link(&network);
+    assert_eq!(counts.len(), 1);
+    assert!(counts.contains_key(&("n1".to_string(), "n4".to_string())));
+
+    // Both paths to n3 have two hops, so the one over the link added first is taken
+    network.clear_hops();
+    network.pay("n1", "alice", "n3", "bob", 1000).await.unwrap();
+    assert_eq!(network.node("n3").received("bob"), 1000);
+    let counts = hops_per_link(&network);
+    assert_eq!(counts.len(), 2);
+    assert!(counts.contains_key(&("n1".to_string(), "n2".to_string())));
+    assert!(counts.contains_key(&("n2".to_string(), "n3".to_string())));
+}
+
+#[tokio::test]
+async fn converts_between_assets() {
+    let network = TestNetwork::builder()
+        .node("n1", "XYZ", 9)
+        .node("n2", "ABC", 6)
+        .link("n1", "n2")
+        .account("n1", "alice")
+        .account("n2", "bob")
+        .rate("XYZ", 1.0)
+        .rate("ABC", 2.0)
+        .build();
+
+    let receipt = network
+        .pay("n1", "alice", "n2", "bob", 1_000_000)
+        .await
+        .unwrap();
+    assert_eq!(receipt.sent_amount, 1_000_000);
+    assert_eq!(receipt.destination_asset_code.as_deref(), Some("ABC"));
+    assert_eq!(receipt.destination_asset_scale, Some(6));
+    // Half as many units, with three fewer decimal places
+    assert!(receipt.delivered_amount >= 495 && receipt.delivered_amount <= 500);
+    assert_eq!(network.node("n2").received("bob"), receipt.delivered_amount);
+}
+
+#[tokio::test]
+async fn expires_packets_by_the_clock() {
+    let clock = ManualClock::default();
+    let network = TestNetwork::builder()
+        .node("n1", "XYZ", 9)
+        .node("n2", "XYZ", 9)
+        .link("n1", "n2")
+        .account("n1", "alice")
+        .account("n2", "bob")
+        .clock(clock.clone().into())
+        .build();
+    let node = network.node("n1");
+    let request = IncomingRequest {
+        from: node.account("alice"),
+        prepare: PrepareBuilder {
+            destination: Address::from_str("example.n2.bob").unwrap(),
+            amount: 100,
+            expires_at: clock.now() + Duration::from_secs(30),
+            execution_condition: &[0; 32],
+            data: &[],
+        }
+        .build(),
+    };
+
+    // The packet is forwarded while the clock says it has not expired yet
+    let _ = node.service().handle_request(request.clone()).await;
+    assert_eq!(network.hops().len(), 1);
+
+    network.clear_hops();
+    clock.advance(Duration::from_secs(31));
+    let reject = node.service().handle_request(request).await.unwrap_err();
+    assert_eq!(reject.code(), ErrorCode::R00_TRANSFER_TIMED_OUT);
+    assert!(network.hops().is_empty());
+}
@@ -26,6 +26,16 @@ pub mod api {
     pub use interledger_api::*;
 }
 
+/// Authentication of the accounts connecting over BTP or ILP over HTTP
+#[cfg(feature = "auth")]
+pub mod auth {
+    //! # interledger-auth
+    //!
+    //! Parsing, hashing and verification of the incoming tokens, and resolution of the accounts
+    //! they belong to, shared by BTP and ILP over HTTP.
+    pub use interledger_auth::*;
+}
+
 /// Bilateral Transport Protocol (BTP) client and server
 #[cfg(feature = "btp")]
 pub mod btp {
@@ -55,6 +65,16 @@ pub mod ccp {
     pub use interledger_ccp::*;
 }
 
+/// ILP over gRPC client and server
+#[cfg(feature = "grpc")]
+pub mod grpc {
+    //! # interledger-grpc
+    //!
+    //! Client and server implementations of a transport for ILP packets over a bidirectional
+    //! gRPC stream, for peers which already standardize on gRPC.
+    pub use interledger_grpc::*;
+}
+
 /// ILP-Over-HTTP client and server
 #[cfg(feature = "http")]
 pub mod http {
@@ -76,13 +96,23 @@ pub mod ildcp {
     pub use interledger_ildcp::*;
 }
 
+/// Experimental ILP over QUIC client and server
+#[cfg(feature = "quic")]
+pub mod quic {
+    //! # interledger-quic
+    //!
+    //! Experimental client and server implementations of a transport for ILP packets over
+    //! unidirectional QUIC streams.
+    pub use interledger_quic::*;
+}
+
 /// Backend for fetching and caching exchange rates from external APIs
 #[cfg(feature = "rates")]
 pub mod rates {
     //! # interledger-rates
     //!
-    //! Utilities for fetching and caching exchange rates from external APIs,
-    //! which supports CoinCap and CryptoCompare rate backends.
+    //! Utilities for fetching and caching exchange rates from pluggable providers,
+    //! with built-in CoinCap, CryptoCompare, fixed rate and HTTP endpoint backends.
     pub use interledger_rates::*;
 }
 